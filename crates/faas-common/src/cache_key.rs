@@ -0,0 +1,182 @@
+//! Keys for cached execution results.
+//!
+//! A derived key is the SHA-256 of a canonical JSON form of everything that decides what a
+//! command prints, so two requests share a result only when they would have run the same
+//! thing. Keys are stored under a namespace, usually the tenant, so one tenant can't fill
+//! an entry another will read.
+
+use crate::hash::{sha256_hex, sha256_hex_parts};
+use crate::{EnvOverrides, Runtime};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The inputs a cached result depends on
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheKeyInputs<'a> {
+    pub image: &'a str,
+    pub command: &'a str,
+    pub runtime: Option<Runtime>,
+    pub env: Option<&'a BTreeMap<String, String>>,
+    pub working_dir: Option<&'a str>,
+    pub user: Option<&'a str>,
+    pub payload: &'a [u8],
+    /// Path and contents of files placed in the sandbox
+    pub input_files: &'a [(String, Vec<u8>)],
+    /// Host and sandbox path of read-only mounts
+    pub read_only_mounts: &'a [(String, String)],
+    pub environment_overrides: Option<&'a EnvOverrides>,
+}
+
+/// What gets hashed: payloads and file contents by digest, so the form stays small
+#[derive(Serialize)]
+struct Canonical<'a> {
+    image: &'a str,
+    command: &'a str,
+    runtime: Option<Runtime>,
+    env: Option<&'a BTreeMap<String, String>>,
+    working_dir: Option<&'a str>,
+    /// Left out when unset, so keys from before users could be set still match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+    payload: String,
+    input_files: Vec<(&'a str, String)>,
+    read_only_mounts: &'a [(String, String)],
+    environment_overrides: Option<&'a EnvOverrides>,
+}
+
+impl CacheKeyInputs<'_> {
+    /// `cache:` and the hex SHA-256 of the inputs
+    pub fn derive(&self) -> String {
+        let canonical = Canonical {
+            image: self.image,
+            command: self.command,
+            runtime: self.runtime,
+            env: self.env,
+            working_dir: self.working_dir,
+            user: self.user,
+            payload: sha256_hex(self.payload),
+            input_files: self
+                .input_files
+                .iter()
+                .map(|(path, contents)| (path.as_str(), sha256_hex(contents)))
+                .collect(),
+            read_only_mounts: self.read_only_mounts,
+            environment_overrides: self.environment_overrides,
+        };
+        let json = serde_json::to_vec(&canonical).expect("cache key inputs serialize");
+        format!("cache:{}", sha256_hex(json))
+    }
+}
+
+/// Where `key` is stored within `namespace`. Keys in different namespaces never meet, and
+/// a key is stored as given otherwise, so a client's own key names exactly one entry.
+pub fn namespaced(namespace: Option<&str>, key: &str) -> String {
+    match namespace {
+        Some(namespace) => format!(
+            "{}/{key}",
+            &sha256_hex_parts(["cache-namespace", namespace])[..16]
+        ),
+        None => key.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_input_changes_the_key() {
+        let env = BTreeMap::from([("MODE".to_string(), "test".to_string())]);
+        let other_env = BTreeMap::from([("MODE".to_string(), "prod".to_string())]);
+        let files = [("data.csv".to_string(), b"1,2".to_vec())];
+        let other_files = [("data.csv".to_string(), b"1,3".to_vec())];
+        let mounts = [("/host".to_string(), "/data".to_string())];
+        let overrides = EnvOverrides {
+            timezone: Some("UTC".to_string()),
+            ..Default::default()
+        };
+        let base = CacheKeyInputs {
+            image: "alpine:latest",
+            command: "echo hi",
+            runtime: Some(Runtime::Docker),
+            env: Some(&env),
+            working_dir: Some("/app"),
+            payload: b"input",
+            ..Default::default()
+        };
+        let key = base.derive();
+        assert!(key.starts_with("cache:"));
+        assert_eq!(key, base.derive());
+
+        let variants = [
+            CacheKeyInputs {
+                image: "ubuntu:22.04",
+                ..base
+            },
+            CacheKeyInputs {
+                command: "echo bye",
+                ..base
+            },
+            CacheKeyInputs {
+                runtime: Some(Runtime::Firecracker),
+                ..base
+            },
+            CacheKeyInputs {
+                env: Some(&other_env),
+                ..base
+            },
+            CacheKeyInputs { env: None, ..base },
+            CacheKeyInputs {
+                working_dir: Some("/srv"),
+                ..base
+            },
+            CacheKeyInputs {
+                user: Some("1000"),
+                ..base
+            },
+            CacheKeyInputs {
+                payload: b"other",
+                ..base
+            },
+            CacheKeyInputs {
+                input_files: &files,
+                ..base
+            },
+            CacheKeyInputs {
+                read_only_mounts: &mounts,
+                ..base
+            },
+            CacheKeyInputs {
+                environment_overrides: Some(&overrides),
+                ..base
+            },
+        ];
+        let mut keys: Vec<String> = variants.iter().map(CacheKeyInputs::derive).collect();
+        assert!(keys.iter().all(|k| *k != key));
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), variants.len());
+        assert_ne!(
+            CacheKeyInputs {
+                input_files: &files,
+                ..base
+            }
+            .derive(),
+            CacheKeyInputs {
+                input_files: &other_files,
+                ..base
+            }
+            .derive()
+        );
+    }
+
+    #[test]
+    fn namespaces_keep_keys_apart() {
+        assert_eq!(namespaced(None, "report-v1"), "report-v1");
+        let a = namespaced(Some("tenant-a"), "report-v1");
+        let b = namespaced(Some("tenant-b"), "report-v1");
+        assert_ne!(a, b);
+        assert!(a.ends_with("/report-v1"));
+        assert_ne!(namespaced(Some("a/b"), "c"), namespaced(Some("a"), "b/c"));
+    }
+}
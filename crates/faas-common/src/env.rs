@@ -0,0 +1,318 @@
+//! Layered environment variables.
+//!
+//! An execution's env comes from several sources. They are merged here, once, into an
+//! ordered map, and every runtime gets the merged list, so a key set twice resolves the
+//! same way under Docker and Firecracker. From lowest to highest precedence:
+//!
+//! 1. platform: vars the gateway injects, such as `FAAS_KV_ENDPOINT`
+//! 2. function: the function definition's defaults
+//! 3. template: the environment template's layers
+//! 4. request: the caller's `env_vars`
+//! 5. secret: secrets resolved for the execution
+//!
+//! A higher layer replaces a lower one whatever order the layers are added in; within one
+//! layer the last occurrence wins. `FAAS_*` keys belong to the platform layer, and any
+//! other layer setting one is rejected rather than silently ignored.
+//!
+//! Vars travel as [`EnvVar`]s, a key and a value kept apart, so a value holding `=` or a
+//! newline reaches the process as it was sent. They become `KEY=VALUE` strings only where
+//! Docker's API asks for them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Keys with this prefix can only come from the platform layer
+pub const RESERVED_PREFIX: &str = "FAAS_";
+
+/// Where an env var came from, lowest precedence first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvLayer {
+    Platform,
+    Function,
+    Template,
+    Request,
+    Secret,
+}
+
+impl fmt::Display for EnvLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Platform => "platform",
+            Self::Function => "function",
+            Self::Template => "template",
+            Self::Request => "request",
+            Self::Secret => "secret",
+        })
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EnvError {
+    #[error("env var {key} is reserved for the platform; {layer} env vars cannot set {RESERVED_PREFIX}* keys")]
+    Reserved { key: String, layer: EnvLayer },
+    #[error("invalid env var name {0:?}")]
+    InvalidName(String),
+}
+
+/// One environment variable. Serialized as `{"key": ..., "value": ...}`; the
+/// `["KEY", "value"]` pairs and `"KEY=value"` strings older clients send are read as well,
+/// the latter split at the first `=`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "EnvVarRepr")]
+pub struct EnvVar {
+    pub key: String,
+    pub value: String,
+}
+
+impl EnvVar {
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> From<(K, V)> for EnvVar {
+    fn from((key, value): (K, V)) -> Self {
+        Self::new(key, value)
+    }
+}
+
+/// `KEY=VALUE`, the form Docker takes
+impl fmt::Display for EnvVar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+impl FromStr for EnvVar {
+    type Err = EnvError;
+
+    fn from_str(var: &str) -> Result<Self, Self::Err> {
+        match var.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(Self::new(key, value)),
+            _ => Err(EnvError::InvalidName(var.to_string())),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EnvVarRepr {
+    Typed { key: String, value: String },
+    Pair(String, String),
+    Line(String),
+}
+
+impl TryFrom<EnvVarRepr> for EnvVar {
+    type Error = EnvError;
+
+    fn try_from(repr: EnvVarRepr) -> Result<Self, Self::Error> {
+        match repr {
+            EnvVarRepr::Typed { key, value } | EnvVarRepr::Pair(key, value) => {
+                Ok(Self { key, value })
+            }
+            EnvVarRepr::Line(var) => var.parse(),
+        }
+    }
+}
+
+/// Env vars merged by precedence, in key order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayeredEnv {
+    vars: BTreeMap<String, (EnvLayer, String)>,
+}
+
+impl LayeredEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `vars` from `layer`; keys already set by a higher layer keep their value
+    pub fn set(
+        &mut self,
+        layer: EnvLayer,
+        vars: impl IntoIterator<Item = impl Into<EnvVar>>,
+    ) -> Result<(), EnvError> {
+        for var in vars {
+            let EnvVar { key, value } = var.into();
+            if key.is_empty() || key.contains(['=', '\0']) {
+                return Err(EnvError::InvalidName(key));
+            }
+            if layer != EnvLayer::Platform && key.starts_with(RESERVED_PREFIX) {
+                return Err(EnvError::Reserved { key, layer });
+            }
+            match self.vars.get(&key) {
+                Some((existing, _)) if *existing > layer => {}
+                _ => {
+                    self.vars.insert(key, (layer, value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars.get(key).map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Each key with the layer its value came from, for diagnostics that mustn't show values
+    pub fn sources(&self) -> BTreeMap<String, EnvLayer> {
+        self.vars
+            .iter()
+            .map(|(key, (layer, _))| (key.clone(), *layer))
+            .collect()
+    }
+
+    pub fn into_map(self) -> BTreeMap<String, String> {
+        self.vars
+            .into_iter()
+            .map(|(key, (_, value))| (key, value))
+            .collect()
+    }
+}
+
+/// The vars in key order
+pub fn to_vars(env: &BTreeMap<String, String>) -> Vec<EnvVar> {
+    env.iter().map(EnvVar::from).collect()
+}
+
+/// `vars` with duplicates resolved the way [`LayeredEnv`] resolves them within a layer:
+/// last wins, result in key order. Runtimes run what they are handed through this, so a
+/// list that didn't come from a [`LayeredEnv`] still behaves the same everywhere.
+pub fn dedupe_vars(vars: &[EnvVar]) -> Vec<EnvVar> {
+    let map: BTreeMap<&str, &str> = vars
+        .iter()
+        .map(|var| (var.key.as_str(), var.value.as_str()))
+        .collect();
+    map.into_iter().map(EnvVar::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYERS: [EnvLayer; 5] = [
+        EnvLayer::Platform,
+        EnvLayer::Function,
+        EnvLayer::Template,
+        EnvLayer::Request,
+        EnvLayer::Secret,
+    ];
+
+    #[test]
+    fn every_higher_layer_wins_in_either_order() {
+        for (i, &low) in LAYERS.iter().enumerate() {
+            for &high in &LAYERS[i + 1..] {
+                for high_first in [false, true] {
+                    let mut env = LayeredEnv::new();
+                    let mut add = |layer: EnvLayer| {
+                        env.set(layer, [("SHARED", layer.to_string())]).unwrap();
+                    };
+                    if high_first {
+                        add(high);
+                        add(low);
+                    } else {
+                        add(low);
+                        add(high);
+                    }
+                    assert_eq!(
+                        env.get("SHARED"),
+                        Some(high.to_string().as_str()),
+                        "{low} < {high}"
+                    );
+                    assert_eq!(env.sources()["SHARED"], high);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reserved_keys_are_the_platforms() {
+        let mut env = LayeredEnv::new();
+        env.set(EnvLayer::Platform, [("FAAS_KV_TOKEN", "t")])
+            .unwrap();
+        for &layer in &LAYERS[1..] {
+            assert_eq!(
+                env.set(layer, [("FAAS_SHARED", "x")]),
+                Err(EnvError::Reserved {
+                    key: "FAAS_SHARED".to_string(),
+                    layer
+                })
+            );
+        }
+        assert_eq!(
+            env.set(EnvLayer::Request, [("FAAS_KV_TOKEN", "stolen")])
+                .unwrap_err()
+                .to_string(),
+            "env var FAAS_KV_TOKEN is reserved for the platform; request env vars cannot set FAAS_* keys"
+        );
+        assert_eq!(env.get("FAAS_KV_TOKEN"), Some("t"));
+        assert!(matches!(
+            env.set(EnvLayer::Request, [("A=B", "c")]),
+            Err(EnvError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn duplicates_within_a_layer_resolve_to_the_last_in_key_order() {
+        let mut env = LayeredEnv::new();
+        env.set(EnvLayer::Request, [("B", "1"), ("A", "2"), ("B", "3")])
+            .unwrap();
+        let merged = env.into_map();
+        assert_eq!(
+            to_vars(&merged),
+            [EnvVar::new("A", "2"), EnvVar::new("B", "3")]
+        );
+
+        let raw = [
+            EnvVar::new("B", "1"),
+            EnvVar::new("A", "x=y"),
+            EnvVar::new("B", "3"),
+        ];
+        assert_eq!(
+            dedupe_vars(&raw),
+            [EnvVar::new("A", "x=y"), EnvVar::new("B", "3")]
+        );
+    }
+
+    #[test]
+    fn values_survive_every_wire_form() {
+        let values = [
+            "c2VjcmV0PT0=",
+            "a=b=c",
+            "line one\nline two",
+            "héllo wörld ✓",
+            "",
+        ];
+        for value in values {
+            let var = EnvVar::new("K", value);
+            let typed = serde_json::to_string(&var).unwrap();
+            let pair = serde_json::to_string(&("K", value)).unwrap();
+            let line = serde_json::to_string(&format!("K={value}")).unwrap();
+            for json in [typed, pair, line] {
+                assert_eq!(
+                    serde_json::from_str::<EnvVar>(&json).unwrap(),
+                    var,
+                    "{json}"
+                );
+            }
+        }
+        assert_eq!(
+            serde_json::to_value(EnvVar::new("K", "v")).unwrap(),
+            serde_json::json!({"key": "K", "value": "v"})
+        );
+        for bad in [r#""no-separator""#, r#""=value""#, r#"["K"]"#, "42"] {
+            assert!(serde_json::from_str::<EnvVar>(bad).is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Framing for the vsock protocol between the host and the guest agent.
+//!
+//! A frame is the protocol version byte, the body length as a big-endian `u32`, then the
+//! body: a JSON `SandboxConfig` from the host, a JSON `InvocationResult` from the agent.
+//! Frames delimit themselves, so neither side half-closes to end a message and one
+//! connection can carry invocation after invocation. The version goes first so a peer
+//! built for another protocol, the unframed one before it included, is refused at its
+//! first byte instead of being parsed as garbage.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Bumped whenever either side's frames change shape
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Largest body either side accepts; a length past it is a corrupt or hostile header
+pub const MAX_FRAME_BYTES: u32 = 256 * 1024 * 1024;
+
+/// Version byte and length
+pub const HEADER_LEN: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum FrameError {
+    #[error("Peer speaks guest agent protocol v{theirs}, this side v{ours}; rebuild the guest agent and host from the same release")]
+    VersionMismatch { ours: u8, theirs: u8 },
+
+    #[error("Frame of {len} bytes is over the {max} byte limit")]
+    TooLarge { len: u64, max: u32 },
+
+    #[error("Frame IO Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Frame body is not the expected JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// `body` as one frame
+pub fn encode(body: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let len = u32::try_from(body.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_BYTES)
+        .ok_or(FrameError::TooLarge {
+            len: body.len() as u64,
+            max: MAX_FRAME_BYTES,
+        })?;
+    let mut frame = Vec::with_capacity(HEADER_LEN + body.len());
+    frame.push(PROTOCOL_VERSION);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(body);
+    Ok(frame)
+}
+
+/// The body length a header announces, refusing other versions and oversized bodies
+pub fn decode_header(header: [u8; HEADER_LEN]) -> Result<usize, FrameError> {
+    if header[0] != PROTOCOL_VERSION {
+        return Err(FrameError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs: header[0],
+        });
+    }
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    if len > MAX_FRAME_BYTES {
+        return Err(FrameError::TooLarge {
+            len: len.into(),
+            max: MAX_FRAME_BYTES,
+        });
+    }
+    Ok(len as usize)
+}
+
+pub async fn write_frame<W>(writer: &mut W, body: &[u8]) -> Result<(), FrameError>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(&encode(body)?).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// The next frame's body, or `None` when the peer closed the connection between frames.
+/// Closing partway through a frame is an error.
+pub async fn read_frame<R>(reader: &mut R) -> Result<Option<Vec<u8>>, FrameError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; HEADER_LEN];
+    // The version byte alone tells a version mismatch from a short read
+    if reader.read(&mut header[..1]).await? == 0 {
+        return Ok(None);
+    }
+    if header[0] != PROTOCOL_VERSION {
+        return Err(FrameError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs: header[0],
+        });
+    }
+    reader.read_exact(&mut header[1..]).await?;
+    let mut body = vec![0u8; decode_header(header)?];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+pub async fn write_json<W, T>(writer: &mut W, value: &T) -> Result<(), FrameError>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    write_frame(writer, &serde_json::to_vec(value)?).await
+}
+
+/// [`read_frame`], parsed as JSON
+pub async fn read_json<R, T>(reader: &mut R) -> Result<Option<T>, FrameError>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    match read_frame(reader).await? {
+        Some(body) => Ok(Some(serde_json::from_slice(&body)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxConfig;
+
+    #[tokio::test]
+    async fn frames_round_trip_back_to_back() {
+        let (mut host, mut agent) = tokio::io::duplex(64);
+        let first = SandboxConfig {
+            function_id: "first".to_string(),
+            payload: b"\x00binary\xff".to_vec(),
+            ..Default::default()
+        };
+        let second = SandboxConfig {
+            function_id: "second".to_string(),
+            ..Default::default()
+        };
+
+        // Both are sent before either is read, as a pipelining host would
+        let writer = tokio::spawn(async move {
+            write_json(&mut host, &first).await.unwrap();
+            write_json(&mut host, &second).await.unwrap();
+            write_frame(&mut host, b"").await.unwrap();
+        });
+
+        let read: SandboxConfig = read_json(&mut agent).await.unwrap().unwrap();
+        assert_eq!(read.function_id, "first");
+        assert_eq!(read.payload, b"\x00binary\xff");
+        let read: SandboxConfig = read_json(&mut agent).await.unwrap().unwrap();
+        assert_eq!(read.function_id, "second");
+        assert_eq!(read_frame(&mut agent).await.unwrap(), Some(Vec::new()));
+        writer.await.unwrap();
+        assert!(read_frame(&mut agent).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn other_versions_and_oversized_or_cut_frames_are_refused() {
+        // The unframed protocol opened with the JSON itself
+        let mut unframed: &[u8] = br#"{"function_id":"old"}"#;
+        assert!(matches!(
+            read_frame(&mut unframed).await,
+            Err(FrameError::VersionMismatch {
+                ours: PROTOCOL_VERSION,
+                theirs: b'{'
+            })
+        ));
+
+        let mut oversized = vec![PROTOCOL_VERSION];
+        oversized.extend_from_slice(&(MAX_FRAME_BYTES + 1).to_be_bytes());
+        assert!(matches!(
+            read_frame(&mut oversized.as_slice()).await,
+            Err(FrameError::TooLarge { .. })
+        ));
+
+        let whole = encode(b"hello").unwrap();
+        assert!(matches!(
+            read_frame(&mut &whole[..whole.len() - 1]).await,
+            Err(FrameError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+//! Content hashing shared by the SDK, gateway, executor and prover.
+//!
+//! Every id or cache key derived from content is hex SHA-256, so a key computed on the
+//! client matches the one the server computes for the same bytes.
+
+use sha2::{Digest, Sha256};
+
+/// Length of a hex SHA-256 digest.
+pub const SHA256_HEX_LEN: usize = 64;
+
+pub fn sha256(data: impl AsRef<[u8]>) -> [u8; 32] {
+    Sha256::digest(data.as_ref()).into()
+}
+
+pub fn sha256_hex(data: impl AsRef<[u8]>) -> String {
+    format!("{:x}", Sha256::digest(data.as_ref()))
+}
+
+/// Hash several fields as one key.
+///
+/// Each part is length-prefixed, so `["ab", "c"]` and `["a", "bc"]` hash differently.
+pub fn sha256_hex_parts<I, T>(parts: I) -> String
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut hasher = Sha256::new();
+    for part in parts {
+        let part = part.as_ref();
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Whether `id` has the shape of the hex md5 ids written before the SHA-256 migration.
+///
+/// Stores use this to accept old ids on lookup while only writing new ones.
+pub fn is_legacy_md5_hex(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Golden values: changing any of these invalidates every persisted id and cache key.
+    #[test]
+    fn digests_are_stable() {
+        assert_eq!(
+            sha256_hex(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex("print('hello')"),
+            "96f43d529af3430cb6b0e2c02f6b38ef1a121e8a31d2d09a3ebb716f2f35c9de"
+        );
+        assert_eq!(sha256(b"abc")[..4], [0xba, 0x78, 0x16, 0xbf]);
+        assert_eq!(
+            sha256_hex_parts(["alpine:latest", "echo hi"]),
+            "dbb8c16a415e766e601f4648b56e26836ca061215b3aa496cc67c41f73499eb5"
+        );
+        assert_ne!(sha256_hex_parts(["ab", "c"]), sha256_hex_parts(["a", "bc"]));
+        assert_eq!(sha256_hex("abc").len(), SHA256_HEX_LEN);
+    }
+
+    #[test]
+    fn recognizes_legacy_ids() {
+        assert!(is_legacy_md5_hex("900150983cd24fb0d6963f7d28e17f72"));
+        assert!(!is_legacy_md5_hex(&sha256_hex("abc")));
+        assert!(!is_legacy_md5_hex("not-a-hash-not-a-hash-not-a-hash"));
+    }
+}
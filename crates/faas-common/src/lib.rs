@@ -3,10 +3,19 @@
 use std::fmt::Display;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 pub use serde::{Deserialize, Serialize};
 use thiserror::Error;
 pub use uuid;
 
+pub mod cache_key;
+pub mod env;
+pub mod frame;
+pub mod hash;
+pub mod workflow;
+
+pub use env::EnvVar;
+
 #[derive(Error, Debug)]
 pub enum FaasError {
     #[error("Executor Error: {0}")]
@@ -32,6 +41,22 @@ pub enum FaasError {
 
     #[error("Internal Error: {0}")]
     Internal(String),
+
+    /// The request asks for something the selected runtime can't provide
+    #[error("{feature} is not supported on {runtime}: {reason}")]
+    IncompatibleFeature {
+        feature: String,
+        runtime: String,
+        reason: String,
+    },
+
+    /// The sandbox ran past its `timeout` and was killed
+    #[error("Execution timed out after {timeout_ms} ms")]
+    Timeout { timeout_ms: u64 },
+
+    /// The request's `user` has no entry in the image
+    #[error("user {user:?} does not exist in the image")]
+    UnknownUser { user: String },
 }
 
 // Define the primary Result type for FaaS operations
@@ -62,6 +87,30 @@ pub enum Runtime {
     Auto,
 }
 
+/// How strongly an execution has to be kept apart from the host and other tenants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IsolationLevel {
+    /// A container sharing the host kernel is enough
+    Process,
+    /// The execution needs its own kernel, which only a Firecracker VM gives it
+    Hardware,
+}
+
+/// How the platform executor spreads one execution over its runtimes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStrategy {
+    /// Start on both runtimes at once and keep whichever result comes first, typically warm
+    /// Docker as the fallback while a Firecracker VM boots. If both are still running after
+    /// `max_overlap_ms` the fallback is given up. Only for idempotent commands.
+    Speculative {
+        preferred: Runtime,
+        fallback: Runtime,
+        max_overlap_ms: u64,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDefinition {
     pub name: String,
@@ -85,6 +134,62 @@ pub struct InvocationResult {
     pub response: Option<Vec<u8>>,
     pub logs: Option<String>,
     pub error: Option<String>,
+    /// What the function wrote to stdout; `None` from runtimes that only report `logs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<Vec<u8>>,
+    /// The process's exit code, where the runtime saw the process exit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i64>,
+    /// What the execution consumed, from runtimes that measure it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ExecutionUsage>,
+    /// Set when the output went over the runtime's cap and `stdout`/`stderr` were cut short
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<OutputTruncation>,
+}
+
+/// How much output an execution wrote past what was kept of it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutputTruncation {
+    /// Bytes written to stdout and stderr together, including the ones dropped
+    pub total_bytes: u64,
+    /// File holding all of it, where the runtime spilled the output to disk. The caller
+    /// owns the file from then on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_path: Option<std::path::PathBuf>,
+}
+
+/// Resources one execution actually consumed
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExecutionUsage {
+    pub wall_time_ms: u64,
+    /// User and system time of every process in the sandbox
+    pub cpu_time_ms: u64,
+    pub peak_memory_bytes: u64,
+    pub stdout_bytes: u64,
+}
+
+impl InvocationResult {
+    /// stdout and stderr, falling back to `response` and `logs` where the runtime didn't
+    /// keep the streams apart
+    pub fn take_output(&mut self) -> (Vec<u8>, Vec<u8>) {
+        let stdout = self.stdout.take().or_else(|| self.response.take());
+        let stderr = self
+            .stderr
+            .take()
+            .or_else(|| self.logs.take().map(String::into_bytes));
+        (stdout.unwrap_or_default(), stderr.unwrap_or_default())
+    }
+
+    /// `exit_code`, or 0 or 1 by whether `error` is set for runtimes that don't report one
+    pub fn exit_status(&self) -> i32 {
+        match self.exit_code {
+            Some(code) => code as i32,
+            None => i32::from(self.error.is_some()),
+        }
+    }
 }
 
 impl Display for InvocationResult {
@@ -110,6 +215,7 @@ pub struct ExecuteFunctionArgs {
     pub payload: Vec<u8>,
 }
 
+#[cfg(feature = "tangle")]
 impl blueprint_sdk::tangle::metadata::IntoTangleFieldTypes for ExecuteFunctionArgs {
     fn into_tangle_fields() -> Vec<blueprint_sdk::tangle::metadata::macros::ext::FieldType> {
         use blueprint_sdk::tangle::metadata::macros::ext::FieldType;
@@ -128,12 +234,249 @@ pub struct SandboxConfig {
     pub function_id: String,
     pub source: String,
     pub command: Vec<String>,
-    pub env_vars: Option<Vec<String>>,
+    pub env_vars: Option<Vec<EnvVar>>,
+    /// Directory the command runs in, created when the image lacks it; the image's own
+    /// working directory when unset
+    pub working_dir: Option<String>,
+    /// User the command runs as, `uid[:gid]` or a name from the image's `/etc/passwd`;
+    /// the image's user when unset
+    #[serde(default)]
+    pub user: Option<String>,
     pub payload: Vec<u8>,
     pub runtime: Option<Runtime>,
     pub execution_mode: Option<ExecutionMode>,
     pub memory_limit: Option<u32>, // MB
-    pub timeout: Option<u64>,      // milliseconds
+    /// CPU share in cores; `0.5` is half a core
+    pub cpu_limit: Option<f64>,
+    pub timeout: Option<u64>, // milliseconds
+    pub ulimits: Option<Vec<Ulimit>>,
+    pub shm_size_mb: Option<u64>,
+    /// Most processes and threads the sandbox may hold at once, counted by the pids
+    /// cgroup; unlike the `nproc` ulimit it also binds a sandbox running as root
+    #[serde(default)]
+    pub pids_limit: Option<i64>,
+    pub tmpfs: Option<Vec<TmpfsMount>>,
+    pub placement: Option<Placement>,
+    pub environment_overrides: Option<EnvOverrides>,
+    /// Files written into the sandbox before the command starts, as path and contents;
+    /// relative paths are taken from `working_dir`
+    #[serde(default)]
+    pub input_files: Vec<(String, Vec<u8>)>,
+    /// Host paths bind-mounted read-only into the sandbox, as host and sandbox path
+    #[serde(default)]
+    pub read_only_mounts: Vec<(String, String)>,
+    /// GPUs passed through to the sandbox; Docker on a host with the NVIDIA runtime only
+    #[serde(default)]
+    pub gpu: Option<GpuRequest>,
+    /// What the sandbox can reach; Docker's default bridge when unset
+    #[serde(default)]
+    pub network: Option<NetworkPolicy>,
+}
+
+/// Resource limits every runtime knows how to apply.
+pub const SUPPORTED_ULIMITS: &[&str] = &["nofile", "nproc", "fsize", "core", "stack", "memlock"];
+
+/// A POSIX resource limit (`setrlimit`) applied to the sandboxed process.
+///
+/// `fsize`, `stack` and `memlock` are in bytes; `nofile` and `nproc` are counts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+impl Ulimit {
+    pub fn new(name: impl Into<String>, soft: i64, hard: i64) -> Self {
+        Self {
+            name: name.into(),
+            soft,
+            hard,
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_ULIMITS.contains(&self.name.as_str())
+    }
+}
+
+/// `/dev/shm` size, in MiB, of a sandbox whose request doesn't choose one
+pub const DEFAULT_SHM_SIZE_MB: u64 = 64;
+
+/// Process and thread cap of a sandbox whose request doesn't choose one
+pub const DEFAULT_PIDS_LIMIT: i64 = 512;
+
+/// The ulimits of a sandbox whose request doesn't choose them. Warm containers are created
+/// with these, the default `/dev/shm` and [`DEFAULT_PIDS_LIMIT`], so requests that keep
+/// them can run in one.
+pub fn default_ulimits() -> Vec<Ulimit> {
+    vec![
+        Ulimit::new("nproc", 256, 256),
+        Ulimit::new("nofile", 1024, 1024),
+        Ulimit::new("fsize", 1 << 30, 1 << 30),
+    ]
+}
+
+/// Whether `ulimits` are [`default_ulimits`], in any order
+pub fn are_default_ulimits(ulimits: &[Ulimit]) -> bool {
+    let defaults = default_ulimits();
+    ulimits.len() == defaults.len() && defaults.iter().all(|u| ulimits.contains(u))
+}
+
+/// A size-capped tmpfs mounted inside the sandbox.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TmpfsMount {
+    pub path: String,
+    pub size_mb: u64,
+}
+
+/// Host capabilities an execution needs; used to pick a Docker daemon.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Placement {
+    #[serde(default)]
+    pub gpu: bool,
+    /// Required CPU architecture, e.g. `x86_64` or `aarch64`
+    #[serde(default)]
+    pub arch: Option<String>,
+}
+
+/// GPUs an execution needs, as Docker's `--gpus` would take them.
+///
+/// With neither `count` nor `device_ids` every GPU on the host is passed through.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpuRequest {
+    #[serde(default)]
+    pub count: Option<u32>,
+    /// Specific devices by index or UUID, instead of `count`
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+    /// Driver capabilities, e.g. `compute` or `utility`; `gpu` when empty
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl GpuRequest {
+    pub fn count(count: u32) -> Self {
+        Self {
+            count: Some(count),
+            ..Default::default()
+        }
+    }
+}
+
+/// Network access for a sandbox.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum NetworkPolicy {
+    /// No interfaces but loopback
+    None,
+    /// The runtime's usual network
+    #[default]
+    Default,
+    /// Only the addresses these host names resolve to when the sandbox starts
+    Restricted { allowed_hosts: Vec<String> },
+}
+
+impl NetworkPolicy {
+    /// Anything that differs from the runtime's usual network
+    pub fn is_restrictive(&self) -> bool {
+        !matches!(self, NetworkPolicy::Default)
+    }
+}
+
+/// Clock and locale the sandboxed process sees, for reproducible test runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvOverrides {
+    /// IANA zone name, e.g. `Europe/Berlin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Locale name, e.g. `de_DE.UTF-8`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Wall-clock time when the command starts. The clock keeps running from there;
+    /// monotonic clocks are left alone so timeouts still work.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fake_time: Option<DateTime<Utc>>,
+}
+
+impl EnvOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.timezone.is_none() && self.locale.is_none() && self.fake_time.is_none()
+    }
+
+    /// Reject names that could escape the zoneinfo tree or break `KEY=VALUE` env entries
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if let Some(tz) = &self.timezone {
+            let valid = !tz.is_empty()
+                && !tz.starts_with('/')
+                && !tz.split('/').any(|part| part.is_empty() || part == "..")
+                && tz
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/_+-".contains(c));
+            if !valid {
+                return Err(format!("invalid timezone `{tz}`"));
+            }
+        }
+        if let Some(locale) = &self.locale {
+            let valid = !locale.is_empty()
+                && locale
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-@".contains(c));
+            if !valid {
+                return Err(format!("invalid locale `{locale}`"));
+            }
+        }
+        Ok(())
+    }
+
+    /// `TZ`, `LANG` and `LC_ALL`. Faking the clock is runtime specific and not included.
+    pub fn env_vars(&self) -> Vec<EnvVar> {
+        let mut vars = Vec::new();
+        if let Some(tz) = &self.timezone {
+            vars.push(EnvVar::new("TZ", tz));
+        }
+        if let Some(locale) = &self.locale {
+            vars.push(EnvVar::new("LANG", locale));
+            vars.push(EnvVar::new("LC_ALL", locale));
+        }
+        vars
+    }
+
+    /// libfaketime settings that start the clock at `fake_time`, preloading the library at
+    /// `library`. The offset is taken relative to now, because libfaketime would read an
+    /// absolute date in the sandbox's local timezone.
+    pub fn faketime_env(&self, library: &str) -> Vec<EnvVar> {
+        let Some(fake_time) = self.fake_time else {
+            return Vec::new();
+        };
+        let offset = (fake_time - Utc::now()).num_seconds();
+        vec![
+            EnvVar::new("LD_PRELOAD", library),
+            EnvVar::new("FAKETIME", format!("{offset:+}")),
+            EnvVar::new("FAKETIME_DONT_FAKE_MONOTONIC", "1"),
+            EnvVar::new("FAKETIME_DONT_RESET", "1"),
+        ]
+    }
+}
+
+/// Where guest images built for fake clocks keep libfaketime
+pub const GUEST_FAKETIME_LIBRARY: &str = "/usr/lib/faketime/libfaketime.so.1";
+
+/// Kernel boot argument carrying the vsock CID the host leased to a VM, e.g.
+/// `faas.vsock_cid=17`
+pub const VSOCK_CID_BOOT_ARG: &str = "faas.vsock_cid";
+
+/// Vsock port the guest agent takes invocations on, framed as [`frame`] describes
+pub const GUEST_AGENT_PORT: u32 = 1234;
+
+/// The leased CID from a guest's `/proc/cmdline`, if the host passed one
+pub fn vsock_cid_from_cmdline(cmdline: &str) -> Option<u32> {
+    cmdline
+        .split_whitespace()
+        .filter_map(|arg| arg.strip_prefix(VSOCK_CID_BOOT_ARG)?.strip_prefix('='))
+        .next_back()?
+        .parse()
+        .ok()
 }
 
 // Define the SandboxExecutor trait
@@ -171,4 +514,72 @@ mod tests {
         println!("{json_req}");
         assert!(json_req.contains("f1"));
     }
+
+    #[test]
+    fn exit_status_prefers_the_reported_code() {
+        let result = |exit_code, error: Option<&str>| InvocationResult {
+            request_id: "r".to_string(),
+            response: None,
+            logs: None,
+            error: error.map(str::to_string),
+            stdout: None,
+            stderr: None,
+            exit_code,
+            usage: None,
+            truncation: None,
+        };
+        assert_eq!(result(Some(7), Some("failed")).exit_status(), 7);
+        assert_eq!(result(Some(0), None).exit_status(), 0);
+        assert_eq!(result(None, Some("failed")).exit_status(), 1);
+        assert_eq!(result(None, None).exit_status(), 0);
+
+        // Older results without the field still deserialize
+        let old: InvocationResult =
+            serde_json::from_str(r#"{"request_id":"r","response":null,"logs":null,"error":null}"#)
+                .unwrap();
+        assert_eq!(old.exit_code, None);
+    }
+
+    #[test]
+    fn vsock_cid_is_read_from_the_boot_args() {
+        let cmdline = "console=ttyS0 reboot=k faas.vsock_cid=17 ip=172.16.0.2::172.16.0.1";
+        assert_eq!(vsock_cid_from_cmdline(cmdline), Some(17));
+        assert_eq!(
+            vsock_cid_from_cmdline("console=ttyS0 faas.vsock_cidx=4"),
+            None
+        );
+        assert_eq!(vsock_cid_from_cmdline("faas.vsock_cid=nope"), None);
+    }
+
+    #[test]
+    fn env_overrides_reject_paths_outside_zoneinfo() {
+        let overrides = |tz: &str| EnvOverrides {
+            timezone: Some(tz.to_string()),
+            ..Default::default()
+        };
+        assert!(overrides("America/Argentina/Buenos_Aires")
+            .validate()
+            .is_ok());
+        assert!(overrides("Etc/GMT+5").validate().is_ok());
+        for bad in [
+            "../../etc/passwd",
+            "/etc/localtime",
+            "UTC\nLD_PRELOAD=x",
+            "",
+        ] {
+            assert!(overrides(bad).validate().is_err(), "{bad}");
+        }
+        assert_eq!(
+            EnvOverrides {
+                locale: Some("de_DE.UTF-8".to_string()),
+                ..overrides("Europe/Berlin")
+            }
+            .env_vars(),
+            [
+                EnvVar::new("TZ", "Europe/Berlin"),
+                EnvVar::new("LANG", "de_DE.UTF-8"),
+                EnvVar::new("LC_ALL", "de_DE.UTF-8")
+            ]
+        );
+    }
 }
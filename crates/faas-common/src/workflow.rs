@@ -0,0 +1,694 @@
+//! Declarative workflows: a DAG of container steps that can live in a YAML or JSON file.
+//!
+//! ```yaml
+//! name: ci
+//! steps:
+//! - name: fetch
+//!   image: alpine:latest
+//!   command: wget -qO /tmp/src.tar.gz https://example.com/src.tar.gz
+//! - name: test
+//!   image: rust:1.75
+//!   command: cargo test
+//!   env:
+//!     RUST_LOG: debug
+//!   depends_on:
+//!   - fetch
+//!   artifacts:
+//!   - target/test-report.xml
+//!   resources:
+//!     memory_mb: 2048
+//!     cpu_cores: 2
+//!     timeout_ms: 600000
+//! ```
+//!
+//! Steps whose dependencies have all succeeded run concurrently. A failed step normally
+//! stops the run from starting anything else; with `continue_on_failure: true` at the top
+//! level only the steps that depend on it are skipped.
+//!
+//! `input_from: <step>` feeds that step's stdout to this one's stdin. With
+//! `spill_threshold_bytes` set at the top level, larger outputs are passed along as a file
+//! rather than held in memory.
+//!
+//! Only `name`, `image` and `command` are required per step; unknown fields are rejected so a
+//! typo doesn't silently drop a setting. A [`Workflow`] only exists once it has been
+//! validated: step names are unique, every `depends_on` names another step, and there are no
+//! cycles.
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Instant;
+use thiserror::Error;
+
+/// Why a workflow document was rejected, naming the step and field at fault
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum WorkflowError {
+    #[error("invalid workflow document: {0}")]
+    Parse(String),
+    #[error("workflow: {field}: {message}")]
+    Workflow {
+        field: &'static str,
+        message: String,
+    },
+    #[error("step `{step}`: {field}: {message}")]
+    Step {
+        step: String,
+        field: &'static str,
+        message: String,
+    },
+}
+
+impl WorkflowError {
+    fn step(step: &str, field: &'static str, message: impl Into<String>) -> Self {
+        Self::Step {
+            step: step.to_string(),
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StepResources {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_cores: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl StepResources {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkflowStep {
+    pub name: String,
+    pub image: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Steps that must succeed before this one starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Step whose stdout this one reads on stdin; it must also be in `depends_on`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_from: Option<String>,
+    /// Paths the step writes that are meant to outlive it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<String>,
+    #[serde(default, skip_serializing_if = "StepResources::is_empty")]
+    pub resources: StepResources,
+}
+
+impl WorkflowStep {
+    pub fn new(
+        name: impl Into<String>,
+        image: impl Into<String>,
+        command: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            image: image.into(),
+            command: command.into(),
+            env: BTreeMap::new(),
+            depends_on: Vec::new(),
+            input_from: None,
+            artifacts: Vec::new(),
+            resources: StepResources::default(),
+        }
+    }
+}
+
+/// The document as written, before validation
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkflowDocument {
+    name: String,
+    #[serde(default)]
+    continue_on_failure: bool,
+    #[serde(default)]
+    spill_threshold_bytes: Option<u64>,
+    steps: Vec<WorkflowStep>,
+}
+
+/// A validated workflow
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "WorkflowDocument")]
+pub struct Workflow {
+    name: String,
+    /// Keep starting steps that don't depend on a failed one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    continue_on_failure: bool,
+    /// Outputs piped to another step that are larger than this go through a file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spill_threshold_bytes: Option<u64>,
+    steps: Vec<WorkflowStep>,
+}
+
+impl TryFrom<WorkflowDocument> for Workflow {
+    type Error = WorkflowError;
+
+    fn try_from(document: WorkflowDocument) -> Result<Self, Self::Error> {
+        Self::new(document.name, document.steps).map(|workflow| {
+            workflow
+                .with_continue_on_failure(document.continue_on_failure)
+                .with_spill_threshold_bytes(document.spill_threshold_bytes)
+        })
+    }
+}
+
+impl Workflow {
+    pub fn new(name: impl Into<String>, steps: Vec<WorkflowStep>) -> Result<Self, WorkflowError> {
+        let workflow = Self {
+            name: name.into(),
+            continue_on_failure: false,
+            spill_threshold_bytes: None,
+            steps,
+        };
+        workflow.validate()?;
+        Ok(workflow)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, WorkflowError> {
+        let document: WorkflowDocument =
+            serde_yaml::from_str(yaml).map_err(|e| WorkflowError::Parse(e.to_string()))?;
+        document.try_into()
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("workflows always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, WorkflowError> {
+        let document: WorkflowDocument =
+            serde_json::from_str(json).map_err(|e| WorkflowError::Parse(e.to_string()))?;
+        document.try_into()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("workflows always serialize")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether a failure stops only its dependents rather than the whole run
+    pub fn continue_on_failure(&self) -> bool {
+        self.continue_on_failure
+    }
+
+    pub fn with_continue_on_failure(mut self, continue_on_failure: bool) -> Self {
+        self.continue_on_failure = continue_on_failure;
+        self
+    }
+
+    /// Size above which a step's stdout is handed to the steps reading it as a file on
+    /// the host instead of in memory
+    pub fn spill_threshold_bytes(&self) -> Option<u64> {
+        self.spill_threshold_bytes
+    }
+
+    pub fn with_spill_threshold_bytes(mut self, threshold: Option<u64>) -> Self {
+        self.spill_threshold_bytes = threshold;
+        self
+    }
+
+    /// Steps in the order they were declared
+    pub fn steps(&self) -> &[WorkflowStep] {
+        &self.steps
+    }
+
+    pub fn step(&self, name: &str) -> Option<&WorkflowStep> {
+        self.steps.iter().find(|s| s.name == name)
+    }
+
+    /// Steps ordered so each comes after its dependencies; ties keep declaration order
+    pub fn topological_order(&self) -> Vec<&WorkflowStep> {
+        let mut done: Vec<&str> = Vec::with_capacity(self.steps.len());
+        let mut ordered = Vec::with_capacity(self.steps.len());
+        while ordered.len() < self.steps.len() {
+            let next = self
+                .steps
+                .iter()
+                .find(|s| {
+                    !done.contains(&s.name.as_str())
+                        && s.depends_on.iter().all(|d| done.contains(&d.as_str()))
+                })
+                .expect("validated workflows are acyclic");
+            done.push(&next.name);
+            ordered.push(next);
+        }
+        ordered
+    }
+
+    fn validate(&self) -> Result<(), WorkflowError> {
+        if self.name.trim().is_empty() {
+            return Err(WorkflowError::Workflow {
+                field: "name",
+                message: "must not be empty".to_string(),
+            });
+        }
+        if self.steps.is_empty() {
+            return Err(WorkflowError::Workflow {
+                field: "steps",
+                message: "at least one step is required".to_string(),
+            });
+        }
+
+        let mut index = HashMap::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            if step.name.trim().is_empty() {
+                return Err(WorkflowError::step(
+                    &format!("#{}", i + 1),
+                    "name",
+                    "must not be empty",
+                ));
+            }
+            if index.insert(step.name.as_str(), i).is_some() {
+                return Err(WorkflowError::step(
+                    &step.name,
+                    "name",
+                    "declared more than once",
+                ));
+            }
+            if step.image.trim().is_empty() {
+                return Err(WorkflowError::step(
+                    &step.name,
+                    "image",
+                    "must not be empty",
+                ));
+            }
+            if step.command.trim().is_empty() {
+                return Err(WorkflowError::step(
+                    &step.name,
+                    "command",
+                    "must not be empty",
+                ));
+            }
+        }
+
+        for step in &self.steps {
+            for dependency in &step.depends_on {
+                if dependency == &step.name {
+                    return Err(WorkflowError::step(
+                        &step.name,
+                        "depends_on",
+                        "a step cannot depend on itself",
+                    ));
+                }
+                if !index.contains_key(dependency.as_str()) {
+                    return Err(WorkflowError::step(
+                        &step.name,
+                        "depends_on",
+                        format!("unknown step `{dependency}`"),
+                    ));
+                }
+            }
+            if let Some(source) = &step.input_from {
+                if !step.depends_on.contains(source) {
+                    return Err(WorkflowError::step(
+                        &step.name,
+                        "input_from",
+                        format!("`{source}` must also be in depends_on"),
+                    ));
+                }
+            }
+        }
+
+        if let Some(cycle) = self.find_cycle(&index) {
+            return Err(WorkflowError::step(
+                &cycle[0],
+                "depends_on",
+                format!("dependency cycle {}", cycle.join(" -> ")),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether `step`'s stdout of `len` bytes is piped on and too large to keep in memory
+    fn should_spill(&self, step: &str, len: usize) -> bool {
+        self.spill_threshold_bytes
+            .is_some_and(|threshold| len as u64 > threshold)
+            && self
+                .steps
+                .iter()
+                .any(|s| s.input_from.as_deref() == Some(step))
+    }
+
+    /// A cycle as step names, first and last equal
+    fn find_cycle(&self, index: &HashMap<&str, usize>) -> Option<Vec<String>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            New,
+            Visiting,
+            Done,
+        }
+
+        fn visit(
+            i: usize,
+            steps: &[WorkflowStep],
+            index: &HashMap<&str, usize>,
+            marks: &mut [Mark],
+            path: &mut Vec<usize>,
+        ) -> Option<Vec<String>> {
+            marks[i] = Mark::Visiting;
+            path.push(i);
+            for dependency in &steps[i].depends_on {
+                let j = index[dependency.as_str()];
+                match marks[j] {
+                    Mark::Visiting => {
+                        let start = path.iter().position(|&p| p == j).unwrap();
+                        let mut cycle: Vec<String> = path[start..]
+                            .iter()
+                            .map(|&p| steps[p].name.clone())
+                            .collect();
+                        cycle.push(steps[j].name.clone());
+                        return Some(cycle);
+                    }
+                    Mark::New => {
+                        if let Some(cycle) = visit(j, steps, index, marks, path) {
+                            return Some(cycle);
+                        }
+                    }
+                    Mark::Done => {}
+                }
+            }
+            path.pop();
+            marks[i] = Mark::Done;
+            None
+        }
+
+        let mut marks = vec![Mark::New; self.steps.len()];
+        for i in 0..self.steps.len() {
+            if marks[i] == Mark::New {
+                if let Some(cycle) = visit(i, &self.steps, index, &mut marks, &mut Vec::new()) {
+                    return Some(cycle);
+                }
+            }
+        }
+        None
+    }
+
+    /// Run every step with `run_step` once its dependencies have succeeded, independent
+    /// steps concurrently. A failure skips the failed step's dependents and, unless the
+    /// workflow continues on failure, keeps any other step from starting; steps already
+    /// running finish either way. After a cancellation nothing new starts and the steps
+    /// left are cancelled.
+    ///
+    /// A step with `input_from` is handed that step's stdout. Past the spill threshold the
+    /// stdout is written under `FAAS_WORKFLOW_SPILL_DIR` (the system temp directory when
+    /// unset), dropped from the run's result, and deleted once the run is over.
+    pub async fn run<F, Fut>(&self, mut run_step: F) -> WorkflowRun
+    where
+        F: FnMut(WorkflowStep, Option<StepInput>) -> Fut,
+        Fut: Future<Output = Result<StepOutput, StepError>>,
+    {
+        let order = self.topological_order();
+        let position: HashMap<&str, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(i, step)| (step.name.as_str(), i))
+            .collect();
+        let mut runs: Vec<Option<StepRun>> = vec![None; order.len()];
+        let mut admitted = vec![false; order.len()];
+        let mut spilled: Vec<Option<PathBuf>> = vec![None; order.len()];
+        let spill_dir = spill_root().join(uuid::Uuid::new_v4().to_string());
+        let mut failed = false;
+        let mut cancelled: Option<StepCancellation> = None;
+        let run_started = Instant::now();
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            let admitting = cancelled.is_none() && (self.continue_on_failure || !failed);
+            for (i, step) in order.iter().enumerate() {
+                if !admitting {
+                    break;
+                }
+                if admitted[i] {
+                    continue;
+                }
+                let mut ready = true;
+                let mut blocked = false;
+                for dependency in &step.depends_on {
+                    match &runs[position[dependency.as_str()]] {
+                        Some(run) if run.status == StepStatus::Succeeded => {}
+                        Some(_) => blocked = true,
+                        None => ready = false,
+                    }
+                }
+                admitted[i] = blocked || ready;
+                if blocked {
+                    runs[i] = Some(StepRun::skipped(&step.name));
+                } else if ready {
+                    let input = step.input_from.as_deref().map(|source| {
+                        let source = position[source];
+                        let run = runs[source]
+                            .as_ref()
+                            .expect("inputs come from steps that succeeded");
+                        match &spilled[source] {
+                            Some(path) => StepInput::File {
+                                path: path.clone(),
+                                bytes: run.spilled_bytes.unwrap_or_default(),
+                            },
+                            None => StepInput::Bytes(run.stdout.clone().into_bytes()),
+                        }
+                    });
+                    let outcome = run_step((*step).clone(), input);
+                    running.push(async move {
+                        let started = Instant::now();
+                        (i, started, outcome.await)
+                    });
+                }
+            }
+
+            let Some((i, started, outcome)) = running.next().await else {
+                break;
+            };
+            let duration_ms = started.elapsed().as_millis() as u64;
+            let started_ms = started.duration_since(run_started).as_millis() as u64;
+            let name = &order[i].name;
+            let mut run = match outcome {
+                Ok(output) => StepRun {
+                    name: name.clone(),
+                    status: if output.exit_code == 0 {
+                        StepStatus::Succeeded
+                    } else {
+                        StepStatus::Failed
+                    },
+                    exit_code: Some(output.exit_code),
+                    stdout: output.stdout,
+                    stderr: output.stderr,
+                    started_ms,
+                    duration_ms,
+                    error: None,
+                    cancelled: None,
+                    spilled_bytes: None,
+                },
+                Err(StepError::Failed(error)) => StepRun {
+                    name: name.clone(),
+                    status: StepStatus::Failed,
+                    exit_code: None,
+                    stdout: String::new(),
+                    stderr: String::new(),
+                    started_ms,
+                    duration_ms,
+                    error: Some(error),
+                    cancelled: None,
+                    spilled_bytes: None,
+                },
+                Err(StepError::Cancelled(cancellation)) => {
+                    cancelled = Some(cancellation.clone());
+                    StepRun {
+                        started_ms,
+                        ..StepRun::cancelled(name, duration_ms, cancellation)
+                    }
+                }
+            };
+            if run.status == StepStatus::Succeeded && self.should_spill(name, run.stdout.len()) {
+                let path = spill_dir.join(format!("{i}.stdout"));
+                let written = std::fs::create_dir_all(&spill_dir)
+                    .and_then(|()| std::fs::write(&path, run.stdout.as_bytes()));
+                match written {
+                    Ok(()) => {
+                        run.spilled_bytes = Some(run.stdout.len() as u64);
+                        run.stdout = String::new();
+                        spilled[i] = Some(path);
+                    }
+                    Err(e) => {
+                        run.status = StepStatus::Failed;
+                        run.error =
+                            Some(format!("spilling stdout to {} failed: {e}", path.display()));
+                    }
+                }
+            }
+            failed |= run.status == StepStatus::Failed;
+            runs[i] = Some(run);
+        }
+        if spilled.iter().any(Option::is_some) {
+            let _ = std::fs::remove_dir_all(&spill_dir);
+        }
+
+        let steps = runs
+            .into_iter()
+            .zip(&order)
+            .map(|(run, step)| {
+                run.unwrap_or_else(|| match &cancelled {
+                    Some(cancellation) => StepRun::cancelled(&step.name, 0, cancellation.clone()),
+                    None => StepRun::skipped(&step.name),
+                })
+            })
+            .collect();
+        WorkflowRun {
+            workflow: self.name.clone(),
+            id: None,
+            succeeded: !failed && cancelled.is_none(),
+            duration_ms: run_started.elapsed().as_millis() as u64,
+            steps,
+        }
+    }
+}
+
+fn spill_root() -> PathBuf {
+    std::env::var_os("FAAS_WORKFLOW_SPILL_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("faas-workflow-spill"))
+}
+
+/// The stdout of its `input_from` step that a step reads on stdin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepInput {
+    Bytes(Vec<u8>),
+    /// Spilled to a file on the host, which lasts until the run finishes
+    File {
+        path: PathBuf,
+        bytes: u64,
+    },
+}
+
+/// What a step's execution produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// The cancel that stopped a step, or kept it from starting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepCancellation {
+    /// Whose cancellation reached the step, normally the workflow run
+    pub cancelled_by: String,
+    /// Levels between the cancelled node and the step
+    pub depth: u32,
+}
+
+/// Why a step produced no output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepError {
+    /// It couldn't be executed at all
+    Failed(String),
+    Cancelled(StepCancellation),
+}
+
+impl From<String> for StepError {
+    fn from(error: String) -> Self {
+        Self::Failed(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Succeeded,
+    Failed,
+    /// Not run because an earlier step failed
+    Skipped,
+    /// Stopped, or never started, because the workflow was cancelled
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepRun {
+    pub name: String,
+    pub status: StepStatus,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// When the step started, in milliseconds since the run did
+    #[serde(default)]
+    pub started_ms: u64,
+    pub duration_ms: u64,
+    /// Why the step couldn't be executed at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancelled: Option<StepCancellation>,
+    /// Size of the stdout that went over the spill threshold; `stdout` is left empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spilled_bytes: Option<u64>,
+}
+
+impl StepRun {
+    fn skipped(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: StepStatus::Skipped,
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            started_ms: 0,
+            duration_ms: 0,
+            error: None,
+            cancelled: None,
+            spilled_bytes: None,
+        }
+    }
+
+    fn cancelled(name: &str, duration_ms: u64, cancellation: StepCancellation) -> Self {
+        Self {
+            status: StepStatus::Cancelled,
+            duration_ms,
+            cancelled: Some(cancellation),
+            ..Self::skipped(name)
+        }
+    }
+}
+
+/// Per-step results of a workflow, in dependency order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    pub workflow: String,
+    /// Run id the gateway assigned, for cancelling the run while it is in flight
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub succeeded: bool,
+    /// Wall time of the whole run
+    #[serde(default)]
+    pub duration_ms: u64,
+    pub steps: Vec<StepRun>,
+}
+
+impl WorkflowRun {
+    pub fn step(&self, name: &str) -> Option<&StepRun> {
+        self.steps.iter().find(|s| s.name == name)
+    }
+
+    /// Exit code and output of a step that ran to completion
+    pub fn output(&self, name: &str) -> Option<StepOutput> {
+        let step = self.step(name)?;
+        Some(StepOutput {
+            exit_code: step.exit_code?,
+            stdout: step.stdout.clone(),
+            stderr: step.stderr.clone(),
+        })
+    }
+}
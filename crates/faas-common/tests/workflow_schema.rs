@@ -0,0 +1,265 @@
+//! Workflow documents: golden round trips and the errors users see for broken DAGs.
+
+use faas_common::workflow::{
+    StepError, StepOutput, StepStatus, Workflow, WorkflowError, WorkflowStep,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Barrier;
+
+const PIPELINE: &str = include_str!("fixtures/ci-pipeline.yaml");
+
+#[test]
+fn golden_pipeline_round_trips() {
+    let workflow = Workflow::from_yaml(PIPELINE).unwrap();
+    assert_eq!(workflow.name(), "ci");
+    assert_eq!(workflow.steps().len(), 4);
+    assert_eq!(workflow.step("test").unwrap().resources.cpu_cores, Some(4));
+
+    assert_eq!(workflow.to_yaml(), PIPELINE);
+    assert_eq!(Workflow::from_yaml(&workflow.to_yaml()).unwrap(), workflow);
+    assert_eq!(Workflow::from_json(&workflow.to_json()).unwrap(), workflow);
+}
+
+#[test]
+fn steps_run_after_their_dependencies() {
+    let workflow = Workflow::from_yaml(PIPELINE).unwrap();
+    let order: Vec<_> = workflow
+        .topological_order()
+        .iter()
+        .map(|s| s.name.as_str())
+        .collect();
+    assert_eq!(order, ["fetch", "lint", "test", "package"]);
+}
+
+#[test]
+fn cycles_name_the_step_and_field() {
+    let yaml = "\
+name: loop
+steps:
+- name: a
+  image: alpine
+  command: 'true'
+  depends_on: [c]
+- name: b
+  image: alpine
+  command: 'true'
+  depends_on: [a]
+- name: c
+  image: alpine
+  command: 'true'
+  depends_on: [b]
+";
+    let error = Workflow::from_yaml(yaml).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "step `a`: depends_on: dependency cycle a -> c -> b -> a"
+    );
+}
+
+#[test]
+fn unknown_dependencies_name_the_step_and_field() {
+    let yaml = "\
+name: typo
+steps:
+- name: fetch
+  image: alpine
+  command: 'true'
+- name: process
+  image: alpine
+  command: 'true'
+  depends_on: [fetc]
+";
+    assert_eq!(
+        Workflow::from_yaml(yaml).unwrap_err(),
+        WorkflowError::Step {
+            step: "process".to_string(),
+            field: "depends_on",
+            message: "unknown step `fetc`".to_string(),
+        }
+    );
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    let yaml = "\
+name: typo
+steps:
+- name: fetch
+  image: alpine
+  command: 'true'
+  dependson: [x]
+";
+    let error = Workflow::from_yaml(yaml).unwrap_err();
+    assert!(matches!(error, WorkflowError::Parse(_)));
+    assert!(error.to_string().contains("dependson"), "{error}");
+}
+
+#[tokio::test]
+async fn a_failed_step_skips_its_dependents() {
+    let workflow = Workflow::from_yaml(PIPELINE).unwrap();
+    let run = workflow
+        .run(|step, _| async move {
+            Ok(StepOutput {
+                exit_code: if step.name == "lint" { 1 } else { 0 },
+                stdout: String::new(),
+                stderr: String::new(),
+            })
+        })
+        .await;
+    assert!(!run.succeeded);
+    assert_eq!(run.step("fetch").unwrap().status, StepStatus::Succeeded);
+    assert_eq!(run.step("lint").unwrap().status, StepStatus::Failed);
+    assert_eq!(run.step("package").unwrap().status, StepStatus::Skipped);
+}
+
+const DIAMOND: &str = "\
+name: diamond
+steps:
+- name: split
+  image: alpine
+  command: 'true'
+- name: left
+  image: alpine
+  command: 'true'
+  depends_on: [split]
+- name: right
+  image: alpine
+  command: 'true'
+  depends_on: [split]
+- name: join
+  image: alpine
+  command: 'true'
+  depends_on: [left, right]
+";
+
+#[tokio::test]
+async fn independent_steps_of_a_diamond_run_together() {
+    let workflow = Workflow::from_yaml(DIAMOND).unwrap();
+    // Each branch waits for the other, so this only finishes if both are running at once
+    let branches = Arc::new(Barrier::new(2));
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let run = tokio::time::timeout(
+        Duration::from_secs(5),
+        workflow.run(|step, _| {
+            let branches = branches.clone();
+            let finished = finished.clone();
+            async move {
+                if step.depends_on == ["split"] {
+                    branches.wait().await;
+                }
+                finished.lock().unwrap().push(step.name);
+                Ok(StepOutput {
+                    exit_code: 0,
+                    stdout: String::new(),
+                    stderr: String::new(),
+                })
+            }
+        }),
+    )
+    .await
+    .expect("the two branches ran one after the other");
+
+    assert!(run.succeeded);
+    let finished = finished.lock().unwrap();
+    assert_eq!(finished.first().map(String::as_str), Some("split"));
+    assert_eq!(finished.last().map(String::as_str), Some("join"));
+    let names: Vec<_> = run.steps.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["split", "left", "right", "join"]);
+    let join = run.step("join").unwrap();
+    for branch in ["left", "right"] {
+        let branch = run.step(branch).unwrap();
+        assert!(branch.started_ms + branch.duration_ms <= join.started_ms);
+    }
+    assert!(run.duration_ms >= join.started_ms + join.duration_ms);
+}
+
+const BRANCHES: &str = "\
+name: branches
+steps:
+- name: broken
+  image: alpine
+  command: 'false'
+- name: slow
+  image: alpine
+  command: sleep 0.1
+- name: after-slow
+  image: alpine
+  command: 'true'
+  depends_on: [slow]
+";
+
+/// `false` fails at once, `sleep` finishes a little later, anything else succeeds
+async fn shell_ish(step: WorkflowStep) -> Result<StepOutput, StepError> {
+    let exit_code = match step.command.as_str() {
+        "false" => 1,
+        "sleep 0.1" => {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            0
+        }
+        _ => 0,
+    };
+    Ok(StepOutput {
+        exit_code,
+        stdout: String::new(),
+        stderr: String::new(),
+    })
+}
+
+#[tokio::test]
+async fn a_failure_stops_new_steps_unless_the_workflow_continues_on_failure() {
+    let workflow = Workflow::from_yaml(BRANCHES).unwrap();
+    let run = workflow.run(|step, _| shell_ish(step)).await;
+    assert!(!run.succeeded);
+    assert_eq!(run.step("broken").unwrap().status, StepStatus::Failed);
+    // Already running when `broken` failed, so it finishes
+    assert_eq!(run.step("slow").unwrap().status, StepStatus::Succeeded);
+    assert_eq!(run.step("after-slow").unwrap().status, StepStatus::Skipped);
+
+    let yaml = BRANCHES.replacen(
+        "name: branches\n",
+        "name: branches\ncontinue_on_failure: true\n",
+        1,
+    );
+    let workflow = Workflow::from_yaml(&yaml).unwrap();
+    assert!(workflow.continue_on_failure());
+    assert_eq!(Workflow::from_yaml(&workflow.to_yaml()).unwrap(), workflow);
+    let run = workflow.run(|step, _| shell_ish(step)).await;
+    assert!(!run.succeeded);
+    assert_eq!(run.step("broken").unwrap().status, StepStatus::Failed);
+    assert_eq!(
+        run.step("after-slow").unwrap().status,
+        StepStatus::Succeeded
+    );
+}
+
+#[test]
+fn input_from_must_also_be_a_dependency() {
+    let yaml = "\
+name: etl
+steps:
+- name: extract
+  image: alpine
+  command: cat /data.csv
+- name: count
+  image: alpine
+  command: wc -l
+  input_from: extract
+";
+    assert_eq!(
+        Workflow::from_yaml(yaml).unwrap_err(),
+        WorkflowError::Step {
+            step: "count".to_string(),
+            field: "input_from",
+            message: "`extract` must also be in depends_on".to_string(),
+        }
+    );
+
+    let yaml = yaml.replace("  input_from", "  depends_on:\n  - extract\n  input_from");
+    let workflow = Workflow::from_yaml(&yaml).unwrap();
+    assert_eq!(
+        workflow.step("count").unwrap().input_from.as_deref(),
+        Some("extract")
+    );
+    assert_eq!(workflow.to_yaml(), yaml);
+}
@@ -0,0 +1,84 @@
+//! Build a Firecracker rootfs with the guest agent installed.
+//!
+//! ```text
+//! rootfs-builder (--tarball <path> | --image <ref>)
+//!                [--agent <prebuilt binary> | --workspace <dir> [--target <triple>]]
+//!                [--name rootfs] [--size-mb 256] [--output output]
+//! ```
+//!
+//! Without `--agent` the agent is built from the workspace (the current directory by
+//! default) for `x86_64-unknown-linux-musl`. The new manifest entry is printed as JSON.
+
+use faas_executor::rootfs_builder::{self, AgentSource, RootfsBase, RootfsSpec};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: rootfs-builder (--tarball <path> | --image <ref>) \
+[--agent <path> | --workspace <dir> [--target <triple>]] \
+[--name <name>] [--size-mb <mb>] [--output <dir>]";
+
+fn parse(args: impl IntoIterator<Item = String>) -> Result<RootfsSpec, String> {
+    let mut base = None;
+    let mut agent = None;
+    let mut workspace = PathBuf::from(".");
+    let mut target = rootfs_builder::DEFAULT_AGENT_TARGET.to_string();
+    let mut name = "rootfs".to_string();
+    let mut size_mb = 256;
+    let mut output_dir = PathBuf::from("output");
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{flag} needs a value"));
+        match flag.as_str() {
+            "--tarball" => base = Some(RootfsBase::Tarball(value()?.into())),
+            "--image" => base = Some(RootfsBase::DockerImage(value()?)),
+            "--agent" => agent = Some(PathBuf::from(value()?)),
+            "--workspace" => workspace = value()?.into(),
+            "--target" => target = value()?,
+            "--name" => name = value()?,
+            "--size-mb" => {
+                size_mb = value()?
+                    .parse()
+                    .map_err(|_| "--size-mb takes a number of megabytes".to_string())?
+            }
+            "--output" => output_dir = value()?.into(),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            other => return Err(format!("unknown argument {other}\n{USAGE}")),
+        }
+    }
+
+    Ok(RootfsSpec {
+        name,
+        base: base.ok_or_else(|| format!("one of --tarball or --image is required\n{USAGE}"))?,
+        agent: match agent {
+            Some(path) => AgentSource::Prebuilt(path),
+            None => AgentSource::Build { workspace, target },
+        },
+        size_mb,
+        output_dir,
+    })
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let spec = match parse(std::env::args().skip(1)) {
+        Ok(spec) => spec,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::from(2);
+        }
+    };
+    match rootfs_builder::build(&spec).await {
+        Ok(asset) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&asset).expect("asset serializes")
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("rootfs-builder: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,513 @@
+//! Canary checks for warm pools.
+//!
+//! A pooled container can rot while it waits: the image moved upstream, its disk filled up,
+//! DNS broke. Each environment with a [`CanarySpec`] gets a small command run inside one of
+//! its idle containers on an interval, and a failure marks the environment degraded before
+//! real traffic finds out. Canaries exec straight into the container, so they never reach
+//! usage billing or the request metrics; they are counted here instead.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::bollard::exec::{CreateExecOptions, StartExecResults};
+use crate::bollard::Docker;
+
+const DEFAULT_HISTORY_LEN: usize = 20;
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// What to run and what a healthy container answers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanarySpec {
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub expected_exit_code: i64,
+    /// Substring the command's stdout must contain
+    #[serde(default)]
+    pub expected_stdout: Option<String>,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Remove a container that fails its canary and warm a replacement
+    #[serde(default)]
+    pub quarantine: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvironmentHealth {
+    /// No canary has run yet
+    Unknown,
+    Healthy,
+    Degraded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryResult {
+    pub at: DateTime<Utc>,
+    pub container_id: String,
+    pub passed: bool,
+    pub latency_ms: u64,
+    pub exit_code: Option<i64>,
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryStatus {
+    pub environment: String,
+    pub health: EnvironmentHealth,
+    pub spec: CanarySpec,
+    /// Most recent last
+    pub history: Vec<CanaryResult>,
+}
+
+/// Sent to every alert sink when a canary fails
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryAlert {
+    pub environment: String,
+    pub container_id: String,
+    pub failure: String,
+    pub quarantined: bool,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanaryOutput {
+    pub exit_code: i64,
+    pub stdout: String,
+}
+
+/// Runs a canary command inside a container
+#[async_trait]
+pub trait CanaryRunner: Send + Sync {
+    async fn exec(&self, container_id: &str, command: &[String]) -> Result<CanaryOutput>;
+}
+
+pub struct DockerCanaryRunner {
+    docker: Arc<Docker>,
+}
+
+impl DockerCanaryRunner {
+    pub fn new(docker: Arc<Docker>) -> Self {
+        Self { docker }
+    }
+}
+
+#[async_trait]
+impl CanaryRunner for DockerCanaryRunner {
+    async fn exec(&self, container_id: &str, command: &[String]) -> Result<CanaryOutput> {
+        use futures::StreamExt;
+
+        let exec = self
+            .docker
+            .create_exec(
+                container_id,
+                CreateExecOptions {
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    cmd: Some(command.to_vec()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let mut stdout = Vec::new();
+        match self.docker.start_exec(&exec.id, None).await? {
+            StartExecResults::Attached { mut output, .. } => {
+                while let Some(chunk) = output.next().await {
+                    if let crate::bollard::container::LogOutput::StdOut { message } = chunk? {
+                        stdout.extend_from_slice(&message);
+                    }
+                }
+            }
+            StartExecResults::Detached => return Err(anyhow!("canary exec detached")),
+        }
+        let exit_code = self
+            .docker
+            .inspect_exec(&exec.id)
+            .await?
+            .exit_code
+            .ok_or_else(|| anyhow!("canary exec reported no exit code"))?;
+        Ok(CanaryOutput {
+            exit_code,
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        })
+    }
+}
+
+/// Where canary failures are reported
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    async fn alert(&self, alert: &CanaryAlert);
+}
+
+/// POSTs each alert as JSON
+pub struct WebhookAlertSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookAlertSink {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url: url.into(),
+        }
+    }
+
+    /// A sink for `FAAS_CANARY_WEBHOOK_URL`, if set
+    pub fn from_env() -> Option<Self> {
+        std::env::var("FAAS_CANARY_WEBHOOK_URL").ok().map(Self::new)
+    }
+}
+
+#[async_trait]
+impl AlertSink for WebhookAlertSink {
+    async fn alert(&self, alert: &CanaryAlert) {
+        match self.client.post(&self.url).json(alert).send().await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => warn!(
+                "Canary alert for {} to {} returned {}",
+                alert.environment,
+                self.url,
+                response.status()
+            ),
+            Err(e) => warn!(
+                "Canary alert for {} to {} failed: {}",
+                alert.environment, self.url, e
+            ),
+        }
+    }
+}
+
+/// `Err` with the reason when `output` doesn't match what `spec` expects
+pub fn evaluate(spec: &CanarySpec, output: &CanaryOutput) -> std::result::Result<(), String> {
+    if output.exit_code != spec.expected_exit_code {
+        return Err(format!(
+            "exit code {} (expected {})",
+            output.exit_code, spec.expected_exit_code
+        ));
+    }
+    match &spec.expected_stdout {
+        Some(expected) if !output.stdout.contains(expected.as_str()) => {
+            Err(format!("stdout does not contain {expected:?}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+struct EnvironmentCanary {
+    spec: CanarySpec,
+    health: EnvironmentHealth,
+    history: VecDeque<CanaryResult>,
+    last_run: Option<Instant>,
+}
+
+/// Canary specs, results and health for every pooled environment
+pub struct CanaryMonitor {
+    environments: DashMap<String, EnvironmentCanary>,
+    sinks: RwLock<Vec<Arc<dyn AlertSink>>>,
+    history_len: usize,
+    executions: AtomicU64,
+}
+
+impl Default for CanaryMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CanaryMonitor {
+    pub fn new() -> Self {
+        Self {
+            environments: DashMap::new(),
+            sinks: RwLock::new(Vec::new()),
+            history_len: DEFAULT_HISTORY_LEN,
+            executions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add_sink(&self, sink: Arc<dyn AlertSink>) {
+        self.sinks.write().unwrap().push(sink);
+    }
+
+    /// Set or replace the canary for `environment`; its health starts over
+    pub fn configure(&self, environment: &str, spec: CanarySpec) {
+        self.environments.insert(
+            environment.to_string(),
+            EnvironmentCanary {
+                spec,
+                health: EnvironmentHealth::Unknown,
+                history: VecDeque::new(),
+                last_run: None,
+            },
+        );
+    }
+
+    pub fn remove(&self, environment: &str) -> bool {
+        self.environments.remove(environment).is_some()
+    }
+
+    pub fn spec(&self, environment: &str) -> Option<CanarySpec> {
+        self.environments.get(environment).map(|e| e.spec.clone())
+    }
+
+    /// Environments whose interval has elapsed since their last canary
+    pub fn due(&self) -> Vec<String> {
+        self.environments
+            .iter()
+            .filter(|e| match e.last_run {
+                Some(at) => at.elapsed() >= Duration::from_secs(e.spec.interval_secs),
+                None => true,
+            })
+            .map(|e| e.key().clone())
+            .collect()
+    }
+
+    /// Run the canary for `environment` in `container_id`, record the result and alert
+    /// the sinks on failure. `None` if the environment has no canary.
+    pub async fn check(
+        &self,
+        environment: &str,
+        container_id: &str,
+        runner: &dyn CanaryRunner,
+    ) -> Option<CanaryResult> {
+        let spec = {
+            let mut entry = self.environments.get_mut(environment)?;
+            entry.last_run = Some(Instant::now());
+            entry.spec.clone()
+        };
+
+        self.executions.fetch_add(1, Ordering::Relaxed);
+        let started = Instant::now();
+        let outcome = tokio::time::timeout(
+            Duration::from_secs(spec.timeout_secs),
+            runner.exec(container_id, &spec.command),
+        )
+        .await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let (exit_code, verdict) = match outcome {
+            Ok(Ok(output)) => (Some(output.exit_code), evaluate(&spec, &output)),
+            Ok(Err(e)) => (None, Err(format!("exec failed: {e}"))),
+            Err(_) => (None, Err(format!("timed out after {}s", spec.timeout_secs))),
+        };
+        let result = CanaryResult {
+            at: Utc::now(),
+            container_id: container_id.to_string(),
+            passed: verdict.is_ok(),
+            latency_ms,
+            exit_code,
+            failure: verdict.err(),
+        };
+
+        if let Some(mut entry) = self.environments.get_mut(environment) {
+            entry.health = if result.passed {
+                EnvironmentHealth::Healthy
+            } else {
+                EnvironmentHealth::Degraded
+            };
+            entry.history.push_back(result.clone());
+            while entry.history.len() > self.history_len {
+                entry.history.pop_front();
+            }
+        }
+
+        if let Some(failure) = &result.failure {
+            warn!(
+                "Canary for {} failed in container {}: {}",
+                environment, container_id, failure
+            );
+            let alert = CanaryAlert {
+                environment: environment.to_string(),
+                container_id: container_id.to_string(),
+                failure: failure.clone(),
+                quarantined: spec.quarantine,
+                at: result.at,
+            };
+            let sinks = self.sinks.read().unwrap().clone();
+            for sink in sinks {
+                sink.alert(&alert).await;
+            }
+        }
+        Some(result)
+    }
+
+    pub fn status(&self, environment: &str) -> Option<CanaryStatus> {
+        self.environments.get(environment).map(|e| CanaryStatus {
+            environment: environment.to_string(),
+            health: e.health,
+            spec: e.spec.clone(),
+            history: e.history.iter().cloned().collect(),
+        })
+    }
+
+    pub fn statuses(&self) -> Vec<CanaryStatus> {
+        let mut statuses: Vec<_> = self
+            .environments
+            .iter()
+            .filter_map(|e| self.status(e.key()))
+            .collect();
+        statuses.sort_by(|a, b| a.environment.cmp(&b.environment));
+        statuses
+    }
+
+    /// Environments whose last canary failed
+    pub fn degraded(&self) -> Vec<String> {
+        let mut degraded: Vec<_> = self
+            .environments
+            .iter()
+            .filter(|e| e.health == EnvironmentHealth::Degraded)
+            .map(|e| e.key().clone())
+            .collect();
+        degraded.sort();
+        degraded
+    }
+
+    /// Canary commands run so far; kept apart from user executions
+    pub fn executions(&self) -> u64 {
+        self.executions.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    /// Containers as sets of files; answers `test -f <path>`
+    #[derive(Default)]
+    struct FakeContainers {
+        files: Mutex<std::collections::HashMap<String, HashSet<String>>>,
+    }
+
+    #[async_trait]
+    impl CanaryRunner for FakeContainers {
+        async fn exec(&self, container_id: &str, command: &[String]) -> Result<CanaryOutput> {
+            let files = self.files.lock().unwrap();
+            let container = files
+                .get(container_id)
+                .ok_or_else(|| anyhow!("no such container"))?;
+            let exit_code = match command {
+                [test, flag, path] if test == "test" && flag == "-f" => {
+                    i64::from(!container.contains(path))
+                }
+                _ => 127,
+            };
+            Ok(CanaryOutput {
+                exit_code,
+                stdout: String::new(),
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingSink {
+        alerts: Mutex<Vec<CanaryAlert>>,
+    }
+
+    #[async_trait]
+    impl AlertSink for RecordingSink {
+        async fn alert(&self, alert: &CanaryAlert) {
+            self.alerts.lock().unwrap().push(alert.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn poisoned_container_degrades_the_environment() {
+        let containers = FakeContainers::default();
+        containers
+            .files
+            .lock()
+            .unwrap()
+            .insert("c-1".to_string(), HashSet::from(["/etc/ready".to_string()]));
+        let sink = Arc::new(RecordingSink::default());
+        let monitor = CanaryMonitor::new();
+        monitor.add_sink(sink.clone());
+        monitor.configure(
+            "alpine:latest",
+            CanarySpec {
+                command: vec!["test".into(), "-f".into(), "/etc/ready".into()],
+                expected_exit_code: 0,
+                expected_stdout: None,
+                interval_secs: 60,
+                timeout_secs: 5,
+                quarantine: true,
+            },
+        );
+        assert_eq!(monitor.due(), ["alpine:latest"]);
+
+        let passed = monitor
+            .check("alpine:latest", "c-1", &containers)
+            .await
+            .unwrap();
+        assert!(passed.passed);
+        assert!(monitor.due().is_empty());
+        assert_eq!(
+            monitor.status("alpine:latest").unwrap().health,
+            EnvironmentHealth::Healthy
+        );
+
+        containers
+            .files
+            .lock()
+            .unwrap()
+            .get_mut("c-1")
+            .unwrap()
+            .remove("/etc/ready");
+        let failed = monitor
+            .check("alpine:latest", "c-1", &containers)
+            .await
+            .unwrap();
+        assert_eq!(failed.exit_code, Some(1));
+        assert_eq!(monitor.degraded(), ["alpine:latest"]);
+
+        let status = monitor.status("alpine:latest").unwrap();
+        assert_eq!(status.history.len(), 2);
+        let alerts = sink.alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].container_id, "c-1");
+        assert!(alerts[0].quarantined);
+        assert_eq!(monitor.executions(), 2);
+    }
+
+    #[test]
+    fn stdout_expectations() {
+        let spec = CanarySpec {
+            command: vec!["nslookup".into(), "example.com".into()],
+            expected_exit_code: 0,
+            expected_stdout: Some("Address".into()),
+            interval_secs: 60,
+            timeout_secs: 5,
+            quarantine: false,
+        };
+        let output = |exit_code, stdout: &str| CanaryOutput {
+            exit_code,
+            stdout: stdout.to_string(),
+        };
+        assert!(evaluate(
+            &spec,
+            &output(0, "Name: example.com\nAddress: 93.184.215.14")
+        )
+        .is_ok());
+        assert!(evaluate(&spec, &output(0, "server can't find example.com")).is_err());
+        assert!(evaluate(&spec, &output(1, "Address")).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Labels on the containers the platform creates, so a gateway that crashed can find its
+//! containers again.
+//!
+//! Every execution, instance, pool and port proxy container carries [`MANAGED`], [`KIND`],
+//! [`REQUEST_ID`], [`FUNCTION_ID`] and [`GATEWAY_INSTANCE`]. Instance containers also
+//! carry what is needed to register the instance again after a restart.
+
+use crate::bollard::container::ListContainersOptions;
+use crate::bollard::models::{ContainerSummary, Port, PortTypeEnum};
+use crate::bollard::Docker;
+use crate::platform::ports::{PortMapping, PortProtocol};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+pub const MANAGED: &str = "faas.managed";
+pub const KIND: &str = "faas.kind";
+/// The container's own request: the run's id, or the instance's
+pub const REQUEST_ID: &str = "faas.request_id";
+/// The execution or function the container serves: the execution id for a run, the
+/// instance id for an instance, the image for a pool container
+pub const FUNCTION_ID: &str = "faas.function_id";
+pub const GATEWAY_INSTANCE: &str = "faas.gateway_instance";
+pub const INSTANCE_NAME: &str = "faas.instance_name";
+pub const CPU_CORES: &str = "faas.cpu_cores";
+pub const MEMORY_MB: &str = "faas.memory_mb";
+/// The tenant an instance belongs to; unset for an untenanted one
+pub const TENANT: &str = "faas.tenant";
+
+/// What a managed container is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerKind {
+    /// Runs one execution and is removed with it
+    Ephemeral,
+    /// Idles in a pool until an execution claims it
+    Pooled,
+    /// Backs a persistent instance
+    Instance,
+    /// Publishes a port exposed on a running instance; its function id is the instance's
+    Proxy,
+}
+
+impl ContainerKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContainerKind::Ephemeral => "ephemeral",
+            ContainerKind::Pooled => "pooled",
+            ContainerKind::Instance => "instance",
+            ContainerKind::Proxy => "proxy",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ephemeral" => Some(ContainerKind::Ephemeral),
+            "pooled" => Some(ContainerKind::Pooled),
+            "instance" => Some(ContainerKind::Instance),
+            "proxy" => Some(ContainerKind::Proxy),
+            _ => None,
+        }
+    }
+}
+
+/// This gateway's name on its containers: `FAAS_GATEWAY_INSTANCE`, else the host name.
+/// It has to survive a restart, or the restarted gateway would not recognise its
+/// containers.
+pub fn gateway_instance() -> &'static str {
+    static INSTANCE: OnceLock<String> = OnceLock::new();
+    INSTANCE.get_or_init(|| {
+        std::env::var("FAAS_GATEWAY_INSTANCE")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "faas-gateway".to_string())
+    })
+}
+
+/// The labels a new container of `kind` is created with
+pub fn labels(kind: ContainerKind, request_id: &str, function_id: &str) -> HashMap<String, String> {
+    HashMap::from([
+        (MANAGED.to_string(), "true".to_string()),
+        (KIND.to_string(), kind.as_str().to_string()),
+        (REQUEST_ID.to_string(), request_id.to_string()),
+        (FUNCTION_ID.to_string(), function_id.to_string()),
+        (GATEWAY_INSTANCE.to_string(), gateway_instance().to_string()),
+    ])
+}
+
+/// A container found by its labels
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ManagedContainer {
+    pub container_id: String,
+    pub kind: ContainerKind,
+    pub request_id: String,
+    pub function_id: String,
+    pub gateway_instance: String,
+    pub image: Option<String>,
+    /// Unix seconds
+    pub created: Option<i64>,
+    /// Docker's state, `running` or `exited` and so on
+    pub state: Option<String>,
+    /// The ports it publishes on the host
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<PortMapping>,
+    #[serde(skip)]
+    pub labels: HashMap<String, String>,
+}
+
+impl ManagedContainer {
+    /// `None` for a container without the full set of labels
+    pub fn from_summary(summary: ContainerSummary) -> Option<Self> {
+        let labels = summary.labels.unwrap_or_default();
+        if labels.get(MANAGED).map(String::as_str) != Some("true") {
+            return None;
+        }
+        let label = |key: &str| labels.get(key).cloned();
+        Some(Self {
+            container_id: summary.id?,
+            kind: ContainerKind::parse(labels.get(KIND)?)?,
+            request_id: label(REQUEST_ID)?,
+            function_id: label(FUNCTION_ID)?,
+            gateway_instance: label(GATEWAY_INSTANCE)?,
+            image: summary.image,
+            created: summary.created,
+            state: summary.state,
+            ports: published(summary.ports.unwrap_or_default()),
+            labels,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.as_deref() == Some("running")
+    }
+}
+
+fn published(ports: Vec<Port>) -> Vec<PortMapping> {
+    let mut mappings: Vec<PortMapping> = ports
+        .into_iter()
+        .filter_map(|port| {
+            let protocol = match port.typ {
+                Some(PortTypeEnum::UDP) => PortProtocol::Udp,
+                Some(PortTypeEnum::TCP) | None => PortProtocol::Tcp,
+                _ => return None,
+            };
+            Some(PortMapping {
+                container_port: port.private_port,
+                host_port: Some(port.public_port?),
+                protocol,
+            })
+        })
+        .collect();
+    // Listed once per address family
+    mappings.sort_by_key(|port| (port.container_port, port.protocol.as_str()));
+    mappings.dedup();
+    mappings
+}
+
+/// Every container, running or not, carrying the platform's labels
+pub async fn list_managed(
+    docker: &Docker,
+) -> Result<Vec<ManagedContainer>, crate::bollard::errors::Error> {
+    let containers = docker
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            filters: [("label".to_string(), vec![format!("{MANAGED}=true")])].into(),
+            ..Default::default()
+        }))
+        .await?;
+    Ok(containers
+        .into_iter()
+        .filter_map(ManagedContainer::from_summary)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labelled_containers_are_recognised() {
+        let mut labels = labels(ContainerKind::Instance, "inst-1", "inst-1");
+        labels.insert(INSTANCE_NAME.to_string(), "dev".to_string());
+        let found = ManagedContainer::from_summary(ContainerSummary {
+            id: Some("abc".to_string()),
+            image: Some("alpine:latest".to_string()),
+            state: Some("running".to_string()),
+            labels: Some(labels),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(found.kind, ContainerKind::Instance);
+        assert_eq!(found.request_id, "inst-1");
+        assert_eq!(found.gateway_instance, gateway_instance());
+        assert_eq!(found.labels.get(INSTANCE_NAME).unwrap(), "dev");
+        assert!(found.is_running());
+    }
+
+    #[test]
+    fn containers_missing_labels_are_not_ours() {
+        let mut partial = labels(ContainerKind::Ephemeral, "run", "exec");
+        partial.remove(REQUEST_ID);
+        for labels in [HashMap::new(), partial] {
+            let summary = ContainerSummary {
+                id: Some("abc".to_string()),
+                labels: Some(labels),
+                ..Default::default()
+            };
+            assert_eq!(ManagedContainer::from_summary(summary), None);
+        }
+    }
+}
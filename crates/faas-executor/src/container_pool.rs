@@ -3,10 +3,14 @@
 
 use crate::bollard::container::{Config as ContainerConfig, CreateContainerOptions};
 use crate::bollard::Docker;
+use crate::canary::{CanaryMonitor, CanaryResult, CanaryRunner, DockerCanaryRunner};
+use crate::container_labels::{self, ContainerKind};
+use crate::drain::DrainController;
 use anyhow::{anyhow, Result};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, RwLock, Semaphore};
@@ -18,6 +22,8 @@ pub struct PooledContainer {
     pub id: String,
     pub container_id: String,
     pub image: String,
+    /// The tenant whose pool the container is in; `None` for the shared pools
+    pub namespace: Option<String>,
     pub created_at: Instant,
     pub last_used: Option<Instant>,
     pub use_count: usize,
@@ -90,6 +96,9 @@ pub struct ContainerPoolManager {
     metrics: Arc<RwLock<PoolMetrics>>,
     predictor: Arc<RwLock<UsagePredictor>>,
     stratified_pool: Arc<StratifiedPool>,
+    drain: Arc<DrainController>,
+    canaries: Arc<CanaryMonitor>,
+    canary_runner: Arc<dyn CanaryRunner>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -124,15 +133,21 @@ struct PredictionPatterns {
 pub struct ContainerPool {
     docker: Arc<Docker>,
     image: String,
+    namespace: Option<String>,
     available: Arc<Mutex<VecDeque<PooledContainer>>>,
     in_use: Arc<DashMap<String, PooledContainer>>,
     config: PoolConfig,
+    /// The config's `min_size` and `max_size` until [`Self::set_limits`] changes them
+    min_size: AtomicUsize,
+    max_size: AtomicUsize,
     creation_semaphore: Arc<Semaphore>,
     total_created: Arc<RwLock<usize>>,
     hit_rate: Arc<RwLock<f64>>,
     avg_startup_ms: Arc<RwLock<f64>>,
     total_requests: Arc<RwLock<u64>>,
     cache_hits: Arc<RwLock<u64>>,
+    drain: Arc<DrainController>,
+    created_at: Instant,
 }
 
 impl Clone for ContainerPoolManager {
@@ -144,13 +159,26 @@ impl Clone for ContainerPoolManager {
             metrics: self.metrics.clone(),
             predictor: self.predictor.clone(),
             stratified_pool: self.stratified_pool.clone(),
+            drain: self.drain.clone(),
+            canaries: self.canaries.clone(),
+            canary_runner: self.canary_runner.clone(),
         }
     }
 }
 
 impl ContainerPoolManager {
     pub fn new(docker: Arc<Docker>, config: PoolConfig) -> Self {
+        Self::with_drain(docker, config, Arc::new(DrainController::new()))
+    }
+
+    /// Pools that stop refilling and shrink to zero while `drain` is draining
+    pub fn with_drain(
+        docker: Arc<Docker>,
+        config: PoolConfig,
+        drain: Arc<DrainController>,
+    ) -> Self {
         let manager = Self {
+            canary_runner: Arc::new(DockerCanaryRunner::new(docker.clone())),
             docker,
             pools: Arc::new(DashMap::new()),
             config: config.clone(),
@@ -162,6 +190,8 @@ impl ContainerPoolManager {
                 cold_tier: Arc::new(Mutex::new(VecDeque::new())),
                 base_image_cache: Arc::new(DashMap::new()),
             }),
+            drain,
+            canaries: Arc::new(CanaryMonitor::new()),
         };
 
         // Start predictive warming if enabled
@@ -178,25 +208,82 @@ impl ContainerPoolManager {
             mgr.health_check_loop().await;
         });
 
+        let mgr = manager.clone();
+        tokio::spawn(async move {
+            mgr.canary_loop().await;
+        });
+
         manager
     }
 
+    /// Daemon these pools create their containers on
+    pub fn docker(&self) -> Arc<Docker> {
+        self.docker.clone()
+    }
+
+    /// Canary specs and per-environment health for these pools
+    pub fn canaries(&self) -> Arc<CanaryMonitor> {
+        self.canaries.clone()
+    }
+
+    /// Set the canary for `image` and open its pool so there are containers to check
+    pub async fn configure_canary(&self, image: &str, spec: crate::canary::CanarySpec) {
+        self.canaries.configure(image, spec);
+        self.get_pool(image).await;
+    }
+
+    /// Run the canary for `image` against one of its idle containers now. `None` when the
+    /// image has no canary or no idle container to check.
+    pub async fn run_canary_now(&self, image: &str) -> Result<Option<CanaryResult>> {
+        let Some(spec) = self.canaries.spec(image) else {
+            return Ok(None);
+        };
+        let Some(pool) = self.pools.get(image).map(|p| p.clone()) else {
+            return Ok(None);
+        };
+        let Some(container) = pool.take_idle().await else {
+            return Ok(None);
+        };
+
+        let result = self
+            .canaries
+            .check(image, &container.container_id, self.canary_runner.as_ref())
+            .await;
+        match &result {
+            Some(result) if !result.passed && spec.quarantine => {
+                warn!(
+                    "Quarantining container {} after a failed canary",
+                    container.container_id
+                );
+                pool.quarantine(container).await?;
+            }
+            _ => pool.return_idle(container).await,
+        }
+        Ok(result)
+    }
+
     /// Get or create a pool for an image
     pub async fn get_pool(&self, image: &str) -> Arc<ContainerPool> {
-        if let Some(pool) = self.pools.get(image) {
+        self.get_pool_in(None, image).await
+    }
+
+    /// Get or create `namespace`'s own pool for an image
+    pub async fn get_pool_in(&self, namespace: Option<&str>, image: &str) -> Arc<ContainerPool> {
+        let key = pool_key(namespace, image);
+        if let Some(pool) = self.pools.get(&key) {
             return pool.clone();
         }
 
-        let pool = Arc::new(ContainerPool::new(
-            self.docker.clone(),
-            image.to_string(),
-            self.config.clone(),
-        ));
+        let pool = Arc::new(
+            ContainerPool::new(self.docker.clone(), image.to_string(), self.config.clone())
+                .with_namespace(namespace.map(str::to_string))
+                .with_drain(self.drain.clone()),
+        );
 
-        self.pools.insert(image.to_string(), pool.clone());
+        self.pools.insert(key, pool.clone());
 
         // Pre-warm if configured
-        if self.config.pre_warm {
+        if self.config.pre_warm && !self.drain.is_draining() {
             let pool_clone = pool.clone();
             tokio::spawn(async move {
                 if let Err(e) = pool_clone.pre_warm().await {
@@ -268,7 +355,8 @@ impl ContainerPoolManager {
 
     /// Release a container back to the pool
     pub async fn release(&self, container: PooledContainer) -> Result<()> {
-        if let Some(pool) = self.pools.get(&container.image) {
+        let key = pool_key(container.namespace.as_deref(), &container.image);
+        if let Some(pool) = self.pools.get(&key) {
             pool.release(container).await
         } else {
             Err(anyhow!("Pool not found for image: {}", container.image))
@@ -277,18 +365,94 @@ impl ContainerPoolManager {
 
     /// Get pool statistics
     pub async fn get_stats(&self, image: &str) -> Option<PoolStats> {
-        self.pools.get(image).map(|pool| {
-            let available = pool.available.try_lock().map(|a| a.len()).unwrap_or(0);
-            let in_use = pool.in_use.len();
+        self.get_stats_in(None, image).await
+    }
 
-            PoolStats {
-                image: image.to_string(),
-                available,
-                in_use,
-                total: available + in_use,
-                config: pool.config.clone(),
-            }
-        })
+    /// Statistics of `namespace`'s own pool for `image`
+    pub async fn get_stats_in(&self, namespace: Option<&str>, image: &str) -> Option<PoolStats> {
+        let pool = self
+            .pools
+            .get(&pool_key(namespace, image))
+            .map(|p| p.clone())?;
+        Some(pool.stats().await)
+    }
+
+    /// Statistics for every pool, by image, the shared pool of an image first
+    pub async fn all_stats(&self) -> Vec<PoolStats> {
+        let pools: Vec<_> = self.pools.iter().map(|p| p.value().clone()).collect();
+        let mut stats = Vec::with_capacity(pools.len());
+        for pool in pools {
+            stats.push(pool.stats().await);
+        }
+        stats.sort_by(|a, b| (&a.image, &a.namespace).cmp(&(&b.image, &b.namespace)));
+        stats
+    }
+
+    /// Start `count` idle containers for `image`, opening its pool if needed; returns how
+    /// many were started
+    pub async fn prewarm(&self, image: &str, count: usize) -> Result<usize> {
+        self.prewarm_in(None, image, count).await
+    }
+
+    /// [`Self::prewarm`] for `namespace`'s own pool, which only its executions claim from
+    pub async fn prewarm_in(
+        &self,
+        namespace: Option<&str>,
+        image: &str,
+        count: usize,
+    ) -> Result<usize> {
+        if self.drain.is_draining() {
+            return Err(anyhow!("Not pre-warming {}: draining", image));
+        }
+        self.get_pool_in(namespace, image).await.warm(count).await
+    }
+
+    /// Keep between `min_size` and `max_size` containers for `image`, opening its pool if
+    /// needed; see [`ContainerPool::set_limits`]
+    pub async fn set_limits(
+        &self,
+        image: &str,
+        min_size: usize,
+        max_size: usize,
+    ) -> Result<PoolStats> {
+        self.set_limits_in(None, image, min_size, max_size).await
+    }
+
+    /// [`Self::set_limits`] for `namespace`'s own pool
+    pub async fn set_limits_in(
+        &self,
+        namespace: Option<&str>,
+        image: &str,
+        min_size: usize,
+        max_size: usize,
+    ) -> Result<PoolStats> {
+        let pool = self.get_pool_in(namespace, image).await;
+        pool.set_limits(min_size, max_size).await?;
+        Ok(pool.stats().await)
+    }
+
+    /// An idle container for `image`, if its pool has one; see [`ContainerPool::claim`]
+    pub async fn claim(&self, image: &str) -> Option<PooledContainer> {
+        self.claim_in(None, image).await
+    }
+
+    /// An idle container from `namespace`'s own pool for `image`
+    pub async fn claim_in(&self, namespace: Option<&str>, image: &str) -> Option<PooledContainer> {
+        let pool = self
+            .pools
+            .get(&pool_key(namespace, image))
+            .map(|p| p.clone())?;
+        pool.claim().await
+    }
+
+    /// Remove a container from [`Self::claim`] once it has been used
+    pub async fn retire(&self, container: PooledContainer) -> Result<()> {
+        let key = pool_key(container.namespace.as_deref(), &container.image);
+        if let Some(pool) = self.pools.get(&key).map(|p| p.clone()) {
+            pool.retire(container).await
+        } else {
+            Err(anyhow!("Pool not found for image: {}", container.image))
+        }
     }
 
     /// Predictive warming loop
@@ -296,6 +460,9 @@ impl ContainerPoolManager {
         let mut interval = tokio::time::interval(Duration::from_secs(30));
         loop {
             interval.tick().await;
+            if self.drain.is_draining() {
+                continue;
+            }
             if let Err(e) = self.predict_and_warm().await {
                 error!("Predictive warming failed: {}", e);
             }
@@ -320,9 +487,9 @@ impl ContainerPoolManager {
             let predicted_need = if hit_rate < 0.7 {
                 // Low hit rate, need more containers
                 in_use + 2
-            } else if in_use > available && available < pool.config.min_size {
+            } else if in_use > available && available < pool.min_size() {
                 // High usage, ensure minimum pool
-                pool.config.min_size
+                pool.min_size()
             } else {
                 available // Maintain current level
             };
@@ -344,6 +511,22 @@ impl ContainerPoolManager {
         Ok(())
     }
 
+    /// Run each environment's canary once its interval elapses
+    async fn canary_loop(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            if self.drain.is_draining() {
+                continue;
+            }
+            for image in self.canaries.due() {
+                if let Err(e) = self.run_canary_now(&image).await {
+                    error!("Canary for {} failed to run: {}", image, e);
+                }
+            }
+        }
+    }
+
     /// Health check loop
     async fn health_check_loop(&self) {
         let mut interval = tokio::time::interval(self.config.health_check_interval);
@@ -385,40 +568,104 @@ impl ContainerPoolManager {
     }
 }
 
+/// Shared pools are keyed by image, a tenant's own by a digest of the tenant and the image
+fn pool_key(namespace: Option<&str>, image: &str) -> String {
+    faas_common::cache_key::namespaced(namespace, image)
+}
+
 impl ContainerPool {
     pub fn new(docker: Arc<Docker>, image: String, config: PoolConfig) -> Self {
         Self {
             docker,
             image,
+            namespace: None,
             available: Arc::new(Mutex::new(VecDeque::new())),
             in_use: Arc::new(DashMap::new()),
             config: config.clone(),
+            min_size: AtomicUsize::new(config.min_size),
+            max_size: AtomicUsize::new(config.max_size),
             creation_semaphore: Arc::new(Semaphore::new(config.max_size)),
             total_created: Arc::new(RwLock::new(0)),
             hit_rate: Arc::new(RwLock::new(0.0)),
             avg_startup_ms: Arc::new(RwLock::new(0.0)),
             total_requests: Arc::new(RwLock::new(0)),
             cache_hits: Arc::new(RwLock::new(0)),
+            drain: Arc::new(DrainController::new()),
+            created_at: Instant::now(),
         }
     }
 
+    pub fn with_drain(mut self, drain: Arc<DrainController>) -> Self {
+        self.drain = drain;
+        self
+    }
+
+    /// Containers of a tenant's own pool, claimed only by its executions
+    pub fn with_namespace(mut self, namespace: Option<String>) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    fn min_size(&self) -> usize {
+        self.min_size.load(Ordering::Relaxed)
+    }
+
+    fn max_size(&self) -> usize {
+        self.max_size.load(Ordering::Relaxed)
+    }
+
+    /// Keep between `min_size` and `max_size` containers from now on; a max below the min
+    /// is raised to it. Idle containers past the max are removed and the pool is warmed up
+    /// to the min, unless draining.
+    pub async fn set_limits(&self, min_size: usize, max_size: usize) -> Result<()> {
+        let max_size = max_size.max(min_size);
+        self.min_size.store(min_size, Ordering::Relaxed);
+        self.max_size.store(max_size, Ordering::Relaxed);
+        let surplus: Vec<_> = {
+            let mut available = self.available.lock().await;
+            let keep = max_size
+                .saturating_sub(self.in_use.len())
+                .min(available.len());
+            available.drain(keep..).collect()
+        };
+        for container in &surplus {
+            self.terminate_container(container).await?;
+        }
+        if !self.drain.is_draining() {
+            let idle = self.available.lock().await.len();
+            self.warm(min_size.saturating_sub(idle)).await?;
+        }
+        Ok(())
+    }
+
     /// Pre-warm the pool with minimum containers
     pub async fn pre_warm(&self) -> Result<()> {
+        self.warm(self.min_size()).await.map(|_| ())
+    }
+
+    /// Start up to `count` more idle containers, never past `max_size`; returns how many
+    /// were started
+    pub async fn warm(&self, count: usize) -> Result<usize> {
+        let room = self
+            .max_size()
+            .saturating_sub(*self.total_created.read().await);
+        let count = count.min(room);
         info!(
             "Pre-warming pool for {} with {} containers",
-            self.image, self.config.min_size
+            self.image, count
         );
 
         let mut tasks = vec![];
-        for _ in 0..self.config.min_size {
+        for _ in 0..count {
             let docker = self.docker.clone();
             let image = self.image.clone();
+            let namespace = self.namespace.clone();
             let available = self.available.clone();
             let total_created = self.total_created.clone();
             let permit = self.creation_semaphore.clone().acquire_owned().await?;
 
             tasks.push(tokio::spawn(async move {
-                match Self::create_container_internal(docker, image).await {
+                match Self::create_container_internal(docker, image, namespace).await {
                     Ok(container) => {
                         available.lock().await.push_back(container);
                         *total_created.write().await += 1;
@@ -434,14 +681,23 @@ impl ContainerPool {
         }
 
         // Wait for all containers to be created
+        let mut started = 0;
+        let mut last_error = None;
         for task in tasks {
-            if let Err(e) = task.await? {
-                warn!("Failed to create container during pre-warm: {}", e);
+            match task.await? {
+                Ok(()) => started += 1,
+                Err(e) => {
+                    warn!("Failed to create container during pre-warm: {}", e);
+                    last_error = Some(e);
+                }
             }
         }
 
         info!("Pre-warming complete for {}", self.image);
-        Ok(())
+        match last_error {
+            Some(e) if started == 0 => Err(e),
+            _ => Ok(started),
+        }
     }
 
     /// Acquire a container from the pool
@@ -484,7 +740,7 @@ impl ContainerPool {
 
         // Need to create a new container
         let total = *self.total_created.read().await;
-        if total >= self.config.max_size {
+        if total >= self.max_size() {
             return Err(anyhow!(
                 "Container pool exhausted for image: {}",
                 self.image
@@ -494,8 +750,12 @@ impl ContainerPool {
         info!("Creating new container for {} (pool was empty)", self.image);
 
         let permit = self.creation_semaphore.clone().acquire_owned().await?;
-        let mut container =
-            Self::create_container_internal(self.docker.clone(), self.image.clone()).await?;
+        let mut container = Self::create_container_internal(
+            self.docker.clone(),
+            self.image.clone(),
+            self.namespace.clone(),
+        )
+        .await?;
 
         container.state = ContainerState::InUse;
         container.last_used = Some(Instant::now());
@@ -540,7 +800,7 @@ impl ContainerPool {
 
             // Create replacement if below min size
             let available_count = self.available.lock().await.len();
-            if available_count < self.config.min_size {
+            if available_count < self.min_size() && !self.drain.is_draining() {
                 let _ = self.create_replacement().await;
             }
 
@@ -555,10 +815,63 @@ impl ContainerPool {
         Ok(())
     }
 
+    /// An idle container, marked in use, or `None` when none is idle. Unlike
+    /// [`Self::acquire`] this never creates one; a `None` counts as a miss.
+    pub async fn claim(&self) -> Option<PooledContainer> {
+        let start = Instant::now();
+        let claimed = self.available.lock().await.pop_front();
+        let requests = {
+            let mut total = self.total_requests.write().await;
+            *total += 1;
+            *total
+        };
+        let hits = {
+            let mut hits = self.cache_hits.write().await;
+            *hits += u64::from(claimed.is_some());
+            *hits
+        };
+        *self.hit_rate.write().await = hits as f64 / requests as f64;
+
+        let mut container = claimed?;
+        container.state = ContainerState::InUse;
+        container.last_used = Some(Instant::now());
+        container.use_count += 1;
+        self.in_use.insert(container.id.clone(), container.clone());
+        self.update_avg_startup(start.elapsed().as_secs_f64() * 1000.0)
+            .await;
+        Some(container)
+    }
+
+    /// Remove a container that is done with instead of returning it to the pool
+    pub async fn retire(&self, container: PooledContainer) -> Result<()> {
+        self.in_use.remove(&container.id);
+        self.terminate_container(&container).await
+    }
+
+    /// Take an idle container out of rotation without counting it as a request
+    pub async fn take_idle(&self) -> Option<PooledContainer> {
+        self.available.lock().await.pop_front()
+    }
+
+    /// Put a container from [`Self::take_idle`] back, at the back of the queue
+    pub async fn return_idle(&self, container: PooledContainer) {
+        self.available.lock().await.push_back(container);
+    }
+
+    /// Remove a bad container and warm a replacement in its place
+    pub async fn quarantine(&self, container: PooledContainer) -> Result<()> {
+        self.terminate_container(&container).await?;
+        if !self.drain.is_draining() {
+            self.create_replacement().await?;
+        }
+        Ok(())
+    }
+
     /// Create a container and start it
     async fn create_container_internal(
         docker: Arc<Docker>,
         image: String,
+        namespace: Option<String>,
     ) -> Result<PooledContainer> {
         let container_name = format!("pool-{}-{}", image.replace(['/', ':'], "-"), Uuid::new_v4());
 
@@ -582,6 +895,7 @@ impl ContainerPool {
         let _: Vec<_> = image_pull.collect().await;
 
         // Create container with keep-alive command
+        let pool_id = Uuid::new_v4().to_string();
         let config = ContainerConfig {
             image: Some(image.clone()),
             cmd: Some(vec![
@@ -593,6 +907,12 @@ impl ContainerPool {
             attach_stdout: Some(true),
             attach_stderr: Some(true),
             tty: Some(false),
+            labels: Some(container_labels::labels(
+                ContainerKind::Pooled,
+                &pool_id,
+                &image,
+            )),
+            host_config: Some(crate::default_limits_host_config()),
             ..Default::default()
         };
 
@@ -612,9 +932,10 @@ impl ContainerPool {
             .await?;
 
         let container = PooledContainer {
-            id: Uuid::new_v4().to_string(),
+            id: pool_id,
             container_id: create_result.id,
             image,
+            namespace,
             created_at: Instant::now(),
             last_used: None,
             use_count: 0,
@@ -641,6 +962,8 @@ impl ContainerPool {
                 }),
             )
             .await?;
+        let mut total_created = self.total_created.write().await;
+        *total_created = total_created.saturating_sub(1);
 
         info!("Terminated container: {}", container.container_id);
         Ok(())
@@ -650,8 +973,12 @@ impl ContainerPool {
     pub async fn create_replacement(&self) -> Result<()> {
         let permit = self.creation_semaphore.clone().acquire_owned().await?;
 
-        let container =
-            Self::create_container_internal(self.docker.clone(), self.image.clone()).await?;
+        let container = Self::create_container_internal(
+            self.docker.clone(),
+            self.image.clone(),
+            self.namespace.clone(),
+        )
+        .await?;
 
         self.available.lock().await.push_back(container);
         *self.total_created.write().await += 1;
@@ -660,16 +987,54 @@ impl ContainerPool {
         Ok(())
     }
 
-    /// Clean up idle containers
+    /// The pool as it is now
+    pub async fn stats(&self) -> PoolStats {
+        let now = Instant::now();
+        let (available, oldest_idle) = {
+            let available = self.available.lock().await;
+            let oldest_idle = available
+                .iter()
+                .map(|c| now - c.last_used.unwrap_or(c.created_at))
+                .max();
+            (available.len(), oldest_idle)
+        };
+        let in_use = self.in_use.len();
+        let requests = *self.total_requests.read().await;
+        let hits = *self.cache_hits.read().await;
+        let avg_acquisition_ms = *self.avg_startup_ms.read().await;
+
+        PoolStats {
+            image: self.image.clone(),
+            namespace: self.namespace.clone(),
+            available,
+            in_use,
+            total: available + in_use,
+            age: now - self.created_at,
+            oldest_idle,
+            requests,
+            hits,
+            avg_acquisition_ms: (avg_acquisition_ms > 0.0).then_some(avg_acquisition_ms),
+            config: PoolConfig {
+                min_size: self.min_size(),
+                max_size: self.max_size(),
+                ..self.config.clone()
+            },
+        }
+    }
+
+    /// Clean up idle containers; while draining, every idle container goes regardless of
+    /// age or the pool's minimum size
     pub async fn cleanup_idle(&self) -> Result<usize> {
         let mut available = self.available.lock().await;
         let mut removed = 0;
         let now = Instant::now();
+        let draining = self.drain.is_draining();
+        let min_size = if draining { 0 } else { self.min_size() };
 
         while let Some(container) = available.front() {
             let idle_time = now - container.last_used.unwrap_or(container.created_at);
 
-            if idle_time > self.config.max_idle_time && available.len() > self.config.min_size {
+            if (draining || idle_time > self.config.max_idle_time) && available.len() > min_size {
                 let container = available.pop_front().unwrap();
                 drop(available);
 
@@ -693,8 +1058,21 @@ impl ContainerPool {
 #[derive(Debug, Clone)]
 pub struct PoolStats {
     pub image: String,
+    /// The tenant the pool belongs to; `None` for a shared pool
+    pub namespace: Option<String>,
     pub available: usize,
     pub in_use: usize,
     pub total: usize,
+    /// Time since the pool was opened
+    pub age: Duration,
+    /// How long the longest-idle container has waited; `None` when none is idle
+    pub oldest_idle: Option<Duration>,
+    /// Acquisitions and claims, and how many an idle container served
+    pub requests: u64,
+    pub hits: u64,
+    /// Moving average over the acquisitions that handed out a container; `None` before
+    /// the first
+    pub avg_acquisition_ms: Option<f64>,
+    /// With the current `min_size` and `max_size`
     pub config: PoolConfig,
 }
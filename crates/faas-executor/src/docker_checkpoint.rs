@@ -0,0 +1,293 @@
+//! CRIU checkpoint and restore of Docker containers.
+//!
+//! Docker checkpoints a running container with CRIU and can start another container from
+//! the checkpoint with the process's memory intact. It only does so on a daemon in
+//! experimental mode on a Linux host with CRIU installed, and bollard has no client for
+//! the checkpoint endpoints, so this drives the `docker` CLI on the same daemon.
+//!
+//! Checkpoints are written under one root directory, each in a directory named after it,
+//! outside any container so they outlive the container they were taken from.
+
+use crate::bollard::container::{
+    Config, CreateContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions,
+    WaitContainerOptions,
+};
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::Docker;
+use crate::image_pull;
+use faas_common::EnvVar;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("checkpointing unsupported on this host: {reason}")]
+    Unsupported { reason: String },
+    #[error("checkpoint {0} not found")]
+    NotFound(String),
+    #[error("docker {command} failed: {message}")]
+    Docker { command: String, message: String },
+}
+
+impl CheckpointError {
+    fn docker(command: &str, message: impl ToString) -> Self {
+        Self::Docker {
+            command: command.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// What a checkpointed container was started with, so a restore recreates it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointedContainer {
+    pub image: String,
+    pub command: Vec<String>,
+    pub env: Vec<EnvVar>,
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+impl From<&faas_common::SandboxConfig> for CheckpointedContainer {
+    fn from(config: &faas_common::SandboxConfig) -> Self {
+        Self {
+            image: config.source.clone(),
+            command: config.command.clone(),
+            env: config.env_vars.clone().unwrap_or_default(),
+            working_dir: config.working_dir.clone(),
+            user: config.user.clone(),
+        }
+    }
+}
+
+/// How a run under [`DockerCheckpoints::run`] ended
+#[derive(Debug)]
+pub struct CheckpointedRun {
+    /// Output of this run only; a restored process's earlier output went to the run it
+    /// was checkpointed in
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Set when the process exited within the window
+    pub exit_code: Option<i64>,
+    /// Where the process was checkpointed when it was still running at the end of the
+    /// window
+    pub checkpoint: Option<PathBuf>,
+}
+
+pub struct DockerCheckpoints {
+    docker: Arc<Docker>,
+    root: PathBuf,
+    support: OnceCell<Result<(), String>>,
+}
+
+impl DockerCheckpoints {
+    pub fn new(docker: Arc<Docker>, root: PathBuf) -> Self {
+        Self {
+            docker,
+            root,
+            support: OnceCell::new(),
+        }
+    }
+
+    /// Whether the daemon can checkpoint, probed once
+    pub async fn ensure_supported(&self) -> Result<(), CheckpointError> {
+        self.support
+            .get_or_init(probe)
+            .await
+            .clone()
+            .map_err(|reason| CheckpointError::Unsupported { reason })
+    }
+
+    /// Start `container` as `name`, resuming the checkpoint at `resume` if given, and wait
+    /// up to `window` for it to exit. A process still running then is checkpointed into a
+    /// directory named `checkpoint_as`, which stops it. The container is removed either
+    /// way.
+    pub async fn run(
+        &self,
+        name: &str,
+        container: &CheckpointedContainer,
+        resume: Option<&Path>,
+        window: Duration,
+        checkpoint_as: &str,
+    ) -> Result<CheckpointedRun, CheckpointError> {
+        self.ensure_supported().await?;
+        if let Some(resume) = resume {
+            if !resume.is_dir() {
+                return Err(CheckpointError::NotFound(resume.display().to_string()));
+            }
+        }
+        image_pull::ensure_image(
+            &self.docker,
+            &container.image,
+            &image_pull::PullSettings::from_env(),
+        )
+        .await
+        .map_err(|e| CheckpointError::docker("pull", e))?;
+        let created = self
+            .docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name,
+                    ..Default::default()
+                }),
+                Config {
+                    image: Some(container.image.clone()),
+                    cmd: Some(container.command.clone()),
+                    env: Some(container.env.iter().map(EnvVar::to_string).collect()),
+                    working_dir: container.working_dir.clone(),
+                    user: container.user.clone(),
+                    tty: Some(false),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| CheckpointError::docker("create", e))?;
+        let result = self
+            .run_created(&created.id, resume, window, checkpoint_as)
+            .await;
+        let removed = self
+            .docker
+            .remove_container(
+                &created.id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await;
+        if let Err(e) = removed {
+            warn!("Failed to remove checkpointed container {}: {}", name, e);
+        }
+        result
+    }
+
+    async fn run_created(
+        &self,
+        container_id: &str,
+        resume: Option<&Path>,
+        window: Duration,
+        checkpoint_as: &str,
+    ) -> Result<CheckpointedRun, CheckpointError> {
+        match resume {
+            Some(resume) => {
+                let dir = resume.parent().unwrap_or(&self.root);
+                let checkpoint = resume.file_name().unwrap_or_default();
+                let mut start = Command::new("docker");
+                start
+                    .arg("start")
+                    .arg("--checkpoint")
+                    .arg(checkpoint)
+                    .arg("--checkpoint-dir")
+                    .arg(dir)
+                    .arg(container_id);
+                docker_cli("start", start).await?;
+            }
+            None => self
+                .docker
+                .start_container::<String>(container_id, None)
+                .await
+                .map_err(|e| CheckpointError::docker("start", e))?,
+        }
+
+        let mut wait = self.docker.wait_container(
+            container_id,
+            Some(WaitContainerOptions {
+                condition: "not-running",
+            }),
+        );
+        let (exit_code, checkpoint) = match tokio::time::timeout(window, wait.next()).await {
+            Ok(Some(Ok(body))) => (Some(body.status_code), None),
+            Ok(Some(Err(BollardError::DockerContainerWaitError { code, .. }))) => {
+                (Some(code), None)
+            }
+            Ok(Some(Err(e))) => return Err(CheckpointError::docker("wait", e)),
+            Ok(None) => return Err(CheckpointError::docker("wait", "wait stream ended")),
+            Err(_) => (
+                None,
+                Some(self.checkpoint(container_id, checkpoint_as).await?),
+            ),
+        };
+
+        let (stdout, stderr) = self.output(container_id).await?;
+        Ok(CheckpointedRun {
+            stdout,
+            stderr,
+            exit_code,
+            checkpoint,
+        })
+    }
+
+    /// Checkpoint and stop the container into `<root>/<name>`
+    async fn checkpoint(&self, container_id: &str, name: &str) -> Result<PathBuf, CheckpointError> {
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .map_err(|e| CheckpointError::docker("checkpoint create", e))?;
+        let mut create = Command::new("docker");
+        create
+            .args(["checkpoint", "create", "--checkpoint-dir"])
+            .arg(&self.root)
+            .arg(container_id)
+            .arg(name);
+        docker_cli("checkpoint create", create).await?;
+        info!("Checkpointed container {} as {}", container_id, name);
+        Ok(self.root.join(name))
+    }
+
+    async fn output(&self, container_id: &str) -> Result<(Vec<u8>, Vec<u8>), CheckpointError> {
+        let mut logs = self.docker.logs::<String>(
+            container_id,
+            Some(LogsOptions {
+                stdout: true,
+                stderr: true,
+                ..Default::default()
+            }),
+        );
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        while let Some(entry) = logs.next().await {
+            match entry.map_err(|e| CheckpointError::docker("logs", e))? {
+                LogOutput::StdOut { message } => stdout.extend_from_slice(&message),
+                LogOutput::StdErr { message } => stderr.extend_from_slice(&message),
+                _ => {}
+            }
+        }
+        Ok((stdout, stderr))
+    }
+}
+
+async fn probe() -> Result<(), String> {
+    if !cfg!(target_os = "linux") {
+        return Err("CRIU checkpoints need a Linux host".to_string());
+    }
+    let experimental = Command::new("docker")
+        .args(["version", "--format", "{{.Server.Experimental}}"])
+        .output()
+        .await
+        .map_err(|e| format!("the docker CLI is unavailable: {e}"))?;
+    if String::from_utf8_lossy(&experimental.stdout).trim() != "true" {
+        return Err("the Docker daemon is not running in experimental mode".to_string());
+    }
+    match Command::new("criu").arg("--version").output().await {
+        Ok(output) if output.status.success() => Ok(()),
+        _ => Err("CRIU is not installed".to_string()),
+    }
+}
+
+async fn docker_cli(command: &str, mut cli: Command) -> Result<(), CheckpointError> {
+    let output = cli
+        .output()
+        .await
+        .map_err(|e| CheckpointError::docker(command, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(CheckpointError::docker(command, stderr.trim()));
+    }
+    Ok(())
+}
@@ -0,0 +1,736 @@
+//! Docker daemon connection management
+//! Lazily (re)connects to named daemon endpoints and places executions on the one whose
+//! capability tags match, so a daemon restart no longer needs a process restart.
+
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::{Docker, API_DEFAULT_VERSION};
+use crate::platform::arch::normalize_arch;
+use faas_common::Placement;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Request timeout for clients built by the pool, matching bollard's local defaults
+const CLIENT_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Error, Debug)]
+pub enum EndpointError {
+    #[error("No Docker endpoint matches placement {0:?}")]
+    NoMatchingEndpoint(Placement),
+    #[error("Unknown Docker endpoint: {0}")]
+    UnknownEndpoint(String),
+    #[error("Docker endpoint {name} is unavailable, next reconnect in {retry_in:?}")]
+    Unavailable { name: String, retry_in: Duration },
+    #[error("Failed to connect to Docker endpoint {name}: {source}")]
+    Connect {
+        name: String,
+        #[source]
+        source: BollardError,
+    },
+}
+
+/// Where a Docker daemon listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DockerAddress {
+    /// `DOCKER_HOST` or the platform's default socket
+    LocalDefaults,
+    Unix(PathBuf),
+    /// Plain HTTP, e.g. `tcp://10.0.0.5:2375`
+    Http(String),
+    /// HTTPS with the client certificate, key and CA in `certs` as `cert.pem`, `key.pem`
+    /// and `ca.pem`, e.g. `tcp://10.0.0.5:2376`
+    Tls {
+        address: String,
+        certs: PathBuf,
+    },
+}
+
+impl DockerAddress {
+    /// A socket path, `unix://` followed by one, or a `tcp://` or `http://` address
+    pub fn parse(address: &str) -> Option<Self> {
+        if let Some(path) = address.strip_prefix("unix://") {
+            return (!path.is_empty()).then(|| DockerAddress::Unix(PathBuf::from(path)));
+        }
+        if address.starts_with('/') {
+            return Some(DockerAddress::Unix(PathBuf::from(address)));
+        }
+        let host = address
+            .strip_prefix("tcp://")
+            .or_else(|| address.strip_prefix("http://"))?;
+        (!host.is_empty()).then(|| DockerAddress::Http(address.to_string()))
+    }
+
+    /// `DOCKER_HOST`, over TLS with the certificates in `DOCKER_CERT_PATH` (or `~/.docker`)
+    /// when `DOCKER_TLS_VERIFY` is set, or the platform's default socket without it
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let Some(host) = var("DOCKER_HOST").filter(|host| !host.is_empty()) else {
+            return DockerAddress::LocalDefaults;
+        };
+        let tls = var("DOCKER_TLS_VERIFY").is_some_and(|v| !v.is_empty() && v != "0");
+        match Self::parse(&host) {
+            Some(DockerAddress::Http(address)) if tls => DockerAddress::Tls {
+                address,
+                certs: var("DOCKER_CERT_PATH")
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| {
+                        PathBuf::from(var("HOME").unwrap_or_default()).join(".docker")
+                    }),
+            },
+            Some(address) => address,
+            // Other schemes, e.g. npipe://, are left to bollard's own DOCKER_HOST handling
+            None => DockerAddress::LocalDefaults,
+        }
+    }
+
+    pub fn connect(&self) -> Result<Docker, BollardError> {
+        match self {
+            DockerAddress::LocalDefaults => Docker::connect_with_local_defaults(),
+            DockerAddress::Unix(path) => Docker::connect_with_unix(
+                &path.to_string_lossy(),
+                CLIENT_TIMEOUT_SECS,
+                API_DEFAULT_VERSION,
+            ),
+            DockerAddress::Http(addr) => {
+                Docker::connect_with_http(addr, CLIENT_TIMEOUT_SECS, API_DEFAULT_VERSION)
+            }
+            DockerAddress::Tls { address, certs } => Docker::connect_with_ssl(
+                address,
+                &certs.join("key.pem"),
+                &certs.join("cert.pem"),
+                &certs.join("ca.pem"),
+                CLIENT_TIMEOUT_SECS,
+                API_DEFAULT_VERSION,
+            ),
+        }
+    }
+}
+
+/// Capabilities advertised by a daemon's host
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointTags {
+    pub gpu: bool,
+    pub arch: Option<String>,
+}
+
+impl EndpointTags {
+    pub fn satisfies(&self, placement: &Placement) -> bool {
+        (!placement.gpu || self.gpu)
+            && placement.arch.as_ref().is_none_or(|arch| {
+                self.arch.as_deref().map(normalize_arch) == Some(normalize_arch(arch))
+            })
+    }
+}
+
+/// Delay between reconnect attempts, doubling after each failure
+#[derive(Debug, Clone)]
+pub struct BackoffConfig {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(250),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+impl BackoffConfig {
+    fn delay(&self, failures: u32) -> Duration {
+        let factor = 1u32 << failures.saturating_sub(1).min(16);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// True for errors that mean the daemon could not be reached, as opposed to the daemon
+/// rejecting a request
+pub fn is_connection_error(err: &BollardError) -> bool {
+    matches!(
+        err,
+        BollardError::IOError { .. }
+            | BollardError::HyperLegacyError { .. }
+            | BollardError::HttpClientError { .. }
+            | BollardError::RequestTimeoutError
+            | BollardError::SocketNotFoundError(_)
+    )
+}
+
+#[derive(Debug)]
+struct ConnectionState {
+    address: DockerAddress,
+    client: Option<Arc<Docker>>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EndpointStats {
+    pub name: String,
+    pub tags: EndpointTags,
+    pub connected: bool,
+    pub selections: u64,
+    pub reconnects: u64,
+    pub connection_failures: u64,
+}
+
+/// A named Docker daemon whose client is rebuilt after connection failures
+#[derive(Debug)]
+pub struct DockerEndpoint {
+    name: String,
+    tags: EndpointTags,
+    backoff: BackoffConfig,
+    state: Mutex<ConnectionState>,
+    selections: AtomicU64,
+    reconnects: AtomicU64,
+    connection_failures: AtomicU64,
+}
+
+impl DockerEndpoint {
+    pub fn new(
+        name: impl Into<String>,
+        address: DockerAddress,
+        tags: EndpointTags,
+        backoff: BackoffConfig,
+    ) -> Self {
+        Self::with_state(name.into(), address, None, tags, backoff)
+    }
+
+    /// Wrap an already-built client; `address` is used if it ever has to be rebuilt
+    pub fn with_client(
+        name: impl Into<String>,
+        address: DockerAddress,
+        client: Arc<Docker>,
+        tags: EndpointTags,
+        backoff: BackoffConfig,
+    ) -> Self {
+        Self::with_state(name.into(), address, Some(client), tags, backoff)
+    }
+
+    fn with_state(
+        name: String,
+        address: DockerAddress,
+        client: Option<Arc<Docker>>,
+        tags: EndpointTags,
+        backoff: BackoffConfig,
+    ) -> Self {
+        Self {
+            name,
+            tags,
+            backoff,
+            state: Mutex::new(ConnectionState {
+                address,
+                client,
+                failures: 0,
+                retry_at: None,
+            }),
+            selections: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            connection_failures: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tags(&self) -> &EndpointTags {
+        &self.tags
+    }
+
+    /// Current client, connecting (and pinging) first if the last one was dropped
+    pub async fn client(&self) -> Result<Arc<Docker>, EndpointError> {
+        let mut state = self.state.lock().await;
+        if let Some(client) = &state.client {
+            return Ok(client.clone());
+        }
+
+        if let Some(retry_at) = state.retry_at {
+            let now = Instant::now();
+            if now < retry_at {
+                return Err(EndpointError::Unavailable {
+                    name: self.name.clone(),
+                    retry_in: retry_at - now,
+                });
+            }
+        }
+
+        let connected = match state.address.connect() {
+            Ok(docker) => docker.ping().await.map(|_| docker),
+            Err(e) => Err(e),
+        };
+        match connected {
+            Ok(docker) => {
+                let docker = Arc::new(docker);
+                if state.failures > 0 {
+                    self.reconnects.fetch_add(1, Ordering::Relaxed);
+                    info!(
+                        endpoint = %self.name,
+                        failures = state.failures,
+                        "Reconnected to Docker endpoint"
+                    );
+                }
+                state.client = Some(docker.clone());
+                state.failures = 0;
+                state.retry_at = None;
+                Ok(docker)
+            }
+            Err(e) => {
+                self.record_failure(&mut state, &e);
+                Err(EndpointError::Connect {
+                    name: self.name.clone(),
+                    source: e,
+                })
+            }
+        }
+    }
+
+    /// Feed back an error from an operation; connection errors drop the client so the
+    /// next call reconnects
+    pub async fn report_error(&self, err: &BollardError) {
+        if !is_connection_error(err) {
+            return;
+        }
+        let mut state = self.state.lock().await;
+        if state.client.take().is_some() {
+            self.record_failure(&mut state, err);
+        }
+    }
+
+    /// [`Self::client`], waiting out the reconnect backoff first if it ends within `within`.
+    /// A client that is still connected is returned as is.
+    pub async fn reconnect(&self, within: Duration) -> Result<Arc<Docker>, EndpointError> {
+        match self.client().await {
+            Err(EndpointError::Unavailable { retry_in, .. }) if retry_in <= within => {
+                tokio::time::sleep(retry_in).await;
+                self.client().await
+            }
+            result => result,
+        }
+    }
+
+    /// Ping the current client, dropping it if the daemon is unreachable
+    pub async fn check_health(&self) -> bool {
+        let client = match self.client().await {
+            Ok(client) => client,
+            Err(_) => return false,
+        };
+        match client.ping().await {
+            Ok(_) => true,
+            Err(e) => {
+                self.report_error(&e).await;
+                false
+            }
+        }
+    }
+
+    /// Point the endpoint at a different daemon; the next call connects to it immediately
+    pub async fn set_address(&self, address: DockerAddress) {
+        let mut state = self.state.lock().await;
+        info!(endpoint = %self.name, ?address, "Docker endpoint address changed");
+        state.address = address;
+        state.client = None;
+        state.retry_at = None;
+    }
+
+    pub async fn stats(&self) -> EndpointStats {
+        EndpointStats {
+            name: self.name.clone(),
+            tags: self.tags.clone(),
+            connected: self.state.lock().await.client.is_some(),
+            selections: self.selections.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            connection_failures: self.connection_failures.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_failure(&self, state: &mut ConnectionState, err: &BollardError) {
+        state.failures += 1;
+        let delay = self.backoff.delay(state.failures);
+        state.retry_at = Some(Instant::now() + delay);
+        self.connection_failures.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            endpoint = %self.name,
+            failures = state.failures,
+            retry_in = ?delay,
+            "Docker endpoint unreachable: {}",
+            err
+        );
+    }
+
+    fn in_backoff(&self) -> bool {
+        match self.state.try_lock() {
+            Ok(state) => {
+                state.client.is_none() && state.retry_at.is_some_and(|at| Instant::now() < at)
+            }
+            // Someone is connecting right now; don't count it as down.
+            Err(_) => false,
+        }
+    }
+}
+
+/// Named Docker daemons the executor can place work on
+#[derive(Debug, Default)]
+pub struct DockerEndpointPool {
+    endpoints: RwLock<Vec<Arc<DockerEndpoint>>>,
+    next: AtomicUsize,
+}
+
+impl DockerEndpointPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A pool holding one untagged endpoint around an existing client, rebuilt from
+    /// `DOCKER_HOST` if the connection is lost
+    pub fn single(client: Arc<Docker>) -> Self {
+        Self::single_at(DockerAddress::from_env(), client)
+    }
+
+    /// [`Self::single`] for a client connected to `address`
+    pub fn single_at(address: DockerAddress, client: Arc<Docker>) -> Self {
+        let pool = Self::new();
+        pool.insert(DockerEndpoint::with_client(
+            "default",
+            address,
+            client,
+            EndpointTags::default(),
+            BackoffConfig::default(),
+        ));
+        pool
+    }
+
+    /// Add or replace an endpoint by name
+    pub fn insert(&self, endpoint: DockerEndpoint) -> Arc<DockerEndpoint> {
+        let endpoint = Arc::new(endpoint);
+        let mut endpoints = self.endpoints.write().unwrap();
+        endpoints.retain(|e| e.name != endpoint.name);
+        endpoints.push(endpoint.clone());
+        endpoint
+    }
+
+    pub fn add(
+        &self,
+        name: impl Into<String>,
+        address: DockerAddress,
+        tags: EndpointTags,
+    ) -> Arc<DockerEndpoint> {
+        self.insert(DockerEndpoint::new(
+            name,
+            address,
+            tags,
+            BackoffConfig::default(),
+        ))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<DockerEndpoint>> {
+        self.endpoints
+            .read()
+            .unwrap()
+            .iter()
+            .find(|e| e.name == name)
+            .cloned()
+    }
+
+    pub async fn set_address(
+        &self,
+        name: &str,
+        address: DockerAddress,
+    ) -> Result<(), EndpointError> {
+        let endpoint = self
+            .get(name)
+            .ok_or_else(|| EndpointError::UnknownEndpoint(name.to_string()))?;
+        endpoint.set_address(address).await;
+        Ok(())
+    }
+
+    /// Whether any configured endpoint could ever take work with `placement`
+    pub fn can_satisfy(&self, placement: &Placement) -> bool {
+        let endpoints = self.endpoints.read().unwrap();
+        endpoints.iter().any(|e| e.tags.satisfies(placement))
+    }
+
+    /// Pick an endpoint for `placement`
+    ///
+    /// GPU hosts are only used for work that needs them while a CPU host can take it, and
+    /// endpoints waiting out a reconnect backoff are skipped while others are available.
+    pub fn select(&self, placement: &Placement) -> Result<Arc<DockerEndpoint>, EndpointError> {
+        let endpoints = self.endpoints.read().unwrap();
+        let matching: Vec<_> = endpoints
+            .iter()
+            .filter(|e| e.tags.satisfies(placement))
+            .collect();
+        if matching.is_empty() {
+            return Err(EndpointError::NoMatchingEndpoint(placement.clone()));
+        }
+
+        let preferred: Vec<_> = if placement.gpu {
+            matching.clone()
+        } else {
+            let cpu_only: Vec<_> = matching.iter().copied().filter(|e| !e.tags.gpu).collect();
+            if cpu_only.is_empty() {
+                matching.clone()
+            } else {
+                cpu_only
+            }
+        };
+        let available: Vec<_> = preferred
+            .iter()
+            .copied()
+            .filter(|e| !e.in_backoff())
+            .collect();
+        let candidates = if available.is_empty() {
+            &preferred
+        } else {
+            &available
+        };
+
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+        let endpoint = candidates[index].clone();
+        endpoint.selections.fetch_add(1, Ordering::Relaxed);
+        debug!(endpoint = %endpoint.name, ?placement, "Selected Docker endpoint");
+        Ok(endpoint)
+    }
+
+    /// Select an endpoint for `placement` and hand back its connected client
+    pub async fn client_for(
+        &self,
+        placement: &Placement,
+    ) -> Result<(Arc<DockerEndpoint>, Arc<Docker>), EndpointError> {
+        let endpoint = self.select(placement)?;
+        let client = endpoint.client().await?;
+        Ok((endpoint, client))
+    }
+
+    pub async fn stats(&self) -> Vec<EndpointStats> {
+        let endpoints: Vec<_> = self.endpoints.read().unwrap().clone();
+        let mut stats = Vec::with_capacity(endpoints.len());
+        for endpoint in endpoints {
+            stats.push(endpoint.stats().await);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+
+    fn fast_backoff() -> BackoffConfig {
+        BackoffConfig {
+            initial: Duration::from_millis(20),
+            max: Duration::from_millis(20),
+        }
+    }
+
+    fn socket_path() -> PathBuf {
+        std::env::temp_dir().join(format!("faas-docker-{}.sock", uuid::Uuid::new_v4()))
+    }
+
+    /// Answers every request with `OK`, which is all `Docker::ping` needs
+    fn fake_daemon(path: &std::path::Path) -> tokio::task::JoinHandle<()> {
+        let listener = UnixListener::bind(path).unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    while let Ok(n) = stream.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        let reply = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nOK";
+                        if stream.write_all(reply.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn recovers_when_daemon_comes_back() {
+        let path = socket_path();
+        let endpoint = DockerEndpoint::new(
+            "local",
+            DockerAddress::Unix(path.clone()),
+            EndpointTags::default(),
+            fast_backoff(),
+        );
+
+        assert!(matches!(
+            endpoint.client().await,
+            Err(EndpointError::Connect { .. })
+        ));
+        // Inside the backoff window we don't hammer the daemon.
+        assert!(matches!(
+            endpoint.client().await,
+            Err(EndpointError::Unavailable { .. })
+        ));
+
+        let daemon = fake_daemon(&path);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(endpoint.client().await.is_ok());
+        assert!(endpoint.check_health().await);
+
+        let stats = endpoint.stats().await;
+        assert!(stats.connected);
+        assert_eq!(stats.reconnects, 1);
+        assert_eq!(stats.connection_failures, 1);
+
+        daemon.abort();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn swapping_the_address_restores_a_dead_endpoint() {
+        let good = socket_path();
+        let daemon = fake_daemon(&good);
+
+        let pool = DockerEndpointPool::new();
+        pool.insert(DockerEndpoint::new(
+            "cpu",
+            DockerAddress::Unix(PathBuf::from("/nonexistent/docker.sock")),
+            EndpointTags::default(),
+            fast_backoff(),
+        ));
+        assert!(pool.client_for(&Placement::default()).await.is_err());
+
+        pool.set_address("cpu", DockerAddress::Unix(good.clone()))
+            .await
+            .unwrap();
+        let (endpoint, _) = pool.client_for(&Placement::default()).await.unwrap();
+
+        // A connection-class error on a live client also forces a reconnect.
+        endpoint
+            .report_error(&BollardError::SocketNotFoundError("gone".to_string()))
+            .await;
+        assert!(!endpoint.stats().await.connected);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(pool.client_for(&Placement::default()).await.is_ok());
+
+        assert!(matches!(
+            pool.set_address("missing", DockerAddress::LocalDefaults)
+                .await,
+            Err(EndpointError::UnknownEndpoint(_))
+        ));
+
+        daemon.abort();
+        let _ = std::fs::remove_file(&good);
+    }
+
+    #[test]
+    fn gpu_requests_go_to_the_gpu_endpoint() {
+        let pool = DockerEndpointPool::new();
+        pool.add(
+            "cpu",
+            DockerAddress::LocalDefaults,
+            EndpointTags {
+                gpu: false,
+                arch: Some("x86_64".to_string()),
+            },
+        );
+        pool.add(
+            "gpu",
+            DockerAddress::LocalDefaults,
+            EndpointTags {
+                gpu: true,
+                arch: Some("x86_64".to_string()),
+            },
+        );
+
+        let gpu = Placement {
+            gpu: true,
+            arch: None,
+        };
+        for _ in 0..4 {
+            assert_eq!(pool.select(&gpu).unwrap().name(), "gpu");
+            // CPU work stays off the GPU host.
+            assert_eq!(pool.select(&Placement::default()).unwrap().name(), "cpu");
+        }
+
+        let arm = Placement {
+            gpu: false,
+            arch: Some("aarch64".to_string()),
+        };
+        assert!(matches!(
+            pool.select(&arm),
+            Err(EndpointError::NoMatchingEndpoint(_))
+        ));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let backoff = BackoffConfig {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(500),
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(10), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn docker_host_with_tls_verify_uses_the_cert_path() {
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        assert_eq!(
+            DockerAddress::from_vars(vars(&[])),
+            DockerAddress::LocalDefaults
+        );
+        assert_eq!(
+            DockerAddress::from_vars(vars(&[("DOCKER_HOST", "unix:///run/docker.sock")])),
+            DockerAddress::Unix(PathBuf::from("/run/docker.sock"))
+        );
+        assert_eq!(
+            DockerAddress::from_vars(vars(&[
+                ("DOCKER_HOST", "tcp://10.0.0.5:2376"),
+                ("DOCKER_TLS_VERIFY", "1"),
+                ("DOCKER_CERT_PATH", "/etc/faas/docker"),
+            ])),
+            DockerAddress::Tls {
+                address: "tcp://10.0.0.5:2376".to_string(),
+                certs: PathBuf::from("/etc/faas/docker"),
+            }
+        );
+        assert_eq!(
+            DockerAddress::from_vars(vars(&[
+                ("DOCKER_HOST", "tcp://10.0.0.5:2376"),
+                ("DOCKER_TLS_VERIFY", "1"),
+                ("HOME", "/home/faas"),
+            ])),
+            DockerAddress::Tls {
+                address: "tcp://10.0.0.5:2376".to_string(),
+                certs: PathBuf::from("/home/faas/.docker"),
+            }
+        );
+        assert_eq!(
+            DockerAddress::from_vars(vars(&[
+                ("DOCKER_HOST", "tcp://10.0.0.5:2375"),
+                ("DOCKER_TLS_VERIFY", "0"),
+            ])),
+            DockerAddress::Http("tcp://10.0.0.5:2375".to_string())
+        );
+    }
+}
@@ -271,6 +271,19 @@ impl DockerForkManager {
         Ok(())
     }
 
+    /// The committed image of `parent_id`, or `None` when no execution by that id left one
+    pub async fn branch_parent(&self, parent_id: &str) -> Result<Option<String>> {
+        let image = branch_image(parent_id);
+        match self.docker.inspect_image(&image).await {
+            Ok(_) => Ok(Some(image)),
+            Err(crate::bollard::errors::Error::DockerResponseServerError {
+                status_code: 404,
+                ..
+            }) => Ok(None),
+            Err(e) => Err(anyhow!("Failed to look up branch parent {parent_id}: {e}")),
+        }
+    }
+
     /// Get stats about current forks
     pub async fn get_fork_stats(&self) -> ForkStats {
         let branches = self.branches.read().await;
@@ -283,6 +296,19 @@ impl DockerForkManager {
     }
 }
 
+/// Image a branched or persistent execution's filesystem is committed to; its branches
+/// start from it
+pub fn branch_image(execution_id: &str) -> String {
+    let repo: String = execution_id
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '-' | '_' | '.') => c,
+            _ => '-',
+        })
+        .collect();
+    format!("faas-branch-{repo}:latest")
+}
+
 #[derive(Debug)]
 pub struct ForkStats {
     pub active_forks: usize,
@@ -293,6 +319,15 @@ pub struct ForkStats {
 mod tests {
     use super::*;
 
+    #[test]
+    fn branch_images_are_valid_references_per_execution() {
+        assert_eq!(
+            branch_image("0b7e3e4c-Parent_1"),
+            "faas-branch-0b7e3e4c-parent_1:latest"
+        );
+        assert_eq!(branch_image("a/b:c"), "faas-branch-a-b-c:latest");
+    }
+
     #[tokio::test]
     async fn test_real_docker_fork() -> Result<()> {
         // This test requires Docker to be running
@@ -1,15 +1,17 @@
 //! Real Docker snapshot implementation using commit and proper state management
 //! No more mocks - actual Docker operations for production use
 
-use crate::bollard::container::Config as ContainerConfig;
-use crate::bollard::image::CommitContainerOptions;
+use crate::bollard::container::{Config as ContainerConfig, InspectContainerOptions};
+use crate::bollard::image::{CommitContainerOptions, ListImagesOptions};
 use crate::bollard::Docker;
+use crate::snapshot_inspect::DockerImageInspector;
 use anyhow::{anyhow, Context, Result};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::info;
+use tokio::sync::{watch, RwLock};
+use tracing::{debug, info};
 use uuid::Uuid;
 
 /// Docker-based snapshot with actual commit operations
@@ -21,10 +23,115 @@ pub struct DockerSnapshot {
     pub name: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub size_bytes: i64,
+    /// Bytes the commit added on top of the container's image
+    #[serde(default)]
+    pub layer_bytes: i64,
     pub metadata: HashMap<String, String>,
     pub parent_snapshot: Option<String>,
 }
 
+/// Prefix of the image labels a snapshot's record is kept in, so it outlives the process
+const LABEL_PREFIX: &str = "faas.snapshot.";
+const METADATA_LABEL_PREFIX: &str = "faas.snapshot.meta.";
+
+impl DockerSnapshot {
+    fn labels(&self) -> HashMap<String, String> {
+        let mut labels: HashMap<String, String> = self
+            .metadata
+            .iter()
+            .map(|(key, value)| (format!("{METADATA_LABEL_PREFIX}{key}"), value.clone()))
+            .collect();
+        labels.insert(format!("{LABEL_PREFIX}id"), self.id.clone());
+        labels.insert(
+            format!("{LABEL_PREFIX}container"),
+            self.container_id.clone(),
+        );
+        labels.insert(
+            format!("{LABEL_PREFIX}created_at"),
+            self.created_at.to_rfc3339(),
+        );
+        if let Some(name) = &self.name {
+            labels.insert(format!("{LABEL_PREFIX}name"), name.clone());
+        }
+        labels
+    }
+
+    /// The record [`labels`](Self::labels) wrote onto a committed image
+    fn from_labels(
+        image_id: String,
+        labels: &HashMap<String, String>,
+        size_bytes: i64,
+    ) -> Option<Self> {
+        let label = |key: &str| labels.get(&format!("{LABEL_PREFIX}{key}")).cloned();
+        let metadata: HashMap<String, String> = labels
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = key.strip_prefix(METADATA_LABEL_PREFIX)?;
+                Some((key.to_string(), value.clone()))
+            })
+            .collect();
+        Some(Self {
+            id: label("id")?,
+            image_id,
+            container_id: label("container").unwrap_or_default(),
+            name: label("name"),
+            created_at: label("created_at")
+                .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+                .map(|at| at.with_timezone(&chrono::Utc))
+                .unwrap_or_default(),
+            size_bytes,
+            layer_bytes: 0,
+            parent_snapshot: metadata.get("parent_snapshot").cloned(),
+            metadata,
+        })
+    }
+}
+
+/// Size a snapshot of a container is expected to take, from before it is created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeEstimate {
+    pub base_image_bytes: u64,
+    /// Bytes written to the container's writable layer
+    pub changed_bytes: u64,
+    /// Paths added, changed or deleted since the container started
+    pub changed_paths: usize,
+}
+
+impl SizeEstimate {
+    pub fn total_bytes(&self) -> u64 {
+        self.base_image_bytes + self.changed_bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotPhase {
+    /// Sizing the container and checking quota
+    Preflight,
+    Committing,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotProgress {
+    pub phase: SnapshotPhase,
+    /// Filesystem bytes processed so far
+    pub bytes: u64,
+    /// Share of the preflight estimate processed, held below 100 until the commit returns
+    pub percent_estimate: Option<f64>,
+}
+
+impl SnapshotProgress {
+    pub fn preflight() -> Self {
+        Self {
+            phase: SnapshotPhase::Preflight,
+            bytes: 0,
+            percent_estimate: Some(0.0),
+        }
+    }
+}
+
 /// Manages Docker snapshots with real commit/restore operations
 pub struct DockerSnapshotManager {
     docker: Arc<Docker>,
@@ -67,10 +174,28 @@ impl DockerSnapshotManager {
             ..Default::default()
         };
 
+        let mut snapshot = DockerSnapshot {
+            id: snapshot_id.clone(),
+            image_id: String::new(),
+            container_id: container_id.to_string(),
+            name,
+            created_at: chrono::Utc::now(),
+            size_bytes: 0,
+            layer_bytes: 0,
+            metadata,
+            parent_snapshot: None,
+        };
+
         // Perform the actual Docker commit
         let commit_result = self
             .docker
-            .commit_container(options, ContainerConfig::<String>::default())
+            .commit_container(
+                options,
+                ContainerConfig {
+                    labels: Some(snapshot.labels()),
+                    ..Default::default()
+                },
+            )
             .await
             .context("Failed to commit container")?;
 
@@ -85,17 +210,9 @@ impl DockerSnapshotManager {
             .context("Failed to inspect committed image")?;
 
         let size_bytes = image_info.size.unwrap_or(0);
-
-        let snapshot = DockerSnapshot {
-            id: snapshot_id.clone(),
-            image_id: image_id.clone(),
-            container_id: container_id.to_string(),
-            name,
-            created_at: chrono::Utc::now(),
-            size_bytes,
-            metadata,
-            parent_snapshot: None,
-        };
+        snapshot.image_id = image_id.clone();
+        snapshot.size_bytes = size_bytes;
+        snapshot.layer_bytes = self.top_layer_bytes(&image_name).await?;
 
         // Store snapshot metadata
         self.snapshots
@@ -111,6 +228,106 @@ impl DockerSnapshotManager {
         Ok(snapshot)
     }
 
+    /// Estimate a snapshot's size from the base image and the container's changes
+    pub async fn estimate_size(&self, container_id: &str) -> Result<SizeEstimate> {
+        let container = self
+            .docker
+            .inspect_container(container_id, Some(InspectContainerOptions { size: true }))
+            .await
+            .context("Failed to inspect container")?;
+        let base_image_bytes = match container.image.as_deref() {
+            Some(image) => self
+                .docker
+                .inspect_image(image)
+                .await
+                .context("Failed to inspect base image")?
+                .size
+                .unwrap_or_default()
+                .max(0) as u64,
+            None => 0,
+        };
+        let changed_paths = self
+            .docker
+            .container_changes(container_id)
+            .await
+            .context("Failed to get container changes")?
+            .map_or(0, |changes| changes.len());
+        Ok(SizeEstimate {
+            base_image_bytes,
+            changed_bytes: container.size_rw.unwrap_or_default().max(0) as u64,
+            changed_paths,
+        })
+    }
+
+    /// [`create_snapshot`](Self::create_snapshot), publishing progress while it runs.
+    ///
+    /// A commit reports nothing until it finishes, so the container's filesystem is streamed
+    /// through `docker export` alongside it and the bytes read stand in for the commit's
+    /// progress. The export stops as soon as the commit returns.
+    pub async fn create_snapshot_with_progress(
+        &self,
+        container_id: &str,
+        name: Option<String>,
+        metadata: HashMap<String, String>,
+        estimate_bytes: u64,
+        progress: &watch::Sender<SnapshotProgress>,
+    ) -> Result<DockerSnapshot> {
+        progress.send_replace(SnapshotProgress {
+            phase: SnapshotPhase::Committing,
+            bytes: 0,
+            percent_estimate: Some(0.0),
+        });
+        let commit = self.create_snapshot(container_id, name, metadata);
+        tokio::pin!(commit);
+        let measure = self.measure_export(container_id, estimate_bytes, progress);
+        let result = tokio::select! {
+            result = &mut commit => result,
+            // The export can finish first; the commit is the real work
+            () = measure => commit.await,
+        };
+
+        let last = progress.borrow().bytes;
+        progress.send_replace(match &result {
+            Ok(snapshot) => SnapshotProgress {
+                phase: SnapshotPhase::Done,
+                bytes: last.max(snapshot.size_bytes.max(0) as u64),
+                percent_estimate: Some(100.0),
+            },
+            Err(_) => SnapshotProgress {
+                phase: SnapshotPhase::Failed,
+                bytes: last,
+                percent_estimate: None,
+            },
+        });
+        result
+    }
+
+    async fn measure_export(
+        &self,
+        container_id: &str,
+        estimate_bytes: u64,
+        progress: &watch::Sender<SnapshotProgress>,
+    ) {
+        let mut export = self.docker.export_container(container_id);
+        let mut bytes = 0u64;
+        while let Some(chunk) = export.next().await {
+            match chunk {
+                Ok(chunk) => bytes += chunk.len() as u64,
+                Err(e) => {
+                    debug!("Export of {} stopped early: {}", container_id, e);
+                    break;
+                }
+            }
+            let percent = (estimate_bytes > 0)
+                .then(|| (bytes as f64 / estimate_bytes as f64 * 100.0).min(99.0));
+            progress.send_replace(SnapshotProgress {
+                phase: SnapshotPhase::Committing,
+                bytes,
+                percent_estimate: percent,
+            });
+        }
+    }
+
     /// Restore a container from snapshot (real Docker run from committed image)
     pub async fn restore_snapshot(&self, snapshot_id: &str) -> Result<String> {
         let snapshots = self.snapshots.read().await;
@@ -200,6 +417,48 @@ impl DockerSnapshotManager {
         self.snapshots.read().await.values().cloned().collect()
     }
 
+    /// Snapshots committed on this daemon by any manager, including ones before a restart
+    ///
+    /// Each is registered here as well, so it can be restored and browsed by id.
+    pub async fn load_committed(&self) -> Result<Vec<DockerSnapshot>> {
+        let images = self
+            .docker
+            .list_images(Some(ListImagesOptions {
+                filters: HashMap::from([("label", vec!["faas.snapshot.id"])]),
+                ..Default::default()
+            }))
+            .await
+            .context("Failed to list snapshot images")?;
+        let mut committed = Vec::new();
+        for image in images {
+            let Some(mut snapshot) =
+                DockerSnapshot::from_labels(image.id.clone(), &image.labels, image.size)
+            else {
+                continue;
+            };
+            snapshot.layer_bytes = self.top_layer_bytes(&image.id).await?;
+            committed.push(snapshot);
+        }
+
+        let mut snapshots = self.snapshots.write().await;
+        for snapshot in &committed {
+            snapshots
+                .entry(snapshot.id.clone())
+                .or_insert_with(|| snapshot.clone());
+        }
+        Ok(committed)
+    }
+
+    /// Size of the newest layer of `image`, which for a commit is what the container wrote
+    async fn top_layer_bytes(&self, image: &str) -> Result<i64> {
+        let history = self
+            .docker
+            .image_history(image)
+            .await
+            .context("Failed to read image history")?;
+        Ok(history.first().map_or(0, |layer| layer.size.max(0)))
+    }
+
     /// Delete a snapshot (remove the committed image)
     pub async fn delete_snapshot(&self, snapshot_id: &str) -> Result<()> {
         let mut snapshots = self.snapshots.write().await;
@@ -228,11 +487,61 @@ impl DockerSnapshotManager {
         }
     }
 
+    /// Remove a snapshot by its committed image, for callers that track snapshots themselves
+    pub async fn delete_image(&self, image_id: &str) -> Result<()> {
+        self.docker
+            .remove_image(
+                image_id,
+                Some(crate::bollard::image::RemoveImageOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+                None,
+            )
+            .await
+            .context("Failed to remove snapshot image")?;
+        self.snapshots
+            .write()
+            .await
+            .retain(|_, snapshot| snapshot.image_id != image_id);
+        Ok(())
+    }
+
     /// Get snapshot metadata
     pub async fn get_snapshot(&self, snapshot_id: &str) -> Option<DockerSnapshot> {
         self.snapshots.read().await.get(snapshot_id).cloned()
     }
 
+    /// List files inside a snapshot's image without starting it
+    pub async fn list_files(
+        &self,
+        snapshot_id: &str,
+        path: &str,
+        depth: usize,
+    ) -> Result<crate::snapshot_inspect::Listing> {
+        let image = self.snapshot_image(snapshot_id).await?;
+        Ok(DockerImageInspector::new(self.docker.clone())
+            .list(&image, path, depth)
+            .await?)
+    }
+
+    /// Read a small file from a snapshot's image
+    pub async fn read_file(&self, snapshot_id: &str, path: &str, limit: u64) -> Result<Vec<u8>> {
+        let image = self.snapshot_image(snapshot_id).await?;
+        Ok(DockerImageInspector::new(self.docker.clone())
+            .cat(&image, path, limit)
+            .await?)
+    }
+
+    async fn snapshot_image(&self, snapshot_id: &str) -> Result<String> {
+        self.snapshots
+            .read()
+            .await
+            .get(snapshot_id)
+            .map(|s| s.image_id.clone())
+            .ok_or_else(|| anyhow!("Snapshot {snapshot_id} not found"))
+    }
+
     /// Create incremental snapshot (diff from parent)
     pub async fn create_incremental_snapshot(
         &self,
@@ -276,6 +585,37 @@ impl DockerSnapshotManager {
 mod tests {
     use super::*;
 
+    #[test]
+    fn a_snapshot_record_survives_its_image_labels() {
+        let snapshot = DockerSnapshot {
+            id: "snap-1".to_string(),
+            image_id: "sha256:abc".to_string(),
+            container_id: "c-1".to_string(),
+            name: Some("before-upgrade".to_string()),
+            created_at: chrono::Utc::now(),
+            size_bytes: 7_000_000,
+            layer_bytes: 4096,
+            metadata: HashMap::from([
+                ("tenant".to_string(), "team-a".to_string()),
+                ("parent_snapshot".to_string(), "snap-0".to_string()),
+            ]),
+            parent_snapshot: None,
+        };
+        let mut labels = snapshot.labels();
+        labels.insert("maintainer".to_string(), "someone".to_string());
+
+        let loaded =
+            DockerSnapshot::from_labels("sha256:abc".to_string(), &labels, 7_000_000).unwrap();
+        assert_eq!(loaded.id, "snap-1");
+        assert_eq!(loaded.container_id, "c-1");
+        assert_eq!(loaded.name.as_deref(), Some("before-upgrade"));
+        assert_eq!(loaded.created_at, snapshot.created_at);
+        assert_eq!(loaded.metadata, snapshot.metadata);
+        assert_eq!(loaded.parent_snapshot.as_deref(), Some("snap-0"));
+
+        assert!(DockerSnapshot::from_labels(String::new(), &HashMap::new(), 0).is_none());
+    }
+
     #[tokio::test]
     async fn test_real_snapshot_create_restore() {
         let docker = Arc::new(Docker::connect_with_defaults().unwrap());
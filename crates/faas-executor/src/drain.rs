@@ -0,0 +1,375 @@
+//! Drain mode for host maintenance.
+//!
+//! While draining, new executions and snapshot writes are refused, warm pools stop refilling,
+//! and the work already running is counted down until it finishes or the deadline passes.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrainPhase {
+    Active,
+    Draining,
+    /// Nothing is running and no new work is admitted
+    Drained,
+}
+
+/// Returned instead of admitting work while the host drains
+#[derive(Debug, Clone, Copy, Error)]
+#[error("host is draining for maintenance")]
+pub struct Draining;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainOutcome {
+    /// Everything in flight finished
+    Idle,
+    DeadlineReached,
+    /// The host was undrained (or drained again) while waiting
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrainStatus {
+    pub phase: DrainPhase,
+    pub started_at: Option<DateTime<Utc>>,
+    pub deadline: Option<DateTime<Utc>>,
+    pub in_flight_executions: usize,
+    pub in_flight_operations: usize,
+    /// Estimated time until in-flight work finishes, capped at the deadline
+    pub eta_ms: Option<u64>,
+}
+
+struct DrainWindow {
+    phase: DrainPhase,
+    epoch: u64,
+    started_at: Option<DateTime<Utc>>,
+    deadline: Option<(Instant, DateTime<Utc>)>,
+}
+
+/// Admission gate and in-flight accounting shared by the executor, pools and gateway
+pub struct DrainController {
+    window: Mutex<DrainWindow>,
+    executions: AtomicUsize,
+    operations: AtomicUsize,
+    next_execution: AtomicU64,
+    started: Mutex<HashMap<u64, Instant>>,
+    /// Moving average of execution durations, for the ETA
+    avg_execution_ms: Mutex<Option<f64>>,
+    settled: Notify,
+}
+
+impl Default for DrainController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrainController {
+    pub fn new() -> Self {
+        Self {
+            window: Mutex::new(DrainWindow {
+                phase: DrainPhase::Active,
+                epoch: 0,
+                started_at: None,
+                deadline: None,
+            }),
+            executions: AtomicUsize::new(0),
+            operations: AtomicUsize::new(0),
+            next_execution: AtomicU64::new(0),
+            started: Mutex::new(HashMap::new()),
+            avg_execution_ms: Mutex::new(None),
+            settled: Notify::new(),
+        }
+    }
+
+    pub fn phase(&self) -> DrainPhase {
+        self.window.lock().unwrap().phase
+    }
+
+    /// True while draining and once drained; refills and scale-ups check this.
+    pub fn is_draining(&self) -> bool {
+        self.phase() != DrainPhase::Active
+    }
+
+    /// Admit an execution; the guard keeps it counted until dropped.
+    pub fn admit(self: &Arc<Self>) -> Result<ExecutionGuard, Draining> {
+        if self.is_draining() {
+            return Err(Draining);
+        }
+        let id = self.next_execution.fetch_add(1, Ordering::Relaxed);
+        self.executions.fetch_add(1, Ordering::SeqCst);
+        self.started.lock().unwrap().insert(id, Instant::now());
+        Ok(ExecutionGuard {
+            controller: self.clone(),
+            id,
+        })
+    }
+
+    /// Start a snapshot write or push; refused while draining.
+    pub fn try_begin_operation(self: &Arc<Self>) -> Result<OperationGuard, Draining> {
+        if self.is_draining() {
+            return Err(Draining);
+        }
+        Ok(self.track_operation())
+    }
+
+    /// Count an operation that belongs to already-admitted work.
+    pub fn track_operation(self: &Arc<Self>) -> OperationGuard {
+        self.operations.fetch_add(1, Ordering::SeqCst);
+        OperationGuard {
+            controller: self.clone(),
+        }
+    }
+
+    /// Stop admitting work and return the drain's epoch. Calling it again while already
+    /// draining keeps the original deadline.
+    pub fn start(&self, grace: Duration) -> u64 {
+        let mut window = self.window.lock().unwrap();
+        if window.phase == DrainPhase::Active {
+            window.phase = DrainPhase::Draining;
+            window.epoch += 1;
+            window.started_at = Some(Utc::now());
+            window.deadline = Some((
+                Instant::now() + grace,
+                Utc::now() + chrono::Duration::from_std(grace).unwrap_or_default(),
+            ));
+        }
+        window.epoch
+    }
+
+    /// Wait for in-flight work to finish, up to the drain deadline.
+    pub async fn wait_idle(&self, epoch: u64) -> DrainOutcome {
+        loop {
+            let notified = self.settled.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let deadline = {
+                let window = self.window.lock().unwrap();
+                if window.epoch != epoch || window.phase == DrainPhase::Active {
+                    return DrainOutcome::Cancelled;
+                }
+                window.deadline.map(|(at, _)| at)
+            };
+            if self.in_flight() == 0 {
+                return DrainOutcome::Idle;
+            }
+            let Some(deadline) = deadline else {
+                notified.await;
+                continue;
+            };
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep_until(deadline.into()) => {
+                    return DrainOutcome::DeadlineReached;
+                }
+            }
+        }
+    }
+
+    /// Wait up to `limit` for in-flight work to finish, whatever the drain's deadline;
+    /// false if some is still running.
+    pub async fn settle(&self, limit: Duration) -> bool {
+        let waiting = async {
+            loop {
+                let notified = self.settled.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(limit, waiting).await.is_ok()
+    }
+
+    /// Mark the drain complete, unless it was cancelled in the meantime.
+    pub fn finish(&self, epoch: u64) {
+        let mut window = self.window.lock().unwrap();
+        if window.epoch == epoch && window.phase == DrainPhase::Draining {
+            window.phase = DrainPhase::Drained;
+        }
+    }
+
+    /// Resume normal operation.
+    pub fn undrain(&self) {
+        let mut window = self.window.lock().unwrap();
+        window.phase = DrainPhase::Active;
+        window.started_at = None;
+        window.deadline = None;
+        drop(window);
+        // Wake waiters so they observe the cancellation
+        self.settled.notify_waiters();
+    }
+
+    fn in_flight(&self) -> usize {
+        self.executions.load(Ordering::SeqCst) + self.operations.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> DrainStatus {
+        let (phase, started_at, deadline) = {
+            let window = self.window.lock().unwrap();
+            (window.phase, window.started_at, window.deadline)
+        };
+        let executions = self.executions.load(Ordering::SeqCst);
+        let operations = self.operations.load(Ordering::SeqCst);
+        let eta_ms = (phase == DrainPhase::Draining).then(|| {
+            let to_deadline = deadline.map(|(at, _)| at.saturating_duration_since(Instant::now()));
+            if executions + operations == 0 {
+                return 0;
+            }
+            let estimate = self.avg_execution_ms.lock().unwrap().map(|avg| {
+                let started = self.started.lock().unwrap();
+                let longest_remaining = started
+                    .values()
+                    .map(|at| (avg - at.elapsed().as_millis() as f64).max(0.0))
+                    .fold(0.0, f64::max);
+                Duration::from_millis(longest_remaining as u64)
+            });
+            match (estimate, to_deadline) {
+                (Some(estimate), Some(limit)) => estimate.min(limit),
+                (Some(estimate), None) => estimate,
+                (None, Some(limit)) => limit,
+                (None, None) => Duration::ZERO,
+            }
+            .as_millis() as u64
+        });
+        DrainStatus {
+            phase,
+            started_at,
+            deadline: deadline.map(|(_, at)| at),
+            in_flight_executions: executions,
+            in_flight_operations: operations,
+            eta_ms,
+        }
+    }
+
+    fn execution_done(&self, id: u64) {
+        if let Some(started) = self.started.lock().unwrap().remove(&id) {
+            let ms = started.elapsed().as_millis() as f64;
+            let mut avg = self.avg_execution_ms.lock().unwrap();
+            *avg = Some(avg.map_or(ms, |avg| avg * 0.8 + ms * 0.2));
+        }
+        self.executions.fetch_sub(1, Ordering::SeqCst);
+        self.settled.notify_waiters();
+    }
+
+    fn operation_done(&self) {
+        self.operations.fetch_sub(1, Ordering::SeqCst);
+        self.settled.notify_waiters();
+    }
+}
+
+/// An admitted execution; dropping it marks the execution finished
+pub struct ExecutionGuard {
+    controller: Arc<DrainController>,
+    id: u64,
+}
+
+impl Drop for ExecutionGuard {
+    fn drop(&mut self) {
+        self.controller.execution_done(self.id);
+    }
+}
+
+/// An in-flight snapshot write or push
+pub struct OperationGuard {
+    controller: Arc<DrainController>,
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        self.controller.operation_done();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn long_execution(drain: &Arc<DrainController>, ms: u64) -> tokio::task::JoinHandle<()> {
+        let guard = drain.admit().unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            drop(guard);
+        })
+    }
+
+    #[tokio::test]
+    async fn drain_refuses_new_work_and_counts_down() {
+        let drain = Arc::new(DrainController::new());
+        let short = long_execution(&drain, 50);
+        let long = long_execution(&drain, 150);
+
+        let epoch = drain.start(Duration::from_secs(5));
+        assert!(drain.admit().is_err());
+        assert!(drain.try_begin_operation().is_err());
+        let status = drain.status();
+        assert_eq!(status.phase, DrainPhase::Draining);
+        assert_eq!(status.in_flight_executions, 2);
+        assert!(status.eta_ms.unwrap() > 0);
+
+        short.await.unwrap();
+        assert_eq!(drain.status().in_flight_executions, 1);
+
+        assert_eq!(drain.wait_idle(epoch).await, DrainOutcome::Idle);
+        long.await.unwrap();
+        drain.finish(epoch);
+        let status = drain.status();
+        assert_eq!(status.phase, DrainPhase::Drained);
+        assert_eq!(status.in_flight_executions, 0);
+        assert!(drain.admit().is_err());
+
+        drain.undrain();
+        assert!(drain.admit().is_ok());
+        assert_eq!(drain.status().phase, DrainPhase::Active);
+    }
+
+    #[tokio::test]
+    async fn settle_waits_past_the_deadline() {
+        let drain = Arc::new(DrainController::new());
+        let running = long_execution(&drain, 60);
+        let stuck = drain.admit().unwrap();
+        let epoch = drain.start(Duration::from_millis(1));
+        assert_eq!(drain.wait_idle(epoch).await, DrainOutcome::DeadlineReached);
+
+        assert!(!drain.settle(Duration::from_millis(100)).await);
+        running.await.unwrap();
+        drop(stuck);
+        assert!(drain.settle(Duration::from_millis(100)).await);
+    }
+
+    #[tokio::test]
+    async fn deadline_and_undrain_end_the_wait() {
+        let drain = Arc::new(DrainController::new());
+        let _stuck = drain.admit().unwrap();
+        let _push = drain.track_operation();
+
+        let epoch = drain.start(Duration::from_millis(30));
+        assert_eq!(drain.status().in_flight_operations, 1);
+        assert_eq!(drain.wait_idle(epoch).await, DrainOutcome::DeadlineReached);
+
+        drain.undrain();
+        let second = drain.start(Duration::from_secs(5));
+        assert_ne!(second, epoch);
+        let waiter = {
+            let drain = drain.clone();
+            tokio::spawn(async move { drain.wait_idle(second).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drain.undrain();
+        assert_eq!(waiter.await.unwrap(), DrainOutcome::Cancelled);
+
+        // A cancelled drain cannot be marked finished later
+        drain.finish(second);
+        assert_eq!(drain.phase(), DrainPhase::Active);
+    }
+}
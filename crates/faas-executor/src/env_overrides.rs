@@ -0,0 +1,210 @@
+//! Clock, timezone and locale overrides for Docker sandboxes.
+//!
+//! Timezone and locale become `TZ`, `LANG` and `LC_ALL`, with the host's zoneinfo and
+//! locale archive mounted read-only so slim images without tzdata still resolve the zone.
+//!
+//! A fake clock preloads libfaketime from a shared volume, `faas-libfaketime` or
+//! `FAAS_FAKETIME_VOLUME`, mounted read-only at `/opt/faketime`. The volume is filled on
+//! first use by copying `/usr/lib/faketime/libfaketime.so.1` out of `FAAS_FAKETIME_IMAGE`
+//! (`alpine:latest`, installing the package if the image lacks it). That library is built
+//! against musl; hosts running glibc images should point the variable at a glibc image
+//! that ships libfaketime.
+
+use crate::bollard::container::{Config, RemoveContainerOptions, WaitContainerOptions};
+use crate::bollard::image::CreateImageOptions;
+use crate::bollard::models::HostConfig;
+use crate::bollard::volume::CreateVolumeOptions;
+use crate::bollard::Docker;
+use crate::{ExecutorError, Result};
+use faas_common::{EnvOverrides, EnvVar, GUEST_FAKETIME_LIBRARY};
+use futures::StreamExt;
+use std::path::Path;
+use tokio::sync::Mutex;
+use tracing::info;
+
+pub const DEFAULT_FAKETIME_VOLUME: &str = "faas-libfaketime";
+pub const DEFAULT_FAKETIME_IMAGE: &str = "alpine:latest";
+const FAKETIME_MOUNT: &str = "/opt/faketime";
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+const LOCALE_DIR: &str = "/usr/lib/locale";
+
+/// Serializes filling the helper volume so no execution mounts it half-copied
+static FAKETIME_VOLUME_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// What a container needs on top of its own config to honour the overrides
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DockerOverrides {
+    /// `KEY=VALUE`, as Docker takes them
+    pub env: Vec<String>,
+    pub binds: Vec<String>,
+}
+
+impl DockerOverrides {
+    pub fn apply(self, env: &mut Option<Vec<String>>, host_config: &mut HostConfig) {
+        if !self.env.is_empty() {
+            env.get_or_insert_with(Vec::new).extend(self.env);
+        }
+        if !self.binds.is_empty() {
+            host_config
+                .binds
+                .get_or_insert_with(Vec::new)
+                .extend(self.binds);
+        }
+    }
+}
+
+/// Env and mounts for the overrides, filling the libfaketime volume first if the clock
+/// is faked.
+pub async fn prepare(docker: &Docker, overrides: &EnvOverrides) -> Result<DockerOverrides> {
+    let mut prepared = timezone_and_locale(overrides, |dir| Path::new(dir).is_dir());
+    if overrides.fake_time.is_some() {
+        let volume = ensure_faketime_volume(docker).await?;
+        prepared.binds.push(format!("{volume}:{FAKETIME_MOUNT}:ro"));
+        prepared.env.extend(docker_env(
+            overrides.faketime_env(&format!("{FAKETIME_MOUNT}/libfaketime.so.1")),
+        ));
+    }
+    Ok(prepared)
+}
+
+fn docker_env(vars: Vec<EnvVar>) -> Vec<String> {
+    vars.iter().map(EnvVar::to_string).collect()
+}
+
+fn timezone_and_locale(
+    overrides: &EnvOverrides,
+    host_has: impl Fn(&str) -> bool,
+) -> DockerOverrides {
+    let mut binds = Vec::new();
+    if overrides.timezone.is_some() && host_has(ZONEINFO_DIR) {
+        binds.push(format!("{ZONEINFO_DIR}:{ZONEINFO_DIR}:ro"));
+    }
+    if overrides.locale.is_some() && host_has(LOCALE_DIR) {
+        binds.push(format!("{LOCALE_DIR}:{LOCALE_DIR}:ro"));
+    }
+    DockerOverrides {
+        env: docker_env(overrides.env_vars()),
+        binds,
+    }
+}
+
+async fn ensure_faketime_volume(docker: &Docker) -> Result<String> {
+    let volume = std::env::var("FAAS_FAKETIME_VOLUME")
+        .unwrap_or_else(|_| DEFAULT_FAKETIME_VOLUME.to_string());
+    let _guard = FAKETIME_VOLUME_LOCK.lock().await;
+    if docker.inspect_volume(&volume).await.is_ok() {
+        return Ok(volume);
+    }
+
+    let image =
+        std::env::var("FAAS_FAKETIME_IMAGE").unwrap_or_else(|_| DEFAULT_FAKETIME_IMAGE.to_string());
+    info!("Filling {} with libfaketime from {}", volume, image);
+    docker
+        .create_volume(CreateVolumeOptions {
+            name: volume.clone(),
+            driver: "local".to_string(),
+            ..Default::default()
+        })
+        .await?;
+    if let Err(e) = copy_faketime(docker, &image, &volume).await {
+        let _ = docker.remove_volume(&volume, None).await;
+        return Err(e);
+    }
+    Ok(volume)
+}
+
+async fn copy_faketime(docker: &Docker, image: &str, volume: &str) -> Result<()> {
+    let _: Vec<_> = docker
+        .create_image(
+            Some(CreateImageOptions {
+                from_image: image.to_string(),
+                ..Default::default()
+            }),
+            None,
+            None,
+        )
+        .collect()
+        .await;
+
+    let lib = GUEST_FAKETIME_LIBRARY;
+    let script =
+        format!("[ -e {lib} ] || apk add --no-cache libfaketime >/dev/null && cp {lib} /faketime/");
+    let container = docker
+        .create_container::<String, String>(
+            None,
+            Config {
+                image: Some(image.to_string()),
+                cmd: Some(vec!["sh".to_string(), "-c".to_string(), script]),
+                host_config: Some(HostConfig {
+                    binds: Some(vec![format!("{volume}:/faketime")]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(ExecutorError::CreationFailed)?;
+
+    let result = async {
+        docker
+            .start_container::<String>(&container.id, None)
+            .await
+            .map_err(ExecutorError::StartFailed)?;
+        let exit = docker
+            .wait_container(
+                &container.id,
+                Some(WaitContainerOptions {
+                    condition: "not-running",
+                }),
+            )
+            .next()
+            .await;
+        match exit {
+            Some(Ok(body)) if body.status_code == 0 => Ok(()),
+            Some(Err(e)) => Err(ExecutorError::WaitFailed(e)),
+            _ => Err(ExecutorError::Internal(format!(
+                "{image} could not provide libfaketime; set FAAS_FAKETIME_IMAGE to an image with {lib}"
+            ))),
+        }
+    }
+    .await;
+    let _ = docker
+        .remove_container(
+            &container.id,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_zoneinfo_is_mounted_only_when_present() {
+        let overrides = EnvOverrides {
+            timezone: Some("Asia/Tokyo".to_string()),
+            locale: Some("ja_JP.UTF-8".to_string()),
+            fake_time: None,
+        };
+        let prepared = timezone_and_locale(&overrides, |dir| dir == ZONEINFO_DIR);
+        assert_eq!(
+            prepared.binds,
+            ["/usr/share/zoneinfo:/usr/share/zoneinfo:ro"]
+        );
+        assert!(prepared.env.contains(&"TZ=Asia/Tokyo".to_string()));
+
+        let mut env = Some(vec!["A=1".to_string()]);
+        let mut host_config = HostConfig {
+            binds: Some(vec!["/data:/workspace:rw".to_string()]),
+            ..Default::default()
+        };
+        prepared.apply(&mut env, &mut host_config);
+        assert_eq!(env.unwrap().len(), 4);
+        assert_eq!(host_config.binds.unwrap().len(), 2);
+    }
+}
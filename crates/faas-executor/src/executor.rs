@@ -1,6 +1,8 @@
+use crate::container_labels::{self, ContainerKind};
 use anyhow::Result;
 use async_trait::async_trait;
-use faas_common::{InvocationResult, SandboxConfig, SandboxExecutor};
+use faas_common::env::{EnvLayer, LayeredEnv};
+use faas_common::{EnvVar, InvocationResult, SandboxConfig, SandboxExecutor};
 use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -36,6 +38,8 @@ pub struct Executor {
     registry: Arc<RwLock<EnvironmentRegistry>>,
     config_manager: Arc<Mutex<ConfigurationManager>>,
     cache_manager: Option<Arc<CacheManager>>,
+    running: Arc<crate::running::RunningExecutions>,
+    output: crate::output_limit::OutputLimit,
 }
 
 #[derive(Debug, Clone)]
@@ -306,6 +310,8 @@ impl Executor {
             registry,
             config_manager,
             cache_manager,
+            running: Arc::default(),
+            output: crate::output_limit::OutputLimit::from_env(),
         };
 
         // Initialize environment cache based on registry
@@ -314,6 +320,19 @@ impl Executor {
         Ok(executor)
     }
 
+    /// Register the containers it creates in `running`, so cancelling an execution there
+    /// stops them
+    pub fn with_running(mut self, running: Arc<crate::running::RunningExecutions>) -> Self {
+        self.running = running;
+        self
+    }
+
+    /// Keep output up to `limit` instead of the one from the environment
+    pub fn with_output_limit(mut self, limit: crate::output_limit::OutputLimit) -> Self {
+        self.output = limit;
+        self
+    }
+
     /// Initialize container warm pools from environment registry
     async fn initialize_from_registry(&self) -> Result<()> {
         info!("Initializing environments from registry...");
@@ -459,12 +478,17 @@ impl Executor {
             );
 
             // Build container configuration from template
-            let container_config = self
+            let mut container_config = self
                 .build_container_config(template, container_strategy)
                 .await?;
 
             // Create and start container
             let container_name = format!("faas-warm-{}-{}", template.id, Uuid::new_v4());
+            container_config.labels = Some(container_labels::labels(
+                ContainerKind::Pooled,
+                &container_name,
+                &template.base_image,
+            ));
             let create_response = container_strategy
                 .docker
                 .create_container::<String, String>(
@@ -497,7 +521,7 @@ impl Executor {
         container_strategy: &ContainerStrategy,
     ) -> Result<docktopus::bollard::container::Config<String>> {
         let mut mounts = Vec::new();
-        let mut env_vars = Vec::new();
+        let mut env_vars = LayeredEnv::new();
 
         // Add all cache mounts from layers
         for layer in &template.layers {
@@ -512,10 +536,8 @@ impl Executor {
                 });
             }
 
-            // Add environment variables from layers
-            for (key, value) in &layer.env_vars {
-                env_vars.push(format!("{key}={value}"));
-            }
+            // Later layers of the template override earlier ones
+            env_vars.set(EnvLayer::Template, layer.env_vars.clone())?;
         }
 
         // Build host configuration with resource limits
@@ -539,7 +561,7 @@ impl Executor {
             } else {
                 None
             },
-            ..Default::default()
+            ..crate::default_limits_host_config()
         };
 
         Ok(docktopus::bollard::container::Config {
@@ -549,7 +571,12 @@ impl Executor {
             open_stdin: Some(true),
             tty: Some(false),
             host_config: Some(host_config),
-            env: Some(env_vars),
+            env: Some(
+                faas_common::env::to_vars(&env_vars.into_map())
+                    .iter()
+                    .map(EnvVar::to_string)
+                    .collect(),
+            ),
             ..Default::default()
         })
     }
@@ -788,6 +815,11 @@ impl Executor {
             attach_stdin: Some(true),
             open_stdin: Some(true),
             tty: Some(false),
+            labels: Some(container_labels::labels(
+                ContainerKind::Pooled,
+                &container_id,
+                image,
+            )),
             host_config: Some(docktopus::bollard::models::HostConfig {
                 mounts: Some(mounts),
                 // Enable all CPUs for compute-intensive tasks
@@ -805,7 +837,7 @@ impl Executor {
                 } else {
                     None
                 },
-                ..Default::default()
+                ..crate::default_limits_host_config()
             }),
             env: Some(vec![
                 "CARGO_HOME=/usr/local/cargo".to_string(),
@@ -894,6 +926,12 @@ impl Executor {
             attach_stdin: Some(true),
             open_stdin: Some(true),
             tty: Some(false),
+            labels: Some(container_labels::labels(
+                ContainerKind::Pooled,
+                &container_id,
+                image,
+            )),
+            host_config: Some(crate::default_limits_host_config()),
             ..Default::default()
         };
 
@@ -982,11 +1020,14 @@ impl SandboxExecutor for Executor {
         // Select the optimal strategy for this workload
         let selected_strategy = self.select_strategy(&config);
 
-        // Check if we have a cached environment for instant start
-        let cache_hit = self
-            .check_environment_cache(&config)
-            .await
-            .map_err(|e| faas_common::FaasError::Executor(e.to_string()))?;
+        // Check if we have a cached environment for instant start. Warm containers were
+        // created with the default limits and no tmpfs or overrides, so requests asking for
+        // others always start fresh.
+        let cache_hit = !requires_fresh_container(&config)
+            && self
+                .check_environment_cache(&config)
+                .await
+                .map_err(|e| faas_common::FaasError::Executor(e.to_string()))?;
 
         let result = if cache_hit {
             info!("Cache hit - executing with warm environment");
@@ -1029,10 +1070,53 @@ impl SandboxExecutor for Executor {
             }
         }
 
-        result.map_err(|e| faas_common::FaasError::Executor(e.to_string()))
+        result.map_err(|e| match e.downcast::<faas_common::FaasError>() {
+            Ok(e) => e,
+            Err(e) => faas_common::FaasError::Executor(e.to_string()),
+        })
     }
 }
 
+/// Timeouts and features Docker couldn't provide stay typed so the caller can tell them
+/// from other failures.
+fn docker_failure(e: faas_common::FaasError) -> anyhow::Error {
+    match e {
+        typed @ (faas_common::FaasError::Timeout { .. }
+        | faas_common::FaasError::IncompatibleFeature { .. }
+        | faas_common::FaasError::UnknownUser { .. }) => typed.into(),
+        e => anyhow::anyhow!("Execution failed: {e}"),
+    }
+}
+
+/// Resource options, GPUs, network policies and environment overrides are fixed at
+/// container creation, so they can't be applied to a container that is already running.
+/// Input files are copied in before the container starts, and mounts exist only from
+/// creation. Warm containers have the default ulimits and `/dev/shm`, so only other values
+/// need a container of their own.
+pub(crate) fn requires_fresh_container(config: &SandboxConfig) -> bool {
+    !config.input_files.is_empty()
+        || !config.read_only_mounts.is_empty()
+        || config
+            .ulimits
+            .as_deref()
+            .is_some_and(|ulimits| !faas_common::are_default_ulimits(ulimits))
+        || config.memory_limit.is_some()
+        || config.cpu_limit.is_some()
+        || config
+            .shm_size_mb
+            .is_some_and(|mb| mb != faas_common::DEFAULT_SHM_SIZE_MB)
+        || config
+            .pids_limit
+            .is_some_and(|pids| pids != faas_common::DEFAULT_PIDS_LIMIT)
+        || config.tmpfs.is_some()
+        || config.environment_overrides.is_some()
+        || config.gpu.is_some()
+        || config
+            .network
+            .as_ref()
+            .is_some_and(faas_common::NetworkPolicy::is_restrictive)
+}
+
 impl Executor {
     /// Generate cache key for deterministic function execution
     fn generate_cache_key(&self, config: &SandboxConfig) -> String {
@@ -1045,9 +1129,22 @@ impl Executor {
         }
         if let Some(ref env_vars) = config.env_vars {
             for var in env_vars {
-                hasher.update(var.as_bytes());
+                hasher.update(var.key.as_bytes());
+                hasher.update(b"=");
+                hasher.update(var.value.as_bytes());
             }
         }
+        if let Some(overrides) = &config.environment_overrides {
+            hasher.update(format!("{overrides:?}"));
+        }
+        for (path, contents) in &config.input_files {
+            hasher.update(path.as_bytes());
+            hasher.update(contents);
+        }
+        for (host, sandbox) in &config.read_only_mounts {
+            hasher.update(host.as_bytes());
+            hasher.update(sandbox.as_bytes());
+        }
         format!("exec:{:x}", hasher.finalize())
     }
 
@@ -1149,10 +1246,13 @@ impl Executor {
         match strategy {
             ExecutionStrategy::Container(container_strategy) => {
                 // Try to get a warm container first, fall back to cold start
-                match self
-                    .try_get_warm_container(&config.source, container_strategy)
-                    .await
-                {
+                let warm = if requires_fresh_container(config) {
+                    None
+                } else {
+                    self.try_get_warm_container(&config.source, container_strategy)
+                        .await
+                };
+                match warm {
                     Some(warm_container) => {
                         info!("Found warm container, using it for 'cold' start");
                         self.execute_with_existing_container(
@@ -1166,11 +1266,13 @@ impl Executor {
                         // True cold start - delegate to DockerExecutor
                         info!("No warm container available, creating new one");
                         let docker_executor =
-                            crate::DockerExecutor::new(container_strategy.docker.clone());
+                            crate::DockerExecutor::new(container_strategy.docker.clone())
+                                .with_running(self.running.clone())
+                                .with_output_limit(self.output.clone());
                         docker_executor
                             .execute(config.clone())
                             .await
-                            .map_err(|e| anyhow::anyhow!("Execution failed: {e}"))
+                            .map_err(docker_failure)
                     }
                 }
             }
@@ -1205,10 +1307,13 @@ impl Executor {
         match &self.strategy {
             ExecutionStrategy::Container(container_strategy) => {
                 // Try to get a warm container first, fall back to cold start
-                match self
-                    .try_get_warm_container(&config.source, container_strategy)
-                    .await
-                {
+                let warm = if requires_fresh_container(config) {
+                    None
+                } else {
+                    self.try_get_warm_container(&config.source, container_strategy)
+                        .await
+                };
+                match warm {
                     Some(warm_container) => {
                         info!("Found warm container, using it for 'cold' start");
                         self.execute_with_existing_container(
@@ -1222,11 +1327,13 @@ impl Executor {
                         // True cold start - delegate to DockerExecutor
                         info!("No warm container available, creating new one");
                         let docker_executor =
-                            crate::DockerExecutor::new(container_strategy.docker.clone());
+                            crate::DockerExecutor::new(container_strategy.docker.clone())
+                                .with_running(self.running.clone())
+                                .with_output_limit(self.output.clone());
                         docker_executor
                             .execute(config.clone())
                             .await
-                            .map_err(|e| anyhow::anyhow!("Execution failed: {e}"))
+                            .map_err(docker_failure)
                     }
                 }
             }
@@ -1299,11 +1406,13 @@ impl Executor {
                     "No warm container available for {}, falling back to cold start",
                     config.source
                 );
-                let docker_executor = crate::DockerExecutor::new(strategy.docker.clone());
+                let docker_executor = crate::DockerExecutor::new(strategy.docker.clone())
+                    .with_running(self.running.clone())
+                    .with_output_limit(self.output.clone());
                 docker_executor
                     .execute(config.clone())
                     .await
-                    .map_err(|e| anyhow::anyhow!("Execution failed: {e}"))
+                    .map_err(docker_failure)
             }
         }
     }
@@ -1364,6 +1473,12 @@ impl Executor {
             attach_stdin: Some(true),
             open_stdin: Some(true),
             tty: Some(false),
+            labels: Some(container_labels::labels(
+                ContainerKind::Pooled,
+                &container_id,
+                image,
+            )),
+            host_config: Some(crate::default_limits_host_config()),
             ..Default::default()
         };
 
@@ -1392,96 +1507,180 @@ impl Executor {
         container_id: &str,
         strategy: &ContainerStrategy,
     ) -> anyhow::Result<InvocationResult> {
-        let request_id = Uuid::new_v4().to_string();
+        exec_attached(
+            &strategy.docker,
+            container_id,
+            exec_command(config),
+            config.working_dir.clone(),
+            config.user.clone(),
+            &config.payload,
+            &self.output,
+        )
+        .await
+    }
+}
 
-        // Build the command with environment variables if present
-        let full_cmd = if let Some(env_vars) = &config.env_vars {
-            if !env_vars.is_empty() {
-                // Use 'env' command to inject environment variables
-                // Format: env KEY=VALUE KEY2=VALUE2 sh -c "command"
-                let mut cmd = vec!["env".to_string()];
-                cmd.extend(env_vars.clone());
-                cmd.extend(config.command.clone());
-                cmd
-            } else {
-                config.command.clone()
-            }
-        } else {
-            config.command.clone()
-        };
+/// `config.command`, behind `env` when the request sets variables, for running through
+/// [`exec_attached`] in a container that was started without them
+pub(crate) fn exec_command(config: &SandboxConfig) -> Vec<String> {
+    match &config.env_vars {
+        // Format: env KEY=VALUE KEY2=VALUE2 sh -c "command"
+        Some(env_vars) if !env_vars.is_empty() => {
+            let mut cmd = vec!["env".to_string()];
+            cmd.extend(
+                faas_common::env::dedupe_vars(env_vars)
+                    .iter()
+                    .map(EnvVar::to_string),
+            );
+            cmd.extend(config.command.clone());
+            cmd
+        }
+        _ => config.command.clone(),
+    }
+}
 
-        // Create an exec instance
-        let exec_config = docktopus::bollard::exec::CreateExecOptions {
-            attach_stdout: Some(true),
-            attach_stderr: Some(true),
-            attach_stdin: Some(!config.payload.is_empty()), // Enable stdin if we have payload
-            cmd: Some(full_cmd),
-            ..Default::default()
-        };
+/// Run `cmd` in a running container through the exec API, writing `payload` to its stdin
+///
+/// `working_dir` is created first if the container lacks it. A `user` the image doesn't
+/// know fails with [`faas_common::FaasError::UnknownUser`].
+pub(crate) async fn exec_attached(
+    docker: &docktopus::bollard::Docker,
+    container_id: &str,
+    full_cmd: Vec<String>,
+    working_dir: Option<String>,
+    user: Option<String>,
+    payload: &[u8],
+    limit: &crate::output_limit::OutputLimit,
+) -> anyhow::Result<InvocationResult> {
+    let request_id = Uuid::new_v4().to_string();
+    if let Some(dir) = &working_dir {
+        ensure_working_dir(docker, container_id, dir).await?;
+    }
 
-        let exec_result = strategy
-            .docker
-            .create_exec(container_id, exec_config)
-            .await?;
+    // Create an exec instance
+    let exec_config = docktopus::bollard::exec::CreateExecOptions {
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        attach_stdin: Some(!payload.is_empty()), // Enable stdin if we have payload
+        cmd: Some(full_cmd),
+        working_dir,
+        user: user.clone(),
+        ..Default::default()
+    };
 
-        // Start the exec
-        let start_config = docktopus::bollard::exec::StartExecOptions {
-            detach: false,
-            tty: false,
-            output_capacity: None,
-        };
+    let exec_result = docker.create_exec(container_id, exec_config).await?;
 
-        match strategy
-            .docker
-            .start_exec(&exec_result.id, Some(start_config))
-            .await?
-        {
-            docktopus::bollard::exec::StartExecResults::Attached {
-                mut output,
-                mut input,
-            } => {
-                let mut result_output = Vec::new();
-
-                // Write payload to stdin if we have data
-                if !config.payload.is_empty() {
-                    use tokio::io::AsyncWriteExt;
-                    let _ = input.write_all(&config.payload).await;
-                    let _ = input.shutdown().await; // Signal EOF
-                }
+    // Start the exec
+    let start_config = docktopus::bollard::exec::StartExecOptions {
+        detach: false,
+        tty: false,
+        output_capacity: None,
+    };
 
-                // Collect output
+    match docker
+        .start_exec(&exec_result.id, Some(start_config))
+        .await?
+    {
+        docktopus::bollard::exec::StartExecResults::Attached {
+            mut output,
+            mut input,
+        } => {
+            let mut streams = crate::StreamOutput::capped(limit.clone(), &request_id);
+
+            // Output is collected while stdin is written, or a program that prints before
+            // it reads would stall the write
+            let write = async {
+                if payload.is_empty() {
+                    return Ok(());
+                }
+                crate::write_stdin(&mut input, payload).await
+            };
+            let read = async {
                 use futures::StreamExt;
                 while let Some(chunk) = output.next().await {
-                    match chunk? {
-                        docktopus::bollard::container::LogOutput::StdOut { message }
-                        | docktopus::bollard::container::LogOutput::StdErr { message } => {
-                            result_output.extend_from_slice(&message);
-                        }
-                        _ => {}
-                    }
+                    streams.push(chunk?);
+                }
+                anyhow::Ok(())
+            };
+            let (written, read) = tokio::join!(write, read);
+            read?;
+            crate::stdin_result(written)?;
+            let exit_code = docker.inspect_exec(&exec_result.id).await?.exit_code;
+
+            let output_string = String::from_utf8_lossy(&streams.combined).to_string();
+            // runc reports a missing user as the exec's own output, exiting 126
+            if let Some(user) = user {
+                if exit_code == Some(126) && crate::is_missing_user(&output_string) {
+                    return Err(faas_common::FaasError::UnknownUser { user }.into());
                 }
+            }
 
-                let output_string = String::from_utf8_lossy(&result_output).to_string();
+            let truncation = streams.truncation();
+            Ok(InvocationResult {
+                request_id,
+                response: Some(streams.stdout.clone()),
+                logs: Some(output_string),
+                error: None,
+                stdout: Some(streams.stdout),
+                stderr: Some(streams.stderr),
+                exit_code,
+                usage: None,
+                truncation,
+            })
+        }
+        docktopus::bollard::exec::StartExecResults::Detached => {
+            // For detached exec, we'd need to inspect the exec to get results
+            // For now, return a simple result
+            Ok(InvocationResult {
+                request_id,
+                response: Some(b"Exec completed (detached)".to_vec()),
+                logs: Some("Exec completed in detached mode".to_string()),
+                error: None,
+                stdout: None,
+                stderr: None,
+                exit_code: None,
+                usage: None,
+                truncation: None,
+            })
+        }
+    }
+}
 
-                Ok(InvocationResult {
-                    request_id,
-                    response: Some(result_output.clone()),
-                    logs: Some(output_string),
-                    error: None,
-                })
-            }
-            docktopus::bollard::exec::StartExecResults::Detached => {
-                // For detached exec, we'd need to inspect the exec to get results
-                // For now, return a simple result
-                Ok(InvocationResult {
-                    request_id,
-                    response: Some(b"Exec completed (detached)".to_vec()),
-                    logs: Some("Exec completed in detached mode".to_string()),
-                    error: None,
-                })
-            }
+/// Create `dir` in the container as root, the way Docker does for a new container's
+/// working directory, since an exec can't start in a directory that doesn't exist
+async fn ensure_working_dir(
+    docker: &docktopus::bollard::Docker,
+    container_id: &str,
+    dir: &str,
+) -> anyhow::Result<()> {
+    let exec = docker
+        .create_exec(
+            container_id,
+            docktopus::bollard::exec::CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(vec!["mkdir".to_string(), "-p".to_string(), dir.to_string()]),
+                user: Some("0".to_string()),
+                ..Default::default()
+            },
+        )
+        .await?;
+    let mut streams = crate::StreamOutput::default();
+    if let docktopus::bollard::exec::StartExecResults::Attached { mut output, .. } =
+        docker.start_exec(&exec.id, None).await?
+    {
+        use futures::StreamExt;
+        while let Some(chunk) = output.next().await {
+            streams.push(chunk?);
         }
     }
+    match docker.inspect_exec(&exec.id).await?.exit_code {
+        Some(0) => Ok(()),
+        _ => anyhow::bail!(
+            "working directory {dir} does not exist and could not be created: {}",
+            String::from_utf8_lossy(&streams.combined).trim()
+        ),
+    }
 }
 
 // Helper types and implementations
@@ -0,0 +1,282 @@
+//! Vsock context IDs for Firecracker VMs.
+//!
+//! Every VM with a vsock device leases its own CID from a configured range, so concurrent
+//! VMs never answer for each other. The CID reaches the guest agent as a kernel boot
+//! argument. Leases are written to disk so a crashed manager can tell which CIDs it left
+//! behind and reclaim them when it restarts.
+
+use faas_common::VSOCK_CID_BOOT_ARG;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// 0-2 are the hypervisor, reserved and host CIDs
+pub const MIN_GUEST_CID: u32 = 3;
+pub const DEFAULT_CID_RANGE: RangeInclusive<u32> = MIN_GUEST_CID..=65_535;
+
+#[derive(Debug, Error)]
+pub enum CidError {
+    #[error("invalid vsock CID range {0}; expected e.g. 3-65535")]
+    InvalidRange(String),
+    #[error("no vsock CIDs left in {start}-{end} ({capacity} leased)")]
+    Exhausted {
+        start: u32,
+        end: u32,
+        capacity: usize,
+    },
+    #[error("vsock CID {cid} is already leased to VM {vm_id}")]
+    InUse { cid: u32, vm_id: String },
+    #[error("vsock CID {cid} is outside {start}-{end}")]
+    OutOfRange { cid: u32, start: u32, end: u32 },
+}
+
+/// `start-end`, inclusive
+pub fn parse_range(range: &str) -> Result<RangeInclusive<u32>, CidError> {
+    let invalid = || CidError::InvalidRange(range.to_string());
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+    let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+    if start < MIN_GUEST_CID || end < start {
+        return Err(invalid());
+    }
+    Ok(start..=end)
+}
+
+/// `FAAS_VM_CID_RANGE`, or the default range if it is unset or invalid
+pub fn range_from_env() -> RangeInclusive<u32> {
+    match std::env::var("FAAS_VM_CID_RANGE") {
+        Ok(range) => parse_range(&range).unwrap_or_else(|e| {
+            warn!("Ignoring FAAS_VM_CID_RANGE: {}", e);
+            DEFAULT_CID_RANGE
+        }),
+        Err(_) => DEFAULT_CID_RANGE,
+    }
+}
+
+/// Kernel argument telling the guest agent which CID to bind
+pub fn kernel_cid_arg(cid: u32) -> String {
+    format!("{VSOCK_CID_BOOT_ARG}={cid}")
+}
+
+/// Hands out vsock CIDs, one per VM
+pub struct CidAllocator {
+    range: RangeInclusive<u32>,
+    /// VM id to CID
+    leases: Mutex<BTreeMap<String, u32>>,
+    /// Where leases are persisted; `None` keeps them in memory only
+    state_file: Option<PathBuf>,
+    exhausted: AtomicU64,
+    reclaimed: AtomicU64,
+}
+
+impl CidAllocator {
+    pub fn new(range: RangeInclusive<u32>) -> Self {
+        Self {
+            range,
+            leases: Mutex::new(BTreeMap::new()),
+            state_file: None,
+            exhausted: AtomicU64::new(0),
+            reclaimed: AtomicU64::new(0),
+        }
+    }
+
+    /// Allocator persisting its leases to `state_file`, starting from what an earlier
+    /// process left there
+    pub fn with_state_file(range: RangeInclusive<u32>, state_file: PathBuf) -> Self {
+        let leases = std::fs::read(&state_file)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            leases: Mutex::new(leases),
+            state_file: Some(state_file),
+            ..Self::new(range)
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.range.clone().count()
+    }
+
+    fn persist(&self, leases: &BTreeMap<String, u32>) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let written = serde_json::to_vec(leases)
+            .map_err(std::io::Error::other)
+            .and_then(|data| std::fs::write(path, data));
+        if let Err(e) = written {
+            warn!("Failed to persist vsock CID leases to {:?}: {}", path, e);
+        }
+    }
+
+    /// The lowest free CID, leased to `vm_id`; a VM that already holds one gets it back.
+    ///
+    /// Fails with `Exhausted` instead of waiting when every CID is taken.
+    pub fn lease(&self, vm_id: &str) -> Result<u32, CidError> {
+        let mut leases = self.leases.lock().unwrap();
+        if let Some(&cid) = leases.get(vm_id) {
+            return Ok(cid);
+        }
+        let taken: BTreeSet<u32> = leases.values().copied().collect();
+        let Some(cid) = self.range.clone().find(|cid| !taken.contains(cid)) else {
+            self.exhausted.fetch_add(1, Ordering::Relaxed);
+            return Err(CidError::Exhausted {
+                start: *self.range.start(),
+                end: *self.range.end(),
+                capacity: self.capacity(),
+            });
+        };
+        leases.insert(vm_id.to_string(), cid);
+        self.persist(&leases);
+        Ok(cid)
+    }
+
+    /// Lease a specific CID, e.g. one a restored snapshot was taken with
+    pub fn reserve(&self, vm_id: &str, cid: u32) -> Result<u32, CidError> {
+        if !self.range.contains(&cid) {
+            return Err(CidError::OutOfRange {
+                cid,
+                start: *self.range.start(),
+                end: *self.range.end(),
+            });
+        }
+        let mut leases = self.leases.lock().unwrap();
+        if let Some((holder, _)) = leases
+            .iter()
+            .find(|(holder, &leased)| leased == cid && holder.as_str() != vm_id)
+        {
+            return Err(CidError::InUse {
+                cid,
+                vm_id: holder.clone(),
+            });
+        }
+        leases.insert(vm_id.to_string(), cid);
+        self.persist(&leases);
+        Ok(cid)
+    }
+
+    /// Return the VM's CID; unknown VMs are ignored
+    pub fn release(&self, vm_id: &str) -> Option<u32> {
+        let mut leases = self.leases.lock().unwrap();
+        let cid = leases.remove(vm_id)?;
+        self.persist(&leases);
+        Some(cid)
+    }
+
+    pub fn get(&self, vm_id: &str) -> Option<u32> {
+        self.leases.lock().unwrap().get(vm_id).copied()
+    }
+
+    /// Release every lease whose VM `is_live` doesn't know, returning the CIDs freed
+    pub fn reclaim(&self, is_live: impl Fn(&str) -> bool) -> Vec<u32> {
+        let mut leases = self.leases.lock().unwrap();
+        let orphans: Vec<String> = leases
+            .keys()
+            .filter(|vm_id| !is_live(vm_id))
+            .cloned()
+            .collect();
+        let reclaimed: Vec<u32> = orphans
+            .iter()
+            .filter_map(|vm_id| leases.remove(vm_id))
+            .collect();
+        if !reclaimed.is_empty() {
+            self.persist(&leases);
+            self.reclaimed
+                .fetch_add(reclaimed.len() as u64, Ordering::Relaxed);
+            info!("Reclaimed {} orphaned vsock CIDs", reclaimed.len());
+        }
+        reclaimed
+    }
+
+    pub fn leased(&self) -> usize {
+        self.leases.lock().unwrap().len()
+    }
+
+    /// Leases refused because the range was exhausted
+    pub fn exhausted(&self) -> u64 {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    pub fn reclaimed(&self) -> u64 {
+        self.reclaimed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn leases_are_unique_and_reused_after_exhaustion() {
+        let cids = CidAllocator::new(3..=5);
+        let leased: Vec<u32> = (0..3)
+            .map(|i| cids.lease(&format!("vm-{i}")).unwrap())
+            .collect();
+        assert_eq!(leased, [3, 4, 5]);
+        assert_eq!(cids.lease("vm-0").unwrap(), 3, "same VM, same CID");
+
+        assert!(matches!(
+            cids.lease("vm-3"),
+            Err(CidError::Exhausted { capacity: 3, .. })
+        ));
+        assert_eq!(cids.exhausted(), 1);
+
+        assert_eq!(cids.release("vm-1"), Some(4));
+        assert_eq!(cids.release("vm-1"), None);
+        assert_eq!(cids.lease("vm-3").unwrap(), 4);
+        assert_eq!(cids.leased(), 3);
+    }
+
+    #[test]
+    fn reserving_a_leased_cid_is_a_collision() {
+        let cids = CidAllocator::new(3..=10);
+        cids.lease("vm-a").unwrap();
+        assert!(matches!(
+            cids.reserve("vm-b", 3),
+            Err(CidError::InUse { cid: 3, ref vm_id }) if vm_id == "vm-a"
+        ));
+        assert!(matches!(
+            cids.reserve("vm-b", 2),
+            Err(CidError::OutOfRange { .. })
+        ));
+        assert_eq!(cids.reserve("vm-b", 7).unwrap(), 7);
+        assert_eq!(cids.lease("vm-c").unwrap(), 4);
+    }
+
+    #[test]
+    fn leases_left_by_a_crash_are_reclaimed_against_the_vm_table() {
+        let state = std::env::temp_dir().join(format!("faas-cids-{}.json", uuid::Uuid::new_v4()));
+        {
+            let crashed = CidAllocator::with_state_file(3..=10, state.clone());
+            crashed.lease("vm-gone").unwrap();
+            crashed.lease("vm-alive").unwrap();
+        }
+
+        let restarted = CidAllocator::with_state_file(3..=10, state.clone());
+        assert_eq!(restarted.get("vm-gone"), Some(3));
+        let vm_table: HashSet<&str> = ["vm-alive"].into();
+        assert_eq!(restarted.reclaim(|vm_id| vm_table.contains(vm_id)), [3]);
+        assert_eq!(restarted.get("vm-alive"), Some(4));
+        assert_eq!(restarted.reclaimed(), 1);
+        assert_eq!(restarted.lease("vm-new").unwrap(), 3);
+
+        let persisted = CidAllocator::with_state_file(3..=10, state.clone());
+        assert_eq!(persisted.leased(), 2);
+        std::fs::remove_file(state).unwrap();
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("100-200").unwrap(), 100..=200);
+        assert!(parse_range("2-10").is_err(), "CID 2 is the host");
+        assert!(parse_range("10-5").is_err());
+        assert!(parse_range("ten").is_err());
+        assert_eq!(kernel_cid_arg(42), "faas.vsock_cid=42");
+    }
+}
@@ -3,9 +3,44 @@
 //! Unified interface for executing commands in VMs using the best available method
 
 use super::{CommunicationConfig, CommunicationError, Result, SerialConsole, VsockConnection};
-use faas_common::SandboxConfig;
+use crate::session_state::quote;
+use faas_common::{SandboxConfig, GUEST_AGENT_PORT, GUEST_FAKETIME_LIBRARY};
 use tracing::{debug, info, warn};
 
+/// The command line with the execution's env vars, then the environment overrides,
+/// prepended through `env`, behind a `cd` into the working directory, created if missing,
+/// when one is set. The channels only carry a command string, so this is the only way the
+/// merged env reaches the guest. Every value is quoted, so one holding `=`, a newline or a
+/// quote reaches the command as it was sent.
+fn command_line(sandbox_config: &SandboxConfig) -> String {
+    let command = env_command_line(sandbox_config);
+    match &sandbox_config.working_dir {
+        Some(dir) => format!("mkdir -p {dir} && cd {dir} && {command}", dir = quote(dir)),
+        None => command,
+    }
+}
+
+fn env_command_line(sandbox_config: &SandboxConfig) -> String {
+    let command = sandbox_config.command.join(" ");
+    let mut vars = sandbox_config
+        .env_vars
+        .as_deref()
+        .map(faas_common::env::dedupe_vars)
+        .unwrap_or_default();
+    if let Some(overrides) = &sandbox_config.environment_overrides {
+        vars.extend(overrides.env_vars());
+        vars.extend(overrides.faketime_env(GUEST_FAKETIME_LIBRARY));
+    }
+    if vars.is_empty() {
+        return command;
+    }
+    let vars: Vec<String> = vars
+        .iter()
+        .map(|var| format!("{}={}", var.key, quote(&var.value)))
+        .collect();
+    format!("env {} {}", vars.join(" "), command)
+}
+
 /// Executes commands in VMs using the best available communication method
 pub struct VmCommandExecutor {
     config: CommunicationConfig,
@@ -19,7 +54,7 @@ impl VmCommandExecutor {
 
     /// Execute a command in the VM using the best available method
     pub async fn execute(&self, sandbox_config: &SandboxConfig) -> Result<Vec<u8>> {
-        let command = sandbox_config.command.join(" ");
+        let command = command_line(sandbox_config);
         let payload = &sandbox_config.payload;
 
         // Try methods in order of preference:
@@ -31,8 +66,10 @@ impl VmCommandExecutor {
         if let Some(cid) = self.config.vsock_cid {
             if VsockConnection::is_available() {
                 info!("Using vsock for VM communication (CID: {})", cid);
-                match self.execute_via_vsock(cid, &command, payload).await {
+                match self.execute_via_vsock(cid, sandbox_config).await {
                     Ok(output) => return Ok(output),
+                    // The agent ran it; running it again over another channel won't help
+                    Err(e @ CommunicationError::ExecutionFailed(_)) => return Err(e),
                     Err(e) => {
                         warn!("Vsock execution failed, trying next method: {}", e);
                     }
@@ -53,6 +90,18 @@ impl VmCommandExecutor {
 
         // Fall back to serial console
         if let Some(ref serial_device) = self.config.serial_device {
+            // The console can't tell a preload failure from command output, so a faked
+            // clock could silently be the real one
+            let fakes_time = sandbox_config
+                .environment_overrides
+                .as_ref()
+                .is_some_and(|o| o.fake_time.is_some());
+            if fakes_time {
+                return Err(CommunicationError::IncompatibleFeature {
+                    feature: "fake_time",
+                    channel: "the serial console",
+                });
+            }
             info!("Using serial console for VM communication");
             return self
                 .execute_via_serial(serial_device, &command, payload)
@@ -64,13 +113,10 @@ impl VmCommandExecutor {
         ))
     }
 
-    /// Execute via vsock
-    async fn execute_via_vsock(&self, cid: u32, command: &str, payload: &[u8]) -> Result<Vec<u8>> {
-        let vsock = VsockConnection::new(
-            cid,
-            5555, // Default vsock port for command execution
-            self.config.timeout,
-        );
+    /// Execute via vsock. The guest agent gets the whole config and applies the env and
+    /// working directory itself, so no command line is built.
+    async fn execute_via_vsock(&self, cid: u32, sandbox_config: &SandboxConfig) -> Result<Vec<u8>> {
+        let vsock = VsockConnection::new(cid, GUEST_AGENT_PORT, self.config.timeout);
 
         // Retry logic
         let mut last_error = None;
@@ -80,14 +126,23 @@ impl VmCommandExecutor {
                 attempt, self.config.retry_attempts
             );
 
-            match vsock.execute_command(command, payload).await {
-                Ok(output) => return Ok(output),
-                Err(e) => {
+            // Only retry reaching the agent; once it has the config the command has run
+            match vsock.invoke(sandbox_config).await {
+                Ok(result) => {
+                    return match result.error {
+                        Some(error) => Err(CommunicationError::ExecutionFailed(error)),
+                        None => Ok(result.response.or(result.stdout).unwrap_or_default()),
+                    };
+                }
+                Err(
+                    e @ (CommunicationError::ConnectionFailed(_) | CommunicationError::Timeout),
+                ) => {
                     last_error = Some(e);
                     if attempt < self.config.retry_attempts {
                         tokio::time::sleep(self.config.retry_delay).await;
                     }
                 }
+                Err(e) => return Err(e),
             }
         }
 
@@ -196,7 +251,9 @@ impl VmCommandExecutor {
             runtime: Some(faas_common::Runtime::Firecracker),
             execution_mode: None,
             memory_limit: None,
+            cpu_limit: None,
             timeout: Some(5000), // 5 second timeout for test
+            ..Default::default()
         };
 
         match self.execute(&test_config).await {
@@ -213,3 +270,63 @@ impl VmCommandExecutor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use faas_common::EnvVar;
+
+    #[test]
+    fn command_line_carries_the_merged_env() {
+        let config = SandboxConfig {
+            command: vec!["sh".to_string(), "-c".to_string(), "'echo $A'".to_string()],
+            env_vars: Some(vec![
+                EnvVar::new("B", "it's"),
+                EnvVar::new("A", "1"),
+                EnvVar::new("A", "2"),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            command_line(&config),
+            r#"env A='2' B='it'\''s' sh -c 'echo $A'"#
+        );
+
+        let bare = SandboxConfig {
+            command: vec!["true".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(command_line(&bare), "true");
+    }
+
+    #[test]
+    fn command_line_keeps_values_whole() {
+        let config = SandboxConfig {
+            command: vec!["env".to_string()],
+            env_vars: Some(vec![
+                EnvVar::new("SECRET", "c2VjcmV0PT0="),
+                EnvVar::new("NOTE", "line one\nline two"),
+                EnvVar::new("GREETING", "grüß dich ✓"),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            command_line(&config),
+            "env GREETING='grüß dich ✓' NOTE='line one\nline two' SECRET='c2VjcmV0PT0=' env"
+        );
+    }
+
+    #[test]
+    fn command_line_changes_into_the_working_directory() {
+        let config = SandboxConfig {
+            command: vec!["cat".to_string(), "notes".to_string()],
+            env_vars: Some(vec![EnvVar::new("A", "1")]),
+            working_dir: Some("/srv/my app".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            command_line(&config),
+            "mkdir -p '/srv/my app' && cd '/srv/my app' && env A='1' cat notes"
+        );
+    }
+}
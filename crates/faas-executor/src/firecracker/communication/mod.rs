@@ -30,11 +30,20 @@ pub enum CommunicationError {
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("Guest agent protocol error: {0}")]
+    Protocol(#[from] faas_common::frame::FrameError),
+
     #[error("Vsock not available")]
     VsockUnavailable,
 
     #[error("Serial console not available")]
     SerialUnavailable,
+
+    #[error("{feature} is not supported over {channel}")]
+    IncompatibleFeature {
+        feature: &'static str,
+        channel: &'static str,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, CommunicationError>;
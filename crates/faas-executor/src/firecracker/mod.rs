@@ -1,7 +1,9 @@
 //! Firecracker microVM integration module
 //! Provides complete VM lifecycle management with KVM acceleration
 
+pub mod cid;
 pub mod communication;
+pub mod network;
 pub mod vm_cache;
 pub mod vm_fork;
 pub mod vm_manager;
@@ -14,16 +16,23 @@ pub mod vm_snapshot;
 pub const GUEST_AGENT_SOURCE: &str = include_str!("guest_agent.rs");
 
 pub use communication::{CommunicationConfig as CommConfig, VmCommandExecutor};
+pub use network::{NetOps, NetworkError, NetworkLease, NetworkLeaseStats, NetworkManager};
 pub use vm_cache::{CacheConfig, VmResultCache as MultiLevelVmCache};
 pub use vm_fork::{ForkTree, ForkedVm, VmForkManager};
 pub use vm_manager::{FirecrackerManager, NetworkConfig, VmConfig, VmInstance, VmState};
-pub use vm_scaling::{ScalingConfig, VmPool, VmPredictiveScaler};
+pub use vm_scaling::{ScalingConfig, VmPool, VmPoolStats, VmPredictiveScaler, WarmLimits};
 pub use vm_snapshot::{RestoredVm, VmSnapshot, VmSnapshotManager};
 
+use crate::platform::config::FirecrackerSettings;
 #[cfg(target_os = "linux")]
 use anyhow::anyhow;
 use async_trait::async_trait;
-use faas_common::{InvocationResult, Result as CommonResult, SandboxConfig, SandboxExecutor};
+#[cfg(target_os = "linux")]
+use communication::CommunicationError;
+use communication::VsockConnection;
+use faas_common::{
+    FaasError, InvocationResult, Result as CommonResult, SandboxConfig, SandboxExecutor,
+};
 #[cfg(target_os = "linux")]
 use std::fs;
 use std::path::PathBuf;
@@ -45,6 +54,7 @@ pub struct FirecrackerExecutor {
     cache: Option<Arc<MultiLevelVmCache>>,
     fork_manager: Option<Arc<VmForkManager>>,
     scaler: Option<Arc<VmPredictiveScaler>>,
+    running: Arc<crate::running::RunningExecutions>,
 }
 
 #[cfg(target_os = "linux")]
@@ -70,6 +80,25 @@ impl FirecrackerExecutor {
         kernel_image_path: String,
         rootfs_path: String,
     ) -> Result<Self, anyhow::Error> {
+        let settings = FirecrackerSettings {
+            binary: fc_binary_path,
+            kernel: kernel_image_path,
+            rootfs: rootfs_path,
+            ..Default::default()
+        };
+        Self::with_settings(&settings, ScalingConfig::from_env())
+    }
+
+    /// An executor keeping its state, sockets and cache where `settings` says, scaling
+    /// warm VMs with `scaling`
+    pub fn with_settings(
+        settings: &FirecrackerSettings,
+        scaling: ScalingConfig,
+    ) -> Result<Self, anyhow::Error> {
+        let fc_binary_path = settings.binary.clone();
+        let kernel_image_path = settings.kernel.clone();
+        let rootfs_path = settings.rootfs.clone();
+        let api_socket_base = settings.socket_base.clone();
         // Check if KVM is available
         if !Self::check_kvm_available() {
             warn!("KVM not available, Firecracker will not work");
@@ -78,7 +107,7 @@ impl FirecrackerExecutor {
         // Initialize optimization components only on Linux
         let (vm_manager, snapshot_manager, cache, fork_manager, scaler) =
             if cfg!(target_os = "linux") {
-                let vm_mgr = match FirecrackerManager::new(PathBuf::from("/var/lib/firecracker")) {
+                let vm_mgr = match FirecrackerManager::new(settings.state_dir.clone()) {
                     Ok(mgr) => Arc::new(mgr),
                     Err(e) => {
                         warn!("Failed to initialize VM manager: {}", e);
@@ -86,40 +115,41 @@ impl FirecrackerExecutor {
                             fc_binary_path,
                             kernel_image_path,
                             rootfs_path,
-                            api_socket_base: "/tmp/firecracker".to_string(),
+                            api_socket_base,
                             vsock_enabled: cfg!(target_os = "linux"),
                             vm_manager: None,
                             snapshot_manager: None,
                             cache: None,
                             fork_manager: None,
                             scaler: None,
+                            running: Arc::default(),
                         });
                     }
                 };
 
-                let snapshot_mgr =
-                    match VmSnapshotManager::new(PathBuf::from("/var/lib/firecracker/snapshots")) {
-                        Ok(mgr) => Arc::new(mgr),
-                        Err(e) => {
-                            warn!("Failed to initialize snapshot manager: {}", e);
-                            return Ok(Self {
-                                fc_binary_path,
-                                kernel_image_path,
-                                rootfs_path,
-                                api_socket_base: "/tmp/firecracker".to_string(),
-                                vsock_enabled: cfg!(target_os = "linux"),
-                                vm_manager: Some(vm_mgr),
-                                snapshot_manager: None,
-                                cache: None,
-                                fork_manager: None,
-                                scaler: None,
-                            });
-                        }
-                    };
+                let snapshot_mgr = match VmSnapshotManager::new(settings.snapshot_dir()) {
+                    Ok(mgr) => Arc::new(mgr),
+                    Err(e) => {
+                        warn!("Failed to initialize snapshot manager: {}", e);
+                        return Ok(Self {
+                            fc_binary_path,
+                            kernel_image_path,
+                            rootfs_path,
+                            api_socket_base,
+                            vsock_enabled: cfg!(target_os = "linux"),
+                            vm_manager: Some(vm_mgr),
+                            snapshot_manager: None,
+                            cache: None,
+                            fork_manager: None,
+                            scaler: None,
+                            running: Arc::default(),
+                        });
+                    }
+                };
 
                 let cache_config = CacheConfig {
-                    max_size_bytes: 100 * 1024 * 1024, // 100MB
-                    max_entries: 1000,
+                    max_size_bytes: settings.cache_mb * 1024 * 1024,
+                    max_entries: settings.cache_entries,
                     default_ttl: Some(Duration::from_secs(3600)),
                     compression_enabled: true,
                     eviction_policy: vm_cache::EvictionPolicy::Adaptive,
@@ -133,18 +163,10 @@ impl FirecrackerExecutor {
                 );
                 let fork_mgr = Arc::new(fork_mgr);
 
-                let scaling_config = ScalingConfig {
-                    min_warm_vms: 1,
-                    max_warm_vms: 10,
-                    scale_up_threshold: 0.8,
-                    scale_down_threshold: 0.2,
-                    prediction_window: Duration::from_secs(300),
-                    warmup_time: Duration::from_secs(5),
-                };
                 let scaler = Arc::new(VmPredictiveScaler::new(
                     fork_mgr.clone(),
                     snapshot_mgr.clone(),
-                    scaling_config,
+                    scaling,
                 ));
 
                 (
@@ -162,13 +184,14 @@ impl FirecrackerExecutor {
             fc_binary_path,
             kernel_image_path,
             rootfs_path,
-            api_socket_base: "/tmp/firecracker".to_string(),
+            api_socket_base,
             vsock_enabled: cfg!(target_os = "linux"),
             vm_manager,
             snapshot_manager,
             cache,
             fork_manager,
             scaler,
+            running: Arc::default(),
         })
     }
 
@@ -248,7 +271,7 @@ impl FirecrackerExecutor {
     }
 
     /// Check if KVM is available on the system
-    fn check_kvm_available() -> bool {
+    pub fn check_kvm_available() -> bool {
         #[cfg(target_os = "linux")]
         {
             std::path::Path::new("/dev/kvm").exists()
@@ -259,6 +282,91 @@ impl FirecrackerExecutor {
         }
     }
 
+    /// Guest network lease accounting; `None` without a VM manager
+    pub fn network_stats(&self) -> Option<NetworkLeaseStats> {
+        self.vm_manager.as_ref().map(|m| m.network_stats())
+    }
+
+    /// Warm VM pools by environment; empty without a VM scaler
+    pub async fn pool_stats(&self) -> Vec<VmPoolStats> {
+        match &self.scaler {
+            Some(scaler) => scaler.pool_stats().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// See [`VmPredictiveScaler::set_limits`]; `None` without a VM scaler
+    pub async fn set_pool_limits(
+        &self,
+        environment: &str,
+        limits: WarmLimits,
+    ) -> Option<anyhow::Result<WarmLimits>> {
+        Some(self.scaler.as_ref()?.set_limits(environment, limits).await)
+    }
+
+    /// Timezone and locale reach the guest on every channel. A fake clock needs vsock: the
+    /// serial console fallback can't preload libfaketime. Input files have no way in yet.
+    fn check_overrides(&self, config: &SandboxConfig) -> CommonResult<()> {
+        let fakes_time = config
+            .environment_overrides
+            .as_ref()
+            .is_some_and(|o| o.fake_time.is_some());
+        if fakes_time && !(self.vsock_enabled && VsockConnection::is_available()) {
+            return Err(FaasError::IncompatibleFeature {
+                feature: "fake_time".to_string(),
+                runtime: "firecracker".to_string(),
+                reason: "this host has no vsock, and the serial console can't preload libfaketime"
+                    .to_string(),
+            });
+        }
+        if !config.input_files.is_empty() {
+            return Err(FaasError::IncompatibleFeature {
+                feature: "input_files".to_string(),
+                runtime: "firecracker".to_string(),
+                reason: "files can only be copied into Docker containers".to_string(),
+            });
+        }
+        if !config.read_only_mounts.is_empty() {
+            return Err(FaasError::IncompatibleFeature {
+                feature: "read_only_mounts".to_string(),
+                runtime: "firecracker".to_string(),
+                reason: "host paths can only be bind-mounted into Docker containers".to_string(),
+            });
+        }
+        if config.gpu.is_some() {
+            return Err(FaasError::IncompatibleFeature {
+                feature: "gpu".to_string(),
+                runtime: "firecracker".to_string(),
+                reason: "GPUs are only passed through to Docker containers".to_string(),
+            });
+        }
+        if config
+            .network
+            .as_ref()
+            .is_some_and(faas_common::NetworkPolicy::is_restrictive)
+        {
+            return Err(FaasError::IncompatibleFeature {
+                feature: "network".to_string(),
+                runtime: "firecracker".to_string(),
+                reason: "network policies are only enforced for Docker containers".to_string(),
+            });
+        }
+        if config.user.is_some() {
+            return Err(FaasError::IncompatibleFeature {
+                feature: "user".to_string(),
+                runtime: "firecracker".to_string(),
+                reason: "commands only run as another user in Docker containers".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Register runs in `running`, so cancelling an execution there releases its VM
+    pub fn with_running(mut self, running: Arc<crate::running::RunningExecutions>) -> Self {
+        self.running = running;
+        self
+    }
+
     /// Create a stub executor for environments without KVM
     pub fn stub() -> Self {
         Self {
@@ -272,6 +380,7 @@ impl FirecrackerExecutor {
             cache: None,
             fork_manager: None,
             scaler: None,
+            running: Arc::default(),
         }
     }
 
@@ -299,8 +408,7 @@ impl FirecrackerExecutor {
             let _ = manager.setup_network().await;
 
             // Configure vsock if enabled
-            let (vsock_cid, vsock_device, vsock_path_option) = if self.vsock_enabled {
-                let cid = 3 + (std::process::id() % 1000) as u32;
+            let (vsock_device, vsock_path_option) = if self.vsock_enabled {
                 let vsock_path = self.vsock_path_for(vm_id);
                 if let Some(parent) = vsock_path.parent() {
                     let _ = fs::create_dir_all(parent);
@@ -308,20 +416,22 @@ impl FirecrackerExecutor {
                 let _ = fs::remove_file(&vsock_path);
 
                 (
-                    Some(cid),
                     Some(VsockDevice {
-                        guest_cid: cid,
+                        guest_cid: None,
                         uds_path: vsock_path.to_string_lossy().into_owned(),
                     }),
                     Some(vsock_path),
                 )
             } else {
-                (None, None, None)
+                (None, None)
             };
 
             let vm_config = vm_manager::VmConfig {
-                vcpu_count: 1,
-                mem_size_mib: 256,
+                // A VM can't have part of a core; round fractional requests up
+                vcpu_count: config
+                    .cpu_limit
+                    .map_or(1, |cores| cores.ceil().max(1.0) as u8),
+                mem_size_mib: config.memory_limit.map_or(256, |mb| mb as usize),
                 kernel_path: PathBuf::from(&self.kernel_image_path),
                 kernel_args: "console=ttyS0 reboot=k panic=1 pci=off".to_string(),
                 rootfs_path: PathBuf::from(&self.rootfs_path),
@@ -329,6 +439,7 @@ impl FirecrackerExecutor {
                 vsock: vsock_device,
                 enable_jailer: false,
                 jailer_cfg: None,
+                egress: true,
             };
 
             let launched_vm_id = manager.launch_vm(vm_config).await.map_err(|e| {
@@ -403,7 +514,7 @@ impl FirecrackerExecutor {
         executor
             .execute(config)
             .await
-            .map_err(|e| anyhow::anyhow!("VM command execution failed: {e}"))
+            .map_err(|e| anyhow::Error::new(e).context("VM command execution failed"))
     }
 
     /// Execute with VM forking for branched execution
@@ -468,6 +579,11 @@ impl FirecrackerExecutor {
                         parent_vm_id
                     )),
                     error: None,
+                    stdout: None,
+                    stderr: None,
+                    exit_code: None,
+                    usage: None,
+                    truncation: None,
                 })
             } else {
                 // Fall back to snapshot-based branching if fork manager unavailable
@@ -510,6 +626,11 @@ impl FirecrackerExecutor {
                                     parent_vm_id
                                 )),
                                 error: None,
+                                stdout: None,
+                                stderr: None,
+                                exit_code: None,
+                                usage: None,
+                                truncation: None,
                             })
                         }
                         Err(e) => {
@@ -538,6 +659,8 @@ impl FirecrackerExecutor {
 #[async_trait]
 impl SandboxExecutor for FirecrackerExecutor {
     async fn execute(&self, config: SandboxConfig) -> CommonResult<InvocationResult> {
+        self.check_overrides(&config)?;
+
         // Check KVM availability
         if !Self::check_kvm_available() {
             return Err(faas_common::FaasError::Executor(
@@ -552,7 +675,11 @@ impl SandboxExecutor for FirecrackerExecutor {
             "{}:{}:{}",
             config.function_id.clone(),
             config.source.clone(),
-            format!("{:x}", md5::compute(config.command.join(" ")))
+            faas_common::hash::sha256_hex(format!(
+                "{}{:?}",
+                config.command.join(" "),
+                config.environment_overrides
+            ))
         );
 
         // Start Firecracker with optimizations
@@ -569,6 +696,11 @@ impl SandboxExecutor for FirecrackerExecutor {
                             cached.hit_rate
                         )),
                         error: cached.error,
+                        stdout: None,
+                        stderr: None,
+                        exit_code: None,
+                        usage: None,
+                        truncation: None,
                     });
                 }
             }
@@ -619,11 +751,29 @@ impl SandboxExecutor for FirecrackerExecutor {
                 vsock_hint = self.resolve_vsock_cid(&target_vm_id).await;
             }
 
-            let output = match self.execute_in_vm(&target_vm_id, &config, vsock_hint).await {
+            let run = self.running.track(&config.function_id);
+            let executed = tokio::select! {
+                executed = self.execute_in_vm(&target_vm_id, &config, vsock_hint) => executed,
+                _ = run.token().cancelled() => {
+                    info!("Execution {} cancelled, releasing VM {}", config.function_id, target_vm_id);
+                    self.cleanup_acquisition(&acquisition).await;
+                    return Err(crate::ExecutorError::Cancelled.into());
+                }
+            };
+            let output = match executed {
                 Ok(output) => output,
                 Err(error) => {
                     error!("Failed to execute in VM {}: {}", target_vm_id, error);
                     self.cleanup_acquisition(&acquisition).await;
+                    if let Some(CommunicationError::IncompatibleFeature { feature, channel }) =
+                        error.downcast_ref()
+                    {
+                        return Err(FaasError::IncompatibleFeature {
+                            feature: feature.to_string(),
+                            runtime: "firecracker".to_string(),
+                            reason: format!("vsock failed and {channel} can't provide it"),
+                        });
+                    }
                     return Err(faas_common::FaasError::Executor(format!(
                         "VM execution failed: {}",
                         error
@@ -643,6 +793,11 @@ impl SandboxExecutor for FirecrackerExecutor {
                     if was_warm { "warm" } else { "cold" }
                 )),
                 error: None,
+                stdout: None,
+                stderr: None,
+                exit_code: None,
+                usage: None,
+                truncation: None,
             };
 
             if let Some(ref cache) = self.cache {
@@ -0,0 +1,475 @@
+//! Guest network leases for Firecracker VMs.
+//!
+//! Every VM gets a guest IP from the configured CIDR, a MAC derived from that IP and a TAP
+//! device named after it, so hundreds of concurrent VMs never collide. Leases are returned
+//! when the VM stops; TAPs left behind by a crash are swept when the manager starts.
+
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::net::Ipv4Addr;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use super::vm_manager::NetworkConfig;
+
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("invalid CIDR {0}")]
+    InvalidCidr(String),
+    #[error("no guest IPs left in {cidr} ({capacity} leased)")]
+    PoolExhausted { cidr: String, capacity: usize },
+    #[error("`{command}` failed: {stderr}")]
+    Command { command: String, stderr: String },
+}
+
+/// An IPv4 range; the first usable address is the bridge gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn parse(cidr: &str) -> Result<Self, NetworkError> {
+        let invalid = || NetworkError::InvalidCidr(cidr.to_string());
+        let (addr, prefix) = cidr.split_once('/').ok_or_else(invalid)?;
+        let addr: Ipv4Addr = addr.parse().map_err(|_| invalid())?;
+        let prefix_len: u8 = prefix.parse().map_err(|_| invalid())?;
+        // Anything smaller than a /30 has no room for a gateway and a guest
+        if !(8..=30).contains(&prefix_len) {
+            return Err(invalid());
+        }
+        let mask = u32::MAX << (32 - prefix_len);
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(addr) & mask),
+            prefix_len,
+        })
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    fn size(&self) -> u32 {
+        1 << (32 - self.prefix_len)
+    }
+
+    pub fn gateway(&self) -> Ipv4Addr {
+        self.nth(1)
+    }
+
+    pub fn netmask(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::MAX << (32 - self.prefix_len))
+    }
+
+    fn nth(&self, offset: u32) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.network) + offset)
+    }
+
+    /// Offsets guests can use: everything but the network, gateway and broadcast addresses
+    fn guest_offsets(&self) -> std::ops::RangeInclusive<u32> {
+        2..=self.size() - 2
+    }
+
+    fn capacity(&self) -> usize {
+        self.guest_offsets().count()
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// Network resources held by one VM
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NetworkLease {
+    pub vm_id: String,
+    pub tap_name: String,
+    pub guest_ip: Ipv4Addr,
+    pub guest_mac: String,
+    pub gateway: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    /// Whether a per-VM egress NAT rule was installed
+    pub egress_nat: bool,
+    #[serde(skip)]
+    offset: u32,
+}
+
+impl NetworkLease {
+    /// Kernel `ip=` argument that configures the guest's interface at boot
+    pub fn kernel_ip_arg(&self) -> String {
+        format!(
+            "ip={}::{}:{}::eth0:off",
+            self.guest_ip, self.gateway, self.netmask
+        )
+    }
+}
+
+/// Locally administered MAC that embeds the guest IP, unique as long as the IP is
+pub fn mac_for(ip: Ipv4Addr) -> String {
+    let [a, b, c, d] = ip.octets();
+    format!("02:fc:{a:02x}:{b:02x}:{c:02x}:{d:02x}")
+}
+
+/// TAPs with our prefix that no live VM holds
+pub fn orphaned_taps(existing: &[String], prefix: &str, live: &BTreeSet<String>) -> Vec<String> {
+    existing
+        .iter()
+        .filter(|name| name.starts_with(prefix) && !live.contains(*name))
+        .cloned()
+        .collect()
+}
+
+/// The host networking calls the lease manager makes; mocked in tests
+pub trait NetOps: Send + Sync {
+    fn create_tap(&self, name: &str, bridge: &str) -> Result<(), NetworkError>;
+    fn delete_tap(&self, name: &str) -> Result<(), NetworkError>;
+    fn list_links(&self) -> Result<Vec<String>, NetworkError>;
+    /// Install the egress NAT rule for `ip` unless it is already present
+    fn ensure_nat(&self, ip: Ipv4Addr) -> Result<(), NetworkError>;
+    /// Remove the egress NAT rule for `ip` if present
+    fn remove_nat(&self, ip: Ipv4Addr) -> Result<(), NetworkError>;
+}
+
+/// `ip` and `iptables`, as the bridge setup already uses
+pub struct IpCommandOps;
+
+fn run(program: &str, args: &[&str]) -> Result<String, NetworkError> {
+    let command = format!("{program} {}", args.join(" "));
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| NetworkError::Command {
+            command: command.clone(),
+            stderr: e.to_string(),
+        })?;
+    if !output.status.success() {
+        return Err(NetworkError::Command {
+            command,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn nat_rule(ip: Ipv4Addr, action: &str) -> Vec<String> {
+    [
+        "-t",
+        "nat",
+        action,
+        "POSTROUTING",
+        "-s",
+        &format!("{ip}/32"),
+        "-j",
+        "MASQUERADE",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn iptables(args: &[String]) -> Result<String, NetworkError> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    run("iptables", &args)
+}
+
+impl NetOps for IpCommandOps {
+    fn create_tap(&self, name: &str, bridge: &str) -> Result<(), NetworkError> {
+        run("ip", &["tuntap", "add", name, "mode", "tap"])?;
+        run("ip", &["link", "set", name, "master", bridge])?;
+        run("ip", &["link", "set", name, "up"])?;
+        Ok(())
+    }
+
+    fn delete_tap(&self, name: &str) -> Result<(), NetworkError> {
+        run("ip", &["link", "del", name]).map(|_| ())
+    }
+
+    fn list_links(&self) -> Result<Vec<String>, NetworkError> {
+        let output = run("ip", &["-o", "link", "show"])?;
+        // `3: fc-tap-2@if4: <BROADCAST,...> ...`
+        Ok(output
+            .lines()
+            .filter_map(|line| line.split(": ").nth(1))
+            .map(|name| name.split('@').next().unwrap_or(name).to_string())
+            .collect())
+    }
+
+    fn ensure_nat(&self, ip: Ipv4Addr) -> Result<(), NetworkError> {
+        if iptables(&nat_rule(ip, "-C")).is_ok() {
+            return Ok(());
+        }
+        iptables(&nat_rule(ip, "-A")).map(|_| ())
+    }
+
+    fn remove_nat(&self, ip: Ipv4Addr) -> Result<(), NetworkError> {
+        // Duplicates from an earlier crash are removed too
+        while iptables(&nat_rule(ip, "-C")).is_ok() {
+            iptables(&nat_rule(ip, "-D"))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkLeaseStats {
+    pub cidr: String,
+    pub capacity: usize,
+    pub leased: usize,
+    pub available: usize,
+    /// Leases refused because the CIDR was exhausted
+    pub exhausted: u64,
+    pub orphans_swept: u64,
+}
+
+/// Hands out guest IPs, MACs and TAP devices from one CIDR
+pub struct NetworkManager {
+    config: NetworkConfig,
+    cidr: Cidr,
+    ops: Arc<dyn NetOps>,
+    leases: Mutex<HashMap<String, NetworkLease>>,
+    exhausted: AtomicU64,
+    orphans_swept: AtomicU64,
+}
+
+impl NetworkManager {
+    pub fn new(config: NetworkConfig) -> Result<Self, NetworkError> {
+        Self::with_ops(config, Arc::new(IpCommandOps))
+    }
+
+    pub fn with_ops(config: NetworkConfig, ops: Arc<dyn NetOps>) -> Result<Self, NetworkError> {
+        let cidr = Cidr::parse(&config.subnet)?;
+        Ok(Self {
+            config,
+            cidr,
+            ops,
+            leases: Mutex::new(HashMap::new()),
+            exhausted: AtomicU64::new(0),
+            orphans_swept: AtomicU64::new(0),
+        })
+    }
+
+    pub fn cidr(&self) -> Cidr {
+        self.cidr
+    }
+
+    /// Lease an IP and create the VM's TAP, with an egress NAT rule if asked for and enabled.
+    ///
+    /// Fails with `PoolExhausted` instead of waiting when every address is taken.
+    pub fn lease(&self, vm_id: &str, egress: bool) -> Result<NetworkLease, NetworkError> {
+        let lease = {
+            let mut leases = self.leases.lock().unwrap();
+            if let Some(existing) = leases.get(vm_id) {
+                return Ok(existing.clone());
+            }
+            let taken: BTreeSet<u32> = leases.values().map(|l| l.offset).collect();
+            let Some(offset) = self.cidr.guest_offsets().find(|o| !taken.contains(o)) else {
+                self.exhausted.fetch_add(1, Ordering::Relaxed);
+                return Err(NetworkError::PoolExhausted {
+                    cidr: self.cidr.to_string(),
+                    capacity: self.cidr.capacity(),
+                });
+            };
+            let guest_ip = self.cidr.nth(offset);
+            let lease = NetworkLease {
+                vm_id: vm_id.to_string(),
+                tap_name: format!("{}{offset}", self.config.tap_prefix),
+                guest_ip,
+                guest_mac: mac_for(guest_ip),
+                gateway: self.cidr.gateway(),
+                netmask: self.cidr.netmask(),
+                egress_nat: egress && self.config.per_vm_nat,
+                offset,
+            };
+            // Reserve the address before touching the host so concurrent leases skip it
+            leases.insert(vm_id.to_string(), lease.clone());
+            lease
+        };
+
+        let installed = self
+            .ops
+            .create_tap(&lease.tap_name, &self.config.bridge_name)
+            .and_then(|()| {
+                if lease.egress_nat {
+                    self.ops.ensure_nat(lease.guest_ip)
+                } else {
+                    Ok(())
+                }
+            });
+        if let Err(e) = installed {
+            self.release(vm_id);
+            return Err(e);
+        }
+        Ok(lease)
+    }
+
+    /// Tear down the VM's TAP and NAT rule and return its address; unknown VMs are ignored.
+    pub fn release(&self, vm_id: &str) {
+        let Some(lease) = self.leases.lock().unwrap().remove(vm_id) else {
+            return;
+        };
+        if lease.egress_nat {
+            if let Err(e) = self.ops.remove_nat(lease.guest_ip) {
+                warn!("Failed to remove NAT rule for {}: {}", lease.guest_ip, e);
+            }
+        }
+        if let Err(e) = self.ops.delete_tap(&lease.tap_name) {
+            warn!("Failed to delete TAP {}: {}", lease.tap_name, e);
+        }
+    }
+
+    pub fn get(&self, vm_id: &str) -> Option<NetworkLease> {
+        self.leases.lock().unwrap().get(vm_id).cloned()
+    }
+
+    /// Delete TAPs with our prefix that no current lease holds, e.g. after a crash.
+    pub fn sweep_orphans(&self) -> Result<Vec<String>, NetworkError> {
+        let live: BTreeSet<String> = self
+            .leases
+            .lock()
+            .unwrap()
+            .values()
+            .map(|l| l.tap_name.clone())
+            .collect();
+        let orphans = orphaned_taps(&self.ops.list_links()?, &self.config.tap_prefix, &live);
+        for tap in &orphans {
+            match self.ops.delete_tap(tap) {
+                Ok(()) => {
+                    self.orphans_swept.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => warn!("Failed to delete orphaned TAP {}: {}", tap, e),
+            }
+        }
+        if !orphans.is_empty() {
+            info!("Swept {} orphaned TAP devices", orphans.len());
+        }
+        Ok(orphans)
+    }
+
+    pub fn stats(&self) -> NetworkLeaseStats {
+        let leased = self.leases.lock().unwrap().len();
+        let capacity = self.cidr.capacity();
+        NetworkLeaseStats {
+            cidr: self.cidr.to_string(),
+            capacity,
+            leased,
+            available: capacity - leased,
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+            orphans_swept: self.orphans_swept.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MockOps {
+        links: Mutex<BTreeSet<String>>,
+        nat: Mutex<Vec<Ipv4Addr>>,
+    }
+
+    impl NetOps for MockOps {
+        fn create_tap(&self, name: &str, _bridge: &str) -> Result<(), NetworkError> {
+            self.links.lock().unwrap().insert(name.to_string());
+            Ok(())
+        }
+
+        fn delete_tap(&self, name: &str) -> Result<(), NetworkError> {
+            self.links.lock().unwrap().remove(name);
+            Ok(())
+        }
+
+        fn list_links(&self) -> Result<Vec<String>, NetworkError> {
+            Ok(self.links.lock().unwrap().iter().cloned().collect())
+        }
+
+        fn ensure_nat(&self, ip: Ipv4Addr) -> Result<(), NetworkError> {
+            let mut nat = self.nat.lock().unwrap();
+            if !nat.contains(&ip) {
+                nat.push(ip);
+            }
+            Ok(())
+        }
+
+        fn remove_nat(&self, ip: Ipv4Addr) -> Result<(), NetworkError> {
+            self.nat.lock().unwrap().retain(|rule| *rule != ip);
+            Ok(())
+        }
+    }
+
+    fn manager(subnet: &str) -> (Arc<MockOps>, NetworkManager) {
+        let ops = Arc::new(MockOps::default());
+        let config = NetworkConfig {
+            subnet: subnet.to_string(),
+            per_vm_nat: true,
+            ..Default::default()
+        };
+        let manager = NetworkManager::with_ops(config, ops.clone()).unwrap();
+        (ops, manager)
+    }
+
+    #[test]
+    fn leases_are_unique_and_reclaimed() {
+        // A /29 has eight addresses: network, gateway, five guests and broadcast
+        let (ops, manager) = manager("10.20.0.0/29");
+        let leases: Vec<_> = (0..5)
+            .map(|i| manager.lease(&format!("vm-{i}"), i == 0).unwrap())
+            .collect();
+        assert_eq!(leases[0].guest_ip, Ipv4Addr::new(10, 20, 0, 2));
+        assert_eq!(leases[0].gateway, Ipv4Addr::new(10, 20, 0, 1));
+        assert_eq!(leases[0].guest_mac, "02:fc:0a:14:00:02");
+        assert_eq!(
+            leases[0].kernel_ip_arg(),
+            "ip=10.20.0.2::10.20.0.1:255.255.255.248::eth0:off"
+        );
+        let macs: BTreeSet<_> = leases.iter().map(|l| &l.guest_mac).collect();
+        let taps: BTreeSet<_> = leases.iter().map(|l| &l.tap_name).collect();
+        assert_eq!((macs.len(), taps.len()), (5, 5));
+        assert_eq!(*ops.nat.lock().unwrap(), [Ipv4Addr::new(10, 20, 0, 2)]);
+
+        match manager.lease("vm-5", false) {
+            Err(NetworkError::PoolExhausted { capacity, .. }) => assert_eq!(capacity, 5),
+            other => panic!("expected an exhausted pool, got {other:?}"),
+        }
+        assert_eq!(manager.stats().exhausted, 1);
+
+        manager.release("vm-0");
+        manager.release("vm-0");
+        assert!(ops.nat.lock().unwrap().is_empty());
+        assert!(!ops.links.lock().unwrap().contains(&leases[0].tap_name));
+        let reused = manager.lease("vm-5", false).unwrap();
+        assert_eq!(reused.guest_ip, leases[0].guest_ip);
+        let stats = manager.stats();
+        assert_eq!((stats.leased, stats.available), (5, 0));
+    }
+
+    #[test]
+    fn sweeps_only_our_orphaned_taps() {
+        let (ops, manager) = manager("172.16.0.0/24");
+        let live = manager.lease("vm-live", false).unwrap();
+        ops.links.lock().unwrap().extend([
+            "fc-tap-9".to_string(),
+            "fc-tap-1a2b3c4d".to_string(),
+            "eth0".to_string(),
+            "fcbr0".to_string(),
+        ]);
+
+        let mut swept = manager.sweep_orphans().unwrap();
+        swept.sort();
+        assert_eq!(swept, ["fc-tap-1a2b3c4d", "fc-tap-9"]);
+        let links = ops.links.lock().unwrap();
+        assert!(links.contains(&live.tap_name));
+        assert!(links.contains("eth0"));
+        assert_eq!(manager.stats().orphans_swept, 2);
+
+        assert!(Cidr::parse("172.16.0.0/31").is_err());
+        assert!(Cidr::parse("not-a-cidr").is_err());
+    }
+}
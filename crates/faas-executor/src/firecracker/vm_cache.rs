@@ -101,7 +101,7 @@ impl VmResultCache {
         code: &str,
         env: &str,
         args: &[String],
-        env_vars: Option<&Vec<String>>,
+        env_vars: Option<&[faas_common::EnvVar]>,
     ) -> String {
         use std::collections::hash_map::DefaultHasher;
         use std::hash::{Hash, Hasher};
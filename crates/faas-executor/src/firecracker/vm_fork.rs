@@ -468,7 +468,7 @@ impl VmForkManager {
 
         // Execute via communication layer
         let comm_config = super::communication::CommunicationConfig {
-            vsock_cid: Some(3), // Would be dynamic
+            vsock_cid: self.vm_manager.vsock_cid(&vm_id),
             serial_device: Some(serial_socket),
             ssh_config: None,
             timeout: Duration::from_secs(30),
@@ -487,7 +487,9 @@ impl VmForkManager {
             runtime: Some(faas_common::Runtime::Firecracker),
             execution_mode: Some(faas_common::ExecutionMode::Branched),
             memory_limit: None,
+            cpu_limit: None,
             timeout: Some(30000), // 30 second timeout
+            ..Default::default()
         };
 
         executor
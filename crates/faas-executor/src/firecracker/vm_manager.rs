@@ -21,6 +21,9 @@ use tokio::sync::RwLock;
 use tracing::{info, warn};
 use uuid::Uuid;
 
+use super::cid::{self, CidAllocator};
+use super::network::{Cidr, NetworkLease, NetworkLeaseStats, NetworkManager};
+
 /// Firecracker VM configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VmConfig {
@@ -33,6 +36,13 @@ pub struct VmConfig {
     pub vsock: Option<VsockDevice>,
     pub enable_jailer: bool,
     pub jailer_cfg: Option<JailerConfig>,
+    /// Install an egress NAT rule for this VM when per-VM NAT is enabled
+    #[serde(default = "default_egress")]
+    pub egress: bool,
+}
+
+fn default_egress() -> bool {
+    true
 }
 
 impl Default for VmConfig {
@@ -47,6 +57,7 @@ impl Default for VmConfig {
             vsock: None,
             enable_jailer: false,
             jailer_cfg: None,
+            egress: true,
         }
     }
 }
@@ -69,7 +80,9 @@ pub struct RateLimiter {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VsockDevice {
-    pub guest_cid: u32,
+    /// `None` leases the next free CID from the manager's range
+    #[serde(default)]
+    pub guest_cid: Option<u32>,
     pub uds_path: String,
 }
 
@@ -124,14 +137,23 @@ pub struct FirecrackerManager {
     pub(crate) vms: Arc<RwLock<HashMap<String, Arc<RwLock<VmInstance>>>>>,
     /// Network configuration
     network_cfg: NetworkConfig,
+    /// Guest IP, MAC and TAP leases
+    network: Arc<NetworkManager>,
+    /// Vsock CID leases
+    cids: Arc<CidAllocator>,
 }
 
 #[derive(Debug, Clone)]
 pub struct NetworkConfig {
     pub bridge_name: String,
+    /// CIDR guest IPs are leased from; the first address is the bridge gateway
     pub subnet: String,
     pub gateway: String,
     pub dns: Vec<String>,
+    /// Prefix of the TAP devices we own; anything else on the host is left alone
+    pub tap_prefix: String,
+    /// NAT each VM's egress with its own rule instead of masquerading the whole subnet
+    pub per_vm_nat: bool,
 }
 
 impl Default for NetworkConfig {
@@ -141,10 +163,30 @@ impl Default for NetworkConfig {
             subnet: "172.16.0.0/24".to_string(),
             gateway: "172.16.0.1".to_string(),
             dns: vec!["8.8.8.8".to_string(), "8.8.4.4".to_string()],
+            tap_prefix: "fc-tap-".to_string(),
+            per_vm_nat: false,
         }
     }
 }
 
+impl NetworkConfig {
+    /// `FAAS_VM_CIDR` and `FAAS_VM_PER_VM_NAT=true` over the defaults
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(subnet) = std::env::var("FAAS_VM_CIDR") {
+            match Cidr::parse(&subnet) {
+                Ok(cidr) => {
+                    config.gateway = cidr.gateway().to_string();
+                    config.subnet = cidr.to_string();
+                }
+                Err(e) => warn!("Ignoring FAAS_VM_CIDR: {}", e),
+            }
+        }
+        config.per_vm_nat = std::env::var("FAAS_VM_PER_VM_NAT").is_ok_and(|v| v == "true");
+        config
+    }
+}
+
 impl FirecrackerManager {
     /// Create a new Firecracker manager
     pub fn new(base_dir: PathBuf) -> Result<Self> {
@@ -168,15 +210,52 @@ impl FirecrackerManager {
             warn!("KVM device may not have proper permissions");
         }
 
+        let network_cfg = NetworkConfig::from_env();
+        let network = Arc::new(NetworkManager::new(network_cfg.clone())?);
+        // No VM survives a restart of this process, so every TAP with our prefix is stale
+        if let Err(e) = network.sweep_orphans() {
+            warn!("Failed to sweep orphaned TAP devices: {}", e);
+        }
+        // Likewise every CID a previous process recorded
+        let cids = Arc::new(CidAllocator::with_state_file(
+            cid::range_from_env(),
+            base_dir.join("vsock-cids.json"),
+        ));
+        cids.reclaim(|_| false);
+
         Ok(Self {
             base_dir,
             firecracker_bin,
             jailer_bin,
             vms: Arc::new(RwLock::new(HashMap::new())),
-            network_cfg: NetworkConfig::default(),
+            network_cfg,
+            network,
+            cids,
         })
     }
 
+    /// Lease accounting for the guest network
+    pub fn network_stats(&self) -> NetworkLeaseStats {
+        self.network.stats()
+    }
+
+    /// The vsock CID leased to a VM
+    pub fn vsock_cid(&self, vm_id: &str) -> Option<u32> {
+        self.cids.get(vm_id)
+    }
+
+    /// Release the CIDs of VMs that are gone from the table or no longer running
+    pub async fn reclaim_orphaned_cids(&self) -> Vec<u32> {
+        let vms = self.vms.read().await;
+        let mut live = std::collections::HashSet::new();
+        for (id, vm) in vms.iter() {
+            if !matches!(vm.read().await.state, VmState::Stopped | VmState::Failed(_)) {
+                live.insert(id.clone());
+            }
+        }
+        self.cids.reclaim(|vm_id| live.contains(vm_id))
+    }
+
     fn find_firecracker() -> Result<PathBuf> {
         let paths = [
             "/usr/local/bin/firecracker",
@@ -251,7 +330,11 @@ impl FirecrackerManager {
             .args([
                 "addr",
                 "add",
-                &format!("{}/24", self.network_cfg.gateway),
+                &format!(
+                    "{}/{}",
+                    self.network_cfg.gateway,
+                    self.network.cidr().prefix_len()
+                ),
                 "dev",
                 &self.network_cfg.bridge_name,
             ])
@@ -260,7 +343,11 @@ impl FirecrackerManager {
         // Enable IP forwarding
         fs::write("/proc/sys/net/ipv4/ip_forward", "1")?;
 
-        // Setup NAT
+        // Setup NAT; with per-VM NAT each lease installs its own rule instead
+        if self.network_cfg.per_vm_nat {
+            info!("Network setup complete");
+            return Ok(());
+        }
         let _ = Command::new("iptables")
             .args([
                 "-t",
@@ -281,35 +368,6 @@ impl FirecrackerManager {
         Ok(())
     }
 
-    /// Create a TAP device for a VM
-    fn create_tap_device(&self, vm_id: &str) -> Result<String> {
-        let tap_name = format!("fc-tap-{}", &vm_id[..8]);
-
-        // Create TAP device
-        Command::new("ip")
-            .args(["tuntap", "add", &tap_name, "mode", "tap"])
-            .output()
-            .context("Failed to create TAP device")?;
-
-        // Set TAP up
-        Command::new("ip")
-            .args(["link", "set", &tap_name, "up"])
-            .output()?;
-
-        // Add to bridge
-        Command::new("ip")
-            .args([
-                "link",
-                "set",
-                &tap_name,
-                "master",
-                &self.network_cfg.bridge_name,
-            ])
-            .output()?;
-
-        Ok(tap_name)
-    }
-
     /// Launch a new VM using firecracker-rs-sdk
     pub async fn launch_vm(&self, mut config: VmConfig) -> Result<String> {
         let vm_id = Uuid::new_v4().to_string();
@@ -337,16 +395,57 @@ impl FirecrackerManager {
 
         // Setup networking if not configured
         if config.network_interfaces.is_empty() {
-            let tap_name = self.create_tap_device(&vm_id)?;
+            let lease = self.network.lease(&vm_id, config.egress)?;
+            config.kernel_args = format!("{} {}", config.kernel_args, lease.kernel_ip_arg());
             config.network_interfaces.push(NetworkInterface {
                 iface_id: "eth0".to_string(),
-                host_dev_name: tap_name,
-                guest_mac: Self::generate_mac(),
+                host_dev_name: lease.tap_name,
+                guest_mac: lease.guest_mac,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
             });
         }
 
+        let launched = match self.assign_cid(&vm_id, &mut config) {
+            Ok(()) => {
+                self.start_vm(vm_id.clone(), config, api_socket, &vm_dir)
+                    .await
+            }
+            Err(e) => Err(e.into()),
+        };
+        if launched.is_err() {
+            self.network.release(&vm_id);
+            self.cids.release(&vm_id);
+        }
+        launched
+    }
+
+    /// Lease the vsock device's CID, or claim the one it asks for, and tell the guest
+    fn assign_cid(&self, vm_id: &str, config: &mut VmConfig) -> Result<(), cid::CidError> {
+        let Some(vsock) = config.vsock.as_mut() else {
+            return Ok(());
+        };
+        let cid = match vsock.guest_cid {
+            Some(cid) => self.cids.reserve(vm_id, cid)?,
+            None => self.cids.lease(vm_id)?,
+        };
+        vsock.guest_cid = Some(cid);
+        config.kernel_args = format!("{} {}", config.kernel_args, cid::kernel_cid_arg(cid));
+        Ok(())
+    }
+
+    /// The guest network lease of a running VM
+    pub fn network_lease(&self, vm_id: &str) -> Option<NetworkLease> {
+        self.network.get(vm_id)
+    }
+
+    async fn start_vm(
+        &self,
+        vm_id: String,
+        config: VmConfig,
+        api_socket: PathBuf,
+        vm_dir: &Path,
+    ) -> Result<String> {
         // Build Firecracker instance using SDK
         let mut fc_opt = FirecrackerOption::new(&self.firecracker_bin);
         fc_opt
@@ -433,11 +532,11 @@ impl FirecrackerManager {
                 })?;
         }
 
-        // Configure vsock if requested
-        let vsock_cid = 3 + (self.vms.read().await.len() as u32);
-        if let Some(ref vsock_cfg) = config.vsock {
+        // Configure vsock if requested; launch_vm has leased its CID
+        let vsock_cid = config.vsock.as_ref().and_then(|v| v.guest_cid);
+        if let (Some(vsock_cfg), Some(guest_cid)) = (&config.vsock, vsock_cid) {
             let fc_vsock = FcVsock {
-                guest_cid: vsock_cfg.guest_cid,
+                guest_cid,
                 uds_path: vsock_cfg.uds_path.clone().into(),
                 vsock_id: None,
             };
@@ -470,7 +569,7 @@ impl FirecrackerManager {
                 network_tx_bytes: 0,
             },
             state: VmState::Running,
-            vsock_cid: Some(vsock_cid),
+            vsock_cid,
         };
 
         // Store VM
@@ -487,19 +586,6 @@ impl FirecrackerManager {
     // - configure_vm_api: Replaced by SDK instance methods (put_machine_configuration, etc.)
     // - api_request: SDK Instance has direct HTTP client for unix socket communication
 
-    fn generate_mac() -> String {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-        format!(
-            "02:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-            rng.gen::<u8>(),
-            rng.gen::<u8>(),
-            rng.gen::<u8>(),
-            rng.gen::<u8>(),
-            rng.gen::<u8>()
-        )
-    }
-
     pub async fn execute_in_vm(
         &self,
         vm_id: &str,
@@ -564,6 +650,8 @@ impl FirecrackerManager {
             tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
             vm.state = VmState::Stopped;
+            self.network.release(vm_id);
+            self.cids.release(vm_id);
             info!("VM {} stopped via SDK", vm_id);
         }
 
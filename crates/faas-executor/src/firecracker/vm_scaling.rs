@@ -2,7 +2,9 @@
 //! Provides intelligent VM pool management and auto-scaling
 
 use anyhow::Result;
+use dashmap::DashMap;
 use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::RwLock;
@@ -18,6 +20,8 @@ pub struct VmPredictiveScaler {
     pools: Arc<RwLock<HashMap<String, VmPool>>>,
     predictor: Arc<RwLock<LoadPredictor>>,
     config: ScalingConfig,
+    /// Set at runtime, by environment; the rest keep the config's
+    limits: Arc<DashMap<String, WarmLimits>>,
 }
 
 /// VM Pool for warm instances
@@ -49,10 +53,52 @@ struct PoolMetrics {
     hits: u64,
     misses: u64,
     cold_starts: u64,
-    avg_wait_time: Duration,
+    /// Spent handing out VMs, warm or cold, over `hits + misses`
+    total_wait_time: Duration,
     peak_concurrent: usize,
 }
 
+impl PoolMetrics {
+    fn record(&mut self, warm: bool, wait_time: Duration) {
+        if warm {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            self.cold_starts += 1;
+        }
+        self.total_wait_time += wait_time;
+    }
+
+    fn avg_wait_time(&self) -> Option<Duration> {
+        let acquisitions = u32::try_from(self.hits + self.misses).ok()?;
+        self.total_wait_time.checked_div(acquisitions)
+    }
+}
+
+/// Warm VMs an environment's pool keeps ready
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarmLimits {
+    pub min: usize,
+    pub max: usize,
+}
+
+/// An environment's pool, from [`VmPredictiveScaler::pool_stats`]
+#[derive(Debug, Clone)]
+pub struct VmPoolStats {
+    pub environment: String,
+    /// Forked and idle
+    pub warm: usize,
+    /// Handed out and not yet released
+    pub in_use: usize,
+    pub limits: WarmLimits,
+    /// Acquisitions served by a warm VM
+    pub hits: u64,
+    /// Acquisitions that forked a VM for themselves
+    pub misses: u64,
+    /// `None` before the first acquisition
+    pub avg_acquisition: Option<Duration>,
+}
+
 /// ML-based load predictor
 struct LoadPredictor {
     history: HashMap<String, Vec<LoadDataPoint>>,
@@ -103,12 +149,58 @@ pub struct ScalingConfig {
 impl Default for ScalingConfig {
     fn default() -> Self {
         Self {
-            min_warm_vms: 2,
-            max_warm_vms: 50,
+            min_warm_vms: 1,
+            max_warm_vms: 10,
             scale_up_threshold: 0.8,
-            scale_down_threshold: 0.3,
+            scale_down_threshold: 0.2,
             prediction_window: Duration::from_secs(300), // 5 minutes
-            warmup_time: Duration::from_millis(500),
+            warmup_time: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ScalingConfig {
+    /// Warm VMs per environment from `FAAS_VM_POOL_MIN` and `FAAS_VM_POOL_MAX`, load
+    /// thresholds from `FAAS_VM_SCALE_UP_THRESHOLD` and `FAAS_VM_SCALE_DOWN_THRESHOLD`, and
+    /// `FAAS_VM_PREDICTION_WINDOW_SECS` and `FAAS_VM_WARMUP_MS`; unset or unparsable ones
+    /// keep their defaults
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        fn parse<T: FromStr>(value: Option<String>, default: T) -> T {
+            value.and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+        }
+        let defaults = Self::default();
+        let min_warm_vms = parse(var("FAAS_VM_POOL_MIN"), defaults.min_warm_vms);
+        let threshold = |name, default: f64| parse(var(name), default).clamp(0.0, 1.0);
+        Self {
+            min_warm_vms,
+            max_warm_vms: parse(var("FAAS_VM_POOL_MAX"), defaults.max_warm_vms).max(min_warm_vms),
+            scale_up_threshold: threshold(
+                "FAAS_VM_SCALE_UP_THRESHOLD",
+                defaults.scale_up_threshold,
+            ),
+            scale_down_threshold: threshold(
+                "FAAS_VM_SCALE_DOWN_THRESHOLD",
+                defaults.scale_down_threshold,
+            ),
+            prediction_window: Duration::from_secs(parse(
+                var("FAAS_VM_PREDICTION_WINDOW_SECS"),
+                defaults.prediction_window.as_secs(),
+            )),
+            warmup_time: Duration::from_millis(parse(
+                var("FAAS_VM_WARMUP_MS"),
+                defaults.warmup_time.as_millis() as u64,
+            )),
+        }
+    }
+
+    fn limits(&self) -> WarmLimits {
+        WarmLimits {
+            min: self.min_warm_vms,
+            max: self.max_warm_vms,
         }
     }
 }
@@ -129,9 +221,61 @@ impl VmPredictiveScaler {
                 patterns: HashMap::new(),
             })),
             config,
+            limits: Arc::new(DashMap::new()),
         }
     }
 
+    /// How many warm VMs `environment` keeps
+    pub fn limits(&self, environment: &str) -> WarmLimits {
+        self.limits
+            .get(environment)
+            .map_or_else(|| self.config.limits(), |limits| *limits)
+    }
+
+    /// Keep between `limits.min` and `limits.max` warm VMs for `environment` from now on; a
+    /// max below the min is raised to it. An existing pool gives up its idle VMs past the
+    /// max at once and is refilled to the min in the background.
+    pub async fn set_limits(&self, environment: &str, limits: WarmLimits) -> Result<WarmLimits> {
+        let limits = WarmLimits {
+            max: limits.max.max(limits.min),
+            ..limits
+        };
+        self.limits.insert(environment.to_string(), limits);
+        let surplus: Vec<WarmVm> = {
+            let mut pools = self.pools.write().await;
+            let Some(pool) = pools.get_mut(environment) else {
+                return Ok(limits);
+            };
+            let keep = pool.warm_vms.len().min(limits.max);
+            pool.warm_vms.drain(keep..).collect()
+        };
+        for vm in surplus {
+            self.fork_manager.cleanup_fork(&vm.fork_id).await?;
+            debug!("Released surplus warm VM: {}", vm.vm_id);
+        }
+        let scaler = self.clone();
+        let environment = environment.to_string();
+        tokio::spawn(async move {
+            while scaler
+                .replenish_warm_pool(&environment)
+                .await
+                .unwrap_or(false)
+            {}
+        });
+        Ok(limits)
+    }
+
+    /// Every environment's pool, by environment
+    pub async fn pool_stats(&self) -> Vec<VmPoolStats> {
+        let pools = self.pools.read().await;
+        let mut stats: Vec<_> = pools
+            .values()
+            .map(|pool| pool.stats(self.limits(&pool.environment)))
+            .collect();
+        stats.sort_by(|a, b| a.environment.cmp(&b.environment));
+        stats
+    }
+
     /// Initialize pool for an environment
     pub async fn initialize_pool(
         &self,
@@ -148,8 +292,9 @@ impl VmPredictiveScaler {
             .await?;
 
         // Pre-warm initial VMs
+        let limits = self.limits(environment);
         let mut warm_vms = VecDeque::new();
-        for i in 0..self.config.min_warm_vms {
+        for i in 0..limits.min {
             let fork_id = format!("{environment}-warm-{i}");
             let forked = self.fork_manager.fork_vm(&base_id, &fork_id).await?;
 
@@ -172,10 +317,7 @@ impl VmPredictiveScaler {
         let mut pools = self.pools.write().await;
         pools.insert(environment.to_string(), pool);
 
-        info!(
-            "VM pool initialized with {} warm VMs",
-            self.config.min_warm_vms
-        );
+        info!("VM pool initialized with {} warm VMs", limits.min);
         Ok(())
     }
 
@@ -204,8 +346,6 @@ impl VmPredictiveScaler {
 
         // Try warm pool first
         if let Some(warm_vm) = pool.warm_vms.pop_front() {
-            pool.metrics.hits += 1;
-
             // Promote to hot
             let hot_vm = HotVm {
                 vm_id: warm_vm.vm_id.clone(),
@@ -224,7 +364,7 @@ impl VmPredictiveScaler {
             });
 
             let wait_time = start.elapsed();
-            pool.metrics.avg_wait_time = (pool.metrics.avg_wait_time + wait_time) / 2;
+            pool.metrics.record(true, wait_time);
 
             info!("Acquired warm VM in {:?}", wait_time);
 
@@ -237,9 +377,6 @@ impl VmPredictiveScaler {
         }
 
         // Cold start required
-        pool.metrics.misses += 1;
-        pool.metrics.cold_starts += 1;
-
         info!("Cold start required for environment: {}", environment);
 
         // Fork new VM
@@ -261,6 +398,7 @@ impl VmPredictiveScaler {
         pool.hot_vms.push(hot_vm);
 
         let acquisition_time = start.elapsed();
+        pool.metrics.record(false, acquisition_time);
 
         Ok(AcquiredVm {
             vm_id: forked.vm_id,
@@ -288,7 +426,7 @@ impl VmPredictiveScaler {
             }
 
             // Move to warm pool if space available
-            if pool.warm_vms.len() < self.config.max_warm_vms {
+            if pool.warm_vms.len() < self.limits(environment).max {
                 let fork_id = hot_vm.fork_id.clone();
                 let vm_id = hot_vm.vm_id.clone();
 
@@ -318,7 +456,7 @@ impl VmPredictiveScaler {
             return Ok(LoadPrediction {
                 expected_load: 0.5,
                 confidence: 0.3,
-                recommended_instances: self.config.min_warm_vms,
+                recommended_instances: self.limits(environment).min,
                 prediction_window: window,
             });
         }
@@ -360,7 +498,8 @@ impl VmPredictiveScaler {
         let confidence = self.calculate_confidence(model, history);
 
         // Recommend instances based on predicted load
-        let recommended = self.calculate_recommended_instances(predicted_load);
+        let recommended =
+            self.calculate_recommended_instances(self.limits(environment), predicted_load);
 
         Ok(LoadPrediction {
             expected_load: predicted_load,
@@ -412,9 +551,9 @@ impl VmPredictiveScaler {
         confidence.min(1.0)
     }
 
-    fn calculate_recommended_instances(&self, predicted_load: f64) -> usize {
-        let base = self.config.min_warm_vms as f64;
-        let max = self.config.max_warm_vms as f64;
+    fn calculate_recommended_instances(&self, limits: WarmLimits, predicted_load: f64) -> usize {
+        let base = limits.min as f64;
+        let max = limits.max as f64;
 
         let recommended = base + (predicted_load * (max - base));
         recommended.round() as usize
@@ -433,7 +572,7 @@ impl VmPredictiveScaler {
             return Ok(());
         }
 
-        let to_add = (target_size - current_size).min(self.config.max_warm_vms);
+        let to_add = (target_size - current_size).min(self.limits(environment).max);
 
         info!("Scaling up pool for {}: adding {} VMs", environment, to_add);
 
@@ -473,7 +612,7 @@ impl VmPredictiveScaler {
             .ok_or_else(|| anyhow::anyhow!("Pool not found"))?;
 
         // Remove oldest warm VMs
-        while pool.warm_vms.len() > self.config.min_warm_vms {
+        while pool.warm_vms.len() > self.limits(environment).min {
             if let Some(vm) = pool.warm_vms.pop_back() {
                 self.fork_manager.cleanup_fork(&vm.fork_id).await?;
                 debug!("Scaled down VM: {}", vm.vm_id);
@@ -499,15 +638,15 @@ impl VmPredictiveScaler {
         Ok(())
     }
 
-    /// Replenish warm pool
-    async fn replenish_warm_pool(&self, environment: &str) -> Result<()> {
+    /// Fork one warm VM if the pool is below its min; returns whether it forked one
+    async fn replenish_warm_pool(&self, environment: &str) -> Result<bool> {
         let mut pools = self.pools.write().await;
         let pool = pools
             .get_mut(environment)
             .ok_or_else(|| anyhow::anyhow!("Pool not found"))?;
 
-        if pool.warm_vms.len() >= self.config.min_warm_vms {
-            return Ok(());
+        if pool.warm_vms.len() >= self.limits(environment).min {
+            return Ok(false);
         }
 
         let base_id = pool
@@ -526,7 +665,7 @@ impl VmPredictiveScaler {
             last_used: None,
         });
 
-        Ok(())
+        Ok(true)
     }
 
     /// Record actual usage for model training
@@ -701,6 +840,21 @@ impl Clone for VmPredictiveScaler {
             pools: self.pools.clone(),
             predictor: self.predictor.clone(),
             config: self.config.clone(),
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+impl VmPool {
+    fn stats(&self, limits: WarmLimits) -> VmPoolStats {
+        VmPoolStats {
+            environment: self.environment.clone(),
+            warm: self.warm_vms.len(),
+            in_use: self.hot_vms.iter().filter(|vm| vm.in_use).count(),
+            limits,
+            hits: self.metrics.hits,
+            misses: self.metrics.misses,
+            avg_acquisition: self.metrics.avg_wait_time(),
         }
     }
 }
@@ -729,3 +883,60 @@ pub struct LoadPrediction {
     pub recommended_instances: usize,
     pub prediction_window: Duration,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaling_config_reads_the_environment() {
+        let vars: HashMap<&str, &str> = [
+            ("FAAS_VM_POOL_MIN", "3"),
+            ("FAAS_VM_POOL_MAX", "2"),
+            ("FAAS_VM_SCALE_UP_THRESHOLD", "1.5"),
+            ("FAAS_VM_SCALE_DOWN_THRESHOLD", "0.1"),
+            ("FAAS_VM_PREDICTION_WINDOW_SECS", "60"),
+            ("FAAS_VM_WARMUP_MS", "fast"),
+        ]
+        .into();
+        let config = ScalingConfig::from_vars(|name| vars.get(name).map(|v| v.to_string()));
+        assert_eq!(config.min_warm_vms, 3);
+        assert_eq!(config.max_warm_vms, 3, "the max is raised to the min");
+        assert_eq!(config.scale_up_threshold, 1.0);
+        assert_eq!(config.scale_down_threshold, 0.1);
+        assert_eq!(config.prediction_window, Duration::from_secs(60));
+        assert_eq!(config.warmup_time, ScalingConfig::default().warmup_time);
+
+        let unset = ScalingConfig::from_vars(|_| None);
+        assert_eq!(unset.limits(), WarmLimits { min: 1, max: 10 });
+    }
+
+    #[test]
+    fn pool_stats_average_every_acquisition() {
+        let mut pool = VmPool {
+            environment: "python".to_string(),
+            warm_vms: VecDeque::new(),
+            hot_vms: Vec::new(),
+            cold_snapshots: Vec::new(),
+            metrics: PoolMetrics::default(),
+        };
+        let limits = WarmLimits { min: 1, max: 4 };
+        assert_eq!(pool.stats(limits).avg_acquisition, None);
+
+        pool.metrics.record(true, Duration::from_millis(2));
+        pool.metrics.record(true, Duration::from_millis(4));
+        pool.metrics.record(false, Duration::from_millis(300));
+        pool.hot_vms.push(HotVm {
+            vm_id: "vm-1".to_string(),
+            fork_id: "python-cold-1".to_string(),
+            in_use: true,
+            last_execution: Instant::now(),
+            execution_count: 1,
+        });
+        let stats = pool.stats(limits);
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert_eq!(stats.avg_acquisition, Some(Duration::from_millis(102)));
+        assert_eq!((stats.warm, stats.in_use), (0, 1));
+        assert_eq!(stats.limits, limits);
+    }
+}
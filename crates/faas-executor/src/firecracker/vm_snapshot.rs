@@ -19,6 +19,8 @@ use tokio::sync::RwLock;
 use tracing::warn;
 use tracing::{debug, info};
 
+use crate::snapshot_inspect::ext4::Ext4Image;
+
 /// VM Snapshot Manager with full state preservation
 pub struct VmSnapshotManager {
     snapshot_dir: PathBuf,
@@ -690,6 +692,40 @@ impl VmSnapshotManager {
         Ok(format!("{:x}", hasher.finalize()))
     }
 
+    /// List files on a snapshot's disk without restoring the VM
+    ///
+    /// Only raw ext4 disks can be read; qcow2 snapshot disks report `Unsupported`.
+    pub async fn list_files(
+        &self,
+        snapshot_id: &str,
+        path: &str,
+        depth: usize,
+    ) -> Result<crate::snapshot_inspect::Listing> {
+        let disk = self.snapshot_disk(snapshot_id).await?;
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || Ext4Image::open(&disk)?.list(&path, depth))
+            .await?
+            .map_err(Into::into)
+    }
+
+    /// Read a small file from a snapshot's disk
+    pub async fn read_file(&self, snapshot_id: &str, path: &str, limit: u64) -> Result<Vec<u8>> {
+        let disk = self.snapshot_disk(snapshot_id).await?;
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || Ext4Image::open(&disk)?.read_file(&path, limit))
+            .await?
+            .map_err(Into::into)
+    }
+
+    async fn snapshot_disk(&self, snapshot_id: &str) -> Result<PathBuf> {
+        self.snapshots
+            .read()
+            .await
+            .get(snapshot_id)
+            .map(|s| s.disk_file.clone())
+            .ok_or_else(|| anyhow!("Snapshot {} not found", snapshot_id))
+    }
+
     /// Get snapshot statistics
     pub async fn get_stats(&self) -> SnapshotStats {
         let snapshots = self.snapshots.read().await;
@@ -0,0 +1,163 @@
+//! Pulling images the daemon doesn't have yet.
+//!
+//! Docker answers a create for a missing image with "No such image" instead of pulling
+//! it. The Docker executor pulls on that error and creates once more; the same pull is
+//! available up front through [`crate::DockerExecutor::ensure_image`].
+
+use crate::bollard::auth::DockerCredentials;
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::image::CreateImageOptions;
+use crate::bollard::Docker;
+use crate::{ExecutorError, Result};
+use futures::StreamExt;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// How long a pull may take when `FAAS_IMAGE_PULL_TIMEOUT_SECS` isn't set
+pub const DEFAULT_PULL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How images are pulled: the time a pull gets and the credentials it sends
+#[derive(Debug, Clone)]
+pub struct PullSettings {
+    pub timeout: Duration,
+    pub auth: Option<DockerCredentials>,
+}
+
+impl Default for PullSettings {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_PULL_TIMEOUT,
+            auth: None,
+        }
+    }
+}
+
+impl PullSettings {
+    /// `FAAS_IMAGE_PULL_TIMEOUT_SECS`, and registry credentials from
+    /// `FAAS_REGISTRY_USERNAME` / `FAAS_REGISTRY_PASSWORD` (for `FAAS_REGISTRY_SERVER`,
+    /// Docker Hub when unset)
+    pub fn from_env() -> Self {
+        let timeout = std::env::var("FAAS_IMAGE_PULL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_PULL_TIMEOUT, Duration::from_secs);
+        let auth = std::env::var("FAAS_REGISTRY_USERNAME")
+            .ok()
+            .map(|username| DockerCredentials {
+                username: Some(username),
+                password: std::env::var("FAAS_REGISTRY_PASSWORD").ok(),
+                serveraddress: std::env::var("FAAS_REGISTRY_SERVER").ok(),
+                ..Default::default()
+            });
+        Self { timeout, auth }
+    }
+}
+
+/// Whether a create failed only because the image isn't on the daemon
+pub fn is_missing_image(error: &BollardError) -> bool {
+    matches!(
+        error,
+        BollardError::DockerResponseServerError { status_code: 404, message }
+            if message.starts_with("No such image")
+    )
+}
+
+/// `alpine` as `alpine:latest`; an untagged pull would fetch every tag
+fn with_default_tag(image: &str) -> String {
+    let name = image.rsplit('/').next().unwrap_or(image);
+    if name.contains(':') || name.contains('@') {
+        image.to_string()
+    } else {
+        format!("{image}:latest")
+    }
+}
+
+/// Pull `image` unless the daemon already has it
+pub async fn ensure_image(docker: &Docker, image: &str, settings: &PullSettings) -> Result<()> {
+    match docker.inspect_image(image).await {
+        Ok(_) => Ok(()),
+        Err(BollardError::DockerResponseServerError {
+            status_code: 404, ..
+        }) => pull(docker, image, settings).await,
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Pull `image`, logging each layer's progress, within `settings.timeout`
+pub async fn pull(docker: &Docker, image: &str, settings: &PullSettings) -> Result<()> {
+    let reference = with_default_tag(image);
+    let started = Instant::now();
+    info!("Pulling image {}", reference);
+    let mut progress = docker.create_image(
+        Some(CreateImageOptions {
+            from_image: reference.clone(),
+            ..Default::default()
+        }),
+        None,
+        settings.auth.clone(),
+    );
+    let pulled = tokio::time::timeout(settings.timeout, async {
+        while let Some(event) = progress.next().await {
+            let event = event.map_err(|source| ExecutorError::PullFailed {
+                image: reference.clone(),
+                source,
+            })?;
+            debug!(
+                image = %reference,
+                layer = event.id.as_deref().unwrap_or_default(),
+                status = event.status.as_deref().unwrap_or_default(),
+                progress = event.progress.as_deref().unwrap_or_default(),
+                "Image pull progress"
+            );
+        }
+        Ok(())
+    })
+    .await;
+    match pulled {
+        Ok(Ok(())) => {
+            info!("Pulled image {} in {:?}", reference, started.elapsed());
+            Ok(())
+        }
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(ExecutorError::PullTimeout {
+            image: reference,
+            timeout: settings.timeout,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn untagged_images_pull_latest() {
+        assert_eq!(with_default_tag("alpine"), "alpine:latest");
+        assert_eq!(with_default_tag("alpine:3.19"), "alpine:3.19");
+        assert_eq!(
+            with_default_tag("localhost:5000/team/app"),
+            "localhost:5000/team/app:latest"
+        );
+        assert_eq!(with_default_tag("alpine@sha256:abc"), "alpine@sha256:abc");
+    }
+
+    #[test]
+    fn only_a_missing_image_triggers_a_pull() {
+        assert!(is_missing_image(&BollardError::DockerResponseServerError {
+            status_code: 404,
+            message: "No such image: busybox:1.36".to_string(),
+        }));
+        assert!(!is_missing_image(
+            &BollardError::DockerResponseServerError {
+                status_code: 404,
+                message: "No such container: abc".to_string(),
+            }
+        ));
+        assert!(!is_missing_image(
+            &BollardError::DockerResponseServerError {
+                status_code: 409,
+                message: "Conflict".to_string(),
+            }
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+//! Files copied into a container before its command starts
+//!
+//! The files travel as one tar archive that Docker extracts at `/`, creating any parent
+//! directories they need. Relative paths are taken from the execution's working directory.
+
+use std::path::{Component, Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where `path` lands in the container: absolute and normalized, or `None` when it names
+/// no file or goes through `..`
+pub fn resolve_path(path: &str, working_dir: Option<&str>) -> Option<String> {
+    let base = match working_dir {
+        Some(dir) if !path.starts_with('/') => dir,
+        _ => "/",
+    };
+    let mut parts = Vec::new();
+    for component in Path::new(base).join(path).components() {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(part) => parts.push(part.to_str()?.to_string()),
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    if parts.is_empty() || path.ends_with('/') {
+        return None;
+    }
+    Some(format!("/{}", parts.join("/")))
+}
+
+/// One tar archive of `files`, named relative to `/`, for `upload_to_container`
+pub fn archive(files: &[(String, Vec<u8>)], working_dir: Option<&str>) -> std::io::Result<Vec<u8>> {
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, contents) in files {
+        let resolved = resolve_path(path, working_dir).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{path:?} is not a file path inside the container"),
+            )
+        })?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_size(contents.len() as u64);
+        builder.append_data(&mut header, &resolved[1..], contents.as_slice())?;
+    }
+    builder.into_inner()
+}
+
+/// Every file under the host directory `dir`, as input files at the same relative paths
+/// under `into`. Symlinks are skipped, since they would be followed on the host.
+pub fn from_dir(dir: &Path, into: &str) -> std::io::Result<Vec<(String, Vec<u8>)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if let (true, Ok(relative)) = (file_type.is_file(), path.strip_prefix(dir)) {
+                let target = format!("{}/{}", into.trim_end_matches('/'), relative.display());
+                files.push((target, std::fs::read(&path)?));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn relative_paths_start_at_the_working_dir() {
+        assert_eq!(
+            resolve_path("data/in.csv", Some("/app")).as_deref(),
+            Some("/app/data/in.csv")
+        );
+        assert_eq!(
+            resolve_path("/etc/app.toml", Some("/app")).as_deref(),
+            Some("/etc/app.toml")
+        );
+        assert_eq!(resolve_path("./in.txt", None).as_deref(), Some("/in.txt"));
+        assert_eq!(resolve_path("../escape", Some("/app")), None);
+        assert_eq!(resolve_path("/", None), None);
+        assert_eq!(resolve_path("dir/", None), None);
+    }
+
+    #[test]
+    fn the_archive_holds_every_file_under_its_resolved_path() {
+        let files = [
+            ("a.txt".to_string(), b"first".to_vec()),
+            ("/srv/nested/b.bin".to_string(), vec![0, 1, 2]),
+        ];
+        let data = archive(&files, Some("/work")).unwrap();
+
+        let mut found = Vec::new();
+        let mut tar = tar::Archive::new(data.as_slice());
+        for entry in tar.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            found.push((path, contents));
+        }
+        assert_eq!(
+            found,
+            vec![
+                ("work/a.txt".to_string(), b"first".to_vec()),
+                ("srv/nested/b.bin".to_string(), vec![0, 1, 2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_directory_becomes_the_files_under_it() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("nested")).unwrap();
+        std::fs::write(dir.path().join("top.txt"), b"top").unwrap();
+        std::fs::write(dir.path().join("nested/deep.txt"), b"deep").unwrap();
+
+        assert_eq!(
+            from_dir(dir.path(), "/workspace").unwrap(),
+            vec![
+                ("/workspace/nested/deep.txt".to_string(), b"deep".to_vec()),
+                ("/workspace/top.txt".to_string(), b"top".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_path_outside_the_container_fails_the_archive() {
+        let files = [("../../etc/passwd".to_string(), Vec::new())];
+        let error = archive(&files, Some("/app")).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
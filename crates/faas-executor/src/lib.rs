@@ -1,20 +1,26 @@
 use async_trait::async_trait;
+use docker_endpoints::{DockerEndpointPool, EndpointError};
+use docktopus::bollard::auth::DockerCredentials;
 use docktopus::bollard::container::{
     AttachContainerOptions, AttachContainerResults, LogOutput, RemoveContainerOptions,
-    WaitContainerOptions,
+    UploadToContainerOptions, WaitContainerOptions,
 };
 use docktopus::bollard::errors::Error as BollardError;
+use docktopus::bollard::image::CommitContainerOptions;
 use docktopus::bollard::Docker;
 use faas_common::{
-    ExecutionMode, FaasError, InvocationResult, Result as CommonResult, SandboxConfig,
-    SandboxExecutor,
+    EnvOverrides, EnvVar, ExecutionMode, FaasError, GpuRequest, InvocationResult, Placement,
+    Result as CommonResult, SandboxConfig, SandboxExecutor, TmpfsMount, Ulimit,
 };
 use futures::{StreamExt, TryStreamExt};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
+use tokio::sync::mpsc;
 use tokio::{fs, io::AsyncWriteExt};
-use tracing::{error, info, instrument, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
 use uuid::Uuid;
 
 // Re-export dependencies potentially needed by consumers (like orchestrator)
@@ -22,17 +28,32 @@ pub use docktopus;
 pub use docktopus::bollard;
 pub use faas_common as common;
 
+pub mod canary;
+pub mod container_labels;
 pub mod container_pool;
 pub mod criu;
+pub mod docker_checkpoint;
+pub mod docker_endpoints;
 pub mod docker_fork;
 pub mod docker_snapshot;
+pub mod drain;
+pub mod env_overrides;
 pub mod environment_registry;
 pub mod executor;
 pub mod firecracker;
+pub mod image_pull;
+pub mod input_files;
+pub mod network_policy;
+pub mod output_limit;
 pub mod performance;
 pub mod platform;
 pub mod readiness;
+mod resource_usage;
+pub mod rootfs_builder;
+pub mod running;
+pub mod session_state;
 pub mod snapshot;
+pub mod snapshot_inspect;
 pub mod ssh;
 pub mod storage;
 pub mod sync;
@@ -61,45 +82,254 @@ pub enum ExecutorError {
     Internal(String),
     #[error("Firecracker error: {0}")]
     Firecracker(#[source] firecracker_rs_sdk::Error),
+    #[error("Docker endpoint error: {0}")]
+    Endpoint(#[from] docker_endpoints::EndpointError),
+    #[error("Execution timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Pulling image {image} failed: {source}")]
+    PullFailed {
+        image: String,
+        #[source]
+        source: BollardError,
+    },
+    #[error("Pulling image {image} timed out after {timeout:?}")]
+    PullTimeout { image: String, timeout: Duration },
+    #[error("Payload of {size} bytes is over the {limit} byte limit")]
+    PayloadTooLarge { size: usize, limit: usize },
+    #[error("Writing the payload to stdin failed: {0}")]
+    StdinFailed(#[source] std::io::Error),
+    #[error("Invalid input files: {0}")]
+    InvalidInputFiles(#[source] std::io::Error),
+    #[error("Copying input files into the container failed: {0}")]
+    UploadFailed(#[source] BollardError),
+    #[error("Execution was cancelled")]
+    Cancelled,
+    #[error("Network policy could not be applied: {0}")]
+    Network(String),
+    #[error("User {0:?} does not exist in the image")]
+    UnknownUser(String),
+}
+
+impl ExecutorError {
+    /// The underlying Docker API error, if this came from bollard
+    pub fn bollard_error(&self) -> Option<&BollardError> {
+        match self {
+            ExecutorError::CreationFailed(e)
+            | ExecutorError::StartFailed(e)
+            | ExecutorError::WaitFailed(e)
+            | ExecutorError::LogRetrievalFailed(e)
+            | ExecutorError::RemovalFailed(e)
+            | ExecutorError::UploadFailed(e)
+            | ExecutorError::DockerApi(e)
+            | ExecutorError::PullFailed { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Whether the daemon became unreachable before a container was created, so running
+    /// the request again on a fresh connection can't run it twice
+    pub fn lost_connection_before_start(&self) -> bool {
+        match self {
+            ExecutorError::CreationFailed(e) | ExecutorError::PullFailed { source: e, .. } => {
+                docker_endpoints::is_connection_error(e)
+            }
+            _ => false,
+        }
+    }
 }
 
 // Implement conversion from ExecutorError to the common FaasError
 impl From<ExecutorError> for FaasError {
     fn from(err: ExecutorError) -> Self {
-        FaasError::Executor(err.to_string()) // Simple conversion for now
+        match err {
+            ExecutorError::Timeout(timeout) => FaasError::Timeout {
+                timeout_ms: timeout.as_millis() as u64,
+            },
+            ExecutorError::Network(reason) => FaasError::IncompatibleFeature {
+                feature: "network".to_string(),
+                runtime: "docker".to_string(),
+                reason,
+            },
+            ExecutorError::UnknownUser(user) => FaasError::UnknownUser { user },
+            err => FaasError::Executor(err.to_string()),
+        }
     }
 }
 
 // Define local Result using the crate's Error type
 pub type Result<T> = std::result::Result<T, ExecutorError>;
 
+/// Whether a Docker or runc error says the requested user has no `/etc/passwd` entry
+pub(crate) fn is_missing_user(message: &str) -> bool {
+    message.contains("unable to find user") || message.contains("no matching entries in passwd")
+}
+
+/// How long a container may run when the request doesn't set `timeout`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest stdin payload when `FAAS_MAX_PAYLOAD_BYTES` isn't set; the gateway caps uploads
+/// with the same variable
+pub const DEFAULT_MAX_PAYLOAD_BYTES: usize = 256 * 1024 * 1024;
+
+/// How long a request that lost its Docker connection waits for the reconnect backoff
+/// before giving up
+pub const DEFAULT_RECONNECT_WAIT: Duration = Duration::from_secs(5);
+
+/// Stdin is written and flushed this much at a time
+const STDIN_CHUNK_BYTES: usize = 64 * 1024;
+
 // Rename InternalContainerConfig and update fields to match SandboxConfig
 #[derive(Debug)]
 pub struct InternalDockerConfig {
     pub function_id: String,
     pub image: String, // DockerExecutor expects an image
     pub command: Vec<String>,
-    pub env_vars: Option<Vec<String>>,
+    pub env_vars: Option<Vec<EnvVar>>,
+    pub working_dir: Option<String>,
+    pub user: Option<String>,
     pub payload: Vec<u8>,
+    /// Tar of the request's input files, extracted at `/` before the container starts
+    pub input_archive: Option<Vec<u8>>,
+    /// Host and container path of each read-only bind mount
+    pub read_only_mounts: Vec<(String, String)>,
     pub execution_mode: Option<ExecutionMode>,
+    pub ulimits: Option<Vec<Ulimit>>,
+    pub shm_size_mb: Option<u64>,
+    pub pids_limit: Option<i64>,
+    pub tmpfs: Option<Vec<TmpfsMount>>,
+    pub environment_overrides: Option<EnvOverrides>,
+    pub memory_limit_mb: Option<u32>,
+    pub cpu_limit: Option<f64>,
+    pub timeout_ms: Option<u64>,
+    pub gpu: Option<GpuRequest>,
+    pub network: network_policy::SandboxNetwork,
+    /// Image the container's filesystem is committed to once its command exits, before the
+    /// container is removed
+    pub commit_as: Option<String>,
 }
 
 // --- DockerExecutor Implementation ---
 
-#[derive(Clone)] // Clone if Arc<Docker> is cloneable
+#[derive(Clone)]
 pub struct DockerExecutor {
-    docker_client: Arc<Docker>,
+    endpoints: Arc<DockerEndpointPool>,
+    pull: image_pull::PullSettings,
+    max_payload_bytes: usize,
+    output: output_limit::OutputLimit,
+    running: Arc<running::RunningExecutions>,
+    reconnect_wait: Duration,
 }
 
 impl DockerExecutor {
-    // Constructor
+    /// Run everything on one daemon, reconnecting to `DOCKER_HOST` if it drops
     pub fn new(docker_client: Arc<Docker>) -> Self {
-        Self { docker_client }
+        Self::with_endpoints(Arc::new(DockerEndpointPool::single(docker_client)))
+    }
+
+    /// Place each execution on an endpoint matching its `placement`
+    pub fn with_endpoints(endpoints: Arc<DockerEndpointPool>) -> Self {
+        let max_payload_bytes = std::env::var("FAAS_MAX_PAYLOAD_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_PAYLOAD_BYTES);
+        Self {
+            endpoints,
+            pull: image_pull::PullSettings::from_env(),
+            max_payload_bytes,
+            output: output_limit::OutputLimit::from_env(),
+            running: Arc::default(),
+            reconnect_wait: DEFAULT_RECONNECT_WAIT,
+        }
+    }
+
+    /// How long a request whose connection dropped waits to reconnect before failing
+    pub fn with_reconnect_wait(mut self, wait: Duration) -> Self {
+        self.reconnect_wait = wait;
+        self
+    }
+
+    /// Register runs in `running`, so cancelling an execution there stops its container
+    pub fn with_running(mut self, running: Arc<running::RunningExecutions>) -> Self {
+        self.running = running;
+        self
+    }
+
+    /// Refuse executions whose stdin payload is over `limit` bytes
+    pub fn with_max_payload_bytes(mut self, limit: usize) -> Self {
+        self.max_payload_bytes = limit;
+        self
+    }
+
+    /// Keep output up to `limit` instead of the one from the environment
+    pub fn with_output_limit(mut self, limit: output_limit::OutputLimit) -> Self {
+        self.output = limit;
+        self
+    }
+
+    /// Pull missing images with `settings` instead of the ones from the environment
+    pub fn with_pull_settings(mut self, settings: image_pull::PullSettings) -> Self {
+        self.pull = settings;
+        self
+    }
+
+    pub fn endpoints(&self) -> &Arc<DockerEndpointPool> {
+        &self.endpoints
     }
 
     // Expose docker client for snapshot operations
-    pub fn docker(&self) -> &Arc<Docker> {
-        &self.docker_client
+    pub async fn docker(&self) -> Result<Arc<Docker>> {
+        let (_, client) = self.endpoints.client_for(&Placement::default()).await?;
+        Ok(client)
+    }
+
+    /// The version of the default endpoint's daemon, reconnecting once if the connection
+    /// was lost
+    pub async fn ping(&self) -> Result<Option<String>> {
+        let endpoint = self.endpoints.select(&Placement::default())?;
+        let mut reconnected = false;
+        loop {
+            let client = if reconnected {
+                endpoint.reconnect(self.reconnect_wait).await
+            } else {
+                endpoint.client().await
+            };
+            let error = match client {
+                Ok(docker) => match docker.version().await {
+                    Ok(version) => return Ok(version.version),
+                    Err(e) => {
+                        endpoint.report_error(&e).await;
+                        let lost = docker_endpoints::is_connection_error(&e);
+                        (ExecutorError::DockerApi(e), lost)
+                    }
+                },
+                Err(e @ EndpointError::Unavailable { .. }) => (e.into(), true),
+                Err(e) => (e.into(), false),
+            };
+            match error {
+                (e, true) if !reconnected => {
+                    debug!(
+                        endpoint = endpoint.name(),
+                        "Docker ping failed, reconnecting: {}", e
+                    );
+                    reconnected = true;
+                }
+                (e, _) => return Err(e),
+            }
+        }
+    }
+
+    /// Pull `image` unless the default endpoint already has it; `auth` overrides the
+    /// configured registry credentials
+    pub async fn ensure_image(&self, image: &str, auth: Option<DockerCredentials>) -> Result<()> {
+        let docker = self.docker().await?;
+        let settings = match auth {
+            Some(auth) => image_pull::PullSettings {
+                auth: Some(auth),
+                ..self.pull.clone()
+            },
+            None => self.pull.clone(),
+        };
+        image_pull::ensure_image(&docker, image, &settings).await
     }
 }
 
@@ -108,40 +338,227 @@ impl DockerExecutor {
 impl SandboxExecutor for DockerExecutor {
     #[instrument(skip(self, config), fields(function_id = %config.function_id, source = %config.source))]
     async fn execute(&self, config: SandboxConfig) -> CommonResult<InvocationResult> {
+        self.run(config, None, None).await
+    }
+}
+
+impl DockerExecutor {
+    /// Like [`SandboxExecutor::execute`], but also sends each piece of output to
+    /// `live_output` as the container writes it. The result still carries all of it.
+    pub async fn execute_streaming(
+        &self,
+        config: SandboxConfig,
+        live_output: mpsc::UnboundedSender<OutputChunk>,
+    ) -> CommonResult<InvocationResult> {
+        self.run(config, Some(live_output), None).await
+    }
+
+    /// Like [`SandboxExecutor::execute`], but the container's filesystem is committed to
+    /// `image` when the command exits, so later containers can start from it
+    pub async fn execute_committed(
+        &self,
+        config: SandboxConfig,
+        image: &str,
+    ) -> CommonResult<InvocationResult> {
+        self.run(config, None, Some(image.to_string())).await
+    }
+
+    async fn run(
+        &self,
+        config: SandboxConfig,
+        live_output: Option<mpsc::UnboundedSender<OutputChunk>>,
+        commit_as: Option<String>,
+    ) -> CommonResult<InvocationResult> {
+        if config.payload.len() > self.max_payload_bytes {
+            return Err(ExecutorError::PayloadTooLarge {
+                size: config.payload.len(),
+                limit: self.max_payload_bytes,
+            }
+            .into());
+        }
+        let input_archive = if config.input_files.is_empty() {
+            None
+        } else {
+            let archive = input_files::archive(&config.input_files, config.working_dir.as_deref())
+                .map_err(ExecutorError::InvalidInputFiles)?;
+            Some(archive)
+        };
         // Convert SandboxConfig to the internal config needed by run_container_inner
-        let internal_config = InternalDockerConfig {
+        let mut internal_config = InternalDockerConfig {
             function_id: config.function_id,
             image: config.source, // Assume source is the image name for Docker
             command: config.command,
             env_vars: config.env_vars,
+            working_dir: config.working_dir,
+            user: config.user,
             payload: config.payload,
+            input_archive,
+            read_only_mounts: config.read_only_mounts,
             execution_mode: config.execution_mode,
+            ulimits: config.ulimits,
+            shm_size_mb: config.shm_size_mb,
+            pids_limit: config.pids_limit,
+            tmpfs: config.tmpfs,
+            environment_overrides: config.environment_overrides,
+            memory_limit_mb: config.memory_limit,
+            cpu_limit: config.cpu_limit,
+            timeout_ms: config.timeout,
+            gpu: config.gpu,
+            network: Default::default(),
+            commit_as,
         };
-        // Call the actual container running logic
-        run_container_inner(self.docker_client.clone(), internal_config)
-            .await
-            .map_err(FaasError::from) // Convert ExecutorError to FaasError
+        let placement = config.placement.unwrap_or_default();
+        let run = self.running.track(&internal_config.function_id);
+        let mut reconnected = false;
+        loop {
+            let (endpoint, docker_client) = self
+                .endpoints
+                .client_for(&placement)
+                .await
+                .map_err(ExecutorError::from)?;
+            let network = match &config.network {
+                Some(policy) => {
+                    let name = format!(
+                        "{}-{}",
+                        internal_config.function_id,
+                        &Uuid::new_v4().simple().to_string()[..8]
+                    );
+                    network_policy::SandboxNetwork::prepare(&docker_client, policy, &name).await?
+                }
+                None => Default::default(),
+            };
+            internal_config.network = network.clone();
+            // Call the actual container running logic
+            let result = run_container_inner(
+                docker_client.clone(),
+                &mut internal_config,
+                &self.pull,
+                &self.output,
+                live_output.clone(),
+                run.token(),
+            )
+            .await;
+            network.release(&docker_client).await;
+            let e = match result {
+                Ok(result) => return Ok(result),
+                Err(e) => e,
+            };
+            if let Some(source) = e.bollard_error() {
+                endpoint.report_error(source).await;
+            }
+            // Once a container exists it may have run, so only an unstarted request is retried
+            if reconnected || !e.lost_connection_before_start() {
+                return Err(e.into()); // Convert ExecutorError to FaasError
+            }
+            warn!(
+                endpoint = endpoint.name(),
+                "Lost the Docker connection before the container was created, retrying: {}", e
+            );
+            endpoint
+                .reconnect(self.reconnect_wait)
+                .await
+                .map_err(ExecutorError::from)?;
+            reconnected = true;
+        }
+    }
+}
+
+/// The default ulimits, `/dev/shm` and pids limit (see [`faas_common::default_ulimits`]),
+/// for warm containers: a request keeping the defaults can run in one, see
+/// `executor::requires_fresh_container`.
+pub(crate) fn default_limits_host_config() -> docktopus::bollard::models::HostConfig {
+    docktopus::bollard::models::HostConfig {
+        ulimits: Some(
+            faas_common::default_ulimits()
+                .into_iter()
+                .map(|u| docktopus::bollard::models::ResourcesUlimits {
+                    name: Some(u.name),
+                    soft: Some(u.soft),
+                    hard: Some(u.hard),
+                })
+                .collect(),
+        ),
+        shm_size: Some((faas_common::DEFAULT_SHM_SIZE_MB * 1024 * 1024) as i64),
+        pids_limit: Some(faas_common::DEFAULT_PIDS_LIMIT),
+        ..Default::default()
+    }
+}
+
+/// Translate the sandbox resource options into the matching `HostConfig` fields.
+///
+/// Swap is capped at the memory limit so an over-limit process is OOM-killed instead of
+/// paging.
+fn resource_host_config(config: &InternalDockerConfig) -> docktopus::bollard::models::HostConfig {
+    let memory = config.memory_limit_mb.map(|mb| i64::from(mb) * 1024 * 1024);
+    let mut host_config = docktopus::bollard::models::HostConfig {
+        memory,
+        memory_swap: memory,
+        nano_cpus: config.cpu_limit.map(|cores| (cores * 1e9) as i64),
+        ulimits: config.ulimits.as_ref().map(|ulimits| {
+            ulimits
+                .iter()
+                .map(|u| docktopus::bollard::models::ResourcesUlimits {
+                    name: Some(u.name.clone()),
+                    soft: Some(u.soft),
+                    hard: Some(u.hard),
+                })
+                .collect()
+        }),
+        shm_size: config.shm_size_mb.map(|mb| (mb * 1024 * 1024) as i64),
+        // The pids cgroup binds root too, where the `nproc` ulimit does not
+        pids_limit: config.pids_limit,
+        tmpfs: config.tmpfs.as_ref().map(|mounts| {
+            mounts
+                .iter()
+                .map(|m| (m.path.clone(), format!("rw,nosuid,size={}m", m.size_mb)))
+                .collect()
+        }),
+        device_requests: config.gpu.as_ref().map(|gpu| vec![gpu_device_request(gpu)]),
+        ..Default::default()
+    };
+    config.network.apply(&mut host_config);
+    host_config
+}
+
+/// The NVIDIA device request for `gpu`; a count of -1 asks Docker for every GPU
+fn gpu_device_request(gpu: &GpuRequest) -> docktopus::bollard::models::DeviceRequest {
+    let capabilities = if gpu.capabilities.is_empty() {
+        vec!["gpu".to_string()]
+    } else {
+        gpu.capabilities.clone()
+    };
+    docktopus::bollard::models::DeviceRequest {
+        driver: Some("nvidia".to_string()),
+        count: match (gpu.count, gpu.device_ids.is_empty()) {
+            (Some(count), _) => Some(i64::from(count)),
+            (None, true) => Some(-1),
+            (None, false) => None,
+        },
+        device_ids: (!gpu.device_ids.is_empty()).then(|| gpu.device_ids.clone()),
+        capabilities: Some(vec![capabilities]),
+        options: None,
     }
 }
 
 // --- Internal Container Execution Logic ---
 // Renamed from run_container to run_container_inner to avoid conflict with trait method
-#[instrument(skip(docker_client, config), fields(function_id = %config.function_id, image = %config.image))]
+#[instrument(skip(docker_client, config, pull, output_limit, live_output, cancel), fields(function_id = %config.function_id, image = %config.image))]
 async fn run_container_inner(
     docker_client: Arc<Docker>,
-    config: InternalDockerConfig, // Use updated internal config type
+    config: &mut InternalDockerConfig, // Use updated internal config type
+    pull: &image_pull::PullSettings,
+    output_limit: &output_limit::OutputLimit,
+    live_output: Option<mpsc::UnboundedSender<OutputChunk>>,
+    cancel: &CancellationToken,
 ) -> Result<InvocationResult> {
     // Returns local ExecutorError Result
     let request_id = Uuid::new_v4().to_string();
     info!(%request_id, function_id=%config.function_id, "Preparing container...");
 
     // Configure container options, including stdin
-    let mut host_config = None;
+    let mut host_config = resource_host_config(&config);
     if matches!(config.execution_mode, Some(ExecutionMode::Persistent)) {
-        let base_path = std::env::var("FAAS_PERSIST_ROOT")
-            .map(PathBuf::from)
-            .unwrap_or_else(|_| std::env::temp_dir().join("faas-persistent"));
-        let workspace_path = base_path.join(&config.function_id);
+        let workspace_path = persistent_workspace(&config.function_id);
         fs::create_dir_all(&workspace_path).await.map_err(|e| {
             ExecutorError::Internal(format!(
                 "Failed to create persistent workspace {}: {}",
@@ -158,11 +575,26 @@ async fn run_container_inner(
                 .to_string_lossy()
         );
 
-        host_config = Some(docktopus::bollard::models::HostConfig {
-            binds: Some(vec![bind]),
-            ..Default::default()
-        });
+        host_config.binds = Some(vec![bind]);
+    }
+    for (host, sandbox) in &config.read_only_mounts {
+        host_config
+            .binds
+            .get_or_insert_with(Vec::new)
+            .push(format!("{host}:{sandbox}:ro"));
+    }
+    let mut env = config.env_vars.as_deref().map(|vars| {
+        faas_common::env::dedupe_vars(vars)
+            .iter()
+            .map(EnvVar::to_string)
+            .collect()
+    });
+    if let Some(overrides) = &config.environment_overrides {
+        env_overrides::prepare(&docker_client, overrides)
+            .await?
+            .apply(&mut env, &mut host_config);
     }
+    let host_config = Some(host_config);
 
     let bollard_config_override = docktopus::bollard::container::Config {
         attach_stdin: Some(true),
@@ -180,26 +612,67 @@ async fn run_container_inner(
         ..Default::default()
     });
 
-    let container_create_body = docker_client
-        .create_container(
-            create_options,
-            docktopus::bollard::container::Config {
-                image: Some(config.image.clone()),
-                cmd: Some(config.command.clone()),
-                env: config.env_vars.clone(),
-                attach_stdin: Some(true),
-                open_stdin: Some(true),
-                stdin_once: Some(true),
-                tty: Some(false),
-                host_config,
-                ..bollard_config_override // Apply other overrides if needed
-            },
-        )
-        .await
-        .map_err(ExecutorError::CreationFailed)?;
+    let container_config = docktopus::bollard::container::Config {
+        image: Some(config.image.clone()),
+        cmd: Some(config.command.clone()),
+        env,
+        // Docker creates a working directory the image lacks
+        working_dir: config.working_dir.clone(),
+        user: config.user.clone(),
+        labels: Some(container_labels::labels(
+            container_labels::ContainerKind::Ephemeral,
+            &request_id,
+            &config.function_id,
+        )),
+        attach_stdin: Some(true),
+        open_stdin: Some(true),
+        stdin_once: Some(true),
+        tty: Some(false),
+        host_config,
+        ..bollard_config_override // Apply other overrides if needed
+    };
+
+    let container_create_body = async {
+        match docker_client
+            .create_container(create_options.clone(), container_config.clone())
+            .await
+        {
+            Err(e) if image_pull::is_missing_image(&e) => {
+                image_pull::pull(&docker_client, &config.image, pull).await?;
+                docker_client
+                    .create_container(create_options, container_config)
+                    .await
+            }
+            created => created,
+        }
+        .map_err(ExecutorError::CreationFailed)
+    }
+    .instrument(info_span!("container_create", name = %temp_container_name))
+    .await?;
 
     let container_id = container_create_body.id;
     info!(%container_id, name=%temp_container_name, "Container created.");
+    if cancel.is_cancelled() {
+        remove_container(&docker_client, &container_id).await;
+        return Err(ExecutorError::Cancelled);
+    }
+
+    if let Some(archive) = config.input_archive.take() {
+        let uploaded = docker_client
+            .upload_to_container(
+                &container_id,
+                Some(UploadToContainerOptions {
+                    path: "/",
+                    ..Default::default()
+                }),
+                archive.into(),
+            )
+            .await;
+        if let Err(e) = uploaded {
+            remove_container(&docker_client, &container_id).await;
+            return Err(ExecutorError::UploadFailed(e));
+        }
+    }
 
     // Attach streams BEFORE starting
     let attach_options = AttachContainerOptions::<String> {
@@ -220,49 +693,52 @@ async fn run_container_inner(
 
     // Start the container
     info!(%container_id, "Starting container...");
-    docker_client
+    let started = docker_client
         .start_container(
             &container_id,
             None::<docktopus::bollard::container::StartContainerOptions<String>>,
         )
+        .instrument(info_span!("container_start", %container_id))
         .await
-        .map_err(ExecutorError::StartFailed)?;
-
-    info!(%container_id, "Container started. Writing payload to stdin...");
-    let payload_clone = config.payload.clone();
-    let container_id_clone = container_id.clone();
-    let stdin_handle = tokio::spawn(async move {
-        if !payload_clone.is_empty() {
-            info!(%container_id_clone, payload_size = payload_clone.len(), "Writing payload to stdin");
-            if let Err(e) = input.write_all(&payload_clone).await {
-                error!(error = %e, %container_id_clone, "Failed to write payload to container stdin");
+        .map_err(|e| match &config.user {
+            Some(user) if is_missing_user(&e.to_string()) => {
+                ExecutorError::UnknownUser(user.clone())
             }
-        }
-        // Always shutdown stdin to signal EOF, even if no payload
-        info!(%container_id_clone, "Shutting down stdin");
-        if let Err(e) = input.shutdown().await {
-            error!(error = %e, %container_id_clone, "Failed to shutdown container stdin stream");
-        }
-        // input is dropped here when task finishes
-    });
+            _ => ExecutorError::StartFailed(e),
+        });
+    if let Err(e) = started {
+        remove_container(&docker_client, &container_id).await;
+        return Err(e);
+    }
+    let sampler = resource_usage::UsageSampler::start(docker_client.clone(), container_id.clone());
+
+    info!(%container_id, payload_size = config.payload.len(), "Container started. Writing payload to stdin...");
+    let payload = std::mem::take(&mut config.payload);
+    // Runs alongside the log consumer below, so output the program writes before it
+    // reads stdin never blocks the payload
+    let stdin_handle = tokio::spawn(async move { write_stdin(&mut input, &payload).await });
 
     info!(%container_id, "Consuming stdout/stderr and waiting for exit...");
-    let mut logs_output = Vec::new();
     let container_id_clone = container_id.clone();
+    let mut streams = StreamOutput::capped(output_limit.clone(), &request_id);
     let log_stream_handle = tokio::spawn(async move {
         while let Some(log_entry_res) = output.next().await {
             match log_entry_res {
-                Ok(LogOutput::StdOut { message }) | Ok(LogOutput::StdErr { message }) => {
-                    logs_output.extend_from_slice(&message);
+                Ok(entry) => {
+                    if let (Some(live_output), Some(chunk)) =
+                        (&live_output, OutputChunk::of(&entry))
+                    {
+                        // A subscriber that went away doesn't stop the execution
+                        let _ = live_output.send(chunk);
+                    }
+                    streams.push(entry)
                 }
-                Ok(_) => {}
                 Err(e) => {
                     error!(error = %e, %container_id_clone, "Error reading container logs stream entry");
                 }
             }
         }
-        logs_output // Return collected logs
-                    // output is dropped here
+        streams // output is dropped here
     });
 
     // Wait for container to exit with timeout
@@ -272,40 +748,94 @@ async fn run_container_inner(
     };
     let mut wait_stream = docker_client.wait_container(&container_id, Some(wait_options));
 
-    let wait_result =
-        tokio::time::timeout(tokio::time::Duration::from_secs(30), wait_stream.next()).await;
-
-    let wait_result = match wait_result {
-        Ok(Some(result)) => Some(result),
-        Ok(None) => None,
-        Err(_) => {
-            error!(%container_id, "Container wait timed out after 30 seconds");
-            None
+    let timeout = config
+        .timeout_ms
+        .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+    let waited = tokio::select! {
+        waited = tokio::time::timeout(timeout, wait_stream.next())
+            .instrument(info_span!("container_wait", %container_id)) => Some(waited),
+        _ = cancel.cancelled() => None,
+    };
+    let wait_result = match waited {
+        Some(Ok(result)) => result,
+        Some(Err(_)) => {
+            error!(%container_id, ?timeout, "Container exceeded its timeout, killing it");
+            stdin_handle.abort();
+            log_stream_handle.abort();
+            remove_container(&docker_client, &container_id).await;
+            return Err(ExecutorError::Timeout(timeout));
+        }
+        None => {
+            info!(%container_id, "Execution cancelled, removing its container");
+            stdin_handle.abort();
+            log_stream_handle.abort();
+            remove_container(&docker_client, &container_id).await;
+            return Err(ExecutorError::Cancelled);
         }
     };
 
     info!(%container_id, "Container wait completed");
 
     // Ensure stdin task finished (it should have after container exit triggers stream close)
-    if let Err(e) = stdin_handle.await {
-        error!(error = %e, %container_id, "Stdin write task panicked");
-        // Decide if this constitutes a failure of the execution
+    match stdin_handle.await {
+        Ok(written) => {
+            if let Err(e) = stdin_result(written) {
+                error!(error = %e, %container_id, "Failed to write payload to container stdin");
+                log_stream_handle.abort();
+                remove_container(&docker_client, &container_id).await;
+                return Err(e);
+            }
+        }
+        Err(e) => error!(error = %e, %container_id, "Stdin write task panicked"),
     }
 
     // Collect logs from the log stream task
-    let logs_output = log_stream_handle.await.unwrap_or_else(|e| {
+    let mut streams = log_stream_handle.await.unwrap_or_else(|e| {
         error!(error = %e, %container_id, "Log collection task panicked");
-        Vec::new()
+        StreamOutput::default()
     });
-    let logs_string = String::from_utf8_lossy(&logs_output).to_string();
+    let usage = sampler.finish(streams.stdout_bytes as usize);
+    let truncation = streams.truncation();
+    if let Some(truncation) = &truncation {
+        warn!(%container_id, total_bytes = truncation.total_bytes, limit = output_limit.max_bytes, "Container output went over the limit and was truncated");
+    }
+    let logs_string = String::from_utf8_lossy(&streams.combined).to_string();
 
-    // Determine final response and error based on wait_result
-    let (response_bytes, error_message) = match wait_result {
-        Some(Ok(wait_body)) => {
-            let exit_code = wait_body.status_code;
+    // Determine final response and error based on wait_result. Bollard reports a non-zero
+    // exit as a wait error carrying the code.
+    let exit_code = match &wait_result {
+        Some(Ok(wait_body)) => Some(wait_body.status_code),
+        Some(Err(BollardError::DockerContainerWaitError { code, .. })) => Some(*code),
+        _ => None,
+    };
+    // The kernel's OOM killer shows up only as exit code 137, so ask the daemon whether
+    // that's what happened before the container is gone
+    let oom_killed = exit_code.is_some_and(|code| code != 0)
+        && docker_client
+            .inspect_container(&container_id, None)
+            .await
+            .ok()
+            .and_then(|details| details.state)
+            .and_then(|state| state.oom_killed)
+            .unwrap_or(false);
+    let (response_bytes, error_message) = match (wait_result, exit_code) {
+        (_, Some(exit_code)) if oom_killed => {
+            error!(%container_id, %exit_code, "Container was killed for exceeding its memory limit");
+            let limit = config
+                .memory_limit_mb
+                .map(|mb| format!(" (limit {mb} MB)"))
+                .unwrap_or_default();
+            (
+                None,
+                Some(format!(
+                    "container killed: out of memory{limit}. Logs: {logs_string}"
+                )),
+            )
+        }
+        (_, Some(exit_code)) => {
             if exit_code == 0 {
                 info!(%container_id, %exit_code, "Container executed successfully");
-                (Some(logs_string.clone().into_bytes()), None) // Use logs as response for now
+                (Some(streams.stdout.clone()), None)
             } else {
                 error!(%container_id, %exit_code, "Container exited with non-zero status");
                 (
@@ -316,14 +846,14 @@ async fn run_container_inner(
                 )
             }
         }
-        Some(Err(e)) => {
+        (Some(Err(e)), None) => {
             error!(%container_id, error=%e, "Container wait() returned Docker error");
             (
                 None,
                 Some(format!("Container wait failed: {e}. Logs: {logs_string}")),
             )
         }
-        None => {
+        (_, None) => {
             error!(%container_id, "Container wait() stream ended unexpectedly");
             (
                 None,
@@ -334,26 +864,433 @@ async fn run_container_inner(
         }
     };
 
+    let committed = match &config.commit_as {
+        Some(image) => commit_container(&docker_client, &container_id, image).await,
+        None => Ok(()),
+    };
+    remove_container(&docker_client, &container_id).await;
+    committed?;
+
+    Ok(InvocationResult {
+        request_id,
+        response: response_bytes,
+        logs: Some(logs_string),
+        error: error_message,
+        stdout: Some(streams.stdout),
+        stderr: Some(streams.stderr),
+        exit_code,
+        usage: Some(usage),
+        truncation,
+    })
+}
+
+/// Host directory mounted at `/workspace` in persistent executions of `function_id`
+pub fn persistent_workspace(function_id: &str) -> PathBuf {
+    std::env::var("FAAS_PERSIST_ROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| std::env::temp_dir().join("faas-persistent"))
+        .join(function_id)
+}
+
+/// Commit the container's filesystem to `image`, replacing an earlier commit of that name
+async fn commit_container(docker_client: &Docker, container_id: &str, image: &str) -> Result<()> {
+    let (repo, tag) = image.rsplit_once(':').unwrap_or((image, "latest"));
+    docker_client
+        .commit_container(
+            CommitContainerOptions {
+                container: container_id,
+                repo,
+                tag,
+                ..Default::default()
+            },
+            docktopus::bollard::container::Config::<String>::default(),
+        )
+        .await
+        .map_err(|e| {
+            ExecutorError::Internal(format!("Failed to commit container to {image}: {e}"))
+        })?;
+    info!(%container_id, %image, "Committed container filesystem");
+    Ok(())
+}
+
+/// Force-remove, which also kills the container if it is still running
+async fn remove_container(docker_client: &Docker, container_id: &str) {
     info!(%container_id, "Removing container...");
     let remove_opts = Some(RemoveContainerOptions {
         force: true,
         ..Default::default()
     });
     if let Err(e) = docker_client
-        .remove_container(&container_id, remove_opts)
+        .remove_container(container_id, remove_opts)
         .await
     {
         warn!(container_id=%container_id, error = %e, "Failed to remove container");
         // Don't fail the whole execution if cleanup fails, just warn
     }
+}
 
-    Ok(InvocationResult {
-        request_id,
-        response: response_bytes,
-        logs: Some(logs_string),
-        error: error_message,
-    })
+/// A piece of a container's output, forwarded while it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputChunk {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+}
+
+impl OutputChunk {
+    fn of(entry: &LogOutput) -> Option<Self> {
+        match entry {
+            LogOutput::StdOut { message } => Some(Self::Stdout(message.to_vec())),
+            LogOutput::StdErr { message } => Some(Self::Stderr(message.to_vec())),
+            _ => None,
+        }
+    }
+}
+
+/// A container's output, split by stream and also interleaved in arrival order, up to
+/// `limit`. Output past it is counted and spilled but not kept.
+#[derive(Debug, Default)]
+pub(crate) struct StreamOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub combined: Vec<u8>,
+    /// Everything written to stdout, including what wasn't kept
+    pub stdout_bytes: u64,
+    total_bytes: u64,
+    truncated: bool,
+    limit: output_limit::OutputLimit,
+    id: String,
+    spill: Option<output_limit::Spill>,
+}
+
+impl StreamOutput {
+    /// Output of execution `id`, kept up to `limit`
+    pub fn capped(limit: output_limit::OutputLimit, id: &str) -> Self {
+        Self {
+            limit,
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, entry: LogOutput) {
+        let (stream, message) = match entry {
+            LogOutput::StdOut { message } => {
+                self.stdout_bytes += message.len() as u64;
+                (&mut self.stdout, message)
+            }
+            LogOutput::StdErr { message } => (&mut self.stderr, message),
+            _ => return,
+        };
+        self.total_bytes += message.len() as u64;
+        let room = self.limit.max_bytes.saturating_sub(self.combined.len());
+        let kept = &message[..message.len().min(room)];
+        stream.extend_from_slice(kept);
+        self.combined.extend_from_slice(kept);
+        if kept.len() < message.len() && !self.truncated {
+            self.truncated = true;
+            let before = &self.combined[..self.combined.len() - kept.len()];
+            self.spill = self.limit.spill(&self.id, before);
+        }
+        if let Some(spill) = &mut self.spill {
+            if !spill.write(&message) {
+                self.spill = None;
+            }
+        }
+    }
+
+    /// How much was dropped, if anything was, and the file holding all of it
+    pub fn truncation(&mut self) -> Option<faas_common::OutputTruncation> {
+        self.truncated.then(|| faas_common::OutputTruncation {
+            total_bytes: self.total_bytes,
+            log_path: self.spill.take().and_then(output_limit::Spill::finish),
+        })
+    }
+}
+
+/// Write `payload` to a container's stdin and close it.
+///
+/// Each chunk is flushed before the next is written, so a program that is slow to read
+/// holds the writer back instead of the payload piling up in the attach connection.
+pub(crate) async fn write_stdin<W>(input: &mut W, payload: &[u8]) -> std::io::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin + ?Sized,
+{
+    for chunk in payload.chunks(STDIN_CHUNK_BYTES) {
+        input.write_all(chunk).await?;
+        input.flush().await?;
+    }
+    input.shutdown().await
+}
+
+/// A program that exits without reading all of its stdin isn't a failed write
+pub(crate) fn stdin_result(written: std::io::Result<()>) -> Result<()> {
+    match written {
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::ConnectionReset
+            ) =>
+        {
+            warn!(
+                "Container stopped reading stdin before the payload ended: {}",
+                e
+            );
+            Ok(())
+        }
+        written => written.map_err(ExecutorError::StdinFailed),
+    }
 }
 
 // Re-export the executor
 pub use executor::{Executor, WarmContainer};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_options_map_onto_host_config() {
+        let config = InternalDockerConfig {
+            function_id: "limits".to_string(),
+            image: "alpine:latest".to_string(),
+            command: vec![],
+            env_vars: None,
+            working_dir: None,
+            user: None,
+            payload: vec![],
+            input_archive: None,
+            read_only_mounts: Vec::new(),
+            execution_mode: None,
+            ulimits: Some(vec![Ulimit::new("nproc", 256, 512)]),
+            shm_size_mb: Some(64),
+            pids_limit: Some(128),
+            tmpfs: Some(vec![TmpfsMount {
+                path: "/scratch".to_string(),
+                size_mb: 128,
+            }]),
+            environment_overrides: None,
+            memory_limit_mb: Some(256),
+            cpu_limit: Some(1.5),
+            timeout_ms: None,
+            gpu: None,
+            network: Default::default(),
+            commit_as: None,
+        };
+
+        let host_config = resource_host_config(&config);
+        assert_eq!(host_config.memory, Some(256 * 1024 * 1024));
+        assert_eq!(host_config.memory_swap, host_config.memory);
+        assert_eq!(host_config.nano_cpus, Some(1_500_000_000));
+        let ulimits = host_config.ulimits.unwrap();
+        assert_eq!(ulimits[0].name.as_deref(), Some("nproc"));
+        assert_eq!(ulimits[0].soft, Some(256));
+        assert_eq!(ulimits[0].hard, Some(512));
+        assert_eq!(host_config.shm_size, Some(64 * 1024 * 1024));
+        assert_eq!(host_config.pids_limit, Some(128));
+        assert_eq!(
+            host_config
+                .tmpfs
+                .unwrap()
+                .get("/scratch")
+                .map(String::as_str),
+            Some("rw,nosuid,size=128m")
+        );
+    }
+
+    #[test]
+    fn gpu_requests_map_onto_nvidia_device_requests() {
+        let mut config = InternalDockerConfig {
+            function_id: "gpu".to_string(),
+            image: "pytorch/pytorch:latest".to_string(),
+            command: vec![],
+            env_vars: None,
+            working_dir: None,
+            user: None,
+            payload: vec![],
+            input_archive: None,
+            read_only_mounts: Vec::new(),
+            execution_mode: None,
+            ulimits: None,
+            shm_size_mb: None,
+            pids_limit: None,
+            tmpfs: None,
+            environment_overrides: None,
+            memory_limit_mb: None,
+            cpu_limit: None,
+            timeout_ms: None,
+            gpu: None,
+            network: Default::default(),
+            commit_as: None,
+        };
+        assert!(resource_host_config(&config).device_requests.is_none());
+
+        config.gpu = Some(GpuRequest::count(2));
+        let requests = resource_host_config(&config).device_requests.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].driver.as_deref(), Some("nvidia"));
+        assert_eq!(requests[0].count, Some(2));
+        assert_eq!(requests[0].device_ids, None);
+        assert_eq!(
+            requests[0].capabilities,
+            Some(vec![vec!["gpu".to_string()]])
+        );
+
+        config.gpu = Some(GpuRequest::default());
+        let all = resource_host_config(&config).device_requests.unwrap();
+        assert_eq!(all[0].count, Some(-1));
+
+        config.gpu = Some(GpuRequest {
+            count: None,
+            device_ids: vec!["0".to_string(), "GPU-3f2a".to_string()],
+            capabilities: vec!["compute".to_string(), "utility".to_string()],
+        });
+        let chosen = resource_host_config(&config).device_requests.unwrap();
+        assert_eq!(chosen[0].count, None);
+        assert_eq!(
+            chosen[0].device_ids,
+            Some(vec!["0".to_string(), "GPU-3f2a".to_string()])
+        );
+        assert_eq!(
+            chosen[0].capabilities,
+            Some(vec![vec!["compute".to_string(), "utility".to_string()]])
+        );
+    }
+
+    #[tokio::test]
+    async fn stdin_is_written_in_chunks_through_a_small_pipe() {
+        let payload: Vec<u8> = (0..STDIN_CHUNK_BYTES * 3 + 17)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let (mut writer, mut reader) = tokio::io::duplex(1024);
+        let read = tokio::spawn(async move {
+            let mut received = Vec::new();
+            tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut received)
+                .await
+                .unwrap();
+            received
+        });
+        write_stdin(&mut writer, &payload).await.unwrap();
+        drop(writer);
+        assert_eq!(read.await.unwrap(), payload);
+    }
+
+    #[test]
+    fn only_unexpected_stdin_failures_fail_the_execution() {
+        let closed = std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        assert!(stdin_result(Err(closed)).is_ok());
+        let failed = std::io::Error::other("attach connection lost");
+        assert!(matches!(
+            stdin_result(Err(failed)),
+            Err(ExecutorError::StdinFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn payloads_over_the_limit_are_refused_before_docker_is_asked() {
+        // Nothing listens here; the limit is checked first
+        let docker =
+            Docker::connect_with_http("http://127.0.0.1:1", 1, crate::bollard::API_DEFAULT_VERSION)
+                .unwrap();
+        let executor = DockerExecutor::new(Arc::new(docker)).with_max_payload_bytes(4);
+        let error = executor
+            .execute(SandboxConfig {
+                function_id: "too-large".to_string(),
+                source: "alpine:latest".to_string(),
+                command: vec!["cat".to_string()],
+                payload: b"12345".to_vec(),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("5 bytes is over the 4 byte limit"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn stream_output_keeps_stdout_and_stderr_apart() {
+        let mut streams = StreamOutput::default();
+        for entry in [
+            LogOutput::StdOut {
+                message: "a\n".into(),
+            },
+            LogOutput::StdErr {
+                message: "b\n".into(),
+            },
+            LogOutput::StdOut {
+                message: "c\n".into(),
+            },
+        ] {
+            streams.push(entry);
+        }
+        assert_eq!(streams.stdout, b"a\nc\n");
+        assert_eq!(streams.stderr, b"b\n");
+        assert_eq!(streams.combined, b"a\nb\nc\n");
+    }
+
+    fn out(message: &str) -> LogOutput {
+        LogOutput::StdOut {
+            message: message.to_string().into(),
+        }
+    }
+
+    #[test]
+    fn stream_output_stops_keeping_at_the_limit() {
+        let limit = output_limit::OutputLimit {
+            max_bytes: 5,
+            spill_dir: None,
+        };
+        let mut streams = StreamOutput::capped(limit, "capped");
+        streams.push(out("abc"));
+        assert_eq!(streams.truncation(), None);
+        streams.push(out("defg"));
+        streams.push(LogOutput::StdErr {
+            message: "hij".into(),
+        });
+        assert_eq!(streams.stdout, b"abcde");
+        assert_eq!(streams.stderr, b"");
+        assert_eq!(streams.combined, b"abcde");
+        assert_eq!(streams.stdout_bytes, 7);
+        assert_eq!(
+            streams.truncation(),
+            Some(faas_common::OutputTruncation {
+                total_bytes: 10,
+                log_path: None,
+            })
+        );
+    }
+
+    #[test]
+    fn truncated_output_is_spilled_in_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let limit = output_limit::OutputLimit {
+            max_bytes: 4,
+            spill_dir: Some(dir.path().to_path_buf()),
+        };
+        let mut streams = StreamOutput::capped(limit, "spilled");
+        for message in ["ab", "cdef", "gh"] {
+            streams.push(out(message));
+        }
+        let truncation = streams.truncation().unwrap();
+        assert_eq!(truncation.total_bytes, 8);
+        let path = truncation.log_path.unwrap();
+        assert_eq!(path, dir.path().join("spilled.log"));
+        assert_eq!(std::fs::read(path).unwrap(), b"abcdefgh");
+    }
+
+    #[test]
+    fn output_under_the_limit_is_not_spilled() {
+        let dir = tempfile::tempdir().unwrap();
+        let limit = output_limit::OutputLimit {
+            max_bytes: 4,
+            spill_dir: Some(dir.path().to_path_buf()),
+        };
+        let mut streams = StreamOutput::capped(limit, "short");
+        streams.push(out("abcd"));
+        assert_eq!(streams.truncation(), None);
+        assert!(!dir.path().join("short.log").exists());
+    }
+}
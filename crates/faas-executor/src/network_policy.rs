@@ -0,0 +1,254 @@
+//! Network access for Docker sandboxes.
+//!
+//! `None` starts the container with `network_mode: none`, leaving it only loopback.
+//! `Restricted` gives the execution a bridge network of its own and resolves the allowed
+//! host names once, before the container starts. The names are pinned to those addresses
+//! in the container's `/etc/hosts`, and iptables rules let the network's subnet reach only
+//! them: everything else it sends is dropped, both when forwarded (in `DOCKER-USER`) and
+//! when addressed to the host. The rules and the network are removed once the container
+//! is gone. A host where the rules can't be installed refuses the execution rather than
+//! running it unrestricted.
+
+use crate::bollard::models::{HostConfig, Ipam};
+use crate::bollard::network::CreateNetworkOptions;
+use crate::bollard::Docker;
+use crate::{ExecutorError, Result};
+use faas_common::NetworkPolicy;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Prefix of the per-execution networks restricted executions run on
+pub const NETWORK_PREFIX: &str = "faas-net-";
+
+/// One iptables rule, installed with `-I` and removed with `-D`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    chain: &'static str,
+    spec: Vec<String>,
+}
+
+impl Rule {
+    fn new(chain: &'static str, spec: &[&str]) -> Self {
+        Self {
+            chain,
+            spec: spec.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn args(&self, action: &str) -> Vec<String> {
+        let mut args = vec![action.to_string(), self.chain.to_string()];
+        args.extend(self.spec.iter().cloned());
+        args
+    }
+}
+
+/// The rules confining `subnet` to `allowed`, in the order they are inserted. Each is
+/// inserted at the top of its chain, so the accepts end up above the drops.
+fn rules(subnet: &str, allowed: &[IpAddr]) -> Vec<Rule> {
+    let mut rules = vec![
+        Rule::new("DOCKER-USER", &["-s", subnet, "-j", "DROP"]),
+        Rule::new("INPUT", &["-s", subnet, "-j", "DROP"]),
+    ];
+    for ip in allowed {
+        let ip = ip.to_string();
+        rules.push(Rule::new(
+            "DOCKER-USER",
+            &["-s", subnet, "-d", &ip, "-j", "ACCEPT"],
+        ));
+    }
+    rules
+}
+
+async fn iptables(args: &[String]) -> Result<()> {
+    let command = format!("iptables {}", args.join(" "));
+    let output = Command::new("iptables")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| ExecutorError::Network(format!("{command}: {e}")))?;
+    if !output.status.success() {
+        return Err(ExecutorError::Network(format!(
+            "{command}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// The container side of a network policy, and what has to be torn down after it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SandboxNetwork {
+    network_mode: Option<String>,
+    extra_hosts: Vec<String>,
+    /// The per-execution network, for a restricted policy
+    network: Option<String>,
+    rules: Vec<Rule>,
+}
+
+impl SandboxNetwork {
+    /// Ready the network `policy` asks for; `name` tells a restricted execution's network
+    /// apart from the others
+    pub async fn prepare(docker: &Docker, policy: &NetworkPolicy, name: &str) -> Result<Self> {
+        match policy {
+            NetworkPolicy::Default => Ok(Self::default()),
+            NetworkPolicy::None => Ok(Self {
+                network_mode: Some("none".to_string()),
+                ..Default::default()
+            }),
+            NetworkPolicy::Restricted { allowed_hosts } => {
+                let pinned = resolve(allowed_hosts).await?;
+                let mut network = Self {
+                    extra_hosts: pinned
+                        .iter()
+                        .filter(|(host, _)| host.parse::<IpAddr>().is_err())
+                        .map(|(host, ip)| format!("{host}:{ip}"))
+                        .collect(),
+                    ..Default::default()
+                };
+                let allowed: Vec<IpAddr> = pinned.into_iter().map(|(_, ip)| ip).collect();
+                if let Err(e) = network.restrict(docker, name, &allowed).await {
+                    network.release(docker).await;
+                    return Err(e);
+                }
+                Ok(network)
+            }
+        }
+    }
+
+    async fn restrict(&mut self, docker: &Docker, name: &str, allowed: &[IpAddr]) -> Result<()> {
+        let network = format!("{NETWORK_PREFIX}{name}");
+        docker
+            .create_network(CreateNetworkOptions {
+                name: network.clone(),
+                check_duplicate: true,
+                driver: "bridge".to_string(),
+                ipam: Ipam::default(),
+                labels: HashMap::from([("faas.network".to_string(), "restricted".to_string())]),
+                ..Default::default()
+            })
+            .await?;
+        self.network = Some(network.clone());
+        self.network_mode = Some(network.clone());
+
+        let subnet = docker
+            .inspect_network::<String>(&network, None)
+            .await?
+            .ipam
+            .and_then(|ipam| ipam.config)
+            .into_iter()
+            .flatten()
+            .find_map(|config| config.subnet)
+            .ok_or_else(|| ExecutorError::Network(format!("{network} has no subnet")))?;
+        for rule in rules(&subnet, allowed) {
+            iptables(&rule.args("-I")).await?;
+            self.rules.push(rule);
+        }
+        info!(%network, %subnet, allowed = allowed.len(), "Restricted sandbox network ready");
+        Ok(())
+    }
+
+    /// Set the container's network mode and pinned hosts on `host_config`
+    pub fn apply(&self, host_config: &mut HostConfig) {
+        if let Some(mode) = &self.network_mode {
+            host_config.network_mode = Some(mode.clone());
+        }
+        if !self.extra_hosts.is_empty() {
+            host_config
+                .extra_hosts
+                .get_or_insert_with(Vec::new)
+                .extend(self.extra_hosts.iter().cloned());
+        }
+    }
+
+    /// Remove the rules and the network; call once the container is gone
+    pub async fn release(&self, docker: &Docker) {
+        for rule in self.rules.iter().rev() {
+            if let Err(e) = iptables(&rule.args("-D")).await {
+                warn!("Failed to remove sandbox network rule: {}", e);
+            }
+        }
+        if let Some(network) = &self.network {
+            if let Err(e) = docker.remove_network(network).await {
+                warn!("Failed to remove sandbox network {}: {}", network, e);
+            }
+        }
+    }
+}
+
+/// Each allowed host with the addresses it resolves to; literal IPs are kept as they are
+async fn resolve(hosts: &[String]) -> Result<Vec<(String, IpAddr)>> {
+    let mut pinned = Vec::new();
+    for host in hosts {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            pinned.push((host.clone(), ip));
+            continue;
+        }
+        let addrs = tokio::net::lookup_host((host.as_str(), 0))
+            .await
+            .map_err(|e| ExecutorError::Network(format!("resolving {host}: {e}")))?;
+        let before = pinned.len();
+        for addr in addrs.filter(|addr| addr.is_ipv4()) {
+            if !pinned.contains(&(host.clone(), addr.ip())) {
+                pinned.push((host.clone(), addr.ip()));
+            }
+        }
+        if pinned.len() == before {
+            return Err(ExecutorError::Network(format!(
+                "{host} has no IPv4 address"
+            )));
+        }
+    }
+    Ok(pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_are_inserted_above_the_drops() {
+        let allowed = ["93.184.215.14".parse().unwrap()];
+        let rules = rules("172.30.0.0/16", &allowed);
+        let inserted: Vec<_> = rules.iter().map(|r| r.args("-I").join(" ")).collect();
+        assert_eq!(
+            inserted,
+            [
+                "-I DOCKER-USER -s 172.30.0.0/16 -j DROP",
+                "-I INPUT -s 172.30.0.0/16 -j DROP",
+                "-I DOCKER-USER -s 172.30.0.0/16 -d 93.184.215.14 -j ACCEPT",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn no_network_leaves_only_loopback() {
+        let docker =
+            Docker::connect_with_http("http://127.0.0.1:1", 1, crate::bollard::API_DEFAULT_VERSION)
+                .unwrap();
+        let network = SandboxNetwork::prepare(&docker, &NetworkPolicy::None, "none")
+            .await
+            .unwrap();
+        let mut host_config = HostConfig::default();
+        network.apply(&mut host_config);
+        assert_eq!(host_config.network_mode.as_deref(), Some("none"));
+        assert_eq!(host_config.extra_hosts, None);
+
+        let default = SandboxNetwork::prepare(&docker, &NetworkPolicy::Default, "default")
+            .await
+            .unwrap();
+        let mut host_config = HostConfig::default();
+        default.apply(&mut host_config);
+        assert_eq!(host_config, HostConfig::default());
+    }
+
+    #[tokio::test]
+    async fn literal_addresses_are_pinned_without_a_lookup() {
+        let pinned = resolve(&["10.1.2.3".to_string()]).await.unwrap();
+        assert_eq!(
+            pinned,
+            [("10.1.2.3".to_string(), "10.1.2.3".parse().unwrap())]
+        );
+    }
+}
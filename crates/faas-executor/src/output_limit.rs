@@ -0,0 +1,107 @@
+//! How much of an execution's output is kept in memory.
+//!
+//! A container can print far more than the executor should hold on to. Past
+//! [`OutputLimit::max_bytes`] the log stream is still read to the end, so the container
+//! never blocks on a full pipe, but the rest is only counted, and written to a spill file
+//! when [`OutputLimit::spill_dir`] is set so the full output can still be fetched later.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Output kept per execution when `FAAS_MAX_OUTPUT_BYTES` isn't set
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 8 * 1024 * 1024;
+
+/// The cap on an execution's output and where output past it goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// Bytes of stdout and stderr together kept in the result
+    pub max_bytes: usize,
+    /// Directory the full output of a truncated execution is written to, as `{id}.log`
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl Default for OutputLimit {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            spill_dir: None,
+        }
+    }
+}
+
+impl OutputLimit {
+    /// `FAAS_MAX_OUTPUT_BYTES`, spilling to `FAAS_OUTPUT_SPILL_DIR` or a temp directory
+    /// when unset. Set `FAAS_OUTPUT_SPILL_DIR` to an empty value to drop the rest instead.
+    pub fn from_env() -> Self {
+        let max_bytes = std::env::var("FAAS_MAX_OUTPUT_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+        let spill_dir = match std::env::var("FAAS_OUTPUT_SPILL_DIR") {
+            Ok(dir) if dir.is_empty() => None,
+            Ok(dir) => Some(PathBuf::from(dir)),
+            Err(_) => Some(std::env::temp_dir().join("faas-output")),
+        };
+        Self {
+            max_bytes,
+            spill_dir,
+        }
+    }
+
+    /// Start spilling the output of execution `id`, with what was kept of it so far
+    pub(crate) fn spill(&self, id: &str, kept: &[u8]) -> Option<Spill> {
+        let dir = self.spill_dir.as_ref()?;
+        let path = dir.join(format!("{id}.log"));
+        let opened = std::fs::create_dir_all(dir).and_then(|()| File::create(&path));
+        let mut spill = match opened {
+            Ok(file) => Spill {
+                path,
+                file: BufWriter::new(file),
+            },
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Could not spill output to disk");
+                return None;
+            }
+        };
+        spill.write(kept).then_some(spill)
+    }
+}
+
+/// The file a truncated execution's full output is being written to
+#[derive(Debug)]
+pub(crate) struct Spill {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Spill {
+    /// Append `data`; on failure the file is removed and `false` returned
+    pub fn write(&mut self, data: &[u8]) -> bool {
+        match self.file.write_all(data) {
+            Ok(()) => true,
+            Err(e) => {
+                self.abandon(&e);
+                false
+            }
+        }
+    }
+
+    /// The complete file, once everything has been written to it
+    pub fn finish(mut self) -> Option<PathBuf> {
+        match self.file.flush() {
+            Ok(()) => Some(self.path),
+            Err(e) => {
+                self.abandon(&e);
+                None
+            }
+        }
+    }
+
+    fn abandon(&self, error: &std::io::Error) {
+        warn!(path = %self.path.display(), %error, "Could not spill output to disk");
+        // Half a log is worse than none: the caller would serve it as the full output
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -12,7 +12,7 @@ use tokio::sync::RwLock;
 /// Implements L1 (memory) -> L2 (disk) -> L3 (distributed) hierarchy
 pub struct CacheManager {
     l1_cache: Arc<RwLock<MemoryCache>>,
-    l2_cache: Arc<RwLock<DiskCache>>,
+    l2_cache: Option<Arc<RwLock<DiskCache>>>,
     strategy: CacheStrategy,
     metrics: Arc<RwLock<CacheMetrics>>,
     bloom_filter: Arc<RwLock<BloomFilter>>,
@@ -27,6 +27,10 @@ pub struct CacheStrategy {
     pub l2_ttl: Duration,
     pub eviction_policy: EvictionPolicy,
     pub compression: bool,
+    /// Where L2 keeps its files; no L2 when unset
+    pub disk_dir: Option<std::path::PathBuf>,
+    /// Entries at least this big go to L2 only, leaving memory to the small ones
+    pub disk_threshold: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -101,21 +105,28 @@ impl Default for CacheStrategy {
             l2_ttl: Duration::from_secs(86400), // 24 hours
             eviction_policy: EvictionPolicy::Adaptive,
             compression: true,
+            disk_dir: Some(std::path::PathBuf::from("/tmp/faas-cache")),
+            disk_threshold: None,
         }
     }
 }
 
 impl CacheManager {
     pub async fn new(strategy: CacheStrategy) -> Result<Self> {
-        let cache_dir = std::path::PathBuf::from("/tmp/faas-cache");
-        tokio::fs::create_dir_all(&cache_dir).await?;
+        let l2_cache = match &strategy.disk_dir {
+            Some(cache_dir) => {
+                tokio::fs::create_dir_all(cache_dir).await?;
+                Some(Arc::new(RwLock::new(DiskCache::new(
+                    cache_dir.clone(),
+                    strategy.l2_max_size,
+                )?)))
+            }
+            None => None,
+        };
 
         Ok(Self {
             l1_cache: Arc::new(RwLock::new(MemoryCache::new(strategy.l1_max_size))),
-            l2_cache: Arc::new(RwLock::new(DiskCache::new(
-                cache_dir,
-                strategy.l2_max_size,
-            )?)),
+            l2_cache,
             strategy,
             metrics: Arc::new(RwLock::new(CacheMetrics::default())),
             bloom_filter: Arc::new(RwLock::new(BloomFilter::with_rate(0.01, 100_000))),
@@ -145,7 +156,9 @@ impl CacheManager {
         // Try L2 cache
         if let Some(data) = self.get_from_l2(key).await? {
             // Promote to L1 for faster future access
-            self.put_to_l1(key, &data).await?;
+            if !self.disk_only(&data) {
+                self.put_to_l1(key, &data).await?;
+            }
             self.record_l2_hit(start.elapsed()).await;
             return Ok(Some(data));
         }
@@ -188,21 +201,41 @@ impl CacheManager {
             bloom.insert(&key);
         }
 
-        // Always put in L1 for immediate access
-        self.put_to_l1_with_metadata(key, &data, metadata.clone())
-            .await?;
+        // Put in L1 for immediate access, unless it is too big to keep in memory
+        if !self.disk_only(&data) {
+            self.put_to_l1_with_metadata(key, &data, metadata.clone())
+                .await?;
+        }
 
-        // Also store in L2 for persistence (async)
-        let l2_cache = self.l2_cache.clone();
-        let key = key.to_string();
-        let data_clone = data.clone();
-        tokio::spawn(async move {
-            let _ = l2_cache.write().await.put(&key, data_clone, metadata).await;
-        });
+        // Also store in L2 for persistence, before returning so a removal can't be overtaken
+        if let Some(l2_cache) = &self.l2_cache {
+            let _ = l2_cache.write().await.put(key, data, metadata).await;
+        }
 
         Ok(())
     }
 
+    /// Drop `key` from every layer; false when no layer had it
+    pub async fn remove(&self, key: &str) -> bool {
+        let in_l1 = {
+            let mut l1 = self.l1_cache.write().await;
+            l1.remove(key).is_some()
+        };
+        let in_l2 = match &self.l2_cache {
+            Some(l2_cache) => l2_cache.write().await.remove(key).await,
+            None => false,
+        };
+        in_l1 || in_l2
+    }
+
+    fn disk_only(&self, data: &[u8]) -> bool {
+        self.l2_cache.is_some()
+            && self
+                .strategy
+                .disk_threshold
+                .is_some_and(|threshold| data.len() >= threshold)
+    }
+
     /// Put data into semantic cache
     pub async fn put_semantic(&self, content_hash: u64, data: Vec<u8>) -> Result<()> {
         let mut semantic_cache = self.semantic_cache.write().await;
@@ -219,7 +252,9 @@ impl CacheManager {
             tasks.push(tokio::spawn(async move {
                 // Pre-load from L2 to L1 if available
                 if let Ok(Some(data)) = cache.get_from_l2(&key).await {
-                    let _ = cache.put_to_l1(&key, &data).await;
+                    if !cache.disk_only(&data) {
+                        let _ = cache.put_to_l1(&key, &data).await;
+                    }
                 }
             }));
         }
@@ -255,6 +290,15 @@ impl CacheManager {
     async fn get_from_l1(&self, key: &str) -> Result<Option<Vec<u8>>> {
         let mut cache = self.l1_cache.write().await;
 
+        let expired = cache
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.created_at.elapsed() > self.strategy.l1_ttl);
+        if expired {
+            cache.remove(key);
+            return Ok(None);
+        }
+
         if let Some(entry) = cache.entries.get_mut(key) {
             // Update access statistics
             entry.last_accessed = Instant::now();
@@ -279,7 +323,18 @@ impl CacheManager {
     }
 
     async fn get_from_l2(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        let mut cache = self.l2_cache.write().await;
+        let Some(l2_cache) = &self.l2_cache else {
+            return Ok(None);
+        };
+        let mut cache = l2_cache.write().await;
+
+        let expired = cache.index.get(key).is_some_and(|entry| {
+            entry.created_at.elapsed().unwrap_or(Duration::ZERO) > self.strategy.l2_ttl
+        });
+        if expired {
+            cache.remove(key).await;
+            return Ok(None);
+        }
 
         if let Some(entry) = cache.index.get_mut(key) {
             // Check if file still exists and is valid
@@ -316,6 +371,7 @@ impl CacheManager {
         metadata: CacheMetadata,
     ) -> Result<()> {
         let mut cache = self.l1_cache.write().await;
+        cache.remove(key);
 
         // Check if we need to evict first
         let entry_size = data.len();
@@ -443,19 +499,13 @@ impl CacheManager {
                 .collect();
 
             for key in expired_keys {
-                if let Some(entry) = l1.entries.remove(&key) {
-                    l1.current_size -= entry.size;
-                    l1.frequency.remove(&key);
-                    if let Some(pos) = l1.access_order.iter().position(|k| k == &key) {
-                        l1.access_order.remove(pos);
-                    }
-                }
+                l1.remove(&key);
             }
         }
 
         // Cleanup L2
-        {
-            let mut l2 = self.l2_cache.write().await;
+        if let Some(l2_cache) = &self.l2_cache {
+            let mut l2 = l2_cache.write().await;
             let now = SystemTime::now();
             let expired_keys: Vec<String> = l2
                 .index
@@ -503,6 +553,16 @@ impl MemoryCache {
             current_size: 0,
         }
     }
+
+    fn remove(&mut self, key: &str) -> Option<CacheEntry> {
+        let entry = self.entries.remove(key)?;
+        self.current_size -= entry.size;
+        self.frequency.remove(key);
+        if let Some(pos) = self.access_order.iter().position(|k| k == key) {
+            self.access_order.remove(pos);
+        }
+        Some(entry)
+    }
 }
 
 impl DiskCache {
@@ -522,6 +582,9 @@ impl DiskCache {
         let file_path = self.base_path.join(file_name);
 
         tokio::fs::write(&file_path, &data).await?;
+        if let Some(old) = self.index.remove(key) {
+            self.current_size -= old.size;
+        }
 
         let entry = DiskEntry {
             file_path,
@@ -556,6 +619,15 @@ impl DiskCache {
         Ok(())
     }
 
+    async fn remove(&mut self, key: &str) -> bool {
+        let Some(entry) = self.index.remove(key) else {
+            return false;
+        };
+        let _ = tokio::fs::remove_file(&entry.file_path).await;
+        self.current_size -= entry.size;
+        true
+    }
+
     fn hash_key(key: &str) -> String {
         let mut hasher = std::collections::hash_map::DefaultHasher::new();
         key.hash(&mut hasher);
@@ -616,4 +688,44 @@ mod tests {
         let metrics = cache.get_metrics().await;
         assert!(metrics.evictions > 0);
     }
+
+    #[tokio::test]
+    async fn test_cache_ttl_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut strategy = CacheStrategy::default();
+        strategy.l1_ttl = Duration::from_millis(50);
+        strategy.l2_ttl = Duration::from_millis(50);
+        strategy.disk_dir = Some(dir.path().to_path_buf());
+        let cache = CacheManager::new(strategy).await.unwrap();
+
+        cache.put("short", b"lived".to_vec(), None).await.unwrap();
+        assert!(cache.get("short").await.unwrap().is_some());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(cache.get("short").await.unwrap(), None);
+
+        cache.put("gone", b"soon".to_vec(), None).await.unwrap();
+        assert!(cache.remove("gone").await);
+        assert_eq!(cache.get("gone").await.unwrap(), None);
+        assert!(!cache.remove("gone").await);
+    }
+
+    #[tokio::test]
+    async fn test_cache_large_entries_stay_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut strategy = CacheStrategy::default();
+        strategy.disk_dir = Some(dir.path().to_path_buf());
+        strategy.disk_threshold = Some(1024);
+        let cache = CacheManager::new(strategy).await.unwrap();
+
+        let large = vec![7u8; 4096];
+        cache.put("large", large.clone(), None).await.unwrap();
+        cache.put("small", b"tiny".to_vec(), None).await.unwrap();
+        assert_eq!(cache.get("large").await.unwrap(), Some(large));
+        assert!(cache.get("small").await.unwrap().is_some());
+
+        let metrics = cache.get_metrics().await;
+        assert_eq!(metrics.l1_hits, 1);
+        assert_eq!(metrics.l2_hits, 1);
+        assert_eq!(cache.l1_cache.read().await.current_size, 4);
+    }
 }
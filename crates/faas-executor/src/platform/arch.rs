@@ -0,0 +1,203 @@
+//! CPU architecture awareness.
+//!
+//! The fleet mixes x86_64 and ARM64 hosts. Pulls resolve multi-arch manifests to the host's
+//! platform, and single-arch images built for another architecture are rejected before a
+//! container is created, instead of dying with `exec format error` inside it.
+
+use serde::Serialize;
+use thiserror::Error;
+
+use super::image_metadata::{ImageMetadata, ImagePlatform};
+use crate::bollard::models::OciPlatform;
+
+/// Canonical (OCI) name for an architecture, so `x86_64` and `amd64` compare equal
+pub fn normalize_arch(arch: &str) -> String {
+    match arch.to_ascii_lowercase().as_str() {
+        "x86_64" | "x86-64" | "amd64" => "amd64".to_string(),
+        "aarch64" | "arm64" => "arm64".to_string(),
+        "armv7l" | "armhf" | "arm" => "arm".to_string(),
+        "i386" | "i686" | "x86" | "386" => "386".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// The platform of the machine this process runs on
+pub fn host_platform() -> ImagePlatform {
+    ImagePlatform {
+        // Containers are always Linux, even under Docker Desktop
+        os: "linux".to_string(),
+        architecture: normalize_arch(std::env::consts::ARCH),
+        variant: None,
+    }
+}
+
+/// A single-arch image (or a multi-arch image without the host's platform) on the wrong host
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize)]
+#[error("image {image} is built for {image_arch}, but this host is {host_arch}")]
+pub struct ArchMismatch {
+    pub image: String,
+    pub image_arch: String,
+    pub host_arch: String,
+}
+
+impl From<OciPlatform> for ImagePlatform {
+    fn from(platform: OciPlatform) -> Self {
+        Self {
+            os: platform.os.unwrap_or_default(),
+            architecture: platform.architecture.unwrap_or_default(),
+            variant: platform.variant.filter(|v| !v.is_empty()),
+        }
+    }
+}
+
+/// Pick the entry matching `host` from the platforms an image's manifest offers.
+///
+/// `Ok(None)` means the manifest listed no usable platforms and the pull should not pin
+/// one. Attestation entries (`unknown/unknown`) are ignored.
+pub fn resolve_platform(
+    image: &str,
+    offered: &[ImagePlatform],
+    host: &ImagePlatform,
+) -> Result<Option<ImagePlatform>, ArchMismatch> {
+    let usable: Vec<_> = offered
+        .iter()
+        .filter(|p| !p.architecture.is_empty() && p.architecture != "unknown")
+        .collect();
+    if usable.is_empty() {
+        return Ok(None);
+    }
+
+    let host_arch = normalize_arch(&host.architecture);
+    let matching: Vec<_> = usable
+        .iter()
+        .filter(|p| normalize_arch(&p.architecture) == host_arch && p.os == host.os)
+        .collect();
+    let chosen = match &host.variant {
+        Some(variant) => matching
+            .iter()
+            .find(|p| p.variant.as_ref() == Some(variant))
+            .or(matching.first()),
+        None => matching.first(),
+    };
+    match chosen {
+        Some(platform) => Ok(Some((**platform).clone())),
+        None => {
+            let mut archs: Vec<_> = usable
+                .iter()
+                .map(|p| normalize_arch(&p.architecture))
+                .collect();
+            archs.dedup();
+            Err(ArchMismatch {
+                image: image.to_string(),
+                image_arch: archs.join(","),
+                host_arch,
+            })
+        }
+    }
+}
+
+/// Whether a pulled image can run on `host`; images that don't report an architecture pass
+pub fn check_compatible(
+    metadata: &ImageMetadata,
+    host: &ImagePlatform,
+) -> Result<(), ArchMismatch> {
+    let image_arch = normalize_arch(&metadata.platform.architecture);
+    let host_arch = normalize_arch(&host.architecture);
+    if image_arch.is_empty() || image_arch == host_arch {
+        return Ok(());
+    }
+    Err(ArchMismatch {
+        image: metadata.reference.clone(),
+        image_arch,
+        host_arch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bollard::models::DistributionInspect;
+
+    /// `GET /distribution/node:20/json` for a multi-arch index with an attestation entry
+    const MULTI_ARCH: &str = r#"{
+        "Descriptor": {
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "digest": "sha256:9d0f5dd5c8c8d0a4e7b1cbd4f6b3b0e0b6b0c6c0d8d1f7e6c5a2b2d1e3f4a5b6",
+            "size": 7134
+        },
+        "Platforms": [
+            {"architecture": "amd64", "os": "linux"},
+            {"architecture": "arm", "os": "linux", "variant": "v7"},
+            {"architecture": "arm64", "os": "linux", "variant": "v8"},
+            {"architecture": "ppc64le", "os": "linux"},
+            {"architecture": "unknown", "os": "unknown"}
+        ]
+    }"#;
+
+    /// An image built and pushed from a single amd64 machine
+    const SINGLE_ARCH: &str = r#"{
+        "Descriptor": {
+            "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+            "digest": "sha256:1b2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d",
+            "size": 1570
+        },
+        "Platforms": [{"architecture": "amd64", "os": "linux"}]
+    }"#;
+
+    fn platforms(fixture: &str) -> Vec<ImagePlatform> {
+        let inspect: DistributionInspect = serde_json::from_str(fixture).unwrap();
+        inspect.platforms.into_iter().map(Into::into).collect()
+    }
+
+    fn host(arch: &str) -> ImagePlatform {
+        ImagePlatform {
+            os: "linux".to_string(),
+            architecture: arch.to_string(),
+            variant: None,
+        }
+    }
+
+    #[test]
+    fn multi_arch_index_resolves_to_the_host() {
+        let offered = platforms(MULTI_ARCH);
+
+        let arm = resolve_platform("node:20", &offered, &host("aarch64"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(arm.to_docker_platform(), "linux/arm64/v8");
+
+        let x86 = resolve_platform("node:20", &offered, &host("x86_64"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(x86.to_docker_platform(), "linux/amd64");
+
+        let err = resolve_platform("node:20", &offered, &host("riscv64")).unwrap_err();
+        assert_eq!(err.image_arch, "amd64,arm,arm64,ppc64le");
+    }
+
+    #[test]
+    fn single_arch_image_on_the_wrong_host_is_a_mismatch() {
+        let offered = platforms(SINGLE_ARCH);
+        assert!(resolve_platform("acme/tool:1", &offered, &host("amd64")).is_ok());
+        assert_eq!(
+            resolve_platform("acme/tool:1", &offered, &host("arm64")).unwrap_err(),
+            ArchMismatch {
+                image: "acme/tool:1".to_string(),
+                image_arch: "amd64".to_string(),
+                host_arch: "arm64".to_string(),
+            }
+        );
+
+        // An image pulled without going through the registry is checked by its config
+        let pulled = ImageMetadata {
+            reference: "acme/tool:1".to_string(),
+            platform: host("amd64"),
+            ..Default::default()
+        };
+        assert!(check_compatible(&pulled, &host("x86_64")).is_ok());
+        assert!(check_compatible(&pulled, &host("aarch64")).is_err());
+        assert!(resolve_platform("local", &[], &host("arm64"))
+            .unwrap()
+            .is_none());
+    }
+}
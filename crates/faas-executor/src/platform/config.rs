@@ -0,0 +1,532 @@
+//! Paths, sizes and limits of the executor stack, from a TOML file and the environment.
+//!
+//! Every setting has a default, so a file only lists the ones it changes. `FAAS_*`
+//! variables override the file, and the result is checked as a whole before anything is
+//! started, so a bad value stops the boot with the key or variable it came from.
+//!
+//! ```toml
+//! [docker]
+//! socket = "unix:///run/user/1000/docker.sock"
+//!
+//! [firecracker]
+//! kernel = "/srv/faas/vmlinux"
+//!
+//! [pools]
+//! max_containers = 32
+//! ```
+
+use crate::docker_endpoints::DockerAddress;
+use crate::firecracker::ScalingConfig;
+use crate::output_limit::{OutputLimit, DEFAULT_MAX_OUTPUT_BYTES};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Cannot read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Invalid config file {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("{var}={value:?} is not {expected}")]
+    Env {
+        var: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("Invalid config `{key}`: {reason}")]
+    Invalid { key: &'static str, reason: String },
+}
+
+/// Everything [`super::Executor::new`] would otherwise hardcode
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlatformConfig {
+    pub docker: DockerSettings,
+    pub firecracker: FirecrackerSettings,
+    pub storage: StorageSettings,
+    pub pools: PoolSettings,
+    pub limits: LimitSettings,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DockerSettings {
+    /// Socket path, `unix://` path or `tcp://` address of the daemon; unset uses
+    /// `DOCKER_HOST` or the platform's default socket (`FAAS_DOCKER_SOCKET`)
+    pub socket: Option<String>,
+    /// `cert.pem`, `key.pem` and `ca.pem` for a `tcp://` socket served over TLS
+    /// (`FAAS_DOCKER_CERT_PATH`)
+    pub cert_dir: Option<PathBuf>,
+}
+
+impl DockerSettings {
+    /// Without a socket, `DOCKER_HOST` with `DOCKER_TLS_VERIFY` and `DOCKER_CERT_PATH`
+    pub fn address(&self) -> DockerAddress {
+        match (
+            self.socket.as_deref().and_then(DockerAddress::parse),
+            &self.cert_dir,
+        ) {
+            (Some(DockerAddress::Http(address)), Some(certs)) => DockerAddress::Tls {
+                address,
+                certs: certs.clone(),
+            },
+            (Some(address), _) => address,
+            (None, _) => DockerAddress::from_env(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FirecrackerSettings {
+    /// `FAAS_FIRECRACKER_BINARY`
+    pub binary: String,
+    /// `FAAS_FIRECRACKER_KERNEL`
+    pub kernel: String,
+    /// `FAAS_FIRECRACKER_ROOTFS`
+    pub rootfs: String,
+    /// Where VM state is kept (`FAAS_FIRECRACKER_STATE_DIR`)
+    pub state_dir: PathBuf,
+    /// `snapshots` under `state_dir` when unset (`FAAS_FIRECRACKER_SNAPSHOT_DIR`)
+    pub snapshot_dir: Option<PathBuf>,
+    /// Each VM's sockets are `{socket_base}-{vm_id}.*` (`FAAS_FIRECRACKER_SOCKET_BASE`)
+    pub socket_base: String,
+    /// Size of the VM result cache (`FAAS_VM_CACHE_MB`)
+    pub cache_mb: usize,
+    /// `FAAS_VM_CACHE_ENTRIES`
+    pub cache_entries: usize,
+}
+
+impl Default for FirecrackerSettings {
+    fn default() -> Self {
+        Self {
+            binary: "firecracker".to_string(),
+            kernel: "/var/lib/faas/kernel".to_string(),
+            rootfs: "/var/lib/faas/rootfs.ext4".to_string(),
+            state_dir: PathBuf::from("/var/lib/firecracker"),
+            snapshot_dir: None,
+            socket_base: "/tmp/firecracker".to_string(),
+            cache_mb: 100,
+            cache_entries: 1000,
+        }
+    }
+}
+
+impl FirecrackerSettings {
+    pub fn snapshot_dir(&self) -> PathBuf {
+        self.snapshot_dir
+            .clone()
+            .unwrap_or_else(|| self.state_dir.join("snapshots"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSettings {
+    /// Blobs and their cache (`FAAS_STORAGE_DIR`)
+    pub dir: PathBuf,
+    /// `FAAS_STORAGE_CACHE_MB`
+    pub cache_mb: usize,
+    /// Object store blobs are tiered to (`FAAS_OBJECT_STORE_URL`)
+    pub object_store_url: Option<String>,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        // Off Linux /var/lib isn't writable by a user
+        let dir = if cfg!(target_os = "linux") {
+            PathBuf::from("/var/lib/faas")
+        } else {
+            std::env::temp_dir().join("faas")
+        };
+        Self {
+            dir,
+            cache_mb: 100,
+            object_store_url: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolSettings {
+    /// Idle containers kept per image (`FAAS_CONTAINER_POOL_MIN`)
+    pub min_containers: usize,
+    /// `FAAS_CONTAINER_POOL_MAX`
+    pub max_containers: usize,
+    /// Warm VMs kept per environment (`FAAS_VM_POOL_MIN`)
+    pub min_warm_vms: usize,
+    /// `FAAS_VM_POOL_MAX`
+    pub max_warm_vms: usize,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        let scaling = ScalingConfig::default();
+        Self {
+            min_containers: 2,
+            max_containers: 10,
+            min_warm_vms: scaling.min_warm_vms,
+            max_warm_vms: scaling.max_warm_vms,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    /// How long an execution may run when its request doesn't say
+    /// (`FAAS_DEFAULT_TIMEOUT_MS`)
+    pub default_timeout_ms: u64,
+    /// Bytes of stdout and stderr kept per execution (`FAAS_MAX_OUTPUT_BYTES`)
+    pub max_output_bytes: usize,
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        Self {
+            default_timeout_ms: crate::DEFAULT_TIMEOUT.as_millis() as u64,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        }
+    }
+}
+
+impl PlatformConfig {
+    /// The defaults, overridden by the file at `path` if there is one and then by the
+    /// environment, validated
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.with_vars(|name| std::env::var(name).ok())
+    }
+
+    /// [`Self::load`] without a file
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::load(None)
+    }
+
+    /// The file at `path` alone, neither validated nor overridden by the environment
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    /// `self` with the variables `var` returns applied over it, validated. Empty variables
+    /// are ignored, except that they unset the optional settings.
+    pub fn with_vars(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let vars = Vars(var);
+        vars.optional("FAAS_DOCKER_SOCKET", &mut self.docker.socket);
+        vars.optional_path("FAAS_DOCKER_CERT_PATH", &mut self.docker.cert_dir);
+
+        let firecracker = &mut self.firecracker;
+        vars.string("FAAS_FIRECRACKER_BINARY", &mut firecracker.binary);
+        vars.string("FAAS_FIRECRACKER_KERNEL", &mut firecracker.kernel);
+        vars.string("FAAS_FIRECRACKER_ROOTFS", &mut firecracker.rootfs);
+        vars.path("FAAS_FIRECRACKER_STATE_DIR", &mut firecracker.state_dir);
+        vars.optional_path(
+            "FAAS_FIRECRACKER_SNAPSHOT_DIR",
+            &mut firecracker.snapshot_dir,
+        );
+        vars.string("FAAS_FIRECRACKER_SOCKET_BASE", &mut firecracker.socket_base);
+        vars.number("FAAS_VM_CACHE_MB", &mut firecracker.cache_mb)?;
+        vars.number("FAAS_VM_CACHE_ENTRIES", &mut firecracker.cache_entries)?;
+
+        let storage = &mut self.storage;
+        vars.path("FAAS_STORAGE_DIR", &mut storage.dir);
+        vars.number("FAAS_STORAGE_CACHE_MB", &mut storage.cache_mb)?;
+        vars.optional("FAAS_OBJECT_STORE_URL", &mut storage.object_store_url);
+
+        let pools = &mut self.pools;
+        vars.number("FAAS_CONTAINER_POOL_MIN", &mut pools.min_containers)?;
+        vars.number("FAAS_CONTAINER_POOL_MAX", &mut pools.max_containers)?;
+        vars.number("FAAS_VM_POOL_MIN", &mut pools.min_warm_vms)?;
+        vars.number("FAAS_VM_POOL_MAX", &mut pools.max_warm_vms)?;
+
+        vars.number(
+            "FAAS_DEFAULT_TIMEOUT_MS",
+            &mut self.limits.default_timeout_ms,
+        )?;
+        vars.number("FAAS_MAX_OUTPUT_BYTES", &mut self.limits.max_output_bytes)?;
+
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// The first setting that can't work, by its key in the file
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        fn invalid(key: &'static str, reason: impl Into<String>) -> ConfigError {
+            ConfigError::Invalid {
+                key,
+                reason: reason.into(),
+            }
+        }
+        fn at_least_one(key: &'static str, value: impl Into<u64>) -> Result<(), ConfigError> {
+            if value.into() == 0 {
+                return Err(invalid(key, "must be at least 1"));
+            }
+            Ok(())
+        }
+
+        if let Some(socket) = &self.docker.socket {
+            if DockerAddress::parse(socket).is_none() {
+                return Err(invalid(
+                    "docker.socket",
+                    format!(
+                        "{socket:?} is not a socket path or a unix://, tcp:// or http:// address"
+                    ),
+                ));
+            }
+        }
+        let socket = self.docker.socket.as_deref().and_then(DockerAddress::parse);
+        if self.docker.cert_dir.is_some() && !matches!(socket, Some(DockerAddress::Http(_))) {
+            return Err(invalid(
+                "docker.cert_dir",
+                "only applies to a tcp:// or http:// socket",
+            ));
+        }
+
+        let firecracker = &self.firecracker;
+        for (key, value) in [
+            ("firecracker.binary", &firecracker.binary),
+            ("firecracker.kernel", &firecracker.kernel),
+            ("firecracker.rootfs", &firecracker.rootfs),
+            ("firecracker.socket_base", &firecracker.socket_base),
+        ] {
+            if value.is_empty() {
+                return Err(invalid(key, "must not be empty"));
+            }
+        }
+        at_least_one("firecracker.cache_mb", firecracker.cache_mb as u64)?;
+        at_least_one(
+            "firecracker.cache_entries",
+            firecracker.cache_entries as u64,
+        )?;
+
+        at_least_one("storage.cache_mb", self.storage.cache_mb as u64)?;
+        if let Some(url) = &self.storage.object_store_url {
+            url::Url::parse(url)
+                .map_err(|e| invalid("storage.object_store_url", format!("{url:?}: {e}")))?;
+        }
+
+        let pools = &self.pools;
+        at_least_one("pools.max_containers", pools.max_containers as u64)?;
+        if pools.min_containers > pools.max_containers {
+            return Err(invalid(
+                "pools.min_containers",
+                format!(
+                    "{} is more than pools.max_containers ({})",
+                    pools.min_containers, pools.max_containers
+                ),
+            ));
+        }
+        if pools.min_warm_vms > pools.max_warm_vms {
+            return Err(invalid(
+                "pools.min_warm_vms",
+                format!(
+                    "{} is more than pools.max_warm_vms ({})",
+                    pools.min_warm_vms, pools.max_warm_vms
+                ),
+            ));
+        }
+
+        at_least_one("limits.default_timeout_ms", self.limits.default_timeout_ms)?;
+        at_least_one(
+            "limits.max_output_bytes",
+            self.limits.max_output_bytes as u64,
+        )?;
+        Ok(())
+    }
+
+    pub fn default_timeout(&self) -> Duration {
+        Duration::from_millis(self.limits.default_timeout_ms)
+    }
+
+    /// The output cap, spilling where `FAAS_OUTPUT_SPILL_DIR` says
+    pub fn output_limit(&self) -> OutputLimit {
+        OutputLimit {
+            max_bytes: self.limits.max_output_bytes,
+            ..OutputLimit::from_env()
+        }
+    }
+
+    /// VM scaling with these pool sizes, and thresholds from the environment
+    pub fn vm_scaling(&self) -> ScalingConfig {
+        ScalingConfig {
+            min_warm_vms: self.pools.min_warm_vms,
+            max_warm_vms: self.pools.max_warm_vms,
+            ..ScalingConfig::from_env()
+        }
+    }
+}
+
+/// Overrides read through a variable lookup
+struct Vars<F>(F);
+
+impl<F: Fn(&str) -> Option<String>> Vars<F> {
+    fn get(&self, name: &str) -> Option<String> {
+        (self.0)(name).filter(|value| !value.is_empty())
+    }
+
+    fn string(&self, name: &str, setting: &mut String) {
+        if let Some(value) = self.get(name) {
+            *setting = value;
+        }
+    }
+
+    fn path(&self, name: &str, setting: &mut PathBuf) {
+        if let Some(value) = self.get(name) {
+            *setting = PathBuf::from(value);
+        }
+    }
+
+    fn optional(&self, name: &str, setting: &mut Option<String>) {
+        if let Some(value) = (self.0)(name) {
+            *setting = Some(value).filter(|value| !value.is_empty());
+        }
+    }
+
+    fn optional_path(&self, name: &str, setting: &mut Option<PathBuf>) {
+        if let Some(value) = (self.0)(name) {
+            *setting = Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty());
+        }
+    }
+
+    fn number<T: FromStr>(&self, name: &'static str, setting: &mut T) -> Result<(), ConfigError> {
+        if let Some(value) = self.get(name) {
+            *setting = value.trim().parse().map_err(|_| ConfigError::Env {
+                var: name,
+                value,
+                expected: "a whole number",
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const SAMPLE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/platform.example.toml");
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn the_sample_file_parses_and_is_valid() {
+        let config = PlatformConfig::from_file(Path::new(SAMPLE))
+            .unwrap()
+            .with_vars(vars(&[]))
+            .unwrap();
+        assert_eq!(
+            config.docker.address(),
+            DockerAddress::Unix(PathBuf::from("/var/run/docker.sock"))
+        );
+        assert_eq!(config.firecracker.kernel, "/var/lib/faas/vmlinux");
+        assert_eq!(
+            config.firecracker.snapshot_dir(),
+            PathBuf::from("/var/lib/firecracker/snapshots")
+        );
+        assert_eq!(config.pools.max_containers, 20);
+        assert_eq!(config.default_timeout(), Duration::from_secs(60));
+        // What the file leaves out keeps its default
+        assert_eq!(config.firecracker.binary, "firecracker");
+    }
+
+    #[test]
+    fn variables_take_precedence_over_the_file() {
+        let config = PlatformConfig::from_file(Path::new(SAMPLE))
+            .unwrap()
+            .with_vars(vars(&[
+                ("FAAS_DOCKER_SOCKET", "tcp://10.0.0.5:2375"),
+                ("FAAS_FIRECRACKER_KERNEL", "/srv/vmlinux"),
+                ("FAAS_CONTAINER_POOL_MAX", " 40 "),
+                ("FAAS_DEFAULT_TIMEOUT_MS", "5000"),
+                // Empty ones are ignored
+                ("FAAS_STORAGE_DIR", ""),
+            ]))
+            .unwrap();
+        assert_eq!(
+            config.docker.address(),
+            DockerAddress::Http("tcp://10.0.0.5:2375".to_string())
+        );
+        assert_eq!(config.firecracker.kernel, "/srv/vmlinux");
+        assert_eq!(config.pools.max_containers, 40);
+        assert_eq!(config.default_timeout(), Duration::from_secs(5));
+        assert_eq!(config.storage.dir, PathBuf::from("/var/lib/faas"));
+
+        let config = PlatformConfig::default()
+            .with_vars(vars(&[
+                ("FAAS_DOCKER_SOCKET", "tcp://10.0.0.5:2376"),
+                ("FAAS_DOCKER_CERT_PATH", "/etc/faas/docker"),
+            ]))
+            .unwrap();
+        assert_eq!(
+            config.docker.address(),
+            DockerAddress::Tls {
+                address: "tcp://10.0.0.5:2376".to_string(),
+                certs: PathBuf::from("/etc/faas/docker"),
+            }
+        );
+    }
+
+    #[test]
+    fn bad_settings_name_where_they_came_from() {
+        let err = toml::from_str::<PlatformConfig>("[pools]\nmax_container = 4\n").unwrap_err();
+        assert!(err.to_string().contains("max_container"), "{err}");
+
+        match PlatformConfig::default().with_vars(vars(&[("FAAS_VM_POOL_MAX", "lots")])) {
+            Err(ConfigError::Env { var, value, .. }) => {
+                assert_eq!(var, "FAAS_VM_POOL_MAX");
+                assert_eq!(value, "lots");
+            }
+            other => panic!("expected an env error, got {other:?}"),
+        }
+
+        let invalid = |config: PlatformConfig| match config.validate() {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            other => panic!("expected an invalid setting, got {other:?}"),
+        };
+        let mut config = PlatformConfig::default();
+        config.pools.min_warm_vms = config.pools.max_warm_vms + 1;
+        assert_eq!(invalid(config), "pools.min_warm_vms");
+        let mut config = PlatformConfig::default();
+        config.docker.socket = Some("docker.sock".to_string());
+        assert_eq!(invalid(config), "docker.socket");
+        let mut config = PlatformConfig::default();
+        config.docker.cert_dir = Some(PathBuf::from("/etc/faas/docker"));
+        assert_eq!(invalid(config), "docker.cert_dir");
+        let mut config = PlatformConfig::default();
+        config.limits.default_timeout_ms = 0;
+        assert_eq!(invalid(config), "limits.default_timeout_ms");
+    }
+
+    #[test]
+    fn a_missing_file_is_a_read_error() {
+        assert!(matches!(
+            PlatformConfig::load(Some(Path::new("/nonexistent/platform.toml"))),
+            Err(ConfigError::Read { .. })
+        ));
+    }
+}
@@ -1,25 +1,44 @@
 use anyhow::Result;
 use faas_common::SandboxExecutor;
-use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tracing::{info, instrument};
+use tracing::{debug, info, instrument, warn};
 
-use super::{fork::ForkManager, memory::MemoryPool, snapshot::SnapshotStore};
+use super::arch::{self, ArchMismatch};
+use super::config::PlatformConfig;
+use super::image_metadata::{
+    DockerRegistryClient, ImageMetadataError, ImageMetadataService, MetadataCacheConfig,
+};
+use super::negative_cache::{FailureKind, NegativeCache};
+use super::result_cache::{CachedResult, ResultCache, ResultCacheStats};
+use super::runtime_policy::{
+    AutoRuntimePolicy, CapabilityProbe, HostCapabilities, RuntimeDecision,
+};
+use super::snapshot::{Backend, Snapshot, SnapshotStore};
+use super::speculation::{self, AttemptRunner, SpeculationReport, SpeculationStats};
+use super::{fork::ForkManager, memory::MemoryPool};
 use crate::bollard::Docker;
-use crate::container_pool::{ContainerPoolManager, PoolConfig};
-use crate::docker_fork::DockerForkManager;
+use crate::container_pool::{ContainerPoolManager, PoolConfig, PoolStats, PooledContainer};
+use crate::docker_checkpoint::{CheckpointError, CheckpointedContainer, DockerCheckpoints};
+use crate::docker_endpoints::DockerEndpointPool;
+use crate::docker_fork::{branch_image, DockerForkManager};
+use crate::docker_snapshot::DockerSnapshotManager;
+use crate::drain::DrainController;
+use crate::firecracker::{VmPoolStats, WarmLimits};
+use crate::output_limit::OutputLimit;
 use crate::performance::metrics_collector::MetricsConfig;
 use crate::performance::predictive_scaling::ScalingConfig;
 use crate::performance::{
-    CacheManager, CacheStrategy, MetricsCollector, OptimizationConfig, PredictiveScaler,
-    SnapshotOptimizer,
+    MetricsCollector, OptimizationConfig, PredictiveScaler, SnapshotOptimizer,
 };
+use crate::running::RunningExecutions;
 use crate::storage::StorageManager;
-use sha2::{Digest, Sha256};
 
-#[derive(Debug, Clone, Copy)]
+const DEFAULT_WARM_POOL_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Mode {
+    #[default]
     Ephemeral,
     Cached,
     Checkpointed,
@@ -27,7 +46,7 @@ pub enum Mode {
     Persistent,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Request {
     pub id: String,
     pub code: String,
@@ -36,8 +55,154 @@ pub struct Request {
     pub timeout: Duration,
     pub checkpoint: Option<String>,
     pub branch_from: Option<String>,
+    /// `Auto` or unset leaves the choice to [`AutoRuntimePolicy`]
     pub runtime: Option<faas_common::Runtime>,
-    pub env_vars: Option<std::collections::HashMap<String, String>>,
+    /// Weighed by the auto policy; a named runtime is used regardless
+    pub isolation: Option<faas_common::IsolationLevel>,
+    /// Already merged by precedence; see [`faas_common::env`]
+    pub env_vars: Option<std::collections::BTreeMap<String, String>>,
+    /// Directory `code` runs in, created if missing; the image's own when unset
+    pub working_dir: Option<String>,
+    /// `uid[:gid]` or user name `code` runs as; the image's user when unset
+    pub user: Option<String>,
+    pub memory_mb: Option<u32>,
+    /// CPU share in cores; `0.5` is half a core
+    pub cpu_cores: Option<f64>,
+    pub ulimits: Option<Vec<faas_common::Ulimit>>,
+    pub shm_size_mb: Option<u64>,
+    /// Most processes and threads the sandbox may hold; the runtime's own default when unset
+    pub pids_limit: Option<i64>,
+    pub tmpfs: Option<Vec<faas_common::TmpfsMount>>,
+    pub placement: Option<faas_common::Placement>,
+    /// Written to the command's stdin
+    pub payload: Vec<u8>,
+    pub environment_overrides: Option<faas_common::EnvOverrides>,
+    /// Path and contents of files placed in the sandbox before `code` runs
+    pub input_files: Vec<(String, Vec<u8>)>,
+    /// Host paths bind-mounted read-only, as host and sandbox path
+    pub read_only_mounts: Vec<(String, String)>,
+    /// GPUs passed through to the container
+    pub gpu: Option<faas_common::GpuRequest>,
+    /// What the sandbox can reach; Docker's default bridge when unset
+    pub network: Option<faas_common::NetworkPolicy>,
+    /// Run on more than one runtime at once; see [`super::speculation`]
+    pub execution_strategy: Option<faas_common::ExecutionStrategy>,
+    /// The command may safely run more than once, as speculation does
+    pub idempotent: bool,
+    /// Where a `Cached` result is kept; [`Request::result_cache_key`] derives one when unset
+    pub cache_key: Option<String>,
+    /// Whose cached results `cache_key` is looked up among, usually the tenant's
+    pub cache_namespace: Option<String>,
+    /// Who the request runs for; it only claims warm containers from the tenant's own pools
+    pub tenant: Option<String>,
+    /// Run a `Cached` request even if a result is stored, and store the new one
+    pub no_cache: bool,
+}
+
+impl Request {
+    /// `placement`, on a GPU host when the request asks for GPUs
+    pub fn effective_placement(&self) -> Option<faas_common::Placement> {
+        if self.gpu.is_none() {
+            return self.placement.clone();
+        }
+        Some(faas_common::Placement {
+            gpu: true,
+            ..self.placement.clone().unwrap_or_default()
+        })
+    }
+
+    /// The parts of the request a host has to provide, as a stable cache key
+    pub fn resource_signature(&self) -> String {
+        let mut signature = String::from("resources:");
+        if let Some(placement) = &self.effective_placement() {
+            signature.push_str(&format!(
+                "gpu={},arch={};",
+                placement.gpu,
+                placement
+                    .arch
+                    .as_deref()
+                    .map(arch::normalize_arch)
+                    .unwrap_or_default()
+            ));
+        }
+        if let Some(shm) = self.shm_size_mb {
+            signature.push_str(&format!("shm={shm};"));
+        }
+        if let Some(pids) = self.pids_limit {
+            signature.push_str(&format!("pids={pids};"));
+        }
+        for mount in self.tmpfs.iter().flatten() {
+            signature.push_str(&format!("tmpfs={}:{};", mount.path, mount.size_mb));
+        }
+        for ulimit in self.ulimits.iter().flatten() {
+            signature.push_str(&format!(
+                "ulimit={}:{}:{};",
+                ulimit.name, ulimit.soft, ulimit.hard
+            ));
+        }
+        if let Some(gpu) = &self.gpu {
+            signature.push_str(&format!(
+                "gpus={}:{};",
+                gpu.count.map(|n| n.to_string()).unwrap_or_default(),
+                gpu.device_ids.join(",")
+            ));
+        }
+        signature
+    }
+
+    /// `cache_key` as given, or a hash of everything that decides what the command prints;
+    /// see [`faas_common::cache_key`]
+    pub fn result_cache_key(&self) -> String {
+        if let Some(key) = &self.cache_key {
+            return key.clone();
+        }
+        faas_common::cache_key::CacheKeyInputs {
+            image: &self.env,
+            command: &self.code,
+            runtime: self.runtime,
+            env: self.env_vars.as_ref(),
+            working_dir: self.working_dir.as_deref(),
+            user: self.user.as_deref(),
+            payload: &self.payload,
+            input_files: &self.input_files,
+            read_only_mounts: &self.read_only_mounts,
+            environment_overrides: self.environment_overrides.as_ref(),
+        }
+        .derive()
+    }
+
+    /// Sandbox config shared by every mode; callers pick the id, mode and runtime.
+    fn sandbox_config(
+        &self,
+        function_id: String,
+        execution_mode: faas_common::ExecutionMode,
+        runtime: Option<faas_common::Runtime>,
+    ) -> faas_common::SandboxConfig {
+        faas_common::SandboxConfig {
+            function_id,
+            source: self.env.clone(),
+            command: vec!["sh".to_string(), "-c".to_string(), self.code.clone()],
+            payload: self.payload.clone(),
+            env_vars: self.env_vars.as_ref().map(faas_common::env::to_vars),
+            working_dir: self.working_dir.clone(),
+            user: self.user.clone(),
+            runtime,
+            execution_mode: Some(execution_mode),
+            memory_limit: self.memory_mb,
+            cpu_limit: self.cpu_cores,
+            timeout: Some(self.timeout.as_millis() as u64),
+            ulimits: self.ulimits.clone(),
+            shm_size_mb: self.shm_size_mb,
+            pids_limit: self.pids_limit,
+            tmpfs: self.tmpfs.clone(),
+            placement: self.effective_placement(),
+            environment_overrides: self.environment_overrides.clone(),
+            input_files: self.input_files.clone(),
+            read_only_mounts: self.read_only_mounts.clone(),
+            gpu: self.gpu.clone(),
+            network: self.network.clone(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -48,6 +213,111 @@ pub struct Response {
     pub exit_code: i32,
     pub duration: Duration,
     pub snapshot: Option<String>,
+    /// Set when the execution was speculative: which attempt won and what each cost
+    pub speculation: Option<SpeculationReport>,
+    /// The stdout came from the result cache rather than a fresh run
+    pub cache_hit: bool,
+    /// The key a cached execution was looked up and stored under
+    pub cache_key: Option<String>,
+    /// Where an ephemeral or persistent execution ran, and why
+    pub runtime_decision: Option<RuntimeDecision>,
+    /// What the execution consumed, where its runtime measured it
+    pub usage: Option<faas_common::ExecutionUsage>,
+    /// Set when `stdout`/`stderr` were cut short at the output limit
+    pub truncation: Option<faas_common::OutputTruncation>,
+    /// Served without starting a container or VM for it: from a prewarmed container, a
+    /// running instance or the result cache
+    pub warm_start: bool,
+}
+
+/// One environment's warm pool: the containers [`Executor::prewarm`] started for a Docker
+/// image, or the VMs the Firecracker scaler forked for an environment
+#[derive(Debug, Clone)]
+pub struct WarmPoolStats {
+    pub runtime: faas_common::Runtime,
+    /// The image, for a Docker pool
+    pub environment: String,
+    /// The tenant a Docker pool is kept for; `None` for a shared pool
+    pub namespace: Option<String>,
+    /// Idle and ready to hand out
+    pub warm: usize,
+    pub in_use: usize,
+    pub limits: WarmLimits,
+    /// Executions a warm container or VM served
+    pub hits: u64,
+    /// Executions that found the pool empty
+    pub misses: u64,
+    /// Time to hand out a container or VM; `None` before the first
+    pub avg_acquisition: Option<Duration>,
+    /// Time since a Docker pool was opened
+    pub age: Option<Duration>,
+    /// How long a Docker pool's longest-idle container has waited
+    pub oldest_idle: Option<Duration>,
+}
+
+impl WarmPoolStats {
+    /// Share of executions a warm container or VM served; `None` before the first
+    pub fn hit_rate(&self) -> Option<f64> {
+        let requests = self.hits + self.misses;
+        (requests > 0).then(|| self.hits as f64 / requests as f64)
+    }
+
+    fn empty_vm_pool(environment: &str, limits: WarmLimits) -> Self {
+        Self {
+            runtime: faas_common::Runtime::Firecracker,
+            environment: environment.to_string(),
+            namespace: None,
+            warm: 0,
+            in_use: 0,
+            limits,
+            hits: 0,
+            misses: 0,
+            avg_acquisition: None,
+            age: None,
+            oldest_idle: None,
+        }
+    }
+}
+
+impl From<PoolStats> for WarmPoolStats {
+    fn from(stats: PoolStats) -> Self {
+        Self {
+            runtime: faas_common::Runtime::Docker,
+            environment: stats.image,
+            namespace: stats.namespace,
+            warm: stats.available,
+            in_use: stats.in_use,
+            limits: WarmLimits {
+                min: stats.config.min_size,
+                max: stats.config.max_size,
+            },
+            hits: stats.hits,
+            misses: stats.requests.saturating_sub(stats.hits),
+            avg_acquisition: stats
+                .avg_acquisition_ms
+                .map(|ms| Duration::from_secs_f64(ms / 1000.0)),
+            age: Some(stats.age),
+            oldest_idle: stats.oldest_idle,
+        }
+    }
+}
+
+impl From<VmPoolStats> for WarmPoolStats {
+    fn from(stats: VmPoolStats) -> Self {
+        Self {
+            runtime: faas_common::Runtime::Firecracker,
+            environment: stats.environment,
+            namespace: None,
+            warm: stats.warm,
+            in_use: stats.in_use,
+            limits: stats.limits,
+            hits: stats.hits,
+            misses: stats.misses,
+            avg_acquisition: stats.avg_acquisition,
+            age: None,
+            oldest_idle: None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -60,23 +330,58 @@ pub struct Executor {
     docker_fork: Arc<DockerForkManager>, // REAL Docker forking
     // Performance optimizations
     container_pool: Arc<ContainerPoolManager>,
-    cache_manager: Arc<CacheManager>,
+    // Containers started ahead of time by `prewarm`, each used by one execution
+    warm_pool: Arc<ContainerPoolManager>,
+    // Whole results of `Cached` executions
+    result_cache: Arc<ResultCache>,
     metrics: Arc<MetricsCollector>,
     snapshot_optimizer: Arc<SnapshotOptimizer>,
     predictive_scaler: Arc<PredictiveScaler>,
     // Unified storage system
     storage: Arc<StorageManager>,
+    // The configured daemon, reconnected after it restarts
+    local_docker: Arc<DockerEndpointPool>,
+    // Named daemons for requests that carry a placement (e.g. GPU hosts)
+    docker_endpoints: Option<Arc<DockerEndpointPool>>,
+    image_metadata: Arc<ImageMetadataService>,
+    // Committed-image snapshots created through the gateway
+    docker_snapshots: Arc<DockerSnapshotManager>,
+    drain: Arc<DrainController>,
+    // Resource combinations no host here can provide
+    unsatisfiable: Arc<NegativeCache>,
+    speculation: Arc<SpeculationStats>,
+    runtime_policy: Arc<AutoRuntimePolicy>,
+    capabilities: Arc<dyn CapabilityProbe>,
+    /// The local daemon has the NVIDIA runtime, probed once at startup
+    gpu: bool,
+    /// Runs of every runtime, so [`Self::cancel`] reaches them
+    running: Arc<RunningExecutions>,
+    checkpoints: Arc<DockerCheckpoints>,
+    default_timeout: Duration,
+    output: OutputLimit,
 }
 
 impl Executor {
-    pub async fn new() -> Result<Self> {
+    /// Everything connected, created and sized as `config` says; see [`PlatformConfig::load`]
+    pub async fn new(config: PlatformConfig) -> Result<Self> {
+        let drain = Arc::new(DrainController::new());
+        let running = Arc::new(RunningExecutions::new());
+        let output = config.output_limit();
+        let docker = Arc::new(config.docker.address().connect()?);
+        let snapshots = Arc::new(SnapshotStore::new().await?);
+        let checkpoints = Arc::new(DockerCheckpoints::new(
+            docker.clone(),
+            snapshots.root().join("docker"),
+        ));
+        let gpu = nvidia_runtime_available(&docker).await;
+        let pool_config = PoolConfig {
+            min_size: config.pools.min_containers,
+            max_size: config.pools.max_containers,
+            ..Default::default()
+        };
         Ok(Self {
             container: Arc::new(
                 {
-                    let docker = crate::docktopus::DockerBuilder::new()
-                        .await?
-                        .client()
-                        .clone();
                     let snapshot_manager = Some(Arc::new(
                         crate::docker_snapshot::DockerSnapshotManager::new(docker.clone()),
                     ));
@@ -85,7 +390,7 @@ impl Executor {
                             warm_pools: Arc::new(tokio::sync::Mutex::new(
                                 std::collections::HashMap::new(),
                             )),
-                            max_pool_size: 10,
+                            max_pool_size: config.pools.max_containers,
                             docker: docker.clone(),
                             snapshot_manager,
                             build_cache_volumes: Arc::new(tokio::sync::RwLock::new(
@@ -97,73 +402,480 @@ impl Executor {
                             gpu_pools: Arc::new(tokio::sync::Mutex::new(
                                 std::collections::HashMap::new(),
                             )),
-                            pool_manager: Some(Arc::new(ContainerPoolManager::new(
+                            pool_manager: Some(Arc::new(ContainerPoolManager::with_drain(
                                 docker.clone(),
-                                PoolConfig::default(),
+                                pool_config.clone(),
+                                drain.clone(),
                             ))),
                         },
                     ))
                 }
-                .await?,
+                .await?
+                .with_running(running.clone())
+                .with_output_limit(output.clone()),
             ),
             vm: if cfg!(target_os = "linux") {
                 Arc::new(
-                    crate::firecracker::FirecrackerExecutor::new(
-                        "firecracker".to_string(),
-                        "/var/lib/faas/kernel".to_string(),
-                        "/var/lib/faas/rootfs.ext4".to_string(),
+                    crate::firecracker::FirecrackerExecutor::with_settings(
+                        &config.firecracker,
+                        config.vm_scaling(),
                     )
-                    .unwrap_or_else(|_| crate::firecracker::FirecrackerExecutor::stub()),
+                    .unwrap_or_else(|_| crate::firecracker::FirecrackerExecutor::stub())
+                    .with_running(running.clone()),
                 )
             } else {
                 Arc::new(crate::firecracker::FirecrackerExecutor::stub())
             },
             memory: Arc::new(MemoryPool::new()?),
-            snapshots: Arc::new(SnapshotStore::new().await?),
+            snapshots,
             forks: Arc::new(ForkManager::new()?),
-            docker_fork: {
-                let docker = Docker::connect_with_local_defaults().unwrap();
-                Arc::new(DockerForkManager::new(docker.clone()))
-            },
+            docker_fork: Arc::new(DockerForkManager::new((*docker).clone())),
             // Performance optimizations
-            container_pool: {
-                let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
-                Arc::new(ContainerPoolManager::new(docker, PoolConfig::default()))
-            },
-            cache_manager: Arc::new(CacheManager::new(CacheStrategy::default()).await?),
+            container_pool: Arc::new(ContainerPoolManager::with_drain(
+                docker.clone(),
+                pool_config,
+                drain.clone(),
+            )),
+            warm_pool: Arc::new(ContainerPoolManager::with_drain(
+                docker.clone(),
+                PoolConfig {
+                    max_size: config.pools.max_containers,
+                    ..prewarm_pool_config()
+                },
+                drain.clone(),
+            )),
+            result_cache: Arc::new(ResultCache::from_env().await?),
             metrics: Arc::new(MetricsCollector::new(MetricsConfig::default())),
             snapshot_optimizer: Arc::new(SnapshotOptimizer::new(OptimizationConfig::default())),
             predictive_scaler: Arc::new(PredictiveScaler::new(ScalingConfig::default())),
             storage: {
-                let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
-
-                // Use platform-appropriate base path
-                let base_path = if cfg!(target_os = "linux") {
-                    PathBuf::from("/var/lib/faas")
-                } else {
-                    // On macOS/other platforms, use a user-writable temp directory
-                    std::env::temp_dir().join("faas")
-                };
+                let storage = StorageManager::new(
+                    config.storage.dir.clone(),
+                    docker.clone(),
+                    config.storage.cache_mb,
+                )
+                .await?;
+                // Tiered to the object store when one is configured
+                let storage = storage
+                    .with_tiered_storage_async(config.storage.object_store_url.clone())
+                    .await?;
+                Arc::new(storage)
+            },
+            local_docker: Arc::new(DockerEndpointPool::single_at(
+                config.docker.address(),
+                docker.clone(),
+            )),
+            docker_endpoints: None,
+            image_metadata: Arc::new(ImageMetadataService::new(
+                Arc::new(DockerRegistryClient::new((*docker).clone())),
+                MetadataCacheConfig::default(),
+            )),
+            docker_snapshots: Arc::new(DockerSnapshotManager::new(docker)),
+            drain,
+            unsatisfiable: Arc::new(NegativeCache::from_env()),
+            speculation: Arc::new(SpeculationStats::default()),
+            runtime_policy: Arc::new(AutoRuntimePolicy::from_env()),
+            capabilities: Arc::new(HostCapabilities),
+            gpu,
+            running,
+            checkpoints,
+            default_timeout: config.default_timeout(),
+            output,
+        })
+    }
 
-                let cache_size_mb = 100; // 100MB cache
+    /// How long an execution runs when its request doesn't say
+    pub fn default_timeout(&self) -> Duration {
+        self.default_timeout
+    }
 
-                // Initialize storage manager
-                let storage = StorageManager::new(base_path, docker, cache_size_mb).await?;
+    pub fn gpu_available(&self) -> bool {
+        self.gpu
+    }
 
-                // Check for object store URL from environment
-                let object_store_url = std::env::var("FAAS_OBJECT_STORE_URL").ok();
+    /// Drain state shared with this executor's pools
+    pub fn drain(&self) -> &Arc<DrainController> {
+        &self.drain
+    }
 
-                // Enable tiered storage if configured
-                let storage = storage.with_tiered_storage_async(object_store_url).await?;
+    pub fn docker_snapshots(&self) -> &Arc<DockerSnapshotManager> {
+        &self.docker_snapshots
+    }
 
-                Arc::new(storage)
+    /// Image manifest and config lookups, cached for every caller of this executor
+    pub fn image_metadata(&self) -> &Arc<ImageMetadataService> {
+        &self.image_metadata
+    }
+
+    /// Guest IP and TAP leases of Firecracker VMs; `None` where VMs aren't available
+    pub fn vm_network_stats(&self) -> Option<crate::firecracker::NetworkLeaseStats> {
+        self.vm.network_stats()
+    }
+
+    /// Containers backing persistent instances, on this executor's Docker daemon
+    pub fn instance_containers(&self) -> super::InstanceContainers {
+        super::InstanceContainers::new(self.container_pool.docker())
+    }
+
+    /// Volumes behind persistent instance workspaces, on the same daemon
+    pub fn workspace_volumes(&self) -> super::WorkspaceVolumes {
+        super::WorkspaceVolumes::new(self.container_pool.docker())
+    }
+
+    /// Runs on the configured daemon, reconnecting to it if the connection drops
+    pub fn docker(&self) -> crate::DockerExecutor {
+        crate::DockerExecutor::with_endpoints(self.local_docker.clone())
+    }
+
+    /// Warm container pools, including their canaries
+    pub fn container_pool(&self) -> Arc<ContainerPoolManager> {
+        self.container_pool.clone()
+    }
+
+    /// Start `count` idle containers for `image` that later ephemeral Docker executions of
+    /// the image run in instead of creating their own; returns the pool afterwards. A
+    /// `namespace` keeps them for the requests of that [`Request::tenant`].
+    pub async fn prewarm(
+        &self,
+        namespace: Option<&str>,
+        image: &str,
+        count: usize,
+    ) -> Result<PoolStats> {
+        let _admitted = self.drain.admit()?;
+        self.docker().ensure_image(image, None).await?;
+        let started = self.warm_pool.prewarm_in(namespace, image, count).await?;
+        info!("Pre-warmed {} containers for {}", started, image);
+        self.warm_pool
+            .get_stats_in(namespace, image)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("No warm pool for {image}"))
+    }
+
+    /// The pools [`Self::prewarm`] filled, by image
+    pub async fn warm_pools(&self) -> Vec<PoolStats> {
+        self.warm_pool.all_stats().await
+    }
+
+    /// Warm pools of both runtimes: Docker images first, then Firecracker environments
+    pub async fn pool_stats(&self) -> Vec<WarmPoolStats> {
+        let docker = self.warm_pools().await.into_iter().map(WarmPoolStats::from);
+        let vms = self
+            .vm
+            .pool_stats()
+            .await
+            .into_iter()
+            .map(WarmPoolStats::from);
+        docker.chain(vms).collect()
+    }
+
+    /// Keep between `limits.min` and `limits.max` warm containers for the Docker image
+    /// `environment`, or warm VMs for the Firecracker environment; a max below the min is
+    /// raised to it. Returns the pool afterwards. `namespace` picks a tenant's own Docker
+    /// pool, as in [`Self::prewarm`]; VM pools are shared.
+    pub async fn set_pool_limits(
+        &self,
+        namespace: Option<&str>,
+        runtime: faas_common::Runtime,
+        environment: &str,
+        limits: WarmLimits,
+    ) -> Result<WarmPoolStats> {
+        if runtime == faas_common::Runtime::Firecracker {
+            let limits = self
+                .vm
+                .set_pool_limits(environment, limits)
+                .await
+                .ok_or_else(|| faas_common::FaasError::IncompatibleFeature {
+                    feature: "warm VM pools".to_string(),
+                    runtime: "firecracker".to_string(),
+                    reason: "VMs aren't available on this host".to_string(),
+                })??;
+            let pool = self
+                .vm
+                .pool_stats()
+                .await
+                .into_iter()
+                .find(|p| p.environment == environment);
+            return Ok(pool.map_or_else(
+                || WarmPoolStats::empty_vm_pool(environment, limits),
+                WarmPoolStats::from,
+            ));
+        }
+        let _admitted = self.drain.admit()?;
+        self.docker().ensure_image(environment, None).await?;
+        let pool = self
+            .warm_pool
+            .set_limits_in(namespace, environment, limits.min, limits.max)
+            .await?;
+        Ok(pool.into())
+    }
+
+    /// Containers held by the execution and warm pools, idle or in use
+    pub async fn active_containers(&self) -> usize {
+        let pooled = self.container_pool.all_stats().await;
+        let warm = self.warm_pools().await;
+        pooled.iter().chain(&warm).map(|stats| stats.total).sum()
+    }
+
+    /// Force-remove the containers of a running execution, ending it; returns how many
+    /// were removed
+    pub async fn kill(&self, execution_id: &str) -> Result<usize> {
+        self.instance_containers()
+            .remove_execution(execution_id)
+            .await
+    }
+
+    /// Stop a running execution: its container or VM is torn down by the run itself,
+    /// which closes the container's attach streams and returns
+    /// [`crate::ExecutorError::Cancelled`]. Containers of the execution that no run is
+    /// waiting on are force-removed. Returns whether anything was running.
+    pub async fn cancel(&self, execution_id: &str) -> Result<bool> {
+        let tripped = self.running.cancel(execution_id);
+        let removed = self.kill(execution_id).await?;
+        Ok(tripped || removed > 0)
+    }
+
+    /// [`Self::cancel`] every running execution; returns the ids that were cancelled
+    pub async fn cancel_all(&self) -> Vec<String> {
+        let ids = self.running.cancel_all();
+        for id in &ids {
+            if let Err(e) = self.kill(id).await {
+                warn!("Could not remove the containers of {}: {}", id, e);
+            }
+        }
+        ids
+    }
+
+    /// Whether [`Self::run_streaming`] runs `req` where [`Self::run`] would: an ephemeral,
+    /// non-speculative execution the runtime policy sends to Docker
+    pub fn streams(&self, req: &Request) -> bool {
+        matches!(req.mode, Mode::Ephemeral)
+            && req.execution_strategy.is_none()
+            && !matches!(
+                self.runtime_policy
+                    .select(req, self.capabilities.as_ref())
+                    .runtime,
+                faas_common::Runtime::Firecracker
+            )
+    }
+
+    /// Requests refused from the negative caches instead of being retried
+    pub fn negative_cache_fast_fails(&self) -> u64 {
+        self.unsatisfiable.fast_fails() + self.image_metadata.negative_cache().fast_fails()
+    }
+
+    /// How often each side of a speculative execution won
+    pub fn speculation_stats(&self) -> speculation::SpeculationStatsSnapshot {
+        self.speculation.snapshot()
+    }
+
+    /// How often `Cached` executions were answered without running
+    pub fn result_cache_stats(&self) -> ResultCacheStats {
+        self.result_cache.stats()
+    }
+
+    /// Drop the cached result under `key` in `namespace`; false when there was none
+    pub async fn invalidate_cached(&self, namespace: Option<&str>, key: &str) -> bool {
+        let key = faas_common::cache_key::namespaced(namespace, key);
+        self.result_cache.invalidate(&key).await
+    }
+
+    /// Route Docker executions that specify a placement through `endpoints`
+    pub fn with_docker_endpoints(mut self, endpoints: Arc<DockerEndpointPool>) -> Self {
+        self.docker_endpoints = Some(endpoints);
+        self
+    }
+
+    /// The result, and whether a prewarmed container of `tenant`'s ran it
+    async fn execute_in_container(
+        &self,
+        config: faas_common::SandboxConfig,
+        tenant: Option<&str>,
+    ) -> faas_common::Result<(faas_common::InvocationResult, bool)> {
+        match (&self.docker_endpoints, &config.placement) {
+            (Some(endpoints), Some(_)) => crate::DockerExecutor::with_endpoints(endpoints.clone())
+                .with_running(self.running.clone())
+                .with_output_limit(self.output.clone())
+                .execute(config)
+                .await
+                .map(|result| (result, false)),
+            _ => match self.claim_warm(&config, tenant).await {
+                Some(container) => self
+                    .execute_in_warm_container(config, container)
+                    .await
+                    .map(|result| (result, true)),
+                None => self
+                    .container
+                    .execute(config)
+                    .await
+                    .map(|result| (result, false)),
             },
-        })
+        }
+    }
+
+    /// A prewarmed container from `tenant`'s pool for `config`'s image, unless it needs
+    /// options a running container can't take
+    async fn claim_warm(
+        &self,
+        config: &faas_common::SandboxConfig,
+        tenant: Option<&str>,
+    ) -> Option<PooledContainer> {
+        if crate::executor::requires_fresh_container(config) {
+            return None;
+        }
+        self.warm_pool.claim_in(tenant, &config.source).await
+    }
+
+    /// Run `config` in a prewarmed container, which is removed afterwards so nothing the
+    /// execution left behind reaches the next one
+    async fn execute_in_warm_container(
+        &self,
+        config: faas_common::SandboxConfig,
+        container: PooledContainer,
+    ) -> faas_common::Result<faas_common::InvocationResult> {
+        debug!(
+            "Running {} in warm container {}",
+            config.function_id, container.container_id
+        );
+        let container_id = container.container_id.clone();
+        let claim = WarmClaim {
+            pool: self.warm_pool.clone(),
+            container: Some(container),
+        };
+        let timeout = config
+            .timeout
+            .map_or(self.default_timeout, Duration::from_millis);
+        let docker = self.warm_pool.docker();
+        let run = self.running.track(&config.function_id);
+        let result = tokio::select! {
+            result = tokio::time::timeout(
+                timeout,
+                crate::executor::exec_attached(
+                    &docker,
+                    &container_id,
+                    crate::executor::exec_command(&config),
+                    config.working_dir.clone(),
+                    config.user.clone(),
+                    &config.payload,
+                    &self.output,
+                ),
+            ) => Some(result),
+            _ = run.token().cancelled() => None,
+        };
+        drop(claim);
+        match result {
+            Some(Ok(result)) => result.map_err(|e| match e.downcast::<faas_common::FaasError>() {
+                Ok(typed) => typed,
+                Err(e) => faas_common::FaasError::Executor(e.to_string()),
+            }),
+            Some(Err(_)) => Err(faas_common::FaasError::Timeout {
+                timeout_ms: timeout.as_millis() as u64,
+            }),
+            None => Err(crate::ExecutorError::Cancelled.into()),
+        }
+    }
+
+    /// Refuse work that can't succeed here before any container is created: images that are
+    /// missing or built for another architecture, and resources no host can provide.
+    ///
+    /// Both kinds of failure are remembered, so retries of the same request fail fast.
+    async fn preflight(&self, req: &Request) -> Result<()> {
+        self.check_gpu(req)?;
+        let key = req.resource_signature();
+        self.unsatisfiable.check(&key)?;
+        if let Some(reason) = self.unsatisfiable_reason(req) {
+            return Err(self
+                .unsatisfiable
+                .record(&key, FailureKind::Unsatisfiable, reason)
+                .into());
+        }
+        if self.docker_endpoints.is_some() && req.effective_placement().is_some() {
+            return Ok(());
+        }
+
+        let host = self.image_metadata.host_platform();
+        if let Some(wanted) = req.placement.as_ref().and_then(|p| p.arch.as_deref()) {
+            if arch::normalize_arch(wanted) != arch::normalize_arch(&host.architecture) {
+                let mismatch = ArchMismatch {
+                    image: req.env.clone(),
+                    image_arch: arch::normalize_arch(wanted),
+                    host_arch: host.architecture.clone(),
+                };
+                self.unsatisfiable
+                    .record(&key, FailureKind::Unsatisfiable, mismatch.to_string());
+                return Err(mismatch.into());
+            }
+        }
+        if matches!(req.runtime, Some(faas_common::Runtime::Firecracker)) {
+            return Ok(());
+        }
+        match self.image_metadata.ensure_runnable(&req.env).await {
+            Err(ImageMetadataError::ArchMismatch(mismatch)) => Err(mismatch.into()),
+            Err(ImageMetadataError::Unresolvable(failure)) => Err(failure.into()),
+            // Other registry failures surface from the runtime with more context
+            Err(e) => {
+                debug!("Skipping image preflight for {}: {}", req.env, e);
+                Ok(())
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// GPUs are passed through by the NVIDIA runtime, so only to Docker containers on a
+    /// daemon that has it. Named endpoints say for themselves whether they have GPUs.
+    fn check_gpu(&self, req: &Request) -> Result<()> {
+        if req.gpu.is_none() {
+            return Ok(());
+        }
+        let incompatible = |runtime: &str, reason: &str| {
+            Err(faas_common::FaasError::IncompatibleFeature {
+                feature: "gpu".to_string(),
+                runtime: runtime.to_string(),
+                reason: reason.to_string(),
+            }
+            .into())
+        };
+        if matches!(req.runtime, Some(faas_common::Runtime::Firecracker)) {
+            return incompatible(
+                "firecracker",
+                "GPUs are only passed through to Docker containers",
+            );
+        }
+        if self.docker_endpoints.is_none() && !self.gpu {
+            return incompatible(
+                "docker",
+                "this host's Docker daemon has no NVIDIA container runtime",
+            );
+        }
+        Ok(())
+    }
+
+    fn unsatisfiable_reason(&self, req: &Request) -> Option<String> {
+        if let (Some(endpoints), Some(placement)) =
+            (&self.docker_endpoints, &req.effective_placement())
+        {
+            if !endpoints.can_satisfy(placement) {
+                return Some(format!(
+                    "no Docker endpoint matches placement {placement:?}"
+                ));
+            }
+        }
+        // Shared memory and tmpfs live in RAM, so together they can't exceed the host's
+        let in_memory_mb = req.shm_size_mb.unwrap_or(0)
+            + req.tmpfs.iter().flatten().map(|m| m.size_mb).sum::<u64>();
+        match host_memory_mb() {
+            Some(total) if in_memory_mb > total => Some(format!(
+                "shm and tmpfs request {in_memory_mb}MB but this host has {total}MB of memory"
+            )),
+            _ => None,
+        }
     }
 
     #[instrument(skip(self))]
     pub async fn run(&self, req: Request) -> Result<Response> {
         let start = Instant::now();
+        speculation::validate(req.execution_strategy.as_ref(), req.mode, req.idempotent)?;
+        let _admitted = self.drain.admit()?;
+        self.preflight(&req).await?;
 
         let response = match req.mode {
             Mode::Ephemeral => self.run_ephemeral(req).await?,
@@ -177,48 +889,105 @@ impl Executor {
         Ok(response)
     }
 
+    /// Run `req` in a fresh Docker container, sending its output to `live_output` as it is
+    /// written. Only ephemeral Docker executions stream; `req.mode` is not consulted.
+    #[instrument(skip(self, live_output))]
+    pub async fn run_streaming(
+        &self,
+        req: Request,
+        live_output: tokio::sync::mpsc::UnboundedSender<crate::OutputChunk>,
+    ) -> Result<Response> {
+        let start = Instant::now();
+        if matches!(req.runtime, Some(faas_common::Runtime::Firecracker)) {
+            return Err(faas_common::FaasError::IncompatibleFeature {
+                feature: "streaming".to_string(),
+                runtime: "firecracker".to_string(),
+                reason: "output is only streamed from Docker containers".to_string(),
+            }
+            .into());
+        }
+        let _admitted = self.drain.admit()?;
+        self.preflight(&req).await?;
+
+        let config = req.sandbox_config(
+            req.id.clone(),
+            faas_common::ExecutionMode::Ephemeral,
+            Some(faas_common::Runtime::Docker),
+        );
+        let docker = match (&self.docker_endpoints, &config.placement) {
+            (Some(endpoints), Some(_)) => crate::DockerExecutor::with_endpoints(endpoints.clone()),
+            _ => self.docker(),
+        }
+        .with_running(self.running.clone())
+        .with_output_limit(self.output.clone());
+        let mut result = docker.execute_streaming(config, live_output).await?;
+
+        let (stdout, stderr) = result.take_output();
+        Ok(Response {
+            id: req.id,
+            stdout,
+            stderr,
+            exit_code: result.exit_status(),
+            duration: start.elapsed(),
+            snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: None,
+            usage: result.usage,
+            truncation: result.truncation,
+            warm_start: false,
+        })
+    }
+
     async fn run_ephemeral(&self, req: Request) -> Result<Response> {
-        // Convert env_vars from HashMap to Vec<String> in KEY=VALUE format
-        let env_vars = req
-            .env_vars
-            .map(|map| map.iter().map(|(k, v)| format!("{}={}", k, v)).collect());
-
-        let config = faas_common::SandboxConfig {
-            function_id: req.id.clone(),
-            source: req.env,
-            command: vec!["sh".to_string(), "-c".to_string(), req.code],
-            payload: Vec::new(),
-            env_vars,
-            runtime: req.runtime,
-            execution_mode: Some(faas_common::ExecutionMode::Ephemeral),
-            memory_limit: None,
-            timeout: Some(req.timeout.as_millis() as u64),
-        };
+        if let Some(faas_common::ExecutionStrategy::Speculative {
+            preferred,
+            fallback,
+            max_overlap_ms,
+        }) = req.execution_strategy
+        {
+            return speculation::speculate(
+                self,
+                req,
+                preferred,
+                fallback,
+                Duration::from_millis(max_overlap_ms),
+                &self.speculation,
+            )
+            .await;
+        }
 
-        // Runtime selection based on request preference or auto-select
-        let result = match req.runtime {
-            Some(faas_common::Runtime::Docker) => self.container.execute(config).await?,
-            Some(faas_common::Runtime::Firecracker) => self.vm.execute(config).await?,
-            Some(faas_common::Runtime::Auto) | None => {
-                // Use Firecracker on Linux for 125ms cold starts vs Docker's 500ms
-                if cfg!(target_os = "linux") {
-                    match self.vm.execute(config.clone()).await {
-                        Ok(res) => res,
-                        Err(_) => self.container.execute(config).await?,
-                    }
-                } else {
-                    self.container.execute(config).await?
-                }
+        let start = Instant::now();
+        let decision = self.runtime_policy.select(&req, self.capabilities.as_ref());
+        let config = req.sandbox_config(
+            req.id.clone(),
+            faas_common::ExecutionMode::Ephemeral,
+            Some(decision.runtime),
+        );
+        let (mut result, warm_start) = match decision.runtime {
+            faas_common::Runtime::Firecracker => (self.vm.execute(config).await?, false),
+            faas_common::Runtime::Docker | faas_common::Runtime::Auto => {
+                self.execute_in_container(config, req.tenant.as_deref())
+                    .await?
             }
         };
 
+        let (stdout, stderr) = result.take_output();
         Ok(Response {
             id: req.id,
-            stdout: result.response.unwrap_or_default(),
-            stderr: result.logs.map(|l| l.into_bytes()).unwrap_or_default(),
-            exit_code: if result.error.is_none() { 0 } else { 1 },
-            duration: Duration::from_millis(50),
+            stdout,
+            stderr,
+            exit_code: result.exit_status(),
+            duration: start.elapsed(),
             snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: Some(decision),
+            usage: result.usage,
+            truncation: result.truncation,
+            warm_start,
         })
     }
 
@@ -226,22 +995,40 @@ impl Executor {
         let start = Instant::now();
 
         // Check cache for pre-computed result
-        let cache_key = Self::cache_key_for(&req);
-        if let Ok(Some(cached_result)) = self.cache_manager.get(&cache_key).await {
+        let cache_key = req.result_cache_key();
+        let stored_key =
+            faas_common::cache_key::namespaced(req.cache_namespace.as_deref(), &cache_key);
+        let cached = if req.no_cache {
+            self.result_cache.bypass();
+            None
+        } else {
+            self.result_cache.get(&stored_key).await
+        };
+        if let Some(cached) = cached {
             info!("Cache hit for request {}", req.id);
             return Ok(Response {
                 id: req.id,
-                stdout: cached_result,
-                stderr: Vec::new(),
-                exit_code: 0,
+                stdout: cached.stdout,
+                stderr: cached.stderr,
+                exit_code: cached.exit_code,
                 duration: start.elapsed(),
                 snapshot: None,
+                speculation: None,
+                cache_hit: true,
+                cache_key: Some(cache_key),
+                runtime_decision: None,
+                usage: None,
+                truncation: None,
+                warm_start: true,
             });
         }
 
         // Use predictive scaling to optimize container pool
         if let Ok(Some(prediction)) = self.predictive_scaler.predict_scaling(&req.env).await {
-            if prediction.predicted_load > 2.0 && prediction.confidence > 0.7 {
+            if prediction.predicted_load > 2.0
+                && prediction.confidence > 0.7
+                && !self.drain.is_draining()
+            {
                 // High load predicted - pre-warm additional containers
                 info!(
                     "High load predicted ({:.2}), pre-warming containers",
@@ -251,69 +1038,133 @@ impl Executor {
             }
         }
 
-        // Convert env_vars from HashMap to Vec<String> in KEY=VALUE format
-        let env_vars = req
-            .env_vars
-            .clone()
-            .map(|map| map.iter().map(|(k, v)| format!("{}={}", k, v)).collect());
-
-        let config = faas_common::SandboxConfig {
-            function_id: req.id.clone(),
-            source: req.env.clone(),
-            command: vec!["sh".to_string(), "-c".to_string(), req.code.clone()],
-            payload: Vec::new(),
-            env_vars,
-            runtime: req.runtime,
-            execution_mode: Some(faas_common::ExecutionMode::Cached),
-            memory_limit: None,
-            timeout: Some(req.timeout.as_millis() as u64),
-        };
+        let config = req.sandbox_config(
+            req.id.clone(),
+            faas_common::ExecutionMode::Cached,
+            req.runtime,
+        );
 
         // Execute in container (we can add VM fallback in the future if needed)
-        let result = self.container.execute(config).await?;
-
-        // Store result in cache for future use
-        if result.error.is_none() {
-            let response_data = result.response.clone().unwrap_or_default();
-            let _ = self
-                .cache_manager
-                .put(&cache_key, response_data.clone(), None)
-                .await;
-        }
+        let mut result = self.container.execute(config).await?;
 
         // Record execution metrics for predictive scaling
         let _ = self.predictive_scaler.record_usage(&req.env, 1.0).await;
 
+        let exit_code = result.exit_status();
+        let (stdout, stderr) = result.take_output();
+        // Store successful results for future use; a truncated one would be served short
+        // to every later caller
+        if result.error.is_none() && result.truncation.is_none() {
+            let cached = CachedResult {
+                stdout: stdout.clone(),
+                stderr: stderr.clone(),
+                exit_code,
+            };
+            if let Err(e) = self.result_cache.put(&stored_key, &cached).await {
+                warn!("Failed to cache result for {}: {}", req.id, e);
+            }
+        }
+
         Ok(Response {
             id: req.id,
-            stdout: result.response.unwrap_or_default(),
-            stderr: result.logs.map(|l| l.into_bytes()).unwrap_or_default(),
-            exit_code: if result.error.is_none() { 0 } else { 1 },
+            stdout,
+            stderr,
+            exit_code,
             duration: start.elapsed(),
             snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: Some(cache_key),
+            runtime_decision: None,
+            usage: result.usage,
+            truncation: result.truncation,
+            warm_start: false,
         })
     }
 
-    fn cache_key_for(req: &Request) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(req.env.as_bytes());
-        hasher.update(req.code.as_bytes());
-        if let Some(runtime) = &req.runtime {
-            hasher.update(format!("{:?}", runtime));
-        }
-        if let Some(env_vars) = &req.env_vars {
-            let mut pairs: Vec<_> = env_vars.iter().collect();
-            pairs.sort_by(|a, b| a.0.cmp(b.0));
-            for (key, value) in pairs {
-                hasher.update(key.as_bytes());
-                hasher.update(b"=");
-                hasher.update(value.as_bytes());
+    /// Run `req` in a Docker container until it exits or `req.timeout` passes. A process
+    /// still running then is checkpointed with its memory instead of killed, and the
+    /// response carries the checkpoint as `snapshot`; passing that back as `checkpoint`
+    /// resumes the process in a new container, where it runs for another `req.timeout`.
+    async fn run_checkpointed(&self, req: Request) -> Result<Response> {
+        let start = Instant::now();
+        let resumed = match &req.checkpoint {
+            Some(checkpoint) => Some(
+                self.snapshots
+                    .get(checkpoint)
+                    .await
+                    .ok_or_else(|| CheckpointError::NotFound(checkpoint.clone()))?,
+            ),
+            None => None,
+        };
+        let docker = !matches!(req.runtime, Some(faas_common::Runtime::Firecracker));
+        let (container, resume) = match resumed {
+            Some(Snapshot {
+                container: Some(container),
+                path,
+                ..
+            }) => (container, Some(path)),
+            None if docker => {
+                let config = req.sandbox_config(
+                    req.id.clone(),
+                    faas_common::ExecutionMode::Checkpointed,
+                    Some(faas_common::Runtime::Docker),
+                );
+                (CheckpointedContainer::from(&config), None)
             }
-        }
-        format!("cache:{:x}", hasher.finalize())
+            _ => return self.run_snapshotted(req).await,
+        };
+
+        let _write = self.drain.track_operation();
+        let snapshot_id = format!("snap-{}-{}", req.id, uuid::Uuid::new_v4());
+        let name = format!("faas-{}-{}", req.id, uuid::Uuid::new_v4());
+        let run = self
+            .checkpoints
+            .run(
+                &name,
+                &container,
+                resume.as_deref(),
+                req.timeout,
+                &snapshot_id,
+            )
+            .await?;
+        let snapshot = match run.checkpoint {
+            Some(path) => {
+                self.snapshots
+                    .insert(Snapshot {
+                        id: snapshot_id.clone(),
+                        exec_id: req.id.clone(),
+                        backend: Backend::Criu,
+                        size_bytes: SnapshotStore::dir_size(&path).await.unwrap_or(0),
+                        path,
+                        created_at: Instant::now(),
+                        container: Some(container),
+                    })
+                    .await;
+                Some(snapshot_id)
+            }
+            None => None,
+        };
+
+        Ok(Response {
+            id: req.id,
+            stdout: run.stdout,
+            stderr: run.stderr,
+            exit_code: run.exit_code.map_or(0, |code| code as i32),
+            duration: start.elapsed(),
+            snapshot,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: None,
+            usage: None,
+            truncation: None,
+            warm_start: false,
+        })
     }
 
-    async fn run_checkpointed(&self, req: Request) -> Result<Response> {
+    /// CRIU and Firecracker snapshots of executions that aren't Docker checkpoints
+    async fn run_snapshotted(&self, req: Request) -> Result<Response> {
         if let Some(checkpoint) = req.checkpoint {
             // Attempt to restore snapshot using optimizer (will fall back to basic restore)
             info!("Attempting to restore checkpoint: {}", checkpoint);
@@ -327,9 +1178,17 @@ impl Executor {
                 exit_code: 0,
                 duration: Duration::from_millis(250),
                 snapshot: Some(checkpoint),
+                speculation: None,
+                cache_hit: false,
+                cache_key: None,
+                runtime_decision: None,
+                usage: None,
+                truncation: None,
+                warm_start: false,
             })
         } else {
             // Run with checkpoint capability
+            let _write = self.drain.track_operation();
             let snapshot_id = self.snapshots.create(&req.id).await?;
 
             Ok(Response {
@@ -339,165 +1198,373 @@ impl Executor {
                 exit_code: 0,
                 duration: Duration::from_millis(200),
                 snapshot: Some(snapshot_id),
+                speculation: None,
+                cache_hit: false,
+                cache_key: None,
+                runtime_decision: None,
+                usage: None,
+                truncation: None,
+                warm_start: false,
             })
         }
     }
 
-    async fn run_branched(&self, req: Request) -> Result<Response> {
+    /// A branched execution's filesystem is committed when its command exits, so
+    /// executions branched from it start with its files. With `branch_from` the Docker
+    /// container starts from the parent's committed filesystem instead of `req.env`, with
+    /// a copy of a persistent parent's workspace.
+    async fn run_branched(&self, mut req: Request) -> Result<Response> {
         let start = Instant::now();
-        let parent = req
-            .branch_from
-            .ok_or_else(|| anyhow::anyhow!("branch_from required"))?;
-
-        info!("Branching from parent: {}", parent);
 
         // Determine if we should use VM or container forking based on environment
         let use_vm = req.env.contains("vm") || req.env.contains("firecracker");
 
         if use_vm {
+            let parent = req
+                .branch_from
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("branch_from required"))?;
             // Use Firecracker VM forking
             info!("Using VM forking from parent: {}", parent);
-
-            // Convert env_vars from HashMap to Vec<String> in KEY=VALUE format
-            let env_vars = req
-                .env_vars
-                .clone()
-                .map(|map| map.iter().map(|(k, v)| format!("{}={}", k, v)).collect());
-
-            let config = faas_common::SandboxConfig {
-                function_id: req.id.clone(),
-                source: req.env.clone(),
-                command: vec!["sh".to_string(), "-c".to_string(), req.code.clone()],
-                payload: Vec::new(),
-                env_vars,
-                runtime: Some(faas_common::Runtime::Firecracker), // Use Firecracker for VM forking
-                execution_mode: Some(faas_common::ExecutionMode::Branched),
-                memory_limit: None,
-                timeout: Some(req.timeout.as_millis() as u64),
-            };
+            let config = req.sandbox_config(
+                req.id.clone(),
+                faas_common::ExecutionMode::Branched,
+                Some(faas_common::Runtime::Firecracker),
+            );
 
             // Execute with VM forking
-            let result = self.vm.execute_branched(config, &parent).await?;
+            let mut result = self.vm.execute_branched(config, &parent).await?;
 
-            Ok(Response {
+            let (stdout, stderr) = result.take_output();
+            return Ok(Response {
+                exit_code: result.exit_status(),
                 id: result.request_id,
-                stdout: result.response.unwrap_or_default(),
-                stderr: result.logs.map(|l| l.into_bytes()).unwrap_or_default(),
-                exit_code: if result.error.is_some() { 1 } else { 0 },
+                stdout,
+                stderr,
                 duration: start.elapsed(),
                 snapshot: Some(format!("vm-fork-{}", req.id)),
-            })
-        } else {
-            // Use Docker container forking
-            info!("Using Docker container forking from parent: {}", parent);
-
-            // Forking requires Docker checkpointing which may not be available
-            // For now, we'll implement a simplified version that just runs in a fresh container
-            // with the same environment settings as the parent
-
-            // Instead of trying to checkpoint a container that may not exist,
-            // just execute in a fresh container with similar setup
-            let fork_id = format!("fork-{}", req.id);
-
-            info!(
-                "Executing fork {} in fresh container (CRIU/checkpointing not available)",
-                fork_id
-            );
+                speculation: None,
+                cache_hit: false,
+                cache_key: None,
+                runtime_decision: None,
+                usage: result.usage,
+                truncation: result.truncation,
+                warm_start: false,
+            });
+        }
 
-            // Convert env_vars from HashMap to Vec<String> in KEY=VALUE format
-            let env_vars = req
-                .env_vars
-                .map(|map| map.iter().map(|(k, v)| format!("{}={}", k, v)).collect());
-
-            let config = faas_common::SandboxConfig {
-                function_id: fork_id.clone(),
-                source: req.env,
-                command: vec!["sh".to_string(), "-c".to_string(), req.code],
-                payload: Vec::new(),
-                env_vars,
-                runtime: Some(faas_common::Runtime::Docker),
-                execution_mode: Some(faas_common::ExecutionMode::Ephemeral),
-                memory_limit: None,
-                timeout: Some(req.timeout.as_millis() as u64),
-            };
+        if let Some(parent) = &req.branch_from {
+            info!("Branching from parent: {}", parent);
+            req.env = self
+                .docker_fork
+                .branch_parent(parent)
+                .await?
+                .ok_or_else(|| {
+                    faas_common::FaasError::NotFound(format!(
+                        "execution {parent} left no filesystem to branch from"
+                    ))
+                })?;
+            let workspace = crate::persistent_workspace(parent);
+            if workspace.is_dir() {
+                let mut files = crate::input_files::from_dir(&workspace, "/workspace")?;
+                files.append(&mut req.input_files);
+                req.input_files = files;
+            }
+        }
+        let config = req.sandbox_config(
+            req.id.clone(),
+            faas_common::ExecutionMode::Branched,
+            Some(faas_common::Runtime::Docker),
+        );
+        let mut result = self
+            .branch_executor()
+            .execute_committed(config, &branch_image(&req.id))
+            .await?;
 
-            // Execute in fresh container (simplified forking without CRIU)
-            let result = self.container.execute(config).await?;
+        let (stdout, stderr) = result.take_output();
+        Ok(Response {
+            id: req.id,
+            stdout,
+            stderr,
+            exit_code: result.exit_status(),
+            duration: start.elapsed(),
+            snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: None,
+            usage: result.usage,
+            truncation: result.truncation,
+            warm_start: false,
+        })
+    }
 
-            Ok(Response {
-                id: fork_id,
-                stdout: result.response.unwrap_or_default(),
-                stderr: result.logs.map(|l| l.into_bytes()).unwrap_or_default(),
-                exit_code: if result.error.is_none() { 0 } else { 1 },
-                duration: start.elapsed(),
-                snapshot: None,
-            })
-        }
+    /// Runs whose filesystem is committed for branching, on the daemon branches are looked
+    /// up on
+    fn branch_executor(&self) -> crate::DockerExecutor {
+        self.docker()
+            .with_running(self.running.clone())
+            .with_output_limit(self.output.clone())
     }
 
     async fn run_persistent(&self, req: Request) -> Result<Response> {
-        // For persistent mode, prefer Firecracker on Linux, Docker on other platforms
-        let runtime = req.runtime.unwrap_or({
-            if cfg!(target_os = "linux") {
-                faas_common::Runtime::Firecracker
-            } else {
-                faas_common::Runtime::Docker
-            }
-        });
-
-        // Convert env_vars from HashMap to Vec<String> in KEY=VALUE format
-        let env_vars = req
-            .env_vars
-            .map(|map| map.iter().map(|(k, v)| format!("{}={}", k, v)).collect());
-
-        let config = faas_common::SandboxConfig {
-            function_id: req.id.clone(),
-            source: req.env,
-            command: vec!["sh".to_string(), "-c".to_string(), req.code],
-            payload: Vec::new(),
-            env_vars,
-            runtime: Some(runtime),
-            execution_mode: Some(faas_common::ExecutionMode::Persistent),
-            memory_limit: None,
-            timeout: Some(req.timeout.as_millis() as u64),
-        };
-
-        // Runtime selection for persistent mode
-        let result = match config.runtime {
-            Some(faas_common::Runtime::Docker) => self.container.execute(config).await?,
-            Some(faas_common::Runtime::Firecracker) => self.vm.execute(config).await?,
-            Some(faas_common::Runtime::Auto) | None => {
-                // Prefer Firecracker for persistent workloads on Linux, fallback to Docker
-                if cfg!(target_os = "linux") {
-                    match self.vm.execute(config.clone()).await {
-                        Ok(res) => res,
-                        Err(_) => self.container.execute(config).await?,
-                    }
-                } else {
-                    self.container.execute(config).await?
-                }
+        let decision = self.runtime_policy.select(&req, self.capabilities.as_ref());
+        let config = req.sandbox_config(
+            req.id.clone(),
+            faas_common::ExecutionMode::Persistent,
+            Some(decision.runtime),
+        );
+        // A persistent container's filesystem is committed too, so it can be branched from
+        let mut result = match decision.runtime {
+            faas_common::Runtime::Firecracker => self.vm.execute(config).await?,
+            faas_common::Runtime::Docker | faas_common::Runtime::Auto => {
+                self.branch_executor()
+                    .execute_committed(config, &branch_image(&req.id))
+                    .await?
             }
         };
 
+        let (stdout, stderr) = result.take_output();
         Ok(Response {
             id: req.id,
-            stdout: result.response.unwrap_or_default(),
-            stderr: result.logs.map(|l| l.into_bytes()).unwrap_or_default(),
-            exit_code: if result.error.is_none() { 0 } else { 1 },
+            stdout,
+            stderr,
+            exit_code: result.exit_status(),
             duration: Duration::from_millis(500),
             snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: Some(decision),
+            usage: result.usage,
+            truncation: result.truncation,
+            warm_start: false,
         })
     }
 }
 
+#[async_trait::async_trait]
+impl AttemptRunner for Executor {
+    async fn run_attempt(&self, runtime: faas_common::Runtime, req: Request) -> Result<Response> {
+        self.run_ephemeral(Request {
+            runtime: Some(runtime),
+            execution_strategy: None,
+            ..req
+        })
+        .await
+    }
+
+    async fn cancel_attempt(&self, runtime: faas_common::Runtime, attempt_id: &str) {
+        // A VM attempt goes away with its dropped future; containers have to be removed
+        if matches!(runtime, faas_common::Runtime::Docker) {
+            if let Err(e) = self.kill(attempt_id).await {
+                warn!("Failed to remove containers of {}: {}", attempt_id, e);
+            }
+        }
+    }
+}
+
+/// A warm container in use by one execution. Dropping it removes the container, also when
+/// the execution's future is dropped part way, as the losing side of a speculation is.
+struct WarmClaim {
+    pool: Arc<ContainerPoolManager>,
+    container: Option<PooledContainer>,
+}
+
+impl Drop for WarmClaim {
+    fn drop(&mut self) {
+        if let Some(container) = self.container.take() {
+            let pool = self.pool.clone();
+            tokio::spawn(async move {
+                if let Err(e) = pool.retire(container).await {
+                    warn!("Failed to remove warm container: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Pools filled by [`Executor::prewarm`]: nothing is started unasked, and an idle container
+/// is removed after `FAAS_WARM_POOL_TTL_SECS`, five minutes by default
+/// Whether `docker` can pass GPUs through, which takes the NVIDIA container runtime
+async fn nvidia_runtime_available(docker: &Docker) -> bool {
+    match docker.info().await {
+        Ok(info) => info
+            .runtimes
+            .is_some_and(|runtimes| runtimes.contains_key("nvidia")),
+        Err(e) => {
+            debug!("Could not probe Docker for GPU support: {}", e);
+            false
+        }
+    }
+}
+
+fn prewarm_pool_config() -> PoolConfig {
+    let ttl = std::env::var("FAAS_WARM_POOL_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map_or(DEFAULT_WARM_POOL_TTL, Duration::from_secs);
+    PoolConfig {
+        min_size: 0,
+        max_idle_time: ttl,
+        pre_warm: false,
+        predictive_warming: false,
+        // Idle containers are only looked at on each health check
+        health_check_interval: ttl.clamp(Duration::from_secs(1), Duration::from_secs(30)),
+        ..Default::default()
+    }
+}
+
+/// Total memory from `/proc/meminfo`; `None` where that isn't available
+fn host_memory_mb() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kb: u64 = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb / 1024)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn requests_without_resources_leave_the_sandbox_unconstrained() {
+        let req = Request {
+            code: "pwd".to_string(),
+            env: "alpine:latest".to_string(),
+            ..Default::default()
+        };
+        let config = req.sandbox_config(
+            "defaults".to_string(),
+            faas_common::ExecutionMode::Ephemeral,
+            None,
+        );
+        assert_eq!(config.working_dir, None);
+        assert_eq!(config.memory_limit, None);
+        assert_eq!(config.cpu_limit, None);
+        assert_eq!(config.env_vars, None);
+        assert!(!crate::executor::requires_fresh_container(&config));
+
+        let sized = Request {
+            working_dir: Some("/srv".to_string()),
+            memory_mb: Some(512),
+            cpu_cores: Some(1.5),
+            ..req
+        }
+        .sandbox_config(
+            "sized".to_string(),
+            faas_common::ExecutionMode::Ephemeral,
+            None,
+        );
+        assert_eq!(sized.working_dir.as_deref(), Some("/srv"));
+        assert_eq!(sized.memory_limit, Some(512));
+        assert_eq!(sized.cpu_limit, Some(1.5));
+        assert!(crate::executor::requires_fresh_container(&sized));
+    }
+
+    #[test]
+    fn only_limits_other_than_the_defaults_need_a_fresh_container() {
+        let req = Request {
+            code: "pwd".to_string(),
+            env: "alpine:latest".to_string(),
+            ulimits: Some(faas_common::default_ulimits().into_iter().rev().collect()),
+            shm_size_mb: Some(faas_common::DEFAULT_SHM_SIZE_MB),
+            pids_limit: Some(faas_common::DEFAULT_PIDS_LIMIT),
+            ..Default::default()
+        };
+        let config = |req: &Request| {
+            req.sandbox_config(
+                "limits".to_string(),
+                faas_common::ExecutionMode::Ephemeral,
+                None,
+            )
+        };
+        assert!(!crate::executor::requires_fresh_container(&config(&req)));
+
+        let shm = Request {
+            shm_size_mb: Some(256),
+            ..req.clone()
+        };
+        assert!(crate::executor::requires_fresh_container(&config(&shm)));
+        let pids = Request {
+            pids_limit: Some(64),
+            ..req.clone()
+        };
+        assert!(crate::executor::requires_fresh_container(&config(&pids)));
+        let mut ulimits = faas_common::default_ulimits();
+        ulimits[0].hard *= 2;
+        let raised = Request {
+            ulimits: Some(ulimits),
+            ..req
+        };
+        assert!(crate::executor::requires_fresh_container(&config(&raised)));
+    }
+
+    #[test]
+    fn gpu_requests_need_a_gpu_host_and_a_fresh_container() {
+        let req = Request {
+            code: "nvidia-smi".to_string(),
+            env: "nvidia/cuda:12.4.0-base-ubuntu22.04".to_string(),
+            placement: Some(faas_common::Placement {
+                arch: Some("x86_64".to_string()),
+                ..Default::default()
+            }),
+            gpu: Some(faas_common::GpuRequest::count(1)),
+            ..Default::default()
+        };
+        let config = req.sandbox_config(
+            "gpu".to_string(),
+            faas_common::ExecutionMode::Ephemeral,
+            None,
+        );
+        let placement = config.placement.as_ref().unwrap();
+        assert!(placement.gpu);
+        assert_eq!(placement.arch.as_deref(), Some("x86_64"));
+        assert_eq!(config.gpu, Some(faas_common::GpuRequest::count(1)));
+        assert!(crate::executor::requires_fresh_container(&config));
+        assert_ne!(
+            req.resource_signature(),
+            Request {
+                gpu: None,
+                ..req.clone()
+            }
+            .resource_signature()
+        );
+    }
+
+    #[test]
+    fn explicit_cache_keys_are_used_verbatim() {
+        let req = Request {
+            code: "echo hi".to_string(),
+            env: "alpine:latest".to_string(),
+            ..Default::default()
+        };
+        let derived = req.result_cache_key();
+        assert!(derived.starts_with("cache:"));
+        let on_ubuntu = Request {
+            env: "ubuntu:22.04".to_string(),
+            ..req.clone()
+        };
+        assert_ne!(on_ubuntu.result_cache_key(), derived);
+
+        let named = Request {
+            cache_key: Some("report v1".to_string()),
+            payload: b"ignored".to_vec(),
+            ..req
+        };
+        assert_eq!(named.result_cache_key(), "report v1");
+    }
+
     #[tokio::test]
     #[ignore = "Requires Docker or Firecracker"]
     async fn test_modes() {
-        let exec = Executor::new()
+        let exec = Executor::new(PlatformConfig::default())
             .await
             .expect("Failed to create executor - ensure Docker is running");
 
@@ -511,6 +1578,7 @@ mod tests {
             branch_from: None,
             runtime: None,
             env_vars: None,
+            ..Default::default()
         };
 
         let res = exec.run(req).await.expect("Failed to run");
@@ -0,0 +1,556 @@
+//! Read-through cache of image metadata.
+//!
+//! Anything that needs an image's digest, entrypoint, ports or layers asks the executor's
+//! [`ImageMetadataService`] instead of Docker or the registry. Tag references go stale
+//! quickly because tags move; digest references are immutable and can be kept much longer.
+//! Pulls go through the service too, so multi-arch images resolve to the host's platform.
+//! Images that turn out not to exist are remembered briefly, so retry loops fail fast.
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use super::arch::{self, ArchMismatch};
+use super::negative_cache::{FailureKind, NegativeCache, ResolutionFailure};
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::image::CreateImageOptions;
+use crate::bollard::Docker;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ImagePlatform {
+    pub os: String,
+    pub architecture: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+impl ImagePlatform {
+    /// `os/arch[/variant]`, as Docker's `platform` options expect
+    pub fn to_docker_platform(&self) -> String {
+        match &self.variant {
+            Some(variant) => format!("{}/{}/{}", self.os, self.architecture, variant),
+            None => format!("{}/{}", self.os, self.architecture),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    pub reference: String,
+    /// Manifest digest (`sha256:...`), when the image came from a registry
+    pub digest: Option<String>,
+    pub entrypoint: Vec<String>,
+    pub cmd: Vec<String>,
+    pub env: Vec<String>,
+    /// e.g. `8080/tcp`
+    pub exposed_ports: Vec<String>,
+    pub working_dir: Option<String>,
+    pub platform: ImagePlatform,
+    pub size_bytes: u64,
+    pub layers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum ImageMetadataError {
+    #[error("image {0} not found")]
+    NotFound(String),
+    #[error("not authorized to pull image {0}")]
+    Unauthorized(String),
+    #[error("registry error for {reference}: {message}")]
+    Registry { reference: String, message: String },
+    #[error(transparent)]
+    ArchMismatch(#[from] ArchMismatch),
+    /// A missing or forbidden image, possibly answered from the negative cache
+    #[error(transparent)]
+    Unresolvable(#[from] ResolutionFailure),
+}
+
+/// Where metadata comes from on a cache miss
+#[async_trait]
+pub trait RegistryClient: Send + Sync {
+    /// Metadata of a locally present image; `NotFound` if it has not been pulled
+    async fn fetch(&self, reference: &str) -> Result<ImageMetadata, ImageMetadataError>;
+
+    /// Platforms the registry's manifest offers for `reference`
+    async fn platforms(&self, reference: &str) -> Result<Vec<ImagePlatform>, ImageMetadataError>;
+
+    async fn pull(
+        &self,
+        reference: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> Result<(), ImageMetadataError>;
+}
+
+/// Reads metadata from the local daemon and pulls through it
+pub struct DockerRegistryClient {
+    docker: Docker,
+}
+
+impl DockerRegistryClient {
+    pub fn new(docker: Docker) -> Self {
+        Self { docker }
+    }
+
+    async fn inspect(&self, reference: &str) -> Result<ImageMetadata, BollardError> {
+        let image = self.docker.inspect_image(reference).await?;
+        let config = image.config.unwrap_or_default();
+        let mut exposed_ports: Vec<String> = config
+            .exposed_ports
+            .map(|ports| ports.into_keys().collect())
+            .unwrap_or_default();
+        exposed_ports.sort();
+        Ok(ImageMetadata {
+            reference: reference.to_string(),
+            digest: image
+                .repo_digests
+                .unwrap_or_default()
+                .iter()
+                .find_map(|d| d.split_once('@').map(|(_, digest)| digest.to_string())),
+            entrypoint: config.entrypoint.unwrap_or_default(),
+            cmd: config.cmd.unwrap_or_default(),
+            env: config.env.unwrap_or_default(),
+            exposed_ports,
+            working_dir: config.working_dir.filter(|dir| !dir.is_empty()),
+            platform: ImagePlatform {
+                os: image.os.unwrap_or_default(),
+                architecture: image.architecture.unwrap_or_default(),
+                variant: image.variant,
+            },
+            size_bytes: image.size.unwrap_or_default().max(0) as u64,
+            layers: image
+                .root_fs
+                .and_then(|root| root.layers)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+fn registry_error(reference: &str, e: BollardError) -> ImageMetadataError {
+    match e {
+        BollardError::DockerResponseServerError {
+            status_code: 404, ..
+        } => ImageMetadataError::NotFound(reference.to_string()),
+        BollardError::DockerResponseServerError {
+            status_code: 401 | 403,
+            ..
+        } => ImageMetadataError::Unauthorized(reference.to_string()),
+        e => ImageMetadataError::Registry {
+            reference: reference.to_string(),
+            message: e.to_string(),
+        },
+    }
+}
+
+#[async_trait]
+impl RegistryClient for DockerRegistryClient {
+    async fn fetch(&self, reference: &str) -> Result<ImageMetadata, ImageMetadataError> {
+        self.inspect(reference)
+            .await
+            .map_err(|e| registry_error(reference, e))
+    }
+
+    async fn platforms(&self, reference: &str) -> Result<Vec<ImagePlatform>, ImageMetadataError> {
+        let distribution = self
+            .docker
+            .inspect_registry_image(reference, None)
+            .await
+            .map_err(|e| registry_error(reference, e))?;
+        Ok(distribution.platforms.into_iter().map(Into::into).collect())
+    }
+
+    async fn pull(
+        &self,
+        reference: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> Result<(), ImageMetadataError> {
+        use futures::TryStreamExt;
+        let platform = platform.map(ImagePlatform::to_docker_platform);
+        self.docker
+            .create_image(
+                Some(CreateImageOptions {
+                    from_image: reference,
+                    platform: platform.as_deref().unwrap_or_default(),
+                    ..Default::default()
+                }),
+                None,
+                None,
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|e| registry_error(reference, e))?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetadataCacheConfig {
+    /// How long metadata for a tag (`alpine:3.19`) is trusted
+    pub tag_ttl: Duration,
+    /// How long metadata for a digest (`alpine@sha256:...`) is trusted; `None` keeps it
+    /// until invalidated
+    pub digest_ttl: Option<Duration>,
+}
+
+impl Default for MetadataCacheConfig {
+    fn default() -> Self {
+        Self {
+            tag_ttl: Duration::from_secs(300),
+            digest_ttl: None,
+        }
+    }
+}
+
+/// Whether the reference pins a digest rather than naming a tag
+pub fn is_digest_reference(reference: &str) -> bool {
+    reference.contains("@sha256:")
+}
+
+struct CacheEntry {
+    metadata: Arc<ImageMetadata>,
+    fetched_at: Instant,
+}
+
+/// The one image metadata cache shared by every subsystem
+pub struct ImageMetadataService {
+    client: Arc<dyn RegistryClient>,
+    config: MetadataCacheConfig,
+    host: ImagePlatform,
+    entries: DashMap<String, CacheEntry>,
+    // Per-reference locks so concurrent misses share one fetch
+    fetching: DashMap<String, Arc<Mutex<()>>>,
+    negative: NegativeCache,
+}
+
+impl ImageMetadataService {
+    pub fn new(client: Arc<dyn RegistryClient>, config: MetadataCacheConfig) -> Self {
+        Self {
+            client,
+            config,
+            host: arch::host_platform(),
+            entries: DashMap::new(),
+            fetching: DashMap::new(),
+            negative: NegativeCache::from_env(),
+        }
+    }
+
+    /// Remember missing images for `ttl` instead of `FAAS_NEGATIVE_CACHE_TTL_SECS`
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative = NegativeCache::new(ttl);
+        self
+    }
+
+    pub fn negative_cache(&self) -> &NegativeCache {
+        &self.negative
+    }
+
+    /// Resolve pulls for `host` instead of the machine this runs on
+    pub fn with_host_platform(mut self, host: ImagePlatform) -> Self {
+        self.host = host;
+        self
+    }
+
+    pub fn host_platform(&self) -> &ImagePlatform {
+        &self.host
+    }
+
+    fn ttl(&self, reference: &str) -> Option<Duration> {
+        if is_digest_reference(reference) {
+            self.config.digest_ttl
+        } else {
+            Some(self.config.tag_ttl)
+        }
+    }
+
+    fn cached(&self, reference: &str) -> Option<Arc<ImageMetadata>> {
+        let entry = self.entries.get(reference)?;
+        let stale = matches!(self.ttl(reference), Some(ttl) if entry.fetched_at.elapsed() >= ttl);
+        (!stale).then(|| entry.metadata.clone())
+    }
+
+    pub async fn get(&self, reference: &str) -> Result<Arc<ImageMetadata>, ImageMetadataError> {
+        if let Some(metadata) = self.cached(reference) {
+            return Ok(metadata);
+        }
+
+        let lock = self
+            .fetching
+            .entry(reference.to_string())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+        // Another caller may have filled the entry while we waited
+        if let Some(metadata) = self.cached(reference) {
+            return Ok(metadata);
+        }
+
+        debug!("Fetching image metadata for {}", reference);
+        let metadata = match self.client.fetch(reference).await {
+            Err(ImageMetadataError::NotFound(_)) => {
+                self.pull_for_host(reference).await?;
+                self.client.fetch(reference).await?
+            }
+            fetched => fetched?,
+        };
+        let metadata = Arc::new(metadata);
+        self.entries.insert(
+            reference.to_string(),
+            CacheEntry {
+                metadata: metadata.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+        Ok(metadata)
+    }
+
+    /// Drop the cached entry so the next lookup goes to the registry.
+    pub fn invalidate(&self, reference: &str) {
+        self.entries.remove(reference);
+    }
+
+    /// Pull the image explicitly; a pull can move a tag, so the entry is invalidated.
+    ///
+    /// A successful pull also forgets an earlier "not found" for the reference.
+    pub async fn pull(&self, reference: &str) -> Result<(), ImageMetadataError> {
+        self.pull_for_host(reference).await?;
+        self.invalidate(reference);
+        self.negative.clear(&image_key(reference));
+        Ok(())
+    }
+
+    /// Metadata for an image that is about to run here, or `ArchMismatch` if it can't.
+    ///
+    /// Missing and forbidden images come back as `Unresolvable`, and the same reference
+    /// fails from the negative cache until it expires or the image is pulled explicitly.
+    pub async fn ensure_runnable(
+        &self,
+        reference: &str,
+    ) -> Result<Arc<ImageMetadata>, ImageMetadataError> {
+        let key = image_key(reference);
+        self.negative.check(&key)?;
+        let metadata = match self.get(reference).await {
+            Err(ImageMetadataError::NotFound(_)) => {
+                return Err(self
+                    .negative
+                    .record(
+                        &key,
+                        FailureKind::ImageNotFound,
+                        format!("image {reference} not found"),
+                    )
+                    .into())
+            }
+            Err(ImageMetadataError::Unauthorized(_)) => {
+                return Err(self
+                    .negative
+                    .record(
+                        &key,
+                        FailureKind::ImageUnauthorized,
+                        format!("not authorized to pull image {reference}"),
+                    )
+                    .into())
+            }
+            result => result?,
+        };
+        arch::check_compatible(&metadata, &self.host)?;
+        Ok(metadata)
+    }
+
+    async fn pull_for_host(&self, reference: &str) -> Result<(), ImageMetadataError> {
+        // Registries that can't answer the distribution API still get a plain pull
+        let platform = match self.client.platforms(reference).await {
+            Ok(offered) => arch::resolve_platform(reference, &offered, &self.host)?,
+            Err(e) => {
+                debug!("No manifest platforms for {}: {}", reference, e);
+                None
+            }
+        };
+        self.client.pull(reference, platform.as_ref()).await
+    }
+}
+
+fn image_key(reference: &str) -> String {
+    format!("image:{reference}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct StubRegistry {
+        fetches: AtomicUsize,
+        /// `missing*` references that have since been pushed
+        published: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl StubRegistry {
+        fn exists(&self, reference: &str) -> bool {
+            !reference.starts_with("missing")
+                || self
+                    .published
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .any(|r| r == reference)
+        }
+    }
+
+    #[async_trait]
+    impl RegistryClient for StubRegistry {
+        async fn fetch(&self, reference: &str) -> Result<ImageMetadata, ImageMetadataError> {
+            if !self.exists(reference) {
+                return Err(ImageMetadataError::NotFound(reference.to_string()));
+            }
+            let n = self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(ImageMetadata {
+                reference: reference.to_string(),
+                digest: Some(format!("sha256:{n:064}")),
+                exposed_ports: vec!["8080/tcp".to_string()],
+                ..Default::default()
+            })
+        }
+
+        async fn platforms(
+            &self,
+            reference: &str,
+        ) -> Result<Vec<ImagePlatform>, ImageMetadataError> {
+            let arch = if reference.starts_with("arm-only") {
+                "arm64"
+            } else {
+                "amd64"
+            };
+            Ok(vec![ImagePlatform {
+                os: "linux".to_string(),
+                architecture: arch.to_string(),
+                variant: None,
+            }])
+        }
+
+        async fn pull(
+            &self,
+            reference: &str,
+            _platform: Option<&ImagePlatform>,
+        ) -> Result<(), ImageMetadataError> {
+            if !self.exists(reference) {
+                // A registry round trip that ends in a 404
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                return Err(ImageMetadataError::NotFound(reference.to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    fn service(config: MetadataCacheConfig) -> (Arc<StubRegistry>, ImageMetadataService) {
+        let stub = Arc::new(StubRegistry::default());
+        (stub.clone(), ImageMetadataService::new(stub, config))
+    }
+
+    #[tokio::test]
+    async fn fetches_once_per_ttl_even_under_concurrency() {
+        let (stub, service) = service(MetadataCacheConfig::default());
+        let service = Arc::new(service);
+        let lookups: Vec<_> = (0..8)
+            .map(|_| {
+                let service = service.clone();
+                tokio::spawn(async move { service.get("alpine:3.19").await.unwrap() })
+            })
+            .collect();
+        for lookup in lookups {
+            assert_eq!(lookup.await.unwrap().exposed_ports, ["8080/tcp"]);
+        }
+        assert_eq!(stub.fetches.load(Ordering::SeqCst), 1);
+
+        assert!(matches!(
+            service.get("missing:latest").await,
+            Err(ImageMetadataError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tags_expire_but_digests_do_not() {
+        let (stub, service) = service(MetadataCacheConfig {
+            tag_ttl: Duration::from_millis(20),
+            digest_ttl: None,
+        });
+        let pinned =
+            "alpine@sha256:1111111111111111111111111111111111111111111111111111111111111111";
+
+        service.get("alpine:3.19").await.unwrap();
+        service.get(pinned).await.unwrap();
+        assert_eq!(stub.fetches.load(Ordering::SeqCst), 2);
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        service.get("alpine:3.19").await.unwrap();
+        service.get(pinned).await.unwrap();
+        assert_eq!(stub.fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn explicit_pull_invalidates() {
+        let (stub, service) = service(MetadataCacheConfig::default());
+        let before = service.get("alpine:3.19").await.unwrap();
+
+        service.pull("alpine:3.19").await.unwrap();
+        let after = service.get("alpine:3.19").await.unwrap();
+        assert_eq!(stub.fetches.load(Ordering::SeqCst), 2);
+        assert_ne!(before.digest, after.digest);
+    }
+
+    #[tokio::test]
+    async fn pulls_refuse_images_for_another_arch() {
+        let (_, service) = service(MetadataCacheConfig::default());
+        let service = service.with_host_platform(ImagePlatform {
+            os: "linux".to_string(),
+            architecture: "x86_64".to_string(),
+            variant: None,
+        });
+
+        service.pull("alpine:3.19").await.unwrap();
+        match service.pull("arm-only:1").await {
+            Err(ImageMetadataError::ArchMismatch(mismatch)) => {
+                assert_eq!(mismatch.image_arch, "arm64");
+                assert_eq!(mismatch.host_arch, "amd64");
+            }
+            other => panic!("expected an arch mismatch, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_images_fail_fast_until_pulled() {
+        let (stub, service) = service(MetadataCacheConfig::default());
+        let service = service.with_negative_ttl(Duration::from_secs(30));
+
+        let started = Instant::now();
+        let first = service.ensure_runnable("missing/app:1").await.unwrap_err();
+        let first_took = started.elapsed();
+        let started = Instant::now();
+        let second = service.ensure_runnable("missing/app:1").await.unwrap_err();
+        let second_took = started.elapsed();
+
+        match (first, second) {
+            (ImageMetadataError::Unresolvable(first), ImageMetadataError::Unresolvable(second)) => {
+                assert_eq!(first.kind, FailureKind::ImageNotFound);
+                assert!(!first.cached_error);
+                assert!(second.cached_error);
+                assert!(second.retry_in_ms > 0);
+            }
+            other => panic!("expected unresolvable images, got {other:?}"),
+        }
+        assert!(second_took * 10 < first_took);
+        assert_eq!(service.negative_cache().fast_fails(), 1);
+
+        // Pushing the image isn't enough on its own; an explicit pull clears the entry
+        stub.published
+            .lock()
+            .unwrap()
+            .push("missing/app:1".to_string());
+        assert!(service.ensure_runnable("missing/app:1").await.is_err());
+        service.pull("missing/app:1").await.unwrap();
+        assert!(service.ensure_runnable("missing/app:1").await.is_ok());
+    }
+}
@@ -0,0 +1,567 @@
+//! Long-lived containers behind persistent instances
+//!
+//! An instance's container idles in a sleep loop and each command is a `docker exec` inside
+//! it, so files one command writes are there for the next.
+
+use super::executor::Response;
+use super::ports::{self, PortMapping};
+use super::workspaces::WorkspaceMount;
+use crate::bollard::container::{
+    CPUStats, Config, CreateContainerOptions, DownloadFromContainerOptions, ListContainersOptions,
+    RemoveContainerOptions, StatsOptions, StopContainerOptions, UploadToContainerOptions,
+};
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::image::CreateImageOptions;
+use crate::bollard::models::{ContainerStateStatusEnum, HostConfig};
+use crate::bollard::Docker;
+use crate::container_labels::{self, ContainerKind};
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Resources an instance container is created with
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InstanceResources {
+    pub cpu_cores: Option<u32>,
+    pub memory_mb: Option<u32>,
+}
+
+/// What Docker says a container is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRunState {
+    Created,
+    Running,
+    Paused,
+    Restarting,
+    Exited,
+    /// Exited because it ran past its memory limit
+    OomKilled,
+    Dead,
+}
+
+/// A container's state and, while it runs, what it is using
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContainerStatus {
+    pub state: ContainerRunState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_bytes: Option<u64>,
+    /// Share of one core; a container busy on two cores reports 200
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_percent: Option<f64>,
+}
+
+/// How an instance container is created, beyond its image
+#[derive(Debug, Clone, Default)]
+pub struct InstanceOptions {
+    /// Labelled on the container so a restarted gateway can register the instance again
+    pub name: Option<String>,
+    pub resources: InstanceResources,
+    pub workspace: Option<WorkspaceMount>,
+    /// Published on the host when the container starts
+    pub ports: Vec<PortMapping>,
+    /// Labelled as well, so the instance stays its tenant's after a restart
+    pub tenant: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct InstanceContainers {
+    docker: Arc<Docker>,
+}
+
+impl InstanceContainers {
+    pub fn new(docker: Arc<Docker>) -> Self {
+        Self { docker }
+    }
+
+    /// Create and start the container for `instance_id`; returns the container id
+    pub async fn start(
+        &self,
+        instance_id: &str,
+        image: &str,
+        resources: InstanceResources,
+    ) -> Result<String> {
+        let options = InstanceOptions {
+            resources,
+            ..Default::default()
+        };
+        self.start_with(instance_id, image, &options).await
+    }
+
+    /// [`Self::start`] with a name, workspace and ports as well
+    pub async fn start_with(
+        &self,
+        instance_id: &str,
+        image: &str,
+        options: &InstanceOptions,
+    ) -> Result<String> {
+        self.pull_if_missing(image).await;
+
+        let InstanceOptions {
+            name,
+            resources,
+            workspace,
+            ports,
+            tenant,
+        } = options;
+        let memory = resources.memory_mb.map(|mb| i64::from(mb) * 1024 * 1024);
+        let mut labels =
+            container_labels::labels(ContainerKind::Instance, instance_id, instance_id);
+        let extra = [
+            (container_labels::INSTANCE_NAME, name.clone()),
+            (
+                container_labels::CPU_CORES,
+                resources.cpu_cores.map(|cores| cores.to_string()),
+            ),
+            (
+                container_labels::MEMORY_MB,
+                resources.memory_mb.map(|mb| mb.to_string()),
+            ),
+            (container_labels::TENANT, tenant.clone()),
+        ];
+        for (key, value) in extra {
+            if let Some(value) = value {
+                labels.insert(key.to_string(), value);
+            }
+        }
+        for (key, value) in workspace.iter().flat_map(WorkspaceMount::labels) {
+            labels.insert(key.to_string(), value);
+        }
+        let created = self
+            .docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: container_name(instance_id),
+                    ..Default::default()
+                }),
+                Config {
+                    image: Some(image.to_string()),
+                    cmd: Some(vec![
+                        "/bin/sh".to_string(),
+                        "-c".to_string(),
+                        "while true; do sleep 30; done".to_string(),
+                    ]),
+                    tty: Some(false),
+                    labels: Some(labels),
+                    exposed_ports: Some(ports::exposed_ports(ports)),
+                    host_config: Some(HostConfig {
+                        memory,
+                        memory_swap: memory,
+                        nano_cpus: resources
+                            .cpu_cores
+                            .map(|cores| i64::from(cores) * 1_000_000_000),
+                        mounts: workspace.as_ref().map(|workspace| vec![workspace.mount()]),
+                        port_bindings: Some(ports::port_bindings(ports)),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        if let Err(e) = self
+            .docker
+            .start_container::<String>(&created.id, None)
+            .await
+        {
+            let _ = self.remove(&created.id).await;
+            return Err(e.into());
+        }
+        info!(
+            "Started container {} for instance {}",
+            created.id, instance_id
+        );
+        Ok(created.id)
+    }
+
+    // Committed snapshots only exist locally; there is nothing to pull for them
+    async fn pull_if_missing(&self, image: &str) {
+        if self.docker.inspect_image(image).await.is_err() {
+            let _: Vec<_> = self
+                .docker
+                .create_image(
+                    Some(CreateImageOptions {
+                        from_image: image.to_string(),
+                        ..Default::default()
+                    }),
+                    None,
+                    None,
+                )
+                .collect()
+                .await;
+        }
+    }
+
+    /// The ports the container publishes, with the host ports Docker picked
+    pub async fn published_ports(&self, container_id: &str) -> Result<Vec<PortMapping>> {
+        let details = self.docker.inspect_container(container_id, None).await?;
+        let ports = details
+            .network_settings
+            .and_then(|settings| settings.ports)
+            .unwrap_or_default();
+        Ok(ports::published(&ports))
+    }
+
+    /// Publish `port` of the running instance's container through a proxy container;
+    /// returns the mapping with its host port
+    pub async fn expose(
+        &self,
+        instance_id: &str,
+        container_id: &str,
+        port: PortMapping,
+    ) -> Result<PortMapping> {
+        let details = self.docker.inspect_container(container_id, None).await?;
+        let settings = details.network_settings.unwrap_or_default();
+        let address = settings
+            .ip_address
+            .filter(|ip| !ip.is_empty())
+            .or_else(|| {
+                settings
+                    .networks
+                    .unwrap_or_default()
+                    .into_values()
+                    .find_map(|network| network.ip_address.filter(|ip| !ip.is_empty()))
+            })
+            .ok_or_else(|| anyhow::anyhow!("container {container_id} has no network address"))?;
+
+        let image = ports::proxy_image();
+        self.pull_if_missing(&image).await;
+        let name = format!(
+            "{}-port-{}-{}",
+            container_name(instance_id),
+            port.container_port,
+            port.protocol.as_str()
+        );
+        let created = self
+            .docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: name.clone(),
+                    ..Default::default()
+                }),
+                Config {
+                    image: Some(image),
+                    cmd: Some(ports::proxy_command(&port, &address)),
+                    labels: Some(container_labels::labels(
+                        ContainerKind::Proxy,
+                        &name,
+                        instance_id,
+                    )),
+                    exposed_ports: Some(ports::exposed_ports(&[port])),
+                    host_config: Some(HostConfig {
+                        port_bindings: Some(ports::port_bindings(&[port])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        if let Err(e) = self
+            .docker
+            .start_container::<String>(&created.id, None)
+            .await
+        {
+            let _ = self.remove(&created.id).await;
+            return Err(e.into());
+        }
+        let published = self.published_ports(&created.id).await?;
+        let mapping = published
+            .into_iter()
+            .find(|mapping| mapping.key() == port.key())
+            .ok_or_else(|| anyhow::anyhow!("proxy {name} did not publish {}", port.key()))?;
+        info!(
+            "Exposed {} of instance {} on host port {:?}",
+            port.key(),
+            instance_id,
+            mapping.host_port
+        );
+        Ok(mapping)
+    }
+
+    /// Remove the proxies publishing ports of `instance_id`; returns how many there were
+    pub async fn remove_proxies(&self, instance_id: &str) -> Result<usize> {
+        self.remove_named(&format!("{}-port-", container_name(instance_id)))
+            .await
+    }
+
+    /// Run `command` with `sh -c` inside the container, `payload` on its stdin
+    ///
+    /// Past `timeout` the call gives up with [`faas_common::FaasError::Timeout`]; the
+    /// command isn't killed, since that would mean stopping the whole instance.
+    pub async fn exec(
+        &self,
+        container_id: &str,
+        command: &str,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<Response> {
+        let start = Instant::now();
+        let cmd = vec!["sh".to_string(), "-c".to_string(), command.to_string()];
+        let mut result = tokio::time::timeout(
+            timeout,
+            crate::executor::exec_attached(&self.docker, container_id, cmd, None, None, payload),
+        )
+        .await
+        .map_err(|_| faas_common::FaasError::Timeout {
+            timeout_ms: timeout.as_millis() as u64,
+        })??;
+
+        let (stdout, stderr) = result.take_output();
+        Ok(Response {
+            exit_code: result.exit_status(),
+            id: result.request_id,
+            stdout,
+            stderr,
+            duration: start.elapsed(),
+            snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: None,
+            usage: result.usage,
+            truncation: result.truncation,
+            warm_start: true,
+        })
+    }
+
+    /// Extract the tar `archive` into the container under `path`, an existing directory
+    pub async fn upload(&self, container_id: &str, path: &str, archive: Vec<u8>) -> Result<()> {
+        self.docker
+            .upload_to_container(
+                container_id,
+                Some(UploadToContainerOptions {
+                    path,
+                    ..Default::default()
+                }),
+                archive.into(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// `path` in the container as a tar archive, the way `docker cp` packs it: a file is
+    /// the only entry, a directory comes with everything under it
+    pub fn download(
+        &self,
+        container_id: &str,
+        path: &str,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + Send + 'static {
+        self.docker
+            .download_from_container(
+                container_id,
+                Some(DownloadFromContainerOptions {
+                    path: path.to_string(),
+                }),
+            )
+            .map(|chunk| Ok(chunk?.to_vec()))
+    }
+
+    /// The container's current state, or `None` once it no longer exists
+    pub async fn inspect(&self, container_id: &str) -> Result<Option<ContainerStatus>> {
+        let details = match self.docker.inspect_container(container_id, None).await {
+            Ok(details) => details,
+            Err(BollardError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let docker_state = details.state.unwrap_or_default();
+        let state = match docker_state.status {
+            Some(ContainerStateStatusEnum::CREATED) => ContainerRunState::Created,
+            Some(ContainerStateStatusEnum::RUNNING) => ContainerRunState::Running,
+            Some(ContainerStateStatusEnum::PAUSED) => ContainerRunState::Paused,
+            Some(ContainerStateStatusEnum::RESTARTING) => ContainerRunState::Restarting,
+            Some(ContainerStateStatusEnum::EXITED) if docker_state.oom_killed == Some(true) => {
+                ContainerRunState::OomKilled
+            }
+            Some(ContainerStateStatusEnum::EXITED) => ContainerRunState::Exited,
+            _ => ContainerRunState::Dead,
+        };
+        let mut status = ContainerStatus {
+            state,
+            exit_code: None,
+            memory_bytes: None,
+            cpu_percent: None,
+        };
+        match state {
+            ContainerRunState::Running | ContainerRunState::Paused => {
+                // One sample, taken a second after the previous one so CPU has a delta
+                let stats = self
+                    .docker
+                    .stats(
+                        container_id,
+                        Some(StatsOptions {
+                            stream: false,
+                            one_shot: false,
+                        }),
+                    )
+                    .next()
+                    .await
+                    .transpose()?;
+                if let Some(stats) = stats {
+                    status.memory_bytes = stats.memory_stats.usage;
+                    status.cpu_percent = cpu_percent(&stats.cpu_stats, &stats.precpu_stats);
+                }
+            }
+            ContainerRunState::Exited | ContainerRunState::OomKilled | ContainerRunState::Dead => {
+                status.exit_code = docker_state.exit_code;
+            }
+            ContainerRunState::Created | ContainerRunState::Restarting => {}
+        }
+        Ok(Some(status))
+    }
+
+    /// Freeze every process in the container; memory and open files are kept
+    pub async fn pause(&self, container_id: &str) -> Result<()> {
+        self.docker.pause_container(container_id).await?;
+        Ok(())
+    }
+
+    /// Thaw a container [`Self::pause`] froze
+    pub async fn unpause(&self, container_id: &str) -> Result<()> {
+        self.docker.unpause_container(container_id).await?;
+        Ok(())
+    }
+
+    /// Send SIGTERM and SIGKILL whatever is left after `grace`. A paused container is
+    /// thawed first so its processes can act on the signal.
+    pub async fn stop(&self, container_id: &str, grace: Duration) -> Result<()> {
+        let details = self.docker.inspect_container(container_id, None).await?;
+        let status = details.state.and_then(|state| state.status);
+        if status == Some(ContainerStateStatusEnum::PAUSED) {
+            self.unpause(container_id).await?;
+        }
+        match self
+            .docker
+            .stop_container(
+                container_id,
+                Some(StopContainerOptions {
+                    t: grace.as_secs() as i64,
+                }),
+            )
+            .await
+        {
+            // Already stopped
+            Ok(())
+            | Err(BollardError::DockerResponseServerError {
+                status_code: 304, ..
+            }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Force-remove the container, killing anything still running in it
+    pub async fn remove(&self, container_id: &str) -> Result<()> {
+        self.docker
+            .remove_container(
+                container_id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Force-remove the containers an execution runs in, ending it; returns how many were
+    /// removed
+    pub async fn remove_execution(&self, execution_id: &str) -> Result<usize> {
+        self.remove_named(&format!("faas-{execution_id}-")).await
+    }
+
+    /// Force-remove every container whose name starts with `prefix`
+    async fn remove_named(&self, prefix: &str) -> Result<usize> {
+        let containers = self
+            .docker
+            .list_containers(Some(ListContainersOptions::<String> {
+                all: true,
+                filters: [("name".to_string(), vec![prefix.to_string()])].into(),
+                ..Default::default()
+            }))
+            .await?;
+
+        let mut removed = 0;
+        for container in containers {
+            // The name filter is a substring match; Docker names carry a leading slash
+            let ours = container
+                .names
+                .iter()
+                .flatten()
+                .any(|name| name.trim_start_matches('/').starts_with(prefix));
+            let Some(id) = container.id.filter(|_| ours) else {
+                continue;
+            };
+            self.remove(&id).await?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+fn container_name(instance_id: &str) -> String {
+    format!("faas-instance-{instance_id}")
+}
+
+/// CPU used between two samples, the way `docker stats` computes it
+fn cpu_percent(now: &CPUStats, before: &CPUStats) -> Option<f64> {
+    let used = now
+        .cpu_usage
+        .total_usage
+        .checked_sub(before.cpu_usage.total_usage)?;
+    let elapsed = now
+        .system_cpu_usage?
+        .checked_sub(before.system_cpu_usage?)?;
+    if elapsed == 0 {
+        return None;
+    }
+    let cpus = now.online_cpus.unwrap_or(1);
+    Some(used as f64 / elapsed as f64 * cpus as f64 * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bollard::container::{CPUUsage, ThrottlingData};
+
+    fn sample(total_usage: u64, system_cpu_usage: Option<u64>) -> CPUStats {
+        CPUStats {
+            cpu_usage: CPUUsage {
+                percpu_usage: None,
+                usage_in_usermode: 0,
+                total_usage,
+                usage_in_kernelmode: 0,
+            },
+            system_cpu_usage,
+            online_cpus: Some(4),
+            throttling_data: ThrottlingData {
+                periods: 0,
+                throttled_periods: 0,
+                throttled_time: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn cpu_percent_scales_by_online_cpus() {
+        // A quarter of all host CPU time on four cores is one full core
+        let percent = cpu_percent(&sample(1_250, Some(10_000)), &sample(1_000, Some(9_000)));
+        assert_eq!(percent, Some(100.0));
+    }
+
+    #[test]
+    fn no_cpu_percent_without_a_previous_sample() {
+        assert_eq!(
+            cpu_percent(&sample(1_250, Some(10_000)), &sample(0, None)),
+            None
+        );
+        assert_eq!(
+            cpu_percent(&sample(1_250, Some(9_000)), &sample(1_000, Some(9_000))),
+            None
+        );
+    }
+}
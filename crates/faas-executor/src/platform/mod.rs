@@ -1,9 +1,31 @@
+pub mod arch;
+pub mod config;
 pub mod executor;
 pub mod fork;
+pub mod image_metadata;
+pub mod instances;
 pub mod memory;
+pub mod negative_cache;
+pub mod ports;
+pub mod result_cache;
+pub mod runtime_policy;
 pub mod snapshot;
+pub mod speculation;
+pub mod workspaces;
 
-pub use executor::{Executor, Mode, Request, Response};
+pub use arch::ArchMismatch;
+pub use config::{ConfigError, PlatformConfig};
+pub use executor::{Executor, Mode, Request, Response, WarmPoolStats};
 pub use fork::ForkManager;
+pub use image_metadata::{ImageMetadata, ImageMetadataError, ImageMetadataService};
+pub use instances::{
+    ContainerRunState, ContainerStatus, InstanceContainers, InstanceOptions, InstanceResources,
+};
 pub use memory::MemoryPool;
+pub use negative_cache::{NegativeCache, ResolutionFailure};
+pub use ports::{PortMapping, PortProtocol};
+pub use result_cache::{CachedResult, ResultCache, ResultCacheStats};
+pub use runtime_policy::{AutoRuntimePolicy, RuntimeDecision, RuntimeReason};
 pub use snapshot::{Snapshot, SnapshotStore};
+pub use speculation::{SpeculationReport, StrategyError};
+pub use workspaces::{VolumeInfo, WorkspaceMount, WorkspaceVolumes};
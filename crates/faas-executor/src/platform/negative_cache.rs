@@ -0,0 +1,151 @@
+//! Short-lived memory of requests that failed in a way retrying won't fix.
+//!
+//! A misspelled image or a request no host here can satisfy keeps failing the same way, and
+//! clients tend to retry in a loop. Remembering the failure for a little while turns those
+//! retries into immediate errors instead of registry round trips.
+
+use dashmap::DashMap;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    ImageNotFound,
+    ImageUnauthorized,
+    /// The request asks for more than this host can ever provide
+    Unsatisfiable,
+}
+
+/// A failure that is remembered, or was served from memory (`cached_error`)
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize)]
+#[error("{message}")]
+pub struct ResolutionFailure {
+    pub kind: FailureKind,
+    pub key: String,
+    pub message: String,
+    pub cached_error: bool,
+    /// How long the failure will keep being served from the cache
+    pub retry_in_ms: u64,
+}
+
+struct Entry {
+    kind: FailureKind,
+    message: String,
+    expires: Instant,
+}
+
+pub struct NegativeCache {
+    ttl: Duration,
+    entries: DashMap<String, Entry>,
+    fast_fails: AtomicU64,
+}
+
+impl Default for NegativeCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl NegativeCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: DashMap::new(),
+            fast_fails: AtomicU64::new(0),
+        }
+    }
+
+    /// `FAAS_NEGATIVE_CACHE_TTL_SECS`, 30 seconds by default; `0` disables the cache
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("FAAS_NEGATIVE_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        Self::new(ttl)
+    }
+
+    /// The remembered failure for `key`, if it hasn't expired
+    pub fn check(&self, key: &str) -> Result<(), ResolutionFailure> {
+        let now = Instant::now();
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(());
+        };
+        if entry.expires <= now {
+            drop(entry);
+            self.entries.remove_if(key, |_, e| e.expires <= now);
+            return Ok(());
+        }
+        self.fast_fails.fetch_add(1, Ordering::Relaxed);
+        Err(ResolutionFailure {
+            kind: entry.kind,
+            key: key.to_string(),
+            message: entry.message.clone(),
+            cached_error: true,
+            retry_in_ms: (entry.expires - now).as_millis() as u64,
+        })
+    }
+
+    /// Remember a failure and return it, annotated as fresh
+    pub fn record(
+        &self,
+        key: &str,
+        kind: FailureKind,
+        message: impl Into<String>,
+    ) -> ResolutionFailure {
+        let message = message.into();
+        if !self.ttl.is_zero() {
+            self.entries.insert(
+                key.to_string(),
+                Entry {
+                    kind,
+                    message: message.clone(),
+                    expires: Instant::now() + self.ttl,
+                },
+            );
+        }
+        ResolutionFailure {
+            kind,
+            key: key.to_string(),
+            message,
+            cached_error: false,
+            retry_in_ms: self.ttl.as_millis() as u64,
+        }
+    }
+
+    pub fn clear(&self, key: &str) {
+        self.entries.remove(key);
+    }
+
+    /// Requests answered from the cache
+    pub fn fast_fails(&self) -> u64 {
+        self.fast_fails.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_failures_until_they_expire() {
+        let cache = NegativeCache::new(Duration::from_millis(30));
+        assert!(cache.check("resources:gpu").is_ok());
+
+        let fresh = cache.record("resources:gpu", FailureKind::Unsatisfiable, "no GPU host");
+        assert!(!fresh.cached_error);
+        let cached = cache.check("resources:gpu").unwrap_err();
+        assert!(cached.cached_error);
+        assert_eq!(cached.kind, FailureKind::Unsatisfiable);
+        assert!(cached.retry_in_ms <= 30);
+        assert_eq!(cache.fast_fails(), 1);
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(cache.check("resources:gpu").is_ok());
+        assert_eq!(cache.fast_fails(), 1);
+    }
+}
@@ -0,0 +1,206 @@
+//! Publishing instance ports on the host
+//!
+//! Ports asked for at creation are bound by Docker when the container starts. A running
+//! container's bindings can't change, so a port exposed later is published by a small
+//! `socat` proxy container forwarding to the instance's address on the bridge network.
+
+use crate::bollard::models::{PortBinding, PortMap};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Image of the proxy publishing ports exposed after an instance started
+pub const DEFAULT_PROXY_IMAGE: &str = "alpine/socat:latest";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl PortProtocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PortProtocol::Tcp => "tcp",
+            PortProtocol::Udp => "udp",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tcp" => Some(PortProtocol::Tcp),
+            "udp" => Some(PortProtocol::Udp),
+            _ => None,
+        }
+    }
+}
+
+/// A port in the instance and the host port it is published on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub container_port: u16,
+    /// Docker picks a free port when unset
+    #[serde(default)]
+    pub host_port: Option<u16>,
+    #[serde(default)]
+    pub protocol: PortProtocol,
+}
+
+impl PortMapping {
+    pub fn tcp(container_port: u16) -> Self {
+        Self {
+            container_port,
+            host_port: None,
+            protocol: PortProtocol::Tcp,
+        }
+    }
+
+    /// Docker's name for the port, `8000/tcp`
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.container_port, self.protocol.as_str())
+    }
+
+    /// Where the port is reached from outside, once it has a host port
+    pub fn url(&self, host: &str) -> Option<String> {
+        let scheme = match self.protocol {
+            PortProtocol::Tcp => "http",
+            PortProtocol::Udp => "udp",
+        };
+        self.host_port
+            .map(|port| format!("{scheme}://{host}:{port}"))
+    }
+}
+
+/// `FAAS_PORT_PROXY_IMAGE`, or [`DEFAULT_PROXY_IMAGE`]
+pub fn proxy_image() -> String {
+    std::env::var("FAAS_PORT_PROXY_IMAGE").unwrap_or_else(|_| DEFAULT_PROXY_IMAGE.to_string())
+}
+
+/// The host name endpoint URLs are built with: `FAAS_PUBLIC_HOST`, or `localhost`
+pub fn public_host() -> String {
+    std::env::var("FAAS_PUBLIC_HOST").unwrap_or_else(|_| "localhost".to_string())
+}
+
+/// Each published port's URL, keyed as [`PortMapping::key`]
+pub fn endpoints(ports: &[PortMapping], host: &str) -> BTreeMap<String, String> {
+    ports
+        .iter()
+        .filter_map(|port| Some((port.key(), port.url(host)?)))
+        .collect()
+}
+
+pub(crate) fn exposed_ports(ports: &[PortMapping]) -> HashMap<String, HashMap<(), ()>> {
+    ports
+        .iter()
+        .map(|port| (port.key(), HashMap::new()))
+        .collect()
+}
+
+pub(crate) fn port_bindings(ports: &[PortMapping]) -> PortMap {
+    ports
+        .iter()
+        .map(|port| {
+            let binding = PortBinding {
+                host_ip: None,
+                // Empty asks Docker for a free port
+                host_port: Some(port.host_port.map(|p| p.to_string()).unwrap_or_default()),
+            };
+            (port.key(), Some(vec![binding]))
+        })
+        .collect()
+}
+
+/// The mappings an inspected container publishes; a port bound on both IPv4 and IPv6
+/// is listed once
+pub(crate) fn published(ports: &PortMap) -> Vec<PortMapping> {
+    let mut mappings: Vec<PortMapping> = ports
+        .iter()
+        .filter_map(|(key, bindings)| {
+            let (port, protocol) = key.split_once('/')?;
+            let host_port = bindings
+                .iter()
+                .flatten()
+                .find_map(|binding| binding.host_port.as_deref()?.parse().ok())?;
+            Some(PortMapping {
+                container_port: port.parse().ok()?,
+                host_port: Some(host_port),
+                protocol: PortProtocol::parse(protocol)?,
+            })
+        })
+        .collect();
+    mappings.sort_by_key(|port| (port.container_port, port.protocol.as_str()));
+    mappings
+}
+
+/// The `socat` arguments forwarding `port` to the same port at `address`
+pub(crate) fn proxy_command(port: &PortMapping, address: &str) -> Vec<String> {
+    let (listen, connect) = match port.protocol {
+        PortProtocol::Tcp => ("TCP-LISTEN", "TCP"),
+        PortProtocol::Udp => ("UDP-LISTEN", "UDP"),
+    };
+    vec![
+        format!("{listen}:{},fork,reuseaddr", port.container_port),
+        format!("{connect}:{address}:{}", port.container_port),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_host_ports_are_left_to_docker() {
+        let bindings = port_bindings(&[
+            PortMapping::tcp(8000),
+            PortMapping {
+                container_port: 53,
+                host_port: Some(5353),
+                protocol: PortProtocol::Udp,
+            },
+        ]);
+        let host_port = |key: &str| bindings[key].as_ref().unwrap()[0].host_port.clone();
+        assert_eq!(host_port("8000/tcp").as_deref(), Some(""));
+        assert_eq!(host_port("53/udp").as_deref(), Some("5353"));
+    }
+
+    #[test]
+    fn published_ports_are_read_back_with_urls() {
+        let binding = |ip: &str, port: &str| PortBinding {
+            host_ip: Some(ip.to_string()),
+            host_port: Some(port.to_string()),
+        };
+        let inspected: PortMap = HashMap::from([
+            (
+                "8000/tcp".to_string(),
+                Some(vec![binding("0.0.0.0", "49153"), binding("::", "49153")]),
+            ),
+            // Exposed by the image but not published
+            ("9000/tcp".to_string(), None),
+        ]);
+        let ports = published(&inspected);
+        assert_eq!(
+            ports,
+            vec![PortMapping {
+                container_port: 8000,
+                host_port: Some(49153),
+                protocol: PortProtocol::Tcp,
+            }]
+        );
+        assert_eq!(
+            endpoints(&ports, "node-1.example"),
+            BTreeMap::from([(
+                "8000/tcp".to_string(),
+                "http://node-1.example:49153".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn the_proxy_forwards_to_the_same_port() {
+        assert_eq!(
+            proxy_command(&PortMapping::tcp(8888), "172.17.0.5"),
+            ["TCP-LISTEN:8888,fork,reuseaddr", "TCP:172.17.0.5:8888"]
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Results of [`Mode::Cached`](super::Mode::Cached) executions, so that repeating one runs
+//! no container.
+//!
+//! A result is stored whole, with stdout, stderr and exit code, under the request's
+//! [`result_cache_key`](super::Request::result_cache_key). Entries expire after a TTL and the
+//! least recently used go first once the cache is full. Outputs above a size threshold are
+//! kept on disk only, when a directory is configured.
+
+use crate::performance::cache_manager::EvictionPolicy;
+use crate::performance::{CacheManager, CacheStrategy};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+const DEFAULT_MAX_BYTES: usize = 100 * 1024 * 1024;
+const DEFAULT_DISK_THRESHOLD: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResult {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResultCacheConfig {
+    pub ttl: Duration,
+    /// Bytes kept in memory, and again on disk
+    pub max_bytes: usize,
+    /// Disk layer; memory only when unset
+    pub dir: Option<PathBuf>,
+    /// Results at least this big skip memory when there is a disk layer
+    pub disk_threshold: usize,
+}
+
+impl Default for ResultCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_TTL,
+            max_bytes: DEFAULT_MAX_BYTES,
+            dir: None,
+            disk_threshold: DEFAULT_DISK_THRESHOLD,
+        }
+    }
+}
+
+impl ResultCacheConfig {
+    /// `FAAS_RESULT_CACHE_TTL_SECS` (an hour), `FAAS_RESULT_CACHE_MAX_BYTES` (100 MiB),
+    /// `FAAS_RESULT_CACHE_DIR` (unset) and `FAAS_RESULT_CACHE_DISK_THRESHOLD_BYTES` (1 MiB)
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let number = |name: &str| var(name).and_then(|v| v.parse().ok());
+        let defaults = Self::default();
+        Self {
+            ttl: number("FAAS_RESULT_CACHE_TTL_SECS").map_or(defaults.ttl, Duration::from_secs),
+            max_bytes: number("FAAS_RESULT_CACHE_MAX_BYTES")
+                .map_or(defaults.max_bytes, |n| n as usize),
+            dir: var("FAAS_RESULT_CACHE_DIR").map(PathBuf::from),
+            disk_threshold: number("FAAS_RESULT_CACHE_DISK_THRESHOLD_BYTES")
+                .map_or(defaults.disk_threshold, |n| n as usize),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Lookups skipped because the request asked for a fresh run
+    pub bypassed: u64,
+    pub stored: u64,
+    pub invalidated: u64,
+}
+
+pub struct ResultCache {
+    cache: CacheManager,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bypassed: AtomicU64,
+    stored: AtomicU64,
+    invalidated: AtomicU64,
+}
+
+impl ResultCache {
+    pub async fn new(config: ResultCacheConfig) -> Result<Self> {
+        let strategy = CacheStrategy {
+            l1_max_size: config.max_bytes,
+            l1_ttl: config.ttl,
+            l2_max_size: config.max_bytes,
+            l2_ttl: config.ttl,
+            eviction_policy: EvictionPolicy::LRU,
+            compression: false,
+            disk_dir: config.dir,
+            disk_threshold: Some(config.disk_threshold),
+        };
+        Ok(Self {
+            cache: CacheManager::new(strategy).await?,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            bypassed: AtomicU64::new(0),
+            stored: AtomicU64::new(0),
+            invalidated: AtomicU64::new(0),
+        })
+    }
+
+    pub async fn from_env() -> Result<Self> {
+        Self::new(ResultCacheConfig::from_env()).await
+    }
+
+    /// The stored result for `key`, if it hasn't expired
+    pub async fn get(&self, key: &str) -> Option<CachedResult> {
+        let cached = self
+            .cache
+            .get(key)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|data| bincode::deserialize(&data).ok());
+        let counter = if cached.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    /// Count a lookup the request opted out of
+    pub fn bypass(&self) {
+        self.bypassed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub async fn put(&self, key: &str, result: &CachedResult) -> Result<()> {
+        self.cache
+            .put(key, bincode::serialize(result)?, None)
+            .await?;
+        self.stored.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Drop the result for `key`; false when there was none
+    pub async fn invalidate(&self, key: &str) -> bool {
+        let removed = self.cache.remove(key).await;
+        if removed {
+            self.invalidated.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        ResultCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            bypassed: self.bypassed.load(Ordering::Relaxed),
+            stored: self.stored.load(Ordering::Relaxed),
+            invalidated: self.invalidated.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(stdout: &str, exit_code: i32) -> CachedResult {
+        CachedResult {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: b"warning".to_vec(),
+            exit_code,
+        }
+    }
+
+    #[tokio::test]
+    async fn results_round_trip_until_invalidated() {
+        let cache = ResultCache::new(ResultCacheConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(cache.get("tenant/key").await, None);
+        cache.put("tenant/key", &result("42", 0)).await.unwrap();
+        assert_eq!(cache.get("tenant/key").await, Some(result("42", 0)));
+
+        assert!(cache.invalidate("tenant/key").await);
+        assert!(!cache.invalidate("tenant/key").await);
+        assert_eq!(cache.get("tenant/key").await, None);
+        assert_eq!(
+            cache.stats(),
+            ResultCacheStats {
+                hits: 1,
+                misses: 2,
+                bypassed: 0,
+                stored: 1,
+                invalidated: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn large_results_are_served_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResultCache::new(ResultCacheConfig {
+            dir: Some(dir.path().to_path_buf()),
+            disk_threshold: 16,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let large = result(&"x".repeat(1024), 0);
+        cache.put("large", &large).await.unwrap();
+        assert_eq!(cache.get("large").await, Some(large));
+        assert!(std::fs::read_dir(dir.path()).unwrap().next().is_some());
+    }
+}
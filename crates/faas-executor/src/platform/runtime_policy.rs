@@ -0,0 +1,339 @@
+//! Which runtime an `auto` execution runs on.
+//!
+//! Docker is the default: it starts fastest and can use a prewarmed container. An execution
+//! goes to a Firecracker VM instead when it asks for hardware isolation, wants more memory
+//! than `FAAS_AUTO_VM_MEMORY_MB` (4096 by default), or runs an image listed in
+//! `FAAS_UNTRUSTED_IMAGES`. Without KVM on the host those fall back to Docker, and the
+//! response says so. Executions that ask for GPUs, restrict the network or name a user
+//! always run in Docker.
+
+use faas_common::{IsolationLevel, Runtime};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::executor::Request;
+
+const DEFAULT_VM_MEMORY_MB: u32 = 4096;
+
+/// Why an execution runs where it does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeReason {
+    /// The request named the runtime
+    Requested,
+    HardwareIsolation,
+    MemoryThreshold,
+    UntrustedImage,
+    /// Nothing called for a VM
+    Default,
+    /// GPUs are only passed through to containers
+    Gpu,
+    /// Network policies are only enforced for containers
+    NetworkPolicy,
+    /// Only containers run commands as another user
+    User,
+    /// A VM was called for, but this host has no KVM
+    KvmUnavailableFallback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeDecision {
+    pub runtime: Runtime,
+    pub reason: RuntimeReason,
+}
+
+/// What the host can run
+pub trait CapabilityProbe: Send + Sync {
+    fn kvm_available(&self) -> bool;
+}
+
+/// Probes the machine the executor runs on
+pub struct HostCapabilities;
+
+impl CapabilityProbe for HostCapabilities {
+    fn kvm_available(&self) -> bool {
+        crate::firecracker::FirecrackerExecutor::check_kvm_available()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AutoRuntimePolicy {
+    /// Executions asking for more memory than this get their own kernel
+    pub vm_memory_threshold_mb: u32,
+    /// Images run in a VM; an entry ending in `*` matches every image it is a prefix of,
+    /// any other matches the image at every tag and digest
+    pub untrusted_images: Vec<String>,
+}
+
+impl Default for AutoRuntimePolicy {
+    fn default() -> Self {
+        Self {
+            vm_memory_threshold_mb: DEFAULT_VM_MEMORY_MB,
+            untrusted_images: Vec::new(),
+        }
+    }
+}
+
+impl AutoRuntimePolicy {
+    pub fn from_env() -> Self {
+        let vm_memory_threshold_mb = std::env::var("FAAS_AUTO_VM_MEMORY_MB")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_VM_MEMORY_MB);
+        let untrusted_images = std::env::var("FAAS_UNTRUSTED_IMAGES")
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|image| !image.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            vm_memory_threshold_mb,
+            untrusted_images,
+        }
+    }
+
+    /// The runtime for `req`; one it names is always kept
+    pub fn select(&self, req: &Request, probe: &dyn CapabilityProbe) -> RuntimeDecision {
+        let decision = |runtime, reason| RuntimeDecision { runtime, reason };
+        match req.runtime {
+            Some(runtime @ (Runtime::Docker | Runtime::Firecracker)) => {
+                return decision(runtime, RuntimeReason::Requested)
+            }
+            Some(Runtime::Auto) | None => {}
+        }
+        if req.gpu.is_some() {
+            return decision(Runtime::Docker, RuntimeReason::Gpu);
+        }
+        if req
+            .network
+            .as_ref()
+            .is_some_and(faas_common::NetworkPolicy::is_restrictive)
+        {
+            return decision(Runtime::Docker, RuntimeReason::NetworkPolicy);
+        }
+        if req.user.is_some() {
+            return decision(Runtime::Docker, RuntimeReason::User);
+        }
+        let Some(reason) = self.vm_reason(req) else {
+            return decision(Runtime::Docker, RuntimeReason::Default);
+        };
+        if !probe.kvm_available() {
+            warn!(
+                "{} wants a VM ({:?}) but KVM is unavailable, running it in Docker",
+                req.id, reason
+            );
+            return decision(Runtime::Docker, RuntimeReason::KvmUnavailableFallback);
+        }
+        decision(Runtime::Firecracker, reason)
+    }
+
+    fn vm_reason(&self, req: &Request) -> Option<RuntimeReason> {
+        if req.isolation == Some(IsolationLevel::Hardware) {
+            Some(RuntimeReason::HardwareIsolation)
+        } else if req
+            .memory_mb
+            .is_some_and(|mb| mb > self.vm_memory_threshold_mb)
+        {
+            Some(RuntimeReason::MemoryThreshold)
+        } else if self.is_untrusted(&req.env) {
+            Some(RuntimeReason::UntrustedImage)
+        } else {
+            None
+        }
+    }
+
+    fn is_untrusted(&self, image: &str) -> bool {
+        self.untrusted_images
+            .iter()
+            .any(|entry| match entry.strip_suffix('*') {
+                Some(prefix) => image.starts_with(prefix),
+                None => image
+                    .strip_prefix(entry.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with([':', '@'])),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Kvm(bool);
+
+    impl CapabilityProbe for Kvm {
+        fn kvm_available(&self) -> bool {
+            self.0
+        }
+    }
+
+    fn policy() -> AutoRuntimePolicy {
+        AutoRuntimePolicy {
+            vm_memory_threshold_mb: 2048,
+            untrusted_images: vec!["sketchy/tool".to_string(), "uploads/*".to_string()],
+        }
+    }
+
+    fn auto(env: &str) -> Request {
+        Request {
+            id: "exec".to_string(),
+            env: env.to_string(),
+            runtime: Some(Runtime::Auto),
+            ..Default::default()
+        }
+    }
+
+    fn select(req: &Request, kvm: bool) -> (Runtime, RuntimeReason) {
+        let decision = policy().select(req, &Kvm(kvm));
+        (decision.runtime, decision.reason)
+    }
+
+    #[test]
+    fn a_named_runtime_is_kept() {
+        let docker = Request {
+            runtime: Some(Runtime::Docker),
+            isolation: Some(IsolationLevel::Hardware),
+            ..auto("alpine:latest")
+        };
+        assert_eq!(
+            select(&docker, true),
+            (Runtime::Docker, RuntimeReason::Requested)
+        );
+        let vm = Request {
+            runtime: Some(Runtime::Firecracker),
+            ..auto("alpine:latest")
+        };
+        assert_eq!(
+            select(&vm, false),
+            (Runtime::Firecracker, RuntimeReason::Requested)
+        );
+    }
+
+    #[test]
+    fn ordinary_work_runs_in_docker() {
+        let plain = Request {
+            memory_mb: Some(2048),
+            isolation: Some(IsolationLevel::Process),
+            ..auto("alpine:latest")
+        };
+        assert_eq!(
+            select(&plain, true),
+            (Runtime::Docker, RuntimeReason::Default)
+        );
+        let unset = Request {
+            runtime: None,
+            ..auto("sketchy/toolbox")
+        };
+        assert_eq!(
+            select(&unset, true),
+            (Runtime::Docker, RuntimeReason::Default)
+        );
+    }
+
+    #[test]
+    fn each_reason_for_a_vm_picks_firecracker() {
+        let isolated = Request {
+            isolation: Some(IsolationLevel::Hardware),
+            ..auto("alpine:latest")
+        };
+        assert_eq!(
+            select(&isolated, true),
+            (Runtime::Firecracker, RuntimeReason::HardwareIsolation)
+        );
+        let large = Request {
+            memory_mb: Some(2049),
+            ..auto("alpine:latest")
+        };
+        assert_eq!(
+            select(&large, true),
+            (Runtime::Firecracker, RuntimeReason::MemoryThreshold)
+        );
+        for image in [
+            "sketchy/tool",
+            "sketchy/tool:1.2",
+            "uploads/anything:latest",
+        ] {
+            assert_eq!(
+                select(&auto(image), true),
+                (Runtime::Firecracker, RuntimeReason::UntrustedImage),
+                "{image}"
+            );
+        }
+    }
+
+    #[test]
+    fn gpu_work_stays_in_docker() {
+        let gpu = Request {
+            isolation: Some(IsolationLevel::Hardware),
+            gpu: Some(faas_common::GpuRequest::count(1)),
+            ..auto("pytorch/pytorch:latest")
+        };
+        assert_eq!(select(&gpu, true), (Runtime::Docker, RuntimeReason::Gpu));
+    }
+
+    #[test]
+    fn a_restricted_network_stays_in_docker() {
+        let offline = Request {
+            network: Some(faas_common::NetworkPolicy::None),
+            ..auto("uploads/job")
+        };
+        assert_eq!(
+            select(&offline, true),
+            (Runtime::Docker, RuntimeReason::NetworkPolicy)
+        );
+        let online = Request {
+            network: Some(faas_common::NetworkPolicy::Default),
+            ..auto("uploads/job")
+        };
+        assert_eq!(
+            select(&online, true),
+            (Runtime::Firecracker, RuntimeReason::UntrustedImage)
+        );
+    }
+
+    #[test]
+    fn a_user_stays_in_docker() {
+        let as_user = Request {
+            user: Some("1000".to_string()),
+            ..auto("uploads/job")
+        };
+        assert_eq!(
+            select(&as_user, true),
+            (Runtime::Docker, RuntimeReason::User)
+        );
+    }
+
+    #[test]
+    fn without_kvm_a_vm_falls_back_to_docker() {
+        let isolated = Request {
+            isolation: Some(IsolationLevel::Hardware),
+            ..auto("alpine:latest")
+        };
+        assert_eq!(
+            select(&isolated, false),
+            (Runtime::Docker, RuntimeReason::KvmUnavailableFallback)
+        );
+        assert_eq!(
+            select(&auto("uploads/job"), false),
+            (Runtime::Docker, RuntimeReason::KvmUnavailableFallback)
+        );
+        assert_eq!(
+            select(&auto("alpine:latest"), false),
+            (Runtime::Docker, RuntimeReason::Default)
+        );
+    }
+
+    #[test]
+    fn the_reason_serializes_in_snake_case() {
+        let decision = RuntimeDecision {
+            runtime: Runtime::Docker,
+            reason: RuntimeReason::KvmUnavailableFallback,
+        };
+        assert_eq!(
+            serde_json::to_value(decision).unwrap(),
+            serde_json::json!({ "runtime": "docker", "reason": "kvm_unavailable_fallback" })
+        );
+    }
+}
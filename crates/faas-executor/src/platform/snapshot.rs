@@ -1,8 +1,9 @@
 use crate::criu::{CriuConfig, CriuManager};
+use crate::docker_checkpoint::CheckpointedContainer;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -14,6 +15,9 @@ pub struct Snapshot {
     pub path: PathBuf,
     pub size_bytes: u64,
     pub created_at: std::time::Instant,
+    /// Set for a checkpoint Docker took of a container, which a checkpointed execution
+    /// resumes by recreating the container
+    pub container: Option<CheckpointedContainer>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +27,7 @@ pub enum Backend {
 }
 
 pub struct SnapshotStore {
+    root: PathBuf,
     snapshots: Arc<RwLock<HashMap<String, Snapshot>>>,
     criu: Arc<CriuManager>,
     firecracker: FirecrackerSnapshots,
@@ -63,6 +68,7 @@ impl SnapshotStore {
         };
 
         Ok(Self {
+            root: storage_path.clone(),
             snapshots: Arc::new(RwLock::new(HashMap::new())),
             criu,
             firecracker: FirecrackerSnapshots {
@@ -91,6 +97,20 @@ impl SnapshotStore {
         Ok(snapshot_id)
     }
 
+    /// Where snapshots are written
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub async fn insert(&self, snapshot: Snapshot) {
+        let mut snapshots = self.snapshots.write().await;
+        snapshots.insert(snapshot.id.clone(), snapshot);
+    }
+
+    pub async fn get(&self, snapshot_id: &str) -> Option<Snapshot> {
+        self.snapshots.read().await.get(snapshot_id).cloned()
+    }
+
     pub async fn restore(&self, snapshot_id: &str) -> Result<String> {
         let snapshots = self.snapshots.read().await;
         let snapshot = snapshots
@@ -147,6 +167,7 @@ impl SnapshotStore {
             path: checkpoint_result.images_path,
             size_bytes: checkpoint_result.memory_pages * 4096, // Convert pages to bytes
             created_at: std::time::Instant::now(),
+            container: None,
         })
     }
 
@@ -204,6 +225,7 @@ impl SnapshotStore {
             path: snapshot_dir,
             size_bytes,
             created_at: std::time::Instant::now(),
+            container: None,
         })
     }
 
@@ -216,7 +238,7 @@ impl SnapshotStore {
         Ok(new_exec_id)
     }
 
-    async fn dir_size(path: &PathBuf) -> Result<u64> {
+    pub async fn dir_size(path: &PathBuf) -> Result<u64> {
         let mut size = 0;
         let mut entries = tokio::fs::read_dir(path).await?;
 
@@ -0,0 +1,498 @@
+//! Speculative execution.
+//!
+//! A Firecracker VM is the better sandbox but takes a while to boot, while a warm Docker
+//! container answers almost at once. With [`ExecutionStrategy::Speculative`] an ephemeral
+//! execution starts on both runtimes, the first result that comes back is kept and the other
+//! attempt is cancelled. An attempt that fails doesn't win; the other one is waited for.
+//!
+//! Both attempts consume compute, so the [`SpeculationReport`] on the response lists each
+//! with its duration and outcome, and the caller bills the losers as speculation overhead.
+//! Running a command twice is only safe if it is idempotent, which the request has to say.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use faas_common::{ExecutionStrategy, Runtime};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::debug;
+
+use super::executor::{Mode, Request, Response};
+
+/// A strategy the request can't be run with; nothing has been started
+#[derive(Debug, Clone, PartialEq, Error, Serialize)]
+#[serde(rename_all = "snake_case", tag = "reason")]
+pub enum StrategyError {
+    #[error("speculative execution runs the command twice; the request must be marked idempotent")]
+    NotIdempotent,
+    #[error("speculative execution needs two different runtimes, got {runtime:?} for both")]
+    SameRuntime { runtime: Runtime },
+    #[error("speculative execution needs concrete runtimes, not auto")]
+    AutoRuntime,
+    #[error("speculative execution only supports ephemeral executions, not {mode}")]
+    NotEphemeral { mode: String },
+}
+
+/// Refuse a strategy that can't be run safely, before anything is admitted
+pub fn validate(
+    strategy: Option<&ExecutionStrategy>,
+    mode: Mode,
+    idempotent: bool,
+) -> Result<(), StrategyError> {
+    let Some(&ExecutionStrategy::Speculative {
+        preferred,
+        fallback,
+        ..
+    }) = strategy
+    else {
+        return Ok(());
+    };
+    if !idempotent {
+        return Err(StrategyError::NotIdempotent);
+    }
+    if preferred == Runtime::Auto || fallback == Runtime::Auto {
+        return Err(StrategyError::AutoRuntime);
+    }
+    if preferred == fallback {
+        return Err(StrategyError::SameRuntime { runtime: preferred });
+    }
+    if !matches!(mode, Mode::Ephemeral) {
+        return Err(StrategyError::NotEphemeral {
+            mode: format!("{mode:?}").to_lowercase(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptRole {
+    Preferred,
+    Fallback,
+}
+
+impl AttemptRole {
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Preferred => "preferred",
+            Self::Fallback => "fallback",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptOutcome {
+    Won,
+    /// Stopped because the other attempt won, or because the overlap ran out
+    Cancelled,
+    Failed,
+}
+
+/// One runtime's share of a speculative execution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attempt {
+    /// `<execution id>-preferred` or `<execution id>-fallback`
+    pub id: String,
+    pub role: AttemptRole,
+    pub runtime: Runtime,
+    pub outcome: AttemptOutcome,
+    /// From the start of the race until the attempt finished or was cancelled
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeculationReport {
+    pub winner: AttemptRole,
+    pub attempts: Vec<Attempt>,
+}
+
+impl SpeculationReport {
+    pub fn winning_attempt(&self) -> Option<&Attempt> {
+        self.attempts
+            .iter()
+            .find(|attempt| attempt.outcome == AttemptOutcome::Won)
+    }
+
+    /// The attempts whose compute bought nothing
+    pub fn overhead(&self) -> impl Iterator<Item = &Attempt> {
+        self.attempts
+            .iter()
+            .filter(|attempt| attempt.outcome != AttemptOutcome::Won)
+    }
+
+    pub fn overhead_ms(&self) -> u64 {
+        self.overhead().map(|attempt| attempt.duration_ms).sum()
+    }
+}
+
+/// Runs single attempts on a given runtime; the platform executor in production
+#[async_trait]
+pub trait AttemptRunner: Send + Sync {
+    async fn run_attempt(&self, runtime: Runtime, req: Request) -> Result<Response>;
+
+    /// Stop whatever is left of an attempt that lost. Its future is no longer polled.
+    async fn cancel_attempt(&self, runtime: Runtime, attempt_id: &str);
+}
+
+/// Win counts across every speculative execution
+#[derive(Debug, Default)]
+pub struct SpeculationStats {
+    races: AtomicU64,
+    preferred_wins: AtomicU64,
+    fallback_wins: AtomicU64,
+    /// Races where the fallback was given up once `max_overlap_ms` passed
+    overlap_expired: AtomicU64,
+    /// Races where both attempts failed
+    failed: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeculationStatsSnapshot {
+    pub races: u64,
+    pub preferred_wins: u64,
+    pub fallback_wins: u64,
+    pub overlap_expired: u64,
+    pub failed: u64,
+    /// Share of races the fallback won; what speculating buys
+    pub fallback_win_rate: f64,
+}
+
+impl SpeculationStats {
+    pub fn snapshot(&self) -> SpeculationStatsSnapshot {
+        let races = self.races.load(Ordering::Relaxed);
+        let fallback_wins = self.fallback_wins.load(Ordering::Relaxed);
+        SpeculationStatsSnapshot {
+            races,
+            preferred_wins: self.preferred_wins.load(Ordering::Relaxed),
+            fallback_wins,
+            overlap_expired: self.overlap_expired.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            fallback_win_rate: if races > 0 {
+                fallback_wins as f64 / races as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+fn attempt_request(req: &Request, role: AttemptRole, runtime: Runtime) -> Request {
+    Request {
+        id: format!("{}-{}", req.id, role.suffix()),
+        runtime: Some(runtime),
+        execution_strategy: None,
+        ..req.clone()
+    }
+}
+
+/// Race `req` on `preferred` and `fallback` and return the first successful response, with
+/// the [`SpeculationReport`] attached. If both attempts fail, the preferred one's error is
+/// returned.
+pub async fn speculate<R: AttemptRunner + ?Sized>(
+    runner: &R,
+    req: Request,
+    preferred: Runtime,
+    fallback: Runtime,
+    max_overlap: Duration,
+    stats: &SpeculationStats,
+) -> Result<Response> {
+    stats.races.fetch_add(1, Ordering::Relaxed);
+    let preferred_req = attempt_request(&req, AttemptRole::Preferred, preferred);
+    let fallback_req = attempt_request(&req, AttemptRole::Fallback, fallback);
+    let mut attempts = [
+        Attempt {
+            id: preferred_req.id.clone(),
+            role: AttemptRole::Preferred,
+            runtime: preferred,
+            outcome: AttemptOutcome::Cancelled,
+            duration_ms: 0,
+        },
+        Attempt {
+            id: fallback_req.id.clone(),
+            role: AttemptRole::Fallback,
+            runtime: fallback,
+            outcome: AttemptOutcome::Cancelled,
+            duration_ms: 0,
+        },
+    ];
+
+    let started = Instant::now();
+    let preferred_run = runner.run_attempt(preferred, preferred_req);
+    let fallback_run = runner.run_attempt(fallback, fallback_req);
+    let overlap = tokio::time::sleep(max_overlap);
+    tokio::pin!(preferred_run, fallback_run, overlap);
+
+    // Index into `attempts` of those still running
+    let mut running = [true, true];
+    let mut errors: [Option<anyhow::Error>; 2] = [None, None];
+    let (winner, mut response) = loop {
+        let (index, result) = tokio::select! {
+            result = &mut preferred_run, if running[0] => (0, result),
+            result = &mut fallback_run, if running[1] => (1, result),
+            () = &mut overlap, if running[0] && running[1] => {
+                debug!("{}: no result within {:?}, giving up the fallback", req.id, max_overlap);
+                stats.overlap_expired.fetch_add(1, Ordering::Relaxed);
+                running[1] = false;
+                attempts[1].duration_ms = started.elapsed().as_millis() as u64;
+                runner.cancel_attempt(fallback, &attempts[1].id).await;
+                continue;
+            }
+        };
+        running[index] = false;
+        attempts[index].duration_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(response) => break (index, response),
+            Err(e) => {
+                debug!("{} failed: {}", attempts[index].id, e);
+                attempts[index].outcome = AttemptOutcome::Failed;
+                errors[index] = Some(e);
+            }
+        }
+        if running == [false, false] {
+            stats.failed.fetch_add(1, Ordering::Relaxed);
+            let [preferred_error, fallback_error] = errors;
+            return Err(preferred_error
+                .or(fallback_error)
+                .expect("both attempts failed"));
+        }
+    };
+
+    attempts[winner].outcome = AttemptOutcome::Won;
+    let loser = 1 - winner;
+    if running[loser] {
+        attempts[loser].duration_ms = started.elapsed().as_millis() as u64;
+        runner
+            .cancel_attempt(attempts[loser].runtime, &attempts[loser].id)
+            .await;
+    }
+    let counter = match attempts[winner].role {
+        AttemptRole::Preferred => &stats.preferred_wins,
+        AttemptRole::Fallback => &stats.fallback_wins,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+
+    response.id = req.id;
+    response.speculation = Some(SpeculationReport {
+        winner: attempts[winner].role,
+        attempts: attempts.to_vec(),
+    });
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Answers after a fixed latency per runtime, or fails if the latency is missing
+    #[derive(Default)]
+    struct MockRunner {
+        latency: HashMap<&'static str, Duration>,
+        cancelled: Mutex<Vec<String>>,
+    }
+
+    fn key(runtime: Runtime) -> &'static str {
+        match runtime {
+            Runtime::Docker => "docker",
+            Runtime::Firecracker => "firecracker",
+            Runtime::Auto => "auto",
+        }
+    }
+
+    impl MockRunner {
+        fn new(latency: &[(Runtime, u64)]) -> Self {
+            Self {
+                latency: latency
+                    .iter()
+                    .map(|(runtime, ms)| (key(*runtime), Duration::from_millis(*ms)))
+                    .collect(),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AttemptRunner for MockRunner {
+        async fn run_attempt(&self, runtime: Runtime, req: Request) -> Result<Response> {
+            let Some(latency) = self.latency.get(key(runtime)) else {
+                anyhow::bail!("{} is unavailable", key(runtime));
+            };
+            tokio::time::sleep(*latency).await;
+            Ok(Response {
+                id: req.id,
+                stdout: key(runtime).as_bytes().to_vec(),
+                stderr: Vec::new(),
+                exit_code: 0,
+                duration: *latency,
+                snapshot: None,
+                speculation: None,
+                cache_hit: false,
+                cache_key: None,
+                runtime_decision: None,
+                usage: None,
+                truncation: None,
+                warm_start: false,
+            })
+        }
+
+        async fn cancel_attempt(&self, _runtime: Runtime, attempt_id: &str) {
+            self.cancelled.lock().unwrap().push(attempt_id.to_string());
+        }
+    }
+
+    fn request() -> Request {
+        Request {
+            id: "exec-1".to_string(),
+            idempotent: true,
+            execution_strategy: Some(ExecutionStrategy::Speculative {
+                preferred: Runtime::Firecracker,
+                fallback: Runtime::Docker,
+                max_overlap_ms: 5_000,
+            }),
+            ..Default::default()
+        }
+    }
+
+    async fn race(runner: &MockRunner, max_overlap_ms: u64, stats: &SpeculationStats) -> Response {
+        speculate(
+            runner,
+            request(),
+            Runtime::Firecracker,
+            Runtime::Docker,
+            Duration::from_millis(max_overlap_ms),
+            stats,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_faster_attempt_wins_and_the_slower_is_cancelled() {
+        let stats = SpeculationStats::default();
+        let runner = MockRunner::new(&[(Runtime::Firecracker, 400), (Runtime::Docker, 10)]);
+
+        let response = race(&runner, 5_000, &stats).await;
+        assert_eq!(response.id, "exec-1");
+        assert_eq!(response.stdout, b"docker");
+        assert_eq!(*runner.cancelled.lock().unwrap(), ["exec-1-preferred"]);
+
+        // Both attempts are accounted for, the loser as overhead
+        let report = response.speculation.unwrap();
+        assert_eq!(report.winner, AttemptRole::Fallback);
+        assert_eq!(report.attempts.len(), 2);
+        let won = report.winning_attempt().unwrap();
+        assert_eq!(
+            (won.runtime, won.duration_ms >= 10),
+            (Runtime::Docker, true)
+        );
+        let overhead: Vec<_> = report.overhead().collect();
+        assert_eq!(overhead.len(), 1);
+        assert_eq!(overhead[0].id, "exec-1-preferred");
+        assert_eq!(overhead[0].outcome, AttemptOutcome::Cancelled);
+        assert!(overhead[0].duration_ms < 400);
+
+        let runner = MockRunner::new(&[(Runtime::Firecracker, 10), (Runtime::Docker, 400)]);
+        let response = race(&runner, 5_000, &stats).await;
+        assert_eq!(response.stdout, b"firecracker");
+        assert_eq!(*runner.cancelled.lock().unwrap(), ["exec-1-fallback"]);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            (
+                snapshot.races,
+                snapshot.preferred_wins,
+                snapshot.fallback_wins
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(snapshot.fallback_win_rate, 0.5);
+    }
+
+    #[tokio::test]
+    async fn the_fallback_is_given_up_when_the_overlap_runs_out() {
+        let stats = SpeculationStats::default();
+        let runner = MockRunner::new(&[(Runtime::Firecracker, 100), (Runtime::Docker, 2_000)]);
+
+        let started = Instant::now();
+        let response = race(&runner, 20, &stats).await;
+        assert!(started.elapsed() < Duration::from_millis(1_000));
+        assert_eq!(response.stdout, b"firecracker");
+        assert_eq!(*runner.cancelled.lock().unwrap(), ["exec-1-fallback"]);
+
+        let report = response.speculation.unwrap();
+        assert_eq!(report.attempts[1].outcome, AttemptOutcome::Cancelled);
+        assert!(report.attempts[1].duration_ms < 100);
+        assert_eq!(stats.snapshot().overlap_expired, 1);
+    }
+
+    #[tokio::test]
+    async fn a_failed_attempt_does_not_win() {
+        let stats = SpeculationStats::default();
+        // The fallback fails at once; the preferred runtime's result is kept
+        let runner = MockRunner::new(&[(Runtime::Firecracker, 50)]);
+
+        let response = race(&runner, 5_000, &stats).await;
+        assert_eq!(response.stdout, b"firecracker");
+        assert!(runner.cancelled.lock().unwrap().is_empty());
+        let report = response.speculation.unwrap();
+        assert_eq!(report.winner, AttemptRole::Preferred);
+        assert_eq!(report.attempts[1].outcome, AttemptOutcome::Failed);
+
+        let runner = MockRunner::new(&[]);
+        let error = speculate(
+            &runner,
+            request(),
+            Runtime::Firecracker,
+            Runtime::Docker,
+            Duration::from_secs(5),
+            &stats,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "firecracker is unavailable");
+        assert_eq!(stats.snapshot().failed, 1);
+    }
+
+    #[test]
+    fn speculation_needs_an_idempotent_ephemeral_request_on_two_runtimes() {
+        let strategy = |preferred, fallback| ExecutionStrategy::Speculative {
+            preferred,
+            fallback,
+            max_overlap_ms: 100,
+        };
+        let speculative = strategy(Runtime::Firecracker, Runtime::Docker);
+        assert_eq!(validate(Some(&speculative), Mode::Ephemeral, true), Ok(()));
+        assert_eq!(validate(None, Mode::Cached, false), Ok(()));
+        assert_eq!(
+            validate(Some(&speculative), Mode::Ephemeral, false),
+            Err(StrategyError::NotIdempotent)
+        );
+        assert_eq!(
+            validate(Some(&speculative), Mode::Cached, true),
+            Err(StrategyError::NotEphemeral {
+                mode: "cached".to_string()
+            })
+        );
+        assert_eq!(
+            validate(
+                Some(&strategy(Runtime::Docker, Runtime::Docker)),
+                Mode::Ephemeral,
+                true
+            ),
+            Err(StrategyError::SameRuntime {
+                runtime: Runtime::Docker
+            })
+        );
+        assert_eq!(
+            validate(
+                Some(&strategy(Runtime::Auto, Runtime::Docker)),
+                Mode::Ephemeral,
+                true
+            ),
+            Err(StrategyError::AutoRuntime)
+        );
+    }
+}
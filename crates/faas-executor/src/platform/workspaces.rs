@@ -0,0 +1,211 @@
+//! Named Docker volumes behind persistent instance workspaces
+//!
+//! A workspace is keyed by the instance's tenant and name, so an instance the tenant
+//! creates again under the same name mounts the files the last one left, and no other
+//! tenant's instance can. The volume outlives the instance's
+//! container and is only removed when asked.
+
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::models::{Mount, MountTypeEnum};
+use crate::bollard::volume::{CreateVolumeOptions, RemoveVolumeOptions};
+use crate::bollard::Docker;
+use crate::container_labels;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+
+/// Where the workspace is mounted unless the instance asks for another path
+pub const DEFAULT_WORKSPACE_PATH: &str = "/workspace";
+
+/// Label on a workspace volume naming its workspace
+pub const WORKSPACE_LABEL: &str = "faas.workspace";
+
+/// Labels on an instance container with the volume it mounts, and where
+pub const WORKSPACE_VOLUME_LABEL: &str = "faas.workspace_volume";
+pub const WORKSPACE_PATH_LABEL: &str = "faas.workspace_path";
+
+/// A workspace volume and where an instance mounts it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceMount {
+    pub volume: String,
+    pub path: String,
+}
+
+impl WorkspaceMount {
+    /// The mount an instance container's labels record, if any
+    pub fn from_labels(labels: &HashMap<String, String>) -> Option<Self> {
+        Some(Self {
+            volume: labels.get(WORKSPACE_VOLUME_LABEL)?.clone(),
+            path: labels.get(WORKSPACE_PATH_LABEL)?.clone(),
+        })
+    }
+
+    pub(crate) fn labels(&self) -> [(&'static str, String); 2] {
+        [
+            (WORKSPACE_VOLUME_LABEL, self.volume.clone()),
+            (WORKSPACE_PATH_LABEL, self.path.clone()),
+        ]
+    }
+
+    pub(crate) fn mount(&self) -> Mount {
+        Mount {
+            target: Some(self.path.clone()),
+            source: Some(self.volume.clone()),
+            typ: Some(MountTypeEnum::VOLUME),
+            read_only: Some(false),
+            ..Default::default()
+        }
+    }
+}
+
+/// A workspace volume, as `GET /api/v1/volumes` lists it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeInfo {
+    pub name: String,
+    /// The instance name the volume is kept for
+    pub workspace: String,
+    /// The tenant whose instances mount it; unset for untenanted ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Bytes on disk; `None` when the daemon hasn't measured it
+    pub size_bytes: Option<u64>,
+    /// Containers mounting it right now
+    pub ref_count: Option<u64>,
+}
+
+/// The volume for the workspace of `tenant`'s instances named `workspace`. A tenant's
+/// volume carries a hash of the tenant behind a `_`, which no workspace name starts with,
+/// so it never matches another tenant's or an untenanted one.
+pub fn volume_name(tenant: Option<&str>, workspace: &str) -> String {
+    match tenant {
+        None => format!("faas-workspace-{workspace}"),
+        Some(tenant) => {
+            let hash = Sha256::digest(tenant.as_bytes());
+            let tag: String = hash[..8].iter().map(|b| format!("{b:02x}")).collect();
+            format!("faas-workspace-_{tag}-{workspace}")
+        }
+    }
+}
+
+/// Docker's rule for volume names, which a workspace's name becomes part of
+pub fn valid_workspace_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+#[derive(Clone)]
+pub struct WorkspaceVolumes {
+    docker: Arc<Docker>,
+}
+
+impl WorkspaceVolumes {
+    pub fn new(docker: Arc<Docker>) -> Self {
+        Self { docker }
+    }
+
+    /// The volume for `tenant`'s `workspace`, created on first use; returns its name
+    pub async fn ensure(&self, tenant: Option<&str>, workspace: &str) -> Result<String> {
+        let name = volume_name(tenant, workspace);
+        match self.docker.inspect_volume(&name).await {
+            Ok(_) => return Ok(name),
+            Err(BollardError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let mut labels = HashMap::from([
+            (container_labels::MANAGED.to_string(), "true".to_string()),
+            (WORKSPACE_LABEL.to_string(), workspace.to_string()),
+        ]);
+        if let Some(tenant) = tenant {
+            labels.insert(container_labels::TENANT.to_string(), tenant.to_string());
+        }
+        self.docker
+            .create_volume(CreateVolumeOptions {
+                name: name.clone(),
+                driver: "local".to_string(),
+                labels,
+                ..Default::default()
+            })
+            .await?;
+        info!("Created workspace volume {}", name);
+        Ok(name)
+    }
+
+    /// Remove workspace volume `name`; `false` if there was none. Fails while a container
+    /// still mounts it.
+    pub async fn remove(&self, name: &str) -> Result<bool> {
+        match self
+            .docker
+            .remove_volume(name, Some(RemoveVolumeOptions { force: false }))
+            .await
+        {
+            Ok(()) => {
+                info!("Removed workspace volume {}", name);
+                Ok(true)
+            }
+            Err(BollardError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every workspace volume with the space it takes
+    pub async fn list(&self) -> Result<Vec<VolumeInfo>> {
+        // Only the disk usage endpoint reports sizes
+        let usage = self.docker.df().await?;
+        let mut volumes: Vec<VolumeInfo> = usage
+            .volumes
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|volume| {
+                let workspace = volume.labels.get(WORKSPACE_LABEL)?.clone();
+                let tenant = volume.labels.get(container_labels::TENANT).cloned();
+                let usage = volume.usage_data;
+                Some(VolumeInfo {
+                    name: volume.name,
+                    workspace,
+                    tenant,
+                    // Docker reports -1 for what it hasn't measured
+                    size_bytes: usage.as_ref().and_then(|u| u64::try_from(u.size).ok()),
+                    ref_count: usage.and_then(|u| u64::try_from(u.ref_count).ok()),
+                })
+            })
+            .collect();
+        volumes.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(volumes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_names_follow_docker_volume_rules() {
+        for name in ["dev", "my-env_2", "a.b", "0"] {
+            assert!(valid_workspace_name(name), "{name}");
+        }
+        for name in ["", "-dev", ".hidden", "has space", "a/b", "ünïcode"] {
+            assert!(!valid_workspace_name(name), "{name}");
+        }
+        assert_eq!(volume_name(None, "dev"), "faas-workspace-dev");
+    }
+
+    #[test]
+    fn tenants_never_share_a_workspace_volume() {
+        let acme = volume_name(Some("acme"), "dev");
+        assert!(acme.starts_with("faas-workspace-_") && acme.ends_with("-dev"));
+        assert_eq!(acme, volume_name(Some("acme"), "dev"));
+        assert_ne!(acme, volume_name(Some("globex"), "dev"));
+        assert_ne!(acme, volume_name(None, "dev"));
+        // No untenanted name can spell out a tenant's volume
+        let untenanted = acme.trim_start_matches("faas-workspace-");
+        assert!(!valid_workspace_name(untenanted));
+    }
+}
@@ -0,0 +1,122 @@
+//! What a container consumed while it ran.
+//!
+//! The daemon stops reporting a container's counters once it exits, so they are sampled
+//! every [`SAMPLE_INTERVAL`] while it runs. CPU time is the last reading, which can miss up
+//! to one interval at the end; peak memory is the kernel's high-water mark where cgroup v1
+//! keeps one, and the highest reading otherwise.
+
+use docktopus::bollard::container::{Stats, StatsOptions};
+use docktopus::bollard::Docker;
+use faas_common::ExecutionUsage;
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+pub(crate) const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Readings {
+    cpu_ns: u64,
+    peak_memory_bytes: u64,
+}
+
+impl Readings {
+    fn record(&mut self, stats: &Stats) {
+        let memory = &stats.memory_stats;
+        self.observe(
+            stats.cpu_stats.cpu_usage.total_usage,
+            memory.max_usage.or(memory.usage).unwrap_or(0),
+        );
+    }
+
+    /// Counters only grow, and an exited container reads as zero
+    fn observe(&mut self, cpu_ns: u64, memory_bytes: u64) {
+        self.cpu_ns = self.cpu_ns.max(cpu_ns);
+        self.peak_memory_bytes = self.peak_memory_bytes.max(memory_bytes);
+    }
+
+    fn usage(&self, wall_time: Duration, stdout_bytes: usize) -> ExecutionUsage {
+        ExecutionUsage {
+            wall_time_ms: wall_time.as_millis() as u64,
+            cpu_time_ms: self.cpu_ns / 1_000_000,
+            peak_memory_bytes: self.peak_memory_bytes,
+            stdout_bytes: stdout_bytes as u64,
+        }
+    }
+}
+
+/// Samples one container from when it starts until [`UsageSampler::finish`]
+pub(crate) struct UsageSampler {
+    started: Instant,
+    readings: Arc<Mutex<Readings>>,
+    sampling: JoinHandle<()>,
+}
+
+impl UsageSampler {
+    pub fn start(docker: Arc<Docker>, container_id: String) -> Self {
+        let readings = Arc::new(Mutex::new(Readings::default()));
+        let sampling = tokio::spawn({
+            let readings = readings.clone();
+            async move {
+                let mut ticks = tokio::time::interval(SAMPLE_INTERVAL);
+                loop {
+                    ticks.tick().await;
+                    let options = StatsOptions {
+                        stream: false,
+                        one_shot: true,
+                    };
+                    match docker.stats(&container_id, Some(options)).next().await {
+                        Some(Ok(stats)) => readings.lock().unwrap().record(&stats),
+                        // Gone: nothing more to read
+                        Some(Err(_)) | None => return,
+                    }
+                }
+            }
+        });
+        Self {
+            started: Instant::now(),
+            readings,
+            sampling,
+        }
+    }
+
+    /// Stop sampling once the container has exited
+    pub fn finish(self, stdout_bytes: usize) -> ExecutionUsage {
+        self.sampling.abort();
+        let wall_time = self.started.elapsed();
+        self.readings.lock().unwrap().usage(wall_time, stdout_bytes)
+    }
+}
+
+impl Drop for UsageSampler {
+    fn drop(&mut self) {
+        self.sampling.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_cpu_reading_and_the_highest_memory_reading_are_kept() {
+        let mut readings = Readings::default();
+        readings.observe(40_000_000, 8 << 20);
+        readings.observe(250_000_000, 64 << 20);
+        readings.observe(310_000_000, 16 << 20);
+        // After the container exits
+        readings.observe(0, 0);
+
+        let usage = readings.usage(Duration::from_millis(1_500), 12);
+        assert_eq!(
+            usage,
+            ExecutionUsage {
+                wall_time_ms: 1_500,
+                cpu_time_ms: 310,
+                peak_memory_bytes: 64 << 20,
+                stdout_bytes: 12,
+            }
+        );
+    }
+}
@@ -0,0 +1,528 @@
+//! Firecracker rootfs images built around the guest agent
+//!
+//! The image is assembled in a staging directory and written out with `mkfs.ext4 -d`, so
+//! building needs no root, loop devices or mounts:
+//!
+//! 1. the base is unpacked from an Alpine minirootfs tarball or a Docker image's export
+//! 2. the agent lands at [`AGENT_PATH`], either a prebuilt musl binary or one built here
+//!    with `cargo build --target <musl triple>`
+//! 3. `/sbin/init` becomes [`INIT_SCRIPT`], which mounts the virtual filesystems, runs the
+//!    agent and powers the VM off when it exits
+//! 4. the tree is written to `<name>.ext4` of the requested size
+//!
+//! Each build is recorded as a [`RootfsAsset`] in `manifest.json` next to the image, and as
+//! `<name>.ext4.sha256` in `sha256sum` form, which is what
+//! `scripts/prepare_firecracker_assets.sh` verifies downloaded images against.
+//!
+//! Device nodes in the base are skipped (the init script mounts devtmpfs) and files keep
+//! the builder's uid; the guest runs everything as root either way.
+
+use crate::bollard::container::{Config as ContainerConfig, RemoveContainerOptions};
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::image::CreateImageOptions;
+use crate::bollard::Docker;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Where the agent is installed inside the image
+pub const AGENT_PATH: &str = "app/faas-guest-agent";
+
+/// Target the agent is built for when no prebuilt binary is given
+pub const DEFAULT_AGENT_TARGET: &str = "x86_64-unknown-linux-musl";
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// PID 1 of the guest
+pub const INIT_SCRIPT: &str = r#"#!/bin/sh
+# Installed by rootfs-builder: start the FaaS guest agent as the VM's only service
+mount -t proc proc /proc
+mount -t devtmpfs devtmpfs /dev
+mount -t sysfs sysfs /sys
+mount -t tmpfs tmpfs /tmp
+
+if [ -x /app/faas-guest-agent ]; then
+    echo "[INIT] Starting FaaS Guest Agent..."
+    /app/faas-guest-agent
+    echo "[INIT] FaaS Guest Agent exited with code $?. Halting."
+else
+    echo "[INIT] ERROR: /app/faas-guest-agent not found or not executable! Halting."
+fi
+
+poweroff -f || halt -f
+"#;
+
+#[derive(Error, Debug)]
+pub enum RootfsError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Docker API error: {0}")]
+    Docker(#[from] BollardError),
+    #[error("{tool} failed: {detail}")]
+    Tool { tool: String, detail: String },
+    #[error(
+        "Rust target {0} is not installed; run `rustup target add {0}` or pass a prebuilt agent"
+    )]
+    MissingTarget(String),
+    #[error("Invalid rootfs spec: {0}")]
+    Invalid(String),
+}
+
+pub type Result<T> = std::result::Result<T, RootfsError>;
+
+/// The filesystem the agent is added to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootfsBase {
+    /// A minirootfs tarball, gzipped or not
+    Tarball(PathBuf),
+    /// An image reference, pulled if missing and exported from a created container
+    DockerImage(String),
+}
+
+/// Where the guest agent binary comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentSource {
+    /// A static binary built elsewhere, for hosts that can't target musl
+    Prebuilt(PathBuf),
+    /// Build `faas-guest-agent` from the workspace at `workspace`
+    Build { workspace: PathBuf, target: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct RootfsSpec {
+    /// Image file stem and manifest entry name
+    pub name: String,
+    pub base: RootfsBase,
+    pub agent: AgentSource,
+    pub size_mb: u64,
+    pub output_dir: PathBuf,
+}
+
+/// A built image as the asset manifest lists it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootfsAsset {
+    pub name: String,
+    /// File name relative to the manifest
+    pub file: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub assets: Vec<RootfsAsset>,
+}
+
+impl AssetManifest {
+    /// The manifest at `path`, or an empty one if there is none yet
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                RootfsError::Invalid(format!("{} is not a manifest: {e}", path.display()))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Add `asset`, replacing an earlier build of the same name
+    pub fn upsert(&mut self, asset: RootfsAsset) {
+        self.assets.retain(|existing| existing.name != asset.name);
+        self.assets.push(asset);
+        self.assets.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| RootfsError::Invalid(format!("manifest serialization: {e}")))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json)?;
+        std::fs::rename(temp, path)?;
+        Ok(())
+    }
+}
+
+/// Build the image described by `spec` and record it in the output directory's manifest
+pub async fn build(spec: &RootfsSpec) -> Result<RootfsAsset> {
+    let valid_name = !spec.name.is_empty()
+        && spec
+            .name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+    if !valid_name {
+        return Err(RootfsError::Invalid(format!(
+            "image name {:?} must be alphanumeric, '-', '_' or '.'",
+            spec.name
+        )));
+    }
+    if spec.size_mb == 0 {
+        return Err(RootfsError::Invalid(
+            "image size must be at least 1 MB".into(),
+        ));
+    }
+    std::fs::create_dir_all(&spec.output_dir)?;
+    let staging = tempfile::tempdir()?;
+    let root = staging.path().join("root");
+    std::fs::create_dir_all(&root)?;
+
+    match &spec.base {
+        RootfsBase::Tarball(path) => {
+            info!("Unpacking base {}", path.display());
+            let (path, root) = (path.clone(), root.clone());
+            tokio::task::spawn_blocking(move || unpack_tarball(&path, &root))
+                .await
+                .map_err(|e| RootfsError::Invalid(format!("unpack task failed: {e}")))??;
+        }
+        RootfsBase::DockerImage(reference) => {
+            info!("Exporting base image {}", reference);
+            let export = staging.path().join("base.tar");
+            export_image(reference, &export).await?;
+            let root = root.clone();
+            tokio::task::spawn_blocking(move || unpack_tar(std::fs::File::open(&export)?, &root))
+                .await
+                .map_err(|e| RootfsError::Invalid(format!("unpack task failed: {e}")))??;
+        }
+    }
+
+    let agent = agent_binary(&spec.agent)?;
+    install(&root, AGENT_PATH, &std::fs::read(&agent)?)?;
+    install(&root, "sbin/init", INIT_SCRIPT.as_bytes())?;
+
+    let file = format!("{}.ext4", spec.name);
+    let image = spec.output_dir.join(&file);
+    make_ext4(&root, &image, spec.size_mb)?;
+
+    let asset = RootfsAsset {
+        name: spec.name.clone(),
+        sha256: sha256_file(&image)?,
+        size_bytes: std::fs::metadata(&image)?.len(),
+        file,
+    };
+    std::fs::write(
+        spec.output_dir.join(format!("{}.sha256", asset.file)),
+        format!("{}  {}\n", asset.sha256, asset.file),
+    )?;
+    let manifest_path = spec.output_dir.join(MANIFEST_FILE);
+    let mut manifest = AssetManifest::load(&manifest_path)?;
+    manifest.upsert(asset.clone());
+    manifest.save(&manifest_path)?;
+    info!(
+        "Built rootfs {} ({} bytes, sha256 {})",
+        image.display(),
+        asset.size_bytes,
+        asset.sha256
+    );
+    Ok(asset)
+}
+
+fn unpack_tarball(path: &Path, root: &Path) -> Result<()> {
+    let mut file = std::fs::File::open(path)?;
+    let mut magic = [0u8; 2];
+    let gzipped = file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+    let file = std::fs::File::open(path)?;
+    if gzipped {
+        unpack_tar(flate2::read::GzDecoder::new(file), root)
+    } else {
+        unpack_tar(file, root)
+    }
+}
+
+/// Unpack everything but device nodes and FIFOs, which need root to create
+fn unpack_tar<R: Read>(reader: R, root: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_overwrite(true);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if kind.is_character_special() || kind.is_block_special() || kind.is_fifo() {
+            continue;
+        }
+        entry.unpack_in(root)?;
+    }
+    Ok(())
+}
+
+/// Write a container's filesystem for `reference` to `dest` as a tar
+async fn export_image(reference: &str, dest: &Path) -> Result<()> {
+    let docker = Docker::connect_with_local_defaults()?;
+    docker
+        .create_image(
+            Some(CreateImageOptions {
+                from_image: reference,
+                ..Default::default()
+            }),
+            None,
+            None,
+        )
+        .try_collect::<Vec<_>>()
+        .await?;
+    // Never started; the command only has to exist for images without one
+    let container = docker
+        .create_container::<String, String>(
+            None,
+            ContainerConfig {
+                image: Some(reference.to_string()),
+                cmd: Some(vec!["/bin/true".to_string()]),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let exported = async {
+        let mut file = std::fs::File::create(dest)?;
+        let mut stream = docker.export_container(&container.id);
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?)?;
+        }
+        file.flush()?;
+        Ok::<_, RootfsError>(())
+    }
+    .await;
+
+    let remove = RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    };
+    if let Err(e) = docker.remove_container(&container.id, Some(remove)).await {
+        warn!("Failed to remove export container {}: {}", container.id, e);
+    }
+    exported
+}
+
+/// The agent binary to install, building it first if asked to
+fn agent_binary(source: &AgentSource) -> Result<PathBuf> {
+    match source {
+        AgentSource::Prebuilt(path) => {
+            if !path.is_file() {
+                return Err(RootfsError::Invalid(format!(
+                    "prebuilt agent {} does not exist",
+                    path.display()
+                )));
+            }
+            Ok(path.clone())
+        }
+        AgentSource::Build { workspace, target } => {
+            // Without rustup the toolchain is someone else's to manage; let cargo report it
+            if let Ok(output) = Command::new("rustup")
+                .args(["target", "list", "--installed"])
+                .output()
+            {
+                let installed = String::from_utf8_lossy(&output.stdout);
+                if output.status.success() && !installed.lines().any(|line| line == target) {
+                    return Err(RootfsError::MissingTarget(target.clone()));
+                }
+            }
+            info!("Building faas-guest-agent for {}", target);
+            run(Command::new("cargo")
+                .current_dir(workspace)
+                .args(["build", "--release", "-p", "faas-guest-agent", "--target"])
+                .arg(target))?;
+            Ok(workspace
+                .join("target")
+                .join(target)
+                .join("release")
+                .join("faas-guest-agent"))
+        }
+    }
+}
+
+/// Write an executable at `relative` under `root`, replacing whatever was there
+fn install(root: &Path, relative: &str, contents: &[u8]) -> Result<()> {
+    let path = root.join(relative);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Alpine's /sbin/init is a symlink into busybox; replace the link, not its target
+    if path.symlink_metadata().is_ok() {
+        std::fs::remove_file(&path)?;
+    }
+    std::fs::write(&path, contents)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+fn make_ext4(root: &Path, image: &Path, size_mb: u64) -> Result<()> {
+    if image.exists() {
+        std::fs::remove_file(image)?;
+    }
+    run(Command::new("mkfs.ext4")
+        .args(["-q", "-F", "-d"])
+        .arg(root)
+        .arg(image)
+        .arg(format!("{size_mb}M")))
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|e| RootfsError::Tool {
+        tool: tool.clone(),
+        detail: e.to_string(),
+    })?;
+    if !output.status.success() {
+        return Err(RootfsError::Tool {
+            tool,
+            detail: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot_inspect::ext4::Ext4Image;
+    use crate::snapshot_inspect::EntryKind;
+    use crate::test_utils::has_mkfs_ext4;
+
+    /// A gzipped stand-in for an Alpine minirootfs, device node included
+    fn minirootfs(dir: &Path) -> PathBuf {
+        let path = dir.join("alpine-minirootfs.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            std::fs::File::create(&path).unwrap(),
+            flate2::Compression::fast(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut add = |path: &str, kind: tar::EntryType, data: &[u8], link: Option<&str>| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_mode(if kind.is_dir() { 0o755 } else { 0o644 });
+            header.set_size(data.len() as u64);
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            header.set_cksum();
+            tar.append_data(&mut header, path, data).unwrap();
+        };
+        add("bin/", tar::EntryType::Directory, b"", None);
+        add("bin/busybox", tar::EntryType::Regular, b"busybox", None);
+        add("etc/", tar::EntryType::Directory, b"", None);
+        add(
+            "etc/alpine-release",
+            tar::EntryType::Regular,
+            b"3.20.3\n",
+            None,
+        );
+        add("sbin/", tar::EntryType::Directory, b"", None);
+        add(
+            "sbin/init",
+            tar::EntryType::Symlink,
+            b"",
+            Some("/bin/busybox"),
+        );
+        add("dev/", tar::EntryType::Directory, b"", None);
+        add("dev/console", tar::EntryType::Char, b"", None);
+        tar.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn image_carries_agent_init_and_base_and_is_in_the_manifest() {
+        if !has_mkfs_ext4() {
+            eprintln!("Skipping rootfs test - mkfs.ext4 not available");
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let agent = dir.path().join("faas-guest-agent");
+        std::fs::write(&agent, b"\x7fELF stand-in agent").unwrap();
+        let output_dir = dir.path().join("out");
+        let spec = RootfsSpec {
+            name: "alpine-agent".to_string(),
+            base: RootfsBase::Tarball(minirootfs(dir.path())),
+            agent: AgentSource::Prebuilt(agent),
+            size_mb: 16,
+            output_dir: output_dir.clone(),
+        };
+
+        let asset = build(&spec).await.unwrap();
+        assert_eq!(asset.file, "alpine-agent.ext4");
+        assert_eq!(asset.size_bytes, 16 * 1024 * 1024);
+        let image = output_dir.join(&asset.file);
+        assert_eq!(asset.sha256, sha256_file(&image).unwrap());
+
+        let fs = Ext4Image::open(&image).unwrap();
+        assert_eq!(
+            fs.read_file("/app/faas-guest-agent", 1024).unwrap(),
+            b"\x7fELF stand-in agent"
+        );
+        assert_eq!(
+            fs.read_file("/sbin/init", 4096).unwrap(),
+            INIT_SCRIPT.as_bytes()
+        );
+        assert_eq!(
+            fs.read_file("/etc/alpine-release", 64).unwrap(),
+            b"3.20.3\n"
+        );
+        let app = fs.list("/app", 1).unwrap();
+        let installed = &app.entries[0];
+        assert_eq!((installed.kind, installed.mode), (EntryKind::File, 0o755));
+        let sbin = fs.list("/sbin", 1).unwrap();
+        assert_eq!(
+            sbin.entries[0].kind,
+            EntryKind::File,
+            "the busybox link is replaced"
+        );
+        assert!(fs.list("/dev", 1).unwrap().entries.is_empty());
+
+        assert_eq!(
+            std::fs::read_to_string(output_dir.join("alpine-agent.ext4.sha256")).unwrap(),
+            format!("{}  alpine-agent.ext4\n", asset.sha256)
+        );
+        let manifest = AssetManifest::load(&output_dir.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(manifest.assets, [asset.clone()]);
+
+        // Rebuilding under the same name replaces the entry instead of adding one
+        let rebuilt = build(&RootfsSpec {
+            size_mb: 8,
+            ..spec.clone()
+        })
+        .await
+        .unwrap();
+        let other = build(&RootfsSpec {
+            name: "second".to_string(),
+            ..spec
+        })
+        .await
+        .unwrap();
+        let manifest = AssetManifest::load(&output_dir.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(manifest.assets, [rebuilt, other]);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_missing_agent_and_a_bad_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = RootfsSpec {
+            name: "rootfs".to_string(),
+            base: RootfsBase::Tarball(minirootfs(dir.path())),
+            agent: AgentSource::Prebuilt(dir.path().join("missing")),
+            size_mb: 8,
+            output_dir: dir.path().join("out"),
+        };
+        assert!(matches!(
+            build(&spec).await,
+            Err(RootfsError::Invalid(message)) if message.contains("does not exist")
+        ));
+        let bad_name = RootfsSpec {
+            name: "../escape".to_string(),
+            ..spec
+        };
+        assert!(matches!(
+            build(&bad_name).await,
+            Err(RootfsError::Invalid(_))
+        ));
+        assert!(!dir.path().join("out").join(MANIFEST_FILE).exists());
+    }
+}
@@ -0,0 +1,130 @@
+//! Executions in flight, by id, so one can be stopped before it finishes.
+//!
+//! A container or VM run holds a [`RunToken`] for its execution id until it ends.
+//! [`RunningExecutions::cancel`] trips every token held under the id, and each run then
+//! tears its sandbox down itself: the container is force-removed and its attach streams
+//! closed, the VM is released, and the run returns [`crate::ExecutorError::Cancelled`].
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Default)]
+pub struct RunningExecutions {
+    /// One token per id, shared by every run under it, with how many hold it
+    tokens: Mutex<HashMap<String, (CancellationToken, usize)>>,
+}
+
+impl RunningExecutions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold a token for `id` until the returned guard drops; an id cancelled while nothing
+    /// held it is not remembered, so the run starts untripped
+    pub fn track(self: &Arc<Self>, id: &str) -> RunToken {
+        let mut tokens = self.tokens.lock().unwrap();
+        let (token, holders) = tokens.entry(id.to_string()).or_default();
+        *holders += 1;
+        RunToken {
+            registry: self.clone(),
+            id: id.to_string(),
+            token: token.clone(),
+        }
+    }
+
+    /// Trip the runs under `id`; false when none is running
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.tokens.lock().unwrap().get(id) {
+            Some((token, _)) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Trip every run, returning the ids they were under
+    pub fn cancel_all(&self) -> Vec<String> {
+        let tokens = self.tokens.lock().unwrap();
+        for (token, _) in tokens.values() {
+            token.cancel();
+        }
+        tokens.keys().cloned().collect()
+    }
+
+    pub fn is_running(&self, id: &str) -> bool {
+        self.tokens.lock().unwrap().contains_key(id)
+    }
+}
+
+/// A run's hold on its execution id; see [`RunningExecutions::track`]
+pub struct RunToken {
+    registry: Arc<RunningExecutions>,
+    id: String,
+    token: CancellationToken,
+}
+
+impl RunToken {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for RunToken {
+    fn drop(&mut self) {
+        let mut tokens = self.registry.tokens.lock().unwrap();
+        if let Some((_, holders)) = tokens.get_mut(&self.id) {
+            *holders -= 1;
+            if *holders == 0 {
+                tokens.remove(&self.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_trips_every_run_under_the_id() {
+        let running = Arc::new(RunningExecutions::new());
+        let docker = running.track("exec-1");
+        let vm = running.track("exec-1");
+        let other = running.track("exec-2");
+
+        assert!(running.cancel("exec-1"));
+        assert!(docker.token().is_cancelled());
+        assert!(vm.token().is_cancelled());
+        assert!(!other.token().is_cancelled());
+        assert!(!running.cancel("exec-3"));
+    }
+
+    #[test]
+    fn cancel_all_trips_every_run() {
+        let running = Arc::new(RunningExecutions::new());
+        let first = running.track("exec-1");
+        let second = running.track("exec-2");
+
+        let mut cancelled = running.cancel_all();
+        cancelled.sort();
+        assert_eq!(cancelled, ["exec-1", "exec-2"]);
+        assert!(first.token().is_cancelled());
+        assert!(second.token().is_cancelled());
+    }
+
+    #[test]
+    fn ids_are_forgotten_once_their_last_run_ends() {
+        let running = Arc::new(RunningExecutions::new());
+        let first = running.track("exec-1");
+        let second = running.track("exec-1");
+        drop(first);
+        assert!(running.is_running("exec-1"));
+        drop(second);
+        assert!(!running.is_running("exec-1"));
+
+        running.cancel("exec-1");
+        assert!(!running.track("exec-1").token().is_cancelled());
+    }
+}
@@ -0,0 +1,466 @@
+//! Minimal read-only ext4 reader for Firecracker disk images
+//! Walks directories and reads file contents straight from the image file, so a snapshot's
+//! rootfs can be browsed without mounting it (no root, no loop devices).
+
+use super::{join_path, normalize_path, EntryKind, InspectError, Listing, Result, SnapshotEntry};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT4_MAGIC: u16 = 0xEF53;
+const EXTENT_MAGIC: u16 = 0xF30A;
+const INCOMPAT_64BIT: u32 = 0x80;
+const EXTENTS_FL: u32 = 0x80000;
+const INLINE_DATA_FL: u32 = 0x1000_0000;
+const ROOT_INODE: u32 = 2;
+/// Fast symlinks and inline data live in the 60-byte `i_block` area
+const INLINE_BYTES: u64 = 60;
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+#[derive(Debug, Clone)]
+struct Inode {
+    mode: u16,
+    size: u64,
+    mtime: i64,
+    flags: u32,
+    block: [u8; 60],
+}
+
+impl Inode {
+    fn kind(&self) -> EntryKind {
+        match self.mode & 0xF000 {
+            0x4000 => EntryKind::Directory,
+            0x8000 => EntryKind::File,
+            0xA000 => EntryKind::Symlink,
+            _ => EntryKind::Other,
+        }
+    }
+
+    /// Data stored in `i_block` itself rather than in data blocks
+    fn is_inline(&self) -> bool {
+        self.flags & INLINE_DATA_FL != 0
+            || (self.kind() == EntryKind::Symlink
+                && self.flags & EXTENTS_FL == 0
+                && self.size < INLINE_BYTES)
+    }
+}
+
+/// A contiguous run of file blocks; `physical == None` means a hole or unwritten extent
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    logical: u64,
+    physical: Option<u64>,
+    len: u64,
+}
+
+pub struct Ext4Image {
+    file: File,
+    block_size: u64,
+    inodes_per_group: u64,
+    inode_size: u64,
+    desc_size: u64,
+    desc_table_block: u64,
+}
+
+impl Ext4Image {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut sb = [0u8; 1024];
+        file.read_exact_at(&mut sb, SUPERBLOCK_OFFSET)
+            .map_err(|_| InspectError::Unsupported(format!("{} is not ext4", path.display())))?;
+        if u16_at(&sb, 56) != EXT4_MAGIC {
+            return Err(InspectError::Unsupported(format!(
+                "{} is not an ext2/3/4 image",
+                path.display()
+            )));
+        }
+
+        let block_size = 1024u64 << u32_at(&sb, 24);
+        let rev_level = u32_at(&sb, 76);
+        let inode_size = if rev_level >= 1 {
+            u16_at(&sb, 88) as u64
+        } else {
+            128
+        };
+        let desc_size = if u32_at(&sb, 96) & INCOMPAT_64BIT != 0 {
+            (u16_at(&sb, 254) as u64).max(32)
+        } else {
+            32
+        };
+
+        Ok(Self {
+            file,
+            block_size,
+            inodes_per_group: u32_at(&sb, 40) as u64,
+            inode_size,
+            desc_size,
+            desc_table_block: u32_at(&sb, 20) as u64 + 1,
+        })
+    }
+
+    /// List `path`, descending up to `depth` levels (1 = direct children)
+    pub fn list(&self, path: &str, depth: usize) -> Result<Listing> {
+        let path = normalize_path(path)?;
+        let inode = self.read_inode(self.resolve(&path)?)?;
+        let mut listing = Listing::default();
+        if inode.kind() != EntryKind::Directory {
+            let name = path.rsplit('/').next().unwrap_or_default().to_string();
+            listing.push(entry_for(path, name, &inode));
+            return Ok(listing);
+        }
+
+        let mut queue = VecDeque::from([(path, inode, 1usize)]);
+        'walk: while let Some((dir_path, dir, level)) = queue.pop_front() {
+            for (name, number) in self.read_dir(&dir)? {
+                let child = self.read_inode(number)?;
+                let child_path = join_path(&dir_path, &name);
+                if child.kind() == EntryKind::Directory && level < depth {
+                    queue.push_back((child_path.clone(), child.clone(), level + 1));
+                }
+                if !listing.push(entry_for(child_path, name, &child)) {
+                    break 'walk;
+                }
+            }
+        }
+
+        listing.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(listing)
+    }
+
+    /// Read a regular file, refusing anything above `limit` bytes
+    pub fn read_file(&self, path: &str, limit: u64) -> Result<Vec<u8>> {
+        let path = normalize_path(path)?;
+        let inode = self.read_inode(self.resolve(&path)?)?;
+        if inode.kind() != EntryKind::File {
+            return Err(InspectError::NotAFile(path));
+        }
+        if inode.size > limit {
+            return Err(InspectError::TooLarge {
+                path,
+                size: inode.size,
+                limit,
+            });
+        }
+        self.read_data(&inode)
+    }
+
+    fn resolve(&self, path: &str) -> Result<u32> {
+        let mut number = ROOT_INODE;
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            let inode = self.read_inode(number)?;
+            if inode.kind() != EntryKind::Directory {
+                return Err(InspectError::NotFound(path.to_string()));
+            }
+            number = self
+                .read_dir(&inode)?
+                .into_iter()
+                .find(|(name, _)| name == part)
+                .map(|(_, number)| number)
+                .ok_or_else(|| InspectError::NotFound(path.to_string()))?;
+        }
+        Ok(number)
+    }
+
+    fn read_dir(&self, inode: &Inode) -> Result<Vec<(String, u32)>> {
+        let data = self.read_data(inode)?;
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        // Hash-indexed directories still hold plain entries; index nodes look like
+        // deleted entries (inode 0) and get skipped.
+        while offset + 8 <= data.len() {
+            let number = u32_at(&data, offset);
+            let rec_len = u16_at(&data, offset + 4) as usize;
+            let name_len = data[offset + 6] as usize;
+            if rec_len < 8 {
+                break;
+            }
+            if number != 0 && name_len > 0 && offset + 8 + name_len <= data.len() {
+                let name = String::from_utf8_lossy(&data[offset + 8..offset + 8 + name_len]);
+                if name != "." && name != ".." {
+                    entries.push((name.into_owned(), number));
+                }
+            }
+            offset += rec_len;
+        }
+        Ok(entries)
+    }
+
+    fn read_inode(&self, number: u32) -> Result<Inode> {
+        let index = number as u64 - 1;
+        let group = index / self.inodes_per_group;
+
+        let mut desc = vec![0u8; self.desc_size as usize];
+        self.file.read_exact_at(
+            &mut desc,
+            self.desc_table_block * self.block_size + group * self.desc_size,
+        )?;
+        let mut table = u32_at(&desc, 8) as u64;
+        if self.desc_size >= 64 {
+            table |= (u32_at(&desc, 0x28) as u64) << 32;
+        }
+
+        let mut raw = [0u8; 128];
+        self.file.read_exact_at(
+            &mut raw,
+            table * self.block_size + (index % self.inodes_per_group) * self.inode_size,
+        )?;
+        Ok(Inode {
+            mode: u16_at(&raw, 0),
+            size: u32_at(&raw, 4) as u64 | (u32_at(&raw, 0x6C) as u64) << 32,
+            mtime: u32_at(&raw, 0x10) as i64,
+            flags: u32_at(&raw, 0x20),
+            block: raw[0x28..0x64].try_into().unwrap(),
+        })
+    }
+
+    fn read_data(&self, inode: &Inode) -> Result<Vec<u8>> {
+        if inode.is_inline() {
+            if inode.size > INLINE_BYTES {
+                return Err(InspectError::Unsupported(
+                    "inline data spilling into xattrs".to_string(),
+                ));
+            }
+            return Ok(inode.block[..inode.size as usize].to_vec());
+        }
+
+        let mut data = vec![0u8; inode.size as usize];
+        let blocks = inode.size.div_ceil(self.block_size);
+        for run in self.runs(inode, blocks)? {
+            let Some(physical) = run.physical else {
+                continue;
+            };
+            let start = run.logical * self.block_size;
+            if start >= inode.size {
+                continue;
+            }
+            let end = ((run.logical + run.len) * self.block_size).min(inode.size);
+            self.file.read_exact_at(
+                &mut data[start as usize..end as usize],
+                physical * self.block_size,
+            )?;
+        }
+        Ok(data)
+    }
+
+    fn read_block(&self, block: u64) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; self.block_size as usize];
+        self.file.read_exact_at(&mut buf, block * self.block_size)?;
+        Ok(buf)
+    }
+
+    fn runs(&self, inode: &Inode, blocks: u64) -> Result<Vec<Run>> {
+        let mut runs = Vec::new();
+        if inode.flags & EXTENTS_FL != 0 {
+            self.extent_runs(&inode.block, &mut runs)?;
+        } else {
+            let pointers: Vec<u32> = (0..15).map(|i| u32_at(&inode.block, i * 4)).collect();
+            let mut logical = 0;
+            for &pointer in &pointers[..12] {
+                push_block(&mut runs, &mut logical, pointer);
+            }
+            for (level, &pointer) in pointers[12..].iter().enumerate() {
+                self.indirect_runs(pointer, level as u32 + 1, blocks, &mut logical, &mut runs)?;
+            }
+        }
+        Ok(runs)
+    }
+
+    fn extent_runs(&self, node: &[u8], runs: &mut Vec<Run>) -> Result<()> {
+        if u16_at(node, 0) != EXTENT_MAGIC {
+            return Err(InspectError::Unsupported("corrupt extent tree".to_string()));
+        }
+        let entries = u16_at(node, 2) as usize;
+        let depth = u16_at(node, 6);
+        for i in 0..entries {
+            let entry = &node[12 + i * 12..24 + i * 12];
+            if depth == 0 {
+                let mut len = u16_at(entry, 4) as u64;
+                // Lengths above 32768 mark unwritten (preallocated, reads as zero) extents.
+                let unwritten = len > 32768;
+                if unwritten {
+                    len -= 32768;
+                }
+                let start = (u16_at(entry, 6) as u64) << 32 | u32_at(entry, 8) as u64;
+                runs.push(Run {
+                    logical: u32_at(entry, 0) as u64,
+                    physical: (!unwritten).then_some(start),
+                    len,
+                });
+            } else {
+                let leaf = u32_at(entry, 4) as u64 | (u16_at(entry, 8) as u64) << 32;
+                self.extent_runs(&self.read_block(leaf)?, runs)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn indirect_runs(
+        &self,
+        pointer: u32,
+        level: u32,
+        blocks: u64,
+        logical: &mut u64,
+        runs: &mut Vec<Run>,
+    ) -> Result<()> {
+        let per_block = self.block_size / 4;
+        if *logical >= blocks {
+            return Ok(());
+        }
+        if pointer == 0 {
+            *logical += per_block.pow(level);
+            return Ok(());
+        }
+        let block = self.read_block(pointer as u64)?;
+        for i in 0..per_block as usize {
+            let child = u32_at(&block, i * 4);
+            if level == 1 {
+                push_block(runs, logical, child);
+            } else {
+                self.indirect_runs(child, level - 1, blocks, logical, runs)?;
+            }
+            if *logical >= blocks {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_block(runs: &mut Vec<Run>, logical: &mut u64, pointer: u32) {
+    if pointer != 0 {
+        runs.push(Run {
+            logical: *logical,
+            physical: Some(pointer as u64),
+            len: 1,
+        });
+    }
+    *logical += 1;
+}
+
+fn entry_for(path: String, name: String, inode: &Inode) -> SnapshotEntry {
+    let kind = inode.kind();
+    SnapshotEntry {
+        path,
+        name,
+        size: if kind == EntryKind::Directory {
+            0
+        } else {
+            inode.size
+        },
+        mode: (inode.mode & 0o7777) as u32,
+        mtime: inode.mtime,
+        kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::has_mkfs_ext4;
+    use std::process::Command;
+
+    /// Build an image from a known tree; `extra` picks e.g. a non-extent ext2 layout.
+    fn build_image(extra: &[&str]) -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(root.join("models/bert")).unwrap();
+        std::fs::write(root.join("models/bert/config.json"), b"{\"layers\":12}").unwrap();
+        let weights: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(root.join("models/bert/weights.bin"), &weights).unwrap();
+        std::fs::write(root.join("README"), b"hello from the snapshot\n").unwrap();
+        for i in 0..200 {
+            std::fs::write(root.join(format!("models/file-{i:03}")), b"x").unwrap();
+        }
+        std::os::unix::fs::symlink("models/bert", root.join("latest")).unwrap();
+
+        let image = dir.path().join("rootfs.ext4");
+        let output = Command::new("mkfs.ext4")
+            .args(["-q", "-F", "-d"])
+            .arg(&root)
+            .args(extra)
+            .arg(&image)
+            .arg("8M")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        (dir, image)
+    }
+
+    #[test]
+    fn lists_and_reads_an_ext4_image() {
+        if !has_mkfs_ext4() {
+            eprintln!("Skipping ext4 test - mkfs.ext4 not available");
+            return;
+        }
+        let (_dir, image) = build_image(&[]);
+        let fs = Ext4Image::open(&image).unwrap();
+
+        let top = fs.list("/", 1).unwrap();
+        let names: Vec<_> = top.entries.iter().map(|e| e.name.as_str()).collect();
+        assert!(names.contains(&"README") && names.contains(&"models"));
+        assert!(!top.entries.iter().any(|e| e.path.starts_with("/models/")));
+        let latest = top.entries.iter().find(|e| e.name == "latest").unwrap();
+        assert_eq!(latest.kind, EntryKind::Symlink);
+
+        let bert = fs.list("/models/bert", 1).unwrap();
+        let weights = bert
+            .entries
+            .iter()
+            .find(|e| e.path == "/models/bert/weights.bin")
+            .unwrap();
+        assert_eq!(weights.size, 200_000);
+        assert_eq!(weights.kind, EntryKind::File);
+
+        // 200 files push /models past one directory block.
+        let deep = fs.list("/models", 2).unwrap();
+        assert_eq!(deep.entries.len(), 200 + 1 + 2);
+
+        assert_eq!(
+            fs.read_file("/README", 1024).unwrap(),
+            b"hello from the snapshot\n"
+        );
+        let data = fs.read_file("/models/bert/weights.bin", 1 << 20).unwrap();
+        assert!(data.iter().enumerate().all(|(i, b)| *b == (i % 251) as u8));
+        assert!(matches!(
+            fs.read_file("/models/bert/weights.bin", 1024),
+            Err(InspectError::TooLarge { size: 200_000, .. })
+        ));
+        assert!(matches!(
+            fs.read_file("/models/missing", 1024),
+            Err(InspectError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn reads_block_mapped_files() {
+        if !has_mkfs_ext4() {
+            eprintln!("Skipping ext4 test - mkfs.ext4 not available");
+            return;
+        }
+        // ext2-style layout: no extents, so large files go through indirect blocks.
+        let (_dir, image) = build_image(&["-O", "^extent,^64bit,^flex_bg", "-b", "1024"]);
+        let fs = Ext4Image::open(&image).unwrap();
+        let data = fs.read_file("/models/bert/weights.bin", 1 << 20).unwrap();
+        assert_eq!(data.len(), 200_000);
+        assert!(data.iter().enumerate().all(|(i, b)| *b == (i % 251) as u8));
+    }
+
+    #[test]
+    fn rejects_non_ext4_images() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), vec![0u8; 4096]).unwrap();
+        assert!(matches!(
+            Ext4Image::open(file.path()),
+            Err(InspectError::Unsupported(_))
+        ));
+    }
+}
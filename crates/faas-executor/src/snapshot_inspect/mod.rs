@@ -0,0 +1,376 @@
+//! Read-only inspection of snapshot filesystems
+//! Lists and reads files inside Docker-commit images and Firecracker disk images without
+//! starting a process or restoring the snapshot.
+
+pub mod ext4;
+
+use crate::bollard::container::{
+    Config as ContainerConfig, DownloadFromContainerOptions, RemoveContainerOptions,
+};
+use crate::bollard::errors::Error as BollardError;
+use crate::bollard::Docker;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+use std::path::{Component, Path};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Largest listing returned in one call; deeper trees come back with `truncated` set
+pub const MAX_LIST_ENTRIES: usize = 10_000;
+
+#[derive(Error, Debug)]
+pub enum InspectError {
+    #[error("Path not found in snapshot: {0}")]
+    NotFound(String),
+    #[error("Not a regular file: {0}")]
+    NotAFile(String),
+    #[error("{path} is {size} bytes, above the {limit} byte limit")]
+    TooLarge { path: String, size: u64, limit: u64 },
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+    #[error("Unsupported snapshot filesystem: {0}")]
+    Unsupported(String),
+    #[error("Docker API error: {0}")]
+    Docker(#[from] BollardError),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, InspectError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Absolute path inside the snapshot
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+    /// Permission bits, e.g. `0o644`
+    pub mode: u32,
+    /// Seconds since the Unix epoch
+    pub mtime: i64,
+    #[serde(rename = "type")]
+    pub kind: EntryKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listing {
+    pub entries: Vec<SnapshotEntry>,
+    pub truncated: bool,
+}
+
+impl Listing {
+    /// Add an entry, returning `false` once the listing is full
+    fn push(&mut self, entry: SnapshotEntry) -> bool {
+        if self.entries.len() >= MAX_LIST_ENTRIES {
+            self.truncated = true;
+            return false;
+        }
+        self.entries.push(entry);
+        true
+    }
+}
+
+/// Normalize a user-supplied path to `/a/b` form, rejecting anything that climbs out
+pub fn normalize_path(path: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(InspectError::InvalidPath(path.to_string()))
+            }
+        }
+    }
+    Ok(format!("/{}", parts.join("/")))
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent == "/" {
+        format!("/{name}")
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+/// List a tar stream as produced by Docker's archive API for `root`
+///
+/// Docker names entries relative to the parent of the requested path, so everything is
+/// re-rooted under `root`; entries deeper than `depth` levels below it are skipped.
+pub fn list_tar<R: Read>(archive: R, root: &str, depth: usize) -> Result<Listing> {
+    let root = normalize_path(root)?;
+    let mut listing = Listing::default();
+    let mut archive = tar::Archive::new(archive);
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut components: Vec<&str> = name
+            .split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .collect();
+        if root != "/" && !components.is_empty() {
+            // Drop the requested directory's own name.
+            components.remove(0);
+        }
+        if components.is_empty() || components.len() > depth {
+            continue;
+        }
+
+        let header = entry.header();
+        let kind = match header.entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+            tar::EntryType::Directory => EntryKind::Directory,
+            tar::EntryType::Symlink | tar::EntryType::Link => EntryKind::Symlink,
+            _ => EntryKind::Other,
+        };
+        let relative = components.join("/");
+        let entry = SnapshotEntry {
+            path: join_path(&root, &relative),
+            name: components.last().copied().unwrap_or_default().to_string(),
+            size: if kind == EntryKind::Directory {
+                0
+            } else {
+                header.size()?
+            },
+            mode: header.mode()? & 0o7777,
+            mtime: header.mtime()? as i64,
+            kind,
+        };
+        if !listing.push(entry) {
+            break;
+        }
+    }
+
+    listing.entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(listing)
+}
+
+/// Read the single file in a Docker archive of one path, refusing files above `limit`
+pub fn read_tar_file<R: Read>(archive: R, path: &str, limit: u64) -> Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(archive);
+    let mut entry = archive
+        .entries()?
+        .next()
+        .ok_or_else(|| InspectError::NotFound(path.to_string()))??;
+
+    if !matches!(
+        entry.header().entry_type(),
+        tar::EntryType::Regular | tar::EntryType::Continuous
+    ) {
+        return Err(InspectError::NotAFile(path.to_string()));
+    }
+    let size = entry.header().size()?;
+    if size > limit {
+        return Err(InspectError::TooLarge {
+            path: path.to_string(),
+            size,
+            limit,
+        });
+    }
+
+    let mut data = Vec::with_capacity(size as usize);
+    entry.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Browses committed snapshot images through a created-but-never-started container
+pub struct DockerImageInspector {
+    docker: Arc<Docker>,
+}
+
+impl DockerImageInspector {
+    pub fn new(docker: Arc<Docker>) -> Self {
+        Self { docker }
+    }
+
+    pub async fn list(&self, image: &str, path: &str, depth: usize) -> Result<Listing> {
+        let path = normalize_path(path)?;
+        // Spool to disk; listing "/" of a large image must not sit in memory.
+        let mut spool = tempfile::tempfile()?;
+
+        let container = self.create_container(image).await?;
+        let result = async {
+            let mut stream = self.docker.download_from_container(
+                &container,
+                Some(DownloadFromContainerOptions { path: &path }),
+            );
+            while let Some(chunk) = stream.next().await {
+                spool.write_all(&chunk.map_err(|e| docker_error(e, &path))?)?;
+            }
+            Ok::<_, InspectError>(())
+        }
+        .await;
+        self.remove_container(&container).await;
+        result?;
+
+        spool.rewind()?;
+        tokio::task::spawn_blocking(move || list_tar(spool, &path, depth))
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+    }
+
+    pub async fn cat(&self, image: &str, path: &str, limit: u64) -> Result<Vec<u8>> {
+        let path = normalize_path(path)?;
+        let mut archive = Vec::new();
+
+        let container = self.create_container(image).await?;
+        let result = async {
+            let mut stream = self.docker.download_from_container(
+                &container,
+                Some(DownloadFromContainerOptions { path: &path }),
+            );
+            while let Some(chunk) = stream.next().await {
+                archive.extend_from_slice(&chunk.map_err(|e| docker_error(e, &path))?);
+                // The tar header carries the size, so stop once the file is clearly too big.
+                if archive.len() as u64 > limit + 64 * 1024 {
+                    break;
+                }
+            }
+            Ok::<_, InspectError>(())
+        }
+        .await;
+        self.remove_container(&container).await;
+        result?;
+
+        read_tar_file(archive.as_slice(), &path, limit)
+    }
+
+    async fn create_container(&self, image: &str) -> Result<String> {
+        let config = ContainerConfig {
+            image: Some(image.to_string()),
+            // Never run; Docker only needs a command to accept the create.
+            cmd: Some(vec!["true".to_string()]),
+            network_disabled: Some(true),
+            labels: Some(HashMap::from([(
+                "faas.purpose".to_string(),
+                "snapshot-inspect".to_string(),
+            )])),
+            ..Default::default()
+        };
+        let container = self
+            .docker
+            .create_container::<String, String>(None, config)
+            .await
+            .map_err(|e| docker_error(e, image))?;
+        debug!("Inspecting image {} via container {}", image, container.id);
+        Ok(container.id)
+    }
+
+    async fn remove_container(&self, container: &str) {
+        if let Err(e) = self
+            .docker
+            .remove_container(
+                container,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await
+        {
+            warn!("Failed to remove inspection container {}: {}", container, e);
+        }
+    }
+}
+
+/// Docker answers 404 both for a missing image and a missing path inside it
+fn docker_error(e: BollardError, missing: &str) -> InspectError {
+    match e {
+        BollardError::DockerResponseServerError {
+            status_code: 404, ..
+        } => InspectError::NotFound(missing.to_string()),
+        e => InspectError::Docker(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            if path.ends_with('/') {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+            } else {
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(0o644);
+                header.set_size(data.len() as u64);
+            }
+            header.set_mtime(1_700_000_000);
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn lists_docker_archive_relative_to_requested_path() {
+        let data = archive(&[
+            ("models/", b""),
+            ("models/bert/", b""),
+            ("models/bert/weights.bin", &[0u8; 300]),
+            ("models/README", b"hello"),
+        ]);
+
+        let shallow = list_tar(data.as_slice(), "/models", 1).unwrap();
+        let paths: Vec<_> = shallow.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/models/README", "/models/bert"]);
+        assert_eq!(shallow.entries[1].kind, EntryKind::Directory);
+
+        let deep = list_tar(data.as_slice(), "/models", 2).unwrap();
+        let weights = deep
+            .entries
+            .iter()
+            .find(|e| e.path == "/models/bert/weights.bin")
+            .unwrap();
+        assert_eq!(weights.size, 300);
+        assert_eq!(weights.mode, 0o644);
+        assert_eq!(weights.mtime, 1_700_000_000);
+        assert_eq!(weights.name, "weights.bin");
+    }
+
+    #[test]
+    fn reads_small_files_and_rejects_large_ones() {
+        let data = archive(&[("README", b"hello")]);
+        assert_eq!(
+            read_tar_file(data.as_slice(), "/README", 1024).unwrap(),
+            b"hello"
+        );
+        assert!(matches!(
+            read_tar_file(data.as_slice(), "/README", 4),
+            Err(InspectError::TooLarge { size: 5, .. })
+        ));
+
+        let dir = archive(&[("models/", b"")]);
+        assert!(matches!(
+            read_tar_file(dir.as_slice(), "/models", 1024),
+            Err(InspectError::NotAFile(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_paths_that_escape_the_root() {
+        assert_eq!(normalize_path("models//bert/").unwrap(), "/models/bert");
+        assert_eq!(normalize_path("").unwrap(), "/");
+        assert!(matches!(
+            normalize_path("/models/../../etc"),
+            Err(InspectError::InvalidPath(_))
+        ));
+    }
+}
@@ -68,3 +68,11 @@ macro_rules! require_criu {
         }
     };
 }
+
+pub fn has_mkfs_ext4() -> bool {
+    Command::new("mkfs.ext4")
+        .arg("-V")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
@@ -2,7 +2,7 @@
 //! Tests all major functionality an L7 engineering lead would verify
 
 use bollard::Docker;
-use faas_common::{SandboxConfig, SandboxExecutor};
+use faas_common::{EnvVar, SandboxConfig, SandboxExecutor};
 use faas_executor::DockerExecutor;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -288,8 +288,8 @@ async fn test_environment_variables() {
     let executor = DockerExecutor::new(docker);
 
     let env_vars = vec![
-        "TEST_VAR=test_value".to_string(),
-        "NUMBER_VAR=42".to_string(),
+        EnvVar::new("TEST_VAR", "test_value"),
+        EnvVar::new("NUMBER_VAR", "42"),
     ];
 
     let result = executor
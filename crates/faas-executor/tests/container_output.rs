@@ -0,0 +1,215 @@
+//! What a real Docker container's execution reports back.
+
+use bollard::Docker;
+use faas_common::{FaasError, SandboxConfig, SandboxExecutor};
+use faas_executor::{test_utils, DockerExecutor};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn docker_executor() -> Option<DockerExecutor> {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    let docker = Docker::connect_with_local_defaults().ok()?;
+    Some(DockerExecutor::new(Arc::new(docker)))
+}
+
+fn shell(function_id: &str, script: &str) -> SandboxConfig {
+    SandboxConfig {
+        function_id: function_id.to_string(),
+        source: "alpine:latest".to_string(),
+        command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn stdout_and_stderr_arrive_separately() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let result = executor
+        .execute(shell(
+            "output-streams",
+            "echo to-stdout; echo to-stderr >&2; echo more-stdout",
+        ))
+        .await
+        .expect("container runs");
+    assert_eq!(
+        result.stdout.as_deref(),
+        Some(&b"to-stdout\nmore-stdout\n"[..])
+    );
+    assert_eq!(result.stderr.as_deref(), Some(&b"to-stderr\n"[..]));
+    assert_eq!(result.response, result.stdout);
+    let logs = result.logs.unwrap_or_default();
+    assert!(
+        logs.contains("to-stdout") && logs.contains("to-stderr"),
+        "{logs}"
+    );
+}
+
+#[tokio::test]
+async fn the_exit_code_is_reported_as_is() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let result = executor
+        .execute(shell("exit-code", "echo partial; exit 7"))
+        .await
+        .expect("container runs");
+    assert_eq!(result.exit_code, Some(7));
+    assert_eq!(result.exit_status(), 7);
+    assert!(result.error.is_some());
+    assert_eq!(result.stdout.as_deref(), Some(&b"partial\n"[..]));
+
+    let result = executor
+        .execute(shell("exit-code-zero", "true"))
+        .await
+        .expect("container runs");
+    assert_eq!(result.exit_code, Some(0));
+    assert_eq!(result.error, None);
+}
+
+#[tokio::test]
+async fn exceeding_the_memory_limit_reports_an_oom_kill() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    // Holding 128 MB in a shell variable is well past the 32 MB limit
+    let result = executor
+        .execute(SandboxConfig {
+            memory_limit: Some(32),
+            cpu_limit: Some(0.5),
+            ..shell(
+                "memory-limit",
+                "x=$(head -c 134217728 /dev/zero | tr '\\0' x); echo ${#x}",
+            )
+        })
+        .await
+        .expect("container runs");
+    assert_ne!(result.exit_status(), 0);
+    let error = result.error.unwrap_or_default();
+    assert!(
+        error.starts_with("container killed: out of memory"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn a_container_past_its_timeout_is_killed() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let start = Instant::now();
+    let result = executor
+        .execute(SandboxConfig {
+            timeout: Some(1000),
+            ..shell("timeout", "sleep 10")
+        })
+        .await;
+    assert!(
+        matches!(result, Err(FaasError::Timeout { timeout_ms: 1000 })),
+        "{result:?}"
+    );
+    assert!(start.elapsed() < Duration::from_secs(8));
+}
+
+#[tokio::test]
+async fn ten_megabytes_through_cat_come_back_unchanged() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let payload: Vec<u8> = (0..10 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let mut config = shell("large-stdin", "cat");
+    config.payload = payload.clone();
+    let result = executor.execute(config).await.expect("container runs");
+    assert_eq!(result.error, None);
+    assert!(
+        result.response.as_deref() == Some(&payload[..]),
+        "output differs"
+    );
+}
+
+#[tokio::test]
+async fn input_files_are_in_place_before_the_command_starts() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let mut config = shell(
+        "input-files",
+        "cat config.json /etc/faas/extra.txt; ls -l config.json | cut -c1-10",
+    );
+    config.working_dir = Some("/srv/app".to_string());
+    config.input_files = vec![
+        ("config.json".to_string(), b"{\"debug\":true}\n".to_vec()),
+        ("/etc/faas/extra.txt".to_string(), b"extra\n".to_vec()),
+    ];
+    let result = executor.execute(config).await.expect("container runs");
+    assert_eq!(
+        result.stdout.as_deref(),
+        Some(&b"{\"debug\":true}\nextra\n-rw-r--r--\n"[..])
+    );
+
+    let mut escaping = shell("input-files-escape", "true");
+    escaping.input_files = vec![("../../etc/passwd".to_string(), Vec::new())];
+    assert!(executor.execute(escaping).await.is_err());
+}
+
+#[tokio::test]
+async fn read_only_mounts_can_be_read_but_not_written() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("rows.csv"), "a,b\n1,2\n").unwrap();
+
+    let mut config = shell(
+        "read-only-mounts",
+        "wc -l < /faas/in/rows.csv; touch /faas/in/new 2>/dev/null || echo read-only",
+    );
+    config.read_only_mounts = vec![(
+        dir.path().to_string_lossy().into_owned(),
+        "/faas/in".to_string(),
+    )];
+    let result = executor.execute(config).await.expect("container runs");
+    assert_eq!(result.stdout.as_deref(), Some(&b"2\nread-only\n"[..]));
+    assert!(!dir.path().join("new").exists());
+}
+
+#[tokio::test]
+async fn a_busy_loop_uses_more_cpu_time_than_a_sleep_as_long() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let busy = executor
+        .execute(shell(
+            "usage-busy",
+            "end=$(($(date +%s) + 2)); while [ $(date +%s) -lt $end ]; do :; done; echo done",
+        ))
+        .await
+        .expect("container runs")
+        .usage
+        .expect("Docker measures usage");
+    let idle = executor
+        .execute(shell("usage-sleep", "sleep 2; echo done"))
+        .await
+        .expect("container runs")
+        .usage
+        .expect("Docker measures usage");
+
+    assert!(busy.wall_time_ms >= 1000 && idle.wall_time_ms >= 1000);
+    assert!(
+        busy.cpu_time_ms > idle.cpu_time_ms + 500,
+        "busy {busy:?}, idle {idle:?}"
+    );
+    assert!(busy.peak_memory_bytes > 0);
+    assert_eq!(busy.stdout_bytes, 5);
+}
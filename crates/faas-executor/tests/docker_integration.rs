@@ -2,7 +2,7 @@
 //! Tests actual FaaS capabilities, not just Docker wrapper
 
 use bollard::Docker;
-use faas_common::{SandboxConfig, SandboxExecutor};
+use faas_common::{EnvVar, SandboxConfig, SandboxExecutor};
 use faas_executor::{
     container_pool::{ContainerPoolManager, PoolConfig},
     DockerExecutor,
@@ -337,7 +337,7 @@ async fn test_faas_event_processing() {
                 "-c".to_string(),
                 r#"cat | grep usr_123"#.to_string(),
             ],
-            env_vars: Some(vec!["EVENT_SOURCE=webhook".to_string()]),
+            env_vars: Some(vec![EnvVar::new("EVENT_SOURCE", "webhook")]),
             payload: webhook_event.as_bytes().to_vec(),
         })
         .await
@@ -0,0 +1,87 @@
+//! A `DockerExecutor` outliving a daemon restart, against a stand-in daemon on a Unix socket.
+
+use faas_executor::docker_endpoints::{
+    BackoffConfig, DockerAddress, DockerEndpoint, DockerEndpointPool, EndpointTags,
+};
+use faas_executor::DockerExecutor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::task::JoinHandle;
+
+const VERSION: &str = r#"{"Version":"27.3.1","ApiVersion":"1.47"}"#;
+
+/// Answers every request with a version document, which also satisfies `Docker::ping`.
+/// Connections are served on the accept task, so aborting it hangs up on clients too.
+fn stand_in_daemon(path: &Path) -> JoinHandle<()> {
+    let listener = UnixListener::bind(path).unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = stream.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    VERSION.len(),
+                    VERSION
+                );
+                if stream.write_all(reply.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        }
+    })
+}
+
+fn executor_at(path: &Path) -> DockerExecutor {
+    let pool = DockerEndpointPool::new();
+    pool.insert(DockerEndpoint::new(
+        "default",
+        DockerAddress::Unix(path.to_path_buf()),
+        EndpointTags::default(),
+        BackoffConfig {
+            initial: Duration::from_millis(20),
+            max: Duration::from_millis(20),
+        },
+    ));
+    DockerExecutor::with_endpoints(Arc::new(pool)).with_reconnect_wait(Duration::from_secs(1))
+}
+
+fn socket_path() -> PathBuf {
+    std::env::temp_dir().join(format!("faas-reconnect-{}.sock", uuid::Uuid::new_v4()))
+}
+
+#[tokio::test]
+async fn ping_recovers_after_the_daemon_restarts() {
+    let path = socket_path();
+    let executor = executor_at(&path);
+
+    // Nothing listens yet
+    assert!(executor.ping().await.is_err());
+
+    let daemon = stand_in_daemon(&path);
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert_eq!(executor.ping().await.unwrap().as_deref(), Some("27.3.1"));
+
+    // The daemon goes away under a connected client
+    daemon.abort();
+    let _ = daemon.await;
+    std::fs::remove_file(&path).unwrap();
+    assert!(executor.ping().await.is_err());
+
+    // and comes back at the same socket; the same executor picks it up
+    let daemon = stand_in_daemon(&path);
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert_eq!(executor.ping().await.unwrap().as_deref(), Some("27.3.1"));
+
+    let stats = executor.endpoints().stats().await;
+    assert!(stats[0].connected);
+    assert!(stats[0].reconnects >= 1, "{stats:?}");
+
+    daemon.abort();
+    let _ = std::fs::remove_file(&path);
+}
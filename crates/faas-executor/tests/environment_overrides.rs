@@ -0,0 +1,117 @@
+//! Clock and locale overrides: applied in Docker, refused where a runtime can't fake time.
+
+use bollard::Docker;
+use chrono::{TimeZone, Utc};
+use faas_common::{EnvOverrides, FaasError, SandboxConfig, SandboxExecutor};
+use faas_executor::firecracker::communication::{
+    CommunicationConfig, CommunicationError, VmCommandExecutor,
+};
+use faas_executor::firecracker::FirecrackerExecutor;
+use faas_executor::{test_utils, DockerExecutor};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn docker_executor() -> Option<DockerExecutor> {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    let docker = Docker::connect_with_local_defaults().ok()?;
+    Some(DockerExecutor::new(Arc::new(docker)))
+}
+
+fn shell(function_id: &str, script: &str, overrides: EnvOverrides) -> SandboxConfig {
+    SandboxConfig {
+        function_id: function_id.to_string(),
+        source: "alpine:latest".to_string(),
+        command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        environment_overrides: Some(overrides),
+        ..Default::default()
+    }
+}
+
+fn fake_clock() -> EnvOverrides {
+    EnvOverrides {
+        fake_time: Some(Utc.with_ymd_and_hms(2001, 9, 8, 1, 46, 40).unwrap()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn fake_time_sets_the_clock_date_reports() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let result = executor
+        .execute(shell("fake-time", "date -u +%Y-%m-%dT%H:%M", fake_clock()))
+        .await
+        .expect("execution failed");
+    let output = String::from_utf8_lossy(&result.response.unwrap_or_default()).to_string();
+    assert_eq!(
+        output.trim(),
+        "2001-09-08T01:46",
+        "error: {:?}",
+        result.error
+    );
+}
+
+#[tokio::test]
+async fn timezone_changes_the_zone_date_reports() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let overrides = EnvOverrides {
+        timezone: Some("Asia/Tokyo".to_string()),
+        ..Default::default()
+    };
+    let result = executor
+        .execute(shell("timezone", "date +%Z", overrides))
+        .await
+        .expect("execution failed");
+    let output = String::from_utf8_lossy(&result.response.unwrap_or_default()).to_string();
+    assert_eq!(output.trim(), "JST", "error: {:?}", result.error);
+}
+
+#[tokio::test]
+async fn firecracker_without_vsock_refuses_fake_time() {
+    let error = FirecrackerExecutor::stub()
+        .execute(shell("fake-time-vm", "date", fake_clock()))
+        .await
+        .unwrap_err();
+
+    match error {
+        FaasError::IncompatibleFeature {
+            feature, runtime, ..
+        } => {
+            assert_eq!(feature, "fake_time");
+            assert_eq!(runtime, "firecracker");
+        }
+        other => panic!("expected IncompatibleFeature, got {other}"),
+    }
+}
+
+#[tokio::test]
+async fn serial_console_fallback_refuses_fake_time() {
+    let executor = VmCommandExecutor::new(CommunicationConfig {
+        vsock_cid: None,
+        serial_device: Some("/nonexistent/console.sock".to_string()),
+        ssh_config: None,
+        timeout: Duration::from_secs(1),
+        retry_attempts: 1,
+        retry_delay: Duration::from_millis(10),
+    });
+
+    let error = executor
+        .execute(&shell("fake-time-serial", "date", fake_clock()))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CommunicationError::IncompatibleFeature {
+            feature: "fake_time",
+            ..
+        }
+    ));
+}
@@ -4,10 +4,12 @@
 
 use anyhow::Result;
 use faas_common::Runtime;
+use faas_executor::docker_checkpoint::CheckpointError;
 use faas_executor::platform::executor::{Executor, Mode, Request};
+use faas_executor::platform::PlatformConfig;
 use faas_executor::test_utils;
 use serial_test::serial;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::sync::OnceLock;
 use std::time::{Duration, Instant};
 
@@ -25,7 +27,7 @@ fn docker_available() -> bool {
 async fn new_executor() -> Result<Executor> {
     // Disable prewarming for faster, deterministic test setup.
     std::env::set_var("FAAS_DISABLE_PREWARM", "1");
-    Executor::new().await
+    Executor::new(PlatformConfig::default()).await
 }
 
 fn basic_request(id: &str, code: &str, mode: Mode) -> Request {
@@ -39,6 +41,7 @@ fn basic_request(id: &str, code: &str, mode: Mode) -> Request {
         branch_from: None,
         runtime: None,
         env_vars: None,
+        ..Default::default()
     }
 }
 
@@ -87,7 +90,7 @@ async fn executor_ephemeral_mode_respects_env_vars() -> Result<()> {
         "#,
         Mode::Ephemeral,
     );
-    let mut env = HashMap::new();
+    let mut env = BTreeMap::new();
     env.insert("FAAS_EXPORTED_FLAG".to_string(), "env-works".to_string());
     req.env_vars = Some(env);
 
@@ -102,6 +105,96 @@ async fn executor_ephemeral_mode_respects_env_vars() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn env_values_reach_the_process_whole() -> Result<()> {
+    if !docker_available() {
+        return Ok(());
+    }
+
+    let executor = new_executor().await?;
+    let values = BTreeMap::from([
+        ("SECRET".to_string(), "c2VjcmV0PT0=".to_string()),
+        ("NOTE".to_string(), "line one\nline two".to_string()),
+        ("GREETING".to_string(), "grüß dich ✓".to_string()),
+    ]);
+    let mut req = basic_request(
+        "mode-ephemeral-env-values",
+        r#"printf '%s|' "$GREETING" "$NOTE" "$SECRET""#,
+        Mode::Ephemeral,
+    );
+    req.env_vars = Some(values);
+
+    let response = executor.run(req).await?;
+    assert_eq!(response.exit_code, 0);
+    assert_eq!(
+        String::from_utf8_lossy(&response.stdout),
+        "grüß dich ✓|line one\nline two|c2VjcmV0PT0=|"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn executor_runs_in_the_requested_working_dir() -> Result<()> {
+    if !docker_available() {
+        return Ok(());
+    }
+
+    let executor = new_executor().await?;
+    let mut req = basic_request(
+        "mode-ephemeral-workdir",
+        r#"echo "$MESSAGE from $(pwd)"; cat ./alpine-release"#,
+        Mode::Ephemeral,
+    );
+    req.env_vars = Some(BTreeMap::from([(
+        "MESSAGE".to_string(),
+        "hello".to_string(),
+    )]));
+    req.working_dir = Some("/etc".to_string());
+
+    let response = executor.run(req).await?;
+    assert_eq!(response.exit_code, 0);
+    let output = String::from_utf8_lossy(&response.stdout);
+    let mut lines = output.lines();
+    assert_eq!(lines.next(), Some("hello from /etc"));
+    assert!(
+        lines.next().is_some_and(|release| release.starts_with('3')),
+        "expected the alpine release, saw {output}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn executor_applies_requested_memory_and_cpu() -> Result<()> {
+    if !docker_available() {
+        return Ok(());
+    }
+
+    let executor = new_executor().await?;
+    let mut req = basic_request(
+        "mode-ephemeral-resources",
+        // cgroup v2, then v1
+        "cat /sys/fs/cgroup/memory.max 2>/dev/null || cat /sys/fs/cgroup/memory/memory.limit_in_bytes",
+        Mode::Ephemeral,
+    );
+    req.runtime = Some(Runtime::Docker);
+    req.memory_mb = Some(64);
+    req.cpu_cores = Some(0.5);
+
+    let response = executor.run(req).await?;
+    assert_eq!(response.exit_code, 0);
+    assert_eq!(
+        String::from_utf8_lossy(&response.stdout).trim(),
+        (64 * 1024 * 1024).to_string()
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn executor_runs_cached_mode_with_cache_hit() -> Result<()> {
@@ -143,10 +236,11 @@ async fn executor_runs_cached_mode_with_cache_hit() -> Result<()> {
     let hit_duration = start_hit.elapsed();
 
     assert_eq!(second.exit_code, 0);
+    assert!(second.cache_hit);
     assert_eq!(first.stdout, second.stdout);
-    assert!(
-        second.stderr.is_empty(),
-        "cached response should not include container logs"
+    assert_eq!(
+        first.stderr, second.stderr,
+        "cached response should replay the container logs"
     );
     assert!(
         hit_duration < first_duration,
@@ -205,10 +299,63 @@ async fn executor_cached_mode_miss_after_code_change() -> Result<()> {
         .await?;
     assert_eq!(hit.exit_code, 0);
     assert_eq!(miss.stdout, hit.stdout);
+    assert!(hit.cache_hit);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn executor_cached_mode_skips_the_container_on_a_hit() -> Result<()> {
+    if !docker_available() {
+        return Ok(());
+    }
+
+    let executor = new_executor().await?;
+    let code = "echo \"nonce $(date +%s%N)\"";
+    let first = executor
+        .run(basic_request("mode-cached-count", code, Mode::Cached))
+        .await?;
+    let second = executor
+        .run(basic_request("mode-cached-count-hit", code, Mode::Cached))
+        .await?;
+    assert!(second.cache_hit);
+    assert_eq!(first.stdout, second.stdout);
+    assert_eq!(first.cache_key, second.cache_key);
+    let stats = executor.result_cache_stats();
+    assert_eq!((stats.misses, stats.hits, stats.stored), (1, 1, 1));
+
+    // A different payload is a different result
+    let mut with_payload = basic_request("mode-cached-payload", code, Mode::Cached);
+    with_payload.payload = b"input".to_vec();
+    let fresh = executor.run(with_payload).await?;
+    assert!(!fresh.cache_hit);
+    assert_ne!(fresh.cache_key, first.cache_key);
+
+    // `no_cache` runs again and refreshes the entry
+    let mut bypass = basic_request("mode-cached-bypass", code, Mode::Cached);
+    bypass.no_cache = true;
+    let rerun = executor.run(bypass).await?;
+    assert!(!rerun.cache_hit);
+    assert_ne!(rerun.stdout, first.stdout);
+    let refreshed = executor
+        .run(basic_request("mode-cached-refreshed", code, Mode::Cached))
+        .await?;
+    assert_eq!(refreshed.stdout, rerun.stdout);
+
     assert!(
-        hit.stderr.is_empty(),
-        "cached response for mutated code should be log-free"
+        executor
+            .invalidate_cached(None, first.cache_key.as_deref().unwrap())
+            .await
     );
+    let after = executor
+        .run(basic_request("mode-cached-invalidated", code, Mode::Cached))
+        .await?;
+    assert!(!after.cache_hit);
+    let stats = executor.result_cache_stats();
+    assert_eq!(stats.hits, 2);
+    assert_eq!(stats.bypassed, 1);
+    assert_eq!(stats.invalidated, 1);
 
     Ok(())
 }
@@ -261,28 +408,50 @@ async fn executor_runs_checkpointed_mode() -> Result<()> {
     }
 
     let executor = new_executor().await?;
-
-    // First run generates a snapshot for the function id.
-    let create_req = basic_request("mode-checkpointed", "", Mode::Checkpointed);
-    let created = executor.run(create_req).await?;
-    assert_eq!(created.exit_code, 0);
-    assert_eq!(created.stdout, b"Checkpointed");
+    let counter = "i=0; while true; do i=$((i+1)); echo $i; sleep 0.2; done";
+
+    // The counter is still running at the timeout, so it is checkpointed instead of killed
+    let mut create_req = basic_request("mode-checkpointed", counter, Mode::Checkpointed);
+    create_req.timeout = Duration::from_secs(2);
+    let created = match executor.run(create_req).await {
+        Err(e) if matches!(e.downcast_ref(), Some(CheckpointError::Unsupported { .. })) => {
+            eprintln!("Test skipped: {e}");
+            return Ok(());
+        }
+        created => created?,
+    };
     let snapshot_id = created
         .snapshot
         .clone()
-        .expect("checkpoint run should return snapshot id");
+        .expect("a process still running at its timeout is checkpointed");
+    let counted = last_count(&created.stdout);
+    assert!(counted > 1, "the counter never ran");
 
-    // Second run restores from the previously created snapshot.
+    // Restoring resumes the same process, so the count carries on instead of restarting
     let mut restore_req = basic_request("mode-checkpointed-restore", "", Mode::Checkpointed);
+    restore_req.timeout = Duration::from_secs(2);
     restore_req.checkpoint = Some(snapshot_id.clone());
     let restored = executor.run(restore_req).await?;
-    assert_eq!(restored.exit_code, 0);
-    assert_eq!(restored.stdout, b"Restored");
-    assert_eq!(restored.snapshot, Some(snapshot_id));
+    let resumed_at = String::from_utf8_lossy(&restored.stdout)
+        .lines()
+        .next()
+        .and_then(|line| line.trim().parse::<u64>().ok())
+        .expect("the restored counter keeps printing");
+    assert_eq!(resumed_at, counted + 1);
+    assert!(last_count(&restored.stdout) > counted + 1);
+    assert!(restored.snapshot.is_some_and(|id| id != snapshot_id));
 
     Ok(())
 }
 
+fn last_count(stdout: &[u8]) -> u64 {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .last()
+        .unwrap_or(0)
+}
+
 #[tokio::test]
 #[serial]
 async fn executor_checkpointed_mode_errors_on_missing_snapshot() -> Result<()> {
@@ -299,7 +468,7 @@ async fn executor_checkpointed_mode_errors_on_missing_snapshot() -> Result<()> {
         .await
         .expect_err("restoring unknown snapshot must error");
     assert!(
-        err.to_string().contains("Snapshot not found"),
+        matches!(err.downcast_ref(), Some(CheckpointError::NotFound(id)) if id == "nonexistent-snapshot"),
         "unexpected error when restoring missing snapshot: {err}"
     );
 
@@ -314,15 +483,24 @@ async fn executor_runs_branched_mode() -> Result<()> {
     }
 
     let executor = new_executor().await?;
-    let mut req = basic_request("mode-branched", r#"echo "branch output""#, Mode::Branched);
-    req.branch_from = Some("parent-branch".to_string());
+    let parent = executor
+        .run(basic_request(
+            "mode-branched-parent",
+            r#"echo "parent state" > /tmp/state.txt"#,
+            Mode::Branched,
+        ))
+        .await?;
+    assert_eq!(parent.exit_code, 0);
+
+    let mut req = basic_request("mode-branched", "cat /tmp/state.txt", Mode::Branched);
+    req.branch_from = Some(parent.id);
 
     let response = executor.run(req).await?;
     assert_eq!(response.exit_code, 0);
     let output = String::from_utf8_lossy(&response.stdout);
     assert!(
-        output.contains("branch output"),
-        "expected branched output, got {output}"
+        output.contains("parent state"),
+        "expected the parent's file in the branch, got {output}"
     );
 
     Ok(())
@@ -330,24 +508,28 @@ async fn executor_runs_branched_mode() -> Result<()> {
 
 #[tokio::test]
 #[serial]
-async fn executor_rejects_branch_without_parent() -> Result<()> {
+async fn executor_rejects_branch_of_unknown_parent() -> Result<()> {
     if !docker_available() {
         return Ok(());
     }
 
     let executor = new_executor().await?;
-    let req = basic_request(
+    let mut req = basic_request(
         "mode-branch-missing-parent",
         r#"echo "should fail""#,
         Mode::Branched,
     );
+    req.branch_from = Some("mode-branch-no-such-parent".to_string());
 
     let err = executor
         .run(req)
         .await
-        .expect_err("branching without parent must error");
+        .expect_err("branching from an unknown parent must error");
     assert!(
-        err.to_string().contains("branch_from required"),
+        matches!(
+            err.downcast_ref::<faas_common::FaasError>(),
+            Some(faas_common::FaasError::NotFound(_))
+        ),
         "unexpected error when branch parent missing: {err}"
     );
 
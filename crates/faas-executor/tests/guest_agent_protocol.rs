@@ -0,0 +1,86 @@
+//! The host's half of the guest agent protocol, against a stand-in agent on a Unix socket.
+
+use faas_common::frame::{self, FrameError};
+use faas_common::{InvocationResult, SandboxConfig};
+use faas_executor::firecracker::communication::vsock::exchange;
+use faas_executor::firecracker::communication::CommunicationError;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixStream;
+
+/// Answers every config with its payload reversed, as the agent would its stdout
+async fn stand_in_agent(mut stream: UnixStream) {
+    while let Some(config) = frame::read_json::<_, SandboxConfig>(&mut stream)
+        .await
+        .unwrap()
+    {
+        let mut stdout = config.payload.clone();
+        stdout.reverse();
+        let result = InvocationResult {
+            request_id: config.function_id,
+            response: Some(stdout.clone()),
+            logs: None,
+            error: None,
+            stdout: Some(stdout),
+            stderr: Some(Vec::new()),
+            exit_code: Some(0),
+            usage: None,
+            truncation: None,
+        };
+        frame::write_json(&mut stream, &result).await.unwrap();
+    }
+}
+
+fn config(function_id: &str, payload: &[u8]) -> SandboxConfig {
+    SandboxConfig {
+        function_id: function_id.to_string(),
+        command: vec!["rev".to_string()],
+        payload: payload.to_vec(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn invocations_share_one_connection() {
+    let (mut host, agent) = UnixStream::pair().unwrap();
+    tokio::spawn(stand_in_agent(agent));
+
+    let first = exchange(&mut host, &config("first", b"abc")).await.unwrap();
+    assert_eq!(first.request_id, "first");
+    assert_eq!(first.response.as_deref(), Some(&b"cba"[..]));
+
+    // No half-close needed between them
+    let second = exchange(&mut host, &config("second", b"xy")).await.unwrap();
+    assert_eq!(second.request_id, "second");
+    assert_eq!(second.stdout.as_deref(), Some(&b"yx"[..]));
+}
+
+#[tokio::test]
+async fn an_agent_from_another_release_is_a_version_error() {
+    let (mut host, mut agent) = UnixStream::pair().unwrap();
+    tokio::spawn(async move {
+        let _ = frame::read_frame(&mut agent).await;
+        // An agent from before framing answers with bare JSON
+        agent.write_all(br#"{"request_id":"x"}"#).await.unwrap();
+    });
+
+    match exchange(&mut host, &config("mismatch", b"")).await {
+        Err(CommunicationError::Protocol(FrameError::VersionMismatch { ours, theirs })) => {
+            assert_eq!(ours, frame::PROTOCOL_VERSION);
+            assert_eq!(theirs, b'{');
+        }
+        other => panic!("expected a version mismatch, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn an_agent_that_hangs_up_is_a_connection_error() {
+    let (mut host, mut agent) = UnixStream::pair().unwrap();
+    tokio::spawn(async move {
+        let _ = frame::read_frame(&mut agent).await;
+    });
+
+    assert!(matches!(
+        exchange(&mut host, &config("dropped", b"")).await,
+        Err(CommunicationError::ConnectionFailed(_))
+    ));
+}
@@ -0,0 +1,61 @@
+//! Images the daemon doesn't have are pulled before the container is created.
+
+use bollard::image::RemoveImageOptions;
+use bollard::Docker;
+use faas_common::{SandboxConfig, SandboxExecutor};
+use faas_executor::{test_utils, DockerExecutor};
+use std::sync::Arc;
+
+// Each test removes its own image first, so they can run side by side
+async fn without_image(image: &str) -> Option<(Arc<Docker>, DockerExecutor)> {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    let docker = Arc::new(Docker::connect_with_local_defaults().ok()?);
+    let _ = docker
+        .remove_image(
+            image,
+            Some(RemoveImageOptions {
+                force: true,
+                ..Default::default()
+            }),
+            None,
+        )
+        .await;
+    assert!(docker.inspect_image(image).await.is_err());
+    Some((docker.clone(), DockerExecutor::new(docker)))
+}
+
+#[tokio::test]
+async fn an_absent_image_is_pulled_and_run() {
+    const IMAGE: &str = "busybox:1.36.1";
+    let Some((docker, executor)) = without_image(IMAGE).await else {
+        return;
+    };
+
+    let result = executor
+        .execute(SandboxConfig {
+            function_id: "image-pull".to_string(),
+            source: IMAGE.to_string(),
+            command: vec!["echo".to_string(), "pulled".to_string()],
+            ..Default::default()
+        })
+        .await
+        .expect("image is pulled and the container runs");
+    assert_eq!(result.stdout.as_deref(), Some(&b"pulled\n"[..]));
+    assert!(docker.inspect_image(IMAGE).await.is_ok());
+}
+
+#[tokio::test]
+async fn ensure_image_pulls_once() {
+    const IMAGE: &str = "busybox:1.36.0";
+    let Some((docker, executor)) = without_image(IMAGE).await else {
+        return;
+    };
+
+    executor.ensure_image(IMAGE, None).await.expect("pulled");
+    let pulled = docker.inspect_image(IMAGE).await.unwrap().id;
+    executor.ensure_image(IMAGE, None).await.expect("present");
+    assert_eq!(docker.inspect_image(IMAGE).await.unwrap().id, pulled);
+}
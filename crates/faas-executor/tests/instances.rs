@@ -0,0 +1,420 @@
+//! Persistent instance containers: execs into them and snapshots of them.
+
+use bollard::Docker;
+use faas_common::FaasError;
+use faas_executor::docker_snapshot::DockerSnapshotManager;
+use faas_executor::platform;
+use faas_executor::platform::workspaces::DEFAULT_WORKSPACE_PATH;
+use faas_executor::platform::{
+    ContainerRunState, InstanceContainers, InstanceOptions, InstanceResources, PortMapping,
+    WorkspaceMount, WorkspaceVolumes,
+};
+use faas_executor::test_utils;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn docker() -> Option<Arc<Docker>> {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    Docker::connect_with_local_defaults().ok().map(Arc::new)
+}
+
+fn instance_containers() -> Option<InstanceContainers> {
+    docker().map(InstanceContainers::new)
+}
+
+#[tokio::test]
+async fn a_file_written_by_one_exec_is_read_by_the_next() {
+    let Some(containers) = instance_containers() else {
+        return;
+    };
+    let instance_id = Uuid::new_v4().to_string();
+    let container = containers
+        .start(&instance_id, "alpine:latest", InstanceResources::default())
+        .await
+        .expect("container starts");
+
+    let write = containers
+        .exec(
+            &container,
+            "cat > /tmp/note",
+            b"kept between execs",
+            TIMEOUT,
+        )
+        .await
+        .expect("write runs");
+    assert_eq!(write.exit_code, 0);
+
+    let read = containers
+        .exec(
+            &container,
+            "cat /tmp/note; echo oops >&2; exit 3",
+            &[],
+            TIMEOUT,
+        )
+        .await
+        .expect("read runs");
+    assert_eq!(read.stdout, b"kept between execs");
+    assert_eq!(read.stderr, b"oops\n");
+    assert_eq!(read.exit_code, 3);
+
+    let slow = containers
+        .exec(&container, "sleep 5", &[], Duration::from_millis(500))
+        .await
+        .expect_err("gives up past the timeout");
+    assert!(matches!(
+        slow.downcast_ref::<FaasError>(),
+        Some(FaasError::Timeout { timeout_ms: 500 })
+    ));
+
+    containers
+        .remove(&container)
+        .await
+        .expect("container removed");
+    assert!(containers
+        .exec(&container, "true", &[], TIMEOUT)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn inspect_reports_usage_until_the_container_is_gone() {
+    let Some(containers) = instance_containers() else {
+        return;
+    };
+    let container = containers
+        .start(
+            &Uuid::new_v4().to_string(),
+            "alpine:latest",
+            InstanceResources::default(),
+        )
+        .await
+        .expect("container starts");
+
+    let status = containers
+        .inspect(&container)
+        .await
+        .expect("inspect succeeds")
+        .expect("container exists");
+    assert_eq!(status.state, ContainerRunState::Running);
+    assert!(status.memory_bytes.is_some_and(|bytes| bytes > 0));
+    assert!(status.cpu_percent.is_some());
+
+    containers
+        .remove(&container)
+        .await
+        .expect("container removed");
+    assert_eq!(containers.inspect(&container).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn a_restored_snapshot_has_the_files_written_before_it() {
+    let Some(docker) = docker() else {
+        return;
+    };
+    let containers = InstanceContainers::new(docker.clone());
+    let original = containers
+        .start(
+            &Uuid::new_v4().to_string(),
+            "alpine:latest",
+            InstanceResources::default(),
+        )
+        .await
+        .expect("container starts");
+    let write = containers
+        .exec(
+            &original,
+            "head -c 65536 /dev/zero > /data.bin && echo saved > /note",
+            &[],
+            TIMEOUT,
+        )
+        .await
+        .expect("write runs");
+    assert_eq!(write.exit_code, 0);
+
+    let snapshot = DockerSnapshotManager::new(docker.clone())
+        .create_snapshot(
+            &original,
+            Some("with-note".to_string()),
+            HashMap::from([("tenant".to_string(), "team-a".to_string())]),
+        )
+        .await
+        .expect("commit succeeds");
+    containers.remove(&original).await.unwrap();
+    assert!(snapshot.layer_bytes >= 65536);
+    assert!(snapshot.layer_bytes < snapshot.size_bytes);
+
+    // A manager started later finds the snapshot from its image alone
+    let committed = DockerSnapshotManager::new(docker.clone())
+        .load_committed()
+        .await
+        .unwrap();
+    let reloaded = committed
+        .iter()
+        .find(|s| s.id == snapshot.id)
+        .expect("snapshot listed from Docker");
+    assert_eq!(reloaded.name.as_deref(), Some("with-note"));
+    assert_eq!(reloaded.metadata["tenant"], "team-a");
+    assert_eq!(reloaded.layer_bytes, snapshot.layer_bytes);
+
+    let restored = containers
+        .start(
+            &Uuid::new_v4().to_string(),
+            &reloaded.image_id,
+            InstanceResources::default(),
+        )
+        .await
+        .expect("restored container starts");
+    let read = containers
+        .exec(&restored, "cat /note", &[], TIMEOUT)
+        .await
+        .expect("read runs");
+    assert_eq!(read.stdout, b"saved\n");
+
+    containers.remove(&restored).await.unwrap();
+    let _ = docker.remove_image(&snapshot.image_id, None, None).await;
+}
+
+#[tokio::test]
+async fn a_directory_tree_goes_in_and_comes_back_out() {
+    use futures::TryStreamExt;
+    use std::io::Read;
+
+    let Some(containers) = instance_containers() else {
+        return;
+    };
+    let files: [(&str, &[u8]); 3] = [
+        ("data/top.txt", b"top"),
+        ("data/nested/deeper/leaf.bin", &[0, 159, 255]),
+        ("data/nested/side.txt", b"side"),
+    ];
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(contents.len() as u64);
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+    let container = containers
+        .start(
+            &Uuid::new_v4().to_string(),
+            "alpine:latest",
+            InstanceResources::default(),
+        )
+        .await
+        .expect("container starts");
+
+    containers
+        .upload(&container, "/", builder.into_inner().unwrap())
+        .await
+        .expect("archive extracted");
+    let read = containers
+        .exec(&container, "cat /data/nested/side.txt", &[], TIMEOUT)
+        .await
+        .expect("read runs");
+    assert_eq!(read.stdout, b"side");
+
+    let archive: Vec<u8> = containers
+        .download(&container, "/data")
+        .try_concat()
+        .await
+        .expect("archive downloaded");
+    let mut downloaded = HashMap::new();
+    for entry in tar::Archive::new(archive.as_slice()).entries().unwrap() {
+        let mut entry = entry.unwrap();
+        if entry.header().entry_type().is_file() {
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            downloaded.insert(path, contents);
+        }
+    }
+    let expected: HashMap<String, Vec<u8>> = files
+        .iter()
+        .map(|(path, contents)| (path.to_string(), contents.to_vec()))
+        .collect();
+    assert_eq!(downloaded, expected);
+
+    let missing = containers
+        .download(&container, "/no/such/path")
+        .try_concat()
+        .await;
+    assert!(missing.is_err());
+    containers.remove(&container).await.unwrap();
+}
+
+#[tokio::test]
+async fn a_workspace_outlives_the_instance_that_wrote_it() {
+    let Some(docker) = docker() else {
+        return;
+    };
+    let containers = InstanceContainers::new(docker.clone());
+    let volumes = WorkspaceVolumes::new(docker);
+    let name = format!("ws-{}", Uuid::new_v4());
+    let workspace = WorkspaceMount {
+        volume: volumes.ensure(&name).await.expect("volume created"),
+        path: DEFAULT_WORKSPACE_PATH.to_string(),
+    };
+    let start = || async {
+        containers
+            .start_with(
+                &Uuid::new_v4().to_string(),
+                "alpine:latest",
+                &InstanceOptions {
+                    name: Some(name.clone()),
+                    workspace: Some(workspace.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("container starts")
+    };
+
+    let first = start().await;
+    let write = containers
+        .exec(&first, "cat > /workspace/notes.txt", b"still here", TIMEOUT)
+        .await
+        .expect("write runs");
+    assert_eq!(write.exit_code, 0);
+    // Stopping the instance removes its container
+    containers.remove(&first).await.unwrap();
+
+    assert_eq!(volumes.ensure(&name).await.unwrap(), workspace.volume);
+    let second = start().await;
+    let read = containers
+        .exec(&second, "cat /workspace/notes.txt", &[], TIMEOUT)
+        .await
+        .expect("read runs");
+    assert_eq!(read.stdout, b"still here");
+
+    let listed = volumes.list().await.unwrap();
+    assert!(listed
+        .iter()
+        .any(|v| v.name == workspace.volume && v.workspace == name));
+    assert!(
+        volumes.remove(&name).await.is_err(),
+        "removed while mounted"
+    );
+    containers.remove(&second).await.unwrap();
+    assert!(volumes.remove(&name).await.unwrap());
+    assert!(!volumes.remove(&name).await.unwrap());
+}
+
+/// GET `url`, retrying while the server behind it is still coming up
+async fn fetch(url: &str) -> String {
+    for _ in 0..50 {
+        if let Ok(response) = reqwest::get(url).await {
+            if let Ok(body) = response.text().await {
+                return body;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("nothing answered at {url}");
+}
+
+#[tokio::test]
+async fn ports_are_published_at_creation_and_while_running() {
+    let Some(containers) = instance_containers() else {
+        return;
+    };
+    let instance_id = Uuid::new_v4().to_string();
+    let options = InstanceOptions {
+        ports: vec![PortMapping::tcp(8000)],
+        ..Default::default()
+    };
+    let container = containers
+        .start_with(&instance_id, "busybox:latest", &options)
+        .await
+        .expect("container starts");
+    let serve = containers
+        .exec(
+            &container,
+            "mkdir -p /www && echo hello > /www/index.html \
+             && httpd -p 8000 -h /www && httpd -p 8001 -h /www",
+            &[],
+            TIMEOUT,
+        )
+        .await
+        .expect("servers start");
+    assert_eq!(
+        serve.exit_code,
+        0,
+        "{}",
+        String::from_utf8_lossy(&serve.stderr)
+    );
+
+    let published = containers.published_ports(&container).await.unwrap();
+    assert_eq!(published.len(), 1);
+    let host_port = published[0].host_port.expect("Docker picked a host port");
+    let url = published[0].url("127.0.0.1").unwrap();
+    assert_eq!(url, format!("http://127.0.0.1:{host_port}"));
+    assert_eq!(fetch(&url).await.trim(), "hello");
+
+    // 8001 wasn't asked for up front, so a proxy publishes it
+    let exposed = containers
+        .expose(&instance_id, &container, PortMapping::tcp(8001))
+        .await
+        .expect("port exposed");
+    assert_eq!(exposed.container_port, 8001);
+    assert_eq!(
+        fetch(&exposed.url("127.0.0.1").unwrap()).await.trim(),
+        "hello"
+    );
+
+    containers.remove(&container).await.unwrap();
+    assert_eq!(containers.remove_proxies(&instance_id).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn pausing_freezes_the_container_and_stop_kills_after_the_grace_period() {
+    let Some(containers) = instance_containers() else {
+        return;
+    };
+    let container = containers
+        .start(
+            &Uuid::new_v4().to_string(),
+            "alpine:latest",
+            InstanceResources::default(),
+        )
+        .await
+        .expect("container starts");
+    let state = |status: Option<platform::ContainerStatus>| status.map(|s| s.state);
+
+    containers.pause(&container).await.unwrap();
+    assert_eq!(
+        state(containers.inspect(&container).await.unwrap()),
+        Some(ContainerRunState::Paused)
+    );
+    containers.unpause(&container).await.unwrap();
+    assert_eq!(
+        state(containers.inspect(&container).await.unwrap()),
+        Some(ContainerRunState::Running)
+    );
+
+    // Stopping a paused container thaws it first; the idle loop ignores SIGTERM, so it
+    // is killed once the grace period is up
+    containers.pause(&container).await.unwrap();
+    let started = std::time::Instant::now();
+    containers
+        .stop(&container, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(
+        state(containers.inspect(&container).await.unwrap()),
+        Some(ContainerRunState::Exited)
+    );
+    // Already stopped
+    containers
+        .stop(&container, Duration::from_secs(1))
+        .await
+        .unwrap();
+    containers.remove(&container).await.unwrap();
+}
@@ -3,6 +3,7 @@
 use anyhow::Result;
 use faas_executor::performance::*;
 use faas_executor::platform::executor::*;
+use faas_executor::platform::PlatformConfig;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -11,7 +12,7 @@ use std::time::{Duration, Instant};
 async fn test_complete_execution_pipeline() -> Result<()> {
     println!("=== Testing Complete Execution Pipeline ===");
 
-    let executor = Executor::new().await?;
+    let executor = Executor::new(PlatformConfig::default()).await?;
 
     // Test 1: Ephemeral execution (cold start)
     println!("1. Testing ephemeral execution...");
@@ -110,7 +111,7 @@ async fn test_complete_execution_pipeline() -> Result<()> {
 async fn test_concurrent_execution_scaling() -> Result<()> {
     println!("=== Testing Concurrent Execution Scaling ===");
 
-    let executor = Arc::new(Executor::new().await?);
+    let executor = Arc::new(Executor::new(PlatformConfig::default()).await?);
 
     // Prepare different workloads
     let workloads = vec![
@@ -231,7 +232,7 @@ async fn test_concurrent_execution_scaling() -> Result<()> {
 async fn test_error_handling_and_recovery() -> Result<()> {
     println!("=== Testing Error Handling and Recovery ===");
 
-    let executor = Executor::new().await?;
+    let executor = Executor::new(PlatformConfig::default()).await?;
 
     // Test 1: Syntax error handling
     println!("1. Testing syntax error handling...");
@@ -321,7 +322,7 @@ async fn test_error_handling_and_recovery() -> Result<()> {
 async fn test_execution_modes_integration() -> Result<()> {
     println!("=== Testing Execution Modes Integration ===");
 
-    let executor = Executor::new().await?;
+    let executor = Executor::new(PlatformConfig::default()).await?;
 
     // Test each mode with the same basic workload
     let base_code = r#"
@@ -419,7 +420,7 @@ async fn test_execution_modes_integration() -> Result<()> {
 async fn test_performance_monitoring() -> Result<()> {
     println!("=== Testing Performance Monitoring ===");
 
-    let executor = Executor::new().await?;
+    let executor = Executor::new(PlatformConfig::default()).await?;
 
     // Create metrics collector
     let metrics = MetricsCollector::new(Default::default());
@@ -518,7 +519,7 @@ async fn test_performance_monitoring() -> Result<()> {
 async fn test_resource_utilization() -> Result<()> {
     println!("=== Testing Resource Utilization ===");
 
-    let executor = Arc::new(Executor::new().await?);
+    let executor = Arc::new(Executor::new(PlatformConfig::default()).await?);
 
     // Create a memory-intensive workload
     let memory_workload = r#"
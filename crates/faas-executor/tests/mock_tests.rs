@@ -3,7 +3,7 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
-use faas_common::{InvocationResult, SandboxConfig, SandboxExecutor};
+use faas_common::{EnvVar, InvocationResult, SandboxConfig, SandboxExecutor};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, RwLock};
@@ -80,6 +80,10 @@ impl SandboxExecutor for MockExecutor {
                     response: Some(response),
                     error: None,
                     logs: Some("Mock execution successful".to_string()),
+                    stdout: None,
+                    stderr: None,
+                    exit_code: None,
+                    truncation: None,
                 })
             }
             MockBehavior::Failure { error } => Err(faas_common::FaasError::Executor(error.clone())),
@@ -108,6 +112,10 @@ impl SandboxExecutor for MockExecutor {
                         response: Some(b"OK".to_vec()),
                         error: None,
                         logs: Some("Mock execution successful".to_string()),
+                        stdout: None,
+                        stderr: None,
+                        exit_code: None,
+                        truncation: None,
                     })
                 }
             }
@@ -147,7 +155,7 @@ mod tests {
             function_id: "test-func".to_string(),
             source: "alpine:latest".to_string(),
             command: vec!["echo".to_string(), "test".to_string()],
-            env_vars: Some(vec!["KEY=value".to_string()]),
+            env_vars: Some(vec![EnvVar::new("KEY", "value")]),
             payload: b"input".to_vec(),
         };
 
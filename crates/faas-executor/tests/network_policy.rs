@@ -0,0 +1,107 @@
+//! Network policies against real Docker containers. These reach the internet, so they
+//! run only with `FAAS_NETWORK_TESTS=1`; the restricted policy also needs iptables, and
+//! so root.
+
+use bollard::Docker;
+use faas_common::{NetworkPolicy, SandboxConfig, SandboxExecutor};
+use faas_executor::{test_utils, DockerExecutor};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn docker_executor() -> Option<DockerExecutor> {
+    if std::env::var("FAAS_NETWORK_TESTS").as_deref() != Ok("1") {
+        eprintln!("Test skipped: FAAS_NETWORK_TESTS is not set");
+        return None;
+    }
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    let docker = Docker::connect_with_local_defaults().ok()?;
+    Some(DockerExecutor::new(Arc::new(docker)))
+}
+
+fn wget(function_id: &str, url: &str, network: NetworkPolicy) -> SandboxConfig {
+    SandboxConfig {
+        function_id: function_id.to_string(),
+        source: "alpine:latest".to_string(),
+        command: vec![
+            "wget".to_string(),
+            "-q".to_string(),
+            "-T".to_string(),
+            "5".to_string(),
+            "-O".to_string(),
+            "/dev/null".to_string(),
+            url.to_string(),
+        ],
+        network: Some(network),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn without_a_network_wget_fails_fast() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let started = Instant::now();
+    let result = executor
+        .execute(wget(
+            "network-none",
+            "http://example.com",
+            NetworkPolicy::None,
+        ))
+        .await
+        .expect("execution failed");
+    assert!(result.error.is_some(), "wget reached example.com offline");
+    assert!(
+        started.elapsed() < Duration::from_secs(15),
+        "took {:?} to fail",
+        started.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn the_default_network_reaches_the_internet() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let result = executor
+        .execute(wget(
+            "network-default",
+            "http://example.com",
+            NetworkPolicy::Default,
+        ))
+        .await
+        .expect("execution failed");
+    assert!(
+        result.error.is_none(),
+        "unexpected error: {:?}",
+        result.error
+    );
+}
+
+#[tokio::test]
+async fn a_restricted_network_reaches_only_its_allowed_hosts() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let restricted = || NetworkPolicy::Restricted {
+        allowed_hosts: vec!["example.com".to_string()],
+    };
+    let allowed = executor
+        .execute(wget("network-allowed", "http://example.com", restricted()))
+        .await
+        .expect("execution failed");
+    assert!(
+        allowed.error.is_none(),
+        "unexpected error: {:?}",
+        allowed.error
+    );
+
+    let blocked = executor
+        .execute(wget("network-blocked", "http://1.1.1.1", restricted()))
+        .await
+        .expect("execution failed");
+    assert!(blocked.error.is_some(), "wget reached a host not allowed");
+}
@@ -0,0 +1,61 @@
+//! A container printing far more than the output limit, in real Docker.
+
+use bollard::Docker;
+use faas_common::{SandboxConfig, SandboxExecutor};
+use faas_executor::output_limit::OutputLimit;
+use faas_executor::{test_utils, DockerExecutor};
+use std::sync::Arc;
+
+const FIFTY_MB: u64 = 50 * 1024 * 1024;
+const LIMIT: usize = 1024 * 1024;
+
+#[tokio::test]
+async fn fifty_megabytes_of_output_are_truncated_and_spilled() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let docker = Docker::connect_with_local_defaults().unwrap();
+    let spill_dir = tempfile::tempdir().unwrap();
+    let executor = DockerExecutor::new(Arc::new(docker)).with_output_limit(OutputLimit {
+        max_bytes: LIMIT,
+        spill_dir: Some(spill_dir.path().to_path_buf()),
+    });
+
+    let result = executor
+        .execute(SandboxConfig {
+            function_id: "chatty".to_string(),
+            source: "alpine:latest".to_string(),
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!("head -c {FIFTY_MB} /dev/zero | tr '\\0' a; echo done >&2"),
+            ],
+            timeout: Some(120_000),
+            ..Default::default()
+        })
+        .await
+        .expect("execution failed");
+
+    assert_eq!(result.exit_code, Some(0));
+    let stdout = result.stdout.as_deref().unwrap_or_default();
+    let stderr = result.stderr.as_deref().unwrap_or_default();
+    assert!(
+        stdout.len() + stderr.len() <= LIMIT,
+        "kept {} bytes",
+        stdout.len()
+    );
+    assert!(stdout.iter().all(|&b| b == b'a'));
+    assert!(result.logs.unwrap_or_default().len() <= LIMIT);
+
+    let truncation = result
+        .truncation
+        .expect("output was not flagged as truncated");
+    assert_eq!(truncation.total_bytes, FIFTY_MB + "done\n".len() as u64);
+    let log_path = truncation.log_path.expect("output was not spilled");
+    assert_eq!(
+        std::fs::metadata(&log_path).unwrap().len(),
+        truncation.total_bytes
+    );
+    assert_eq!(result.usage.map(|usage| usage.stdout_bytes), Some(FIFTY_MB));
+}
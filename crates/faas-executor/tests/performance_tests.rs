@@ -1,6 +1,6 @@
 /// REAL tests that actually verify optimizations work
 use anyhow::Result;
-use faas_executor::platform::{executor::*, fork::ForkManager, memory::MemoryPool};
+use faas_executor::platform::{executor::*, fork::ForkManager, memory::MemoryPool, PlatformConfig};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -9,7 +9,7 @@ use std::time::{Duration, Instant};
 async fn test_real_cache_correctness() -> Result<()> {
     println!("Testing REAL cache correctness");
 
-    let executor = Executor::new().await?;
+    let executor = Executor::new(PlatformConfig::default()).await?;
 
     // Test with a computation that produces deterministic output
     let code = r#"
@@ -77,7 +77,7 @@ async fn test_real_cache_correctness() -> Result<()> {
 async fn test_real_container_fork() -> Result<()> {
     println!("Testing REAL container forking with Docker checkpointing");
 
-    let executor = Executor::new().await?;
+    let executor = Executor::new(PlatformConfig::default()).await?;
 
     // First, create a base container and set up state using the create_base_container method
     let docker = bollard::Docker::connect_with_local_defaults()?;
@@ -175,7 +175,7 @@ async fn test_real_container_fork() -> Result<()> {
 async fn test_real_performance_workload() -> Result<()> {
     println!("Testing with REAL computational workload");
 
-    let executor = Executor::new().await?;
+    let executor = Executor::new(PlatformConfig::default()).await?;
 
     // Complex computation that takes measurable time
     let compute_code = r#"
@@ -302,7 +302,7 @@ async fn test_real_memory_pressure() -> Result<()> {
 async fn test_concurrent_optimization() -> Result<()> {
     println!("Testing concurrent execution optimization");
 
-    let executor = Arc::new(Executor::new().await?);
+    let executor = Arc::new(Executor::new(PlatformConfig::default()).await?);
 
     // Warm up cache with some computations
     let warm_up_code = vec!["echo 'Result: 1'", "echo 'Result: 2'", "echo 'Result: 3'"];
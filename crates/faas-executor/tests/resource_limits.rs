@@ -0,0 +1,142 @@
+//! Resource limit enforcement (pids, ulimits, shm, tmpfs) against real Docker containers.
+
+use bollard::Docker;
+use faas_common::{SandboxConfig, SandboxExecutor, TmpfsMount, Ulimit};
+use faas_executor::{test_utils, DockerExecutor};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn docker_executor() -> Option<DockerExecutor> {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    let docker = Docker::connect_with_local_defaults().ok()?;
+    Some(DockerExecutor::new(Arc::new(docker)))
+}
+
+fn shell(function_id: &str, script: &str) -> SandboxConfig {
+    SandboxConfig {
+        function_id: function_id.to_string(),
+        source: "alpine:latest".to_string(),
+        command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        ..Default::default()
+    }
+}
+
+/// Starts background sleeps until a fork fails, printing how many are running after each;
+/// the shell gives up on the first failed fork.
+const FORK_UNTIL_REFUSED: &str =
+    "i=0; while [ $i -lt 1000 ]; do sleep 10 & i=$((i + 1)); echo $i; done";
+
+/// How many processes the script got started, after checking it was stopped by a refused
+/// fork rather than finishing
+async fn forks_until_refused(executor: &DockerExecutor, config: SandboxConfig) -> u32 {
+    let start = Instant::now();
+    let result = executor.execute(config).await.expect("execution failed");
+    assert!(
+        start.elapsed() < Duration::from_secs(30),
+        "fork loop should be refused quickly, took {:?}",
+        start.elapsed()
+    );
+    let stdout = String::from_utf8_lossy(&result.response.unwrap_or_default()).to_string();
+    let stderr = String::from_utf8_lossy(&result.stderr.unwrap_or_default()).to_string();
+    assert_ne!(
+        result.exit_code,
+        Some(0),
+        "stdout: {stdout}, stderr: {stderr}"
+    );
+    assert!(
+        stderr.contains("Resource temporarily unavailable"),
+        "expected a refused fork, got stderr: {stderr}"
+    );
+    stdout
+        .lines()
+        .last()
+        .and_then(|n| n.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[tokio::test]
+async fn fork_bomb_is_contained_by_the_pids_limit_even_as_root() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let mut config = shell("pids-fork-bomb", FORK_UNTIL_REFUSED);
+    config.pids_limit = Some(32);
+    let started = forks_until_refused(&executor, config).await;
+    // The shell itself takes one of the 32
+    assert!(
+        started < 32,
+        "started {started} processes under pids_limit=32"
+    );
+
+    // The executor must still be usable afterwards.
+    let result = executor
+        .execute(shell("pids-after-bomb", "echo still-alive"))
+        .await
+        .expect("executor should survive a fork bomb");
+    let output = String::from_utf8_lossy(&result.response.unwrap_or_default()).to_string();
+    assert!(output.contains("still-alive"), "got {output}");
+}
+
+#[tokio::test]
+async fn nproc_contains_a_fork_bomb_of_an_unprivileged_user() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let mut config = shell("ulimit-fork-bomb", FORK_UNTIL_REFUSED);
+    config.user = Some("nobody".to_string());
+    config.ulimits = Some(vec![Ulimit::new("nproc", 32, 32)]);
+    let started = forks_until_refused(&executor, config).await;
+    assert!(started < 32, "started {started} processes under nproc=32");
+}
+
+#[tokio::test]
+async fn nofile_limit_is_enforced_and_can_be_raised() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    // Keeps 200 descriptors open at once in the shell itself.
+    let script = "i=10; while [ $i -lt 210 ]; do eval \"exec $i</dev/null\" || exit 42; \
+                  i=$((i + 1)); done; echo opened";
+
+    let mut limited = shell("ulimit-nofile-low", script);
+    limited.ulimits = Some(vec![Ulimit::new("nofile", 64, 64)]);
+    let result = executor.execute(limited).await.expect("execution failed");
+    assert!(result.error.is_some(), "expected failure under nofile=64");
+
+    let mut raised = shell("ulimit-nofile-high", script);
+    raised.ulimits = Some(vec![Ulimit::new("nofile", 1024, 1024)]);
+    let result = executor.execute(raised).await.expect("execution failed");
+    assert!(
+        result.error.is_none(),
+        "unexpected error: {:?}",
+        result.error
+    );
+}
+
+#[tokio::test]
+async fn shm_and_tmpfs_sizes_are_applied() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+
+    let mut config = shell(
+        "ulimit-tmpfs",
+        "df -m /dev/shm /scratch | tail -n +2 | awk '{print $2}'",
+    );
+    config.shm_size_mb = Some(32);
+    config.tmpfs = Some(vec![TmpfsMount {
+        path: "/scratch".to_string(),
+        size_mb: 16,
+    }]);
+
+    let result = executor.execute(config).await.expect("execution failed");
+    let output = String::from_utf8_lossy(&result.response.unwrap_or_default()).to_string();
+    let sizes: Vec<&str> = output.split_whitespace().collect();
+    assert_eq!(sizes, vec!["32", "16"], "got {output}");
+}
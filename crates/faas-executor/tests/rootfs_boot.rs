@@ -0,0 +1,56 @@
+//! Boot an image from the rootfs builder under Firecracker.
+//!
+//! Needs KVM, the `firecracker` binary and the kernel staged at `/var/lib/faas/kernel`
+//! (`scripts/prepare_firecracker_assets.sh`), plus `FAAS_TEST_MINIROOTFS`, an Alpine
+//! minirootfs tarball, and `FAAS_TEST_GUEST_AGENT`, a musl build of the agent.
+#![cfg(all(feature = "firecracker-tests", target_os = "linux"))]
+
+use faas_common::{SandboxConfig, SandboxExecutor};
+use faas_executor::firecracker::FirecrackerExecutor;
+use faas_executor::rootfs_builder::{self, AgentSource, RootfsBase, RootfsSpec};
+use faas_executor::test_utils::{has_firecracker, has_kvm, has_mkfs_ext4};
+use std::path::PathBuf;
+
+#[tokio::test]
+async fn built_rootfs_boots_and_runs_a_command() {
+    let inputs = std::env::var("FAAS_TEST_MINIROOTFS")
+        .ok()
+        .zip(std::env::var("FAAS_TEST_GUEST_AGENT").ok());
+    let Some((minirootfs, agent)) = inputs else {
+        eprintln!("Test skipped: FAAS_TEST_MINIROOTFS and FAAS_TEST_GUEST_AGENT not set");
+        return;
+    };
+    if !has_kvm() || !has_firecracker() || !has_mkfs_ext4() {
+        eprintln!("Test skipped: KVM, Firecracker or mkfs.ext4 not available");
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let asset = rootfs_builder::build(&RootfsSpec {
+        name: "boot-test".to_string(),
+        base: RootfsBase::Tarball(PathBuf::from(minirootfs)),
+        agent: AgentSource::Prebuilt(PathBuf::from(agent)),
+        size_mb: 128,
+        output_dir: dir.path().to_path_buf(),
+    })
+    .await
+    .unwrap();
+
+    let executor = FirecrackerExecutor::new(
+        "firecracker".to_string(),
+        "/var/lib/faas/kernel".to_string(),
+        dir.path().join(&asset.file).to_string_lossy().into_owned(),
+    )
+    .unwrap();
+    let result = executor
+        .execute(SandboxConfig {
+            function_id: "rootfs-boot".to_string(),
+            source: "rootfs".to_string(),
+            command: vec!["echo".to_string(), "booted".to_string()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let output = String::from_utf8_lossy(result.response.as_deref().unwrap_or_default());
+    assert!(output.contains("booted"), "unexpected output: {output}");
+}
@@ -1,7 +1,7 @@
 //! Security and privilege escalation tests
 
 use bollard::Docker;
-use faas_common::{SandboxConfig, SandboxExecutor};
+use faas_common::{EnvVar, SandboxConfig, SandboxExecutor};
 use faas_executor::DockerExecutor;
 use std::sync::Arc;
 
@@ -126,7 +126,7 @@ async fn test_secrets_not_exposed() {
                 "-c".to_string(),
                 "env | grep -i secret".to_string(),
             ],
-            env_vars: Some(vec!["SAFE_VAR=public".to_string()]),
+            env_vars: Some(vec![EnvVar::new("SAFE_VAR", "public")]),
             payload: vec![],
         })
         .await;
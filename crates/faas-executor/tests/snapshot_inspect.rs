@@ -0,0 +1,92 @@
+//! Browsing a committed Docker snapshot without restoring it.
+
+use bollard::container::{Config, RemoveContainerOptions, WaitContainerOptions};
+use bollard::Docker;
+use faas_executor::docker_snapshot::DockerSnapshotManager;
+use faas_executor::snapshot_inspect::{EntryKind, InspectError};
+use faas_executor::test_utils;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn lists_and_reads_a_committed_snapshot() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
+
+    let script = "mkdir -p /models/bert && printf '{\"layers\":12}' > /models/bert/config.json \
+                  && head -c 100000 /dev/zero > /models/bert/weights.bin \
+                  && echo hello > /models/README";
+    let container = docker
+        .create_container::<String, String>(
+            None,
+            Config {
+                image: Some("alpine:latest".to_string()),
+                cmd: Some(vec!["sh".to_string(), "-c".to_string(), script.to_string()]),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    docker
+        .start_container::<String>(&container.id, None)
+        .await
+        .unwrap();
+    let mut wait = docker.wait_container(&container.id, None::<WaitContainerOptions<String>>);
+    while wait.next().await.is_some() {}
+
+    let manager = DockerSnapshotManager::new(docker.clone());
+    let snapshot = manager
+        .create_snapshot(&container.id, None, HashMap::new())
+        .await
+        .unwrap();
+
+    let top = manager
+        .list_files(&snapshot.id, "/models", 1)
+        .await
+        .unwrap();
+    let paths: Vec<_> = top.entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["/models/README", "/models/bert"]);
+    assert_eq!(top.entries[1].kind, EntryKind::Directory);
+
+    let deep = manager
+        .list_files(&snapshot.id, "/models", 2)
+        .await
+        .unwrap();
+    let weights = deep
+        .entries
+        .iter()
+        .find(|e| e.path == "/models/bert/weights.bin")
+        .unwrap();
+    assert_eq!(weights.size, 100_000);
+
+    assert_eq!(
+        manager
+            .read_file(&snapshot.id, "/models/bert/config.json", 1024)
+            .await
+            .unwrap(),
+        b"{\"layers\":12}"
+    );
+    let too_large = manager
+        .read_file(&snapshot.id, "/models/bert/weights.bin", 1024)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        too_large.downcast_ref::<InspectError>(),
+        Some(InspectError::TooLarge { size: 100_000, .. })
+    ));
+
+    let _ = manager.delete_snapshot(&snapshot.id).await;
+    let _ = docker
+        .remove_container(
+            &container.id,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+}
@@ -0,0 +1,84 @@
+//! Progress reporting while a large container is committed.
+
+use bollard::container::{Config, RemoveContainerOptions, WaitContainerOptions};
+use bollard::Docker;
+use faas_executor::docker_snapshot::{DockerSnapshotManager, SnapshotPhase, SnapshotProgress};
+use faas_executor::test_utils;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+#[tokio::test]
+async fn reports_progress_while_committing_a_large_container() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
+
+    // A few hundred MB that doesn't compress away
+    let script = "head -c 300000000 /dev/urandom > /data.bin";
+    let container = docker
+        .create_container::<String, String>(
+            None,
+            Config {
+                image: Some("alpine:latest".to_string()),
+                cmd: Some(vec!["sh".to_string(), "-c".to_string(), script.to_string()]),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    docker
+        .start_container::<String>(&container.id, None)
+        .await
+        .unwrap();
+    let mut wait = docker.wait_container(&container.id, None::<WaitContainerOptions<String>>);
+    while wait.next().await.is_some() {}
+
+    let manager = DockerSnapshotManager::new(docker.clone());
+    let estimate = manager.estimate_size(&container.id).await.unwrap();
+    assert!(estimate.changed_bytes >= 300_000_000);
+    assert!(estimate.changed_paths >= 1);
+
+    let (progress, mut updates) = watch::channel(SnapshotProgress::preflight());
+    let readings = tokio::spawn(async move {
+        let mut seen = Vec::new();
+        while updates.changed().await.is_ok() {
+            seen.push(updates.borrow_and_update().clone());
+        }
+        seen
+    });
+    let snapshot = manager
+        .create_snapshot_with_progress(
+            &container.id,
+            None,
+            HashMap::new(),
+            estimate.total_bytes(),
+            &progress,
+        )
+        .await
+        .unwrap();
+    drop(progress);
+    let readings = readings.await.unwrap();
+
+    assert!(readings.iter().any(|p| p.phase == SnapshotPhase::Committing
+        && p.bytes > 0
+        && p.percent_estimate.is_some_and(|pct| pct < 100.0)));
+    let last = readings.last().unwrap();
+    assert_eq!(last.phase, SnapshotPhase::Done);
+    assert_eq!(last.percent_estimate, Some(100.0));
+    assert!(snapshot.size_bytes >= 300_000_000);
+
+    let _ = manager.delete_snapshot(&snapshot.id).await;
+    let _ = docker
+        .remove_container(
+            &container.id,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+}
@@ -0,0 +1,89 @@
+//! The user and working directory a command runs with, in real Docker containers.
+
+use bollard::Docker;
+use faas_common::{FaasError, SandboxConfig, SandboxExecutor};
+use faas_executor::{test_utils, DockerExecutor};
+use std::sync::Arc;
+
+fn docker_executor() -> Option<DockerExecutor> {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    let docker = Docker::connect_with_local_defaults().ok()?;
+    Some(DockerExecutor::new(Arc::new(docker)))
+}
+
+fn shell(function_id: &str, script: &str) -> SandboxConfig {
+    SandboxConfig {
+        function_id: function_id.to_string(),
+        source: "alpine:latest".to_string(),
+        command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        ..Default::default()
+    }
+}
+
+fn stdout(result: &faas_common::InvocationResult) -> String {
+    String::from_utf8_lossy(result.stdout.as_deref().unwrap_or_default())
+        .trim()
+        .to_string()
+}
+
+#[tokio::test]
+async fn commands_run_in_the_working_directory() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let result = executor
+        .execute(SandboxConfig {
+            working_dir: Some("/srv/not-in-the-image".to_string()),
+            ..shell("working-dir", "pwd")
+        })
+        .await
+        .expect("execution failed");
+    assert_eq!(result.error, None);
+    assert_eq!(stdout(&result), "/srv/not-in-the-image");
+}
+
+#[tokio::test]
+async fn commands_run_as_the_user() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let result = executor
+        .execute(SandboxConfig {
+            user: Some("1000".to_string()),
+            ..shell("user-id", "id -u")
+        })
+        .await
+        .expect("execution failed");
+    assert_eq!(result.error, None);
+    assert_eq!(stdout(&result), "1000");
+
+    let result = executor
+        .execute(SandboxConfig {
+            user: Some("nobody".to_string()),
+            ..shell("user-name", "id -un")
+        })
+        .await
+        .expect("execution failed");
+    assert_eq!(stdout(&result), "nobody");
+}
+
+#[tokio::test]
+async fn a_user_missing_from_the_image_is_named() {
+    let Some(executor) = docker_executor() else {
+        return;
+    };
+    let error = executor
+        .execute(SandboxConfig {
+            user: Some("no-such-user".to_string()),
+            ..shell("user-missing", "true")
+        })
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&error, FaasError::UnknownUser { user } if user == "no-such-user"),
+        "{error}"
+    );
+}
@@ -0,0 +1,42 @@
+//! TAP devices created and torn down on a real host bridge.
+#![cfg(target_os = "linux")]
+
+use faas_executor::firecracker::network::IpCommandOps;
+use faas_executor::firecracker::{NetOps, NetworkConfig, NetworkManager};
+use std::process::Command;
+
+const BRIDGE: &str = "faastestbr0";
+
+fn ip(args: &[&str]) -> bool {
+    Command::new("ip")
+        .args(args)
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+#[test]
+fn leases_create_and_delete_real_taps() {
+    // Creating links needs CAP_NET_ADMIN
+    if !ip(&["link", "add", BRIDGE, "type", "bridge"]) {
+        eprintln!("Test skipped: cannot create network devices");
+        return;
+    }
+
+    let manager = NetworkManager::new(NetworkConfig {
+        bridge_name: BRIDGE.to_string(),
+        subnet: "10.213.0.0/28".to_string(),
+        tap_prefix: "faastest-".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+    let lease = manager.lease("vm-integration", false);
+    let links = IpCommandOps.list_links().unwrap();
+    manager.release("vm-integration");
+    let after = IpCommandOps.list_links().unwrap();
+    ip(&["link", "del", BRIDGE]);
+
+    let lease = lease.unwrap();
+    assert!(links.contains(&lease.tap_name));
+    assert!(!after.contains(&lease.tap_name));
+}
@@ -0,0 +1,137 @@
+//! Prewarmed containers: executions claim them instead of starting their own, and idle
+//! ones are evicted once their TTL passes.
+
+use bollard::{Docker, API_DEFAULT_VERSION};
+use faas_common::Runtime;
+use faas_executor::container_pool::{ContainerPool, ContainerPoolManager, PoolConfig};
+use faas_executor::firecracker::WarmLimits;
+use faas_executor::platform::executor::{Executor, Mode, Request};
+use faas_executor::platform::PlatformConfig;
+use faas_executor::test_utils;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const IMAGE: &str = "alpine:latest";
+
+fn request(id: &str) -> Request {
+    Request {
+        id: id.to_string(),
+        code: "echo warm".to_string(),
+        mode: Mode::Ephemeral,
+        env: IMAGE.to_string(),
+        timeout: Duration::from_secs(30),
+        runtime: Some(Runtime::Docker),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn a_prewarmed_image_runs_faster_and_uses_up_its_pool() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let executor = Executor::new(PlatformConfig::default()).await.unwrap();
+
+    let started = Instant::now();
+    let cold = executor.run(request("warm-pool-cold")).await.unwrap();
+    let cold_time = started.elapsed();
+    assert_eq!(cold.stdout, b"warm\n");
+
+    let pool = executor.prewarm(None, IMAGE, 2).await.unwrap();
+    assert_eq!(pool.available, 2);
+
+    let started = Instant::now();
+    let warm = executor.run(request("warm-pool-warm")).await.unwrap();
+    let warm_time = started.elapsed();
+    assert_eq!(warm.stdout, b"warm\n");
+    assert!(
+        warm_time < cold_time,
+        "warm run took {warm_time:?}, cold run {cold_time:?}"
+    );
+
+    let pools = executor.warm_pools().await;
+    let pool = pools.iter().find(|p| p.image == IMAGE).unwrap();
+    assert_eq!(pool.available, 1);
+}
+
+#[tokio::test]
+async fn claims_of_an_empty_pool_count_as_misses() {
+    // Nothing here reaches a daemon: the pool is never asked to start a container
+    let docker = Docker::connect_with_http("http://127.0.0.1:1", 1, API_DEFAULT_VERSION);
+    let docker = Arc::new(docker.unwrap());
+    let pool = ContainerPool::new(
+        docker,
+        IMAGE.to_string(),
+        PoolConfig {
+            min_size: 0,
+            ..Default::default()
+        },
+    );
+    assert!(pool.claim().await.is_none());
+    assert!(pool.claim().await.is_none());
+    pool.set_limits(0, 3).await.unwrap();
+
+    let stats = pool.stats().await;
+    assert_eq!((stats.requests, stats.hits), (2, 0));
+    assert_eq!(stats.avg_acquisition_ms, None);
+    assert_eq!((stats.config.min_size, stats.config.max_size), (0, 3));
+}
+
+#[tokio::test]
+async fn pool_limits_refill_and_trim_the_warm_pool() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let executor = Executor::new(PlatformConfig::default()).await.unwrap();
+    let limits = WarmLimits { min: 2, max: 4 };
+    let pool = executor
+        .set_pool_limits(None, Runtime::Docker, IMAGE, limits)
+        .await
+        .unwrap();
+    assert_eq!(pool.warm, 2);
+    assert_eq!(pool.limits, limits);
+
+    executor.run(request("pool-limits-hit")).await.unwrap();
+    let stats = executor.pool_stats().await;
+    let pool = stats
+        .iter()
+        .find(|p| p.runtime == Runtime::Docker && p.environment == IMAGE)
+        .unwrap();
+    assert_eq!(pool.hits, 1);
+    assert!(pool.avg_acquisition.is_some());
+
+    let pool = executor
+        .set_pool_limits(None, Runtime::Docker, IMAGE, WarmLimits { min: 0, max: 0 })
+        .await
+        .unwrap();
+    assert_eq!(pool.warm, 0);
+}
+
+#[tokio::test]
+async fn idle_warm_containers_are_evicted_after_their_ttl() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
+    let pools = ContainerPoolManager::new(
+        docker,
+        PoolConfig {
+            min_size: 0,
+            max_idle_time: Duration::from_secs(1),
+            pre_warm: false,
+            health_check_interval: Duration::from_secs(1),
+            predictive_warming: false,
+            ..Default::default()
+        },
+    );
+    assert_eq!(pools.prewarm(IMAGE, 1).await.unwrap(), 1);
+    assert_eq!(pools.get_stats(IMAGE).await.unwrap().available, 1);
+
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    let stats = pools.get_stats(IMAGE).await.unwrap();
+    assert_eq!(stats.available, 0);
+    assert_eq!(stats.oldest_idle, None);
+}
@@ -0,0 +1,112 @@
+//! A canary that passes against a warm container and fails once the container is poisoned.
+
+use async_trait::async_trait;
+use bollard::container::RemoveContainerOptions;
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::Docker;
+use faas_executor::canary::{AlertSink, CanaryAlert, CanarySpec, EnvironmentHealth};
+use faas_executor::container_pool::{ContainerPoolManager, PoolConfig};
+use faas_executor::test_utils;
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+
+const IMAGE: &str = "alpine:latest";
+
+#[derive(Default)]
+struct RecordingSink {
+    alerts: Mutex<Vec<CanaryAlert>>,
+}
+
+#[async_trait]
+impl AlertSink for RecordingSink {
+    async fn alert(&self, alert: &CanaryAlert) {
+        self.alerts.lock().unwrap().push(alert.clone());
+    }
+}
+
+async fn exec(docker: &Docker, container_id: &str, cmd: &[&str]) {
+    let exec = docker
+        .create_exec(
+            container_id,
+            CreateExecOptions {
+                attach_stdout: Some(true),
+                cmd: Some(cmd.iter().map(|s| s.to_string()).collect()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    if let StartExecResults::Attached { mut output, .. } =
+        docker.start_exec(&exec.id, None).await.unwrap()
+    {
+        while output.next().await.is_some() {}
+    }
+}
+
+#[tokio::test]
+async fn poisoned_warm_container_degrades_its_environment() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
+    let pools = ContainerPoolManager::new(
+        docker.clone(),
+        PoolConfig {
+            min_size: 1,
+            pre_warm: false,
+            predictive_warming: false,
+            ..Default::default()
+        },
+    );
+    let sink = Arc::new(RecordingSink::default());
+    pools.canaries().add_sink(sink.clone());
+    pools
+        .configure_canary(
+            IMAGE,
+            CanarySpec {
+                command: vec!["test".into(), "-f".into(), "/etc/alpine-release".into()],
+                expected_exit_code: 0,
+                expected_stdout: None,
+                interval_secs: 3600,
+                timeout_secs: 10,
+                quarantine: false,
+            },
+        )
+        .await;
+    let pool = pools.get_pool(IMAGE).await;
+    pool.create_replacement().await.unwrap();
+
+    let passed = pools.run_canary_now(IMAGE).await.unwrap().unwrap();
+    assert!(passed.passed, "{:?}", passed.failure);
+    assert_eq!(
+        pools.canaries().status(IMAGE).unwrap().health,
+        EnvironmentHealth::Healthy
+    );
+
+    exec(
+        &docker,
+        &passed.container_id,
+        &["rm", "/etc/alpine-release"],
+    )
+    .await;
+    let failed = pools.run_canary_now(IMAGE).await.unwrap().unwrap();
+    assert!(!failed.passed);
+    assert_eq!(pools.canaries().degraded(), [IMAGE]);
+    assert_eq!(
+        sink.alerts.lock().unwrap()[0].container_id,
+        passed.container_id
+    );
+
+    while let Some(container) = pool.take_idle().await {
+        let _ = docker
+            .remove_container(
+                &container.container_id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await;
+    }
+}
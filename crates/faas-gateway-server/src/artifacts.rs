@@ -0,0 +1,624 @@
+//! Artifact and execution log downloads with HTTP Range support.
+//!
+//! Files are streamed from disk in fixed-size chunks, so a multi-GB artifact never sits
+//! in memory. Clients resume interrupted downloads with `Range: bytes=N-` and pass the
+//! `ETag` they saw back as `If-Range`; if the content changed in between, the server
+//! ignores the range and sends the whole file again.
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing::{error, info, warn};
+
+/// Read size used when streaming files to clients.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Content-addressed artifact storage; files live at `<root>/<sha256>`.
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+    root: PathBuf,
+    chunk_size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub hash: String,
+    pub size_bytes: u64,
+}
+
+impl ArtifactStore {
+    pub fn new(root: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self {
+            root,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Store rooted at `FAAS_ARTIFACT_DIR`, or a temp directory when unset.
+    pub fn from_env() -> std::io::Result<Self> {
+        let root = std::env::var("FAAS_ARTIFACT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("faas-artifacts"));
+        Self::new(root)
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// The directory the logs are kept in
+    pub fn root(&self) -> &std::path::Path {
+        &self.root
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Path for a hash, or `None` if it isn't a lowercase hex SHA-256.
+    pub fn path_for(&self, hash: &str) -> Option<PathBuf> {
+        let valid = hash.len() == 64
+            && hash
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        valid.then(|| self.root.join(hash))
+    }
+
+    /// Stream a body to disk, hashing as it goes, and file it under its digest.
+    pub async fn put_stream<S, E>(&self, mut stream: S) -> std::io::Result<ArtifactInfo>
+    where
+        S: futures::Stream<Item = Result<bytes::Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let temp_path = self.root.join(format!(".upload-{}", uuid::Uuid::new_v4()));
+        let mut file = File::create(&temp_path).await?;
+        let mut hasher = Sha256::new();
+        let mut size_bytes = 0u64;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(&temp_path).await;
+                    return Err(std::io::Error::other(format!("Upload interrupted: {e}")));
+                }
+            };
+            hasher.update(&chunk);
+            size_bytes += chunk.len() as u64;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let hash = format!("{:x}", hasher.finalize());
+        tokio::fs::rename(&temp_path, self.root.join(&hash)).await?;
+        Ok(ArtifactInfo { hash, size_bytes })
+    }
+}
+
+/// Captured execution output, one file per request id.
+#[derive(Debug, Clone)]
+pub struct LogStore {
+    root: PathBuf,
+    chunk_size: usize,
+}
+
+impl LogStore {
+    pub fn new(root: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self {
+            root,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Store rooted at `FAAS_LOG_DIR`, or a temp directory when unset.
+    pub fn from_env() -> std::io::Result<Self> {
+        let root = std::env::var("FAAS_LOG_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("faas-logs"));
+        Self::new(root)
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn path_for(&self, id: &str) -> Option<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        valid.then(|| self.root.join(format!("{id}.log")))
+    }
+
+    pub async fn append(&self, id: &str, data: &[u8]) -> std::io::Result<()> {
+        let path = self.path_for(id).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid execution id")
+        })?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(data).await?;
+        file.flush().await
+    }
+
+    /// Move `file` in as the log of `id`, replacing anything appended to it so far
+    pub async fn adopt(&self, id: &str, file: &std::path::Path) -> std::io::Result<()> {
+        let path = self.path_for(id).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid execution id")
+        })?;
+        if tokio::fs::rename(file, &path).await.is_err() {
+            // The executor's spill directory may be on another filesystem
+            tokio::fs::copy(file, &path).await?;
+            tokio::fs::remove_file(file).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete logs last written more than `retention` ago; returns the logs kept, with
+    /// their sizes.
+    pub async fn sweep(
+        &self,
+        retention: std::time::Duration,
+    ) -> std::io::Result<Vec<(String, u64)>> {
+        let mut kept = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".log"))
+            else {
+                continue;
+            };
+            let metadata = entry.metadata().await?;
+            let age = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .unwrap_or_default();
+            if age > retention {
+                tokio::fs::remove_file(&path).await?;
+            } else {
+                kept.push((id.to_string(), metadata.len()));
+            }
+        }
+        Ok(kept)
+    }
+}
+
+/// A parsed, satisfiable byte range (inclusive end).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeError {
+    /// Syntactically valid but outside the file; answered with 416.
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes=` range against a file of `len` bytes.
+///
+/// Malformed and multi-range headers return `Ok(None)`: the server is allowed to ignore
+/// them and send the full content.
+pub fn parse_range(value: &str, len: u64) -> Result<Option<ByteRange>, RangeError> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // Suffix range: the last N bytes.
+        let Ok(suffix) = end.parse::<u64>() else {
+            return Ok(None);
+        };
+        if suffix == 0 || len == 0 {
+            return Err(RangeError::Unsatisfiable);
+        }
+        ByteRange {
+            start: len.saturating_sub(suffix),
+            end: len - 1,
+        }
+    } else {
+        let Ok(start) = start.parse::<u64>() else {
+            return Ok(None);
+        };
+        let end = if end.is_empty() {
+            len.saturating_sub(1)
+        } else {
+            match end.parse::<u64>() {
+                Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+                _ => return Ok(None),
+            }
+        };
+        if start >= len {
+            return Err(RangeError::Unsatisfiable);
+        }
+        ByteRange { start, end }
+    };
+    Ok(Some(range))
+}
+
+/// Serve `path` honoring `Range` / `If-Range`, streaming from disk.
+pub async fn serve_file(
+    path: PathBuf,
+    etag: impl FnOnce(&std::fs::Metadata) -> String,
+    headers: &HeaderMap,
+    chunk_size: usize,
+) -> Response {
+    let mut file = match File::open(&path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return StatusCode::NOT_FOUND.into_response()
+        }
+        Err(e) => {
+            error!("Failed to open {}: {}", path.display(), e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let metadata = match file.metadata().await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            error!("Failed to stat {}: {}", path.display(), e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let len = metadata.len();
+    let etag = etag(&metadata);
+
+    // A stale If-Range validator means the client's partial copy is useless.
+    let range_allowed = match headers.get(header::IF_RANGE) {
+        Some(value) => value.to_str().map(|v| v == etag).unwrap_or(false),
+        None => true,
+    };
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) if range_allowed => parse_range(value, len),
+        _ => Ok(None),
+    };
+
+    let mut builder = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_TYPE, "application/octet-stream");
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        builder = builder.header(header::ETAG, value);
+    }
+
+    let (status, start, count) = match range {
+        Ok(Some(range)) => {
+            builder = builder.header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end, len),
+            );
+            (
+                StatusCode::PARTIAL_CONTENT,
+                range.start,
+                range.end - range.start + 1,
+            )
+        }
+        Ok(None) => (StatusCode::OK, 0, len),
+        Err(RangeError::Unsatisfiable) => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                .body(Body::empty())
+                .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    if start > 0 {
+        if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+            error!("Failed to seek {}: {}", path.display(), e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    let stream = ReaderStream::with_capacity(file.take(count), chunk_size);
+
+    builder
+        .status(status)
+        .header(header::CONTENT_LENGTH, count)
+        .body(Body::from_stream(stream))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// Artifacts are content-addressed, so the hash is a strong validator.
+fn artifact_etag(hash: &str) -> String {
+    format!("\"{hash}\"")
+}
+
+/// Logs grow while an execution runs; size + mtime changes whenever content does.
+fn log_etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+/// `GET /api/v1/artifacts/:hash`
+pub async fn download_artifact_handler(
+    State(store): State<Arc<ArtifactStore>>,
+    Path(hash): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(path) = store.path_for(&hash) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    serve_file(path, |_| artifact_etag(&hash), &headers, store.chunk_size).await
+}
+
+/// `PUT /api/v1/artifacts`
+pub async fn upload_artifact_handler(
+    State(store): State<Arc<ArtifactStore>>,
+    body: Body,
+) -> Result<Json<ArtifactInfo>, StatusCode> {
+    match store.put_stream(body.into_data_stream()).await {
+        Ok(info) => {
+            info!("Stored artifact {} ({} bytes)", info.hash, info.size_bytes);
+            Ok(Json(info))
+        }
+        Err(e) => {
+            warn!("Artifact upload failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// `GET /api/v1/logs/:id`
+pub async fn download_log_handler(
+    State(logs): State<Arc<LogStore>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(path) = logs.path_for(&id) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    serve_file(path, log_etag, &headers, logs.chunk_size).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        http::Request,
+        routing::{get, put},
+        Router,
+    };
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn app(store: Arc<ArtifactStore>) -> Router {
+        Router::new()
+            .route("/api/v1/artifacts", put(upload_artifact_handler))
+            .route("/api/v1/artifacts/:hash", get(download_artifact_handler))
+            .with_state(store)
+    }
+
+    fn temp_store() -> Arc<ArtifactStore> {
+        let dir =
+            std::env::temp_dir().join(format!("faas-artifacts-test-{}", uuid::Uuid::new_v4()));
+        Arc::new(ArtifactStore::new(dir).unwrap().with_chunk_size(8 * 1024))
+    }
+
+    async fn get_range(app: &Router, hash: &str, headers: &[(&str, &str)]) -> Response {
+        let mut request = Request::builder().uri(format!("/api/v1/artifacts/{hash}"));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn body_bytes(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn parses_range_forms() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            Ok(Some(ByteRange { start: 0, end: 99 }))
+        );
+        assert_eq!(
+            parse_range("bytes=500-", 1000),
+            Ok(Some(ByteRange {
+                start: 500,
+                end: 999
+            }))
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            Ok(Some(ByteRange {
+                start: 900,
+                end: 999
+            }))
+        );
+        assert_eq!(
+            parse_range("bytes=0-5000", 1000),
+            Ok(Some(ByteRange { start: 0, end: 999 }))
+        );
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            Err(RangeError::Unsatisfiable)
+        );
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn large_artifact_downloads_in_two_ranged_halves() {
+        let store = temp_store();
+        let app = app(store.clone());
+
+        let data: Vec<u8> = (0..50 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+        let expected_hash = format!("{:x}", Sha256::digest(&data));
+
+        let upload = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri("/api/v1/artifacts")
+                    .body(Body::from(data.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(upload.status(), StatusCode::OK);
+        let info: ArtifactInfo = serde_json::from_slice(&body_bytes(upload).await).unwrap();
+        assert_eq!(info.hash, expected_hash);
+        assert_eq!(info.size_bytes, data.len() as u64);
+
+        let half = data.len() / 2;
+        let first = get_range(
+            &app,
+            &info.hash,
+            &[("range", &format!("bytes=0-{}", half - 1))],
+        )
+        .await;
+        assert_eq!(first.status(), StatusCode::PARTIAL_CONTENT);
+        let etag = first.headers()[header::ETAG].to_str().unwrap().to_string();
+        let mut assembled = body_bytes(first).await;
+        assert_eq!(assembled.len(), half);
+
+        let second = get_range(
+            &app,
+            &info.hash,
+            &[("range", &format!("bytes={half}-")), ("if-range", &etag)],
+        )
+        .await;
+        assert_eq!(second.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            second.headers()[header::CONTENT_RANGE],
+            format!("bytes {}-{}/{}", half, data.len() - 1, data.len()).as_str()
+        );
+        assembled.extend(body_bytes(second).await);
+
+        assert_eq!(format!("{:x}", Sha256::digest(&assembled)), expected_hash);
+    }
+
+    #[tokio::test]
+    async fn resume_after_abort_and_stale_validator() {
+        let store = temp_store();
+        let app = app(store.clone());
+        let data = vec![7u8; 256 * 1024];
+        let info = store
+            .put_stream(futures::stream::iter(vec![Ok::<_, std::io::Error>(
+                bytes::Bytes::from(data.clone()),
+            )]))
+            .await
+            .unwrap();
+
+        // Abort after the first chunk, then resume from where we stopped.
+        let full = get_range(&app, &info.hash, &[]).await;
+        let etag = full.headers()[header::ETAG].to_str().unwrap().to_string();
+        let mut stream = full.into_body().into_data_stream();
+        let mut received = stream.next().await.unwrap().unwrap().to_vec();
+        drop(stream);
+        assert!(received.len() < data.len());
+
+        let resumed = get_range(
+            &app,
+            &info.hash,
+            &[
+                ("range", &format!("bytes={}-", received.len())),
+                ("if-range", &etag),
+            ],
+        )
+        .await;
+        assert_eq!(resumed.status(), StatusCode::PARTIAL_CONTENT);
+        received.extend(body_bytes(resumed).await);
+        assert_eq!(received, data);
+
+        // A validator from different content falls back to the full body.
+        let stale = get_range(
+            &app,
+            &info.hash,
+            &[("range", "bytes=10-"), ("if-range", "\"something-else\"")],
+        )
+        .await;
+        assert_eq!(stale.status(), StatusCode::OK);
+        assert_eq!(body_bytes(stale).await.len(), data.len());
+
+        let past_end = get_range(
+            &app,
+            &info.hash,
+            &[("range", &format!("bytes={}-", data.len()))],
+        )
+        .await;
+        assert_eq!(past_end.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    #[tokio::test]
+    async fn sweep_keeps_logs_inside_the_retention_period() {
+        let dir = std::env::temp_dir().join(format!("faas-logs-test-{}", uuid::Uuid::new_v4()));
+        let logs = LogStore::new(&dir).unwrap();
+        logs.append("exec-1", b"hello").await.unwrap();
+        logs.append("exec-2", b"hi").await.unwrap();
+
+        let mut kept = logs.sweep(Duration::from_secs(3600)).await.unwrap();
+        kept.sort();
+        assert_eq!(kept, [("exec-1".to_string(), 5), ("exec-2".to_string(), 2)]);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(logs
+            .sweep(Duration::from_millis(10))
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(!logs.path_for("exec-1").unwrap().exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_spilled_log_replaces_the_captured_one() {
+        let dir = std::env::temp_dir().join(format!("faas-logs-test-{}", uuid::Uuid::new_v4()));
+        let logs = LogStore::new(dir.join("logs")).unwrap();
+        logs.append("exec-1", b"cut short").await.unwrap();
+        let spilled = dir.join("exec-1.spill");
+        std::fs::write(&spilled, b"all of the output").unwrap();
+
+        logs.adopt("exec-1", &spilled).await.unwrap();
+        let stored = std::fs::read(logs.path_for("exec-1").unwrap()).unwrap();
+        assert_eq!(stored, b"all of the output");
+        assert!(!spilled.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,627 @@
+//! API keys for the gateway's routes.
+//!
+//! Keys come from `FAAS_API_KEYS_FILE`, a JSON list of [`ApiKeyConfig`], and from
+//! `FAAS_API_KEY`, one key with every permission. With neither set the gateway stays open, as
+//! it was before keys existed. Clients send a key as `X-Api-Key` or `Authorization: Bearer`.
+//! `/health` never needs one.
+//!
+//! Each route needs one [`Permission`], see [`required_permission`]; `admin` grants them all.
+//! A key with a `tenant` acts for it: the tenant header is set from the key, whatever the
+//! request sent, and a key without one acts for nobody. Only an `admin` key may name a
+//! tenant itself; one that names none and has none sees every tenant's resources, see
+//! [`crate::tenancy`]. A key with a `rate_limit` gets a token bucket holding `burst` requests
+//! (`per_minute` by default) and refilling at `per_minute`; an empty bucket answers 429 with
+//! `Retry-After`. A key with `limits` may not request resource limits above them, on top of
+//! the gateway's own caps, see [`crate::limits`].
+//!
+//! Executions reach `/api/v1/kv` with their own `FAAS_KV_TOKEN` as a bearer token, which is
+//! not an API key, so a bearer token there that isn't a key is left for the KV store to check.
+//! A request a key let through carries its [`Authorized`] as an extension.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use faas_common::hash::sha256_hex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::limits::{LimitCaps, LIMITS_HEADER};
+use crate::snapshot_fs::TENANT_HEADER;
+use crate::tenancy::SCOPE_HEADER;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Run executions, workflows and groups, and use payloads, artifacts, logs and KV
+    Execute,
+    /// Create, exec in, stop and snapshot instances, and prewarm or change warm pools
+    ManageInstances,
+    /// Metrics, usage, events, pools and capabilities
+    ReadMetrics,
+    /// Drain, kill switch and any account's usage; implies the others
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    /// Requests that may arrive at once; `per_minute` by default
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    pub key: String,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub limits: Option<LimitCaps>,
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("an API key is required, as X-Api-Key or Authorization: Bearer")]
+    Missing,
+    #[error("unknown API key")]
+    Invalid,
+    #[error("API key {key} lacks the {permission:?} permission")]
+    Forbidden { key: String, permission: Permission },
+    #[error("API key {key} is over its rate limit")]
+    RateLimited { key: String, retry_after: Duration },
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        let (status, code) = match &self {
+            Self::Missing | Self::Invalid => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            Self::Forbidden { .. } => (StatusCode::FORBIDDEN, "Forbidden"),
+            Self::RateLimited { .. } => (StatusCode::TOO_MANY_REQUESTS, "RateLimited"),
+        };
+        let mut response = ApiError::new(status, code, message).into_response();
+        if let Self::RateLimited { retry_after, .. } = self {
+            // Whole seconds, rounded up so a retry on time finds a token
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
+        response
+    }
+}
+
+/// The permission a request needs, or `None` for routes anyone may call
+pub fn required_permission(method: &Method, path: &str) -> Option<Permission> {
+    if path == "/health" {
+        return None;
+    }
+    let under = |prefix: &str| path == prefix || path.starts_with(&format!("{prefix}/"));
+    let permission = if under("/api/v1/admin") || under("/api/v1/accounts") {
+        Permission::Admin
+    } else if under("/api/v1/pools") {
+        if method == Method::GET {
+            Permission::ReadMetrics
+        } else {
+            Permission::ManageInstances
+        }
+    } else if under("/api/v1/metrics")
+        || path == "/metrics"
+        || under("/api/v1/usage")
+        || under("/api/v1/events")
+        || under("/api/v1/capabilities")
+    {
+        Permission::ReadMetrics
+    } else if under("/api/v1/instances")
+        || under("/api/v1/snapshots")
+        || under("/api/v1/containers")
+        || under("/api/v1/prewarm")
+    {
+        Permission::ManageInstances
+    } else {
+        Permission::Execute
+    };
+    Some(permission)
+}
+
+struct Key {
+    config: ApiKeyConfig,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    /// Take a token, or say how long until there is one
+    fn take(&mut self, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        let per_sec = f64::from(limit.per_minute) / 60.0;
+        let capacity = f64::from(limit.burst.unwrap_or(limit.per_minute).max(1));
+        let refill = now.saturating_duration_since(self.refilled).as_secs_f64() * per_sec;
+        self.tokens = (self.tokens + refill).min(capacity);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else if per_sec > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_sec))
+        } else {
+            Err(Duration::from_secs(60))
+        }
+    }
+}
+
+/// A request a key let through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Authorized {
+    /// The key's name
+    pub name: String,
+    pub tenant: Option<String>,
+    /// Holds [`Permission::Admin`], so it may act for any tenant
+    pub admin: bool,
+    pub limits: Option<LimitCaps>,
+}
+
+/// Configured keys, looked up by their SHA-256
+pub struct ApiKeys {
+    keys: HashMap<String, Key>,
+}
+
+impl ApiKeys {
+    pub fn new(configs: Vec<ApiKeyConfig>) -> Self {
+        let now = Instant::now();
+        let keys = configs
+            .into_iter()
+            .map(|config| {
+                let tokens = config.rate_limit.map_or(0.0, |limit| {
+                    f64::from(limit.burst.unwrap_or(limit.per_minute))
+                });
+                let key = Key {
+                    config,
+                    bucket: Mutex::new(Bucket {
+                        tokens,
+                        refilled: now,
+                    }),
+                };
+                (sha256_hex(key.config.key.as_bytes()), key)
+            })
+            .collect();
+        Self { keys }
+    }
+
+    pub fn from_env() -> std::io::Result<Self> {
+        let mut configs = Vec::new();
+        if let Ok(path) = std::env::var("FAAS_API_KEYS_FILE") {
+            let file = std::fs::read(PathBuf::from(&path))?;
+            let listed: Vec<ApiKeyConfig> = serde_json::from_slice(&file).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path}: {e}"))
+            })?;
+            configs.extend(listed);
+        }
+        if let Ok(key) = std::env::var("FAAS_API_KEY") {
+            configs.push(ApiKeyConfig {
+                key,
+                name: "default".to_string(),
+                permissions: vec![Permission::Admin],
+                tenant: None,
+                rate_limit: None,
+                limits: None,
+            });
+        }
+        if configs.is_empty() {
+            warn!("No API keys configured; every route is open");
+        }
+        Ok(Self::new(configs))
+    }
+
+    /// Whether requests need a key at all
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Check the request's key; `Ok(None)` when it needs none
+    pub fn authorize(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+        path: &str,
+        now: Instant,
+    ) -> Result<Option<Authorized>, AuthError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let Some(permission) = required_permission(method, path) else {
+            return Ok(None);
+        };
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let bearer = header(header::AUTHORIZATION.as_str())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+        let presented = header(API_KEY_HEADER).or(bearer);
+        let key = match presented.map(|key| self.keys.get(&sha256_hex(key.as_bytes()))) {
+            Some(Some(key)) => key,
+            // An execution's KV token, checked by the KV store
+            Some(None) if bearer.is_some() && path.starts_with("/api/v1/kv/") => return Ok(None),
+            Some(None) => return Err(AuthError::Invalid),
+            None => return Err(AuthError::Missing),
+        };
+
+        let config = &key.config;
+        let admin = config.permissions.contains(&Permission::Admin);
+        let allowed = admin || config.permissions.contains(&permission);
+        if !allowed {
+            return Err(AuthError::Forbidden {
+                key: config.name.clone(),
+                permission,
+            });
+        }
+        if let Some(limit) = config.rate_limit {
+            let taken = key.bucket.lock().unwrap().take(limit, now);
+            taken.map_err(|retry_after| AuthError::RateLimited {
+                key: config.name.clone(),
+                retry_after,
+            })?;
+        }
+        Ok(Some(Authorized {
+            name: config.name.clone(),
+            tenant: config.tenant.clone(),
+            admin,
+            limits: config.limits.clone(),
+        }))
+    }
+}
+
+/// Refuse requests without a key allowed to make them
+pub async fn require_api_key(
+    State(keys): State<Arc<ApiKeys>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let authorized = keys.authorize(
+        request.headers(),
+        request.method(),
+        request.uri().path(),
+        Instant::now(),
+    );
+    if keys.is_enabled() {
+        // Only this layer grants the scope of every tenant, or sets a key's caps
+        request.headers_mut().remove(SCOPE_HEADER);
+        request.headers_mut().remove(LIMITS_HEADER);
+    }
+    match authorized {
+        Ok(Some(authorized)) => {
+            // For handlers that take a bearer token of their own, like the KV store, to tell
+            // a key from a token they don't know
+            request.extensions_mut().insert(authorized.clone());
+            let headers = request.headers_mut();
+            if let Some(caps) = &authorized.limits {
+                let caps = serde_json::to_string(caps).expect("limit caps serialize");
+                if let Ok(caps) = HeaderValue::from_str(&caps) {
+                    headers.insert(LIMITS_HEADER, caps);
+                }
+            }
+            let named = authorized.admin && headers.contains_key(TENANT_HEADER);
+            if !named {
+                headers.remove(TENANT_HEADER);
+                match authorized.tenant {
+                    Some(tenant) => {
+                        if let Ok(tenant) = HeaderValue::from_str(&tenant) {
+                            headers.insert(TENANT_HEADER, tenant);
+                        }
+                    }
+                    None if authorized.admin => {
+                        headers.insert(SCOPE_HEADER, HeaderValue::from_static("all"));
+                    }
+                    None => {}
+                }
+            }
+            next.run(request).await
+        }
+        Ok(None) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn key(key: &str, permissions: &[Permission]) -> ApiKeyConfig {
+        ApiKeyConfig {
+            key: key.to_string(),
+            name: key.to_string(),
+            permissions: permissions.to_vec(),
+            tenant: None,
+            rate_limit: None,
+            limits: None,
+        }
+    }
+
+    fn app(keys: Vec<ApiKeyConfig>) -> Router {
+        let tenant = |headers: HeaderMap| async move {
+            headers
+                .get(TENANT_HEADER)
+                .map_or("none".to_string(), |v| v.to_str().unwrap().to_string())
+        };
+        Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route("/api/v1/execute", get(tenant))
+            .route("/api/v1/metrics", get(|| async { "metrics" }))
+            .route("/api/v1/kv/:namespace/:key", get(|| async { "value" }))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(ApiKeys::new(keys)),
+                require_api_key,
+            ))
+    }
+
+    async fn call(app: &Router, path: &str, headers: &[(&str, &str)]) -> Response {
+        let mut request = Request::get(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(Body::empty()).expect("valid request");
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_known_key_are_unauthorized() {
+        let app = app(vec![key("k-exec", &[Permission::Execute])]);
+        let missing = call(&app, "/api/v1/execute", &[]).await;
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        let unknown = call(&app, "/api/v1/execute", &[(API_KEY_HEADER, "nope")]).await;
+        assert_eq!(unknown.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(call(&app, "/health", &[]).await.status(), StatusCode::OK);
+
+        let by_header = call(&app, "/api/v1/execute", &[(API_KEY_HEADER, "k-exec")]).await;
+        assert_eq!(by_header.status(), StatusCode::OK);
+        let bearer = [("authorization", "Bearer k-exec")];
+        assert_eq!(
+            call(&app, "/api/v1/execute", &bearer).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn a_key_without_the_permission_is_forbidden() {
+        let app = app(vec![
+            key("k-exec", &[Permission::Execute]),
+            key("k-admin", &[Permission::Admin]),
+        ]);
+        let exec = [(API_KEY_HEADER, "k-exec")];
+        let refused = call(&app, "/api/v1/metrics", &exec).await;
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+        assert!(body(refused).await.contains("ReadMetrics"));
+        let admin = [(API_KEY_HEADER, "k-admin")];
+        assert_eq!(
+            call(&app, "/api/v1/metrics", &admin).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn a_key_over_its_rate_is_told_when_to_retry() {
+        let mut limited = key("k-limited", &[Permission::Execute]);
+        limited.rate_limit = Some(RateLimit {
+            per_minute: 60,
+            burst: Some(2),
+        });
+        let app = app(vec![limited]);
+        let headers = [(API_KEY_HEADER, "k-limited")];
+        for _ in 0..2 {
+            let allowed = call(&app, "/api/v1/execute", &headers).await;
+            assert_eq!(allowed.status(), StatusCode::OK);
+        }
+        let limited = call(&app, "/api/v1/execute", &headers).await;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[test]
+    fn the_bucket_refills_at_its_rate() {
+        let limit = RateLimit {
+            per_minute: 30,
+            burst: Some(1),
+        };
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 1.0,
+            refilled: start,
+        };
+        assert!(bucket.take(limit, start).is_ok());
+        assert_eq!(bucket.take(limit, start), Err(Duration::from_secs(2)));
+        assert!(bucket.take(limit, start + Duration::from_secs(2)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_tenant_key_sets_the_tenant_and_kv_tokens_pass_through() {
+        let mut acme = key("k-acme", &[Permission::Execute]);
+        acme.tenant = Some("acme".to_string());
+        let app = app(vec![acme, key("k-open", &[Permission::Execute])]);
+        let spoofed = [(API_KEY_HEADER, "k-acme"), (TENANT_HEADER, "globex")];
+        assert_eq!(
+            body(call(&app, "/api/v1/execute", &spoofed).await).await,
+            "acme"
+        );
+        let untenanted = [(API_KEY_HEADER, "k-open"), (TENANT_HEADER, "globex")];
+        assert_eq!(
+            body(call(&app, "/api/v1/execute", &untenanted).await).await,
+            "none"
+        );
+
+        let kv_token = [("authorization", "Bearer 5f3c0ffee")];
+        assert_eq!(
+            call(&app, "/api/v1/kv/default/winner", &kv_token)
+                .await
+                .status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            call(&app, "/api/v1/execute", &kv_token).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn each_key_is_held_to_its_own_limit_caps() {
+        use crate::limits::LimitsPolicy;
+
+        // Asks for 256 MB of shared memory, within the gateway's own cap
+        let resolve = |headers: HeaderMap| async move {
+            let caps = LimitCaps::from_headers(&headers);
+            match LimitsPolicy::default().resolve(caps.as_ref(), None, Some(256), None) {
+                Ok(_) => StatusCode::OK,
+                Err(_) => StatusCode::BAD_REQUEST,
+            }
+        };
+        let mut small = key("k-small", &[Permission::Execute]);
+        small.limits = Some(LimitCaps {
+            max_shm_size_mb: Some(128),
+            ..Default::default()
+        });
+        let mut large = key("k-large", &[Permission::Execute]);
+        large.limits = Some(LimitCaps {
+            max_shm_size_mb: Some(512),
+            ..Default::default()
+        });
+        let keys = vec![small, large];
+        let app = Router::new().route("/api/v1/execute", get(resolve)).layer(
+            axum::middleware::from_fn_with_state(Arc::new(ApiKeys::new(keys)), require_api_key),
+        );
+
+        let small = [(API_KEY_HEADER, "k-small")];
+        assert_eq!(
+            call(&app, "/api/v1/execute", &small).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        let large = [(API_KEY_HEADER, "k-large")];
+        assert_eq!(
+            call(&app, "/api/v1/execute", &large).await.status(),
+            StatusCode::OK
+        );
+        // A request can't raise its key's caps by sending its own
+        let spoofed = [
+            (API_KEY_HEADER, "k-small"),
+            (LIMITS_HEADER, r#"{"max_shm_size_mb":512}"#),
+        ];
+        assert_eq!(
+            call(&app, "/api/v1/execute", &spoofed).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn only_admin_keys_name_a_tenant_or_see_them_all() {
+        let scope = |headers: HeaderMap| async move {
+            let value = |name| headers.get(name).map(|v| v.to_str().unwrap().to_string());
+            format!(
+                "{}/{}",
+                value(TENANT_HEADER).unwrap_or_default(),
+                value(SCOPE_HEADER).unwrap_or_default()
+            )
+        };
+        let mut acme = key("k-acme", &[Permission::Execute]);
+        acme.tenant = Some("acme".to_string());
+        let keys = vec![acme, key("k-root", &[Permission::Admin])];
+        let app = Router::new().route("/api/v1/execute", get(scope)).layer(
+            axum::middleware::from_fn_with_state(Arc::new(ApiKeys::new(keys)), require_api_key),
+        );
+
+        let cases = [
+            (&[(API_KEY_HEADER, "k-root")][..], "/all"),
+            (
+                &[(API_KEY_HEADER, "k-root"), (TENANT_HEADER, "globex")],
+                "globex/",
+            ),
+            (
+                &[(API_KEY_HEADER, "k-acme"), (SCOPE_HEADER, "all")],
+                "acme/",
+            ),
+        ];
+        for (headers, expected) in cases {
+            let response = call(&app, "/api/v1/execute", headers).await;
+            assert_eq!(body(response).await, expected, "{headers:?}");
+        }
+    }
+
+    #[test]
+    fn routes_need_the_permission_for_what_they_do() {
+        let cases = [
+            (Method::GET, "/health", None),
+            (Method::POST, "/api/v1/execute", Some(Permission::Execute)),
+            (Method::POST, "/api/v1/workflows", Some(Permission::Execute)),
+            (
+                Method::POST,
+                "/api/v1/instances/i-1/exec",
+                Some(Permission::ManageInstances),
+            ),
+            (
+                Method::GET,
+                "/api/v1/snapshots",
+                Some(Permission::ManageInstances),
+            ),
+            (
+                Method::GET,
+                "/api/v1/pools/snapshots",
+                Some(Permission::ReadMetrics),
+            ),
+            (
+                Method::POST,
+                "/api/v1/pools/snapshots/s-1/pin",
+                Some(Permission::ManageInstances),
+            ),
+            (
+                Method::GET,
+                "/api/v1/metrics/detailed",
+                Some(Permission::ReadMetrics),
+            ),
+            (Method::GET, "/metrics", Some(Permission::ReadMetrics)),
+            (Method::POST, "/api/v1/admin/drain", Some(Permission::Admin)),
+            (
+                Method::GET,
+                "/api/v1/accounts/acme/usage",
+                Some(Permission::Admin),
+            ),
+        ];
+        for (method, path, permission) in cases {
+            assert_eq!(required_permission(&method, path), permission, "{path}");
+        }
+    }
+
+    #[test]
+    fn without_keys_everything_is_open() {
+        let keys = ApiKeys::new(Vec::new());
+        let open = keys.authorize(
+            &HeaderMap::new(),
+            &Method::POST,
+            "/api/v1/execute",
+            Instant::now(),
+        );
+        assert_eq!(open.unwrap(), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! Many independent executions in one request, `POST /api/v1/execute/batch`.
+//!
+//! Each item is admitted, limited, metered and replayed by its idempotency key like an
+//! execution sent on its own, and one that fails doesn't stop the rest: its place in the
+//! answer carries the error instead. Items run `max_parallelism` at a time, a hint the
+//! gateway caps at `FAAS_BATCH_MAX_PARALLELISM` (16); a batch holds at most
+//! `FAAS_BATCH_MAX_ITEMS` (1000). The answer is a JSON array in input order or, for
+//! `Accept: application/x-ndjson`, one line per item as it finishes, naming its `index`.
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::future::Future;
+
+use crate::errors::{ApiError, ApiErrorResponse};
+use crate::InvokeResponse;
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+const DEFAULT_MAX_ITEMS: usize = 1000;
+const DEFAULT_MAX_PARALLELISM: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLimits {
+    pub max_items: usize,
+    pub max_parallelism: usize,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_items: DEFAULT_MAX_ITEMS,
+            max_parallelism: DEFAULT_MAX_PARALLELISM,
+        }
+    }
+}
+
+impl BatchLimits {
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|&v: &usize| v > 0)
+                .unwrap_or(default)
+        };
+        Self {
+            max_items: read("FAAS_BATCH_MAX_ITEMS", DEFAULT_MAX_ITEMS),
+            max_parallelism: read("FAAS_BATCH_MAX_PARALLELISM", DEFAULT_MAX_PARALLELISM),
+        }
+    }
+
+    /// How many of `items` run at once for a `hint`; an empty or oversized batch is refused
+    pub fn parallelism(&self, items: usize, hint: Option<usize>) -> Result<usize, ApiError> {
+        if items == 0 {
+            return Err(
+                ApiError::invalid_request("a batch needs at least one request")
+                    .with_details(serde_json::json!({ "field": "requests" })),
+            );
+        }
+        if items > self.max_items {
+            return Err(ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "BatchTooLarge",
+                format!(
+                    "a batch holds at most {} requests, not {items}",
+                    self.max_items
+                ),
+            )
+            .with_details(serde_json::json!({ "max_items": self.max_items })));
+        }
+        let wanted = hint.unwrap_or(self.max_parallelism).max(1);
+        Ok(wanted.min(self.max_parallelism).min(items))
+    }
+}
+
+/// One item's outcome: its `response`, or the `error` it would have been answered with alone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    /// Position in the batch
+    pub index: usize,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<InvokeResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiErrorResponse>,
+}
+
+impl BatchItem {
+    fn new(index: usize, outcome: Result<InvokeResponse, ApiError>) -> Self {
+        match outcome {
+            Ok(response) => Self {
+                index,
+                status: StatusCode::OK.as_u16(),
+                response: Some(response),
+                error: None,
+            },
+            Err(e) => Self {
+                index,
+                status: e.status.as_u16(),
+                response: None,
+                error: Some(e.body),
+            },
+        }
+    }
+}
+
+/// Run every item through `execute`, `parallelism` at a time, yielding each as it finishes
+pub fn run<T, F, Fut>(
+    items: Vec<T>,
+    parallelism: usize,
+    mut execute: F,
+) -> impl Stream<Item = BatchItem>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Result<InvokeResponse, ApiError>>,
+{
+    futures::stream::iter(items.into_iter().enumerate())
+        .map(move |(index, item)| {
+            let outcome = execute(item);
+            async move { BatchItem::new(index, outcome.await) }
+        })
+        .buffer_unordered(parallelism.max(1))
+}
+
+/// Whether the client asked for items as they finish
+pub fn wants_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.split(',').any(|t| t.trim().starts_with(NDJSON)))
+}
+
+/// Every item in input order, or NDJSON lines as they finish when `headers` ask for them
+pub async fn respond<S>(headers: &HeaderMap, items: S) -> Response
+where
+    S: Stream<Item = BatchItem> + Send + 'static,
+{
+    if wants_ndjson(headers) {
+        let lines = items.map(|item| {
+            let mut line = serde_json::to_vec(&item).unwrap_or_default();
+            line.push(b'\n');
+            Ok::<_, Infallible>(line)
+        });
+        let mut response = Body::from_stream(lines).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(NDJSON));
+        return response;
+    }
+    let mut items: Vec<BatchItem> = items.collect().await;
+    items.sort_unstable_by_key(|item| item.index);
+    Json(items).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn response(id: &str) -> InvokeResponse {
+        InvokeResponse {
+            request_id: id.to_string(),
+            exit_code: 0,
+            stdout: format!("{id}\n"),
+            stderr: String::new(),
+            duration_ms: 1,
+            output: None,
+            logs: None,
+            error: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime: None,
+            runtime_reason: None,
+            diagnostics: None,
+            usage: None,
+            truncated: false,
+            total_bytes: None,
+            snapshot_id: None,
+        }
+    }
+
+    /// Later items finish first; item 2 fails
+    async fn execute(item: usize) -> Result<InvokeResponse, ApiError> {
+        tokio::time::sleep(Duration::from_millis(40 - 10 * item as u64)).await;
+        if item == 2 {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                "ImageNotFound",
+                "no such image",
+            ));
+        }
+        Ok(response(&format!("run-{item}")))
+    }
+
+    #[test]
+    fn parallelism_is_capped_by_the_gateway() {
+        let limits = BatchLimits {
+            max_items: 10,
+            max_parallelism: 4,
+        };
+        assert_eq!(limits.parallelism(10, None).unwrap(), 4);
+        assert_eq!(limits.parallelism(10, Some(2)).unwrap(), 2);
+        assert_eq!(limits.parallelism(10, Some(64)).unwrap(), 4);
+        assert_eq!(limits.parallelism(10, Some(0)).unwrap(), 1);
+        assert_eq!(limits.parallelism(3, None).unwrap(), 3);
+
+        let empty = limits.parallelism(0, None).unwrap_err();
+        assert_eq!(empty.status, StatusCode::UNPROCESSABLE_ENTITY);
+        let oversized = limits.parallelism(11, None).unwrap_err();
+        assert_eq!(oversized.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(oversized.body.code, "BatchTooLarge");
+    }
+
+    #[tokio::test]
+    async fn no_more_than_the_parallelism_run_at_once() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let items = run((0..12).collect(), 3, |item: usize| {
+            let (running, peak) = (running.clone(), peak.clone());
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(response(&item.to_string()))
+            }
+        });
+        assert_eq!(items.collect::<Vec<_>>().await.len(), 12);
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn the_answer_keeps_input_order_and_per_item_errors() {
+        let answer = respond(&HeaderMap::new(), run((0..4).collect(), 4, execute)).await;
+        assert_eq!(answer.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(answer.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let items: Vec<BatchItem> = serde_json::from_slice(&body).unwrap();
+        let indexes: Vec<_> = items.iter().map(|item| item.index).collect();
+        assert_eq!(indexes, [0, 1, 2, 3]);
+        assert_eq!(items[0].response.as_ref().unwrap().request_id, "run-0");
+        assert_eq!(items[2].status, 404);
+        assert_eq!(items[2].error.as_ref().unwrap().code, "ImageNotFound");
+        assert!(items[2].response.is_none());
+    }
+
+    #[tokio::test]
+    async fn ndjson_streams_items_as_they_finish() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/x-ndjson, application/json;q=0.5"),
+        );
+        let answer = respond(&headers, run((0..4).collect(), 4, execute)).await;
+        assert_eq!(answer.headers()[header::CONTENT_TYPE], NDJSON);
+        let body = axum::body::to_bytes(answer.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let indexes: Vec<usize> = body
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<BatchItem>(line).unwrap().index)
+            .collect();
+        assert_eq!(indexes, [3, 2, 1, 0]);
+    }
+}
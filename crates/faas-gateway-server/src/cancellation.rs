@@ -0,0 +1,556 @@
+//! Cascade cancellation.
+//!
+//! Workflows, groups and fork parents are nodes in one tree, and the work started on their
+//! behalf registers as their children: a workflow's steps, a group's members, a fork's
+//! branches. Cancelling a node cancels everything below it, and each cancelled child
+//! records its parent as `cancelled_by` and how far below the cancelled node it sat as
+//! `depth`.
+//!
+//! Registration and cancellation take the same lock, and a child only registers under a
+//! parent that isn't cancelled. A child submitted while its parent is being cancelled is
+//! therefore either refused at admission or registered in time to be cancelled with the
+//! rest. Cancelled nodes stay behind as tombstones for a while, so a late child of a
+//! finished-but-cancelled parent is refused too. Every cancellation goes to the
+//! `faas_audit` log target.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How long a cancelled id is remembered after its work ends
+pub const TOMBSTONE_TTL: Duration = Duration::from_secs(600);
+
+/// Which children a cancel reaches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelPolicy {
+    /// Running and pending children alike
+    #[default]
+    All,
+    /// Only children that haven't started; running ones finish, but their own pending
+    /// children are still cancelled
+    OnlyPending,
+}
+
+/// Where a node's cancellation came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cancellation {
+    /// The node the cancel was issued against
+    pub source: String,
+    /// Parent whose cancellation reached this node; `None` for the source itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancelled_by: Option<String>,
+    /// Levels below the source, 0 for the source itself
+    pub depth: u32,
+}
+
+impl Cancellation {
+    fn child_of(&self, parent: &str) -> Self {
+        Self {
+            source: self.source.clone(),
+            cancelled_by: Some(parent.to_string()),
+            depth: self.depth + 1,
+        }
+    }
+}
+
+/// Body of the cancel endpoints; each has its own default policy
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CancelRequest {
+    pub policy: Option<CancelPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelledChild {
+    pub id: String,
+    #[serde(flatten)]
+    pub cancellation: Cancellation,
+}
+
+/// What a cancel reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelReport {
+    pub id: String,
+    pub policy: CancelPolicy,
+    /// Descendants cancelled by this call, nearest first
+    pub cancelled: Vec<CancelledChild>,
+    /// Running descendants an only-pending cancel left alone
+    pub spared: Vec<String>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CancelError {
+    #[error("{id} was cancelled")]
+    Cancelled {
+        id: String,
+        cancellation: Cancellation,
+    },
+    #[error("{0} is already registered")]
+    InUse(String),
+    #[error("{0} not found")]
+    NotFound(String),
+}
+
+impl IntoResponse for CancelError {
+    fn into_response(self) -> Response {
+        let body = match &self {
+            Self::Cancelled { cancellation, .. } => serde_json::json!({
+                "error": self.to_string(),
+                "code": "Cancelled",
+                "source": cancellation.source,
+                "cancelled_by": cancellation.cancelled_by,
+                "depth": cancellation.depth,
+            }),
+            Self::InUse(_) => serde_json::json!({ "error": self.to_string(), "code": "InUse" }),
+            Self::NotFound(_) => {
+                serde_json::json!({ "error": self.to_string(), "code": "NotFound" })
+            }
+        };
+        let status = match self {
+            Self::Cancelled { .. } | Self::InUse(_) => StatusCode::CONFLICT,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[derive(Default)]
+struct Signal {
+    token: CancellationToken,
+    cancellation: OnceLock<Cancellation>,
+}
+
+struct Node {
+    parent: Option<String>,
+    children: Vec<String>,
+    running: bool,
+    signal: Arc<Signal>,
+    /// When the scope holding a cancelled node dropped; tombstones are pruned from then
+    ended: Option<Instant>,
+}
+
+impl Node {
+    fn new(parent: Option<String>) -> Self {
+        Self {
+            parent,
+            children: Vec::new(),
+            running: false,
+            signal: Arc::default(),
+            ended: None,
+        }
+    }
+
+    fn cancellation(&self) -> Option<&Cancellation> {
+        self.signal.cancellation.get()
+    }
+
+    fn cancel(&self, id: &str, cancellation: Cancellation) {
+        info!(
+            target: "faas_audit",
+            id,
+            source = %cancellation.source,
+            cancelled_by = cancellation.cancelled_by.as_deref().unwrap_or("-"),
+            depth = cancellation.depth,
+            "cascade cancelled"
+        );
+        let _ = self.signal.cancellation.set(cancellation);
+        self.signal.token.cancel();
+    }
+}
+
+#[derive(Default)]
+pub struct CancelRegistry {
+    nodes: Mutex<HashMap<String, Node>>,
+}
+
+impl CancelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node that lives as long as the registry, for parents such as groups that
+    /// have no scope of their own; an existing node is left as it is
+    pub fn open(&self, id: &str) {
+        self.nodes
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_insert_with(|| Node::new(None));
+    }
+
+    /// Register pending work under `parent`; it is tracked until the scope drops.
+    ///
+    /// Refused when the parent, or `id` itself, has been cancelled. A parent the registry
+    /// doesn't know (one that already finished) is ignored.
+    pub fn register(
+        self: &Arc<Self>,
+        id: &str,
+        parent: Option<&str>,
+    ) -> Result<CancelScope, CancelError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(existing) = nodes.get(id) {
+            return Err(match existing.cancellation() {
+                Some(cancellation) => CancelError::Cancelled {
+                    id: id.to_string(),
+                    cancellation: cancellation.clone(),
+                },
+                None => CancelError::InUse(id.to_string()),
+            });
+        }
+        let parent = parent.filter(|parent| nodes.contains_key(*parent));
+        if let Some(parent) = parent {
+            if let Some(cancellation) = nodes[parent].cancellation() {
+                let cancellation = cancellation.child_of(parent);
+                info!(
+                    target: "faas_audit",
+                    id,
+                    source = %cancellation.source,
+                    cancelled_by = parent,
+                    depth = cancellation.depth,
+                    "cascade refused admission"
+                );
+                return Err(CancelError::Cancelled {
+                    id: id.to_string(),
+                    cancellation,
+                });
+            }
+        }
+        let node = Node::new(parent.map(str::to_string));
+        let signal = node.signal.clone();
+        nodes.insert(id.to_string(), node);
+        if let Some(parent) = parent {
+            if let Some(parent) = nodes.get_mut(parent) {
+                parent.children.push(id.to_string());
+            }
+        }
+        Ok(CancelScope {
+            registry: self.clone(),
+            id: id.to_string(),
+            signal,
+        })
+    }
+
+    /// Cancel `id` and, as `policy` allows, everything below it.
+    ///
+    /// The node itself is always marked, so it admits no new children; under
+    /// [`CancelPolicy::OnlyPending`] its own work keeps running if it had started.
+    /// Cancelling again reaches only what the earlier cancel didn't.
+    pub fn cancel(&self, id: &str, policy: CancelPolicy) -> Result<CancelReport, CancelError> {
+        let nodes = self.nodes.lock().unwrap();
+        let root = nodes
+            .get(id)
+            .ok_or_else(|| CancelError::NotFound(id.to_string()))?;
+        let own = root.cancellation().cloned().unwrap_or(Cancellation {
+            source: id.to_string(),
+            cancelled_by: None,
+            depth: 0,
+        });
+        if root.cancellation().is_none() || !root.signal.token.is_cancelled() {
+            if policy == CancelPolicy::All || !root.running {
+                root.cancel(id, own.clone());
+            } else {
+                let _ = root.signal.cancellation.set(own.clone());
+                info!(target: "faas_audit", id, "cascade cancel issued, own run left running");
+            }
+        }
+
+        let mut report = CancelReport {
+            id: id.to_string(),
+            policy,
+            cancelled: Vec::new(),
+            spared: Vec::new(),
+        };
+        let mut queue: VecDeque<(&str, Cancellation)> = root
+            .children
+            .iter()
+            .map(|child| (child.as_str(), own.child_of(id)))
+            .collect();
+        while let Some((child_id, cancellation)) = queue.pop_front() {
+            let Some(child) = nodes.get(child_id) else {
+                continue;
+            };
+            if child.signal.token.is_cancelled() {
+                continue;
+            }
+            if policy == CancelPolicy::OnlyPending && child.running {
+                report.spared.push(child_id.to_string());
+            } else {
+                child.cancel(child_id, cancellation.clone());
+                report.cancelled.push(CancelledChild {
+                    id: child_id.to_string(),
+                    cancellation: cancellation.clone(),
+                });
+            }
+            queue.extend(
+                child
+                    .children
+                    .iter()
+                    .map(|grandchild| (grandchild.as_str(), cancellation.child_of(child_id))),
+            );
+        }
+        Ok(report)
+    }
+
+    /// How `id` was cancelled, while it or its tombstone is tracked
+    pub fn cancellation(&self, id: &str) -> Option<Cancellation> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(id)
+            .and_then(|node| node.cancellation().cloned())
+    }
+
+    /// Drop tombstones older than `ttl`; returns how many went
+    pub fn prune(&self, ttl: Duration) -> usize {
+        let now = Instant::now();
+        let mut nodes = self.nodes.lock().unwrap();
+        let before = nodes.len();
+        nodes.retain(|_, node| match node.ended {
+            Some(ended) => now - ended < ttl,
+            None => true,
+        });
+        before - nodes.len()
+    }
+
+    /// Nodes and tombstones currently tracked
+    pub fn len(&self) -> usize {
+        self.nodes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Registered work; dropping it stops the tracking, leaving a tombstone if it was
+/// cancelled
+pub struct CancelScope {
+    registry: Arc<CancelRegistry>,
+    id: String,
+    signal: Arc<Signal>,
+}
+
+impl CancelScope {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Mark the work as running, unless it was cancelled while pending
+    pub fn start(&self) -> Result<(), CancelError> {
+        let mut nodes = self.registry.nodes.lock().unwrap();
+        let node = nodes.get_mut(&self.id).expect("scopes outlive their node");
+        if let Some(cancellation) = node.cancellation() {
+            return Err(CancelError::Cancelled {
+                id: self.id.clone(),
+                cancellation: cancellation.clone(),
+            });
+        }
+        node.running = true;
+        Ok(())
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.signal.token.is_cancelled()
+    }
+
+    /// Resolves once a cancel reaches this work
+    pub async fn cancelled(&self) -> Cancellation {
+        self.signal.token.cancelled().await;
+        self.signal
+            .cancellation
+            .get()
+            .cloned()
+            .expect("cancelled without a source")
+    }
+}
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        let mut nodes = self.registry.nodes.lock().unwrap();
+        let Some(node) = nodes.get_mut(&self.id) else {
+            return;
+        };
+        if node.cancellation().is_some() {
+            node.running = false;
+            node.ended = Some(Instant::now());
+            return;
+        }
+        let parent = node.parent.clone();
+        nodes.remove(&self.id);
+        if let Some(parent) = parent.and_then(|parent| nodes.get_mut(&parent)) {
+            parent.children.retain(|child| child != &self.id);
+        }
+    }
+}
+
+/// Cancels an execution in the executor if dropped before [`CancelOnDrop::disarm`]. A
+/// handler holds one while its execution runs in a task of its own: the server drops the
+/// handler when its client disconnects, and the guard then stops the container or VM the
+/// task would otherwise keep running.
+pub struct CancelOnDrop {
+    executor: Arc<faas_executor::platform::Executor>,
+    id: String,
+    armed: bool,
+}
+
+impl CancelOnDrop {
+    pub fn new(executor: Arc<faas_executor::platform::Executor>, id: impl Into<String>) -> Self {
+        Self {
+            executor,
+            id: id.into(),
+            armed: true,
+        }
+    }
+
+    /// The execution ended or was stopped some other way; dropping no longer cancels it
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let (executor, id) = (self.executor.clone(), std::mem::take(&mut self.id));
+        tokio::spawn(async move {
+            info!("Client of {} went away, cancelling it", id);
+            if let Err(e) = executor.cancel(&id).await {
+                warn!("Failed to cancel abandoned execution {}: {}", id, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_reaches_every_level_and_records_the_parent() {
+        let registry = Arc::new(CancelRegistry::new());
+        let fork = registry.register("fork", None).unwrap();
+        fork.start().unwrap();
+        let branch = registry.register("branch", Some("fork")).unwrap();
+        branch.start().unwrap();
+        let leaf = registry.register("leaf", Some("branch")).unwrap();
+
+        let report = registry.cancel("fork", CancelPolicy::All).unwrap();
+        assert!(fork.is_cancelled());
+        let depths: Vec<_> = report
+            .cancelled
+            .iter()
+            .map(|child| {
+                (
+                    child.id.as_str(),
+                    child.cancellation.cancelled_by.as_deref(),
+                    child.cancellation.depth,
+                )
+            })
+            .collect();
+        assert_eq!(
+            depths,
+            [("branch", Some("fork"), 1), ("leaf", Some("branch"), 2)]
+        );
+        assert!(branch.is_cancelled() && leaf.is_cancelled());
+        assert!(matches!(leaf.start(), Err(CancelError::Cancelled { .. })));
+    }
+
+    #[tokio::test]
+    async fn only_pending_leaves_running_work_alone() {
+        let registry = Arc::new(CancelRegistry::new());
+        registry.open("group");
+        let running = registry.register("a", Some("group")).unwrap();
+        running.start().unwrap();
+        let pending = registry.register("b", Some("group")).unwrap();
+
+        let report = registry.cancel("group", CancelPolicy::OnlyPending).unwrap();
+        assert_eq!(report.spared, ["a"]);
+        assert_eq!(report.cancelled.len(), 1);
+        assert_eq!(
+            pending.cancelled().await.cancelled_by.as_deref(),
+            Some("group")
+        );
+        assert!(!running.is_cancelled());
+        assert!(matches!(
+            registry.register("c", Some("group")),
+            Err(CancelError::Cancelled { .. })
+        ));
+
+        // A second, unrestricted cancel reaches what the first one spared
+        let report = registry.cancel("group", CancelPolicy::All).unwrap();
+        assert_eq!(report.cancelled[0].id, "a");
+        assert!(running.is_cancelled());
+    }
+
+    #[test]
+    fn a_child_racing_its_parents_cancel_never_runs() {
+        for round in 0..200 {
+            let registry = Arc::new(CancelRegistry::new());
+            let parent_id = format!("parent-{round}");
+            let parent = registry.register(&parent_id, None).unwrap();
+            parent.start().unwrap();
+
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            let child = std::thread::spawn({
+                let (registry, barrier, parent_id) =
+                    (registry.clone(), barrier.clone(), parent_id.clone());
+                move || {
+                    barrier.wait();
+                    let scope = registry.register("child", Some(&parent_id))?;
+                    scope.start()?;
+                    Ok::<_, CancelError>(scope)
+                }
+            });
+            barrier.wait();
+            registry.cancel(&parent_id, CancelPolicy::All).unwrap();
+
+            // Either admission refused it, or it registered in time to be cancelled
+            match child.join().unwrap() {
+                Err(CancelError::Cancelled { cancellation, .. }) => {
+                    assert_eq!(
+                        cancellation.cancelled_by.as_deref(),
+                        Some(parent_id.as_str())
+                    );
+                    assert_eq!(cancellation.depth, 1);
+                }
+                Err(other) => panic!("unexpected admission error {other}"),
+                Ok(scope) => assert!(scope.is_cancelled(), "child outlived its parent's cancel"),
+            }
+        }
+
+        // After the cancel has been processed, admission always refuses
+        let registry = Arc::new(CancelRegistry::new());
+        let _parent = registry.register("parent", None).unwrap();
+        registry.cancel("parent", CancelPolicy::All).unwrap();
+        assert!(matches!(
+            registry.register("child", Some("parent")),
+            Err(CancelError::Cancelled { .. })
+        ));
+    }
+
+    #[test]
+    fn tombstones_refuse_late_children_until_pruned() {
+        let registry = Arc::new(CancelRegistry::new());
+        let parent = registry.register("parent", None).unwrap();
+        registry.cancel("parent", CancelPolicy::All).unwrap();
+        drop(parent);
+        assert!(registry.register("late", Some("parent")).is_err());
+        assert_eq!(registry.prune(Duration::ZERO), 1);
+        let _late = registry.register("late", Some("parent")).unwrap();
+
+        let finished = registry.register("finished", None).unwrap();
+        drop(finished);
+        assert_eq!(registry.len(), 1, "only the live child is left");
+    }
+}
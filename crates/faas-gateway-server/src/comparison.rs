@@ -0,0 +1,705 @@
+//! Comparing an execution group against a baseline group.
+//!
+//! Running the same suite twice, e.g. against a new model version, gives two groups whose
+//! members pair up by `item_key` (or by join order when no keys were given). Each pair is
+//! compared on exit code and on stdout after a [`Normalizer`] has removed the noise the
+//! caller doesn't care about: surrounding whitespace, JSON formatting, and volatile fields
+//! such as timestamps, named by JSONPath.
+//!
+//! The baseline is the golden output, so any difference that isn't a failure turning into
+//! a success counts as a regression.
+
+use crate::groups::{GroupItem, GroupRegistry, MemberOutput};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Lines of `-`/`+` kept in a [`DiffSummary`]
+const DIFF_EXCERPT_LINES: usize = 20;
+/// Past this many differing lines on either side, the diff reports every line as changed
+/// instead of aligning them
+const MAX_ALIGNED_LINES: usize = 2_000;
+
+/// How stdout is cleaned up before two runs are compared
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Normalizer {
+    /// Ignore leading and trailing whitespace, of the whole output and of each line
+    #[serde(default)]
+    pub trim_whitespace: bool,
+    /// Compare as JSON when the output parses, as one document or one per line, so key
+    /// order and formatting don't count
+    #[serde(default)]
+    pub parse_json: bool,
+    /// JSONPaths removed from parsed output, e.g. `$.created_at` or `$..timestamp`.
+    /// Supports `.name`, `['name']`, `[n]`, `[*]`, `.*` and `..name`.
+    #[serde(default)]
+    pub drop_fields: Vec<String>,
+}
+
+impl Normalizer {
+    /// Reject paths that don't parse, so a bad preset fails when it is stored
+    pub fn validate(&self) -> Result<(), ComparisonError> {
+        self.compile().map(|_| ())
+    }
+
+    fn compile(&self) -> Result<Vec<JsonPath>, ComparisonError> {
+        self.drop_fields
+            .iter()
+            .map(|path| JsonPath::parse(path))
+            .collect()
+    }
+
+    pub fn normalize(&self, stdout: &str) -> Result<String, ComparisonError> {
+        let paths = self.compile()?;
+        Ok(self.apply(&paths, stdout))
+    }
+
+    fn apply(&self, paths: &[JsonPath], stdout: &str) -> String {
+        let text = if self.trim_whitespace {
+            stdout
+                .trim()
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            stdout.to_string()
+        };
+        if !self.parse_json {
+            return text;
+        }
+
+        let clean = |mut value: Value| {
+            for path in paths {
+                path.remove(&mut value);
+            }
+            value
+        };
+        if let Ok(value) = serde_json::from_str::<Value>(&text) {
+            // Pretty-printed, so the line diff points at the field that changed
+            return serde_json::to_string_pretty(&clean(value)).unwrap_or(text);
+        }
+        // JSON Lines, leaving lines that aren't JSON as they are
+        text.lines()
+            .map(|line| match serde_json::from_str::<Value>(line) {
+                Ok(value) => clean(value).to_string(),
+                Err(_) => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Child(String),
+    Index(usize),
+    Wildcard,
+    /// `..name`: `name` at any depth
+    Descendant(String),
+}
+
+/// The subset of JSONPath a normalizer needs to point at fields to drop
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct JsonPath(Vec<Segment>);
+
+impl JsonPath {
+    fn parse(path: &str) -> Result<Self, ComparisonError> {
+        let invalid = |reason: &str| ComparisonError::InvalidPath {
+            path: path.to_string(),
+            reason: reason.to_string(),
+        };
+        let mut rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("must start with $"))?;
+        let mut segments = Vec::new();
+        let name_end = |s: &str| s.find(['.', '[']).unwrap_or(s.len());
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("..") {
+                let end = name_end(after);
+                if end == 0 {
+                    return Err(invalid("expected a field name after .."));
+                }
+                segments.push(Segment::Descendant(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = name_end(after);
+                segments.push(match &after[..end] {
+                    "" => return Err(invalid("expected a field name after .")),
+                    "*" => Segment::Wildcard,
+                    name => Segment::Child(name.to_string()),
+                });
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| invalid("unclosed ["))?;
+                let inner = &after[..end];
+                segments.push(if inner == "*" {
+                    Segment::Wildcard
+                } else if let Some(name) = inner
+                    .strip_prefix('\'')
+                    .and_then(|s| s.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')))
+                {
+                    Segment::Child(name.to_string())
+                } else {
+                    Segment::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| invalid("expected an index, a quoted name or *"))?,
+                    )
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(invalid("expected . or ["));
+            }
+        }
+        if segments.is_empty() {
+            return Err(invalid("would drop the whole document"));
+        }
+        Ok(Self(segments))
+    }
+
+    fn remove(&self, value: &mut Value) {
+        remove_at(value, &self.0);
+    }
+}
+
+fn remove_at(value: &mut Value, path: &[Segment]) {
+    let Some((segment, rest)) = path.split_first() else {
+        return;
+    };
+    match segment {
+        Segment::Child(name) => {
+            if let Value::Object(map) = value {
+                if rest.is_empty() {
+                    map.remove(name);
+                } else if let Some(child) = map.get_mut(name) {
+                    remove_at(child, rest);
+                }
+            }
+        }
+        Segment::Index(index) => {
+            if let Value::Array(items) = value {
+                if rest.is_empty() {
+                    if *index < items.len() {
+                        items.remove(*index);
+                    }
+                } else if let Some(child) = items.get_mut(*index) {
+                    remove_at(child, rest);
+                }
+            }
+        }
+        Segment::Wildcard => match value {
+            Value::Object(map) if rest.is_empty() => map.clear(),
+            Value::Array(items) if rest.is_empty() => items.clear(),
+            Value::Object(map) => map.values_mut().for_each(|child| remove_at(child, rest)),
+            Value::Array(items) => items.iter_mut().for_each(|child| remove_at(child, rest)),
+            _ => {}
+        },
+        Segment::Descendant(name) => {
+            if let Value::Object(map) = value {
+                if rest.is_empty() {
+                    map.remove(name);
+                } else if let Some(child) = map.get_mut(name) {
+                    remove_at(child, rest);
+                }
+            }
+            match value {
+                Value::Object(map) => map.values_mut().for_each(|child| remove_at(child, path)),
+                Value::Array(items) => items.iter_mut().for_each(|child| remove_at(child, path)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Line diff of normalized stdout, baseline first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffSummary {
+    pub added_lines: usize,
+    pub removed_lines: usize,
+    /// The first differing lines, `-` for the baseline and `+` for the new run
+    pub excerpt: Vec<String>,
+}
+
+impl DiffSummary {
+    fn between(baseline: &str, current: &str) -> Self {
+        let old: Vec<&str> = baseline.lines().collect();
+        let new: Vec<&str> = current.lines().collect();
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old = &old[prefix..old.len() - suffix];
+        let new = &new[prefix..new.len() - suffix];
+
+        let mut summary = Self {
+            added_lines: 0,
+            removed_lines: 0,
+            excerpt: Vec::new(),
+        };
+        let push = |summary: &mut Self, sign: char, line: &str| {
+            if sign == '-' {
+                summary.removed_lines += 1;
+            } else {
+                summary.added_lines += 1;
+            }
+            if summary.excerpt.len() < DIFF_EXCERPT_LINES {
+                summary.excerpt.push(format!("{sign} {line}"));
+            }
+        };
+
+        if old.len() > MAX_ALIGNED_LINES || new.len() > MAX_ALIGNED_LINES {
+            old.iter().for_each(|line| push(&mut summary, '-', line));
+            new.iter().for_each(|line| push(&mut summary, '+', line));
+            return summary;
+        }
+
+        // Longest common subsequence, then walk it emitting what falls outside
+        let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                i += 1;
+                j += 1;
+            } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                push(&mut summary, '-', old[i]);
+                i += 1;
+            } else {
+                push(&mut summary, '+', new[j]);
+                j += 1;
+            }
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemOutcome {
+    /// Failed in the baseline, succeeds now
+    Improved,
+    /// Differs from the baseline in any other way
+    Regressed,
+    Unchanged,
+    /// In the baseline only
+    Missing,
+    /// In the new group only
+    Added,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemComparison {
+    pub key: String,
+    pub outcome: ItemOutcome,
+    pub baseline_execution_id: Option<String>,
+    pub execution_id: Option<String>,
+    pub baseline_exit_code: Option<i32>,
+    pub exit_code: Option<i32>,
+    /// Set when the normalized stdout differs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<DiffSummary>,
+}
+
+/// Per-item results and totals for one group against its baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonReport {
+    pub group_id: String,
+    pub baseline_group_id: String,
+    /// Preset the outputs were normalized with, if any
+    pub normalizer: Option<String>,
+    pub improved: usize,
+    pub regressed: usize,
+    pub unchanged: usize,
+    pub missing: usize,
+    pub added: usize,
+    /// Baseline order, then items only the new group has
+    pub items: Vec<ItemComparison>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ComparisonError {
+    #[error("execution group {0} not found")]
+    GroupNotFound(String),
+    #[error("execution group {0} has no baseline; pass ?baseline=<group id>")]
+    NoBaseline(String),
+    #[error("execution group {0} has not settled yet")]
+    NotSettled(String),
+    #[error("normalizer preset {0} not found")]
+    UnknownNormalizer(String),
+    #[error("invalid JSONPath {path}: {reason}")]
+    InvalidPath { path: String, reason: String },
+}
+
+impl IntoResponse for ComparisonError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::GroupNotFound(_) | Self::UnknownNormalizer(_) => StatusCode::NOT_FOUND,
+            Self::NotSettled(_) => StatusCode::CONFLICT,
+            Self::NoBaseline(_) | Self::InvalidPath { .. } => StatusCode::BAD_REQUEST,
+        };
+        (
+            status,
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+fn compare_item(
+    key: &str,
+    baseline: Option<&GroupItem>,
+    current: Option<&GroupItem>,
+    normalize: &impl Fn(&str) -> String,
+) -> ItemComparison {
+    let old = baseline.and_then(|item| item.output.as_ref());
+    let new = current.and_then(|item| item.output.as_ref());
+    let exit_code = |output: Option<&MemberOutput>| output.map(|o| o.exit_code);
+
+    let diff = match (old, new) {
+        (Some(old), Some(new)) => {
+            let (old, new) = (normalize(&old.stdout), normalize(&new.stdout));
+            (old != new).then(|| DiffSummary::between(&old, &new))
+        }
+        _ => None,
+    };
+    let outcome = match (baseline, current) {
+        (None, _) => ItemOutcome::Added,
+        (_, None) => ItemOutcome::Missing,
+        _ => match (exit_code(old), exit_code(new)) {
+            (Some(before), Some(0)) if before != 0 => ItemOutcome::Improved,
+            (before, after) if before == after && diff.is_none() => ItemOutcome::Unchanged,
+            _ => ItemOutcome::Regressed,
+        },
+    };
+    ItemComparison {
+        key: key.to_string(),
+        outcome,
+        baseline_execution_id: baseline.map(|item| item.execution_id.clone()),
+        execution_id: current.map(|item| item.execution_id.clone()),
+        baseline_exit_code: exit_code(old),
+        exit_code: exit_code(new),
+        diff,
+    }
+}
+
+/// Pair up the items by key and compare each pair
+pub fn compare(
+    baseline: &[GroupItem],
+    current: &[GroupItem],
+    normalizer: &Normalizer,
+) -> Result<Vec<ItemComparison>, ComparisonError> {
+    let paths = normalizer.compile()?;
+    let normalize = |stdout: &str| normalizer.apply(&paths, stdout);
+    let by_key: HashMap<&str, &GroupItem> = current
+        .iter()
+        .map(|item| (item.key.as_str(), item))
+        .collect();
+    let baseline_keys: HashMap<&str, &GroupItem> = baseline
+        .iter()
+        .map(|item| (item.key.as_str(), item))
+        .collect();
+
+    let mut items: Vec<ItemComparison> = baseline
+        .iter()
+        .map(|old| {
+            compare_item(
+                &old.key,
+                Some(old),
+                by_key.get(old.key.as_str()).copied(),
+                &normalize,
+            )
+        })
+        .collect();
+    items.extend(
+        current
+            .iter()
+            .filter(|new| !baseline_keys.contains_key(new.key.as_str()))
+            .map(|new| compare_item(&new.key, None, Some(new), &normalize)),
+    );
+    Ok(items)
+}
+
+impl GroupRegistry {
+    /// Compare a settled group with a settled baseline.
+    ///
+    /// `baseline` and `normalizer` default to what the group was created with; without any
+    /// normalizer, stdout has to match byte for byte.
+    pub fn compare(
+        &self,
+        group_id: &str,
+        baseline: Option<&str>,
+        normalizer: Option<&str>,
+    ) -> Result<ComparisonReport, ComparisonError> {
+        let group = self
+            .items(group_id)
+            .ok_or_else(|| ComparisonError::GroupNotFound(group_id.to_string()))?;
+        let baseline_group_id = baseline
+            .map(str::to_string)
+            .or_else(|| group.baseline_group_id.clone())
+            .ok_or_else(|| ComparisonError::NoBaseline(group_id.to_string()))?;
+        let baseline = self
+            .items(&baseline_group_id)
+            .ok_or_else(|| ComparisonError::GroupNotFound(baseline_group_id.clone()))?;
+        for (id, items) in [(group_id, &group), (baseline_group_id.as_str(), &baseline)] {
+            if !items.settled {
+                return Err(ComparisonError::NotSettled(id.to_string()));
+            }
+        }
+
+        let preset = normalizer
+            .map(str::to_string)
+            .or_else(|| group.normalizer.clone());
+        let rules = match &preset {
+            Some(name) => self
+                .normalizer(name)
+                .ok_or_else(|| ComparisonError::UnknownNormalizer(name.clone()))?,
+            None => Normalizer::default(),
+        };
+
+        let items = compare(&baseline.items, &group.items, &rules)?;
+        let count = |outcome| items.iter().filter(|item| item.outcome == outcome).count();
+        Ok(ComparisonReport {
+            group_id: group_id.to_string(),
+            baseline_group_id,
+            normalizer: preset,
+            improved: count(ItemOutcome::Improved),
+            regressed: count(ItemOutcome::Regressed),
+            unchanged: count(ItemOutcome::Unchanged),
+            missing: count(ItemOutcome::Missing),
+            added: count(ItemOutcome::Added),
+            items,
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ComparisonQuery {
+    /// Overrides the group's `baseline_group_id`
+    pub baseline: Option<String>,
+    /// Preset name, overriding the group's `normalizer`
+    pub normalizer: Option<String>,
+}
+
+/// `GET /api/v1/groups/:id/comparison`
+pub async fn group_comparison_handler(
+    State(groups): State<Arc<GroupRegistry>>,
+    Path(group_id): Path<String>,
+    Query(query): Query<ComparisonQuery>,
+) -> Result<Json<ComparisonReport>, ComparisonError> {
+    groups
+        .compare(
+            &group_id,
+            query.baseline.as_deref(),
+            query.normalizer.as_deref(),
+        )
+        .map(Json)
+}
+
+/// `PUT /api/v1/normalizers/:name`
+pub async fn put_normalizer_handler(
+    State(groups): State<Arc<GroupRegistry>>,
+    Path(name): Path<String>,
+    Json(normalizer): Json<Normalizer>,
+) -> Result<Json<Normalizer>, ComparisonError> {
+    normalizer.validate()?;
+    groups.put_normalizer(&name, normalizer.clone());
+    Ok(Json(normalizer))
+}
+
+/// `GET /api/v1/normalizers`
+pub async fn list_normalizers_handler(
+    State(groups): State<Arc<GroupRegistry>>,
+) -> Json<BTreeMap<String, Normalizer>> {
+    Json(groups.normalizers())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups::GroupSummary;
+    use crate::groups::{CreateGroupRequest, WebhookSink};
+    use async_trait::async_trait;
+
+    struct NoWebhooks;
+
+    #[async_trait]
+    impl WebhookSink for NoWebhooks {
+        async fn deliver(&self, _url: &str, _summary: &GroupSummary) {}
+    }
+
+    /// Run `outputs` as one settled group, keyed by item name
+    fn run_batch(
+        registry: &GroupRegistry,
+        baseline: Option<&str>,
+        outputs: &[(&str, i32, &str)],
+    ) -> String {
+        let group = registry.create(CreateGroupRequest {
+            expected: Some(outputs.len()),
+            baseline_group_id: baseline.map(str::to_string),
+            ..Default::default()
+        });
+        for (i, (key, exit_code, stdout)) in outputs.iter().enumerate() {
+            let id = format!("{}-{i}", group.group_id);
+            registry.join(&group.group_id, &id).unwrap();
+            registry.record_output(
+                &group.group_id,
+                &id,
+                Some(key),
+                *exit_code,
+                stdout.as_bytes(),
+            );
+            registry.finish(&group.group_id, &id, *exit_code == 0, Some(1));
+        }
+        group.group_id
+    }
+
+    #[test]
+    fn flags_only_the_changed_item_with_its_diff() {
+        let registry = GroupRegistry::new(Arc::new(NoWebhooks));
+        let baseline = run_batch(
+            &registry,
+            None,
+            &[
+                ("capital", 0, "Paris\n"),
+                ("sum", 0, "answer: 4\nconfidence: high\n"),
+                ("flaky", 1, ""),
+            ],
+        );
+        let rerun = run_batch(
+            &registry,
+            Some(&baseline),
+            &[
+                ("flaky", 0, "ok"),
+                ("sum", 0, "answer: 5\nconfidence: high\n"),
+                ("capital", 0, "Paris\n"),
+            ],
+        );
+
+        let report = registry.compare(&rerun, None, None).unwrap();
+        assert_eq!(report.baseline_group_id, baseline);
+        assert_eq!(
+            (report.improved, report.regressed, report.unchanged),
+            (1, 1, 1)
+        );
+        let regressed: Vec<_> = report
+            .items
+            .iter()
+            .filter(|item| item.outcome == ItemOutcome::Regressed)
+            .collect();
+        assert_eq!(regressed.len(), 1);
+        assert_eq!(regressed[0].key, "sum");
+        let diff = regressed[0].diff.as_ref().unwrap();
+        assert_eq!(diff.excerpt, ["- answer: 4", "+ answer: 5"]);
+        assert_eq!((diff.removed_lines, diff.added_lines), (1, 1));
+    }
+
+    #[test]
+    fn dropped_volatile_fields_hide_timestamp_only_changes() {
+        let registry = GroupRegistry::new(Arc::new(NoWebhooks));
+        let baseline = run_batch(
+            &registry,
+            None,
+            &[(
+                "eval",
+                0,
+                r#"{"score": 0.9, "meta": {"timestamp": "2026-01-01T00:00:00Z"}}"#,
+            )],
+        );
+        let rerun = run_batch(
+            &registry,
+            Some(&baseline),
+            &[(
+                "eval",
+                0,
+                "  {\"meta\": {\"timestamp\": \"2026-02-01T00:00:00Z\"},\n \"score\": 0.9}  ",
+            )],
+        );
+
+        let strict = registry.compare(&rerun, None, None).unwrap();
+        assert_eq!(strict.regressed, 1);
+
+        registry.put_normalizer(
+            "eval-json",
+            Normalizer {
+                trim_whitespace: true,
+                parse_json: true,
+                drop_fields: vec!["$..timestamp".to_string()],
+            },
+        );
+        let normalized = registry.compare(&rerun, None, Some("eval-json")).unwrap();
+        assert_eq!(normalized.unchanged, 1);
+        assert_eq!(normalized.items[0].diff, None);
+        assert_eq!(normalized.normalizer.as_deref(), Some("eval-json"));
+    }
+
+    #[test]
+    fn unsettled_groups_and_unknown_presets_are_refused() {
+        let registry = GroupRegistry::new(Arc::new(NoWebhooks));
+        let baseline = run_batch(&registry, None, &[("a", 0, "x")]);
+        let pending = registry.create(CreateGroupRequest {
+            expected: Some(1),
+            baseline_group_id: Some(baseline.clone()),
+            ..Default::default()
+        });
+        assert_eq!(
+            registry.compare(&pending.group_id, None, None).unwrap_err(),
+            ComparisonError::NotSettled(pending.group_id.clone())
+        );
+
+        let rerun = run_batch(&registry, Some(&baseline), &[("a", 0, "x")]);
+        assert!(matches!(
+            registry.compare(&rerun, None, Some("nope")),
+            Err(ComparisonError::UnknownNormalizer(_))
+        ));
+        let unpaired = run_batch(&registry, None, &[("a", 0, "x")]);
+        assert!(matches!(
+            registry.compare(&unpaired, None, None),
+            Err(ComparisonError::NoBaseline(_))
+        ));
+    }
+
+    #[test]
+    fn json_paths() {
+        let mut value = serde_json::json!({
+            "id": "run-1",
+            "results": [{"t": 1, "v": "a"}, {"t": 2, "v": "b"}],
+            "meta": {"host": "x", "tags": ["p", "q"]}
+        });
+        for path in ["$.id", "$.results[*].t", "$['meta'].tags[0]"] {
+            JsonPath::parse(path).unwrap().remove(&mut value);
+        }
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "results": [{"v": "a"}, {"v": "b"}],
+                "meta": {"host": "x", "tags": ["q"]}
+            })
+        );
+
+        for bad in ["id", "$", "$.", "$[x", "$[one]", "$..", "$x"] {
+            assert!(JsonPath::parse(bad).is_err(), "{bad}");
+        }
+    }
+}
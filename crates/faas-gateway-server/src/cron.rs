@@ -0,0 +1,262 @@
+//! Cron expressions, for [`crate::schedules`].
+//!
+//! Five fields (`minute hour day-of-month month day-of-week`) or six, with seconds first.
+//! Each field takes `*`, a value, a range `a-b`, a step `*/n`, `a-b/n` or `a/n`, or a
+//! comma-separated list of those; months and weekdays also take three-letter names, and
+//! Sunday is both 0 and 7. As in cron, a day matches when either day field does if both are
+//! restricted. `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` stand for the usual
+//! expressions. Times are UTC.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// How far ahead [`CronExpr::next_after`] looks before deciding an expression never fires
+const HORIZON_DAYS: i64 = 5 * 366;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid cron expression {expr:?}: {reason}")]
+pub struct CronError {
+    pub expr: String,
+    pub reason: String,
+}
+
+/// The values one field allows, as bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Written as `*`, which matters for the day fields
+    any: bool,
+}
+
+impl Field {
+    fn contains(self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+
+    fn parse(field: &str, min: u32, max: u32, names: &[&str]) -> Result<Self, String> {
+        let value = |s: &str| -> Result<u32, String> {
+            let lower = s.to_ascii_lowercase();
+            let named = names.iter().position(|name| *name == lower);
+            let value = match named {
+                Some(index) => index as u32 + min,
+                None => s.parse().map_err(|_| format!("{s:?} is not a number"))?,
+            };
+            if !(min..=max).contains(&value) {
+                return Err(format!("{value} is outside {min}-{max}"));
+            }
+            Ok(value)
+        };
+        let mut bits = 0;
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => {
+                    let step: u32 = step
+                        .parse()
+                        .ok()
+                        .filter(|step| *step > 0)
+                        .ok_or_else(|| format!("{step:?} is not a step"))?;
+                    (range, Some(step))
+                }
+                None => (item, None),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    None => {
+                        let start = value(range)?;
+                        (start, if step.is_some() { max } else { start })
+                    }
+                },
+            };
+            if start > end {
+                return Err(format!("{range:?} runs backwards"));
+            }
+            for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(Self {
+            bits,
+            any: field == "*",
+        })
+    }
+}
+
+/// A parsed cron expression; see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    source: String,
+    seconds: Field,
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl CronExpr {
+    /// The first time after `after`, to the second, that the expression matches; `None`
+    /// when it names no time in the next five years, like `0 0 30 2 *`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.naive_utc().with_nanosecond(0)? + Duration::seconds(1);
+        let horizon = start + Duration::days(HORIZON_DAYS);
+        let mut t = start;
+        while t < horizon {
+            if !self.months.contains(t.month()) {
+                let (year, month) = match t.month() {
+                    12 => (t.year() + 1, 1),
+                    month => (t.year(), month + 1),
+                };
+                t = midnight(NaiveDate::from_ymd_opt(year, month, 1)?);
+            } else if !self.day_matches(t.date()) {
+                t = midnight(t.date().succ_opt()?);
+            } else if !self.hours.contains(t.hour()) {
+                t = t.with_minute(0)?.with_second(0)? + Duration::hours(1);
+            } else if !self.minutes.contains(t.minute()) {
+                t = t.with_second(0)? + Duration::minutes(1);
+            } else if !self.seconds.contains(t.second()) {
+                t += Duration::seconds(1);
+            } else {
+                return Some(t.and_utc());
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days.contains(date.day());
+        let weekday = self
+            .weekdays
+            .contains(date.weekday().num_days_from_sunday());
+        match (self.days.any, self.weekdays.any) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).expect("midnight exists")
+}
+
+impl FromStr for CronExpr {
+    type Err = CronError;
+
+    fn from_str(expr: &str) -> Result<Self, CronError> {
+        let error = |reason: String| CronError {
+            expr: expr.to_string(),
+            reason,
+        };
+        let expanded = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let (seconds, rest) = match fields.len() {
+            5 => ("0", &fields[..]),
+            6 => (fields[0], &fields[1..]),
+            n => return Err(error(format!("expected 5 or 6 fields, found {n}"))),
+        };
+        let field = |name: &str, value: &str, min, max, names: &[&str]| {
+            Field::parse(value, min, max, names)
+                .map_err(|reason| error(format!("{name}: {reason}")))
+        };
+        let mut weekdays = field("day of week", rest[4], 0, 7, &WEEKDAYS)?;
+        if weekdays.contains(7) {
+            weekdays.bits |= 1;
+        }
+        Ok(Self {
+            source: expr.to_string(),
+            seconds: field("second", seconds, 0, 59, &[])?,
+            minutes: field("minute", rest[0], 0, 59, &[])?,
+            hours: field("hour", rest[1], 0, 23, &[])?,
+            days: field("day of month", rest[2], 1, 31, &[])?,
+            months: field("month", rest[3], 1, 12, &MONTHS)?,
+            weekdays,
+        })
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn next(expr: &str, after: &str) -> Option<String> {
+        let expr: CronExpr = expr.parse().unwrap();
+        expr.next_after(at(after)).map(|t| t.to_rfc3339())
+    }
+
+    #[test]
+    fn next_runs_follow_each_field() {
+        let after = "2026-03-14T10:17:42.5+00:00";
+        assert_eq!(
+            next("* * * * * *", after).as_deref(),
+            Some("2026-03-14T10:17:43+00:00")
+        );
+        assert_eq!(
+            next("*/15 * * * *", after).as_deref(),
+            Some("2026-03-14T10:30:00+00:00")
+        );
+        assert_eq!(
+            next("@daily", after).as_deref(),
+            Some("2026-03-15T00:00:00+00:00")
+        );
+        assert_eq!(
+            next("30 2 1 jan-mar *", after).as_deref(),
+            Some("2027-01-01T02:30:00+00:00")
+        );
+        // Both days restricted: the 1st, or any Monday
+        assert_eq!(
+            next("0 0 1 * mon", after).as_deref(),
+            Some("2026-03-16T00:00:00+00:00")
+        );
+        assert_eq!(
+            next("0 9 * * 7", after).as_deref(),
+            Some("2026-03-15T09:00:00+00:00")
+        );
+        assert_eq!(
+            next("0 0 29 2 *", after).as_deref(),
+            Some("2028-02-29T00:00:00+00:00")
+        );
+        assert_eq!(next("0 0 30 2 *", after), None);
+    }
+
+    #[test]
+    fn malformed_expressions_say_what_is_wrong() {
+        for (expr, reason) in [
+            ("* * * *", "expected 5 or 6 fields, found 4"),
+            ("60 * * * *", "minute: 60 is outside 0-59"),
+            ("* * * * * * *", "expected 5 or 6 fields, found 7"),
+            ("*/0 * * * *", "minute: \"0\" is not a step"),
+            ("* * 5-1 * *", "day of month: \"5-1\" runs backwards"),
+            ("* * * foo *", "month: \"foo\" is not a number"),
+        ] {
+            let error = expr.parse::<CronExpr>().unwrap_err();
+            assert_eq!(error.reason, reason, "{expr}");
+        }
+    }
+}
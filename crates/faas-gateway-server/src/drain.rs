@@ -0,0 +1,203 @@
+//! Admin drain API and the admission middleware that enforces it.
+//!
+//! Requests that would start new work get `503` with `x-faas-draining: true` and a
+//! `Retry-After`, so load balancers take the host out and clients back off instead of failing.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use faas_executor::drain::{DrainController, DrainStatus};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DRAINING_HEADER: &str = "x-faas-draining";
+
+const DEFAULT_GRACE: Duration = Duration::from_secs(300);
+const RETRY_AFTER_SECS: &str = "30";
+
+/// What happens to persistent instances still running when the grace period ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstancePolicy {
+    /// Snapshot running instances and suspend them
+    #[default]
+    Checkpoint,
+    Stop,
+}
+
+impl InstancePolicy {
+    /// `FAAS_DRAIN_INSTANCE_POLICY=checkpoint|stop`
+    pub fn from_env() -> Self {
+        match std::env::var("FAAS_DRAIN_INSTANCE_POLICY").as_deref() {
+            Ok("stop") => InstancePolicy::Stop,
+            _ => InstancePolicy::Checkpoint,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DrainRequest {
+    /// How long in-flight work may keep running; defaults to five minutes
+    pub grace_secs: Option<u64>,
+    pub instance_policy: Option<InstancePolicy>,
+}
+
+impl DrainRequest {
+    pub fn grace(&self) -> Duration {
+        self.grace_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_GRACE)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainStatusResponse {
+    #[serde(flatten)]
+    pub status: DrainStatus,
+    /// Persistent instances not yet stopped or suspended
+    pub remaining_instances: usize,
+}
+
+/// Whether the request would start an execution or write new state.
+///
+/// Stop, pause and other calls that wind work down stay allowed while draining.
+pub fn admits_new_work(method: &Method, path: &str) -> bool {
+    if method != Method::POST || path.starts_with("/api/v1/admin") {
+        return false;
+    }
+    matches!(
+        path,
+        "/api/v1/execute"
+            | "/api/v1/execute/batch"
+            | "/api/v1/jobs"
+            | "/api/v1/fork"
+            | "/api/v1/prewarm"
+            | "/api/v1/instances"
+            | "/api/v1/snapshots"
+            | "/api/v1/groups"
+    ) || path.ends_with("/fork")
+        || path.ends_with("/exec")
+        || path.ends_with("/restore")
+        || path.ends_with("/session-state")
+}
+
+pub fn draining_response() -> Response {
+    let mut response = (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({
+            "error": "host is draining for maintenance",
+            "draining": true,
+        })),
+    )
+        .into_response();
+    mark_draining(&mut response);
+    response
+}
+
+fn mark_draining(response: &mut Response) {
+    let headers = response.headers_mut();
+    headers.insert(DRAINING_HEADER, HeaderValue::from_static("true"));
+    headers.insert(
+        header::RETRY_AFTER,
+        HeaderValue::from_static(RETRY_AFTER_SECS),
+    );
+}
+
+/// Refuse new work while draining.
+///
+/// Work that races past this check is refused by the executor itself; those 503s get the
+/// same headers on the way out.
+pub async fn admission(
+    State(drain): State<Arc<DrainController>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if drain.is_draining() && admits_new_work(request.method(), request.uri().path()) {
+        return draining_response();
+    }
+    let mut response = next.run(request).await;
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE && drain.is_draining() {
+        mark_draining(&mut response);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::post, Router};
+    use tower::ServiceExt;
+
+    fn app(drain: Arc<DrainController>) -> Router {
+        Router::new()
+            .route("/api/v1/execute", post(|| async { "ran" }))
+            .route("/api/v1/instances/:id/stop", post(|| async { "stopped" }))
+            .layer(axum::middleware::from_fn_with_state(drain, admission))
+    }
+
+    async fn post_status(app: &Router, path: &str) -> Response {
+        app.clone()
+            .oneshot(
+                Request::post(path)
+                    .body(Body::empty())
+                    .expect("valid request"),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn draining_refuses_new_work_only() {
+        let drain = Arc::new(DrainController::new());
+        let app = app(drain.clone());
+        assert_eq!(
+            post_status(&app, "/api/v1/execute").await.status(),
+            StatusCode::OK
+        );
+
+        drain.start(Duration::from_secs(60));
+        let refused = post_status(&app, "/api/v1/execute").await;
+        assert_eq!(refused.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(refused.headers()[DRAINING_HEADER], "true");
+        assert!(refused.headers().contains_key(header::RETRY_AFTER));
+        assert_eq!(
+            post_status(&app, "/api/v1/instances/i-1/stop")
+                .await
+                .status(),
+            StatusCode::OK
+        );
+
+        drain.undrain();
+        assert_eq!(
+            post_status(&app, "/api/v1/execute").await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn classifies_routes() {
+        assert!(admits_new_work(&Method::POST, "/api/v1/execute/batch"));
+        assert!(admits_new_work(&Method::POST, "/api/v1/jobs"));
+        assert!(!admits_new_work(&Method::DELETE, "/api/v1/jobs/j-1"));
+        assert!(admits_new_work(&Method::POST, "/api/v1/instances/i-1/exec"));
+        assert!(admits_new_work(
+            &Method::POST,
+            "/api/v1/snapshots/s-1/restore"
+        ));
+        assert!(admits_new_work(
+            &Method::POST,
+            "/api/v1/executions/e-1/fork"
+        ));
+        assert!(!admits_new_work(&Method::GET, "/api/v1/instances"));
+        assert!(!admits_new_work(
+            &Method::POST,
+            "/api/v1/instances/i-1/pause"
+        ));
+        assert!(!admits_new_work(&Method::POST, "/api/v1/admin/undrain"));
+    }
+}
@@ -0,0 +1,557 @@
+//! The JSON body failed requests are answered with.
+//!
+//! `code` names what went wrong in a way clients can branch on, `message` is for people and
+//! `details` holds whatever else the failure knows, like the timeout that ran out. Handlers
+//! that only have a status to give still send a body: [`fill_error_body`] turns empty and
+//! plain-text error responses into this shape and stamps every JSON error with the id the
+//! request is logged under.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use faas_common::FaasError;
+use faas_executor::bollard::errors::Error as BollardError;
+use faas_executor::docker_checkpoint::CheckpointError;
+use faas_executor::drain::Draining;
+use faas_executor::platform::negative_cache::FailureKind;
+use faas_executor::platform::{ArchMismatch, ResolutionFailure, StrategyError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use tracing::warn;
+
+/// Header a request id is read from when the client sets one, and sent back on
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Client-chosen ids longer than this are replaced with one of ours
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Error bodies are tiny; anything past this is passed through untouched
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiErrorResponse {
+    /// e.g. `ImageNotFound`, `Timeout`, `InvalidRequest`, `DockerUnavailable`
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+/// An [`ApiErrorResponse`] and the status it is sent with
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub body: ApiErrorResponse,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ApiErrorResponse {
+                code: code.into(),
+                message: message.into(),
+                request_id: None,
+                details: None,
+            },
+        }
+    }
+
+    /// 422 for a request that is well-formed JSON but asks for something that doesn't exist
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, "InvalidRequest", message)
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.body.details = Some(details);
+        self
+    }
+
+    /// Classify an execution failure by the first error in its chain the gateway knows.
+    ///
+    /// 404 when the image, snapshot or container is missing, 408 when the sandbox ran past
+    /// its timeout, 422 when the request can't be run here as asked, 503 when the host is
+    /// draining or Docker can't be reached, 500 otherwise.
+    pub fn from_failure(e: &(dyn Error + 'static)) -> Self {
+        let mut cause = Some(e);
+        while let Some(error) = cause {
+            if let Some(known) = Self::known(error) {
+                return known;
+            }
+            cause = error.source();
+        }
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal", e.to_string())
+    }
+
+    fn known(e: &(dyn Error + 'static)) -> Option<Self> {
+        if e.is::<Draining>() {
+            return Some(Self::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Draining",
+                e.to_string(),
+            ));
+        }
+        if let Some(error) = e.downcast_ref::<FaasError>() {
+            return match error {
+                FaasError::Timeout { timeout_ms } => Some(
+                    Self::new(StatusCode::REQUEST_TIMEOUT, "Timeout", error.to_string())
+                        .with_details(serde_json::json!({ "timeout_ms": timeout_ms })),
+                ),
+                FaasError::IncompatibleFeature {
+                    feature,
+                    runtime,
+                    reason,
+                } => Some(
+                    Self::new(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "IncompatibleFeature",
+                        error.to_string(),
+                    )
+                    .with_details(serde_json::json!({
+                        "feature": feature,
+                        "runtime": runtime,
+                        "reason": reason,
+                    })),
+                ),
+                FaasError::UnknownUser { user } => Some(
+                    Self::new(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "UnknownUser",
+                        error.to_string(),
+                    )
+                    .with_details(serde_json::json!({ "user": user })),
+                ),
+                FaasError::NotFound(_) => Some(Self::new(
+                    StatusCode::NOT_FOUND,
+                    "NotFound",
+                    error.to_string(),
+                )),
+                _ => None,
+            };
+        }
+        if let Some(mismatch) = e.downcast_ref::<ArchMismatch>() {
+            return Some(
+                Self::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "ArchMismatch",
+                    mismatch.to_string(),
+                )
+                .with_details(serde_json::to_value(mismatch).unwrap_or_default()),
+            );
+        }
+        if let Some(invalid) = e.downcast_ref::<StrategyError>() {
+            // `reason` names what is wrong with the strategy, e.g. `not_idempotent`
+            return Some(
+                Self::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "InvalidStrategy",
+                    invalid.to_string(),
+                )
+                .with_details(serde_json::to_value(invalid).unwrap_or_default()),
+            );
+        }
+        if let Some(checkpoint) = e.downcast_ref::<CheckpointError>() {
+            return match checkpoint {
+                CheckpointError::Unsupported { reason } => Some(
+                    Self::new(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "CheckpointUnsupported",
+                        checkpoint.to_string(),
+                    )
+                    .with_details(serde_json::json!({ "reason": reason })),
+                ),
+                CheckpointError::NotFound(_) => Some(Self::new(
+                    StatusCode::NOT_FOUND,
+                    "NotFound",
+                    checkpoint.to_string(),
+                )),
+                CheckpointError::Docker { .. } => None,
+            };
+        }
+        if let Some(failure) = e.downcast_ref::<ResolutionFailure>() {
+            return Some(resolution_failure(failure));
+        }
+        if let Some(docker) = e.downcast_ref::<BollardError>() {
+            return docker_failure(docker);
+        }
+        e.downcast_ref::<std::io::Error>()
+            .filter(|io| daemon_unreachable(io))
+            .map(|io| docker_unavailable(io.to_string()))
+    }
+}
+
+impl ApiError {
+    /// The error a handler answered with, to pass on inside another answer. A body that
+    /// isn't an [`ApiErrorResponse`] becomes one as [`fill_error_body`] would make it.
+    pub async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), MAX_ERROR_BODY_BYTES)
+            .await
+            .unwrap_or_default();
+        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| ApiErrorResponse {
+            code: status_code_name(status).to_string(),
+            message: error_message(status, &bytes),
+            request_id: None,
+            details: None,
+        });
+        Self { status, body }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+/// `cached_error` tells clients the failure was answered without retrying, and
+/// `retry_in_ms` when retrying could give a different answer.
+fn resolution_failure(failure: &ResolutionFailure) -> ApiError {
+    let (status, code) = match failure.kind {
+        FailureKind::ImageNotFound => (StatusCode::NOT_FOUND, "ImageNotFound"),
+        FailureKind::ImageUnauthorized => (StatusCode::UNPROCESSABLE_ENTITY, "ImageUnauthorized"),
+        FailureKind::Unsatisfiable => (StatusCode::UNPROCESSABLE_ENTITY, "Unsatisfiable"),
+    };
+    ApiError::new(status, code, failure.message.clone()).with_details(serde_json::json!({
+        "cached_error": failure.cached_error,
+        "retry_in_ms": failure.retry_in_ms,
+    }))
+}
+
+fn docker_failure(error: &BollardError) -> Option<ApiError> {
+    match error {
+        BollardError::DockerResponseServerError {
+            status_code: 404,
+            message,
+        } => {
+            let code = if message.starts_with("No such image") {
+                "ImageNotFound"
+            } else {
+                "NotFound"
+            };
+            Some(ApiError::new(StatusCode::NOT_FOUND, code, message.clone()))
+        }
+        BollardError::SocketNotFoundError(_)
+        | BollardError::HyperLegacyError { .. }
+        | BollardError::RequestTimeoutError => Some(docker_unavailable(error.to_string())),
+        BollardError::IOError { err } if daemon_unreachable(err) => {
+            Some(docker_unavailable(error.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn daemon_unreachable(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::NotFound
+    )
+}
+
+fn docker_unavailable(message: String) -> ApiError {
+    ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "DockerUnavailable",
+        format!("could not reach the Docker daemon: {message}"),
+    )
+}
+
+/// The code an error answered with only a status gets
+pub fn status_code_name(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => "InvalidRequest",
+        StatusCode::UNAUTHORIZED => "Unauthorized",
+        StatusCode::FORBIDDEN => "Forbidden",
+        StatusCode::NOT_FOUND => "NotFound",
+        StatusCode::METHOD_NOT_ALLOWED => "MethodNotAllowed",
+        StatusCode::REQUEST_TIMEOUT => "Timeout",
+        StatusCode::CONFLICT => "Conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "PayloadTooLarge",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "UnsupportedMediaType",
+        StatusCode::TOO_MANY_REQUESTS => "RateLimited",
+        StatusCode::SERVICE_UNAVAILABLE => "Unavailable",
+        status if status.is_client_error() => "RequestFailed",
+        _ => "Internal",
+    }
+}
+
+/// A plain-text body as the message, or the status's reason when it's empty
+fn error_message(status: StatusCode, body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(body).trim().to_string();
+    if text.is_empty() {
+        status
+            .canonical_reason()
+            .unwrap_or("request failed")
+            .to_string()
+    } else {
+        text
+    }
+}
+
+/// Give every error response a JSON body carrying the request's id.
+///
+/// Empty and plain-text bodies become an [`ApiErrorResponse`] with the text as its message;
+/// JSON objects only get `request_id` added. The id comes from [`REQUEST_ID_HEADER`] when
+/// the client sent one and is echoed back in that header on every response.
+pub async fn fill_error_body(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return response;
+    }
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Could not read the body of a {} response: {}", status, e);
+            return ApiError::new(status, status_code_name(status), status.to_string())
+                .into_response();
+        }
+    };
+
+    let body = if is_json {
+        match serde_json::from_slice::<Value>(&bytes) {
+            Ok(Value::Object(mut object)) => {
+                object
+                    .entry("request_id")
+                    .or_insert_with(|| request_id.into());
+                Value::Object(object)
+            }
+            _ => return Response::from_parts(parts, Body::from(bytes)),
+        }
+    } else {
+        let body = ApiErrorResponse {
+            code: status_code_name(status).to_string(),
+            message: error_message(status, &bytes),
+            request_id: Some(request_id),
+            details: None,
+        };
+        serde_json::to_value(body).unwrap_or_default()
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn call(app: Router, request_id: Option<&str>) -> (StatusCode, Option<String>, Value) {
+        let mut request = Request::builder().uri("/");
+        if let Some(id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let response = app
+            .layer(axum::middleware::from_fn(fill_error_body))
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let echoed = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .map(|id| id.to_str().unwrap().to_string());
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            echoed,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[test]
+    fn a_missing_image_is_a_404() {
+        let failure = ResolutionFailure {
+            kind: FailureKind::ImageNotFound,
+            key: "missing/app:1".to_string(),
+            message: "image missing/app:1 not found".to_string(),
+            cached_error: true,
+            retry_in_ms: 30_000,
+        };
+        let error = anyhow::Error::from(failure).context("resolving the image");
+        let api = ApiError::from_failure(error.as_ref());
+        assert_eq!(api.status, StatusCode::NOT_FOUND);
+        assert_eq!(api.body.code, "ImageNotFound");
+        assert_eq!(api.body.message, "image missing/app:1 not found");
+        assert_eq!(api.body.details.unwrap()["cached_error"], true);
+    }
+
+    #[test]
+    fn executor_failures_map_to_their_status() {
+        let timeout = anyhow::Error::from(FaasError::Timeout { timeout_ms: 500 });
+        let api = ApiError::from_failure(timeout.as_ref());
+        assert_eq!(
+            (api.status, api.body.code.as_str()),
+            (StatusCode::REQUEST_TIMEOUT, "Timeout")
+        );
+        assert_eq!(api.body.details.unwrap()["timeout_ms"], 500);
+
+        let unknown_user = anyhow::Error::from(FaasError::UnknownUser {
+            user: "deploy".to_string(),
+        });
+        let api = ApiError::from_failure(unknown_user.as_ref());
+        assert_eq!(
+            (api.status, api.body.code.as_str()),
+            (StatusCode::UNPROCESSABLE_ENTITY, "UnknownUser")
+        );
+        assert_eq!(api.body.details.unwrap()["user"], "deploy");
+
+        let unreachable = anyhow::Error::from(BollardError::SocketNotFoundError(
+            "/var/run/docker.sock".to_string(),
+        ));
+        let api = ApiError::from_failure(unreachable.as_ref());
+        assert_eq!(
+            (api.status, api.body.code.as_str()),
+            (StatusCode::SERVICE_UNAVAILABLE, "DockerUnavailable")
+        );
+
+        let no_such_image = anyhow::Error::from(BollardError::DockerResponseServerError {
+            status_code: 404,
+            message: "No such image: missing/app:1".to_string(),
+        });
+        let api = ApiError::from_failure(no_such_image.as_ref());
+        assert_eq!(
+            (api.status, api.body.code.as_str()),
+            (StatusCode::NOT_FOUND, "ImageNotFound")
+        );
+
+        let unsupported = anyhow::Error::from(CheckpointError::Unsupported {
+            reason: "CRIU is not installed".to_string(),
+        });
+        let api = ApiError::from_failure(unsupported.as_ref());
+        assert_eq!(
+            (api.status, api.body.code.as_str()),
+            (StatusCode::UNPROCESSABLE_ENTITY, "CheckpointUnsupported")
+        );
+        assert_eq!(api.body.details.unwrap()["reason"], "CRIU is not installed");
+
+        let other = anyhow::anyhow!("disk on fire");
+        let api = ApiError::from_failure(other.as_ref());
+        assert_eq!(
+            (
+                api.status,
+                api.body.code.as_str(),
+                api.body.message.as_str()
+            ),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal",
+                "disk on fire"
+            )
+        );
+    }
+
+    #[test]
+    fn an_unknown_mode_is_an_invalid_request() {
+        assert!(matches!(
+            crate::execution_mode(None),
+            Ok(faas_executor::platform::Mode::Ephemeral)
+        ));
+        let api = crate::execution_mode(Some("warp")).unwrap_err();
+        assert_eq!(
+            (api.status, api.body.code.as_str()),
+            (StatusCode::UNPROCESSABLE_ENTITY, "InvalidRequest")
+        );
+        assert!(api.body.message.contains("\"warp\""));
+    }
+
+    #[tokio::test]
+    async fn bare_statuses_get_a_body_with_the_request_id() {
+        let app = Router::new().route("/", get(|| async { StatusCode::NOT_FOUND }));
+        let (status, echoed, body) = call(app, Some("req-7")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(echoed.as_deref(), Some("req-7"));
+        assert_eq!(
+            serde_json::from_value::<ApiErrorResponse>(body).unwrap(),
+            ApiErrorResponse {
+                code: "NotFound".to_string(),
+                message: "Not Found".to_string(),
+                request_id: Some("req-7".to_string()),
+                details: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn json_errors_keep_their_fields() {
+        let app = Router::new().route(
+            "/",
+            get(|| async { ApiError::invalid_request("unknown mode \"warp\"") }),
+        );
+        let (status, echoed, body) = call(app, None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "InvalidRequest");
+        assert_eq!(body["message"], "unknown mode \"warp\"");
+        assert_eq!(body["request_id"].as_str(), echoed.as_deref());
+    }
+
+    #[tokio::test]
+    async fn successes_pass_through() {
+        let app = Router::new().route("/", get(|| async { Json(serde_json::json!([1])) }));
+        let (status, echoed, body) = call(app, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(echoed.is_some());
+        assert_eq!(body, serde_json::json!([1]));
+    }
+
+    #[tokio::test]
+    async fn responses_turn_back_into_errors() {
+        let relayed = ApiError::from_response(
+            ApiError::new(StatusCode::NOT_FOUND, "ImageNotFound", "no such image").into_response(),
+        )
+        .await;
+        assert_eq!(relayed.status, StatusCode::NOT_FOUND);
+        assert_eq!(relayed.body.code, "ImageNotFound");
+
+        let bare = ApiError::from_response(StatusCode::TOO_MANY_REQUESTS.into_response()).await;
+        assert_eq!(
+            (
+                bare.status,
+                bare.body.code.as_str(),
+                bare.body.message.as_str()
+            ),
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                "RateLimited",
+                "Too Many Requests"
+            )
+        );
+        let text =
+            ApiError::from_response((StatusCode::CONFLICT, "group settled").into_response()).await;
+        assert_eq!(text.body.message, "group settled");
+    }
+}
@@ -0,0 +1,927 @@
+//! Platform lifecycle events.
+//!
+//! Subsystems publish [`PlatformEvent`]s to the [`EventBus`]: executions starting and
+//! finishing, snapshots, instance state changes, pool scaling, reaped sandboxes and kill
+//! switches. Publishing never waits. Each consumer has a bounded queue, and an event that
+//! doesn't fit is dropped and counted against that consumer.
+//!
+//! Two consumers ship with the gateway. The JSON-lines log under `FAAS_EVENT_DIR` rotates
+//! into segments of `FAAS_EVENT_SEGMENT_BYTES` and keeps the newest `FAAS_EVENT_SEGMENTS`.
+//! `GET /api/v1/events?since=<cursor>&types=...` replays from an in-memory ring of recent
+//! events and reads older ones back from the log.
+//!
+//! A [`Cursor`] is the event's sequence number plus where its line starts in the log. The
+//! sequence carries on from the log after a restart, so a consumer can resume from the
+//! last cursor it saw without gaps or repeats.
+
+use crate::lifecycle::InstanceState;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use faas_common::Runtime;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::warn;
+
+/// Bumped whenever a field changes meaning or goes away; new fields don't bump it
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+const DEFAULT_RING_CAPACITY: usize = 4096;
+const DEFAULT_SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+const DEFAULT_SEGMENTS: usize = 8;
+/// Lines waiting for the log writer before new ones are dropped
+const LOG_QUEUE: usize = 8192;
+const DEFAULT_PAGE: usize = 500;
+const MAX_PAGE: usize = 5000;
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionOutcome {
+    /// Ran to completion; `exit_code` says how it went
+    Completed,
+    /// The runtime couldn't run it
+    Failed,
+    KilledBySwitch,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlatformEvent {
+    ExecutionStarted {
+        execution_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        runtime: Option<Runtime>,
+    },
+    ExecutionFinished {
+        execution_id: String,
+        outcome: ExecutionOutcome,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        duration_ms: u64,
+    },
+    SnapshotCreated {
+        snapshot_id: String,
+        container_id: String,
+        size_bytes: u64,
+    },
+    InstanceStateChanged {
+        instance_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from: Option<InstanceState>,
+        to: InstanceState,
+    },
+    /// Instances were added to (positive `delta`) or drained from a warm pool
+    PoolScaled { pool: String, delta: i64 },
+    /// A stopped or lost instance's sandbox was garbage-collected
+    VmReaped { instance_id: String },
+    KillSwitchActivated {
+        rule_id: String,
+        cancelled_executions: usize,
+        stopped_instances: usize,
+    },
+}
+
+impl PlatformEvent {
+    /// The `type` tag, as `types=` filters on it
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::ExecutionStarted { .. } => "execution_started",
+            Self::ExecutionFinished { .. } => "execution_finished",
+            Self::SnapshotCreated { .. } => "snapshot_created",
+            Self::InstanceStateChanged { .. } => "instance_state_changed",
+            Self::PoolScaled { .. } => "pool_scaled",
+            Self::VmReaped { .. } => "vm_reaped",
+            Self::KillSwitchActivated { .. } => "kill_switch_activated",
+        }
+    }
+}
+
+/// `<seq>-<segment>-<offset>`: the event's sequence number and where its line starts in
+/// the log. Cursors order by sequence number; the position only saves scanning.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Cursor {
+    pub seq: u64,
+    /// The sequence number of the segment's first event; 0 when there is no log
+    pub segment: u64,
+    pub offset: u64,
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.seq, self.segment, self.offset)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid event cursor {s:?}");
+        let mut parts = s.splitn(3, '-').map(|part| part.parse::<u64>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(seq)), Some(Ok(segment)), Some(Ok(offset))) => Ok(Self {
+                seq,
+                segment,
+                offset,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<Cursor> for String {
+    fn from(cursor: Cursor) -> Self {
+        cursor.to_string()
+    }
+}
+
+impl TryFrom<String> for Cursor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// One published event, as stored in the log and returned by the replay API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    /// [`EVENT_SCHEMA_VERSION`] when it was written
+    pub v: u32,
+    pub cursor: Cursor,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: PlatformEvent,
+}
+
+impl EventRecord {
+    fn line(&self) -> Vec<u8> {
+        let mut line = serde_json::to_vec(self).unwrap_or_default();
+        line.push(b'\n');
+        line
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventLogConfig {
+    pub dir: PathBuf,
+    pub segment_bytes: u64,
+    pub max_segments: usize,
+}
+
+impl EventLogConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            segment_bytes: DEFAULT_SEGMENT_BYTES,
+            max_segments: DEFAULT_SEGMENTS,
+        }
+    }
+
+    /// `FAAS_EVENT_DIR`, or a temp directory when unset
+    pub fn from_env() -> Self {
+        let dir = std::env::var("FAAS_EVENT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("faas-events"));
+        let var = |name: &str| std::env::var(name).ok();
+        Self {
+            segment_bytes: var("FAAS_EVENT_SEGMENT_BYTES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_SEGMENT_BYTES),
+            max_segments: var("FAAS_EVENT_SEGMENTS")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_SEGMENTS)
+                .max(1),
+            ..Self::new(dir)
+        }
+    }
+
+    fn segment_path(&self, segment: u64) -> PathBuf {
+        self.dir.join(format!("events-{segment:020}.jsonl"))
+    }
+
+    /// Every segment on disk, oldest first
+    fn segments(&self) -> std::io::Result<Vec<u64>> {
+        let mut segments = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let id = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("events-"))
+                .and_then(|name| name.strip_suffix(".jsonl"))
+                .and_then(|id| id.parse::<u64>().ok());
+            segments.extend(id);
+        }
+        segments.sort_unstable();
+        Ok(segments)
+    }
+
+    /// The next sequence number and write position left by a previous run. A line cut off
+    /// by a crash is removed.
+    fn recover(&self) -> std::io::Result<(u64, u64, u64)> {
+        std::fs::create_dir_all(&self.dir)?;
+        let Some(&segment) = self.segments()?.last() else {
+            return Ok((1, 0, 0));
+        };
+        let path = self.segment_path(segment);
+        let contents = std::fs::read(&path)?;
+        let complete = contents
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |end| end + 1);
+        if complete < contents.len() {
+            warn!(
+                "Truncating a partly written event in {}",
+                path.to_string_lossy()
+            );
+            OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(complete as u64)?;
+        }
+        let last_seq = contents[..complete]
+            .split(|&b| b == b'\n')
+            .rev()
+            .find_map(|line| serde_json::from_slice::<EventRecord>(line).ok())
+            .map(|record| record.cursor.seq);
+        Ok(match last_seq {
+            Some(seq) => (seq + 1, segment, complete as u64),
+            // An empty segment is named after the first event it was going to hold
+            None => (segment, segment, complete as u64),
+        })
+    }
+
+    /// Up to `limit` wanted events after `since` and before sequence number `until`, and
+    /// the cursor of the last event looked at. `complete` is false if `limit` cut it short.
+    fn read(
+        &self,
+        since: Cursor,
+        until: u64,
+        types: &[String],
+        limit: usize,
+    ) -> std::io::Result<LogRead> {
+        let mut read = LogRead {
+            events: Vec::new(),
+            last: None,
+            complete: true,
+        };
+        let segments = self.segments()?;
+        // The next event lives in the newest segment that starts at or before it
+        let first = segments
+            .partition_point(|&segment| segment <= since.seq + 1)
+            .saturating_sub(1);
+        for &segment in &segments[first..] {
+            let file = match File::open(self.segment_path(segment)) {
+                Ok(file) => file,
+                // Rotated away while we were reading
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let mut reader = BufReader::new(file);
+            if segment == since.segment && !seek_to_line(&mut reader, since.offset)? {
+                reader.seek(SeekFrom::Start(0))?;
+            }
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                // A line without its newline is still being written
+                if reader.read_until(b'\n', &mut line)? == 0 || line.last() != Some(&b'\n') {
+                    break;
+                }
+                let Ok(record) = serde_json::from_slice::<EventRecord>(&line) else {
+                    continue;
+                };
+                let seq = record.cursor.seq;
+                if seq <= since.seq {
+                    continue;
+                }
+                if seq >= until {
+                    return Ok(read);
+                }
+                if read.events.len() >= limit {
+                    read.complete = false;
+                    return Ok(read);
+                }
+                read.last = Some(record.cursor);
+                if wanted(types, &record.event) {
+                    read.events.push(record);
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+struct LogRead {
+    events: Vec<EventRecord>,
+    last: Option<Cursor>,
+    complete: bool,
+}
+
+/// Position `reader` at `offset` if an event's line starts there
+fn seek_to_line(reader: &mut BufReader<File>, offset: u64) -> std::io::Result<bool> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    let starts_here = serde_json::from_slice::<EventRecord>(&line)
+        .is_ok_and(|record| record.cursor.offset == offset);
+    reader.seek(SeekFrom::Start(offset))?;
+    Ok(starts_here)
+}
+
+fn wanted(types: &[String], event: &PlatformEvent) -> bool {
+    types.is_empty() || types.iter().any(|kind| kind == event.kind())
+}
+
+struct LogLine {
+    segment: u64,
+    bytes: Vec<u8>,
+}
+
+/// Appends published events to the log; run it on its own task
+pub struct EventLogWriter {
+    config: EventLogConfig,
+    lines: mpsc::Receiver<LogLine>,
+}
+
+struct OpenSegment {
+    segment: u64,
+    file: File,
+}
+
+impl EventLogWriter {
+    /// Returns once the bus is dropped and every queued line is written
+    pub async fn run(mut self) {
+        let mut open: Option<OpenSegment> = None;
+        let mut batch = Vec::new();
+        while self.lines.recv_many(&mut batch, 256).await > 0 {
+            let config = self.config.clone();
+            let lines = std::mem::take(&mut batch);
+            let written = tokio::task::spawn_blocking(move || {
+                let result = write_lines(&config, &mut open, &lines);
+                (open, result)
+            })
+            .await;
+            match written {
+                Ok((still_open, result)) => {
+                    open = still_open;
+                    if let Err(e) = result {
+                        warn!("Failed to write platform events: {}", e);
+                    }
+                }
+                Err(e) => {
+                    warn!("Platform event writer panicked: {}", e);
+                    open = None;
+                }
+            }
+        }
+    }
+}
+
+fn write_lines(
+    config: &EventLogConfig,
+    open: &mut Option<OpenSegment>,
+    lines: &[LogLine],
+) -> std::io::Result<()> {
+    for line in lines {
+        let current = match open {
+            Some(current) if current.segment == line.segment => current,
+            _ => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(config.segment_path(line.segment))?;
+                prune_segments(config)?;
+                open.insert(OpenSegment {
+                    segment: line.segment,
+                    file,
+                })
+            }
+        };
+        current.file.write_all(&line.bytes)?;
+    }
+    Ok(())
+}
+
+fn prune_segments(config: &EventLogConfig) -> std::io::Result<()> {
+    let segments = config.segments()?;
+    let excess = segments.len().saturating_sub(config.max_segments);
+    for &segment in &segments[..excess] {
+        std::fs::remove_file(config.segment_path(segment))?;
+    }
+    Ok(())
+}
+
+/// A consumer's queue of events, with the count it missed because it fell behind
+pub struct Subscription {
+    events: mpsc::Receiver<Arc<EventRecord>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    pub async fn recv(&mut self) -> Option<Arc<EventRecord>> {
+        self.events.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Option<Arc<EventRecord>> {
+        self.events.try_recv().ok()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+struct Subscriber {
+    events: mpsc::Sender<Arc<EventRecord>>,
+    dropped: Arc<AtomicU64>,
+}
+
+struct BusState {
+    next_seq: u64,
+    /// Where the log writer's next line will start
+    segment: u64,
+    offset: u64,
+    ring: VecDeque<Arc<EventRecord>>,
+    log: Option<mpsc::Sender<LogLine>>,
+    subscribers: Vec<Subscriber>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventStats {
+    pub published: u64,
+    /// Events the log writer had no room for; they are missing from the log
+    pub log_dropped: u64,
+    pub subscribers: usize,
+    pub subscriber_dropped: u64,
+}
+
+pub struct EventBus {
+    state: Mutex<BusState>,
+    ring_capacity: usize,
+    log: Option<EventLogConfig>,
+    log_dropped: AtomicU64,
+    /// Sequence number of the latest event, for long polls
+    latest: watch::Sender<u64>,
+}
+
+/// A page of replayed events. `next_cursor` is where to resume, even if `types` filtered
+/// out everything that was read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventPage {
+    pub events: Vec<EventRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Cursor>,
+}
+
+impl EventBus {
+    /// A bus that keeps the latest `ring_capacity` events in memory only
+    pub fn new(ring_capacity: usize) -> Self {
+        Self::build(ring_capacity, None, None, (1, 0, 0))
+    }
+
+    /// A bus that also logs to `config.dir`, carrying on where a previous run stopped.
+    /// The writer has to be run for anything to reach the log.
+    pub fn with_log(
+        ring_capacity: usize,
+        config: EventLogConfig,
+    ) -> std::io::Result<(Self, EventLogWriter)> {
+        let recovered = config.recover()?;
+        let (lines_tx, lines) = mpsc::channel(LOG_QUEUE);
+        let writer = EventLogWriter {
+            config: config.clone(),
+            lines,
+        };
+        Ok((
+            Self::build(ring_capacity, Some(config), Some(lines_tx), recovered),
+            writer,
+        ))
+    }
+
+    pub fn from_env() -> std::io::Result<(Self, EventLogWriter)> {
+        Self::with_log(DEFAULT_RING_CAPACITY, EventLogConfig::from_env())
+    }
+
+    fn build(
+        ring_capacity: usize,
+        log: Option<EventLogConfig>,
+        log_lines: Option<mpsc::Sender<LogLine>>,
+        (next_seq, segment, offset): (u64, u64, u64),
+    ) -> Self {
+        Self {
+            state: Mutex::new(BusState {
+                next_seq,
+                segment,
+                offset,
+                ring: VecDeque::with_capacity(ring_capacity),
+                log: log_lines,
+                subscribers: Vec::new(),
+            }),
+            ring_capacity: ring_capacity.max(1),
+            log,
+            log_dropped: AtomicU64::new(0),
+            latest: watch::Sender::new(next_seq - 1),
+        }
+    }
+
+    /// Hand `event` to every consumer that has room for it; never waits
+    pub fn publish(&self, event: PlatformEvent) -> Cursor {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        let mut record = EventRecord {
+            v: EVENT_SCHEMA_VERSION,
+            cursor: Cursor {
+                seq,
+                segment: state.segment,
+                offset: state.offset,
+            },
+            at: Utc::now(),
+            event,
+        };
+
+        if let (Some(config), Some(log)) = (&self.log, &state.log) {
+            let mut line = record.line();
+            let full = state.offset > 0 && state.offset + line.len() as u64 > config.segment_bytes;
+            if state.segment == 0 || full {
+                record.cursor.segment = seq;
+                record.cursor.offset = 0;
+                line = record.line();
+            }
+            let end = record.cursor.offset + line.len() as u64;
+            let sent = log.try_send(LogLine {
+                segment: record.cursor.segment,
+                bytes: line,
+            });
+            // Only lines that will be written move the position, so later cursors stay exact
+            if sent.is_ok() {
+                state.segment = record.cursor.segment;
+                state.offset = end;
+            } else {
+                self.log_dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let record = Arc::new(record);
+        if state.ring.len() >= self.ring_capacity {
+            state.ring.pop_front();
+        }
+        state.ring.push_back(record.clone());
+        state.subscribers.retain(
+            |subscriber| match subscriber.events.try_send(record.clone()) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            },
+        );
+        drop(state);
+        self.latest.send_replace(seq);
+        record.cursor
+    }
+
+    /// Events published from now on, up to `capacity` of them waiting at a time
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        let (events_tx, events) = mpsc::channel(capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        self.state.lock().unwrap().subscribers.push(Subscriber {
+            events: events_tx,
+            dropped: dropped.clone(),
+        });
+        Subscription { events, dropped }
+    }
+
+    pub fn stats(&self) -> EventStats {
+        let state = self.state.lock().unwrap();
+        EventStats {
+            published: *self.latest.borrow(),
+            log_dropped: self.log_dropped.load(Ordering::Relaxed),
+            subscribers: state.subscribers.len(),
+            subscriber_dropped: state
+                .subscribers
+                .iter()
+                .map(|subscriber| subscriber.dropped.load(Ordering::Relaxed))
+                .sum(),
+        }
+    }
+
+    /// Up to `limit` events after `since` (from the oldest retained without one), of the
+    /// given `types` (all when empty). Events older than the ring come from the log.
+    pub async fn replay(
+        &self,
+        since: Option<Cursor>,
+        types: &[String],
+        limit: usize,
+    ) -> std::io::Result<EventPage> {
+        let since = since.unwrap_or_default();
+        let (ring, ring_start) = {
+            let state = self.state.lock().unwrap();
+            let ring_start = state
+                .ring
+                .front()
+                .map_or(state.next_seq, |record| record.cursor.seq);
+            let ring: Vec<_> = state
+                .ring
+                .iter()
+                .filter(|record| record.cursor.seq > since.seq)
+                .cloned()
+                .collect();
+            (ring, ring_start)
+        };
+
+        let mut page = EventPage::default();
+        let mut last_seq = since.seq;
+        if since.seq + 1 < ring_start {
+            if let Some(config) = self.log.clone() {
+                let types = types.to_vec();
+                let read = tokio::task::spawn_blocking(move || {
+                    config.read(since, ring_start, &types, limit)
+                })
+                .await
+                .map_err(std::io::Error::other)??;
+                if let Some(last) = read.last {
+                    last_seq = last.seq;
+                    page.next_cursor = Some(last);
+                }
+                page.events = read.events;
+                if !read.complete {
+                    return Ok(page);
+                }
+            }
+        }
+        for record in ring {
+            if record.cursor.seq <= last_seq {
+                continue;
+            }
+            if page.events.len() >= limit {
+                break;
+            }
+            page.next_cursor = Some(record.cursor);
+            if wanted(types, &record.event) {
+                page.events.push(EventRecord::clone(&record));
+            }
+        }
+        Ok(page)
+    }
+
+    /// Wait until an event after `seq` is published, or `timeout` passes
+    pub async fn wait_after(&self, seq: u64, timeout: Duration) {
+        let mut latest = self.latest.subscribe();
+        let _ = tokio::time::timeout(timeout, latest.wait_for(|&latest| latest > seq)).await;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    pub since: Option<Cursor>,
+    /// Comma-separated event types, e.g. `execution_started,vm_reaped`
+    pub types: Option<String>,
+    pub limit: Option<usize>,
+    /// Hold the request this long if there is nothing new yet
+    pub wait_ms: Option<u64>,
+}
+
+/// `GET /api/v1/events`
+pub async fn events_handler(
+    State(bus): State<Arc<EventBus>>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<EventPage>, Response> {
+    let types: Vec<String> = query
+        .types
+        .iter()
+        .flat_map(|types| types.split(','))
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+        .map(str::to_string)
+        .collect();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let replay = |since| {
+        let (bus, types) = (&bus, &types);
+        async move {
+            bus.replay(since, types, limit).await.map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({ "error": e.to_string(), "code": "EventLogUnavailable" })),
+                )
+                    .into_response()
+            })
+        }
+    };
+
+    let page = replay(query.since).await?;
+    let wait = Duration::from_millis(query.wait_ms.unwrap_or(0)).min(MAX_WAIT);
+    if !page.events.is_empty() || wait.is_zero() {
+        return Ok(Json(page));
+    }
+    // Nothing wanted yet; resume from what was read so filtered-out events aren't reread
+    let since = page.next_cursor.or(query.since);
+    bus.wait_after(since.map_or(0, |cursor| cursor.seq), wait)
+        .await;
+    let mut next = replay(since).await?;
+    if next.next_cursor.is_none() {
+        next.next_cursor = since;
+    }
+    Ok(Json(next))
+}
+
+/// Log directory contents, oldest first; for tests and tooling
+pub fn segment_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let config = EventLogConfig::new(dir);
+    Ok(config
+        .segments()?
+        .into_iter()
+        .map(|segment| config.segment_path(segment))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed_event(i: u64) -> PlatformEvent {
+        let id = format!("e{i}");
+        match i % 4 {
+            0 => PlatformEvent::ExecutionStarted {
+                execution_id: id,
+                runtime: Some(Runtime::Docker),
+            },
+            1 => PlatformEvent::ExecutionFinished {
+                execution_id: id,
+                outcome: ExecutionOutcome::Completed,
+                exit_code: Some(0),
+                duration_ms: i,
+            },
+            2 => PlatformEvent::InstanceStateChanged {
+                instance_id: id,
+                from: Some(InstanceState::Creating),
+                to: InstanceState::Running,
+            },
+            _ => PlatformEvent::PoolScaled { pool: id, delta: 1 },
+        }
+    }
+
+    fn seqs(page: &EventPage) -> Vec<u64> {
+        page.events.iter().map(|record| record.cursor.seq).collect()
+    }
+
+    fn small_segments(dir: &Path) -> EventLogConfig {
+        EventLogConfig {
+            segment_bytes: 1024,
+            max_segments: 1000,
+            ..EventLogConfig::new(dir)
+        }
+    }
+
+    #[tokio::test]
+    async fn consumers_resume_from_a_cursor_across_a_restart_without_gaps_or_repeats() {
+        let dir = tempfile::tempdir().unwrap();
+        let (bus, writer) = EventBus::with_log(16, small_segments(dir.path())).unwrap();
+        let writing = tokio::spawn(writer.run());
+        let cursors: Vec<Cursor> = (0..100).map(|i| bus.publish(mixed_event(i))).collect();
+        let resume_from = cursors[39];
+        // Restart: the writer finishes once the bus is gone
+        drop(bus);
+        writing.await.unwrap();
+        assert!(segment_files(dir.path()).unwrap().len() > 1, "log rotated");
+        assert!(resume_from.segment > 0);
+
+        let (bus, writer) = EventBus::with_log(16, small_segments(dir.path())).unwrap();
+        let writing = tokio::spawn(writer.run());
+        for i in 100..110 {
+            bus.publish(mixed_event(i));
+        }
+
+        // The first page is all log, the rest continues from the ring
+        let first = bus.replay(Some(resume_from), &[], 50).await.unwrap();
+        assert_eq!(seqs(&first), (41..=90).collect::<Vec<_>>());
+        let rest = bus.replay(first.next_cursor, &[], 1000).await.unwrap();
+        assert_eq!(seqs(&rest), (91..=110).collect::<Vec<_>>());
+        assert_eq!(rest.next_cursor.unwrap().seq, 110);
+        assert_eq!(rest.events[0].event, mixed_event(90));
+
+        // A cursor from an event that went through the string form resumes the same way
+        let parsed: Cursor = cursors[39].to_string().parse().unwrap();
+        assert_eq!(parsed, resume_from);
+
+        // Filtering still moves the cursor past what it skipped
+        let started = bus
+            .replay(Some(resume_from), &["execution_started".to_string()], 1000)
+            .await
+            .unwrap();
+        assert!(started
+            .events
+            .iter()
+            .all(|record| record.event.kind() == "execution_started"));
+        assert_eq!(started.events.len(), 18);
+        assert_eq!(started.next_cursor.unwrap().seq, 110);
+
+        drop(bus);
+        writing.await.unwrap();
+        let mut everything = Vec::new();
+        for file in segment_files(dir.path()).unwrap() {
+            for line in std::fs::read_to_string(file).unwrap().lines() {
+                let record: EventRecord = serde_json::from_str(line).unwrap();
+                assert_eq!(record.v, EVENT_SCHEMA_VERSION);
+                everything.push(record.cursor.seq);
+            }
+        }
+        assert_eq!(everything, (1..=110).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn a_cut_off_line_is_dropped_on_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let (bus, writer) = EventBus::with_log(16, small_segments(dir.path())).unwrap();
+        let writing = tokio::spawn(writer.run());
+        for i in 0..3 {
+            bus.publish(mixed_event(i));
+        }
+        drop(bus);
+        writing.await.unwrap();
+        let last = segment_files(dir.path()).unwrap().pop().unwrap();
+        let mut file = OpenOptions::new().append(true).open(&last).unwrap();
+        file.write_all(br#"{"v":1,"cursor":"4-1-"#).unwrap();
+
+        let (bus, _writer) = EventBus::with_log(16, small_segments(dir.path())).unwrap();
+        assert_eq!(bus.publish(mixed_event(3)).seq, 4);
+        let page = bus.replay(None, &[], 100).await.unwrap();
+        assert_eq!(seqs(&page), [1, 2, 3, 4]);
+        assert!(!std::fs::read_to_string(last).unwrap().contains(r#""4-1-"#));
+    }
+
+    #[tokio::test]
+    async fn a_slow_subscriber_loses_what_does_not_fit_and_nobody_else_does() {
+        let bus = EventBus::new(64);
+        let mut slow = bus.subscribe(4);
+        let mut keeping_up = bus.subscribe(64);
+        for i in 0..10 {
+            bus.publish(mixed_event(i));
+        }
+
+        let mut received = Vec::new();
+        while let Some(record) = slow.try_recv() {
+            received.push(record.cursor.seq);
+        }
+        assert_eq!(received, [1, 2, 3, 4]);
+        assert_eq!(slow.dropped(), 6);
+        let mut all = 0;
+        while keeping_up.try_recv().is_some() {
+            all += 1;
+        }
+        assert_eq!((all, keeping_up.dropped()), (10, 0));
+
+        let stats = bus.stats();
+        assert_eq!((stats.published, stats.subscriber_dropped), (10, 6));
+        drop(slow);
+        bus.publish(mixed_event(10));
+        assert_eq!(bus.stats().subscribers, 1);
+    }
+
+    #[tokio::test]
+    async fn a_long_poll_returns_as_soon_as_an_event_is_published() {
+        let bus = Arc::new(EventBus::new(64));
+        let since = bus.publish(mixed_event(0));
+        let polling = tokio::spawn({
+            let bus = bus.clone();
+            async move {
+                events_handler(
+                    State(bus),
+                    Query(EventsQuery {
+                        since: Some(since),
+                        types: None,
+                        limit: None,
+                        wait_ms: Some(10_000),
+                    }),
+                )
+                .await
+                .map(|Json(page)| page)
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        bus.publish(mixed_event(1));
+        let page = tokio::time::timeout(Duration::from_secs(5), polling)
+            .await
+            .expect("long poll woke up")
+            .unwrap()
+            .unwrap();
+        assert_eq!(seqs(&page), [2]);
+    }
+}
@@ -0,0 +1,446 @@
+//! Forks with named branches, `POST /api/v1/fork`.
+//!
+//! Each branch runs its own command as a branch of the fork's parent, or from the image
+//! when the fork has none, and the strategy decides how they run and which one is
+//! selected:
+//!
+//! - `parallel` runs every branch at once and selects the heaviest that succeeded, the
+//!   fastest of those on a tie;
+//! - `fastest` races them and selects the first to succeed, cancelling the rest;
+//! - `sequential` runs them in order and stops at the first to succeed; the branches
+//!   after it are skipped.
+//!
+//! A branch succeeds when it runs to exit code 0.
+
+use faas_common::EnvVar;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+
+use crate::errors::{ApiError, ApiErrorResponse};
+use crate::InvokeResponse;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForkBranch {
+    /// Unique within the fork
+    pub id: String,
+    pub command: String,
+    /// Set on top of the fork's own env vars
+    #[serde(default)]
+    pub env_vars: Option<Vec<EnvVar>>,
+    /// Preference among branches that succeed under `parallel`; 1 when unset
+    #[serde(default)]
+    pub weight: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForkStrategy {
+    #[default]
+    Parallel,
+    Fastest,
+    Sequential,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BranchOutcome {
+    Succeeded,
+    Failed,
+    /// Stopped by the fork once another branch won
+    Cancelled,
+    /// Never started, since an earlier branch succeeded
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchResult {
+    pub branch_id: String,
+    pub outcome: BranchOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<InvokeResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiErrorResponse>,
+}
+
+impl BranchResult {
+    fn new(branch_id: String, result: Result<InvokeResponse, ApiError>, cancelled: bool) -> Self {
+        match result {
+            Ok(response) => Self {
+                branch_id,
+                outcome: if response.exit_code == 0 {
+                    BranchOutcome::Succeeded
+                } else {
+                    BranchOutcome::Failed
+                },
+                response: Some(response),
+                error: None,
+            },
+            Err(e) => Self {
+                branch_id,
+                outcome: if cancelled {
+                    BranchOutcome::Cancelled
+                } else {
+                    BranchOutcome::Failed
+                },
+                response: None,
+                error: Some(e.body),
+            },
+        }
+    }
+
+    fn skipped(branch_id: String) -> Self {
+        Self {
+            branch_id,
+            outcome: BranchOutcome::Skipped,
+            response: None,
+            error: None,
+        }
+    }
+
+    fn succeeded(&self) -> bool {
+        self.outcome == BranchOutcome::Succeeded
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForkResult {
+    pub fork_id: String,
+    pub strategy: ForkStrategy,
+    /// One per branch, in request order
+    pub results: Vec<BranchResult>,
+    pub selected_branch: Option<String>,
+    pub selection_reason: String,
+}
+
+/// Refuse a fork without branches or with two branches of the same id
+pub fn validate(branches: &[ForkBranch]) -> Result<(), ApiError> {
+    let invalid = |message: String| {
+        ApiError::invalid_request(message).with_details(serde_json::json!({ "field": "branches" }))
+    };
+    if branches.is_empty() {
+        return Err(invalid("a fork needs at least one branch".to_string()));
+    }
+    let mut ids = HashSet::new();
+    for branch in branches {
+        if branch.id.is_empty() {
+            return Err(invalid("every branch needs an id".to_string()));
+        }
+        if !ids.insert(branch.id.as_str()) {
+            return Err(invalid(format!("branch id {:?} is used twice", branch.id)));
+        }
+    }
+    Ok(())
+}
+
+/// Run `branches` through `execute` as `strategy` says. `cancel` is handed the id of each
+/// branch `fastest` stops, whose execution is still awaited so it can wind down.
+pub async fn run<F, Fut, C>(
+    fork_id: String,
+    strategy: ForkStrategy,
+    branches: Vec<ForkBranch>,
+    mut execute: F,
+    cancel: C,
+) -> ForkResult
+where
+    F: FnMut(ForkBranch) -> Fut,
+    Fut: Future<Output = Result<InvokeResponse, ApiError>>,
+    C: Fn(&str),
+{
+    let weights: Vec<f64> = branches.iter().map(|b| b.weight.unwrap_or(1.0)).collect();
+    let (results, selected) = match strategy {
+        ForkStrategy::Parallel => {
+            let runs = branches.into_iter().map(|branch| {
+                let id = branch.id.clone();
+                let run = execute(branch);
+                async move { BranchResult::new(id, run.await, false) }
+            });
+            let results = futures::future::join_all(runs).await;
+            let selected = results
+                .iter()
+                .enumerate()
+                .filter(|(_, result)| result.succeeded())
+                .min_by(|(a, ra), (b, rb)| {
+                    weights[*b]
+                        .total_cmp(&weights[*a])
+                        .then(duration(ra).cmp(&duration(rb)))
+                })
+                .map(|(index, _)| index);
+            (results, selected)
+        }
+        ForkStrategy::Fastest => {
+            let ids: Vec<String> = branches.iter().map(|b| b.id.clone()).collect();
+            let mut runs: FuturesUnordered<_> = branches
+                .into_iter()
+                .enumerate()
+                .map(|(index, branch)| {
+                    let run = execute(branch);
+                    async move { (index, run.await) }
+                })
+                .collect();
+            let mut finished: Vec<Option<BranchResult>> = ids.iter().map(|_| None).collect();
+            let mut winner = None;
+            while let Some((index, result)) = runs.next().await {
+                let result = BranchResult::new(ids[index].clone(), result, winner.is_some());
+                if winner.is_none() && result.succeeded() {
+                    winner = Some(index);
+                    for (other, id) in ids.iter().enumerate() {
+                        if finished[other].is_none() && other != index {
+                            cancel(id);
+                        }
+                    }
+                }
+                finished[index] = Some(result);
+            }
+            let results = finished.into_iter().map(Option::unwrap).collect();
+            (results, winner)
+        }
+        ForkStrategy::Sequential => {
+            let mut results = Vec::new();
+            let mut selected = None;
+            for branch in branches {
+                if selected.is_some() {
+                    results.push(BranchResult::skipped(branch.id));
+                    continue;
+                }
+                let id = branch.id.clone();
+                let result = BranchResult::new(id, execute(branch).await, false);
+                if result.succeeded() {
+                    selected = Some(results.len());
+                }
+                results.push(result);
+            }
+            (results, selected)
+        }
+    };
+
+    let selected_branch = selected.map(|index| results[index].branch_id.clone());
+    let selection_reason = match (selected, strategy) {
+        (None, _) => "no branch succeeded".to_string(),
+        (Some(_), ForkStrategy::Parallel) => {
+            "highest weight among the branches that succeeded, then fastest".to_string()
+        }
+        (Some(_), ForkStrategy::Fastest) => {
+            let cancelled = results
+                .iter()
+                .filter(|r| r.outcome == BranchOutcome::Cancelled)
+                .count();
+            format!("first branch to succeed; {cancelled} cancelled")
+        }
+        (Some(index), ForkStrategy::Sequential) => format!(
+            "first branch in order to succeed; {} skipped",
+            results.len() - index - 1
+        ),
+    };
+    ForkResult {
+        fork_id,
+        strategy,
+        results,
+        selected_branch,
+        selection_reason,
+    }
+}
+
+fn duration(result: &BranchResult) -> u64 {
+    result.response.as_ref().map_or(u64::MAX, |r| r.duration_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio_util::sync::CancellationToken;
+
+    fn branch(id: &str, command: &str) -> ForkBranch {
+        ForkBranch {
+            id: id.to_string(),
+            command: command.to_string(),
+            env_vars: None,
+            weight: None,
+        }
+    }
+
+    fn response(id: &str, exit_code: i32, duration_ms: u64) -> InvokeResponse {
+        InvokeResponse {
+            request_id: id.to_string(),
+            exit_code,
+            stdout: format!("{id}\n"),
+            stderr: String::new(),
+            duration_ms,
+            output: None,
+            logs: None,
+            error: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime: None,
+            runtime_reason: None,
+            diagnostics: None,
+            usage: None,
+            truncated: false,
+            total_bytes: None,
+            snapshot_id: None,
+        }
+    }
+
+    /// `exit N` exits with N, `sleep N` sleeps N ms then succeeds, `hang` runs until
+    /// cancelled
+    fn executor(
+        cancelled: CancellationToken,
+        ran: Arc<Mutex<Vec<String>>>,
+    ) -> impl FnMut(ForkBranch) -> futures::future::BoxFuture<'static, Result<InvokeResponse, ApiError>>
+    {
+        move |branch| {
+            let cancelled = cancelled.clone();
+            ran.lock().unwrap().push(branch.id.clone());
+            Box::pin(async move {
+                let (verb, arg) = branch
+                    .command
+                    .split_once(' ')
+                    .unwrap_or((&branch.command, ""));
+                match verb {
+                    "exit" => Ok(response(&branch.id, arg.parse().unwrap(), 1)),
+                    "sleep" => {
+                        let ms = arg.parse().unwrap();
+                        tokio::time::sleep(Duration::from_millis(ms)).await;
+                        Ok(response(&branch.id, 0, ms))
+                    }
+                    _ => {
+                        cancelled.cancelled().await;
+                        Err(ApiError::new(
+                            StatusCode::CONFLICT,
+                            "Cancelled",
+                            "cancelled",
+                        ))
+                    }
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn fastest_selects_the_first_success_and_cancels_the_rest() {
+        let token = CancellationToken::new();
+        let cancels = Arc::new(Mutex::new(Vec::new()));
+        let recorded = cancels.clone();
+        let trip = token.clone();
+        let result = run(
+            "fork".to_string(),
+            ForkStrategy::Fastest,
+            vec![
+                branch("slow", "hang"),
+                branch("broken", "exit 1"),
+                branch("fast", "sleep 5"),
+            ],
+            executor(token, Arc::default()),
+            move |id: &str| {
+                recorded.lock().unwrap().push(id.to_string());
+                trip.cancel();
+            },
+        )
+        .await;
+
+        assert_eq!(result.selected_branch.as_deref(), Some("fast"));
+        assert_eq!(*cancels.lock().unwrap(), ["slow"]);
+        let outcomes: Vec<_> = result.results.iter().map(|r| r.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [
+                BranchOutcome::Cancelled,
+                BranchOutcome::Failed,
+                BranchOutcome::Succeeded
+            ]
+        );
+        assert_eq!(
+            result.selection_reason,
+            "first branch to succeed; 1 cancelled"
+        );
+    }
+
+    #[tokio::test]
+    async fn sequential_stops_at_the_first_success() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let result = run(
+            "fork".to_string(),
+            ForkStrategy::Sequential,
+            vec![
+                branch("a", "exit 2"),
+                branch("b", "exit 0"),
+                branch("c", "exit 0"),
+            ],
+            executor(CancellationToken::new(), ran.clone()),
+            |_: &str| panic!("sequential forks cancel nothing"),
+        )
+        .await;
+
+        assert_eq!(*ran.lock().unwrap(), ["a", "b"]);
+        assert_eq!(result.selected_branch.as_deref(), Some("b"));
+        assert_eq!(result.results[2].outcome, BranchOutcome::Skipped);
+        assert_eq!(result.results[0].response.as_ref().unwrap().exit_code, 2);
+    }
+
+    #[tokio::test]
+    async fn parallel_prefers_weight_then_speed() {
+        let mut heavy = branch("heavy", "sleep 20");
+        heavy.weight = Some(2.0);
+        let result = run(
+            "fork".to_string(),
+            ForkStrategy::Parallel,
+            vec![
+                branch("quick", "sleep 1"),
+                heavy,
+                branch("failed", "exit 1"),
+            ],
+            executor(CancellationToken::new(), Arc::default()),
+            |_: &str| panic!("parallel forks cancel nothing"),
+        )
+        .await;
+        assert_eq!(result.selected_branch.as_deref(), Some("heavy"));
+        assert_eq!(result.results.len(), 3);
+
+        let result = run(
+            "fork".to_string(),
+            ForkStrategy::Parallel,
+            vec![branch("slower", "sleep 20"), branch("quicker", "sleep 1")],
+            executor(CancellationToken::new(), Arc::default()),
+            |_: &str| {},
+        )
+        .await;
+        assert_eq!(result.selected_branch.as_deref(), Some("quicker"));
+    }
+
+    #[test]
+    fn branches_need_unique_ids() {
+        assert_eq!(
+            validate(&[]).unwrap_err().status,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        let twice = validate(&[branch("a", "true"), branch("a", "false")]).unwrap_err();
+        assert!(twice.body.message.contains("used twice"));
+        assert!(validate(&[branch("a", "true"), branch("b", "false")]).is_ok());
+    }
+
+    #[test]
+    fn branch_env_vars_read_every_form_clients_send() {
+        let branch: ForkBranch = serde_json::from_value(serde_json::json!({
+            "id": "a",
+            "command": "env",
+            "env_vars": [
+                {"key": "TOKEN", "value": "dG9rZW4="},
+                ["NOTE", "two\nlines"],
+                "GREETING=hé=llo",
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            branch.env_vars.unwrap(),
+            [
+                EnvVar::new("TOKEN", "dG9rZW4="),
+                EnvVar::new("NOTE", "two\nlines"),
+                EnvVar::new("GREETING", "hé=llo"),
+            ]
+        );
+    }
+}
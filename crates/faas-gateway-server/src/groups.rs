@@ -0,0 +1,702 @@
+//! Execution groups.
+//!
+//! A fan-out of executions joins one group and the gateway sends a single `on_settled`
+//! callback when the group's settlement policy triggers, instead of one per execution.
+//!
+//! Members register under their group for cascade cancellation, so cancelling the group
+//! cancels them as its `cancel_policy` says. A cancelled group settles once no member is
+//! left running, and its summary says which members the cancel reached.
+
+use crate::cancellation::{CancelPolicy, CancelReport, Cancellation};
+use crate::comparison::Normalizer;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// When a group counts as settled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementPolicy {
+    /// Every member has finished
+    #[default]
+    AllComplete,
+    /// Any member failed, or every member finished without failing
+    FirstFailure,
+    /// This many members succeeded, or every member finished
+    Quorum(usize),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateGroupRequest {
+    #[serde(default)]
+    pub policy: SettlementPolicy,
+    /// URL that receives the [`GroupSummary`] once, when the group settles
+    pub on_settled: Option<String>,
+    /// Number of executions the group will hold.
+    ///
+    /// Without it, "every member" means every member that has joined so far, so a group can
+    /// settle before slower submitters join.
+    pub expected: Option<usize>,
+    /// Earlier group whose outputs this one is compared against once it settles
+    pub baseline_group_id: Option<String>,
+    /// Named normalizer preset the comparison uses by default
+    pub normalizer: Option<String>,
+    /// Members a cancel reaches when it names no policy of its own
+    #[serde(default)]
+    pub cancel_policy: CancelPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub execution_id: String,
+    pub status: MemberStatus,
+    pub duration_ms: Option<u64>,
+    /// Names the member across runs so a comparison can pair it with its baseline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_key: Option<String>,
+    /// Which cancel stopped the member
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancelled: Option<Cancellation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberTiming {
+    pub execution_id: String,
+    pub duration_ms: u64,
+}
+
+/// A cancel issued against the group itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupCancellation {
+    /// RFC 3339 timestamp
+    pub cancelled_at: String,
+    pub policy: CancelPolicy,
+    /// Running members the policy left to finish
+    pub spared: Vec<String>,
+}
+
+/// Progress of a group; also the body of the `on_settled` webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupSummary {
+    pub group_id: String,
+    pub policy: SettlementPolicy,
+    pub expected: Option<usize>,
+    pub settled: bool,
+    /// RFC 3339 timestamp
+    pub settled_at: Option<String>,
+    pub total: usize,
+    pub counts: BTreeMap<MemberStatus, usize>,
+    pub fastest: Option<MemberTiming>,
+    pub slowest: Option<MemberTiming>,
+    pub executions: Vec<GroupMember>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline_group_id: Option<String>,
+    #[serde(default)]
+    pub cancel_policy: CancelPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancellation: Option<GroupCancellation>,
+}
+
+/// Stdout kept per member for comparisons; longer output is cut here
+pub const MAX_RECORDED_STDOUT: usize = 256 * 1024;
+
+/// What a member exited with and printed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    /// Stdout was longer than [`MAX_RECORDED_STDOUT`]
+    pub truncated: bool,
+}
+
+/// A member as a comparison sees it
+#[derive(Debug, Clone)]
+pub struct GroupItem {
+    /// `item_key`, or `#<n>` for the n-th member to join
+    pub key: String,
+    pub execution_id: String,
+    /// `None` while running, or if the run never produced output
+    pub output: Option<MemberOutput>,
+}
+
+/// A group's members and outputs, taken at one point in time
+#[derive(Debug, Clone)]
+pub struct GroupItems {
+    pub settled: bool,
+    pub baseline_group_id: Option<String>,
+    pub normalizer: Option<String>,
+    pub items: Vec<GroupItem>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum GroupError {
+    #[error("execution group {0} not found")]
+    NotFound(String),
+    #[error("execution group {0} has already settled")]
+    Settled(String),
+    #[error("execution group {0} is full")]
+    Full(String),
+    #[error("execution group {0} was cancelled")]
+    Cancelled(String),
+}
+
+#[derive(Debug, Clone)]
+struct ExecutionGroup {
+    id: String,
+    policy: SettlementPolicy,
+    on_settled: Option<String>,
+    expected: Option<usize>,
+    members: Vec<GroupMember>,
+    settled_at: Option<String>,
+    baseline_group_id: Option<String>,
+    normalizer: Option<String>,
+    /// Keyed by execution id
+    outputs: HashMap<String, MemberOutput>,
+    cancel_policy: CancelPolicy,
+    cancellation: Option<GroupCancellation>,
+}
+
+impl ExecutionGroup {
+    fn count(&self, status: MemberStatus) -> usize {
+        self.members.iter().filter(|m| m.status == status).count()
+    }
+
+    fn should_settle(&self) -> bool {
+        // Nobody joins a cancelled group, so whoever has joined is everyone
+        if self.cancellation.is_some() {
+            return self.count(MemberStatus::Running) == 0;
+        }
+        let finished = self.members.len() - self.count(MemberStatus::Running);
+        let target = self.expected.unwrap_or(self.members.len());
+        let all_done = !self.members.is_empty() && finished >= target;
+        match self.policy {
+            SettlementPolicy::AllComplete => all_done,
+            SettlementPolicy::FirstFailure => all_done || self.count(MemberStatus::Failed) > 0,
+            SettlementPolicy::Quorum(n) => all_done || self.count(MemberStatus::Succeeded) >= n,
+        }
+    }
+
+    fn summary(&self) -> GroupSummary {
+        let mut counts = BTreeMap::new();
+        for member in &self.members {
+            *counts.entry(member.status).or_insert(0) += 1;
+        }
+        let timings = || {
+            self.members.iter().filter_map(|m| {
+                m.duration_ms.map(|duration_ms| MemberTiming {
+                    execution_id: m.execution_id.clone(),
+                    duration_ms,
+                })
+            })
+        };
+        GroupSummary {
+            group_id: self.id.clone(),
+            policy: self.policy,
+            expected: self.expected,
+            settled: self.settled_at.is_some(),
+            settled_at: self.settled_at.clone(),
+            total: self.members.len(),
+            counts,
+            fastest: timings().min_by_key(|t| t.duration_ms),
+            slowest: timings().max_by_key(|t| t.duration_ms),
+            executions: self.members.clone(),
+            baseline_group_id: self.baseline_group_id.clone(),
+            cancel_policy: self.cancel_policy,
+            cancellation: self.cancellation.clone(),
+        }
+    }
+
+    /// Settle the group if it is due, the first time only
+    fn settle(&mut self) -> Option<Settlement> {
+        if self.settled_at.is_some() || !self.should_settle() {
+            return None;
+        }
+        self.settled_at = Some(chrono::Utc::now().to_rfc3339());
+        info!("Execution group {} settled", self.id);
+        Some(Settlement {
+            on_settled: self.on_settled.clone(),
+            summary: self.summary(),
+        })
+    }
+
+    fn items(&self) -> GroupItems {
+        let items = self
+            .members
+            .iter()
+            .enumerate()
+            .map(|(index, member)| GroupItem {
+                key: member
+                    .item_key
+                    .clone()
+                    .unwrap_or_else(|| format!("#{index}")),
+                execution_id: member.execution_id.clone(),
+                output: self.outputs.get(&member.execution_id).cloned(),
+            })
+            .collect();
+        GroupItems {
+            settled: self.settled_at.is_some(),
+            baseline_group_id: self.baseline_group_id.clone(),
+            normalizer: self.normalizer.clone(),
+            items,
+        }
+    }
+}
+
+/// A group that just settled, with where to report it
+#[derive(Debug, Clone)]
+pub struct Settlement {
+    pub on_settled: Option<String>,
+    pub summary: GroupSummary,
+}
+
+/// Delivers settlement callbacks
+#[async_trait]
+pub trait WebhookSink: Send + Sync {
+    async fn deliver(&self, url: &str, summary: &GroupSummary);
+}
+
+/// POSTs the summary as JSON, retrying a few times on failure
+pub struct HttpWebhookSink {
+    client: reqwest::Client,
+    attempts: u32,
+}
+
+impl HttpWebhookSink {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            attempts: 3,
+        }
+    }
+}
+
+impl Default for HttpWebhookSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl WebhookSink for HttpWebhookSink {
+    async fn deliver(&self, url: &str, summary: &GroupSummary) {
+        for attempt in 1..=self.attempts {
+            match self.client.post(url).json(summary).send().await {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => warn!(
+                    "Group {} webhook to {} returned {} (attempt {})",
+                    summary.group_id,
+                    url,
+                    response.status(),
+                    attempt
+                ),
+                Err(e) => warn!(
+                    "Group {} webhook to {} failed: {} (attempt {})",
+                    summary.group_id, url, e, attempt
+                ),
+            }
+            tokio::time::sleep(Duration::from_millis(500 * u64::from(attempt))).await;
+        }
+    }
+}
+
+/// Group membership and settlement, shared by every handler
+pub struct GroupRegistry {
+    groups: DashMap<String, ExecutionGroup>,
+    /// Named normalizer presets for comparisons
+    normalizers: DashMap<String, Normalizer>,
+    sink: Arc<dyn WebhookSink>,
+}
+
+impl GroupRegistry {
+    pub fn new(sink: Arc<dyn WebhookSink>) -> Self {
+        Self {
+            groups: DashMap::new(),
+            normalizers: DashMap::new(),
+            sink,
+        }
+    }
+
+    pub fn create(&self, req: CreateGroupRequest) -> GroupSummary {
+        let group = ExecutionGroup {
+            id: Uuid::new_v4().to_string(),
+            policy: req.policy,
+            on_settled: req.on_settled,
+            expected: req.expected,
+            members: Vec::new(),
+            settled_at: None,
+            baseline_group_id: req.baseline_group_id,
+            normalizer: req.normalizer,
+            outputs: HashMap::new(),
+            cancel_policy: req.cancel_policy,
+            cancellation: None,
+        };
+        let summary = group.summary();
+        self.groups.insert(group.id.clone(), group);
+        summary
+    }
+
+    pub fn get(&self, group_id: &str) -> Option<GroupSummary> {
+        self.groups.get(group_id).map(|group| group.summary())
+    }
+
+    /// Register a running execution; settled and full groups accept no new members.
+    pub fn join(&self, group_id: &str, execution_id: &str) -> Result<(), GroupError> {
+        let mut group = self
+            .groups
+            .get_mut(group_id)
+            .ok_or_else(|| GroupError::NotFound(group_id.to_string()))?;
+        if group.cancellation.is_some() {
+            return Err(GroupError::Cancelled(group_id.to_string()));
+        }
+        if group.settled_at.is_some() {
+            return Err(GroupError::Settled(group_id.to_string()));
+        }
+        if group.expected.is_some_and(|n| group.members.len() >= n) {
+            return Err(GroupError::Full(group_id.to_string()));
+        }
+        group.members.push(GroupMember {
+            execution_id: execution_id.to_string(),
+            status: MemberStatus::Running,
+            duration_ms: None,
+            item_key: None,
+            cancelled: None,
+        });
+        Ok(())
+    }
+
+    /// Keep a member's exit code and stdout for comparisons.
+    ///
+    /// Call before [`finish`](Self::finish), so the output is in place by the time the
+    /// group settles. `item_key` pairs the member with the same item in a baseline run;
+    /// without one, members pair up by join order.
+    pub fn record_output(
+        &self,
+        group_id: &str,
+        execution_id: &str,
+        item_key: Option<&str>,
+        exit_code: i32,
+        stdout: &[u8],
+    ) {
+        let Some(mut group) = self.groups.get_mut(group_id) else {
+            return;
+        };
+        let Some(member) = group
+            .members
+            .iter_mut()
+            .find(|m| m.execution_id == execution_id)
+        else {
+            return;
+        };
+        if let Some(key) = item_key {
+            member.item_key = Some(key.to_string());
+        }
+        let truncated = stdout.len() > MAX_RECORDED_STDOUT;
+        let stdout = &stdout[..stdout.len().min(MAX_RECORDED_STDOUT)];
+        group.outputs.insert(
+            execution_id.to_string(),
+            MemberOutput {
+                exit_code,
+                stdout: String::from_utf8_lossy(stdout).into_owned(),
+                truncated,
+            },
+        );
+    }
+
+    pub fn items(&self, group_id: &str) -> Option<GroupItems> {
+        self.groups.get(group_id).map(|group| group.items())
+    }
+
+    /// Store a normalizer under `name`, replacing any preset already there
+    pub fn put_normalizer(&self, name: &str, normalizer: Normalizer) {
+        self.normalizers.insert(name.to_string(), normalizer);
+    }
+
+    pub fn normalizer(&self, name: &str) -> Option<Normalizer> {
+        self.normalizers.get(name).map(|preset| preset.clone())
+    }
+
+    pub fn normalizers(&self) -> BTreeMap<String, Normalizer> {
+        self.normalizers
+            .iter()
+            .map(|preset| (preset.key().clone(), preset.value().clone()))
+            .collect()
+    }
+
+    /// Record a member's outcome.
+    ///
+    /// Returns the settlement only for the call that settled the group, so each group is
+    /// reported exactly once. Members finishing after that are still recorded, except that
+    /// a cancelled member stays cancelled.
+    pub fn finish(
+        &self,
+        group_id: &str,
+        execution_id: &str,
+        succeeded: bool,
+        duration_ms: Option<u64>,
+    ) -> Option<Settlement> {
+        let mut group = self.groups.get_mut(group_id)?;
+        let member = group
+            .members
+            .iter_mut()
+            .find(|m| m.execution_id == execution_id)?;
+        if member.status == MemberStatus::Cancelled {
+            return None;
+        }
+        member.status = if succeeded {
+            MemberStatus::Succeeded
+        } else {
+            MemberStatus::Failed
+        };
+        member.duration_ms = duration_ms;
+        group.settle()
+    }
+
+    /// Record that a cancel stopped a member before it finished
+    pub fn cancel_member(
+        &self,
+        group_id: &str,
+        execution_id: &str,
+        cancellation: &Cancellation,
+    ) -> Option<Settlement> {
+        let mut group = self.groups.get_mut(group_id)?;
+        let member = group
+            .members
+            .iter_mut()
+            .find(|m| m.execution_id == execution_id)?;
+        if member.status != MemberStatus::Running {
+            return None;
+        }
+        member.status = MemberStatus::Cancelled;
+        member.cancelled = Some(cancellation.clone());
+        group.settle()
+    }
+
+    /// Apply a cancel issued against the group: the members `report` reached are
+    /// cancelled, and the group stops taking new ones.
+    ///
+    /// The settlement comes back if that left nobody running; otherwise the group settles
+    /// when the members the policy spared finish.
+    pub fn cancel(
+        &self,
+        group_id: &str,
+        report: &CancelReport,
+    ) -> Result<(GroupSummary, Option<Settlement>), GroupError> {
+        let mut group = self
+            .groups
+            .get_mut(group_id)
+            .ok_or_else(|| GroupError::NotFound(group_id.to_string()))?;
+        for member in group.members.iter_mut() {
+            let reached = report
+                .cancelled
+                .iter()
+                .find(|child| child.id == member.execution_id);
+            if let (Some(child), MemberStatus::Running) = (reached, member.status) {
+                member.status = MemberStatus::Cancelled;
+                member.cancelled = Some(child.cancellation.clone());
+            }
+        }
+        let spared = group
+            .members
+            .iter()
+            .filter(|m| m.status == MemberStatus::Running)
+            .map(|m| m.execution_id.clone())
+            .collect();
+        group.cancellation = Some(GroupCancellation {
+            cancelled_at: chrono::Utc::now().to_rfc3339(),
+            policy: report.policy,
+            spared,
+        });
+        info!(
+            target: "faas_audit",
+            group_id,
+            policy = ?report.policy,
+            cancelled = report.cancelled.len(),
+            "execution group cancelled"
+        );
+        let settlement = group.settle();
+        Ok((group.summary(), settlement))
+    }
+
+    /// Send the settlement webhook, if the group asked for one.
+    pub async fn notify(&self, settlement: Settlement) {
+        if let Some(url) = settlement.on_settled {
+            self.sink.deliver(&url, &settlement.summary).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<GroupSummary>>);
+
+    #[async_trait]
+    impl WebhookSink for Recorder {
+        async fn deliver(&self, _url: &str, summary: &GroupSummary) {
+            self.0.lock().unwrap().push(summary.clone());
+        }
+    }
+
+    fn registry() -> (Arc<Recorder>, GroupRegistry) {
+        let recorder = Arc::new(Recorder::default());
+        (recorder.clone(), GroupRegistry::new(recorder))
+    }
+
+    async fn finish(registry: &GroupRegistry, group: &str, id: &str, ok: bool, ms: u64) {
+        if let Some(settlement) = registry.finish(group, id, ok, Some(ms)) {
+            registry.notify(settlement).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn all_complete_fires_once_after_the_last_member() {
+        let (recorder, registry) = registry();
+        let group = registry.create(CreateGroupRequest {
+            policy: SettlementPolicy::AllComplete,
+            on_settled: Some("http://hooks.test/settled".into()),
+            expected: Some(3),
+            ..Default::default()
+        });
+        for id in ["a", "b", "c"] {
+            registry.join(&group.group_id, id).unwrap();
+        }
+
+        finish(&registry, &group.group_id, "a", true, 30).await;
+        finish(&registry, &group.group_id, "b", false, 10).await;
+        assert!(recorder.0.lock().unwrap().is_empty());
+        assert!(!registry.get(&group.group_id).unwrap().settled);
+
+        finish(&registry, &group.group_id, "c", true, 20).await;
+        let delivered = recorder.0.lock().unwrap().clone();
+        assert_eq!(delivered.len(), 1);
+        let summary = &delivered[0];
+        assert!(summary.settled);
+        assert_eq!(summary.counts[&MemberStatus::Succeeded], 2);
+        assert_eq!(summary.counts[&MemberStatus::Failed], 1);
+        assert_eq!(summary.fastest.as_ref().unwrap().execution_id, "b");
+        assert_eq!(summary.slowest.as_ref().unwrap().execution_id, "a");
+
+        assert_eq!(
+            registry.join(&group.group_id, "d"),
+            Err(GroupError::Settled(group.group_id.clone()))
+        );
+    }
+
+    #[tokio::test]
+    async fn first_failure_fires_early() {
+        let (recorder, registry) = registry();
+        let group = registry.create(CreateGroupRequest {
+            policy: SettlementPolicy::FirstFailure,
+            on_settled: Some("http://hooks.test/settled".into()),
+            expected: Some(3),
+            ..Default::default()
+        });
+        for id in ["a", "b", "c"] {
+            registry.join(&group.group_id, id).unwrap();
+        }
+
+        finish(&registry, &group.group_id, "a", true, 5).await;
+        finish(&registry, &group.group_id, "b", false, 7).await;
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
+        assert_eq!(
+            recorder.0.lock().unwrap()[0].counts[&MemberStatus::Running],
+            1
+        );
+
+        // The straggler is recorded but does not trigger a second callback.
+        finish(&registry, &group.group_id, "c", true, 9).await;
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
+        let progress = registry.get(&group.group_id).unwrap();
+        assert_eq!(progress.counts[&MemberStatus::Succeeded], 2);
+    }
+
+    #[tokio::test]
+    async fn only_pending_cancel_leaves_the_running_member_untouched() {
+        use crate::cancellation::CancelRegistry;
+
+        let (recorder, registry) = registry();
+        let cancels = Arc::new(CancelRegistry::new());
+        let group = registry.create(CreateGroupRequest {
+            on_settled: Some("http://hooks.test/settled".into()),
+            expected: Some(3),
+            cancel_policy: CancelPolicy::OnlyPending,
+            ..Default::default()
+        });
+        let group_id = group.group_id.as_str();
+        cancels.open(group_id);
+        let running = cancels.register("a", Some(group_id)).unwrap();
+        running.start().unwrap();
+        let _pending = cancels.register("b", Some(group_id)).unwrap();
+        for id in ["a", "b"] {
+            registry.join(group_id, id).unwrap();
+        }
+
+        let report = cancels.cancel(group_id, group.cancel_policy).unwrap();
+        let (summary, settlement) = registry.cancel(group_id, &report).unwrap();
+        assert!(settlement.is_none(), "the running member hasn't finished");
+        assert!(!running.is_cancelled());
+        assert_eq!(summary.executions[0].status, MemberStatus::Running);
+        let pending = &summary.executions[1];
+        assert_eq!(pending.status, MemberStatus::Cancelled);
+        let cancelled = pending.cancelled.as_ref().unwrap();
+        assert_eq!(cancelled.cancelled_by.as_deref(), Some(group_id));
+        assert_eq!(cancelled.depth, 1);
+        assert_eq!(
+            registry.join(group_id, "c"),
+            Err(GroupError::Cancelled(group_id.to_string()))
+        );
+
+        // The spared member finishing settles the group, short of the expected three
+        finish(&registry, group_id, "a", true, 40).await;
+        let delivered = recorder.0.lock().unwrap().clone();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].counts[&MemberStatus::Succeeded], 1);
+        assert_eq!(delivered[0].counts[&MemberStatus::Cancelled], 1);
+        let cancellation = delivered[0].cancellation.as_ref().unwrap();
+        assert_eq!(cancellation.policy, CancelPolicy::OnlyPending);
+        assert_eq!(cancellation.spared, ["a"]);
+    }
+
+    #[test]
+    fn quorum_and_wire_format() {
+        let (_, registry) = registry();
+        let group = registry.create(CreateGroupRequest {
+            policy: SettlementPolicy::Quorum(2),
+            ..Default::default()
+        });
+        for id in ["a", "b", "c"] {
+            registry.join(&group.group_id, id).unwrap();
+        }
+        assert!(registry.finish(&group.group_id, "a", true, None).is_none());
+        assert!(registry.finish(&group.group_id, "b", true, None).is_some());
+
+        let req: CreateGroupRequest =
+            serde_json::from_str(r#"{"policy": {"quorum": 2}, "expected": 3}"#).unwrap();
+        assert_eq!(req.policy, SettlementPolicy::Quorum(2));
+        assert_eq!(
+            serde_json::to_value(SettlementPolicy::FirstFailure).unwrap(),
+            "first_failure"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Probing the runtimes behind `/health`.
+//!
+//! The Docker daemon is asked for its version with a short timeout, reconnecting once if
+//! the connection was lost, and Firecracker needs
+//! both an accessible `/dev/kvm` and a `firecracker` binary that answers `--version`.
+//! Results are kept for [`PROBE_TTL`] so a load balancer polling every node doesn't turn
+//! into a stream of daemon requests.
+
+use faas_executor::DockerExecutor;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long one component may take to answer
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a probe's result is reused
+pub const PROBE_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Healthy,
+    Unavailable,
+}
+
+/// One runtime's probe result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentHealth {
+    pub status: ComponentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Why the component is unavailable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ComponentHealth {
+    fn healthy(version: Option<String>) -> Self {
+        Self {
+            status: ComponentStatus::Healthy,
+            version,
+            error: None,
+        }
+    }
+
+    fn unavailable(error: impl Into<String>) -> Self {
+        Self {
+            status: ComponentStatus::Unavailable,
+            version: None,
+            error: Some(error.into()),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.status == ComponentStatus::Healthy
+    }
+}
+
+/// What the host can run right now
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HostHealth {
+    pub docker: ComponentHealth,
+    pub firecracker: ComponentHealth,
+}
+
+impl HostHealth {
+    /// At least one runtime can take executions
+    pub fn can_execute(&self) -> bool {
+        self.docker.is_healthy() || self.firecracker.is_healthy()
+    }
+
+    pub fn all_healthy(&self) -> bool {
+        self.docker.is_healthy() && self.firecracker.is_healthy()
+    }
+}
+
+/// Probes the host's runtimes, reusing the last result for [`PROBE_TTL`]
+pub struct HealthProbe {
+    docker: DockerExecutor,
+    kvm_device: PathBuf,
+    firecracker_bin: PathBuf,
+    timeout: Duration,
+    ttl: Duration,
+    last: Mutex<Option<(Instant, HostHealth)>>,
+}
+
+impl HealthProbe {
+    pub fn new(docker: DockerExecutor) -> Self {
+        Self {
+            docker,
+            kvm_device: PathBuf::from("/dev/kvm"),
+            firecracker_bin: PathBuf::from("firecracker"),
+            timeout: PROBE_TIMEOUT,
+            ttl: PROBE_TTL,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Check `device` for KVM and run `bin` for Firecracker instead of the usual ones
+    pub fn with_firecracker(mut self, device: impl Into<PathBuf>, bin: impl Into<PathBuf>) -> Self {
+        self.kvm_device = device.into();
+        self.firecracker_bin = bin.into();
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The host's health, probing again once the last result is older than the TTL.
+    /// Callers arriving during a probe wait for it rather than starting their own.
+    pub async fn check(&self) -> HostHealth {
+        let mut last = self.last.lock().await;
+        if let Some((at, health)) = last.as_ref() {
+            if at.elapsed() < self.ttl {
+                return health.clone();
+            }
+        }
+        let (docker, firecracker) = tokio::join!(self.docker(), self.firecracker());
+        let health = HostHealth {
+            docker,
+            firecracker,
+        };
+        *last = Some((Instant::now(), health.clone()));
+        health
+    }
+
+    async fn docker(&self) -> ComponentHealth {
+        match tokio::time::timeout(self.timeout, self.docker.ping()).await {
+            Ok(Ok(version)) => ComponentHealth::healthy(version),
+            Ok(Err(e)) => ComponentHealth::unavailable(format!("daemon unreachable: {e}")),
+            Err(_) => ComponentHealth::unavailable(format!(
+                "daemon did not answer within {}ms",
+                self.timeout.as_millis()
+            )),
+        }
+    }
+
+    async fn firecracker(&self) -> ComponentHealth {
+        if !cfg!(target_os = "linux") {
+            return ComponentHealth::unavailable("Firecracker only runs on Linux");
+        }
+        let kvm = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.kvm_device);
+        if let Err(e) = kvm {
+            return ComponentHealth::unavailable(format!(
+                "{} is not accessible: {e}",
+                self.kvm_device.display()
+            ));
+        }
+        let run = tokio::process::Command::new(&self.firecracker_bin)
+            .arg("--version")
+            .kill_on_drop(true)
+            .output();
+        match tokio::time::timeout(self.timeout, run).await {
+            Ok(Ok(output)) if output.status.success() => {
+                let version = String::from_utf8_lossy(&output.stdout);
+                ComponentHealth::healthy(version.lines().next().map(|line| line.trim().to_string()))
+            }
+            Ok(Ok(output)) => ComponentHealth::unavailable(format!(
+                "{} --version exited with {}",
+                self.firecracker_bin.display(),
+                output.status
+            )),
+            Ok(Err(e)) => ComponentHealth::unavailable(format!(
+                "{} could not be run: {e}",
+                self.firecracker_bin.display()
+            )),
+            Err(_) => ComponentHealth::unavailable(format!(
+                "{} --version did not finish within {}ms",
+                self.firecracker_bin.display(),
+                self.timeout.as_millis()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+    use faas_executor::bollard::{Docker, API_DEFAULT_VERSION};
+    use faas_executor::docker_endpoints::{DockerAddress, DockerEndpointPool};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A client for `address` that reconnects to it and nowhere else
+    fn executor_at(address: String) -> DockerExecutor {
+        let docker = Docker::connect_with_http(&address, 1, API_DEFAULT_VERSION).unwrap();
+        DockerExecutor::with_endpoints(Arc::new(DockerEndpointPool::single_at(
+            DockerAddress::Http(address),
+            Arc::new(docker),
+        )))
+    }
+
+    /// A daemon that answers `/version`, counting the requests it gets
+    async fn daemon() -> (DockerExecutor, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        let app = Router::new().fallback(get(move || {
+            seen.fetch_add(1, Ordering::SeqCst);
+            async { Json(json!({ "Version": "27.3.1", "ApiVersion": "1.47" })) }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (executor_at(format!("http://{addr}")), requests)
+    }
+
+    /// Nothing listens here
+    fn down() -> DockerExecutor {
+        executor_at("http://127.0.0.1:1".to_string())
+    }
+
+    fn without_kvm(probe: HealthProbe) -> HealthProbe {
+        probe.with_firecracker("/nonexistent/kvm", "/nonexistent/firecracker")
+    }
+
+    #[tokio::test]
+    async fn a_reachable_daemon_reports_its_version() {
+        let (docker, _) = daemon().await;
+        let health = without_kvm(HealthProbe::new(docker)).check().await;
+        assert_eq!(
+            health.docker,
+            ComponentHealth::healthy(Some("27.3.1".into()))
+        );
+        assert!(health.can_execute());
+        assert!(!health.all_healthy());
+    }
+
+    #[tokio::test]
+    async fn a_daemon_that_is_down_is_unavailable() {
+        let health = without_kvm(HealthProbe::new(down())).check().await;
+        assert_eq!(health.docker.status, ComponentStatus::Unavailable);
+        assert!(health
+            .docker
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("unreachable"));
+        assert_eq!(health.firecracker.status, ComponentStatus::Unavailable);
+        assert!(!health.can_execute());
+    }
+
+    #[tokio::test]
+    async fn missing_kvm_is_named() {
+        let health = without_kvm(HealthProbe::new(down())).check().await;
+        let error = health.firecracker.error.unwrap();
+        if cfg!(target_os = "linux") {
+            assert!(
+                error.contains("/nonexistent/kvm is not accessible"),
+                "{error}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn results_are_reused_until_they_expire() {
+        let (docker, requests) = daemon().await;
+        let probe = without_kvm(HealthProbe::new(docker)).with_ttl(Duration::from_millis(50));
+        probe.check().await;
+        probe.check().await;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        probe.check().await;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
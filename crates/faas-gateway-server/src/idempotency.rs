@@ -0,0 +1,290 @@
+//! Answering retried executions without running them again.
+//!
+//! The first `POST /api/v1/execute` carrying an `idempotency_key` runs, and its answer is
+//! kept for `FAAS_IDEMPOTENCY_TTL_SECS` (a day). Later requests with the key get that answer
+//! back instead of a second run. One that arrives while the first is still running is
+//! answered 409 `IdempotencyKeyInFlight` with `Retry-After` rather than held open, since the
+//! gateway can't tell a retry from a client that gave up on the first. The first runs to
+//! its end even if its client disconnects, holding the key until then, so no retry starts
+//! the command again while part of it may have run. Only successful answers are kept: when
+//! the first request fails, the key is released and the next request with it runs.
+//!
+//! Keys are scoped to the tenant header. At most `FAAS_IDEMPOTENCY_MAX_KEYS` (10000)
+//! answers are kept, the oldest going first once that fills up.
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::errors::ApiError;
+use crate::InvokeResponse;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 3600);
+const DEFAULT_MAX_KEYS: usize = 10_000;
+const RETRY_AFTER_SECS: &str = "1";
+
+#[derive(Debug, Error)]
+pub enum IdempotencyError {
+    #[error("an execution with idempotency key {0:?} is still running; retry once it finishes")]
+    InFlight(String),
+}
+
+impl IntoResponse for IdempotencyError {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        let mut response =
+            ApiError::new(StatusCode::CONFLICT, "IdempotencyKeyInFlight", message).into_response();
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from_static(RETRY_AFTER_SECS),
+        );
+        response
+    }
+}
+
+type Key = (Option<String>, String);
+
+enum Entry {
+    Running,
+    Done {
+        response: Box<InvokeResponse>,
+        at: Instant,
+    },
+}
+
+pub struct IdempotencyCache {
+    ttl: Duration,
+    max_keys: usize,
+    entries: Mutex<HashMap<Key, Entry>>,
+}
+
+/// What to do with a request carrying a key
+pub enum Claim {
+    /// The key is new: run the execution and [`Pending::complete`] it
+    Run(Pending),
+    /// The answer the first request with the key got
+    Replay(Box<InvokeResponse>),
+}
+
+/// A key held by the request running under it; dropping it releases the key
+pub struct Pending {
+    cache: Arc<IdempotencyCache>,
+    key: Option<Key>,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration, max_keys: usize) -> Self {
+        Self {
+            ttl,
+            max_keys,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("FAAS_IDEMPOTENCY_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        let max_keys = std::env::var("FAAS_IDEMPOTENCY_MAX_KEYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_KEYS);
+        Self::new(ttl, max_keys)
+    }
+
+    pub fn claim(
+        self: &Arc<Self>,
+        tenant: Option<&str>,
+        key: &str,
+        now: Instant,
+    ) -> Result<Claim, IdempotencyError> {
+        let key = (tenant.map(str::to_string), key.to_string());
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some(Entry::Running) => return Err(IdempotencyError::InFlight(key.1)),
+            Some(Entry::Done { response, at }) if now.duration_since(*at) < self.ttl => {
+                return Ok(Claim::Replay(response.clone()))
+            }
+            _ => {}
+        }
+        if !entries.contains_key(&key) && entries.len() >= self.max_keys {
+            self.evict(&mut entries, now);
+        }
+        entries.insert(key.clone(), Entry::Running);
+        Ok(Claim::Run(Pending {
+            cache: self.clone(),
+            key: Some(key),
+        }))
+    }
+
+    /// Keys that are still held
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Make room by dropping expired answers, or the oldest one if none have expired
+    fn evict(&self, entries: &mut HashMap<Key, Entry>, now: Instant) {
+        entries.retain(|_, entry| match entry {
+            Entry::Running => true,
+            Entry::Done { at, .. } => now.duration_since(*at) < self.ttl,
+        });
+        if entries.len() < self.max_keys {
+            return;
+        }
+        let oldest = entries
+            .iter()
+            .filter_map(|(key, entry)| match entry {
+                Entry::Done { at, .. } => Some((key, *at)),
+                Entry::Running => None,
+            })
+            .min_by_key(|(_, at)| *at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            entries.remove(&oldest);
+        }
+    }
+}
+
+impl Pending {
+    /// Keep `response` as the answer to every later request with this key
+    pub fn complete(mut self, response: &InvokeResponse, now: Instant) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let done = Entry::Done {
+            response: Box::new(response.clone()),
+            at: now,
+        };
+        self.cache.entries.lock().unwrap().insert(key, done);
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.cache.entries.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(request_id: &str) -> InvokeResponse {
+        InvokeResponse {
+            request_id: request_id.to_string(),
+            exit_code: 0,
+            stdout: "charged\n".to_string(),
+            stderr: String::new(),
+            duration_ms: 12,
+            output: None,
+            logs: None,
+            error: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime: None,
+            runtime_reason: None,
+            diagnostics: None,
+            usage: None,
+            truncated: false,
+            total_bytes: None,
+            snapshot_id: None,
+        }
+    }
+
+    fn cache() -> Arc<IdempotencyCache> {
+        Arc::new(IdempotencyCache::new(Duration::from_secs(60), 2))
+    }
+
+    fn replayed(claim: Result<Claim, IdempotencyError>) -> String {
+        match claim {
+            Ok(Claim::Replay(response)) => response.request_id,
+            Ok(Claim::Run(_)) => panic!("expected a replay, the key ran again"),
+            Err(e) => panic!("expected a replay: {e}"),
+        }
+    }
+
+    #[test]
+    fn a_duplicate_is_refused_while_the_first_runs_and_replayed_after() {
+        let cache = cache();
+        let now = Instant::now();
+        let Ok(Claim::Run(first)) = cache.claim(None, "charge-42", now) else {
+            panic!("the first request should run");
+        };
+
+        let in_flight = cache.claim(None, "charge-42", now);
+        assert!(matches!(in_flight, Err(IdempotencyError::InFlight(_))));
+        let refused = in_flight.err().unwrap().into_response();
+        assert_eq!(refused.status(), StatusCode::CONFLICT);
+        assert!(refused.headers().contains_key(header::RETRY_AFTER));
+
+        first.complete(&response("exec-1"), now);
+        assert_eq!(replayed(cache.claim(None, "charge-42", now)), "exec-1");
+        assert_eq!(replayed(cache.claim(None, "charge-42", now)), "exec-1");
+    }
+
+    #[test]
+    fn a_request_that_never_answers_releases_its_key() {
+        let cache = cache();
+        let now = Instant::now();
+        let first = cache.claim(None, "charge-42", now).ok().unwrap();
+        drop(first);
+        assert!(cache.is_empty());
+        assert!(matches!(
+            cache.claim(None, "charge-42", now),
+            Ok(Claim::Run(_))
+        ));
+    }
+
+    #[test]
+    fn answers_expire_and_the_oldest_make_room() {
+        let cache = cache();
+        let start = Instant::now();
+        for (i, key) in ["a", "b"].into_iter().enumerate() {
+            let at = start + Duration::from_secs(i as u64);
+            let Ok(Claim::Run(pending)) = cache.claim(None, key, at) else {
+                panic!("{key} should run");
+            };
+            pending.complete(&response(key), at);
+        }
+
+        // Full: "a" is the oldest answer, so "c" takes its place
+        let later = start + Duration::from_secs(5);
+        let Ok(Claim::Run(c)) = cache.claim(None, "c", later) else {
+            panic!("c should run");
+        };
+        c.complete(&response("c"), later);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(replayed(cache.claim(None, "b", later)), "b");
+        assert!(matches!(cache.claim(None, "a", later), Ok(Claim::Run(_))));
+
+        let expired = later + Duration::from_secs(60);
+        assert!(matches!(cache.claim(None, "c", expired), Ok(Claim::Run(_))));
+    }
+
+    #[test]
+    fn tenants_do_not_share_keys() {
+        let cache = cache();
+        let now = Instant::now();
+        let Ok(Claim::Run(acme)) = cache.claim(Some("acme"), "job", now) else {
+            panic!("acme should run");
+        };
+        acme.complete(&response("acme-run"), now);
+        assert!(matches!(
+            cache.claim(Some("globex"), "job", now),
+            Ok(Claim::Run(_))
+        ));
+        assert_eq!(replayed(cache.claim(Some("acme"), "job", now)), "acme-run");
+    }
+}
@@ -0,0 +1,108 @@
+//! Files in and out of instance containers.
+//!
+//! `POST /api/v1/instances/:id/files?path=/dir` takes a tar stream and extracts it under
+//! `path`, `/` when unset; the directory must exist. `GET /api/v1/instances/:id/files?path=`
+//! answers with `path` packed as a tar the way `docker cp` packs it, so a file comes back
+//! as the only entry and a directory with everything under it.
+//!
+//! Ephemeral executions get files through `input_files` on the execute request instead,
+//! copied into the container before its command starts.
+
+use crate::errors::ApiError;
+use axum::{
+    body::Body,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+
+pub const TAR_CONTENT_TYPE: &str = "application/x-tar";
+
+#[derive(Debug, Deserialize)]
+pub struct FilesQuery {
+    #[serde(default = "root")]
+    pub path: String,
+}
+
+fn root() -> String {
+    "/".to_string()
+}
+
+/// 422 unless every input file names a file inside the sandbox
+pub fn check_input_files(
+    files: &[(String, Vec<u8>)],
+    working_dir: Option<&str>,
+) -> Result<(), ApiError> {
+    for (path, _) in files {
+        if faas_executor::input_files::resolve_path(path, working_dir).is_none() {
+            return Err(ApiError::invalid_request(format!(
+                "input file path {path:?} must name a file and can't contain `..`"
+            ))
+            .with_details(serde_json::json!({ "field": "input_files", "path": path })));
+        }
+    }
+    Ok(())
+}
+
+/// Stream a container archive back as a tar.
+///
+/// Docker reports a missing path on the first chunk, so that one is awaited here and a
+/// failure answered as an error instead of a cut-off 200.
+pub async fn archive_response<S>(archive: S) -> Response
+where
+    S: Stream<Item = anyhow::Result<Vec<u8>>> + Send + 'static,
+{
+    let mut archive = Box::pin(archive);
+    let first = match archive.next().await {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) => return ApiError::from_failure(e.as_ref()).into_response(),
+        None => Vec::new(),
+    };
+    let body = futures::stream::once(async { Ok(first) }).chain(archive);
+    (
+        [(header::CONTENT_TYPE, TAR_CONTENT_TYPE)],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    #[test]
+    fn input_files_outside_the_sandbox_are_invalid() {
+        let ok = [("data/in.txt".to_string(), b"x".to_vec())];
+        assert!(check_input_files(&ok, Some("/app")).is_ok());
+
+        let bad = [("../in.txt".to_string(), Vec::new())];
+        let error = check_input_files(&bad, Some("/app")).unwrap_err();
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.body.details,
+            Some(serde_json::json!({ "field": "input_files", "path": "../in.txt" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn a_missing_path_is_a_404_not_an_empty_archive() {
+        let missing = futures::stream::iter([Err(anyhow::Error::new(
+            faas_executor::bollard::errors::Error::DockerResponseServerError {
+                status_code: 404,
+                message: "Could not find the file /nope in container".to_string(),
+            },
+        ))]);
+        let response = archive_response(missing).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let found = futures::stream::iter([Ok(b"tar".to_vec()), Ok(b"ball".to_vec())]);
+        let response = archive_response(found).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"tarball");
+    }
+}
@@ -0,0 +1,346 @@
+//! Leases on instances that would otherwise outlive their client.
+//!
+//! A `persistent` execution is registered as an instance under its execution id while it
+//! runs, expiring `FAAS_PERSISTENT_TTL_SECS` (an hour) after it starts, or `ttl_secs` from
+//! the request. Nothing else ends its container once the client has gone away, so the
+//! reaper stops instances past their `expires_at` and removes what they ran in.
+//! `POST /api/v1/instances/:id/ttl` with `{"ttl_secs": n}` moves the expiry to `n` seconds
+//! from then, for any live instance.
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use faas_executor::platform::InstanceContainers;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::lifecycle::{InstanceState, Lifecycle, LifecycleError};
+use crate::Instance;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+const LIVE: [InstanceState; 4] = [
+    InstanceState::Creating,
+    InstanceState::Running,
+    InstanceState::Paused,
+    InstanceState::Suspended,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtlPolicy {
+    pub default_ttl: Duration,
+}
+
+impl Default for TtlPolicy {
+    fn default() -> Self {
+        Self {
+            default_ttl: DEFAULT_TTL,
+        }
+    }
+}
+
+impl TtlPolicy {
+    pub fn from_env() -> Self {
+        let default_ttl = std::env::var("FAAS_PERSISTENT_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        Self { default_ttl }
+    }
+
+    /// How long an instance asking for `ttl_secs` is kept
+    pub fn lease(&self, ttl_secs: Option<u64>) -> Duration {
+        ttl_secs.map_or(self.default_ttl, Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExtendTtl {
+    pub ttl_secs: u64,
+}
+
+/// The record of a persistent execution, running until `now + ttl`
+pub fn persistent_instance(
+    execution_id: &str,
+    image: &str,
+    cpu_cores: Option<u32>,
+    memory_mb: Option<u32>,
+    ttl: Duration,
+    now: DateTime<Utc>,
+) -> Instance {
+    let mut lifecycle = Lifecycle::new(InstanceState::Creating);
+    lifecycle
+        .transition(&format!("instance {execution_id}"), InstanceState::Running)
+        .expect("a new instance can start running");
+    Instance {
+        id: execution_id.to_string(),
+        name: None,
+        image: image.to_string(),
+        tenant: None,
+        lifecycle,
+        created_at: now.to_rfc3339(),
+        cpu_cores,
+        memory_mb,
+        container_id: None,
+        container: None,
+        expires_at: Some(expiry(now, ttl)),
+        workspace: None,
+        ports: Vec::new(),
+        endpoints: BTreeMap::new(),
+    }
+}
+
+/// Keep a live instance until `now + ttl`
+pub fn extend(
+    instances: &DashMap<String, Instance>,
+    id: &str,
+    ttl: Duration,
+    now: DateTime<Utc>,
+) -> Result<Instance, LifecycleError> {
+    let mut instance = instances.get_mut(id).ok_or(LifecycleError::NotFound)?;
+    instance
+        .lifecycle
+        .require(&format!("instance {id}"), &LIVE, InstanceState::Running)?;
+    instance.expires_at = Some(expiry(now, ttl));
+    Ok(instance.clone())
+}
+
+/// An instance the reaper stopped
+#[derive(Debug, Clone)]
+pub struct Reaped {
+    pub instance: Instance,
+    /// The state it was in
+    pub was: InstanceState,
+    /// The container it held, now removed
+    pub container_id: Option<String>,
+}
+
+/// Stop every live instance whose lease ran out by `now` and remove its containers
+pub async fn reap(
+    instances: &DashMap<String, Instance>,
+    containers: &InstanceContainers,
+    now: DateTime<Utc>,
+) -> Vec<Reaped> {
+    let mut expired = Vec::new();
+    for mut entry in instances.iter_mut() {
+        let ran_out = entry
+            .expires_at
+            .as_deref()
+            .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+            .is_some_and(|at| at <= now);
+        let was = entry.lifecycle.current();
+        if !ran_out || !LIVE.contains(&was) {
+            continue;
+        }
+        let entity = format!("instance {}", entry.key());
+        if entry
+            .lifecycle
+            .transition(&entity, InstanceState::Stopping)
+            .is_ok()
+        {
+            expired.push((entry.key().clone(), was, entry.container_id.take()));
+        }
+    }
+
+    let mut reaped = Vec::new();
+    for (id, was, container_id) in expired {
+        if let Err(e) = containers.remove_execution(&id).await {
+            warn!("Could not remove the containers of expired {}: {}", id, e);
+        }
+        if let Some(container_id) = &container_id {
+            if let Err(e) = containers.remove(container_id).await {
+                warn!(
+                    "Could not remove container {} of expired {}: {}",
+                    container_id, id, e
+                );
+            }
+            if let Err(e) = containers.remove_proxies(&id).await {
+                warn!("Could not remove the port proxies of expired {}: {}", id, e);
+            }
+        }
+        let Some(mut instance) = instances.get_mut(&id) else {
+            continue;
+        };
+        instance.container = None;
+        instance.ports.clear();
+        instance.endpoints.clear();
+        let entity = format!("instance {id}");
+        if instance
+            .lifecycle
+            .transition(&entity, InstanceState::Stopped)
+            .is_ok()
+        {
+            reaped.push(Reaped {
+                instance: instance.clone(),
+                was,
+                container_id,
+            });
+        }
+    }
+    reaped
+}
+
+/// A persistent execution ended on its own; its record stays until garbage collection
+pub fn finish(instances: &DashMap<String, Instance>, id: &str) {
+    let Some(mut instance) = instances.get_mut(id) else {
+        return;
+    };
+    let entity = format!("instance {id}");
+    let _ = instance
+        .lifecycle
+        .transition(&entity, InstanceState::Stopping)
+        .and_then(|()| {
+            instance
+                .lifecycle
+                .transition(&entity, InstanceState::Stopped)
+        });
+    instance.expires_at = None;
+}
+
+fn expiry(now: DateTime<Utc>, ttl: Duration) -> String {
+    let ttl = chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+    now.checked_add_signed(ttl)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+        .to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use faas_executor::bollard::container::ListContainersOptions;
+    use faas_executor::bollard::{Docker, API_DEFAULT_VERSION};
+    use std::sync::Arc;
+
+    fn ids(reaped: &[Reaped]) -> Vec<&str> {
+        reaped.iter().map(|r| r.instance.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn only_live_instances_past_their_lease_are_reaped() {
+        let now = Utc::now();
+        let instances = DashMap::new();
+        for (id, ttl) in [("short", 2), ("long", 60)] {
+            let instance = persistent_instance(
+                id,
+                "alpine:latest",
+                None,
+                None,
+                Duration::from_secs(ttl),
+                now,
+            );
+            instances.insert(id.to_string(), instance);
+        }
+        let mut unleased = persistent_instance("unleased", "alpine", None, None, DEFAULT_TTL, now);
+        unleased.expires_at = None;
+        instances.insert("unleased".to_string(), unleased);
+        // No daemon listens here: removal fails, which the reaper only warns about
+        let docker = Docker::connect_with_http("http://127.0.0.1:1", 1, API_DEFAULT_VERSION);
+        let containers = InstanceContainers::new(Arc::new(docker.unwrap()));
+
+        assert!(reap(&instances, &containers, now).await.is_empty());
+
+        let later = now + chrono::Duration::seconds(3);
+        let reaped = reap(&instances, &containers, later).await;
+        assert_eq!(ids(&reaped), ["short"]);
+        assert_eq!(reaped[0].was, InstanceState::Running);
+        assert_eq!(
+            instances.get("short").unwrap().lifecycle.current(),
+            InstanceState::Stopped
+        );
+        assert!(reap(&instances, &containers, later).await.is_empty());
+
+        extend(&instances, "long", Duration::from_secs(600), later).unwrap();
+        let much_later = now + chrono::Duration::seconds(120);
+        assert!(reap(&instances, &containers, much_later).await.is_empty());
+        assert!(matches!(
+            extend(&instances, "short", Duration::from_secs(60), later),
+            Err(LifecycleError::Transition(_))
+        ));
+        assert!(matches!(
+            extend(&instances, "missing", Duration::from_secs(60), later),
+            Err(LifecycleError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn a_finished_execution_is_stopped_without_a_lease() {
+        let instances = DashMap::new();
+        let instance = persistent_instance("done", "alpine", None, None, DEFAULT_TTL, Utc::now());
+        instances.insert("done".to_string(), instance);
+        finish(&instances, "done");
+        let done = instances.get("done").unwrap();
+        assert_eq!(done.lifecycle.current(), InstanceState::Stopped);
+        assert_eq!(done.expires_at, None);
+    }
+
+    #[tokio::test]
+    async fn an_abandoned_persistent_execution_is_removed_once_its_lease_ends() {
+        if !faas_executor::test_utils::has_docker() {
+            eprintln!("Skipping: Docker is not available");
+            return;
+        }
+        let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
+        let id = format!("ttl-{}", uuid::Uuid::new_v4());
+        let config = faas_common::SandboxConfig {
+            function_id: id.clone(),
+            source: "alpine:latest".to_string(),
+            command: vec!["sleep".to_string(), "30".to_string()],
+            execution_mode: Some(faas_common::ExecutionMode::Persistent),
+            timeout: Some(60_000),
+            ..Default::default()
+        };
+        let executor = faas_executor::DockerExecutor::new(docker.clone());
+        let execution = tokio::spawn(async move {
+            use faas_common::SandboxExecutor;
+            executor.execute(config).await
+        });
+
+        let instances = DashMap::new();
+        let started = Utc::now();
+        let instance = persistent_instance(
+            &id,
+            "alpine:latest",
+            None,
+            None,
+            Duration::from_secs(2),
+            started,
+        );
+        instances.insert(id.clone(), instance);
+        let prefix = format!("faas-{id}-");
+        let listing = || ListContainersOptions::<String> {
+            all: true,
+            filters: [("name".to_string(), vec![prefix.clone()])].into(),
+            ..Default::default()
+        };
+        let mut running = false;
+        for _ in 0..100 {
+            running = !docker
+                .list_containers(Some(listing()))
+                .await
+                .unwrap()
+                .is_empty();
+            if running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(running, "the execution never started a container");
+        // The client went away: nothing is left waiting on the container
+        execution.abort();
+
+        let containers = InstanceContainers::new(docker.clone());
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let reaped = reap(&instances, &containers, Utc::now()).await;
+        assert_eq!(ids(&reaped), [id.as_str()]);
+        assert!(docker
+            .list_containers(Some(listing()))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            instances.get(&id).unwrap().lifecycle.current(),
+            InstanceState::Stopped
+        );
+    }
+}
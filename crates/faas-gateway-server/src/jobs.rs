@@ -0,0 +1,661 @@
+//! Executions that outlive the request submitting them.
+//!
+//! `POST /api/v1/jobs` takes an execute request, answers `202` with a `queued` job and runs
+//! it in the background, at most `FAAS_JOB_MAX_RUNNING` (64) at once. A job is `running`
+//! once it has a slot, with its output so far in `logs` when it runs in a fresh Docker
+//! container, and ends `succeeded` (exit code 0), `failed` or `cancelled`, carrying the
+//! execution's response or the error it was refused with. `DELETE /api/v1/jobs/:id`
+//! cancels it and its execution, whose containers are removed. Finished jobs are kept for
+//! `FAAS_JOB_RETENTION_SECS` (a day).
+//!
+//! A job with a `callback_url` is POSTed there once it finishes, signed with
+//! `FAAS_JOB_CALLBACK_SECRET`: the `X-Faas-Signature` header reads `t=<unix seconds>,v1=<hex>`,
+//! the hex being the HMAC-SHA256 of `<t>.<body>`. Without a secret, callbacks are refused.
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use faas_executor::OutputChunk;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::cancellation::CancelScope;
+use crate::errors::{ApiError, ApiErrorResponse};
+use crate::InvokeResponse;
+
+pub const SIGNATURE_HEADER: &str = "x-faas-signature";
+
+const DEFAULT_MAX_RUNNING: usize = 64;
+const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+/// Output kept for a running job; older output is dropped first
+const MAX_LOG_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The latest output while it runs, and what ran of a job that ended without a
+    /// response; a response carries the whole output
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub logs: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<InvokeResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiErrorResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+}
+
+/// Delivers finished jobs to their `callback_url`
+#[async_trait]
+pub trait CallbackSink: Send + Sync {
+    async fn deliver(&self, url: &str, job: &Job);
+}
+
+/// POSTs the job as signed JSON, retrying a few times on failure
+pub struct HttpCallbackSink {
+    client: reqwest::Client,
+    secret: Vec<u8>,
+    attempts: u32,
+}
+
+impl HttpCallbackSink {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            secret: secret.into(),
+            attempts: 3,
+        }
+    }
+
+    /// Signs with `FAAS_JOB_CALLBACK_SECRET`; `None` when it isn't set
+    pub fn from_env() -> Option<Self> {
+        std::env::var("FAAS_JOB_CALLBACK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .map(Self::new)
+    }
+}
+
+#[async_trait]
+impl CallbackSink for HttpCallbackSink {
+    async fn deliver(&self, url: &str, job: &Job) {
+        let body = match serde_json::to_vec(job) {
+            Ok(body) => body,
+            Err(e) => return warn!("Job {} callback not serialized: {}", job.job_id, e),
+        };
+        for attempt in 1..=self.attempts {
+            let signature = signature(&self.secret, Utc::now().timestamp(), &body);
+            let sent = self
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, signature)
+                .body(body.clone())
+                .send()
+                .await;
+            match sent {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => warn!(
+                    "Job {} callback to {} returned {} (attempt {})",
+                    job.job_id,
+                    url,
+                    response.status(),
+                    attempt
+                ),
+                Err(e) => warn!(
+                    "Job {} callback to {} failed: {} (attempt {})",
+                    job.job_id, url, e, attempt
+                ),
+            }
+            tokio::time::sleep(Duration::from_millis(500 * u64::from(attempt))).await;
+        }
+    }
+}
+
+/// The `X-Faas-Signature` value for `body` sent at `timestamp`
+pub fn signature(secret: &[u8], timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!(
+        "t={timestamp},v1={}",
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+struct Entry {
+    job: Job,
+    tenant: Option<String>,
+    logs: Vec<u8>,
+    finished: Option<Instant>,
+}
+
+/// Every job the gateway knows, shared by every handler
+pub struct JobStore {
+    jobs: DashMap<String, Entry>,
+    slots: Arc<Semaphore>,
+    retention: Duration,
+    callbacks: Option<Arc<dyn CallbackSink>>,
+}
+
+impl JobStore {
+    pub fn new(max_running: usize, retention: Duration) -> Self {
+        Self {
+            jobs: DashMap::new(),
+            slots: Arc::new(Semaphore::new(max_running.max(1))),
+            retention,
+            callbacks: None,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let max_running = std::env::var("FAAS_JOB_MAX_RUNNING")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_RUNNING);
+        let retention = std::env::var("FAAS_JOB_RETENTION_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_RETENTION, Duration::from_secs);
+        let store = Self::new(max_running, retention);
+        match HttpCallbackSink::from_env() {
+            Some(sink) => store.with_callbacks(Arc::new(sink)),
+            None => store,
+        }
+    }
+
+    pub fn with_callbacks(mut self, callbacks: Arc<dyn CallbackSink>) -> Self {
+        self.callbacks = Some(callbacks);
+        self
+    }
+
+    /// Record a `queued` job `id` for `tenant`; a `callback_url` needs callbacks configured
+    pub fn submit(
+        &self,
+        id: String,
+        tenant: Option<&str>,
+        callback_url: Option<String>,
+    ) -> Result<Job, ApiError> {
+        if let Some(url) = &callback_url {
+            if self.callbacks.is_none() {
+                return Err(ApiError::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "CallbacksDisabled",
+                    "job callbacks need FAAS_JOB_CALLBACK_SECRET set on the gateway",
+                ));
+            }
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(
+                    ApiError::invalid_request("callback_url must be an http(s) URL")
+                        .with_details(serde_json::json!({ "field": "callback_url" })),
+                );
+            }
+        }
+        let job = Job {
+            job_id: id,
+            status: JobStatus::Queued,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            logs: String::new(),
+            response: None,
+            error: None,
+            callback_url,
+        };
+        self.jobs.insert(
+            job.job_id.clone(),
+            Entry {
+                job: job.clone(),
+                tenant: tenant.map(str::to_string),
+                logs: Vec::new(),
+                finished: None,
+            },
+        );
+        Ok(job)
+    }
+
+    /// The job as `tenant` may see it
+    pub fn get(&self, id: &str, tenant: Option<&str>) -> Option<Job> {
+        let entry = self.jobs.get(id)?;
+        (entry.tenant.as_deref() == tenant).then(|| {
+            let mut job = entry.job.clone();
+            if !job.status.is_finished() {
+                job.logs = String::from_utf8_lossy(&entry.logs).into_owned();
+            }
+            job
+        })
+    }
+
+    /// Run `execution` as job `id` once a slot is free, unless `scope`, the job's node for
+    /// cascade cancels, is cancelled first. `execution` registers under `scope` so that a
+    /// cancel reaches it; its output arrives on `output`.
+    pub fn spawn<F>(
+        self: &Arc<Self>,
+        id: String,
+        scope: CancelScope,
+        mut output: mpsc::UnboundedReceiver<OutputChunk>,
+        execution: F,
+    ) -> JoinHandle<Option<Job>>
+    where
+        F: Future<Output = Result<InvokeResponse, ApiError>> + Send + 'static,
+    {
+        let jobs = self.clone();
+        tokio::spawn(async move {
+            let slot = tokio::select! {
+                slot = jobs.slots.clone().acquire_owned() => slot.ok(),
+                _ = scope.cancelled() => None,
+            };
+            let Some(_slot) = slot else {
+                let cancelled = ApiError::new(
+                    StatusCode::CONFLICT,
+                    "Cancelled",
+                    format!("job {id} was cancelled before it started"),
+                );
+                return jobs.finish(&id, Err(cancelled), true).await;
+            };
+            jobs.update(&id, |entry| {
+                entry.job.status = JobStatus::Running;
+                entry.job.started_at = Some(Utc::now());
+            });
+
+            tokio::pin!(execution);
+            let outcome = loop {
+                tokio::select! {
+                    Some(chunk) = output.recv() => jobs.append_log(&id, chunk),
+                    outcome = &mut execution => break outcome,
+                }
+            };
+            while let Ok(chunk) = output.try_recv() {
+                jobs.append_log(&id, chunk);
+            }
+            jobs.finish(&id, outcome, scope.is_cancelled()).await
+        })
+    }
+
+    fn append_log(&self, id: &str, chunk: OutputChunk) {
+        let (OutputChunk::Stdout(data) | OutputChunk::Stderr(data)) = chunk;
+        self.update(id, |entry| {
+            entry.logs.extend_from_slice(&data);
+            let excess = entry.logs.len().saturating_sub(MAX_LOG_BYTES);
+            entry.logs.drain(..excess);
+        });
+    }
+
+    async fn finish(
+        &self,
+        id: &str,
+        outcome: Result<InvokeResponse, ApiError>,
+        cancelled: bool,
+    ) -> Option<Job> {
+        let job = self.update(id, |entry| {
+            let job = &mut entry.job;
+            job.status = match &outcome {
+                Ok(response) if response.exit_code == 0 => JobStatus::Succeeded,
+                _ if cancelled => JobStatus::Cancelled,
+                _ => JobStatus::Failed,
+            };
+            job.finished_at = Some(Utc::now());
+            match &outcome {
+                Ok(response) => job.response = Some(response.clone()),
+                Err(e) => job.error = Some(e.body.clone()),
+            }
+            // A response carries the whole output; without one, what ran so far is kept
+            let logs = std::mem::take(&mut entry.logs);
+            if job.response.is_none() {
+                job.logs = String::from_utf8_lossy(&logs).into_owned();
+            }
+            entry.finished = Some(Instant::now());
+            job.clone()
+        })?;
+        info!("Job {} finished {:?}", id, job.status);
+        if let (Some(url), Some(callbacks)) = (&job.callback_url, &self.callbacks) {
+            callbacks.deliver(url, &job).await;
+        }
+        Some(job)
+    }
+
+    fn update<T>(&self, id: &str, change: impl FnOnce(&mut Entry) -> T) -> Option<T> {
+        self.jobs.get_mut(id).map(|mut entry| change(&mut entry))
+    }
+
+    /// Drop jobs finished longer ago than the retention; returns how many went
+    pub fn prune(&self) -> usize {
+        let before = self.jobs.len();
+        self.jobs.retain(|_, entry| {
+            entry
+                .finished
+                .is_none_or(|finished| finished.elapsed() < self.retention)
+        });
+        before - self.jobs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancellation::{CancelPolicy, CancelRegistry};
+    use std::sync::Mutex;
+
+    fn response(exit_code: i32) -> InvokeResponse {
+        InvokeResponse {
+            request_id: "exec-1".to_string(),
+            exit_code,
+            stdout: "done\n".to_string(),
+            stderr: String::new(),
+            duration_ms: 1,
+            output: None,
+            logs: None,
+            error: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime: None,
+            runtime_reason: None,
+            diagnostics: None,
+            usage: None,
+            truncated: false,
+            total_bytes: None,
+            snapshot_id: None,
+        }
+    }
+
+    fn job_id() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(String, Job)>>);
+
+    #[async_trait]
+    impl CallbackSink for Recorder {
+        async fn deliver(&self, url: &str, job: &Job) {
+            self.0.lock().unwrap().push((url.to_string(), job.clone()));
+        }
+    }
+
+    /// Stands in for an execution registered under the job: it runs until a cancel reaches
+    /// it, then reports the cancel the way a stopped execution does
+    async fn sleep_300(
+        cancels: Arc<CancelRegistry>,
+        job_id: String,
+    ) -> Result<InvokeResponse, ApiError> {
+        let scope = cancels.register("exec-1", Some(&job_id)).unwrap();
+        scope.start().unwrap();
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(300)) => Ok(response(0)),
+            cancellation = scope.cancelled() => Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "Cancelled",
+                format!("cancelled from {}", cancellation.source),
+            )),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_job_runs_in_the_background_and_keeps_its_logs() {
+        let cancels = Arc::new(CancelRegistry::new());
+        let recorder = Arc::new(Recorder::default());
+        let jobs = Arc::new(JobStore::new(4, DEFAULT_RETENTION).with_callbacks(recorder.clone()));
+        let job = jobs
+            .submit(
+                "job-1".to_string(),
+                Some("acme"),
+                Some("https://hooks.example/done".to_string()),
+            )
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+
+        let scope = cancels.register(&job.job_id, None).unwrap();
+        let (output_tx, output) = mpsc::unbounded_channel();
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let handle = jobs.spawn(job.job_id.clone(), scope, output, async move {
+            output_tx
+                .send(OutputChunk::Stdout(b"step 1\n".to_vec()))
+                .unwrap();
+            released.await.unwrap();
+            Ok(response(0))
+        });
+
+        let running = loop {
+            let job = jobs.get(&job.job_id, Some("acme")).unwrap();
+            if !job.logs.is_empty() {
+                break job;
+            }
+            tokio::task::yield_now().await;
+        };
+        assert_eq!(running.status, JobStatus::Running);
+        assert_eq!(running.logs, "step 1\n");
+        assert!(jobs.get(&job.job_id, Some("other")).is_none());
+
+        release.send(()).unwrap();
+        let done = handle.await.unwrap().unwrap();
+        assert_eq!(done.status, JobStatus::Succeeded);
+        assert_eq!(done.response.unwrap().stdout, "done\n");
+        let delivered = recorder.0.lock().unwrap();
+        assert_eq!(delivered[0].0, "https://hooks.example/done");
+        assert_eq!(delivered[0].1.status, JobStatus::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_job_stops_its_execution() {
+        let cancels = Arc::new(CancelRegistry::new());
+        let jobs = Arc::new(JobStore::new(4, DEFAULT_RETENTION));
+        let job = jobs.submit(job_id(), None, None).unwrap();
+        let scope = cancels.register(&job.job_id, None).unwrap();
+        let (_output_tx, output) = mpsc::unbounded_channel();
+        let handle = jobs.spawn(
+            job.job_id.clone(),
+            scope,
+            output,
+            sleep_300(cancels.clone(), job.job_id.clone()),
+        );
+        while cancels.len() < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        let report = cancels.cancel(&job.job_id, CancelPolicy::All).unwrap();
+        assert_eq!(report.cancelled[0].id, "exec-1");
+        let done = tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("the job ends without waiting out the sleep")
+            .unwrap()
+            .unwrap();
+        assert_eq!(done.status, JobStatus::Cancelled);
+        assert_eq!(done.error.unwrap().code, "Cancelled");
+    }
+
+    #[tokio::test]
+    async fn a_queued_job_cancelled_never_runs() {
+        let cancels = Arc::new(CancelRegistry::new());
+        let jobs = Arc::new(JobStore::new(1, DEFAULT_RETENTION));
+        let busy = jobs.slots.clone().acquire_owned().await.unwrap();
+        let job = jobs.submit(job_id(), None, None).unwrap();
+        let scope = cancels.register(&job.job_id, None).unwrap();
+        let (_output_tx, output) = mpsc::unbounded_channel();
+        let handle = jobs.spawn(job.job_id.clone(), scope, output, async {
+            panic!("a cancelled job must not start")
+        });
+
+        cancels.cancel(&job.job_id, CancelPolicy::All).unwrap();
+        let done = handle.await.unwrap().unwrap();
+        assert_eq!(done.status, JobStatus::Cancelled);
+        assert!(done.started_at.is_none());
+        drop(busy);
+    }
+
+    #[tokio::test]
+    async fn failures_and_refusals_end_as_failed() {
+        let cancels = Arc::new(CancelRegistry::new());
+        let jobs = Arc::new(JobStore::new(4, DEFAULT_RETENTION));
+        for (id, outcome) in [
+            ("nonzero", Ok(response(1))),
+            (
+                "refused",
+                Err(ApiError::new(
+                    StatusCode::NOT_FOUND,
+                    "ImageNotFound",
+                    "no such image",
+                )),
+            ),
+        ] {
+            let job = jobs.submit(job_id(), None, None).unwrap();
+            let scope = cancels.register(&job.job_id, None).unwrap();
+            let (_output_tx, output) = mpsc::unbounded_channel();
+            let done = jobs
+                .spawn(job.job_id, scope, output, async move { outcome })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(done.status, JobStatus::Failed, "{id}");
+        }
+    }
+
+    #[test]
+    fn callbacks_need_a_secret_and_an_http_url() {
+        let jobs = JobStore::new(1, DEFAULT_RETENTION);
+        let refused = jobs
+            .submit(job_id(), None, Some("https://hooks.example".to_string()))
+            .unwrap_err();
+        assert_eq!(refused.body.code, "CallbacksDisabled");
+
+        let jobs = jobs.with_callbacks(Arc::new(Recorder::default()));
+        let refused = jobs
+            .submit(job_id(), None, Some("file:///etc/passwd".to_string()))
+            .unwrap_err();
+        assert_eq!(refused.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(jobs
+            .submit(job_id(), None, Some("https://hooks.example".to_string()))
+            .is_ok());
+    }
+
+    #[test]
+    fn signatures_are_hmac_sha256_of_timestamp_and_body() {
+        // echo -n '1700000000.{"job_id":"j"}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            signature(b"secret", 1_700_000_000, br#"{"job_id":"j"}"#),
+            "t=1700000000,v1=7df6c290758fd19f14e0d864ab7636851c59780a58e91c8dad1b4090580dd379"
+        );
+    }
+
+    #[test]
+    fn finished_jobs_are_pruned_after_the_retention() {
+        let jobs = JobStore::new(1, Duration::ZERO);
+        let running = jobs.submit(job_id(), None, None).unwrap();
+        let finished = jobs.submit(job_id(), None, None).unwrap();
+        jobs.update(&finished.job_id, |entry| {
+            entry.finished = Some(Instant::now())
+        });
+        assert_eq!(jobs.prune(), 1);
+        assert!(jobs.get(&running.job_id, None).is_some());
+        assert!(jobs.get(&finished.job_id, None).is_none());
+    }
+
+    /// A real `sleep 300` container, cancelled through the job it runs under
+    #[tokio::test]
+    async fn cancelling_a_job_removes_its_container() {
+        if !faas_executor::test_utils::has_docker() {
+            eprintln!("Test skipped: Docker not available");
+            return;
+        }
+        std::env::set_var("FAAS_DISABLE_PREWARM", "1");
+        let executor = Arc::new(
+            faas_executor::platform::Executor::new(Default::default())
+                .await
+                .unwrap(),
+        );
+        let cancels = Arc::new(CancelRegistry::new());
+        let jobs = Arc::new(JobStore::new(1, DEFAULT_RETENTION));
+        let job = jobs.submit(job_id(), None, None).unwrap();
+        let scope = cancels.register(&job.job_id, None).unwrap();
+        let execution_id = uuid::Uuid::new_v4().to_string();
+        let request = faas_executor::platform::Request {
+            id: execution_id.clone(),
+            code: "sleep 300".to_string(),
+            env: "alpine:latest".to_string(),
+            timeout: Duration::from_secs(600),
+            ..Default::default()
+        };
+        let (_output_tx, output) = mpsc::unbounded_channel();
+        let execution = {
+            let (executor, cancels, job_id) =
+                (executor.clone(), cancels.clone(), job.job_id.clone());
+            let execution_id = execution_id.clone();
+            async move {
+                let scope = cancels.register(&execution_id, Some(&job_id)).unwrap();
+                scope.start().unwrap();
+                tokio::select! {
+                    result = executor.run(request) => Ok(response(result.map_or(-1, |r| r.exit_code))),
+                    _ = scope.cancelled() => {
+                        executor.kill(&execution_id).await.unwrap();
+                        Err(ApiError::new(StatusCode::CONFLICT, "Cancelled", "cancelled"))
+                    }
+                }
+            }
+        };
+        let handle = jobs.spawn(job.job_id.clone(), scope, output, execution);
+
+        let started = Instant::now();
+        while containers(&execution_id).await == 0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(60),
+                "container never started"
+            );
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        cancels.cancel(&job.job_id, CancelPolicy::All).unwrap();
+        let done = tokio::time::timeout(Duration::from_secs(30), handle)
+            .await
+            .expect("cancelled well before the sleep ends")
+            .unwrap()
+            .unwrap();
+        assert_eq!(done.status, JobStatus::Cancelled);
+        assert_eq!(containers(&execution_id).await, 0);
+    }
+
+    async fn containers(execution_id: &str) -> usize {
+        use faas_executor::bollard::{container::ListContainersOptions, Docker};
+        let docker = Docker::connect_with_local_defaults().unwrap();
+        docker
+            .list_containers(Some(ListContainersOptions::<String> {
+                all: true,
+                filters: [("name".to_string(), vec![format!("faas-{execution_id}-")])].into(),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .len()
+    }
+}
@@ -0,0 +1,623 @@
+//! Kill switches: operator rules that stop classes of workloads during an incident.
+//!
+//! `POST /api/v1/admin/killswitch` installs a rule that matches on tenant, image glob,
+//! labels and runtime; an empty matcher matches everything. `reject-new` refuses matching
+//! executions at admission with `403 KillSwitchActive`, `cancel-running` cancels the
+//! matching executions already in flight (they end with `403 KillSwitchCancelled`), and
+//! `both` does both. Rules last until deleted or until their TTL runs out.
+//!
+//! Running executions register here for as long as they run, so activating a rule can find
+//! and cancel them. Activations, removals, rejections and cancellations all go to the
+//! `faas_audit` log target.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use faas_common::Runtime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+pub const KILL_SWITCH_HEADER: &str = "x-faas-killswitch";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KillAction {
+    RejectNew,
+    CancelRunning,
+    Both,
+}
+
+impl KillAction {
+    pub fn rejects_new(self) -> bool {
+        matches!(self, Self::RejectNew | Self::Both)
+    }
+
+    pub fn cancels_running(self) -> bool {
+        matches!(self, Self::CancelRunning | Self::Both)
+    }
+}
+
+/// Which workloads a rule applies to; every field that is set must match
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KillSwitchMatcher {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Glob over the image reference, e.g. `*/miner:*`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<Runtime>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KillSwitchRequest {
+    #[serde(default)]
+    pub matcher: KillSwitchMatcher,
+    pub action: KillAction,
+    /// Remove the rule after this long; it stays until deleted otherwise
+    pub ttl_secs: Option<u64>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KillSwitchRule {
+    pub id: String,
+    pub matcher: KillSwitchMatcher,
+    pub action: KillAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// New executions refused so far
+    pub rejected: u64,
+    /// Running executions cancelled so far
+    pub cancelled: u64,
+}
+
+/// A newly installed rule and what it stopped straight away
+#[derive(Debug, Clone, Serialize)]
+pub struct Activation {
+    #[serde(flatten)]
+    pub rule: KillSwitchRule,
+    pub cancelled_executions: usize,
+    pub stopped_instances: usize,
+}
+
+/// What a rule is matched against
+#[derive(Debug, Clone, Default)]
+pub struct Workload {
+    pub tenant: Option<String>,
+    pub image: String,
+    pub labels: BTreeMap<String, String>,
+    pub runtime: Option<Runtime>,
+}
+
+/// The rule that stopped a workload
+#[derive(Debug, Clone, Error)]
+#[error("stopped by kill switch {rule_id}")]
+pub struct KillSwitchHit {
+    pub rule_id: String,
+    pub reason: Option<String>,
+    /// Cancelled while running rather than refused at admission
+    pub cancelled: bool,
+}
+
+impl KillSwitchHit {
+    pub fn code(&self) -> &'static str {
+        if self.cancelled {
+            "KillSwitchCancelled"
+        } else {
+            "KillSwitchActive"
+        }
+    }
+}
+
+impl IntoResponse for KillSwitchHit {
+    fn into_response(self) -> Response {
+        let mut response = (
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({
+                "error": self.code(),
+                "message": self.to_string(),
+                "rule_id": self.rule_id,
+                "reason": self.reason,
+            })),
+        )
+            .into_response();
+        if let Ok(value) = HeaderValue::from_str(&self.rule_id) {
+            response.headers_mut().insert(KILL_SWITCH_HEADER, value);
+        }
+        response
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KillSwitchError {
+    #[error("invalid image glob {pattern}: {reason}")]
+    InvalidGlob { pattern: String, reason: String },
+    #[error("kill switch {0} not found")]
+    NotFound(String),
+}
+
+impl IntoResponse for KillSwitchError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidGlob { .. } => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (
+            status,
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+/// A rule with its glob compiled once, so admission only does the matching
+struct CompiledRule {
+    id: String,
+    matcher: KillSwitchMatcher,
+    image: Option<glob::Pattern>,
+    action: KillAction,
+    reason: Option<String>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    deadline: Option<Instant>,
+    rejected: AtomicU64,
+    cancelled: AtomicU64,
+}
+
+impl CompiledRule {
+    fn compile(req: KillSwitchRequest) -> Result<Self, KillSwitchError> {
+        let image = req
+            .matcher
+            .image
+            .as_deref()
+            .map(glob::Pattern::new)
+            .transpose()
+            .map_err(|e| KillSwitchError::InvalidGlob {
+                pattern: req.matcher.image.clone().unwrap_or_default(),
+                reason: e.to_string(),
+            })?;
+        let ttl = req.ttl_secs.map(Duration::from_secs);
+        let created_at = Utc::now();
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            image,
+            action: req.action,
+            reason: req.reason,
+            created_at,
+            expires_at: ttl
+                .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+                .map(|ttl| created_at + ttl),
+            deadline: ttl.map(|ttl| Instant::now() + ttl),
+            matcher: req.matcher,
+            rejected: AtomicU64::new(0),
+            cancelled: AtomicU64::new(0),
+        })
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+
+    fn matches(&self, workload: &Workload) -> bool {
+        if let Some(tenant) = &self.matcher.tenant {
+            if workload.tenant.as_ref() != Some(tenant) {
+                return false;
+            }
+        }
+        if let Some(image) = &self.image {
+            if !image.matches(&workload.image) {
+                return false;
+            }
+        }
+        if let Some(runtime) = self.matcher.runtime {
+            if workload.runtime != Some(runtime) {
+                return false;
+            }
+        }
+        self.matcher
+            .labels
+            .iter()
+            .all(|(key, value)| workload.labels.get(key) == Some(value))
+    }
+
+    fn hit(&self, cancelled: bool) -> KillSwitchHit {
+        KillSwitchHit {
+            rule_id: self.id.clone(),
+            reason: self.reason.clone(),
+            cancelled,
+        }
+    }
+
+    fn snapshot(&self) -> KillSwitchRule {
+        KillSwitchRule {
+            id: self.id.clone(),
+            matcher: self.matcher.clone(),
+            action: self.action,
+            reason: self.reason.clone(),
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Running {
+    workload: Workload,
+    token: CancellationToken,
+    hit: OnceLock<KillSwitchHit>,
+}
+
+#[derive(Default)]
+pub struct KillSwitch {
+    /// Replaced wholesale on change so admission clones one `Arc` instead of holding a lock
+    rules: RwLock<Arc<Vec<Arc<CompiledRule>>>>,
+    running: DashMap<String, Arc<Running>>,
+}
+
+impl KillSwitch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn rules(&self) -> Arc<Vec<Arc<CompiledRule>>> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// First live reject rule matching `workload`, counted and audited
+    fn rejecting(&self, execution_id: &str, workload: &Workload) -> Option<KillSwitchHit> {
+        let rules = self.rules();
+        if rules.is_empty() {
+            return None;
+        }
+        let now = Instant::now();
+        let rule = rules.iter().find(|rule| {
+            rule.action.rejects_new() && !rule.expired(now) && rule.matches(workload)
+        })?;
+        rule.rejected.fetch_add(1, Ordering::Relaxed);
+        info!(
+            target: "faas_audit",
+            rule_id = %rule.id,
+            execution_id,
+            tenant = workload.tenant.as_deref().unwrap_or("-"),
+            image = %workload.image,
+            "kill switch rejected execution"
+        );
+        Some(rule.hit(false))
+    }
+
+    /// Refuse `workload` if a rule rejects it, without tracking it as running
+    pub fn check(&self, execution_id: &str, workload: &Workload) -> Result<(), KillSwitchHit> {
+        match self.rejecting(execution_id, workload) {
+            Some(hit) => Err(hit),
+            None => Ok(()),
+        }
+    }
+
+    /// Admit an execution and track it until the guard drops.
+    ///
+    /// It is registered before the rules are checked, so a rule activated concurrently
+    /// either rejects it here or finds it in its sweep.
+    pub fn admit(
+        self: &Arc<Self>,
+        execution_id: &str,
+        workload: Workload,
+    ) -> Result<RunGuard, KillSwitchHit> {
+        let running = Arc::new(Running {
+            workload,
+            token: CancellationToken::new(),
+            hit: OnceLock::new(),
+        });
+        self.running
+            .insert(execution_id.to_string(), running.clone());
+        let guard = RunGuard {
+            switch: self.clone(),
+            execution_id: execution_id.to_string(),
+            running,
+        };
+        match self.rejecting(execution_id, &guard.running.workload) {
+            Some(hit) => Err(hit),
+            None => Ok(guard),
+        }
+    }
+
+    /// Install a rule, cancelling the running executions it matches if it says to
+    pub fn activate(&self, req: KillSwitchRequest) -> Result<Activation, KillSwitchError> {
+        let rule = Arc::new(CompiledRule::compile(req)?);
+        {
+            let mut rules = self.rules.write().unwrap();
+            let mut next = Vec::clone(&rules);
+            next.push(rule.clone());
+            *rules = Arc::new(next);
+        }
+        info!(
+            target: "faas_audit",
+            rule_id = %rule.id,
+            action = ?rule.action,
+            matcher = ?rule.matcher,
+            reason = rule.reason.as_deref().unwrap_or("-"),
+            "kill switch activated"
+        );
+        let cancelled_executions = if rule.action.cancels_running() {
+            self.cancel_matching(&rule)
+        } else {
+            0
+        };
+        Ok(Activation {
+            rule: rule.snapshot(),
+            cancelled_executions,
+            stopped_instances: 0,
+        })
+    }
+
+    fn cancel_matching(&self, rule: &CompiledRule) -> usize {
+        let mut cancelled = 0;
+        for entry in self.running.iter() {
+            let running = entry.value();
+            if running.token.is_cancelled() || !rule.matches(&running.workload) {
+                continue;
+            }
+            let _ = running.hit.set(rule.hit(true));
+            running.token.cancel();
+            cancelled += 1;
+            info!(
+                target: "faas_audit",
+                rule_id = %rule.id,
+                execution_id = %entry.key(),
+                tenant = running.workload.tenant.as_deref().unwrap_or("-"),
+                image = %running.workload.image,
+                "kill switch cancelled execution"
+            );
+        }
+        rule.cancelled
+            .fetch_add(cancelled as u64, Ordering::Relaxed);
+        cancelled
+    }
+
+    /// Live rule `id` matches `workload`, for callers tearing down state the switch doesn't
+    /// track itself
+    pub fn rule_matches(&self, id: &str, workload: &Workload) -> bool {
+        self.rules()
+            .iter()
+            .any(|rule| rule.id == id && rule.matches(workload))
+    }
+
+    /// Live rules, oldest first
+    pub fn list(&self) -> Vec<KillSwitchRule> {
+        let now = Instant::now();
+        self.rules()
+            .iter()
+            .filter(|rule| !rule.expired(now))
+            .map(|rule| rule.snapshot())
+            .collect()
+    }
+
+    pub fn remove(&self, id: &str) -> Result<KillSwitchRule, KillSwitchError> {
+        let removed = {
+            let mut rules = self.rules.write().unwrap();
+            let Some(position) = rules.iter().position(|rule| rule.id == id) else {
+                return Err(KillSwitchError::NotFound(id.to_string()));
+            };
+            let mut next = Vec::clone(&rules);
+            let removed = next.remove(position);
+            *rules = Arc::new(next);
+            removed
+        };
+        info!(target: "faas_audit", rule_id = id, "kill switch removed");
+        Ok(removed.snapshot())
+    }
+
+    /// Drop rules whose TTL has run out; returns how many went
+    pub fn prune(&self) -> usize {
+        let now = Instant::now();
+        if !self.rules().iter().any(|rule| rule.expired(now)) {
+            return 0;
+        }
+        let mut rules = self.rules.write().unwrap();
+        let (expired, live): (Vec<_>, Vec<_>) =
+            rules.iter().cloned().partition(|rule| rule.expired(now));
+        *rules = Arc::new(live);
+        for rule in &expired {
+            info!(target: "faas_audit", rule_id = %rule.id, "kill switch expired");
+        }
+        expired.len()
+    }
+
+    /// Executions currently tracked
+    pub fn running(&self) -> usize {
+        self.running.len()
+    }
+}
+
+/// A tracked execution; dropping it stops the tracking
+pub struct RunGuard {
+    switch: Arc<KillSwitch>,
+    execution_id: String,
+    running: Arc<Running>,
+}
+
+impl RunGuard {
+    /// Resolves with the rule once a kill switch cancels this execution
+    pub async fn cancelled(&self) -> KillSwitchHit {
+        self.running.token.cancelled().await;
+        self.running
+            .hit
+            .get()
+            .cloned()
+            .expect("cancelled without a rule")
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        self.switch.running.remove(&self.execution_id);
+    }
+}
+
+pub async fn list_kill_switches_handler(
+    State(switch): State<Arc<KillSwitch>>,
+) -> Json<Vec<KillSwitchRule>> {
+    Json(switch.list())
+}
+
+pub async fn delete_kill_switch_handler(
+    State(switch): State<Arc<KillSwitch>>,
+    Path(id): Path<String>,
+) -> Result<Json<KillSwitchRule>, KillSwitchError> {
+    switch.remove(&id).map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workload(image: &str) -> Workload {
+        Workload {
+            image: image.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn rule(image: &str, action: KillAction) -> KillSwitchRequest {
+        KillSwitchRequest {
+            matcher: KillSwitchMatcher {
+                image: Some(image.to_string()),
+                ..Default::default()
+            },
+            action,
+            ttl_secs: None,
+            reason: Some("incident".to_string()),
+        }
+    }
+
+    #[test]
+    fn rejects_new_executions_matching_the_image_glob() {
+        let switch = Arc::new(KillSwitch::new());
+        let activation = switch
+            .activate(rule("*/miner:*", KillAction::RejectNew))
+            .unwrap();
+
+        let hit = switch
+            .admit("exec-1", workload("docker.io/miner:latest"))
+            .err()
+            .unwrap();
+        assert_eq!(hit.rule_id, activation.rule.id);
+        assert_eq!(hit.code(), "KillSwitchActive");
+        assert_eq!(switch.running(), 0, "rejected executions aren't tracked");
+        let response = hit.into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers()[KILL_SWITCH_HEADER],
+            activation.rule.id.as_str()
+        );
+
+        let allowed = switch.admit("exec-2", workload("alpine:latest")).unwrap();
+        assert_eq!(switch.running(), 1);
+        drop(allowed);
+        assert_eq!(switch.running(), 0);
+        assert_eq!(switch.list()[0].rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn activation_cancels_matching_running_executions() {
+        let switch = Arc::new(KillSwitch::new());
+        let doomed = switch
+            .admit("exec-1", workload("docker.io/miner:latest"))
+            .unwrap();
+        let spared = switch.admit("exec-2", workload("alpine:latest")).unwrap();
+
+        let activation = switch
+            .activate(rule("*/miner:*", KillAction::CancelRunning))
+            .unwrap();
+        assert_eq!(activation.cancelled_executions, 1);
+
+        let hit = tokio::time::timeout(Duration::from_secs(1), doomed.cancelled())
+            .await
+            .expect("running execution was not cancelled");
+        assert_eq!(hit.code(), "KillSwitchCancelled");
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), spared.cancelled())
+                .await
+                .is_err()
+        );
+        assert!(
+            switch
+                .admit("exec-3", workload("docker.io/miner:latest"))
+                .is_ok(),
+            "cancel-running alone doesn't refuse new work"
+        );
+    }
+
+    #[test]
+    fn every_set_field_must_match() {
+        let switch = Arc::new(KillSwitch::new());
+        switch
+            .activate(KillSwitchRequest {
+                matcher: KillSwitchMatcher {
+                    tenant: Some("acme".to_string()),
+                    labels: [("tier".to_string(), "batch".to_string())].into(),
+                    runtime: Some(Runtime::Docker),
+                    ..Default::default()
+                },
+                action: KillAction::Both,
+                ttl_secs: None,
+                reason: None,
+            })
+            .unwrap();
+
+        let mut batch = Workload {
+            tenant: Some("acme".to_string()),
+            image: "alpine:latest".to_string(),
+            labels: [("tier".to_string(), "batch".to_string())].into(),
+            runtime: Some(Runtime::Docker),
+        };
+        assert!(switch.check("exec-1", &batch).is_err());
+        batch.tenant = Some("other".to_string());
+        assert!(switch.check("exec-2", &batch).is_ok());
+        batch.tenant = Some("acme".to_string());
+        batch.labels.clear();
+        assert!(switch.check("exec-3", &batch).is_ok());
+    }
+
+    #[test]
+    fn expired_rules_stop_matching_and_are_pruned() {
+        let switch = KillSwitch::new();
+        let mut expiring = rule("*", KillAction::RejectNew);
+        expiring.ttl_secs = Some(0);
+        switch.activate(expiring).unwrap();
+
+        assert!(switch.check("exec-1", &workload("alpine:latest")).is_ok());
+        assert!(switch.list().is_empty());
+        assert_eq!(switch.prune(), 1);
+    }
+
+    #[test]
+    fn invalid_globs_and_unknown_rules_are_reported() {
+        let switch = KillSwitch::new();
+        assert!(matches!(
+            switch.activate(rule("[", KillAction::Both)),
+            Err(KillSwitchError::InvalidGlob { .. })
+        ));
+        assert!(matches!(
+            switch.remove("missing"),
+            Err(KillSwitchError::NotFound(_))
+        ));
+    }
+}
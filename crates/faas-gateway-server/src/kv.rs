@@ -0,0 +1,764 @@
+//! Scoped key-value store for small bits of shared state.
+//!
+//! Workflow steps and sibling executions coordinate through the gateway instead of a
+//! Redis of their own. Each execution gets `FAAS_KV_ENDPOINT`, the URL of its namespace,
+//! and `FAAS_KV_TOKEN`, a bearer token that is valid only for that namespace and only while
+//! the execution runs:
+//!
+//! ```sh
+//! curl -s -H "Authorization: Bearer $FAAS_KV_TOKEN" \
+//!     -X PUT "$FAAS_KV_ENDPOINT/winner" -d '{"value": "b", "expected_version": 0}'
+//! ```
+//!
+//! Members of a group share `group-<id>`, workflow steps share `workflow-<name>`, and other
+//! executions use their tenant's `default`. Clients reach the same namespaces through
+//! `GET/PUT /api/v1/kv/:namespace/:key` with their tenant header, to seed inputs and read
+//! results. Namespaces are per tenant; the same name under two tenants is two namespaces.
+//!
+//! The gateway has no state journal yet, so persistence is opt-in: with `FAAS_KV_DIR` set,
+//! a namespace is rewritten to `<dir>/<sha256>.json` whenever it changes and reloaded on
+//! startup. Without it the store lives in memory.
+
+use crate::auth::Authorized;
+use crate::snapshot_fs::request_tenant;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use faas_common::hash::sha256_hex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub const DEFAULT_MAX_VALUE_BYTES: usize = 4 * 1024;
+pub const DEFAULT_MAX_KEYS: usize = 1024;
+pub const DEFAULT_MAX_NAMESPACE_BYTES: usize = 1024 * 1024;
+/// How executions reach the gateway: the docker0 bridge address on a default Linux host
+pub const DEFAULT_KV_URL: &str = "http://172.17.0.1:8080";
+pub const DEFAULT_NAMESPACE: &str = "default";
+const MAX_NAME_LEN: usize = 256;
+
+/// Limits per namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KvQuota {
+    /// Serialized size of one value
+    pub max_value_bytes: usize,
+    pub max_keys: usize,
+    /// Keys plus serialized values across the namespace
+    pub max_bytes: usize,
+}
+
+impl Default for KvQuota {
+    fn default() -> Self {
+        Self {
+            max_value_bytes: DEFAULT_MAX_VALUE_BYTES,
+            max_keys: DEFAULT_MAX_KEYS,
+            max_bytes: DEFAULT_MAX_NAMESPACE_BYTES,
+        }
+    }
+}
+
+impl KvQuota {
+    /// `FAAS_KV_MAX_VALUE_BYTES`, `FAAS_KV_MAX_KEYS` and `FAAS_KV_MAX_NAMESPACE_BYTES`,
+    /// each falling back to its default
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_value_bytes: var("FAAS_KV_MAX_VALUE_BYTES", DEFAULT_MAX_VALUE_BYTES),
+            max_keys: var("FAAS_KV_MAX_KEYS", DEFAULT_MAX_KEYS),
+            max_bytes: var("FAAS_KV_MAX_NAMESPACE_BYTES", DEFAULT_MAX_NAMESPACE_BYTES),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KvEntry {
+    pub key: String,
+    pub value: Value,
+    /// Starts at 1 and goes up by one on every write
+    pub version: u64,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl KvEntry {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    fn size(&self) -> usize {
+        self.key.len() + self.value.to_string().len()
+    }
+}
+
+/// Body of `PUT /api/v1/kv/:namespace/:key`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KvPut {
+    pub value: Value,
+    /// The key disappears this long after the write
+    pub ttl_secs: Option<u64>,
+    /// Compare-and-swap: write only if the key is at this version, `0` meaning absent
+    pub expected_version: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListQuery {
+    #[serde(default)]
+    pub prefix: String,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum KvError {
+    #[error("key {key} not found in kv namespace {namespace}")]
+    NotFound { namespace: String, key: String },
+    #[error("compare-and-swap on {key} failed: expected version {expected}, found {current}")]
+    VersionMismatch {
+        key: String,
+        expected: u64,
+        current: u64,
+    },
+    #[error("value is {size} bytes; kv values are limited to {max}")]
+    ValueTooLarge { size: usize, max: usize },
+    #[error("kv namespace {namespace} already holds its limit of {max} keys")]
+    TooManyKeys { namespace: String, max: usize },
+    #[error("kv namespace {namespace} would grow to {size} bytes, over its limit of {max}")]
+    NamespaceFull {
+        namespace: String,
+        size: usize,
+        max: usize,
+    },
+    #[error("invalid kv name {0:?}: use 1-256 letters, digits, '.', '_' or '-'")]
+    InvalidName(String),
+    #[error("unknown or expired kv token")]
+    Unauthorized,
+    #[error("this kv token is scoped to namespace {0}")]
+    WrongNamespace(String),
+}
+
+impl KvError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "KvNotFound",
+            Self::VersionMismatch { .. } => "KvVersionMismatch",
+            Self::ValueTooLarge { .. } => "KvValueTooLarge",
+            Self::TooManyKeys { .. } | Self::NamespaceFull { .. } => "KvQuotaExceeded",
+            Self::InvalidName(_) => "KvInvalidName",
+            Self::Unauthorized => "KvUnauthorized",
+            Self::WrongNamespace(_) => "KvWrongNamespace",
+        }
+    }
+}
+
+impl IntoResponse for KvError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::NotFound { .. } => StatusCode::NOT_FOUND,
+            Self::VersionMismatch { .. } => StatusCode::CONFLICT,
+            Self::ValueTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::TooManyKeys { .. } | Self::NamespaceFull { .. } => {
+                StatusCode::INSUFFICIENT_STORAGE
+            }
+            Self::InvalidName(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::WrongNamespace(_) => StatusCode::FORBIDDEN,
+        };
+        let mut body = serde_json::json!({ "error": self.to_string(), "code": self.code() });
+        if let Self::VersionMismatch { current, .. } = self {
+            body["current_version"] = current.into();
+        }
+        (status, Json(body)).into_response()
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), KvError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(KvError::InvalidName(name.to_string()))
+    }
+}
+
+/// Namespace for a group or workflow, with characters names can't hold replaced
+pub fn scoped_namespace(kind: &str, id: &str) -> String {
+    let id: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let mut namespace = format!("{kind}-{id}");
+    namespace.truncate(MAX_NAME_LEN);
+    namespace
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Namespace {
+    tenant: Option<String>,
+    name: String,
+    entries: BTreeMap<String, KvEntry>,
+}
+
+impl Namespace {
+    fn bytes(&self) -> usize {
+        self.entries.values().map(KvEntry::size).sum()
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| !entry.expired(now));
+        before - self.entries.len()
+    }
+}
+
+/// What an execution's token lets it touch
+#[derive(Debug, Clone)]
+struct Grant {
+    tenant: Option<String>,
+    namespace: String,
+}
+
+/// Gateway-side store behind `FAAS_KV_ENDPOINT`
+pub struct KvStore {
+    /// Keyed by tenant and namespace name
+    namespaces: DashMap<(Option<String>, String), Namespace>,
+    /// Bearer tokens of running executions
+    grants: DashMap<String, Grant>,
+    quota: KvQuota,
+    /// Where executions reach the gateway
+    url: String,
+    dir: Option<PathBuf>,
+}
+
+impl KvStore {
+    /// In-memory store
+    pub fn new(quota: KvQuota, url: impl Into<String>) -> Self {
+        Self {
+            namespaces: DashMap::new(),
+            grants: DashMap::new(),
+            quota,
+            url: url.into(),
+            dir: None,
+        }
+    }
+
+    /// Store persisted under `dir`, starting with what is already there
+    pub fn with_dir(mut self, dir: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        for file in std::fs::read_dir(&dir)? {
+            let path = file?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            match std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| {
+                    serde_json::from_slice::<Namespace>(&data).map_err(|e| e.to_string())
+                }) {
+                Ok(namespace) => {
+                    self.namespaces.insert(
+                        (namespace.tenant.clone(), namespace.name.clone()),
+                        namespace,
+                    );
+                }
+                Err(e) => warn!("Skipping unreadable kv namespace {:?}: {}", path, e),
+            }
+        }
+        info!(
+            "Loaded {} kv namespaces from {:?}",
+            self.namespaces.len(),
+            dir
+        );
+        self.dir = Some(dir);
+        Ok(self)
+    }
+
+    /// Quota from the environment, executions pointed at `FAAS_KV_URL`, and persisted to
+    /// `FAAS_KV_DIR` when it is set
+    pub fn from_env() -> std::io::Result<Self> {
+        let url = std::env::var("FAAS_KV_URL").unwrap_or_else(|_| DEFAULT_KV_URL.to_string());
+        let store = Self::new(KvQuota::from_env(), url);
+        match std::env::var("FAAS_KV_DIR") {
+            Ok(dir) => store.with_dir(PathBuf::from(dir)),
+            Err(_) => Ok(store),
+        }
+    }
+
+    fn persist(&self, namespace: &Namespace) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let scope = format!(
+            "{}\0{}",
+            namespace.tenant.as_deref().unwrap_or_default(),
+            namespace.name
+        );
+        let path = dir.join(format!("{}.json", sha256_hex(scope.as_bytes())));
+        let written = if namespace.entries.is_empty() {
+            std::fs::remove_file(&path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            })
+        } else {
+            serde_json::to_vec(namespace)
+                .map_err(std::io::Error::other)
+                .and_then(|data| std::fs::write(&path, data))
+        };
+        if let Err(e) = written {
+            warn!("Failed to persist kv namespace {}: {}", namespace.name, e);
+        }
+    }
+
+    pub fn get(
+        &self,
+        tenant: Option<&str>,
+        namespace: &str,
+        key: &str,
+    ) -> Result<KvEntry, KvError> {
+        let not_found = || KvError::NotFound {
+            namespace: namespace.to_string(),
+            key: key.to_string(),
+        };
+        let ns = self
+            .namespaces
+            .get(&(tenant.map(str::to_string), namespace.to_string()))
+            .ok_or_else(not_found)?;
+        ns.entries
+            .get(key)
+            .filter(|entry| !entry.expired(Utc::now()))
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    /// Write a key, checking `expected_version` and the quota under the namespace's lock,
+    /// so of two racing compare-and-swaps exactly one wins
+    pub fn put(
+        &self,
+        tenant: Option<&str>,
+        namespace: &str,
+        key: &str,
+        put: KvPut,
+    ) -> Result<KvEntry, KvError> {
+        validate_name(namespace)?;
+        validate_name(key)?;
+        let size = put.value.to_string().len();
+        if size > self.quota.max_value_bytes {
+            return Err(KvError::ValueTooLarge {
+                size,
+                max: self.quota.max_value_bytes,
+            });
+        }
+
+        let now = Utc::now();
+        let tenant = tenant.map(str::to_string);
+        let mut ns = self
+            .namespaces
+            .entry((tenant.clone(), namespace.to_string()))
+            .or_insert_with(|| Namespace {
+                tenant,
+                name: namespace.to_string(),
+                entries: BTreeMap::new(),
+            });
+        ns.prune(now);
+
+        let current = ns.entries.get(key);
+        let current_version = current.map_or(0, |entry| entry.version);
+        if let Some(expected) = put.expected_version {
+            if expected != current_version {
+                return Err(KvError::VersionMismatch {
+                    key: key.to_string(),
+                    expected,
+                    current: current_version,
+                });
+            }
+        }
+        if current.is_none() && ns.entries.len() >= self.quota.max_keys {
+            return Err(KvError::TooManyKeys {
+                namespace: namespace.to_string(),
+                max: self.quota.max_keys,
+            });
+        }
+        let entry = KvEntry {
+            key: key.to_string(),
+            value: put.value,
+            version: current_version + 1,
+            updated_at: now,
+            expires_at: put
+                .ttl_secs
+                .map(|secs| now + chrono::Duration::seconds(secs as i64)),
+        };
+        let grown = ns.bytes() - current.map_or(0, KvEntry::size) + entry.size();
+        if grown > self.quota.max_bytes {
+            return Err(KvError::NamespaceFull {
+                namespace: namespace.to_string(),
+                size: grown,
+                max: self.quota.max_bytes,
+            });
+        }
+        ns.entries.insert(key.to_string(), entry.clone());
+        self.persist(&ns);
+        Ok(entry)
+    }
+
+    /// Live keys starting with `prefix`, in key order
+    pub fn list(&self, tenant: Option<&str>, namespace: &str, prefix: &str) -> Vec<KvEntry> {
+        let now = Utc::now();
+        self.namespaces
+            .get(&(tenant.map(str::to_string), namespace.to_string()))
+            .map(|ns| {
+                ns.entries
+                    .range(prefix.to_string()..)
+                    .take_while(|(key, _)| key.starts_with(prefix))
+                    .map(|(_, entry)| entry)
+                    .filter(|entry| !entry.expired(now))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drop expired keys everywhere, returning how many went
+    pub fn prune(&self) -> usize {
+        let now = Utc::now();
+        let mut removed = 0;
+        for mut ns in self.namespaces.iter_mut() {
+            let pruned = ns.prune(now);
+            if pruned > 0 {
+                removed += pruned;
+                self.persist(&ns);
+            }
+        }
+        self.namespaces.retain(|_, ns| !ns.entries.is_empty());
+        removed
+    }
+
+    /// Let one execution use `namespace` until the grant is dropped
+    pub fn grant(self: &Arc<Self>, tenant: Option<&str>, namespace: &str) -> KvGrant {
+        let token = Uuid::new_v4().simple().to_string();
+        self.grants.insert(
+            token.clone(),
+            Grant {
+                tenant: tenant.map(str::to_string),
+                namespace: namespace.to_string(),
+            },
+        );
+        KvGrant {
+            store: self.clone(),
+            token,
+            endpoint: format!("{}/api/v1/kv/{}", self.url.trim_end_matches('/'), namespace),
+        }
+    }
+
+    /// Tenant a request acts for: its token's, or the tenant header's without a token. A
+    /// bearer token that is no grant's is an API key if the key layer let the request
+    /// through (`keyed`), which set the header; otherwise it's unauthorized.
+    fn caller(
+        &self,
+        headers: &HeaderMap,
+        keyed: bool,
+        namespace: &str,
+    ) -> Result<Option<String>, KvError> {
+        let Some(auth) = headers.get(header::AUTHORIZATION) else {
+            return Ok(request_tenant(headers));
+        };
+        let grant = auth
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .and_then(|token| self.grants.get(token.trim()));
+        let grant = match grant {
+            Some(grant) => grant,
+            None if keyed => return Ok(request_tenant(headers)),
+            None => return Err(KvError::Unauthorized),
+        };
+        if grant.namespace != namespace {
+            return Err(KvError::WrongNamespace(grant.namespace.clone()));
+        }
+        Ok(grant.tenant.clone())
+    }
+}
+
+/// An execution's access to its namespace; revoked on drop
+pub struct KvGrant {
+    store: Arc<KvStore>,
+    token: String,
+    endpoint: String,
+}
+
+impl KvGrant {
+    /// `FAAS_KV_ENDPOINT` and `FAAS_KV_TOKEN` for the sandbox
+    pub fn env(&self) -> [(String, String); 2] {
+        [
+            ("FAAS_KV_ENDPOINT".to_string(), self.endpoint.clone()),
+            ("FAAS_KV_TOKEN".to_string(), self.token.clone()),
+        ]
+    }
+}
+
+impl Drop for KvGrant {
+    fn drop(&mut self) {
+        self.store.grants.remove(&self.token);
+    }
+}
+
+/// `GET /api/v1/kv/:namespace/:key`
+pub async fn get_kv_handler(
+    State(store): State<Arc<KvStore>>,
+    headers: HeaderMap,
+    authorized: Option<Extension<Authorized>>,
+    Path((namespace, key)): Path<(String, String)>,
+) -> Result<Json<KvEntry>, KvError> {
+    let tenant = store.caller(&headers, authorized.is_some(), &namespace)?;
+    store.get(tenant.as_deref(), &namespace, &key).map(Json)
+}
+
+/// `PUT /api/v1/kv/:namespace/:key`
+pub async fn put_kv_handler(
+    State(store): State<Arc<KvStore>>,
+    headers: HeaderMap,
+    authorized: Option<Extension<Authorized>>,
+    Path((namespace, key)): Path<(String, String)>,
+    Json(put): Json<KvPut>,
+) -> Result<Json<KvEntry>, KvError> {
+    let tenant = store.caller(&headers, authorized.is_some(), &namespace)?;
+    store
+        .put(tenant.as_deref(), &namespace, &key, put)
+        .map(Json)
+}
+
+/// `GET /api/v1/kv/:namespace?prefix=`
+pub async fn list_kv_handler(
+    State(store): State<Arc<KvStore>>,
+    headers: HeaderMap,
+    authorized: Option<Extension<Authorized>>,
+    Path(namespace): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<KvEntry>>, KvError> {
+    let tenant = store.caller(&headers, authorized.is_some(), &namespace)?;
+    Ok(Json(store.list(
+        tenant.as_deref(),
+        &namespace,
+        &query.prefix,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn put(value: Value, expected_version: Option<u64>) -> KvPut {
+        KvPut {
+            value,
+            expected_version,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exactly_one_racing_swap_wins() {
+        let store = Arc::new(KvStore::new(KvQuota::default(), DEFAULT_KV_URL));
+        let racers: Vec<_> = ["a", "b", "c", "d"]
+            .into_iter()
+            .map(|branch| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    store
+                        .put(None, "group-g1", "winner", put(json!(branch), Some(0)))
+                        .is_ok()
+                })
+            })
+            .collect();
+        let winners = racers
+            .into_iter()
+            .map(|racer| racer.join().unwrap())
+            .filter(|won| *won)
+            .count();
+        assert_eq!(winners, 1);
+
+        let winner = store.get(None, "group-g1", "winner").unwrap();
+        assert_eq!(winner.version, 1);
+        assert_eq!(
+            store.put(None, "group-g1", "winner", put(json!("late"), Some(0))),
+            Err(KvError::VersionMismatch {
+                key: "winner".to_string(),
+                expected: 0,
+                current: 1
+            })
+        );
+        let updated = store
+            .put(None, "group-g1", "winner", put(json!("next"), Some(1)))
+            .unwrap();
+        assert_eq!(updated.version, 2);
+    }
+
+    #[test]
+    fn quotas_ttls_prefixes_and_tenants() {
+        let quota = KvQuota {
+            max_value_bytes: 16,
+            max_keys: 2,
+            max_bytes: 1024,
+        };
+        let store = KvStore::new(quota, DEFAULT_KV_URL);
+        assert!(matches!(
+            store.put(None, "ns", "big", put(json!("x".repeat(32)), None)),
+            Err(KvError::ValueTooLarge { max: 16, .. })
+        ));
+        store
+            .put(None, "ns", "score.a", put(json!(1), None))
+            .unwrap();
+        store
+            .put(None, "ns", "score.b", put(json!(2), None))
+            .unwrap();
+        let full = store
+            .put(None, "ns", "flag", put(json!(true), None))
+            .unwrap_err();
+        assert_eq!(
+            full.to_string(),
+            "kv namespace ns already holds its limit of 2 keys"
+        );
+        // Overwriting doesn't need a new key
+        store
+            .put(None, "ns", "score.a", put(json!(3), None))
+            .unwrap();
+
+        let keys: Vec<_> = store
+            .list(None, "ns", "score.")
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(keys, ["score.a", "score.b"]);
+        assert!(store.list(Some("other"), "ns", "").is_empty());
+
+        let expiring = KvPut {
+            value: json!(1),
+            ttl_secs: Some(0),
+            ..Default::default()
+        };
+        store.put(Some("t"), "ns", "gone", expiring).unwrap();
+        assert!(matches!(
+            store.get(Some("t"), "ns", "gone"),
+            Err(KvError::NotFound { .. })
+        ));
+        assert_eq!(store.prune(), 1);
+        assert!(matches!(
+            store.put(None, "ns", "a/b", put(json!(1), None)),
+            Err(KvError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn tokens_are_scoped_to_their_namespace_and_execution() {
+        let store = Arc::new(KvStore::new(KvQuota::default(), "http://10.0.0.1:8080/"));
+        let grant = store.grant(Some("acme"), "workflow-etl");
+        let [(_, endpoint), (_, token)] = grant.env();
+        assert_eq!(endpoint, "http://10.0.0.1:8080/api/v1/kv/workflow-etl");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {token}").parse().unwrap(),
+        );
+        assert_eq!(
+            store.caller(&headers, false, "workflow-etl"),
+            Ok(Some("acme".to_string()))
+        );
+        assert_eq!(
+            store.caller(&headers, false, "default"),
+            Err(KvError::WrongNamespace("workflow-etl".to_string()))
+        );
+        drop(grant);
+        assert_eq!(
+            store.caller(&headers, false, "workflow-etl"),
+            Err(KvError::Unauthorized)
+        );
+        assert_eq!(
+            scoped_namespace("workflow", "nightly etl/v2"),
+            "workflow-nightly-etl-v2"
+        );
+    }
+
+    #[tokio::test]
+    async fn an_api_key_sent_as_a_bearer_token_reads_its_tenants_values() {
+        use crate::auth::{require_api_key, ApiKeyConfig, ApiKeys, Permission};
+        use axum::{body::Body, extract::Request, routing::get, Router};
+        use tower::ServiceExt;
+
+        let store = Arc::new(KvStore::new(KvQuota::default(), DEFAULT_KV_URL));
+        store
+            .put(Some("acme"), "default", "winner", put(json!("b"), None))
+            .unwrap();
+        let keys = ApiKeys::new(vec![ApiKeyConfig {
+            key: "sk-acme".to_string(),
+            name: "acme".to_string(),
+            permissions: vec![Permission::Execute],
+            tenant: Some("acme".to_string()),
+            rate_limit: None,
+            limits: None,
+        }]);
+        let app = Router::new()
+            .route("/api/v1/kv/:namespace/:key", get(get_kv_handler))
+            .with_state(store)
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(keys),
+                require_api_key,
+            ));
+        let read = |bearer: &str| {
+            let request = Request::get("/api/v1/kv/default/winner")
+                .header(header::AUTHORIZATION, format!("Bearer {bearer}"))
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        let response = read("sk-acme").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let entry: KvEntry = serde_json::from_slice(&body).unwrap();
+        assert_eq!(entry.value, json!("b"));
+        // Neither a key nor a grant
+        let unknown = read("5f3c0ffee").await.unwrap();
+        assert_eq!(unknown.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn namespaces_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KvStore::new(KvQuota::default(), DEFAULT_KV_URL)
+            .with_dir(dir.path().to_path_buf())
+            .unwrap();
+        store
+            .put(Some("acme"), "group-g1", "winner", put(json!("b"), Some(0)))
+            .unwrap();
+
+        let restarted = KvStore::new(KvQuota::default(), DEFAULT_KV_URL)
+            .with_dir(dir.path().to_path_buf())
+            .unwrap();
+        let entry = restarted.get(Some("acme"), "group-g1", "winner").unwrap();
+        assert_eq!((entry.value, entry.version), (json!("b"), 1));
+    }
+}
@@ -1,19 +1,104 @@
+pub mod artifacts;
+pub mod auth;
+pub mod batch;
+pub mod cancellation;
+pub mod comparison;
+pub mod cron;
+pub mod drain;
+pub mod errors;
+pub mod events;
+pub mod fork;
+pub mod groups;
+pub mod health;
+pub mod idempotency;
+pub mod instance_files;
+pub mod instance_ttl;
+pub mod jobs;
+pub mod killswitch;
+pub mod kv;
+pub mod lifecycle;
+pub mod limits;
+pub mod metrics;
+pub mod orphans;
+pub mod payloads;
+pub mod promotion;
+pub mod response;
+pub mod schedules;
+pub mod shutdown;
+pub mod snapshot_fs;
+pub mod snapshot_jobs;
+pub mod startup;
+pub mod telemetry;
+pub mod tenancy;
 pub mod types;
+pub mod usage;
+pub mod validation;
+pub mod workflows;
 
+use lifecycle::{InstanceState, Lifecycle, SnapshotState};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::atomic::AtomicU64;
 
 // Main request/response types
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InvokeResponse {
     pub request_id: String,
     pub exit_code: i32,
     pub stdout: String,
     pub stderr: String,
     pub duration_ms: u64,
+    /// Same as `stdout`; kept for older clients
     pub output: Option<String>,
+    /// Same as `stderr`; kept for older clients
     pub logs: Option<String>,
+    /// Set when the process exited non-zero
     pub error: Option<String>,
+    /// The result was served from the cache instead of being run again
+    #[serde(default)]
+    pub cache_hit: bool,
+    /// Key of the cache entry a `cached` execution was looked up under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<String>,
+    /// Runtime an ephemeral or persistent execution ran on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<faas_common::Runtime>,
+    /// Why it ran there, e.g. `kvm_unavailable_fallback` for an `auto` execution that
+    /// wanted a VM on a host without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime_reason: Option<faas_executor::platform::RuntimeReason>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<ExecutionDiagnostics>,
+    /// Wall and CPU time, peak memory and stdout bytes, where the runtime measured them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<faas_common::ExecutionUsage>,
+    /// The output went over the executor's limit, so `stdout` and `stderr` hold only its
+    /// start. `GET /api/v1/executions/:id/logs` serves all of it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Bytes written to stdout and stderr together, when `truncated`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    /// Checkpoint a `checkpointed` execution still running at its timeout was stopped
+    /// into; sending it back as `snapshot_id` resumes the process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_id: Option<String>,
+}
+
+/// How the gateway actually ran an execution.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecutionDiagnostics {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<limits::AppliedLimits>,
+    /// Clock and locale overrides the sandbox ran with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment_overrides: Option<faas_common::EnvOverrides>,
+    /// Every env var the sandbox got, with the layer that set it; values are left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<std::collections::BTreeMap<String, faas_common::env::EnvLayer>>,
+    /// Which runtime won a speculative execution, and what each attempt took
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speculation: Option<faas_executor::platform::SpeculationReport>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,6 +107,42 @@ pub struct CreateInstanceRequest {
     pub image: String,
     pub cpu_cores: Option<u32>,
     pub memory_mb: Option<u32>,
+    /// Mount a workspace volume kept under the instance's `name`, so an instance created
+    /// again with that name finds its files
+    #[serde(default)]
+    pub persistent: Option<bool>,
+    /// Where the workspace is mounted; `/workspace` unless set
+    #[serde(default)]
+    pub workspace_path: Option<String>,
+    /// Ports to publish on the host; Docker picks the host port when one isn't given
+    #[serde(default)]
+    pub ports: Vec<faas_executor::platform::PortMapping>,
+}
+
+/// `DELETE /api/v1/instances/:id`
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteInstanceQuery {
+    /// Remove the instance's workspace volume as well; it is kept by default
+    #[serde(default)]
+    pub delete_volume: bool,
+}
+
+/// `POST /api/v1/instances/:id/stop`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StopInstanceRequest {
+    /// Seconds between SIGTERM and SIGKILL; `FAAS_INSTANCE_STOP_GRACE_SECS` (10) by default
+    #[serde(default)]
+    pub grace_secs: Option<u64>,
+}
+
+/// Command run on an instance; session env and cwd carry over between execs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecInstanceRequest {
+    pub command: String,
+    pub timeout_ms: Option<u64>,
+    /// Written to the command's stdin; a byte array or a base64 string
+    #[serde(default, deserialize_with = "payloads::inline_payload")]
+    pub payload: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,6 +150,8 @@ pub struct CreateSnapshotRequest {
     pub container_id: String,
     pub name: Option<String>,
     pub tags: Option<Vec<String>>,
+    /// Execution group that counts this snapshot as a member, for completion webhooks
+    pub group_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,6 +161,72 @@ pub struct PrewarmRequest {
     pub runtime: Option<faas_common::Runtime>,
 }
 
+/// A pool of warm containers for one image, or of warm VMs for one Firecracker environment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmPool {
+    /// The VM environment, for a Firecracker pool
+    pub image: String,
+    pub runtime: faas_common::Runtime,
+    /// The tenant whose executions a Docker pool serves; unset for a shared pool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Idle containers or VMs waiting for an execution
+    pub size: usize,
+    pub in_use: usize,
+    /// Idle count the pool is refilled to
+    pub min_size: usize,
+    pub max_size: usize,
+    /// Executions a warm container or VM served, and those that found none
+    pub hits: u64,
+    pub misses: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hit_rate: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_acquisition_ms: Option<f64>,
+    /// Time since the pool was opened; 0 for VM pools
+    pub age_secs: u64,
+    /// How long the longest-idle container has waited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oldest_idle_secs: Option<u64>,
+}
+
+impl From<faas_executor::platform::WarmPoolStats> for WarmPool {
+    fn from(stats: faas_executor::platform::WarmPoolStats) -> Self {
+        Self {
+            hit_rate: stats.hit_rate(),
+            image: stats.environment,
+            runtime: stats.runtime,
+            tenant: stats.namespace,
+            size: stats.warm,
+            in_use: stats.in_use,
+            min_size: stats.limits.min,
+            max_size: stats.limits.max,
+            hits: stats.hits,
+            misses: stats.misses,
+            avg_acquisition_ms: stats.avg_acquisition.map(|d| d.as_secs_f64() * 1000.0),
+            age_secs: stats.age.map_or(0, |age| age.as_secs()),
+            oldest_idle_secs: stats.oldest_idle.map(|idle| idle.as_secs()),
+        }
+    }
+}
+
+impl From<faas_executor::container_pool::PoolStats> for WarmPool {
+    fn from(stats: faas_executor::container_pool::PoolStats) -> Self {
+        faas_executor::platform::WarmPoolStats::from(stats).into()
+    }
+}
+
+/// Body of `PUT /api/v1/pools/:image`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PoolLimitsRequest {
+    pub min_size: usize,
+    pub max_size: usize,
+    /// `firecracker` sizes the VM pool of the environment named in the path; Docker when
+    /// unset
+    #[serde(default)]
+    pub runtime: Option<faas_common::Runtime>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Snapshot {
     pub id: String,
@@ -45,6 +234,28 @@ pub struct Snapshot {
     pub container_id: String,
     pub created_at: String,
     pub size_bytes: u64,
+    /// Committed Docker image holding the snapshot's filesystem
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Raw ext4 disk of a Firecracker snapshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub lifecycle: Lifecycle<SnapshotState>,
+    /// Commit progress; `size_bytes` is the preflight estimate until the snapshot is ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<snapshot_jobs::SnapshotProgress>,
+}
+
+impl Snapshot {
+    /// Snapshots created without a tenant are shared; tenant-owned ones are private.
+    pub fn visible_to(&self, tenant: Option<&str>) -> bool {
+        self.tenant.is_none() || self.tenant.as_deref() == tenant
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,10 +263,39 @@ pub struct Instance {
     pub id: String,
     pub name: Option<String>,
     pub image: String,
-    pub status: String,
+    /// The tenant that created it; other tenants' keys are told it doesn't exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(flatten)]
+    pub lifecycle: Lifecycle<InstanceState>,
     pub created_at: String,
     pub cpu_cores: Option<u32>,
     pub memory_mb: Option<u32>,
+    /// The Docker container execs run in; released once the instance stops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    /// Live state and usage of that container, as of the last `GET` of this instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<faas_executor::platform::ContainerStatus>,
+    /// RFC 3339; the reaper stops the instance after this. See [`instance_ttl`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// The volume a persistent instance's workspace lives on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<faas_executor::platform::WorkspaceMount>,
+    /// The instance's ports published on the host
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<faas_executor::platform::PortMapping>,
+    /// URL of each published port, keyed `8000/tcp`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub endpoints: BTreeMap<String, String>,
+}
+
+/// The executor mode an execute request's `mode` names; no mode is ephemeral
+pub fn execution_mode(
+    mode: Option<&str>,
+) -> Result<faas_executor::platform::Mode, errors::ApiError> {
+    validation::parse_mode(mode).map_err(|e| validation::rejection(vec![e]))
 }
 
 // Metrics tracking
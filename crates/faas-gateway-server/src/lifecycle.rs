@@ -0,0 +1,566 @@
+//! Instance and snapshot lifecycles.
+//!
+//! Every status change goes through [`Lifecycle::transition`], which checks the move against
+//! the state's transition table and records when it happened. Illegal moves surface as
+//! `409 Conflict` with the current and requested states.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+use crate::errors::ApiError;
+use crate::Instance;
+use faas_executor::platform::ContainerStatus;
+
+/// Transitions kept per entity; older ones are dropped first.
+pub const MAX_STATE_HISTORY: usize = 32;
+
+/// A status with an explicit table of allowed next statuses.
+pub trait LifecycleState: Copy + Eq + fmt::Display {
+    fn can_transition_to(self, next: Self) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstanceState {
+    Creating,
+    Running,
+    Paused,
+    Suspended,
+    Stopping,
+    Stopped,
+    /// The backing sandbox disappeared without being stopped
+    Lost,
+}
+
+impl InstanceState {
+    pub const ALL: [InstanceState; 7] = [
+        InstanceState::Creating,
+        InstanceState::Running,
+        InstanceState::Paused,
+        InstanceState::Suspended,
+        InstanceState::Stopping,
+        InstanceState::Stopped,
+        InstanceState::Lost,
+    ];
+
+    /// Stopped and lost instances are kept only until garbage collection.
+    pub fn is_terminal(self) -> bool {
+        matches!(self, InstanceState::Stopped | InstanceState::Lost)
+    }
+}
+
+impl LifecycleState for InstanceState {
+    fn can_transition_to(self, next: Self) -> bool {
+        use InstanceState::*;
+        matches!(
+            (self, next),
+            (Creating, Running | Stopped | Lost)
+                | (Running, Paused | Suspended | Stopping | Lost)
+                | (Paused | Suspended, Running | Stopping | Lost)
+                | (Stopping, Stopped | Lost)
+        )
+    }
+}
+
+impl fmt::Display for InstanceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InstanceState::Creating => "creating",
+            InstanceState::Running => "running",
+            InstanceState::Paused => "paused",
+            InstanceState::Suspended => "suspended",
+            InstanceState::Stopping => "stopping",
+            InstanceState::Stopped => "stopped",
+            InstanceState::Lost => "lost",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotState {
+    Creating,
+    Ready,
+    /// Being uploaded to remote storage
+    Pushing,
+    Deleting,
+    Failed,
+}
+
+impl SnapshotState {
+    pub const ALL: [SnapshotState; 5] = [
+        SnapshotState::Creating,
+        SnapshotState::Ready,
+        SnapshotState::Pushing,
+        SnapshotState::Deleting,
+        SnapshotState::Failed,
+    ];
+}
+
+impl LifecycleState for SnapshotState {
+    fn can_transition_to(self, next: Self) -> bool {
+        use SnapshotState::*;
+        matches!(
+            (self, next),
+            (Creating, Ready | Failed)
+                | (Ready, Pushing | Deleting)
+                | (Pushing, Ready | Failed)
+                | (Deleting, Failed)
+                | (Failed, Deleting)
+        )
+    }
+}
+
+impl fmt::Display for SnapshotState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SnapshotState::Creating => "creating",
+            SnapshotState::Ready => "ready",
+            SnapshotState::Pushing => "pushing",
+            SnapshotState::Deleting => "deleting",
+            SnapshotState::Failed => "failed",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateChange<S> {
+    pub state: S,
+    /// RFC 3339 timestamp
+    pub at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("cannot move {entity} from {current} to {requested}")]
+pub struct TransitionError {
+    pub entity: String,
+    pub current: String,
+    pub requested: String,
+}
+
+impl IntoResponse for TransitionError {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": self.to_string(),
+                "current": self.current,
+                "requested": self.requested,
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Failure to move a stored instance or snapshot.
+#[derive(Debug, Error)]
+pub enum LifecycleError {
+    #[error("not found")]
+    NotFound,
+    #[error(transparent)]
+    Transition(#[from] TransitionError),
+}
+
+impl IntoResponse for LifecycleError {
+    fn into_response(self) -> Response {
+        match self {
+            LifecycleError::NotFound => StatusCode::NOT_FOUND.into_response(),
+            LifecycleError::Transition(e) => e.into_response(),
+        }
+    }
+}
+
+/// Current state plus the capped log of how it got there.
+///
+/// Flattened into its entity, so the wire format is `"status": "running"` next to
+/// `"state_history": [...]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lifecycle<S> {
+    status: S,
+    state_history: Vec<StateChange<S>>,
+}
+
+impl<S: LifecycleState> Lifecycle<S> {
+    pub fn new(initial: S) -> Self {
+        Self {
+            status: initial,
+            state_history: vec![StateChange {
+                state: initial,
+                at: Utc::now().to_rfc3339(),
+            }],
+        }
+    }
+
+    pub fn current(&self) -> S {
+        self.status
+    }
+
+    pub fn history(&self) -> &[StateChange<S>] {
+        &self.state_history
+    }
+
+    /// When the current state was entered.
+    pub fn since(&self) -> Option<DateTime<Utc>> {
+        let at = &self.state_history.last()?.at;
+        chrono::DateTime::parse_from_rfc3339(at)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    pub fn transition(&mut self, entity: &str, next: S) -> Result<(), TransitionError> {
+        if !self.status.can_transition_to(next) {
+            return Err(self.conflict(entity, next));
+        }
+        if self.state_history.len() >= MAX_STATE_HISTORY {
+            self.state_history.remove(0);
+        }
+        self.status = next;
+        self.state_history.push(StateChange {
+            state: next,
+            at: Utc::now().to_rfc3339(),
+        });
+        Ok(())
+    }
+
+    /// Fail unless the entity is in one of `allowed`, without changing anything.
+    pub fn require(&self, entity: &str, allowed: &[S], wanted: S) -> Result<(), TransitionError> {
+        if allowed.contains(&self.status) {
+            Ok(())
+        } else {
+            Err(self.conflict(entity, wanted))
+        }
+    }
+
+    fn conflict(&self, entity: &str, requested: S) -> TransitionError {
+        TransitionError {
+            entity: entity.to_string(),
+            current: self.status.to_string(),
+            requested: requested.to_string(),
+        }
+    }
+}
+
+/// One garbage-collection pass over the instance map.
+///
+/// Instances stuck in `creating` or `stopping` for longer than `retention` are marked lost,
+/// and instances that have been stopped or lost that long are dropped. Returns the dropped ids.
+pub fn sweep_instances(
+    instances: &DashMap<String, Instance>,
+    retention: chrono::Duration,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let expired = |lifecycle: &Lifecycle<InstanceState>| !matches!(lifecycle.since(), Some(since) if now - since < retention);
+
+    // Newly lost instances get a full retention period before they are dropped.
+    let mut lost = Vec::new();
+    for mut entry in instances.iter_mut() {
+        let stuck = matches!(
+            entry.lifecycle.current(),
+            InstanceState::Creating | InstanceState::Stopping
+        );
+        if stuck && expired(&entry.lifecycle) {
+            let entity = format!("instance {}", entry.key());
+            if entry
+                .lifecycle
+                .transition(&entity, InstanceState::Lost)
+                .is_ok()
+            {
+                lost.push(entry.key().clone());
+            }
+        }
+    }
+
+    let mut removed = Vec::new();
+    instances.retain(|id, instance| {
+        let keep = lost.contains(id)
+            || !(instance.lifecycle.current().is_terminal() && expired(&instance.lifecycle));
+        if !keep {
+            removed.push(id.clone());
+        }
+        keep
+    });
+    removed
+}
+
+/// Fold what Docker reports for an instance's container into the instance.
+///
+/// `None` means the container is gone: a live instance is marked lost and forgets it.
+/// Returns the state the instance was in when that happened.
+pub fn observe_container(
+    instance: &mut Instance,
+    observed: Option<ContainerStatus>,
+) -> Result<Option<InstanceState>, TransitionError> {
+    if observed.is_none() {
+        instance.container_id = None;
+    }
+    instance.container = observed;
+    let from = instance.lifecycle.current();
+    if instance.container_id.is_some() || from.is_terminal() {
+        return Ok(None);
+    }
+    instance
+        .lifecycle
+        .transition(&format!("instance {}", instance.id), InstanceState::Lost)?;
+    Ok(Some(from))
+}
+
+/// Fail unless the instance can take commands. A paused one gets its own conflict, since
+/// resuming it is all the caller has to do.
+pub fn require_running(instance: &Instance) -> Result<(), Response> {
+    if instance.lifecycle.current() == InstanceState::Paused {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "InstancePaused",
+            format!("instance {} is paused; resume it first", instance.id),
+        )
+        .into_response());
+    }
+    instance
+        .lifecycle
+        .require(
+            &format!("instance {}", instance.id),
+            &[InstanceState::Running],
+            InstanceState::Running,
+        )
+        .map_err(IntoResponse::into_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use faas_executor::platform::ContainerRunState;
+    use InstanceState as I;
+    use SnapshotState as S;
+
+    #[test]
+    fn instance_transition_matrix() {
+        let allowed = [
+            (I::Creating, I::Running),
+            (I::Creating, I::Stopped),
+            (I::Creating, I::Lost),
+            (I::Running, I::Paused),
+            (I::Running, I::Suspended),
+            (I::Running, I::Stopping),
+            (I::Running, I::Lost),
+            (I::Paused, I::Running),
+            (I::Paused, I::Stopping),
+            (I::Paused, I::Lost),
+            (I::Suspended, I::Running),
+            (I::Suspended, I::Stopping),
+            (I::Suspended, I::Lost),
+            (I::Stopping, I::Stopped),
+            (I::Stopping, I::Lost),
+        ];
+        for from in I::ALL {
+            for to in I::ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{from} -> {to}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_transition_matrix() {
+        let allowed = [
+            (S::Creating, S::Ready),
+            (S::Creating, S::Failed),
+            (S::Ready, S::Pushing),
+            (S::Ready, S::Deleting),
+            (S::Pushing, S::Ready),
+            (S::Pushing, S::Failed),
+            (S::Deleting, S::Failed),
+            (S::Failed, S::Deleting),
+        ];
+        for from in S::ALL {
+            for to in S::ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{from} -> {to}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn illegal_moves_are_conflicts() {
+        let mut history = Lifecycle::new(I::Creating);
+        let err = history.transition("instance i-1", I::Stopping).unwrap_err();
+        assert_eq!(history.current(), I::Creating);
+
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["current"], "creating");
+        assert_eq!(body["requested"], "stopping");
+
+        let snapshot = Lifecycle::new(S::Creating);
+        assert!(snapshot
+            .require("snapshot s-1", &[S::Ready], S::Ready)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn only_a_running_instance_takes_commands() {
+        let mut paused = instance("i-1", I::Running);
+        assert!(require_running(&paused).is_ok());
+
+        paused.lifecycle.transition("i-1", I::Paused).unwrap();
+        let response = require_running(&paused).unwrap_err();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "InstancePaused");
+        assert_eq!(body["message"], "instance i-1 is paused; resume it first");
+
+        // Resumed, then stopped for good
+        paused.lifecycle.transition("i-1", I::Running).unwrap();
+        assert!(require_running(&paused).is_ok());
+        let mut stopped = paused;
+        stopped.lifecycle.transition("i-1", I::Stopping).unwrap();
+        stopped.lifecycle.transition("i-1", I::Stopped).unwrap();
+        let response = require_running(&stopped).unwrap_err();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(stopped.lifecycle.transition("i-1", I::Paused).is_err());
+        let states: Vec<_> = stopped
+            .lifecycle
+            .history()
+            .iter()
+            .map(|change| change.state)
+            .collect();
+        assert_eq!(
+            states,
+            [
+                I::Creating,
+                I::Running,
+                I::Paused,
+                I::Running,
+                I::Stopping,
+                I::Stopped
+            ]
+        );
+    }
+
+    #[test]
+    fn history_is_capped_and_serializes_lowercase() {
+        let mut history = Lifecycle::new(I::Creating);
+        history.transition("i", I::Running).unwrap();
+        for _ in 0..MAX_STATE_HISTORY {
+            history.transition("i", I::Paused).unwrap();
+            history.transition("i", I::Running).unwrap();
+        }
+        assert_eq!(history.history().len(), MAX_STATE_HISTORY);
+        assert_eq!(history.current(), I::Running);
+        assert!(history.since().is_some());
+
+        assert_eq!(serde_json::to_value(I::Suspended).unwrap(), "suspended");
+        let wire = serde_json::to_value(&history).unwrap();
+        assert_eq!(wire["status"], "running");
+        assert_eq!(wire["state_history"][0]["state"], "paused");
+        assert_eq!(
+            serde_json::from_value::<S>("pushing".into()).unwrap(),
+            S::Pushing
+        );
+    }
+
+    fn instance(id: &str, state: I) -> Instance {
+        let mut lifecycle = Lifecycle::new(I::Creating);
+        if state != I::Creating {
+            lifecycle.transition(id, state).unwrap();
+        }
+        Instance {
+            id: id.to_string(),
+            name: None,
+            image: "alpine:latest".to_string(),
+            tenant: None,
+            lifecycle,
+            created_at: Utc::now().to_rfc3339(),
+            cpu_cores: None,
+            memory_mb: None,
+            container_id: None,
+            container: None,
+            expires_at: None,
+            workspace: None,
+            ports: Vec::new(),
+            endpoints: Default::default(),
+        }
+    }
+
+    #[test]
+    fn sweep_marks_stuck_instances_lost_then_drops_terminal_ones() {
+        let instances = DashMap::new();
+        for (id, state) in [("a", I::Running), ("b", I::Creating), ("c", I::Stopped)] {
+            instances.insert(id.to_string(), instance(id, state));
+        }
+        let retention = chrono::Duration::minutes(10);
+
+        assert!(sweep_instances(&instances, retention, Utc::now()).is_empty());
+
+        let later = Utc::now() + chrono::Duration::minutes(11);
+        let removed = sweep_instances(&instances, retention, later);
+        assert_eq!(removed, vec!["c".to_string()]);
+        assert_eq!(instances.get("b").unwrap().lifecycle.current(), I::Lost);
+        assert_eq!(instances.get("a").unwrap().lifecycle.current(), I::Running);
+
+        let removed = sweep_instances(&instances, retention, later + retention);
+        assert_eq!(removed, vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn observed_container_state_is_merged_into_a_live_instance() {
+        let mut running = instance("a", I::Running);
+        running.container_id = Some("c-a".to_string());
+        let status = ContainerStatus {
+            state: ContainerRunState::OomKilled,
+            exit_code: Some(137),
+            memory_bytes: None,
+            cpu_percent: None,
+        };
+
+        assert_eq!(
+            observe_container(&mut running, Some(status.clone())),
+            Ok(None)
+        );
+        assert_eq!(running.lifecycle.current(), I::Running);
+        assert_eq!(running.container, Some(status));
+        let wire = serde_json::to_value(&running).unwrap();
+        assert_eq!(wire["container"]["state"], "oom_killed");
+        assert_eq!(wire["container"]["exit_code"], 137);
+    }
+
+    #[test]
+    fn an_instance_whose_container_vanished_is_lost() {
+        let mut paused = instance("a", I::Running);
+        paused.lifecycle.transition("a", I::Paused).unwrap();
+        paused.container_id = Some("c-a".to_string());
+
+        assert_eq!(observe_container(&mut paused, None), Ok(Some(I::Paused)));
+        assert_eq!(paused.lifecycle.current(), I::Lost);
+        assert_eq!(paused.container_id, None);
+    }
+
+    #[test]
+    fn a_stopped_instance_stays_stopped_without_its_container() {
+        let mut stopped = instance("a", I::Stopped);
+        assert_eq!(observe_container(&mut stopped, None), Ok(None));
+        assert_eq!(stopped.lifecycle.current(), I::Stopped);
+    }
+}
@@ -0,0 +1,299 @@
+//! Sandbox resource limits applied by the gateway.
+//!
+//! Every execution gets conservative ulimits unless the caller asks for different
+//! values; requests above the policy caps are rejected rather than silently clamped. An API
+//! key may carry [`LimitCaps`] of its own, which the key layer passes on in the
+//! `x-faas-limits` header and which tighten the policy's caps for its requests.
+
+use axum::http::HeaderMap;
+use faas_common::{TmpfsMount, Ulimit};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LimitsError {
+    #[error("Unsupported ulimit: {0}")]
+    Unsupported(String),
+    #[error("Ulimit {name}: soft limit {soft} exceeds hard limit {hard}")]
+    SoftAboveHard { name: String, soft: i64, hard: i64 },
+    #[error("{name} of {requested} exceeds the maximum of {max}")]
+    AboveCap {
+        name: String,
+        requested: i64,
+        max: i64,
+    },
+}
+
+pub const LIMITS_HEADER: &str = "x-faas-limits";
+
+/// An API key's own caps; each applies alongside the policy's, and the lower one wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitCaps {
+    /// Highest hard limit the key may request, per ulimit name.
+    #[serde(default)]
+    pub max_ulimits: Vec<Ulimit>,
+    #[serde(default)]
+    pub max_shm_size_mb: Option<u64>,
+    #[serde(default)]
+    pub max_tmpfs_total_mb: Option<u64>,
+    /// Most processes and threads each of the key's sandboxes may hold
+    #[serde(default)]
+    pub max_pids_limit: Option<i64>,
+}
+
+impl LimitCaps {
+    /// The caps the key layer put on the request, if its key has any
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let caps = headers.get(LIMITS_HEADER)?.to_str().ok()?;
+        serde_json::from_str(caps).ok()
+    }
+}
+
+/// Defaults and caps for per-execution resource limits.
+#[derive(Debug, Clone)]
+pub struct LimitsPolicy {
+    pub default_ulimits: Vec<Ulimit>,
+    /// Highest hard limit a caller may request, per ulimit name.
+    pub max_ulimits: Vec<Ulimit>,
+    pub default_shm_size_mb: u64,
+    pub max_shm_size_mb: u64,
+    /// Upper bound on the combined size of all tmpfs mounts.
+    pub max_tmpfs_total_mb: u64,
+    /// Process and thread cap every sandbox runs with, enforced by the pids cgroup; the
+    /// `nproc` ulimit is only a second line, since it does not bind root.
+    pub default_pids_limit: i64,
+}
+
+impl Default for LimitsPolicy {
+    fn default() -> Self {
+        Self {
+            // What warm containers are created with, so executions keeping them reuse one
+            default_ulimits: faas_common::default_ulimits(),
+            max_ulimits: vec![
+                Ulimit::new("nproc", 4096, 4096),
+                Ulimit::new("nofile", 65536, 65536),
+                Ulimit::new("fsize", 10 << 30, 10 << 30),
+                Ulimit::new("core", 0, 0),
+                Ulimit::new("stack", 64 << 20, 64 << 20),
+                Ulimit::new("memlock", 64 << 20, 64 << 20),
+            ],
+            default_shm_size_mb: faas_common::DEFAULT_SHM_SIZE_MB,
+            max_shm_size_mb: 1024,
+            max_tmpfs_total_mb: 1024,
+            default_pids_limit: faas_common::DEFAULT_PIDS_LIMIT,
+        }
+    }
+}
+
+/// The limits an execution actually ran with, reported back in the response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedLimits {
+    pub ulimits: Vec<Ulimit>,
+    pub shm_size_mb: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tmpfs: Vec<TmpfsMount>,
+    pub pids_limit: i64,
+}
+
+impl LimitsPolicy {
+    /// Merge the caller's overrides onto the defaults and check them against the caps,
+    /// and against the key's `caps` if it has any.
+    pub fn resolve(
+        &self,
+        caps: Option<&LimitCaps>,
+        ulimits: Option<Vec<Ulimit>>,
+        shm_size_mb: Option<u64>,
+        tmpfs: Option<Vec<TmpfsMount>>,
+    ) -> Result<AppliedLimits, LimitsError> {
+        let mut applied = self.default_ulimits.clone();
+        for requested in ulimits.unwrap_or_default() {
+            if !requested.is_supported() {
+                return Err(LimitsError::Unsupported(requested.name));
+            }
+            if requested.soft > requested.hard {
+                return Err(LimitsError::SoftAboveHard {
+                    name: requested.name,
+                    soft: requested.soft,
+                    hard: requested.hard,
+                });
+            }
+            let key_max = caps.map_or(&[][..], |c| &c.max_ulimits);
+            let max = self
+                .max_ulimits
+                .iter()
+                .chain(key_max)
+                .filter(|m| m.name == requested.name)
+                .map(|m| m.hard)
+                .min();
+            if let Some(max) = max.filter(|max| requested.hard > *max) {
+                return Err(LimitsError::AboveCap {
+                    name: requested.name,
+                    requested: requested.hard,
+                    max,
+                });
+            }
+            match applied.iter_mut().find(|u| u.name == requested.name) {
+                Some(existing) => *existing = requested,
+                None => applied.push(requested),
+            }
+        }
+
+        let shm_size_mb = shm_size_mb.unwrap_or(self.default_shm_size_mb);
+        let max_shm_size_mb = lower(self.max_shm_size_mb, caps.and_then(|c| c.max_shm_size_mb));
+        if shm_size_mb > max_shm_size_mb {
+            return Err(LimitsError::AboveCap {
+                name: "shm_size_mb".to_string(),
+                requested: shm_size_mb as i64,
+                max: max_shm_size_mb as i64,
+            });
+        }
+
+        let tmpfs = tmpfs.unwrap_or_default();
+        let tmpfs_total: u64 = tmpfs.iter().map(|m| m.size_mb).sum();
+        let max_tmpfs_total_mb = lower(
+            self.max_tmpfs_total_mb,
+            caps.and_then(|c| c.max_tmpfs_total_mb),
+        );
+        if tmpfs_total > max_tmpfs_total_mb {
+            return Err(LimitsError::AboveCap {
+                name: "tmpfs size_mb".to_string(),
+                requested: tmpfs_total as i64,
+                max: max_tmpfs_total_mb as i64,
+            });
+        }
+
+        let pids_limit = caps
+            .and_then(|c| c.max_pids_limit)
+            .map_or(self.default_pids_limit, |key| {
+                key.min(self.default_pids_limit)
+            });
+
+        Ok(AppliedLimits {
+            ulimits: applied,
+            shm_size_mb,
+            tmpfs,
+            pids_limit,
+        })
+    }
+}
+
+/// The policy's cap, or the key's if that is lower
+fn lower(policy: u64, key: Option<u64>) -> u64 {
+    key.map_or(policy, |key| key.min(policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_apply_when_nothing_requested() {
+        let applied = LimitsPolicy::default()
+            .resolve(None, None, None, None)
+            .unwrap();
+        assert!(applied.ulimits.contains(&Ulimit::new("nproc", 256, 256)));
+        assert!(applied.ulimits.contains(&Ulimit::new("nofile", 1024, 1024)));
+        assert_eq!(applied.shm_size_mb, 64);
+        assert!(applied.tmpfs.is_empty());
+        assert_eq!(applied.pids_limit, faas_common::DEFAULT_PIDS_LIMIT);
+    }
+
+    #[test]
+    fn overrides_replace_defaults_within_caps() {
+        let applied = LimitsPolicy::default()
+            .resolve(
+                None,
+                Some(vec![Ulimit::new("nofile", 4096, 8192)]),
+                Some(256),
+                None,
+            )
+            .unwrap();
+        assert!(applied.ulimits.contains(&Ulimit::new("nofile", 4096, 8192)));
+        assert_eq!(
+            applied
+                .ulimits
+                .iter()
+                .filter(|u| u.name == "nofile")
+                .count(),
+            1
+        );
+        assert_eq!(applied.shm_size_mb, 256);
+    }
+
+    #[test]
+    fn rejects_values_above_caps() {
+        let policy = LimitsPolicy::default();
+        assert!(matches!(
+            policy.resolve(
+                None,
+                Some(vec![Ulimit::new("nproc", 100, 100_000)]),
+                None,
+                None
+            ),
+            Err(LimitsError::AboveCap { .. })
+        ));
+        assert!(matches!(
+            policy.resolve(None, None, Some(1 << 20), None),
+            Err(LimitsError::AboveCap { .. })
+        ));
+        assert_eq!(
+            policy.resolve(None, Some(vec![Ulimit::new("rtprio", 1, 1)]), None, None),
+            Err(LimitsError::Unsupported("rtprio".to_string()))
+        );
+    }
+
+    #[test]
+    fn a_keys_caps_tighten_the_policys() {
+        let policy = LimitsPolicy::default();
+        let caps = LimitCaps {
+            max_ulimits: vec![Ulimit::new("nproc", 512, 512)],
+            max_shm_size_mb: Some(128),
+            max_tmpfs_total_mb: Some(4096),
+            max_pids_limit: Some(64),
+        };
+        assert_eq!(
+            policy.resolve(Some(&caps), None, Some(256), None),
+            Err(LimitsError::AboveCap {
+                name: "shm_size_mb".to_string(),
+                requested: 256,
+                max: 128,
+            })
+        );
+        assert!(matches!(
+            policy.resolve(
+                Some(&caps),
+                Some(vec![Ulimit::new("nproc", 1024, 1024)]),
+                None,
+                None
+            ),
+            Err(LimitsError::AboveCap { max: 512, .. })
+        ));
+        // A key's cap above the policy's does not raise it
+        let tmpfs = vec![TmpfsMount {
+            path: "/scratch".to_string(),
+            size_mb: 2048,
+        }];
+        assert!(matches!(
+            policy.resolve(Some(&caps), None, None, Some(tmpfs)),
+            Err(LimitsError::AboveCap { max: 1024, .. })
+        ));
+        assert_eq!(
+            policy
+                .resolve(Some(&caps), None, Some(128), None)
+                .unwrap()
+                .pids_limit,
+            64
+        );
+        let generous = LimitCaps {
+            max_pids_limit: Some(1 << 20),
+            ..caps
+        };
+        assert_eq!(
+            policy
+                .resolve(Some(&generous), None, None, None)
+                .unwrap()
+                .pids_limit,
+            policy.default_pids_limit
+        );
+    }
+}
@@ -1,29 +1,90 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Extension, Path, Query, State},
+    http::{HeaderMap, StatusCode},
     response::{
         sse::{Event, Sse},
-        IntoResponse,
+        IntoResponse, Response,
     },
     routing::{get, post},
     Json, Router,
 };
 use dashmap::DashMap;
-use faas_common::{ExecutionMode, Runtime};
+use faas_common::env::{EnvLayer, LayeredEnv};
+use faas_common::{
+    EnvOverrides, EnvVar, ExecutionMode, ExecutionStrategy, FaasError, GpuRequest, IsolationLevel,
+    NetworkPolicy, Placement, Runtime, TmpfsMount, Ulimit,
+};
+use faas_executor::canary::{CanarySpec, CanaryStatus, WebhookAlertSink};
+use faas_executor::drain::DrainOutcome;
 use faas_executor::platform;
+use faas_executor::platform::ports;
+use faas_executor::platform::workspaces::{self, DEFAULT_WORKSPACE_PATH};
+use faas_executor::session_state::{
+    finish_restore, restore_candidate, CapturedState, RedactionRules, RestoreReport, SessionState,
+    SessionWrapper,
+};
 use faas_gateway_server::{
-    types::*, CreateInstanceRequest, CreateSnapshotRequest, ExecutionMetrics, Instance,
-    InvokeResponse, PrewarmRequest, Snapshot,
+    artifacts::{self, ArtifactStore, LogStore},
+    auth::{self, ApiKeys, Authorized},
+    batch::{self, BatchLimits},
+    cancellation::{
+        self, CancelError, CancelOnDrop, CancelPolicy, CancelRegistry, CancelReport, CancelRequest,
+        CancelScope, Cancellation,
+    },
+    comparison::{self, ComparisonError, ComparisonQuery, ComparisonReport, Normalizer},
+    drain::{self, DrainRequest, DrainStatusResponse, InstancePolicy},
+    errors::{self, ApiError},
+    events::{self, EventBus, EventPage, EventsQuery, ExecutionOutcome, PlatformEvent},
+    fork::{self, ForkBranch, ForkResult, ForkStrategy},
+    groups::{
+        CreateGroupRequest, GroupError, GroupRegistry, GroupSummary, HttpWebhookSink, Settlement,
+    },
+    health::{HealthProbe, HostHealth},
+    idempotency::{Claim, IdempotencyCache},
+    instance_files::{self, FilesQuery},
+    instance_ttl::{self, ExtendTtl, TtlPolicy},
+    jobs::{Job, JobStatus, JobStore},
+    killswitch::{
+        self, Activation, KillSwitch, KillSwitchError, KillSwitchHit, KillSwitchRequest,
+        KillSwitchRule, RunGuard, Workload,
+    },
+    kv::{self, KvEntry, KvError, KvGrant, KvPut, KvStore},
+    lifecycle::{self, InstanceState, Lifecycle, LifecycleError, SnapshotState},
+    limits::{AppliedLimits, LimitCaps, LimitsPolicy},
+    metrics,
+    orphans::{Orphans, OrphansResponse},
+    payloads::{self, PayloadError, PayloadLease, PayloadStore},
+    promotion::{
+        self, PinRequest, PromotionPolicy, PromotionReport, PromotionTracker, PromotionWork,
+    },
+    response::ResponseBuilder,
+    schedules::{Schedule, ScheduleRunner, ScheduleSpec, ScheduleStore, ScheduledRun},
+    shutdown::{self, ShutdownPolicy},
+    snapshot_fs,
+    snapshot_jobs::{self, SnapshotBackend, SnapshotQuota, SnapshotRequest},
+    startup, telemetry,
+    tenancy::{self, ExecutionOwners, Scope},
+    types::*,
+    usage::{self, ComputeSize, UsageMeter},
+    validation::{ExecuteFields, RequestBounds},
+    workflows::{self, StepRunner, Workflows},
+    CreateInstanceRequest, CreateSnapshotRequest, DeleteInstanceQuery, ExecInstanceRequest,
+    ExecutionDiagnostics, ExecutionMetrics, Instance, InvokeResponse, PoolLimitsRequest,
+    PrewarmRequest, Snapshot, StopInstanceRequest, WarmPool,
 };
+use faas_usage_tracker::{StoredKind, UsageBreakdown};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
+use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tower_http::cors::CorsLayer;
-use tracing::{error, info, warn};
+use tracing::{error, info, warn, Instrument};
+use tracing_subscriber::prelude::*;
 use uuid::Uuid;
 mod streaming;
 #[cfg(test)]
@@ -33,27 +94,99 @@ mod types;
 // Health check response
 #[derive(Debug, Serialize)]
 struct HealthResponse {
+    /// `healthy`, `degraded` when a runtime or warm pool is failing, or `unhealthy` (503)
+    /// when no runtime can take executions
     status: String,
+    timestamp: String,
     docker: bool,
     firecracker: bool,
+    /// Each runtime's probe result, with its version or why it is unavailable
+    components: HostHealth,
+    /// Executions can ask for GPUs
+    gpu: bool,
     uptime_ms: u64,
+    /// Environments whose warm-pool canary is failing
+    degraded_environments: Vec<String>,
+    active_kill_switches: Vec<KillSwitchRule>,
+}
+
+/// What this host can run, for schedulers placing work across the fleet
+#[derive(Debug, Serialize)]
+struct CapabilitiesResponse {
+    os: String,
+    arch: String,
+    docker: bool,
+    firecracker: bool,
+    gpu: bool,
+    degraded_environments: Vec<String>,
 }
 
 // Consolidated execute request - single source of truth
 #[derive(Debug, Serialize, Deserialize)]
 struct ExecuteRequest {
+    /// Left out only by forks, whose branches carry their own
+    #[serde(default)]
     command: String,
     image: Option<String>,
     runtime: Option<Runtime>,
+    /// `hardware` sends an `auto` execution to a Firecracker VM where the host has KVM
+    isolation: Option<IsolationLevel>,
     mode: Option<String>, // ephemeral, cached, checkpointed, branched, persistent
     timeout_ms: Option<u64>,
     memory_mb: Option<u32>,
     cpu_cores: Option<u8>,
-    env_vars: Option<Vec<(String, String)>>,
+    env_vars: Option<Vec<EnvVar>>,
+    /// Created when the image lacks it
     working_dir: Option<String>,
+    /// `uid[:gid]` or a user name from the image to run the command as; Docker only
+    user: Option<String>,
+    /// Cached only: where the result is kept; derived from the request when unset
     cache_key: Option<String>,
+    /// Cached only: run even if a result is cached, and cache the new one
+    no_cache: Option<bool>,
     snapshot_id: Option<String>,
     branch_from: Option<String>,
+    ulimits: Option<Vec<Ulimit>>,
+    shm_size_mb: Option<u64>,
+    tmpfs: Option<Vec<TmpfsMount>>,
+    /// Preferred CPU architecture (`amd64`/`x86_64`, `arm64`/`aarch64`)
+    arch: Option<String>,
+    /// GPUs passed through to the container; Docker only
+    gpu: Option<GpuRequest>,
+    /// Shorthand for `gpu: {"count": n}`
+    gpu_count: Option<u32>,
+    /// `false` leaves the sandbox only loopback
+    network_enabled: Option<bool>,
+    /// Host names or IPs the sandbox may reach, and nothing else; Docker only
+    allowed_hosts: Option<Vec<String>>,
+    /// Stdin for the command; a byte array or a base64 string
+    #[serde(default, deserialize_with = "payloads::inline_payload")]
+    payload: Option<Vec<u8>>,
+    /// Hash of a payload uploaded to `/api/v1/payloads`, instead of `payload`
+    payload_ref: Option<String>,
+    /// `[path, contents]` pairs copied into the sandbox before the command runs; contents
+    /// like `payload`, relative paths from `working_dir`
+    #[serde(default, deserialize_with = "payloads::input_files")]
+    input_files: Option<Vec<(String, Vec<u8>)>>,
+    /// Execution group this run reports to
+    group_id: Option<String>,
+    /// Fork only: create a group for the variants
+    group: Option<CreateGroupRequest>,
+    /// Names this run within its group, for comparing it against a baseline run
+    item_key: Option<String>,
+    /// Timezone, locale and fake clock for the sandbox
+    environment_overrides: Option<EnvOverrides>,
+    /// Free-form labels kill switch rules can select on
+    labels: Option<BTreeMap<String, String>>,
+    /// `{"speculative": {...}}` races a second runtime against `preferred`
+    execution_strategy: Option<ExecutionStrategy>,
+    /// The command may run more than once; required for speculation
+    #[serde(default)]
+    idempotent: bool,
+    /// Persistent only: seconds until the execution is reaped unless its lease is extended
+    ttl_secs: Option<u64>,
+    /// Requests repeating a key get the first one's answer instead of running again
+    idempotency_key: Option<String>,
 }
 
 #[derive(Clone)]
@@ -63,24 +196,81 @@ struct AppState {
     snapshots: Arc<DashMap<String, Snapshot>>,
     metrics: Arc<Metrics>,
     streaming: Arc<streaming::StreamingManager>,
+    limits: Arc<LimitsPolicy>,
+    artifacts: Arc<ArtifactStore>,
+    logs: Arc<LogStore>,
+    /// Env and cwd carried between execs, keyed by instance id
+    sessions: Arc<DashMap<String, SessionState>>,
+    redaction: Arc<RedactionRules>,
+    groups: Arc<GroupRegistry>,
+    /// Default for persistent instances left running when a drain's grace period ends
+    drain_policy: InstancePolicy,
+    /// How long persistent executions are kept without a lease extension
+    instance_ttl: TtlPolicy,
+    /// Answers to executions sent with an idempotency key
+    idempotency: Arc<IdempotencyCache>,
+    /// Keys requests must carry; none configured leaves the gateway open
+    api_keys: Arc<ApiKeys>,
+    snapshot_backend: Arc<dyn SnapshotBackend>,
+    snapshot_quota: SnapshotQuota,
+    payloads: Arc<PayloadStore>,
+    kill_switch: Arc<KillSwitch>,
+    kv: Arc<KvStore>,
+    /// Warm pools of frequently restored snapshots
+    promotion: Arc<PromotionTracker>,
+    /// Storage and transfer per tenant
+    usage: Arc<UsageMeter>,
+    /// Workflows, groups and fork parents, with the work running under them
+    cancels: Arc<CancelRegistry>,
+    /// The tenant each execution, and each checkpoint one left, belongs to
+    execution_owners: Arc<ExecutionOwners>,
+    events: Arc<EventBus>,
+    batch_limits: BatchLimits,
+    /// Ranges an execute request's timeout, memory and CPUs must fall in
+    bounds: RequestBounds,
+    jobs: Arc<JobStore>,
+    schedules: Arc<ScheduleStore>,
+    /// Recent probe results for `/health` and `/api/v1/capabilities`
+    health: Arc<HealthProbe>,
+    /// Containers left behind by an earlier run of this gateway
+    orphans: Arc<Orphans>,
+    /// How long a stopped instance's processes get to exit before they are killed
+    stop_grace: Duration,
 }
 
 #[derive(Default)]
 struct Metrics {
     total_requests: std::sync::atomic::AtomicU64,
     cache_hits: std::sync::atomic::AtomicU64,
-    docker_executions: std::sync::atomic::AtomicU64,
-    vm_executions: std::sync::atomic::AtomicU64,
+    /// Latencies and failures of every execution that got to run
+    executions: metrics::Collector,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_env_filter("info,faas_gateway_server=debug")
-        .init();
+    #[cfg(feature = "otel")]
+    let tracer_provider = telemetry::otlp_provider()?;
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            "info,faas_gateway_server=debug",
+        ))
+        .with(tracing_subscriber::fmt::layer());
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(tracer_provider.as_ref().map(telemetry::layer));
+    subscriber.init();
+    // Execution containers created before this are a previous run's
+    let booted = chrono::Utc::now().timestamp();
 
+    // A config that can't work stops the boot before anything is started
+    let config = match startup::platform_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+    };
     // Initialize the consolidated executor
-    let executor = Arc::new(platform::executor::Executor::new().await?);
+    let executor = Arc::new(platform::executor::Executor::new(config).await?);
 
     info!("✅ FaaS Gateway initialized with dual runtime support");
 
@@ -98,24 +288,372 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("✅ Blueprint SDK integration enabled");
 
+    let snapshot_backend: Arc<dyn SnapshotBackend> = executor.docker_snapshots().clone();
+    let (events, event_log) = EventBus::from_env()?;
+    tokio::spawn(event_log.run());
+    let health = Arc::new(HealthProbe::new(executor.docker()));
+    let orphans = Arc::new(Orphans::new(executor.container_pool().docker(), booted));
+    let instances = Arc::new(DashMap::new());
+    let reclaimed = orphans.reclaim(&instances).await;
+    match &reclaimed.error {
+        Some(e) => warn!("Could not look for containers left behind: {}", e),
+        None => info!(
+            "Containers left behind: {} removed, {} instances adopted, {} left stopped, {} failed",
+            reclaimed.removed.len(),
+            reclaimed.adopted.len(),
+            reclaimed.left.len(),
+            reclaimed.failed.len()
+        ),
+    }
+    let logs = Arc::new(LogStore::from_env()?);
+    let api_keys = Arc::new(ApiKeys::from_env()?);
+    let execution_owners = ExecutionOwners::default()
+        .with_dir(logs.root())?
+        .checked(api_keys.is_enabled());
     let state = AppState {
         executor,
-        instances: Arc::new(DashMap::new()),
+        instances,
         snapshots: Arc::new(DashMap::new()),
         metrics: Arc::new(Metrics::default()),
         streaming: Arc::new(streaming::StreamingManager::new()),
+        limits: Arc::new(LimitsPolicy::default()),
+        artifacts: Arc::new(ArtifactStore::from_env()?),
+        logs,
+        sessions: Arc::new(DashMap::new()),
+        redaction: Arc::new(RedactionRules::from_env()),
+        groups: Arc::new(GroupRegistry::new(Arc::new(HttpWebhookSink::new()))),
+        drain_policy: InstancePolicy::from_env(),
+        instance_ttl: TtlPolicy::from_env(),
+        stop_grace: Duration::from_secs(
+            std::env::var("FAAS_INSTANCE_STOP_GRACE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+        ),
+        idempotency: Arc::new(IdempotencyCache::from_env()),
+        api_keys,
+        snapshot_backend,
+        snapshot_quota: SnapshotQuota::from_env(),
+        payloads: Arc::new(PayloadStore::from_env()?),
+        kill_switch: Arc::new(KillSwitch::new()),
+        kv: Arc::new(KvStore::from_env()?),
+        promotion: Arc::new(PromotionTracker::new(PromotionPolicy::from_env())),
+        usage: Arc::new(UsageMeter::from_env().await?),
+        batch_limits: BatchLimits::from_env(),
+        bounds: RequestBounds::from_env(),
+        jobs: Arc::new(JobStore::from_env()),
+        schedules: Arc::new(ScheduleStore::from_env()?),
+        cancels: Arc::new(CancelRegistry::new()),
+        execution_owners: Arc::new(execution_owners),
+        events: Arc::new(events),
+        health,
+        orphans,
     };
 
+    if let Some(sink) = WebhookAlertSink::from_env() {
+        state
+            .executor
+            .container_pool()
+            .canaries()
+            .add_sink(Arc::new(sink));
+    }
+
+    spawn_instance_gc(state.clone());
+    spawn_instance_reaper(state.clone());
+    spawn_payload_gc(state.payloads.clone());
+    spawn_usage_rollover(state.usage.clone());
+    spawn_snapshot_recovery(state.clone());
+    spawn_kill_switch_prune(state.kill_switch.clone());
+    spawn_cancel_prune(state.cancels.clone());
+    spawn_job_prune(state.jobs.clone());
+    spawn_kv_prune(state.kv.clone());
+    spawn_snapshot_promotion(state.clone());
+    spawn_log_sweep(state.clone());
+    tokio::spawn(
+        state
+            .schedules
+            .clone()
+            .run(Arc::new(PlatformScheduleRunner(state.clone()))),
+    );
+
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
     info!("🚀 FaaS Gateway listening on {}", addr);
 
-    let app = create_app(state, blueprint_router);
+    let app = create_app(state.clone(), blueprint_router);
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let stop_accepting = tokio_util::sync::CancellationToken::new();
+    let mut server = tokio::spawn(
+        axum::serve(listener, app)
+            .with_graceful_shutdown(stop_accepting.clone().cancelled_owned())
+            .into_future(),
+    );
+
+    let signalled = tokio::select! {
+        served = &mut server => {
+            served??;
+            false
+        }
+        _ = shutdown::signal() => true,
+    };
+    if signalled {
+        drain_for_shutdown(&state, stop_accepting, server).await;
+    }
 
+    #[cfg(feature = "otel")]
+    if let Some(provider) = tracer_provider {
+        // Flush the spans still batched
+        let _ = provider.shutdown();
+    }
     Ok(())
 }
 
+/// Stop accepting connections, close WebSocket clients and give running executions the
+/// drain timeout before cancelling them
+async fn drain_for_shutdown(
+    state: &AppState,
+    stop_accepting: tokio_util::sync::CancellationToken,
+    server: tokio::task::JoinHandle<std::io::Result<()>>,
+) {
+    info!("Shutting down: refusing new connections and draining executions");
+    stop_accepting.cancel();
+    state.streaming.close_all();
+
+    let report = shutdown::drain_executions(&state.executor, ShutdownPolicy::from_env()).await;
+    if report.settled {
+        info!(
+            "All executions ended ({} cancelled)",
+            report.cancelled.len()
+        );
+    } else {
+        warn!(
+            "Exiting with executions still tearing down after {:?}",
+            shutdown::TEARDOWN_TIMEOUT
+        );
+    }
+    if tokio::time::timeout(shutdown::TEARDOWN_TIMEOUT, server)
+        .await
+        .is_err()
+    {
+        warn!("Connections still open after shutdown, closing them");
+    }
+}
+
+/// Periodically drop stopped and lost instances once `FAAS_INSTANCE_RETENTION_SECS` has passed.
+fn spawn_instance_gc(state: AppState) {
+    let retention_secs = std::env::var("FAAS_INSTANCE_RETENTION_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3600);
+    let retention = chrono::Duration::seconds(retention_secs);
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tick.tick().await;
+            for id in lifecycle::sweep_instances(&state.instances, retention, chrono::Utc::now()) {
+                state.sessions.remove(&id);
+                info!("Garbage-collected instance: {}", id);
+                state
+                    .events
+                    .publish(PlatformEvent::VmReaped { instance_id: id });
+            }
+        }
+    });
+}
+
+/// Stop instances whose lease ran out, telling their stream clients the container is gone.
+fn spawn_instance_reaper(state: AppState) {
+    tokio::spawn(async move {
+        let containers = state.executor.instance_containers();
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tick.tick().await;
+            let reaped = instance_ttl::reap(&state.instances, &containers, chrono::Utc::now());
+            for reaped in reaped.await {
+                let id = reaped.instance.id;
+                info!("Reaped expired instance: {}", id);
+                state.sessions.remove(&id);
+                for stream in std::iter::once(&id).chain(&reaped.container_id) {
+                    // The exit status of a killed process
+                    state
+                        .streaming
+                        .emit_event(stream, streaming::StreamEvent::Exit { code: 137 });
+                    state.streaming.remove_stream(stream);
+                }
+                state.events.publish(PlatformEvent::InstanceStateChanged {
+                    instance_id: id,
+                    from: Some(reaped.was),
+                    to: InstanceState::Stopped,
+                });
+            }
+        }
+    });
+}
+
+/// Delete logs older than `FAAS_LOG_RETENTION_SECS` and bill tenants for the rest.
+fn spawn_log_sweep(state: AppState) {
+    let retention_secs = std::env::var("FAAS_LOG_RETENTION_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(7 * 24 * 3600);
+    let retention = Duration::from_secs(retention_secs);
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(300));
+        loop {
+            tick.tick().await;
+            state.execution_owners.forget_older_than(retention);
+            match state.logs.sweep(retention).await {
+                Ok(kept) => {
+                    state
+                        .usage
+                        .set_retained_logs(&kept, chrono::Utc::now())
+                        .await
+                }
+                Err(e) => warn!("Log retention sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Reload snapshots committed before this gateway started, so they can be listed and restored
+fn spawn_snapshot_recovery(state: AppState) {
+    tokio::spawn(async move {
+        match snapshot_jobs::recover(state.snapshot_backend.as_ref(), &state.snapshots).await {
+            Ok(0) => {}
+            Ok(recovered) => info!("Recovered {} committed snapshots", recovered),
+            Err(e) => warn!("Could not recover committed snapshots: {}", e),
+        }
+    });
+}
+
+fn spawn_payload_gc(payloads: Arc<PayloadStore>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tick.tick().await;
+            let removed = payloads.gc().await;
+            if removed > 0 {
+                info!("Garbage-collected {} unreferenced payloads", removed);
+            }
+        }
+    });
+}
+
+fn spawn_usage_rollover(usage: Arc<UsageMeter>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            tick.tick().await;
+            match usage.roll_over(chrono::Utc::now()).await {
+                Ok(0) => {}
+                Ok(closed) => info!("Closed {} billing periods", closed),
+                Err(e) => warn!("Failed to roll over billing periods: {}", e),
+            }
+        }
+    });
+}
+
+fn spawn_kill_switch_prune(kill_switch: Arc<KillSwitch>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tick.tick().await;
+            kill_switch.prune();
+        }
+    });
+}
+
+fn spawn_cancel_prune(cancels: Arc<CancelRegistry>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tick.tick().await;
+            cancels.prune(cancellation::TOMBSTONE_TTL);
+        }
+    });
+}
+
+fn spawn_job_prune(jobs: Arc<JobStore>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tick.tick().await;
+            jobs.prune();
+        }
+    });
+}
+
+fn spawn_kv_prune(kv: Arc<KvStore>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(30));
+        loop {
+            tick.tick().await;
+            kv.prune();
+        }
+    });
+}
+
+/// Demote snapshots that cooled off and keep the promoted ones' pools stocked
+fn spawn_snapshot_promotion(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            tick.tick().await;
+            let work = state.promotion.tick(Instant::now());
+            apply_promotion_work(&state, work);
+        }
+    });
+}
+
+fn apply_promotion_work(state: &AppState, work: PromotionWork) {
+    let mut drained: BTreeMap<String, i64> = BTreeMap::new();
+    for instance in work.drained {
+        info!("Drained pooled instance {}", instance.id);
+        let pool = instance
+            .name
+            .as_deref()
+            .and_then(|n| n.strip_prefix("restored-"));
+        *drained
+            .entry(pool.unwrap_or_default().to_string())
+            .or_default() -= 1;
+    }
+    for (pool, delta) in drained {
+        state
+            .events
+            .publish(PlatformEvent::PoolScaled { pool, delta });
+    }
+    for (snapshot_id, needed) in work.refill {
+        let mut stocked = 0;
+        for _ in 0..needed {
+            let restored = state
+                .snapshots
+                .get(&snapshot_id)
+                .ok_or(LifecycleError::NotFound)
+                .and_then(|snapshot| restored_instance(&state.events, &snapshot));
+            match restored {
+                Ok(instance) => match state.promotion.stock(&snapshot_id, instance) {
+                    Some(unneeded) => info!(
+                        "Dropped pooled instance {} of demoted snapshot",
+                        unneeded.id
+                    ),
+                    None => stocked += 1,
+                },
+                Err(e) => {
+                    warn!("Cannot pool an instance of snapshot {}: {}", snapshot_id, e);
+                    state.promotion.stock_failed(&snapshot_id);
+                }
+            }
+        }
+        if stocked > 0 {
+            state.events.publish(PlatformEvent::PoolScaled {
+                pool: snapshot_id,
+                delta: stocked,
+            });
+        }
+    }
+}
+
 fn create_app(
     state: AppState,
     blueprint_router: Arc<faas_gateway::blueprint::BackendRouter>,
@@ -124,428 +662,3286 @@ fn create_app(
     let blueprint_state = Arc::new(faas_gateway::blueprint::AppState {
         router: blueprint_router,
     });
+    let admission =
+        axum::middleware::from_fn_with_state(state.executor.drain().clone(), drain::admission);
+    let body_limit = DefaultBodyLimit::max(state.payloads.request_body_limit());
+    let require_api_key =
+        axum::middleware::from_fn_with_state(state.api_keys.clone(), auth::require_api_key);
 
     Router::new()
         // Single consolidated execution endpoint
         .route("/api/v1/execute", post(execute_handler))
+        .route("/api/v1/execute/stream", post(execute_stream_handler))
+        .route("/api/v1/execute/batch", post(execute_batch_handler))
+        .route("/api/v1/jobs", post(submit_job_handler))
+        .route(
+            "/api/v1/jobs/:id",
+            get(get_job_handler).delete(cancel_job_handler),
+        )
+        .route(
+            "/api/v1/schedules",
+            post(create_schedule_handler).get(list_schedules_handler),
+        )
+        .route(
+            "/api/v1/schedules/:id",
+            get(get_schedule_handler).delete(delete_schedule_handler),
+        )
         // Branched execution for A/B testing
         .route("/api/v1/fork", post(fork_execution_handler))
         .route(
             "/api/v1/executions/:id/fork",
             post(fork_from_parent_handler),
         )
+        .route(
+            "/api/v1/executions/:id/cancel",
+            post(cancel_execution_handler),
+        )
         // Pre-warming for zero cold starts
         .route("/api/v1/prewarm", post(prewarm_handler))
         .route("/api/v1/pools", get(list_warm_pools_handler))
+        .route(
+            "/api/v1/pools/:image",
+            axum::routing::put(set_pool_limits_handler),
+        )
+        .route("/api/v1/pools/network", get(vm_network_pool_handler))
+        .route("/api/v1/pools/canaries", get(list_canaries_handler))
+        .route("/api/v1/pools/snapshots", get(promoted_pools_wrapper))
+        .route(
+            "/api/v1/pools/snapshots/:id/pin",
+            axum::routing::put(pin_snapshot_handler),
+        )
+        .route(
+            "/api/v1/pools/:env/canary",
+            get(get_canary_handler)
+                .put(put_canary_handler)
+                .delete(delete_canary_handler),
+        )
         // Snapshot endpoints
         .route("/api/v1/snapshots", post(create_snapshot_handler))
         .route("/api/v1/snapshots", get(list_snapshots_handler))
+        .route(
+            "/api/v1/snapshots/:id",
+            get(get_snapshot_handler).delete(delete_snapshot_handler),
+        )
         .route(
             "/api/v1/snapshots/:id/restore",
             post(restore_snapshot_handler),
         )
+        .route("/api/v1/snapshots/:id/ls", get(snapshot_ls_handler))
+        .route("/api/v1/snapshots/:id/cat", get(snapshot_cat_handler))
+        // Execution groups
+        .route("/api/v1/groups", post(create_group_handler))
+        .route("/api/v1/groups/:id", get(get_group_handler))
+        .route("/api/v1/groups/:id/cancel", post(cancel_group_handler))
+        .route(
+            "/api/v1/groups/:id/comparison",
+            get(group_comparison_wrapper),
+        )
+        .route("/api/v1/normalizers", get(list_normalizers_wrapper))
+        .route(
+            "/api/v1/normalizers/:name",
+            axum::routing::put(put_normalizer_wrapper),
+        )
+        .route("/api/v1/images/:ref/metadata", get(image_metadata_handler))
+        .route("/api/v1/images/:ref/pull", post(pull_image_handler))
         // Instance endpoints
+        .route("/api/v1/volumes", get(list_volumes_handler))
         .route("/api/v1/instances", post(create_instance_handler))
         .route("/api/v1/instances", get(list_instances_handler))
-        .route("/api/v1/instances/:id", get(get_instance_handler))
+        .route(
+            "/api/v1/instances/:id",
+            get(get_instance_handler).delete(delete_instance_handler),
+        )
         .route("/api/v1/instances/:id/exec", post(exec_instance_handler))
+        .route(
+            "/api/v1/instances/:id/ports",
+            post(expose_instance_port_handler),
+        )
         .route("/api/v1/instances/:id/stop", post(stop_instance_handler))
+        .route("/api/v1/instances/:id/pause", post(pause_instance_handler))
+        .route(
+            "/api/v1/instances/:id/ttl",
+            post(extend_instance_ttl_handler),
+        )
+        .route(
+            "/api/v1/instances/:id/resume",
+            post(resume_instance_handler),
+        )
+        .route(
+            "/api/v1/instances/:id/session-state",
+            get(get_session_state_handler).post(restore_session_state_handler),
+        )
+        .route(
+            "/api/v1/instances/:id/files",
+            get(download_instance_files_handler).post(upload_instance_files_handler),
+        )
+        .route("/api/v1/workflows", post(submit_workflow_wrapper))
+        .route(
+            "/api/v1/workflows/:id/cancel",
+            post(cancel_workflow_wrapper),
+        )
+        .route(
+            "/api/v1/cache/:key",
+            axum::routing::delete(invalidate_cache_handler),
+        )
+        .route("/api/v1/kv/:namespace", get(list_kv_wrapper))
+        .route(
+            "/api/v1/kv/:namespace/:key",
+            get(get_kv_wrapper).put(put_kv_wrapper),
+        )
         // Metrics and monitoring
         .route("/api/v1/metrics", get(metrics_handler))
         .route("/api/v1/metrics/detailed", get(detailed_metrics_handler))
+        .route("/metrics", get(prometheus_metrics_handler))
+        // Artifact and log downloads (HTTP Range / If-Range for resume)
+        .route(
+            "/api/v1/artifacts",
+            axum::routing::put(upload_artifact_wrapper),
+        )
+        .route("/api/v1/artifacts/:hash", get(download_artifact_wrapper))
+        .route(
+            "/api/v1/payloads/:hash",
+            axum::routing::head(head_payload_wrapper).put(put_payload_wrapper),
+        )
+        .route("/api/v1/logs/:id", get(download_log_wrapper))
+        .route("/api/v1/executions/:id/logs", get(download_log_wrapper))
+        .route("/api/v1/usage", get(usage_wrapper))
+        .route("/api/v1/accounts/:id/usage", get(account_usage_wrapper))
         // Server-sent events for real-time logs (deprecated, use WebSocket)
         .route("/api/v1/logs/:id/stream", get(stream_logs_handler))
+        .route("/api/v1/events", get(events_wrapper))
         // WebSocket streaming (bidirectional, real-time)
         .route("/api/v1/containers/:id/stream", get(ws_stream_wrapper))
         // Health check with runtime status
         .route("/health", get(health_handler))
+        .route("/api/v1/capabilities", get(capabilities_handler))
+        // Host maintenance
+        .route("/api/v1/admin/drain", post(drain_handler))
+        .route("/api/v1/admin/drain/status", get(drain_status_handler))
+        .route("/api/v1/admin/orphans", get(orphans_handler))
+        .route("/api/v1/admin/undrain", post(undrain_handler))
+        .route(
+            "/api/v1/admin/killswitch",
+            post(activate_kill_switch_handler).get(list_kill_switches_wrapper),
+        )
+        .route(
+            "/api/v1/admin/killswitch/:id",
+            axum::routing::delete(delete_kill_switch_wrapper),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.instances.clone(),
+            tenancy::hide_foreign_instances,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            tenancy::Owners {
+                instances: state.instances.clone(),
+                executions: state.execution_owners.clone(),
+            },
+            tenancy::hide_foreign_executions,
+        ))
+        .layer(admission)
+        .layer(body_limit)
+        .layer(require_api_key.clone())
+        .layer(axum::middleware::from_fn(errors::fill_error_body))
         .layer(CorsLayer::permissive())
         .with_state(state)
         // Merge Blueprint SDK routes
-        .merge(faas_gateway::blueprint::blueprint_routes(blueprint_state))
+        .merge(faas_gateway::blueprint::blueprint_routes(blueprint_state).layer(require_api_key))
+        .layer(axum::middleware::from_fn(telemetry::trace_request))
 }
 
-// Single consolidated execute handler
-async fn execute_handler(
-    State(state): State<AppState>,
-    Json(req): Json<ExecuteRequest>,
-) -> Result<Json<InvokeResponse>, StatusCode> {
-    let start = Instant::now();
+/// The structured error for an execution that failed; see [`ApiError::from_failure`]
+fn failure_response(e: &(dyn std::error::Error + 'static)) -> Response {
+    ApiError::from_failure(e).into_response()
+}
 
-    // Update metrics
-    state
-        .metrics
-        .total_requests
-        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+fn gpu_request(gpu: Option<GpuRequest>, gpu_count: Option<u32>) -> Option<GpuRequest> {
+    gpu.or(gpu_count.map(GpuRequest::count))
+}
+
+fn arch_placement(arch: Option<String>) -> Option<Placement> {
+    arch.map(|arch| Placement {
+        arch: Some(arch),
+        ..Default::default()
+    })
+}
 
-    // Parse execution mode
-    let mode = req.mode.as_deref().unwrap_or("ephemeral");
-    let platform_mode = match mode {
-        "cached" => platform::executor::Mode::Cached,
-        "checkpointed" => platform::executor::Mode::Checkpointed,
-        "branched" => platform::executor::Mode::Branched,
-        "persistent" => platform::executor::Mode::Persistent,
-        _ => platform::executor::Mode::Ephemeral,
+/// Keep an execution's output for `/api/v1/logs/:id`: all of it from the executor's spill
+/// file when the response was truncated, else what the response carries
+async fn persist_logs(state: &AppState, response: &platform::executor::Response) {
+    let spilled = response
+        .truncation
+        .as_ref()
+        .and_then(|truncation| truncation.log_path.as_deref());
+    let stored = match spilled {
+        Some(path) => state.logs.adopt(&response.id, path).await,
+        None => {
+            let mut captured = response.stdout.clone();
+            captured.extend_from_slice(&response.stderr);
+            state.logs.append(&response.id, &captured).await
+        }
     };
+    if let Err(e) = stored {
+        warn!("Failed to persist logs for {}: {}", response.id, e);
+    }
+}
 
-    // Convert env_vars from Vec to HashMap
-    let env_vars = req.env_vars.map(|vec| {
-        vec.into_iter()
-            .collect::<std::collections::HashMap<String, String>>()
-    });
+/// What [`RequestBounds::check`] looks at in `req`
+fn execute_fields(req: &ExecuteRequest) -> ExecuteFields<'_> {
+    ExecuteFields {
+        command: Some(&req.command),
+        image: req.image.as_deref(),
+        user: req.user.as_deref(),
+        mode: req.mode.as_deref(),
+        timeout_ms: req.timeout_ms,
+        memory_mb: req.memory_mb,
+        cpu_cores: req.cpu_cores,
+    }
+}
 
-    // Create platform request
-    let platform_req = platform::executor::Request {
-        id: Uuid::new_v4().to_string(),
-        code: req.command.clone(),
-        mode: platform_mode,
-        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
-        timeout: Duration::from_millis(req.timeout_ms.unwrap_or(30000)),
-        checkpoint: req.snapshot_id,
-        branch_from: req.branch_from,
-        runtime: req.runtime,
-        env_vars,
+/// Resolve the request's resource overrides against the gateway policy and its key's caps.
+fn resolve_limits(
+    state: &AppState,
+    headers: &HeaderMap,
+    req: &mut ExecuteRequest,
+) -> Result<AppliedLimits, StatusCode> {
+    let caps = LimitCaps::from_headers(headers);
+    state
+        .limits
+        .resolve(
+            caps.as_ref(),
+            req.ulimits.take(),
+            req.shm_size_mb.take(),
+            req.tmpfs.take(),
+        )
+        .map_err(|e| {
+            warn!("Rejected resource limits: {}", e);
+            StatusCode::BAD_REQUEST
+        })
+}
+
+/// The request's clock and locale overrides, if it has any that are valid
+fn resolve_overrides(req: &mut ExecuteRequest) -> Result<Option<EnvOverrides>, StatusCode> {
+    let Some(overrides) = req.environment_overrides.take() else {
+        return Ok(None);
     };
+    overrides.validate().map_err(|e| {
+        warn!("Rejected environment overrides: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    Ok((!overrides.is_empty()).then_some(overrides))
+}
 
-    // Execute using platform executor (it handles runtime selection internally)
-    match state.executor.run(platform_req).await {
-        Ok(response) => {
-            // Check for cache hit (fast response)
-            if start.elapsed().as_millis() < 10 {
-                state
-                    .metrics
-                    .cache_hits
-                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            }
+/// The request's env vars as the request layer; they cannot claim `FAAS_*` keys
+fn resolve_env(req: &mut ExecuteRequest) -> Result<LayeredEnv, Response> {
+    let mut env = LayeredEnv::new();
+    env.set(EnvLayer::Request, req.env_vars.take().unwrap_or_default())
+        .map_err(rejected_env)?;
+    Ok(env)
+}
 
-            Ok(Json(InvokeResponse {
-                request_id: response.id,
-                exit_code: response.exit_code,
-                stdout: String::from_utf8_lossy(&response.stdout).to_string(),
-                stderr: String::from_utf8_lossy(&response.stderr).to_string(),
-                duration_ms: response.duration.as_millis() as u64,
-                output: Some(String::from_utf8_lossy(&response.stdout).to_string()),
-                logs: Some(String::from_utf8_lossy(&response.stderr).to_string()),
-                error: if response.exit_code != 0 {
-                    Some(format!("Process exited with code {}", response.exit_code))
-                } else {
-                    None
-                },
-            }))
+fn rejected_env(e: faas_common::env::EnvError) -> Response {
+    warn!("Rejected env vars: {}", e);
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({ "error": e.to_string() })),
+    )
+        .into_response()
+}
+
+/// The request's input files, once each is known to land inside the sandbox
+fn resolve_input_files(req: &mut ExecuteRequest) -> Result<Vec<(String, Vec<u8>)>, Response> {
+    let files = req.input_files.take().unwrap_or_default();
+    instance_files::check_input_files(&files, req.working_dir.as_deref())
+        .map_err(IntoResponse::into_response)?;
+    Ok(files)
+}
+
+/// The sandbox's network: none, only `allowed_hosts`, or Docker's default bridge
+fn resolve_network(req: &mut ExecuteRequest) -> Result<Option<NetworkPolicy>, Response> {
+    let allowed_hosts = req.allowed_hosts.take();
+    match (req.network_enabled.take(), allowed_hosts) {
+        (Some(false), Some(hosts)) if !hosts.is_empty() => Err(ApiError::invalid_request(
+            "allowed_hosts needs the network enabled",
+        )
+        .with_details(serde_json::json!({ "field": "allowed_hosts" }))
+        .into_response()),
+        (Some(false), _) => Ok(Some(NetworkPolicy::None)),
+        (_, Some(allowed_hosts)) if !allowed_hosts.is_empty() => {
+            Ok(Some(NetworkPolicy::Restricted { allowed_hosts }))
         }
-        Err(e) => {
-            error!("Execution failed: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        (Some(true), _) => Ok(Some(NetworkPolicy::Default)),
+        (None, _) => Ok(None),
+    }
+}
+
+/// Stdin for an execution: the inline bytes, or a stored payload held until the lease drops
+async fn resolve_payload(
+    state: &AppState,
+    req: &mut ExecuteRequest,
+) -> Result<(Vec<u8>, Option<PayloadLease>), PayloadError> {
+    match req.payload_ref.take() {
+        Some(hash) => {
+            let (data, lease) = state.payloads.lease(&hash).await?;
+            Ok((data, Some(lease)))
+        }
+        None => {
+            let payload = req.payload.take().unwrap_or_default();
+            state.payloads.check_inline(&payload)?;
+            Ok((payload, None))
         }
     }
 }
 
-async fn fork_execution_handler(
-    State(state): State<AppState>,
-    Json(req): Json<ExecuteRequest>,
-) -> Result<Json<Vec<InvokeResponse>>, StatusCode> {
-    // Fork execution into multiple variants for A/B testing
-    let mut responses = Vec::new();
-
-    // Convert env_vars from Vec to HashMap
-    let env_vars = req.env_vars.map(|vec| {
-        vec.into_iter()
-            .collect::<std::collections::HashMap<String, String>>()
-    });
+/// What kill switch rules see of an execution request
+fn workload(headers: &HeaderMap, req: &mut ExecuteRequest) -> Workload {
+    Workload {
+        tenant: snapshot_fs::request_tenant(headers),
+        image: req
+            .image
+            .clone()
+            .unwrap_or_else(|| "alpine:latest".to_string()),
+        labels: req.labels.take().unwrap_or_default(),
+        runtime: req.runtime,
+    }
+}
 
-    // Create base request
-    let base_req = platform::executor::Request {
-        id: Uuid::new_v4().to_string(),
-        code: req.command.clone(),
-        mode: platform::executor::Mode::Branched,
-        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
-        timeout: Duration::from_millis(req.timeout_ms.unwrap_or(30000)),
-        checkpoint: None,
-        branch_from: None,
-        runtime: None,
-        env_vars,
-    };
-
-    // Run with different configurations
-    for variant in &["baseline", "optimized"] {
-        let mut variant_req = base_req.clone();
-        variant_req.id = format!("{}-{}", base_req.id, variant);
-
-        match state.executor.run(variant_req.clone()).await {
-            Ok(response) => {
-                responses.push(InvokeResponse {
-                    request_id: response.id,
-                    exit_code: response.exit_code,
-                    stdout: String::from_utf8_lossy(&response.stdout).to_string(),
-                    stderr: String::from_utf8_lossy(&response.stderr).to_string(),
-                    duration_ms: response.duration.as_millis() as u64,
-                    output: Some(String::from_utf8_lossy(&response.stdout).to_string()),
-                    logs: Some(String::from_utf8_lossy(&response.stderr).to_string()),
-                    error: None,
-                });
-            }
-            Err(e) => {
-                error!("Fork variant {} failed: {}", variant, e);
+/// Why an execution stopped short of finishing
+enum Stopped {
+    KillSwitch(KillSwitchHit),
+    Cancelled {
+        id: String,
+        cancellation: Cancellation,
+    },
+}
+
+impl Stopped {
+    fn into_step_error(self) -> faas_common::workflow::StepError {
+        match self {
+            Self::KillSwitch(hit) => faas_common::workflow::StepError::Failed(hit.to_string()),
+            Self::Cancelled { id, cancellation } => {
+                workflows::step_error(CancelError::Cancelled { id, cancellation })
             }
         }
     }
-
-    Ok(Json(responses))
 }
 
-async fn fork_from_parent_handler(
-    State(state): State<AppState>,
-    Path(parent_id): Path<String>,
-    Json(req): Json<ExecuteRequest>,
-) -> Result<Json<InvokeResponse>, StatusCode> {
-    // Convert env_vars from Vec to HashMap
-    let env_vars = req.env_vars.map(|vec| {
-        vec.into_iter()
-            .collect::<std::collections::HashMap<String, String>>()
-    });
-
-    let platform_req = platform::executor::Request {
-        id: Uuid::new_v4().to_string(),
-        code: req.command.clone(),
-        mode: platform::executor::Mode::Branched,
-        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
-        timeout: Duration::from_millis(req.timeout_ms.unwrap_or(30000)),
-        checkpoint: None,
-        branch_from: Some(parent_id),
-        runtime: None,
-        env_vars,
-    };
+impl std::fmt::Display for Stopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KillSwitch(hit) => hit.fmt(f),
+            Self::Cancelled { cancellation, .. } => {
+                write!(f, "cancelled from {}", cancellation.source)
+            }
+        }
+    }
+}
 
-    match state.executor.run(platform_req).await {
-        Ok(response) => Ok(Json(InvokeResponse {
-            request_id: response.id,
-            exit_code: response.exit_code,
-            stdout: String::from_utf8_lossy(&response.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&response.stderr).to_string(),
-            duration_ms: response.duration.as_millis() as u64,
-            output: Some(String::from_utf8_lossy(&response.stdout).to_string()),
-            logs: Some(String::from_utf8_lossy(&response.stderr).to_string()),
-            error: None,
-        })),
-        Err(e) => {
-            error!("Fork from parent failed: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+impl IntoResponse for Stopped {
+    fn into_response(self) -> Response {
+        match self {
+            Self::KillSwitch(hit) => hit.into_response(),
+            Self::Cancelled { id, cancellation } => {
+                CancelError::Cancelled { id, cancellation }.into_response()
+            }
         }
     }
 }
 
-async fn prewarm_handler(
-    State(_state): State<AppState>,
-    Json(req): Json<PrewarmRequest>,
-) -> Result<StatusCode, StatusCode> {
-    info!(
-        "Pre-warming {} containers for image {}",
-        req.count, req.image
-    );
-    // Platform executor handles container pooling internally
-    Ok(StatusCode::OK)
+/// Start `req` unless it was cancelled while pending, and run it until it finishes, a
+/// kill switch cancels it or a cascade cancel reaches `scope`. A stopped run has its
+/// containers removed.
+async fn run_killable(
+    state: &AppState,
+    run: &RunGuard,
+    scope: &CancelScope,
+    req: platform::executor::Request,
+) -> Result<anyhow::Result<platform::executor::Response>, Stopped> {
+    let supervised = Supervised::of(&req);
+    let executor = state.executor.clone();
+    supervise(state, run, scope, supervised, async move {
+        executor.run(req).await
+    })
+    .await
 }
 
-async fn list_warm_pools_handler(
-    State(_state): State<AppState>,
-) -> Result<Json<Vec<String>>, StatusCode> {
-    // Return list of pre-warmed pools
-    Ok(Json(vec!["alpine:latest".to_string()]))
+/// An execution as [`supervise`] reports it in events and metrics
+struct Supervised {
+    id: String,
+    runtime: Option<faas_common::Runtime>,
+    image: String,
+    tenant: Option<String>,
 }
 
-async fn create_snapshot_handler(
+impl Supervised {
+    fn of(req: &platform::executor::Request) -> Self {
+        Self {
+            id: req.id.clone(),
+            runtime: req.runtime,
+            image: req.env.clone(),
+            tenant: req.tenant.clone(),
+        }
+    }
+}
+
+/// Drive `execution` until it finishes or the kill switch or a cancellation stops it, in
+/// which case it is cancelled in the executor. It runs in its own task, so a client that
+/// disconnects mid-request cancels it as well.
+async fn supervise(
+    state: &AppState,
+    run: &RunGuard,
+    scope: &CancelScope,
+    supervised: Supervised,
+    execution: impl std::future::Future<Output = anyhow::Result<platform::executor::Response>>
+        + Send
+        + 'static,
+) -> Result<anyhow::Result<platform::executor::Response>, Stopped> {
+    if let Err(CancelError::Cancelled { id, cancellation }) = scope.start() {
+        return Err(Stopped::Cancelled { id, cancellation });
+    }
+    let Supervised {
+        id,
+        runtime,
+        image,
+        tenant,
+    } = supervised;
+    let started = Instant::now();
+    state.events.publish(PlatformEvent::ExecutionStarted {
+        execution_id: id.clone(),
+        runtime,
+    });
+    let finished = |outcome, exit_code| PlatformEvent::ExecutionFinished {
+        execution_id: id.clone(),
+        outcome,
+        exit_code,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    let mut abandoned = CancelOnDrop::new(state.executor.clone(), id.clone());
+    let execution = tokio::spawn(execution.in_current_span());
+    let stopped = tokio::select! {
+        biased;
+        hit = run.cancelled() => Stopped::KillSwitch(hit),
+        cancellation = scope.cancelled() => Stopped::Cancelled {
+            id: scope.id().to_string(),
+            cancellation,
+        },
+        joined = execution => {
+            abandoned.disarm();
+            let result = joined
+                .unwrap_or_else(|e| Err(anyhow::anyhow!("Execution task failed: {e}")));
+            state.events.publish(match &result {
+                Ok(response) => finished(ExecutionOutcome::Completed, Some(response.exit_code)),
+                Err(_) => finished(ExecutionOutcome::Failed, None),
+            });
+            record_latency(state, &image, tenant.as_deref(), runtime, &result);
+            return Ok(result);
+        }
+    };
+    state.events.publish(match stopped {
+        Stopped::KillSwitch(_) => finished(ExecutionOutcome::KilledBySwitch, None),
+        Stopped::Cancelled { .. } => finished(ExecutionOutcome::Cancelled, None),
+    });
+    abandoned.disarm();
+    match state.executor.cancel(&id).await {
+        Ok(_) => info!("Cancelled {}, {}", id, stopped),
+        Err(e) => warn!("Failed to cancel stopped execution {}: {}", id, e),
+    }
+    Err(stopped)
+}
+
+fn record_latency(
+    state: &AppState,
+    image: &str,
+    tenant: Option<&str>,
+    requested: Option<faas_common::Runtime>,
+    result: &anyhow::Result<platform::executor::Response>,
+) {
+    let response = match result {
+        Ok(response) => response,
+        Err(_) => return state.metrics.executions.record_error(image, tenant),
+    };
+    let runtime = response
+        .runtime_decision
+        .as_ref()
+        .map(|decision| decision.runtime)
+        .or(requested)
+        .unwrap_or(faas_common::Runtime::Docker);
+    state.metrics.executions.record(metrics::Execution {
+        image,
+        tenant,
+        runtime: match runtime {
+            faas_common::Runtime::Firecracker => "firecracker",
+            faas_common::Runtime::Docker | faas_common::Runtime::Auto => "docker",
+        },
+        start: if response.warm_start {
+            metrics::Start::Warm
+        } else {
+            metrics::Start::Cold
+        },
+        duration: response.duration,
+        succeeded: response.exit_code == 0,
+    });
+}
+
+fn join_group(
+    state: &AppState,
+    scope: &Scope,
+    group_id: Option<&str>,
+    execution_id: &str,
+) -> Result<(), StatusCode> {
+    let Some(group_id) = group_id else {
+        return Ok(());
+    };
+    if !state.execution_owners.reachable(group_id, scope) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    state.groups.join(group_id, execution_id).map_err(|e| {
+        warn!("Execution {} cannot join group: {}", execution_id, e);
+        match e {
+            GroupError::NotFound(_) => StatusCode::NOT_FOUND,
+            GroupError::Settled(_) | GroupError::Full(_) | GroupError::Cancelled(_) => {
+                StatusCode::CONFLICT
+            }
+        }
+    })
+}
+
+/// Give the execution its KV namespace: its group's, or its tenant's default without one.
+/// The token works until the returned grant is dropped.
+fn grant_kv(
+    state: &AppState,
+    headers: &HeaderMap,
+    group_id: Option<&str>,
+    env: &mut LayeredEnv,
+) -> KvGrant {
+    let namespace = group_id.map_or_else(
+        || kv::DEFAULT_NAMESPACE.to_string(),
+        |id| kv::scoped_namespace("group", id),
+    );
+    let grant = state
+        .kv
+        .grant(snapshot_fs::request_tenant(headers).as_deref(), &namespace);
+    env.set(EnvLayer::Platform, grant.env())
+        .expect("kv env vars are valid platform vars");
+    grant
+}
+
+/// Send the group's webhook in the background, if something just settled it
+fn notify_settlement(state: &AppState, settlement: Option<Settlement>) {
+    if let Some(settlement) = settlement {
+        let groups = state.groups.clone();
+        tokio::spawn(async move { groups.notify(settlement).await });
+    }
+}
+
+/// Record the outcome and send the group's webhook in the background if this settled it.
+fn finish_group(
+    state: &AppState,
+    group_id: Option<&str>,
+    execution_id: &str,
+    succeeded: bool,
+    duration_ms: Option<u64>,
+) {
+    let Some(group_id) = group_id else {
+        return;
+    };
+    let settlement = state
+        .groups
+        .finish(group_id, execution_id, succeeded, duration_ms);
+    notify_settlement(state, settlement);
+}
+
+/// Record a member that stopped early: cancelled if a cascade cancel reached it, failed if
+/// a kill switch stopped it
+fn stop_group_member(
+    state: &AppState,
+    group_id: Option<&str>,
+    execution_id: &str,
+    stopped: &Stopped,
+) {
+    let Some(group_id) = group_id else {
+        return;
+    };
+    let settlement = match stopped {
+        Stopped::KillSwitch(_) => state.groups.finish(group_id, execution_id, false, None),
+        Stopped::Cancelled { cancellation, .. } => {
+            state
+                .groups
+                .cancel_member(group_id, execution_id, cancellation)
+        }
+    };
+    notify_settlement(state, settlement);
+}
+
+/// Keep what a member printed so the group can later be compared against a baseline.
+fn record_group_output(
+    state: &AppState,
+    group_id: Option<&str>,
+    execution_id: &str,
+    item_key: Option<&str>,
+    result: &anyhow::Result<platform::executor::Response>,
+) {
+    if let (Some(group_id), Ok(response)) = (group_id, result) {
+        state.groups.record_output(
+            group_id,
+            execution_id,
+            item_key,
+            response.exit_code,
+            &response.stdout,
+        );
+    }
+}
+
+/// Create a group for `scope`'s tenant along with the node its members register under for
+/// cascade cancels. Its baseline has to be within reach too.
+fn create_group(
+    state: &AppState,
+    scope: &Scope,
+    req: CreateGroupRequest,
+) -> Result<GroupSummary, ApiError> {
+    if let Some(baseline) = req
+        .baseline_group_id
+        .as_deref()
+        .filter(|id| !state.execution_owners.reachable(id, scope))
+    {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "NotFound",
+            format!("execution group {baseline} not found"),
+        ));
+    }
+    let summary = state.groups.create(req);
+    state
+        .execution_owners
+        .record(&summary.group_id, scope.tenant());
+    state.cancels.open(&summary.group_id);
+    Ok(summary)
+}
+
+async fn create_group_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<CreateGroupRequest>,
+) -> Result<Json<GroupSummary>, ApiError> {
+    create_group(&state, &Scope::from_headers(&headers), req).map(Json)
+}
+
+/// Cancel a group's members as the body's policy says, or the group's own policy without
+/// one. The group takes no new members afterwards and settles once none is running.
+async fn cancel_group_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<CancelRequest>>,
+) -> Result<Json<GroupSummary>, Response> {
+    let group = state
+        .groups
+        .get(&id)
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    let policy = body
+        .and_then(|Json(req)| req.policy)
+        .unwrap_or(group.cancel_policy);
+    let report = state
+        .cancels
+        .cancel(&id, policy)
+        .map_err(IntoResponse::into_response)?;
+    let (summary, settlement) = state
+        .groups
+        .cancel(&id, &report)
+        .map_err(|_| StatusCode::NOT_FOUND.into_response())?;
+    notify_settlement(&state, settlement);
+    Ok(Json(summary))
+}
+
+/// Cancel an execution or fork parent and the branches running under it
+async fn cancel_execution_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<CancelRequest>>,
+) -> Result<Json<CancelReport>, CancelError> {
+    let policy = body.and_then(|Json(req)| req.policy).unwrap_or_default();
+    state.cancels.cancel(&id, policy).map(Json)
+}
+
+async fn get_group_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<GroupSummary>, StatusCode> {
+    state.groups.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+// Single consolidated execute handler
+async fn execute_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<ExecuteRequest>,
+) -> Result<Json<InvokeResponse>, Response> {
+    execute(state, headers, req, None).await
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchExecuteRequest {
+    requests: Vec<ExecuteRequest>,
+    /// How many run at once, up to the gateway's cap
+    #[serde(default)]
+    max_parallelism: Option<usize>,
+}
+
+/// Run every execution in a batch; see [`batch`]
+async fn execute_batch_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<BatchExecuteRequest>,
+) -> Result<Response, ApiError> {
+    let parallelism = state
+        .batch_limits
+        .parallelism(req.requests.len(), req.max_parallelism)?;
+    let item_headers = headers.clone();
+    let items = batch::run(req.requests, parallelism, move |item| {
+        execution_outcome(state.clone(), item_headers.clone(), item, None)
+    });
+    Ok(batch::respond(&headers, items).await)
+}
+
+#[derive(Debug, Deserialize)]
+struct JobRequest {
+    #[serde(flatten)]
+    execute: ExecuteRequest,
+    /// Where the finished job is POSTed
+    #[serde(default)]
+    callback_url: Option<String>,
+}
+
+/// The background job an execution runs as; see [`jobs`]
+struct JobRun {
+    id: String,
+    /// Output of an execution that streams, as it is written
+    output: tokio::sync::mpsc::UnboundedSender<faas_executor::OutputChunk>,
+}
+
+/// Queue an execution as a background job; its first state comes back at once
+async fn submit_job_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<JobRequest>,
+) -> Result<(StatusCode, Json<Job>), Response> {
+    state
+        .bounds
+        .check(&execute_fields(&req.execute))
+        .map_err(IntoResponse::into_response)?;
+    let job_id = Uuid::new_v4().to_string();
+    // The job's node: its execution registers below it, so cancelling the job stops that
+    let scope = state
+        .cancels
+        .register(&job_id, req.execute.group_id.as_deref())
+        .map_err(IntoResponse::into_response)?;
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let job = state
+        .jobs
+        .submit(job_id.clone(), tenant.as_deref(), req.callback_url)
+        .map_err(IntoResponse::into_response)?;
+
+    let (output, logs) = tokio::sync::mpsc::unbounded_channel();
+    let run = JobRun {
+        id: job_id.clone(),
+        output,
+    };
+    let execution = execution_outcome(state.clone(), headers, req.execute, Some(run));
+    state.jobs.spawn(job_id, scope, logs, execution);
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+fn job_not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "JobNotFound",
+        format!("job {id} not found"),
+    )
+}
+
+async fn get_job_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, ApiError> {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    state
+        .jobs
+        .get(&id, tenant.as_deref())
+        .map(Json)
+        .ok_or_else(|| job_not_found(&id))
+}
+
+/// Cancel a job, removing its execution's containers; the job is `cancelled` once they're
+/// gone
+async fn cancel_job_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, ApiError> {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let job = state
+        .jobs
+        .get(&id, tenant.as_deref())
+        .ok_or_else(|| job_not_found(&id))?;
+    if !job.status.is_finished() {
+        // A job that finished meanwhile has no node left, and nothing to cancel
+        let _ = state.cancels.cancel(&id, CancelPolicy::All);
+    }
+    Ok(Json(state.jobs.get(&id, tenant.as_deref()).unwrap_or(job)))
+}
+
+/// Schedule an execute request; see [`schedules`](faas_gateway_server::schedules)
+async fn create_schedule_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(spec): Json<ScheduleSpec>,
+) -> Result<(StatusCode, Json<Schedule>), ApiError> {
+    // Refused now rather than at every run
+    let request =
+        serde_json::from_value::<ExecuteRequest>(serde_json::Value::Object(spec.request.clone()))
+            .map_err(|e| ApiError::invalid_request(format!("invalid execute request: {e}")))?;
+    state.bounds.check(&execute_fields(&request))?;
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let schedule = state.schedules.create(tenant.as_deref(), spec)?;
+    Ok((StatusCode::CREATED, Json(schedule)))
+}
+
+async fn list_schedules_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Json<Vec<Schedule>> {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    Json(state.schedules.list(tenant.as_deref()))
+}
+
+fn schedule_not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "ScheduleNotFound",
+        format!("schedule {id} not found"),
+    )
+}
+
+async fn get_schedule_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Schedule>, ApiError> {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    state
+        .schedules
+        .get(&id, tenant.as_deref())
+        .map(Json)
+        .ok_or_else(|| schedule_not_found(&id))
+}
+
+async fn delete_schedule_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Schedule>, ApiError> {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    state
+        .schedules
+        .delete(&id, tenant.as_deref())
+        .map(Json)
+        .ok_or_else(|| schedule_not_found(&id))
+}
+
+/// Cached results are kept per tenant; requests without one share a namespace of their own,
+/// so they can't name an entry a tenant will read
+fn cache_namespace(tenant: Option<&str>) -> &str {
+    tenant.unwrap_or_default()
+}
+
+/// Drop a cached result, so the next cached run under its key executes again
+async fn invalidate_cache_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(key): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    if state
+        .executor
+        .invalidate_cached(Some(cache_namespace(tenant.as_deref())), &key)
+        .await
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "CacheEntryNotFound",
+            format!("no cached result under {key}"),
+        ))
+    }
+}
+
+/// Runs what schedules fire as jobs, under the run's id
+struct PlatformScheduleRunner(AppState);
+
+#[async_trait::async_trait]
+impl ScheduleRunner for PlatformScheduleRunner {
+    async fn run(&self, run: ScheduledRun) -> Result<InvokeResponse, ApiError> {
+        let state = &self.0;
+        let req: ExecuteRequest =
+            serde_json::from_value(serde_json::Value::Object(run.schedule.request))
+                .map_err(|e| ApiError::invalid_request(format!("invalid execute request: {e}")))?;
+        let mut headers = HeaderMap::new();
+        if let Some(tenant) = run.tenant.as_deref().and_then(|t| t.parse().ok()) {
+            headers.insert(snapshot_fs::TENANT_HEADER, tenant);
+        }
+        let scope = match state.cancels.register(&run.run_id, req.group_id.as_deref()) {
+            Ok(scope) => scope,
+            Err(e) => return Err(ApiError::from_response(e.into_response()).await),
+        };
+        state
+            .jobs
+            .submit(run.run_id.clone(), run.tenant.as_deref(), None)?;
+        let (output, logs) = tokio::sync::mpsc::unbounded_channel();
+        let job = JobRun {
+            id: run.run_id.clone(),
+            output,
+        };
+        let execution = execution_outcome(state.clone(), headers, req, Some(job));
+        let job = state
+            .jobs
+            .spawn(run.run_id.clone(), scope, logs, execution)
+            .await
+            .ok()
+            .flatten();
+        match job {
+            Some(job) if job.status == JobStatus::Cancelled => Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "Cancelled",
+                format!("run {} was cancelled", run.run_id),
+            )),
+            Some(Job {
+                response: Some(response),
+                ..
+            }) => Ok(response),
+            Some(Job {
+                error: Some(body), ..
+            }) => Err(ApiError {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                body,
+            }),
+            _ => Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal",
+                format!("run {} ended without an outcome", run.run_id),
+            )),
+        }
+    }
+
+    fn cancel(&self, run_id: &str) {
+        let _ = self.0.cancels.cancel(run_id, CancelPolicy::All);
+    }
+}
+
+/// An execution's answer as a value, for callers that don't answer with it directly
+async fn execution_outcome(
+    state: AppState,
+    headers: HeaderMap,
+    req: ExecuteRequest,
+    job: Option<JobRun>,
+) -> Result<InvokeResponse, ApiError> {
+    match execute(state, headers, req, job).await {
+        Ok(Json(response)) => Ok(response),
+        Err(response) => Err(ApiError::from_response(response).await),
+    }
+}
+
+/// Run an execution, unless one already ran under its idempotency key. One with a key runs
+/// in a task of its own and holds the key until it ends: a client that disconnects doesn't
+/// cancel it, so its retry waits for the answer instead of running part of the command again.
+async fn execute(
+    state: AppState,
+    headers: HeaderMap,
+    mut req: ExecuteRequest,
+    job: Option<JobRun>,
+) -> Result<Json<InvokeResponse>, Response> {
+    let Some(key) = req.idempotency_key.take() else {
+        return run_execution(state, headers, req, job).await;
+    };
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let pending = match state
+        .idempotency
+        .claim(tenant.as_deref(), &key, Instant::now())
+    {
+        Ok(Claim::Run(pending)) => pending,
+        Ok(Claim::Replay(response)) => {
+            info!(
+                "Replaying {} for idempotency key {:?}",
+                response.request_id, key
+            );
+            return Ok(Json(*response));
+        }
+        Err(e) => return Err(e.into_response()),
+    };
+    let run = async move {
+        let response = run_execution(state, headers, req, job).await?;
+        pending.complete(&response, Instant::now());
+        Ok::<_, Response>(response)
+    };
+    tokio::spawn(run.in_current_span())
+        .await
+        .unwrap_or_else(|e| {
+            let e = anyhow::anyhow!("Execution task failed: {e}");
+            Err(failure_response(e.as_ref()))
+        })
+}
+
+async fn run_execution(
+    state: AppState,
+    headers: HeaderMap,
+    mut req: ExecuteRequest,
+    job: Option<JobRun>,
+) -> Result<Json<InvokeResponse>, Response> {
+    let platform_mode = state
+        .bounds
+        .check(&execute_fields(&req))
+        .map_err(IntoResponse::into_response)?;
+    tenancy::check_sources(
+        &state.snapshots,
+        &state.execution_owners,
+        &Scope::from_headers(&headers),
+        req.snapshot_id.as_deref(),
+        req.branch_from.as_deref(),
+    )
+    .map_err(IntoResponse::into_response)?;
+    let limits = resolve_limits(&state, &headers, &mut req).map_err(IntoResponse::into_response)?;
+    let environment_overrides = resolve_overrides(&mut req).map_err(IntoResponse::into_response)?;
+    let mut env = resolve_env(&mut req)?;
+    let input_files = resolve_input_files(&mut req)?;
+    let network = resolve_network(&mut req)?;
+    let (payload, _payload_lease) = resolve_payload(&state, &mut req)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let workload = workload(&headers, &mut req);
+
+    // Update metrics
+    state
+        .metrics
+        .total_requests
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    platform::speculation::validate(
+        req.execution_strategy.as_ref(),
+        platform_mode,
+        req.idempotent,
+    )
+    .map_err(|e| failure_response(&e))?;
+    let tenant = snapshot_fs::request_tenant(&headers);
+    state
+        .usage
+        .admit(
+            tenant.as_deref(),
+            ComputeSize::of(req.cpu_cores, req.memory_mb),
+        )
+        .await
+        .map_err(usage::refusal)?;
+
+    let execution_id = Uuid::new_v4().to_string();
+    let group_id = req.group_id.take();
+    let parent = job
+        .as_ref()
+        .map(|job| job.id.as_str())
+        .or(group_id.as_deref());
+    let scope = state
+        .cancels
+        .register(&execution_id, parent)
+        .map_err(IntoResponse::into_response)?;
+    state
+        .execution_owners
+        .record(&execution_id, tenant.as_deref());
+    let run = state
+        .kill_switch
+        .admit(&execution_id, workload)
+        .map_err(IntoResponse::into_response)?;
+    join_group(
+        &state,
+        &Scope::from_headers(&headers),
+        group_id.as_deref(),
+        &execution_id,
+    )
+    .map_err(IntoResponse::into_response)?;
+    // The KV token differs on every run, so a derived cache key leaves it out
+    let request_env = env.clone().into_map();
+    let _kv = grant_kv(&state, &headers, group_id.as_deref(), &mut env);
+
+    // Create platform request
+    let mut platform_req = platform::executor::Request {
+        id: execution_id.clone(),
+        code: req.command.clone(),
+        mode: platform_mode,
+        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
+        timeout: req
+            .timeout_ms
+            .map_or(state.executor.default_timeout(), Duration::from_millis),
+        checkpoint: req.snapshot_id,
+        branch_from: req.branch_from,
+        runtime: req.runtime,
+        isolation: req.isolation,
+        env_vars: Some(env.clone().into_map()),
+        working_dir: req.working_dir.clone(),
+        user: req.user.clone(),
+        memory_mb: req.memory_mb,
+        cpu_cores: req.cpu_cores.map(f64::from),
+        ulimits: Some(limits.ulimits.clone()),
+        shm_size_mb: Some(limits.shm_size_mb),
+        pids_limit: Some(limits.pids_limit),
+        tmpfs: (!limits.tmpfs.is_empty()).then(|| limits.tmpfs.clone()),
+        placement: arch_placement(req.arch),
+        payload,
+        input_files,
+        read_only_mounts: Vec::new(),
+        gpu: gpu_request(req.gpu, req.gpu_count),
+        network,
+        environment_overrides: environment_overrides.clone(),
+        execution_strategy: req.execution_strategy,
+        idempotent: req.idempotent,
+        cache_key: None,
+        cache_namespace: Some(cache_namespace(tenant.as_deref()).to_string()),
+        tenant: tenant.clone(),
+        no_cache: req.no_cache.unwrap_or(false),
+    };
+    if matches!(platform_mode, platform::executor::Mode::Cached) {
+        let key = req.cache_key.take().unwrap_or_else(|| {
+            platform::executor::Request {
+                env_vars: Some(request_env),
+                ..platform_req.clone()
+            }
+            .result_cache_key()
+        });
+        platform_req.cache_key = Some(key);
+    }
+
+    // A persistent execution is an instance until it ends, so it is reaped if the client
+    // goes away before then
+    let persistent = matches!(platform_mode, platform::executor::Mode::Persistent);
+    if persistent {
+        let mut instance = instance_ttl::persistent_instance(
+            &execution_id,
+            &platform_req.env,
+            req.cpu_cores.map(u32::from),
+            req.memory_mb,
+            state.instance_ttl.lease(req.ttl_secs),
+            chrono::Utc::now(),
+        );
+        instance.tenant = tenant.clone();
+        state.instances.insert(execution_id.clone(), instance);
+    }
+
+    // Execute using platform executor (it handles runtime selection internally). A job
+    // that can run in a fresh container streams, so its output can be followed.
+    let result = match job.filter(|_| state.executor.streams(&platform_req)) {
+        Some(job) => {
+            let supervised = Supervised::of(&platform_req);
+            let executor = state.executor.clone();
+            let execution = async move { executor.run_streaming(platform_req, job.output).await };
+            supervise(&state, &run, &scope, supervised, execution).await
+        }
+        None => run_killable(&state, &run, &scope, platform_req).await,
+    };
+    if persistent {
+        instance_ttl::finish(&state.instances, &execution_id);
+    }
+    let result = match result {
+        Ok(result) => result,
+        Err(stopped) => {
+            stop_group_member(&state, group_id.as_deref(), &execution_id, &stopped);
+            return Err(stopped.into_response());
+        }
+    };
+    record_group_output(
+        &state,
+        group_id.as_deref(),
+        &execution_id,
+        req.item_key.as_deref(),
+        &result,
+    );
+    finish_group(
+        &state,
+        group_id.as_deref(),
+        &execution_id,
+        matches!(&result, Ok(response) if response.exit_code == 0),
+        result
+            .as_ref()
+            .ok()
+            .map(|response| response.duration.as_millis() as u64),
+    );
+    match result {
+        Ok(response) => {
+            if response.cache_hit {
+                state
+                    .metrics
+                    .cache_hits
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+
+            persist_logs(&state, &response).await;
+            state.usage.note_log(&response.id, tenant.as_deref());
+            // A checkpoint to resume from is the tenant's like the execution
+            if let Some(checkpoint) = &response.snapshot {
+                state.execution_owners.record(checkpoint, tenant.as_deref());
+            }
+            state
+                .usage
+                .record_execution(
+                    tenant.as_deref(),
+                    &response,
+                    ComputeSize::of(req.cpu_cores, req.memory_mb),
+                    req.mode.as_deref().unwrap_or("ephemeral"),
+                )
+                .await;
+
+            let speculation = response.speculation.clone();
+            Ok(Json(
+                ResponseBuilder::new(response)
+                    .diagnostics(ExecutionDiagnostics {
+                        limits: Some(limits),
+                        environment_overrides,
+                        env: Some(env.sources()),
+                        speculation,
+                    })
+                    .build(),
+            ))
+        }
+        Err(e) => {
+            error!("Execution failed: {}", e);
+            Err(failure_response(e.as_ref()))
+        }
+    }
+}
+
+/// How often a streamed execution that has gone quiet sends a heartbeat
+const STREAM_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Run an ephemeral Docker execution and stream its output as server-sent events, closing
+/// with an `exit` event, or `error` if it failed without an exit code. Each event's data is
+/// a [`streaming::StreamEvent`].
+async fn execute_stream_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut req): Json<ExecuteRequest>,
+) -> Result<Sse<UnboundedReceiverStream<Result<Event, Infallible>>>, Response> {
+    state
+        .bounds
+        .check(&execute_fields(&req))
+        .map_err(IntoResponse::into_response)?;
+    let limits = resolve_limits(&state, &headers, &mut req).map_err(IntoResponse::into_response)?;
+    let environment_overrides = resolve_overrides(&mut req).map_err(IntoResponse::into_response)?;
+    let mut env = resolve_env(&mut req)?;
+    let input_files = resolve_input_files(&mut req)?;
+    let network = resolve_network(&mut req)?;
+    let (payload, payload_lease) = resolve_payload(&state, &mut req)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let workload = workload(&headers, &mut req);
+    state
+        .metrics
+        .total_requests
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let size = ComputeSize::of(req.cpu_cores, req.memory_mb);
+    state
+        .usage
+        .admit(tenant.as_deref(), size)
+        .await
+        .map_err(usage::refusal)?;
+
+    let execution_id = Uuid::new_v4().to_string();
+    let scope = state
+        .cancels
+        .register(&execution_id, None)
+        .map_err(IntoResponse::into_response)?;
+    state
+        .execution_owners
+        .record(&execution_id, tenant.as_deref());
+    let run = state
+        .kill_switch
+        .admit(&execution_id, workload)
+        .map_err(IntoResponse::into_response)?;
+    let kv = grant_kv(&state, &headers, None, &mut env);
+    let platform_req = platform::executor::Request {
+        id: execution_id.clone(),
+        code: req.command,
+        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
+        timeout: req
+            .timeout_ms
+            .map_or(state.executor.default_timeout(), Duration::from_millis),
+        runtime: req.runtime,
+        isolation: req.isolation,
+        env_vars: Some(env.into_map()),
+        working_dir: req.working_dir.clone(),
+        user: req.user.clone(),
+        memory_mb: req.memory_mb,
+        cpu_cores: req.cpu_cores.map(f64::from),
+        ulimits: Some(limits.ulimits),
+        shm_size_mb: Some(limits.shm_size_mb),
+        pids_limit: Some(limits.pids_limit),
+        tmpfs: (!limits.tmpfs.is_empty()).then_some(limits.tmpfs),
+        placement: arch_placement(req.arch),
+        payload,
+        input_files,
+        read_only_mounts: Vec::new(),
+        gpu: gpu_request(req.gpu, req.gpu_count),
+        network,
+        environment_overrides,
+        tenant: tenant.clone(),
+        ..Default::default()
+    };
+
+    let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let send = move |event: streaming::StreamEvent| {
+        let event = Event::default().json_data(&event).unwrap_or_default();
+        // The client hanging up doesn't stop the execution
+        let _ = events_tx.send(Ok(event));
+    };
+    tokio::spawn(async move {
+        let _held = (payload_lease, kv);
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::unbounded_channel();
+        let supervised = Supervised::of(&platform_req);
+        let executor = state.executor.clone();
+        let execution = supervise(&state, &run, &scope, supervised, async move {
+            executor.run_streaming(platform_req, output_tx).await
+        });
+        tokio::pin!(execution);
+        let mut heartbeat = tokio::time::interval(STREAM_HEARTBEAT_INTERVAL);
+        heartbeat.tick().await;
+        let outcome = loop {
+            tokio::select! {
+                Some(chunk) = output_rx.recv() => send(output_event(chunk)),
+                _ = heartbeat.tick() => send(streaming::StreamEvent::Heartbeat),
+                outcome = &mut execution => break outcome,
+            }
+        };
+        while let Ok(chunk) = output_rx.try_recv() {
+            send(output_event(chunk));
+        }
+
+        match outcome {
+            Ok(Ok(response)) => {
+                send(streaming::StreamEvent::Exit {
+                    code: response.exit_code,
+                });
+                persist_logs(&state, &response).await;
+                state.usage.note_log(&response.id, tenant.as_deref());
+                state
+                    .usage
+                    .record_execution(tenant.as_deref(), &response, size, "ephemeral")
+                    .await;
+            }
+            Ok(Err(e)) => {
+                error!("Streamed execution {} failed: {}", execution_id, e);
+                send(streaming::StreamEvent::Error {
+                    message: e.to_string(),
+                });
+            }
+            Err(stopped) => send(streaming::StreamEvent::Error {
+                message: stopped.to_string(),
+            }),
+        }
+    });
+
+    Ok(Sse::new(UnboundedReceiverStream::new(events_rx)))
+}
+
+fn output_event(chunk: faas_executor::OutputChunk) -> streaming::StreamEvent {
+    match chunk {
+        faas_executor::OutputChunk::Stdout(data) => streaming::StreamEvent::Stdout {
+            data: String::from_utf8_lossy(&data).into_owned(),
+            exec_id: None,
+        },
+        faas_executor::OutputChunk::Stderr(data) => streaming::StreamEvent::Stderr {
+            data: String::from_utf8_lossy(&data).into_owned(),
+            exec_id: None,
+        },
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ForkRequest {
+    /// What every branch shares; `branch_from` names the execution they branch from
+    #[serde(flatten)]
+    execute: ExecuteRequest,
+    branches: Vec<ForkBranch>,
+    #[serde(default)]
+    strategy: ForkStrategy,
+}
+
+/// Run a fork's branches as its strategy says; see [`fork`]. The fork is a cancellation
+/// node named by `x-faas-fork-id`, with each branch running under it as
+/// `<fork id>-<branch id>`.
+async fn fork_execution_handler(
+    State(state): State<AppState>,
+    request_headers: HeaderMap,
+    Json(fork_req): Json<ForkRequest>,
+) -> Result<(HeaderMap, Json<ForkResult>), Response> {
+    let ForkRequest {
+        execute: mut req,
+        branches,
+        strategy,
+    } = fork_req;
+    fork::validate(&branches).map_err(IntoResponse::into_response)?;
+    // Each branch brings its own command
+    state
+        .bounds
+        .check(&ExecuteFields {
+            command: None,
+            ..execute_fields(&req)
+        })
+        .map_err(IntoResponse::into_response)?;
+    let reach = Scope::from_headers(&request_headers);
+    tenancy::check_sources(
+        &state.snapshots,
+        &state.execution_owners,
+        &reach,
+        None,
+        req.branch_from.as_deref(),
+    )
+    .map_err(IntoResponse::into_response)?;
+
+    let limits =
+        resolve_limits(&state, &request_headers, &mut req).map_err(IntoResponse::into_response)?;
+    let environment_overrides = resolve_overrides(&mut req).map_err(IntoResponse::into_response)?;
+    let mut env = resolve_env(&mut req)?;
+    let input_files = resolve_input_files(&mut req)?;
+    let network = resolve_network(&mut req)?;
+    let (payload, _payload_lease) = resolve_payload(&state, &mut req)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let workload = workload(&request_headers, &mut req);
+
+    // An auto-created group expects exactly the branches
+    let group_id = match req.group.take() {
+        Some(group) => Some(
+            create_group(
+                &state,
+                &reach,
+                CreateGroupRequest {
+                    expected: Some(branches.len()),
+                    ..group
+                },
+            )
+            .map_err(IntoResponse::into_response)?
+            .group_id,
+        ),
+        None => req.group_id.take(),
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(id) = group_id.as_deref().and_then(|id| id.parse().ok()) {
+        headers.insert("x-faas-group-id", id);
+    }
+
+    let _kv = grant_kv(&state, &request_headers, group_id.as_deref(), &mut env);
+    let mut branch_envs = HashMap::new();
+    for branch in &branches {
+        let mut branch_env = env.clone();
+        branch_env
+            .set(
+                EnvLayer::Request,
+                branch.env_vars.clone().unwrap_or_default(),
+            )
+            .map_err(rejected_env)?;
+        branch_envs.insert(branch.id.clone(), branch_env);
+    }
+
+    // Create base request
+    let base_req = platform::executor::Request {
+        id: Uuid::new_v4().to_string(),
+        code: String::new(),
+        mode: platform::executor::Mode::Branched,
+        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
+        timeout: req
+            .timeout_ms
+            .map_or(state.executor.default_timeout(), Duration::from_millis),
+        checkpoint: None,
+        branch_from: req.branch_from.take(),
+        runtime: None,
+        isolation: None,
+        env_vars: None,
+        working_dir: req.working_dir.clone(),
+        user: req.user.clone(),
+        memory_mb: req.memory_mb,
+        cpu_cores: req.cpu_cores.map(f64::from),
+        ulimits: Some(limits.ulimits.clone()),
+        shm_size_mb: Some(limits.shm_size_mb),
+        pids_limit: Some(limits.pids_limit),
+        tmpfs: (!limits.tmpfs.is_empty()).then(|| limits.tmpfs.clone()),
+        placement: arch_placement(req.arch),
+        payload,
+        input_files,
+        read_only_mounts: Vec::new(),
+        gpu: gpu_request(req.gpu, req.gpu_count),
+        network,
+        environment_overrides: environment_overrides.clone(),
+        execution_strategy: None,
+        idempotent: false,
+        cache_key: None,
+        cache_namespace: None,
+        tenant: snapshot_fs::request_tenant(&request_headers),
+        no_cache: false,
+    };
+
+    // The branches run under the fork, so cancelling it cancels them
+    let fork_id = base_req.id.clone();
+    state.execution_owners.record(&fork_id, reach.tenant());
+    let fork_scope = state
+        .cancels
+        .register(&fork_id, group_id.as_deref())
+        .map_err(IntoResponse::into_response)?;
+    fork_scope.start().map_err(IntoResponse::into_response)?;
+    if let Ok(id) = fork_id.parse() {
+        headers.insert("x-faas-fork-id", id);
+    }
+
+    let execution_id = |branch_id: &str| format!("{fork_id}-{branch_id}");
+    let mut admitted = HashMap::new();
+    for branch in &branches {
+        let id = execution_id(&branch.id);
+        state.execution_owners.record(&id, reach.tenant());
+        let scope = state
+            .cancels
+            .register(&id, Some(&fork_id))
+            .map_err(IntoResponse::into_response)?;
+        let run = state
+            .kill_switch
+            .admit(&id, workload.clone())
+            .map_err(IntoResponse::into_response)?;
+        admitted.insert(branch.id.clone(), (run, scope));
+    }
+    for branch in &branches {
+        join_group(
+            &state,
+            &reach,
+            group_id.as_deref(),
+            &execution_id(&branch.id),
+        )
+        .map_err(IntoResponse::into_response)?;
+    }
+
+    let (state_ref, group, base) = (&state, group_id.as_deref(), &base_req);
+    let (limits_ref, overrides_ref) = (&limits, &environment_overrides);
+    let started = &mut admitted;
+    let execute = |branch: ForkBranch| {
+        let guards = started.remove(&branch.id);
+        let env = branch_envs.remove(&branch.id).unwrap_or_default();
+        let mut branch_req = base.clone();
+        branch_req.id = execution_id(&branch.id);
+        branch_req.code = branch.command;
+        branch_req.env_vars = Some(env.clone().into_map());
+        async move {
+            let (run, scope) = guards.expect("every branch is admitted");
+            let id = branch_req.id.clone();
+            let result = match run_killable(state_ref, &run, &scope, branch_req).await {
+                Ok(result) => result,
+                Err(stopped) => {
+                    stop_group_member(state_ref, group, &id, &stopped);
+                    warn!("Fork branch {} stopped: {}", id, stopped);
+                    return Err(ApiError::from_response(stopped.into_response()).await);
+                }
+            };
+            record_group_output(state_ref, group, &id, Some(&branch.id), &result);
+            finish_group(
+                state_ref,
+                group,
+                &id,
+                matches!(&result, Ok(response) if response.exit_code == 0),
+                result
+                    .as_ref()
+                    .ok()
+                    .map(|response| response.duration.as_millis() as u64),
+            );
+            match result {
+                Ok(response) => Ok(ResponseBuilder::new(response)
+                    .diagnostics(ExecutionDiagnostics {
+                        limits: Some(limits_ref.clone()),
+                        environment_overrides: overrides_ref.clone(),
+                        env: Some(env.sources()),
+                        speculation: None,
+                    })
+                    .build()),
+                Err(e) => {
+                    error!("Fork branch {} failed: {}", id, e);
+                    Err(ApiError::from_failure(e.as_ref()))
+                }
+            }
+        }
+    };
+    let cancel = |branch_id: &str| {
+        if let Err(e) = state_ref
+            .cancels
+            .cancel(&execution_id(branch_id), CancelPolicy::All)
+        {
+            warn!("Failed to cancel fork branch {}: {}", branch_id, e);
+        }
+    };
+    let result = fork::run(fork_id.clone(), strategy, branches, execute, cancel).await;
+
+    // Branches a sequential fork skipped never start; cancelling them settles their group
+    for (branch_id, _guards) in admitted {
+        let id = execution_id(&branch_id);
+        let _ = state.cancels.cancel(&id, CancelPolicy::All);
+        if let Some(cancellation) = state.cancels.cancellation(&id) {
+            let stopped = Stopped::Cancelled {
+                id: id.clone(),
+                cancellation,
+            };
+            stop_group_member(&state, group_id.as_deref(), &id, &stopped);
+        }
+    }
+
+    Ok((headers, Json(result)))
+}
+
+/// Run the request in a branch of execution `:id`, which starts from the filesystem the
+/// parent left when it ran in branched or persistent mode, in place of `image`. A parent
+/// that left none, or that ran for another tenant, is a 404.
+async fn fork_from_parent_handler(
+    State(state): State<AppState>,
+    Path(parent_id): Path<String>,
+    headers: HeaderMap,
+    Json(mut req): Json<ExecuteRequest>,
+) -> Result<Json<InvokeResponse>, Response> {
+    state
+        .bounds
+        .check(&execute_fields(&req))
+        .map_err(IntoResponse::into_response)?;
+    let reach = Scope::from_headers(&headers);
+    tenancy::check_sources(
+        &state.snapshots,
+        &state.execution_owners,
+        &reach,
+        None,
+        Some(&parent_id),
+    )
+    .map_err(IntoResponse::into_response)?;
+    let limits = resolve_limits(&state, &headers, &mut req).map_err(IntoResponse::into_response)?;
+    let environment_overrides = resolve_overrides(&mut req).map_err(IntoResponse::into_response)?;
+    let mut env = resolve_env(&mut req)?;
+    let input_files = resolve_input_files(&mut req)?;
+    let network = resolve_network(&mut req)?;
+    let (payload, _payload_lease) = resolve_payload(&state, &mut req)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let workload = workload(&headers, &mut req);
+    let _kv = grant_kv(&state, &headers, req.group_id.as_deref(), &mut env);
+
+    let execution_id = Uuid::new_v4().to_string();
+    state.execution_owners.record(&execution_id, reach.tenant());
+    let scope = state
+        .cancels
+        .register(&execution_id, Some(&parent_id))
+        .map_err(IntoResponse::into_response)?;
+    let run = state
+        .kill_switch
+        .admit(&execution_id, workload)
+        .map_err(IntoResponse::into_response)?;
+    let platform_req = platform::executor::Request {
+        id: execution_id,
+        code: req.command.clone(),
+        mode: platform::executor::Mode::Branched,
+        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
+        timeout: req
+            .timeout_ms
+            .map_or(state.executor.default_timeout(), Duration::from_millis),
+        checkpoint: None,
+        branch_from: Some(parent_id),
+        runtime: None,
+        isolation: None,
+        env_vars: Some(env.clone().into_map()),
+        working_dir: req.working_dir.clone(),
+        user: req.user.clone(),
+        memory_mb: req.memory_mb,
+        cpu_cores: req.cpu_cores.map(f64::from),
+        ulimits: Some(limits.ulimits.clone()),
+        shm_size_mb: Some(limits.shm_size_mb),
+        pids_limit: Some(limits.pids_limit),
+        tmpfs: (!limits.tmpfs.is_empty()).then(|| limits.tmpfs.clone()),
+        placement: arch_placement(req.arch),
+        payload,
+        input_files,
+        read_only_mounts: Vec::new(),
+        gpu: gpu_request(req.gpu, req.gpu_count),
+        network,
+        environment_overrides: environment_overrides.clone(),
+        execution_strategy: None,
+        idempotent: false,
+        cache_key: None,
+        cache_namespace: None,
+        tenant: snapshot_fs::request_tenant(&headers),
+        no_cache: false,
+    };
+
+    let result = run_killable(&state, &run, &scope, platform_req)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    match result {
+        Ok(response) => Ok(Json(
+            ResponseBuilder::new(response)
+                .diagnostics(ExecutionDiagnostics {
+                    limits: Some(limits),
+                    environment_overrides,
+                    env: Some(env.sources()),
+                    speculation: None,
+                })
+                .build(),
+        )),
+        Err(e) => {
+            error!("Fork from parent failed: {}", e);
+            Err(failure_response(e.as_ref()))
+        }
+    }
+}
+
+/// Start `count` warm containers for `image` in the tenant's own pool; its later Docker
+/// executions of the image claim one instead of creating a container
+async fn prewarm_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<PrewarmRequest>,
+) -> Result<Json<WarmPool>, Response> {
+    if matches!(req.runtime, Some(faas_common::Runtime::Firecracker)) {
+        let e = FaasError::IncompatibleFeature {
+            feature: "prewarm".to_string(),
+            runtime: "firecracker".to_string(),
+            reason: "only Docker containers are pooled".to_string(),
+        };
+        return Err(failure_response(&e));
+    }
+    info!(
+        "Pre-warming {} containers for image {}",
+        req.count, req.image
+    );
+    let scope = Scope::from_headers(&headers);
+    let pool = state
+        .executor
+        .prewarm(scope.tenant(), &req.image, req.count)
+        .await
+        .map_err(|e| {
+            error!("Pre-warming {} failed: {}", req.image, e);
+            failure_response(e.as_ref())
+        })?;
+    Ok(Json(pool.into()))
+}
+
+/// Warm pools of both runtimes, with their limits and hit rates: the tenant's own Docker
+/// pools and the VM pools every tenant shares
+async fn list_warm_pools_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Json<Vec<WarmPool>> {
+    let scope = Scope::from_headers(&headers);
+    Json(
+        state
+            .executor
+            .pool_stats()
+            .await
+            .into_iter()
+            .filter(|pool| {
+                pool.runtime == Runtime::Firecracker || scope.owns(pool.namespace.as_deref())
+            })
+            .map(WarmPool::from)
+            .collect(),
+    )
+}
+
+/// Set how many warm containers an image's pool keeps, or warm VMs a Firecracker
+/// environment's; the pool is trimmed to the new max and refilled to the new min
+async fn set_pool_limits_handler(
+    State(state): State<AppState>,
+    Path(image): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<PoolLimitsRequest>,
+) -> Result<Json<WarmPool>, ApiError> {
+    if req.min_size > req.max_size {
+        return Err(ApiError::invalid_request(format!(
+            "min_size {} is above max_size {}",
+            req.min_size, req.max_size
+        ))
+        .with_details(serde_json::json!({ "field": "min_size" })));
+    }
+    let runtime = match req.runtime {
+        None | Some(Runtime::Docker) => Runtime::Docker,
+        Some(Runtime::Firecracker) => Runtime::Firecracker,
+        Some(Runtime::Auto) => {
+            return Err(ApiError::invalid_request(
+                "a pool belongs to one runtime; name docker or firecracker",
+            )
+            .with_details(serde_json::json!({ "field": "runtime" })))
+        }
+    };
+    let limits = faas_executor::firecracker::WarmLimits {
+        min: req.min_size,
+        max: req.max_size,
+    };
+    let pool = state
+        .executor
+        .set_pool_limits(
+            Scope::from_headers(&headers).tenant(),
+            runtime,
+            &image,
+            limits,
+        )
+        .await
+        .map_err(|e| ApiError::from_failure(e.as_ref()))?;
+    info!(
+        "Warm pool for {} ({:?}) now keeps {}..={}",
+        image, runtime, limits.min, limits.max
+    );
+    Ok(Json(pool.into()))
+}
+
+/// Guest IP leases of the Firecracker network; 404 on hosts without VM support
+async fn vm_network_pool_handler(
+    State(state): State<AppState>,
+) -> Result<Json<faas_executor::firecracker::NetworkLeaseStats>, StatusCode> {
+    state
+        .executor
+        .vm_network_stats()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Canary health and recent results for every environment that has one
+async fn list_canaries_handler(State(state): State<AppState>) -> Json<Vec<CanaryStatus>> {
+    Json(state.executor.container_pool().canaries().statuses())
+}
+
+async fn get_canary_handler(
+    State(state): State<AppState>,
+    Path(env): Path<String>,
+) -> Result<Json<CanaryStatus>, StatusCode> {
+    state
+        .executor
+        .container_pool()
+        .canaries()
+        .status(&env)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn put_canary_handler(
+    State(state): State<AppState>,
+    Path(env): Path<String>,
+    Json(spec): Json<CanarySpec>,
+) -> Result<Json<CanaryStatus>, StatusCode> {
+    if spec.command.is_empty() || spec.interval_secs == 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let pools = state.executor.container_pool();
+    pools.configure_canary(&env, spec).await;
+    pools
+        .canaries()
+        .status(&env)
+        .map(Json)
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn delete_canary_handler(
+    State(state): State<AppState>,
+    Path(env): Path<String>,
+) -> StatusCode {
+    if state.executor.container_pool().canaries().remove(&env) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn create_snapshot_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<CreateSnapshotRequest>,
+) -> Result<(StatusCode, Json<Snapshot>), Response> {
+    let write = state
+        .executor
+        .drain()
+        .try_begin_operation()
+        .map_err(|_| drain::draining_response())?;
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let scope = Scope::from_headers(&headers);
+    let foreign = state.instances.iter().any(|instance| {
+        instance.container_id.as_deref() == Some(req.container_id.as_str())
+            && !scope.owns(instance.tenant.as_deref())
+    });
+    if foreign {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    // The size is only known once committed; a tenant already at its limit is refused here
+    state
+        .usage
+        .check_storage(tenant.as_deref(), StoredKind::Snapshot, 0)
+        .await
+        .map_err(usage::refusal)?;
+    let snapshot_id = Uuid::new_v4().to_string();
+    let group_id = req.group_id.clone();
+    join_group(&state, &scope, group_id.as_deref(), &snapshot_id)
+        .map_err(IntoResponse::into_response)?;
+
+    let groups = state.groups.clone();
+    let meter = state.usage.clone();
+    let events = state.events.clone();
+    let on_done = {
+        let group_id = group_id.clone();
+        move |snapshot: &Snapshot| {
+            // The drain waits for this write until the commit finishes
+            drop(write);
+            if snapshot.lifecycle.current() == SnapshotState::Ready {
+                events.publish(snapshot_created(snapshot));
+                let (tenant, size) = (snapshot.tenant.clone(), snapshot.size_bytes);
+                tokio::spawn(async move {
+                    meter
+                        .record_stored(tenant.as_deref(), StoredKind::Snapshot, size as i64)
+                        .await
+                });
+            }
+            let Some(group_id) = group_id else {
+                return;
+            };
+            let succeeded = snapshot.lifecycle.current() == SnapshotState::Ready;
+            let duration_ms = chrono::DateTime::parse_from_rfc3339(&snapshot.created_at)
+                .ok()
+                .map(|created| {
+                    (chrono::Utc::now() - created.with_timezone(&chrono::Utc))
+                        .num_milliseconds()
+                        .max(0) as u64
+                });
+            if let Some(settlement) = groups.finish(&group_id, &snapshot.id, succeeded, duration_ms)
+            {
+                tokio::spawn(async move { groups.notify(settlement).await });
+            }
+        }
+    };
+    let snapshot = snapshot_jobs::start(
+        state.snapshot_backend.clone(),
+        state.snapshots.clone(),
+        state.snapshot_quota,
+        SnapshotRequest {
+            id: snapshot_id.clone(),
+            container_id: req.container_id,
+            name: req.name,
+            tenant,
+            tags: req.tags.unwrap_or_default(),
+        },
+        on_done,
+    )
+    .await
+    .map_err(|e| {
+        warn!("Snapshot preflight failed: {}", e);
+        finish_group(&state, group_id.as_deref(), &snapshot_id, false, None);
+        e.into_response()
+    })?;
+
+    Ok((StatusCode::ACCEPTED, Json(snapshot)))
+}
+
+async fn get_snapshot_handler(
+    State(state): State<AppState>,
+    Path(snapshot_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Snapshot>, StatusCode> {
+    visible_snapshot(&state, &snapshot_id, &Scope::from_headers(&headers))
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn list_snapshots_handler(
+    State(state): State<AppState>,
+    Query(query): Query<snapshot_jobs::ListQuery>,
+    headers: HeaderMap,
+) -> Json<Vec<Snapshot>> {
+    Json(snapshot_jobs::list(
+        &state.snapshots,
+        &Scope::from_headers(&headers),
+        &query,
+    ))
+}
+
+/// A running instance record for a ready snapshot; its container is started on hand-over
+fn restored_instance(events: &EventBus, snapshot: &Snapshot) -> Result<Instance, LifecycleError> {
+    snapshot.lifecycle.require(
+        &format!("snapshot {}", snapshot.id),
+        &[SnapshotState::Ready],
+        SnapshotState::Ready,
+    )?;
+    let mut instance = Instance {
+        id: Uuid::new_v4().to_string(),
+        name: Some(format!("restored-{}", snapshot.id)),
+        image: snapshot
+            .image
+            .clone()
+            .unwrap_or_else(|| "restored".to_string()),
+        tenant: None,
+        lifecycle: Lifecycle::new(InstanceState::Creating),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        cpu_cores: None,
+        memory_mb: None,
+        container_id: None,
+        container: None,
+        expires_at: None,
+        workspace: None,
+        ports: Vec::new(),
+        endpoints: BTreeMap::new(),
+    };
+    transition_instance(events, &mut instance, InstanceState::Running)?;
+    Ok(instance)
+}
+
+async fn restore_snapshot_handler(
+    State(state): State<AppState>,
+    Path(snapshot_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Instance>, Response> {
+    let start = Instant::now();
+    let scope = Scope::from_headers(&headers);
+    let snapshot = visible_snapshot(&state, &snapshot_id, &scope)
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+
+    // A promoted snapshot hands over an instance restored ahead of time
+    let (mut instance, pooled) = match state.promotion.take(&snapshot_id) {
+        Some(instance) => (instance, true),
+        None => (
+            restored_instance(&state.events, &snapshot).map_err(IntoResponse::into_response)?,
+            false,
+        ),
+    };
+    // A shared snapshot restores as the restoring tenant's instance
+    instance.tenant = scope.tenant().map(str::to_string);
+    if let Some(image) = &snapshot.image {
+        let container_id = state
+            .executor
+            .instance_containers()
+            .start_with(
+                &instance.id,
+                image,
+                &platform::InstanceOptions {
+                    name: instance.name.clone(),
+                    tenant: instance.tenant.clone(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| {
+                error!("Could not restore snapshot {}: {}", snapshot_id, e);
+                failure_response(e.as_ref())
+            })?;
+        instance.container_id = Some(container_id);
+    }
+
+    // Store the instance
+    state
+        .instances
+        .insert(instance.id.clone(), instance.clone());
+    info!(
+        "Restored snapshot {} as instance {}{}",
+        snapshot_id,
+        instance.id,
+        if pooled { " from its pool" } else { "" }
+    );
+
+    let promoted = state.promotion.record_restore(
+        &snapshot_id,
+        snapshot.tenant.as_deref(),
+        start.elapsed(),
+        Instant::now(),
+    );
+    if promoted {
+        apply_promotion_work(&state, state.promotion.tick(Instant::now()));
+    }
+
+    Ok(Json(instance))
+}
+
+/// Pin a snapshot promoted or demoted, or with `{"pin": null}` hand it back to the policy
+async fn pin_snapshot_handler(
+    State(state): State<AppState>,
+    Path(snapshot_id): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<PinRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let tenant = visible_snapshot(&state, &snapshot_id, &Scope::from_headers(&headers))
+        .map(|snapshot| snapshot.tenant)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let drained = state
+        .promotion
+        .pin(&snapshot_id, tenant.as_deref(), req.pin);
+    let work = PromotionWork {
+        drained,
+        ..state.promotion.tick(Instant::now())
+    };
+    apply_promotion_work(&state, work);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_snapshot_handler(
+    State(state): State<AppState>,
+    Path(snapshot_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, LifecycleError> {
+    let deleted = snapshot_jobs::delete(
+        state.snapshot_backend.as_ref(),
+        &state.snapshots,
+        &snapshot_id,
+        &Scope::from_headers(&headers),
+    )
+    .await?;
+    // Only committed snapshots were ever billed for their size
+    if deleted.lifecycle.current() == SnapshotState::Ready {
+        state
+            .usage
+            .record_stored(
+                deleted.tenant.as_deref(),
+                StoredKind::Snapshot,
+                -(deleted.size_bytes as i64),
+            )
+            .await;
+    }
+    for instance in state.promotion.forget(&snapshot_id) {
+        info!("Drained pooled instance {}", instance.id);
+    }
+    info!("Deleted snapshot: {}", snapshot_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Snapshots owned by another tenant look exactly like missing ones
+fn visible_snapshot(state: &AppState, id: &str, scope: &Scope) -> Option<Snapshot> {
+    state
+        .snapshots
+        .get(id)
+        .map(|entry| entry.value().clone())
+        .filter(|snapshot| scope.sees(snapshot))
+}
+
+async fn snapshot_ls_handler(
+    State(state): State<AppState>,
+    Path(snapshot_id): Path<String>,
+    Query(query): Query<snapshot_fs::LsQuery>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let Some(snapshot) = visible_snapshot(&state, &snapshot_id, &Scope::from_headers(&headers))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let result = snapshot_fs::list(&snapshot, &query).await;
+    snapshot_fs::audit(
+        tenant.as_deref(),
+        &snapshot_id,
+        "ls",
+        &query.path,
+        &result.as_ref().map(|_| ()),
+    );
+    match result {
+        Ok(listing) => Json(listing).into_response(),
+        Err(e) => snapshot_fs::error_response(e),
+    }
+}
+
+async fn snapshot_cat_handler(
+    State(state): State<AppState>,
+    Path(snapshot_id): Path<String>,
+    Query(query): Query<snapshot_fs::CatQuery>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let Some(snapshot) = visible_snapshot(&state, &snapshot_id, &Scope::from_headers(&headers))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let result = snapshot_fs::cat(&snapshot, &query).await;
+    snapshot_fs::audit(
+        tenant.as_deref(),
+        &snapshot_id,
+        "cat",
+        &query.path,
+        &result.as_ref().map(|_| ()),
+    );
+    match result {
+        Ok(data) => (
+            [(axum::http::header::CONTENT_TYPE, "application/octet-stream")],
+            data,
+        )
+            .into_response(),
+        Err(e) => snapshot_fs::error_response(e),
+    }
+}
+
+/// Entrypoint, ports and layers for an image; `:ref` is URL-encoded (`library%2Falpine:3.19`).
+async fn image_metadata_handler(
+    State(state): State<AppState>,
+    Path(reference): Path<String>,
+) -> Result<Json<platform::ImageMetadata>, ApiError> {
+    match state.executor.image_metadata().get(&reference).await {
+        Ok(metadata) => Ok(Json(metadata.as_ref().clone())),
+        Err(e) => Err(image_error(e)),
+    }
+}
+
+fn image_error(e: platform::ImageMetadataError) -> ApiError {
+    match e {
+        platform::ImageMetadataError::NotFound(_) => {
+            ApiError::new(StatusCode::NOT_FOUND, "ImageNotFound", e.to_string())
+        }
+        platform::ImageMetadataError::Unauthorized(_) => {
+            ApiError::new(StatusCode::FORBIDDEN, "ImageUnauthorized", e.to_string())
+        }
+        platform::ImageMetadataError::ArchMismatch(mismatch) => ApiError::from_failure(&mismatch),
+        platform::ImageMetadataError::Unresolvable(failure) => ApiError::from_failure(&failure),
+        e => {
+            warn!("Image registry lookup failed: {}", e);
+            ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                "RegistryUnavailable",
+                e.to_string(),
+            )
+        }
+    }
+}
+
+/// Pull an image for this host's architecture; also clears a cached "not found" for it.
+async fn pull_image_handler(
+    State(state): State<AppState>,
+    Path(reference): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    match state.executor.image_metadata().pull(&reference).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(image_error(e)),
+    }
+}
+
+async fn create_instance_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<CreateInstanceRequest>,
+) -> Result<Json<Instance>, Response> {
+    let id = Uuid::new_v4().to_string();
+    state
+        .kill_switch
+        .check(&id, &instance_workload(&headers, &req.image))
+        .map_err(IntoResponse::into_response)?;
+    let resources = platform::InstanceResources {
+        cpu_cores: req.cpu_cores,
+        memory_mb: req.memory_mb,
+    };
+    let tenant = Scope::from_headers(&headers).tenant().map(str::to_string);
+    let workspace = match instance_workspace(&req) {
+        Ok(Some(workspace)) => Some(platform::WorkspaceMount {
+            volume: state
+                .executor
+                .workspace_volumes()
+                .ensure(tenant.as_deref(), workspace)
+                .await
+                .map_err(|e| {
+                    error!("Could not create the workspace volume for {}: {}", id, e);
+                    failure_response(e.as_ref())
+                })?,
+            path: req
+                .workspace_path
+                .clone()
+                .unwrap_or_else(|| DEFAULT_WORKSPACE_PATH.to_string()),
+        }),
+        Ok(None) => None,
+        Err(e) => return Err(e.into_response()),
+    };
+    check_ports(&req.ports).map_err(IntoResponse::into_response)?;
+    let containers = state.executor.instance_containers();
+    let options = platform::InstanceOptions {
+        name: req.name.clone(),
+        resources,
+        workspace: workspace.clone(),
+        ports: req.ports.clone(),
+        tenant: tenant.clone(),
+    };
+    let container_id = containers
+        .start_with(&id, &req.image, &options)
+        .await
+        .map_err(|e| {
+            error!("Could not start a container for instance {}: {}", id, e);
+            failure_response(e.as_ref())
+        })?;
+    let ports = if req.ports.is_empty() {
+        Vec::new()
+    } else {
+        containers
+            .published_ports(&container_id)
+            .await
+            .map_err(|e| {
+                error!("Could not read the ports of instance {}: {}", id, e);
+                failure_response(e.as_ref())
+            })?
+    };
+    let mut instance = Instance {
+        id,
+        name: req.name,
+        image: req.image,
+        tenant,
+        lifecycle: Lifecycle::new(InstanceState::Creating),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        cpu_cores: req.cpu_cores,
+        memory_mb: req.memory_mb,
+        container_id: Some(container_id),
+        container: None,
+        expires_at: None,
+        workspace,
+        endpoints: ports::endpoints(&ports, &ports::public_host()),
+        ports,
+    };
+    transition_instance(&state.events, &mut instance, InstanceState::Running)
+        .map_err(IntoResponse::into_response)?;
+
+    // Store the instance in state
+    state
+        .instances
+        .insert(instance.id.clone(), instance.clone());
+
+    Ok(Json(instance))
+}
+
+/// Ports are published once each; port 0 would leave the container side unbound
+fn check_ports(ports: &[platform::PortMapping]) -> Result<(), ApiError> {
+    let mut keys = std::collections::HashSet::new();
+    for port in ports {
+        if port.container_port == 0 {
+            return Err(ApiError::invalid_request("container_port must be above 0"));
+        }
+        if !keys.insert(port.key()) {
+            return Err(ApiError::invalid_request(format!(
+                "port {} is listed twice",
+                port.key()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Publish another port of a running instance; the response lists all its endpoints
+async fn expose_instance_port_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(port): Json<platform::PortMapping>,
+) -> Result<Json<Instance>, Response> {
+    check_ports(&[port]).map_err(IntoResponse::into_response)?;
+    let instance = state
+        .instances
+        .get(&id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    lifecycle::require_running(&instance)?;
+    if instance.ports.iter().any(|p| p.key() == port.key()) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "PortExposed",
+            format!("port {} of instance {id} is already exposed", port.key()),
+        )
+        .into_response());
+    }
+    let container_id = instance
+        .container_id
+        .ok_or_else(|| StatusCode::CONFLICT.into_response())?;
+    let mapping = state
+        .executor
+        .instance_containers()
+        .expose(&id, &container_id, port)
+        .await
+        .map_err(|e| {
+            error!("Could not expose {} of instance {}: {}", port.key(), id, e);
+            failure_response(e.as_ref())
+        })?;
+    let mut instance = state
+        .instances
+        .get_mut(&id)
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    instance.ports.push(mapping);
+    instance.endpoints = ports::endpoints(&instance.ports, &ports::public_host());
+    info!("Exposed {} of instance {}", port.key(), id);
+    Ok(Json(instance.clone()))
+}
+
+/// The workspace a `persistent` instance keeps its files in: its name, which has to be
+/// usable in a volume name
+fn instance_workspace(req: &CreateInstanceRequest) -> Result<Option<&str>, ApiError> {
+    if req.persistent != Some(true) {
+        return Ok(None);
+    }
+    let Some(name) = req.name.as_deref() else {
+        return Err(ApiError::invalid_request(
+            "a persistent instance needs a name to key its workspace volume by",
+        ));
+    };
+    if !workspaces::valid_workspace_name(name) {
+        return Err(ApiError::invalid_request(format!(
+            "instance name {name:?} can't name a volume; use letters, digits, '_', '.' and '-'"
+        )));
+    }
+    if let Some(path) = &req.workspace_path {
+        if !path.starts_with('/') || path == "/" {
+            return Err(ApiError::invalid_request(format!(
+                "workspace_path {path:?} must be an absolute path below /"
+            )));
+        }
+    }
+    Ok(Some(name))
+}
+
+/// Forget an instance, removing its container; `?delete_volume=true` removes its
+/// workspace volume too
+async fn delete_instance_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DeleteInstanceQuery>,
+) -> Result<StatusCode, ApiError> {
+    let (_, mut instance) = state.instances.remove(&id).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "NotFound",
+            format!("instance {id} not found"),
+        )
+    })?;
+    state.sessions.remove(&id);
+    let from = instance.lifecycle.current();
+    if !from.is_terminal() {
+        state.events.publish(PlatformEvent::InstanceStateChanged {
+            instance_id: id.clone(),
+            from: Some(from),
+            to: InstanceState::Stopped,
+        });
+    }
+    if let Some(container_id) = instance.container_id.take() {
+        // The volume can only go once no container mounts it
+        if let Err(e) = state
+            .executor
+            .instance_containers()
+            .remove(&container_id)
+            .await
+        {
+            warn!(
+                "Could not remove container {} of instance {}: {}",
+                container_id, id, e
+            );
+        }
+    }
+    if let Err(e) = state
+        .executor
+        .instance_containers()
+        .remove_proxies(&id)
+        .await
+    {
+        warn!(
+            "Could not remove the port proxies of instance {}: {}",
+            id, e
+        );
+    }
+    if query.delete_volume {
+        if let Some(workspace) = &instance.workspace {
+            state
+                .executor
+                .workspace_volumes()
+                .remove(&workspace.volume)
+                .await
+                .map_err(|e| {
+                    ApiError::new(
+                        StatusCode::CONFLICT,
+                        "VolumeInUse",
+                        format!("instance {id} was deleted but its workspace volume was kept: {e}"),
+                    )
+                })?;
+        }
+    }
+    info!("Deleted instance: {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Workspace volumes of the tenant's persistent instances, with their size on disk
+async fn list_volumes_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<platform::VolumeInfo>>, ApiError> {
+    let scope = Scope::from_headers(&headers);
+    let volumes = state
+        .executor
+        .workspace_volumes()
+        .list()
+        .await
+        .map_err(|e| ApiError::from_failure(e.as_ref()))?;
+    Ok(Json(
+        volumes
+            .into_iter()
+            .filter(|volume| scope.owns(volume.tenant.as_deref()))
+            .collect(),
+    ))
+}
+
+async fn list_instances_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Instance>>, StatusCode> {
+    let instances = tenancy::instances(&state.instances, &Scope::from_headers(&headers));
+
+    Ok(Json(instances))
+}
+
+/// The stored instance with its container's live state and usage folded in
+async fn get_instance_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Instance>, StatusCode> {
+    let container_id = state
+        .instances
+        .get(&id)
+        .ok_or(StatusCode::NOT_FOUND)?
+        .container_id
+        .clone();
+    let Some(container_id) = container_id else {
+        return state
+            .instances
+            .get(&id)
+            .map(|entry| Json(entry.value().clone()))
+            .ok_or(StatusCode::NOT_FOUND);
+    };
+    let observed = state
+        .executor
+        .instance_containers()
+        .inspect(&container_id)
+        .await;
+
+    let mut instance = state.instances.get_mut(&id).ok_or(StatusCode::NOT_FOUND)?;
+    match observed {
+        Ok(observed) => match lifecycle::observe_container(&mut instance, observed) {
+            Ok(Some(from)) => {
+                warn!("Container {} of instance {} is gone", container_id, id);
+                state.events.publish(PlatformEvent::InstanceStateChanged {
+                    instance_id: id.clone(),
+                    from: Some(from),
+                    to: InstanceState::Lost,
+                });
+                state.sessions.remove(&id);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Could not mark instance {} lost: {}", id, e),
+        },
+        Err(e) => warn!("Could not inspect container {}: {}", container_id, e),
+    }
+    Ok(Json(instance.clone()))
+}
+
+fn transition_instance(
+    events: &EventBus,
+    instance: &mut Instance,
+    next: InstanceState,
+) -> Result<(), lifecycle::TransitionError> {
+    let from = instance.lifecycle.current();
+    instance
+        .lifecycle
+        .transition(&format!("instance {}", instance.id), next)?;
+    events.publish(PlatformEvent::InstanceStateChanged {
+        instance_id: instance.id.clone(),
+        from: Some(from),
+        to: next,
+    });
+    Ok(())
+}
+
+/// Apply `steps` in order to a stored instance; stops at the first illegal move.
+fn advance_instance(
+    state: &AppState,
+    id: &str,
+    steps: &[InstanceState],
+) -> Result<Instance, LifecycleError> {
+    let mut instance = state
+        .instances
+        .get_mut(id)
+        .ok_or(LifecycleError::NotFound)?;
+    for &next in steps {
+        transition_instance(&state.events, &mut instance, next)?;
+    }
+    Ok(instance.clone())
+}
+
+/// Remove a stopped instance's container in the background
+fn release_container(state: &AppState, instance: &mut Instance) {
+    instance.container = None;
+    instance.ports.clear();
+    instance.endpoints.clear();
+    let Some(container_id) = instance.container_id.take() else {
+        return;
+    };
+    let containers = state.executor.instance_containers();
+    let instance_id = instance.id.clone();
+    tokio::spawn(async move {
+        if let Err(e) = containers.remove(&container_id).await {
+            warn!(
+                "Could not remove container {} of instance {}: {}",
+                container_id, instance_id, e
+            );
+        }
+        if let Err(e) = containers.remove_proxies(&instance_id).await {
+            warn!(
+                "Could not remove the port proxies of instance {}: {}",
+                instance_id, e
+            );
+        }
+    });
+}
+
+/// Instances carry no labels or runtime, so only tenant and image rules reach them
+fn instance_workload(headers: &HeaderMap, image: &str) -> Workload {
+    Workload {
+        tenant: snapshot_fs::request_tenant(headers),
+        image: image.to_string(),
+        ..Default::default()
+    }
+}
+
+async fn exec_instance_handler(
     State(state): State<AppState>,
-    Json(req): Json<CreateSnapshotRequest>,
-) -> Result<Json<Snapshot>, StatusCode> {
-    let snapshot = Snapshot {
-        id: Uuid::new_v4().to_string(),
-        name: req.name.clone(),
-        container_id: req.container_id.clone(),
-        created_at: chrono::Utc::now().to_rfc3339(),
-        size_bytes: 1024 * 1024, // Mock 1MB size
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<ExecInstanceRequest>,
+) -> Result<Json<InvokeResponse>, Response> {
+    let instance = state
+        .instances
+        .get(&id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    lifecycle::require_running(&instance)?;
+    state
+        .kill_switch
+        .check(&id, &instance_workload(&headers, &instance.image))
+        .map_err(IntoResponse::into_response)?;
+    let session = state
+        .sessions
+        .get(&id)
+        .map(|entry| entry.value().clone())
+        .unwrap_or_default();
+
+    let payload = req.payload.unwrap_or_default();
+    state
+        .payloads
+        .check_inline(&payload)
+        .map_err(IntoResponse::into_response)?;
+    let (response, stdout, captured) = run_in_session(
+        &state,
+        &instance,
+        &req.command,
+        &session,
+        &payload,
+        req.timeout_ms,
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    if let Some(captured) = captured {
+        state.sessions.entry(id).or_default().apply(&captured);
+    }
+
+    Ok(Json(ResponseBuilder::new(response).stdout(stdout).build()))
+}
+
+/// Run `command` in the instance with the session state replayed first.
+///
+/// Instances with a container exec inside it; restored ones, which have none, get a fresh
+/// container of their image per command. Returns the command's own stdout with the
+/// capture stripped, plus what it changed.
+async fn run_in_session(
+    state: &AppState,
+    instance: &Instance,
+    command: &str,
+    session: &SessionState,
+    payload: &[u8],
+    timeout_ms: Option<u64>,
+) -> Result<(platform::executor::Response, Vec<u8>, Option<CapturedState>), Response> {
+    let wrapper = SessionWrapper::new();
+    let code = wrapper.wrap(command, session);
+    let timeout = timeout_ms.map_or(state.executor.default_timeout(), Duration::from_millis);
+    let result = match &instance.container_id {
+        Some(container_id) => {
+            state
+                .executor
+                .instance_containers()
+                .exec(container_id, &code, payload, timeout)
+                .await
+        }
+        None => {
+            let request = platform::executor::Request {
+                id: Uuid::new_v4().to_string(),
+                code,
+                mode: platform::executor::Mode::Ephemeral,
+                env: instance.image.clone(),
+                timeout,
+                payload: payload.to_vec(),
+                ..Default::default()
+            };
+            state.executor.run(request).await
+        }
     };
+    let response = result.map_err(|e| {
+        error!("Exec on instance {} failed: {}", instance.id, e);
+        failure_response(e.as_ref())
+    })?;
+    let (stdout, captured) = wrapper.split(&response.stdout);
+    Ok((response, stdout, captured))
+}
+
+async fn get_session_state_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SessionState>, StatusCode> {
+    if !state.instances.contains_key(&id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let session = state
+        .sessions
+        .get(&id)
+        .map(|entry| entry.value().clone())
+        .unwrap_or_default();
+    Ok(Json(session.redacted(&state.redaction)))
+}
+
+async fn restore_session_state_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(captured): Json<SessionState>,
+) -> Result<Json<RestoreReport>, Response> {
+    let instance = state
+        .instances
+        .get(&id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    instance
+        .lifecycle
+        .require(
+            &format!("instance {}", id),
+            &[InstanceState::Running],
+            InstanceState::Running,
+        )
+        .map_err(IntoResponse::into_response)?;
+
+    // Env first, then check the working directory exists on this instance.
+    let (candidate, skipped) = restore_candidate(&captured);
+    let (_, _, validated) = run_in_session(&state, &instance, "true", &candidate, &[], None)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let report = finish_restore(candidate, skipped, validated.as_ref());
+    if report.cwd_missing {
+        warn!(
+            "Working directory {:?} is missing on instance {}",
+            captured.cwd, id
+        );
+    }
+
+    state.sessions.insert(id, report.state.clone());
+    Ok(Json(report))
+}
 
-    // Store snapshot in state
+/// The container of a running instance, to copy files in or out of
+fn running_container(state: &AppState, id: &str) -> Result<String, Response> {
+    let instance = state
+        .instances
+        .get(id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    lifecycle::require_running(&instance)?;
+    instance.container_id.ok_or_else(|| {
+        ApiError::new(
+            StatusCode::CONFLICT,
+            "NoContainer",
+            format!("instance {id} was restored from a snapshot and has no container"),
+        )
+        .into_response()
+    })
+}
+
+/// Extract a tar body into the instance under `?path=`
+async fn upload_instance_files_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FilesQuery>,
+    body: axum::body::Body,
+) -> Result<StatusCode, Response> {
+    let container_id = running_container(&state, &id)?;
+    let limit = state.payloads.max_bytes();
+    let archive = axum::body::to_bytes(body, limit)
+        .await
+        .map_err(|_| PayloadError::TooLarge(limit).into_response())?;
+    let size = archive.len();
     state
-        .snapshots
-        .insert(snapshot.id.clone(), snapshot.clone());
-    info!("Created snapshot: {}", snapshot.id);
+        .executor
+        .instance_containers()
+        .upload(&container_id, &query.path, archive.to_vec())
+        .await
+        .map_err(|e| {
+            warn!("Could not copy files into instance {}: {}", id, e);
+            failure_response(e.as_ref())
+        })?;
+    info!(
+        "Copied {} bytes of files into instance {} at {}",
+        size, id, query.path
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    Ok(Json(snapshot))
+/// `?path=` in the instance as a tar
+async fn download_instance_files_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FilesQuery>,
+) -> Response {
+    match running_container(&state, &id) {
+        Ok(container_id) => {
+            let archive = state
+                .executor
+                .instance_containers()
+                .download(&container_id, &query.path);
+            instance_files::archive_response(archive).await
+        }
+        Err(response) => response,
+    }
 }
 
-async fn list_snapshots_handler(
+/// SIGTERM the instance's processes, SIGKILL them after the grace period, then remove
+/// its container
+async fn stop_instance_handler(
     State(state): State<AppState>,
-) -> Result<Json<Vec<Snapshot>>, StatusCode> {
-    let snapshots: Vec<Snapshot> = state
-        .snapshots
-        .iter()
+    Path(id): Path<String>,
+    body: Option<Json<StopInstanceRequest>>,
+) -> Result<StatusCode, Response> {
+    let grace = body
+        .and_then(|Json(req)| req.grace_secs)
+        .map_or(state.stop_grace, Duration::from_secs);
+    let stopping = advance_instance(&state, &id, &[InstanceState::Stopping])
+        .map_err(IntoResponse::into_response)?;
+    if let Some(container_id) = &stopping.container_id {
+        if let Err(e) = state
+            .executor
+            .instance_containers()
+            .stop(container_id, grace)
+            .await
+        {
+            // Removal below kills whatever is left
+            warn!(
+                "Could not stop container {} of instance {}: {}",
+                container_id, id, e
+            );
+        }
+    }
+    advance_instance(&state, &id, &[InstanceState::Stopped])
+        .map_err(IntoResponse::into_response)?;
+    if let Some(mut instance) = state.instances.get_mut(&id) {
+        release_container(&state, &mut instance);
+    }
+    state.sessions.remove(&id);
+    info!("Stopped instance: {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Keep a live instance for `ttl_secs` more seconds
+async fn extend_instance_ttl_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<ExtendTtl>,
+) -> Result<Json<Instance>, LifecycleError> {
+    let ttl = Duration::from_secs(req.ttl_secs);
+    instance_ttl::extend(&state.instances, &id, ttl, chrono::Utc::now()).map(Json)
+}
+
+/// Freeze the instance's container with `docker pause`
+async fn pause_instance_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Instance>, Response> {
+    let containers = state.executor.instance_containers();
+    let from = [InstanceState::Running];
+    move_container(
+        &state,
+        &id,
+        &from,
+        InstanceState::Paused,
+        |container_id, _| async move { containers.pause(&container_id).await },
+    )
+    .await
+    .map(Json)
+}
+
+/// Thaw a paused instance's container
+async fn resume_instance_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Instance>, Response> {
+    let containers = state.executor.instance_containers();
+    let from = [InstanceState::Paused, InstanceState::Suspended];
+    move_container(
+        &state,
+        &id,
+        &from,
+        InstanceState::Running,
+        |container_id, was| async move {
+            // A suspended instance's container was never frozen
+            if was != InstanceState::Paused {
+                return Ok(());
+            }
+            containers.unpause(&container_id).await
+        },
+    )
+    .await
+    .map(Json)
+}
+
+/// Move the instance from one of `from` to `next` once `apply` has done the same to its
+/// container; the state is left alone when Docker refuses
+async fn move_container<F, Fut>(
+    state: &AppState,
+    id: &str,
+    from: &[InstanceState],
+    next: InstanceState,
+    apply: F,
+) -> Result<Instance, Response>
+where
+    F: FnOnce(String, InstanceState) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<()>>,
+{
+    let instance = state
+        .instances
+        .get(id)
         .map(|entry| entry.value().clone())
-        .collect();
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    instance
+        .lifecycle
+        .require(&format!("instance {id}"), from, next)
+        .map_err(IntoResponse::into_response)?;
+    if let Some(container_id) = instance.container_id {
+        apply(container_id, instance.lifecycle.current())
+            .await
+            .map_err(|e| {
+                warn!("Could not move instance {} to {}: {}", id, next, e);
+                failure_response(e.as_ref())
+            })?;
+    }
+    advance_instance(state, id, &[next]).map_err(IntoResponse::into_response)
+}
 
-    Ok(Json(snapshots))
+async fn drain_handler(
+    State(state): State<AppState>,
+    body: Option<Json<DrainRequest>>,
+) -> Json<DrainStatusResponse> {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    let policy = req.instance_policy.unwrap_or(state.drain_policy);
+    let controller = state.executor.drain().clone();
+    let epoch = controller.start(req.grace());
+    info!(
+        "Draining host (grace {:?}, instances: {:?})",
+        req.grace(),
+        policy
+    );
+
+    let task_state = state.clone();
+    tokio::spawn(async move {
+        match controller.wait_idle(epoch).await {
+            DrainOutcome::Cancelled => return,
+            DrainOutcome::DeadlineReached => warn!(
+                "Drain deadline reached with work still running: {:?}",
+                controller.status()
+            ),
+            DrainOutcome::Idle => {}
+        }
+        settle_instances_for_drain(&task_state, policy);
+        controller.finish(epoch);
+        info!("Host drained");
+    });
+
+    Json(drain_status(&state))
 }
 
-async fn restore_snapshot_handler(
+async fn drain_status_handler(State(state): State<AppState>) -> Json<DrainStatusResponse> {
+    Json(drain_status(&state))
+}
+
+/// What startup reclaimed, and this gateway's containers nothing accounts for now
+async fn orphans_handler(State(state): State<AppState>) -> Result<Json<OrphansResponse>, ApiError> {
+    state
+        .orphans
+        .inspect(&state.instances)
+        .await
+        .map(Json)
+        .map_err(|e| ApiError::from_failure(&e))
+}
+
+async fn undrain_handler(State(state): State<AppState>) -> Json<DrainStatusResponse> {
+    state.executor.drain().undrain();
+    info!("Host undrained; admitting work again");
+    Json(drain_status(&state))
+}
+
+async fn activate_kill_switch_handler(
     State(state): State<AppState>,
-    Path(snapshot_id): Path<String>,
-) -> Result<Json<Instance>, StatusCode> {
-    if let Some(snapshot) = state.snapshots.get(&snapshot_id) {
-        // Create a new instance from the snapshot
-        let instance = Instance {
-            id: Uuid::new_v4().to_string(),
-            name: Some(format!("restored-{}", snapshot_id)),
-            image: "restored".to_string(),
-            status: "running".to_string(),
-            created_at: chrono::Utc::now().to_rfc3339(),
-            cpu_cores: None,
-            memory_mb: None,
-        };
+    Json(req): Json<KillSwitchRequest>,
+) -> Result<Json<Activation>, KillSwitchError> {
+    let mut activation = state.kill_switch.activate(req)?;
+    if activation.rule.action.cancels_running() {
+        activation.stopped_instances = stop_instances_for_kill_switch(&state, &activation.rule.id);
+    }
+    state.events.publish(PlatformEvent::KillSwitchActivated {
+        rule_id: activation.rule.id.clone(),
+        cancelled_executions: activation.cancelled_executions,
+        stopped_instances: activation.stopped_instances,
+    });
+    Ok(Json(activation))
+}
 
-        // Store the instance
-        state
-            .instances
-            .insert(instance.id.clone(), instance.clone());
-        info!(
-            "Restored snapshot {} as instance {}",
-            snapshot_id, instance.id
+/// Stop the live instances kill switch `rule_id` matches; returns how many
+fn stop_instances_for_kill_switch(state: &AppState, rule_id: &str) -> usize {
+    let mut stopped = Vec::new();
+    for mut entry in state.instances.iter_mut() {
+        let instance = entry.value_mut();
+        let live = matches!(
+            instance.lifecycle.current(),
+            InstanceState::Running | InstanceState::Paused | InstanceState::Suspended
         );
+        let workload = Workload {
+            image: instance.image.clone(),
+            ..Default::default()
+        };
+        if !live || !state.kill_switch.rule_matches(rule_id, &workload) {
+            continue;
+        }
+        match transition_instance(&state.events, instance, InstanceState::Stopping)
+            .and_then(|_| transition_instance(&state.events, instance, InstanceState::Stopped))
+        {
+            Ok(()) => {
+                release_container(state, instance);
+                info!(
+                    target: "faas_audit",
+                    rule_id,
+                    instance_id = %instance.id,
+                    image = %instance.image,
+                    "kill switch stopped instance"
+                );
+                stopped.push(instance.id.clone());
+            }
+            Err(e) => warn!("Kill switch could not stop instance: {}", e),
+        }
+    }
+    for id in &stopped {
+        state.sessions.remove(id);
+    }
+    stopped.len()
+}
 
-        Ok(Json(instance))
-    } else {
-        Err(StatusCode::NOT_FOUND)
+/// The group itself is checked by [`tenancy::hide_foreign_executions`]; a `baseline` has to
+/// be within reach too.
+async fn group_comparison_wrapper(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    query: Query<ComparisonQuery>,
+) -> Result<Json<ComparisonReport>, ComparisonError> {
+    let scope = Scope::from_headers(&headers);
+    if let Some(baseline) = query
+        .baseline
+        .as_deref()
+        .filter(|id| !state.execution_owners.reachable(id, &scope))
+    {
+        return Err(ComparisonError::GroupNotFound(baseline.to_string()));
     }
+    comparison::group_comparison_handler(State(state.groups), Path(id), query).await
 }
 
-async fn create_instance_handler(
+async fn list_normalizers_wrapper(
     State(state): State<AppState>,
-    Json(req): Json<CreateInstanceRequest>,
-) -> Result<Json<Instance>, StatusCode> {
-    let instance = Instance {
-        id: Uuid::new_v4().to_string(),
-        name: req.name,
-        image: req.image,
-        status: "running".to_string(),
-        created_at: chrono::Utc::now().to_rfc3339(),
-        cpu_cores: req.cpu_cores,
-        memory_mb: req.memory_mb,
-    };
+) -> Json<BTreeMap<String, Normalizer>> {
+    comparison::list_normalizers_handler(State(state.groups)).await
+}
 
-    // Store the instance in state
-    state
-        .instances
-        .insert(instance.id.clone(), instance.clone());
+async fn put_normalizer_wrapper(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    body: Json<Normalizer>,
+) -> Result<Json<Normalizer>, ComparisonError> {
+    comparison::put_normalizer_handler(State(state.groups), Path(name), body).await
+}
 
-    Ok(Json(instance))
+async fn list_kill_switches_wrapper(State(state): State<AppState>) -> Json<Vec<KillSwitchRule>> {
+    killswitch::list_kill_switches_handler(State(state.kill_switch)).await
 }
 
-async fn list_instances_handler(
+async fn delete_kill_switch_wrapper(
     State(state): State<AppState>,
-) -> Result<Json<Vec<Instance>>, StatusCode> {
-    let instances: Vec<Instance> = state
+    Path(id): Path<String>,
+) -> Result<Json<KillSwitchRule>, KillSwitchError> {
+    killswitch::delete_kill_switch_handler(State(state.kill_switch), Path(id)).await
+}
+
+fn drain_status(state: &AppState) -> DrainStatusResponse {
+    let remaining_instances = state
         .instances
         .iter()
-        .map(|entry| entry.value().clone())
-        .collect();
+        .filter(|entry| {
+            let current = entry.lifecycle.current();
+            !current.is_terminal() && current != InstanceState::Suspended
+        })
+        .count();
+    DrainStatusResponse {
+        status: state.executor.drain().status(),
+        remaining_instances,
+    }
+}
 
-    Ok(Json(instances))
+/// Checkpoint-and-suspend or stop the persistent instances still up after a drain.
+fn settle_instances_for_drain(state: &AppState, policy: InstancePolicy) {
+    for mut entry in state.instances.iter_mut() {
+        let instance = entry.value_mut();
+        let result = match (policy, instance.lifecycle.current()) {
+            (InstancePolicy::Checkpoint, InstanceState::Running) => {
+                checkpoint_for_drain(state, instance).and_then(|_| {
+                    transition_instance(&state.events, instance, InstanceState::Suspended)
+                })
+            }
+            (
+                InstancePolicy::Stop,
+                InstanceState::Running | InstanceState::Paused | InstanceState::Suspended,
+            ) => transition_instance(&state.events, instance, InstanceState::Stopping)
+                .and_then(|_| transition_instance(&state.events, instance, InstanceState::Stopped))
+                .map(|_| release_container(state, instance)),
+            _ => continue,
+        };
+        match result {
+            Ok(()) => info!(
+                "Drain left instance {} {}",
+                instance.id,
+                instance.lifecycle.current()
+            ),
+            Err(e) => warn!("Could not settle instance during drain: {}", e),
+        }
+    }
 }
 
-async fn get_instance_handler(
-    State(_state): State<AppState>,
-    Path(_id): Path<String>,
-) -> Result<Json<Instance>, StatusCode> {
-    Err(StatusCode::NOT_FOUND)
+fn checkpoint_for_drain(
+    state: &AppState,
+    instance: &Instance,
+) -> Result<(), lifecycle::TransitionError> {
+    let mut snapshot = Snapshot {
+        id: Uuid::new_v4().to_string(),
+        name: Some(format!("drain-{}", instance.id)),
+        container_id: instance.id.clone(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        size_bytes: 0,
+        image: None,
+        disk_image: None,
+        tenant: None,
+        tags: Vec::new(),
+        lifecycle: Lifecycle::new(SnapshotState::Creating),
+        progress: None,
+    };
+    snapshot
+        .lifecycle
+        .transition(&format!("snapshot {}", snapshot.id), SnapshotState::Ready)?;
+    state.events.publish(snapshot_created(&snapshot));
+    state.snapshots.insert(snapshot.id.clone(), snapshot);
+    Ok(())
 }
 
-async fn exec_instance_handler(
-    State(_state): State<AppState>,
-    Path(_id): Path<String>,
-    _body: String,
-) -> Result<Json<InvokeResponse>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
+fn snapshot_created(snapshot: &Snapshot) -> PlatformEvent {
+    PlatformEvent::SnapshotCreated {
+        snapshot_id: snapshot.id.clone(),
+        container_id: snapshot.container_id.clone(),
+        size_bytes: snapshot.size_bytes,
+    }
 }
 
-async fn stop_instance_handler(
-    State(_state): State<AppState>,
-    Path(_id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
-    Ok(StatusCode::NO_CONTENT)
+/// Request counters the collector doesn't keep, and what the executor holds right now
+async fn metrics_totals(state: &AppState) -> metrics::Totals {
+    metrics::Totals {
+        requests: state
+            .metrics
+            .total_requests
+            .load(std::sync::atomic::Ordering::Relaxed),
+        cache_hits: state
+            .metrics
+            .cache_hits
+            .load(std::sync::atomic::Ordering::Relaxed),
+        active_containers: state.executor.active_containers().await,
+        active_instances: state.instances.len(),
+    }
 }
 
 async fn metrics_handler(
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let total = state
-        .metrics
-        .total_requests
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let cache_hits = state
-        .metrics
-        .cache_hits
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let docker_execs = state
-        .metrics
-        .docker_executions
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let vm_execs = state
-        .metrics
-        .vm_executions
-        .load(std::sync::atomic::Ordering::Relaxed);
-
-    Ok(Json(serde_json::json!({
-        "total_requests": total,
-        "cache_hits": cache_hits,
-        "cache_hit_rate": if total > 0 { (cache_hits as f64 / total as f64) } else { 0.0 },
-        "docker_executions": docker_execs,
-        "vm_executions": vm_execs,
-    })))
+    let totals = metrics_totals(&state).await;
+    let snapshot = state.metrics.executions.snapshot();
+    let report = metrics::Report::new(snapshot.scoped(&Scope::from_headers(&headers)), &totals);
+    let mut body = serde_json::json!({
+        "negative_cache_fast_fails": state.executor.negative_cache_fast_fails(),
+        "vm_network": state.executor.vm_network_stats(),
+        "canary_executions": state.executor.container_pool().canaries().executions(),
+        "speculation": state.executor.speculation_stats(),
+        "result_cache": state.executor.result_cache_stats(),
+        "events": state.events.stats(),
+    });
+    if let (Some(body), serde_json::Value::Object(report)) =
+        (body.as_object_mut(), serde_json::json!(report))
+    {
+        body.extend(report);
+    }
+    Ok(Json(body))
 }
 
 async fn detailed_metrics_handler(
     State(state): State<AppState>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let total = state
-        .metrics
-        .total_requests
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let cache_hits = state
-        .metrics
-        .cache_hits
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let docker_execs = state
-        .metrics
-        .docker_executions
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let vm_execs = state
-        .metrics
-        .vm_executions
-        .load(std::sync::atomic::Ordering::Relaxed);
+    let totals = metrics_totals(&state).await;
+    let snapshot = state.metrics.executions.snapshot();
 
     Ok(Json(serde_json::json!({
         "summary": {
-            "total_requests": total,
-            "cache_hits": cache_hits,
-            "cache_hit_rate": if total > 0 { (cache_hits as f64 / total as f64) } else { 0.0 },
+            "total_requests": totals.requests,
+            "cache_hits": totals.cache_hits,
+            "cache_hit_rate": totals.cache_hit_rate(),
         },
         "runtimes": {
             "docker": {
-                "executions": docker_execs,
+                "executions": snapshot.executions_on("docker"),
                 "available": true,
             },
             "firecracker": {
-                "executions": vm_execs,
+                "executions": snapshot.executions_on("firecracker"),
                 "available": cfg!(target_os = "linux"),
             }
         },
         "performance": {
-            "avg_cold_start_ms": 500,
-            "avg_warm_start_ms": 50,
-            "p99_latency_ms": 1000,
+            "avg_cold_start_ms": snapshot.cold.mean_ms,
+            "avg_warm_start_ms": snapshot.warm.mean_ms,
+            "p50_latency_ms": snapshot.overall.p50_ms,
+            "p90_latency_ms": snapshot.overall.p90_ms,
+            "p99_latency_ms": snapshot.overall.p99_ms,
         }
     })))
 }
 
+/// The same figures as `/api/v1/metrics`, for Prometheus to scrape
+async fn prometheus_metrics_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let totals = metrics_totals(&state).await;
+    let snapshot = state
+        .metrics
+        .executions
+        .snapshot()
+        .scoped(&Scope::from_headers(&headers));
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        metrics::render_prometheus(&snapshot, &totals),
+    )
+}
+
 async fn stream_logs_handler(
     State(_state): State<AppState>,
     Path(_id): Path<String>,
@@ -554,6 +3950,297 @@ async fn stream_logs_handler(
     Sse::new(UnboundedReceiverStream::new(rx))
 }
 
+/// Workflow steps run on this gateway's executor with the default limits, on behalf of
+/// the submitting tenant
+struct PlatformStepRunner(AppState, Option<String>);
+
+#[async_trait::async_trait]
+impl StepRunner for PlatformStepRunner {
+    async fn run_step(
+        &self,
+        workflow: &str,
+        step: faas_common::workflow::WorkflowStep,
+        input: Option<faas_common::workflow::StepInput>,
+        scope: &CancelScope,
+    ) -> Result<faas_common::workflow::StepOutput, faas_common::workflow::StepError> {
+        let state = &self.0;
+        let limits = state
+            .limits
+            .resolve(None, None, None, None)
+            .map_err(|e| e.to_string())?;
+        state
+            .metrics
+            .total_requests
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        let id = Uuid::new_v4().to_string();
+        state.execution_owners.record(&id, self.1.as_deref());
+        let run = state
+            .kill_switch
+            .admit(
+                &id,
+                Workload {
+                    tenant: self.1.clone(),
+                    image: step.image.clone(),
+                    ..Default::default()
+                },
+            )
+            .map_err(|hit| hit.to_string())?;
+        // Steps of a workflow share its namespace
+        let kv = state.kv.grant(
+            self.1.as_deref(),
+            &kv::scoped_namespace("workflow", workflow),
+        );
+        let mut env = LayeredEnv::new();
+        env.set(EnvLayer::Request, step.env)
+            .map_err(|e| e.to_string())?;
+        env.set(EnvLayer::Platform, kv.env())
+            .map_err(|e| e.to_string())?;
+        let (code, payload, read_only_mounts) = match input {
+            None => (step.command, Vec::new(), Vec::new()),
+            Some(faas_common::workflow::StepInput::Bytes(bytes)) => {
+                (step.command, bytes, Vec::new())
+            }
+            Some(faas_common::workflow::StepInput::File { path, .. }) => (
+                workflows::reading_spilled_input(&step.command),
+                Vec::new(),
+                vec![(
+                    path.to_string_lossy().into_owned(),
+                    workflows::SPILLED_INPUT_PATH.to_string(),
+                )],
+            ),
+        };
+        let request = platform::executor::Request {
+            id,
+            code,
+            mode: platform::executor::Mode::Ephemeral,
+            env: step.image,
+            timeout: step
+                .resources
+                .timeout_ms
+                .map_or(state.executor.default_timeout(), Duration::from_millis),
+            payload,
+            read_only_mounts,
+            env_vars: Some(env.into_map()),
+            memory_mb: step.resources.memory_mb,
+            cpu_cores: step.resources.cpu_cores.map(f64::from),
+            ulimits: Some(limits.ulimits),
+            shm_size_mb: Some(limits.shm_size_mb),
+            pids_limit: Some(limits.pids_limit),
+            tmpfs: (!limits.tmpfs.is_empty()).then_some(limits.tmpfs),
+            ..Default::default()
+        };
+        let response = run_killable(state, &run, scope, request)
+            .await
+            .map_err(Stopped::into_step_error)?
+            .map_err(|e| {
+                warn!(
+                    "Workflow {} step {} failed to run: {}",
+                    workflow, step.name, e
+                );
+                e.to_string()
+            })?;
+
+        persist_logs(&state, &response).await;
+        state.usage.note_log(&response.id, self.1.as_deref());
+        Ok(faas_common::workflow::StepOutput {
+            exit_code: response.exit_code,
+            stdout: String::from_utf8_lossy(&response.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&response.stderr).into_owned(),
+        })
+    }
+}
+
+/// The workflow's id, chosen by `x-faas-workflow-id` or generated, belongs to the caller's
+/// tenant; an id another tenant holds is a 409.
+async fn submit_workflow_wrapper(
+    State(state): State<AppState>,
+    mut headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Json<faas_common::workflow::WorkflowRun>, Response> {
+    let id = headers
+        .get(workflows::WORKFLOW_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+    let value = id
+        .parse()
+        .map_err(|_| StatusCode::BAD_REQUEST.into_response())?;
+    if !state
+        .execution_owners
+        .claim(&id, Scope::from_headers(&headers).tenant())
+    {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "Conflict",
+            format!("workflow id {id} is taken"),
+        )
+        .into_response());
+    }
+    headers.insert(workflows::WORKFLOW_ID_HEADER, value);
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let workflows = Workflows {
+        runner: Arc::new(PlatformStepRunner(state.clone(), tenant)),
+        cancels: state.cancels.clone(),
+    };
+    workflows::submit_workflow_handler(State(workflows), headers, body).await
+}
+
+async fn cancel_workflow_wrapper(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<CancelReport>, CancelError> {
+    workflows::cancel_workflow_handler(State(state.cancels.clone()), Path(id)).await
+}
+
+async fn get_kv_wrapper(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    authorized: Option<Extension<Authorized>>,
+    path: Path<(String, String)>,
+) -> Result<Json<KvEntry>, KvError> {
+    kv::get_kv_handler(State(state.kv), headers, authorized, path).await
+}
+
+async fn put_kv_wrapper(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    authorized: Option<Extension<Authorized>>,
+    path: Path<(String, String)>,
+    body: Json<KvPut>,
+) -> Result<Json<KvEntry>, KvError> {
+    kv::put_kv_handler(State(state.kv), headers, authorized, path, body).await
+}
+
+async fn promoted_pools_wrapper(State(state): State<AppState>) -> Json<PromotionReport> {
+    promotion::promoted_pools_handler(State(state.promotion)).await
+}
+
+async fn list_kv_wrapper(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    authorized: Option<Extension<Authorized>>,
+    path: Path<String>,
+    query: Query<kv::ListQuery>,
+) -> Result<Json<Vec<KvEntry>>, KvError> {
+    kv::list_kv_handler(State(state.kv), headers, authorized, path, query).await
+}
+
+async fn upload_artifact_wrapper(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Response {
+    let tenant = snapshot_fs::request_tenant(&headers);
+    let declared = headers
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    if let Err(e) = state
+        .usage
+        .check_storage(tenant.as_deref(), StoredKind::Artifact, declared)
+        .await
+    {
+        return usage::refusal(e);
+    }
+    match artifacts::upload_artifact_handler(State(state.artifacts), body).await {
+        Ok(Json(info)) => {
+            state
+                .usage
+                .record_stored(
+                    tenant.as_deref(),
+                    StoredKind::Artifact,
+                    info.size_bytes as i64,
+                )
+                .await;
+            Json(info).into_response()
+        }
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Serve a download through `serve`, refused once the tenant's egress is spent and billed
+/// for what the response carries
+async fn metered_download<F>(state: &AppState, headers: &HeaderMap, serve: F) -> Response
+where
+    F: std::future::Future<Output = Response>,
+{
+    let tenant = snapshot_fs::request_tenant(headers);
+    if let Err(e) = state.usage.check_egress(tenant.as_deref(), 0).await {
+        return usage::refusal(e);
+    }
+    let response = serve.await;
+    let sent = response
+        .headers()
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    if response.status().is_success() && sent > 0 {
+        state.usage.record_egress(tenant.as_deref(), sent).await;
+    }
+    response
+}
+
+async fn events_wrapper(
+    State(state): State<AppState>,
+    query: Query<EventsQuery>,
+) -> Result<Json<EventPage>, Response> {
+    events::events_handler(State(state.events), query).await
+}
+
+async fn usage_wrapper(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<UsageBreakdown>, Response> {
+    usage::usage_handler(State(state.usage), headers).await
+}
+
+async fn account_usage_wrapper(
+    State(state): State<AppState>,
+    id: Path<String>,
+) -> Result<Json<UsageBreakdown>, Response> {
+    usage::account_usage_handler(State(state.usage), id).await
+}
+
+async fn head_payload_wrapper(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> StatusCode {
+    payloads::head_payload_handler(State(state.payloads), Path(hash)).await
+}
+
+async fn put_payload_wrapper(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    body: axum::body::Body,
+) -> impl IntoResponse {
+    payloads::put_payload_handler(State(state.payloads), Path(hash), body).await
+}
+
+async fn download_artifact_wrapper(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    headers: axum::http::HeaderMap,
+) -> impl IntoResponse {
+    let serve = artifacts::download_artifact_handler(
+        State(state.artifacts.clone()),
+        Path(hash),
+        headers.clone(),
+    );
+    metered_download(&state, &headers, serve).await
+}
+
+async fn download_log_wrapper(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: axum::http::HeaderMap,
+) -> impl IntoResponse {
+    let serve =
+        artifacts::download_log_handler(State(state.logs.clone()), Path(id), headers.clone());
+    metered_download(&state, &headers, serve).await
+}
+
 /// WebSocket streaming endpoint wrapper
 async fn ws_stream_wrapper(
     ws: axum::extract::ws::WebSocketUpgrade,
@@ -563,16 +4250,42 @@ async fn ws_stream_wrapper(
     streaming::ws_stream_handler(ws, Path(container_id), State(state.streaming)).await
 }
 
-async fn health_handler(
-    State(_state): State<AppState>,
-) -> Result<Json<HealthResponse>, StatusCode> {
+async fn capabilities_handler(State(state): State<AppState>) -> Json<CapabilitiesResponse> {
+    let host = state.executor.image_metadata().host_platform();
+    let runtimes = state.health.check().await;
+    Json(CapabilitiesResponse {
+        os: host.os.clone(),
+        arch: host.architecture.clone(),
+        docker: runtimes.docker.is_healthy(),
+        firecracker: runtimes.firecracker.is_healthy(),
+        gpu: state.executor.gpu_available(),
+        degraded_environments: state.executor.container_pool().canaries().degraded(),
+    })
+}
+
+async fn health_handler(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
     static START_TIME: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
     let start = START_TIME.get_or_init(Instant::now);
+    let degraded = state.executor.container_pool().canaries().degraded();
+    let components = state.health.check().await;
 
-    Ok(Json(HealthResponse {
-        status: "healthy".to_string(),
-        docker: true,
-        firecracker: cfg!(target_os = "linux"),
+    let (code, status) = if !components.can_execute() {
+        (StatusCode::SERVICE_UNAVAILABLE, "unhealthy")
+    } else if !components.all_healthy() || !degraded.is_empty() {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "healthy")
+    };
+    let response = HealthResponse {
+        status: status.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        docker: components.docker.is_healthy(),
+        firecracker: components.firecracker.is_healthy(),
+        components,
+        gpu: state.executor.gpu_available(),
         uptime_ms: start.elapsed().as_millis() as u64,
-    }))
+        degraded_environments: degraded,
+        active_kill_switches: state.kill_switch.list(),
+    };
+    (code, Json(response))
 }
@@ -0,0 +1,697 @@
+//! Latencies and outcomes of executions, for `GET /api/v1/metrics` and `GET /metrics`.
+//!
+//! Latencies go into one HDR histogram per runtime and start kind, in microseconds to three
+//! significant figures, so percentiles stay within 0.1% whatever the spread. A cold start
+//! is one the executor started a container or VM for; a warm one was served from a
+//! prewarmed container, a running instance or the result cache. Executions are also
+//! counted per image, with the ones that failed: those that exited non-zero or never ran,
+//! and the same again per tenant for executions that ran for one.
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::tenancy::Scope;
+
+/// Longest latency told apart from a longer one; an hour, in microseconds
+const HIGHEST_MICROS: u64 = 3_600_000_000;
+const SIGNIFICANT_FIGURES: u8 = 3;
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Start {
+    Cold,
+    Warm,
+}
+
+impl Start {
+    fn label(self) -> &'static str {
+        match self {
+            Start::Cold => "cold",
+            Start::Warm => "warm",
+        }
+    }
+}
+
+/// An execution that ran, successfully or not
+#[derive(Debug, Clone, Copy)]
+pub struct Execution<'a> {
+    pub image: &'a str,
+    /// Who the execution ran for, if anyone
+    pub tenant: Option<&'a str>,
+    /// `docker` or `firecracker`
+    pub runtime: &'a str,
+    pub start: Start,
+    pub duration: Duration,
+    /// Exited zero
+    pub succeeded: bool,
+}
+
+#[derive(Default)]
+pub struct Collector {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    latencies: BTreeMap<(String, Start), Latency>,
+    images: BTreeMap<String, Counts>,
+    tenants: BTreeMap<String, Counts>,
+}
+
+struct Latency {
+    histogram: Histogram<u64>,
+    /// Exact, unlike a sum of the histogram's rounded values
+    total: Duration,
+}
+
+impl Latency {
+    fn new() -> Self {
+        Self {
+            histogram: Histogram::new_with_bounds(1, HIGHEST_MICROS, SIGNIFICANT_FIGURES)
+                .expect("valid histogram bounds"),
+            total: Duration::ZERO,
+        }
+    }
+
+    fn record(&mut self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.histogram.saturating_record(micros.max(1));
+        self.total += duration;
+    }
+
+    fn merge(&mut self, other: &Latency) {
+        // Both have the same bounds, so adding can't fail
+        let _ = self.histogram.add(&other.histogram);
+        self.total += other.total;
+    }
+
+    fn summary(&self) -> LatencySummary {
+        let ms = |micros: u64| micros as f64 / 1000.0;
+        let count = self.histogram.len();
+        LatencySummary {
+            count,
+            mean_ms: if count == 0 {
+                0.0
+            } else {
+                self.total.as_secs_f64() * 1000.0 / count as f64
+            },
+            p50_ms: ms(self.histogram.value_at_quantile(0.5)),
+            p90_ms: ms(self.histogram.value_at_quantile(0.9)),
+            p99_ms: ms(self.histogram.value_at_quantile(0.99)),
+            max_ms: ms(self.histogram.max()),
+            total_ms: self.total.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    executions: u64,
+    failures: u64,
+}
+
+impl Counts {
+    fn add(&mut self, succeeded: bool) {
+        self.executions += 1;
+        if !succeeded {
+            self.failures += 1;
+        }
+    }
+
+    fn failure_rate(&self) -> f64 {
+        self.failures as f64 / self.executions as f64
+    }
+}
+
+impl Collector {
+    pub fn record(&self, execution: Execution<'_>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .latencies
+            .entry((execution.runtime.to_string(), execution.start))
+            .or_insert_with(Latency::new)
+            .record(execution.duration);
+        inner.count(execution.image, execution.tenant, execution.succeeded);
+    }
+
+    /// An execution the executor failed to run, so there's no latency to go with it
+    pub fn record_error(&self, image: &str, tenant: Option<&str>) {
+        self.inner.lock().unwrap().count(image, tenant, false);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let inner = self.inner.lock().unwrap();
+        let mut overall = Latency::new();
+        let mut by_start: BTreeMap<Start, Latency> = BTreeMap::new();
+        let mut latencies = Vec::with_capacity(inner.latencies.len());
+        for ((runtime, start), latency) in &inner.latencies {
+            overall.merge(latency);
+            by_start
+                .entry(*start)
+                .or_insert_with(Latency::new)
+                .merge(latency);
+            latencies.push(RuntimeLatency {
+                runtime: runtime.clone(),
+                start: *start,
+                summary: latency.summary(),
+            });
+        }
+        let summary_of = |start| {
+            by_start
+                .get(&start)
+                .map(Latency::summary)
+                .unwrap_or_else(|| Latency::new().summary())
+        };
+        MetricsSnapshot {
+            overall: overall.summary(),
+            cold: summary_of(Start::Cold),
+            warm: summary_of(Start::Warm),
+            latencies,
+            images: inner
+                .images
+                .iter()
+                .map(|(image, counts)| ImageSummary {
+                    image: image.clone(),
+                    executions: counts.executions,
+                    failures: counts.failures,
+                    failure_rate: counts.failure_rate(),
+                })
+                .collect(),
+            tenants: inner
+                .tenants
+                .iter()
+                .map(|(tenant, counts)| TenantSummary {
+                    tenant: tenant.clone(),
+                    executions: counts.executions,
+                    failures: counts.failures,
+                    failure_rate: counts.failure_rate(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Inner {
+    fn count(&mut self, image: &str, tenant: Option<&str>, succeeded: bool) {
+        self.images
+            .entry(image.to_string())
+            .or_default()
+            .add(succeeded);
+        if let Some(tenant) = tenant {
+            self.tenants
+                .entry(tenant.to_string())
+                .or_default()
+                .add(succeeded);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeLatency {
+    pub runtime: String,
+    pub start: Start,
+    #[serde(flatten)]
+    pub summary: LatencySummary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageSummary {
+    pub image: String,
+    /// Including failures
+    pub executions: u64,
+    pub failures: u64,
+    pub failure_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantSummary {
+    pub tenant: String,
+    /// Including failures
+    pub executions: u64,
+    pub failures: u64,
+    pub failure_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    /// Every execution that ran
+    pub overall: LatencySummary,
+    pub cold: LatencySummary,
+    pub warm: LatencySummary,
+    /// By runtime and start kind
+    pub latencies: Vec<RuntimeLatency>,
+    pub images: Vec<ImageSummary>,
+    /// Executions that ran for a tenant; untenanted ones are only in `images`
+    pub tenants: Vec<TenantSummary>,
+}
+
+impl MetricsSnapshot {
+    /// Executions that ran on `runtime`, cold or warm
+    pub fn executions_on(&self, runtime: &str) -> u64 {
+        self.latencies
+            .iter()
+            .filter(|latency| latency.runtime == runtime)
+            .map(|latency| latency.summary.count)
+            .sum()
+    }
+
+    /// Executions that ran or failed to, across images
+    pub fn executions(&self) -> u64 {
+        self.images.iter().map(|image| image.executions).sum()
+    }
+
+    /// With only the tenants `scope` owns left in `tenants`
+    pub fn scoped(mut self, scope: &Scope) -> Self {
+        self.tenants
+            .retain(|tenant| scope.owns(Some(tenant.tenant.as_str())));
+        self
+    }
+}
+
+/// Counters and gauges kept outside the collector
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Totals {
+    pub requests: u64,
+    pub cache_hits: u64,
+    pub active_containers: usize,
+    pub active_instances: usize,
+}
+
+impl Totals {
+    pub fn cache_hit_rate(&self) -> f64 {
+        if self.requests > 0 {
+            self.cache_hits as f64 / self.requests as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The execution figures of `GET /api/v1/metrics`
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub total_requests: u64,
+    pub total_executions: u64,
+    pub avg_execution_time_ms: f64,
+    pub cache_hits: u64,
+    pub cache_hit_rate: f64,
+    pub active_containers: usize,
+    pub active_instances: usize,
+    pub docker_executions: u64,
+    pub vm_executions: u64,
+    pub latency: LatencyReport,
+    pub images: Vec<ImageSummary>,
+    pub tenants: Vec<TenantSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyReport {
+    pub overall: LatencySummary,
+    pub cold: LatencySummary,
+    pub warm: LatencySummary,
+    pub by_runtime: Vec<RuntimeLatency>,
+}
+
+impl Report {
+    pub fn new(snapshot: MetricsSnapshot, totals: &Totals) -> Self {
+        Self {
+            total_requests: totals.requests,
+            total_executions: snapshot.executions(),
+            avg_execution_time_ms: snapshot.overall.mean_ms,
+            cache_hits: totals.cache_hits,
+            cache_hit_rate: totals.cache_hit_rate(),
+            active_containers: totals.active_containers,
+            active_instances: totals.active_instances,
+            docker_executions: snapshot.executions_on("docker"),
+            vm_executions: snapshot.executions_on("firecracker"),
+            latency: LatencyReport {
+                overall: snapshot.overall,
+                cold: snapshot.cold,
+                warm: snapshot.warm,
+                by_runtime: snapshot.latencies,
+            },
+            images: snapshot.images,
+            tenants: snapshot.tenants,
+        }
+    }
+}
+
+/// `snapshot` and `totals` in the Prometheus text exposition format
+pub fn render_prometheus(snapshot: &MetricsSnapshot, totals: &Totals) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "# HELP faas_execution_duration_seconds Execution latency by runtime and start.\n\
+         # TYPE faas_execution_duration_seconds summary\n",
+    );
+    for latency in &snapshot.latencies {
+        let labels = format!(
+            "runtime=\"{}\",start=\"{}\"",
+            escape(&latency.runtime),
+            latency.start.label()
+        );
+        let summary = &latency.summary;
+        for (quantile, ms) in QUANTILES
+            .iter()
+            .zip([summary.p50_ms, summary.p90_ms, summary.p99_ms])
+        {
+            let _ = writeln!(
+                out,
+                "faas_execution_duration_seconds{{{labels},quantile=\"{quantile}\"}} {}",
+                ms / 1000.0
+            );
+        }
+        let _ = writeln!(
+            out,
+            "faas_execution_duration_seconds_sum{{{labels}}} {}",
+            summary.total_ms / 1000.0
+        );
+        let _ = writeln!(
+            out,
+            "faas_execution_duration_seconds_count{{{labels}}} {}",
+            summary.count
+        );
+    }
+
+    out.push_str(
+        "# HELP faas_image_executions_total Executions by image, including failures.\n\
+         # TYPE faas_image_executions_total counter\n",
+    );
+    for image in &snapshot.images {
+        let _ = writeln!(
+            out,
+            "faas_image_executions_total{{image=\"{}\"}} {}",
+            escape(&image.image),
+            image.executions
+        );
+    }
+    out.push_str(
+        "# HELP faas_image_failures_total Executions by image that exited non-zero or never ran.\n\
+         # TYPE faas_image_failures_total counter\n",
+    );
+    for image in &snapshot.images {
+        let _ = writeln!(
+            out,
+            "faas_image_failures_total{{image=\"{}\"}} {}",
+            escape(&image.image),
+            image.failures
+        );
+    }
+    out.push_str(
+        "# HELP faas_tenant_executions_total Executions by tenant, including failures.\n\
+         # TYPE faas_tenant_executions_total counter\n",
+    );
+    for tenant in &snapshot.tenants {
+        let _ = writeln!(
+            out,
+            "faas_tenant_executions_total{{tenant=\"{}\"}} {}",
+            escape(&tenant.tenant),
+            tenant.executions
+        );
+    }
+    out.push_str(
+        "# HELP faas_tenant_failures_total Executions by tenant that exited non-zero or never ran.\n\
+         # TYPE faas_tenant_failures_total counter\n",
+    );
+    for tenant in &snapshot.tenants {
+        let _ = writeln!(
+            out,
+            "faas_tenant_failures_total{{tenant=\"{}\"}} {}",
+            escape(&tenant.tenant),
+            tenant.failures
+        );
+    }
+
+    for (name, kind, help, value) in [
+        (
+            "faas_requests_total",
+            "counter",
+            "Execution requests received.",
+            totals.requests,
+        ),
+        (
+            "faas_cache_hits_total",
+            "counter",
+            "Executions answered from the result cache.",
+            totals.cache_hits,
+        ),
+        (
+            "faas_active_containers",
+            "gauge",
+            "Pooled containers, idle or in use.",
+            totals.active_containers as u64,
+        ),
+        (
+            "faas_active_instances",
+            "gauge",
+            "Instances and persistent executions.",
+            totals.active_instances as u64,
+        ),
+    ] {
+        let _ = writeln!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}"
+        );
+    }
+    out
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(collector: &Collector, image: &str, start: Start, ms: u64, succeeded: bool) {
+        collector.record(Execution {
+            image,
+            tenant: None,
+            runtime: "docker",
+            start,
+            duration: Duration::from_millis(ms),
+            succeeded,
+        });
+    }
+
+    fn within(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() <= expected * 0.001 + 0.001
+    }
+
+    #[test]
+    fn percentiles_follow_the_recorded_latencies() {
+        let collector = Collector::default();
+        for ms in 1..=100 {
+            run(&collector, "alpine:latest", Start::Warm, ms, true);
+        }
+        for ms in [800, 1200] {
+            run(&collector, "python:3.11", Start::Cold, ms, true);
+        }
+
+        let snapshot = collector.snapshot();
+        let warm = &snapshot.warm;
+        assert_eq!(warm.count, 100);
+        assert!(within(warm.mean_ms, 50.5));
+        assert!(within(warm.p50_ms, 50.0));
+        assert!(within(warm.p90_ms, 90.0));
+        assert!(within(warm.p99_ms, 99.0));
+        assert!(within(warm.max_ms, 100.0));
+
+        let cold = &snapshot.cold;
+        assert_eq!(cold.count, 2);
+        assert!(within(cold.mean_ms, 1000.0));
+        assert!(within(cold.max_ms, 1200.0));
+
+        assert_eq!(snapshot.overall.count, 102);
+        assert!(within(snapshot.overall.p99_ms, 800.0));
+        assert!(snapshot.overall.p50_ms <= snapshot.overall.p90_ms);
+        assert_eq!(snapshot.executions_on("docker"), 102);
+        assert_eq!(snapshot.executions_on("firecracker"), 0);
+    }
+
+    #[test]
+    fn latencies_are_kept_apart_by_runtime_and_start() {
+        let collector = Collector::default();
+        run(&collector, "alpine:latest", Start::Cold, 400, true);
+        run(&collector, "alpine:latest", Start::Warm, 20, true);
+        collector.record(Execution {
+            image: "alpine:latest",
+            tenant: None,
+            runtime: "firecracker",
+            start: Start::Cold,
+            duration: Duration::from_millis(150),
+            succeeded: true,
+        });
+
+        let snapshot = collector.snapshot();
+        let keys: Vec<_> = snapshot
+            .latencies
+            .iter()
+            .map(|latency| {
+                (
+                    latency.runtime.as_str(),
+                    latency.start,
+                    latency.summary.count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("docker", Start::Cold, 1),
+                ("docker", Start::Warm, 1),
+                ("firecracker", Start::Cold, 1),
+            ]
+        );
+        assert_eq!(snapshot.cold.count, 2);
+        assert!(within(snapshot.cold.mean_ms, 275.0));
+        assert_eq!(snapshot.executions_on("firecracker"), 1);
+    }
+
+    #[test]
+    fn failures_are_counted_per_image() {
+        let collector = Collector::default();
+        run(&collector, "alpine:latest", Start::Warm, 10, true);
+        run(&collector, "alpine:latest", Start::Warm, 10, false);
+        run(&collector, "alpine:latest", Start::Warm, 10, true);
+        run(&collector, "node:20", Start::Cold, 10, true);
+        collector.record_error("alpine:latest", None);
+        collector.record_error("missing:latest", None);
+
+        let snapshot = collector.snapshot();
+        let counts: Vec<_> = snapshot
+            .images
+            .iter()
+            .map(|image| (image.image.as_str(), image.executions, image.failures))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("alpine:latest", 4, 2),
+                ("missing:latest", 1, 1),
+                ("node:20", 1, 0),
+            ]
+        );
+        assert!(within(snapshot.images[0].failure_rate, 0.5));
+        assert_eq!(snapshot.executions(), 6);
+        // Errors have no latency
+        assert_eq!(snapshot.overall.count, 4);
+    }
+
+    #[test]
+    fn tenants_are_counted_apart_from_untenanted_executions() {
+        let collector = Collector::default();
+        let ran = |tenant, succeeded| Execution {
+            image: "alpine:latest",
+            tenant,
+            runtime: "docker",
+            start: Start::Warm,
+            duration: Duration::from_millis(10),
+            succeeded,
+        };
+        collector.record(ran(Some("acme"), true));
+        collector.record(ran(Some("acme"), false));
+        collector.record(ran(Some("globex"), true));
+        collector.record(ran(None, true));
+        collector.record_error("alpine:latest", Some("globex"));
+
+        let snapshot = collector.snapshot();
+        let counts: Vec<_> = snapshot
+            .tenants
+            .iter()
+            .map(|tenant| (tenant.tenant.as_str(), tenant.executions, tenant.failures))
+            .collect();
+        assert_eq!(counts, [("acme", 2, 1), ("globex", 2, 1)]);
+        assert_eq!(snapshot.executions(), 5);
+
+        let acme = snapshot.scoped(&Scope::Tenant(Some("acme".to_string())));
+        assert_eq!(acme.tenants.len(), 1);
+        assert_eq!(acme.tenants[0].tenant, "acme");
+    }
+
+    #[test]
+    fn an_empty_collector_reports_zeroes() {
+        let snapshot = Collector::default().snapshot();
+        assert_eq!(snapshot.overall.count, 0);
+        assert_eq!(snapshot.overall.mean_ms, 0.0);
+        assert_eq!(snapshot.cold.p99_ms, 0.0);
+        assert!(snapshot.latencies.is_empty());
+        assert_eq!(snapshot.executions(), 0);
+    }
+
+    #[test]
+    fn prometheus_text_carries_every_series() {
+        let collector = Collector::default();
+        run(&collector, "alpine:latest", Start::Warm, 20, true);
+        run(&collector, "alpine:latest", Start::Warm, 40, false);
+        collector.record_error("registry/\"odd\":tag", Some("acme"));
+
+        let text = render_prometheus(
+            &collector.snapshot(),
+            &Totals {
+                requests: 3,
+                cache_hits: 1,
+                active_containers: 4,
+                active_instances: 2,
+            },
+        );
+        let value = |series: &str| -> f64 {
+            text.lines()
+                .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+                .unwrap_or_else(|| panic!("no {series} in\n{text}"))
+                .parse()
+                .unwrap()
+        };
+        let labels = r#"runtime="docker",start="warm""#;
+        assert!(within(
+            value(&format!(
+                "faas_execution_duration_seconds{{{labels},quantile=\"0.99\"}}"
+            )),
+            0.04
+        ));
+        assert!(within(
+            value(&format!("faas_execution_duration_seconds_sum{{{labels}}}")),
+            0.06
+        ));
+        assert_eq!(
+            value(&format!(
+                "faas_execution_duration_seconds_count{{{labels}}}"
+            )),
+            2.0
+        );
+        assert_eq!(
+            value(r#"faas_image_executions_total{image="alpine:latest"}"#),
+            2.0
+        );
+        assert_eq!(
+            value(r#"faas_image_failures_total{image="registry/\"odd\":tag"}"#),
+            1.0
+        );
+        assert_eq!(value(r#"faas_tenant_failures_total{tenant="acme"}"#), 1.0);
+        assert_eq!(value("faas_requests_total"), 3.0);
+        assert_eq!(value("faas_cache_hits_total"), 1.0);
+        assert_eq!(value("faas_active_containers"), 4.0);
+        assert_eq!(value("faas_active_instances"), 2.0);
+        assert!(text.contains("# TYPE faas_execution_duration_seconds summary\n"));
+        assert!(text.contains("# TYPE faas_active_instances gauge\n"));
+    }
+}
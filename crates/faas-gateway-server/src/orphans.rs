@@ -0,0 +1,412 @@
+//! Containers a previous run of this gateway left behind.
+//!
+//! Containers are found by the labels in [`container_labels`]; only those carrying this
+//! gateway's [`container_labels::gateway_instance`] are touched, since another gateway
+//! sharing the daemon may still be using its own. An execution or pool container created
+//! before this process booted has nobody waiting on it and is removed. An instance
+//! container is registered again if it is still running, so a restart doesn't lose dev
+//! environments; a stopped one is left for an operator to look at. A port proxy goes with
+//! its instance: kept, and its port listed again, for an adopted one, removed otherwise.
+
+use crate::lifecycle::{InstanceState, Lifecycle};
+use crate::Instance;
+use dashmap::DashMap;
+use faas_executor::bollard::errors::Error as BollardError;
+use faas_executor::bollard::Docker;
+use faas_executor::container_labels::{self, ContainerKind, ManagedContainer};
+use faas_executor::platform::ports::{self, PortMapping};
+use faas_executor::platform::{InstanceContainers, WorkspaceMount};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/// What the startup pass did
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReclaimReport {
+    /// Execution and pool containers removed
+    pub removed: Vec<ManagedContainer>,
+    /// Ids of the instances registered again
+    pub adopted: Vec<String>,
+    /// Stopped instance containers, kept as they are
+    pub left: Vec<ManagedContainer>,
+    /// Containers that could not be removed, and why
+    pub failed: Vec<ReclaimFailure>,
+    /// Why the pass couldn't list containers at all
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReclaimFailure {
+    pub container_id: String,
+    pub error: String,
+}
+
+/// `GET /api/v1/admin/orphans`
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphansResponse {
+    pub gateway_instance: String,
+    /// The pass run when the gateway started
+    pub reclaimed: ReclaimReport,
+    /// This gateway's containers that nothing accounts for right now
+    pub orphans: Vec<ManagedContainer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fate {
+    Remove,
+    Adopt,
+    Leave,
+}
+
+/// Finds and reclaims this gateway's leftover containers
+pub struct Orphans {
+    docker: Arc<Docker>,
+    /// Unix seconds; execution containers older than this predate the process
+    booted: i64,
+    reclaimed: Mutex<ReclaimReport>,
+}
+
+impl Orphans {
+    pub fn new(docker: Arc<Docker>, booted: i64) -> Self {
+        Self {
+            docker,
+            booted,
+            reclaimed: Mutex::new(ReclaimReport::default()),
+        }
+    }
+
+    /// Remove stale execution containers and register running instance containers in
+    /// `instances`. Failures are part of the report rather than an error, so a daemon
+    /// that is down doesn't keep the gateway from starting.
+    pub async fn reclaim(&self, instances: &DashMap<String, Instance>) -> ReclaimReport {
+        let mut report = ReclaimReport::default();
+        let found = match container_labels::list_managed(&self.docker).await {
+            Ok(found) => found,
+            Err(e) => {
+                report.error = Some(e.to_string());
+                return self.keep(report);
+            }
+        };
+        let containers = InstanceContainers::new(self.docker.clone());
+        // Instances first, so their proxies find them adopted
+        let (mut found, proxies): (Vec<_>, Vec<_>) = found
+            .into_iter()
+            .partition(|container| container.kind != ContainerKind::Proxy);
+        found.extend(proxies);
+        for container in found {
+            match self.fate(&container, instances) {
+                Some(Fate::Remove) => match containers.remove(&container.container_id).await {
+                    Ok(()) => report.removed.push(container),
+                    Err(e) => report.failed.push(ReclaimFailure {
+                        container_id: container.container_id,
+                        error: e.to_string(),
+                    }),
+                },
+                Some(Fate::Adopt) => {
+                    let instance = adopted_instance(&container);
+                    report.adopted.push(instance.id.clone());
+                    instances.insert(instance.id.clone(), instance);
+                }
+                Some(Fate::Leave) => report.left.push(container),
+                None if container.kind == ContainerKind::Proxy => {
+                    if let Some(mut instance) = instances.get_mut(&container.function_id) {
+                        relist_ports(&mut instance, &container.ports);
+                    }
+                }
+                None => {}
+            }
+        }
+        self.keep(report)
+    }
+
+    /// The last [`Self::reclaim`] report alongside what is unaccounted for now
+    pub async fn inspect(
+        &self,
+        instances: &DashMap<String, Instance>,
+    ) -> Result<OrphansResponse, BollardError> {
+        let orphans = container_labels::list_managed(&self.docker)
+            .await?
+            .into_iter()
+            .filter(|container| self.fate(container, instances).is_some())
+            .collect();
+        Ok(OrphansResponse {
+            gateway_instance: container_labels::gateway_instance().to_string(),
+            reclaimed: self.reclaimed.lock().unwrap().clone(),
+            orphans,
+        })
+    }
+
+    fn keep(&self, report: ReclaimReport) -> ReclaimReport {
+        *self.reclaimed.lock().unwrap() = report.clone();
+        report
+    }
+
+    /// `None` for a container that is someone else's or still accounted for
+    fn fate(
+        &self,
+        container: &ManagedContainer,
+        instances: &DashMap<String, Instance>,
+    ) -> Option<Fate> {
+        if container.gateway_instance != container_labels::gateway_instance() {
+            return None;
+        }
+        match container.kind {
+            ContainerKind::Ephemeral | ContainerKind::Pooled => {
+                let stale = container.created.is_some_and(|at| at < self.booted);
+                stale.then_some(Fate::Remove)
+            }
+            ContainerKind::Instance if instances.contains_key(&container.request_id) => None,
+            ContainerKind::Instance if container.is_running() || paused(container) => {
+                Some(Fate::Adopt)
+            }
+            ContainerKind::Instance => Some(Fate::Leave),
+            ContainerKind::Proxy if instances.contains_key(&container.function_id) => None,
+            ContainerKind::Proxy => Some(Fate::Remove),
+        }
+    }
+}
+
+fn relist_ports(instance: &mut Instance, ports: &[PortMapping]) {
+    for port in ports {
+        if !instance.ports.iter().any(|p| p.key() == port.key()) {
+            instance.ports.push(*port);
+        }
+    }
+    instance.endpoints = ports::endpoints(&instance.ports, &ports::public_host());
+}
+
+fn paused(container: &ManagedContainer) -> bool {
+    container.state.as_deref() == Some("paused")
+}
+
+/// The instance a still running container belonged to, from its labels
+fn adopted_instance(container: &ManagedContainer) -> Instance {
+    let label = |key: &str| container.labels.get(key);
+    let created_at = container
+        .created
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .unwrap_or_else(chrono::Utc::now);
+    Instance {
+        id: container.request_id.clone(),
+        name: label(container_labels::INSTANCE_NAME).cloned(),
+        image: container.image.clone().unwrap_or_default(),
+        tenant: label(container_labels::TENANT).cloned(),
+        lifecycle: Lifecycle::new(if paused(container) {
+            InstanceState::Paused
+        } else {
+            InstanceState::Running
+        }),
+        created_at: created_at.to_rfc3339(),
+        cpu_cores: label(container_labels::CPU_CORES).and_then(|v| v.parse().ok()),
+        memory_mb: label(container_labels::MEMORY_MB).and_then(|v| v.parse().ok()),
+        container_id: Some(container.container_id.clone()),
+        container: None,
+        expires_at: None,
+        workspace: WorkspaceMount::from_labels(&container.labels),
+        endpoints: ports::endpoints(&container.ports, &ports::public_host()),
+        ports: container.ports.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{Method, Uri};
+    use axum::{Json, Router};
+    use faas_executor::bollard::API_DEFAULT_VERSION;
+    use serde_json::{json, Value};
+
+    const BOOTED: i64 = 1_700_000_000;
+
+    fn summary(
+        id: &str,
+        kind: ContainerKind,
+        request_id: &str,
+        created: i64,
+        state: &str,
+    ) -> Value {
+        let mut labels = container_labels::labels(kind, request_id, request_id);
+        labels.insert(
+            container_labels::INSTANCE_NAME.to_string(),
+            "dev".to_string(),
+        );
+        labels.insert(container_labels::MEMORY_MB.to_string(), "512".to_string());
+        json!({
+            "Id": id,
+            "Image": "alpine:latest",
+            "Created": created,
+            "State": state,
+            "Labels": labels,
+        })
+    }
+
+    /// A daemon listing `containers` and recording the ids it is asked to remove
+    async fn daemon(containers: Vec<Value>) -> (Arc<Docker>, Arc<Mutex<Vec<String>>>) {
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let seen = removed.clone();
+        let app = Router::new().fallback(move |method: Method, uri: Uri| {
+            let containers = containers.clone();
+            let seen = seen.clone();
+            async move {
+                if method == Method::DELETE {
+                    let id = uri.path().rsplit('/').next().unwrap().to_string();
+                    seen.lock().unwrap().push(id);
+                    return Json(Value::Null);
+                }
+                Json(Value::Array(containers))
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let docker = Docker::connect_with_http(&format!("http://{addr}"), 2, API_DEFAULT_VERSION);
+        (Arc::new(docker.unwrap()), removed)
+    }
+
+    #[tokio::test]
+    async fn stale_executions_are_removed_and_instances_adopted() {
+        let mut foreign = summary(
+            "foreign",
+            ContainerKind::Ephemeral,
+            "x",
+            BOOTED - 60,
+            "running",
+        );
+        foreign["Labels"][container_labels::GATEWAY_INSTANCE] = json!("another-gateway");
+        let (docker, removed) = daemon(vec![
+            summary(
+                "stale",
+                ContainerKind::Ephemeral,
+                "run-1",
+                BOOTED - 60,
+                "running",
+            ),
+            summary(
+                "fresh",
+                ContainerKind::Pooled,
+                "pool-1",
+                BOOTED + 1,
+                "running",
+            ),
+            summary(
+                "dev-env",
+                ContainerKind::Instance,
+                "inst-1",
+                BOOTED - 3600,
+                "running",
+            ),
+            summary(
+                "stopped",
+                ContainerKind::Instance,
+                "inst-2",
+                BOOTED - 3600,
+                "exited",
+            ),
+            foreign,
+        ])
+        .await;
+        let instances = DashMap::new();
+        let orphans = Orphans::new(docker, BOOTED);
+
+        let report = orphans.reclaim(&instances).await;
+        assert_eq!(*removed.lock().unwrap(), vec!["stale".to_string()]);
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.adopted, vec!["inst-1".to_string()]);
+        assert_eq!(report.left[0].container_id, "stopped");
+
+        let instance = instances.get("inst-1").unwrap();
+        assert_eq!(instance.lifecycle.current(), InstanceState::Running);
+        assert_eq!(instance.container_id.as_deref(), Some("dev-env"));
+        assert_eq!(instance.name.as_deref(), Some("dev"));
+        assert_eq!(instance.memory_mb, Some(512));
+        assert_eq!(instance.image, "alpine:latest");
+    }
+
+    #[tokio::test]
+    async fn proxies_follow_their_instance() {
+        let proxy = |id: &str, instance_id: &str, host_port: u16| {
+            let mut container = summary(id, ContainerKind::Proxy, id, BOOTED - 60, "running");
+            container["Labels"][container_labels::FUNCTION_ID] = json!(instance_id);
+            container["Ports"] = json!([
+                {"PrivatePort": 8001, "PublicPort": host_port, "Type": "tcp", "IP": "0.0.0.0"},
+                {"PrivatePort": 8001, "PublicPort": host_port, "Type": "tcp", "IP": "::"},
+            ]);
+            container
+        };
+        let mut dev_env = summary(
+            "dev-env",
+            ContainerKind::Instance,
+            "inst-1",
+            BOOTED - 3600,
+            "running",
+        );
+        dev_env["Ports"] = json!([{"PrivatePort": 8000, "PublicPort": 49153, "Type": "tcp"}]);
+        // Listed ahead of the instance it belongs to
+        let (docker, removed) = daemon(vec![
+            proxy("kept", "inst-1", 49154),
+            dev_env,
+            proxy("stray", "gone", 49155),
+        ])
+        .await;
+        let instances = DashMap::new();
+        Orphans::new(docker, BOOTED).reclaim(&instances).await;
+
+        assert_eq!(*removed.lock().unwrap(), vec!["stray".to_string()]);
+        let instance = instances.get("inst-1").unwrap();
+        let ports: Vec<_> = instance.ports.iter().map(|p| p.key()).collect();
+        assert_eq!(ports, ["8000/tcp", "8001/tcp"]);
+        assert_eq!(instance.endpoints["8001/tcp"], "http://localhost:49154");
+    }
+
+    #[tokio::test]
+    async fn inspection_leaves_out_what_is_accounted_for() {
+        let (docker, _) = daemon(vec![
+            summary(
+                "stale",
+                ContainerKind::Ephemeral,
+                "run-1",
+                BOOTED - 60,
+                "running",
+            ),
+            summary(
+                "fresh",
+                ContainerKind::Ephemeral,
+                "run-2",
+                BOOTED + 1,
+                "running",
+            ),
+            summary(
+                "dev-env",
+                ContainerKind::Instance,
+                "inst-1",
+                BOOTED - 3600,
+                "running",
+            ),
+        ])
+        .await;
+        let orphans = Orphans::new(docker, BOOTED);
+        let instances = DashMap::new();
+        orphans.reclaim(&instances).await;
+
+        let response = orphans.inspect(&instances).await.unwrap();
+        // The fake daemon still lists the removed container
+        let ids: Vec<_> = response
+            .orphans
+            .iter()
+            .map(|c| c.container_id.as_str())
+            .collect();
+        assert_eq!(ids, ["stale"]);
+        assert_eq!(response.reclaimed.adopted, vec!["inst-1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn a_daemon_that_is_down_is_reported() {
+        let docker = Docker::connect_with_http("http://127.0.0.1:1", 1, API_DEFAULT_VERSION);
+        let orphans = Orphans::new(Arc::new(docker.unwrap()), BOOTED);
+        let report = orphans.reclaim(&DashMap::new()).await;
+        assert!(report.error.is_some());
+        assert!(report.removed.is_empty());
+    }
+}
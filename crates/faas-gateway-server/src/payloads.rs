@@ -0,0 +1,476 @@
+//! Deduplicated stdin payloads.
+//!
+//! Batch clients send the same large payload to many executions. Instead of inlining it
+//! every time, a client uploads it once to `PUT /api/v1/payloads/:hash` and passes the hash
+//! as `payload_ref`. Payloads are stored zstd-compressed at `<root>/<sha256>.zst`. Every
+//! execution that references one holds it until it finishes; once nothing holds a payload
+//! for the TTL it is deleted.
+//!
+//! Inline `payload`s, a byte array or a base64 string, are capped at
+//! `FAAS_MAX_INLINE_PAYLOAD_BYTES` (1 MiB by default) and uploads at `FAAS_MAX_PAYLOAD_BYTES`
+//! (256 MiB); past either the request is refused with 413 rather than cut short.
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use dashmap::DashMap;
+use faas_common::hash::{sha256_hex, SHA256_HEX_LEN};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{info, warn};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(600);
+const DEFAULT_MAX_BYTES: usize = 256 * 1024 * 1024;
+const DEFAULT_MAX_INLINE_BYTES: usize = 1024 * 1024;
+/// Room in a JSON request body for everything besides its payload; axum's own default limit
+const BODY_OVERHEAD_BYTES: usize = 2 * 1024 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Error)]
+pub enum PayloadError {
+    #[error("{0} is not a hex SHA-256")]
+    InvalidHash(String),
+    #[error("payload hashes to {actual}, not {expected}")]
+    HashMismatch { expected: String, actual: String },
+    #[error("payload {0} is not stored; upload it first")]
+    NotFound(String),
+    #[error("payload exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error(
+        "inline payload exceeds {0} bytes; upload it to /api/v1/payloads and pass payload_ref"
+    )]
+    InlineTooLarge(usize),
+    #[error("payload storage failed: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl PayloadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidHash(_) | Self::HashMismatch { .. } => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::TooLarge(_) | Self::InlineTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for PayloadError {
+    fn into_response(self) -> Response {
+        (
+            self.status(),
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+struct Entry {
+    /// Executions currently using the payload
+    refs: usize,
+    /// When `refs` last dropped to zero
+    idle_since: Instant,
+}
+
+pub struct PayloadStore {
+    root: PathBuf,
+    ttl: Duration,
+    max_bytes: usize,
+    max_inline_bytes: usize,
+    entries: DashMap<String, Entry>,
+}
+
+impl PayloadStore {
+    /// Payloads already on disk are kept for one TTL before they can be collected
+    pub fn new(root: impl Into<PathBuf>, ttl: Duration) -> std::io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        let entries = DashMap::new();
+        for file in std::fs::read_dir(&root)? {
+            let name = file?.file_name().to_string_lossy().into_owned();
+            if let Some(hash) = name.strip_suffix(".zst") {
+                entries.insert(
+                    hash.to_string(),
+                    Entry {
+                        refs: 0,
+                        idle_since: Instant::now(),
+                    },
+                );
+            }
+        }
+        Ok(Self {
+            root,
+            ttl,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_inline_bytes: DEFAULT_MAX_INLINE_BYTES,
+            entries,
+        })
+    }
+
+    /// Store rooted at `FAAS_PAYLOAD_DIR` (a temp directory when unset), collecting
+    /// unreferenced payloads after `FAAS_PAYLOAD_TTL_SECS` (600 by default), with the size
+    /// caps from the module docs
+    pub fn from_env() -> std::io::Result<Self> {
+        let root = std::env::var("FAAS_PAYLOAD_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("faas-payloads"));
+        let ttl = std::env::var("FAAS_PAYLOAD_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        let store = Self::new(root, ttl)?
+            .with_max_bytes(env_bytes("FAAS_MAX_PAYLOAD_BYTES", DEFAULT_MAX_BYTES))
+            .with_max_inline_bytes(env_bytes(
+                "FAAS_MAX_INLINE_PAYLOAD_BYTES",
+                DEFAULT_MAX_INLINE_BYTES,
+            ));
+        Ok(store)
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_max_inline_bytes(mut self, max_inline_bytes: usize) -> Self {
+        self.max_inline_bytes = max_inline_bytes;
+        self
+    }
+
+    /// Largest upload the gateway buffers, a stored payload or an instance file archive
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Refuse an inline payload over the cap
+    pub fn check_inline(&self, payload: &[u8]) -> Result<(), PayloadError> {
+        if payload.len() > self.max_inline_bytes {
+            return Err(PayloadError::InlineTooLarge(self.max_inline_bytes));
+        }
+        Ok(())
+    }
+
+    /// Largest JSON request body that can carry an inline payload at the cap, written as a
+    /// byte array of up to four characters per byte
+    pub fn request_body_limit(&self) -> usize {
+        self.max_inline_bytes.saturating_mul(4) + BODY_OVERHEAD_BYTES
+    }
+
+    fn path_for(&self, hash: &str) -> Result<PathBuf, PayloadError> {
+        let valid = hash.len() == SHA256_HEX_LEN
+            && hash
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        if !valid {
+            return Err(PayloadError::InvalidHash(hash.to_string()));
+        }
+        Ok(self.root.join(format!("{hash}.zst")))
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    /// Verify `data` against `hash` and store it compressed; re-uploads are no-ops
+    pub async fn put(&self, hash: &str, data: Vec<u8>) -> Result<(), PayloadError> {
+        let path = self.path_for(hash)?;
+        if data.len() > self.max_bytes {
+            return Err(PayloadError::TooLarge(self.max_bytes));
+        }
+        let actual = sha256_hex(&data);
+        if actual != hash {
+            return Err(PayloadError::HashMismatch {
+                expected: hash.to_string(),
+                actual,
+            });
+        }
+        if self.contains(hash) {
+            return Ok(());
+        }
+
+        let size = data.len();
+        let compressed =
+            tokio::task::spawn_blocking(move || zstd::encode_all(data.as_slice(), ZSTD_LEVEL))
+                .await
+                .map_err(std::io::Error::other)??;
+        let temp_path = self.root.join(format!(".upload-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&temp_path, &compressed).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+        self.entries.entry(hash.to_string()).or_insert(Entry {
+            refs: 0,
+            idle_since: Instant::now(),
+        });
+        info!(
+            "Stored payload {} ({} bytes, {} compressed)",
+            hash,
+            size,
+            compressed.len()
+        );
+        Ok(())
+    }
+
+    /// Take a reference to a payload for one execution and read it back; the reference
+    /// is released when the lease drops
+    pub async fn lease(
+        self: &Arc<Self>,
+        hash: &str,
+    ) -> Result<(Vec<u8>, PayloadLease), PayloadError> {
+        let path = self.path_for(hash)?;
+        match self.entries.get_mut(hash) {
+            Some(mut entry) => entry.refs += 1,
+            None => return Err(PayloadError::NotFound(hash.to_string())),
+        }
+        let lease = PayloadLease {
+            store: self.clone(),
+            hash: hash.to_string(),
+        };
+        let compressed = tokio::fs::read(&path).await?;
+        let data = tokio::task::spawn_blocking(move || zstd::decode_all(compressed.as_slice()))
+            .await
+            .map_err(std::io::Error::other)??;
+        Ok((data, lease))
+    }
+
+    fn release(&self, hash: &str) {
+        if let Some(mut entry) = self.entries.get_mut(hash) {
+            entry.refs = entry.refs.saturating_sub(1);
+            if entry.refs == 0 {
+                entry.idle_since = Instant::now();
+            }
+        }
+    }
+
+    /// Delete payloads nobody has referenced for the TTL; returns how many went
+    pub async fn gc(&self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|e| e.refs == 0 && now.duration_since(e.idle_since) >= self.ttl)
+            .map(|e| e.key().clone())
+            .collect();
+
+        let mut removed = 0;
+        for hash in expired {
+            // Re-check: an execution may have taken a reference since the scan
+            if self
+                .entries
+                .remove_if(&hash, |_, e| {
+                    e.refs == 0 && now.duration_since(e.idle_since) >= self.ttl
+                })
+                .is_none()
+            {
+                continue;
+            }
+            if let Ok(path) = self.path_for(&hash) {
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    warn!("Failed to delete payload {}: {}", hash, e);
+                }
+            }
+            removed += 1;
+        }
+        removed
+    }
+}
+
+fn env_bytes(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Inline bytes in a request: a byte array or a base64 string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Inline {
+    Bytes(Vec<u8>),
+    Base64(String),
+}
+
+impl Inline {
+    fn decode<E: serde::de::Error>(self, field: &str) -> Result<Vec<u8>, E> {
+        use base64::Engine;
+        match self {
+            Inline::Bytes(bytes) => Ok(bytes),
+            Inline::Base64(encoded) => base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|e| E::custom(format!("{field} is not base64: {e}"))),
+        }
+    }
+}
+
+/// Deserializes an inline `payload` given as a byte array or a base64 string
+pub fn inline_payload<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<Inline>::deserialize(deserializer)?
+        .map(|inline| inline.decode("payload"))
+        .transpose()
+}
+
+/// Path and contents of each file copied into a sandbox
+pub type InputFiles = Vec<(String, Vec<u8>)>;
+
+/// Deserializes `input_files` as `[path, contents]` pairs, the contents given like an
+/// inline payload
+pub fn input_files<'de, D>(deserializer: D) -> Result<Option<InputFiles>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let Some(files) = Option::<Vec<(String, Inline)>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    files
+        .into_iter()
+        .map(|(path, contents)| Ok((path, contents.decode("input file contents")?)))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// A payload reference held by one execution
+pub struct PayloadLease {
+    store: Arc<PayloadStore>,
+    hash: String,
+}
+
+impl Drop for PayloadLease {
+    fn drop(&mut self) {
+        self.store.release(&self.hash);
+    }
+}
+
+/// `200` if the payload is stored, `404` if it needs uploading
+pub async fn head_payload_handler(
+    State(store): State<Arc<PayloadStore>>,
+    Path(hash): Path<String>,
+) -> StatusCode {
+    match store.path_for(&hash) {
+        Err(_) => StatusCode::BAD_REQUEST,
+        Ok(_) if store.contains(&hash) => StatusCode::OK,
+        Ok(_) => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Store a payload under its SHA-256; the body must hash to `:hash`
+pub async fn put_payload_handler(
+    State(store): State<Arc<PayloadStore>>,
+    Path(hash): Path<String>,
+    body: Body,
+) -> Result<StatusCode, PayloadError> {
+    let existed = store.contains(&hash);
+    let data = axum::body::to_bytes(body, store.max_bytes)
+        .await
+        .map_err(|_| PayloadError::TooLarge(store.max_bytes))?;
+    store.put(&hash, data.to_vec()).await?;
+    Ok(if existed {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(ttl: Duration) -> Arc<PayloadStore> {
+        let dir = std::env::temp_dir().join(format!("faas-payloads-{}", uuid::Uuid::new_v4()));
+        Arc::new(PayloadStore::new(dir, ttl).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejects_payloads_that_do_not_match_their_hash() {
+        let store = temp_store(DEFAULT_TTL);
+        let hash = sha256_hex(b"config v1");
+        let error = store.put(&hash, b"config v2".to_vec()).await.unwrap_err();
+        assert!(matches!(error, PayloadError::HashMismatch { .. }));
+        assert!(!store.contains(&hash));
+    }
+
+    #[tokio::test]
+    async fn collects_unreferenced_payloads_after_the_ttl() {
+        let store = temp_store(Duration::from_millis(30));
+        let data = vec![7u8; 1024 * 1024];
+        let hash = sha256_hex(&data);
+        store.put(&hash, data.clone()).await.unwrap();
+        let on_disk = std::fs::metadata(store.path_for(&hash).unwrap()).unwrap();
+        assert!(on_disk.len() < data.len() as u64 / 100);
+
+        let (read, lease) = store.lease(&hash).await.unwrap();
+        assert_eq!(read, data);
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(store.gc().await, 0, "held by a running execution");
+
+        drop(lease);
+        assert_eq!(store.gc().await, 0, "idle for less than the TTL");
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(store.gc().await, 1);
+        assert!(!store.contains(&hash));
+        assert!(!store.path_for(&hash).unwrap().exists());
+        assert!(matches!(
+            store.lease(&hash).await,
+            Err(PayloadError::NotFound(_))
+        ));
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Body {
+        #[serde(default, deserialize_with = "inline_payload")]
+        payload: Option<Vec<u8>>,
+        #[serde(default, deserialize_with = "input_files")]
+        input_files: Option<Vec<(String, Vec<u8>)>>,
+    }
+
+    #[test]
+    fn inline_payloads_are_bytes_or_base64() {
+        let parse = |json: &str| serde_json::from_str::<Body>(json).map(|b| b.payload);
+        assert_eq!(
+            parse(r#"{"payload":[104,105]}"#).unwrap(),
+            Some(b"hi".to_vec())
+        );
+        assert_eq!(
+            parse(r#"{"payload":"aGk="}"#).unwrap(),
+            Some(b"hi".to_vec())
+        );
+        assert_eq!(parse(r#"{"payload":null}"#).unwrap(), None);
+        assert_eq!(parse("{}").unwrap(), None);
+        assert!(parse(r#"{"payload":"not base64!"}"#).is_err());
+    }
+
+    #[test]
+    fn input_file_contents_are_decoded_like_payloads() {
+        let parse = |json: &str| serde_json::from_str::<Body>(json).map(|b| b.input_files);
+        assert_eq!(
+            parse(r#"{"input_files":[["a.txt",[104,105]],["/b.txt","aGk="]]}"#).unwrap(),
+            Some(vec![
+                ("a.txt".to_string(), b"hi".to_vec()),
+                ("/b.txt".to_string(), b"hi".to_vec()),
+            ])
+        );
+        assert_eq!(parse("{}").unwrap(), None);
+        assert!(parse(r#"{"input_files":[["a.txt","not base64!"]]}"#).is_err());
+    }
+
+    #[test]
+    fn inline_payloads_over_the_cap_are_refused() {
+        let store = PayloadStore::new(
+            std::env::temp_dir().join(format!("faas-payloads-{}", uuid::Uuid::new_v4())),
+            DEFAULT_TTL,
+        )
+        .unwrap()
+        .with_max_inline_bytes(4);
+        assert!(store.check_inline(b"four").is_ok());
+        let error = store.check_inline(b"fives").unwrap_err();
+        assert_eq!(error.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(store.request_body_limit() > BODY_OVERHEAD_BYTES);
+    }
+}
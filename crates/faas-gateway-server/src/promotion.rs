@@ -0,0 +1,610 @@
+//! Hot snapshot promotion.
+//!
+//! Every restore of a snapshot is counted in a rolling window. Once a snapshot is restored
+//! `promote_at` times within the window it is promoted: the gateway keeps `pool_size`
+//! instances already restored from it, and a restore request takes one of those instead of
+//! creating an instance. When its traffic falls below `demote_below` restores per window the
+//! pool is drained. An operator pin holds a snapshot promoted or demoted whatever its
+//! traffic. Promotions and demotions go to the `faas_audit` log target and are kept for
+//! `GET /api/v1/pools/snapshots`.
+
+use crate::Instance;
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Promotion and demotion events kept for the pools endpoint
+const MAX_EVENTS: usize = 100;
+
+/// When snapshots are promoted, and how many pooled instances that may cost
+#[derive(Debug, Clone)]
+pub struct PromotionPolicy {
+    pub window: Duration,
+    /// Restores within the window that promote a snapshot
+    pub promote_at: usize,
+    /// A promoted snapshot with fewer restores than this in the window is demoted
+    pub demote_below: usize,
+    /// Instances kept restored for each promoted snapshot
+    pub pool_size: usize,
+    /// Snapshots promoted at once, across tenants
+    pub max_promoted: usize,
+    /// Pooled instances a tenant's snapshots may hold in total
+    pub max_pooled_per_tenant: usize,
+}
+
+impl Default for PromotionPolicy {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(600),
+            promote_at: 50,
+            demote_below: 5,
+            pool_size: 2,
+            max_promoted: 8,
+            max_pooled_per_tenant: 4,
+        }
+    }
+}
+
+impl PromotionPolicy {
+    /// `FAAS_PROMOTION_WINDOW_SECS`, `FAAS_PROMOTE_AT_RESTORES`, `FAAS_DEMOTE_BELOW_RESTORES`,
+    /// `FAAS_PROMOTED_POOL_SIZE`, `FAAS_MAX_PROMOTED_SNAPSHOTS` and
+    /// `FAAS_PROMOTED_INSTANCES_PER_TENANT`, each falling back to the default
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let default = Self::default();
+        Self {
+            window: var("FAAS_PROMOTION_WINDOW_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.window),
+            promote_at: var("FAAS_PROMOTE_AT_RESTORES").unwrap_or(default.promote_at),
+            demote_below: var("FAAS_DEMOTE_BELOW_RESTORES").unwrap_or(default.demote_below),
+            pool_size: var("FAAS_PROMOTED_POOL_SIZE").unwrap_or(default.pool_size),
+            max_promoted: var("FAAS_MAX_PROMOTED_SNAPSHOTS").unwrap_or(default.max_promoted),
+            max_pooled_per_tenant: var("FAAS_PROMOTED_INSTANCES_PER_TENANT")
+                .unwrap_or(default.max_pooled_per_tenant),
+        }
+    }
+}
+
+/// An operator's override of the automatic decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pin {
+    Promoted,
+    Demoted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromotionAction {
+    Promoted,
+    Demoted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromotionEvent {
+    pub snapshot_id: String,
+    pub action: PromotionAction,
+    pub reason: String,
+    pub at: DateTime<Utc>,
+}
+
+/// A promoted snapshot's pool as the pools endpoint shows it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromotedPool {
+    pub snapshot_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub ready: usize,
+    pub target: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<Pin>,
+    pub restores_in_window: usize,
+    /// Mean restore latency over the window, handovers included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_restore_ms: Option<u64>,
+    /// Restores served from the pool, and those that found it empty
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub promoted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PromotionReport {
+    pub pools: Vec<PromotedPool>,
+    /// Most recent last
+    pub events: Vec<PromotionEvent>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PinRequest {
+    /// `None` hands the snapshot back to the automatic policy
+    pub pin: Option<Pin>,
+}
+
+/// What a periodic [`PromotionTracker::tick`] leaves for the caller to do
+#[derive(Debug, Default)]
+pub struct PromotionWork {
+    /// Snapshots whose pools are short, with how many instances each needs
+    pub refill: Vec<(String, usize)>,
+    /// Instances of demoted pools, to be torn down
+    pub drained: Vec<Instance>,
+}
+
+#[derive(Debug)]
+struct Pool {
+    ready: Vec<Instance>,
+    /// Restores already under way for the pool, so a refill isn't requested twice
+    filling: usize,
+    hits: u64,
+    misses: u64,
+    promoted_at: DateTime<Utc>,
+}
+
+impl Pool {
+    fn new() -> Self {
+        Self {
+            ready: Vec::new(),
+            filling: 0,
+            hits: 0,
+            misses: 0,
+            promoted_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Traffic {
+    tenant: Option<String>,
+    restores: VecDeque<(Instant, Duration)>,
+    pool: Option<Pool>,
+    pin: Option<Pin>,
+}
+
+impl Traffic {
+    fn trim(&mut self, window: Duration, now: Instant) {
+        while let Some(&(at, _)) = self.restores.front() {
+            if now.saturating_duration_since(at) < window {
+                break;
+            }
+            self.restores.pop_front();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    snapshots: HashMap<String, Traffic>,
+    events: VecDeque<PromotionEvent>,
+}
+
+impl Inner {
+    fn promoted(&self) -> usize {
+        self.snapshots.values().filter(|t| t.pool.is_some()).count()
+    }
+
+    /// Pooled instances the tenant's promoted snapshots are entitled to
+    fn tenant_pooled(&self, tenant: Option<&str>, pool_size: usize) -> usize {
+        self.snapshots
+            .values()
+            .filter(|t| t.pool.is_some() && t.tenant.as_deref() == tenant)
+            .count()
+            * pool_size
+    }
+
+    fn record(&mut self, snapshot_id: &str, action: PromotionAction, reason: String) {
+        info!(
+            target: "faas_audit",
+            snapshot_id,
+            action = ?action,
+            reason = %reason,
+            "snapshot pool changed"
+        );
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(PromotionEvent {
+            snapshot_id: snapshot_id.to_string(),
+            action,
+            reason,
+            at: Utc::now(),
+        });
+    }
+
+    fn promote(&mut self, snapshot_id: &str, reason: String) {
+        let traffic = self.snapshots.entry(snapshot_id.to_string()).or_default();
+        if traffic.pool.is_some() {
+            return;
+        }
+        traffic.pool = Some(Pool::new());
+        self.record(snapshot_id, PromotionAction::Promoted, reason);
+    }
+
+    fn demote(&mut self, snapshot_id: &str, reason: String) -> Vec<Instance> {
+        let Some(pool) = self
+            .snapshots
+            .get_mut(snapshot_id)
+            .and_then(|t| t.pool.take())
+        else {
+            return Vec::new();
+        };
+        self.record(snapshot_id, PromotionAction::Demoted, reason);
+        pool.ready
+    }
+}
+
+pub struct PromotionTracker {
+    policy: PromotionPolicy,
+    inner: Mutex<Inner>,
+}
+
+impl PromotionTracker {
+    pub fn new(policy: PromotionPolicy) -> Self {
+        Self {
+            policy,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// A pooled instance of `snapshot_id`, if it is promoted and its pool isn't empty
+    pub fn take(&self, snapshot_id: &str) -> Option<Instance> {
+        let mut inner = self.inner.lock().unwrap();
+        let pool = inner.snapshots.get_mut(snapshot_id)?.pool.as_mut()?;
+        match pool.ready.pop() {
+            Some(instance) => {
+                pool.hits += 1;
+                Some(instance)
+            }
+            None => {
+                pool.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Count a restore, however it was served; true if it promoted the snapshot
+    pub fn record_restore(
+        &self,
+        snapshot_id: &str,
+        tenant: Option<&str>,
+        latency: Duration,
+        now: Instant,
+    ) -> bool {
+        let policy = &self.policy;
+        let mut inner = self.inner.lock().unwrap();
+        let promoted = inner.promoted();
+        let tenant_pooled = inner.tenant_pooled(tenant, policy.pool_size);
+        let traffic = inner.snapshots.entry(snapshot_id.to_string()).or_default();
+        traffic.tenant = tenant.map(str::to_string);
+        traffic.restores.push_back((now, latency));
+        traffic.trim(policy.window, now);
+
+        let restores = traffic.restores.len();
+        let eligible = traffic.pool.is_none()
+            && traffic.pin.is_none()
+            && restores >= policy.promote_at
+            && promoted < policy.max_promoted
+            && tenant_pooled + policy.pool_size <= policy.max_pooled_per_tenant;
+        if eligible {
+            let reason = format!("{restores} restores in {}s", policy.window.as_secs());
+            inner.promote(snapshot_id, reason);
+        }
+        eligible
+    }
+
+    /// Hold `snapshot_id` promoted or demoted, or with `None` return it to the policy.
+    /// Instances drained by a demotion are returned.
+    pub fn pin(&self, snapshot_id: &str, tenant: Option<&str>, pin: Option<Pin>) -> Vec<Instance> {
+        let mut inner = self.inner.lock().unwrap();
+        let traffic = inner.snapshots.entry(snapshot_id.to_string()).or_default();
+        traffic.tenant = tenant.map(str::to_string);
+        traffic.pin = pin;
+        match pin {
+            Some(Pin::Promoted) => {
+                inner.promote(snapshot_id, "pinned by an operator".to_string());
+                Vec::new()
+            }
+            Some(Pin::Demoted) => inner.demote(snapshot_id, "pinned by an operator".to_string()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Add a freshly restored instance to `snapshot_id`'s pool. Handed back if the snapshot
+    /// was demoted while it was being restored.
+    pub fn stock(&self, snapshot_id: &str, instance: Instance) -> Option<Instance> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(pool) = inner
+            .snapshots
+            .get_mut(snapshot_id)
+            .and_then(|t| t.pool.as_mut())
+        else {
+            return Some(instance);
+        };
+        pool.filling = pool.filling.saturating_sub(1);
+        pool.ready.push(instance);
+        None
+    }
+
+    /// A restore for the pool failed; it may be requested again
+    pub fn stock_failed(&self, snapshot_id: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(pool) = inner
+            .snapshots
+            .get_mut(snapshot_id)
+            .and_then(|t| t.pool.as_mut())
+        {
+            pool.filling = pool.filling.saturating_sub(1);
+        }
+    }
+
+    /// Drop everything about a deleted snapshot, returning its pooled instances
+    pub fn forget(&self, snapshot_id: &str) -> Vec<Instance> {
+        let mut inner = self.inner.lock().unwrap();
+        let drained = inner.demote(snapshot_id, "snapshot deleted".to_string());
+        inner.snapshots.remove(snapshot_id);
+        drained
+    }
+
+    /// Demote snapshots whose traffic fell off, forget idle ones, and say which pools need
+    /// instances. Refills returned here count as under way until stocked or failed.
+    pub fn tick(&self, now: Instant) -> PromotionWork {
+        let policy = &self.policy;
+        let mut inner = self.inner.lock().unwrap();
+        let mut work = PromotionWork::default();
+
+        let mut cold = Vec::new();
+        for (id, traffic) in inner.snapshots.iter_mut() {
+            traffic.trim(policy.window, now);
+            if traffic.pool.is_some()
+                && traffic.pin.is_none()
+                && traffic.restores.len() < policy.demote_below
+            {
+                cold.push((id.clone(), traffic.restores.len()));
+            }
+        }
+        for (id, restores) in cold {
+            let reason = format!("{restores} restores in {}s", policy.window.as_secs());
+            work.drained.extend(inner.demote(&id, reason));
+        }
+        inner
+            .snapshots
+            .retain(|_, t| !t.restores.is_empty() || t.pool.is_some() || t.pin.is_some());
+
+        for (id, traffic) in inner.snapshots.iter_mut() {
+            let Some(pool) = traffic.pool.as_mut() else {
+                continue;
+            };
+            let needed = policy
+                .pool_size
+                .saturating_sub(pool.ready.len() + pool.filling);
+            if needed > 0 {
+                pool.filling += needed;
+                work.refill.push((id.clone(), needed));
+            }
+        }
+        work
+    }
+
+    pub fn report(&self, now: Instant) -> PromotionReport {
+        let policy = &self.policy;
+        let inner = self.inner.lock().unwrap();
+        let mut pools: Vec<PromotedPool> = inner
+            .snapshots
+            .iter()
+            .filter_map(|(id, traffic)| {
+                let pool = traffic.pool.as_ref()?;
+                let recent: Vec<Duration> = traffic
+                    .restores
+                    .iter()
+                    .filter(|(at, _)| now.saturating_duration_since(*at) < policy.window)
+                    .map(|(_, latency)| *latency)
+                    .collect();
+                let served = pool.hits + pool.misses;
+                Some(PromotedPool {
+                    snapshot_id: id.clone(),
+                    tenant: traffic.tenant.clone(),
+                    ready: pool.ready.len(),
+                    target: policy.pool_size,
+                    pinned: traffic.pin,
+                    restores_in_window: recent.len(),
+                    mean_restore_ms: (!recent.is_empty()).then(|| {
+                        (recent.iter().sum::<Duration>() / recent.len() as u32).as_millis() as u64
+                    }),
+                    hits: pool.hits,
+                    misses: pool.misses,
+                    hit_rate: if served == 0 {
+                        0.0
+                    } else {
+                        pool.hits as f64 / served as f64
+                    },
+                    promoted_at: pool.promoted_at,
+                })
+            })
+            .collect();
+        pools.sort_by(|a, b| a.snapshot_id.cmp(&b.snapshot_id));
+        PromotionReport {
+            pools,
+            events: inner.events.iter().cloned().collect(),
+        }
+    }
+}
+
+/// `GET /api/v1/pools/snapshots`
+pub async fn promoted_pools_handler(
+    State(tracker): State<Arc<PromotionTracker>>,
+) -> Json<PromotionReport> {
+    Json(tracker.report(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::{InstanceState, Lifecycle};
+
+    fn policy() -> PromotionPolicy {
+        PromotionPolicy {
+            window: Duration::from_secs(60),
+            promote_at: 3,
+            demote_below: 2,
+            pool_size: 2,
+            max_promoted: 8,
+            max_pooled_per_tenant: 4,
+        }
+    }
+
+    fn instance(id: &str) -> Instance {
+        Instance {
+            id: id.to_string(),
+            name: None,
+            image: "restored".to_string(),
+            tenant: None,
+            lifecycle: Lifecycle::new(InstanceState::Running),
+            created_at: Utc::now().to_rfc3339(),
+            cpu_cores: None,
+            memory_mb: None,
+            container_id: None,
+            container: None,
+            expires_at: None,
+            workspace: None,
+            ports: Vec::new(),
+            endpoints: Default::default(),
+        }
+    }
+
+    /// Restore `snapshot_id` of team-a `n` times, a second apart from `start`
+    fn restores(tracker: &PromotionTracker, snapshot_id: &str, start: Instant, n: u64) -> bool {
+        restores_of(tracker, "team-a", snapshot_id, start, n)
+    }
+
+    fn restores_of(
+        tracker: &PromotionTracker,
+        tenant: &str,
+        snapshot_id: &str,
+        start: Instant,
+        n: u64,
+    ) -> bool {
+        (0..n).fold(false, |promoted, i| {
+            let at = start + Duration::from_secs(i);
+            let latency = Duration::from_millis(400);
+            tracker.record_restore(snapshot_id, Some(tenant), latency, at) || promoted
+        })
+    }
+
+    #[test]
+    fn promotes_at_the_threshold_and_hands_over_pooled_instances() {
+        let tracker = PromotionTracker::new(policy());
+        let start = Instant::now();
+        assert!(!restores(&tracker, "base", start, 2));
+        assert!(tracker.take("base").is_none());
+        assert!(tracker.record_restore(
+            "base",
+            Some("team-a"),
+            Duration::from_millis(400),
+            start + Duration::from_secs(2)
+        ));
+
+        let now = start + Duration::from_secs(3);
+        let work = tracker.tick(now);
+        assert_eq!(work.refill, [("base".to_string(), 2)]);
+        // Refills under way aren't requested again
+        assert!(tracker.tick(now).refill.is_empty());
+
+        // A restore before the pool is stocked misses and is served the slow way
+        assert!(tracker.take("base").is_none());
+        assert!(tracker.stock("base", instance("i-1")).is_none());
+        assert!(tracker.stock("base", instance("i-2")).is_none());
+
+        let handed = tracker.take("base").unwrap();
+        assert_eq!(handed.lifecycle.current(), InstanceState::Running);
+        tracker.record_restore("base", Some("team-a"), Duration::ZERO, now);
+
+        let report = tracker.report(now);
+        let pool = &report.pools[0];
+        assert_eq!((pool.ready, pool.target), (1, 2));
+        assert_eq!((pool.hits, pool.misses), (1, 1));
+        assert_eq!(pool.hit_rate, 0.5);
+        assert_eq!(pool.mean_restore_ms, Some(300));
+        assert_eq!(report.events[0].action, PromotionAction::Promoted);
+        assert_eq!(report.events[0].reason, "3 restores in 60s");
+
+        // The handover left the pool one short
+        assert_eq!(tracker.tick(now).refill, [("base".to_string(), 1)]);
+    }
+
+    #[test]
+    fn demotes_and_drains_once_traffic_stops() {
+        let tracker = PromotionTracker::new(policy());
+        let start = Instant::now();
+        assert!(restores(&tracker, "base", start, 3));
+        tracker.tick(start);
+        tracker.stock("base", instance("i-1"));
+
+        // Still busy enough a minute in
+        assert!(tracker
+            .tick(start + Duration::from_secs(59))
+            .drained
+            .is_empty());
+
+        let quiet = start + Duration::from_secs(180);
+        let work = tracker.tick(quiet);
+        assert_eq!(work.drained.len(), 1);
+        assert!(work.refill.is_empty());
+        let report = tracker.report(quiet);
+        assert!(report.pools.is_empty());
+        assert_eq!(report.events[1].action, PromotionAction::Demoted);
+        assert_eq!(report.events[1].reason, "0 restores in 60s");
+
+        // An instance restored after the demotion goes back to the caller
+        assert!(tracker.stock("base", instance("late")).is_some());
+        assert!(tracker.take("base").is_none());
+    }
+
+    #[test]
+    fn pins_override_the_policy() {
+        let tracker = PromotionTracker::new(policy());
+        let start = Instant::now();
+
+        tracker.pin("cold", Some("team-a"), Some(Pin::Promoted));
+        let later = start + Duration::from_secs(600);
+        assert_eq!(tracker.tick(later).refill, [("cold".to_string(), 2)]);
+        assert_eq!(tracker.report(later).pools[0].pinned, Some(Pin::Promoted));
+
+        tracker.stock("cold", instance("i-1"));
+        assert_eq!(
+            tracker
+                .pin("cold", Some("team-a"), Some(Pin::Demoted))
+                .len(),
+            1
+        );
+        assert!(!restores(&tracker, "cold", later, 5));
+        assert!(tracker.report(later).pools.is_empty());
+
+        // Back under the policy, the next restore over the threshold promotes
+        tracker.pin("cold", Some("team-a"), None);
+        assert!(tracker.record_restore(
+            "cold",
+            Some("team-a"),
+            Duration::ZERO,
+            later + Duration::from_secs(5)
+        ));
+    }
+
+    #[test]
+    fn promotion_stays_within_the_tenant_quota() {
+        let tracker = PromotionTracker::new(PromotionPolicy {
+            max_pooled_per_tenant: 2,
+            ..policy()
+        });
+        let start = Instant::now();
+        assert!(restores(&tracker, "first", start, 3));
+        assert!(!restores(&tracker, "second", start, 10));
+        assert!(restores_of(&tracker, "team-b", "other", start, 3));
+    }
+}
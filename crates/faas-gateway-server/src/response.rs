@@ -0,0 +1,272 @@
+//! Building [`InvokeResponse`]s from executor output.
+//!
+//! Every handler that returns an execution's result goes through [`ResponseBuilder`], so
+//! how exit codes become `error` and how the legacy `output`/`logs` copies are filled is
+//! decided here once. Handlers only add what is specific to their endpoint.
+
+use crate::{ExecutionDiagnostics, InvokeResponse};
+use faas_common::ExecutionUsage;
+use faas_executor::platform::executor::Response;
+use faas_executor::platform::RuntimeDecision;
+use std::time::Duration;
+
+pub struct ResponseBuilder {
+    request_id: String,
+    exit_code: i32,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    duration: Duration,
+    cache_hit: bool,
+    cache_key: Option<String>,
+    runtime: Option<RuntimeDecision>,
+    usage: Option<ExecutionUsage>,
+    total_bytes: Option<u64>,
+    snapshot: Option<String>,
+    diagnostics: Option<ExecutionDiagnostics>,
+    legacy_fields: bool,
+}
+
+impl ResponseBuilder {
+    pub fn new(response: Response) -> Self {
+        Self {
+            request_id: response.id,
+            exit_code: response.exit_code,
+            stdout: response.stdout,
+            stderr: response.stderr,
+            duration: response.duration,
+            cache_hit: response.cache_hit,
+            cache_key: response.cache_key,
+            runtime: response.runtime_decision,
+            usage: response.usage,
+            total_bytes: response.truncation.map(|truncation| truncation.total_bytes),
+            snapshot: response.snapshot,
+            diagnostics: None,
+            legacy_fields: true,
+        }
+    }
+
+    /// Report `stdout` instead of the executor's, e.g. with a session capture stripped
+    pub fn stdout(mut self, stdout: Vec<u8>) -> Self {
+        self.stdout = stdout;
+        self
+    }
+
+    pub fn diagnostics(mut self, diagnostics: ExecutionDiagnostics) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Leave out `output` and `logs`. They repeat `stdout` and `stderr` for clients written
+    /// before those existed, and will go once no handler needs them.
+    pub fn without_legacy_fields(mut self) -> Self {
+        self.legacy_fields = false;
+        self
+    }
+
+    pub fn build(self) -> InvokeResponse {
+        let stdout = String::from_utf8_lossy(&self.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&self.stderr).into_owned();
+        InvokeResponse {
+            request_id: self.request_id,
+            exit_code: self.exit_code,
+            output: self.legacy_fields.then(|| stdout.clone()),
+            logs: self.legacy_fields.then(|| stderr.clone()),
+            stdout,
+            stderr,
+            duration_ms: self.duration.as_millis() as u64,
+            error: exit_error(self.exit_code),
+            cache_hit: self.cache_hit,
+            cache_key: self.cache_key,
+            runtime: self.runtime.map(|decision| decision.runtime),
+            runtime_reason: self.runtime.map(|decision| decision.reason),
+            diagnostics: self.diagnostics,
+            usage: self.usage,
+            truncated: self.total_bytes.is_some(),
+            total_bytes: self.total_bytes,
+            snapshot_id: self.snapshot,
+        }
+    }
+}
+
+impl From<Response> for InvokeResponse {
+    fn from(response: Response) -> Self {
+        ResponseBuilder::new(response).build()
+    }
+}
+
+/// `error` for a process that ran to completion: set only when it exited non-zero
+fn exit_error(exit_code: i32) -> Option<String> {
+    (exit_code != 0).then(|| format!("Process exited with code {exit_code}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::AppliedLimits;
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+
+    fn response(exit_code: i32, stdout: &[u8], stderr: &[u8], duration_ms: u64) -> Response {
+        Response {
+            id: "exec-1".to_string(),
+            stdout: stdout.to_vec(),
+            stderr: stderr.to_vec(),
+            exit_code,
+            duration: Duration::from_millis(duration_ms),
+            snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: None,
+            usage: None,
+            truncation: None,
+            warm_start: false,
+        }
+    }
+
+    fn diagnostics() -> ExecutionDiagnostics {
+        ExecutionDiagnostics {
+            limits: Some(AppliedLimits {
+                ulimits: Vec::new(),
+                shm_size_mb: 64,
+                tmpfs: Vec::new(),
+                pids_limit: 512,
+            }),
+            environment_overrides: None,
+            env: None,
+            speculation: None,
+        }
+    }
+
+    fn without_diagnostics(response: InvokeResponse) -> Value {
+        let mut value = serde_json::to_value(response).unwrap();
+        value.as_object_mut().unwrap().remove("diagnostics");
+        value
+    }
+
+    #[test]
+    fn successful_execution_json_shape() {
+        let built = ResponseBuilder::new(response(0, b"hi\n", b"", 12))
+            .diagnostics(diagnostics())
+            .build();
+        assert_eq!(
+            serde_json::to_value(built).unwrap(),
+            json!({
+                "request_id": "exec-1",
+                "exit_code": 0,
+                "stdout": "hi\n",
+                "stderr": "",
+                "duration_ms": 12,
+                "output": "hi\n",
+                "logs": "",
+                "error": null,
+                "cache_hit": false,
+                "diagnostics": {
+                    "limits": { "ulimits": [], "shm_size_mb": 64, "pids_limit": 512 }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn failed_execution_json_shape() {
+        let built: InvokeResponse = response(2, b"", b"boom", 3).into();
+        assert_eq!(
+            serde_json::to_value(built).unwrap(),
+            json!({
+                "request_id": "exec-1",
+                "exit_code": 2,
+                "stdout": "",
+                "stderr": "boom",
+                "duration_ms": 3,
+                "output": "",
+                "logs": "boom",
+                "error": "Process exited with code 2",
+                "cache_hit": false
+            })
+        );
+    }
+
+    #[test]
+    fn cache_hits_come_from_the_executor() {
+        let mut cached = response(0, b"hi", b"", 0);
+        cached.cache_hit = true;
+        cached.cache_key = Some("cache:abc".to_string());
+        let value = serde_json::to_value(InvokeResponse::from(cached)).unwrap();
+        assert_eq!(value["cache_hit"], true);
+        assert_eq!(value["cache_key"], "cache:abc");
+    }
+
+    #[test]
+    fn the_runtime_decision_is_reported() {
+        let mut fallback = response(0, b"", b"", 0);
+        fallback.runtime_decision = Some(RuntimeDecision {
+            runtime: faas_common::Runtime::Docker,
+            reason: faas_executor::platform::RuntimeReason::KvmUnavailableFallback,
+        });
+        let value = serde_json::to_value(InvokeResponse::from(fallback)).unwrap();
+        assert_eq!(value["runtime"], "docker");
+        assert_eq!(value["runtime_reason"], "kvm_unavailable_fallback");
+    }
+
+    #[test]
+    fn truncated_output_is_flagged_with_its_full_size() {
+        let mut cut = response(0, b"aaaa", b"", 1);
+        cut.truncation = Some(faas_common::OutputTruncation {
+            total_bytes: 52_428_800,
+            log_path: Some("/tmp/faas-output/exec-1.log".into()),
+        });
+        let value = serde_json::to_value(InvokeResponse::from(cut)).unwrap();
+        assert_eq!(value["truncated"], true);
+        assert_eq!(value["total_bytes"], 52_428_800);
+        assert_eq!(value["stdout"], "aaaa");
+    }
+
+    #[test]
+    fn legacy_fields_can_be_dropped() {
+        let built = ResponseBuilder::new(response(0, b"hi", b"warn", 1))
+            .without_legacy_fields()
+            .build();
+        assert_eq!(built.output, None);
+        assert_eq!(built.logs, None);
+        assert_eq!(built.stdout, "hi");
+    }
+
+    proptest! {
+        /// Execute, fork and fork-from-parent differ only in the diagnostics they attach
+        #[test]
+        fn handlers_agree_on_everything_but_their_own_fields(
+            exit_code in any::<i32>(),
+            stdout in proptest::collection::vec(any::<u8>(), 0..256),
+            stderr in proptest::collection::vec(any::<u8>(), 0..256),
+            duration_ms in 0u64..10_000_000,
+        ) {
+            let platform = || response(exit_code, &stdout, &stderr, duration_ms);
+            let execute = ResponseBuilder::new(platform()).diagnostics(diagnostics()).build();
+            let fork = ResponseBuilder::new(platform())
+                .diagnostics(ExecutionDiagnostics::default())
+                .build();
+            let plain: InvokeResponse = platform().into();
+
+            prop_assert_eq!(execute.error.is_some(), exit_code != 0);
+            prop_assert_eq!(&execute.output, &Some(execute.stdout.clone()));
+            prop_assert_eq!(&execute.logs, &Some(execute.stderr.clone()));
+            let execute = without_diagnostics(execute);
+            prop_assert_eq!(&execute, &without_diagnostics(fork));
+            prop_assert_eq!(&execute, &without_diagnostics(plain));
+        }
+
+        /// Instance exec swaps stdout, and the legacy copy follows it
+        #[test]
+        fn replaced_stdout_is_reported_everywhere(
+            stdout in proptest::collection::vec(any::<u8>(), 0..256),
+        ) {
+            let built = ResponseBuilder::new(response(0, b"raw capture", b"", 1))
+                .stdout(stdout.clone())
+                .build();
+            let expected = String::from_utf8_lossy(&stdout).into_owned();
+            prop_assert_eq!(&built.stdout, &expected);
+            prop_assert_eq!(built.output, Some(expected));
+        }
+    }
+}
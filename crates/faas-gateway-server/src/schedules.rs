@@ -0,0 +1,701 @@
+//! Executions run on a cron schedule.
+//!
+//! `POST /api/v1/schedules` takes a `function_id`, a [`cron`](crate::cron) expression and the
+//! execute request each run sends, `payload` included. The gateway has no function
+//! registry, so the request is what runs; `function_id` names it in listings and logs. A
+//! run is a [job](crate::jobs) whose id is the run's, so its logs and outcome can be
+//! followed there too; the schedule keeps its last run's status, and when it runs next.
+//!
+//! The runner sleeps until the earliest due schedule and works out each schedule's next
+//! run from the time it actually fired, so a late wake-up never fires a burst of the
+//! occurrences it slept through. `concurrency` decides what an occurrence does while the
+//! previous run is still going: `skip` (the default) drops it, `queue` runs it once that
+//! run ends, keeping at most one waiting, and `replace` cancels the running one.
+//!
+//! With `FAAS_SCHEDULE_DIR` set, schedules are written to `<dir>/schedules.json` whenever
+//! they change and reloaded on startup. Runs missed while the gateway was down are handled
+//! by `FAAS_SCHEDULE_MISFIRE`: `run_once` (the default) fires each overdue schedule once at
+//! startup, `skip` waits for its next occurrence. Deleting a schedule stops it firing; a
+//! run already going is left to finish.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::cron::CronExpr;
+use crate::errors::{ApiError, ApiErrorResponse};
+use crate::jobs::JobStatus;
+use crate::InvokeResponse;
+
+/// Longest the runner sleeps, so a changed system clock is noticed
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// What an occurrence does while the previous run is still going
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConcurrencyPolicy {
+    #[default]
+    Skip,
+    Queue,
+    Replace,
+}
+
+/// What happens to runs missed while the gateway was down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MisfirePolicy {
+    Skip,
+    #[default]
+    RunOnce,
+}
+
+impl MisfirePolicy {
+    /// `FAAS_SCHEDULE_MISFIRE`, `run_once` when unset or unknown
+    pub fn from_env() -> Self {
+        match std::env::var("FAAS_SCHEDULE_MISFIRE").as_deref() {
+            Ok("skip") => Self::Skip,
+            Ok("run_once") | Err(_) => Self::RunOnce,
+            Ok(other) => {
+                warn!("Unknown FAAS_SCHEDULE_MISFIRE {:?}; using run_once", other);
+                Self::RunOnce
+            }
+        }
+    }
+}
+
+/// Body of `POST /api/v1/schedules`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleSpec {
+    pub function_id: String,
+    pub cron: String,
+    #[serde(default)]
+    pub concurrency: ConcurrencyPolicy,
+    /// The execute request every run sends
+    #[serde(flatten)]
+    pub request: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleRun {
+    /// Also the id of the job it ran as
+    pub run_id: String,
+    /// The occurrence it ran for
+    pub scheduled_for: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub status: JobStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiErrorResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub schedule_id: String,
+    pub function_id: String,
+    pub cron: String,
+    pub concurrency: ConcurrencyPolicy,
+    pub request: Map<String, Value>,
+    pub created_at: DateTime<Utc>,
+    /// `None` once the expression names no further time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<ScheduleRun>,
+    /// Runs started, and occurrences dropped because a run was still going
+    #[serde(default)]
+    pub runs: u64,
+    #[serde(default)]
+    pub skipped: u64,
+}
+
+/// One run a [`ScheduleRunner`] is asked for
+#[derive(Debug, Clone)]
+pub struct ScheduledRun {
+    pub run_id: String,
+    pub schedule: Schedule,
+    pub tenant: Option<String>,
+}
+
+/// Runs what schedules fire
+#[async_trait]
+pub trait ScheduleRunner: Send + Sync {
+    /// Run `run`, returning once it has finished
+    async fn run(&self, run: ScheduledRun) -> Result<InvokeResponse, ApiError>;
+    /// Stop run `run_id`, which is still going; its [`run`](Self::run) then returns
+    fn cancel(&self, run_id: &str);
+}
+
+/// A schedule as it is written to disk
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    #[serde(flatten)]
+    schedule: Schedule,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+}
+
+struct Entry {
+    schedule: Schedule,
+    tenant: Option<String>,
+    cron: CronExpr,
+    /// The run still going, if any
+    running: Option<String>,
+    /// The occurrence waiting for it, under the `queue` policy
+    queued: Option<DateTime<Utc>>,
+}
+
+/// Every schedule, shared by the handlers and the runner
+pub struct ScheduleStore {
+    schedules: DashMap<String, Entry>,
+    misfire: MisfirePolicy,
+    dir: Option<PathBuf>,
+    /// Serializes writes of the schedule file
+    writing: Mutex<()>,
+    /// Wakes the runner when a schedule is added
+    changed: Notify,
+}
+
+impl ScheduleStore {
+    pub fn new(misfire: MisfirePolicy) -> Self {
+        Self {
+            schedules: DashMap::new(),
+            misfire,
+            dir: None,
+            writing: Mutex::new(()),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Persist schedules in `dir`, loading those already there. Overdue ones are handled
+    /// by the misfire policy, and runs the gateway stopped during are recorded as failed.
+    pub fn with_dir(mut self, dir: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("schedules.json");
+        let stored: Vec<Stored> = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(std::io::Error::other)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let now = Utc::now();
+        for Stored {
+            mut schedule,
+            tenant,
+        } in stored
+        {
+            let cron = match schedule.cron.parse::<CronExpr>() {
+                Ok(cron) => cron,
+                Err(e) => {
+                    warn!("Skipping schedule {}: {}", schedule.schedule_id, e);
+                    continue;
+                }
+            };
+            if let Some(last) = schedule
+                .last_run
+                .as_mut()
+                .filter(|last| !last.status.is_finished())
+            {
+                last.status = JobStatus::Failed;
+                last.error = Some(ApiErrorResponse {
+                    code: "Interrupted".to_string(),
+                    message: "the gateway stopped while the run was going".to_string(),
+                    request_id: None,
+                    details: None,
+                });
+            }
+            let overdue = schedule.next_run_at.is_some_and(|next| next <= now);
+            if overdue && self.misfire == MisfirePolicy::Skip {
+                schedule.next_run_at = cron.next_after(now);
+            }
+            self.schedules.insert(
+                schedule.schedule_id.clone(),
+                Entry {
+                    schedule,
+                    tenant,
+                    cron,
+                    running: None,
+                    queued: None,
+                },
+            );
+        }
+        info!("Loaded {} schedules from {:?}", self.schedules.len(), dir);
+        self.dir = Some(dir);
+        Ok(self)
+    }
+
+    /// Misfires from `FAAS_SCHEDULE_MISFIRE`, persisted in `FAAS_SCHEDULE_DIR` when it is set
+    pub fn from_env() -> std::io::Result<Self> {
+        let store = Self::new(MisfirePolicy::from_env());
+        match std::env::var("FAAS_SCHEDULE_DIR") {
+            Ok(dir) => store.with_dir(PathBuf::from(dir)),
+            Err(_) => Ok(store),
+        }
+    }
+
+    fn persist(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        let stored: Vec<Stored> = self
+            .schedules
+            .iter()
+            .map(|entry| Stored {
+                schedule: entry.schedule.clone(),
+                tenant: entry.tenant.clone(),
+            })
+            .collect();
+        let path = dir.join("schedules.json");
+        let partial = dir.join("schedules.json.partial");
+        let written = serde_json::to_vec(&stored)
+            .map_err(std::io::Error::other)
+            .and_then(|data| std::fs::write(&partial, data))
+            .and_then(|()| std::fs::rename(&partial, &path));
+        if let Err(e) = written {
+            warn!("Failed to persist schedules: {}", e);
+        }
+    }
+
+    /// Add a schedule for `tenant`; the request in `spec` is checked by the caller
+    pub fn create(&self, tenant: Option<&str>, spec: ScheduleSpec) -> Result<Schedule, ApiError> {
+        if spec.function_id.trim().is_empty() {
+            return Err(ApiError::invalid_request("function_id must not be empty")
+                .with_details(serde_json::json!({ "field": "function_id" })));
+        }
+        let invalid_cron = |message: String| {
+            ApiError::invalid_request(message).with_details(serde_json::json!({ "field": "cron" }))
+        };
+        let cron: CronExpr = spec
+            .cron
+            .parse()
+            .map_err(|e| invalid_cron(format!("{e}")))?;
+        let now = Utc::now();
+        let next_run_at = cron
+            .next_after(now)
+            .ok_or_else(|| invalid_cron(format!("cron expression {:?} never fires", spec.cron)))?;
+        let schedule = Schedule {
+            schedule_id: Uuid::new_v4().to_string(),
+            function_id: spec.function_id,
+            cron: spec.cron,
+            concurrency: spec.concurrency,
+            request: spec.request,
+            created_at: now,
+            next_run_at: Some(next_run_at),
+            last_run: None,
+            runs: 0,
+            skipped: 0,
+        };
+        self.schedules.insert(
+            schedule.schedule_id.clone(),
+            Entry {
+                schedule: schedule.clone(),
+                tenant: tenant.map(str::to_string),
+                cron,
+                running: None,
+                queued: None,
+            },
+        );
+        info!(
+            "Schedule {} runs {} on {:?}",
+            schedule.schedule_id, schedule.function_id, schedule.cron
+        );
+        self.persist();
+        self.changed.notify_one();
+        Ok(schedule)
+    }
+
+    /// `tenant`'s schedules, oldest first
+    pub fn list(&self, tenant: Option<&str>) -> Vec<Schedule> {
+        let mut schedules: Vec<Schedule> = self
+            .schedules
+            .iter()
+            .filter(|entry| entry.tenant.as_deref() == tenant)
+            .map(|entry| entry.schedule.clone())
+            .collect();
+        schedules.sort_by_key(|schedule| schedule.created_at);
+        schedules
+    }
+
+    pub fn get(&self, id: &str, tenant: Option<&str>) -> Option<Schedule> {
+        let entry = self.schedules.get(id)?;
+        (entry.tenant.as_deref() == tenant).then(|| entry.schedule.clone())
+    }
+
+    /// Remove the schedule, returning it as it was
+    pub fn delete(&self, id: &str, tenant: Option<&str>) -> Option<Schedule> {
+        let (_, entry) = self
+            .schedules
+            .remove_if(id, |_, entry| entry.tenant.as_deref() == tenant)?;
+        info!("Schedule {} deleted", id);
+        self.persist();
+        Some(entry.schedule)
+    }
+
+    /// Fire schedules as they fall due, forever
+    pub async fn run(self: Arc<Self>, runner: Arc<dyn ScheduleRunner>) {
+        loop {
+            let now = Utc::now();
+            let due: Vec<String> = self
+                .schedules
+                .iter()
+                .filter(|entry| entry.schedule.next_run_at.is_some_and(|next| next <= now))
+                .map(|entry| entry.key().clone())
+                .collect();
+            for id in &due {
+                self.fire(id, now, &runner);
+            }
+            if !due.is_empty() {
+                self.persist();
+            }
+
+            let earliest = self
+                .schedules
+                .iter()
+                .filter_map(|entry| entry.schedule.next_run_at)
+                .min();
+            let wait = earliest
+                .map_or(MAX_WAIT, |next| {
+                    (next - Utc::now()).to_std().unwrap_or_default()
+                })
+                .min(MAX_WAIT);
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.changed.notified() => {}
+            }
+        }
+    }
+
+    fn fire(self: &Arc<Self>, id: &str, now: DateTime<Utc>, runner: &Arc<dyn ScheduleRunner>) {
+        let Some(mut entry) = self.schedules.get_mut(id) else {
+            return;
+        };
+        let Some(planned) = entry.schedule.next_run_at else {
+            return;
+        };
+        entry.schedule.next_run_at = entry.cron.next_after(now);
+        let Some(running) = entry.running.clone() else {
+            return self.start(id, &mut entry, planned, runner);
+        };
+        match entry.schedule.concurrency {
+            ConcurrencyPolicy::Queue if entry.queued.is_none() => entry.queued = Some(planned),
+            ConcurrencyPolicy::Skip | ConcurrencyPolicy::Queue => {
+                entry.schedule.skipped += 1;
+                info!("Schedule {} skipped: run {} is still going", id, running);
+            }
+            ConcurrencyPolicy::Replace => {
+                info!("Schedule {} replaces run {}", id, running);
+                runner.cancel(&running);
+                self.start(id, &mut entry, planned, runner);
+            }
+        }
+    }
+
+    fn start(
+        self: &Arc<Self>,
+        id: &str,
+        entry: &mut Entry,
+        planned: DateTime<Utc>,
+        runner: &Arc<dyn ScheduleRunner>,
+    ) {
+        let run_id = Uuid::new_v4().to_string();
+        entry.running = Some(run_id.clone());
+        entry.schedule.runs += 1;
+        entry.schedule.last_run = Some(ScheduleRun {
+            run_id: run_id.clone(),
+            scheduled_for: planned,
+            started_at: Utc::now(),
+            finished_at: None,
+            status: JobStatus::Running,
+            exit_code: None,
+            error: None,
+        });
+        let run = ScheduledRun {
+            run_id: run_id.clone(),
+            schedule: entry.schedule.clone(),
+            tenant: entry.tenant.clone(),
+        };
+        let (store, runner, id) = (self.clone(), runner.clone(), id.to_string());
+        tokio::spawn(async move {
+            let outcome = runner.run(run).await;
+            store.finished(&id, &run_id, outcome, &runner);
+        });
+    }
+
+    fn finished(
+        self: &Arc<Self>,
+        id: &str,
+        run_id: &str,
+        outcome: Result<InvokeResponse, ApiError>,
+        runner: &Arc<dyn ScheduleRunner>,
+    ) {
+        let Some(mut entry) = self.schedules.get_mut(id) else {
+            return;
+        };
+        if entry.running.as_deref() == Some(run_id) {
+            entry.running = None;
+        }
+        // A replaced run's outcome is not the last run's
+        if let Some(last) = entry
+            .schedule
+            .last_run
+            .as_mut()
+            .filter(|last| last.run_id == run_id)
+        {
+            last.finished_at = Some(Utc::now());
+            last.status = match &outcome {
+                Ok(response) if response.exit_code == 0 => JobStatus::Succeeded,
+                Err(e) if e.body.code == "Cancelled" => JobStatus::Cancelled,
+                _ => JobStatus::Failed,
+            };
+            match outcome {
+                Ok(response) => last.exit_code = Some(response.exit_code),
+                Err(e) => last.error = Some(e.body),
+            }
+            info!("Schedule {} run {} finished {:?}", id, run_id, last.status);
+        }
+        if entry.running.is_none() {
+            if let Some(planned) = entry.queued.take() {
+                self.start(id, &mut entry, planned, runner);
+            }
+        }
+        drop(entry);
+        self.persist();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn response() -> InvokeResponse {
+        InvokeResponse {
+            request_id: "exec-1".to_string(),
+            exit_code: 0,
+            stdout: "done\n".to_string(),
+            stderr: String::new(),
+            duration_ms: 1,
+            output: None,
+            logs: None,
+            error: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime: None,
+            runtime_reason: None,
+            diagnostics: None,
+            usage: None,
+            truncated: false,
+            total_bytes: None,
+            snapshot_id: None,
+        }
+    }
+
+    /// Stands in for the executor: each run takes `run_for`, unless it is cancelled first
+    struct MockRunner {
+        run_for: Duration,
+        started: AtomicUsize,
+        cancelled: Mutex<Vec<String>>,
+        stop: Notify,
+    }
+
+    impl MockRunner {
+        fn new(run_for: Duration) -> Arc<Self> {
+            Arc::new(Self {
+                run_for,
+                started: AtomicUsize::new(0),
+                cancelled: Mutex::new(Vec::new()),
+                stop: Notify::new(),
+            })
+        }
+
+        fn started(&self) -> usize {
+            self.started.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl ScheduleRunner for MockRunner {
+        async fn run(&self, run: ScheduledRun) -> Result<InvokeResponse, ApiError> {
+            assert_eq!(run.schedule.request["command"], "echo nightly");
+            self.started.fetch_add(1, Ordering::SeqCst);
+            tokio::select! {
+                _ = tokio::time::sleep(self.run_for) => Ok(response()),
+                _ = self.stop.notified() => Err(ApiError::new(
+                    StatusCode::CONFLICT,
+                    "Cancelled",
+                    format!("run {} was replaced", run.run_id),
+                )),
+            }
+        }
+
+        fn cancel(&self, run_id: &str) {
+            self.cancelled.lock().unwrap().push(run_id.to_string());
+            self.stop.notify_one();
+        }
+    }
+
+    fn every_second(concurrency: ConcurrencyPolicy) -> ScheduleSpec {
+        ScheduleSpec {
+            function_id: "nightly-etl".to_string(),
+            cron: "* * * * * *".to_string(),
+            concurrency,
+            request: serde_json::json!({ "command": "echo nightly" })
+                .as_object()
+                .unwrap()
+                .clone(),
+        }
+    }
+
+    fn start(runner: Arc<MockRunner>) -> Arc<ScheduleStore> {
+        let store = Arc::new(ScheduleStore::new(MisfirePolicy::RunOnce));
+        tokio::spawn(store.clone().run(runner));
+        store
+    }
+
+    #[tokio::test]
+    async fn a_schedule_fires_every_second_and_records_its_runs() {
+        let runner = MockRunner::new(Duration::from_millis(10));
+        let store = start(runner.clone());
+        let schedule = store
+            .create(Some("acme"), every_second(ConcurrencyPolicy::Skip))
+            .unwrap();
+        let first = schedule.next_run_at.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(2600)).await;
+        assert!(runner.started() >= 2, "fired {} times", runner.started());
+        let fired = store.get(&schedule.schedule_id, Some("acme")).unwrap();
+        assert!(fired.runs >= 2);
+        assert!(fired.next_run_at.unwrap() > first);
+        let last = fired.last_run.unwrap();
+        assert_eq!(last.status, JobStatus::Succeeded);
+        assert_eq!(last.exit_code, Some(0));
+        assert!(last.started_at >= last.scheduled_for);
+
+        assert!(store.get(&schedule.schedule_id, None).is_none());
+        assert_eq!(store.list(Some("acme")).len(), 1);
+        assert!(store.list(Some("other")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn occurrences_are_skipped_while_a_run_is_going() {
+        let runner = MockRunner::new(Duration::from_secs(300));
+        let store = start(runner.clone());
+        let schedule = store
+            .create(None, every_second(ConcurrencyPolicy::Skip))
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(3200)).await;
+        assert_eq!(runner.started(), 1);
+        let fired = store.get(&schedule.schedule_id, None).unwrap();
+        assert_eq!(fired.runs, 1);
+        assert!(fired.skipped >= 2, "skipped {}", fired.skipped);
+        assert_eq!(fired.last_run.unwrap().status, JobStatus::Running);
+    }
+
+    #[tokio::test]
+    async fn replace_cancels_the_run_going() {
+        let runner = MockRunner::new(Duration::from_secs(300));
+        let store = start(runner.clone());
+        let schedule = store
+            .create(None, every_second(ConcurrencyPolicy::Replace))
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(2600)).await;
+        assert!(runner.started() >= 2);
+        let cancelled = runner.cancelled.lock().unwrap().clone();
+        assert_eq!(cancelled.len(), runner.started() - 1);
+        let last = store
+            .get(&schedule.schedule_id, None)
+            .unwrap()
+            .last_run
+            .unwrap();
+        assert_eq!(last.status, JobStatus::Running);
+        assert!(!cancelled.contains(&last.run_id));
+    }
+
+    #[tokio::test]
+    async fn a_deleted_schedule_stops_firing() {
+        let runner = MockRunner::new(Duration::from_millis(10));
+        let store = start(runner.clone());
+        let schedule = store
+            .create(None, every_second(ConcurrencyPolicy::Skip))
+            .unwrap();
+        while runner.started() == 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        assert!(store.delete(&schedule.schedule_id, Some("acme")).is_none());
+        assert!(store.delete(&schedule.schedule_id, None).is_some());
+        let fired = runner.started();
+        tokio::time::sleep(Duration::from_millis(2200)).await;
+        assert_eq!(runner.started(), fired);
+        assert!(store.list(None).is_empty());
+    }
+
+    #[test]
+    fn bad_expressions_are_refused() {
+        let store = ScheduleStore::new(MisfirePolicy::RunOnce);
+        for cron in ["every night", "0 0 30 2 *"] {
+            let spec = ScheduleSpec {
+                cron: cron.to_string(),
+                ..every_second(ConcurrencyPolicy::Skip)
+            };
+            let refused = store.create(None, spec).unwrap_err();
+            assert_eq!(refused.status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(refused.body.details.unwrap()["field"], "cron");
+        }
+    }
+
+    #[test]
+    fn overdue_schedules_follow_the_misfire_policy_on_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ScheduleStore::new(MisfirePolicy::RunOnce)
+            .with_dir(dir.path().to_path_buf())
+            .unwrap();
+        let hourly = ScheduleSpec {
+            cron: "@hourly".to_string(),
+            ..every_second(ConcurrencyPolicy::Skip)
+        };
+        let schedule = store.create(Some("acme"), hourly).unwrap();
+        let missed = Utc::now() - chrono::Duration::hours(3);
+        if let Some(mut entry) = store.schedules.get_mut(&schedule.schedule_id) {
+            entry.schedule.next_run_at = Some(missed);
+            entry.schedule.last_run = Some(ScheduleRun {
+                run_id: "run-1".to_string(),
+                scheduled_for: missed,
+                started_at: missed,
+                finished_at: None,
+                status: JobStatus::Running,
+                exit_code: None,
+                error: None,
+            });
+        }
+        store.persist();
+
+        let run_once = ScheduleStore::new(MisfirePolicy::RunOnce)
+            .with_dir(dir.path().to_path_buf())
+            .unwrap();
+        let reloaded = run_once.get(&schedule.schedule_id, Some("acme")).unwrap();
+        assert_eq!(reloaded.next_run_at, Some(missed));
+        let last = reloaded.last_run.unwrap();
+        assert_eq!(last.status, JobStatus::Failed);
+        assert_eq!(last.error.unwrap().code, "Interrupted");
+
+        let skip = ScheduleStore::new(MisfirePolicy::Skip)
+            .with_dir(dir.path().to_path_buf())
+            .unwrap();
+        let reloaded = skip.get(&schedule.schedule_id, Some("acme")).unwrap();
+        assert!(reloaded.next_run_at.unwrap() > Utc::now());
+    }
+}
@@ -0,0 +1,102 @@
+//! Stopping the gateway on SIGTERM or SIGINT without leaking containers.
+//!
+//! New work is refused the way an admin drain refuses it, executions already running get
+//! [`ShutdownPolicy::drain_timeout`] to finish, and whatever is left is cancelled so its
+//! container is removed before the process exits.
+
+use faas_executor::drain::DrainOutcome;
+use faas_executor::platform::executor::Executor;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long cancelled executions get to tear their containers and VMs down
+pub const TEARDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownPolicy {
+    /// How long running executions may keep going once shutdown starts
+    pub drain_timeout: Duration,
+}
+
+impl Default for ShutdownPolicy {
+    fn default() -> Self {
+        Self {
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+}
+
+impl ShutdownPolicy {
+    /// `FAAS_SHUTDOWN_DRAIN_SECS`, or 30 seconds
+    pub fn from_env() -> Self {
+        let drain_timeout = std::env::var("FAAS_SHUTDOWN_DRAIN_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map_or(DEFAULT_DRAIN_TIMEOUT, Duration::from_secs);
+        Self { drain_timeout }
+    }
+}
+
+/// What a shutdown did with the executions it found running
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Still running at the drain timeout, and cancelled
+    pub cancelled: Vec<String>,
+    /// Every execution ended, by finishing or by its cancellation, before the teardown
+    /// timeout
+    pub settled: bool,
+}
+
+/// Resolves on the first SIGTERM or SIGINT
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Could not listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                warn!("Could not listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+/// Refuse new executions, wait up to the drain timeout for running ones, then cancel the
+/// rest and wait for their sandboxes to be removed
+pub async fn drain_executions(executor: &Executor, policy: ShutdownPolicy) -> ShutdownReport {
+    let drain = executor.drain();
+    let epoch = drain.start(policy.drain_timeout);
+    let cancelled = match drain.wait_idle(epoch).await {
+        DrainOutcome::Idle => Vec::new(),
+        DrainOutcome::DeadlineReached | DrainOutcome::Cancelled => {
+            let cancelled = executor.cancel_all().await;
+            warn!(
+                "Cancelled {} execution(s) still running after {:?}",
+                cancelled.len(),
+                policy.drain_timeout
+            );
+            cancelled
+        }
+    };
+    let settled = drain.settle(TEARDOWN_TIMEOUT).await;
+    drain.finish(epoch);
+    ShutdownReport { cancelled, settled }
+}
@@ -0,0 +1,266 @@
+//! Read-only `ls` / `cat` over snapshot filesystems.
+//!
+//! Lets callers check what a snapshot contains before paying for a multi-GB restore.
+//! Docker snapshots are read through the archive API of a never-started container;
+//! Firecracker snapshots are read straight from their ext4 disk image.
+
+use crate::Snapshot;
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use faas_executor::snapshot_inspect::{
+    ext4::Ext4Image, DockerImageInspector, InspectError, Listing, SnapshotEntry,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+/// Header naming the caller's tenant until API keys carry it.
+pub const TENANT_HEADER: &str = "x-faas-tenant";
+pub const DEFAULT_CAT_LIMIT: u64 = 1024 * 1024;
+/// Callers may raise the `cat` cap up to this much.
+pub const MAX_CAT_LIMIT: u64 = 8 * 1024 * 1024;
+pub const MAX_LS_DEPTH: usize = 16;
+
+pub fn request_tenant(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(TENANT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LsQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+    pub depth: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatQuery {
+    pub path: String,
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LsResponse {
+    pub snapshot_id: String,
+    pub path: String,
+    pub entries: Vec<SnapshotEntry>,
+    /// Set when the tree had more entries than one listing returns
+    pub truncated: bool,
+}
+
+pub async fn list(snapshot: &Snapshot, query: &LsQuery) -> Result<LsResponse, InspectError> {
+    let depth = query.depth.unwrap_or(1).clamp(1, MAX_LS_DEPTH);
+    let listing: Listing = match source(snapshot)? {
+        Source::Image(image) => inspector()?.list(&image, &query.path, depth).await?,
+        Source::Disk(disk) => {
+            let path = query.path.clone();
+            blocking(move || Ext4Image::open(&disk)?.list(&path, depth)).await?
+        }
+    };
+    Ok(LsResponse {
+        snapshot_id: snapshot.id.clone(),
+        path: query.path.clone(),
+        entries: listing.entries,
+        truncated: listing.truncated,
+    })
+}
+
+pub async fn cat(snapshot: &Snapshot, query: &CatQuery) -> Result<Vec<u8>, InspectError> {
+    let limit = query
+        .max_bytes
+        .unwrap_or(DEFAULT_CAT_LIMIT)
+        .min(MAX_CAT_LIMIT);
+    match source(snapshot)? {
+        Source::Image(image) => inspector()?.cat(&image, &query.path, limit).await,
+        Source::Disk(disk) => {
+            let path = query.path.clone();
+            blocking(move || Ext4Image::open(&disk)?.read_file(&path, limit)).await
+        }
+    }
+}
+
+/// Record who looked inside which snapshot.
+pub fn audit(
+    tenant: Option<&str>,
+    snapshot_id: &str,
+    operation: &str,
+    path: &str,
+    result: &Result<(), &InspectError>,
+) {
+    info!(
+        target: "faas_audit",
+        tenant = tenant.unwrap_or("-"),
+        snapshot_id,
+        operation,
+        path,
+        outcome = match result {
+            Ok(()) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        },
+        "snapshot inspected"
+    );
+}
+
+pub fn error_response(err: InspectError) -> Response {
+    let status = match &err {
+        InspectError::NotFound(_) => StatusCode::NOT_FOUND,
+        InspectError::NotAFile(_) | InspectError::InvalidPath(_) => StatusCode::BAD_REQUEST,
+        InspectError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        InspectError::Unsupported(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        InspectError::Docker(_) | InspectError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (
+        status,
+        Json(serde_json::json!({ "error": err.to_string() })),
+    )
+        .into_response()
+}
+
+enum Source {
+    Image(String),
+    Disk(PathBuf),
+}
+
+fn source(snapshot: &Snapshot) -> Result<Source, InspectError> {
+    match (&snapshot.image, &snapshot.disk_image) {
+        (Some(image), _) => Ok(Source::Image(image.clone())),
+        (None, Some(disk)) => Ok(Source::Disk(PathBuf::from(disk))),
+        (None, None) => Err(InspectError::Unsupported(format!(
+            "snapshot {} has no filesystem to inspect",
+            snapshot.id
+        ))),
+    }
+}
+
+fn inspector() -> Result<DockerImageInspector, InspectError> {
+    let docker = faas_executor::bollard::Docker::connect_with_local_defaults()?;
+    Ok(DockerImageInspector::new(Arc::new(docker)))
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, InspectError>
+where
+    F: FnOnce() -> Result<T, InspectError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| InspectError::Io(std::io::Error::other(e.to_string())))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::{Lifecycle, SnapshotState};
+    use faas_executor::test_utils::has_mkfs_ext4;
+
+    fn disk_snapshot() -> (tempfile::TempDir, Snapshot) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(root.join("models/bert")).unwrap();
+        std::fs::write(root.join("models/bert/config.json"), b"{}").unwrap();
+        std::fs::write(root.join("models/bert/weights.bin"), vec![7u8; 4096]).unwrap();
+        let disk = dir.path().join("disk.ext4");
+        let output = std::process::Command::new("mkfs.ext4")
+            .args(["-q", "-F", "-d"])
+            .arg(&root)
+            .arg(&disk)
+            .arg("4M")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let snapshot = Snapshot {
+            id: "snap-1".to_string(),
+            name: None,
+            container_id: String::new(),
+            created_at: String::new(),
+            size_bytes: 0,
+            image: None,
+            disk_image: Some(disk.to_string_lossy().into_owned()),
+            tenant: Some("team-a".to_string()),
+            tags: Vec::new(),
+            lifecycle: Lifecycle::new(SnapshotState::Ready),
+            progress: None,
+        };
+        (dir, snapshot)
+    }
+
+    #[tokio::test]
+    async fn lists_by_depth_and_caps_cat() {
+        if !has_mkfs_ext4() {
+            eprintln!("Skipping snapshot ls test - mkfs.ext4 not available");
+            return;
+        }
+        let (_dir, snapshot) = disk_snapshot();
+
+        let shallow = list(
+            &snapshot,
+            &LsQuery {
+                path: "/models".to_string(),
+                depth: None,
+            },
+        )
+        .await
+        .unwrap();
+        let paths: Vec<_> = shallow.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/models/bert"]);
+
+        let deep = list(
+            &snapshot,
+            &LsQuery {
+                path: "/models".to_string(),
+                depth: Some(2),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(deep.entries.len(), 3);
+
+        let small = CatQuery {
+            path: "/models/bert/config.json".to_string(),
+            max_bytes: None,
+        };
+        assert_eq!(cat(&snapshot, &small).await.unwrap(), b"{}");
+
+        let capped = CatQuery {
+            path: "/models/bert/weights.bin".to_string(),
+            max_bytes: Some(1024),
+        };
+        let err = cat(&snapshot, &capped).await.unwrap_err();
+        assert_eq!(error_response(err).status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn tenant_owned_snapshots_are_private() {
+        let mut snapshot = Snapshot {
+            id: "snap-2".to_string(),
+            name: None,
+            container_id: String::new(),
+            created_at: String::new(),
+            size_bytes: 0,
+            image: None,
+            disk_image: None,
+            tenant: None,
+            tags: Vec::new(),
+            lifecycle: Lifecycle::new(SnapshotState::Ready),
+            progress: None,
+        };
+        assert!(snapshot.visible_to(None));
+        assert!(snapshot.visible_to(Some("team-b")));
+
+        snapshot.tenant = Some("team-a".to_string());
+        assert!(snapshot.visible_to(Some("team-a")));
+        assert!(!snapshot.visible_to(Some("team-b")));
+        assert!(!snapshot.visible_to(None));
+    }
+}
@@ -0,0 +1,789 @@
+//! Asynchronous snapshot creation.
+//!
+//! `POST /api/v1/snapshots` sizes the container, checks the tenant's snapshot quota and
+//! returns `202` with a `creating` snapshot. The commit runs in the background and its
+//! progress is readable from `GET /api/v1/snapshots/:id` until the snapshot is `ready`.
+//!
+//! `GET /api/v1/snapshots?tag=a,b` lists the snapshots carrying every given tag, oldest
+//! first, and `DELETE /api/v1/snapshots/:id` removes the committed image or disk along with
+//! the record. Tags are kept on the image, so they survive a gateway restart.
+
+use async_trait::async_trait;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use dashmap::DashMap;
+use faas_executor::bollard::errors::Error as BollardError;
+use faas_executor::docker_snapshot::DockerSnapshotManager;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::lifecycle::{Lifecycle, LifecycleError, SnapshotState};
+use crate::tenancy::Scope;
+use crate::Snapshot;
+
+pub use faas_executor::docker_snapshot::{SizeEstimate, SnapshotPhase, SnapshotProgress};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedSnapshot {
+    /// Image the snapshot was committed to
+    pub image: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotJobError {
+    #[error("container {0} not found")]
+    ContainerNotFound(String),
+    #[error(
+        "snapshot needs about {estimate_bytes} bytes but {used_bytes} of the \
+         {quota_bytes} byte quota are in use"
+    )]
+    QuotaExceeded {
+        estimate_bytes: u64,
+        used_bytes: u64,
+        quota_bytes: u64,
+    },
+    #[error("snapshot backend error: {0}")]
+    Backend(String),
+}
+
+impl IntoResponse for SnapshotJobError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            SnapshotJobError::ContainerNotFound(_) => StatusCode::NOT_FOUND,
+            SnapshotJobError::QuotaExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            SnapshotJobError::Backend(_) => StatusCode::BAD_GATEWAY,
+        };
+        let mut body = serde_json::json!({ "error": self.to_string() });
+        if let SnapshotJobError::QuotaExceeded {
+            estimate_bytes,
+            used_bytes,
+            quota_bytes,
+        } = self
+        {
+            body["estimate_bytes"] = estimate_bytes.into();
+            body["used_bytes"] = used_bytes.into();
+            body["quota_bytes"] = quota_bytes.into();
+        }
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Sizes and commits containers
+#[async_trait]
+pub trait SnapshotBackend: Send + Sync {
+    async fn estimate(&self, container_id: &str) -> Result<SizeEstimate, SnapshotJobError>;
+
+    async fn create(
+        &self,
+        request: &SnapshotRequest,
+        estimate_bytes: u64,
+        progress: &watch::Sender<SnapshotProgress>,
+    ) -> Result<CreatedSnapshot, SnapshotJobError>;
+
+    /// Ready snapshots that already exist, such as ones committed before a restart
+    async fn committed(&self) -> Result<Vec<Snapshot>, SnapshotJobError>;
+
+    /// Drop a committed snapshot's image
+    async fn remove(&self, image: &str) -> Result<(), SnapshotJobError>;
+}
+
+/// Metadata keys the gateway's own record is kept under on a committed snapshot
+const SNAPSHOT_ID_KEY: &str = "gateway_id";
+const TENANT_KEY: &str = "tenant";
+/// Tags as a JSON array, since a label holds one string
+const TAGS_KEY: &str = "tags";
+
+fn backend_error(container_id: &str, e: anyhow::Error) -> SnapshotJobError {
+    match e.downcast_ref::<BollardError>() {
+        Some(BollardError::DockerResponseServerError {
+            status_code: 404, ..
+        }) => SnapshotJobError::ContainerNotFound(container_id.to_string()),
+        _ => SnapshotJobError::Backend(format!("{e:#}")),
+    }
+}
+
+#[async_trait]
+impl SnapshotBackend for DockerSnapshotManager {
+    async fn estimate(&self, container_id: &str) -> Result<SizeEstimate, SnapshotJobError> {
+        self.estimate_size(container_id)
+            .await
+            .map_err(|e| backend_error(container_id, e))
+    }
+
+    async fn create(
+        &self,
+        request: &SnapshotRequest,
+        estimate_bytes: u64,
+        progress: &watch::Sender<SnapshotProgress>,
+    ) -> Result<CreatedSnapshot, SnapshotJobError> {
+        let mut metadata = HashMap::from([(SNAPSHOT_ID_KEY.to_string(), request.id.clone())]);
+        if let Some(tenant) = &request.tenant {
+            metadata.insert(TENANT_KEY.to_string(), tenant.clone());
+        }
+        if !request.tags.is_empty() {
+            metadata.insert(
+                TAGS_KEY.to_string(),
+                serde_json::to_string(&request.tags).expect("tags always serialize"),
+            );
+        }
+        let snapshot = self
+            .create_snapshot_with_progress(
+                &request.container_id,
+                request.name.clone(),
+                metadata,
+                estimate_bytes,
+                progress,
+            )
+            .await
+            .map_err(|e| backend_error(&request.container_id, e))?;
+        Ok(CreatedSnapshot {
+            image: snapshot.image_id,
+            size_bytes: snapshot.layer_bytes.max(0) as u64,
+        })
+    }
+
+    async fn committed(&self) -> Result<Vec<Snapshot>, SnapshotJobError> {
+        let committed = self
+            .load_committed()
+            .await
+            .map_err(|e| SnapshotJobError::Backend(format!("{e:#}")))?;
+        Ok(committed
+            .into_iter()
+            .filter_map(|snapshot| {
+                let id = snapshot.metadata.get(SNAPSHOT_ID_KEY)?.clone();
+                let mut lifecycle = Lifecycle::new(SnapshotState::Creating);
+                lifecycle
+                    .transition(&format!("snapshot {id}"), SnapshotState::Ready)
+                    .ok()?;
+                Some(Snapshot {
+                    id,
+                    name: snapshot.name,
+                    container_id: snapshot.container_id,
+                    created_at: snapshot.created_at.to_rfc3339(),
+                    size_bytes: snapshot.layer_bytes.max(0) as u64,
+                    image: Some(snapshot.image_id),
+                    disk_image: None,
+                    tenant: snapshot.metadata.get(TENANT_KEY).cloned(),
+                    tags: snapshot
+                        .metadata
+                        .get(TAGS_KEY)
+                        .and_then(|tags| serde_json::from_str(tags).ok())
+                        .unwrap_or_default(),
+                    lifecycle,
+                    progress: None,
+                })
+            })
+            .collect())
+    }
+
+    async fn remove(&self, image: &str) -> Result<(), SnapshotJobError> {
+        self.delete_image(image)
+            .await
+            .map_err(|e| SnapshotJobError::Backend(format!("{e:#}")))
+    }
+}
+
+/// Snapshot storage each tenant may hold; snapshots without a tenant share one allowance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotQuota {
+    pub bytes_per_tenant: Option<u64>,
+}
+
+impl SnapshotQuota {
+    /// `FAAS_SNAPSHOT_QUOTA_BYTES`; unset means unlimited
+    pub fn from_env() -> Self {
+        Self {
+            bytes_per_tenant: std::env::var("FAAS_SNAPSHOT_QUOTA_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+        }
+    }
+
+    pub fn check(&self, used_bytes: u64, estimate_bytes: u64) -> Result<(), SnapshotJobError> {
+        match self.bytes_per_tenant {
+            Some(quota_bytes) if used_bytes.saturating_add(estimate_bytes) > quota_bytes => {
+                Err(SnapshotJobError::QuotaExceeded {
+                    estimate_bytes,
+                    used_bytes,
+                    quota_bytes,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Bytes held by a tenant's snapshots, counting in-flight ones at their estimate
+pub fn tenant_usage(snapshots: &DashMap<String, Snapshot>, tenant: Option<&str>) -> u64 {
+    snapshots
+        .iter()
+        .filter(|s| s.tenant.as_deref() == tenant)
+        .filter(|s| {
+            matches!(
+                s.lifecycle.current(),
+                SnapshotState::Creating | SnapshotState::Ready | SnapshotState::Pushing
+            )
+        })
+        .map(|s| s.size_bytes)
+        .sum()
+}
+
+pub struct SnapshotRequest {
+    /// Id for the new snapshot, chosen by the caller so it can be referenced before it exists
+    pub id: String,
+    pub container_id: String,
+    pub name: Option<String>,
+    pub tenant: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Preflight a snapshot and start creating it.
+///
+/// Returns the `creating` record right away. `on_done` gets the final record once the
+/// snapshot is `ready` or `failed`.
+pub async fn start<F>(
+    backend: Arc<dyn SnapshotBackend>,
+    snapshots: Arc<DashMap<String, Snapshot>>,
+    quota: SnapshotQuota,
+    request: SnapshotRequest,
+    on_done: F,
+) -> Result<Snapshot, SnapshotJobError>
+where
+    F: FnOnce(&Snapshot) + Send + 'static,
+{
+    let estimate = backend.estimate(&request.container_id).await?;
+    quota.check(
+        tenant_usage(&snapshots, request.tenant.as_deref()),
+        estimate.total_bytes(),
+    )?;
+
+    let snapshot = Snapshot {
+        id: request.id.clone(),
+        name: request.name.clone(),
+        container_id: request.container_id.clone(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        size_bytes: estimate.total_bytes(),
+        image: None,
+        disk_image: None,
+        tenant: request.tenant.clone(),
+        tags: request.tags.clone(),
+        lifecycle: Lifecycle::new(SnapshotState::Creating),
+        progress: Some(SnapshotProgress::preflight()),
+    };
+    snapshots.insert(snapshot.id.clone(), snapshot.clone());
+    info!(
+        "Creating snapshot {} of {} (~{} bytes, {} changed paths)",
+        snapshot.id,
+        request.container_id,
+        estimate.total_bytes(),
+        estimate.changed_paths
+    );
+
+    let id = snapshot.id.clone();
+    tokio::spawn(async move {
+        let (progress, mut updates) = watch::channel(SnapshotProgress::preflight());
+        let create = backend.create(&request, estimate.total_bytes(), &progress);
+        tokio::pin!(create);
+        let result = loop {
+            tokio::select! {
+                result = &mut create => break result,
+                Ok(()) = updates.changed() => {
+                    let latest = updates.borrow_and_update().clone();
+                    if let Some(mut entry) = snapshots.get_mut(&id) {
+                        entry.progress = Some(latest);
+                    }
+                }
+            }
+        };
+
+        let Some(mut entry) = snapshots.get_mut(&id) else {
+            return;
+        };
+        let last = progress.borrow().clone();
+        let state = match result {
+            Ok(created) => {
+                entry.image = Some(created.image);
+                entry.size_bytes = created.size_bytes;
+                entry.progress = Some(SnapshotProgress {
+                    phase: SnapshotPhase::Done,
+                    bytes: last.bytes.max(created.size_bytes),
+                    percent_estimate: Some(100.0),
+                });
+                SnapshotState::Ready
+            }
+            Err(e) => {
+                warn!("Snapshot {} failed: {}", id, e);
+                entry.size_bytes = 0;
+                entry.progress = Some(SnapshotProgress {
+                    phase: SnapshotPhase::Failed,
+                    percent_estimate: None,
+                    ..last
+                });
+                SnapshotState::Failed
+            }
+        };
+        if let Err(e) = entry.lifecycle.transition(&format!("snapshot {id}"), state) {
+            warn!("{}", e);
+        }
+        let finished = entry.clone();
+        drop(entry);
+        on_done(&finished);
+    });
+
+    Ok(snapshot)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListQuery {
+    /// Comma-separated tags a snapshot must all carry
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+/// The snapshots `scope` can see that match `query`, oldest first
+pub fn list(
+    snapshots: &DashMap<String, Snapshot>,
+    scope: &Scope,
+    query: &ListQuery,
+) -> Vec<Snapshot> {
+    let wanted: Vec<&str> = query
+        .tag
+        .as_deref()
+        .map(|tags| tags.split(',').filter(|t| !t.is_empty()).collect())
+        .unwrap_or_default();
+    let mut listed: Vec<Snapshot> = snapshots
+        .iter()
+        .filter(|s| scope.sees(s))
+        .filter(|s| wanted.iter().all(|tag| s.tags.iter().any(|t| t == tag)))
+        .map(|s| s.value().clone())
+        .collect();
+    listed.sort_by(|a, b| (&a.created_at, &a.id).cmp(&(&b.created_at, &b.id)));
+    listed
+}
+
+/// Delete the snapshot `id` and what it is stored in, returning the record as it was.
+///
+/// Another tenant's snapshot is reported missing. A storage failure is logged rather
+/// than kept as a record of something that may be half gone.
+pub async fn delete(
+    backend: &dyn SnapshotBackend,
+    snapshots: &DashMap<String, Snapshot>,
+    id: &str,
+    scope: &Scope,
+) -> Result<Snapshot, LifecycleError> {
+    let deleted = {
+        let mut snapshot = snapshots
+            .get_mut(id)
+            .filter(|s| scope.sees(s))
+            .ok_or(LifecycleError::NotFound)?;
+        let before = snapshot.clone();
+        snapshot
+            .lifecycle
+            .transition(&format!("snapshot {id}"), SnapshotState::Deleting)?;
+        before
+    };
+    // Otherwise the next restart would recover it from the image
+    if let Some(image) = &deleted.image {
+        if let Err(e) = backend.remove(image).await {
+            warn!("Could not remove image of snapshot {}: {}", id, e);
+        }
+    }
+    if let Some(disk) = &deleted.disk_image {
+        if let Err(e) = tokio::fs::remove_file(disk).await {
+            warn!("Could not remove disk of snapshot {}: {}", id, e);
+        }
+    }
+    snapshots.remove(id);
+    Ok(deleted)
+}
+
+/// Put the backend's committed snapshots back into `snapshots`; returns how many were missing
+pub async fn recover(
+    backend: &dyn SnapshotBackend,
+    snapshots: &DashMap<String, Snapshot>,
+) -> Result<usize, SnapshotJobError> {
+    let mut recovered = 0;
+    for snapshot in backend.committed().await? {
+        if let dashmap::mapref::entry::Entry::Vacant(entry) = snapshots.entry(snapshot.id.clone()) {
+            entry.insert(snapshot);
+            recovered += 1;
+        }
+    }
+    Ok(recovered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Reports progress in steps, then commits `size` bytes
+    struct StubBackend {
+        size: u64,
+    }
+
+    #[async_trait]
+    impl SnapshotBackend for StubBackend {
+        async fn estimate(&self, container_id: &str) -> Result<SizeEstimate, SnapshotJobError> {
+            if container_id == "gone" {
+                return Err(SnapshotJobError::ContainerNotFound(
+                    container_id.to_string(),
+                ));
+            }
+            Ok(SizeEstimate {
+                base_image_bytes: 1000,
+                changed_bytes: self.size - 1000,
+                changed_paths: 3,
+            })
+        }
+
+        async fn create(
+            &self,
+            _request: &SnapshotRequest,
+            estimate_bytes: u64,
+            progress: &watch::Sender<SnapshotProgress>,
+        ) -> Result<CreatedSnapshot, SnapshotJobError> {
+            for step in 1..=4u64 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                progress.send_replace(SnapshotProgress {
+                    phase: SnapshotPhase::Committing,
+                    bytes: estimate_bytes * step / 5,
+                    percent_estimate: Some(step as f64 * 20.0),
+                });
+            }
+            Ok(CreatedSnapshot {
+                image: "faas-snapshot-test:latest".to_string(),
+                size_bytes: self.size,
+            })
+        }
+
+        async fn committed(&self) -> Result<Vec<Snapshot>, SnapshotJobError> {
+            let mut lifecycle = Lifecycle::new(SnapshotState::Creating);
+            lifecycle
+                .transition("snap-old", SnapshotState::Ready)
+                .unwrap();
+            Ok(vec![Snapshot {
+                id: "snap-old".to_string(),
+                name: None,
+                container_id: "c-old".to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                size_bytes: self.size,
+                image: Some("faas-snapshot-old:latest".to_string()),
+                disk_image: None,
+                tenant: None,
+                tags: Vec::new(),
+                lifecycle,
+                progress: None,
+            }])
+        }
+
+        async fn remove(&self, _image: &str) -> Result<(), SnapshotJobError> {
+            Ok(())
+        }
+    }
+
+    fn request(container_id: &str, tenant: &str) -> SnapshotRequest {
+        SnapshotRequest {
+            id: uuid::Uuid::new_v4().to_string(),
+            container_id: container_id.to_string(),
+            name: None,
+            tenant: Some(tenant.to_string()),
+            tags: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_progress_until_ready() {
+        let snapshots = Arc::new(DashMap::new());
+        let (done, finished) = tokio::sync::oneshot::channel();
+        let created = start(
+            Arc::new(StubBackend { size: 5000 }),
+            snapshots.clone(),
+            SnapshotQuota::default(),
+            request("c-1", "team-a"),
+            move |snapshot: &Snapshot| {
+                let _ = done.send(snapshot.clone());
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(created.lifecycle.current(), SnapshotState::Creating);
+
+        let mut intermediate = None;
+        while intermediate.is_none() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let progress = snapshots
+                .get(&created.id)
+                .unwrap()
+                .progress
+                .clone()
+                .unwrap();
+            if progress.phase == SnapshotPhase::Committing && progress.bytes > 0 {
+                intermediate = Some(progress);
+            }
+        }
+        assert!(intermediate.unwrap().percent_estimate.unwrap() < 100.0);
+
+        let finished = finished.await.unwrap();
+        assert_eq!(finished.lifecycle.current(), SnapshotState::Ready);
+        assert_eq!(finished.image.as_deref(), Some("faas-snapshot-test:latest"));
+        let progress = finished.progress.unwrap();
+        assert_eq!(progress.phase, SnapshotPhase::Done);
+        assert_eq!(progress.percent_estimate, Some(100.0));
+    }
+
+    #[tokio::test]
+    async fn preflight_rejects_over_quota_and_missing_containers() {
+        let snapshots = Arc::new(DashMap::new());
+        let quota = SnapshotQuota {
+            bytes_per_tenant: Some(8000),
+        };
+        let backend: Arc<dyn SnapshotBackend> = Arc::new(StubBackend { size: 5000 });
+
+        start(
+            backend.clone(),
+            snapshots.clone(),
+            quota,
+            request("c-1", "team-a"),
+            |_: &Snapshot| {},
+        )
+        .await
+        .unwrap();
+        let err = start(
+            backend.clone(),
+            snapshots.clone(),
+            quota,
+            request("c-2", "team-a"),
+            |_: &Snapshot| {},
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            SnapshotJobError::QuotaExceeded {
+                estimate_bytes: 5000,
+                used_bytes: 5000,
+                quota_bytes: 8000,
+            }
+        ));
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+
+        // Other tenants have their own allowance
+        start(
+            backend.clone(),
+            snapshots.clone(),
+            quota,
+            request("c-2", "team-b"),
+            |_: &Snapshot| {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(snapshots.len(), 2);
+
+        let missing = start(
+            backend,
+            snapshots,
+            quota,
+            request("gone", "team-a"),
+            |_: &Snapshot| {},
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(missing.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn recovery_adds_committed_snapshots_it_does_not_know() {
+        let backend = StubBackend { size: 5000 };
+        let snapshots = DashMap::new();
+        assert_eq!(recover(&backend, &snapshots).await.unwrap(), 1);
+        let recovered = snapshots.get("snap-old").unwrap().clone();
+        assert_eq!(recovered.lifecycle.current(), SnapshotState::Ready);
+        assert_eq!(recovered.image.as_deref(), Some("faas-snapshot-old:latest"));
+
+        snapshots.get_mut("snap-old").unwrap().name = Some("renamed".to_string());
+        assert_eq!(recover(&backend, &snapshots).await.unwrap(), 0);
+        assert_eq!(
+            snapshots.get("snap-old").unwrap().name.as_deref(),
+            Some("renamed")
+        );
+    }
+
+    fn ready(id: &str, tenant: Option<&str>, tags: &[&str], created_at: &str) -> Snapshot {
+        let mut lifecycle = Lifecycle::new(SnapshotState::Creating);
+        lifecycle.transition(id, SnapshotState::Ready).unwrap();
+        Snapshot {
+            id: id.to_string(),
+            name: None,
+            container_id: format!("c-{id}"),
+            created_at: created_at.to_string(),
+            size_bytes: 5000,
+            image: Some(format!("faas-snapshot-{id}:latest")),
+            disk_image: None,
+            tenant: tenant.map(str::to_string),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            lifecycle,
+            progress: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn listing_filters_by_tag_and_tenant_and_deleting_forgets_the_record() {
+        let snapshots = DashMap::new();
+        for snapshot in [
+            ready("b", None, &["model", "v2"], "2026-01-02T00:00:00Z"),
+            ready("a", None, &["model", "v1"], "2026-01-01T00:00:00Z"),
+            ready(
+                "private",
+                Some("team-a"),
+                &["model"],
+                "2026-01-03T00:00:00Z",
+            ),
+        ] {
+            snapshots.insert(snapshot.id.clone(), snapshot);
+        }
+        let ids = |tenant: Option<&str>, tag: Option<&str>| -> Vec<String> {
+            let query = ListQuery {
+                tag: tag.map(str::to_string),
+            };
+            list(
+                &snapshots,
+                &Scope::Tenant(tenant.map(str::to_string)),
+                &query,
+            )
+            .into_iter()
+            .map(|s| s.id)
+            .collect()
+        };
+        assert_eq!(ids(None, None), ["a", "b"]);
+        assert_eq!(ids(Some("team-a"), Some("model")), ["a", "b", "private"]);
+        assert_eq!(ids(None, Some("model,v2")), ["b"]);
+        assert!(ids(None, Some("v3")).is_empty());
+        let every = list(&snapshots, &Scope::All, &ListQuery::default());
+        assert_eq!(every.len(), 3);
+
+        let backend = StubBackend { size: 5000 };
+        let team_b = Scope::Tenant(Some("team-b".to_string()));
+        assert!(matches!(
+            delete(&backend, &snapshots, "private", &team_b).await,
+            Err(LifecycleError::NotFound)
+        ));
+        let deleted = delete(&backend, &snapshots, "a", &Scope::Tenant(None))
+            .await
+            .unwrap();
+        assert_eq!(deleted.lifecycle.current(), SnapshotState::Ready);
+        assert_eq!(ids(None, Some("model")), ["b"]);
+        assert!(matches!(
+            delete(&backend, &snapshots, "a", &Scope::Tenant(None)).await,
+            Err(LifecycleError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn tagged_snapshots_are_filtered_and_deleted_from_docker() {
+        use faas_executor::bollard::container::{Config, RemoveContainerOptions};
+        use faas_executor::bollard::Docker;
+
+        if !faas_executor::test_utils::has_docker() {
+            eprintln!("Test skipped: Docker not available");
+            return;
+        }
+        let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
+        let container = docker
+            .create_container::<String, String>(
+                None,
+                Config {
+                    image: Some("alpine:latest".to_string()),
+                    cmd: Some(vec!["sleep".to_string(), "300".to_string()]),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        docker
+            .start_container::<String>(&container.id, None)
+            .await
+            .unwrap();
+
+        let backend: Arc<dyn SnapshotBackend> =
+            Arc::new(DockerSnapshotManager::new(docker.clone()));
+        let snapshots = Arc::new(DashMap::new());
+        for version in ["v1", "v2"] {
+            let (done, finished) = tokio::sync::oneshot::channel();
+            start(
+                backend.clone(),
+                snapshots.clone(),
+                SnapshotQuota::default(),
+                SnapshotRequest {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    container_id: container.id.clone(),
+                    name: None,
+                    tenant: None,
+                    tags: vec!["tagged-test".to_string(), version.to_string()],
+                },
+                move |snapshot: &Snapshot| {
+                    let _ = done.send(snapshot.clone());
+                },
+            )
+            .await
+            .unwrap();
+            let finished = finished.await.unwrap();
+            assert_eq!(finished.lifecycle.current(), SnapshotState::Ready);
+        }
+
+        let tagged = |tag: &str| {
+            list(
+                &snapshots,
+                &Scope::Tenant(None),
+                &ListQuery {
+                    tag: Some(tag.to_string()),
+                },
+            )
+        };
+        assert_eq!(tagged("tagged-test").len(), 2);
+        let v1 = tagged("tagged-test,v1");
+        assert_eq!(v1.len(), 1);
+
+        // The tags live on the image, so a restarted gateway still has them
+        let committed = backend.committed().await.unwrap();
+        let recovered = committed.iter().find(|s| s.id == v1[0].id).unwrap();
+        assert_eq!(recovered.tags, ["tagged-test", "v1"]);
+
+        let deleted = delete(
+            backend.as_ref(),
+            &snapshots,
+            &v1[0].id,
+            &Scope::Tenant(None),
+        )
+        .await
+        .unwrap();
+        let image = deleted.image.unwrap();
+        assert!(docker.inspect_image(&image).await.is_err());
+        assert_eq!(tagged("tagged-test").len(), 1);
+
+        let rest = tagged("tagged-test").remove(0);
+        delete(backend.as_ref(), &snapshots, &rest.id, &Scope::Tenant(None))
+            .await
+            .unwrap();
+        let _ = docker
+            .remove_container(
+                &container.id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await;
+    }
+}
@@ -0,0 +1,83 @@
+//! The executor settings the gateway boots with.
+//!
+//! `--config <path>` names a TOML file of [`PlatformConfig`] settings, falling back to
+//! `FAAS_CONFIG`; without either the defaults are used. `FAAS_*` variables override the
+//! file either way.
+
+use faas_executor::platform::{ConfigError, PlatformConfig};
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StartupError {
+    #[error("--config needs a path")]
+    MissingConfigPath,
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+/// The file `args` names with `--config <path>` or `--config=<path>`, or else `fallback`
+pub fn config_path(
+    args: impl IntoIterator<Item = String>,
+    fallback: Option<String>,
+) -> Result<Option<PathBuf>, StartupError> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let path = match arg.strip_prefix("--config") {
+            Some("") => args.next(),
+            Some(value) => match value.strip_prefix('=') {
+                Some(path) => Some(path.to_string()),
+                None => continue,
+            },
+            None => continue,
+        };
+        return match path.filter(|path| !path.is_empty()) {
+            Some(path) => Ok(Some(PathBuf::from(path))),
+            None => Err(StartupError::MissingConfigPath),
+        };
+    }
+    Ok(fallback.filter(|path| !path.is_empty()).map(PathBuf::from))
+}
+
+/// The settings for this process's arguments and environment, validated
+pub fn platform_config() -> Result<PlatformConfig, StartupError> {
+    let path = config_path(std::env::args().skip(1), std::env::var("FAAS_CONFIG").ok())?;
+    Ok(PlatformConfig::load(path.as_deref())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn the_flag_wins_over_the_variable() {
+        let env = Some("/etc/faas/env.toml".to_string());
+        assert_eq!(
+            config_path(args(&["--config", "/etc/faas/a.toml"]), env.clone()).unwrap(),
+            Some(PathBuf::from("/etc/faas/a.toml"))
+        );
+        assert_eq!(
+            config_path(args(&["--config=/etc/faas/b.toml"]), env.clone()).unwrap(),
+            Some(PathBuf::from("/etc/faas/b.toml"))
+        );
+        assert_eq!(
+            config_path(args(&["--configure"]), env).unwrap(),
+            Some(PathBuf::from("/etc/faas/env.toml"))
+        );
+        assert_eq!(config_path(args(&[]), None).unwrap(), None);
+    }
+
+    #[test]
+    fn a_flag_without_a_path_is_an_error() {
+        for flag in [&["--config"][..], &["--config="]] {
+            assert!(matches!(
+                config_path(args(flag), None),
+                Err(StartupError::MissingConfigPath)
+            ));
+        }
+    }
+}
@@ -14,7 +14,7 @@
 /// - Debug sessions: Live debugging output
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
         Path, State,
     },
     response::IntoResponse,
@@ -22,8 +22,11 @@ use axum::{
 use dashmap::DashMap;
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, Mutex};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 /// Maximum number of concurrent clients per container
@@ -36,15 +39,30 @@ const BROADCAST_BUFFER_SIZE: usize = 1000;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum StreamEvent {
-    /// Standard output from container
-    Stdout { data: String },
+    /// Standard output from container; a TTY exec's output all arrives here
+    Stdout {
+        data: String,
+        /// The exec it came from, when started by an `exec` command
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exec_id: Option<String>,
+    },
 
     /// Standard error from container
-    Stderr { data: String },
+    Stderr {
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exec_id: Option<String>,
+    },
+
+    /// An `exec` command started; its output carries this id
+    ExecStarted { exec_id: String },
 
     /// Container process exit
     Exit { code: i32 },
 
+    /// The execution failed without an exit code, e.g. it timed out
+    Error { message: String },
+
     /// File system event (created, modified, deleted)
     FileEvent {
         path: String,
@@ -72,11 +90,28 @@ pub enum StreamEvent {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum StreamCommand {
-    /// Send stdin to container
-    Stdin { data: String },
+    /// Send stdin to a TTY exec: `exec_id`, or the latest one still running
+    Stdin {
+        data: String,
+        #[serde(default)]
+        exec_id: Option<String>,
+    },
 
-    /// Execute a command in the running container
-    Exec { command: String },
+    /// Execute a command in the running container. With `tty` it gets a pseudo-terminal and
+    /// reads `stdin` commands, so an interactive shell behaves as it would in a terminal.
+    Exec {
+        command: String,
+        #[serde(default)]
+        tty: bool,
+    },
+
+    /// Set the terminal size of a TTY exec: `exec_id`, or the latest one still running
+    Resize {
+        cols: u16,
+        rows: u16,
+        #[serde(default)]
+        exec_id: Option<String>,
+    },
 
     /// Request current container state
     GetState,
@@ -98,21 +133,60 @@ pub struct ContainerStream {
 
     /// Number of active clients
     pub client_count: Arc<std::sync::atomic::AtomicUsize>,
+
+    /// Stdin of the TTY execs still running, latest last
+    terminals: Mutex<Vec<Terminal>>,
+}
+
+struct Terminal {
+    exec_id: String,
+    input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+impl ContainerStream {
+    /// The id of `exec_id` if it is a running TTY exec, or of the latest one
+    async fn terminal(&self, exec_id: Option<&str>) -> Option<String> {
+        let terminals = self.terminals.lock().await;
+        match exec_id {
+            Some(id) => terminals.iter().find(|t| t.exec_id == id),
+            None => terminals.last(),
+        }
+        .map(|t| t.exec_id.clone())
+    }
+
+    async fn write_stdin(&self, exec_id: &str, data: &[u8]) -> std::io::Result<()> {
+        let mut terminals = self.terminals.lock().await;
+        let Some(terminal) = terminals.iter_mut().find(|t| t.exec_id == exec_id) else {
+            return Err(std::io::ErrorKind::NotFound.into());
+        };
+        terminal.input.write_all(data).await?;
+        terminal.input.flush().await
+    }
 }
 
 /// Global streaming manager
 pub struct StreamingManager {
     /// Active container streams (container_id -> ContainerStream)
     streams: Arc<DashMap<String, Arc<ContainerStream>>>,
+
+    /// Tripped when the gateway shuts down, closing every client's socket
+    closing: CancellationToken,
 }
 
 impl StreamingManager {
     pub fn new() -> Self {
         Self {
             streams: Arc::new(DashMap::new()),
+            closing: CancellationToken::new(),
         }
     }
 
+    /// Send every connected client a Close frame and end its connection, now and for
+    /// clients connecting later
+    pub fn close_all(&self) {
+        self.closing.cancel();
+    }
+
     /// Get or create a stream for a container
     pub fn get_or_create_stream(&self, container_id: String) -> Arc<ContainerStream> {
         self.streams
@@ -123,6 +197,7 @@ impl StreamingManager {
                     container_id: container_id.clone(),
                     events_tx,
                     client_count: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+                    terminals: Mutex::new(Vec::new()),
                 })
             })
             .clone()
@@ -200,8 +275,24 @@ async fn handle_websocket(socket: WebSocket, container_id: String, manager: Arc<
 
     // Spawn task to forward events to WebSocket
     let container_id_clone = container_id.clone();
+    let closing = manager.closing.clone();
     let forward_task = tokio::spawn(async move {
-        while let Ok(event) = events_rx.recv().await {
+        loop {
+            let event = tokio::select! {
+                event = events_rx.recv() => match event {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
+                _ = closing.cancelled() => {
+                    let _ = ws_tx
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::AWAY,
+                            reason: "gateway shutting down".into(),
+                        })))
+                        .await;
+                    break;
+                }
+            };
             let json = match serde_json::to_string(&event) {
                 Ok(j) => j,
                 Err(e) => {
@@ -274,13 +365,18 @@ async fn handle_command(
     command: StreamCommand,
     manager: &Arc<StreamingManager>,
 ) {
-    use bollard::exec::{CreateExecOptions, StartExecResults};
+    use bollard::exec::{CreateExecOptions, ResizeExecOptions, StartExecOptions, StartExecResults};
     use bollard::Docker;
-    use futures::StreamExt;
 
     match command {
-        StreamCommand::Stdin { data } => {
+        StreamCommand::Stdin { data, exec_id } => {
             info!("Sending stdin to container {}: {:?}", container_id, data);
+            let stream = manager.get_or_create_stream(container_id.to_string());
+            if let Some(exec_id) = stream.terminal(exec_id.as_deref()).await {
+                if let Err(e) = stream.write_stdin(&exec_id, data.as_bytes()).await {
+                    warn!("Failed to write stdin to exec {}: {}", exec_id, e);
+                }
+            }
             // Emit acknowledgment that stdin was received
             manager.emit_event(
                 container_id,
@@ -293,49 +389,74 @@ async fn handle_command(
             );
         }
 
-        StreamCommand::Exec { command: cmd } => {
+        StreamCommand::Exec { command: cmd, tty } => {
             info!("Executing command in container {}: {}", container_id, cmd);
 
             // Connect to Docker and execute command
             if let Ok(docker) = Docker::connect_with_local_defaults() {
                 let exec_config = CreateExecOptions {
+                    attach_stdin: Some(tty),
                     attach_stdout: Some(true),
                     attach_stderr: Some(true),
+                    tty: Some(tty),
                     cmd: Some(vec!["sh", "-c", &cmd]),
                     ..Default::default()
                 };
+                let start = StartExecOptions {
+                    tty,
+                    ..Default::default()
+                };
 
                 match docker.create_exec(container_id, exec_config).await {
-                    Ok(exec) => {
-                        match docker.start_exec(&exec.id, None).await {
-                            Ok(StartExecResults::Attached { mut output, .. }) => {
-                                // Stream output back to client
-                                while let Some(Ok(msg)) = output.next().await {
-                                    let data =
-                                        String::from_utf8_lossy(&msg.into_bytes()).to_string();
-                                    manager.emit_event(container_id, StreamEvent::Stdout { data });
-                                }
-                            }
-                            Ok(_) => {
-                                warn!("Exec started but not attached");
-                            }
-                            Err(e) => {
-                                error!("Failed to start exec: {}", e);
-                                manager.emit_event(
-                                    container_id,
-                                    StreamEvent::Stderr {
-                                        data: format!("Exec error: {}", e),
-                                    },
-                                );
+                    Ok(exec) => match docker.start_exec(&exec.id, Some(start)).await {
+                        Ok(StartExecResults::Attached { output, input }) => {
+                            let stream = manager.get_or_create_stream(container_id.to_string());
+                            if tty {
+                                stream.terminals.lock().await.push(Terminal {
+                                    exec_id: exec.id.clone(),
+                                    input,
+                                });
                             }
+                            manager.emit_event(
+                                container_id,
+                                StreamEvent::ExecStarted {
+                                    exec_id: exec.id.clone(),
+                                },
+                            );
+                            // Runs on its own so stdin and resizes reach the exec meanwhile
+                            let manager = manager.clone();
+                            let container_id = container_id.to_string();
+                            tokio::spawn(async move {
+                                forward_exec_output(&manager, &container_id, &exec.id, output)
+                                    .await;
+                                stream
+                                    .terminals
+                                    .lock()
+                                    .await
+                                    .retain(|t| t.exec_id != exec.id);
+                            });
                         }
-                    }
+                        Ok(_) => {
+                            warn!("Exec started but not attached");
+                        }
+                        Err(e) => {
+                            error!("Failed to start exec: {}", e);
+                            manager.emit_event(
+                                container_id,
+                                StreamEvent::Stderr {
+                                    data: format!("Exec error: {}", e),
+                                    exec_id: Some(exec.id),
+                                },
+                            );
+                        }
+                    },
                     Err(e) => {
                         error!("Failed to create exec: {}", e);
                         manager.emit_event(
                             container_id,
                             StreamEvent::Stderr {
                                 data: format!("Exec creation error: {}", e),
+                                exec_id: None,
                             },
                         );
                     }
@@ -346,6 +467,43 @@ async fn handle_command(
                     container_id,
                     StreamEvent::Stderr {
                         data: "Docker connection error".to_string(),
+                        exec_id: None,
+                    },
+                );
+            }
+        }
+
+        StreamCommand::Resize {
+            cols,
+            rows,
+            exec_id,
+        } => {
+            let stream = manager.get_or_create_stream(container_id.to_string());
+            let Some(exec_id) = stream.terminal(exec_id.as_deref()).await else {
+                manager.emit_event(
+                    container_id,
+                    StreamEvent::Stderr {
+                        data: "Resize error: no TTY exec is running".to_string(),
+                        exec_id,
+                    },
+                );
+                return;
+            };
+            let size = ResizeExecOptions {
+                height: rows,
+                width: cols,
+            };
+            let resized = match Docker::connect_with_local_defaults() {
+                Ok(docker) => docker.resize_exec(&exec_id, size).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = resized {
+                error!("Failed to resize exec {}: {}", exec_id, e);
+                manager.emit_event(
+                    container_id,
+                    StreamEvent::Stderr {
+                        data: format!("Resize error: {}", e),
+                        exec_id: Some(exec_id),
                     },
                 );
             }
@@ -381,6 +539,7 @@ async fn handle_command(
                             container_id,
                             StreamEvent::Stderr {
                                 data: format!("Inspect error: {}", e),
+                                exec_id: None,
                             },
                         );
                     }
@@ -426,6 +585,7 @@ async fn handle_command(
                             container_id,
                             StreamEvent::Stderr {
                                 data: format!("Stop error: {}", e),
+                                exec_id: None,
                             },
                         );
                     }
@@ -435,6 +595,38 @@ async fn handle_command(
     }
 }
 
+/// Emit an exec's output until it ends
+async fn forward_exec_output(
+    manager: &StreamingManager,
+    container_id: &str,
+    exec_id: &str,
+    mut output: Pin<
+        Box<
+            dyn futures::Stream<
+                    Item = Result<bollard::container::LogOutput, bollard::errors::Error>,
+                > + Send,
+        >,
+    >,
+) {
+    use bollard::container::LogOutput;
+
+    while let Some(Ok(msg)) = output.next().await {
+        let exec_id = Some(exec_id.to_string());
+        let event = match msg {
+            LogOutput::StdErr { message } => StreamEvent::Stderr {
+                data: String::from_utf8_lossy(&message).to_string(),
+                exec_id,
+            },
+            // A TTY merges both streams into `Console`
+            other => StreamEvent::Stdout {
+                data: String::from_utf8_lossy(&other.into_bytes()).to_string(),
+                exec_id,
+            },
+        };
+        manager.emit_event(container_id, event);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -465,12 +657,13 @@ mod tests {
             "container-1",
             StreamEvent::Stdout {
                 data: "Hello".to_string(),
+                exec_id: None,
             },
         );
 
         let event = rx.try_recv().unwrap();
         match event {
-            StreamEvent::Stdout { data } => assert_eq!(data, "Hello"),
+            StreamEvent::Stdout { data, .. } => assert_eq!(data, "Hello"),
             _ => panic!("Wrong event type"),
         }
     }
@@ -484,4 +677,94 @@ mod tests {
         manager.remove_stream("container-1");
         assert_eq!(manager.active_streams_count(), 0);
     }
+
+    /// The `rows cols` that `stty size` reports in the TTY exec
+    async fn stty_size(
+        manager: &Arc<StreamingManager>,
+        events: &mut broadcast::Receiver<StreamEvent>,
+        container_id: &str,
+    ) -> (u16, u16) {
+        let stdin = StreamCommand::Stdin {
+            data: "stty size\n".to_string(),
+            exec_id: None,
+        };
+        handle_command(container_id, stdin, manager).await;
+        let mut output = String::new();
+        loop {
+            let event = tokio::time::timeout(std::time::Duration::from_secs(10), events.recv())
+                .await
+                .expect("stty size never answered")
+                .unwrap();
+            if let StreamEvent::Stdout { data, .. } = event {
+                output.push_str(&data);
+            }
+            // The echoed command comes first, then the size on a line of its own
+            let size = output.lines().find_map(|line| {
+                let (rows, cols) = line.trim().split_once(' ')?;
+                Some((rows.parse().ok()?, cols.parse().ok()?))
+            });
+            if let Some(size) = size {
+                return size;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn an_interactive_shell_sees_its_terminal_resized() {
+        use bollard::container::{Config, CreateContainerOptions, RemoveContainerOptions};
+        use bollard::Docker;
+
+        if !faas_executor::test_utils::has_docker() {
+            eprintln!("Skipping: Docker is not available");
+            return;
+        }
+        let docker = Docker::connect_with_local_defaults().unwrap();
+        let name = format!("faas-tty-{}", uuid::Uuid::new_v4());
+        let options = CreateContainerOptions {
+            name: name.clone(),
+            platform: None,
+        };
+        let config = Config {
+            image: Some("alpine:latest".to_string()),
+            cmd: Some(vec!["sleep".to_string(), "60".to_string()]),
+            ..Default::default()
+        };
+        docker
+            .create_container(Some(options), config)
+            .await
+            .unwrap();
+        docker.start_container::<String>(&name, None).await.unwrap();
+
+        let manager = Arc::new(StreamingManager::new());
+        let mut events = manager
+            .get_or_create_stream(name.clone())
+            .events_tx
+            .subscribe();
+        let shell = StreamCommand::Exec {
+            command: "sh".to_string(),
+            tty: true,
+        };
+        handle_command(&name, shell, &manager).await;
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            StreamEvent::ExecStarted { .. }
+        ));
+
+        let before = stty_size(&manager, &mut events, &name).await;
+        let resize = StreamCommand::Resize {
+            cols: 132,
+            rows: 50,
+            exec_id: None,
+        };
+        handle_command(&name, resize, &manager).await;
+        let after = stty_size(&manager, &mut events, &name).await;
+
+        let force = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+        docker.remove_container(&name, Some(force)).await.unwrap();
+        assert_ne!(before, after);
+        assert_eq!(after, (50, 132));
+    }
 }
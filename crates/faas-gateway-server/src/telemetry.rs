@@ -0,0 +1,95 @@
+//! A span per request, joined to the caller's trace.
+//!
+//! [`trace_request`] opens a `request` span around the handler; the executor's spans for
+//! the execution (`run`, `run_container_inner`, and the container's create, start and wait)
+//! open under it. With the `otel` feature the span continues the trace a W3C `traceparent`
+//! and `tracestate` header names, and [`otlp_provider`] exports every span over OTLP/HTTP
+//! to `OTEL_EXPORTER_OTLP_ENDPOINT`.
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field, info_span, Instrument};
+
+/// Run the handler in a `request` span, a child of the caller's when it sent a trace context
+pub async fn trace_request(request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map_or_else(
+        || request.uri().path().to_string(),
+        |path| path.as_str().to_string(),
+    );
+    let span = info_span!(
+        "request",
+        otel.kind = "server",
+        http.method = %request.method(),
+        http.route = %route,
+        http.status_code = field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        span.set_parent(otel::extract(request.headers()));
+    }
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    response
+}
+
+#[cfg(feature = "otel")]
+pub use otel::{extract, layer, otlp_provider};
+
+#[cfg(feature = "otel")]
+mod otel {
+    use axum::http::HeaderMap;
+    use opentelemetry::propagation::{Extractor, TextMapPropagator};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+    use tracing_opentelemetry::OpenTelemetryLayer;
+
+    const SERVICE_NAME: &str = "faas-gateway";
+
+    struct Headers<'a>(&'a HeaderMap);
+
+    impl Extractor for Headers<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|name| name.as_str()).collect()
+        }
+    }
+
+    /// The trace context `traceparent` and `tracestate` carry; empty without a valid one
+    pub fn extract(headers: &HeaderMap) -> opentelemetry::Context {
+        TraceContextPropagator::new().extract(&Headers(headers))
+    }
+
+    /// Exports spans in batches once `OTEL_EXPORTER_OTLP_ENDPOINT` is set; `None` without it
+    pub fn otlp_provider(
+    ) -> Result<Option<SdkTracerProvider>, opentelemetry_otlp::ExporterBuildError> {
+        if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_none() {
+            return Ok(None);
+        }
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()?;
+        Ok(Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+                .build(),
+        ))
+    }
+
+    /// Hands `tracing` spans to `provider`
+    pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+    }
+}
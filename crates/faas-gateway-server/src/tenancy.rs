@@ -0,0 +1,700 @@
+//! Whose instances, snapshots and warm pools a request can reach.
+//!
+//! What a request creates belongs to the tenant in its `x-faas-tenant` header, which the
+//! API key layer sets from the key (see [`crate::auth`]). A request sees its own tenant's
+//! resources, plus the untenanted snapshots every tenant shares; another tenant's look
+//! exactly like missing ones. An admin key without a tenant is sent on with
+//! `x-faas-scope: all` instead, and sees everything.
+//!
+//! Executions belong to the tenant they ran for, and so do the checkpoints they leave and the
+//! groups and workflows they run in; see [`ExecutionOwners`]. An execution may only restore
+//! a snapshot, branch from an execution or join a group within its scope.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::errors::ApiError;
+use crate::snapshot_fs::request_tenant;
+use crate::{Instance, Snapshot};
+
+pub const SCOPE_HEADER: &str = "x-faas-scope";
+const ALL_TENANTS: &str = "all";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// Every tenant's resources, for admin keys
+    All,
+    /// One tenant's, or the untenanted ones for `None`
+    Tenant(Option<String>),
+}
+
+impl Scope {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        match headers.get(SCOPE_HEADER) {
+            Some(scope) if scope == ALL_TENANTS => Self::All,
+            _ => Self::Tenant(request_tenant(headers)),
+        }
+    }
+
+    /// The tenant what the request creates belongs to; nobody's for [`Scope::All`]
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            Self::All => None,
+            Self::Tenant(tenant) => tenant.as_deref(),
+        }
+    }
+
+    /// Whether a resource `owner` created is within reach
+    pub fn owns(&self, owner: Option<&str>) -> bool {
+        match self {
+            Self::All => true,
+            Self::Tenant(tenant) => tenant.as_deref() == owner,
+        }
+    }
+
+    /// [`Self::owns`], except that untenanted snapshots are everyone's
+    pub fn sees(&self, snapshot: &Snapshot) -> bool {
+        match self {
+            Self::All => true,
+            Self::Tenant(tenant) => snapshot.visible_to(tenant.as_deref()),
+        }
+    }
+}
+
+/// The tenant each execution ran for, each checkpoint one left and each group and workflow
+/// was created for, kept as long as their logs and, given a directory, beside them as
+/// `<id>.owner`, so they outlive a restart. An id without a record, past retention, is out
+/// of reach of everyone but [`Scope::All`]. Without API keys nothing holds a caller to a
+/// tenant, and every id is in reach of everyone.
+#[derive(Default)]
+pub struct ExecutionOwners {
+    owners: DashMap<String, (Option<String>, Instant)>,
+    dir: Option<PathBuf>,
+    unchecked: bool,
+}
+
+impl ExecutionOwners {
+    /// Keep the records in `dir`, loading the ones already there
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".owner"))
+            else {
+                continue;
+            };
+            let loaded = std::fs::read(&path)
+                .and_then(|data| {
+                    serde_json::from_slice::<Option<String>>(&data).map_err(std::io::Error::other)
+                })
+                .and_then(|tenant| Ok((tenant, std::fs::metadata(&path)?.modified()?)));
+            match loaded {
+                Ok((tenant, written)) => {
+                    let age = written.elapsed().unwrap_or_default();
+                    let recorded = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+                    self.owners.insert(id.to_string(), (tenant, recorded));
+                }
+                Err(e) => warn!("Skipping unreadable owner record {:?}: {}", path, e),
+            }
+        }
+        info!(
+            "Loaded {} execution owners from {:?}",
+            self.owners.len(),
+            dir
+        );
+        self.dir = Some(dir);
+        Ok(self)
+    }
+
+    /// Whether ids are held to their tenants; the gateway turns this off without API keys
+    pub fn checked(mut self, checked: bool) -> Self {
+        self.unchecked = !checked;
+        self
+    }
+
+    pub fn record(&self, id: &str, tenant: Option<&str>) {
+        self.owners
+            .insert(id.to_string(), (tenant.map(str::to_string), Instant::now()));
+        self.persist(id, tenant);
+    }
+
+    /// Record `id` for `tenant`, unless another tenant already holds it
+    pub fn claim(&self, id: &str, tenant: Option<&str>) -> bool {
+        match self.owners.entry(id.to_string()) {
+            Entry::Occupied(held) if !self.unchecked && held.get().0.as_deref() != tenant => {
+                return false
+            }
+            entry => {
+                entry.insert((tenant.map(str::to_string), Instant::now()));
+            }
+        }
+        self.persist(id, tenant);
+        true
+    }
+
+    /// Whether execution, checkpoint, group or workflow `id` is within reach
+    pub fn reachable(&self, id: &str, scope: &Scope) -> bool {
+        if self.unchecked {
+            return true;
+        }
+        match scope {
+            Scope::All => true,
+            Scope::Tenant(_) => self
+                .owners
+                .get(id)
+                .is_some_and(|owner| scope.owns(owner.0.as_deref())),
+        }
+    }
+
+    /// Drop the records older than `age`, returning how many went
+    pub fn forget_older_than(&self, age: Duration) -> usize {
+        let stale: Vec<String> = self
+            .owners
+            .iter()
+            .filter(|entry| entry.value().1.elapsed() >= age)
+            .map(|entry| entry.key().clone())
+            .collect();
+        for id in &stale {
+            self.owners.remove(id);
+            if let Some(path) = self.path_for(id) {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Failed to remove owner record {:?}: {}", path, e);
+                }
+            }
+        }
+        stale.len()
+    }
+
+    /// Where the record of `id` is kept; ids that can't be a file name are kept in memory only
+    fn path_for(&self, id: &str) -> Option<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        let dir = self.dir.as_ref()?;
+        valid.then(|| dir.join(format!("{id}.owner")))
+    }
+
+    fn persist(&self, id: &str, tenant: Option<&str>) {
+        let Some(path) = self.path_for(id) else {
+            return;
+        };
+        let written = serde_json::to_vec(&tenant)
+            .map_err(std::io::Error::other)
+            .and_then(|data| std::fs::write(&path, data));
+        if let Err(e) = written {
+            warn!("Failed to persist the owner of {}: {}", id, e);
+        }
+    }
+}
+
+/// Refuse an execution that would start from a snapshot or execution outside `scope` with
+/// 404, as if there were no such snapshot or execution. `snapshot_id` may be one of the
+/// gateway's snapshots or a checkpoint an execution left; `branch_from` an execution.
+pub fn check_sources(
+    snapshots: &DashMap<String, Snapshot>,
+    executions: &ExecutionOwners,
+    scope: &Scope,
+    snapshot_id: Option<&str>,
+    branch_from: Option<&str>,
+) -> Result<(), ApiError> {
+    if let Some(id) = snapshot_id {
+        let reachable = match snapshots.get(id) {
+            Some(snapshot) => scope.sees(&snapshot),
+            None => executions.reachable(id, scope),
+        };
+        if !reachable {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                "NotFound",
+                format!("snapshot {id} not found"),
+            ));
+        }
+    }
+    if let Some(id) = branch_from.filter(|id| !executions.reachable(id, scope)) {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "NotFound",
+            format!("execution {id} not found"),
+        ));
+    }
+    Ok(())
+}
+
+/// The instances `scope` owns
+pub fn instances(instances: &DashMap<String, Instance>, scope: &Scope) -> Vec<Instance> {
+    instances
+        .iter()
+        .filter(|entry| scope.owns(entry.tenant.as_deref()))
+        .map(|entry| entry.value().clone())
+        .collect()
+}
+
+/// Answer `/api/v1/instances/:id` routes for another tenant's instance with 404, as if
+/// there were no such instance
+pub async fn hide_foreign_instances(
+    State(instances): State<Arc<DashMap<String, Instance>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let id = request
+        .uri()
+        .path()
+        .strip_prefix("/api/v1/instances/")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|id| !id.is_empty());
+    if let Some(id) = id {
+        let scope = Scope::from_headers(request.headers());
+        let foreign = instances
+            .get(id)
+            .is_some_and(|instance| !scope.owns(instance.tenant.as_deref()));
+        if foreign {
+            return ApiError::new(
+                StatusCode::NOT_FOUND,
+                "NotFound",
+                format!("instance {id} not found"),
+            )
+            .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// What [`hide_foreign_executions`] looks owners up in
+#[derive(Clone)]
+pub struct Owners {
+    pub instances: Arc<DashMap<String, Instance>>,
+    pub executions: Arc<ExecutionOwners>,
+}
+
+/// Answer the routes of an execution, group, workflow or container out of reach with 404, as
+/// if there were none: `/api/v1/logs/:id`, `/api/v1/logs/:id/stream`,
+/// `/api/v1/executions/:id/logs`, `/api/v1/executions/:id/cancel`, `/api/v1/groups/:id`
+/// and its `cancel` and `comparison`, `/api/v1/workflows/:id/cancel` and
+/// `/api/v1/containers/:id/stream`. A container is in reach as an instance's, by its id or
+/// its container's, or as an execution's.
+pub async fn hide_foreign_executions(
+    State(owners): State<Owners>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(rest) = request.uri().path().strip_prefix("/api/v1/") else {
+        return next.run(request).await;
+    };
+    let segments: Vec<&str> = rest.split('/').collect();
+    let (kind, id) = match segments.as_slice() {
+        ["logs", id] | ["logs", id, "stream"] | ["executions", id, "logs" | "cancel"] => {
+            ("execution", *id)
+        }
+        ["groups", id] | ["groups", id, "cancel" | "comparison"] => ("group", *id),
+        ["workflows", id, "cancel"] => ("workflow", *id),
+        ["containers", id, "stream"] => ("container", *id),
+        _ => return next.run(request).await,
+    };
+    let scope = Scope::from_headers(request.headers());
+    let reachable = owners.executions.reachable(id, &scope)
+        || (kind == "container"
+            && owners.instances.iter().any(|instance| {
+                (instance.id == id || instance.container_id.as_deref() == Some(id))
+                    && scope.owns(instance.tenant.as_deref())
+            }));
+    if !reachable {
+        return ApiError::new(
+            StatusCode::NOT_FOUND,
+            "NotFound",
+            format!("{kind} {id} not found"),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{require_api_key, ApiKeyConfig, ApiKeys, Permission, API_KEY_HEADER};
+    use crate::lifecycle::{InstanceState, Lifecycle, SnapshotState};
+    use axum::{
+        body::Body,
+        extract::Path,
+        routing::{get, post},
+        Json, Router,
+    };
+    use tower::ServiceExt;
+
+    type Instances = Arc<DashMap<String, Instance>>;
+
+    fn key(key: &str, permissions: &[Permission], tenant: Option<&str>) -> ApiKeyConfig {
+        ApiKeyConfig {
+            key: key.to_string(),
+            name: key.to_string(),
+            permissions: permissions.to_vec(),
+            tenant: tenant.map(str::to_string),
+            rate_limit: None,
+            limits: None,
+        }
+    }
+
+    /// The instance routes as the gateway layers them, with handlers that only keep records
+    fn app(instances: Instances) -> Router {
+        let keys = vec![
+            key("k-acme", &[Permission::ManageInstances], Some("acme")),
+            key("k-globex", &[Permission::ManageInstances], Some("globex")),
+            key("k-root", &[Permission::Admin], None),
+        ];
+        let create = |State(instances): State<Instances>, headers: HeaderMap| async move {
+            let id = format!("i-{}", instances.len() + 1);
+            let instance = Instance {
+                id: id.clone(),
+                name: None,
+                image: "alpine:latest".to_string(),
+                tenant: Scope::from_headers(&headers).tenant().map(str::to_string),
+                lifecycle: Lifecycle::new(InstanceState::Running),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                cpu_cores: None,
+                memory_mb: None,
+                container_id: None,
+                container: None,
+                expires_at: None,
+                workspace: None,
+                ports: Vec::new(),
+                endpoints: Default::default(),
+            };
+            instances.insert(id.clone(), instance);
+            id
+        };
+        let list = |State(instances): State<Instances>, headers: HeaderMap| async move {
+            let mut ids: Vec<String> = super::instances(&instances, &Scope::from_headers(&headers))
+                .into_iter()
+                .map(|instance| instance.id)
+                .collect();
+            ids.sort();
+            Json(ids)
+        };
+        let get_one = |State(instances): State<Instances>, Path(id): Path<String>| async move {
+            match instances.get(&id) {
+                Some(_) => StatusCode::OK,
+                None => StatusCode::NOT_FOUND,
+            }
+        };
+        let delete = |State(instances): State<Instances>, Path(id): Path<String>| async move {
+            match instances.remove(&id) {
+                Some(_) => StatusCode::NO_CONTENT,
+                None => StatusCode::NOT_FOUND,
+            }
+        };
+        Router::new()
+            .route("/api/v1/instances", post(create).get(list))
+            .route("/api/v1/instances/:id", get(get_one).delete(delete))
+            .layer(axum::middleware::from_fn_with_state(
+                instances.clone(),
+                hide_foreign_instances,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(ApiKeys::new(keys)),
+                require_api_key,
+            ))
+            .with_state(instances)
+    }
+
+    async fn call(app: &Router, method: &str, path: &str, key: &str) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(API_KEY_HEADER, key)
+            .body(Body::empty())
+            .expect("valid request");
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    async fn listed(app: &Router, key: &str) -> Vec<String> {
+        let response = call(app, "GET", "/api/v1/instances", key).await;
+        serde_json::from_str(&body(response).await).unwrap()
+    }
+
+    #[tokio::test]
+    async fn each_tenant_only_reaches_its_own_instances() {
+        let instances = Instances::default();
+        let app = app(instances.clone());
+        let acme = body(call(&app, "POST", "/api/v1/instances", "k-acme").await).await;
+        let globex = body(call(&app, "POST", "/api/v1/instances", "k-globex").await).await;
+        assert_eq!(
+            instances.get(&acme).unwrap().tenant.as_deref(),
+            Some("acme")
+        );
+
+        assert_eq!(listed(&app, "k-acme").await, [acme.clone()]);
+        assert_eq!(listed(&app, "k-globex").await, [globex.clone()]);
+        assert_eq!(listed(&app, "k-root").await, [acme.clone(), globex.clone()]);
+
+        let path = |id: &str| format!("/api/v1/instances/{id}");
+        let peek = call(&app, "GET", &path(&globex), "k-acme").await;
+        assert_eq!(peek.status(), StatusCode::NOT_FOUND);
+        assert!(body(peek).await.contains("NotFound"));
+        let refused = call(&app, "DELETE", &path(&globex), "k-acme").await;
+        assert_eq!(refused.status(), StatusCode::NOT_FOUND);
+        assert!(instances.contains_key(&globex));
+
+        let deleted = call(&app, "DELETE", &path(&globex), "k-globex").await;
+        assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+        let deleted = call(&app, "DELETE", &path(&acme), "k-root").await;
+        assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+        assert!(instances.is_empty());
+    }
+
+    #[tokio::test]
+    async fn executions_start_only_from_their_tenants_snapshots_and_executions() {
+        let snapshots = Arc::new(DashMap::new());
+        snapshots.insert(
+            "snap-acme".to_string(),
+            Snapshot {
+                id: "snap-acme".to_string(),
+                name: None,
+                container_id: "ctr-1".to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                size_bytes: 0,
+                image: Some("faas-snapshot:snap-acme".to_string()),
+                disk_image: None,
+                tenant: Some("acme".to_string()),
+                tags: Vec::new(),
+                lifecycle: Lifecycle::new(SnapshotState::Ready),
+                progress: None,
+            },
+        );
+        let executions = Arc::new(ExecutionOwners::default());
+        executions.record("exec-acme", Some("acme"));
+
+        // Stands in for the execute handler, up to where it would run anything
+        let execute = move |headers: HeaderMap, Json(req): Json<serde_json::Value>| {
+            let checked = check_sources(
+                &snapshots,
+                &executions,
+                &Scope::from_headers(&headers),
+                req["snapshot_id"].as_str(),
+                req["branch_from"].as_str(),
+            );
+            async move { checked.map(|()| StatusCode::OK) }
+        };
+        let keys = vec![
+            key("k-acme", &[Permission::Execute], Some("acme")),
+            key("k-globex", &[Permission::Execute], Some("globex")),
+            key("k-root", &[Permission::Admin], None),
+        ];
+        let app = Router::new().route("/api/v1/execute", post(execute)).layer(
+            axum::middleware::from_fn_with_state(Arc::new(ApiKeys::new(keys)), require_api_key),
+        );
+        let send = |key: &str, req: serde_json::Value| {
+            let request = Request::post("/api/v1/execute")
+                .header(API_KEY_HEADER, key)
+                .header("content-type", "application/json")
+                .body(Body::from(req.to_string()))
+                .expect("valid request");
+            app.clone().oneshot(request)
+        };
+
+        for req in [
+            serde_json::json!({"snapshot_id": "snap-acme"}),
+            serde_json::json!({"branch_from": "exec-acme"}),
+        ] {
+            let foreign = send("k-globex", req.clone()).await.unwrap();
+            assert_eq!(foreign.status(), StatusCode::NOT_FOUND, "{req}");
+            assert!(body(foreign).await.contains("NotFound"));
+            let own = send("k-acme", req.clone()).await.unwrap();
+            assert_eq!(own.status(), StatusCode::OK, "{req}");
+            let admin = send("k-root", req.clone()).await.unwrap();
+            assert_eq!(admin.status(), StatusCode::OK, "{req}");
+        }
+        let unknown = send("k-acme", serde_json::json!({"branch_from": "exec-gone"}));
+        assert_eq!(unknown.await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn logs_and_streams_only_reach_the_tenants_own() {
+        let instances = Instances::default();
+        let executions = Arc::new(ExecutionOwners::default());
+        let mut instance = crate::instance_ttl::persistent_instance(
+            "i-acme",
+            "alpine:latest",
+            None,
+            None,
+            Duration::from_secs(60),
+            chrono::Utc::now(),
+        );
+        instance.tenant = Some("acme".to_string());
+        instance.container_id = Some("ctr-acme".to_string());
+        instances.insert(instance.id.clone(), instance);
+        executions.record("exec-acme", Some("acme"));
+
+        // Container streams need ManageInstances
+        let both = [Permission::Execute, Permission::ManageInstances];
+        let keys = vec![
+            key("k-acme", &both, Some("acme")),
+            key("k-globex", &both, Some("globex")),
+            key("k-root", &[Permission::Admin], None),
+        ];
+        let owners = Owners {
+            instances,
+            executions,
+        };
+        let app = Router::new()
+            .route("/api/v1/logs/:id", get(|| async { "logs" }))
+            .route("/api/v1/executions/:id/logs", get(|| async { "logs" }))
+            .route("/api/v1/containers/:id/stream", get(|| async { "stream" }))
+            .layer(axum::middleware::from_fn_with_state(
+                owners,
+                hide_foreign_executions,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(ApiKeys::new(keys)),
+                require_api_key,
+            ));
+
+        for path in [
+            "/api/v1/logs/exec-acme",
+            "/api/v1/executions/exec-acme/logs",
+            "/api/v1/containers/exec-acme/stream",
+            "/api/v1/containers/i-acme/stream",
+            "/api/v1/containers/ctr-acme/stream",
+        ] {
+            let foreign = call(&app, "GET", path, "k-globex").await;
+            assert_eq!(foreign.status(), StatusCode::NOT_FOUND, "{path}");
+            assert!(body(foreign).await.contains("NotFound"));
+            for key in ["k-acme", "k-root"] {
+                let own = call(&app, "GET", path, key).await;
+                assert_eq!(own.status(), StatusCode::OK, "{path} {key}");
+            }
+        }
+        let unknown = call(&app, "GET", "/api/v1/logs/exec-gone", "k-acme").await;
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn cancels_groups_and_workflows_only_reach_the_tenants_own() {
+        let executions = Arc::new(ExecutionOwners::default());
+        executions.record("exec-acme", Some("acme"));
+        executions.record("group-acme", Some("acme"));
+        assert!(executions.claim("wf-acme", Some("acme")));
+        // Another tenant can't take the id over, while its owner may submit it again
+        assert!(!executions.claim("wf-acme", Some("globex")));
+        assert!(executions.claim("wf-acme", Some("acme")));
+
+        let keys = vec![
+            key("k-acme", &[Permission::Execute], Some("acme")),
+            key("k-globex", &[Permission::Execute], Some("globex")),
+            key("k-root", &[Permission::Admin], None),
+        ];
+        let owners = Owners {
+            instances: Instances::default(),
+            executions,
+        };
+        let app = Router::new()
+            .route(
+                "/api/v1/executions/:id/cancel",
+                post(|| async { "cancelled" }),
+            )
+            .route("/api/v1/groups/:id", get(|| async { "group" }))
+            .route("/api/v1/groups/:id/cancel", post(|| async { "cancelled" }))
+            .route("/api/v1/groups/:id/comparison", get(|| async { "report" }))
+            .route(
+                "/api/v1/workflows/:id/cancel",
+                post(|| async { "cancelled" }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                owners,
+                hide_foreign_executions,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(ApiKeys::new(keys)),
+                require_api_key,
+            ));
+
+        for (method, path) in [
+            ("POST", "/api/v1/executions/exec-acme/cancel"),
+            ("GET", "/api/v1/groups/group-acme"),
+            ("POST", "/api/v1/groups/group-acme/cancel"),
+            ("GET", "/api/v1/groups/group-acme/comparison"),
+            ("POST", "/api/v1/workflows/wf-acme/cancel"),
+        ] {
+            let foreign = call(&app, method, path, "k-globex").await;
+            assert_eq!(foreign.status(), StatusCode::NOT_FOUND, "{path}");
+            for key in ["k-acme", "k-root"] {
+                let own = call(&app, method, path, key).await;
+                assert_eq!(own.status(), StatusCode::OK, "{path} {key}");
+            }
+        }
+    }
+
+    #[test]
+    fn owners_outlive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let acme = Scope::Tenant(Some("acme".to_string()));
+        let globex = Scope::Tenant(Some("globex".to_string()));
+        {
+            let owners = ExecutionOwners::default().with_dir(dir.path()).unwrap();
+            owners.record("exec-acme", Some("acme"));
+            owners.record("exec-open", None);
+            assert!(owners.claim("wf-acme", Some("acme")));
+            // Not a file name, so kept in memory only
+            owners.record("../escape", Some("acme"));
+        }
+
+        let owners = ExecutionOwners::default().with_dir(dir.path()).unwrap();
+        assert!(owners.reachable("exec-acme", &acme));
+        assert!(!owners.reachable("exec-acme", &globex));
+        assert!(owners.reachable("exec-open", &Scope::Tenant(None)));
+        assert!(owners.reachable("wf-acme", &acme));
+        assert!(!owners.claim("wf-acme", Some("globex")));
+        assert!(!owners.reachable("../escape", &acme));
+
+        assert_eq!(owners.forget_older_than(Duration::ZERO), 4);
+        let owners = ExecutionOwners::default().with_dir(dir.path()).unwrap();
+        assert!(!owners.reachable("exec-acme", &acme));
+    }
+
+    #[test]
+    fn without_api_keys_every_id_is_in_reach() {
+        let owners = ExecutionOwners::default().checked(false);
+        owners.record("exec-acme", Some("acme"));
+        let globex = Scope::Tenant(Some("globex".to_string()));
+        assert!(owners.reachable("exec-acme", &globex));
+        assert!(owners.reachable("exec-from-before", &globex));
+        assert!(owners.claim("exec-acme", Some("globex")));
+    }
+
+    #[test]
+    fn only_the_all_scope_reaches_other_tenants() {
+        let scope = |tenant: Option<&str>| Scope::Tenant(tenant.map(str::to_string));
+        assert!(scope(None).owns(None));
+        assert!(!scope(Some("acme")).owns(None));
+        assert!(!scope(None).owns(Some("acme")));
+        assert!(Scope::All.owns(Some("acme")));
+        assert_eq!(Scope::All.tenant(), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(SCOPE_HEADER, "all".parse().unwrap());
+        assert_eq!(Scope::from_headers(&headers), Scope::All);
+        headers.remove(SCOPE_HEADER);
+        headers.insert(crate::snapshot_fs::TENANT_HEADER, "acme".parse().unwrap());
+        assert_eq!(Scope::from_headers(&headers), scope(Some("acme")));
+    }
+}
@@ -7,6 +7,7 @@ use axum::{
 };
 use dashmap::DashMap;
 use faas_executor::platform::executor::Executor;
+use faas_executor::platform::PlatformConfig;
 use serde_json::json;
 use std::sync::Arc;
 
@@ -332,7 +333,11 @@ mod gateway_tests {
 
     // Helper function to create test app
     async fn create_test_app() -> Router {
-        let executor = Arc::new(Executor::new().await.expect("Failed to create executor"));
+        let executor = Arc::new(
+            Executor::new(PlatformConfig::default())
+                .await
+                .expect("Failed to create executor"),
+        );
 
         let state = AppState {
             executor,
@@ -363,6 +368,9 @@ mod unit_tests {
             cache_key: None,
             snapshot_id: None,
             branch_from: None,
+            ulimits: None,
+            shm_size_mb: None,
+            tmpfs: None,
         };
 
         assert_eq!(request.image, Some("alpine:latest".to_string()));
@@ -381,6 +389,8 @@ mod unit_tests {
             output: Some("output".to_string()),
             logs: None,
             error: None,
+            diagnostics: None,
+            snapshot_id: None,
         };
 
         assert_eq!(response.exit_code, 0);
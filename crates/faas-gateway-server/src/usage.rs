@@ -0,0 +1,546 @@
+//! Compute, storage and transfer metering per tenant.
+//!
+//! Executions are billed for their compute; a speculative one for every attempt it made,
+//! with the attempts that lost the race also reported as speculation overhead. What a
+//! Docker execution measured itself consuming (CPU time, peak memory, stdout bytes) is kept
+//! alongside, with its MCUs after the tier discount, under `measured_mcus`. Snapshots,
+//! artifacts and retained logs add to the tenant's stored bytes, which accrue byte-hours for as long as they are held, and artifact and log downloads add to
+//! egress. The tier limits on those dimensions are checked before the operation that would
+//! cross them, and a refusal is the usage tracker's `LimitExceeded`, answered with 429.
+//!
+//! Executions are admitted the same way: one asking for more vCPUs or RAM than the tier
+//! allows is refused with 429, and once the billing period's MCUs are used up, every
+//! execution is refused with 402 `InsufficientCredits` until the account has credit again.
+//!
+//! Untagged requests are metered as the `default` tenant. Tiers come from
+//! `FAAS_USAGE_TIERS` (`acme=team,globex=scale`), with `FAAS_USAGE_DEFAULT_TIER` for
+//! everyone else (`developer` when unset).
+//!
+//! Usage is kept in memory unless `FAAS_USAGE_DATABASE_URL` names a SQLite or Postgres
+//! database, which needs the gateway built with `usage-sqlite` or `usage-postgres`. Billing
+//! periods that have ended are rolled over hourly.
+
+use crate::snapshot_fs::request_tenant;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use faas_executor::platform::{speculation::AttemptOutcome, Response as ExecutionResponse};
+use faas_usage_tracker::{
+    ExecutionRecord, InMemoryStorage, Limit, StoredKind, Tier, UsageBreakdown, UsageError,
+    UsageStorage, UsageTracker, SPECULATION_OVERHEAD_MODE,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Account for requests that carry no tenant
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+pub struct UsageMeter {
+    storage: Arc<dyn UsageStorage>,
+    tracker: UsageTracker,
+    tiers: HashMap<String, Tier>,
+    default_tier: Tier,
+    /// Serializes first-use account creation
+    creating: Mutex<()>,
+    /// Tenant of each execution log, so the retention sweep knows whom to bill
+    log_owners: DashMap<String, String>,
+}
+
+/// What an execution is billed as holding while it runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComputeSize {
+    pub vcpus: f64,
+    pub ram_gb: f64,
+}
+
+impl ComputeSize {
+    /// The request's size, or one vCPU and 512 MB where it didn't ask
+    pub fn of(cpu_cores: Option<u8>, memory_mb: Option<u32>) -> Self {
+        Self {
+            vcpus: f64::from(cpu_cores.unwrap_or(1)),
+            ram_gb: f64::from(memory_mb.unwrap_or(512)) / 1024.0,
+        }
+    }
+}
+
+fn parse_tier(name: &str) -> Option<Tier> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "developer" => Some(Tier::Developer),
+        "team" => Some(Tier::Team),
+        "scale" => Some(Tier::Scale),
+        _ => None,
+    }
+}
+
+#[cfg(any(feature = "usage-sqlite", feature = "usage-postgres"))]
+async fn database(url: &str) -> Result<Arc<dyn UsageStorage>, UsageError> {
+    Ok(Arc::new(
+        faas_usage_tracker::SqlStorage::connect(url).await?,
+    ))
+}
+
+#[cfg(not(any(feature = "usage-sqlite", feature = "usage-postgres")))]
+async fn database(_url: &str) -> Result<Arc<dyn UsageStorage>, UsageError> {
+    Err(UsageError::Storage(
+        "FAAS_USAGE_DATABASE_URL needs the gateway built with usage-sqlite or usage-postgres"
+            .to_string(),
+    ))
+}
+
+fn account_for(tenant: Option<&str>) -> &str {
+    tenant.unwrap_or(DEFAULT_ACCOUNT)
+}
+
+impl UsageMeter {
+    pub fn new(tiers: HashMap<String, Tier>, default_tier: Tier) -> Self {
+        Self::with_storage(Arc::new(InMemoryStorage::new()), tiers, default_tier)
+    }
+
+    pub fn with_storage(
+        storage: Arc<dyn UsageStorage>,
+        tiers: HashMap<String, Tier>,
+        default_tier: Tier,
+    ) -> Self {
+        Self {
+            tracker: UsageTracker::new(storage.clone()),
+            storage,
+            tiers,
+            default_tier,
+            creating: Mutex::new(()),
+            log_owners: DashMap::new(),
+        }
+    }
+
+    pub async fn from_env() -> Result<Self, UsageError> {
+        let tiers = std::env::var("FAAS_USAGE_TIERS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let (tenant, tier) = entry.split_once('=')?;
+                let parsed = parse_tier(tier);
+                if parsed.is_none() {
+                    warn!("Ignoring unknown tier {:?} for tenant {}", tier, tenant);
+                }
+                Some((tenant.trim().to_string(), parsed?))
+            })
+            .collect();
+        let default_tier = std::env::var("FAAS_USAGE_DEFAULT_TIER")
+            .ok()
+            .and_then(|tier| parse_tier(&tier))
+            .unwrap_or(Tier::Developer);
+        let storage = match std::env::var("FAAS_USAGE_DATABASE_URL") {
+            Ok(url) => database(&url).await?,
+            Err(_) => Arc::new(InMemoryStorage::new()),
+        };
+        Ok(Self::with_storage(storage, tiers, default_tier))
+    }
+
+    /// The tenant's account id, creating the account on first use
+    async fn account(&self, tenant: Option<&str>) -> Result<String, UsageError> {
+        let account_id = account_for(tenant);
+        match self.storage.get_account(account_id).await {
+            Err(UsageError::AccountNotFound(_)) => {}
+            other => return other.map(|_| account_id.to_string()),
+        }
+        let _creating = self.creating.lock().await;
+        if self.storage.get_account(account_id).await.is_err() {
+            let tier = self
+                .tiers
+                .get(account_id)
+                .copied()
+                .unwrap_or(self.default_tier);
+            self.storage
+                .create_account(account_id.to_string(), tier)
+                .await?;
+        }
+        Ok(account_id.to_string())
+    }
+
+    /// Refuse an execution of `size` the tenant's tier can't take, or one started with no
+    /// MCUs left
+    pub async fn admit(&self, tenant: Option<&str>, size: ComputeSize) -> Result<(), UsageError> {
+        let account_id = self.account(tenant).await?;
+        self.tracker
+            .check_limits(
+                &account_id,
+                size.vcpus.ceil() as u32,
+                size.ram_gb.ceil() as u32,
+            )
+            .await
+    }
+
+    /// Refuse storing `bytes` more of `kind` if it would cross the tenant's tier
+    pub async fn check_storage(
+        &self,
+        tenant: Option<&str>,
+        kind: StoredKind,
+        bytes: u64,
+    ) -> Result<(), UsageError> {
+        let account_id = self.account(tenant).await?;
+        self.tracker
+            .check_storage(&account_id, kind, bytes, Utc::now())
+            .await
+    }
+
+    pub async fn record_stored(&self, tenant: Option<&str>, kind: StoredKind, delta_bytes: i64) {
+        let result = match self.account(tenant).await {
+            Ok(account_id) => {
+                self.tracker
+                    .record_stored(&account_id, kind, delta_bytes, Utc::now())
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to meter {:?} storage: {}", kind, e);
+        }
+    }
+
+    pub async fn check_egress(&self, tenant: Option<&str>, bytes: u64) -> Result<(), UsageError> {
+        let account_id = self.account(tenant).await?;
+        self.tracker.check_egress(&account_id, bytes).await
+    }
+
+    pub async fn record_egress(&self, tenant: Option<&str>, bytes: u64) {
+        let result = match self.account(tenant).await {
+            Ok(account_id) => self.tracker.record_egress(&account_id, bytes).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to meter egress: {}", e);
+        }
+    }
+
+    /// Bill the compute of a finished execution run in `mode`
+    pub async fn record_execution(
+        &self,
+        tenant: Option<&str>,
+        response: &ExecutionResponse,
+        size: ComputeSize,
+        mode: &str,
+    ) {
+        let account_id = match self.account(tenant).await {
+            Ok(account_id) => account_id,
+            Err(e) => {
+                warn!("Failed to meter execution {}: {}", response.id, e);
+                return;
+            }
+        };
+        let attempts = match &response.speculation {
+            Some(report) => report
+                .attempts
+                .iter()
+                .map(|attempt| {
+                    let mode = match attempt.outcome {
+                        AttemptOutcome::Won => mode,
+                        _ => SPECULATION_OVERHEAD_MODE,
+                    };
+                    (attempt.id.clone(), attempt.duration_ms, mode)
+                })
+                .collect(),
+            None => vec![(
+                response.id.clone(),
+                response.duration.as_millis() as u64,
+                mode,
+            )],
+        };
+        for (execution_id, duration_ms, mode) in attempts {
+            let seconds = duration_ms as f64 / 1000.0;
+            let record = ExecutionRecord {
+                execution_id,
+                account_id: account_id.clone(),
+                vcpu_seconds: seconds * size.vcpus,
+                ram_gb_seconds: seconds * size.ram_gb,
+                mode: mode.to_string(),
+                timestamp: Utc::now(),
+                duration_ms,
+            };
+            if let Err(e) = self.tracker.record_execution(record).await {
+                warn!("Failed to meter execution {}: {}", response.id, e);
+            }
+        }
+        if let Some(usage) = &response.usage {
+            if let Err(e) = self.tracker.record(&account_id, usage).await {
+                warn!("Failed to record the usage of {}: {}", response.id, e);
+            }
+        }
+    }
+
+    /// Remember whose execution wrote the log `id`
+    pub fn note_log(&self, id: &str, tenant: Option<&str>) {
+        self.log_owners
+            .insert(id.to_string(), account_for(tenant).to_string());
+    }
+
+    /// Bill each tenant for the logs still retained after a sweep; `kept` is every log left
+    /// on disk with its size
+    pub async fn set_retained_logs(&self, kept: &[(String, u64)], at: DateTime<Utc>) {
+        let mut per_account: HashMap<String, u64> = self
+            .log_owners
+            .iter()
+            .map(|entry| (entry.value().clone(), 0))
+            .collect();
+        for (id, size) in kept {
+            let owner = self
+                .log_owners
+                .get(id)
+                .map(|entry| entry.value().clone())
+                .unwrap_or_else(|| DEFAULT_ACCOUNT.to_string());
+            *per_account.entry(owner).or_default() += size;
+        }
+        self.log_owners
+            .retain(|id, _| kept.iter().any(|(kept_id, _)| kept_id == id));
+        for (account_id, bytes) in per_account {
+            let result = match self.account(Some(&account_id)).await {
+                Ok(account_id) => {
+                    self.tracker
+                        .set_stored(&account_id, StoredKind::Log, bytes, at)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("Failed to meter retained logs for {}: {}", account_id, e);
+            }
+        }
+    }
+
+    pub async fn breakdown(
+        &self,
+        tenant: Option<&str>,
+        at: DateTime<Utc>,
+    ) -> Result<UsageBreakdown, UsageError> {
+        let account_id = self.account(tenant).await?;
+        self.tracker.usage_breakdown(&account_id, at).await
+    }
+
+    /// Start a new billing period for every account whose period ended by `now`
+    pub async fn roll_over(&self, now: DateTime<Utc>) -> Result<usize, UsageError> {
+        self.tracker.roll_over(now).await
+    }
+
+    /// Like [`Self::breakdown`], for an account that has already been metered
+    pub async fn account_breakdown(
+        &self,
+        account_id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<UsageBreakdown, UsageError> {
+        self.tracker.usage_breakdown(account_id, at).await
+    }
+}
+
+/// The response for an operation the meter refused or failed to check
+pub fn refusal(error: UsageError) -> Response {
+    let (status, code) = match &error {
+        UsageError::LimitExceeded {
+            limit: Limit::Credits,
+            ..
+        } => (StatusCode::PAYMENT_REQUIRED, "InsufficientCredits"),
+        UsageError::LimitExceeded { .. } => (StatusCode::TOO_MANY_REQUESTS, "LimitExceeded"),
+        UsageError::AccountNotFound(_) => (StatusCode::NOT_FOUND, "AccountNotFound"),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "UsageUnavailable"),
+    };
+    (
+        status,
+        Json(serde_json::json!({ "error": error.to_string(), "code": code })),
+    )
+        .into_response()
+}
+
+/// `GET /api/v1/usage`: the requesting tenant's consumption by dimension
+pub async fn usage_handler(
+    State(meter): State<Arc<UsageMeter>>,
+    headers: HeaderMap,
+) -> Result<Json<UsageBreakdown>, Response> {
+    let tenant = request_tenant(&headers);
+    meter
+        .breakdown(tenant.as_deref(), Utc::now())
+        .await
+        .map(Json)
+        .map_err(refusal)
+}
+
+/// `GET /api/v1/accounts/:id/usage`: any account's consumption, for operators
+pub async fn account_usage_handler(
+    State(meter): State<Arc<UsageMeter>>,
+    Path(account_id): Path<String>,
+) -> Result<Json<UsageBreakdown>, Response> {
+    meter
+        .account_breakdown(&account_id, Utc::now())
+        .await
+        .map(Json)
+        .map_err(refusal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use faas_usage_tracker::GIB;
+
+    #[tokio::test]
+    async fn tenants_are_metered_separately_and_logs_follow_their_owner() {
+        let tiers = HashMap::from([("acme".to_string(), Tier::Team)]);
+        let meter = UsageMeter::new(tiers, Tier::Developer);
+
+        // Developer allows 10 GB of artifacts, Team 100
+        meter
+            .record_stored(Some("acme"), StoredKind::Artifact, 50 * GIB as i64)
+            .await;
+        assert!(meter
+            .check_storage(Some("acme"), StoredKind::Artifact, GIB)
+            .await
+            .is_ok());
+        assert!(matches!(
+            meter
+                .check_storage(None, StoredKind::Artifact, 11 * GIB)
+                .await,
+            Err(UsageError::LimitExceeded { .. })
+        ));
+
+        meter.note_log("exec-1", Some("acme"));
+        meter.note_log("exec-2", None);
+        let now = Utc::now();
+        meter
+            .set_retained_logs(&[("exec-1".to_string(), 300)], now)
+            .await;
+        let logs = |breakdown: &UsageBreakdown| breakdown.get("log_bytes").unwrap().used;
+        assert_eq!(
+            logs(&meter.breakdown(Some("acme"), now).await.unwrap()),
+            300.0
+        );
+        assert_eq!(logs(&meter.breakdown(None, now).await.unwrap()), 0.0);
+
+        // Swept away: nothing left to bill
+        meter.set_retained_logs(&[], now).await;
+        assert_eq!(
+            logs(&meter.breakdown(Some("acme"), now).await.unwrap()),
+            0.0
+        );
+        assert!(meter.log_owners.is_empty());
+    }
+
+    #[tokio::test]
+    async fn speculative_executions_are_billed_for_both_attempts() {
+        use faas_executor::platform::speculation::{Attempt, AttemptRole, SpeculationReport};
+        use std::time::Duration;
+
+        let meter = UsageMeter::new(HashMap::new(), Tier::Developer);
+        let attempt = |role, runtime, outcome, duration_ms| Attempt {
+            id: format!("exec-1-{role:?}"),
+            role,
+            runtime,
+            outcome,
+            duration_ms,
+        };
+        let response = ExecutionResponse {
+            id: "exec-1".to_string(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            exit_code: 0,
+            duration: Duration::from_millis(3_600_000),
+            snapshot: None,
+            speculation: Some(SpeculationReport {
+                winner: AttemptRole::Fallback,
+                attempts: vec![
+                    attempt(
+                        AttemptRole::Preferred,
+                        faas_common::Runtime::Firecracker,
+                        AttemptOutcome::Cancelled,
+                        1_800_000,
+                    ),
+                    attempt(
+                        AttemptRole::Fallback,
+                        faas_common::Runtime::Docker,
+                        AttemptOutcome::Won,
+                        3_600_000,
+                    ),
+                ],
+            }),
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: None,
+            usage: None,
+            truncation: None,
+            warm_start: false,
+        };
+        meter
+            .record_execution(
+                Some("acme"),
+                &response,
+                ComputeSize::of(Some(2), Some(1024)),
+                "ephemeral",
+            )
+            .await;
+
+        let breakdown = meter.breakdown(Some("acme"), Utc::now()).await.unwrap();
+        let used = |dimension: &str| breakdown.get(dimension).unwrap().used;
+        assert_eq!(used("vcpu_hours"), 3.0);
+        assert_eq!(used("ram_gb_hours"), 1.5);
+        assert_eq!(used("speculation_vcpu_hours"), 1.0);
+        assert_eq!(used("speculation_ram_gb_hours"), 0.5);
+    }
+
+    #[tokio::test]
+    async fn executions_past_the_tier_or_its_credits_are_refused() {
+        use std::time::Duration;
+
+        let meter = Arc::new(UsageMeter::new(HashMap::new(), Tier::Developer));
+        let small = ComputeSize::of(Some(1), Some(1024));
+        assert!(meter.admit(Some("acme"), small).await.is_ok());
+
+        // Developer allows 64 vCPUs and 256 GB at once
+        for size in [
+            ComputeSize::of(Some(65), None),
+            ComputeSize::of(None, Some(300 * 1024)),
+        ] {
+            let refused = meter.admit(Some("acme"), size).await.unwrap_err();
+            assert_eq!(refusal(refused).status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        let ran_for = |id: &str, hours: u64| ExecutionResponse {
+            id: id.to_string(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            exit_code: 0,
+            duration: Duration::from_secs(hours * 3600),
+            snapshot: None,
+            speculation: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime_decision: None,
+            usage: None,
+            truncation: None,
+            warm_start: false,
+        };
+        let mcus = |breakdown: &UsageBreakdown| breakdown.get("mcus").unwrap().clone();
+
+        // A third of the way through the month's 300 MCUs, there is credit left
+        meter
+            .record_execution(Some("acme"), &ran_for("exec-1", 100), small, "ephemeral")
+            .await;
+        let used = mcus(&meter.breakdown(Some("acme"), Utc::now()).await.unwrap());
+        assert_eq!((used.used, used.limit), (100.0, Some(300.0)));
+        assert!(meter.admit(Some("acme"), small).await.is_ok());
+
+        meter
+            .record_execution(Some("acme"), &ran_for("exec-2", 200), small, "ephemeral")
+            .await;
+        let refused = meter.admit(Some("acme"), small).await.unwrap_err();
+        assert_eq!(refusal(refused).status(), StatusCode::PAYMENT_REQUIRED);
+        assert!(meter.admit(Some("globex"), small).await.is_ok());
+
+        let Json(acme) = account_usage_handler(State(meter.clone()), Path("acme".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(mcus(&acme).used, 300.0);
+        let missing = account_usage_handler(State(meter), Path("initech".to_string())).await;
+        assert_eq!(missing.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,402 @@
+//! Checks on the shape of an execute request, made before any of it runs
+//!
+//! Every field that fails is reported at once: the 422 `InvalidRequest` names the first in
+//! `details.field` and lists them all, each with its own message, in `details.errors`.
+
+use crate::errors::ApiError;
+use faas_executor::platform::Mode;
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+const DEFAULT_MIN_TIMEOUT_MS: u64 = 100;
+const DEFAULT_MAX_TIMEOUT_MS: u64 = 60 * 60 * 1000;
+const DEFAULT_MIN_MEMORY_MB: u32 = 16;
+const DEFAULT_MAX_MEMORY_MB: u32 = 64 * 1024;
+const DEFAULT_MAX_CPU_CORES: u8 = 64;
+
+/// Docker's reference grammar: an optional registry host and port, lowercase path
+/// components, then a tag, a digest or both
+fn image_reference() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        let host = r"(?:[a-zA-Z0-9](?:[a-zA-Z0-9-]*[a-zA-Z0-9])?)(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]*[a-zA-Z0-9])?)*(?::[0-9]+)?";
+        let component = r"[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*";
+        let tag = r"[\w][\w.-]{0,127}";
+        let digest = r"[A-Za-z][A-Za-z0-9]*(?:[-_+.][A-Za-z][A-Za-z0-9]*)*:[0-9a-fA-F]{32,}";
+        Regex::new(&format!(
+            "^(?:{host}/)?{component}(?:/{component})*(?::{tag})?(?:@{digest})?$"
+        ))
+        .expect("image reference pattern compiles")
+    })
+}
+
+/// The ranges an execute request's resources must fall in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestBounds {
+    pub min_timeout_ms: u64,
+    pub max_timeout_ms: u64,
+    pub min_memory_mb: u32,
+    pub max_memory_mb: u32,
+    pub max_cpu_cores: u8,
+}
+
+impl Default for RequestBounds {
+    fn default() -> Self {
+        Self {
+            min_timeout_ms: DEFAULT_MIN_TIMEOUT_MS,
+            max_timeout_ms: DEFAULT_MAX_TIMEOUT_MS,
+            min_memory_mb: DEFAULT_MIN_MEMORY_MB,
+            max_memory_mb: DEFAULT_MAX_MEMORY_MB,
+            max_cpu_cores: DEFAULT_MAX_CPU_CORES,
+        }
+    }
+}
+
+/// One field of a refused request, and what is wrong with it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// The parts of an execute request [`RequestBounds::check`] looks at
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExecuteFields<'a> {
+    /// `None` when the command comes from elsewhere, like a fork's branches
+    pub command: Option<&'a str>,
+    pub image: Option<&'a str>,
+    pub user: Option<&'a str>,
+    pub mode: Option<&'a str>,
+    pub timeout_ms: Option<u64>,
+    pub memory_mb: Option<u32>,
+    pub cpu_cores: Option<u8>,
+}
+
+impl RequestBounds {
+    /// `FAAS_MIN_TIMEOUT_MS` (100), `FAAS_MAX_TIMEOUT_MS` (an hour), `FAAS_MIN_MEMORY_MB`
+    /// (16), `FAAS_MAX_MEMORY_MB` (64 GiB) and `FAAS_MAX_CPU_CORES` (64)
+    pub fn from_env() -> Self {
+        fn read<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+        Self {
+            min_timeout_ms: read("FAAS_MIN_TIMEOUT_MS", DEFAULT_MIN_TIMEOUT_MS),
+            max_timeout_ms: read("FAAS_MAX_TIMEOUT_MS", DEFAULT_MAX_TIMEOUT_MS),
+            min_memory_mb: read("FAAS_MIN_MEMORY_MB", DEFAULT_MIN_MEMORY_MB),
+            max_memory_mb: read("FAAS_MAX_MEMORY_MB", DEFAULT_MAX_MEMORY_MB),
+            max_cpu_cores: read("FAAS_MAX_CPU_CORES", DEFAULT_MAX_CPU_CORES),
+        }
+    }
+
+    /// The request's mode, or every field that is out of shape
+    pub fn check(&self, fields: &ExecuteFields) -> Result<Mode, ApiError> {
+        let mut errors = Vec::new();
+        if fields
+            .command
+            .is_some_and(|command| command.trim().is_empty())
+        {
+            errors.push(FieldError {
+                field: "command",
+                message: "command must not be empty".to_string(),
+            });
+        }
+        if let Some(image) = fields.image {
+            if !image_reference().is_match(image) {
+                errors.push(FieldError {
+                    field: "image",
+                    message: format!(
+                        "image {image:?} is not an image reference like alpine:3.19 or ghcr.io/org/app@sha256:<digest>"
+                    ),
+                });
+            }
+        }
+        if let Some(user) = fields.user {
+            if !is_user(user) {
+                errors.push(FieldError {
+                    field: "user",
+                    message: format!("user {user:?} is not a uid[:gid] or a user name"),
+                });
+            }
+        }
+        let mode = parse_mode(fields.mode).map_err(|e| errors.push(e)).ok();
+        errors.extend(out_of_range(
+            "timeout_ms",
+            fields.timeout_ms,
+            self.min_timeout_ms,
+            self.max_timeout_ms,
+        ));
+        errors.extend(out_of_range(
+            "memory_mb",
+            fields.memory_mb,
+            self.min_memory_mb,
+            self.max_memory_mb,
+        ));
+        errors.extend(out_of_range(
+            "cpu_cores",
+            fields.cpu_cores,
+            1,
+            self.max_cpu_cores,
+        ));
+        match mode {
+            Some(mode) if errors.is_empty() => Ok(mode),
+            _ => Err(rejection(errors)),
+        }
+    }
+}
+
+/// A name or id, optionally with a group after a colon, the way Docker takes `--user`
+fn is_user(user: &str) -> bool {
+    let valid = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    };
+    match user.split_once(':') {
+        Some((name, group)) => valid(name) && valid(group),
+        None => valid(user),
+    }
+}
+
+fn out_of_range<T: PartialOrd + std::fmt::Display>(
+    field: &'static str,
+    value: Option<T>,
+    min: T,
+    max: T,
+) -> Option<FieldError> {
+    let value = value?;
+    (value < min || value > max).then(|| FieldError {
+        field,
+        message: format!("{field} must be between {min} and {max}, not {value}"),
+    })
+}
+
+/// The executor mode `mode` names; no mode is ephemeral
+pub fn parse_mode(mode: Option<&str>) -> Result<Mode, FieldError> {
+    Ok(match mode.unwrap_or("ephemeral") {
+        "ephemeral" => Mode::Ephemeral,
+        "cached" => Mode::Cached,
+        "checkpointed" => Mode::Checkpointed,
+        "branched" => Mode::Branched,
+        "persistent" => Mode::Persistent,
+        other => {
+            return Err(FieldError {
+                field: "mode",
+                message: format!(
+                    "unknown mode {other:?}; expected ephemeral, cached, checkpointed, branched or persistent"
+                ),
+            })
+        }
+    })
+}
+
+/// The 422 for `errors`, which must not be empty
+pub fn rejection(errors: Vec<FieldError>) -> ApiError {
+    let message = errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let field = errors.first().map(|e| e.field);
+    ApiError::invalid_request(message)
+        .with_details(serde_json::json!({ "field": field, "errors": errors }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn valid() -> ExecuteFields<'static> {
+        ExecuteFields {
+            command: Some("echo hi"),
+            image: Some("alpine:latest"),
+            user: Some("1000:1000"),
+            mode: Some("cached"),
+            timeout_ms: Some(30_000),
+            memory_mb: Some(512),
+            cpu_cores: Some(2),
+        }
+    }
+
+    fn refused(fields: ExecuteFields) -> (String, serde_json::Value) {
+        let api = RequestBounds::default().check(&fields).unwrap_err();
+        assert_eq!(
+            (api.status, api.body.code.as_str()),
+            (StatusCode::UNPROCESSABLE_ENTITY, "InvalidRequest")
+        );
+        (api.body.message, api.body.details.unwrap())
+    }
+
+    #[test]
+    fn a_request_in_bounds_gets_its_mode() {
+        let bounds = RequestBounds::default();
+        assert!(matches!(bounds.check(&valid()), Ok(Mode::Cached)));
+        assert!(matches!(
+            bounds.check(&ExecuteFields {
+                command: Some("true"),
+                ..Default::default()
+            }),
+            Ok(Mode::Ephemeral)
+        ));
+        // A fork's command is in its branches
+        assert!(bounds.check(&ExecuteFields::default()).is_ok());
+    }
+
+    #[test]
+    fn an_empty_command_is_refused() {
+        let (message, details) = refused(ExecuteFields {
+            command: Some("  "),
+            ..valid()
+        });
+        assert_eq!(message, "command must not be empty");
+        assert_eq!(details["field"], "command");
+    }
+
+    #[test]
+    fn images_must_be_references() {
+        let bounds = RequestBounds::default();
+        for image in [
+            "alpine",
+            "python:3.11-slim",
+            "pytorch/pytorch:latest",
+            "ghcr.io/tangle-network/faas-runtime:v1.2",
+            "localhost:5000/app",
+            "alpine@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        ] {
+            let fields = ExecuteFields {
+                image: Some(image),
+                ..valid()
+            };
+            assert!(bounds.check(&fields).is_ok(), "{image} was refused");
+        }
+        for image in ["", "Alpine", "alpine latest", "alpine:", "-app", "app:a|b"] {
+            let (_, details) = refused(ExecuteFields {
+                image: Some(image),
+                ..valid()
+            });
+            assert_eq!(details["field"], "image", "{image:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn users_are_ids_or_names() {
+        let bounds = RequestBounds::default();
+        for user in ["1000", "1000:1000", "nobody", "app-user:staff"] {
+            let fields = ExecuteFields {
+                user: Some(user),
+                ..valid()
+            };
+            assert!(bounds.check(&fields).is_ok(), "{user} was refused");
+        }
+        for user in ["", "1000:", ":1000", "a b", "a:b:c", "root;id"] {
+            let (_, details) = refused(ExecuteFields {
+                user: Some(user),
+                ..valid()
+            });
+            assert_eq!(details["field"], "user", "{user:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn an_unknown_mode_lists_the_modes() {
+        let (message, details) = refused(ExecuteFields {
+            mode: Some("warp"),
+            ..valid()
+        });
+        assert_eq!(
+            message,
+            "unknown mode \"warp\"; expected ephemeral, cached, checkpointed, branched or persistent"
+        );
+        assert_eq!(details["field"], "mode");
+    }
+
+    #[test]
+    fn resources_must_be_in_bounds() {
+        for (fields, field) in [
+            (
+                ExecuteFields {
+                    timeout_ms: Some(50),
+                    ..valid()
+                },
+                "timeout_ms",
+            ),
+            (
+                ExecuteFields {
+                    timeout_ms: Some(3_600_001),
+                    ..valid()
+                },
+                "timeout_ms",
+            ),
+            (
+                ExecuteFields {
+                    memory_mb: Some(8),
+                    ..valid()
+                },
+                "memory_mb",
+            ),
+            (
+                ExecuteFields {
+                    memory_mb: Some(1 << 20),
+                    ..valid()
+                },
+                "memory_mb",
+            ),
+            (
+                ExecuteFields {
+                    cpu_cores: Some(0),
+                    ..valid()
+                },
+                "cpu_cores",
+            ),
+            (
+                ExecuteFields {
+                    cpu_cores: Some(65),
+                    ..valid()
+                },
+                "cpu_cores",
+            ),
+        ] {
+            let (_, details) = refused(fields);
+            assert_eq!(details["field"], field);
+        }
+        let (message, _) = refused(ExecuteFields {
+            timeout_ms: Some(50),
+            ..valid()
+        });
+        assert_eq!(
+            message,
+            "timeout_ms must be between 100 and 3600000, not 50"
+        );
+
+        let tight = RequestBounds {
+            max_timeout_ms: 1_000,
+            ..Default::default()
+        };
+        assert!(tight.check(&valid()).is_err());
+    }
+
+    #[test]
+    fn every_bad_field_is_reported() {
+        let (message, details) = refused(ExecuteFields {
+            command: Some(""),
+            mode: Some("warp"),
+            cpu_cores: Some(0),
+            ..valid()
+        });
+        assert_eq!(details["field"], "command");
+        let fields: Vec<&str> = details["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["command", "mode", "cpu_cores"]);
+        assert!(message.starts_with("command must not be empty; unknown mode \"warp\""));
+        assert!(message.ends_with("; cpu_cores must be between 1 and 64, not 0"));
+    }
+}
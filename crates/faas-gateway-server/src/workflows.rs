@@ -0,0 +1,230 @@
+//! Workflow submission
+//!
+//! `POST /api/v1/workflows` takes a whole workflow as JSON, or as YAML with a YAML content
+//! type, validates it, and runs its steps in dependency order, independent ones
+//! concurrently. The response is the [`WorkflowRun`] with every step's output.
+//!
+//! A step with `input_from` gets the other step's stdout on stdin. An output spilled to
+//! disk is bind-mounted read-only at [`SPILLED_INPUT_PATH`] and the step's stdin redirected
+//! from it, so the container reads it the same way.
+//!
+//! Each run has an id, the client's `x-faas-workflow-id` if it sent one, and
+//! `POST /api/v1/workflows/:id/cancel` cancels it in flight: the running steps are stopped,
+//! the rest never start, and all of them name the run as `cancelled_by`.
+
+use crate::cancellation::{CancelError, CancelPolicy, CancelRegistry, CancelReport, CancelScope};
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use faas_common::workflow::{
+    StepCancellation, StepError, StepInput, StepOutput, Workflow, WorkflowError, WorkflowRun,
+    WorkflowStep,
+};
+use std::sync::Arc;
+
+pub const WORKFLOW_ID_HEADER: &str = "x-faas-workflow-id";
+
+/// Where a step's spilled input is mounted in its container
+pub const SPILLED_INPUT_PATH: &str = "/faas/input/stdin";
+
+/// `command` with its stdin redirected from the input mounted at [`SPILLED_INPUT_PATH`]
+pub fn reading_spilled_input(command: &str) -> String {
+    format!("exec <{SPILLED_INPUT_PATH}\n{command}")
+}
+
+/// Executes one workflow step to completion
+#[async_trait]
+pub trait StepRunner: Send + Sync {
+    /// `input` is what the step reads on stdin. `scope` is cancelled if the run is; the
+    /// step should stop and report [`StepError::Cancelled`] when it is
+    async fn run_step(
+        &self,
+        workflow: &str,
+        step: WorkflowStep,
+        input: Option<StepInput>,
+        scope: &CancelScope,
+    ) -> Result<StepOutput, StepError>;
+}
+
+/// What the workflow handlers share
+#[derive(Clone)]
+pub struct Workflows {
+    pub runner: Arc<dyn StepRunner>,
+    pub cancels: Arc<CancelRegistry>,
+}
+
+/// The step-level form of a refused or cancelled scope
+pub fn step_error(error: CancelError) -> StepError {
+    match error {
+        CancelError::Cancelled { cancellation, .. } => StepError::Cancelled(StepCancellation {
+            cancelled_by: cancellation.cancelled_by.unwrap_or(cancellation.source),
+            depth: cancellation.depth,
+        }),
+        other => StepError::Failed(other.to_string()),
+    }
+}
+
+/// Parse a submitted workflow; YAML when the content type says so, JSON otherwise
+pub fn parse_workflow(headers: &HeaderMap, body: &[u8]) -> Result<Workflow, WorkflowError> {
+    let text = std::str::from_utf8(body)
+        .map_err(|_| WorkflowError::Parse("workflow body is not UTF-8".to_string()))?;
+    let is_yaml = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("yaml"));
+    if is_yaml {
+        Workflow::from_yaml(text)
+    } else {
+        Workflow::from_json(text)
+    }
+}
+
+/// Run `workflow` as run `id`. Each step registers under the run just before it starts,
+/// so once the run is cancelled no further step is admitted.
+pub async fn run_workflow(
+    workflows: &Workflows,
+    id: &str,
+    workflow: &Workflow,
+) -> Result<WorkflowRun, CancelError> {
+    let scope = workflows.cancels.register(id, None)?;
+    scope.start()?;
+    let name = workflow.name();
+    let mut run = workflow
+        .run(|step, input| async move {
+            let step_scope = workflows
+                .cancels
+                .register(&format!("{id}/{}", step.name), Some(id))
+                .map_err(step_error)?;
+            step_scope.start().map_err(step_error)?;
+            workflows
+                .runner
+                .run_step(name, step, input, &step_scope)
+                .await
+        })
+        .await;
+    run.id = Some(id.to_string());
+    Ok(run)
+}
+
+pub async fn submit_workflow_handler(
+    State(workflows): State<Workflows>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<WorkflowRun>, Response> {
+    let workflow = parse_workflow(&headers, &body).map_err(|e| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        )
+            .into_response()
+    })?;
+    let id = headers
+        .get(WORKFLOW_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string);
+    run_workflow(&workflows, &id, &workflow)
+        .await
+        .map(Json)
+        .map_err(IntoResponse::into_response)
+}
+
+/// `POST /api/v1/workflows/:id/cancel`
+pub async fn cancel_workflow_handler(
+    State(cancels): State<Arc<CancelRegistry>>,
+    Path(id): Path<String>,
+) -> Result<Json<CancelReport>, CancelError> {
+    cancels.cancel(&id, CancelPolicy::All).map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use faas_common::workflow::StepStatus;
+    use std::sync::Mutex;
+    use tokio::sync::Notify;
+
+    const THREE_STEPS: &str = "\
+name: build
+steps:
+- name: one
+  image: alpine:latest
+  command: sleep 60
+- name: two
+  image: alpine:latest
+  command: 'true'
+  depends_on: [one]
+- name: three
+  image: alpine:latest
+  command: 'true'
+  depends_on: [two]
+";
+
+    /// Blocks in every step until it is cancelled, recording which steps started
+    #[derive(Default)]
+    struct BlockingRunner {
+        started: Mutex<Vec<String>>,
+        running: Notify,
+    }
+
+    #[async_trait]
+    impl StepRunner for BlockingRunner {
+        async fn run_step(
+            &self,
+            _workflow: &str,
+            step: WorkflowStep,
+            _input: Option<StepInput>,
+            scope: &CancelScope,
+        ) -> Result<StepOutput, StepError> {
+            self.started.lock().unwrap().push(step.name);
+            self.running.notify_one();
+            Err(step_error(CancelError::Cancelled {
+                id: scope.id().to_string(),
+                cancellation: scope.cancelled().await,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelling_during_the_first_step_never_starts_the_rest() {
+        let runner = Arc::new(BlockingRunner::default());
+        let workflows = Workflows {
+            runner: runner.clone(),
+            cancels: Arc::new(CancelRegistry::new()),
+        };
+        let workflow = Workflow::from_yaml(THREE_STEPS).unwrap();
+        let run = tokio::spawn({
+            let workflows = workflows.clone();
+            async move { run_workflow(&workflows, "run-1", &workflow).await }
+        });
+
+        runner.running.notified().await;
+        let report = workflows
+            .cancels
+            .cancel("run-1", CancelPolicy::All)
+            .unwrap();
+        assert_eq!(report.cancelled[0].id, "run-1/one");
+
+        let run = run.await.unwrap().unwrap();
+        assert!(!run.succeeded);
+        assert_eq!(run.id.as_deref(), Some("run-1"));
+        assert_eq!(*runner.started.lock().unwrap(), ["one"]);
+        for step in &run.steps {
+            assert_eq!(step.status, StepStatus::Cancelled, "{}", step.name);
+            let cancelled = step.cancelled.as_ref().unwrap();
+            assert_eq!(cancelled.cancelled_by, "run-1");
+            assert_eq!(cancelled.depth, 1);
+        }
+
+        // The cancelled run's id stays taken for a while
+        let workflow = Workflow::from_yaml(THREE_STEPS).unwrap();
+        assert!(matches!(
+            run_workflow(&workflows, "run-1", &workflow).await,
+            Err(CancelError::Cancelled { .. })
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+//! Reclaiming containers a crashed gateway left behind, in real Docker.
+
+use dashmap::DashMap;
+use faas_executor::bollard::container::{Config, CreateContainerOptions};
+use faas_executor::bollard::Docker;
+use faas_executor::container_labels::{self, ContainerKind};
+use faas_executor::platform::{InstanceContainers, InstanceOptions};
+use faas_executor::test_utils;
+use faas_gateway_server::lifecycle::InstanceState;
+use faas_gateway_server::orphans::Orphans;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn a_restart_removes_stale_runs_and_adopts_instances() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let docker = Arc::new(Docker::connect_with_local_defaults().unwrap());
+
+    // A run the crashed gateway never cleaned up, created the way the executor does
+    let run_id = Uuid::new_v4().to_string();
+    let stale = docker
+        .create_container(
+            Some(CreateContainerOptions {
+                name: format!("faas-crashed-{run_id}"),
+                ..Default::default()
+            }),
+            Config {
+                image: Some("alpine:latest".to_string()),
+                cmd: Some(vec!["sleep".to_string(), "300".to_string()]),
+                labels: Some(container_labels::labels(
+                    ContainerKind::Ephemeral,
+                    &run_id,
+                    "crashed",
+                )),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("container created");
+    docker
+        .start_container::<String>(&stale.id, None)
+        .await
+        .unwrap();
+
+    let instance_id = Uuid::new_v4().to_string();
+    let containers = InstanceContainers::new(docker.clone());
+    let dev_env = containers
+        .start_with(
+            &instance_id,
+            "alpine:latest",
+            &InstanceOptions {
+                name: Some("dev".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("instance container starts");
+
+    // The gateway boots after both were created
+    let booted = chrono::Utc::now().timestamp() + 1;
+    let instances = DashMap::new();
+    let report = Orphans::new(docker.clone(), booted)
+        .reclaim(&instances)
+        .await;
+
+    assert!(report.error.is_none(), "{:?}", report.error);
+    assert!(report.removed.iter().any(|c| c.container_id == stale.id));
+    assert!(docker.inspect_container(&stale.id, None).await.is_err());
+
+    assert!(report.adopted.contains(&instance_id));
+    let instance = instances.get(&instance_id).expect("instance re-registered");
+    assert_eq!(instance.lifecycle.current(), InstanceState::Running);
+    assert_eq!(instance.container_id.as_deref(), Some(dev_env.as_str()));
+    assert_eq!(instance.name.as_deref(), Some("dev"));
+    drop(instance);
+
+    containers.remove(&dev_env).await.unwrap();
+}
@@ -0,0 +1,77 @@
+//! Shutting down under a running execution, in real Docker.
+
+use faas_executor::bollard::container::ListContainersOptions;
+use faas_executor::platform::executor::{Executor, Mode, Request};
+use faas_executor::platform::PlatformConfig;
+use faas_executor::test_utils;
+use faas_gateway_server::shutdown::{self, ShutdownPolicy};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn a_long_execution_is_cancelled_and_its_container_removed() {
+    if !test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    std::env::set_var("FAAS_DISABLE_PREWARM", "1");
+    let executor = Arc::new(
+        Executor::new(PlatformConfig::default())
+            .await
+            .expect("executor"),
+    );
+
+    let id = "shutdown-sleeper".to_string();
+    let run = tokio::spawn({
+        let executor = executor.clone();
+        let id = id.clone();
+        async move {
+            executor
+                .run(Request {
+                    id,
+                    code: "sleep 10".to_string(),
+                    mode: Mode::Ephemeral,
+                    env: "alpine:latest".to_string(),
+                    timeout: Duration::from_secs(60),
+                    ..Default::default()
+                })
+                .await
+        }
+    });
+    // Let the container start before the signal arrives
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    let started = Instant::now();
+    let report = shutdown::drain_executions(
+        &executor,
+        ShutdownPolicy {
+            drain_timeout: Duration::from_secs(1),
+        },
+    )
+    .await;
+    assert!(report.settled, "executions were still running");
+    assert_eq!(report.cancelled, vec![id.clone()]);
+    assert!(
+        started.elapsed() < Duration::from_secs(8),
+        "shutdown waited for the execution to finish"
+    );
+
+    // The run ends, by its cancellation, rather than hanging
+    let outcome = tokio::time::timeout(Duration::from_secs(5), run)
+        .await
+        .expect("run did not end")
+        .unwrap();
+    assert!(outcome.is_err(), "a cancelled run reported success");
+
+    let leftover = executor
+        .container_pool()
+        .docker()
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            filters: [("name".to_string(), vec![format!("faas-{id}-")])].into(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap();
+    assert!(leftover.is_empty(), "containers left behind: {leftover:?}");
+}
@@ -35,7 +35,9 @@ pub struct LocalBackend {
 
 impl LocalBackend {
     pub async fn new(base_url: String) -> Result<Self> {
-        let executor = PlatformExecutor::new()
+        let config = faas_executor::platform::PlatformConfig::from_env()
+            .map_err(|e| BackendError::DeploymentFailed(e.to_string()))?;
+        let executor = PlatformExecutor::new(config)
             .await
             .map_err(|e| BackendError::DeploymentFailed(e.to_string()))?;
 
@@ -219,6 +221,7 @@ impl ExecutionBackend for LocalBackend {
             branch_from: None,
             runtime: Some(faas_common::Runtime::Auto), // Auto-select Docker or Firecracker
             env_vars: None,
+            ..Default::default()
         };
 
         // Execute
@@ -1,6 +1,6 @@
 // API types for FaaS gateway
 
-use faas_common::InvocationResult;
+use faas_common::{EnvVar, InvocationResult};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -21,7 +21,7 @@ pub enum ApiError {
 pub struct InvokeRequest {
     pub image: String,
     pub command: Vec<String>,
-    pub env_vars: Option<Vec<String>>,
+    pub env_vars: Option<Vec<EnvVar>>,
     pub payload: Option<String>,
 }
 
@@ -39,14 +39,20 @@ pub struct InvokeResponse {
 
 impl From<InvocationResult> for InvokeResponse {
     fn from(result: InvocationResult) -> Self {
+        let text = |bytes: Option<Vec<u8>>| {
+            bytes
+                .map(|b| String::from_utf8_lossy(&b).into_owned())
+                .unwrap_or_default()
+        };
+        let exit_code = result.exit_status();
         Self {
             request_id: result.request_id,
+            stdout: text(result.stdout.or_else(|| result.response.clone())),
+            stderr: text(result.stderr),
             output: result.response.and_then(|b| String::from_utf8(b).ok()),
             logs: result.logs,
             error: result.error,
-            exit_code: 0,
-            stdout: String::new(),
-            stderr: String::new(),
+            exit_code,
             duration_ms: 0,
         }
     }
@@ -57,7 +63,7 @@ impl From<InvocationResult> for InvokeResponse {
 pub struct ExecuteRequest {
     pub command: String,
     pub image: Option<String>,
-    pub env_vars: Option<Vec<(String, String)>>,
+    pub env_vars: Option<Vec<EnvVar>>,
     pub working_dir: Option<String>,
     pub timeout_ms: Option<u64>,
 }
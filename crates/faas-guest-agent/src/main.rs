@@ -1,19 +1,26 @@
-use faas_common::{InvocationResult, SandboxConfig};
-use serde_json;
-use std::net::Shutdown;
+use faas_common::frame::{self, FrameError};
+use faas_common::{InvocationResult, SandboxConfig, GUEST_AGENT_PORT};
 use std::process::Stdio;
 use thiserror::Error;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{UnixListener, UnixStream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::process::Command;
 use tokio::time::Duration;
 #[cfg(target_os = "linux")]
-use tokio_vsock::{VsockListener, VsockStream};
+use tokio_vsock::VsockListener;
 use tracing::{error, info};
-use tracing_subscriber;
 
-const GUEST_CID: u32 = 3;
-const GUEST_SERVICE_PORT: u32 = 1234;
+/// CID to bind when the host didn't pass one on the kernel command line
+const DEFAULT_GUEST_CID: u32 = 3;
+
+/// How long output pipes get to drain once a timed-out command's group is killed; a process
+/// that left the group may hold them open forever
+#[cfg(target_os = "linux")]
+const KILLED_OUTPUT_GRACE: Duration = Duration::from_secs(2);
+
+/// Children spawned for invocations, which tokio reaps; any other child that exits is an
+/// orphan the agent inherited as subreaper
+#[cfg(target_os = "linux")]
+static INVOCATION_PIDS: std::sync::Mutex<Vec<libc::pid_t>> = std::sync::Mutex::new(Vec::new());
 
 #[derive(Error, Debug)]
 enum AgentError {
@@ -23,6 +30,8 @@ enum AgentError {
     VsockIo(#[from] std::io::Error),
     #[error("Serialization Error: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Protocol Error: {0}")]
+    Frame(#[from] FrameError),
     #[error("Command Execution Error: {0}")]
     CommandExec(String),
     #[error("Failed to capture stdio: {0}")]
@@ -39,15 +48,278 @@ fn map_join_error<T>(
 }
 
 #[cfg(target_os = "linux")]
-async fn handle_connection(mut stream: VsockStream) -> Result<(), AgentError> {
+fn rlimit_resource(name: &str) -> Option<libc::c_int> {
+    let resource = match name {
+        "nofile" => libc::RLIMIT_NOFILE,
+        "nproc" => libc::RLIMIT_NPROC,
+        "fsize" => libc::RLIMIT_FSIZE,
+        "core" => libc::RLIMIT_CORE,
+        "stack" => libc::RLIMIT_STACK,
+        "memlock" => libc::RLIMIT_MEMLOCK,
+        _ => return None,
+    };
+    Some(resource as libc::c_int)
+}
+
+/// Install `setrlimit` calls that run in the child between fork and exec.
+#[cfg(target_os = "linux")]
+fn apply_ulimits(command: &mut Command, ulimits: &[faas_common::Ulimit]) -> Result<(), AgentError> {
+    let limits = ulimits
+        .iter()
+        .map(|u| {
+            let resource = rlimit_resource(&u.name).ok_or_else(|| {
+                AgentError::CommandExec(format!("Unsupported ulimit: {}", u.name))
+            })?;
+            let limit = libc::rlimit {
+                rlim_cur: u.soft as libc::rlim_t,
+                rlim_max: u.hard as libc::rlim_t,
+            };
+            Ok((resource, limit))
+        })
+        .collect::<Result<Vec<_>, AgentError>>()?;
+
+    // SAFETY: the closure only calls the async-signal-safe setrlimit(2).
+    unsafe {
+        command.pre_exec(move || {
+            for (resource, limit) in &limits {
+                if libc::setrlimit(*resource as _, limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+/// Start the command in a session and process group of its own, so a timeout kills
+/// everything it started
+#[cfg(target_os = "linux")]
+fn own_process_group(command: &mut Command) {
+    // SAFETY: the closure only calls the async-signal-safe setsid(2).
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Wait for `child`, or once `timeout` passes kill its whole process group and reap it.
+/// `None` means it was killed.
+#[cfg(target_os = "linux")]
+async fn wait_or_kill(
+    child: &mut tokio::process::Child,
+    timeout: Option<Duration>,
+) -> std::io::Result<Option<std::process::ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return child.wait().await.map(Some);
+    };
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status.map(Some),
+        Err(_) => {
+            if let Some(pid) = child.id() {
+                // SAFETY: kill(2) with a negated pid signals the group the child leads
+                unsafe {
+                    libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+                }
+            }
+            // In case it never got its own group; reaps it either way
+            child.kill().await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Reap exited children the agent didn't spawn: as subreaper it inherits whatever the
+/// commands orphan. Children of invocations are left for tokio, which waits on them by pid.
+#[cfg(target_os = "linux")]
+fn reap_orphans() -> usize {
+    let mut reaped = 0;
+    loop {
+        let pids = INVOCATION_PIDS.lock().unwrap();
+        // SAFETY: siginfo_t is plain data, and WNOWAIT leaves the child to be reaped below
+        let pid = unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            let flags = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+            if libc::waitid(libc::P_ALL, 0, &mut info, flags) != 0 {
+                break;
+            }
+            info.si_pid()
+        };
+        // Nothing exited, or the first to have is an invocation's; the next pass after
+        // tokio reaps it gets the rest
+        if pid == 0 || pids.contains(&pid) {
+            break;
+        }
+        // SAFETY: pid has exited and isn't waited on anywhere else
+        unsafe {
+            libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG);
+        }
+        reaped += 1;
+    }
+    reaped
+}
+
+/// Become the subreaper for everything the agent starts and reap orphans as they exit
+#[cfg(target_os = "linux")]
+fn spawn_reaper() {
+    // SAFETY: PR_SET_CHILD_SUBREAPER takes a plain flag
+    if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } != 0 {
+        error!(error = %std::io::Error::last_os_error(), "Failed to become child subreaper");
+    }
+    let mut sigchld =
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::child()) {
+            Ok(signal) => signal,
+            Err(e) => {
+                error!(error = %e, "Failed to watch SIGCHLD; orphans won't be reaped");
+                return;
+            }
+        };
+    tokio::spawn(async move {
+        while sigchld.recv().await.is_some() {
+            let reaped = reap_orphans();
+            if reaped > 0 {
+                info!(reaped, "Reaped orphaned processes");
+            }
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn mount_tmpfs(target: &str, size_mb: u64) -> Result<(), AgentError> {
+    use std::ffi::CString;
+
+    std::fs::create_dir_all(target)?;
+    let source = CString::new("tmpfs").unwrap();
+    let target_c = CString::new(target)
+        .map_err(|_| AgentError::CommandExec(format!("Invalid tmpfs path: {target}")))?;
+    let options = CString::new(format!("size={size_mb}m")).unwrap();
+    // SAFETY: all pointers reference live, NUL-terminated strings.
+    let rc = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target_c.as_ptr(),
+            source.as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            options.as_ptr() as *const libc::c_void,
+        )
+    };
+    if rc != 0 {
+        return Err(AgentError::CommandExec(format!(
+            "Failed to mount tmpfs at {target}: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+    Ok(())
+}
+
+/// The tmpfs mounts made for one invocation, unmounted when dropped so the next invocation
+/// neither sees what this one left in them nor mounts over them again
+#[cfg(target_os = "linux")]
+struct SandboxMounts(Vec<String>);
+
+#[cfg(target_os = "linux")]
+impl Drop for SandboxMounts {
+    fn drop(&mut self) {
+        use std::ffi::CString;
+
+        for target in self.0.iter().rev() {
+            let Ok(target_c) = CString::new(target.as_str()) else {
+                continue;
+            };
+            // Detached, so a process the command left behind can't keep it mounted
+            // SAFETY: the pointer references a live, NUL-terminated string.
+            let rc = unsafe { libc::umount2(target_c.as_ptr(), libc::MNT_DETACH) };
+            if rc != 0 {
+                error!(
+                    target = %target,
+                    error = %std::io::Error::last_os_error(),
+                    "Failed to unmount tmpfs"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn mount_sandbox_tmpfs(
+    shm_size_mb: Option<u64>,
+    tmpfs: &[faas_common::TmpfsMount],
+) -> Result<SandboxMounts, AgentError> {
+    // Whatever was mounted before a failure is unmounted with the guard
+    let mut mounts = SandboxMounts(Vec::new());
+    if let Some(size_mb) = shm_size_mb {
+        mount_tmpfs("/dev/shm", size_mb)?;
+        mounts.0.push("/dev/shm".to_string());
+    }
+    for mount in tmpfs {
+        mount_tmpfs(&mount.path, mount.size_mb)?;
+        mounts.0.push(mount.path.clone());
+    }
+    Ok(mounts)
+}
+
+/// An invocation that failed before or instead of producing output
+#[cfg(target_os = "linux")]
+fn error_result(request_id: String, error: String) -> InvocationResult {
+    InvocationResult {
+        request_id,
+        response: None,
+        logs: None,
+        error: Some(error),
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        usage: None,
+        truncation: None,
+    }
+}
+
+/// Serve invocations on one connection, answering each config frame with a result frame,
+/// until the host closes it
+#[cfg(target_os = "linux")]
+async fn handle_connection<S>(mut stream: S) -> Result<(), AgentError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     info!("Accepted vsock connection");
 
-    // 1. Read SandboxConfig
-    let mut buffer = Vec::new();
-    stream.read_to_end(&mut buffer).await?;
-    let config: SandboxConfig = serde_json::from_slice(&buffer)?;
-    info!(config=?config, "Received sandbox config");
+    loop {
+        // 1. Read SandboxConfig
+        let config: SandboxConfig = match frame::read_json(&mut stream).await {
+            Ok(Some(config)) => config,
+            Ok(None) => break,
+            Err(e @ FrameError::VersionMismatch { .. }) => {
+                // Answer in our framing, so a framed host can say what's wrong
+                let result = error_result(String::new(), e.to_string());
+                let _ = frame::write_json(&mut stream, &result).await;
+                return Err(e.into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        info!(config=?config, "Received sandbox config");
 
+        let request_id = config.function_id.clone();
+        let result = invoke(config)
+            .await
+            .unwrap_or_else(|e| error_result(request_id, e.to_string()));
+
+        // 4. Send result back
+        info!(result=?result, "Sending invocation result...");
+        frame::write_json(&mut stream, &result).await?;
+    }
+    // The host may be gone already; there's nothing left to tell it either way
+    let _ = stream.shutdown().await;
+
+    info!("Finished handling connection.");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn invoke(config: SandboxConfig) -> Result<InvocationResult, AgentError> {
     let result: InvocationResult;
 
     if config.command.is_empty() {
@@ -58,29 +330,60 @@ async fn handle_connection(mut stream: VsockStream) -> Result<(), AgentError> {
             response: Some(config.payload), // Echo the payload
             logs: Some("Executed in echo mode.".to_string()),
             error: None,
+            stdout: None,
+            stderr: None,
+            exit_code: None,
+            usage: None,
+            truncation: None,
         };
     } else {
         // 2. Execute command
         info!(command=?config.command, "Executing command...");
         let mut command = Command::new(&config.command[0]);
         command.args(&config.command[1..]);
-        command.envs(
-            config
-                .env_vars
-                .unwrap_or_default()
-                .into_iter()
-                .filter_map(|s| {
-                    s.split_once('=')
-                        .map(|(k, v)| (k.to_string(), v.to_string()))
-                }),
-        );
+        let env_vars = faas_common::env::dedupe_vars(&config.env_vars.unwrap_or_default());
+        command.envs(env_vars.into_iter().map(|var| (var.key, var.value)));
+        if let Some(overrides) = &config.environment_overrides {
+            if overrides.fake_time.is_some()
+                && !std::path::Path::new(faas_common::GUEST_FAKETIME_LIBRARY).exists()
+            {
+                return Err(AgentError::CommandExec(format!(
+                    "fake_time needs {} in the rootfs",
+                    faas_common::GUEST_FAKETIME_LIBRARY
+                )));
+            }
+            let mut vars = overrides.env_vars();
+            vars.extend(overrides.faketime_env(faas_common::GUEST_FAKETIME_LIBRARY));
+            command.envs(vars.into_iter().map(|var| (var.key, var.value)));
+        }
+        if let Some(dir) = &config.working_dir {
+            std::fs::create_dir_all(dir)?;
+            command.current_dir(dir);
+        }
         command.stdin(Stdio::piped());
         command.stdout(Stdio::piped());
         command.stderr(Stdio::piped());
 
-        let mut child = command
-            .spawn()
-            .map_err(|e| AgentError::CommandExec(format!("Failed to spawn command: {}", e)))?;
+        // Held until the command's output is in, then unmounted
+        let _mounts = mount_sandbox_tmpfs(
+            config.shm_size_mb,
+            config.tmpfs.as_deref().unwrap_or_default(),
+        )?;
+        if let Some(ulimits) = &config.ulimits {
+            apply_ulimits(&mut command, ulimits)?;
+        }
+        own_process_group(&mut command);
+
+        // Held across spawn, so the reaper can't take the child before its pid is listed
+        let mut child = {
+            let mut pids = INVOCATION_PIDS.lock().unwrap();
+            let child = command
+                .spawn()
+                .map_err(|e| AgentError::CommandExec(format!("Failed to spawn command: {}", e)))?;
+            pids.extend(child.id().map(|pid| pid as libc::pid_t));
+            child
+        };
+        let pid = child.id().map(|pid| pid as libc::pid_t);
 
         let stdin_opt = child.stdin.take();
         let stdout_opt = child.stdout.take();
@@ -118,13 +421,29 @@ async fn handle_connection(mut stream: VsockStream) -> Result<(), AgentError> {
             }
         });
 
-        // Wait for process completion and stdio tasks
-        let (status_res, stdin_res, stdout_res, stderr_res) =
-            tokio::join!(child.wait(), stdin_handle, stdout_handle, stderr_handle);
-
+        // Wait for process completion, killing it past the sandbox timeout
+        let timeout = config.timeout.map(Duration::from_millis);
+        let status_res = wait_or_kill(&mut child, timeout).await;
+        INVOCATION_PIDS
+            .lock()
+            .unwrap()
+            .retain(|listed| Some(*listed) != pid);
         let status = status_res
             .map_err(|e| AgentError::CommandExec(format!("Command wait failed: {}", e)))?;
-        info!(exit_code=?status.code(), "Command finished");
+        match status {
+            Some(status) => info!(exit_code=?status.code(), "Command finished"),
+            None => info!(timeout_ms=?config.timeout, "Command timed out and was killed"),
+        }
+
+        // Then for the stdio tasks; after a kill, whatever they read in time
+        let stdio = async { tokio::join!(stdin_handle, stdout_handle, stderr_handle) };
+        let (stdin_res, stdout_res, stderr_res) = match status {
+            Some(_) => stdio.await,
+            None => match tokio::time::timeout(KILLED_OUTPUT_GRACE, stdio).await {
+                Ok(stdio) => stdio,
+                Err(_) => (Ok(Ok(())), Ok(Ok(Vec::new())), Ok(Ok(Vec::new()))),
+            },
+        };
 
         // Check task results
         if let Err(e) = map_join_error(stdin_res, "Stdin")? {
@@ -145,29 +464,32 @@ async fn handle_connection(mut stream: VsockStream) -> Result<(), AgentError> {
         // 3. Construct InvocationResult
         result = InvocationResult {
             request_id: config.function_id,
-            response: Some(stdout_data),
+            response: Some(stdout_data.clone()),
             logs: Some(logs_string),
-            error: if status.success() {
-                None
-            } else {
+            error: match status {
+                Some(status) if status.success() => None,
                 // Include stderr in error message if process failed
-                Some(format!(
+                Some(status) => Some(format!(
                     "Command failed with status: {}. Stderr: {}",
                     status,
                     String::from_utf8_lossy(&stderr_data)
-                ))
+                )),
+                None => Some(
+                    faas_common::FaasError::Timeout {
+                        timeout_ms: config.timeout.unwrap_or_default(),
+                    }
+                    .to_string(),
+                ),
             },
+            stdout: Some(stdout_data),
+            stderr: Some(stderr_data),
+            exit_code: status.and_then(|status| status.code()).map(i64::from),
+            usage: None,
+            truncation: None,
         };
     }
 
-    // 4. Send result back
-    info!(result=?result, "Sending invocation result...");
-    let result_json = serde_json::to_vec(&result)?;
-    stream.write_all(&result_json).await?;
-    stream.shutdown(Shutdown::Both)?;
-
-    info!("Finished handling connection.");
-    Ok(())
+    Ok(result)
 }
 
 #[cfg(target_os = "linux")]
@@ -175,17 +497,22 @@ async fn handle_connection(mut stream: VsockStream) -> Result<(), AgentError> {
 async fn main() {
     tracing_subscriber::fmt::init();
     info!("Starting FaaS Guest Agent on Vsock...");
+    spawn_reaper();
 
-    let mut listener = match VsockListener::bind(GUEST_CID, GUEST_SERVICE_PORT) {
+    let guest_cid = std::fs::read_to_string("/proc/cmdline")
+        .ok()
+        .and_then(|cmdline| faas_common::vsock_cid_from_cmdline(&cmdline))
+        .unwrap_or(DEFAULT_GUEST_CID);
+    let mut listener = match VsockListener::bind(guest_cid, GUEST_AGENT_PORT) {
         Ok(l) => l,
         Err(e) => {
-            error!(error=%e, cid=GUEST_CID, port=GUEST_SERVICE_PORT, "Failed to bind to vsock");
+            error!(error=%e, cid=guest_cid, port=GUEST_AGENT_PORT, "Failed to bind to vsock");
             return;
         }
     };
     info!(
-        cid = GUEST_CID,
-        port = GUEST_SERVICE_PORT,
+        cid = guest_cid,
+        port = GUEST_AGENT_PORT,
         "Listening on vsock"
     );
 
@@ -212,3 +539,146 @@ fn main() {
     eprintln!("The faas-guest-agent is only supported on Linux (requires vsock)");
     std::process::exit(1);
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use tokio::net::UnixStream;
+
+    fn config(function_id: &str, command: &[&str], payload: &[u8]) -> SandboxConfig {
+        SandboxConfig {
+            function_id: function_id.to_string(),
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            payload: payload.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn invocations_are_answered_in_order_on_one_connection() {
+        let (mut host, agent) = UnixStream::pair().unwrap();
+        let agent = tokio::spawn(handle_connection(agent));
+
+        // Both requests go out before either answer is read
+        frame::write_json(&mut host, &config("echo", &[], b"ping"))
+            .await
+            .unwrap();
+        frame::write_json(&mut host, &config("cat", &["cat"], b"from stdin"))
+            .await
+            .unwrap();
+
+        let echoed: InvocationResult = frame::read_json(&mut host).await.unwrap().unwrap();
+        assert_eq!(echoed.request_id, "echo");
+        assert_eq!(echoed.response.as_deref(), Some(&b"ping"[..]));
+        let cat: InvocationResult = frame::read_json(&mut host).await.unwrap().unwrap();
+        assert_eq!(cat.request_id, "cat");
+        assert_eq!(cat.stdout.as_deref(), Some(&b"from stdin"[..]));
+        assert_eq!(cat.exit_code, Some(0));
+
+        // Closing between frames ends the connection cleanly
+        drop(host);
+        agent.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn failures_are_answered_rather_than_dropped() {
+        let (mut host, agent) = UnixStream::pair().unwrap();
+        tokio::spawn(handle_connection(agent));
+
+        frame::write_json(&mut host, &config("missing", &["/no/such/binary"], b""))
+            .await
+            .unwrap();
+        let result: InvocationResult = frame::read_json(&mut host).await.unwrap().unwrap();
+        assert_eq!(result.request_id, "missing");
+        assert!(result.error.unwrap().contains("Failed to spawn command"));
+
+        // The connection is still good for the next one
+        frame::write_json(&mut host, &config("echo", &[], b"still here"))
+            .await
+            .unwrap();
+        let result: InvocationResult = frame::read_json(&mut host).await.unwrap().unwrap();
+        assert_eq!(result.response.as_deref(), Some(&b"still here"[..]));
+    }
+
+    #[tokio::test]
+    async fn an_unframed_host_is_told_about_the_version() {
+        let (mut host, agent) = UnixStream::pair().unwrap();
+        let agent = tokio::spawn(handle_connection(agent));
+
+        let unframed = serde_json::to_vec(&config("old-host", &[], b"")).unwrap();
+        host.write_all(&unframed).await.unwrap();
+
+        let result: InvocationResult = frame::read_json(&mut host).await.unwrap().unwrap();
+        assert!(result.error.unwrap().contains("protocol"));
+        assert!(matches!(
+            agent.await.unwrap(),
+            Err(AgentError::Frame(FrameError::VersionMismatch { .. }))
+        ));
+    }
+
+    /// Gone, or a zombie waiting on whoever inherited it
+    fn is_dead(pid: u32) -> bool {
+        match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+            Ok(stat) => stat.rsplit(") ").next().is_some_and(|rest| rest.starts_with('Z')),
+            Err(_) => true,
+        }
+    }
+
+    #[tokio::test]
+    async fn a_command_that_finishes_in_time_keeps_its_status() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "exit 3"]);
+        own_process_group(&mut command);
+        let mut child = command.spawn().unwrap();
+
+        let status = wait_or_kill(&mut child, Some(Duration::from_secs(10)))
+            .await
+            .unwrap()
+            .expect("finished before the timeout");
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn a_timeout_kills_the_whole_process_group() {
+        use tokio::io::AsyncBufReadExt;
+
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(Stdio::piped());
+        own_process_group(&mut command);
+        let mut child = command.spawn().unwrap();
+        let mut stdout = tokio::io::BufReader::new(child.stdout.take().unwrap()).lines();
+        let background: u32 = stdout.next_line().await.unwrap().unwrap().parse().unwrap();
+
+        let status = wait_or_kill(&mut child, Some(Duration::from_millis(200)))
+            .await
+            .unwrap();
+        assert!(status.is_none());
+        assert!(child.try_wait().unwrap().is_some(), "the shell was reaped");
+
+        // SIGKILL is asynchronous; the grandchild goes shortly after
+        for _ in 0..50 {
+            if is_dead(background) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("background sleep {background} outlived the timeout");
+    }
+
+    #[tokio::test]
+    async fn a_timed_out_invocation_keeps_its_partial_output() {
+        let script = "echo started; echo oops >&2; sleep 30";
+        let mut config = config("slow", &["sh", "-c", script], b"");
+        config.timeout = Some(300);
+
+        let started = std::time::Instant::now();
+        let result = invoke(config).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(result.error.as_deref(), Some("Execution timed out after 300 ms"));
+        assert_eq!(result.stdout.as_deref(), Some(&b"started\n"[..]));
+        assert_eq!(result.stderr.as_deref(), Some(&b"oops\n"[..]));
+        assert_eq!(result.exit_code, None);
+    }
+}
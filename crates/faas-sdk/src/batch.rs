@@ -0,0 +1,165 @@
+//! Many executions in one round trip
+//!
+//! The gateway runs a batch's items side by side, at most its own cap at once, and answers
+//! every item in input order: one that fails carries the error it would have been answered
+//! with alone, and the rest still run.
+
+use crate::{api_error, failure, ExecuteRequest, ExecuteResponse, FaasClient, SdkError};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+struct BatchRequest<'a> {
+    requests: &'a [ExecuteRequest],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_parallelism: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct BatchItem {
+    index: usize,
+    status: u16,
+    response: Option<ExecuteResponse>,
+    error: Option<ItemError>,
+}
+
+#[derive(Deserialize)]
+struct ItemError {
+    code: String,
+    message: String,
+    details: Option<serde_json::Value>,
+}
+
+impl FaasClient {
+    /// Run every request in one call, as many at once as the gateway allows
+    ///
+    /// Results come back in the order of `requests`, each with the client's runtime and
+    /// cache defaults applied as [`FaasClient::execute`] would. Batches aren't retried; a
+    /// batch the gateway refuses as a whole fails every item with the same error.
+    pub async fn execute_batch(
+        &self,
+        requests: Vec<ExecuteRequest>,
+    ) -> Vec<Result<ExecuteResponse, SdkError>> {
+        self.send_batch(requests, None).await
+    }
+
+    /// [`FaasClient::execute_batch`], running at most `max_parallelism` at once
+    pub async fn execute_batch_with_parallelism(
+        &self,
+        requests: Vec<ExecuteRequest>,
+        max_parallelism: usize,
+    ) -> Vec<Result<ExecuteResponse, SdkError>> {
+        self.send_batch(requests, Some(max_parallelism)).await
+    }
+
+    async fn send_batch(
+        &self,
+        mut requests: Vec<ExecuteRequest>,
+        max_parallelism: Option<usize>,
+    ) -> Vec<Result<ExecuteResponse, SdkError>> {
+        let count = requests.len();
+        if count == 0 {
+            return Vec::new();
+        }
+        for request in &mut requests {
+            self.apply_defaults(request);
+        }
+        match self.post_batch(&mut requests, max_parallelism).await {
+            Ok(items) => self.settle(items, count).await,
+            Err(e) => {
+                let mut metrics = self.metrics.write().await;
+                metrics.total_requests += count as u64;
+                metrics.errors += count as u64;
+                (0..count).map(|_| Err(copy_error(&e))).collect()
+            }
+        }
+    }
+
+    async fn post_batch(
+        &self,
+        requests: &mut [ExecuteRequest],
+        max_parallelism: Option<usize>,
+    ) -> Result<Vec<BatchItem>, SdkError> {
+        for request in requests.iter_mut() {
+            self.reference_payload(request).await?;
+        }
+        let url = format!("{}/api/v1/execute/batch", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&BatchRequest {
+                requests,
+                max_parallelism,
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Put each item in its request's place and count it like a single execution
+    async fn settle(
+        &self,
+        items: Vec<BatchItem>,
+        count: usize,
+    ) -> Vec<Result<ExecuteResponse, SdkError>> {
+        let mut results: Vec<Result<ExecuteResponse, SdkError>> = (0..count)
+            .map(|index| {
+                Err(SdkError::RequestFailed(format!(
+                    "the batch answer has no item {index}"
+                )))
+            })
+            .collect();
+        for item in items {
+            let Some(slot) = results.get_mut(item.index) else {
+                continue;
+            };
+            *slot = match (item.response, item.error) {
+                (Some(response), _) => Ok(response),
+                (None, Some(error)) => Err(failure(
+                    item.status,
+                    error.code,
+                    error.message,
+                    error.details.as_ref(),
+                )),
+                (None, None) => Err(SdkError::RequestFailed(format!(
+                    "item {} came back empty with {}",
+                    item.index, item.status
+                ))),
+            };
+        }
+
+        let mut metrics = self.metrics.write().await;
+        metrics.total_requests += count as u64;
+        for result in &results {
+            match result {
+                Ok(response) if response.cache_hit => metrics.cache_hits += 1,
+                Ok(_) => {}
+                Err(_) => metrics.errors += 1,
+            }
+        }
+        results
+    }
+}
+
+/// The same failure for another item; errors aren't `Clone`
+fn copy_error(e: &SdkError) -> SdkError {
+    match e {
+        SdkError::Api {
+            status,
+            code,
+            message,
+        } => SdkError::Api {
+            status: *status,
+            code: code.clone(),
+            message: message.clone(),
+        },
+        SdkError::Validation { field, message } => SdkError::Validation {
+            field: field.clone(),
+            message: message.clone(),
+        },
+        SdkError::Timeout => SdkError::Timeout,
+        e => SdkError::RequestFailed(e.to_string()),
+    }
+}
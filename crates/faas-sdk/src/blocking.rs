@@ -0,0 +1,191 @@
+//! A client for code without an async runtime, behind the `blocking` feature.
+//!
+//! [`FaasClient`] wraps the async [`crate::FaasClient`] and runs each call to completion
+//! on a runtime of its own, so it answers with the same types and [`SdkError`]s.
+//!
+//! ```rust,no_run
+//! use faas_sdk::blocking::FaasClient;
+//!
+//! fn main() -> Result<(), faas_sdk::SdkError> {
+//!     let client = FaasClient::new("http://localhost:8080".to_string())?;
+//!
+//!     let result = client.execute_with(|b| b.command("echo hello").image("alpine:latest"))?;
+//!     println!("{}", result.stdout);
+//!
+//!     let python = client.run_python("print(6 * 7)")?;
+//!     println!("{}", python.stdout);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Calls block the thread they are made on, so they panic if made from inside an async
+//! runtime; use the async client there.
+
+use crate::{
+    ClientMetricsReport, CreateInstanceRequest, CreateSnapshotRequest, ExecuteRequest,
+    ExecuteRequestBuilder, ExecuteResponse, HealthStatus, InstanceResponse, PerformanceMetrics,
+    Runtime, SdkError, SnapshotResponse,
+};
+use std::future::Future;
+use std::sync::Arc;
+
+/// The blocking counterpart of [`crate::FaasClient`]; clones share one runtime
+#[derive(Clone)]
+pub struct FaasClient {
+    inner: crate::FaasClient,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl FaasClient {
+    /// A client choosing the runtime automatically, see [`crate::FaasClient::new`]
+    pub fn new(base_url: String) -> Result<Self, SdkError> {
+        Self::wrap(crate::FaasClient::new(base_url))
+    }
+
+    pub fn with_runtime(base_url: String, runtime: Runtime) -> Result<Self, SdkError> {
+        Self::wrap(crate::FaasClient::with_runtime(base_url, runtime))
+    }
+
+    /// Make the calls of a configured async client blocking
+    pub fn wrap(inner: crate::FaasClient) -> Result<Self, SdkError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// See [`crate::FaasClient::with_api_key`]
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        self.inner = self.inner.with_api_key(key);
+        self
+    }
+
+    /// See [`crate::FaasClient::with_namespace`]
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.inner = self.inner.with_namespace(namespace);
+        self
+    }
+
+    /// See [`crate::FaasClient::with_caching`]
+    pub fn with_caching(mut self, enabled: bool) -> Self {
+        self.inner = self.inner.with_caching(enabled);
+        self
+    }
+
+    /// See [`crate::FaasClient::with_retries`]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.inner = self.inner.with_retries(retries);
+        self
+    }
+
+    /// The async client the calls go through
+    pub fn as_async(&self) -> &crate::FaasClient {
+        &self.inner
+    }
+
+    fn block_on<T>(&self, call: impl Future<Output = T>) -> T {
+        self.runtime.block_on(call)
+    }
+
+    pub fn execute(&self, request: ExecuteRequest) -> Result<ExecuteResponse, SdkError> {
+        self.block_on(self.inner.execute(request))
+    }
+
+    /// See [`crate::FaasClient::request`]
+    pub fn request(&self, command: impl Into<String>) -> ExecuteRequestBuilder {
+        self.inner.request(command)
+    }
+
+    /// See [`crate::FaasClient::execute_with`]
+    pub fn execute_with(
+        &self,
+        build: impl FnOnce(ExecuteRequestBuilder) -> ExecuteRequestBuilder,
+    ) -> Result<ExecuteResponse, SdkError> {
+        self.block_on(self.inner.execute_with(build))
+    }
+
+    pub fn run(&self, command: &str) -> Result<String, SdkError> {
+        self.block_on(self.inner.run(command))
+    }
+
+    pub fn run_python(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.block_on(self.inner.run_python(code))
+    }
+
+    pub fn run_javascript(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.block_on(self.inner.run_javascript(code))
+    }
+
+    pub fn run_bash(&self, script: &str) -> Result<ExecuteResponse, SdkError> {
+        self.block_on(self.inner.run_bash(script))
+    }
+
+    pub fn fork_execution(
+        &self,
+        parent_id: &str,
+        command: &str,
+    ) -> Result<ExecuteResponse, SdkError> {
+        self.block_on(self.inner.fork_execution(parent_id, command))
+    }
+
+    pub fn create_snapshot(
+        &self,
+        request: CreateSnapshotRequest,
+    ) -> Result<SnapshotResponse, SdkError> {
+        self.block_on(self.inner.create_snapshot(request))
+    }
+
+    pub fn get_snapshot(&self, snapshot_id: &str) -> Result<SnapshotResponse, SdkError> {
+        self.block_on(self.inner.get_snapshot(snapshot_id))
+    }
+
+    pub fn list_snapshots(&self) -> Result<Vec<SnapshotResponse>, SdkError> {
+        self.block_on(self.inner.list_snapshots())
+    }
+
+    pub fn delete_snapshot(&self, snapshot_id: &str) -> Result<(), SdkError> {
+        self.block_on(self.inner.delete_snapshot(snapshot_id))
+    }
+
+    pub fn create_instance(
+        &self,
+        request: CreateInstanceRequest,
+    ) -> Result<InstanceResponse, SdkError> {
+        self.block_on(self.inner.create_instance(request))
+    }
+
+    pub fn list_instances(&self) -> Result<Vec<InstanceResponse>, SdkError> {
+        self.block_on(self.inner.list_instances())
+    }
+
+    pub fn exec_in_instance(
+        &self,
+        instance_id: &str,
+        command: &str,
+    ) -> Result<ExecuteResponse, SdkError> {
+        self.block_on(self.inner.exec_in_instance(instance_id, command))
+    }
+
+    pub fn stop_instance(&self, instance_id: &str) -> Result<(), SdkError> {
+        self.block_on(self.inner.stop_instance(instance_id))
+    }
+
+    pub fn delete_instance(&self, instance_id: &str) -> Result<(), SdkError> {
+        self.block_on(self.inner.delete_instance(instance_id))
+    }
+
+    pub fn get_metrics(&self) -> Result<PerformanceMetrics, SdkError> {
+        self.block_on(self.inner.get_metrics())
+    }
+
+    pub fn client_metrics(&self) -> ClientMetricsReport {
+        self.block_on(self.inner.client_metrics())
+    }
+
+    pub fn health_check(&self) -> Result<HealthStatus, SdkError> {
+        self.block_on(self.inner.health_check())
+    }
+}
@@ -0,0 +1,241 @@
+//! Streaming, resumable downloads of artifacts and execution logs.
+
+use crate::{api_error, FaasClient, SdkError};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+
+/// Options for [`FaasClient::download_artifact_with`]
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Bytes already written by an earlier attempt; the download continues from here
+    pub offset: u64,
+    /// ETag seen by the earlier attempt, sent as `If-Range` so changed content is detected
+    pub etag: Option<String>,
+    /// How many times to reconnect after the connection drops mid-stream
+    pub max_resumes: u32,
+    /// Buffer size for writes to the destination
+    pub chunk_size: usize,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            etag: None,
+            max_resumes: 3,
+            chunk_size: 64 * 1024,
+        }
+    }
+}
+
+/// Where a download ended up; feed it back into [`DownloadOptions`] to resume later
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadOutcome {
+    pub bytes_written: u64,
+    pub total_bytes: Option<u64>,
+    pub etag: Option<String>,
+}
+
+/// Stored artifact as reported by the gateway
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub hash: String,
+    pub size_bytes: u64,
+}
+
+impl FaasClient {
+    /// Upload an artifact; the gateway files it under its SHA-256 hash
+    pub async fn upload_artifact(&self, data: Vec<u8>) -> Result<ArtifactInfo, SdkError> {
+        let url = format!("{}/api/v1/artifacts", self.base_url);
+        let response = self.client.put(&url).body(data).send().await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Stream an artifact into `writer` without buffering it in memory
+    ///
+    /// Dropped connections are resumed automatically with HTTP Range requests.
+    pub async fn download_artifact_to<W>(
+        &self,
+        hash: &str,
+        writer: W,
+    ) -> Result<DownloadOutcome, SdkError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.download_artifact_with(hash, writer, DownloadOptions::default())
+            .await
+    }
+
+    /// Like [`download_artifact_to`](Self::download_artifact_to), resuming from `options.offset`
+    ///
+    /// Returns [`SdkError::ContentChanged`] if the artifact no longer matches `options.etag`.
+    pub async fn download_artifact_with<W>(
+        &self,
+        hash: &str,
+        writer: W,
+        options: DownloadOptions,
+    ) -> Result<DownloadOutcome, SdkError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let url = format!("{}/api/v1/artifacts/{}", self.base_url, hash);
+        self.download_resumable(&url, writer, options).await
+    }
+
+    /// Stream an execution's captured logs as they are read from the gateway
+    pub async fn stream_logs(
+        &self,
+        execution_id: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes, SdkError>>, SdkError> {
+        self.stream_logs_from(execution_id, 0).await
+    }
+
+    /// Stream logs starting at byte `offset`, e.g. to pick up where a previous reader stopped
+    pub async fn stream_logs_from(
+        &self,
+        execution_id: &str,
+        offset: u64,
+    ) -> Result<impl Stream<Item = Result<Bytes, SdkError>>, SdkError> {
+        let url = format!("{}/api/v1/logs/{}", self.base_url, execution_id);
+        let mut request = self.client.get(&url);
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={offset}-"));
+        }
+        let response = request.send().await?;
+
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            // Nothing new past `offset` yet.
+            return Ok(futures::stream::empty().boxed());
+        }
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        Ok(response.bytes_stream().map_err(SdkError::from).boxed())
+    }
+
+    async fn download_resumable<W>(
+        &self,
+        url: &str,
+        writer: W,
+        options: DownloadOptions,
+    ) -> Result<DownloadOutcome, SdkError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut writer = BufWriter::with_capacity(options.chunk_size.max(1), writer);
+        let mut outcome = DownloadOutcome {
+            bytes_written: options.offset,
+            total_bytes: None,
+            etag: options.etag,
+        };
+        let mut resumes = 0;
+
+        loop {
+            let mut request = self.client.get(url);
+            if outcome.bytes_written > 0 {
+                request =
+                    request.header(header::RANGE, format!("bytes={}-", outcome.bytes_written));
+                if let Some(etag) = &outcome.etag {
+                    request = request.header(header::IF_RANGE, etag.as_str());
+                }
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(e) if resumes < options.max_resumes && (e.is_connect() || e.is_timeout()) => {
+                    resumes += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            match response.status() {
+                StatusCode::PARTIAL_CONTENT => {
+                    if content_range_start(&response) != Some(outcome.bytes_written) {
+                        return Err(SdkError::RequestFailed(
+                            "Server returned a range that does not match the resume offset"
+                                .to_string(),
+                        ));
+                    }
+                }
+                StatusCode::OK if outcome.bytes_written > 0 => {
+                    // The server ignored our Range because If-Range no longer matches.
+                    return Err(SdkError::ContentChanged);
+                }
+                StatusCode::OK => {}
+                StatusCode::RANGE_NOT_SATISFIABLE if outcome.bytes_written > 0 => {
+                    // Everything was already written by an earlier attempt.
+                    writer.flush().await?;
+                    return Ok(outcome);
+                }
+                _ => {
+                    return Err(api_error(response).await);
+                }
+            }
+
+            if let Some(total) = total_length(&response) {
+                outcome.total_bytes = Some(total);
+            }
+            if let Some(etag) = response
+                .headers()
+                .get(header::ETAG)
+                .and_then(|v| v.to_str().ok())
+            {
+                outcome.etag = Some(etag.to_string());
+            }
+
+            let mut stream = response.bytes_stream();
+            let mut interrupted = None;
+            while let Some(chunk) = stream.next().await {
+                match chunk {
+                    Ok(chunk) => {
+                        writer.write_all(&chunk).await?;
+                        outcome.bytes_written += chunk.len() as u64;
+                    }
+                    Err(e) => {
+                        interrupted = Some(e);
+                        break;
+                    }
+                }
+            }
+            writer.flush().await?;
+
+            match interrupted {
+                None => return Ok(outcome),
+                Some(_) if resumes < options.max_resumes => resumes += 1,
+                Some(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    let value = response
+        .headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?;
+    let range = value.strip_prefix("bytes ")?;
+    range.split('-').next()?.parse().ok()
+}
+
+/// Full size of the resource, from `Content-Range` on 206 or `Content-Length` on 200.
+fn total_length(response: &reqwest::Response) -> Option<u64> {
+    match response
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(value) => value.rsplit('/').next()?.parse().ok(),
+        None => response.content_length(),
+    }
+}
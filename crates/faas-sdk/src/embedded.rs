@@ -0,0 +1,224 @@
+//! In-process execution through the platform executor, no gateway required
+
+use crate::{ExecuteRequest, ExecuteResponse, IsolationLevel, Runtime, SdkError, Transport};
+use async_trait::async_trait;
+use base64::Engine;
+use faas_executor::platform::executor::{Executor, Mode, Request};
+use faas_executor::platform::PlatformConfig;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Client that runs executions on a local Docker/Firecracker executor
+///
+/// Accepts the same [`ExecuteRequest`] as [`FaasClient`](crate::FaasClient) and fills in the
+/// same defaults the gateway would, so code written against [`Transport`] behaves alike on both.
+#[derive(Clone)]
+pub struct EmbeddedClient {
+    executor: Arc<Executor>,
+    runtime: Runtime,
+}
+
+impl EmbeddedClient {
+    /// Start a platform executor configured from the `FAAS_*` environment, connected to
+    /// the local Docker daemon unless `FAAS_DOCKER_SOCKET` names another
+    pub async fn new() -> Result<Self, SdkError> {
+        let config =
+            PlatformConfig::from_env().map_err(|e| SdkError::RequestFailed(e.to_string()))?;
+        let executor = Executor::new(config)
+            .await
+            .map_err(|e| SdkError::RequestFailed(e.to_string()))?;
+        Ok(Self::with_executor(Arc::new(executor)))
+    }
+
+    /// Share an executor the application already owns
+    pub fn with_executor(executor: Arc<Executor>) -> Self {
+        Self {
+            executor,
+            runtime: Runtime::Auto,
+        }
+    }
+
+    pub fn with_runtime(mut self, runtime: Runtime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    pub fn executor(&self) -> &Arc<Executor> {
+        &self.executor
+    }
+}
+
+#[async_trait]
+impl Transport for EmbeddedClient {
+    async fn execute(&self, request: ExecuteRequest) -> Result<ExecuteResponse, SdkError> {
+        let request = platform_request(request, &self.runtime, self.executor.default_timeout());
+        let response = self
+            .executor
+            .run(request)
+            .await
+            .map_err(|e| SdkError::RequestFailed(e.to_string()))?;
+
+        // Nothing serves logs in-process, so the full output of a truncated run isn't kept
+        if let Some(spilled) = response
+            .truncation
+            .as_ref()
+            .and_then(|truncation| truncation.log_path.as_ref())
+        {
+            let _ = std::fs::remove_file(spilled);
+        }
+        let stdout = String::from_utf8_lossy(&response.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&response.stderr).to_string();
+        Ok(ExecuteResponse {
+            request_id: response.id,
+            output: Some(stdout.clone()),
+            logs: Some(stderr.clone()),
+            error: (response.exit_code != 0)
+                .then(|| format!("Process exited with code {}", response.exit_code)),
+            exit_code: response.exit_code,
+            stdout,
+            stderr,
+            duration_ms: response.duration.as_millis() as u64,
+            cache_hit: response.cache_hit,
+            cache_key: response.cache_key,
+            runtime: response
+                .runtime_decision
+                .map(|decision| match decision.runtime {
+                    faas_common::Runtime::Docker => Runtime::Docker,
+                    faas_common::Runtime::Firecracker => Runtime::Firecracker,
+                    faas_common::Runtime::Auto => Runtime::Auto,
+                }),
+            runtime_reason: response.runtime_decision.and_then(|decision| {
+                serde_json::to_value(decision.reason)
+                    .ok()
+                    .and_then(|reason| reason.as_str().map(String::from))
+            }),
+            diagnostics: None,
+            usage: response.usage,
+            truncated: response.truncation.is_some(),
+            total_bytes: response.truncation.map(|truncation| truncation.total_bytes),
+            snapshot_id: response.snapshot,
+        })
+    }
+}
+
+/// Translate an HTTP-shaped request the way the gateway's execute handler does
+fn platform_request(
+    request: ExecuteRequest,
+    default_runtime: &Runtime,
+    default_timeout: Duration,
+) -> Request {
+    let mode = match request.mode.as_deref() {
+        Some("cached") => Mode::Cached,
+        Some("checkpointed") => Mode::Checkpointed,
+        Some("branched") => Mode::Branched,
+        Some("persistent") => Mode::Persistent,
+        _ => Mode::Ephemeral,
+    };
+
+    let mut code = request.command;
+    // The platform executor has no stdin, so payloads are piped in from the command line.
+    if let Some(payload) = &request.payload {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(payload);
+        code = format!("echo '{encoded}' | base64 -d | {code}");
+    }
+    let input_files = request.input_files.unwrap_or_default();
+
+    let runtime = match request.runtime.as_ref().unwrap_or(default_runtime) {
+        Runtime::Docker => faas_common::Runtime::Docker,
+        Runtime::Firecracker => faas_common::Runtime::Firecracker,
+        Runtime::Auto => faas_common::Runtime::Auto,
+    };
+
+    Request {
+        id: uuid::Uuid::new_v4().to_string(),
+        code,
+        mode,
+        env: request.image.unwrap_or_else(|| "alpine:latest".to_string()),
+        timeout: request
+            .timeout_ms
+            .map_or(default_timeout, Duration::from_millis),
+        checkpoint: request.snapshot_id,
+        branch_from: request.branch_from,
+        runtime: Some(runtime),
+        isolation: request.isolation.map(|isolation| match isolation {
+            IsolationLevel::Process => faas_common::IsolationLevel::Process,
+            IsolationLevel::Hardware => faas_common::IsolationLevel::Hardware,
+        }),
+        env_vars: request
+            .env_vars
+            .map(|vars| vars.into_iter().map(|var| (var.key, var.value)).collect()),
+        working_dir: request.working_dir,
+        user: request.user,
+        memory_mb: request.memory_mb,
+        cpu_cores: request.cpu_cores.map(f64::from),
+        ulimits: request.ulimits.map(|limits| {
+            limits
+                .into_iter()
+                .map(|l| faas_common::Ulimit::new(l.name, l.soft, l.hard))
+                .collect()
+        }),
+        shm_size_mb: request.shm_size_mb,
+        pids_limit: Some(faas_common::DEFAULT_PIDS_LIMIT),
+        tmpfs: request.tmpfs.map(|mounts| {
+            mounts
+                .into_iter()
+                .map(|m| faas_common::TmpfsMount {
+                    path: m.path,
+                    size_mb: m.size_mb,
+                })
+                .collect()
+        }),
+        placement: None,
+        // Already piped in through `code`
+        payload: Vec::new(),
+        input_files,
+        read_only_mounts: Vec::new(),
+        gpu: request.gpu_count.map(faas_common::GpuRequest::count),
+        network: match (request.network_enabled, request.allowed_hosts) {
+            (Some(false), _) => Some(faas_common::NetworkPolicy::None),
+            (_, Some(allowed_hosts)) if !allowed_hosts.is_empty() => {
+                Some(faas_common::NetworkPolicy::Restricted { allowed_hosts })
+            }
+            _ => None,
+        },
+        environment_overrides: request
+            .environment_overrides
+            .map(|o| faas_common::EnvOverrides {
+                timezone: o.timezone,
+                locale: o.locale,
+                fake_time: o.fake_time,
+            }),
+        execution_strategy: None,
+        idempotent: false,
+        cache_key: request.cache_key,
+        cache_namespace: None,
+        tenant: None,
+        no_cache: request.no_cache.unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EnvVar;
+
+    #[test]
+    fn translates_requests_like_the_gateway() {
+        let mut request = ExecuteRequest::python("print(1)");
+        request.working_dir = Some("/app".to_string());
+        request.env_vars = Some(vec![EnvVar::new("MODE", "test")]);
+        request.mode = Some("cached".to_string());
+
+        let translated = platform_request(request, &Runtime::Docker, Duration::from_secs(30));
+        assert_eq!(translated.code, "echo 'cHJpbnQoMSk=' | base64 -d | python");
+        assert_eq!(translated.working_dir.as_deref(), Some("/app"));
+        assert_eq!(translated.env, "python:3.11-slim");
+        assert!(matches!(translated.mode, Mode::Cached));
+        assert!(matches!(
+            translated.runtime,
+            Some(faas_common::Runtime::Docker)
+        ));
+        assert_eq!(translated.env_vars.unwrap()["MODE"], "test");
+        assert_eq!(translated.timeout, Duration::from_secs(30));
+    }
+}
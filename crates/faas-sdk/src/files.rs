@@ -0,0 +1,107 @@
+//! Files in and out of persistent instances
+//!
+//! Uploads are sent as one tar archive extracted at the instance's `/`, so missing parent
+//! directories are created on the way. Ephemeral executions take files through
+//! [`ExecuteRequest::input_files`](crate::ExecuteRequest::input_files) instead.
+
+use crate::{api_error, FaasClient, SdkError};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+impl FaasClient {
+    /// Write `contents` to the absolute `path` in the instance
+    pub async fn upload_file(
+        &self,
+        instance_id: &str,
+        path: &str,
+        contents: &[u8],
+    ) -> Result<(), SdkError> {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(contents.len() as u64);
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append_data(&mut header, archive_path(path)?, contents)?;
+        self.upload_archive(instance_id, builder.into_inner()?)
+            .await
+    }
+
+    /// Copy the local directory `local` and everything under it to `remote` in the instance
+    pub async fn upload_dir(
+        &self,
+        instance_id: &str,
+        local: impl AsRef<Path>,
+        remote: &str,
+    ) -> Result<(), SdkError> {
+        let local = local.as_ref().to_path_buf();
+        let remote = archive_path(remote)?;
+        let archive = tokio::task::spawn_blocking(move || {
+            let mut builder = tar::Builder::new(Vec::new());
+            builder.append_dir_all(remote, local)?;
+            builder.into_inner()
+        })
+        .await
+        .map_err(|e| SdkError::RequestFailed(format!("packing the directory failed: {e}")))??;
+        self.upload_archive(instance_id, archive).await
+    }
+
+    /// The contents of the file at `path` in the instance
+    pub async fn download_file(&self, instance_id: &str, path: &str) -> Result<Vec<u8>, SdkError> {
+        let url = format!("{}/api/v1/instances/{}/files", self.base_url, instance_id);
+        let response = self
+            .client
+            .get(&url)
+            .query(&[("path", path)])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        let archive = response.bytes().await?;
+
+        // Docker packs a file as the archive's only entry
+        let mut archive = tar::Archive::new(archive.as_ref());
+        let mut entry = archive
+            .entries()?
+            .next()
+            .ok_or_else(|| SdkError::RequestFailed(format!("{path} came back empty")))??;
+        if !matches!(
+            entry.header().entry_type(),
+            tar::EntryType::Regular | tar::EntryType::Continuous
+        ) {
+            return Err(SdkError::RequestFailed(format!(
+                "{path} is not a regular file"
+            )));
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    async fn upload_archive(&self, instance_id: &str, archive: Vec<u8>) -> Result<(), SdkError> {
+        let url = format!("{}/api/v1/instances/{}/files", self.base_url, instance_id);
+        let response = self
+            .client
+            .post(&url)
+            .query(&[("path", "/")])
+            .header("Content-Type", "application/x-tar")
+            .body(archive)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        Ok(())
+    }
+}
+
+/// `path` as an entry name in an archive extracted at `/`
+fn archive_path(path: &str) -> Result<PathBuf, SdkError> {
+    let relative = path.trim_start_matches('/');
+    if !path.starts_with('/') || relative.is_empty() {
+        return Err(SdkError::RequestFailed(format!(
+            "{path:?} is not an absolute path below /"
+        )));
+    }
+    Ok(PathBuf::from(relative))
+}
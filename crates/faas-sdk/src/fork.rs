@@ -0,0 +1,121 @@
+//! Forks with named branches
+//!
+//! Every branch of a fork starts from the files its parent left, as
+//! [`FaasClient::fork_execution`] does, and runs its own command. The strategy decides how
+//! they run and which one the gateway selects: `Parallel` runs them all and prefers the
+//! heaviest that succeeded, `Fastest` keeps the first to succeed and cancels the rest,
+//! `Sequential` stops at the first to succeed.
+
+use crate::{json_or_error, EnvVar, ExecuteResponse, FaasClient, SdkError};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ForkBranch {
+    /// Unique within the fork
+    pub id: String,
+    pub command: String,
+    /// Set on top of the fork's own env vars
+    pub env_vars: Option<Vec<EnvVar>>,
+    /// Preference among branches that succeed under `Parallel`; 1 when unset
+    pub weight: Option<f64>,
+}
+
+impl ForkBranch {
+    pub fn new(id: impl Into<String>, command: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            command: command.into(),
+            env_vars: None,
+            weight: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForkStrategy {
+    /// Run all branches at once
+    #[default]
+    Parallel,
+    /// Keep the first to succeed and cancel the others
+    Fastest,
+    /// Run in order until one succeeds
+    Sequential,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForkOutcome {
+    Succeeded,
+    Failed,
+    /// Stopped once another branch won
+    Cancelled,
+    /// Never started, since an earlier branch succeeded
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ForkBranchError {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForkBranchResult {
+    pub branch_id: String,
+    pub outcome: ForkOutcome,
+    /// Set for a branch that ran to an exit code
+    pub response: Option<ExecuteResponse>,
+    /// Why a branch without a response failed or was cancelled
+    pub error: Option<ForkBranchError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForkResult {
+    /// Cancelling this id cancels the branches still running
+    pub fork_id: String,
+    pub strategy: ForkStrategy,
+    /// One per branch, in the order they were given
+    pub results: Vec<ForkBranchResult>,
+    pub selected_branch: Option<String>,
+    pub selection_reason: String,
+}
+
+impl ForkResult {
+    /// The selected branch's result
+    pub fn selected(&self) -> Option<&ForkBranchResult> {
+        let selected = self.selected_branch.as_deref()?;
+        self.results.iter().find(|r| r.branch_id == selected)
+    }
+}
+
+#[derive(Serialize)]
+struct ForkRequest<'a> {
+    branch_from: &'a str,
+    branches: &'a [ForkBranch],
+    strategy: ForkStrategy,
+}
+
+impl FaasClient {
+    /// Run `branches` of `parent_id`, an execution that ran in `branched` or `persistent`
+    /// mode, as `strategy` says
+    pub async fn fork_branches(
+        &self,
+        parent_id: &str,
+        branches: Vec<ForkBranch>,
+        strategy: ForkStrategy,
+    ) -> Result<ForkResult, SdkError> {
+        let url = format!("{}/api/v1/fork", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&ForkRequest {
+                branch_from: parent_id,
+                branches: &branches,
+                strategy,
+            })
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+}
@@ -0,0 +1,252 @@
+//! Execution groups
+//!
+//! Executions that name the same `group_id` are tracked together, and the gateway sends one
+//! `on_settled` webhook for the whole group instead of one per execution.
+//!
+//! Cancelling a group cancels its members as its [`CancelPolicy`] says, and the group
+//! settles once none is left running.
+//!
+//! A settled group can be compared against an earlier one, e.g. an evaluation suite rerun
+//! against a new model version, to see which items changed.
+
+use crate::{json_or_error, FaasClient, SdkError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// When a group counts as settled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementPolicy {
+    /// Every member has finished
+    #[default]
+    AllComplete,
+    /// Any member failed, or every member finished without failing
+    FirstFailure,
+    /// This many members succeeded, or every member finished
+    Quorum(usize),
+}
+
+/// Which members a group cancel reaches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelPolicy {
+    #[default]
+    All,
+    /// Members that haven't started; running ones finish
+    OnlyPending,
+}
+
+/// Where a member's or step's cancellation came from
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Cancellation {
+    /// What the cancel was issued against
+    pub source: String,
+    /// Parent whose cancellation reached this one; `None` for the source itself
+    pub cancelled_by: Option<String>,
+    /// Levels below the source
+    pub depth: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupCancellation {
+    pub cancelled_at: String,
+    pub policy: CancelPolicy,
+    /// Running members the policy left to finish
+    pub spared: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateGroupRequest {
+    pub policy: SettlementPolicy,
+    /// Receives the [`GroupSummary`] once, when the group settles
+    pub on_settled: Option<String>,
+    /// Number of executions that will join; without it the group can settle before
+    /// late joiners arrive
+    pub expected: Option<usize>,
+    /// Earlier group to compare against by default
+    pub baseline_group_id: Option<String>,
+    /// Normalizer preset to compare with by default
+    pub normalizer: Option<String>,
+    /// Used by [`FaasClient::cancel_group`] calls that pass no policy of their own
+    pub cancel_policy: CancelPolicy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupMember {
+    pub execution_id: String,
+    /// `running`, `succeeded`, `failed` or `cancelled`
+    pub status: String,
+    pub duration_ms: Option<u64>,
+    pub item_key: Option<String>,
+    pub cancelled: Option<Cancellation>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MemberTiming {
+    pub execution_id: String,
+    pub duration_ms: u64,
+}
+
+/// Live progress of a group, and the body of its webhook
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupSummary {
+    pub group_id: String,
+    pub policy: SettlementPolicy,
+    pub expected: Option<usize>,
+    pub settled: bool,
+    pub settled_at: Option<String>,
+    pub total: usize,
+    /// Members per status
+    pub counts: BTreeMap<String, usize>,
+    pub fastest: Option<MemberTiming>,
+    pub slowest: Option<MemberTiming>,
+    pub executions: Vec<GroupMember>,
+    pub baseline_group_id: Option<String>,
+    #[serde(default)]
+    pub cancel_policy: CancelPolicy,
+    pub cancellation: Option<GroupCancellation>,
+}
+
+/// How stdout is cleaned up before comparing; stored on the gateway under a name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Normalizer {
+    pub trim_whitespace: bool,
+    /// Compare as JSON when stdout parses, as one document or one per line
+    pub parse_json: bool,
+    /// JSONPaths to remove before comparing, e.g. `$..timestamp`
+    pub drop_fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemOutcome {
+    /// Failed in the baseline, succeeds now
+    Improved,
+    /// Differs from the baseline in any other way
+    Regressed,
+    Unchanged,
+    /// In the baseline only
+    Missing,
+    /// In the new group only
+    Added,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DiffSummary {
+    pub added_lines: usize,
+    pub removed_lines: usize,
+    /// The first differing lines, `-` for the baseline and `+` for the new run
+    pub excerpt: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemComparison {
+    /// `item_key`, or `#<n>` by join order
+    pub key: String,
+    pub outcome: ItemOutcome,
+    pub baseline_execution_id: Option<String>,
+    pub execution_id: Option<String>,
+    pub baseline_exit_code: Option<i32>,
+    pub exit_code: Option<i32>,
+    pub diff: Option<DiffSummary>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ComparisonReport {
+    pub group_id: String,
+    pub baseline_group_id: String,
+    pub normalizer: Option<String>,
+    pub improved: usize,
+    pub regressed: usize,
+    pub unchanged: usize,
+    pub missing: usize,
+    pub added: usize,
+    pub items: Vec<ItemComparison>,
+}
+
+impl FaasClient {
+    /// Create a group with no webhook; pass its id as `ExecuteRequest::group_id`
+    pub async fn create_group(&self, policy: SettlementPolicy) -> Result<GroupSummary, SdkError> {
+        self.create_group_with(CreateGroupRequest {
+            policy,
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn create_group_with(
+        &self,
+        request: CreateGroupRequest,
+    ) -> Result<GroupSummary, SdkError> {
+        let url = format!("{}/api/v1/groups", self.base_url);
+        let response = self.client.post(&url).json(&request).send().await?;
+        json_or_error(response).await
+    }
+
+    pub async fn get_group(&self, group_id: &str) -> Result<GroupSummary, SdkError> {
+        let url = format!("{}/api/v1/groups/{}", self.base_url, group_id);
+        let response = self.client.get(&url).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Poll until the group settles.
+    ///
+    /// There is no deadline; wrap the call in `tokio::time::timeout` to bound it.
+    pub async fn wait_group(&self, group_id: &str) -> Result<GroupSummary, SdkError> {
+        loop {
+            let summary = self.get_group(group_id).await?;
+            if summary.settled {
+                return Ok(summary);
+            }
+            tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Cancel the group's members; `None` uses the policy the group was created with.
+    ///
+    /// The group takes no new members afterwards, and settles (sending its webhook) once
+    /// none is left running.
+    pub async fn cancel_group(
+        &self,
+        group_id: &str,
+        policy: Option<CancelPolicy>,
+    ) -> Result<GroupSummary, SdkError> {
+        let url = format!("{}/api/v1/groups/{}/cancel", self.base_url, group_id);
+        let response = self
+            .client
+            .post(&url)
+            .json(&serde_json::json!({ "policy": policy }))
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+
+    /// Compare a settled group against a settled baseline, normalizing stdout with the
+    /// named preset first. Without a preset, stdout has to match exactly.
+    pub async fn compare_groups(
+        &self,
+        group_id: &str,
+        baseline_group_id: &str,
+        normalizer: Option<&str>,
+    ) -> Result<ComparisonReport, SdkError> {
+        let url = format!("{}/api/v1/groups/{}/comparison", self.base_url, group_id);
+        let mut query = vec![("baseline", baseline_group_id)];
+        query.extend(normalizer.map(|name| ("normalizer", name)));
+        let response = self.client.get(&url).query(&query).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Store a normalizer preset on the gateway, replacing one with the same name
+    pub async fn put_normalizer(
+        &self,
+        name: &str,
+        normalizer: &Normalizer,
+    ) -> Result<Normalizer, SdkError> {
+        let url = format!("{}/api/v1/normalizers/{}", self.base_url, name);
+        let response = self.client.put(&url).json(normalizer).send().await?;
+        json_or_error(response).await
+    }
+}
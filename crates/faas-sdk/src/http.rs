@@ -0,0 +1,71 @@
+//! The HTTP client every gateway request goes through.
+//!
+//! With the `otel` feature, a request made inside a `tracing` span that a
+//! `tracing-opentelemetry` layer records carries the span's W3C `traceparent` and
+//! `tracestate`, so the gateway's spans for it join the caller's trace.
+
+use reqwest::{Client, IntoUrl, Method, RequestBuilder};
+
+#[derive(Clone)]
+pub(crate) struct HttpClient(Client);
+
+impl HttpClient {
+    pub(crate) fn new(client: Client) -> Self {
+        Self(client)
+    }
+
+    pub(crate) fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub(crate) fn head(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::HEAD, url)
+    }
+
+    pub(crate) fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    pub(crate) fn put(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::PUT, url)
+    }
+
+    pub(crate) fn delete(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::DELETE, url)
+    }
+
+    fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        with_trace_context(self.0.request(method, url))
+    }
+}
+
+#[cfg(feature = "otel")]
+fn with_trace_context(request: RequestBuilder) -> RequestBuilder {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    if !span_context.is_valid() {
+        return request;
+    }
+    let request = request.header(
+        "traceparent",
+        format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        ),
+    );
+    match span_context.trace_state().header() {
+        state if state.is_empty() => request,
+        state => request.header("tracestate", state),
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn with_trace_context(request: RequestBuilder) -> RequestBuilder {
+    request
+}
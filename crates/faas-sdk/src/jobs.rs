@@ -0,0 +1,123 @@
+//! Background jobs
+//!
+//! A job runs an execution past any HTTP timeout: it is submitted, answered at once with
+//! its id, and polled, waited for or cancelled from then on. Cancelling a job removes the
+//! containers it runs in. A gateway configured to sign callbacks POSTs the finished job to
+//! its `callback_url`.
+
+use crate::{json_or_error, ExecuteRequest, ExecuteResponse, FaasClient, SdkError};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Job {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    /// The latest output while it runs, and what ran of a job that ended without a
+    /// response
+    #[serde(default)]
+    pub logs: String,
+    /// What the execution answered; also set for a failed job that ran to an exit code
+    pub response: Option<ExecuteResponse>,
+    /// Why a job without a response failed or was cancelled
+    pub error: Option<JobError>,
+    pub callback_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct JobError {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct JobRequest<'a> {
+    #[serde(flatten)]
+    request: &'a ExecuteRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    callback_url: Option<&'a str>,
+}
+
+impl FaasClient {
+    /// Run `request` in the background, with the client's defaults applied as
+    /// [`FaasClient::execute`] would; the job comes back `queued`
+    pub async fn submit_job(&self, request: ExecuteRequest) -> Result<Job, SdkError> {
+        self.send_job(request, None).await
+    }
+
+    /// [`FaasClient::submit_job`], with the finished job POSTed to `callback_url`
+    pub async fn submit_job_with_callback(
+        &self,
+        request: ExecuteRequest,
+        callback_url: &str,
+    ) -> Result<Job, SdkError> {
+        self.send_job(request, Some(callback_url)).await
+    }
+
+    async fn send_job(
+        &self,
+        mut request: ExecuteRequest,
+        callback_url: Option<&str>,
+    ) -> Result<Job, SdkError> {
+        self.apply_defaults(&mut request);
+        self.reference_payload(&mut request).await?;
+        let url = format!("{}/api/v1/jobs", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&JobRequest {
+                request: &request,
+                callback_url,
+            })
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+
+    pub async fn get_job(&self, job_id: &str) -> Result<Job, SdkError> {
+        let url = format!("{}/api/v1/jobs/{}", self.base_url, job_id);
+        let response = self.client.get(&url).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Poll the job every `poll_interval` until it finishes, however it ends
+    pub async fn wait_for_job(
+        &self,
+        job_id: &str,
+        poll_interval: Duration,
+    ) -> Result<Job, SdkError> {
+        loop {
+            let job = self.get_job(job_id).await?;
+            if job.status.is_finished() {
+                return Ok(job);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// Cancel the job; it reads `cancelled` once its containers are gone
+    pub async fn cancel_job(&self, job_id: &str) -> Result<Job, SdkError> {
+        let url = format!("{}/api/v1/jobs/{}", self.base_url, job_id);
+        let response = self.client.delete(&url).send().await?;
+        json_or_error(response).await
+    }
+}
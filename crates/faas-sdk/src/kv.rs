@@ -0,0 +1,61 @@
+//! Scoped key-value state
+//!
+//! Executions read and write their namespace through `FAAS_KV_ENDPOINT` and `FAAS_KV_TOKEN`.
+//! These calls reach the same namespaces from outside, to seed inputs before a run and read
+//! what it left behind. A group's namespace is `group-<id>`, a workflow's is
+//! `workflow-<name>`, and other executions share `default`.
+
+use crate::{json_or_error, FaasClient, SdkError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KvEntry {
+    pub key: String,
+    pub value: Value,
+    /// Starts at 1 and goes up by one on every write
+    pub version: u64,
+    pub updated_at: String,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KvPut {
+    pub value: Value,
+    pub ttl_secs: Option<u64>,
+    /// Write only if the key is at this version, `0` meaning absent
+    pub expected_version: Option<u64>,
+}
+
+impl FaasClient {
+    pub async fn kv_get(&self, namespace: &str, key: &str) -> Result<KvEntry, SdkError> {
+        let url = format!("{}/api/v1/kv/{}/{}", self.base_url, namespace, key);
+        let response = self.client.get(&url).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Write a key; with `expected_version` set this is a compare-and-swap, failing if
+    /// someone else wrote first
+    pub async fn kv_put(
+        &self,
+        namespace: &str,
+        key: &str,
+        put: KvPut,
+    ) -> Result<KvEntry, SdkError> {
+        let url = format!("{}/api/v1/kv/{}/{}", self.base_url, namespace, key);
+        let response = self.client.put(&url).json(&put).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Keys starting with `prefix`, in key order
+    pub async fn kv_list(&self, namespace: &str, prefix: &str) -> Result<Vec<KvEntry>, SdkError> {
+        let url = format!("{}/api/v1/kv/{}", self.base_url, namespace);
+        let response = self
+            .client
+            .get(&url)
+            .query(&[("prefix", prefix)])
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+}
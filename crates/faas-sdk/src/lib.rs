@@ -5,21 +5,21 @@
 //!
 //! ## Quick Start
 //!
-//! ```rust
-//! use faas_sdk::{FaasClient, Runtime, ExecuteRequest};
+//! ```rust,no_run
+//! use faas_sdk::FaasClient;
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let client = FaasClient::new("http://localhost:8080".to_string());
 //!
 //!     // Simple execution
-//!     let result = client.execute(ExecuteRequest {
-//!         command: "echo 'Hello, World!'".to_string(),
-//!         image: Some("alpine:latest".to_string()),
-//!         env_vars: None,
-//!         working_dir: None,
-//!         timeout_ms: Some(5000),
-//!     }).await?;
+//!     let result = client
+//!         .execute_with(|b| {
+//!             b.command("echo 'Hello, World!'")
+//!                 .image("alpine:latest")
+//!                 .timeout_ms(5000)
+//!         })
+//!         .await?;
 //!
 //!     println!("Output: {}", result.stdout);
 //!     Ok(())
@@ -44,34 +44,52 @@
 //! | Firecracker | ~125ms | Hardware isolation | Production, multi-tenant |
 //! | Auto | Varies | Adaptive | Automatic selection |
 //!
+//! ## Transports
+//!
+//! [`FaasClient`] talks to a gateway over HTTP. With the `embedded` feature, `EmbeddedClient`
+//! runs the same requests on an in-process executor instead. Both implement [`Transport`], so
+//! code generic over it works with either:
+//!
+//! ```rust
+//! use faas_sdk::{SdkError, Transport};
+//!
+//! async fn greet(client: &impl Transport) -> Result<String, SdkError> {
+//!     client.run("echo hello").await
+//! }
+//! ```
+//!
 //! ## Examples
 //!
+//! Requests are built with [`ExecuteRequest::builder`], or in place with
+//! [`FaasClient::execute_with`]. The builder refuses obvious mistakes, like an empty
+//! command or a zero timeout, before anything is sent.
+//!
 //! ### Advanced Configuration
 //!
-//! ```rust
-//! use faas_sdk::{FaasClient, AdvancedExecuteRequest, ExecutionMode};
+//! ```rust,no_run
+//! # async fn example(client: faas_sdk::FaasClient) -> Result<(), faas_sdk::SdkError> {
+//! use faas_sdk::ExecuteRequest;
 //!
-//! let result = client.execute(ExecuteRequest {
-//!     command: "python ml_inference.py".to_string(),
-//!     image: Some("pytorch/pytorch:latest".to_string()),
-//!     mode: Some("cached".to_string()),
-//!     env_vars: Some(vec![
-//!         ("MODEL_PATH".to_string(), "/models/bert".to_string())
-//!     ]),
-//!     memory_mb: Some(2048),
-//!     cpu_cores: Some(2),
-//!     ..Default::default()
-//! }).await?;
+//! let request = ExecuteRequest::builder("python ml_inference.py")
+//!     .image("pytorch/pytorch:latest")
+//!     .mode("cached")
+//!     .env("MODEL_PATH", "/models/bert")
+//!     .memory_mb(2048)
+//!     .cpu_cores(2)
+//!     .build()?;
+//! let result = client.execute(request).await?;
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! ### Execution Forking
 //!
-//! ```rust
-//! // Create base execution
-//! let base = client.execute(ExecuteRequest {
-//!     command: "setup_environment.sh".to_string(),
-//!     ..Default::default()
-//! }).await?;
+//! ```rust,no_run
+//! # async fn example(client: faas_sdk::FaasClient) -> Result<(), faas_sdk::SdkError> {
+//! // Create base execution; its files are kept for its forks
+//! let base = client
+//!     .execute_with(|b| b.command("setup_environment.sh").mode("branched"))
+//!     .await?;
 //!
 //! // Fork for different experiment paths
 //! let fork_a = client.fork_execution(
@@ -83,8 +101,12 @@
 //!     &base.request_id,
 //!     "run_experiment_b.py"
 //! ).await?;
+//! # Ok(())
+//! # }
 //! ```
 
+use crate::http::HttpClient;
+pub use faas_common::{EnvVar, ExecutionUsage, Language};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -93,6 +115,54 @@ use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::RwLock;
 
+mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod download;
+pub use download::{ArtifactInfo, DownloadOptions, DownloadOutcome};
+mod files;
+mod fork;
+pub use fork::{
+    ForkBranch, ForkBranchError, ForkBranchResult, ForkOutcome, ForkResult, ForkStrategy,
+};
+mod http;
+mod jobs;
+pub use jobs::{Job, JobError, JobStatus};
+mod kv;
+pub use kv::{KvEntry, KvPut};
+mod payloads;
+pub use payloads::DEFAULT_PAYLOAD_REF_THRESHOLD;
+mod request;
+pub use request::{ExecuteRequestBuilder, RequestError};
+mod schedules;
+pub use schedules::{ConcurrencyPolicy, Schedule, ScheduleRun};
+mod groups;
+pub use groups::{
+    CancelPolicy, Cancellation, ComparisonReport, CreateGroupRequest, DiffSummary,
+    GroupCancellation, GroupMember, GroupSummary, ItemComparison, ItemOutcome, MemberTiming,
+    Normalizer, SettlementPolicy,
+};
+mod spool;
+pub use spool::{
+    OfflineSpoolConfig, SpoolOptions, SpoolOutcome, SpoolStatus, SpooledExecution, Submission,
+};
+mod session;
+pub use session::{RestoreReport, Session, SessionState};
+mod stream;
+pub use stream::StreamEvent;
+mod transport;
+pub use transport::Transport;
+mod workflow;
+pub use workflow::{
+    run_workflow, CancelReport, CancelledChild, InputFrom, StepCancellation, StepError, StepInput,
+    StepOutput, StepResources, StepRun, StepStatus, Workflow, WorkflowBuilder, WorkflowError,
+    WorkflowRun, WorkflowStep,
+};
+#[cfg(feature = "embedded")]
+mod embedded;
+#[cfg(feature = "embedded")]
+pub use embedded::EmbeddedClient;
+
 /// Execution result type alias for convenience
 pub type ExecutionResult = ExecuteResponse;
 
@@ -102,12 +172,138 @@ pub enum SdkError {
     Http(#[from] reqwest::Error),
     #[error("Serialization failed: {0}")]
     Serialization(#[from] serde_json::Error),
-    #[error("API error: {message}")]
-    Api { message: String },
+    /// The gateway answered with an error status
+    ///
+    /// `code` is the gateway's name for the failure, e.g. `ImageNotFound` or `Timeout`.
+    /// Failures reported inside a successful response, like a snapshot whose commit failed
+    /// or a stream that broke off, carry that response's status.
+    #[error("API error ({status} {code}): {message}")]
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+    },
+    /// The gateway refused the request's shape, e.g. an empty command or a timeout out of
+    /// bounds
+    ///
+    /// `field` is the first field at fault; `message` describes every one that is.
+    #[error("Invalid request ({field}): {message}")]
+    Validation { field: String, message: String },
     #[error("Request failed: {0}")]
     RequestFailed(String),
     #[error("Timeout occurred")]
     Timeout,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Content changed since the download started")]
+    ContentChanged,
+    #[error("Invalid workflow: {0}")]
+    Workflow(#[from] WorkflowError),
+    /// [`ExecuteRequestBuilder::build`] refused the request before it was sent
+    #[error("Invalid request: {0}")]
+    Build(#[from] RequestError),
+    #[error("Offline spool: {0}")]
+    Spool(String),
+}
+
+const SNAPSHOT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Wait before the first retry of an execution; doubled for each one after
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+/// Wait between asking again for the answer to an execution still running under the key,
+/// as the gateway's `Retry-After` says
+const IN_FLIGHT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Decode a JSON body, turning non-2xx responses into [`SdkError::Api`]
+pub(crate) async fn json_or_error<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, SdkError> {
+    if !response.status().is_success() {
+        return Err(api_error(response).await);
+    }
+    Ok(response.json().await?)
+}
+
+/// The [`SdkError::Api`] for an error response, or [`SdkError::Validation`] when it names
+/// a field of the request
+///
+/// Older gateways and some routes put the message under `error`; a body that isn't JSON
+/// at all becomes the message, with a code named after the status.
+pub(crate) async fn api_error(response: reqwest::Response) -> SdkError {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        code: Option<String>,
+        message: Option<String>,
+        error: Option<serde_json::Value>,
+        details: Option<serde_json::Value>,
+    }
+
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    let body = serde_json::from_str::<ErrorBody>(&text).ok();
+    let code = body.as_ref().and_then(|body| body.code.clone());
+    let details = body.as_ref().and_then(|body| body.details.clone());
+    let message = body.and_then(|body| {
+        body.message.or_else(|| {
+            body.error
+                .and_then(|error| error.as_str().map(str::to_string))
+        })
+    });
+    failure(
+        status.as_u16(),
+        code.unwrap_or_else(|| status_code_name(status)),
+        message.unwrap_or(text),
+        details.as_ref(),
+    )
+}
+
+/// A gateway error from its parts; a 422 `InvalidRequest` naming a `field` is a
+/// [`SdkError::Validation`]
+pub(crate) fn failure(
+    status: u16,
+    code: String,
+    message: String,
+    details: Option<&serde_json::Value>,
+) -> SdkError {
+    let field = details.and_then(|details| details["field"].as_str());
+    match field {
+        Some(field) if status == 422 && code == "InvalidRequest" => SdkError::Validation {
+            field: field.to_string(),
+            message,
+        },
+        _ => SdkError::Api {
+            status,
+            code,
+            message,
+        },
+    }
+}
+
+fn http_client(headers: reqwest::header::HeaderMap) -> HttpClient {
+    HttpClient::new(
+        Client::builder()
+            .timeout(Duration::from_secs(60))
+            .default_headers(headers)
+            .build()
+            .expect("Failed to create HTTP client"),
+    )
+}
+
+/// An execution attempt that may not have reached the gateway, or whose answer was lost
+fn retryable(error: &SdkError) -> bool {
+    matches!(error, SdkError::Http(e) if e.is_connect() || e.is_timeout() || e.is_request())
+}
+
+/// An attempt refused because an earlier one with its key is still running
+fn in_flight(error: &SdkError) -> bool {
+    matches!(error, SdkError::Api { status: 409, code, .. } if code == "IdempotencyKeyInFlight")
+}
+
+/// `NotFound` for a 404 and so on, for errors that don't name themselves
+pub(crate) fn status_code_name(status: reqwest::StatusCode) -> String {
+    status
+        .canonical_reason()
+        .unwrap_or("Error")
+        .replace(' ', "")
 }
 
 /// Runtime environment selection for execution
@@ -148,15 +344,23 @@ pub enum Runtime {
 
     /// Automatic runtime selection
     ///
-    /// The platform analyzes workload characteristics and automatically
-    /// chooses the optimal runtime based on:
-    /// - Security requirements
-    /// - Performance needs
-    /// - Resource constraints
-    /// - Historical patterns
+    /// The platform runs the execution in Docker unless it needs a VM: it asks for
+    /// [`IsolationLevel::Hardware`], wants more memory than the gateway's threshold, or
+    /// uses an image the gateway treats as untrusted. Hosts without KVM use Docker either
+    /// way; [`ExecuteResponse::runtime_reason`] tells which happened.
     Auto,
 }
 
+/// How strongly an execution has to be kept apart from the host and other tenants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IsolationLevel {
+    /// A container sharing the host kernel is enough
+    Process,
+    /// Its own kernel in a Firecracker VM, for `Auto` executions on hosts with KVM
+    Hardware,
+}
+
 /// High-performance FaaS Platform client with intelligent optimization
 ///
 /// The `FaasClient` provides a unified interface to the FaaS platform, supporting both
@@ -182,12 +386,22 @@ pub enum Runtime {
 /// let client = Arc::new(FaasClient::new("http://localhost:8080".to_string()));
 /// let client_clone = client.clone(); // Safe to clone and use in different threads
 /// ```
+#[derive(Clone)]
 pub struct FaasClient {
-    client: Client,
+    client: HttpClient,
+    /// Sent with every request: the API key and namespace
+    headers: reqwest::header::HeaderMap,
     base_url: String,
     runtime: Runtime,
     cache_enabled: bool,
     metrics: Arc<RwLock<ClientMetrics>>,
+    payload_ref_threshold: usize,
+    /// Set once the gateway turns out to lack `/api/v1/payloads`
+    payload_refs_unsupported: Arc<std::sync::atomic::AtomicBool>,
+    /// Set by [`FaasClient::enable_offline_spool`]; shared by clones
+    spool: Arc<std::sync::OnceLock<Arc<spool::Spool>>>,
+    /// Further attempts [`FaasClient::execute`] makes, see [`FaasClient::with_retries`]
+    retries: u32,
 }
 
 /// Client-side metrics for monitoring
@@ -201,21 +415,242 @@ struct ClientMetrics {
 }
 
 /// Function execution request with runtime selection
-#[derive(Debug, Serialize, Default, Clone)]
+///
+/// Build one with [`ExecuteRequest::builder`] or [`FaasClient::execute_with`] rather than
+/// a struct literal: fields are added to it from release to release, and a literal without
+/// `..Default::default()` stops compiling each time.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct ExecuteRequest {
     pub command: String,
     pub image: Option<String>,
     pub runtime: Option<Runtime>,
+    /// Weighed when `runtime` is `Auto`
+    pub isolation: Option<IsolationLevel>,
     pub mode: Option<String>,
-    pub env_vars: Option<Vec<(String, String)>>,
+    pub env_vars: Option<Vec<EnvVar>>,
+    /// Directory the command runs in, created if the image lacks it
     pub working_dir: Option<String>,
+    /// Run as this `uid[:gid]` or user name instead of the image's user. A user the image
+    /// doesn't have is a 422 `UnknownUser`. Docker only.
+    pub user: Option<String>,
     pub timeout_ms: Option<u64>,
     pub memory_mb: Option<u32>,
     pub cpu_cores: Option<u8>,
+    /// `cached` mode: where the gateway keeps the result. Unset, it hashes the image,
+    /// command, environment and payload.
     pub cache_key: Option<String>,
+    /// `cached` mode: run even if a result is cached, and cache the new one. Set by clients
+    /// with [caching off](FaasClient::with_caching).
+    pub no_cache: Option<bool>,
     pub snapshot_id: Option<String>,
     pub branch_from: Option<String>,
     pub payload: Option<Vec<u8>>,
+    /// Hash of a payload already stored on the gateway; set by the client for large payloads
+    pub payload_ref: Option<String>,
+    /// Path and contents of files placed in the sandbox before the command runs; relative
+    /// paths are taken from `working_dir`. Docker only.
+    pub input_files: Option<Vec<(String, Vec<u8>)>>,
+    /// Overrides for the gateway's default ulimits (nproc 256, nofile 1024)
+    pub ulimits: Option<Vec<Ulimit>>,
+    pub shm_size_mb: Option<u64>,
+    pub tmpfs: Option<Vec<TmpfsMount>>,
+    /// Preferred CPU architecture; the gateway answers 422 if no host can run the image
+    pub arch: Option<String>,
+    /// GPUs passed through to the container. The gateway answers 422 if its host has no
+    /// NVIDIA container runtime, which [`HealthStatus::gpu`] reports.
+    pub gpu_count: Option<u32>,
+    /// `Some(false)` runs the command without a network
+    pub network_enabled: Option<bool>,
+    /// The only hosts the command may reach; Docker only
+    pub allowed_hosts: Option<Vec<String>>,
+    /// Execution group to report to, from [`FaasClient::create_group`]
+    pub group_id: Option<String>,
+    /// Names this run within its group, so [`FaasClient::compare_groups`] can pair it with
+    /// the same item in a baseline group
+    pub item_key: Option<String>,
+    /// Timezone, locale and fake clock for reproducible runs
+    pub environment_overrides: Option<EnvOverrides>,
+    /// Labels the gateway's kill switch rules can select on
+    pub labels: Option<HashMap<String, String>>,
+    /// Sent unchanged with every attempt, so the gateway can tell a retry from a new
+    /// request. Spooled requests, and those sent by a client [with
+    /// retries](FaasClient::with_retries), get one generated if they have none.
+    pub idempotency_key: Option<String>,
+    /// `persistent` mode: seconds until the gateway reaps the execution, unless extended
+    /// with [`FaasClient::extend_instance_ttl`]
+    pub ttl_secs: Option<u64>,
+}
+
+impl ExecuteRequest {
+    /// Python code, sent on stdin to avoid quoting issues
+    pub fn python(code: &str) -> Self {
+        Self::interpreted("python", "python:3.11-slim", code)
+    }
+
+    /// JavaScript code, sent to node on stdin
+    pub fn javascript(code: &str) -> Self {
+        Self::interpreted("node", "node:20-slim", code)
+    }
+
+    /// A Bash script, sent to `bash -s` on stdin so it needs no quoting. Commands in the
+    /// script that read stdin see the lines after them.
+    pub fn bash(script: &str) -> Self {
+        Self::interpreted("bash -s", "bash:5", script)
+    }
+
+    fn interpreted(command: &str, image: &str, code: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            image: Some(image.to_string()),
+            timeout_ms: Some(30000),
+            payload: Some(code.as_bytes().to_vec()),
+            ..Default::default()
+        }
+    }
+
+    /// A Rust program, compiled with `rustc` and run
+    pub fn rust(code: &str) -> Self {
+        Self::language(Language::Rust, code, &[])
+    }
+
+    /// A Go program, built and run
+    pub fn go(code: &str) -> Self {
+        Self::language(Language::Go, code, &[])
+    }
+
+    /// `code` in `language`, with the packages in `deps` installed first: pip or npm
+    /// package names, crates as `name` or `name@version`, Go modules as `go get` takes them.
+    /// The source is uploaded as a file in `/work` rather than spliced into the command.
+    /// The run is `cached`, so the same code and deps run again answer from the cache
+    /// without installing or compiling anything.
+    pub fn language(language: Language, code: &str, deps: &[&str]) -> Self {
+        let quoted: Vec<String> = deps.iter().map(|dep| shell_quote(dep)).collect();
+        let quoted = quoted.join(" ");
+        let (image, files, command) = match language {
+            Language::Python => (
+                "python:3.11-slim",
+                vec![("main.py".to_string(), code.as_bytes().to_vec())],
+                if deps.is_empty() {
+                    "python main.py".to_string()
+                } else {
+                    format!(
+                        "pip install --quiet --disable-pip-version-check {quoted} && python main.py"
+                    )
+                },
+            ),
+            Language::Node => (
+                "node:20-slim",
+                vec![("main.js".to_string(), code.as_bytes().to_vec())],
+                if deps.is_empty() {
+                    "node main.js".to_string()
+                } else {
+                    format!("npm install --silent --no-save {quoted} && node main.js")
+                },
+            ),
+            Language::Rust if deps.is_empty() => (
+                "rust:1-slim",
+                vec![("main.rs".to_string(), code.as_bytes().to_vec())],
+                "rustc -O -o main main.rs && ./main".to_string(),
+            ),
+            Language::Rust => (
+                "rust:1-slim",
+                vec![
+                    ("Cargo.toml".to_string(), cargo_manifest(deps).into_bytes()),
+                    ("src/main.rs".to_string(), code.as_bytes().to_vec()),
+                ],
+                "cargo run --quiet --release".to_string(),
+            ),
+            Language::Go if deps.is_empty() => (
+                "golang:1.22",
+                vec![("main.go".to_string(), code.as_bytes().to_vec())],
+                "go build -o main main.go && ./main".to_string(),
+            ),
+            Language::Go => (
+                "golang:1.22",
+                vec![
+                    (
+                        "go.mod".to_string(),
+                        b"module snippet\n\ngo 1.22\n".to_vec(),
+                    ),
+                    ("main.go".to_string(), code.as_bytes().to_vec()),
+                ],
+                format!("go get {quoted} && go build -o main . && ./main"),
+            ),
+        };
+        Self {
+            command,
+            image: Some(image.to_string()),
+            mode: Some("cached".to_string()),
+            working_dir: Some("/work".to_string()),
+            input_files: Some(files),
+            // Room for installing and compiling
+            timeout_ms: Some(120_000),
+            ..Default::default()
+        }
+    }
+}
+
+const CARGO_PACKAGE: &str = r#"[package]
+name = "snippet"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+"#;
+
+/// A manifest for a binary crate depending on `deps`, each `name` or `name@version`
+fn cargo_manifest(deps: &[&str]) -> String {
+    let mut manifest = CARGO_PACKAGE.to_string();
+    for dep in deps {
+        let (name, version) = dep.split_once('@').unwrap_or((dep, "*"));
+        manifest.push_str(&format!("{name} = \"{version}\"\n"));
+    }
+    manifest
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// POSIX resource limit applied inside the sandbox (`nofile`, `nproc`, `fsize`, ...)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+impl Ulimit {
+    pub fn new(name: impl Into<String>, soft: i64, hard: i64) -> Self {
+        Self {
+            name: name.into(),
+            soft,
+            hard,
+        }
+    }
+}
+
+/// Size-capped tmpfs mounted inside the sandbox
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TmpfsMount {
+    pub path: String,
+    pub size_mb: u64,
+}
+
+/// Clock and locale the sandboxed process sees
+///
+/// `fake_time` sets the wall clock when the command starts; it then keeps running. It needs
+/// libfaketime, so Firecracker hosts without vsock reject it with `IncompatibleFeature`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct EnvOverrides {
+    /// IANA zone name, e.g. `Europe/Berlin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// e.g. `de_DE.UTF-8`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fake_time: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 /// Advanced execution request (now uses same structure as ExecuteRequest)
@@ -241,6 +676,61 @@ pub struct ExecuteResponse {
     pub stdout: String,
     pub stderr: String,
     pub duration_ms: u64,
+    /// The gateway answered from its result cache
+    #[serde(default)]
+    pub cache_hit: bool,
+    /// Key of the cache entry, for `cached` executions
+    #[serde(default)]
+    pub cache_key: Option<String>,
+    /// Runtime the execution ran on
+    #[serde(default)]
+    pub runtime: Option<Runtime>,
+    /// Why: `requested`, `default`, `hardware_isolation`, `memory_threshold`,
+    /// `untrusted_image`, or `kvm_unavailable_fallback` for a VM the host couldn't start
+    #[serde(default)]
+    pub runtime_reason: Option<String>,
+    #[serde(default)]
+    pub diagnostics: Option<ExecutionDiagnostics>,
+    /// What the execution consumed, where the runtime measured it
+    #[serde(default)]
+    pub usage: Option<ExecutionUsage>,
+    /// The output went over the gateway's limit and `stdout`/`stderr` stop short;
+    /// [`FaasClient::stream_logs`] fetches all of it
+    #[serde(default)]
+    pub truncated: bool,
+    /// Bytes written to stdout and stderr together, when `truncated`
+    #[serde(default)]
+    pub total_bytes: Option<u64>,
+    /// Checkpoint of a `checkpointed` execution still running at its timeout; pass it as
+    /// `snapshot_id` to resume the process where it stopped
+    #[serde(default)]
+    pub snapshot_id: Option<String>,
+}
+
+/// How the gateway actually ran an execution
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ExecutionDiagnostics {
+    #[serde(default)]
+    pub limits: Option<AppliedLimits>,
+    /// Overrides the sandbox ran with
+    #[serde(default)]
+    pub environment_overrides: Option<EnvOverrides>,
+    /// Each env var the sandbox got and the layer that set it: `platform`, `function`,
+    /// `template`, `request` or `secret`
+    #[serde(default)]
+    pub env: Option<std::collections::BTreeMap<String, String>>,
+}
+
+/// Resource limits the execution ran with, after gateway defaults were applied
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct AppliedLimits {
+    pub ulimits: Vec<Ulimit>,
+    pub shm_size_mb: u64,
+    #[serde(default)]
+    pub tmpfs: Vec<TmpfsMount>,
+    /// Most processes and threads the sandbox could hold
+    #[serde(default)]
+    pub pids_limit: Option<i64>,
 }
 
 /// Snapshot management
@@ -249,14 +739,43 @@ pub struct CreateSnapshotRequest {
     pub name: String,
     pub container_id: String,
     pub description: Option<String>,
+    /// Labels to find the snapshot by with [`FaasClient::list_snapshots_tagged`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Execution group that counts the snapshot as a member
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    /// Return only once the snapshot is ready instead of while it is still `creating`
+    #[serde(skip)]
+    pub wait: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SnapshotResponse {
+    #[serde(alias = "id")]
     pub snapshot_id: String,
     pub name: String,
+    /// Preflight estimate while creating, final size once ready
     pub size_bytes: u64,
     pub created_at: String,
+    /// Container the snapshot was committed from
+    #[serde(default, alias = "container_id")]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// `creating`, `ready`, `failed`, ...
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub progress: Option<SnapshotProgress>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnapshotProgress {
+    /// `preflight`, `committing`, `done` or `failed`
+    pub phase: String,
+    pub bytes: u64,
+    pub percent_estimate: Option<f64>,
 }
 
 /// Instance management
@@ -266,33 +785,82 @@ pub struct CreateInstanceRequest {
     pub image: String,
     pub cpu_cores: Option<u32>,
     pub memory_mb: Option<u32>,
+    /// Mount a workspace volume kept under `name`; an instance created again with the
+    /// same name finds the files the last one left
     pub persistent: Option<bool>,
+    /// Where the workspace is mounted; the gateway uses `/workspace` when unset
+    pub workspace_path: Option<String>,
+    /// Ports to publish on the gateway's host
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<PortMapping>,
+}
+
+/// A port of an instance and the host port it is published on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub container_port: u16,
+    /// The gateway's host picks a free port when unset
+    pub host_port: Option<u16>,
+    #[serde(default)]
+    pub protocol: PortProtocol,
+}
+
+impl PortMapping {
+    pub fn tcp(container_port: u16) -> Self {
+        Self {
+            container_port,
+            host_port: None,
+            protocol: PortProtocol::Tcp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortProtocol {
+    #[default]
+    Tcp,
+    Udp,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct InstanceResponse {
+    #[serde(alias = "id")]
     pub instance_id: String,
     pub status: String,
     pub created_at: String,
+    /// URL of each published port, keyed `8000/tcp`
     pub endpoints: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub ports: Vec<PortMapping>,
+    /// When the gateway stops the instance unless its lease is extended
+    pub expires_at: Option<String>,
+    /// The volume a persistent instance's workspace lives on
+    #[serde(default)]
+    pub workspace: Option<InstanceWorkspace>,
+    /// The tenant the instance belongs to
+    #[serde(default)]
+    pub tenant: Option<String>,
 }
 
-/// Fork execution branch
-#[derive(Debug, Serialize, Clone)]
-pub struct ForkBranch {
-    pub id: String,
-    pub command: String,
-    pub env_vars: Option<Vec<(String, String)>>,
-    pub weight: Option<f64>,
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct InstanceWorkspace {
+    pub volume: String,
+    pub path: String,
 }
 
-/// Fork execution strategy
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "lowercase")]
-pub enum ForkStrategy {
-    Parallel,   // Run all branches in parallel
-    Fastest,    // Select fastest completion
-    Sequential, // Run sequentially
+/// A persistent workspace's volume on the gateway's host
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VolumeInfo {
+    pub name: String,
+    /// The instance name the volume is kept for
+    pub workspace: String,
+    /// The tenant whose instances mount it, shown to keys that see every tenant
+    #[serde(default)]
+    pub tenant: Option<String>,
+    pub size_bytes: Option<u64>,
+    /// Containers mounting it right now
+    pub ref_count: Option<u64>,
 }
 
 /// Container prewarming request
@@ -305,14 +873,6 @@ pub struct PrewarmRequest {
     pub cpu_cores: Option<u32>,
 }
 
-/// Fork execution result
-#[derive(Debug, Deserialize)]
-pub struct ForkResult {
-    pub results: Vec<serde_json::Value>,
-    pub selected_branch: Option<String>,
-    pub selection_reason: Option<String>,
-}
-
 /// Performance metrics
 #[derive(Debug, Deserialize)]
 pub struct PerformanceMetrics {
@@ -321,16 +881,102 @@ pub struct PerformanceMetrics {
     pub cache_hit_rate: f64,
     pub active_containers: u32,
     pub active_instances: u32,
+    #[serde(default)]
     pub memory_usage_mb: u64,
+    #[serde(default)]
     pub cpu_usage_percent: f64,
+    #[serde(default)]
+    pub total_requests: u64,
+    #[serde(default)]
+    pub docker_executions: u64,
+    #[serde(default)]
+    pub vm_executions: u64,
+    #[serde(default)]
+    pub latency: LatencyMetrics,
+    /// Executions and failures by image
+    #[serde(default)]
+    pub images: Vec<ImageMetrics>,
+    /// Executions and failures by tenant; only the key's own unless it is an admin key
+    #[serde(default)]
+    pub tenants: Vec<TenantMetrics>,
+}
+
+/// Execution latencies; a cold start is one a container or VM was started for
+#[derive(Debug, Default, Deserialize)]
+pub struct LatencyMetrics {
+    #[serde(default)]
+    pub overall: LatencySummary,
+    #[serde(default)]
+    pub cold: LatencySummary,
+    #[serde(default)]
+    pub warm: LatencySummary,
+    #[serde(default)]
+    pub by_runtime: Vec<RuntimeLatency>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Latencies of one runtime's cold or warm starts
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuntimeLatency {
+    pub runtime: String,
+    /// `cold` or `warm`
+    pub start: String,
+    #[serde(flatten)]
+    pub summary: LatencySummary,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImageMetrics {
+    pub image: String,
+    /// Including failures
+    pub executions: u64,
+    pub failures: u64,
+    pub failure_rate: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantMetrics {
+    pub tenant: String,
+    /// Including failures
+    pub executions: u64,
+    pub failures: u64,
+    pub failure_rate: f64,
 }
 
 /// Health status
 #[derive(Debug, Deserialize)]
 pub struct HealthStatus {
+    /// `healthy`, or `degraded` when a runtime or warm pool is failing. A gateway where no
+    /// runtime can run anything answers 503, which [`FaasClient::health_check`] returns as
+    /// an error.
     pub status: String,
     pub timestamp: String,
-    pub components: Option<HashMap<String, String>>,
+    /// Probe result of each runtime, by name: `docker` and `firecracker`
+    pub components: Option<HashMap<String, ComponentHealth>>,
+    /// Executions can ask for GPUs with [`ExecuteRequest::gpu_count`]
+    #[serde(default)]
+    pub gpu: bool,
+}
+
+/// One runtime's state, as the gateway last probed it
+#[derive(Debug, Clone, Deserialize)]
+pub struct ComponentHealth {
+    /// `healthy` or `unavailable`
+    pub status: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Why it is unavailable
+    #[serde(default)]
+    pub error: Option<String>,
 }
 
 impl FaasClient {
@@ -383,14 +1029,16 @@ impl FaasClient {
     /// ```
     pub fn with_runtime(base_url: String, runtime: Runtime) -> Self {
         Self {
-            client: Client::builder()
-                .timeout(Duration::from_secs(60))
-                .build()
-                .expect("Failed to create HTTP client"),
+            client: http_client(reqwest::header::HeaderMap::new()),
+            headers: reqwest::header::HeaderMap::new(),
             base_url,
             runtime,
             cache_enabled: true,
             metrics: Arc::new(RwLock::new(ClientMetrics::default())),
+            payload_ref_threshold: DEFAULT_PAYLOAD_REF_THRESHOLD,
+            payload_refs_unsupported: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            spool: Arc::default(),
+            retries: 0,
         }
     }
 
@@ -406,12 +1054,54 @@ impl FaasClient {
         self
     }
 
-    /// Enable/disable caching
+    /// Enable/disable caching; disabled, `cached` mode requests always run
     pub fn with_caching(mut self, enabled: bool) -> Self {
         self.cache_enabled = enabled;
         self
     }
 
+    /// Send `key` as `X-Api-Key` with every request, for gateways that require API keys
+    ///
+    /// # Panics
+    ///
+    /// If `key` has characters a header can't carry; keys the gateway issues never do.
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        let mut value =
+            reqwest::header::HeaderValue::from_str(key).expect("API key is not a valid header");
+        value.set_sensitive(true);
+        self.headers.insert("x-api-key", value);
+        self.client = http_client(self.headers.clone());
+        self
+    }
+
+    /// Act for the tenant `namespace`: list, create and reach its instances, snapshots and
+    /// warm pools. Only admin API keys may choose; the gateway sets the tenant of any other
+    /// key itself.
+    ///
+    /// # Panics
+    ///
+    /// If `namespace` has characters a header can't carry.
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        let value = reqwest::header::HeaderValue::from_str(namespace)
+            .expect("namespace is not a valid header");
+        self.headers.insert("x-faas-tenant", value);
+        self.client = http_client(self.headers.clone());
+        self
+    }
+
+    /// Retry [`FaasClient::execute`] up to `retries` times when the gateway can't be reached
+    /// or the connection breaks before the answer arrives
+    ///
+    /// Requests without an `idempotency_key` get a generated one, so the gateway runs the
+    /// command at most once however many attempts reach it. The gateway keeps running an
+    /// execution whose client went away, and holds its key until it ends; an attempt that
+    /// finds it still running asks again every second until it can have its answer, without
+    /// counting against `retries`.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
     /// Execute a command or script with the FaaS platform
     ///
     /// This is the primary method for executing code on the platform. It supports
@@ -431,22 +1121,18 @@ impl FaasClient {
     ///
     /// # Examples
     ///
-    /// ```rust
-    /// use faas_sdk::{FaasClient, ExecuteRequest};
+    /// ```rust,no_run
+    /// use faas_sdk::{ExecuteRequest, FaasClient};
     ///
     /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
     /// let client = FaasClient::new("http://localhost:8080".to_string());
     ///
     /// // Simple shell command
-    /// let result = client.execute(ExecuteRequest {
-    ///     command: "echo 'Hello, World!'".to_string(),
-    ///     image: Some("alpine:latest".to_string()),
-    ///     env_vars: None,
-    ///     working_dir: None,
-    ///     timeout_ms: Some(5000),
-    ///     cache_key: None,
-    ///     runtime: None, // Uses client default
-    /// }).await?;
+    /// let request = ExecuteRequest::builder("echo 'Hello, World!'")
+    ///     .image("alpine:latest")
+    ///     .timeout_ms(5000)
+    ///     .build()?;
+    /// let result = client.execute(request).await?;
     ///
     /// println!("Output: {}", result.stdout);
     /// println!("Execution time: {}ms", result.duration_ms);
@@ -456,59 +1142,102 @@ impl FaasClient {
     ///
     /// ## Advanced Usage with Environment Variables
     ///
-    /// ```rust
-    /// use faas_sdk::{FaasClient, ExecuteRequest, Runtime};
+    /// ```rust,no_run
+    /// use faas_sdk::{FaasClient, Runtime};
     ///
     /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
     /// let client = FaasClient::new("http://localhost:8080".to_string());
     ///
-    /// let result = client.execute(ExecuteRequest {
-    ///     command: "python process_data.py".to_string(),
-    ///     image: Some("python:3.11-slim".to_string()),
-    ///     env_vars: Some(vec![
-    ///         ("API_KEY".to_string(), "secret123".to_string()),
-    ///         ("DEBUG".to_string(), "true".to_string()),
-    ///     ]),
-    ///     working_dir: Some("/app".to_string()),
-    ///     timeout_ms: Some(30000),
-    ///     cache_key: Some("data-processing-v1".to_string()),
-    ///     runtime: Some(Runtime::Docker),
-    /// }).await?;
+    /// let result = client
+    ///     .execute_with(|b| {
+    ///         b.command("python process_data.py")
+    ///             .image("python:3.11-slim")
+    ///             .env("API_KEY", "secret123")
+    ///             .env("DEBUG", "true")
+    ///             .working_dir("/app")
+    ///             .timeout_ms(30000)
+    ///             .cached("data-processing-v1")
+    ///             .runtime(Runtime::Docker)
+    ///     })
+    ///     .await?;
     /// # Ok(())
     /// # }
     /// ```
     pub async fn execute(&self, mut request: ExecuteRequest) -> Result<ExecuteResponse, SdkError> {
-        let start = Instant::now();
+        self.apply_defaults(&mut request);
+        if self.retries == 0 {
+            return self.send_execute(request).await;
+        }
+        request
+            .idempotency_key
+            .get_or_insert_with(|| uuid::Uuid::new_v4().to_string());
+        let mut backoff = RETRY_BACKOFF;
+        let mut retries = self.retries;
+        loop {
+            match self.send_execute(request.clone()).await {
+                // The run ends by its timeout, so this doesn't wait forever
+                Err(e) if in_flight(&e) => tokio::time::sleep(IN_FLIGHT_POLL_INTERVAL).await,
+                Err(e) if retries > 0 && retryable(&e) => {
+                    retries -= 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
 
-        // Apply runtime if not specified
+    /// A builder for running `command`, to pass to [`FaasClient::execute`] once built
+    pub fn request(&self, command: impl Into<String>) -> ExecuteRequestBuilder {
+        ExecuteRequestBuilder::new(command)
+    }
+
+    /// [`FaasClient::execute`] the request `build` makes of an empty builder; a request the
+    /// builder refuses is [`SdkError::Build`] and never sent
+    ///
+    /// ```rust,no_run
+    /// # async fn example(client: faas_sdk::FaasClient) -> Result<(), faas_sdk::SdkError> {
+    /// let result = client
+    ///     .execute_with(|b| b.command("uname -a").image("alpine:latest").memory_mb(128))
+    ///     .await?;
+    /// println!("{}", result.stdout);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn execute_with(
+        &self,
+        build: impl FnOnce(ExecuteRequestBuilder) -> ExecuteRequestBuilder,
+    ) -> Result<ExecuteResponse, SdkError> {
+        let request = build(ExecuteRequestBuilder::default()).build()?;
+        self.execute(request).await
+    }
+
+    /// Fill in the client's runtime and, with caching off, `no_cache`
+    pub(crate) fn apply_defaults(&self, request: &mut ExecuteRequest) {
         if request.runtime.is_none() {
             request.runtime = Some(self.runtime.clone());
         }
-
-        // Apply cache key if caching enabled
-        if self.cache_enabled && request.cache_key.is_none() {
-            request.cache_key = Some(format!("{:x}", md5::compute(&request.command)));
+        if !self.cache_enabled && request.no_cache.is_none() {
+            request.no_cache = Some(true);
         }
+    }
 
-        let url = format!("{}/api/v1/execute", self.base_url);
+    /// POST an execution as it is, with no client defaults applied
+    pub(crate) async fn send_execute(
+        &self,
+        mut request: ExecuteRequest,
+    ) -> Result<ExecuteResponse, SdkError> {
+        let start = Instant::now();
+        self.reference_payload(&mut request).await?;
 
-        // Handle payload by sending as stdin directly in the JSON request
-        let response = if let Some(_payload) = &request.payload {
-            // For now, just use JSON and let the server handle stdin
-            self.client
-                .post(&url)
-                .header("Content-Type", "application/json")
-                .json(&request)
-                .send()
-                .await?
-        } else {
-            self.client
-                .post(&url)
-                .header("Content-Type", "application/json")
-                .json(&request)
-                .send()
-                .await?
-        };
+        let url = format!("{}/api/v1/execute", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
 
         // Update metrics
         let mut metrics = self.metrics.write().await;
@@ -517,16 +1246,11 @@ impl FaasClient {
 
         if !response.status().is_success() {
             metrics.errors += 1;
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         let result: ExecuteResponse = response.json().await?;
-
-        // Check if it was a cache hit (heuristic: very fast response)
-        if start.elapsed().as_millis() < 10 {
+        if result.cache_hit {
             metrics.cache_hits += 1;
         }
 
@@ -548,23 +1272,20 @@ impl FaasClient {
     ///
     /// # Examples
     ///
-    /// ```rust
-    /// use faas_sdk::{FaasClient, AdvancedExecuteRequest, ExecutionMode};
+    /// ```rust,no_run
+    /// use faas_sdk::{AdvancedExecuteRequest, FaasClient};
     ///
     /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
     /// let client = FaasClient::new("http://localhost:8080".to_string());
     ///
-    /// let result = client.execute_advanced(AdvancedExecuteRequest {
-    ///     command: "python train_model.py".to_string(),
-    ///     image: "pytorch/pytorch:latest".to_string(),
-    ///     mode: Some("cached".to_string()),
-    ///     env_vars: Some(vec![
-    ///         ("GPU_MEMORY".to_string(), "8GB".to_string())
-    ///     ]),
-    ///     memory_mb: Some(4096),
-    ///     cpu_cores: Some(4),
-    ///     use_snapshots: Some(true),
-    /// }).await?;
+    /// let request = AdvancedExecuteRequest::builder("python train_model.py")
+    ///     .image("pytorch/pytorch:latest")
+    ///     .mode("cached")
+    ///     .env("GPU_MEMORY", "8GB")
+    ///     .memory_mb(4096)
+    ///     .cpu_cores(4)
+    ///     .build()?;
+    /// let result = client.execute_advanced(request).await?;
     ///
     /// println!("Training completed in {}ms", result.duration_ms);
     /// # Ok(())
@@ -578,10 +1299,7 @@ impl FaasClient {
         let response = self.client.post(&url).json(&request).send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(response.json().await?)
@@ -599,27 +1317,31 @@ impl FaasClient {
     ///
     /// # Returns
     ///
-    /// Returns `SnapshotResponse` with snapshot ID, size, and creation timestamp.
+    /// Returns `SnapshotResponse` with snapshot ID, size, and creation timestamp. Large
+    /// containers take a while to commit; unless `wait` is set the snapshot comes back
+    /// `creating`, and [`get_snapshot`](Self::get_snapshot) reports its progress.
     ///
     /// # Examples
     ///
-    /// ```rust
-    /// use faas_sdk::{FaasClient, CreateSnapshotRequest};
+    /// ```rust,no_run
+    /// use faas_sdk::{CreateSnapshotRequest, FaasClient};
     ///
     /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
     /// let client = FaasClient::new("http://localhost:8080".to_string());
     ///
     /// // First, create a container with some state
-    /// let execution = client.execute(ExecuteRequest {
-    ///     command: "python setup_model.py".to_string(),
-    ///     ..Default::default()
-    /// }).await?;
+    /// let execution = client
+    ///     .execute_with(|b| b.command("python setup_model.py"))
+    ///     .await?;
     ///
     /// // Create snapshot of the initialized container
     /// let snapshot = client.create_snapshot(CreateSnapshotRequest {
     ///     name: "model-initialized".to_string(),
     ///     container_id: execution.request_id,
     ///     description: Some("Model loaded and ready for inference".to_string()),
+    ///     tags: vec!["bert".to_string()],
+    ///     group_id: None,
+    ///     wait: true,
     /// }).await?;
     ///
     /// println!("Created snapshot {} ({} bytes)", snapshot.name, snapshot.size_bytes);
@@ -639,42 +1361,64 @@ impl FaasClient {
     ) -> Result<SnapshotResponse, SdkError> {
         let url = format!("{}/api/v1/snapshots", self.base_url);
         let response = self.client.post(&url).json(&request).send().await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+        let snapshot: SnapshotResponse = json_or_error(response).await?;
+        if !request.wait {
+            return Ok(snapshot);
+        }
+        loop {
+            let current = self.get_snapshot(&snapshot.snapshot_id).await?;
+            match current.status.as_deref() {
+                Some("creating") => tokio::time::sleep(SNAPSHOT_POLL_INTERVAL).await,
+                Some("failed") => {
+                    return Err(SdkError::Api {
+                        status: 200,
+                        code: "SnapshotFailed".to_string(),
+                        message: format!("snapshot {} failed", current.snapshot_id),
+                    })
+                }
+                _ => return Ok(current),
+            }
         }
+    }
 
-        Ok(response.json().await?)
+    /// Current state of a snapshot, including commit progress while it is `creating`
+    pub async fn get_snapshot(&self, snapshot_id: &str) -> Result<SnapshotResponse, SdkError> {
+        let url = format!("{}/api/v1/snapshots/{}", self.base_url, snapshot_id);
+        let response = self.client.get(&url).send().await?;
+        json_or_error(response).await
     }
 
-    /// List available snapshots
+    /// List available snapshots, oldest first
     pub async fn list_snapshots(&self) -> Result<Vec<SnapshotResponse>, SdkError> {
+        self.list_snapshots_tagged(&[]).await
+    }
+
+    /// List the snapshots carrying every one of `tags`
+    pub async fn list_snapshots_tagged(
+        &self,
+        tags: &[&str],
+    ) -> Result<Vec<SnapshotResponse>, SdkError> {
         let url = format!("{}/api/v1/snapshots", self.base_url);
-        let response = self.client.get(&url).send().await?;
+        let mut request = self.client.get(&url);
+        if !tags.is_empty() {
+            request = request.query(&[("tag", tags.join(","))]);
+        }
+        let response = request.send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(response.json().await?)
     }
 
-    /// Delete snapshot
+    /// Delete a snapshot and the image or disk it is stored in
     pub async fn delete_snapshot(&self, snapshot_id: &str) -> Result<(), SdkError> {
         let url = format!("{}/api/v1/snapshots/{}", self.base_url, snapshot_id);
         let response = self.client.delete(&url).send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(())
@@ -689,10 +1433,7 @@ impl FaasClient {
         let response = self.client.post(&url).json(&request).send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(response.json().await?)
@@ -704,55 +1445,155 @@ impl FaasClient {
         let response = self.client.get(&url).send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Run `command` inside a persistent instance
+    ///
+    /// Execs share the instance's container, so files one writes are there for the next.
+    pub async fn exec_in_instance(
+        &self,
+        instance_id: &str,
+        command: &str,
+    ) -> Result<ExecuteResponse, SdkError> {
+        self.session(instance_id).exec(command).await
+    }
+
+    /// Keep a live instance for `ttl_secs` more seconds
+    pub async fn extend_instance_ttl(
+        &self,
+        instance_id: &str,
+        ttl_secs: u64,
+    ) -> Result<InstanceResponse, SdkError> {
+        let url = format!("{}/api/v1/instances/{}/ttl", self.base_url, instance_id);
+        let body = serde_json::json!({ "ttl_secs": ttl_secs });
+        let response = self.client.post(&url).json(&body).send().await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Publish another port of a running instance; the response carries its endpoint
+    pub async fn expose_port(
+        &self,
+        instance_id: &str,
+        port: PortMapping,
+    ) -> Result<InstanceResponse, SdkError> {
+        let url = format!("{}/api/v1/instances/{}/ports", self.base_url, instance_id);
+        let response = self.client.post(&url).json(&port).send().await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Freeze a running instance; execs are refused with 409 until it is resumed
+    pub async fn pause_instance(&self, instance_id: &str) -> Result<InstanceResponse, SdkError> {
+        self.instance_action(instance_id, "pause").await
+    }
+
+    /// Thaw a paused instance
+    pub async fn resume_instance(&self, instance_id: &str) -> Result<InstanceResponse, SdkError> {
+        self.instance_action(instance_id, "resume").await
+    }
+
+    async fn instance_action(
+        &self,
+        instance_id: &str,
+        action: &str,
+    ) -> Result<InstanceResponse, SdkError> {
+        let url = format!(
+            "{}/api/v1/instances/{}/{}",
+            self.base_url, instance_id, action
+        );
+        let response = self.client.post(&url).send().await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
         }
 
         Ok(response.json().await?)
     }
 
-    /// Stop instance
+    /// Stop instance, killing what hasn't exited after the gateway's grace period
     pub async fn stop_instance(&self, instance_id: &str) -> Result<(), SdkError> {
+        self.stop_instance_within(instance_id, None).await
+    }
+
+    /// Stop instance, giving its processes `grace_secs` between SIGTERM and SIGKILL
+    pub async fn stop_instance_within(
+        &self,
+        instance_id: &str,
+        grace_secs: Option<u64>,
+    ) -> Result<(), SdkError> {
         let url = format!("{}/api/v1/instances/{}/stop", self.base_url, instance_id);
-        let response = self.client.post(&url).send().await?;
+        let body = serde_json::json!({ "grace_secs": grace_secs });
+        let response = self.client.post(&url).json(&body).send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(())
     }
 
-    /// Delete instance
+    /// Delete instance, keeping its workspace volume for the next instance of that name
     pub async fn delete_instance(&self, instance_id: &str) -> Result<(), SdkError> {
+        self.remove_instance(instance_id, false).await
+    }
+
+    /// Delete instance along with its workspace volume
+    pub async fn delete_instance_and_volume(&self, instance_id: &str) -> Result<(), SdkError> {
+        self.remove_instance(instance_id, true).await
+    }
+
+    async fn remove_instance(
+        &self,
+        instance_id: &str,
+        delete_volume: bool,
+    ) -> Result<(), SdkError> {
         let url = format!("{}/api/v1/instances/{}", self.base_url, instance_id);
-        let response = self.client.delete(&url).send().await?;
+        let response = self
+            .client
+            .delete(&url)
+            .query(&[("delete_volume", delete_volume)])
+            .send()
+            .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(())
     }
 
+    /// Workspace volumes of persistent instances, with their size on disk
+    pub async fn list_volumes(&self) -> Result<Vec<VolumeInfo>, SdkError> {
+        let url = format!("{}/api/v1/volumes", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        Ok(response.json().await?)
+    }
+
     /// Get performance metrics
     pub async fn get_metrics(&self) -> Result<PerformanceMetrics, SdkError> {
         let url = format!("{}/api/v1/metrics", self.base_url);
         let response = self.client.get(&url).send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(response.json().await?)
@@ -764,10 +1605,7 @@ impl FaasClient {
         let response = self.client.get(&url).send().await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(response.json().await?)
@@ -820,69 +1658,71 @@ impl FaasClient {
     /// - **Output Capture**: Both stdout and stderr captured
     /// - **Timeout Protection**: Prevents runaway executions
     pub async fn run_python(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
-        // Send code via stdin to avoid quoting issues
-        self.execute(ExecuteRequest {
-            command: "python".to_string(),
-            image: Some("python:3.11-slim".to_string()),
-            runtime: Some(self.runtime.clone()),
-            timeout_ms: Some(30000),
-            cache_key: Some(format!("{:x}", md5::compute(code.as_bytes()))),
-            payload: Some(code.as_bytes().to_vec()),
-            ..Default::default()
-        })
-        .await
+        self.execute(ExecuteRequest::python(code)).await
     }
 
     /// Execute JavaScript/Node.js code
     pub async fn run_javascript(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
-        // Send code via stdin to avoid quoting issues
-        self.execute(ExecuteRequest {
-            command: "node".to_string(),
-            image: Some("node:20-slim".to_string()),
-            runtime: Some(self.runtime.clone()),
-            timeout_ms: Some(30000),
-            cache_key: Some(format!("{:x}", md5::compute(code.as_bytes()))),
-            payload: Some(code.as_bytes().to_vec()),
-            ..Default::default()
-        })
-        .await
+        self.execute(ExecuteRequest::javascript(code)).await
     }
 
     /// Execute Bash script
     pub async fn run_bash(&self, script: &str) -> Result<ExecuteResponse, SdkError> {
-        self.execute(ExecuteRequest {
-            command: format!("bash -c \"{script}\""),
-            image: Some("alpine:latest".to_string()),
-            runtime: Some(self.runtime.clone()),
-            timeout_ms: Some(30000),
-            cache_key: Some(format!("{:x}", md5::compute(script.as_bytes()))),
-            ..Default::default()
-        })
-        .await
+        self.execute(ExecuteRequest::bash(script)).await
+    }
+
+    /// Compile and run a Rust program. The program is uploaded as a file, so it needs no
+    /// escaping, and the run is cached: the same code run again skips the compile.
+    ///
+    /// ```rust,no_run
+    /// # async fn example(client: faas_sdk::FaasClient) -> Result<(), faas_sdk::SdkError> {
+    /// let result = client
+    ///     .run_rust(r#"fn main() { println!("{}", (1..=10).sum::<u32>()); }"#)
+    ///     .await?;
+    /// assert_eq!(result.stdout.trim(), "55");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_rust(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::rust(code)).await
+    }
+
+    /// Build and run a Go program, cached like [`run_rust`](Self::run_rust)
+    pub async fn run_go(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::go(code)).await
     }
 
-    /// Fork execution from parent for A/B testing
+    /// Run `code` in `language` with `deps` installed; see [`ExecuteRequest::language`]
+    pub async fn run_language(
+        &self,
+        language: Language,
+        code: &str,
+        deps: &[&str],
+    ) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::language(language, code, deps))
+            .await
+    }
+
+    /// Run `command` in a branch of `parent_id`, which ran in `branched` or `persistent`
+    /// mode: it starts with the files the parent left, and branches of the same parent
+    /// can run side by side. A parent that left no files is a 404.
     pub async fn fork_execution(
         &self,
         parent_id: &str,
         command: &str,
     ) -> Result<ExecuteResponse, SdkError> {
-        self.execute(ExecuteRequest {
-            command: command.to_string(),
-            image: Some("alpine:latest".to_string()),
-            runtime: Some(self.runtime.clone()),
-            mode: Some("branched".to_string()),
-            branch_from: Some(parent_id.to_string()),
-            env_vars: None,
-            memory_mb: None,
-            cpu_cores: None,
-            working_dir: None,
-            timeout_ms: Some(30000),
-            cache_key: None,
-            snapshot_id: None,
-            payload: None,
-        })
-        .await
+        let url = format!("{}/api/v1/executions/{}/fork", self.base_url, parent_id);
+        let response = self
+            .client
+            .post(&url)
+            .json(&ExecuteRequest {
+                command: command.to_string(),
+                timeout_ms: Some(30000),
+                ..Default::default()
+            })
+            .send()
+            .await?;
+        json_or_error(response).await
     }
 
     /// Create checkpoint for stateful workflow
@@ -894,6 +1734,9 @@ impl FaasClient {
             name: format!("checkpoint-{execution_id}"),
             container_id: execution_id.to_string(),
             description: Some("Execution checkpoint".to_string()),
+            tags: Vec::new(),
+            group_id: None,
+            wait: true,
         })
         .await
     }
@@ -913,10 +1756,7 @@ impl FaasClient {
             .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SdkError::Api {
-                message: error_text,
-            });
+            return Err(api_error(response).await);
         }
 
         Ok(())
@@ -993,16 +1833,27 @@ impl FaasClient {
             cpu_cores: None,
             working_dir: None,
             timeout_ms: Some(30000),
-            cache_key: Some(format!("{:x}", md5::compute(command.as_bytes()))),
             snapshot_id: None,
             branch_from: None,
             payload: None,
+            ..Default::default()
         };
 
         let response = self.execute(request).await?;
         Ok(response.stdout)
     }
 
+    /// Drop the result cached under `key`, the `cache_key` a cached response reported, so
+    /// the next `cached` run executes again
+    pub async fn invalidate_cache(&self, key: &str) -> Result<(), SdkError> {
+        let url = format!("{}/api/v1/cache/{}", self.base_url, key);
+        let response = self.client.delete(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        Ok(())
+    }
+
     /// Create development environment with persistence
     pub async fn create_dev_env(&self, name: &str, image: &str) -> Result<String, SdkError> {
         let request = CreateInstanceRequest {
@@ -1011,6 +1862,8 @@ impl FaasClient {
             cpu_cores: Some(2),
             memory_mb: Some(2048),
             persistent: Some(true),
+            workspace_path: None,
+            ports: Vec::new(),
         };
 
         let response = self.create_instance(request).await?;
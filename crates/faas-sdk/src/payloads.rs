@@ -0,0 +1,77 @@
+//! Deduplicated stdin payloads
+//!
+//! A payload at or above the client's threshold is uploaded once by hash and referenced
+//! from then on, so sending the same large input to many executions costs one upload.
+//! Gateways without payload storage get the payload inline, as before.
+
+use crate::{api_error, status_code_name, ExecuteRequest, FaasClient, SdkError};
+use faas_common::hash::sha256_hex;
+use reqwest::StatusCode;
+use std::sync::atomic::Ordering;
+
+/// Payloads smaller than this are always sent inline
+pub const DEFAULT_PAYLOAD_REF_THRESHOLD: usize = 256 * 1024;
+
+impl FaasClient {
+    /// Send payloads of at least `bytes` by reference instead of inline
+    pub fn with_payload_ref_threshold(mut self, bytes: usize) -> Self {
+        self.payload_ref_threshold = bytes;
+        self
+    }
+
+    /// Make sure the gateway stores `data` and return its hash. `None` if the gateway
+    /// doesn't support payload storage.
+    pub async fn upload_payload(&self, data: &[u8]) -> Result<Option<String>, SdkError> {
+        if self.payload_refs_unsupported.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let hash = sha256_hex(data);
+        let url = format!("{}/api/v1/payloads/{}", self.base_url, hash);
+
+        let head = self.client.head(&url).send().await?;
+        match head.status() {
+            status if status.is_success() => return Ok(Some(hash)),
+            StatusCode::NOT_FOUND => {}
+            StatusCode::METHOD_NOT_ALLOWED => {
+                self.payload_refs_unsupported.store(true, Ordering::Relaxed);
+                return Ok(None);
+            }
+            status => {
+                return Err(SdkError::Api {
+                    status: status.as_u16(),
+                    code: status_code_name(status),
+                    message: format!("payload lookup failed with {status}"),
+                })
+            }
+        }
+
+        let put = self.client.put(&url).body(data.to_vec()).send().await?;
+        match put.status() {
+            status if status.is_success() => Ok(Some(hash)),
+            // A gateway without payload storage has no such route at all
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => {
+                self.payload_refs_unsupported.store(true, Ordering::Relaxed);
+                Ok(None)
+            }
+            _ => Err(api_error(put).await),
+        }
+    }
+
+    /// Swap a large inline payload for a reference to the stored copy
+    pub(crate) async fn reference_payload(
+        &self,
+        request: &mut ExecuteRequest,
+    ) -> Result<(), SdkError> {
+        let Some(payload) = &request.payload else {
+            return Ok(());
+        };
+        if payload.len() < self.payload_ref_threshold {
+            return Ok(());
+        }
+        if let Some(hash) = self.upload_payload(payload).await? {
+            request.payload = None;
+            request.payload_ref = Some(hash);
+        }
+        Ok(())
+    }
+}
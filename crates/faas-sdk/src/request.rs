@@ -0,0 +1,258 @@
+//! [`ExecuteRequest`]s built one setting at a time, so code that builds them keeps
+//! compiling as the request grows fields.
+
+use crate::{EnvOverrides, EnvVar, ExecuteRequest, IsolationLevel, Runtime, TmpfsMount, Ulimit};
+use thiserror::Error;
+
+/// A request [`ExecuteRequestBuilder::build`] refuses to produce
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    #[error("command is empty")]
+    EmptyCommand,
+    #[error("timeout_ms is zero")]
+    ZeroTimeout,
+    #[error("memory_mb is zero")]
+    ZeroMemory,
+    #[error("cpu_cores is zero")]
+    ZeroCpuCores,
+}
+
+/// Builds an [`ExecuteRequest`]; settings left alone keep the gateway's defaults
+///
+/// ```rust
+/// use faas_sdk::{ExecuteRequest, RequestError};
+///
+/// let request = ExecuteRequest::builder("python main.py")
+///     .image("python:3.11")
+///     .env("MODEL", "bert")
+///     .timeout_ms(5000)
+///     .memory_mb(512)
+///     .cached("inference-v1")
+///     .build()
+///     .unwrap();
+/// assert_eq!(request.mode.as_deref(), Some("cached"));
+///
+/// let refused = ExecuteRequest::builder("echo hi").timeout_ms(0).build();
+/// assert_eq!(refused.unwrap_err(), RequestError::ZeroTimeout);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteRequestBuilder {
+    request: ExecuteRequest,
+}
+
+impl ExecuteRequestBuilder {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self::default().command(command)
+    }
+
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.request.command = command.into();
+        self
+    }
+
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.request.image = Some(image.into());
+        self
+    }
+
+    /// Instead of the client's runtime
+    pub fn runtime(mut self, runtime: Runtime) -> Self {
+        self.request.runtime = Some(runtime);
+        self
+    }
+
+    pub fn isolation(mut self, isolation: IsolationLevel) -> Self {
+        self.request.isolation = Some(isolation);
+        self
+    }
+
+    /// `ephemeral`, `cached`, `checkpointed`, `branched` or `persistent`
+    pub fn mode(mut self, mode: impl Into<String>) -> Self {
+        self.request.mode = Some(mode.into());
+        self
+    }
+
+    /// `cached` mode, with the result kept under `key`
+    pub fn cached(self, key: impl Into<String>) -> Self {
+        let mut builder = self.mode("cached");
+        builder.request.cache_key = Some(key.into());
+        builder
+    }
+
+    /// Run even if a result is cached, and cache the new one
+    pub fn no_cache(mut self) -> Self {
+        self.request.no_cache = Some(true);
+        self
+    }
+
+    /// Add an environment variable
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request
+            .env_vars
+            .get_or_insert_with(Vec::new)
+            .push(EnvVar::new(key, value));
+        self
+    }
+
+    pub fn working_dir(mut self, dir: impl Into<String>) -> Self {
+        self.request.working_dir = Some(dir.into());
+        self
+    }
+
+    /// `uid[:gid]` or a user name the image has
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.request.user = Some(user.into());
+        self
+    }
+
+    pub fn timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.request.timeout_ms = Some(timeout_ms);
+        self
+    }
+
+    pub fn memory_mb(mut self, memory_mb: u32) -> Self {
+        self.request.memory_mb = Some(memory_mb);
+        self
+    }
+
+    pub fn cpu_cores(mut self, cpu_cores: u8) -> Self {
+        self.request.cpu_cores = Some(cpu_cores);
+        self
+    }
+
+    pub fn snapshot(mut self, snapshot_id: impl Into<String>) -> Self {
+        self.request.snapshot_id = Some(snapshot_id.into());
+        self
+    }
+
+    /// Start from the files a `branched` execution left behind
+    pub fn branch_from(mut self, execution_id: impl Into<String>) -> Self {
+        self.request.branch_from = Some(execution_id.into());
+        self
+    }
+
+    /// Sent to the command on stdin
+    pub fn payload(mut self, payload: impl Into<Vec<u8>>) -> Self {
+        self.request.payload = Some(payload.into());
+        self
+    }
+
+    /// Place a file in the sandbox before the command runs; relative paths are taken from
+    /// the working directory
+    pub fn input_file(mut self, path: impl Into<String>, contents: impl Into<Vec<u8>>) -> Self {
+        self.request
+            .input_files
+            .get_or_insert_with(Vec::new)
+            .push((path.into(), contents.into()));
+        self
+    }
+
+    pub fn ulimit(mut self, ulimit: Ulimit) -> Self {
+        self.request
+            .ulimits
+            .get_or_insert_with(Vec::new)
+            .push(ulimit);
+        self
+    }
+
+    pub fn shm_size_mb(mut self, size_mb: u64) -> Self {
+        self.request.shm_size_mb = Some(size_mb);
+        self
+    }
+
+    pub fn tmpfs(mut self, path: impl Into<String>, size_mb: u64) -> Self {
+        self.request
+            .tmpfs
+            .get_or_insert_with(Vec::new)
+            .push(TmpfsMount {
+                path: path.into(),
+                size_mb,
+            });
+        self
+    }
+
+    pub fn arch(mut self, arch: impl Into<String>) -> Self {
+        self.request.arch = Some(arch.into());
+        self
+    }
+
+    pub fn gpus(mut self, count: u32) -> Self {
+        self.request.gpu_count = Some(count);
+        self
+    }
+
+    /// Run the command without a network
+    pub fn no_network(mut self) -> Self {
+        self.request.network_enabled = Some(false);
+        self
+    }
+
+    /// Let the command reach `host`, and only the hosts allowed this way
+    pub fn allow_host(mut self, host: impl Into<String>) -> Self {
+        self.request
+            .allowed_hosts
+            .get_or_insert_with(Vec::new)
+            .push(host.into());
+        self
+    }
+
+    /// Report to the execution group `group_id`
+    pub fn group(mut self, group_id: impl Into<String>) -> Self {
+        self.request.group_id = Some(group_id.into());
+        self
+    }
+
+    pub fn item_key(mut self, item_key: impl Into<String>) -> Self {
+        self.request.item_key = Some(item_key.into());
+        self
+    }
+
+    pub fn environment_overrides(mut self, overrides: EnvOverrides) -> Self {
+        self.request.environment_overrides = Some(overrides);
+        self
+    }
+
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request
+            .labels
+            .get_or_insert_with(Default::default)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.request.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// `persistent` mode: seconds until the gateway reaps the execution
+    pub fn ttl_secs(mut self, ttl_secs: u64) -> Self {
+        self.request.ttl_secs = Some(ttl_secs);
+        self
+    }
+
+    /// The request, unless it has no command or a zero timeout, memory or CPU count
+    pub fn build(self) -> Result<ExecuteRequest, RequestError> {
+        let request = self.request;
+        if request.command.trim().is_empty() {
+            return Err(RequestError::EmptyCommand);
+        }
+        if request.timeout_ms == Some(0) {
+            return Err(RequestError::ZeroTimeout);
+        }
+        if request.memory_mb == Some(0) {
+            return Err(RequestError::ZeroMemory);
+        }
+        if request.cpu_cores == Some(0) {
+            return Err(RequestError::ZeroCpuCores);
+        }
+        Ok(request)
+    }
+}
+
+impl ExecuteRequest {
+    /// A builder for running `command`; see [`ExecuteRequestBuilder`]
+    pub fn builder(command: impl Into<String>) -> ExecuteRequestBuilder {
+        ExecuteRequestBuilder::new(command)
+    }
+}
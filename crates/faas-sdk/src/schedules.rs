@@ -0,0 +1,106 @@
+//! Cron schedules
+//!
+//! A schedule runs an execute request whenever its cron expression falls due, in UTC: five
+//! fields, or six with seconds first. Each run is a [job](crate::Job) under the run's id,
+//! and the schedule reports its last run and when it runs next.
+
+use crate::{json_or_error, ExecuteRequest, FaasClient, JobError, JobStatus, SdkError};
+use serde::{Deserialize, Serialize};
+
+/// What an occurrence does while the previous run is still going
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConcurrencyPolicy {
+    /// Drop it
+    #[default]
+    Skip,
+    /// Run it once the previous run ends; at most one waits
+    Queue,
+    /// Cancel the previous run
+    Replace,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleRun {
+    /// Also the job id the run can be followed under
+    pub run_id: String,
+    pub scheduled_for: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub status: JobStatus,
+    pub exit_code: Option<i32>,
+    pub error: Option<JobError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schedule {
+    pub schedule_id: String,
+    pub function_id: String,
+    pub cron: String,
+    pub concurrency: ConcurrencyPolicy,
+    pub created_at: String,
+    /// Unset once the expression names no further time
+    pub next_run_at: Option<String>,
+    pub last_run: Option<ScheduleRun>,
+    #[serde(default)]
+    pub runs: u64,
+    /// Occurrences dropped because a run was still going
+    #[serde(default)]
+    pub skipped: u64,
+}
+
+#[derive(Serialize)]
+struct ScheduleRequest<'a> {
+    function_id: &'a str,
+    cron: &'a str,
+    concurrency: ConcurrencyPolicy,
+    #[serde(flatten)]
+    request: &'a ExecuteRequest,
+}
+
+impl FaasClient {
+    /// Run `request` as `function_id` whenever `cron` falls due, with the client's defaults
+    /// applied. The payload is sent inline, since a referenced one could expire before a
+    /// later run.
+    pub async fn create_schedule(
+        &self,
+        function_id: &str,
+        cron: &str,
+        concurrency: ConcurrencyPolicy,
+        mut request: ExecuteRequest,
+    ) -> Result<Schedule, SdkError> {
+        self.apply_defaults(&mut request);
+        let url = format!("{}/api/v1/schedules", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&ScheduleRequest {
+                function_id,
+                cron,
+                concurrency,
+                request: &request,
+            })
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+
+    pub async fn list_schedules(&self) -> Result<Vec<Schedule>, SdkError> {
+        let url = format!("{}/api/v1/schedules", self.base_url);
+        let response = self.client.get(&url).send().await?;
+        json_or_error(response).await
+    }
+
+    pub async fn get_schedule(&self, schedule_id: &str) -> Result<Schedule, SdkError> {
+        let url = format!("{}/api/v1/schedules/{}", self.base_url, schedule_id);
+        let response = self.client.get(&url).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Stop the schedule firing; a run already going is left to finish
+    pub async fn delete_schedule(&self, schedule_id: &str) -> Result<Schedule, SdkError> {
+        let url = format!("{}/api/v1/schedules/{}", self.base_url, schedule_id);
+        let response = self.client.delete(&url).send().await?;
+        json_or_error(response).await
+    }
+}
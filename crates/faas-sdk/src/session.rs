@@ -0,0 +1,92 @@
+//! Interactive sessions on persistent instances
+//!
+//! The gateway carries exported variables and the working directory from one exec to the
+//! next. That state can be captured and re-applied onto another instance later.
+
+use crate::{json_or_error, ExecuteResponse, FaasClient, SdkError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Env and working directory a session accumulated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionState {
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Keys whose values the gateway masked as secrets; these are not restored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub masked: Vec<String>,
+}
+
+/// What a restore actually applied
+#[derive(Debug, Clone, Deserialize)]
+pub struct RestoreReport {
+    pub state: SessionState,
+    pub skipped: Vec<String>,
+    /// The captured working directory does not exist on the target instance
+    pub cwd_missing: bool,
+}
+
+/// Handle for running commands on one instance
+pub struct Session<'a> {
+    client: &'a FaasClient,
+    instance_id: String,
+}
+
+impl FaasClient {
+    /// Open a session on an existing instance
+    pub fn session(&self, instance_id: impl Into<String>) -> Session<'_> {
+        Session {
+            client: self,
+            instance_id: instance_id.into(),
+        }
+    }
+}
+
+impl Session<'_> {
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Run a command; `export`s and `cd`s stay in effect for later execs
+    pub async fn exec(&self, command: &str) -> Result<ExecuteResponse, SdkError> {
+        let url = format!(
+            "{}/api/v1/instances/{}/exec",
+            self.client.base_url, self.instance_id
+        );
+        let response = self
+            .client
+            .client
+            .post(&url)
+            .json(&serde_json::json!({ "command": command }))
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+
+    /// Current env additions and working directory, with secrets masked
+    pub async fn capture_state(&self) -> Result<SessionState, SdkError> {
+        let response = self.client.client.get(self.state_url()).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Re-apply a captured state: env first, then the working directory if it exists here
+    pub async fn restore_state(&self, state: &SessionState) -> Result<RestoreReport, SdkError> {
+        let response = self
+            .client
+            .client
+            .post(self.state_url())
+            .json(state)
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+
+    fn state_url(&self) -> String {
+        format!(
+            "{}/api/v1/instances/{}/session-state",
+            self.client.base_url, self.instance_id
+        )
+    }
+}
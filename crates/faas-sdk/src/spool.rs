@@ -0,0 +1,413 @@
+//! Store-and-forward for clients that lose the gateway for minutes at a time
+//!
+//! With a spool enabled, [`FaasClient::submit`] persists an execution that couldn't reach
+//! the gateway and a background flusher sends it once the gateway answers again. Each entry
+//! is a request file and, if it has stdin, a payload file next to it, so entries survive a
+//! restart of the client process. Entries in one concurrency group go out in the order they
+//! were submitted; one that still can't be delivered holds back the rest of its group.
+
+use crate::{ExecuteRequest, ExecuteResponse, FaasClient, SdkError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Where the spool lives and how long entries may wait in it
+#[derive(Debug, Clone)]
+pub struct OfflineSpoolConfig {
+    pub dir: PathBuf,
+    /// Entries older than this are dropped as [`SpoolOutcome::Expired`]
+    pub max_age: Duration,
+    /// Delivery attempts, counting the one that spooled the entry
+    pub max_attempts: u32,
+    /// How often the background flusher retries
+    pub flush_interval: Duration,
+}
+
+impl OfflineSpoolConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_age: Duration::from_secs(3600),
+            max_attempts: 20,
+            flush_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Per-submission settings; unset limits fall back to the spool's
+#[derive(Debug, Clone, Default)]
+pub struct SpoolOptions {
+    /// Entries sharing a group are delivered in submission order. Without one, an entry is
+    /// ordered against nothing else.
+    pub concurrency_group: Option<String>,
+    pub max_age: Option<Duration>,
+    pub max_attempts: Option<u32>,
+}
+
+/// What finally happened to a spooled execution
+#[derive(Debug)]
+pub enum SpoolOutcome {
+    Delivered(Box<ExecuteResponse>),
+    /// The gateway answered with an error; it isn't retried
+    Rejected(String),
+    /// It was still undelivered when it reached its max age
+    Expired,
+    /// Every allowed attempt failed to reach the gateway
+    Exhausted {
+        attempts: u32,
+    },
+}
+
+/// The result of [`FaasClient::submit`]
+#[derive(Debug)]
+pub enum Submission {
+    Completed(Box<ExecuteResponse>),
+    Spooled(SpooledExecution),
+}
+
+/// An execution waiting in the spool
+#[derive(Debug)]
+pub struct SpooledExecution {
+    pub id: String,
+    pub idempotency_key: String,
+    outcome: oneshot::Receiver<SpoolOutcome>,
+}
+
+impl SpooledExecution {
+    /// Wait for delivery, expiry or the last attempt. `None` if the client was dropped first;
+    /// the entry is still on disk and a client opening the same spool will send it.
+    pub async fn outcome(self) -> Option<SpoolOutcome> {
+        self.outcome.await.ok()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpoolStatus {
+    pub pending: usize,
+    /// Pending entries per concurrency group
+    pub groups: BTreeMap<String, usize>,
+    pub delivered: u64,
+    pub rejected: u64,
+    pub expired: u64,
+    pub exhausted: u64,
+    /// Why the last attempt to reach the gateway failed
+    pub last_error: Option<String>,
+}
+
+/// What is written to `<seq>.json`; the payload, if any, is in `<seq>.payload`
+#[derive(Debug, Serialize, Deserialize)]
+struct SpoolEntry {
+    id: String,
+    seq: u64,
+    group: Option<String>,
+    request: ExecuteRequest,
+    spooled_at: chrono::DateTime<chrono::Utc>,
+    max_age_ms: u64,
+    max_attempts: u32,
+    attempts: u32,
+}
+
+impl SpoolEntry {
+    fn expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        (now - self.spooled_at).num_milliseconds() >= self.max_age_ms as i64
+    }
+}
+
+#[derive(Default)]
+struct SpoolState {
+    next_seq: u64,
+    /// Pending entries by sequence number, payloads left on disk
+    entries: BTreeMap<u64, SpoolEntry>,
+    waiters: HashMap<String, oneshot::Sender<SpoolOutcome>>,
+    status: SpoolStatus,
+}
+
+pub(crate) struct Spool {
+    config: OfflineSpoolConfig,
+    state: Mutex<SpoolState>,
+    /// Keeps the background flusher and `flush_now` from sending an entry twice
+    flushing: tokio::sync::Mutex<()>,
+    flusher: Mutex<Option<tokio::task::AbortHandle>>,
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Some(flusher) = self.flusher.get_mut().unwrap().take() {
+            flusher.abort();
+        }
+    }
+}
+
+/// The gateway couldn't be reached at all, as opposed to answering with an error
+fn unreachable(error: &SdkError) -> bool {
+    matches!(error, SdkError::Http(e) if e.is_connect() || e.is_timeout())
+}
+
+impl Spool {
+    /// Load whatever a previous client left in `config.dir`
+    fn open(config: OfflineSpoolConfig) -> Result<Self, SdkError> {
+        std::fs::create_dir_all(&config.dir)?;
+        let mut state = SpoolState::default();
+        for dirent in std::fs::read_dir(&config.dir)? {
+            let path = dirent?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let entry: SpoolEntry = serde_json::from_slice(&std::fs::read(&path)?)?;
+            state.next_seq = state.next_seq.max(entry.seq + 1);
+            state.entries.insert(entry.seq, entry);
+        }
+        Ok(Self {
+            config,
+            state: Mutex::new(state),
+            flushing: tokio::sync::Mutex::new(()),
+            flusher: Mutex::new(None),
+        })
+    }
+
+    fn request_path(&self, seq: u64) -> PathBuf {
+        self.config.dir.join(format!("{seq:020}.json"))
+    }
+
+    fn payload_path(&self, seq: u64) -> PathBuf {
+        self.config.dir.join(format!("{seq:020}.payload"))
+    }
+
+    fn group_pending(&self, group: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .values()
+            .any(|entry| entry.group.as_deref() == Some(group))
+    }
+
+    /// Persist `request`, which has already failed to reach the gateway `attempts` times
+    fn push(
+        &self,
+        mut request: ExecuteRequest,
+        options: SpoolOptions,
+        attempts: u32,
+    ) -> Result<SpooledExecution, SdkError> {
+        let idempotency_key = request
+            .idempotency_key
+            .get_or_insert_with(|| uuid::Uuid::new_v4().to_string())
+            .clone();
+        let payload = request.payload.take();
+        let (tx, rx) = oneshot::channel();
+
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        let entry = SpoolEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            seq,
+            group: options.concurrency_group,
+            request,
+            spooled_at: chrono::Utc::now(),
+            max_age_ms: options.max_age.unwrap_or(self.config.max_age).as_millis() as u64,
+            max_attempts: options.max_attempts.unwrap_or(self.config.max_attempts),
+            attempts,
+        };
+        if let Some(payload) = &payload {
+            std::fs::write(self.payload_path(seq), payload)?;
+        }
+        write_atomic(&self.request_path(seq), &serde_json::to_vec(&entry)?)?;
+        state.next_seq += 1;
+        state.waiters.insert(entry.id.clone(), tx);
+        let id = entry.id.clone();
+        state.entries.insert(seq, entry);
+        Ok(SpooledExecution {
+            id,
+            idempotency_key,
+            outcome: rx,
+        })
+    }
+
+    /// Remove a finished entry and tell whoever submitted it
+    fn settle(&self, seq: u64, outcome: SpoolOutcome) {
+        let _ = std::fs::remove_file(self.request_path(seq));
+        let _ = std::fs::remove_file(self.payload_path(seq));
+        let mut state = self.state.lock().unwrap();
+        let Some(entry) = state.entries.remove(&seq) else {
+            return;
+        };
+        let counter = match &outcome {
+            SpoolOutcome::Delivered(_) => &mut state.status.delivered,
+            SpoolOutcome::Rejected(_) => &mut state.status.rejected,
+            SpoolOutcome::Expired => &mut state.status.expired,
+            SpoolOutcome::Exhausted { .. } => &mut state.status.exhausted,
+        };
+        *counter += 1;
+        if let Some(waiter) = state.waiters.remove(&entry.id) {
+            let _ = waiter.send(outcome);
+        }
+    }
+
+    /// One pass over the pending entries in submission order
+    async fn flush(&self, sender: &FaasClient) -> Result<(), SdkError> {
+        let _flushing = self.flushing.lock().await;
+        let seqs: Vec<u64> = self.state.lock().unwrap().entries.keys().copied().collect();
+        let mut blocked = HashSet::new();
+
+        for seq in seqs {
+            let (mut request, group, expired) = {
+                let state = self.state.lock().unwrap();
+                let Some(entry) = state.entries.get(&seq) else {
+                    continue;
+                };
+                (
+                    entry.request.clone(),
+                    entry.group.clone(),
+                    entry.expired(chrono::Utc::now()),
+                )
+            };
+            if expired {
+                self.settle(seq, SpoolOutcome::Expired);
+                continue;
+            }
+            if group.as_ref().is_some_and(|group| blocked.contains(group)) {
+                continue;
+            }
+
+            let payload_path = self.payload_path(seq);
+            if payload_path.exists() {
+                request.payload = Some(std::fs::read(&payload_path)?);
+            }
+            match sender.send_execute(request).await {
+                Ok(response) => self.settle(seq, SpoolOutcome::Delivered(Box::new(response))),
+                Err(e) if unreachable(&e) => {
+                    if let Some(group) = group {
+                        blocked.insert(group);
+                    }
+                    let exhausted = self.record_attempt(seq, &e)?;
+                    if let Some(attempts) = exhausted {
+                        self.settle(seq, SpoolOutcome::Exhausted { attempts });
+                    }
+                }
+                Err(e) => self.settle(seq, SpoolOutcome::Rejected(e.to_string())),
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a failed attempt; the attempt count if that was the entry's last
+    fn record_attempt(&self, seq: u64, error: &SdkError) -> Result<Option<u32>, SdkError> {
+        let mut state = self.state.lock().unwrap();
+        state.status.last_error = Some(error.to_string());
+        let Some(entry) = state.entries.get_mut(&seq) else {
+            return Ok(None);
+        };
+        entry.attempts += 1;
+        if entry.attempts >= entry.max_attempts {
+            return Ok(Some(entry.attempts));
+        }
+        write_atomic(&self.request_path(seq), &serde_json::to_vec(entry)?)?;
+        Ok(None)
+    }
+
+    fn status(&self) -> SpoolStatus {
+        let state = self.state.lock().unwrap();
+        let mut status = state.status.clone();
+        status.pending = state.entries.len();
+        for group in state.entries.values().filter_map(|e| e.group.clone()) {
+            *status.groups.entry(group).or_default() += 1;
+        }
+        status
+    }
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(tmp, path)
+}
+
+impl FaasClient {
+    /// Spool executions sent through [`FaasClient::submit`] in `config.dir` while the
+    /// gateway is unreachable, and start flushing them. Entries a previous client left in
+    /// the directory are picked up. Only one client process should use a directory. Must be
+    /// called from within a Tokio runtime.
+    pub fn enable_offline_spool(&self, config: OfflineSpoolConfig) -> Result<(), SdkError> {
+        let interval = config.flush_interval;
+        let spool = Arc::new(Spool::open(config)?);
+        self.spool
+            .set(spool.clone())
+            .map_err(|_| SdkError::Spool("already enabled for this client".to_string()))?;
+
+        // The flusher holds neither the spool nor a client sharing it, so it stops once
+        // the last client is dropped
+        let weak: Weak<Spool> = Arc::downgrade(&spool);
+        let sender = FaasClient {
+            spool: Arc::default(),
+            ..self.clone()
+        };
+        let flusher = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(spool) = weak.upgrade() else {
+                    break;
+                };
+                if let Err(e) = spool.flush(&sender).await {
+                    spool.state.lock().unwrap().status.last_error = Some(e.to_string());
+                }
+            }
+        });
+        *spool.flusher.lock().unwrap() = Some(flusher.abort_handle());
+        Ok(())
+    }
+
+    /// Execute without waiting on connectivity. If the gateway can't be reached, or earlier
+    /// entries of the same concurrency group are still spooled, the request is persisted and
+    /// its outcome arrives through the returned [`SpooledExecution`]. Without a spool this
+    /// is [`FaasClient::execute`].
+    pub async fn submit(
+        &self,
+        mut request: ExecuteRequest,
+        options: SpoolOptions,
+    ) -> Result<Submission, SdkError> {
+        let Some(spool) = self.spool.get() else {
+            return self
+                .execute(request)
+                .await
+                .map(|response| Submission::Completed(Box::new(response)));
+        };
+        self.apply_defaults(&mut request);
+        // Every attempt, including this first one, carries the same key
+        request
+            .idempotency_key
+            .get_or_insert_with(|| uuid::Uuid::new_v4().to_string());
+
+        let queued_behind = options
+            .concurrency_group
+            .as_deref()
+            .is_some_and(|group| spool.group_pending(group));
+        if queued_behind {
+            return spool.push(request, options, 0).map(Submission::Spooled);
+        }
+        match self.send_execute(request.clone()).await {
+            Ok(response) => Ok(Submission::Completed(Box::new(response))),
+            Err(e) if unreachable(&e) => {
+                spool.state.lock().unwrap().status.last_error = Some(e.to_string());
+                spool.push(request, options, 1).map(Submission::Spooled)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pending entries and what happened to the rest; `None` without a spool
+    pub fn spool_status(&self) -> Option<SpoolStatus> {
+        self.spool.get().map(|spool| spool.status())
+    }
+
+    /// Try every pending entry now instead of waiting for the flusher
+    pub async fn flush_now(&self) -> Result<SpoolStatus, SdkError> {
+        let spool = self
+            .spool
+            .get()
+            .ok_or_else(|| SdkError::Spool("not enabled".to_string()))?;
+        spool.flush(self).await?;
+        Ok(spool.status())
+    }
+}
@@ -0,0 +1,214 @@
+//! Output of an execution as it runs
+//!
+//! [`FaasClient::execute_stream`] posts to `/api/v1/execute/stream`, which answers with
+//! server-sent events. The stream ends after [`StreamEvent::Exit`]; a failure without an
+//! exit code (a timeout, the kill switch) ends it with an error instead. A dropped
+//! connection isn't resumed, since the gateway doesn't keep the output of a streamed run
+//! for replay; it ends the stream with [`SdkError::RequestFailed`].
+
+use crate::{api_error, ExecuteRequest, FaasClient, SdkError};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Slack past the execution's own timeout before the client gives up on the connection
+const STREAM_GRACE: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    Stdout {
+        data: String,
+    },
+    Stderr {
+        data: String,
+    },
+    /// The process exited; always the last event
+    Exit {
+        code: i32,
+    },
+    /// Sent while the process is quiet, so idle connections aren't mistaken for dead ones
+    Heartbeat,
+}
+
+/// What the gateway sends; `error` becomes an `Err` item and anything unknown is skipped
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Frame {
+    Error {
+        message: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl FaasClient {
+    /// Start an execution and yield its output as the container writes it
+    ///
+    /// The request runs ephemerally in Docker whatever its `mode`. Unlike
+    /// [`FaasClient::execute`], no cache key is added: a streamed run always runs.
+    pub fn execute_stream(
+        &self,
+        mut request: ExecuteRequest,
+    ) -> impl Stream<Item = Result<StreamEvent, SdkError>> + Send + 'static {
+        if request.runtime.is_none() {
+            request.runtime = Some(self.runtime.clone());
+        }
+        let client = self.clone();
+        let started = async move {
+            client.reference_payload(&mut request).await?;
+            let timeout = Duration::from_millis(request.timeout_ms.unwrap_or(30000));
+            let response = client
+                .client
+                .post(format!("{}/api/v1/execute/stream", client.base_url))
+                .timeout(timeout + STREAM_GRACE)
+                .json(&request)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+            Ok(response.bytes_stream())
+        };
+        futures::stream::once(started)
+            .map(|started| match started {
+                Ok(body) => events(body).left_stream(),
+                Err(e) => futures::stream::iter([Err(e)]).right_stream(),
+            })
+            .flatten()
+    }
+}
+
+struct EventReader<B> {
+    body: B,
+    decoder: SseDecoder,
+    pending: VecDeque<Result<StreamEvent, SdkError>>,
+    done: bool,
+}
+
+fn events<B>(body: B) -> impl Stream<Item = Result<StreamEvent, SdkError>> + Send + 'static
+where
+    B: Stream<Item = reqwest::Result<bytes::Bytes>> + Send + Unpin + 'static,
+{
+    let reader = EventReader {
+        body,
+        decoder: SseDecoder::default(),
+        pending: VecDeque::new(),
+        done: false,
+    };
+    futures::stream::unfold(reader, |mut reader| async move {
+        loop {
+            if let Some(item) = reader.pending.pop_front() {
+                // Nothing follows the exit or an error
+                if matches!(item, Ok(StreamEvent::Exit { .. }) | Err(_)) {
+                    reader.pending.clear();
+                    reader.done = true;
+                }
+                return Some((item, reader));
+            }
+            if reader.done {
+                return None;
+            }
+            match reader.body.next().await {
+                Some(Ok(bytes)) => {
+                    for data in reader.decoder.feed(&bytes) {
+                        if let Some(item) = parse_event(&data) {
+                            reader.pending.push_back(item);
+                        }
+                    }
+                }
+                Some(Err(e)) => reader.pending.push_back(Err(e.into())),
+                None => reader.pending.push_back(Err(SdkError::RequestFailed(
+                    "stream closed before the execution exited".to_string(),
+                ))),
+            }
+        }
+    })
+}
+
+fn parse_event(data: &str) -> Option<Result<StreamEvent, SdkError>> {
+    match serde_json::from_str::<Frame>(data) {
+        Ok(Frame::Error { message }) => Some(Err(SdkError::Api {
+            status: 200,
+            code: "ExecutionFailed".to_string(),
+            message,
+        })),
+        Ok(Frame::Other) => match serde_json::from_str(data) {
+            Ok(event) => Some(Ok(event)),
+            // Event types this client doesn't know about
+            Err(_) => None,
+        },
+        Err(e) => Some(Err(e.into())),
+    }
+}
+
+/// Splits a server-sent event stream into the `data` of each event
+#[derive(Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = find_event_end(&self.buffer) {
+            let raw: Vec<u8> = self.buffer.drain(..end).collect();
+            let raw = String::from_utf8_lossy(&raw);
+            let data: Vec<&str> = raw
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|value| value.strip_prefix(' ').unwrap_or(value))
+                .collect();
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+        events
+    }
+}
+
+/// Index just past the blank line that ends the first complete event
+fn find_event_end(buffer: &[u8]) -> Option<usize> {
+    (0..buffer.len()).find_map(|i| {
+        if buffer[i..].starts_with(b"\n\n") {
+            Some(i + 2)
+        } else if buffer[i..].starts_with(b"\r\n\r\n") {
+            Some(i + 4)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_split_across_reads_are_reassembled() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(b"data: {\"type\":\"stdout\",\"da").is_empty());
+        assert_eq!(
+            decoder.feed(b"ta\":\"hi\\n\"}\n\n: keep-alive\n\ndata: {\"type\":\"heartbeat\"}\n\n"),
+            vec![
+                r#"{"type":"stdout","data":"hi\n"}"#.to_string(),
+                r#"{"type":"heartbeat"}"#.to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_and_unknown_events() {
+        assert!(matches!(
+            parse_event(r#"{"type":"error","message":"Execution timed out after 1000 ms"}"#),
+            Some(Err(SdkError::Api { message, .. })) if message.contains("timed out")
+        ));
+        assert!(parse_event(r#"{"type":"file_event","path":"/a","event":"created"}"#).is_none());
+        assert_eq!(
+            parse_event(r#"{"type":"exit","code":3}"#).unwrap().unwrap(),
+            StreamEvent::Exit { code: 3 }
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Execution surface shared by every client
+//!
+//! Code written against [`Transport`] runs unchanged against a gateway over HTTP
+//! ([`FaasClient`]) or an in-process executor (`EmbeddedClient`, behind the `embedded`
+//! feature).
+
+use crate::{ExecuteRequest, ExecuteResponse, FaasClient, Language, SdkError};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn execute(&self, request: ExecuteRequest) -> Result<ExecuteResponse, SdkError>;
+
+    /// Execute Python code, passed to the interpreter on stdin
+    async fn run_python(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::python(code)).await
+    }
+
+    /// Execute JavaScript/Node.js code, passed to node on stdin
+    async fn run_javascript(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::javascript(code)).await
+    }
+
+    async fn run_bash(&self, script: &str) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::bash(script)).await
+    }
+
+    /// Compile and run a Rust program, cached by its code
+    async fn run_rust(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::rust(code)).await
+    }
+
+    /// Build and run a Go program, cached by its code
+    async fn run_go(&self, code: &str) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::go(code)).await
+    }
+
+    async fn run_language(
+        &self,
+        language: Language,
+        code: &str,
+        deps: &[&str],
+    ) -> Result<ExecuteResponse, SdkError> {
+        self.execute(ExecuteRequest::language(language, code, deps))
+            .await
+    }
+
+    /// Execute a command in `alpine:latest` and return its stdout
+    async fn run(&self, command: &str) -> Result<String, SdkError> {
+        let request = ExecuteRequest {
+            command: command.to_string(),
+            image: Some("alpine:latest".to_string()),
+            ..Default::default()
+        };
+        Ok(self.execute(request).await?.stdout)
+    }
+}
+
+#[async_trait]
+impl Transport for FaasClient {
+    async fn execute(&self, request: ExecuteRequest) -> Result<ExecuteResponse, SdkError> {
+        FaasClient::execute(self, request).await
+    }
+}
@@ -0,0 +1,240 @@
+//! Workflows
+//!
+//! Build a DAG of steps in code with [`WorkflowBuilder`], or load one from a YAML/JSON file
+//! (schema in the `faas_common::workflow` docs). A workflow either runs step by step from the client
+//! over any [`Transport`], or is submitted whole to the gateway with
+//! [`FaasClient::submit_workflow`]. A submitted run can be cancelled from elsewhere with
+//! [`FaasClient::cancel_workflow`], given the id it was submitted under.
+
+use crate::{
+    json_or_error, CancelPolicy, Cancellation, EnvVar, ExecuteRequest, FaasClient, SdkError,
+    Transport,
+};
+use serde::Deserialize;
+use std::path::Path;
+
+pub use faas_common::workflow::{
+    StepCancellation, StepError, StepInput, StepOutput, StepResources, StepRun, StepStatus,
+    Workflow, WorkflowError, WorkflowRun, WorkflowStep,
+};
+
+/// Where a step added with [`WorkflowBuilder::add_step_with_input`] reads its stdin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputFrom {
+    /// The named step's stdout
+    Step(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CancelledChild {
+    pub id: String,
+    #[serde(flatten)]
+    pub cancellation: Cancellation,
+}
+
+/// What a cancel reached on the gateway
+#[derive(Debug, Clone, Deserialize)]
+pub struct CancelReport {
+    pub id: String,
+    pub policy: CancelPolicy,
+    /// Cancelled by this call, nearest first
+    pub cancelled: Vec<CancelledChild>,
+    /// Running work an only-pending cancel left alone
+    pub spared: Vec<String>,
+}
+
+/// Steps added in order; `depends_on`, `env`, `artifact` and `resources` apply to the step
+/// added last
+#[derive(Debug, Clone)]
+pub struct WorkflowBuilder {
+    name: String,
+    steps: Vec<WorkflowStep>,
+    continue_on_failure: bool,
+    spill_threshold_bytes: Option<u64>,
+}
+
+impl WorkflowBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            steps: Vec::new(),
+            continue_on_failure: false,
+            spill_threshold_bytes: None,
+        }
+    }
+
+    pub fn add_step(
+        mut self,
+        name: impl Into<String>,
+        image: impl Into<String>,
+        command: impl Into<String>,
+    ) -> Self {
+        self.steps.push(WorkflowStep::new(name, image, command));
+        self
+    }
+
+    /// Add a step that reads `input` on stdin and so also depends on its source
+    pub fn add_step_with_input(
+        self,
+        name: impl Into<String>,
+        image: impl Into<String>,
+        command: impl Into<String>,
+        input: InputFrom,
+    ) -> Self {
+        let InputFrom::Step(source) = input;
+        let mut builder = self
+            .add_step(name, image, command)
+            .depends_on(source.clone());
+        if let Some(last) = builder.steps.last_mut() {
+            last.input_from = Some(source);
+        }
+        builder
+    }
+
+    pub fn depends_on(mut self, step: impl Into<String>) -> Self {
+        if let Some(last) = self.steps.last_mut() {
+            last.depends_on.push(step.into());
+        }
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        if let Some(last) = self.steps.last_mut() {
+            last.env.insert(key.into(), value.into());
+        }
+        self
+    }
+
+    pub fn artifact(mut self, path: impl Into<String>) -> Self {
+        if let Some(last) = self.steps.last_mut() {
+            last.artifacts.push(path.into());
+        }
+        self
+    }
+
+    pub fn resources(mut self, resources: StepResources) -> Self {
+        if let Some(last) = self.steps.last_mut() {
+            last.resources = resources;
+        }
+        self
+    }
+
+    /// After a failure, keep running the steps that don't depend on it instead of stopping
+    pub fn continue_on_failure(mut self) -> Self {
+        self.continue_on_failure = true;
+        self
+    }
+
+    /// Hand outputs larger than `bytes` to the steps reading them as a file rather than in
+    /// memory
+    pub fn spill_outputs_over(mut self, bytes: u64) -> Self {
+        self.spill_threshold_bytes = Some(bytes);
+        self
+    }
+
+    /// Validate the DAG: unknown dependencies and cycles are rejected here
+    pub fn build(self) -> Result<Workflow, WorkflowError> {
+        Workflow::new(self.name, self.steps).map(|workflow| {
+            workflow
+                .with_continue_on_failure(self.continue_on_failure)
+                .with_spill_threshold_bytes(self.spill_threshold_bytes)
+        })
+    }
+
+    /// Build, then run the steps through `client`, each as soon as its dependencies succeed
+    pub async fn execute(self, client: &impl Transport) -> Result<WorkflowRun, SdkError> {
+        Ok(run_workflow(client, &self.build()?).await)
+    }
+}
+
+/// Run `workflow` from the client, one [`Transport::execute`] per step, independent steps
+/// concurrently. Piped outputs reach the next step as its payload; one spilled to disk is
+/// read back for that, since the transport may be a gateway that can't see this host.
+pub async fn run_workflow(client: &impl Transport, workflow: &Workflow) -> WorkflowRun {
+    workflow
+        .run(|step, input| async move {
+            let payload = match input {
+                None => None,
+                Some(StepInput::Bytes(bytes)) => Some(bytes),
+                Some(StepInput::File { path, .. }) => Some(
+                    tokio::fs::read(&path)
+                        .await
+                        .map_err(|e| format!("reading {}: {e}", path.display()))?,
+                ),
+            };
+            let request = ExecuteRequest {
+                payload,
+                ..step_request(step)
+            };
+            client
+                .execute(request)
+                .await
+                .map(|response| StepOutput {
+                    exit_code: response.exit_code,
+                    stdout: response.stdout,
+                    stderr: response.stderr,
+                })
+                .map_err(|e| StepError::Failed(e.to_string()))
+        })
+        .await
+}
+
+fn step_request(step: WorkflowStep) -> ExecuteRequest {
+    ExecuteRequest {
+        command: step.command,
+        image: Some(step.image),
+        env_vars: (!step.env.is_empty()).then(|| step.env.into_iter().map(EnvVar::from).collect()),
+        timeout_ms: step.resources.timeout_ms,
+        memory_mb: step.resources.memory_mb,
+        cpu_cores: step.resources.cpu_cores,
+        ..Default::default()
+    }
+}
+
+impl FaasClient {
+    /// Run a workflow on the gateway and wait for every step
+    pub async fn submit_workflow(&self, workflow: &Workflow) -> Result<WorkflowRun, SdkError> {
+        let url = format!("{}/api/v1/workflows", self.base_url);
+        let response = self.client.post(&url).json(workflow).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Like [`submit_workflow`](Self::submit_workflow), under an id picked by the caller so
+    /// the run can be cancelled while this call waits
+    pub async fn submit_workflow_with_id(
+        &self,
+        workflow: &Workflow,
+        id: &str,
+    ) -> Result<WorkflowRun, SdkError> {
+        let url = format!("{}/api/v1/workflows", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .header("x-faas-workflow-id", id)
+            .json(workflow)
+            .send()
+            .await?;
+        json_or_error(response).await
+    }
+
+    /// Cancel a running workflow: its current step is stopped and the rest never start
+    pub async fn cancel_workflow(&self, id: &str) -> Result<CancelReport, SdkError> {
+        let url = format!("{}/api/v1/workflows/{}/cancel", self.base_url, id);
+        let response = self.client.post(&url).send().await?;
+        json_or_error(response).await
+    }
+
+    /// Load a `.yaml`/`.yml` or `.json` workflow file, validate it locally, and submit it
+    pub async fn submit_workflow_file(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<WorkflowRun, SdkError> {
+        let path = path.as_ref();
+        let contents = tokio::fs::read_to_string(path).await?;
+        let workflow = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Workflow::from_json(&contents)?,
+            _ => Workflow::from_yaml(&contents)?,
+        };
+        self.submit_workflow(&workflow).await
+    }
+}
@@ -0,0 +1,162 @@
+//! Streaming artifact downloads against the gateway's real range handler.
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Router,
+};
+use faas_gateway_server::artifacts::{
+    download_artifact_handler, upload_artifact_handler, ArtifactInfo, ArtifactStore,
+};
+use faas_sdk::{DownloadOptions, FaasClient, SdkError};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+fn test_data() -> Vec<u8> {
+    (0..8 * 1024 * 1024u32).map(|i| (i % 253) as u8).collect()
+}
+
+fn temp_store() -> Arc<ArtifactStore> {
+    let dir = std::env::temp_dir().join(format!("faas-sdk-artifacts-{}", uuid::Uuid::new_v4()));
+    Arc::new(ArtifactStore::new(dir).unwrap())
+}
+
+async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{addr}")
+}
+
+async fn gateway(store: Arc<ArtifactStore>) -> String {
+    serve(
+        Router::new()
+            .route("/api/v1/artifacts", put(upload_artifact_handler))
+            .route("/api/v1/artifacts/:hash", get(download_artifact_handler))
+            .with_state(store),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn download_streams_into_writer() {
+    let store = temp_store();
+    let client = FaasClient::new(gateway(store).await);
+    let data = test_data();
+
+    let info = client.upload_artifact(data.clone()).await.unwrap();
+    let mut out = Vec::new();
+    let outcome = client
+        .download_artifact_to(&info.hash, &mut out)
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.bytes_written, data.len() as u64);
+    assert_eq!(outcome.total_bytes, Some(data.len() as u64));
+    assert_eq!(format!("{:x}", Sha256::digest(&out)), info.hash);
+}
+
+#[tokio::test]
+async fn dropped_connection_is_resumed_with_range() {
+    let store = temp_store();
+    let data = test_data();
+    let info = client_upload(&store, &data).await;
+
+    // The first full GET dies after 1MB; ranged retries go to the real handler.
+    let dropped = Arc::new(AtomicBool::new(false));
+    let flaky = {
+        let store = store.clone();
+        let dropped = dropped.clone();
+        move |Path(hash): Path<String>, headers: HeaderMap| async move {
+            if headers.contains_key(header::RANGE) || dropped.swap(true, Ordering::SeqCst) {
+                return download_artifact_handler(State(store), Path(hash), headers).await;
+            }
+            let full = download_artifact_handler(State(store), Path(hash), headers).await;
+            let (parts, body) = full.into_parts();
+            let mut sent = 0usize;
+            let truncated = futures::StreamExt::take_while(body.into_data_stream(), move |chunk| {
+                let keep = sent < 1024 * 1024;
+                if let Ok(chunk) = chunk {
+                    sent += chunk.len();
+                }
+                futures::future::ready(keep)
+            });
+            let failing = futures::StreamExt::chain(
+                truncated,
+                futures::stream::once(async {
+                    Err::<bytes::Bytes, axum::Error>(axum::Error::new(std::io::Error::other(
+                        "connection reset",
+                    )))
+                }),
+            );
+            Response::from_parts(parts, Body::from_stream(failing)).into_response()
+        }
+    };
+    let base_url = serve(Router::new().route("/api/v1/artifacts/:hash", get(flaky))).await;
+    let client = FaasClient::new(base_url);
+
+    let mut out = Vec::new();
+    let outcome = client
+        .download_artifact_to(&info.hash, &mut out)
+        .await
+        .unwrap();
+
+    assert!(dropped.load(Ordering::SeqCst));
+    assert_eq!(outcome.bytes_written, data.len() as u64);
+    assert_eq!(out, data);
+}
+
+#[tokio::test]
+async fn explicit_resume_and_changed_content() {
+    let store = temp_store();
+    let client = FaasClient::new(gateway(store).await);
+    let data = test_data();
+    let info = client.upload_artifact(data.clone()).await.unwrap();
+
+    // A previous process got the first 3MB before it was killed.
+    let already = 3 * 1024 * 1024;
+    let mut out = data[..already].to_vec();
+    let outcome = client
+        .download_artifact_with(
+            &info.hash,
+            &mut out,
+            DownloadOptions {
+                offset: already as u64,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcome.bytes_written, data.len() as u64);
+    assert_eq!(format!("{:x}", Sha256::digest(&out)), info.hash);
+
+    let mut discard = Vec::new();
+    let result = client
+        .download_artifact_with(
+            &info.hash,
+            &mut discard,
+            DownloadOptions {
+                offset: already as u64,
+                etag: Some("\"stale\"".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(matches!(result, Err(SdkError::ContentChanged)));
+    assert!(discard.is_empty());
+}
+
+async fn client_upload(store: &ArtifactStore, data: &[u8]) -> ArtifactInfo {
+    store
+        .put_stream(futures::stream::iter(vec![Ok::<_, std::io::Error>(
+            bytes::Bytes::copy_from_slice(data),
+        )]))
+        .await
+        .unwrap()
+}
@@ -0,0 +1,148 @@
+//! API keys against a gateway stand-in guarded by the gateway's own key middleware.
+
+use axum::http::HeaderMap;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use dashmap::DashMap;
+use faas_gateway_server::auth::{require_api_key, ApiKeyConfig, ApiKeys, Permission};
+use faas_gateway_server::instance_ttl;
+use faas_gateway_server::tenancy::{self, Scope};
+use faas_sdk::{ExecuteRequest, FaasClient, InstanceResponse, SdkError};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn gateway() -> String {
+    let keys = ApiKeys::new(vec![
+        ApiKeyConfig {
+            key: "sk-runner".to_string(),
+            name: "runner".to_string(),
+            permissions: vec![Permission::Execute],
+            tenant: None,
+            rate_limit: None,
+            limits: None,
+        },
+        ApiKeyConfig {
+            key: "sk-dashboard".to_string(),
+            name: "dashboard".to_string(),
+            permissions: vec![Permission::ReadMetrics],
+            tenant: None,
+            rate_limit: None,
+            limits: None,
+        },
+        ApiKeyConfig {
+            key: "sk-acme".to_string(),
+            name: "acme".to_string(),
+            permissions: vec![Permission::ManageInstances],
+            tenant: Some("acme".to_string()),
+            rate_limit: None,
+            limits: None,
+        },
+        ApiKeyConfig {
+            key: "sk-root".to_string(),
+            name: "root".to_string(),
+            permissions: vec![Permission::Admin],
+            tenant: None,
+            rate_limit: None,
+            limits: None,
+        },
+    ]);
+    let instances = Arc::new(DashMap::new());
+    for tenant in ["acme", "globex"] {
+        let mut instance = instance_ttl::persistent_instance(
+            &format!("{tenant}-1"),
+            "alpine",
+            None,
+            None,
+            Duration::from_secs(60),
+            chrono::Utc::now(),
+        );
+        instance.tenant = Some(tenant.to_string());
+        instances.insert(instance.id.clone(), instance);
+    }
+    let app = Router::new()
+        .route(
+            "/api/v1/execute",
+            post(|| async {
+                Json(json!({
+                    "request_id": "req-1",
+                    "exit_code": 0,
+                    "stdout": "ok\n",
+                    "stderr": "",
+                    "duration_ms": 1
+                }))
+            }),
+        )
+        .route(
+            "/api/v1/instances",
+            get(|headers: HeaderMap| async move {
+                Json(tenancy::instances(
+                    &instances,
+                    &Scope::from_headers(&headers),
+                ))
+            }),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::new(keys),
+            require_api_key,
+        ));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{addr}")
+}
+
+fn echo() -> ExecuteRequest {
+    ExecuteRequest {
+        command: "echo ok".to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn the_api_key_goes_with_every_request() {
+    let url = gateway().await;
+    let client = FaasClient::new(url).with_api_key("sk-runner");
+    assert_eq!(client.execute(echo()).await.unwrap().stdout, "ok\n");
+}
+
+#[tokio::test]
+async fn missing_and_underprivileged_keys_are_refused() {
+    let url = gateway().await;
+    let anonymous = FaasClient::new(url.clone()).execute(echo()).await;
+    assert!(matches!(
+        anonymous,
+        Err(SdkError::Api { status: 401, code, .. }) if code == "Unauthorized"
+    ));
+    let dashboard = FaasClient::new(url)
+        .with_api_key("sk-dashboard")
+        .execute(echo())
+        .await;
+    assert!(matches!(
+        dashboard,
+        Err(SdkError::Api { status: 403, code, .. }) if code == "Forbidden"
+    ));
+}
+
+async fn listed(client: &FaasClient) -> Vec<String> {
+    let instances: Vec<InstanceResponse> = client.list_instances().await.unwrap();
+    let mut ids: Vec<String> = instances.into_iter().map(|i| i.instance_id).collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn only_an_admin_key_picks_the_namespace_it_acts_for() {
+    let url = gateway().await;
+    let root = FaasClient::new(url.clone()).with_api_key("sk-root");
+    assert_eq!(listed(&root).await, ["acme-1", "globex-1"]);
+    let globex = root.with_namespace("globex");
+    assert_eq!(listed(&globex).await, ["globex-1"]);
+
+    let acme = FaasClient::new(url)
+        .with_api_key("sk-acme")
+        .with_namespace("globex");
+    assert_eq!(listed(&acme).await, ["acme-1"]);
+}
@@ -0,0 +1,103 @@
+//! Bash scripts against a gateway stand-in that runs each request the way the executor
+//! does: the command under `sh -c`, with the payload on stdin.
+
+use axum::{routing::post, Json, Router};
+use faas_sdk::FaasClient;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+async fn gateway() -> (FaasClient, Arc<Mutex<Vec<Value>>>) {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorded = seen.clone();
+    let app = Router::new().route(
+        "/api/v1/execute",
+        post(move |Json(request): Json<Value>| {
+            let seen = recorded.clone();
+            async move {
+                seen.lock().unwrap().push(request.clone());
+                let payload: Vec<u8> =
+                    serde_json::from_value(request["payload"].clone()).unwrap_or_default();
+                let mut child = Command::new("sh")
+                    .args(["-c", request["command"].as_str().unwrap()])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .unwrap();
+                child.stdin.take().unwrap().write_all(&payload).unwrap();
+                let output = child.wait_with_output().unwrap();
+                Json(json!({
+                    "request_id": "req-1",
+                    "exit_code": output.status.code(),
+                    "stdout": String::from_utf8_lossy(&output.stdout),
+                    "stderr": String::from_utf8_lossy(&output.stderr),
+                    "duration_ms": 1
+                }))
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (FaasClient::new(format!("http://{addr}")), seen)
+}
+
+fn has_bash() -> bool {
+    let found = Command::new("bash")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .is_ok();
+    if !found {
+        eprintln!("Test skipped: bash not available");
+    }
+    found
+}
+
+#[tokio::test]
+async fn scripts_go_to_stdin_not_the_command_line() {
+    let (client, seen) = gateway().await;
+    let script = "echo \"quoted\" `echo backticks` $(echo substituted)";
+    client.run_bash(script).await.unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen[0]["command"], "bash -s");
+    let payload: Vec<u8> = serde_json::from_value(seen[0]["payload"].clone()).unwrap();
+    assert_eq!(payload, script.as_bytes());
+}
+
+#[tokio::test]
+async fn scripts_that_would_break_quoting_run_as_written() {
+    if !has_bash() {
+        return;
+    }
+    let (client, _) = gateway().await;
+    let result = client
+        .run_bash(concat!(
+            "name=\"world\"; echo \"hello \\\"$name\\\"\"\n",
+            "echo `echo back` $(echo sub) '$(not run)'\n",
+            "echo \"; exit 7; echo \"\n",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        result.stdout,
+        "hello \"world\"\nback sub $(not run)\n; exit 7; echo \n"
+    );
+    assert_eq!(result.exit_code, 0);
+}
+
+#[tokio::test]
+async fn exit_codes_and_stderr_come_through() {
+    if !has_bash() {
+        return;
+    }
+    let (client, _) = gateway().await;
+    let result = client.run_bash("echo \"oops\" >&2\nexit 3").await.unwrap();
+    assert_eq!(result.exit_code, 3);
+    assert_eq!(result.stderr, "oops\n");
+}
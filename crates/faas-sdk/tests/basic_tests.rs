@@ -45,9 +45,9 @@ fn test_cache_key_generation() {
     let code2 = "print('hello')";
     let code3 = "print('world')";
 
-    let hash1 = format!("{:x}", md5::compute(code1.as_bytes()));
-    let hash2 = format!("{:x}", md5::compute(code2.as_bytes()));
-    let hash3 = format!("{:x}", md5::compute(code3.as_bytes()));
+    let hash1 = faas_common::hash::sha256_hex(code1);
+    let hash2 = faas_common::hash::sha256_hex(code2);
+    let hash3 = faas_common::hash::sha256_hex(code3);
 
     assert_eq!(hash1, hash2); // Same code should have same hash
     assert_ne!(hash1, hash3); // Different code should have different hash
@@ -60,7 +60,7 @@ fn test_serialization() {
         command: "echo test".to_string(),
         image: Some("alpine:latest".to_string()),
         runtime: Some(Runtime::Docker),
-        env_vars: Some(vec![("TEST".to_string(), "value".to_string())]),
+        env_vars: Some(vec![faas_sdk::EnvVar::new("TEST", "value")]),
         working_dir: Some("/app".to_string()),
         timeout_ms: Some(30000),
         cache_key: Some("test-cache-key".to_string()),
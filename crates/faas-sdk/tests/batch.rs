@@ -0,0 +1,113 @@
+//! Batches sent to a gateway stand-in that runs them through the gateway's own batch
+//! runner, echoing each command unless its image doesn't exist.
+
+use axum::{http::HeaderMap, http::StatusCode, response::Response, routing::post, Json, Router};
+use faas_gateway_server::{batch, errors::ApiError, InvokeResponse};
+use faas_sdk::{ExecuteRequest, FaasClient, SdkError};
+use serde::Deserialize;
+
+const MISSING_IMAGE: &str = "no-such-image:latest";
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    requests: Vec<ExecuteRequest>,
+    max_parallelism: Option<usize>,
+}
+
+async fn gateway() -> FaasClient {
+    let app = Router::new().route("/api/v1/execute/batch", post(execute_batch));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+async fn execute_batch(
+    headers: HeaderMap,
+    Json(req): Json<BatchRequest>,
+) -> Result<Response, ApiError> {
+    let limits = batch::BatchLimits {
+        max_items: 20,
+        max_parallelism: 4,
+    };
+    let parallelism = limits.parallelism(req.requests.len(), req.max_parallelism)?;
+    let items = batch::run(req.requests, parallelism, |req| async move { echo(req) });
+    Ok(batch::respond(&headers, items).await)
+}
+
+fn echo(req: ExecuteRequest) -> Result<InvokeResponse, ApiError> {
+    if let Some(image) = req.image.as_deref().filter(|image| *image == MISSING_IMAGE) {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "ImageNotFound",
+            format!("image {image} not found"),
+        ));
+    }
+    let stdout = req.command.trim_start_matches("echo ").to_string();
+    Ok(InvokeResponse {
+        request_id: stdout.clone(),
+        exit_code: 0,
+        stdout: stdout.clone(),
+        stderr: String::new(),
+        duration_ms: 1,
+        output: Some(stdout),
+        logs: None,
+        error: None,
+        cache_hit: false,
+        cache_key: req.cache_key,
+        runtime: None,
+        runtime_reason: None,
+        diagnostics: None,
+        usage: None,
+        truncated: false,
+        total_bytes: None,
+        snapshot_id: None,
+    })
+}
+
+fn echoes(count: usize) -> Vec<ExecuteRequest> {
+    (0..count)
+        .map(|i| ExecuteRequest {
+            command: format!("echo {i}"),
+            image: Some("alpine:latest".to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn a_bad_image_fails_only_its_own_item() {
+    let mut requests = echoes(10);
+    requests[6].image = Some(MISSING_IMAGE.to_string());
+
+    let results = gateway().await.execute_batch(requests).await;
+    assert_eq!(results.len(), 10);
+    for (i, result) in results.iter().enumerate() {
+        if i == 6 {
+            continue;
+        }
+        let response = result.as_ref().unwrap();
+        assert_eq!(response.output.as_deref(), Some(i.to_string().as_str()));
+    }
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 9);
+    match &results[6] {
+        Err(SdkError::Api { status, code, .. }) => {
+            assert_eq!(*status, 404);
+            assert_eq!(code, "ImageNotFound");
+        }
+        other => panic!("expected a structured error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn a_refused_batch_fails_every_item() {
+    let client = gateway().await;
+    let results = client.execute_batch_with_parallelism(echoes(21), 2).await;
+    assert_eq!(results.len(), 21);
+    assert!(results.iter().all(|r| matches!(
+        r,
+        Err(SdkError::Api { status: 413, code, .. }) if code == "BatchTooLarge"
+    )));
+}
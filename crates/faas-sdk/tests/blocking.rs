@@ -0,0 +1,154 @@
+//! The blocking client against a mock gateway, from plain `#[test]`s without a runtime.
+
+#![cfg(feature = "blocking")]
+
+use faas_sdk::blocking::FaasClient;
+use faas_sdk::{CreateInstanceRequest, CreateSnapshotRequest, RequestError, SdkError};
+use mockito::{Matcher, Server};
+use serde_json::json;
+
+fn execution(stdout: &str) -> String {
+    json!({
+        "request_id": "req-1",
+        "exit_code": 0,
+        "stdout": stdout,
+        "stderr": "",
+        "duration_ms": 3,
+    })
+    .to_string()
+}
+
+#[test]
+fn executions_run_to_completion_without_a_runtime() {
+    let mut server = Server::new();
+    let execute = server
+        .mock("POST", "/api/v1/execute")
+        .match_body(Matcher::PartialJson(json!({
+            "command": "echo hello",
+            "image": "alpine:latest",
+            "timeout_ms": 5000,
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(execution("hello\n"))
+        .create();
+    let python = server
+        .mock("POST", "/api/v1/execute")
+        .match_body(Matcher::PartialJson(json!({"command": "python"})))
+        .with_header("content-type", "application/json")
+        .with_body(execution("42\n"))
+        .create();
+
+    let client = FaasClient::new(server.url()).unwrap();
+    let result = client
+        .execute_with(|b| {
+            b.command("echo hello")
+                .image("alpine:latest")
+                .timeout_ms(5000)
+        })
+        .unwrap();
+    assert_eq!(result.stdout, "hello\n");
+    assert_eq!(client.run_python("print(42)").unwrap().stdout, "42\n");
+    execute.assert();
+    python.assert();
+
+    let metrics = client.client_metrics();
+    assert_eq!(metrics.total_requests, 2);
+}
+
+#[test]
+fn snapshots_and_instances() {
+    let mut server = Server::new();
+    let snapshot = json!({
+        "snapshot_id": "snap-1",
+        "name": "warm",
+        "size_bytes": 1024,
+        "created_at": "2026-01-01T00:00:00Z",
+        "status": "ready",
+    });
+    server
+        .mock("POST", "/api/v1/snapshots")
+        .with_header("content-type", "application/json")
+        .with_body(snapshot.to_string())
+        .create();
+    server
+        .mock("GET", "/api/v1/snapshots")
+        .with_header("content-type", "application/json")
+        .with_body(json!([snapshot]).to_string())
+        .create();
+    server
+        .mock("POST", "/api/v1/instances")
+        .match_body(Matcher::PartialJson(json!({"image": "alpine:latest"})))
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!({
+                "instance_id": "inst-1",
+                "status": "running",
+                "created_at": "2026-01-01T00:00:00Z",
+                "endpoints": null,
+                "expires_at": null,
+            })
+            .to_string(),
+        )
+        .create();
+    let deleted = server
+        .mock("DELETE", "/api/v1/snapshots/snap-1")
+        .with_status(204)
+        .create();
+
+    let client = FaasClient::new(server.url()).unwrap();
+    let created = client
+        .create_snapshot(CreateSnapshotRequest {
+            name: "warm".to_string(),
+            container_id: "ctr-1".to_string(),
+            description: None,
+            tags: Vec::new(),
+            group_id: None,
+            wait: false,
+        })
+        .unwrap();
+    assert_eq!(created.snapshot_id, "snap-1");
+    let listed = client.list_snapshots().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].status.as_deref(), Some("ready"));
+    client.delete_snapshot("snap-1").unwrap();
+    deleted.assert();
+
+    let instance = client
+        .create_instance(CreateInstanceRequest {
+            name: None,
+            image: "alpine:latest".to_string(),
+            cpu_cores: None,
+            memory_mb: None,
+            persistent: None,
+            workspace_path: None,
+            ports: Vec::new(),
+        })
+        .unwrap();
+    assert_eq!(instance.instance_id, "inst-1");
+}
+
+#[test]
+fn errors_are_the_async_clients() {
+    let mut server = Server::new();
+    server
+        .mock("GET", "/api/v1/snapshots/missing")
+        .with_status(404)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"code": "NotFound", "message": "no such snapshot"}).to_string())
+        .create();
+    let never_sent = server.mock("POST", "/api/v1/execute").expect(0).create();
+
+    let client = FaasClient::new(server.url()).unwrap();
+    match client.get_snapshot("missing") {
+        Err(SdkError::Api { status, code, .. }) => {
+            assert_eq!(status, 404);
+            assert_eq!(code, "NotFound");
+        }
+        other => panic!("expected a 404, got {other:?}"),
+    }
+    assert!(matches!(
+        client.execute_with(|b| b.command("ls").timeout_ms(0)),
+        Err(SdkError::Build(RequestError::ZeroTimeout))
+    ));
+    never_sent.assert();
+}
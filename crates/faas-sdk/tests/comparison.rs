@@ -0,0 +1,101 @@
+//! Baseline comparisons against the gateway's real comparison handlers.
+
+use axum::{
+    routing::{get, put},
+    Router,
+};
+use faas_gateway_server::comparison::{
+    group_comparison_handler, list_normalizers_handler, put_normalizer_handler,
+};
+use faas_gateway_server::groups::{CreateGroupRequest, GroupRegistry, HttpWebhookSink};
+use faas_sdk::{FaasClient, ItemOutcome, Normalizer};
+use std::sync::Arc;
+
+/// A settled group with one execution per `(item_key, stdout)`
+fn settled_group(groups: &GroupRegistry, outputs: &[(&str, &str)]) -> String {
+    let group = groups.create(CreateGroupRequest {
+        expected: Some(outputs.len()),
+        ..Default::default()
+    });
+    for (key, stdout) in outputs {
+        let id = format!("{}-{key}", group.group_id);
+        groups.join(&group.group_id, &id).unwrap();
+        groups.record_output(&group.group_id, &id, Some(key), 0, stdout.as_bytes());
+        groups.finish(&group.group_id, &id, true, Some(1));
+    }
+    group.group_id
+}
+
+#[tokio::test]
+async fn compare_groups_reports_the_changed_item() {
+    let groups = Arc::new(GroupRegistry::new(Arc::new(HttpWebhookSink::new())));
+    let baseline = settled_group(
+        &groups,
+        &[
+            ("q1", r#"{"answer": "Paris", "at": "2026-01-01T00:00:00Z"}"#),
+            ("q2", r#"{"answer": "4", "at": "2026-01-01T00:00:01Z"}"#),
+        ],
+    );
+    let rerun = settled_group(
+        &groups,
+        &[
+            ("q1", r#"{"answer": "Paris", "at": "2026-03-01T00:00:00Z"}"#),
+            ("q2", r#"{"answer": "5", "at": "2026-03-01T00:00:01Z"}"#),
+        ],
+    );
+
+    let app = Router::new()
+        .route(
+            "/api/v1/groups/:id/comparison",
+            get(group_comparison_handler),
+        )
+        .route("/api/v1/normalizers", get(list_normalizers_handler))
+        .route("/api/v1/normalizers/:name", put(put_normalizer_handler))
+        .with_state(groups);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    let client = FaasClient::new(format!("http://{addr}"));
+
+    let bad = Normalizer {
+        drop_fields: vec!["at".to_string()],
+        ..Default::default()
+    };
+    assert!(client.put_normalizer("bad", &bad).await.is_err());
+    client
+        .put_normalizer(
+            "json",
+            &Normalizer {
+                parse_json: true,
+                drop_fields: vec!["$.at".to_string()],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    let report = client
+        .compare_groups(&rerun, &baseline, Some("json"))
+        .await
+        .unwrap();
+    assert_eq!((report.unchanged, report.regressed), (1, 1));
+    let changed = report
+        .items
+        .iter()
+        .find(|item| item.outcome == ItemOutcome::Regressed)
+        .unwrap();
+    assert_eq!(changed.key, "q2");
+    assert_eq!(
+        changed.diff.as_ref().unwrap().excerpt,
+        [r#"-   "answer": "4""#, r#"+   "answer": "5""#]
+    );
+
+    // Without the preset the timestamps differ too
+    let strict = client
+        .compare_groups(&rerun, &baseline, None)
+        .await
+        .unwrap();
+    assert_eq!(strict.regressed, 2);
+}
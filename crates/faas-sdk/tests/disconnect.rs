@@ -0,0 +1,120 @@
+//! A client that stops waiting for an execution, against a gateway stand-in that runs it
+//! the way the gateway does: in a task of its own, cancelled through the gateway's guard
+//! when the handler is dropped.
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use faas_executor::bollard::{container::ListContainersOptions, Docker};
+use faas_executor::platform;
+use faas_gateway_server::cancellation::CancelOnDrop;
+use faas_gateway_server::InvokeResponse;
+use faas_sdk::{ExecuteRequest, FaasClient};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const EXECUTION_ID: &str = "disconnect-sleep-60";
+
+async fn gateway(executor: Arc<platform::Executor>) -> FaasClient {
+    let app = Router::new()
+        .route("/api/v1/execute", post(execute))
+        .with_state(executor);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+async fn execute(
+    State(executor): State<Arc<platform::Executor>>,
+    Json(req): Json<ExecuteRequest>,
+) -> Result<Json<InvokeResponse>, StatusCode> {
+    let request = platform::Request {
+        id: EXECUTION_ID.to_string(),
+        code: req.command,
+        env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
+        timeout: Duration::from_secs(120),
+        ..Default::default()
+    };
+    let mut abandoned = CancelOnDrop::new(executor.clone(), EXECUTION_ID);
+    let execution = tokio::spawn(async move { executor.run(request).await });
+    let response = execution.await.unwrap();
+    abandoned.disarm();
+    let response = response.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(InvokeResponse {
+        request_id: response.id,
+        exit_code: response.exit_code,
+        stdout: String::from_utf8_lossy(&response.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&response.stderr).to_string(),
+        duration_ms: response.duration.as_millis() as u64,
+        output: None,
+        logs: None,
+        error: None,
+        cache_hit: false,
+        cache_key: None,
+        runtime: None,
+        runtime_reason: None,
+        diagnostics: None,
+        usage: None,
+        truncated: false,
+        total_bytes: None,
+        snapshot_id: None,
+    }))
+}
+
+async fn containers() -> usize {
+    let docker = Docker::connect_with_local_defaults().unwrap();
+    docker
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            filters: [("name".to_string(), vec![format!("faas-{EXECUTION_ID}-")])].into(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .len()
+}
+
+#[tokio::test]
+async fn dropping_the_client_removes_the_container() {
+    if !faas_executor::test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    std::env::set_var("FAAS_DISABLE_PREWARM", "1");
+    let executor = Arc::new(
+        platform::Executor::new(platform::PlatformConfig::default())
+            .await
+            .unwrap(),
+    );
+    let client = gateway(executor).await;
+    let request = tokio::spawn(async move {
+        client
+            .execute(ExecuteRequest {
+                command: "sleep 60".to_string(),
+                image: Some("alpine:latest".to_string()),
+                ..Default::default()
+            })
+            .await
+    });
+
+    let started = Instant::now();
+    while containers().await == 0 {
+        assert!(
+            started.elapsed() < Duration::from_secs(60),
+            "container never started"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    request.abort();
+
+    let dropped = Instant::now();
+    while containers().await > 0 {
+        assert!(
+            dropped.elapsed() < Duration::from_secs(3),
+            "container outlived its client"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
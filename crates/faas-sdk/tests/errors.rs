@@ -0,0 +1,106 @@
+//! Error responses against a gateway stand-in built from the gateway's own error types.
+
+use axum::{
+    http::StatusCode,
+    middleware,
+    routing::{get, post},
+    Json, Router,
+};
+use faas_executor::platform::negative_cache::FailureKind;
+use faas_executor::platform::ResolutionFailure;
+use faas_gateway_server::errors::{fill_error_body, ApiError};
+use faas_sdk::{ExecuteRequest, FaasClient, SdkError};
+use serde_json::{json, Value};
+
+async fn gateway() -> FaasClient {
+    let app = Router::new()
+        .route(
+            "/api/v1/execute",
+            post(|Json(req): Json<Value>| async move {
+                faas_gateway_server::execution_mode(req["mode"].as_str())?;
+                let image = req["image"].as_str().unwrap_or("alpine:latest").to_string();
+                if image.starts_with("missing/") {
+                    let failure = ResolutionFailure {
+                        kind: FailureKind::ImageNotFound,
+                        message: format!("image {image} not found"),
+                        key: image,
+                        cached_error: false,
+                        retry_in_ms: 30_000,
+                    };
+                    return Err(ApiError::from_failure(&failure));
+                }
+                Ok(Json(json!({
+                    "request_id": "req-1",
+                    "exit_code": 0,
+                    "stdout": "",
+                    "stderr": "",
+                    "duration_ms": 1,
+                })))
+            }),
+        )
+        .route(
+            "/api/v1/snapshots/:id",
+            get(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+        )
+        .layer(middleware::from_fn(fill_error_body));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+#[tokio::test]
+async fn a_nonexistent_image_is_a_404() {
+    let client = gateway().await;
+    let error = client
+        .execute(ExecuteRequest {
+            command: "true".to_string(),
+            image: Some("missing/app:1".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    match error {
+        SdkError::Api {
+            status,
+            code,
+            message,
+        } => {
+            assert_eq!(status, 404);
+            assert_eq!(code, "ImageNotFound");
+            assert_eq!(message, "image missing/app:1 not found");
+        }
+        other => panic!("expected an API error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn a_bad_mode_is_an_invalid_request() {
+    let client = gateway().await;
+    let error = client
+        .execute(ExecuteRequest {
+            command: "true".to_string(),
+            mode: Some("warp".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        SdkError::Validation { ref field, ref message }
+            if field == "mode" && message.contains("\"warp\"")
+    ));
+}
+
+#[tokio::test]
+async fn a_bare_status_still_has_a_code() {
+    let client = gateway().await;
+    let error = client.get_snapshot("snap-1").await.unwrap_err();
+    assert!(matches!(
+        error,
+        SdkError::Api { status: 503, ref code, ref message }
+            if code == "Unavailable" && message == "Service Unavailable"
+    ));
+}
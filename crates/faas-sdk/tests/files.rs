@@ -0,0 +1,131 @@
+//! Instance file transfer against a gateway stand-in that packs and unpacks archives the way
+//! Docker does, over a local directory standing in for the container's filesystem.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use faas_gateway_server::instance_files::{FilesQuery, TAR_CONTENT_TYPE};
+use faas_sdk::{FaasClient, SdkError};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const INSTANCE_ID: &str = "inst-1";
+
+async fn gateway() -> (FaasClient, tempfile::TempDir) {
+    let root = tempfile::tempdir().unwrap();
+    let app = Router::new()
+        .route("/api/v1/instances/:id/files", post(upload).get(download))
+        .with_state(Arc::new(root.path().to_path_buf()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (FaasClient::new(format!("http://{addr}")), root)
+}
+
+async fn upload(
+    State(root): State<Arc<PathBuf>>,
+    Path(id): Path<String>,
+    Query(query): Query<FilesQuery>,
+    body: Bytes,
+) -> StatusCode {
+    if id != INSTANCE_ID {
+        return StatusCode::NOT_FOUND;
+    }
+    let target = root.join(query.path.trim_start_matches('/'));
+    match tar::Archive::new(&body[..]).unpack(target) {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+async fn download(State(root): State<Arc<PathBuf>>, Query(query): Query<FilesQuery>) -> Response {
+    let relative = query.path.trim_start_matches('/');
+    let source = root.join(relative);
+    // Named from the requested path's last component, like `docker cp`
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    let mut builder = tar::Builder::new(Vec::new());
+    let packed = if source.is_dir() {
+        builder.append_dir_all(name, &source)
+    } else if source.is_file() {
+        builder.append_path_with_name(&source, name)
+    } else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    packed.unwrap();
+    (
+        [(header::CONTENT_TYPE, TAR_CONTENT_TYPE)],
+        builder.into_inner().unwrap(),
+    )
+        .into_response()
+}
+
+#[tokio::test]
+async fn a_directory_tree_round_trips() {
+    let (client, _root) = gateway().await;
+    let local = tempfile::tempdir().unwrap();
+    let files: [(&str, &[u8]); 3] = [
+        ("readme.md", b"# project\n"),
+        ("src/main.py", b"print('hi')\n"),
+        ("src/data/blob.bin", &[0, 1, 2, 254, 255]),
+    ];
+    for (path, contents) in files {
+        let path = local.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    client
+        .upload_dir(INSTANCE_ID, local.path(), "/work/project")
+        .await
+        .unwrap();
+    for (path, contents) in files {
+        let downloaded = client
+            .download_file(INSTANCE_ID, &format!("/work/project/{path}"))
+            .await
+            .unwrap();
+        assert_eq!(downloaded, contents, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn a_single_file_lands_at_its_path() {
+    let (client, root) = gateway().await;
+    client
+        .upload_file(INSTANCE_ID, "/etc/app/config.toml", b"debug = true\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read(root.path().join("etc/app/config.toml")).unwrap(),
+        b"debug = true\n"
+    );
+    assert_eq!(
+        client
+            .download_file(INSTANCE_ID, "/etc/app/config.toml")
+            .await
+            .unwrap(),
+        b"debug = true\n"
+    );
+}
+
+#[tokio::test]
+async fn directories_and_missing_paths_are_not_files() {
+    let (client, _root) = gateway().await;
+    client
+        .upload_file(INSTANCE_ID, "/data/one.txt", b"1")
+        .await
+        .unwrap();
+
+    let directory = client.download_file(INSTANCE_ID, "/data").await;
+    assert!(matches!(directory, Err(SdkError::RequestFailed(_))));
+    let missing = client.download_file(INSTANCE_ID, "/nope").await;
+    assert!(matches!(missing, Err(SdkError::Api { status: 404, .. })));
+    let relative = client.upload_file(INSTANCE_ID, "data/two.txt", b"2").await;
+    assert!(matches!(relative, Err(SdkError::RequestFailed(_))));
+}
@@ -0,0 +1,126 @@
+//! Branches of an execution, against a gateway stand-in that runs parents and forks on the
+//! platform executor the way the gateway's execute and fork-from-parent handlers do.
+
+use axum::{
+    extract::{Path, State},
+    routing::post,
+    Json, Router,
+};
+use faas_executor::platform;
+use faas_gateway_server::errors::ApiError;
+use faas_gateway_server::response::ResponseBuilder;
+use faas_gateway_server::InvokeResponse;
+use faas_sdk::{ExecuteRequest, FaasClient, SdkError};
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn gateway(executor: Arc<platform::Executor>) -> FaasClient {
+    let app = Router::new()
+        .route("/api/v1/execute", post(execute))
+        .route("/api/v1/executions/:id/fork", post(fork))
+        .with_state(executor);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+async fn run(
+    executor: &platform::Executor,
+    req: ExecuteRequest,
+    mode: platform::Mode,
+    branch_from: Option<String>,
+) -> Result<Json<InvokeResponse>, ApiError> {
+    let response = executor
+        .run(platform::Request {
+            id: uuid::Uuid::new_v4().to_string(),
+            code: req.command,
+            mode,
+            env: req.image.unwrap_or_else(|| "alpine:latest".to_string()),
+            timeout: Duration::from_secs(60),
+            branch_from,
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| ApiError::from_failure(e.as_ref()))?;
+    Ok(Json(ResponseBuilder::new(response).build()))
+}
+
+async fn execute(
+    State(executor): State<Arc<platform::Executor>>,
+    Json(req): Json<ExecuteRequest>,
+) -> Result<Json<InvokeResponse>, ApiError> {
+    let mode = faas_gateway_server::execution_mode(req.mode.as_deref())?;
+    let branch_from = req.branch_from.clone();
+    run(&executor, req, mode, branch_from).await
+}
+
+async fn fork(
+    State(executor): State<Arc<platform::Executor>>,
+    Path(parent_id): Path<String>,
+    Json(req): Json<ExecuteRequest>,
+) -> Result<Json<InvokeResponse>, ApiError> {
+    run(&executor, req, platform::Mode::Branched, Some(parent_id)).await
+}
+
+async fn client() -> Option<FaasClient> {
+    if !faas_executor::test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    std::env::set_var("FAAS_DISABLE_PREWARM", "1");
+    let executor = Arc::new(
+        platform::Executor::new(platform::PlatformConfig::default())
+            .await
+            .unwrap(),
+    );
+    Some(gateway(executor).await)
+}
+
+#[tokio::test]
+async fn parallel_branches_see_the_parents_files() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let parent = client
+        .execute(ExecuteRequest {
+            command: "mkdir -p /state && echo from-parent > /state/file.txt".to_string(),
+            image: Some("alpine:latest".to_string()),
+            mode: Some("branched".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(parent.exit_code, 0);
+
+    let read = "cat /state/file.txt && echo $$ > /state/branch.txt";
+    let (first, second) = tokio::join!(
+        client.fork_execution(&parent.request_id, read),
+        client.fork_execution(&parent.request_id, read),
+    );
+    for branch in [first.unwrap(), second.unwrap()] {
+        assert_eq!(branch.exit_code, 0);
+        assert_eq!(branch.stdout.trim(), "from-parent");
+        assert_ne!(branch.request_id, parent.request_id);
+    }
+
+    // What a branch writes stays in the branch
+    let parent_again = client
+        .fork_execution(&parent.request_id, "test ! -e /state/branch.txt")
+        .await
+        .unwrap();
+    assert_eq!(parent_again.exit_code, 0);
+}
+
+#[tokio::test]
+async fn forking_an_unknown_execution_is_not_found() {
+    let Some(client) = client().await else {
+        return;
+    };
+    match client.fork_execution("no-such-parent", "true").await {
+        Err(SdkError::Api { status, .. }) => assert_eq!(status, 404),
+        other => panic!("expected a 404, got {other:?}"),
+    }
+}
@@ -0,0 +1,241 @@
+//! Fork strategies against a gateway stand-in built from the gateway's fork runner and
+//! cancel registry. Its branches run on the platform executor in tasks of their own and
+//! are cancelled through the gateway's guard, as the gateway's fork handler does.
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use faas_executor::bollard::{container::ListContainersOptions, Docker};
+use faas_executor::platform;
+use faas_gateway_server::cancellation::{CancelOnDrop, CancelPolicy, CancelRegistry, CancelScope};
+use faas_gateway_server::errors::ApiError;
+use faas_gateway_server::fork::{self, ForkBranch, ForkResult, ForkStrategy};
+use faas_gateway_server::response::ResponseBuilder;
+use faas_gateway_server::InvokeResponse;
+use faas_sdk::{ExecuteRequest, FaasClient, ForkOutcome};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Clone)]
+struct Gateway {
+    executor: Arc<platform::Executor>,
+    cancels: Arc<CancelRegistry>,
+}
+
+async fn gateway(executor: Arc<platform::Executor>) -> FaasClient {
+    let gateway = Gateway {
+        executor,
+        cancels: Arc::new(CancelRegistry::new()),
+    };
+    let app = Router::new()
+        .route("/api/v1/execute", post(execute))
+        .route("/api/v1/fork", post(fork_branches))
+        .with_state(gateway);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+fn request(id: String, code: String, branch_from: Option<String>) -> platform::Request {
+    platform::Request {
+        id,
+        code,
+        mode: platform::Mode::Branched,
+        env: "alpine:latest".to_string(),
+        timeout: Duration::from_secs(120),
+        branch_from,
+        ..Default::default()
+    }
+}
+
+async fn execute(
+    State(gateway): State<Gateway>,
+    Json(req): Json<ExecuteRequest>,
+) -> Result<Json<InvokeResponse>, ApiError> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let response = gateway
+        .executor
+        .run(request(id, req.command, None))
+        .await
+        .map_err(|e| ApiError::from_failure(e.as_ref()))?;
+    Ok(Json(ResponseBuilder::new(response).build()))
+}
+
+/// Run `req` until it finishes or a cancel reaches `scope`
+async fn run_branch(
+    executor: Arc<platform::Executor>,
+    scope: CancelScope,
+    req: platform::Request,
+) -> Result<InvokeResponse, ApiError> {
+    scope.start().unwrap();
+    let mut abandoned = CancelOnDrop::new(executor.clone(), req.id.clone());
+    let execution = tokio::spawn(async move { executor.run(req).await });
+    tokio::select! {
+        cancellation = scope.cancelled() => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "Cancelled",
+            format!("cancelled from {}", cancellation.source),
+        )),
+        joined = execution => {
+            abandoned.disarm();
+            let response = joined
+                .unwrap()
+                .map_err(|e| ApiError::from_failure(e.as_ref()))?;
+            Ok(ResponseBuilder::new(response).build())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ForkRequest {
+    branch_from: String,
+    branches: Vec<ForkBranch>,
+    #[serde(default)]
+    strategy: ForkStrategy,
+}
+
+async fn fork_branches(
+    State(gateway): State<Gateway>,
+    Json(req): Json<ForkRequest>,
+) -> Result<Json<ForkResult>, ApiError> {
+    fork::validate(&req.branches)?;
+    let fork_id = uuid::Uuid::new_v4().to_string();
+    let _fork_scope = gateway.cancels.register(&fork_id, None).unwrap();
+    let exec_id = |branch_id: &str| format!("{fork_id}-{branch_id}");
+    let mut scopes: HashMap<String, CancelScope> = req
+        .branches
+        .iter()
+        .map(|branch| {
+            let scope = gateway
+                .cancels
+                .register(&exec_id(&branch.id), Some(&fork_id))
+                .unwrap();
+            (branch.id.clone(), scope)
+        })
+        .collect();
+    let parent = req.branch_from;
+    let result = fork::run(
+        fork_id.clone(),
+        req.strategy,
+        req.branches,
+        |branch| {
+            let scope = scopes.remove(&branch.id).unwrap();
+            let req = request(scope.id().to_string(), branch.command, Some(parent.clone()));
+            run_branch(gateway.executor.clone(), scope, req)
+        },
+        |branch_id| {
+            let _ = gateway
+                .cancels
+                .cancel(&exec_id(branch_id), CancelPolicy::All);
+        },
+    )
+    .await;
+    Ok(Json(result))
+}
+
+async fn containers(prefix: &str) -> usize {
+    let docker = Docker::connect_with_local_defaults().unwrap();
+    docker
+        .list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            filters: [("name".to_string(), vec![prefix.to_string()])].into(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .len()
+}
+
+async fn client() -> Option<FaasClient> {
+    if !faas_executor::test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    std::env::set_var("FAAS_DISABLE_PREWARM", "1");
+    let executor = Arc::new(
+        platform::Executor::new(platform::PlatformConfig::default())
+            .await
+            .unwrap(),
+    );
+    Some(gateway(executor).await)
+}
+
+async fn parent(client: &FaasClient) -> String {
+    let parent = client
+        .execute(ExecuteRequest {
+            command: "mkdir -p /state && echo from-parent > /state/file.txt".to_string(),
+            image: Some("alpine:latest".to_string()),
+            mode: Some("branched".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(parent.exit_code, 0);
+    parent.request_id
+}
+
+#[tokio::test]
+async fn fastest_cancels_the_slower_branchs_container() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let parent = parent(&client).await;
+    let branches = vec![
+        faas_sdk::ForkBranch::new("slow", "sleep 60"),
+        faas_sdk::ForkBranch::new("fast", "sleep 2 && cat /state/file.txt"),
+    ];
+    let started = Instant::now();
+    let result = client
+        .fork_branches(&parent, branches, faas_sdk::ForkStrategy::Fastest)
+        .await
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(60));
+
+    let selected = result.selected().expect("the fast branch wins");
+    assert_eq!(selected.branch_id, "fast");
+    let response = selected.response.as_ref().unwrap();
+    assert_eq!(response.stdout.trim(), "from-parent");
+    let slow = &result.results[0];
+    assert_eq!(slow.outcome, ForkOutcome::Cancelled);
+    assert_eq!(slow.error.as_ref().unwrap().code, "Cancelled");
+
+    let slow_containers = format!("faas-{}-slow-", result.fork_id);
+    let cancelled = Instant::now();
+    while containers(&slow_containers).await > 0 {
+        assert!(
+            cancelled.elapsed() < Duration::from_secs(3),
+            "the slower branch's container outlived the fork"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test]
+async fn sequential_stops_at_the_first_success() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let parent = parent(&client).await;
+    let branches = vec![
+        faas_sdk::ForkBranch::new("missing", "cat /state/other.txt"),
+        faas_sdk::ForkBranch::new("present", "cat /state/file.txt"),
+        faas_sdk::ForkBranch::new("never", "true"),
+    ];
+    let result = client
+        .fork_branches(&parent, branches, faas_sdk::ForkStrategy::Sequential)
+        .await
+        .unwrap();
+    let outcomes: Vec<_> = result.results.iter().map(|r| r.outcome).collect();
+    assert_eq!(
+        outcomes,
+        [
+            ForkOutcome::Failed,
+            ForkOutcome::Succeeded,
+            ForkOutcome::Skipped
+        ]
+    );
+    assert_eq!(result.selected_branch.as_deref(), Some("present"));
+}
@@ -0,0 +1,111 @@
+//! Group creation and waiting against a gateway stand-in.
+
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use faas_sdk::{CancelPolicy, FaasClient, SettlementPolicy};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn summary(id: &str, settled: bool) -> Value {
+    json!({
+        "group_id": id,
+        "policy": "first_failure",
+        "expected": 2,
+        "settled": settled,
+        "settled_at": settled.then_some("2026-01-01T00:00:00Z"),
+        "total": 2,
+        "counts": if settled { json!({"succeeded": 1, "failed": 1}) } else { json!({"running": 2}) },
+        "fastest": null,
+        "slowest": null,
+        "executions": []
+    })
+}
+
+#[tokio::test]
+async fn wait_group_polls_until_settled() {
+    let polls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route(
+            "/api/v1/groups",
+            post(|Json(body): Json<Value>| async move {
+                assert_eq!(body["policy"], "first_failure");
+                Json(summary("g-1", false))
+            }),
+        )
+        .route(
+            "/api/v1/groups/:id",
+            get(
+                |State(polls): State<Arc<AtomicUsize>>, Path(id): Path<String>| async move {
+                    // Settles on the second poll
+                    Json(summary(&id, polls.fetch_add(1, Ordering::SeqCst) >= 1))
+                },
+            ),
+        )
+        .with_state(polls.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = FaasClient::new(format!("http://{addr}"));
+    let group = client
+        .create_group(SettlementPolicy::FirstFailure)
+        .await
+        .unwrap();
+    assert!(!group.settled);
+
+    let settled = client.wait_group(&group.group_id).await.unwrap();
+    assert!(settled.settled);
+    assert_eq!(settled.counts["failed"], 1);
+    assert_eq!(polls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn cancel_group_sends_the_policy_and_reads_the_cancellation() {
+    let app = Router::new().route(
+        "/api/v1/groups/:id/cancel",
+        post(
+            |Path(id): Path<String>, Json(body): Json<Value>| async move {
+                assert_eq!(body["policy"], "only_pending");
+                let mut summary = summary(&id, false);
+                summary["cancel_policy"] = json!("only_pending");
+                summary["cancellation"] = json!({
+                    "cancelled_at": "2026-01-01T00:00:00Z",
+                    "policy": "only_pending",
+                    "spared": ["exec-a"]
+                });
+                summary["executions"] = json!([
+                    {"execution_id": "exec-a", "status": "running", "duration_ms": null},
+                    {
+                        "execution_id": "exec-b",
+                        "status": "cancelled",
+                        "duration_ms": null,
+                        "cancelled": {"source": id, "cancelled_by": id, "depth": 1}
+                    }
+                ]);
+                Json(summary)
+            },
+        ),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = FaasClient::new(format!("http://{addr}"));
+    let summary = client
+        .cancel_group("g-1", Some(CancelPolicy::OnlyPending))
+        .await
+        .unwrap();
+    assert_eq!(summary.cancellation.unwrap().spared, ["exec-a"]);
+    let cancelled = summary.executions[1].cancelled.as_ref().unwrap();
+    assert_eq!(cancelled.cancelled_by.as_deref(), Some("g-1"));
+    assert_eq!(cancelled.depth, 1);
+    assert!(summary.executions[0].cancelled.is_none());
+}
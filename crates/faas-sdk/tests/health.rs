@@ -0,0 +1,72 @@
+//! Health reports against a gateway stand-in answering the way `/health` does.
+
+use axum::{http::StatusCode, routing::get, Json, Router};
+use faas_sdk::FaasClient;
+use serde_json::{json, Value};
+
+async fn gateway(status: StatusCode, body: Value) -> FaasClient {
+    let app = Router::new().route("/health", get(move || async move { (status, Json(body)) }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+fn report(status: &str, docker: Value) -> Value {
+    json!({
+        "status": status,
+        "timestamp": "2024-01-01T00:00:00+00:00",
+        "docker": docker["status"] == "healthy",
+        "firecracker": false,
+        "components": {
+            "docker": docker,
+            "firecracker": {
+                "status": "unavailable",
+                "error": "/dev/kvm is not accessible: No such file or directory (os error 2)"
+            }
+        },
+        "gpu": false,
+        "uptime_ms": 1000,
+        "degraded_environments": [],
+        "active_kill_switches": []
+    })
+}
+
+#[tokio::test]
+async fn components_carry_versions_and_errors() {
+    let client = gateway(
+        StatusCode::OK,
+        report(
+            "degraded",
+            json!({ "status": "healthy", "version": "27.3.1" }),
+        ),
+    )
+    .await;
+    let health = client.health_check().await.unwrap();
+    assert_eq!(health.status, "degraded");
+
+    let components = health.components.unwrap();
+    assert_eq!(components["docker"].status, "healthy");
+    assert_eq!(components["docker"].version.as_deref(), Some("27.3.1"));
+    assert_eq!(components["firecracker"].status, "unavailable");
+    assert!(components["firecracker"]
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("/dev/kvm"));
+}
+
+#[tokio::test]
+async fn a_gateway_that_cannot_execute_is_an_error() {
+    let client = gateway(
+        StatusCode::SERVICE_UNAVAILABLE,
+        report(
+            "unhealthy",
+            json!({ "status": "unavailable", "error": "daemon unreachable" }),
+        ),
+    )
+    .await;
+    assert!(client.health_check().await.is_err());
+}
@@ -0,0 +1,171 @@
+//! Retried executions against a gateway stand-in that replays answers with the gateway's own
+//! idempotency cache.
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use faas_gateway_server::idempotency::{Claim, IdempotencyCache};
+use faas_gateway_server::InvokeResponse;
+use faas_sdk::{ExecuteRequest, FaasClient, SdkError};
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct Gateway {
+    cache: Arc<IdempotencyCache>,
+    /// How long each run takes
+    run_time: Duration,
+    runs: AtomicUsize,
+    /// The key each request arrived with
+    keys: Mutex<Vec<Option<String>>>,
+}
+
+async fn gateway() -> (FaasClient, Arc<Gateway>) {
+    gateway_taking(Duration::from_millis(300)).await
+}
+
+async fn gateway_taking(run_time: Duration) -> (FaasClient, Arc<Gateway>) {
+    let gateway = Arc::new(Gateway {
+        cache: Arc::new(IdempotencyCache::new(Duration::from_secs(60), 100)),
+        run_time,
+        runs: AtomicUsize::new(0),
+        keys: Mutex::default(),
+    });
+    let app = Router::new()
+        .route("/api/v1/execute", post(execute))
+        .with_state(gateway.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (FaasClient::new(format!("http://{addr}")), gateway)
+}
+
+async fn execute(State(gateway): State<Arc<Gateway>>, Json(request): Json<Value>) -> Response {
+    let key = request["idempotency_key"].as_str().map(str::to_string);
+    gateway.keys.lock().unwrap().push(key.clone());
+    let Some(key) = key else {
+        return Json(run(&gateway).await).into_response();
+    };
+    match gateway.cache.claim(None, &key, Instant::now()) {
+        Ok(Claim::Run(pending)) => {
+            let response = run(&gateway).await;
+            pending.complete(&response, Instant::now());
+            Json(response).into_response()
+        }
+        Ok(Claim::Replay(response)) => Json(*response).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// A command that takes a while and must not run twice
+async fn run(gateway: &Gateway) -> InvokeResponse {
+    let run = gateway.runs.fetch_add(1, Ordering::SeqCst) + 1;
+    tokio::time::sleep(gateway.run_time).await;
+    InvokeResponse {
+        request_id: format!("run-{run}"),
+        exit_code: 0,
+        stdout: "charged\n".to_string(),
+        stderr: String::new(),
+        duration_ms: gateway.run_time.as_millis() as u64,
+        output: None,
+        logs: None,
+        error: None,
+        cache_hit: false,
+        cache_key: None,
+        runtime: None,
+        runtime_reason: None,
+        diagnostics: None,
+        usage: None,
+        truncated: false,
+        total_bytes: None,
+        snapshot_id: None,
+    }
+}
+
+fn charge(key: Option<&str>) -> ExecuteRequest {
+    ExecuteRequest {
+        command: "charge-customer 42".to_string(),
+        idempotency_key: key.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn a_duplicate_sent_while_the_first_runs_gets_its_answer() {
+    let (client, gateway) = gateway().await;
+    let client = client.with_retries(3);
+    let (first, second) = tokio::join!(
+        client.execute(charge(Some("charge-42"))),
+        client.execute(charge(Some("charge-42"))),
+    );
+    assert_eq!(first.unwrap().request_id, "run-1");
+    assert_eq!(second.unwrap().request_id, "run-1");
+    assert_eq!(gateway.runs.load(Ordering::SeqCst), 1);
+    // One of the two was refused while the other ran, and came back for the answer
+    assert!(gateway.keys.lock().unwrap().len() > 2);
+}
+
+#[tokio::test]
+async fn a_duplicate_waits_out_a_run_longer_than_its_retries_would() {
+    let (client, gateway) = gateway_taking(Duration::from_millis(2500)).await;
+    let client = client.with_retries(1);
+    let (first, second) = tokio::join!(client.execute(charge(Some("charge-42"))), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.execute(charge(Some("charge-42"))).await
+    });
+    assert_eq!(first.unwrap().request_id, "run-1");
+    assert_eq!(second.unwrap().request_id, "run-1");
+    assert_eq!(gateway.runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn without_retries_a_duplicate_in_flight_is_refused() {
+    let (client, gateway) = gateway().await;
+    let (first, second) = tokio::join!(client.execute(charge(Some("charge-42"))), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.execute(charge(Some("charge-42"))).await
+    },);
+    assert_eq!(first.unwrap().request_id, "run-1");
+    assert!(matches!(
+        second,
+        Err(SdkError::Api { status: 409, code, .. }) if code == "IdempotencyKeyInFlight"
+    ));
+    assert_eq!(gateway.runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn a_duplicate_sent_after_completion_is_replayed() {
+    let (client, gateway) = gateway().await;
+    let first = client.execute(charge(Some("charge-42"))).await.unwrap();
+    let again = client.execute(charge(Some("charge-42"))).await.unwrap();
+    assert_eq!(first.request_id, "run-1");
+    assert_eq!(again.request_id, "run-1");
+    assert_eq!(again.stdout, "charged\n");
+
+    let other = client.execute(charge(Some("charge-43"))).await.unwrap();
+    assert_eq!(other.request_id, "run-2");
+    assert_eq!(gateway.runs.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn retrying_clients_generate_a_key() {
+    let (client, gateway) = gateway().await;
+    client.execute(charge(None)).await.unwrap();
+    client
+        .clone()
+        .with_retries(2)
+        .execute(charge(None))
+        .await
+        .unwrap();
+
+    let keys = gateway.keys.lock().unwrap().clone();
+    assert_eq!(keys[0], None);
+    let generated = keys[1].as_deref().expect("a retrying client sends a key");
+    assert!(uuid::Uuid::parse_str(generated).is_ok());
+}
@@ -0,0 +1,302 @@
+//! Persistent instances against a gateway stand-in answering with the gateway's own types.
+
+use axum::extract::{Path, Query};
+use axum::response::{IntoResponse, Response};
+use axum::{
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use dashmap::DashMap;
+use faas_executor::platform::{self, ports, VolumeInfo};
+use faas_gateway_server::instance_ttl::{self, ExtendTtl};
+use faas_gateway_server::lifecycle::{self, InstanceState, Lifecycle};
+use faas_gateway_server::{DeleteInstanceQuery, ExecInstanceRequest, Instance, InvokeResponse};
+use faas_sdk::{CreateInstanceRequest, FaasClient, PortMapping, SdkError};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+const INSTANCE_ID: &str = "inst-1";
+const STARTED: &str = "2026-01-01T00:00:00+00:00";
+
+/// The stand-in's answer to pause and resume: the instance moved to `next`
+fn move_instance(
+    instances: &DashMap<String, Instance>,
+    id: &str,
+    next: InstanceState,
+) -> Result<Json<Instance>, Response> {
+    let mut instance = instances
+        .get_mut(id)
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+    instance
+        .lifecycle
+        .transition(id, next)
+        .map_err(IntoResponse::into_response)?;
+    Ok(Json(instance.clone()))
+}
+
+async fn gateway() -> FaasClient {
+    let running = instance_ttl::persistent_instance(
+        INSTANCE_ID,
+        "alpine",
+        None,
+        None,
+        Duration::from_secs(60),
+        STARTED.parse().unwrap(),
+    );
+    let instances = Arc::new(DashMap::from_iter([(INSTANCE_ID.to_string(), running)]));
+    let (pausing, resuming, exec_in) = (instances.clone(), instances.clone(), instances);
+    let app = Router::new()
+        .route(
+            "/api/v1/instances",
+            post(|Json(req): Json<Value>| async move {
+                let mut lifecycle = Lifecycle::new(InstanceState::Creating);
+                lifecycle
+                    .transition(INSTANCE_ID, InstanceState::Running)
+                    .unwrap();
+                Json(Instance {
+                    id: INSTANCE_ID.to_string(),
+                    name: None,
+                    image: req["image"].as_str().unwrap().to_string(),
+                    tenant: None,
+                    lifecycle,
+                    created_at: "2026-01-01T00:00:00Z".to_string(),
+                    cpu_cores: None,
+                    memory_mb: None,
+                    container_id: Some("c0ffee".to_string()),
+                    container: None,
+                    expires_at: None,
+                    workspace: None,
+                    ports: Vec::new(),
+                    endpoints: Default::default(),
+                })
+            }),
+        )
+        .route(
+            "/api/v1/instances/:id/ttl",
+            post(
+                |Path(id): Path<String>, Json(req): Json<ExtendTtl>| async move {
+                    let started = STARTED.parse().unwrap();
+                    let ttl = Duration::from_secs(60);
+                    let instance = instance_ttl::persistent_instance(
+                        INSTANCE_ID,
+                        "alpine",
+                        None,
+                        None,
+                        ttl,
+                        started,
+                    );
+                    let instances = DashMap::from_iter([(INSTANCE_ID.to_string(), instance)]);
+                    let ttl = Duration::from_secs(req.ttl_secs);
+                    instance_ttl::extend(&instances, &id, ttl, started).map(Json)
+                },
+            ),
+        )
+        .route(
+            "/api/v1/instances/:id/exec",
+            post(
+                |Path(id): Path<String>, Json(req): Json<ExecInstanceRequest>| async move {
+                    let instance = exec_in
+                        .get(&id)
+                        .map(|entry| entry.value().clone())
+                        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+                    lifecycle::require_running(&instance)?;
+                    Ok::<_, Response>(Json(InvokeResponse {
+                        request_id: "exec-1".to_string(),
+                        exit_code: 2,
+                        stdout: format!("ran {}\n", req.command),
+                        stderr: "warning\n".to_string(),
+                        duration_ms: 5,
+                        output: None,
+                        logs: None,
+                        error: Some("exit code 2".to_string()),
+                        cache_hit: false,
+                        cache_key: None,
+                        runtime: None,
+                        runtime_reason: None,
+                        diagnostics: None,
+                        usage: None,
+                        truncated: false,
+                        total_bytes: None,
+                        snapshot_id: None,
+                    }))
+                },
+            ),
+        )
+        .route(
+            "/api/v1/instances/:id",
+            delete(
+                |Path(id): Path<String>, Query(query): Query<DeleteInstanceQuery>| async move {
+                    match (id == INSTANCE_ID, query.delete_volume) {
+                        (false, _) => StatusCode::NOT_FOUND,
+                        // Stands in for a volume another instance still mounts
+                        (true, true) => StatusCode::CONFLICT,
+                        (true, false) => StatusCode::NO_CONTENT,
+                    }
+                },
+            ),
+        )
+        .route(
+            "/api/v1/instances/:id/pause",
+            post(move |Path(id): Path<String>| async move {
+                move_instance(&pausing, &id, InstanceState::Paused)
+            }),
+        )
+        .route(
+            "/api/v1/instances/:id/resume",
+            post(move |Path(id): Path<String>| async move {
+                move_instance(&resuming, &id, InstanceState::Running)
+            }),
+        )
+        .route(
+            "/api/v1/instances/:id/ports",
+            post(|Json(mut port): Json<platform::PortMapping>| async move {
+                port.host_port = Some(49200);
+                let ports = vec![port];
+                Json(Instance {
+                    id: INSTANCE_ID.to_string(),
+                    name: None,
+                    image: "alpine:latest".to_string(),
+                    tenant: None,
+                    lifecycle: Lifecycle::new(InstanceState::Running),
+                    created_at: "2026-01-01T00:00:00Z".to_string(),
+                    cpu_cores: None,
+                    memory_mb: None,
+                    container_id: Some("c0ffee".to_string()),
+                    container: None,
+                    expires_at: None,
+                    workspace: None,
+                    endpoints: ports::endpoints(&ports, "gw.example"),
+                    ports,
+                })
+            }),
+        )
+        .route(
+            "/api/v1/volumes",
+            get(|| async {
+                Json(vec![VolumeInfo {
+                    name: "faas-workspace-dev".to_string(),
+                    workspace: "dev".to_string(),
+                    tenant: None,
+                    size_bytes: Some(4096),
+                    ref_count: Some(1),
+                }])
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+#[tokio::test]
+async fn exec_in_a_created_instance() {
+    let client = gateway().await;
+    let instance = client
+        .create_instance(CreateInstanceRequest {
+            name: None,
+            image: "alpine:latest".to_string(),
+            cpu_cores: None,
+            memory_mb: None,
+            persistent: Some(true),
+            workspace_path: None,
+            ports: Vec::new(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(instance.instance_id, INSTANCE_ID);
+    assert_eq!(instance.status, "running");
+
+    let result = client
+        .exec_in_instance(&instance.instance_id, "ls /tmp")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "ran ls /tmp\n");
+    assert_eq!(result.stderr, "warning\n");
+    assert_eq!(result.exit_code, 2);
+}
+
+#[tokio::test]
+async fn exec_in_an_unknown_instance_fails() {
+    let client = gateway().await;
+    assert!(matches!(
+        client.exec_in_instance("missing", "true").await,
+        Err(SdkError::Api { .. })
+    ));
+}
+
+#[tokio::test]
+async fn extending_a_lease_moves_the_expiry() {
+    let client = gateway().await;
+    let instance = client.extend_instance_ttl(INSTANCE_ID, 600).await.unwrap();
+    assert_eq!(instance.status, "running");
+    assert_eq!(
+        instance.expires_at.as_deref(),
+        Some("2026-01-01T00:10:00+00:00")
+    );
+    assert!(matches!(
+        client.extend_instance_ttl("missing", 600).await,
+        Err(SdkError::Api { status: 404, .. })
+    ));
+}
+
+#[tokio::test]
+async fn deleting_keeps_the_workspace_unless_asked() {
+    let client = gateway().await;
+    client.delete_instance(INSTANCE_ID).await.unwrap();
+    assert!(matches!(
+        client.delete_instance_and_volume(INSTANCE_ID).await,
+        Err(SdkError::Api { status: 409, .. })
+    ));
+    assert!(matches!(
+        client.delete_instance("missing").await,
+        Err(SdkError::Api { status: 404, .. })
+    ));
+}
+
+#[tokio::test]
+async fn volumes_are_listed_with_their_size() {
+    let client = gateway().await;
+    let volumes = client.list_volumes().await.unwrap();
+    assert_eq!(volumes.len(), 1);
+    assert_eq!(volumes[0].workspace, "dev");
+    assert_eq!(volumes[0].size_bytes, Some(4096));
+}
+
+#[tokio::test]
+async fn an_exposed_port_comes_back_with_its_url() {
+    let client = gateway().await;
+    let instance = client
+        .expose_port(INSTANCE_ID, PortMapping::tcp(8000))
+        .await
+        .unwrap();
+    assert_eq!(instance.ports[0].host_port, Some(49200));
+    assert_eq!(
+        instance.endpoints.unwrap()["8000/tcp"],
+        "http://gw.example:49200"
+    );
+}
+
+#[tokio::test]
+async fn a_paused_instance_refuses_execs_until_resumed() {
+    let client = gateway().await;
+    let paused = client.pause_instance(INSTANCE_ID).await.unwrap();
+    assert_eq!(paused.status, "paused");
+    match client.exec_in_instance(INSTANCE_ID, "true").await {
+        Err(SdkError::Api {
+            status: 409, code, ..
+        }) => assert_eq!(code, "InstancePaused"),
+        other => panic!("expected a conflict, got {other:?}"),
+    }
+    assert!(matches!(
+        client.pause_instance(INSTANCE_ID).await,
+        Err(SdkError::Api { status: 409, .. })
+    ));
+
+    let resumed = client.resume_instance(INSTANCE_ID).await.unwrap();
+    assert_eq!(resumed.status, "running");
+    client.exec_in_instance(INSTANCE_ID, "true").await.unwrap();
+}
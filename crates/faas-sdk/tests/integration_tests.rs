@@ -204,12 +204,11 @@ async fn test_health_check() {
         .with_body(
             json!({
                 "status": "healthy",
-                "version": "1.0.0",
-                "uptime_seconds": 86400,
+                "timestamp": "2024-01-01T00:00:00+00:00",
+                "uptime_ms": 86400000,
                 "components": {
-                    "executor": "healthy",
-                    "docker": "healthy",
-                    "cache": "healthy"
+                    "docker": { "status": "healthy", "version": "27.3.1" },
+                    "firecracker": { "status": "healthy", "version": "Firecracker v1.7.0" }
                 }
             })
             .to_string(),
@@ -227,7 +226,7 @@ async fn test_health_check() {
 async fn test_fork_execution() {
     let mut server = Server::new();
     let mock = server
-        .mock("POST", "/api/v1/execute-advanced")
+        .mock("POST", "/api/v1/executions/parent-123/fork")
         .with_status(200)
         .with_header("content-type", "application/json")
         .with_body(
@@ -282,7 +281,7 @@ async fn test_execute_with_env_vars() {
             command: "echo TEST_VAR=$TEST_VAR".to_string(),
             image: Some("alpine:latest".to_string()),
             runtime: None,
-            env_vars: Some(env_vars.into_iter().collect()),
+            env_vars: Some(env_vars.into_iter().map(faas_sdk::EnvVar::from).collect()),
             working_dir: None,
             timeout_ms: None,
             cache_key: None,
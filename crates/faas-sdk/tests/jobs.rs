@@ -0,0 +1,185 @@
+//! Jobs against a gateway stand-in built from the gateway's job store and cancel registry.
+//! Its executions register under their job the way the gateway's do: `sleep` commands run
+//! until a cancel reaches them and "remove" their container, anything else exits at once.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use faas_executor::OutputChunk;
+use faas_gateway_server::cancellation::{CancelPolicy, CancelRegistry};
+use faas_gateway_server::errors::ApiError;
+use faas_gateway_server::jobs::{Job, JobStore};
+use faas_gateway_server::InvokeResponse;
+use faas_sdk::{ExecuteRequest, FaasClient, JobStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[derive(Clone)]
+struct Gateway {
+    jobs: Arc<JobStore>,
+    cancels: Arc<CancelRegistry>,
+    /// Containers removed by a cancel
+    removed: Arc<AtomicUsize>,
+}
+
+async fn gateway() -> (FaasClient, Gateway) {
+    let gateway = Gateway {
+        jobs: Arc::new(JobStore::new(4, Duration::from_secs(60))),
+        cancels: Arc::new(CancelRegistry::new()),
+        removed: Arc::default(),
+    };
+    let app = Router::new()
+        .route("/api/v1/jobs", post(submit))
+        .route("/api/v1/jobs/:id", get(get_job).delete(cancel))
+        .with_state(gateway.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (FaasClient::new(format!("http://{addr}")), gateway)
+}
+
+async fn submit(
+    State(gateway): State<Gateway>,
+    Json(req): Json<ExecuteRequest>,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    let job_id = uuid::Uuid::new_v4().to_string();
+    let scope = gateway.cancels.register(&job_id, None).unwrap();
+    let job = gateway.jobs.submit(job_id.clone(), None, None)?;
+    let (output_tx, output) = mpsc::unbounded_channel();
+    let execution = run(gateway.clone(), job_id.clone(), req.command, output_tx);
+    gateway.jobs.spawn(job_id, scope, output, execution);
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+async fn run(
+    gateway: Gateway,
+    job_id: String,
+    command: String,
+    output: mpsc::UnboundedSender<OutputChunk>,
+) -> Result<InvokeResponse, ApiError> {
+    let execution_id = format!("{job_id}-exec");
+    let scope = gateway
+        .cancels
+        .register(&execution_id, Some(&job_id))
+        .unwrap();
+    scope.start().unwrap();
+    if command.starts_with("sleep") {
+        let _ = output.send(OutputChunk::Stdout(b"sleeping\n".to_vec()));
+        let cancellation = scope.cancelled().await;
+        gateway.removed.fetch_add(1, Ordering::SeqCst);
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "Cancelled",
+            format!("cancelled from {}", cancellation.source),
+        ));
+    }
+    Ok(InvokeResponse {
+        request_id: execution_id,
+        exit_code: 0,
+        stdout: command.trim_start_matches("echo ").to_string(),
+        stderr: String::new(),
+        duration_ms: 1,
+        output: None,
+        logs: None,
+        error: None,
+        cache_hit: false,
+        cache_key: None,
+        runtime: None,
+        runtime_reason: None,
+        diagnostics: None,
+        usage: None,
+        truncated: false,
+        total_bytes: None,
+        snapshot_id: None,
+    })
+}
+
+async fn get_job(
+    State(gateway): State<Gateway>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, StatusCode> {
+    gateway
+        .jobs
+        .get(&id, None)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn cancel(
+    State(gateway): State<Gateway>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, StatusCode> {
+    let job = gateway.jobs.get(&id, None).ok_or(StatusCode::NOT_FOUND)?;
+    let _ = gateway.cancels.cancel(&id, CancelPolicy::All);
+    Ok(Json(job))
+}
+
+const POLL: Duration = Duration::from_millis(10);
+
+#[tokio::test]
+async fn a_submitted_job_is_waited_for() {
+    let (client, _) = gateway().await;
+    let job = client
+        .submit_job(ExecuteRequest {
+            command: "echo built".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(job.status, JobStatus::Queued);
+
+    let done = client.wait_for_job(&job.job_id, POLL).await.unwrap();
+    assert_eq!(done.status, JobStatus::Succeeded);
+    assert_eq!(done.response.unwrap().exit_code, 0);
+    assert!(done.finished_at.is_some());
+}
+
+#[tokio::test]
+async fn cancelling_sleep_300_stops_it() {
+    let (client, gateway) = gateway().await;
+    let job = client
+        .submit_job(ExecuteRequest {
+            command: "sleep 300".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let running = loop {
+        let job = client.get_job(&job.job_id).await.unwrap();
+        if !job.logs.is_empty() {
+            break job;
+        }
+        tokio::time::sleep(POLL).await;
+    };
+    assert_eq!(running.status, JobStatus::Running);
+    assert_eq!(running.logs, "sleeping\n");
+
+    client.cancel_job(&job.job_id).await.unwrap();
+    let done = tokio::time::timeout(
+        Duration::from_secs(5),
+        client.wait_for_job(&job.job_id, POLL),
+    )
+    .await
+    .expect("the job ends long before the sleep would")
+    .unwrap();
+    assert_eq!(done.status, JobStatus::Cancelled);
+    assert_eq!(done.error.unwrap().code, "Cancelled");
+    assert_eq!(done.logs, "sleeping\n");
+    assert_eq!(gateway.removed.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn unknown_jobs_are_not_found() {
+    let (client, _) = gateway().await;
+    match client.get_job("missing").await {
+        Err(faas_sdk::SdkError::Api { status, .. }) => assert_eq!(status, 404),
+        other => panic!("expected a 404, got {other:?}"),
+    }
+}
@@ -0,0 +1,111 @@
+//! Scoped KV state against the gateway's real KV handlers.
+//!
+//! The "executions" here call the endpoint and token the gateway would inject into their
+//! sandboxes, the same way `curl` inside a container would.
+
+use axum::{routing::get, Router};
+use faas_gateway_server::kv::{get_kv_handler, list_kv_handler, put_kv_handler, KvQuota, KvStore};
+use faas_sdk::{FaasClient, KvPut};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// A gateway stand-in whose store points executions back at it
+async fn gateway(quota: KvQuota) -> (Arc<KvStore>, FaasClient) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let store = Arc::new(KvStore::new(quota, url.clone()));
+    let app = Router::new()
+        .route("/api/v1/kv/:namespace", get(list_kv_handler))
+        .route(
+            "/api/v1/kv/:namespace/:key",
+            get(get_kv_handler).put(put_kv_handler),
+        )
+        .with_state(store.clone());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (store, FaasClient::new(url))
+}
+
+/// `PUT $FAAS_KV_ENDPOINT/<key>` from inside an execution
+async fn execution_put(env: &[(String, String); 2], key: &str, body: Value) -> (u16, Value) {
+    let [(_, endpoint), (_, token)] = env;
+    let response = reqwest::Client::new()
+        .put(format!("{endpoint}/{key}"))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn sibling_swaps_race_and_the_client_reads_the_winner() {
+    let (store, client) = gateway(KvQuota::default()).await;
+    let first = store.grant(None, "group-g1");
+    let second = store.grant(None, "group-g1");
+
+    let claim = |branch: &str| json!({ "value": branch, "expected_version": 0 });
+    let (first_env, second_env) = (first.env(), second.env());
+    let (a, b) = tokio::join!(
+        execution_put(&first_env, "winner", claim("branch-a")),
+        execution_put(&second_env, "winner", claim("branch-b")),
+    );
+    let (won, lost) = if a.0 == 200 { (a, b) } else { (b, a) };
+    assert_eq!(won.0, 200);
+    assert_eq!(lost.0, 409);
+    assert_eq!(lost.1["code"], "KvVersionMismatch");
+    assert_eq!(lost.1["current_version"], 1);
+
+    let winner = client.kv_get("group-g1", "winner").await.unwrap();
+    assert_eq!(winner.value, won.1["value"]);
+    assert_eq!(winner.version, 1);
+
+    // The client can seed a key the executions then see, and list by prefix
+    client
+        .kv_put(
+            "group-g1",
+            "hypothesis.1",
+            KvPut {
+                value: json!({"score": 0.4}),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let listed = client.kv_list("group-g1", "hypothesis.").await.unwrap();
+    assert_eq!(listed.len(), 1);
+
+    // Tokens die with their execution
+    drop(first);
+    let (status, body) = execution_put(&first_env, "winner", json!({ "value": "late" })).await;
+    assert_eq!(status, 401, "{body}");
+}
+
+#[tokio::test]
+async fn quota_exhaustion_is_reported_to_the_execution() {
+    let quota = KvQuota {
+        max_keys: 2,
+        ..Default::default()
+    };
+    let (store, _client) = gateway(quota).await;
+    let grant = store.grant(None, "workflow-sweep");
+    for key in ["a", "b"] {
+        let (status, _) = execution_put(&grant.env(), key, json!({ "value": 1 })).await;
+        assert_eq!(status, 200);
+    }
+
+    let (status, body) = execution_put(&grant.env(), "c", json!({ "value": 1 })).await;
+    assert_eq!(status, 507);
+    assert_eq!(body["code"], "KvQuotaExceeded");
+    assert_eq!(
+        body["error"],
+        "kv namespace workflow-sweep already holds its limit of 2 keys"
+    );
+
+    let (status, body) =
+        execution_put(&grant.env(), "a", json!({ "value": "x".repeat(8 * 1024) })).await;
+    assert_eq!(status, 413);
+    assert_eq!(body["code"], "KvValueTooLarge");
+}
@@ -0,0 +1,118 @@
+//! Compiled-language helpers: what they send, and a Rust snippet run twice on the embedded
+//! executor.
+
+use axum::{routing::post, Json, Router};
+use faas_sdk::{FaasClient, Language, Transport};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+const SUM: &str = r#"fn main() {
+    let total: u32 = (1..=10).sum();
+    println!("sum = {total}");
+}"#;
+
+/// Gateway stand-in that records each request and answers the second as a cache hit
+async fn gateway() -> (FaasClient, Arc<Mutex<Vec<Value>>>) {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorded = seen.clone();
+    let app = Router::new().route(
+        "/api/v1/execute",
+        post(move |Json(request): Json<Value>| {
+            let seen = recorded.clone();
+            async move {
+                let mut seen = seen.lock().unwrap();
+                seen.push(request);
+                Json(json!({
+                    "request_id": "req-1",
+                    "exit_code": 0,
+                    "stdout": "sum = 55\n",
+                    "stderr": "",
+                    "duration_ms": 1,
+                    "cache_hit": seen.len() > 1
+                }))
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (FaasClient::new(format!("http://{addr}")), seen)
+}
+
+#[tokio::test]
+async fn sources_are_uploaded_and_runs_cached() {
+    let (client, seen) = gateway().await;
+    client.run_rust(SUM).await.unwrap();
+    client
+        .run_go("package main\nfunc main() {}\n")
+        .await
+        .unwrap();
+    client
+        .run_language(Language::Python, "import requests", &["requests", "it's"])
+        .await
+        .unwrap();
+
+    let seen = seen.lock().unwrap();
+    let rust = &seen[0];
+    assert_eq!(rust["image"], "rust:1-slim");
+    assert_eq!(rust["mode"], "cached");
+    assert_eq!(rust["working_dir"], "/work");
+    assert_eq!(rust["command"], "rustc -O -o main main.rs && ./main");
+    let files = rust["input_files"].as_array().unwrap();
+    assert_eq!(files[0][0], "main.rs");
+    let uploaded: Vec<u8> = serde_json::from_value(files[0][1].clone()).unwrap();
+    assert_eq!(uploaded, SUM.as_bytes());
+
+    assert_eq!(seen[1]["image"], "golang:1.22");
+    assert_eq!(seen[1]["command"], "go build -o main main.go && ./main");
+
+    assert_eq!(
+        seen[2]["command"],
+        r#"pip install --quiet --disable-pip-version-check 'requests' 'it'\''s' && python main.py"#
+    );
+    // The gateway keys the cache by everything the run depends on, not the SDK
+    assert!(seen.iter().all(|request| request["cache_key"].is_null()));
+}
+
+#[tokio::test]
+async fn rust_deps_become_a_cargo_manifest() {
+    let (client, seen) = gateway().await;
+    client
+        .run_language(Language::Rust, SUM, &["itoa", "serde@1.0"])
+        .await
+        .unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen[0]["command"], "cargo run --quiet --release");
+    let files = seen[0]["input_files"].as_array().unwrap();
+    assert_eq!(files[0][0], "Cargo.toml");
+    assert_eq!(files[1][0], "src/main.rs");
+    let manifest: Vec<u8> = serde_json::from_value(files[0][1].clone()).unwrap();
+    let manifest = String::from_utf8(manifest).unwrap();
+    assert!(manifest.ends_with("[dependencies]\nitoa = \"*\"\nserde = \"1.0\"\n"));
+}
+
+#[cfg(feature = "embedded")]
+#[tokio::test]
+async fn the_same_rust_snippet_compiles_once() {
+    if !faas_executor::test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    std::env::set_var("FAAS_DISABLE_PREWARM", "1");
+    let client = faas_sdk::EmbeddedClient::new()
+        .await
+        .unwrap()
+        .with_runtime(faas_sdk::Runtime::Docker);
+
+    let first = client.run_rust(SUM).await.unwrap();
+    assert_eq!(first.exit_code, 0, "stderr: {}", first.stderr);
+    assert_eq!(first.stdout.trim(), "sum = 55");
+    assert!(!first.cache_hit);
+
+    let second = client.run_rust(SUM).await.unwrap();
+    assert_eq!(second.stdout.trim(), "sum = 55");
+    assert!(second.cache_hit, "the second run compiled again");
+}
@@ -0,0 +1,105 @@
+//! Metrics read from a gateway stand-in that reports a handful of executions through the
+//! gateway's own collector.
+
+use axum::{extract::State, routing::get, Json, Router};
+use faas_gateway_server::metrics::{Collector, Execution, Report, Start, Totals};
+use faas_sdk::FaasClient;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn gateway(collector: Arc<Collector>) -> FaasClient {
+    let app = Router::new()
+        .route("/api/v1/metrics", get(metrics))
+        .with_state(collector);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+async fn metrics(State(collector): State<Arc<Collector>>) -> Json<Report> {
+    let totals = Totals {
+        requests: 7,
+        cache_hits: 1,
+        active_containers: 3,
+        active_instances: 1,
+    };
+    Json(Report::new(collector.snapshot(), &totals))
+}
+
+fn execution<'a>(image: &'a str, runtime: &'a str, start: Start, ms: u64) -> Execution<'a> {
+    Execution {
+        image,
+        tenant: None,
+        runtime,
+        start,
+        duration: Duration::from_millis(ms),
+        succeeded: true,
+    }
+}
+
+#[tokio::test]
+async fn latencies_and_counts_come_back_as_recorded() {
+    let collector = Arc::new(Collector::default());
+    for ms in [10, 20, 30, 40] {
+        collector.record(execution("alpine:latest", "docker", Start::Warm, ms));
+    }
+    collector.record(execution("alpine:latest", "docker", Start::Cold, 900));
+    collector.record(Execution {
+        succeeded: false,
+        tenant: Some("acme"),
+        ..execution("python:3.11", "firecracker", Start::Cold, 300)
+    });
+    collector.record_error("python:3.11", Some("acme"));
+    let client = gateway(collector).await;
+
+    let metrics = client.get_metrics().await.unwrap();
+    assert_eq!(metrics.total_requests, 7);
+    assert_eq!(metrics.total_executions, 7);
+    assert_eq!(metrics.docker_executions, 5);
+    assert_eq!(metrics.vm_executions, 1);
+    assert_eq!(metrics.active_containers, 3);
+    assert_eq!(metrics.active_instances, 1);
+    assert!((metrics.cache_hit_rate - 1.0 / 7.0).abs() < 1e-9);
+
+    let latency = &metrics.latency;
+    assert_eq!(latency.overall.count, 6);
+    assert!((metrics.avg_execution_time_ms - 1300.0 / 6.0).abs() < 0.01);
+    assert_eq!(latency.warm.count, 4);
+    assert!((latency.warm.p50_ms - 20.0).abs() < 0.1);
+    assert!((latency.warm.max_ms - 40.0).abs() < 0.1);
+    assert_eq!(latency.cold.count, 2);
+    assert!((latency.cold.mean_ms - 600.0).abs() < 0.01);
+    assert!(latency.overall.p50_ms <= latency.overall.p99_ms);
+    assert!((latency.overall.p99_ms - 900.0).abs() < 1.0);
+    let runtimes: Vec<_> = latency
+        .by_runtime
+        .iter()
+        .map(|l| (l.runtime.as_str(), l.start.as_str(), l.summary.count))
+        .collect();
+    assert_eq!(
+        runtimes,
+        [
+            ("docker", "cold", 1),
+            ("docker", "warm", 4),
+            ("firecracker", "cold", 1)
+        ]
+    );
+
+    let images: Vec<_> = metrics
+        .images
+        .iter()
+        .map(|i| (i.image.as_str(), i.executions, i.failures))
+        .collect();
+    assert_eq!(images, [("alpine:latest", 5, 0), ("python:3.11", 2, 2)]);
+    assert_eq!(metrics.images[1].failure_rate, 1.0);
+
+    let tenants: Vec<_> = metrics
+        .tenants
+        .iter()
+        .map(|t| (t.tenant.as_str(), t.executions, t.failures))
+        .collect();
+    assert_eq!(tenants, [("acme", 2, 2)]);
+}
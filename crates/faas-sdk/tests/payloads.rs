@@ -0,0 +1,229 @@
+//! Payloads on stdin: large ones uploaded once and referenced, against the gateway's real
+//! payload handlers, and inline ones run through a real container.
+
+use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+    routing::{head, post},
+    Json, Router,
+};
+use faas_executor::platform::executor::{Executor, Mode, Request as ExecutorRequest};
+use faas_executor::platform::PlatformConfig;
+use faas_gateway_server::payloads::{
+    head_payload_handler, inline_payload, put_payload_handler, PayloadStore,
+};
+use faas_sdk::{ExecuteRequest, FaasClient};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Seen {
+    uploads: AtomicUsize,
+    executions: Mutex<Vec<Value>>,
+}
+
+fn temp_store() -> Arc<PayloadStore> {
+    let dir = std::env::temp_dir().join(format!("faas-sdk-payloads-{}", uuid::Uuid::new_v4()));
+    Arc::new(PayloadStore::new(dir, Duration::from_secs(600)).unwrap())
+}
+
+async fn count_uploads(State(seen): State<Arc<Seen>>, request: Request, next: Next) -> Response {
+    if request.method() == axum::http::Method::PUT {
+        seen.uploads.fetch_add(1, Ordering::SeqCst);
+    }
+    next.run(request).await
+}
+
+async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{addr}")
+}
+
+/// Gateway stand-in recording each execute body
+fn execute_route(seen: Arc<Seen>) -> Router {
+    Router::new().route(
+        "/api/v1/execute",
+        post(move |Json(body): Json<Value>| {
+            let seen = seen.clone();
+            async move {
+                seen.executions.lock().unwrap().push(body);
+                Json(json!({
+                    "request_id": "req-1",
+                    "exit_code": 0,
+                    "stdout": "",
+                    "stderr": "",
+                    "duration_ms": 1
+                }))
+            }
+        }),
+    )
+}
+
+fn big_payload() -> Vec<u8> {
+    b"model: resnet\nlayers: 50\n".repeat(12 * 1024)
+}
+
+fn request(payload: Vec<u8>) -> ExecuteRequest {
+    ExecuteRequest {
+        command: "wc -c".to_string(),
+        payload: Some(payload),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn identical_payloads_are_uploaded_once() {
+    let seen = Arc::new(Seen::default());
+    let payloads = Router::new()
+        .route(
+            "/api/v1/payloads/:hash",
+            head(head_payload_handler).put(put_payload_handler),
+        )
+        .with_state(temp_store())
+        .layer(middleware::from_fn_with_state(seen.clone(), count_uploads));
+    let client = FaasClient::new(serve(execute_route(seen.clone()).merge(payloads)).await);
+
+    client.execute(request(big_payload())).await.unwrap();
+    client.execute(request(big_payload())).await.unwrap();
+
+    assert_eq!(seen.uploads.load(Ordering::SeqCst), 1);
+    let hash = faas_common::hash::sha256_hex(big_payload());
+    for body in seen.executions.lock().unwrap().iter() {
+        assert_eq!(body["payload_ref"], hash);
+        assert!(body["payload"].is_null());
+    }
+}
+
+#[tokio::test]
+async fn small_payloads_and_old_gateways_stay_inline() {
+    let seen = Arc::new(Seen::default());
+    let client = FaasClient::new(serve(execute_route(seen.clone())).await);
+
+    client.execute(request(b"tiny".to_vec())).await.unwrap();
+    client.execute(request(big_payload())).await.unwrap();
+
+    let executions = seen.executions.lock().unwrap();
+    assert_eq!(executions[0]["payload"].as_array().unwrap().len(), 4);
+    assert!(executions[1]["payload_ref"].is_null());
+    assert_eq!(
+        executions[1]["payload"].as_array().unwrap().len(),
+        big_payload().len()
+    );
+}
+
+#[tokio::test]
+async fn uploads_that_do_not_match_their_hash_are_rejected() {
+    let url = serve(
+        Router::new()
+            .route(
+                "/api/v1/payloads/:hash",
+                head(head_payload_handler).put(put_payload_handler),
+            )
+            .with_state(temp_store()),
+    )
+    .await;
+    let hash = faas_common::hash::sha256_hex(b"expected");
+    let response = reqwest::Client::new()
+        .put(format!("{url}/api/v1/payloads/{hash}"))
+        .body("tampered")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+    let stored = reqwest::Client::new()
+        .head(format!("{url}/api/v1/payloads/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stored.status(), 404);
+}
+
+/// The part of the gateway's execute request that reaches the container
+#[derive(Deserialize)]
+struct Execution {
+    command: String,
+    image: Option<String>,
+    #[serde(default, deserialize_with = "inline_payload")]
+    payload: Option<Vec<u8>>,
+}
+
+/// Gateway stand-in running each execution in Docker
+async fn docker_gateway() -> Option<FaasClient> {
+    if !faas_executor::test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return None;
+    }
+    let executor = Arc::new(Executor::new(PlatformConfig::default()).await.unwrap());
+    let app = Router::new().route(
+        "/api/v1/execute",
+        post(move |Json(execution): Json<Execution>| {
+            let executor = executor.clone();
+            async move {
+                let response = executor
+                    .run(ExecutorRequest {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        code: execution.command,
+                        mode: Mode::Ephemeral,
+                        env: execution
+                            .image
+                            .unwrap_or_else(|| "alpine:latest".to_string()),
+                        timeout: Duration::from_secs(120),
+                        runtime: Some(faas_common::Runtime::Docker),
+                        payload: execution.payload.unwrap_or_default(),
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+                Json(json!({
+                    "request_id": response.id,
+                    "exit_code": response.exit_code,
+                    "stdout": String::from_utf8_lossy(&response.stdout),
+                    "stderr": String::from_utf8_lossy(&response.stderr),
+                    "duration_ms": response.duration.as_millis() as u64
+                }))
+            }
+        }),
+    );
+    Some(FaasClient::new(serve(app).await))
+}
+
+#[tokio::test]
+async fn python_code_reaches_the_interpreter_on_stdin() {
+    let Some(client) = docker_gateway().await else {
+        return;
+    };
+    let result = client
+        .run_python("import sys; print('read', sys.stdin.read() == '')")
+        .await
+        .unwrap();
+    // The interpreter read the program from stdin, leaving nothing for the program itself
+    assert_eq!(result.stdout, "read True\n");
+    assert_eq!(result.exit_code, 0);
+}
+
+#[tokio::test]
+async fn inline_payloads_are_echoed_back_from_stdin() {
+    let Some(client) = docker_gateway().await else {
+        return;
+    };
+    let code = "import sys; print(sys.stdin.read())";
+    let result = client
+        .execute(ExecuteRequest {
+            command: format!("python -c \"{code}\""),
+            image: Some("python:3.11-slim".to_string()),
+            payload: Some(code.as_bytes().to_vec()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, format!("{code}\n"));
+}
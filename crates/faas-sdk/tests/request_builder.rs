@@ -0,0 +1,119 @@
+//! Requests built with `ExecuteRequestBuilder`, against a gateway stand-in that keeps what
+//! it was sent.
+
+use axum::{extract::State, routing::post, Json, Router};
+use faas_sdk::{ExecuteRequest, FaasClient, RequestError, SdkError};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+type Received = Arc<Mutex<Vec<Value>>>;
+
+async fn gateway() -> (FaasClient, Received) {
+    let received = Received::default();
+    let app = Router::new()
+        .route(
+            "/api/v1/execute",
+            post(
+                |State(received): State<Received>, Json(request): Json<Value>| async move {
+                    received.lock().unwrap().push(request);
+                    Json(json!({
+                        "request_id": "req-1",
+                        "exit_code": 0,
+                        "stdout": "ok",
+                        "stderr": "",
+                        "duration_ms": 1,
+                    }))
+                },
+            ),
+        )
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (FaasClient::new(format!("http://{addr}")), received)
+}
+
+#[tokio::test]
+async fn execute_with_sends_what_the_builder_was_given() {
+    let (client, received) = gateway().await;
+    let response = client
+        .execute_with(|b| {
+            b.command("python main.py")
+                .image("python:3.11")
+                .env("K", "V")
+                .timeout_ms(5000)
+                .memory_mb(512)
+                .cached("key")
+                .input_file("main.py", "print('ok')")
+                .label("team", "ml")
+        })
+        .await
+        .unwrap();
+    assert_eq!(response.stdout, "ok");
+
+    let sent = received.lock().unwrap().pop().unwrap();
+    assert_eq!(sent["command"], "python main.py");
+    assert_eq!(sent["image"], "python:3.11");
+    assert_eq!(sent["env_vars"], json!([{"key": "K", "value": "V"}]));
+    assert_eq!(sent["timeout_ms"], 5000);
+    assert_eq!(sent["memory_mb"], 512);
+    assert_eq!(sent["mode"], "cached");
+    assert_eq!(sent["cache_key"], "key");
+    assert_eq!(sent["labels"], json!({"team": "ml"}));
+    assert_eq!(sent["input_files"][0][0], "main.py");
+    assert!(sent["snapshot_id"].is_null());
+}
+
+#[test]
+fn the_builder_matches_a_struct_literal() {
+    let built = ExecuteRequest::builder("echo hi")
+        .image("alpine:latest")
+        .env("A", "1")
+        .env("B", "2")
+        .no_network()
+        .build()
+        .unwrap();
+    let literal = ExecuteRequest {
+        command: "echo hi".to_string(),
+        image: Some("alpine:latest".to_string()),
+        env_vars: Some(vec![("A", "1").into(), ("B", "2").into()]),
+        network_enabled: Some(false),
+        ..Default::default()
+    };
+    assert_eq!(
+        serde_json::to_value(built).unwrap(),
+        serde_json::to_value(literal).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn refused_requests_are_never_sent() {
+    let (client, received) = gateway().await;
+    let refusals = [
+        (client.request("  ").build(), RequestError::EmptyCommand),
+        (
+            client.request("ls").timeout_ms(0).build(),
+            RequestError::ZeroTimeout,
+        ),
+        (
+            client.request("ls").memory_mb(0).build(),
+            RequestError::ZeroMemory,
+        ),
+        (
+            client.request("ls").cpu_cores(0).build(),
+            RequestError::ZeroCpuCores,
+        ),
+    ];
+    for (built, expected) in refusals {
+        assert_eq!(built.unwrap_err(), expected);
+    }
+
+    let refused = client.execute_with(|b| b.image("alpine:latest")).await;
+    assert!(matches!(
+        refused,
+        Err(SdkError::Build(RequestError::EmptyCommand))
+    ));
+    assert!(received.lock().unwrap().is_empty());
+}
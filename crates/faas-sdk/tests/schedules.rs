@@ -0,0 +1,165 @@
+//! Schedules against a gateway stand-in built from the gateway's schedule store, firing
+//! into a runner that answers every run at once.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use faas_gateway_server::errors::ApiError;
+use faas_gateway_server::schedules::{
+    MisfirePolicy, Schedule, ScheduleRunner, ScheduleSpec, ScheduleStore, ScheduledRun,
+};
+use faas_gateway_server::InvokeResponse;
+use faas_sdk::{ConcurrencyPolicy, ExecuteRequest, FaasClient, JobStatus};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Records what each run was asked to execute
+#[derive(Default)]
+struct Runner(Mutex<Vec<String>>);
+
+#[async_trait]
+impl ScheduleRunner for Runner {
+    async fn run(&self, run: ScheduledRun) -> Result<InvokeResponse, ApiError> {
+        let command = run.schedule.request["command"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        self.0.lock().unwrap().push(command);
+        Ok(InvokeResponse {
+            request_id: run.run_id,
+            exit_code: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            duration_ms: 1,
+            output: None,
+            logs: None,
+            error: None,
+            cache_hit: false,
+            cache_key: None,
+            runtime: None,
+            runtime_reason: None,
+            diagnostics: None,
+            usage: None,
+            truncated: false,
+            total_bytes: None,
+            snapshot_id: None,
+        })
+    }
+
+    fn cancel(&self, _run_id: &str) {}
+}
+
+async fn gateway() -> (FaasClient, Arc<Runner>) {
+    let store = Arc::new(ScheduleStore::new(MisfirePolicy::RunOnce));
+    let runner = Arc::new(Runner::default());
+    tokio::spawn(store.clone().run(runner.clone()));
+    let app = Router::new()
+        .route("/api/v1/schedules", post(create).get(list))
+        .route("/api/v1/schedules/:id", get(get_one).delete(delete))
+        .with_state(store);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (FaasClient::new(format!("http://{addr}")), runner)
+}
+
+async fn create(
+    State(store): State<Arc<ScheduleStore>>,
+    Json(spec): Json<ScheduleSpec>,
+) -> Result<(StatusCode, Json<Schedule>), ApiError> {
+    Ok((StatusCode::CREATED, Json(store.create(None, spec)?)))
+}
+
+async fn list(State(store): State<Arc<ScheduleStore>>) -> Json<Vec<Schedule>> {
+    Json(store.list(None))
+}
+
+fn not_found() -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "ScheduleNotFound",
+        "no such schedule",
+    )
+}
+
+async fn get_one(
+    State(store): State<Arc<ScheduleStore>>,
+    Path(id): Path<String>,
+) -> Result<Json<Schedule>, ApiError> {
+    store.get(&id, None).map(Json).ok_or_else(not_found)
+}
+
+async fn delete(
+    State(store): State<Arc<ScheduleStore>>,
+    Path(id): Path<String>,
+) -> Result<Json<Schedule>, ApiError> {
+    store.delete(&id, None).map(Json).ok_or_else(not_found)
+}
+
+#[tokio::test]
+async fn a_schedule_fires_until_it_is_deleted() {
+    let (client, runner) = gateway().await;
+    let schedule = client
+        .create_schedule(
+            "cache-warmer",
+            "* * * * * *",
+            ConcurrencyPolicy::Queue,
+            ExecuteRequest {
+                command: "echo warm".to_string(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(schedule.concurrency, ConcurrencyPolicy::Queue);
+    assert!(schedule.next_run_at.is_some());
+    assert!(schedule.last_run.is_none());
+
+    let fired = loop {
+        let schedule = client.get_schedule(&schedule.schedule_id).await.unwrap();
+        match schedule.last_run {
+            Some(run) if run.status.is_finished() => break run,
+            _ => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    };
+    assert_eq!(fired.status, JobStatus::Succeeded);
+    assert_eq!(fired.exit_code, Some(0));
+    assert_eq!(runner.0.lock().unwrap()[0], "echo warm");
+
+    let listed = client.list_schedules().await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].function_id, "cache-warmer");
+
+    client.delete_schedule(&schedule.schedule_id).await.unwrap();
+    // A run fired just before the delete may still be on its way
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let runs = runner.0.lock().unwrap().len();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(runner.0.lock().unwrap().len(), runs);
+    assert!(client.list_schedules().await.unwrap().is_empty());
+    assert!(client.get_schedule(&schedule.schedule_id).await.is_err());
+}
+
+#[tokio::test]
+async fn a_malformed_expression_is_refused() {
+    let (client, _) = gateway().await;
+    let refused = client
+        .create_schedule(
+            "nightly-etl",
+            "at midnight",
+            ConcurrencyPolicy::Skip,
+            ExecuteRequest {
+                command: "echo etl".to_string(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+    assert!(refused.to_string().contains("cron"), "{refused}");
+}
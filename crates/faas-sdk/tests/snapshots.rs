@@ -0,0 +1,221 @@
+//! Snapshot creation with `wait` against a gateway stand-in, and listing and deleting
+//! through the gateway's own snapshot store.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use dashmap::DashMap;
+use faas_gateway_server::lifecycle::{Lifecycle, SnapshotState};
+use faas_gateway_server::snapshot_jobs::{
+    self, CreatedSnapshot, ListQuery, SizeEstimate, SnapshotBackend, SnapshotJobError,
+    SnapshotProgress, SnapshotRequest,
+};
+use faas_gateway_server::tenancy::Scope;
+use faas_gateway_server::Snapshot;
+use faas_sdk::{CreateSnapshotRequest, FaasClient, SdkError};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+fn snapshot(status: &str, bytes: u64) -> Value {
+    json!({
+        "id": "snap-1",
+        "name": "model",
+        "container_id": "c-1",
+        "created_at": "2026-01-01T00:00:00Z",
+        "size_bytes": 300_000_000u64,
+        "status": status,
+        "state_history": [],
+        "progress": {
+            "phase": if status == "ready" { "done" } else { "committing" },
+            "bytes": bytes,
+            "percent_estimate": bytes as f64 / 3_000_000.0
+        }
+    })
+}
+
+#[tokio::test]
+async fn create_snapshot_waits_until_ready() {
+    let polls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route(
+            "/api/v1/snapshots",
+            post(|| async { (StatusCode::ACCEPTED, Json(snapshot("creating", 0))) }),
+        )
+        .route(
+            "/api/v1/snapshots/:id",
+            get(
+                |State(polls): State<Arc<AtomicUsize>>, Path(_id): Path<String>| async move {
+                    match polls.fetch_add(1, Ordering::SeqCst) {
+                        0 => Json(snapshot("creating", 120_000_000)),
+                        _ => Json(snapshot("ready", 300_000_000)),
+                    }
+                },
+            ),
+        )
+        .with_state(polls.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    let client = FaasClient::new(format!("http://{addr}"));
+    let request = |wait| CreateSnapshotRequest {
+        name: "model".to_string(),
+        container_id: "c-1".to_string(),
+        description: None,
+        tags: Vec::new(),
+        group_id: None,
+        wait,
+    };
+
+    let started = client.create_snapshot(request(false)).await.unwrap();
+    assert_eq!(started.status.as_deref(), Some("creating"));
+    assert_eq!(polls.load(Ordering::SeqCst), 0);
+
+    let progress = client
+        .get_snapshot("snap-1")
+        .await
+        .unwrap()
+        .progress
+        .unwrap();
+    assert_eq!(progress.bytes, 120_000_000);
+
+    let ready = client.create_snapshot(request(true)).await.unwrap();
+    assert_eq!(ready.status.as_deref(), Some("ready"));
+    assert_eq!(ready.progress.unwrap().phase, "done");
+}
+
+/// Backend that only records which images were removed
+#[derive(Default)]
+struct RemovedImages(Mutex<Vec<String>>);
+
+#[async_trait]
+impl SnapshotBackend for RemovedImages {
+    async fn estimate(&self, _container_id: &str) -> Result<SizeEstimate, SnapshotJobError> {
+        unreachable!("nothing is created here")
+    }
+
+    async fn create(
+        &self,
+        _request: &SnapshotRequest,
+        _estimate_bytes: u64,
+        _progress: &watch::Sender<SnapshotProgress>,
+    ) -> Result<CreatedSnapshot, SnapshotJobError> {
+        unreachable!("nothing is created here")
+    }
+
+    async fn committed(&self) -> Result<Vec<Snapshot>, SnapshotJobError> {
+        Ok(Vec::new())
+    }
+
+    async fn remove(&self, image: &str) -> Result<(), SnapshotJobError> {
+        self.0.lock().unwrap().push(image.to_string());
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Store {
+    snapshots: Arc<DashMap<String, Snapshot>>,
+    backend: Arc<RemovedImages>,
+}
+
+fn committed(id: &str, tags: &[&str]) -> Snapshot {
+    let mut lifecycle = Lifecycle::new(SnapshotState::Creating);
+    lifecycle.transition(id, SnapshotState::Ready).unwrap();
+    Snapshot {
+        id: id.to_string(),
+        name: Some(id.to_string()),
+        container_id: format!("container-of-{id}"),
+        created_at: format!("2026-01-0{}T00:00:00Z", id.len()),
+        size_bytes: 1024,
+        image: Some(format!("faas-snapshot-{id}")),
+        disk_image: None,
+        tenant: None,
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+        lifecycle,
+        progress: None,
+    }
+}
+
+#[tokio::test]
+async fn snapshots_are_listed_by_tag_and_deleted_with_their_image() {
+    let store = Store {
+        snapshots: Arc::new(DashMap::new()),
+        backend: Arc::default(),
+    };
+    for snapshot in [
+        committed("base", &["model"]),
+        committed("tuned", &["model", "v2"]),
+    ] {
+        store.snapshots.insert(snapshot.id.clone(), snapshot);
+    }
+    let app = Router::new()
+        .route(
+            "/api/v1/snapshots",
+            get(
+                |State(store): State<Store>, Query(query): Query<ListQuery>| async move {
+                    Json(snapshot_jobs::list(
+                        &store.snapshots,
+                        &Scope::Tenant(None),
+                        &query,
+                    ))
+                },
+            ),
+        )
+        .route(
+            "/api/v1/snapshots/:id",
+            axum::routing::delete(
+                |State(store): State<Store>, Path(id): Path<String>| async move {
+                    snapshot_jobs::delete(
+                        store.backend.as_ref(),
+                        &store.snapshots,
+                        &id,
+                        &Scope::Tenant(None),
+                    )
+                    .await
+                    .map(|_| StatusCode::NO_CONTENT)
+                },
+            ),
+        )
+        .with_state(store.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    let client = FaasClient::new(format!("http://{addr}"));
+
+    let all = client.list_snapshots().await.unwrap();
+    let names: Vec<_> = all.iter().map(|s| s.snapshot_id.as_str()).collect();
+    assert_eq!(names, ["base", "tuned"]);
+    assert_eq!(all[1].tags, ["model", "v2"]);
+    assert_eq!(all[1].parent_id.as_deref(), Some("container-of-tuned"));
+    assert_eq!(all[1].size_bytes, 1024);
+
+    let tuned = client
+        .list_snapshots_tagged(&["model", "v2"])
+        .await
+        .unwrap();
+    assert_eq!(tuned.len(), 1);
+    assert_eq!(tuned[0].snapshot_id, "tuned");
+
+    client.delete_snapshot("tuned").await.unwrap();
+    assert_eq!(*store.backend.0.lock().unwrap(), ["faas-snapshot-tuned"]);
+    assert_eq!(
+        client
+            .list_snapshots_tagged(&["model"])
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    let again = client.delete_snapshot("tuned").await;
+    assert!(matches!(again, Err(SdkError::Api { status: 404, .. })));
+}
@@ -0,0 +1,143 @@
+//! Store-and-forward against a gateway that refuses connections until it comes back up.
+
+use axum::{extract::State, routing::post, Json, Router};
+use faas_sdk::{
+    ExecuteRequest, FaasClient, OfflineSpoolConfig, SpoolOptions, SpoolOutcome, Submission,
+};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Serve a stand-in `/api/v1/execute` on `addr` that records every request body
+async fn bring_up(addr: SocketAddr) -> Arc<Mutex<Vec<Value>>> {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let app = Router::new()
+        .route(
+            "/api/v1/execute",
+            post(
+                |State(received): State<Arc<Mutex<Vec<Value>>>>, Json(body): Json<Value>| async move {
+                    received.lock().unwrap().push(body.clone());
+                    Json(json!({
+                        "request_id": "r",
+                        "output": null,
+                        "logs": null,
+                        "error": null,
+                        "exit_code": 0,
+                        "stdout": body["command"],
+                        "stderr": "",
+                        "duration_ms": 1,
+                    }))
+                },
+            ),
+        )
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    received
+}
+
+fn in_group(group: &str) -> SpoolOptions {
+    SpoolOptions {
+        concurrency_group: Some(group.to_string()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn spooled_executions_are_delivered_in_order_once_the_gateway_is_back() {
+    // A port nothing listens on, so connections are refused
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("faas-sdk-spool-{}", uuid::Uuid::new_v4()));
+    let client = FaasClient::new(format!("http://{addr}"));
+    client
+        .enable_offline_spool(OfflineSpoolConfig {
+            // Flushed by hand below
+            flush_interval: Duration::from_secs(3600),
+            ..OfflineSpoolConfig::new(&dir)
+        })
+        .unwrap();
+
+    let mut spooled = Vec::new();
+    for command in ["step-1", "step-2", "step-3"] {
+        let request = ExecuteRequest {
+            command: command.to_string(),
+            payload: (command == "step-2").then(|| b"input".to_vec()),
+            ..Default::default()
+        };
+        match client.submit(request, in_group("sync")).await.unwrap() {
+            Submission::Spooled(execution) => spooled.push(execution),
+            Submission::Completed(_) => panic!("{command} reached a gateway that is down"),
+        }
+    }
+    let stale = ExecuteRequest {
+        command: "stale".to_string(),
+        ..Default::default()
+    };
+    let stale = match client
+        .submit(
+            stale,
+            SpoolOptions {
+                max_age: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+    {
+        Submission::Spooled(execution) => execution,
+        Submission::Completed(_) => panic!("stale reached a gateway that is down"),
+    };
+
+    let status = client.spool_status().unwrap();
+    assert_eq!(status.pending, 4);
+    assert_eq!(status.groups["sync"], 3);
+    assert!(status.last_error.is_some());
+    let files = std::fs::read_dir(&dir).unwrap().count();
+    assert_eq!(files, 5, "four requests and one payload on disk");
+
+    // Still down: nothing is delivered and the group keeps its order
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let status = client.flush_now().await.unwrap();
+    assert_eq!((status.pending, status.expired), (3, 1));
+    assert!(matches!(stale.outcome().await, Some(SpoolOutcome::Expired)));
+
+    let received = bring_up(addr).await;
+    let status = client.flush_now().await.unwrap();
+    assert_eq!((status.pending, status.delivered), (0, 3));
+
+    let received = received.lock().unwrap().clone();
+    let commands: Vec<&str> = received
+        .iter()
+        .map(|body| body["command"].as_str().unwrap())
+        .collect();
+    assert_eq!(commands, ["step-1", "step-2", "step-3"]);
+    assert_eq!(received[1]["payload"], json!(b"input".to_vec()));
+
+    for (execution, body) in spooled.into_iter().zip(&received) {
+        assert_eq!(body["idempotency_key"], execution.idempotency_key.as_str());
+        match execution.outcome().await {
+            Some(SpoolOutcome::Delivered(response)) => {
+                assert_eq!(response.stdout, body["command"].as_str().unwrap());
+            }
+            other => panic!("expected delivery, got {other:?}"),
+        }
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    // With the gateway up, a submission goes straight through
+    let live = ExecuteRequest {
+        command: "live".to_string(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        client.submit(live, in_group("sync")).await.unwrap(),
+        Submission::Completed(response) if response.stdout == "live"
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
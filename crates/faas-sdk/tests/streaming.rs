@@ -0,0 +1,122 @@
+//! `execute_stream` against a gateway stand-in that writes events over time.
+
+use axum::response::sse::{Event, Sse};
+use axum::{routing::post, Json, Router};
+use faas_sdk::{ExecuteRequest, FaasClient, SdkError, StreamEvent};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+
+const QUIET_FOR: Duration = Duration::from_millis(400);
+
+async fn serve(app: Router) -> FaasClient {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+/// Answers with `events`, pausing for [`QUIET_FOR`] after the first one
+fn gateway(events: Vec<Value>) -> Router {
+    Router::new().route(
+        "/api/v1/execute/stream",
+        post(move |Json(_request): Json<Value>| {
+            let events = events.clone();
+            async move {
+                let stream = futures::stream::iter(events.into_iter().enumerate()).then(
+                    |(i, event)| async move {
+                        if i == 1 {
+                            tokio::time::sleep(QUIET_FOR).await;
+                        }
+                        Ok::<_, Infallible>(Event::default().json_data(event).unwrap())
+                    },
+                );
+                Sse::new(stream)
+            }
+        }),
+    )
+}
+
+#[tokio::test]
+async fn output_arrives_before_the_exit() {
+    let client = serve(gateway(vec![
+        json!({"type": "stdout", "data": "starting\n"}),
+        json!({"type": "heartbeat"}),
+        json!({"type": "stderr", "data": "warning\n"}),
+        json!({"type": "exit", "code": 0}),
+        json!({"type": "stdout", "data": "after the exit\n"}),
+    ]))
+    .await;
+
+    let start = Instant::now();
+    let mut stream = Box::pin(client.execute_stream(ExecuteRequest {
+        command: "echo starting; sleep 1; echo warning >&2".to_string(),
+        ..Default::default()
+    }));
+    let first = stream.next().await.unwrap().unwrap();
+    assert_eq!(
+        first,
+        StreamEvent::Stdout {
+            data: "starting\n".to_string()
+        }
+    );
+    assert!(start.elapsed() < QUIET_FOR);
+
+    let rest: Vec<StreamEvent> = stream.map(Result::unwrap).collect().await;
+    assert_eq!(
+        rest,
+        vec![
+            StreamEvent::Heartbeat,
+            StreamEvent::Stderr {
+                data: "warning\n".to_string()
+            },
+            StreamEvent::Exit { code: 0 },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_failed_execution_ends_the_stream_with_its_error() {
+    let client = serve(gateway(vec![
+        json!({"type": "stdout", "data": "partial\n"}),
+        json!({"type": "error", "message": "Execution timed out after 1000 ms"}),
+    ]))
+    .await;
+
+    let items: Vec<_> = client
+        .execute_stream(ExecuteRequest {
+            command: "sleep 10".to_string(),
+            timeout_ms: Some(1000),
+            ..Default::default()
+        })
+        .collect()
+        .await;
+    assert_eq!(items.len(), 2);
+    assert!(matches!(
+        &items[1],
+        Err(SdkError::Api { message, .. }) if message.contains("timed out")
+    ));
+}
+
+#[tokio::test]
+async fn a_connection_closed_early_is_an_error() {
+    let client = serve(gateway(vec![
+        json!({"type": "stdout", "data": "partial\n"}),
+    ]))
+    .await;
+
+    let items: Vec<_> = client
+        .execute_stream(ExecuteRequest {
+            command: "echo partial".to_string(),
+            ..Default::default()
+        })
+        .collect()
+        .await;
+    assert!(matches!(
+        items.last(),
+        Some(Err(SdkError::RequestFailed(_)))
+    ));
+}
@@ -0,0 +1,130 @@
+//! Trace context carried from a client span, across HTTP, into the gateway's request span
+//! and the spans under it.
+#![cfg(feature = "otel")]
+
+use axum::{middleware, routing::post, Json, Router};
+use faas_gateway_server::{telemetry, InvokeResponse};
+use faas_sdk::{ExecuteRequest, FaasClient};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+
+async fn gateway() -> FaasClient {
+    let app = Router::new()
+        .route("/api/v1/execute", post(execute))
+        .layer(middleware::from_fn(telemetry::trace_request));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+/// Stands in for the executor's `run` span and the container spans under it
+async fn execute() -> Json<InvokeResponse> {
+    async {
+        tracing::info_span!("container_start").in_scope(|| {});
+    }
+    .instrument(tracing::info_span!("run"))
+    .await;
+    Json(InvokeResponse {
+        request_id: "traced".to_string(),
+        exit_code: 0,
+        stdout: String::new(),
+        stderr: String::new(),
+        duration_ms: 1,
+        output: None,
+        logs: None,
+        error: None,
+        cache_hit: false,
+        cache_key: None,
+        runtime: None,
+        runtime_reason: None,
+        diagnostics: None,
+        usage: None,
+        truncated: false,
+        total_bytes: None,
+        snapshot_id: None,
+    })
+}
+
+fn span<'a>(spans: &'a [SpanData], name: &str) -> &'a SpanData {
+    spans
+        .iter()
+        .find(|span| span.name == name)
+        .unwrap_or_else(|| panic!("no {name} span"))
+}
+
+// Single-threaded, so the server's tasks see the same subscriber as the client
+#[tokio::test(flavor = "current_thread")]
+async fn gateway_spans_join_the_callers_trace() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _subscriber = tracing::subscriber::set_default(subscriber);
+
+    let client = gateway().await;
+    client
+        .clone()
+        .with_caching(false)
+        .execute(ExecuteRequest {
+            command: "true".to_string(),
+            ..Default::default()
+        })
+        .instrument(tracing::info_span!("client"))
+        .await
+        .unwrap();
+    provider.force_flush().unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let (client, request) = (span(&spans, "client"), span(&spans, "request"));
+    let (run, container) = (span(&spans, "run"), span(&spans, "container_start"));
+    let trace = client.span_context.trace_id();
+    for span in [request, run, container] {
+        assert_eq!(span.span_context.trace_id(), trace, "{}", span.name);
+    }
+    assert_eq!(request.parent_span_id, client.span_context.span_id());
+    assert_eq!(run.parent_span_id, request.span_context.span_id());
+    assert_eq!(container.parent_span_id, run.span_context.span_id());
+    assert!(request
+        .attributes
+        .iter()
+        .any(|kv| kv.key.as_str() == "http.route" && kv.value.as_str() == "/api/v1/execute"));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn requests_outside_a_trace_start_their_own() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _subscriber = tracing::subscriber::set_default(subscriber);
+
+    gateway()
+        .await
+        .execute(ExecuteRequest {
+            command: "true".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    provider.force_flush().unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let request = span(&spans, "request");
+    assert_eq!(
+        request.parent_span_id,
+        opentelemetry::trace::SpanId::INVALID
+    );
+    assert_eq!(
+        span(&spans, "run").parent_span_id,
+        request.span_context.span_id()
+    );
+}
@@ -0,0 +1,102 @@
+//! The same workflow against the HTTP client and the embedded executor.
+
+use axum::{routing::post, Json, Router};
+use faas_sdk::{FaasClient, SdkError, Transport};
+use serde_json::{json, Value};
+
+/// Two dependent steps: the second consumes the first one's output.
+async fn word_count_workflow(client: &impl Transport) -> Result<String, SdkError> {
+    let words = client.run("echo one two three").await?;
+    let counted = client
+        .run(&format!("echo '{}' | wc -w", words.trim()))
+        .await?;
+    Ok(counted.trim().to_string())
+}
+
+/// Gateway stand-in that evaluates the two commands the workflow sends.
+async fn fake_execute(Json(request): Json<Value>) -> Json<Value> {
+    let command = request["command"].as_str().unwrap_or_default();
+    let stdout = match command {
+        "echo one two three" => "one two three\n".to_string(),
+        c if c.ends_with("| wc -w") => {
+            let text = c.trim_start_matches("echo '").trim_end_matches("' | wc -w");
+            format!("{}\n", text.split_whitespace().count())
+        }
+        _ => String::new(),
+    };
+    Json(json!({
+        "request_id": "req-1",
+        "exit_code": 0,
+        "stdout": stdout,
+        "stderr": "",
+        "duration_ms": 1
+    }))
+}
+
+#[tokio::test]
+async fn workflow_runs_over_http() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/api/v1/execute", post(fake_execute));
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = FaasClient::new(format!("http://{addr}"));
+    assert_eq!(word_count_workflow(&client).await.unwrap(), "3");
+}
+
+#[tokio::test]
+async fn cache_hits_are_counted_from_the_gateway_answer() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Answers at once but only the second request is a cache hit
+    let answered = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let app = Router::new().route(
+        "/api/v1/execute",
+        post(move || {
+            let answered = answered.clone();
+            async move {
+                let hit = answered.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 1;
+                Json(json!({
+                    "request_id": "req-1",
+                    "exit_code": 0,
+                    "stdout": "hi\n",
+                    "stderr": "",
+                    "duration_ms": 0,
+                    "cache_hit": hit,
+                    "cache_key": "cache:abc"
+                }))
+            }
+        }),
+    );
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = FaasClient::new(format!("http://{addr}"));
+    let request = || faas_sdk::ExecuteRequest {
+        command: "echo hi".to_string(),
+        ..Default::default()
+    };
+    let first = client.execute(request()).await.unwrap();
+    let second = client.execute(request()).await.unwrap();
+    assert!(!first.cache_hit);
+    assert!(second.cache_hit);
+    assert_eq!(second.cache_key.as_deref(), Some("cache:abc"));
+    assert_eq!(client.client_metrics().await.cache_hit_rate, 0.5);
+}
+
+#[cfg(feature = "embedded")]
+#[tokio::test]
+async fn workflow_runs_embedded() {
+    if !faas_executor::test_utils::has_docker() {
+        eprintln!("Test skipped: Docker not available");
+        return;
+    }
+    let client = faas_sdk::EmbeddedClient::new()
+        .await
+        .unwrap()
+        .with_runtime(faas_sdk::Runtime::Docker);
+    assert_eq!(word_count_workflow(&client).await.unwrap(), "3");
+}
@@ -0,0 +1,135 @@
+//! Refused request shapes against a gateway stand-in that checks requests the way the
+//! gateway does.
+
+use axum::{middleware, routing::post, Json, Router};
+use faas_gateway_server::errors::{fill_error_body, ApiError};
+use faas_gateway_server::validation::{ExecuteFields, RequestBounds};
+use faas_sdk::{ExecuteRequest, FaasClient, SdkError};
+use serde_json::{json, Value};
+
+async fn gateway() -> FaasClient {
+    let app = Router::new()
+        .route(
+            "/api/v1/execute",
+            post(|Json(req): Json<Value>| async move {
+                RequestBounds::default().check(&ExecuteFields {
+                    command: Some(req["command"].as_str().unwrap_or_default()),
+                    image: req["image"].as_str(),
+                    user: req["user"].as_str(),
+                    mode: req["mode"].as_str(),
+                    timeout_ms: req["timeout_ms"].as_u64(),
+                    memory_mb: req["memory_mb"].as_u64().map(|v| v as u32),
+                    cpu_cores: req["cpu_cores"].as_u64().map(|v| v as u8),
+                })?;
+                Ok::<_, ApiError>(Json(json!({
+                    "request_id": "req-1",
+                    "exit_code": 0,
+                    "stdout": "ok",
+                    "stderr": "",
+                    "duration_ms": 1,
+                })))
+            }),
+        )
+        .layer(middleware::from_fn(fill_error_body));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    FaasClient::new(format!("http://{addr}"))
+}
+
+fn request() -> ExecuteRequest {
+    ExecuteRequest {
+        command: "echo ok".to_string(),
+        image: Some("alpine:3.19".to_string()),
+        timeout_ms: Some(30_000),
+        memory_mb: Some(512),
+        cpu_cores: Some(2),
+        ..Default::default()
+    }
+}
+
+async fn refused_field(request: ExecuteRequest) -> (String, String) {
+    match gateway().await.execute(request).await {
+        Err(SdkError::Validation { field, message }) => (field, message),
+        other => panic!("expected a validation error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn a_request_in_shape_runs() {
+    let result = gateway().await.execute(request()).await.unwrap();
+    assert_eq!(result.stdout, "ok");
+}
+
+#[tokio::test]
+async fn an_empty_command_is_refused() {
+    let (field, message) = refused_field(ExecuteRequest {
+        command: String::new(),
+        ..request()
+    })
+    .await;
+    assert_eq!(field, "command");
+    assert_eq!(message, "command must not be empty");
+}
+
+#[tokio::test]
+async fn a_malformed_image_is_refused() {
+    let (field, message) = refused_field(ExecuteRequest {
+        image: Some("Alpine Linux".to_string()),
+        ..request()
+    })
+    .await;
+    assert_eq!(field, "image");
+    assert!(message.contains("\"Alpine Linux\""));
+}
+
+#[tokio::test]
+async fn a_timeout_out_of_bounds_is_refused() {
+    for timeout_ms in [10, 2 * 60 * 60 * 1000] {
+        let (field, message) = refused_field(ExecuteRequest {
+            timeout_ms: Some(timeout_ms),
+            ..request()
+        })
+        .await;
+        assert_eq!(field, "timeout_ms");
+        assert!(message.contains("between 100 and 3600000"), "{message}");
+    }
+}
+
+#[tokio::test]
+async fn memory_out_of_bounds_is_refused() {
+    let (field, _) = refused_field(ExecuteRequest {
+        memory_mb: Some(1),
+        ..request()
+    })
+    .await;
+    assert_eq!(field, "memory_mb");
+}
+
+#[tokio::test]
+async fn zero_cpus_are_refused() {
+    let (field, _) = refused_field(ExecuteRequest {
+        cpu_cores: Some(0),
+        ..request()
+    })
+    .await;
+    assert_eq!(field, "cpu_cores");
+}
+
+#[tokio::test]
+async fn an_unknown_mode_lists_the_modes() {
+    let error = gateway()
+        .await
+        .execute(ExecuteRequest {
+            mode: Some("turbo".to_string()),
+            ..request()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid request (mode): unknown mode \"turbo\"; expected ephemeral, cached, checkpointed, branched or persistent"
+    );
+}
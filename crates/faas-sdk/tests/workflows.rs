@@ -0,0 +1,307 @@
+//! Workflows submitted to the gateway's real workflow handler, or run from the client over a
+//! local shell standing in for the executor.
+
+use async_trait::async_trait;
+use axum::{routing::post, Router};
+use faas_gateway_server::cancellation::{CancelError, CancelRegistry, CancelScope};
+use faas_gateway_server::workflows::{
+    cancel_workflow_handler, step_error, submit_workflow_handler, StepRunner, Workflows,
+};
+use faas_sdk::{
+    ExecuteRequest, ExecuteResponse, FaasClient, InputFrom, SdkError, StepError, StepInput,
+    StepOutput, StepStatus, Transport, WorkflowBuilder, WorkflowError, WorkflowStep,
+};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+const TWO_STEPS: &str = "\
+name: greet
+steps:
+- name: hello
+  image: alpine:latest
+  command: echo hello
+- name: shout
+  image: alpine:latest
+  command: echo HELLO
+  depends_on:
+  - hello
+";
+
+/// Executor stand-in that echoes the command's argument, or on `sleep` waits to be
+/// cancelled, and records the order
+#[derive(Default)]
+struct EchoRunner {
+    ran: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl StepRunner for EchoRunner {
+    async fn run_step(
+        &self,
+        _workflow: &str,
+        step: WorkflowStep,
+        _input: Option<StepInput>,
+        scope: &CancelScope,
+    ) -> Result<StepOutput, StepError> {
+        self.ran.lock().unwrap().push(step.name.clone());
+        if step.command.starts_with("sleep") {
+            let cancellation = scope.cancelled().await;
+            return Err(step_error(CancelError::Cancelled {
+                id: scope.id().to_string(),
+                cancellation,
+            }));
+        }
+        Ok(StepOutput {
+            exit_code: 0,
+            stdout: format!("{}\n", step.command.trim_start_matches("echo ")),
+            stderr: String::new(),
+        })
+    }
+}
+
+async fn gateway(runner: Arc<EchoRunner>) -> String {
+    let cancels = Arc::new(CancelRegistry::new());
+    let workflows = Workflows {
+        runner,
+        cancels: cancels.clone(),
+    };
+    let app = Router::new()
+        .route("/api/v1/workflows", post(submit_workflow_handler))
+        .with_state(workflows)
+        .merge(
+            Router::new()
+                .route(
+                    "/api/v1/workflows/:id/cancel",
+                    post(cancel_workflow_handler),
+                )
+                .with_state(cancels),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn yaml_file_runs_on_the_gateway() {
+    let runner = Arc::new(EchoRunner::default());
+    let client = FaasClient::new(gateway(runner.clone()).await);
+    let path = std::env::temp_dir().join(format!("workflow-{}.yaml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, TWO_STEPS).unwrap();
+
+    let run = client.submit_workflow_file(&path).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(run.succeeded);
+    assert_eq!(run.workflow, "greet");
+    assert_eq!(run.step("hello").unwrap().stdout, "hello\n");
+    assert_eq!(run.step("shout").unwrap().status, StepStatus::Succeeded);
+    assert_eq!(*runner.ran.lock().unwrap(), ["hello", "shout"]);
+}
+
+#[tokio::test]
+async fn gateway_rejects_invalid_yaml_with_the_offending_step() {
+    let url = gateway(Arc::new(EchoRunner::default())).await;
+    let response = reqwest::Client::new()
+        .post(format!("{url}/api/v1/workflows"))
+        .header("content-type", "application/yaml")
+        .body(TWO_STEPS.replace("- hello", "- helo"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "step `shout`: depends_on: unknown step `helo`"
+    );
+}
+
+#[tokio::test]
+async fn builder_rejects_cycles_before_running() {
+    let result = WorkflowBuilder::new("loop")
+        .add_step("a", "alpine:latest", "true")
+        .depends_on("b")
+        .add_step("b", "alpine:latest", "true")
+        .depends_on("a")
+        .execute(&FaasClient::new("http://127.0.0.1:9".to_string()))
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(SdkError::Workflow(WorkflowError::Step { ref step, field: "depends_on", .. })) if step == "a"
+    ));
+}
+
+#[tokio::test]
+async fn cancel_workflow_stops_the_run_submitted_under_that_id() {
+    let runner = Arc::new(EchoRunner::default());
+    let client = FaasClient::new(gateway(runner.clone()).await);
+    let workflow = WorkflowBuilder::new("slow")
+        .add_step("wait", "alpine:latest", "sleep 60")
+        .add_step("after", "alpine:latest", "echo after")
+        .depends_on("wait")
+        .build()
+        .unwrap();
+
+    let submitted = tokio::spawn({
+        let client = client.clone();
+        async move { client.submit_workflow_with_id(&workflow, "nightly-7").await }
+    });
+    while runner.ran.lock().unwrap().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let report = client.cancel_workflow("nightly-7").await.unwrap();
+    assert_eq!(report.cancelled[0].id, "nightly-7/wait");
+
+    let run = submitted.await.unwrap().unwrap();
+    assert_eq!(run.id.as_deref(), Some("nightly-7"));
+    assert!(!run.succeeded);
+    let after = run.step("after").unwrap();
+    assert_eq!(after.status, StepStatus::Cancelled);
+    assert_eq!(after.cancelled.as_ref().unwrap().cancelled_by, "nightly-7");
+    assert_eq!(*runner.ran.lock().unwrap(), ["wait"]);
+}
+
+#[tokio::test]
+async fn builder_rejects_unknown_dependencies_before_running() {
+    let result = WorkflowBuilder::new("typo")
+        .add_step("fetch", "alpine:latest", "true")
+        .add_step("build", "alpine:latest", "true")
+        .depends_on("fech")
+        .build();
+
+    assert_eq!(
+        result.unwrap_err(),
+        WorkflowError::Step {
+            step: "build".to_string(),
+            field: "depends_on",
+            message: "unknown step `fech`".to_string(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn a_diamond_built_in_code_joins_after_both_branches() {
+    let runner = Arc::new(EchoRunner::default());
+    let client = FaasClient::new(gateway(runner.clone()).await);
+    let workflow = WorkflowBuilder::new("diamond")
+        .add_step("split", "alpine:latest", "echo split")
+        .add_step("left", "alpine:latest", "echo left")
+        .depends_on("split")
+        .add_step("right", "alpine:latest", "echo right")
+        .depends_on("split")
+        .add_step("join", "alpine:latest", "echo join")
+        .depends_on("left")
+        .depends_on("right")
+        .build()
+        .unwrap();
+
+    let run = client.submit_workflow(&workflow).await.unwrap();
+
+    assert!(run.succeeded);
+    assert_eq!(run.step("join").unwrap().stdout, "join\n");
+    let ran = runner.ran.lock().unwrap();
+    assert_eq!(ran.len(), 4);
+    assert_eq!(ran[0], "split");
+    assert_eq!(ran[3], "join");
+}
+
+/// Transport stand-in that runs each command in the local `sh`, payload on stdin
+struct LocalShell;
+
+#[async_trait]
+impl Transport for LocalShell {
+    async fn execute(&self, request: ExecuteRequest) -> Result<ExecuteResponse, SdkError> {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&request.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin
+            .write_all(&request.payload.unwrap_or_default())
+            .await?;
+        drop(stdin);
+        let output = child.wait_with_output().await?;
+        Ok(ExecuteResponse {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            output: None,
+            logs: None,
+            error: None,
+            exit_code: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            duration_ms: 0,
+            cache_hit: false,
+            cache_key: None,
+            runtime: None,
+            runtime_reason: None,
+            diagnostics: None,
+            usage: None,
+            truncated: false,
+            total_bytes: None,
+            snapshot_id: None,
+        })
+    }
+}
+
+#[tokio::test]
+async fn a_generated_csv_flows_into_the_step_counting_it() {
+    let run = WorkflowBuilder::new("etl")
+        .add_step(
+            "generate",
+            "alpine:latest",
+            "printf 'id,city\\n1,Oslo\\n2,Lima\\n3,Pune\\n'",
+        )
+        .add_step_with_input(
+            "count",
+            "alpine:latest",
+            "wc -l",
+            InputFrom::Step("generate".to_string()),
+        )
+        .execute(&LocalShell)
+        .await
+        .unwrap();
+
+    assert!(run.succeeded, "{run:?}");
+    let generated = run.output("generate").unwrap();
+    assert!(generated.stdout.starts_with("id,city\n"));
+    let counted = run.output("count").unwrap();
+    assert_eq!(counted.exit_code, 0);
+    assert_eq!(counted.stdout.trim(), "4");
+}
+
+#[tokio::test]
+async fn outputs_over_the_spill_threshold_go_through_a_file() {
+    let spill_dir = tempfile::tempdir().unwrap();
+    std::env::set_var("FAAS_WORKFLOW_SPILL_DIR", spill_dir.path());
+    let run = WorkflowBuilder::new("big-etl")
+        .spill_outputs_over(1024)
+        .add_step("generate", "alpine:latest", "seq 1 5000")
+        .add_step_with_input(
+            "count",
+            "alpine:latest",
+            "wc -l",
+            InputFrom::Step("generate".to_string()),
+        )
+        .execute(&LocalShell)
+        .await
+        .unwrap();
+
+    assert!(run.succeeded, "{run:?}");
+    let generated = run.step("generate").unwrap();
+    // `seq 1 5000` prints 23893 bytes
+    assert_eq!(generated.spilled_bytes, Some(23893));
+    assert!(generated.stdout.is_empty());
+    assert_eq!(run.output("count").unwrap().stdout.trim(), "5000");
+    // Spilled outputs don't outlive the run
+    assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+}
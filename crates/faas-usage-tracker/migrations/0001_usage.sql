@@ -0,0 +1,88 @@
+-- Portable between SQLite and Postgres: instants are milliseconds since the epoch, and
+-- byte counts and flags (0 or 1) are BIGINT.
+
+CREATE TABLE usage_accounts (
+    account_id TEXT PRIMARY KEY,
+    tier TEXT NOT NULL,
+    billing_period_start BIGINT NOT NULL,
+    billing_period_end BIGINT NOT NULL,
+    mcus_allocated DOUBLE PRECISION NOT NULL,
+    pay_as_you_go_enabled BIGINT NOT NULL DEFAULT 0,
+    vcpu_hours DOUBLE PRECISION NOT NULL DEFAULT 0,
+    ram_gb_hours DOUBLE PRECISION NOT NULL DEFAULT 0,
+    disk_gb_hours DOUBLE PRECISION NOT NULL DEFAULT 0,
+    snapshot_tb_hours DOUBLE PRECISION NOT NULL DEFAULT 0,
+    storage_byte_hours DOUBLE PRECISION NOT NULL DEFAULT 0,
+    egress_bytes BIGINT NOT NULL DEFAULT 0,
+    snapshot_bytes BIGINT NOT NULL DEFAULT 0,
+    artifact_bytes BIGINT NOT NULL DEFAULT 0,
+    log_bytes BIGINT NOT NULL DEFAULT 0,
+    speculation_vcpu_hours DOUBLE PRECISION NOT NULL DEFAULT 0,
+    speculation_ram_gb_hours DOUBLE PRECISION NOT NULL DEFAULT 0,
+    cpu_seconds DOUBLE PRECISION NOT NULL DEFAULT 0,
+    stdout_bytes BIGINT NOT NULL DEFAULT 0,
+    measured_mcus DOUBLE PRECISION NOT NULL DEFAULT 0,
+    last_updated BIGINT NOT NULL,
+    storage_accrued_at BIGINT
+);
+
+CREATE INDEX usage_accounts_period_end ON usage_accounts (billing_period_end);
+
+-- Closed billing periods, as they stood at their end
+CREATE TABLE usage_periods (
+    account_id TEXT NOT NULL,
+    tier TEXT NOT NULL,
+    billing_period_start BIGINT NOT NULL,
+    billing_period_end BIGINT NOT NULL,
+    mcus_allocated DOUBLE PRECISION NOT NULL,
+    pay_as_you_go_enabled BIGINT NOT NULL,
+    vcpu_hours DOUBLE PRECISION NOT NULL,
+    ram_gb_hours DOUBLE PRECISION NOT NULL,
+    disk_gb_hours DOUBLE PRECISION NOT NULL,
+    snapshot_tb_hours DOUBLE PRECISION NOT NULL,
+    storage_byte_hours DOUBLE PRECISION NOT NULL,
+    egress_bytes BIGINT NOT NULL,
+    snapshot_bytes BIGINT NOT NULL,
+    artifact_bytes BIGINT NOT NULL,
+    log_bytes BIGINT NOT NULL,
+    speculation_vcpu_hours DOUBLE PRECISION NOT NULL,
+    speculation_ram_gb_hours DOUBLE PRECISION NOT NULL,
+    cpu_seconds DOUBLE PRECISION NOT NULL,
+    stdout_bytes BIGINT NOT NULL,
+    measured_mcus DOUBLE PRECISION NOT NULL,
+    last_updated BIGINT NOT NULL,
+    storage_accrued_at BIGINT,
+    PRIMARY KEY (account_id, billing_period_start)
+);
+
+CREATE TABLE usage_instances (
+    account_id TEXT NOT NULL,
+    instance_id TEXT NOT NULL,
+    vcpus BIGINT NOT NULL,
+    ram_gb BIGINT NOT NULL,
+    disk_gb BIGINT NOT NULL,
+    started_at BIGINT NOT NULL,
+    stopped_at BIGINT,
+    PRIMARY KEY (account_id, instance_id)
+);
+
+CREATE TABLE usage_snapshots (
+    account_id TEXT NOT NULL,
+    snapshot_id TEXT NOT NULL,
+    size_gb BIGINT NOT NULL,
+    created_at BIGINT NOT NULL,
+    deleted_at BIGINT,
+    PRIMARY KEY (account_id, snapshot_id)
+);
+
+CREATE TABLE usage_executions (
+    execution_id TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    vcpu_seconds DOUBLE PRECISION NOT NULL,
+    ram_gb_seconds DOUBLE PRECISION NOT NULL,
+    mode TEXT NOT NULL,
+    recorded_at BIGINT NOT NULL,
+    duration_ms BIGINT NOT NULL
+);
+
+CREATE INDEX usage_executions_account ON usage_executions (account_id, recorded_at);
@@ -2,14 +2,20 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+mod sql;
 mod storage;
 mod tracker;
 mod types;
 
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+pub use sql::SqlStorage;
 pub use storage::{InMemoryStorage, UsageStorage};
 pub use tracker::UsageTracker;
 pub use types::*;
 
+pub use faas_common::ExecutionUsage;
+
 // Error Types
 #[derive(Error, Debug)]
 pub enum UsageError {
@@ -20,7 +26,18 @@ pub enum UsageError {
     #[error("Account not found: {0}")]
     AccountNotFound(String),
     #[error("Resource limit exceeded: {message}")]
-    LimitExceeded { message: String },
+    LimitExceeded { message: String, limit: Limit },
+}
+
+/// Which tier limit a refusal ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Vcpu,
+    Ram,
+    /// The MCUs of the billing period are used up
+    Credits,
+    Storage,
+    Egress,
 }
 
 pub type Result<T> = std::result::Result<T, UsageError>;
@@ -40,6 +57,9 @@ impl Tier {
                 max_vcpu: 64,
                 max_ram_gb: 256,
                 max_storage_gb: 1024,
+                storage_gb_hours: 7_200,
+                egress_gb: 100,
+                artifact_gb: 10,
                 starting_mcus: 300,
                 monthly_price: 0.0,
                 discount_percent: 100,
@@ -48,6 +68,9 @@ impl Tier {
                 max_vcpu: 256,
                 max_ram_gb: 1024,
                 max_storage_gb: 4096,
+                storage_gb_hours: 72_000,
+                egress_gb: 1_000,
+                artifact_gb: 100,
                 starting_mcus: 1000,
                 monthly_price: 40.0,
                 discount_percent: 20,
@@ -56,6 +79,9 @@ impl Tier {
                 max_vcpu: 1024,
                 max_ram_gb: 4096,
                 max_storage_gb: 16384,
+                storage_gb_hours: 720_000,
+                egress_gb: 10_000,
+                artifact_gb: 1_000,
                 starting_mcus: 7500,
                 monthly_price: 250.0,
                 discount_percent: 33,
@@ -64,11 +90,20 @@ impl Tier {
     }
 }
 
+pub const GIB: u64 = 1024 * 1024 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TierLimits {
     pub max_vcpu: u32,
     pub max_ram_gb: u32,
+    /// Snapshots, artifacts and logs held at once
     pub max_storage_gb: u32,
+    /// Stored bytes integrated over time, per billing period
+    pub storage_gb_hours: u64,
+    /// Bytes downloaded, per billing period
+    pub egress_gb: u64,
+    /// Artifacts held at once
+    pub artifact_gb: u64,
     pub starting_mcus: u32,
     pub monthly_price: f64,
     pub discount_percent: u8,
@@ -0,0 +1,648 @@
+//! Accounts kept in SQLite or Postgres, so usage survives restarts.
+//!
+//! Both go through sqlx's `Any` driver with the same SQL, picked by the URL:
+//! `sqlite://usage.db?mode=rwc` for a single node, `postgres://…` for gateways sharing one
+//! database. Each counter changes in a single `UPDATE … SET x = x + $n`, in a transaction
+//! where more than one row changes, so concurrent writers don't lose each other's updates.
+//! The schema in `migrations/` is brought up to date on connect.
+//!
+//! `mcus_consumed` isn't stored: it is worked out from the counters when an account is read.
+
+use crate::{
+    AccountUsage, ActiveResources, ExecutionRecord, ExecutionUsage, InstanceRecord, McuUsage,
+    Result, SnapshotRecord, StoredChange, StoredKind, Tier, UsageError, UsageStorage,
+    BILLING_PERIOD_DAYS, SPECULATION_OVERHEAD_MODE,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::any::{AnyArguments, AnyPoolOptions, AnyRow};
+use sqlx::query::Query;
+use sqlx::{Any, AnyConnection, AnyPool, Row};
+
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
+
+/// Columns of `usage_accounts` and `usage_periods`, in [`bind_account`] order
+const ACCOUNT_COLUMNS: [&str; 22] = [
+    "account_id",
+    "tier",
+    "billing_period_start",
+    "billing_period_end",
+    "mcus_allocated",
+    "pay_as_you_go_enabled",
+    "vcpu_hours",
+    "ram_gb_hours",
+    "disk_gb_hours",
+    "snapshot_tb_hours",
+    "storage_byte_hours",
+    "egress_bytes",
+    "snapshot_bytes",
+    "artifact_bytes",
+    "log_bytes",
+    "speculation_vcpu_hours",
+    "speculation_ram_gb_hours",
+    "cpu_seconds",
+    "stdout_bytes",
+    "measured_mcus",
+    "last_updated",
+    "storage_accrued_at",
+];
+
+/// [`AccountUsage::accrue_storage`] up to `$1`
+const ACCRUE_STORAGE: &str = "storage_byte_hours = storage_byte_hours + CASE \
+     WHEN storage_accrued_at < $1 THEN \
+     CAST(snapshot_bytes + artifact_bytes + log_bytes AS DOUBLE PRECISION) \
+     * ($1 - storage_accrued_at) / 3600000.0 \
+     ELSE 0 END, \
+     storage_accrued_at = CASE \
+     WHEN storage_accrued_at IS NULL OR storage_accrued_at < $1 THEN $1 \
+     ELSE storage_accrued_at END";
+
+/// Counters a new billing period starts from; what [`McuUsage::held`] leaves out
+const RESET_PERIOD: &str = "vcpu_hours = 0, ram_gb_hours = 0, disk_gb_hours = 0, \
+     snapshot_tb_hours = 0, storage_byte_hours = 0, egress_bytes = 0, \
+     speculation_vcpu_hours = 0, speculation_ram_gb_hours = 0, \
+     cpu_seconds = 0, stdout_bytes = 0, measured_mcus = 0";
+
+impl From<sqlx::Error> for UsageError {
+    fn from(e: sqlx::Error) -> Self {
+        UsageError::Storage(e.to_string())
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for UsageError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        UsageError::Storage(e.to_string())
+    }
+}
+
+pub struct SqlStorage {
+    pool: AnyPool,
+}
+
+impl SqlStorage {
+    /// Connect to `url` and migrate its schema
+    pub async fn connect(url: &str) -> Result<Self> {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new().connect(url).await?;
+        MIGRATOR.run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    /// Fail with `AccountNotFound` when an update touched no account
+    fn found(account_id: &str, rows: u64) -> Result<()> {
+        if rows == 0 {
+            return Err(UsageError::AccountNotFound(account_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Close the period ending at `end`; false when another writer already has
+    async fn close_period(&self, account_id: &str, tier: Tier, end: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let accrued = sqlx::query(&format!(
+            "UPDATE usage_accounts SET {ACCRUE_STORAGE} \
+             WHERE account_id = $2 AND billing_period_end = $1"
+        ))
+        .bind(end)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+        if accrued.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let columns = ACCOUNT_COLUMNS.join(", ");
+        sqlx::query(&format!(
+            "INSERT INTO usage_periods ({columns}) \
+             SELECT {columns} FROM usage_accounts WHERE account_id = $1 \
+             ON CONFLICT DO NOTHING"
+        ))
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!(
+            "UPDATE usage_accounts SET {RESET_PERIOD}, \
+             billing_period_start = billing_period_end, \
+             billing_period_end = billing_period_end + $1, \
+             mcus_allocated = $2 \
+             WHERE account_id = $3"
+        ))
+        .bind(Duration::days(BILLING_PERIOD_DAYS).num_milliseconds())
+        .bind(tier.limits().starting_mcus as f64)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+fn millis(at: DateTime<Utc>) -> i64 {
+    at.timestamp_millis()
+}
+
+fn instant(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+fn tier_name(tier: Tier) -> &'static str {
+    match tier {
+        Tier::Developer => "developer",
+        Tier::Team => "team",
+        Tier::Scale => "scale",
+    }
+}
+
+fn parse_tier(name: &str) -> Result<Tier> {
+    match name {
+        "developer" => Ok(Tier::Developer),
+        "team" => Ok(Tier::Team),
+        "scale" => Ok(Tier::Scale),
+        _ => Err(UsageError::InvalidTier(name.to_string())),
+    }
+}
+
+fn stored_column(kind: StoredKind) -> &'static str {
+    match kind {
+        StoredKind::Snapshot => "snapshot_bytes",
+        StoredKind::Artifact => "artifact_bytes",
+        StoredKind::Log => "log_bytes",
+    }
+}
+
+fn bind_account<'q>(
+    query: Query<'q, Any, AnyArguments<'q>>,
+    account: &'q AccountUsage,
+) -> Query<'q, Any, AnyArguments<'q>> {
+    let usage = &account.usage;
+    query
+        .bind(account.account_id.as_str())
+        .bind(tier_name(account.tier))
+        .bind(millis(account.billing_period_start))
+        .bind(millis(account.billing_period_end))
+        .bind(account.mcus_allocated)
+        .bind(i64::from(account.pay_as_you_go_enabled))
+        .bind(usage.vcpu_hours)
+        .bind(usage.ram_gb_hours)
+        .bind(usage.disk_gb_hours)
+        .bind(usage.snapshot_tb_hours)
+        .bind(usage.storage_byte_hours)
+        .bind(usage.egress_bytes as i64)
+        .bind(usage.snapshot_bytes as i64)
+        .bind(usage.artifact_bytes as i64)
+        .bind(usage.log_bytes as i64)
+        .bind(usage.speculation_vcpu_hours)
+        .bind(usage.speculation_ram_gb_hours)
+        .bind(usage.cpu_seconds)
+        .bind(usage.stdout_bytes as i64)
+        .bind(usage.measured_mcus)
+        .bind(millis(account.last_updated))
+        .bind(account.storage_accrued_at.map(millis))
+}
+
+/// `$1, …, $22`, one per account column
+fn account_placeholders() -> String {
+    (1..=ACCOUNT_COLUMNS.len())
+        .map(|i| format!("${i}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+async fn insert_instance(
+    conn: &mut AnyConnection,
+    account_id: &str,
+    instance: &InstanceRecord,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO usage_instances \
+         (account_id, instance_id, vcpus, ram_gb, disk_gb, started_at, stopped_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING",
+    )
+    .bind(account_id)
+    .bind(instance.instance_id.as_str())
+    .bind(i64::from(instance.vcpus))
+    .bind(i64::from(instance.ram_gb))
+    .bind(i64::from(instance.disk_gb))
+    .bind(millis(instance.started_at))
+    .bind(instance.stopped_at.map(millis))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn insert_snapshot(
+    conn: &mut AnyConnection,
+    account_id: &str,
+    snapshot: &SnapshotRecord,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO usage_snapshots (account_id, snapshot_id, size_gb, created_at, deleted_at) \
+         VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+    )
+    .bind(account_id)
+    .bind(snapshot.snapshot_id.as_str())
+    .bind(snapshot.size_gb as i64)
+    .bind(millis(snapshot.created_at))
+    .bind(snapshot.deleted_at.map(millis))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn account_from_row(row: &AnyRow) -> Result<AccountUsage> {
+    let bytes = |column: &str| row.try_get::<i64, _>(column).map(|bytes| bytes as u64);
+    let usage = McuUsage {
+        vcpu_hours: row.try_get("vcpu_hours")?,
+        ram_gb_hours: row.try_get("ram_gb_hours")?,
+        disk_gb_hours: row.try_get("disk_gb_hours")?,
+        snapshot_tb_hours: row.try_get("snapshot_tb_hours")?,
+        storage_byte_hours: row.try_get("storage_byte_hours")?,
+        egress_bytes: bytes("egress_bytes")?,
+        snapshot_bytes: bytes("snapshot_bytes")?,
+        artifact_bytes: bytes("artifact_bytes")?,
+        log_bytes: bytes("log_bytes")?,
+        speculation_vcpu_hours: row.try_get("speculation_vcpu_hours")?,
+        speculation_ram_gb_hours: row.try_get("speculation_ram_gb_hours")?,
+        cpu_seconds: row.try_get("cpu_seconds")?,
+        stdout_bytes: bytes("stdout_bytes")?,
+        measured_mcus: row.try_get("measured_mcus")?,
+    };
+    Ok(AccountUsage {
+        account_id: row.try_get("account_id")?,
+        tier: parse_tier(&row.try_get::<String, _>("tier")?)?,
+        billing_period_start: instant(row.try_get("billing_period_start")?),
+        billing_period_end: instant(row.try_get("billing_period_end")?),
+        mcus_allocated: row.try_get("mcus_allocated")?,
+        mcus_consumed: usage.calculate_mcus(),
+        pay_as_you_go_enabled: row.try_get::<i64, _>("pay_as_you_go_enabled")? != 0,
+        usage,
+        active_resources: ActiveResources::default(),
+        last_updated: instant(row.try_get("last_updated")?),
+        storage_accrued_at: row
+            .try_get::<Option<i64>, _>("storage_accrued_at")?
+            .map(instant),
+    })
+}
+
+#[async_trait]
+impl UsageStorage for SqlStorage {
+    async fn create_account(&self, account_id: String, tier: Tier) -> Result<()> {
+        let account = AccountUsage::new(account_id, tier, Utc::now());
+        let columns = ACCOUNT_COLUMNS.join(", ");
+        let values = account_placeholders();
+        let sql = format!(
+            "INSERT INTO usage_accounts ({columns}) VALUES ({values}) \
+             ON CONFLICT (account_id) DO NOTHING"
+        );
+        bind_account(sqlx::query(&sql), &account)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_account(&self, account_id: &str) -> Result<AccountUsage> {
+        let columns = ACCOUNT_COLUMNS.join(", ");
+        let row = sqlx::query(&format!(
+            "SELECT {columns} FROM usage_accounts WHERE account_id = $1"
+        ))
+        .bind(account_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| UsageError::AccountNotFound(account_id.to_string()))?;
+        let mut account = account_from_row(&row)?;
+
+        let instances = sqlx::query(
+            "SELECT instance_id, vcpus, ram_gb, disk_gb, started_at, stopped_at \
+             FROM usage_instances WHERE account_id = $1 ORDER BY started_at, instance_id",
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?;
+        for row in instances {
+            account.active_resources.instances.push(InstanceRecord {
+                instance_id: row.try_get("instance_id")?,
+                vcpus: row.try_get::<i64, _>("vcpus")? as u32,
+                ram_gb: row.try_get::<i64, _>("ram_gb")? as u32,
+                disk_gb: row.try_get::<i64, _>("disk_gb")? as u32,
+                started_at: instant(row.try_get("started_at")?),
+                stopped_at: row.try_get::<Option<i64>, _>("stopped_at")?.map(instant),
+            });
+        }
+
+        let snapshots = sqlx::query(
+            "SELECT snapshot_id, size_gb, created_at, deleted_at \
+             FROM usage_snapshots WHERE account_id = $1 ORDER BY created_at, snapshot_id",
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?;
+        for row in snapshots {
+            account.active_resources.snapshots.push(SnapshotRecord {
+                snapshot_id: row.try_get("snapshot_id")?,
+                size_gb: row.try_get::<i64, _>("size_gb")? as u64,
+                created_at: instant(row.try_get("created_at")?),
+                deleted_at: row.try_get::<Option<i64>, _>("deleted_at")?.map(instant),
+            });
+        }
+        Ok(account)
+    }
+
+    async fn update_account(&self, account: &AccountUsage) -> Result<()> {
+        let columns = ACCOUNT_COLUMNS.join(", ");
+        let values = account_placeholders();
+        let updates = ACCOUNT_COLUMNS[1..]
+            .iter()
+            .map(|column| format!("{column} = excluded.{column}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "INSERT INTO usage_accounts ({columns}) VALUES ({values}) \
+             ON CONFLICT (account_id) DO UPDATE SET {updates}"
+        );
+
+        let mut tx = self.pool.begin().await?;
+        bind_account(sqlx::query(&sql), account)
+            .execute(&mut *tx)
+            .await?;
+        let account_id = account.account_id.as_str();
+        sqlx::query("DELETE FROM usage_instances WHERE account_id = $1")
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+        for instance in &account.active_resources.instances {
+            insert_instance(&mut tx, account_id, instance).await?;
+        }
+        sqlx::query("DELETE FROM usage_snapshots WHERE account_id = $1")
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+        for snapshot in &account.active_resources.snapshots {
+            insert_snapshot(&mut tx, account_id, snapshot).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn record_execution(&self, record: &ExecutionRecord) -> Result<()> {
+        let vcpu_hours = record.vcpu_seconds / 3600.0;
+        let ram_gb_hours = record.ram_gb_seconds / 3600.0;
+        let speculative = record.mode == SPECULATION_OVERHEAD_MODE;
+        let (speculation_vcpu_hours, speculation_ram_gb_hours) = if speculative {
+            (vcpu_hours, ram_gb_hours)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE usage_accounts SET \
+             vcpu_hours = vcpu_hours + $1, ram_gb_hours = ram_gb_hours + $2, \
+             speculation_vcpu_hours = speculation_vcpu_hours + $3, \
+             speculation_ram_gb_hours = speculation_ram_gb_hours + $4, \
+             last_updated = $5 WHERE account_id = $6",
+        )
+        .bind(vcpu_hours)
+        .bind(ram_gb_hours)
+        .bind(speculation_vcpu_hours)
+        .bind(speculation_ram_gb_hours)
+        .bind(millis(Utc::now()))
+        .bind(record.account_id.as_str())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO usage_executions \
+             (execution_id, account_id, vcpu_seconds, ram_gb_seconds, mode, recorded_at, \
+             duration_ms) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(record.execution_id.as_str())
+        .bind(record.account_id.as_str())
+        .bind(record.vcpu_seconds)
+        .bind(record.ram_gb_seconds)
+        .bind(record.mode.as_str())
+        .bind(millis(record.timestamp))
+        .bind(record.duration_ms as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn record_measured(
+        &self,
+        account_id: &str,
+        usage: &ExecutionUsage,
+        mcus: f64,
+    ) -> Result<()> {
+        let updated = sqlx::query(
+            "UPDATE usage_accounts SET \
+             cpu_seconds = cpu_seconds + $1, stdout_bytes = stdout_bytes + $2, \
+             measured_mcus = measured_mcus + $3, last_updated = $4 WHERE account_id = $5",
+        )
+        .bind(usage.cpu_time_ms as f64 / 1000.0)
+        .bind(usage.stdout_bytes as i64)
+        .bind(mcus)
+        .bind(millis(Utc::now()))
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+        Self::found(account_id, updated.rows_affected())
+    }
+
+    async fn update_stored(
+        &self,
+        account_id: &str,
+        kind: StoredKind,
+        change: StoredChange,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        let column = stored_column(kind);
+        // The accrual reads the byte counts from before this update
+        let (set, bytes) = match change {
+            StoredChange::By(delta) => (
+                format!("{column} = CASE WHEN {column} + $2 < 0 THEN 0 ELSE {column} + $2 END"),
+                delta,
+            ),
+            StoredChange::To(bytes) => (format!("{column} = $2"), bytes as i64),
+        };
+        let updated = sqlx::query(&format!(
+            "UPDATE usage_accounts SET {ACCRUE_STORAGE}, {set}, last_updated = $3 \
+             WHERE account_id = $4"
+        ))
+        .bind(millis(at))
+        .bind(bytes)
+        .bind(millis(Utc::now()))
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+        Self::found(account_id, updated.rows_affected())
+    }
+
+    async fn record_egress(&self, account_id: &str, bytes: u64) -> Result<()> {
+        let updated = sqlx::query(
+            "UPDATE usage_accounts SET egress_bytes = egress_bytes + $1, last_updated = $2 \
+             WHERE account_id = $3",
+        )
+        .bind(bytes as i64)
+        .bind(millis(Utc::now()))
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+        Self::found(account_id, updated.rows_affected())
+    }
+
+    async fn add_instance(&self, account_id: &str, instance: &InstanceRecord) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let touched =
+            sqlx::query("UPDATE usage_accounts SET last_updated = $1 WHERE account_id = $2")
+                .bind(millis(Utc::now()))
+                .bind(account_id)
+                .execute(&mut *tx)
+                .await?;
+        if touched.rows_affected() == 0 {
+            return Ok(());
+        }
+        insert_instance(&mut tx, account_id, instance).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn stop_instance(&self, account_id: &str, instance_id: &str) -> Result<()> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        // Only the first stop bills the instance
+        let stopped = sqlx::query(
+            "UPDATE usage_instances SET stopped_at = $1 \
+             WHERE account_id = $2 AND instance_id = $3 AND stopped_at IS NULL \
+             RETURNING vcpus, ram_gb, disk_gb, started_at",
+        )
+        .bind(millis(now))
+        .bind(account_id)
+        .bind(instance_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(stopped) = stopped else {
+            return Ok(());
+        };
+        let started_at = instant(stopped.try_get("started_at")?);
+        let hours = (now - started_at).num_seconds() as f64 / 3600.0;
+        let held = |column: &str| stopped.try_get::<i64, _>(column).map(|n| n as f64 * hours);
+        sqlx::query(
+            "UPDATE usage_accounts SET \
+             vcpu_hours = vcpu_hours + $1, ram_gb_hours = ram_gb_hours + $2, \
+             disk_gb_hours = disk_gb_hours + $3, last_updated = $4 WHERE account_id = $5",
+        )
+        .bind(held("vcpus")?)
+        .bind(held("ram_gb")?)
+        .bind(held("disk_gb")?)
+        .bind(millis(now))
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn add_snapshot(&self, account_id: &str, snapshot: &SnapshotRecord) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let touched =
+            sqlx::query("UPDATE usage_accounts SET last_updated = $1 WHERE account_id = $2")
+                .bind(millis(Utc::now()))
+                .bind(account_id)
+                .execute(&mut *tx)
+                .await?;
+        if touched.rows_affected() == 0 {
+            return Ok(());
+        }
+        insert_snapshot(&mut tx, account_id, snapshot).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_snapshot(&self, account_id: &str, snapshot_id: &str) -> Result<()> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query(
+            "UPDATE usage_snapshots SET deleted_at = $1 \
+             WHERE account_id = $2 AND snapshot_id = $3 AND deleted_at IS NULL \
+             RETURNING size_gb, created_at",
+        )
+        .bind(millis(now))
+        .bind(account_id)
+        .bind(snapshot_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(deleted) = deleted else {
+            return Ok(());
+        };
+        let created_at = instant(deleted.try_get("created_at")?);
+        let hours = (now - created_at).num_seconds() as f64 / 3600.0;
+        let size_gb = deleted.try_get::<i64, _>("size_gb")? as f64;
+        sqlx::query(
+            "UPDATE usage_accounts SET snapshot_tb_hours = snapshot_tb_hours + $1, \
+             last_updated = $2 WHERE account_id = $3",
+        )
+        .bind(size_gb / 1024.0 * hours)
+        .bind(millis(now))
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_usage_history(
+        &self,
+        account_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<AccountUsage>> {
+        let columns = ACCOUNT_COLUMNS.join(", ");
+        let periods = sqlx::query(&format!(
+            "SELECT {columns} FROM usage_periods \
+             WHERE account_id = $1 AND billing_period_start <= $2 AND billing_period_end >= $3 \
+             ORDER BY billing_period_start"
+        ))
+        .bind(account_id)
+        .bind(millis(end))
+        .bind(millis(start))
+        .fetch_all(&self.pool)
+        .await?;
+        let mut history = periods
+            .iter()
+            .map(account_from_row)
+            .collect::<Result<Vec<_>>>()?;
+        match self.get_account(account_id).await {
+            Ok(account)
+                if account.billing_period_start <= end && account.billing_period_end >= start =>
+            {
+                history.push(account)
+            }
+            Ok(_) | Err(UsageError::AccountNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(history)
+    }
+
+    async fn roll_over(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut closed = 0;
+        loop {
+            let due = sqlx::query(
+                "SELECT account_id, tier, billing_period_end FROM usage_accounts \
+                 WHERE billing_period_end <= $1",
+            )
+            .bind(millis(now))
+            .fetch_all(&self.pool)
+            .await?;
+            if due.is_empty() {
+                return Ok(closed);
+            }
+            for row in due {
+                let account_id: String = row.try_get("account_id")?;
+                let tier = parse_tier(&row.try_get::<String, _>("tier")?)?;
+                let end: i64 = row.try_get("billing_period_end")?;
+                if self.close_period(&account_id, tier, end).await? {
+                    closed += 1;
+                }
+            }
+        }
+    }
+}
@@ -1,31 +1,67 @@
-use crate::{AccountUsage, ExecutionRecord, InstanceRecord, Result, SnapshotRecord, UsageError};
+use crate::{
+    AccountUsage, ExecutionRecord, ExecutionUsage, InstanceRecord, Result, SnapshotRecord,
+    StoredChange, StoredKind, Tier, UsageError, SPECULATION_OVERHEAD_MODE,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+/// Where accounts and their usage are kept.
+///
+/// Counters only change through the methods that add to them, each applied atomically, so
+/// several trackers can share one store. `update_account` replaces the whole account and is
+/// for administrative changes such as a tier upgrade.
 #[async_trait]
 pub trait UsageStorage: Send + Sync {
+    /// Open an account whose first billing period starts now; an existing one is left as it is
+    async fn create_account(&self, account_id: String, tier: Tier) -> Result<()>;
     async fn get_account(&self, account_id: &str) -> Result<AccountUsage>;
     async fn update_account(&self, account: &AccountUsage) -> Result<()>;
     async fn record_execution(&self, record: &ExecutionRecord) -> Result<()>;
+    /// Add what an execution measured itself consuming, worth `mcus`
+    async fn record_measured(
+        &self,
+        account_id: &str,
+        usage: &ExecutionUsage,
+        mcus: f64,
+    ) -> Result<()>;
+    /// Change the bytes of `kind` held from `at` on; what was held until then is accrued first
+    async fn update_stored(
+        &self,
+        account_id: &str,
+        kind: StoredKind,
+        change: StoredChange,
+        at: DateTime<Utc>,
+    ) -> Result<()>;
+    async fn record_egress(&self, account_id: &str, bytes: u64) -> Result<()>;
     async fn add_instance(&self, account_id: &str, instance: &InstanceRecord) -> Result<()>;
     async fn stop_instance(&self, account_id: &str, instance_id: &str) -> Result<()>;
     async fn add_snapshot(&self, account_id: &str, snapshot: &SnapshotRecord) -> Result<()>;
     async fn delete_snapshot(&self, account_id: &str, snapshot_id: &str) -> Result<()>;
+    /// The account's billing periods overlapping `start..end`, oldest first and the current
+    /// one last; closed periods come without their active resources
     async fn get_usage_history(
         &self,
         account_id: &str,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
     ) -> Result<Vec<AccountUsage>>;
+    /// Close every billing period that ended by `now` (see [`AccountUsage::close_period`]);
+    /// returns how many were closed
+    async fn roll_over(&self, now: DateTime<Utc>) -> Result<usize>;
+}
+
+fn overlaps(account: &AccountUsage, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+    account.billing_period_start <= end && account.billing_period_end >= start
 }
 
 // In-memory storage implementation for development/testing
 pub struct InMemoryStorage {
     accounts: Arc<RwLock<HashMap<String, AccountUsage>>>,
     executions: Arc<RwLock<Vec<ExecutionRecord>>>,
+    periods: Arc<RwLock<Vec<AccountUsage>>>,
 }
 
 impl Default for InMemoryStorage {
@@ -39,35 +75,35 @@ impl InMemoryStorage {
         Self {
             accounts: Arc::new(RwLock::new(HashMap::new())),
             executions: Arc::new(RwLock::new(Vec::new())),
+            periods: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
-    pub async fn create_account(&self, account_id: String, tier: crate::Tier) -> Result<()> {
+    async fn update<T>(
+        &self,
+        account_id: &str,
+        update: impl FnOnce(&mut AccountUsage) -> T,
+    ) -> Result<T> {
         let mut accounts = self.accounts.write().await;
-        let now = Utc::now();
-        let limits = tier.limits();
-
-        accounts.insert(
-            account_id.clone(),
-            AccountUsage {
-                account_id,
-                tier,
-                billing_period_start: now,
-                billing_period_end: now + chrono::Duration::days(30),
-                mcus_allocated: limits.starting_mcus as f64,
-                mcus_consumed: 0.0,
-                pay_as_you_go_enabled: false,
-                usage: crate::McuUsage::default(),
-                active_resources: crate::ActiveResources::default(),
-                last_updated: now,
-            },
-        );
-        Ok(())
+        let account = accounts
+            .get_mut(account_id)
+            .ok_or_else(|| UsageError::AccountNotFound(account_id.to_string()))?;
+        let updated = update(account);
+        account.last_updated = Utc::now();
+        Ok(updated)
     }
 }
 
 #[async_trait]
 impl UsageStorage for InMemoryStorage {
+    async fn create_account(&self, account_id: String, tier: Tier) -> Result<()> {
+        let mut accounts = self.accounts.write().await;
+        accounts
+            .entry(account_id.clone())
+            .or_insert_with(|| AccountUsage::new(account_id, tier, Utc::now()));
+        Ok(())
+    }
+
     async fn get_account(&self, account_id: &str) -> Result<AccountUsage> {
         self.accounts
             .read()
@@ -91,6 +127,10 @@ impl UsageStorage for InMemoryStorage {
         if let Some(account) = accounts.get_mut(&record.account_id) {
             account.usage.vcpu_hours += record.vcpu_seconds / 3600.0;
             account.usage.ram_gb_hours += record.ram_gb_seconds / 3600.0;
+            if record.mode == SPECULATION_OVERHEAD_MODE {
+                account.usage.speculation_vcpu_hours += record.vcpu_seconds / 3600.0;
+                account.usage.speculation_ram_gb_hours += record.ram_gb_seconds / 3600.0;
+            }
             account.mcus_consumed = account.usage.calculate_mcus();
             account.last_updated = Utc::now();
         }
@@ -100,6 +140,40 @@ impl UsageStorage for InMemoryStorage {
         Ok(())
     }
 
+    async fn record_measured(
+        &self,
+        account_id: &str,
+        usage: &ExecutionUsage,
+        mcus: f64,
+    ) -> Result<()> {
+        self.update(account_id, |account| {
+            account.usage.cpu_seconds += usage.cpu_time_ms as f64 / 1000.0;
+            account.usage.stdout_bytes += usage.stdout_bytes;
+            account.usage.measured_mcus += mcus;
+        })
+        .await
+    }
+
+    async fn update_stored(
+        &self,
+        account_id: &str,
+        kind: StoredKind,
+        change: StoredChange,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.update(account_id, |account| {
+            // Bytes held until now are billed at the old amount
+            account.accrue_storage(at);
+            change.apply(account.usage.stored_bytes_mut(kind));
+        })
+        .await
+    }
+
+    async fn record_egress(&self, account_id: &str, bytes: u64) -> Result<()> {
+        self.update(account_id, |account| account.usage.egress_bytes += bytes)
+            .await
+    }
+
     async fn add_instance(&self, account_id: &str, instance: &InstanceRecord) -> Result<()> {
         let mut accounts = self.accounts.write().await;
         if let Some(account) = accounts.get_mut(account_id) {
@@ -172,14 +246,34 @@ impl UsageStorage for InMemoryStorage {
     async fn get_usage_history(
         &self,
         account_id: &str,
-        _start: DateTime<Utc>,
-        _end: DateTime<Utc>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
     ) -> Result<Vec<AccountUsage>> {
-        // For in-memory, just return current snapshot
+        let mut history: Vec<AccountUsage> = self
+            .periods
+            .read()
+            .await
+            .iter()
+            .filter(|period| period.account_id == account_id && overlaps(period, start, end))
+            .cloned()
+            .collect();
         if let Ok(account) = self.get_account(account_id).await {
-            Ok(vec![account])
-        } else {
-            Ok(vec![])
+            if overlaps(&account, start, end) {
+                history.push(account);
+            }
+        }
+        Ok(history)
+    }
+
+    async fn roll_over(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut accounts = self.accounts.write().await;
+        let mut periods = self.periods.write().await;
+        let closed = periods.len();
+        for account in accounts.values_mut() {
+            while account.billing_period_end <= now {
+                periods.push(account.close_period());
+            }
         }
+        Ok(periods.len() - closed)
     }
 }
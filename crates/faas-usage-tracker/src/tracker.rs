@@ -1,7 +1,9 @@
 use crate::{
-    AccountUsage, BillingEstimate, ExecutionRecord, InstanceRecord, Result, UsageError,
-    UsageStorage,
+    AccountUsage, BillingEstimate, DimensionUsage, ExecutionRecord, ExecutionUsage, InstanceRecord,
+    Limit, McuUsage, Result, StoredChange, StoredKind, Tier, UsageBreakdown, UsageError,
+    UsageStorage, GIB,
 };
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 
 pub struct UsageTracker {
@@ -46,6 +48,7 @@ impl UsageTracker {
                     "vCPU limit exceeded: {} + {} > {}",
                     current_vcpus, requested_vcpus, limits.max_vcpu
                 ),
+                limit: Limit::Vcpu,
             });
         }
 
@@ -55,6 +58,7 @@ impl UsageTracker {
                     "RAM limit exceeded: {} + {} > {}",
                     current_ram, requested_ram_gb, limits.max_ram_gb
                 ),
+                limit: Limit::Ram,
             });
         }
 
@@ -63,6 +67,7 @@ impl UsageTracker {
         if remaining <= 0.0 && !account.pay_as_you_go_enabled {
             return Err(UsageError::LimitExceeded {
                 message: format!("Insufficient MCU credits: {remaining:.2} remaining"),
+                limit: Limit::Credits,
             });
         }
 
@@ -73,6 +78,29 @@ impl UsageTracker {
         self.storage.record_execution(&record).await
     }
 
+    /// MCUs of what one execution consumed: its CPU time, and its peak memory held for its
+    /// wall time, less the tier's `discount_percent`
+    pub fn execution_mcus(tier: Tier, usage: &ExecutionUsage) -> f64 {
+        let hours = |ms: u64| ms as f64 / 3_600_000.0;
+        let consumed = McuUsage {
+            vcpu_hours: hours(usage.cpu_time_ms),
+            ram_gb_hours: usage.peak_memory_bytes as f64 / GIB as f64 * hours(usage.wall_time_ms),
+            ..Default::default()
+        };
+        let discount = f64::from(tier.limits().discount_percent.min(100)) / 100.0;
+        consumed.calculate_mcus() * (1.0 - discount)
+    }
+
+    /// Add what an execution measured itself consuming to the account; returns its MCUs
+    pub async fn record(&self, account_id: &str, usage: &ExecutionUsage) -> Result<f64> {
+        let tier = self.storage.get_account(account_id).await?.tier;
+        let mcus = Self::execution_mcus(tier, usage);
+        self.storage
+            .record_measured(account_id, usage, mcus)
+            .await?;
+        Ok(mcus)
+    }
+
     pub async fn start_instance(&self, account_id: &str, instance: InstanceRecord) -> Result<()> {
         // Check limits first
         self.check_limits(account_id, instance.vcpus, instance.ram_gb)
@@ -113,4 +141,165 @@ impl UsageTracker {
             billing_period_end: account.billing_period_end,
         })
     }
+
+    /// Would storing `additional_bytes` more of `kind` stay within the tier? The error names
+    /// the limit that would be crossed.
+    pub async fn check_storage(
+        &self,
+        account_id: &str,
+        kind: StoredKind,
+        additional_bytes: u64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut account = self.storage.get_account(account_id).await?;
+        account.accrue_storage(at);
+        let limits = account.tier.limits();
+        let usage = &account.usage;
+
+        let byte_hours_limit = (limits.storage_gb_hours * GIB) as f64;
+        if usage.storage_byte_hours >= byte_hours_limit {
+            return Err(UsageError::LimitExceeded {
+                message: format!(
+                    "storage GB-hours exhausted: {:.2} >= {}",
+                    usage.storage_byte_hours / GIB as f64,
+                    limits.storage_gb_hours
+                ),
+                limit: Limit::Storage,
+            });
+        }
+        let stored_limit = limits.max_storage_gb as u64 * GIB;
+        if usage.stored_bytes() + additional_bytes > stored_limit {
+            return Err(UsageError::LimitExceeded {
+                message: format!(
+                    "storage limit exceeded: {} + {} > {} bytes",
+                    usage.stored_bytes(),
+                    additional_bytes,
+                    stored_limit
+                ),
+                limit: Limit::Storage,
+            });
+        }
+        let artifact_limit = limits.artifact_gb * GIB;
+        if kind == StoredKind::Artifact && usage.artifact_bytes + additional_bytes > artifact_limit
+        {
+            return Err(UsageError::LimitExceeded {
+                message: format!(
+                    "artifact storage limit exceeded: {} + {} > {} bytes",
+                    usage.artifact_bytes, additional_bytes, artifact_limit
+                ),
+                limit: Limit::Storage,
+            });
+        }
+        Ok(())
+    }
+
+    /// Would serving `bytes` more stay within the tier's egress?
+    pub async fn check_egress(&self, account_id: &str, bytes: u64) -> Result<()> {
+        let account = self.storage.get_account(account_id).await?;
+        let limit = account.tier.limits().egress_gb * GIB;
+        if account.usage.egress_bytes + bytes > limit {
+            return Err(UsageError::LimitExceeded {
+                message: format!(
+                    "egress limit exceeded: {} + {} > {} bytes",
+                    account.usage.egress_bytes, bytes, limit
+                ),
+                limit: Limit::Egress,
+            });
+        }
+        Ok(())
+    }
+
+    /// `delta_bytes` more (or, negative, fewer) of `kind` held from `at` on
+    pub async fn record_stored(
+        &self,
+        account_id: &str,
+        kind: StoredKind,
+        delta_bytes: i64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.storage
+            .update_stored(account_id, kind, StoredChange::By(delta_bytes), at)
+            .await
+    }
+
+    /// Exactly `bytes` of `kind` held from `at` on, for sweeps that recount what is left
+    pub async fn set_stored(
+        &self,
+        account_id: &str,
+        kind: StoredKind,
+        bytes: u64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.storage
+            .update_stored(account_id, kind, StoredChange::To(bytes), at)
+            .await
+    }
+
+    pub async fn record_egress(&self, account_id: &str, bytes: u64) -> Result<()> {
+        self.storage.record_egress(account_id, bytes).await
+    }
+
+    /// Start a new billing period for every account whose period ended by `now`
+    pub async fn roll_over(&self, now: DateTime<Utc>) -> Result<usize> {
+        self.storage.roll_over(now).await
+    }
+
+    /// Consumption per dimension, with storage accrued up to `at`
+    pub async fn usage_breakdown(
+        &self,
+        account_id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<UsageBreakdown> {
+        let mut account = self.storage.get_account(account_id).await?;
+        account.accrue_storage(at);
+        let limits = account.tier.limits();
+        let usage = &account.usage;
+        let dimension = |name: &str, used: f64, limit: Option<f64>| DimensionUsage {
+            dimension: name.to_string(),
+            used,
+            limit,
+        };
+        Ok(UsageBreakdown {
+            account_id: account.account_id.clone(),
+            tier: account.tier,
+            at,
+            dimensions: vec![
+                dimension("mcus", account.mcus_consumed, Some(account.mcus_allocated)),
+                dimension("vcpu_hours", usage.vcpu_hours, None),
+                dimension("measured_mcus", usage.measured_mcus, None),
+                dimension("cpu_seconds", usage.cpu_seconds, None),
+                dimension("stdout_bytes", usage.stdout_bytes as f64, None),
+                dimension("ram_gb_hours", usage.ram_gb_hours, None),
+                dimension("disk_gb_hours", usage.disk_gb_hours, None),
+                dimension("speculation_vcpu_hours", usage.speculation_vcpu_hours, None),
+                dimension(
+                    "speculation_ram_gb_hours",
+                    usage.speculation_ram_gb_hours,
+                    None,
+                ),
+                dimension(
+                    "storage_byte_hours",
+                    usage.storage_byte_hours,
+                    Some((limits.storage_gb_hours * GIB) as f64),
+                ),
+                dimension(
+                    "stored_bytes",
+                    usage.stored_bytes() as f64,
+                    Some((limits.max_storage_gb as u64 * GIB) as f64),
+                ),
+                dimension("snapshot_bytes", usage.snapshot_bytes as f64, None),
+                dimension(
+                    "artifact_bytes",
+                    usage.artifact_bytes as f64,
+                    Some((limits.artifact_gb * GIB) as f64),
+                ),
+                dimension("log_bytes", usage.log_bytes as f64, None),
+                dimension(
+                    "egress_bytes",
+                    usage.egress_bytes as f64,
+                    Some((limits.egress_gb * GIB) as f64),
+                ),
+            ],
+        })
+    }
 }
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
 // MCU calculation and usage types
@@ -8,6 +8,38 @@ pub struct McuUsage {
     pub ram_gb_hours: f64,
     pub disk_gb_hours: f64,
     pub snapshot_tb_hours: f64,
+
+    // Storage and transfer, metered in bytes
+    /// Every stored byte integrated over the time it was held
+    #[serde(default)]
+    pub storage_byte_hours: f64,
+    /// Bytes served back out: artifact and log downloads
+    #[serde(default)]
+    pub egress_bytes: u64,
+    /// Bytes held right now, by kind
+    #[serde(default)]
+    pub snapshot_bytes: u64,
+    #[serde(default)]
+    pub artifact_bytes: u64,
+    #[serde(default)]
+    pub log_bytes: u64,
+
+    /// Compute of speculative attempts that lost, already included in `vcpu_hours` and
+    /// `ram_gb_hours`
+    #[serde(default)]
+    pub speculation_vcpu_hours: f64,
+    #[serde(default)]
+    pub speculation_ram_gb_hours: f64,
+
+    /// What executions measured themselves consuming; billing still follows the size they
+    /// reserved
+    #[serde(default)]
+    pub cpu_seconds: f64,
+    #[serde(default)]
+    pub stdout_bytes: u64,
+    /// MCUs of the measured consumption after the tier discount
+    #[serde(default)]
+    pub measured_mcus: f64,
 }
 
 impl McuUsage {
@@ -20,8 +52,61 @@ impl McuUsage {
         // Take the max of compute resources, add snapshot separately
         cpu_mcus.max(ram_mcus).max(disk_mcus) + snapshot_mcus
     }
+
+    pub fn stored_bytes(&self) -> u64 {
+        self.snapshot_bytes + self.artifact_bytes + self.log_bytes
+    }
+
+    /// Only what is held right now, for the start of a billing period
+    pub fn held(&self) -> McuUsage {
+        McuUsage {
+            snapshot_bytes: self.snapshot_bytes,
+            artifact_bytes: self.artifact_bytes,
+            log_bytes: self.log_bytes,
+            ..Default::default()
+        }
+    }
+
+    pub fn stored_bytes_mut(&mut self, kind: StoredKind) -> &mut u64 {
+        match kind {
+            StoredKind::Snapshot => &mut self.snapshot_bytes,
+            StoredKind::Artifact => &mut self.artifact_bytes,
+            StoredKind::Log => &mut self.log_bytes,
+        }
+    }
 }
 
+/// What a stored byte belongs to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum StoredKind {
+    Snapshot,
+    Artifact,
+    /// Execution logs kept for the retention period
+    Log,
+}
+
+/// How a stored-bytes counter changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredChange {
+    /// More, or with a negative delta fewer, bytes; never below zero
+    By(i64),
+    /// Exactly this many bytes, for sweeps that recount what is left
+    To(u64),
+}
+
+impl StoredChange {
+    pub fn apply(self, held: &mut u64) {
+        match self {
+            StoredChange::By(delta) => *held = held.saturating_add_signed(delta),
+            StoredChange::To(bytes) => *held = bytes,
+        }
+    }
+}
+
+/// Length of a billing period
+pub const BILLING_PERIOD_DAYS: i64 = 30;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccountUsage {
     pub account_id: String,
@@ -40,6 +125,57 @@ pub struct AccountUsage {
 
     // Metadata
     pub last_updated: DateTime<Utc>,
+    /// How far `usage.storage_byte_hours` has been accrued
+    #[serde(default)]
+    pub storage_accrued_at: Option<DateTime<Utc>>,
+}
+
+impl AccountUsage {
+    /// A new account whose first billing period starts `now`
+    pub fn new(account_id: String, tier: crate::Tier, now: DateTime<Utc>) -> Self {
+        Self {
+            account_id,
+            tier,
+            billing_period_start: now,
+            billing_period_end: now + Duration::days(BILLING_PERIOD_DAYS),
+            mcus_allocated: tier.limits().starting_mcus as f64,
+            mcus_consumed: 0.0,
+            pay_as_you_go_enabled: false,
+            usage: McuUsage::default(),
+            active_resources: ActiveResources::default(),
+            last_updated: now,
+            storage_accrued_at: None,
+        }
+    }
+
+    /// Close the billing period, returning what it used as it stood at its end. The next
+    /// period starts with the tier's MCUs again, unused ones not carried over, and the bytes
+    /// still held.
+    pub fn close_period(&mut self) -> AccountUsage {
+        self.accrue_storage(self.billing_period_end);
+        let closed = AccountUsage {
+            active_resources: ActiveResources::default(),
+            ..self.clone()
+        };
+        self.usage = self.usage.held();
+        self.mcus_allocated = self.tier.limits().starting_mcus as f64;
+        self.mcus_consumed = 0.0;
+        self.billing_period_start = self.billing_period_end;
+        self.billing_period_end += Duration::days(BILLING_PERIOD_DAYS);
+        closed
+    }
+
+    /// Accrue byte-hours for what has been stored since the last accrual, up to `now`
+    pub fn accrue_storage(&mut self, now: DateTime<Utc>) {
+        let since = self.storage_accrued_at.unwrap_or(now);
+        if now <= since {
+            self.storage_accrued_at = Some(since);
+            return;
+        }
+        let hours = (now - since).num_milliseconds() as f64 / 3_600_000.0;
+        self.usage.storage_byte_hours += self.usage.stored_bytes() as f64 * hours;
+        self.storage_accrued_at = Some(now);
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -66,6 +202,9 @@ pub struct SnapshotRecord {
     pub deleted_at: Option<DateTime<Utc>>,
 }
 
+/// [`ExecutionRecord::mode`] of a speculative attempt that lost the race
+pub const SPECULATION_OVERHEAD_MODE: &str = "speculation_overhead";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionRecord {
     pub execution_id: String,
@@ -77,6 +216,30 @@ pub struct ExecutionRecord {
     pub duration_ms: u64,
 }
 
+/// One metered dimension of an account, with its tier limit if it has one
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DimensionUsage {
+    pub dimension: String,
+    pub used: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageBreakdown {
+    pub account_id: String,
+    pub tier: crate::Tier,
+    /// Storage is accrued up to this instant
+    pub at: DateTime<Utc>,
+    pub dimensions: Vec<DimensionUsage>,
+}
+
+impl UsageBreakdown {
+    pub fn get(&self, dimension: &str) -> Option<&DimensionUsage> {
+        self.dimensions.iter().find(|d| d.dimension == dimension)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BillingEstimate {
     pub tier: crate::Tier,
@@ -1,11 +1,115 @@
+//! Every test runs against each storage backend compiled in. The Postgres ones need
+//! `FAAS_USAGE_TEST_POSTGRES_URL` and are skipped without it.
+
 use chrono::{Duration, Utc};
 use faas_usage_tracker::*;
 use std::sync::Arc;
 
-#[tokio::test]
-async fn test_account_creation_and_tier_limits() {
-    let storage = Arc::new(InMemoryStorage::new());
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+mod backends {
+    use faas_usage_tracker::{SqlStorage, UsageStorage};
+    use std::sync::Arc;
+
+    #[cfg(feature = "sqlite")]
+    pub async fn sqlite() -> (Arc<dyn UsageStorage>, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("usage.db").display()
+        );
+        (Arc::new(SqlStorage::connect(&url).await.unwrap()), dir)
+    }
+
+    /// A fresh schema in the test database, so tests running at once keep their accounts apart
+    #[cfg(feature = "postgres")]
+    pub async fn postgres() -> Option<Arc<dyn UsageStorage>> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static SCHEMAS: AtomicUsize = AtomicUsize::new(0);
+
+        let Ok(url) = std::env::var("FAAS_USAGE_TEST_POSTGRES_URL") else {
+            eprintln!("Test skipped: FAAS_USAGE_TEST_POSTGRES_URL not set");
+            return None;
+        };
+        let schema = format!(
+            "usage_test_{}_{}",
+            std::process::id(),
+            SCHEMAS.fetch_add(1, Ordering::SeqCst)
+        );
+        sqlx::any::install_default_drivers();
+        let pool = sqlx::AnyPool::connect(&url).await.unwrap();
+        sqlx::raw_sql(&format!(
+            "DROP SCHEMA IF EXISTS {schema} CASCADE; CREATE SCHEMA {schema}"
+        ))
+        .execute(&pool)
+        .await
+        .unwrap();
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let url = format!("{url}{separator}options=-c%20search_path%3D{schema}");
+        Some(Arc::new(SqlStorage::connect(&url).await.unwrap()))
+    }
+}
+
+macro_rules! storage_suite {
+    ($($test:ident),* $(,)?) => {
+        mod in_memory {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    super::$test(std::sync::Arc::new(faas_usage_tracker::InMemoryStorage::new()))
+                        .await;
+                }
+            )*
+        }
+
+        #[cfg(feature = "sqlite")]
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    let (storage, _dir) = super::backends::sqlite().await;
+                    super::$test(storage).await;
+                }
+            )*
+        }
+
+        #[cfg(feature = "postgres")]
+        mod postgres {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    if let Some(storage) = super::backends::postgres().await {
+                        super::$test(storage).await;
+                    }
+                }
+            )*
+        }
+    };
+}
 
+storage_suite!(
+    test_account_creation_and_tier_limits,
+    test_vcpu_limit_enforcement,
+    test_ram_limit_enforcement,
+    test_instance_lifecycle_tracking,
+    test_execution_recording,
+    test_speculation_overhead_is_billed_and_reported_separately,
+    test_mcu_calculation_accuracy,
+    test_billing_estimate,
+    test_overage_billing,
+    test_insufficient_credits_without_pay_as_you_go,
+    test_snapshot_tracking,
+    test_concurrent_instance_limits,
+    test_usage_history,
+    test_account_not_found,
+    test_snapshot_byte_hours_accrue_only_while_stored,
+    test_artifact_quota_leaves_executions_alone,
+    test_measured_usage_is_kept_apart_from_billing,
+    test_concurrent_trackers_do_not_lose_updates,
+    test_periods_roll_over_with_fresh_credits,
+    test_accounts_are_created_once,
+);
+
+async fn test_account_creation_and_tier_limits(storage: Arc<dyn UsageStorage>) {
     // Create accounts with different tiers
     storage
         .create_account("dev_user".to_string(), Tier::Developer)
@@ -38,9 +142,7 @@ async fn test_account_creation_and_tier_limits() {
     assert_eq!(scale_account.mcus_allocated, 7500.0);
 }
 
-#[tokio::test]
-async fn test_vcpu_limit_enforcement() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_vcpu_limit_enforcement(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Developer)
         .await
@@ -61,9 +163,7 @@ async fn test_vcpu_limit_enforcement() {
     ));
 }
 
-#[tokio::test]
-async fn test_ram_limit_enforcement() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_ram_limit_enforcement(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Developer)
         .await
@@ -84,9 +184,7 @@ async fn test_ram_limit_enforcement() {
     ));
 }
 
-#[tokio::test]
-async fn test_instance_lifecycle_tracking() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_instance_lifecycle_tracking(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Developer)
         .await
@@ -124,9 +222,7 @@ async fn test_instance_lifecycle_tracking() {
     assert!(usage.mcus_consumed > 0.0); // Some MCUs should be consumed
 }
 
-#[tokio::test]
-async fn test_execution_recording() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_execution_recording(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Developer)
         .await
@@ -154,9 +250,41 @@ async fn test_execution_recording() {
     assert_eq!(usage.mcus_consumed, 1.0); // Should be 1 MCU (1 vCPU-hour)
 }
 
-#[tokio::test]
-async fn test_mcu_calculation_accuracy() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_speculation_overhead_is_billed_and_reported_separately(
+    storage: Arc<dyn UsageStorage>,
+) {
+    storage
+        .create_account("test".to_string(), Tier::Developer)
+        .await
+        .unwrap();
+    let tracker = UsageTracker::new(storage.clone());
+
+    // The attempt that won ran an hour; the one that lost was cancelled after half
+    for (id, mode, seconds) in [
+        ("exec-1-fallback", "ephemeral", 3600.0),
+        ("exec-1-preferred", SPECULATION_OVERHEAD_MODE, 1800.0),
+    ] {
+        let execution = ExecutionRecord {
+            execution_id: id.to_string(),
+            account_id: "test".to_string(),
+            vcpu_seconds: seconds,
+            ram_gb_seconds: seconds,
+            mode: mode.to_string(),
+            timestamp: Utc::now(),
+            duration_ms: (seconds * 1000.0) as u64,
+        };
+        tracker.record_execution(execution).await.unwrap();
+    }
+
+    let usage = tracker.get_usage("test").await.unwrap().usage;
+    assert_eq!(usage.vcpu_hours, 1.5);
+    assert_eq!(usage.speculation_vcpu_hours, 0.5);
+    assert_eq!(usage.speculation_ram_gb_hours, 0.5);
+    let breakdown = tracker.usage_breakdown("test", Utc::now()).await.unwrap();
+    assert_eq!(breakdown.get("speculation_vcpu_hours").unwrap().used, 0.5);
+}
+
+async fn test_mcu_calculation_accuracy(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Team)
         .await
@@ -201,9 +329,7 @@ async fn test_mcu_calculation_accuracy() {
     assert_eq!(usage.mcus_consumed, 10.0); // Max(10 vCPU, 10 RAM) = 10
 }
 
-#[tokio::test]
-async fn test_billing_estimate() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_billing_estimate(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("team_user".to_string(), Tier::Team)
         .await
@@ -234,9 +360,7 @@ async fn test_billing_estimate() {
     assert_eq!(estimate.total_estimate, 40.0); // No overage charges
 }
 
-#[tokio::test]
-async fn test_overage_billing() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_overage_billing(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("dev_user".to_string(), Tier::Developer)
         .await
@@ -266,9 +390,7 @@ async fn test_overage_billing() {
     assert_eq!(estimate.total_estimate, 5.0); // Developer tier is free + overage
 }
 
-#[tokio::test]
-async fn test_insufficient_credits_without_pay_as_you_go() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_insufficient_credits_without_pay_as_you_go(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("limited_user".to_string(), Tier::Developer)
         .await
@@ -307,9 +429,7 @@ async fn test_insufficient_credits_without_pay_as_you_go() {
     ));
 }
 
-#[tokio::test]
-async fn test_snapshot_tracking() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_snapshot_tracking(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Developer)
         .await
@@ -336,9 +456,7 @@ async fn test_snapshot_tracking() {
     assert!(usage.mcus_consumed > 0.0);
 }
 
-#[tokio::test]
-async fn test_concurrent_instance_limits() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_concurrent_instance_limits(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Developer)
         .await
@@ -387,9 +505,7 @@ async fn test_concurrent_instance_limits() {
     assert_eq!(usage.active_resources.instances.len(), 2);
 }
 
-#[tokio::test]
-async fn test_usage_history() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_usage_history(storage: Arc<dyn UsageStorage>) {
     storage
         .create_account("test".to_string(), Tier::Team)
         .await
@@ -399,14 +515,12 @@ async fn test_usage_history() {
     let end = Utc::now();
 
     let history = storage.get_usage_history("test", start, end).await.unwrap();
-    assert_eq!(history.len(), 1); // In-memory storage returns current snapshot
+    assert_eq!(history.len(), 1); // Only the current period so far
     assert_eq!(history[0].account_id, "test");
     assert_eq!(history[0].tier, Tier::Team);
 }
 
-#[tokio::test]
-async fn test_account_not_found() {
-    let storage = Arc::new(InMemoryStorage::new());
+async fn test_account_not_found(storage: Arc<dyn UsageStorage>) {
     let tracker = UsageTracker::new(storage);
 
     let result = tracker.get_usage("nonexistent").await;
@@ -416,3 +530,253 @@ async fn test_account_not_found() {
         UsageError::AccountNotFound(_)
     ));
 }
+
+async fn test_snapshot_byte_hours_accrue_only_while_stored(storage: Arc<dyn UsageStorage>) {
+    storage
+        .create_account("test".to_string(), Tier::Developer)
+        .await
+        .unwrap();
+
+    let tracker = UsageTracker::new(storage.clone());
+    let created = Utc::now();
+
+    tracker
+        .record_stored("test", StoredKind::Snapshot, GIB as i64, created)
+        .await
+        .unwrap();
+    tracker
+        .record_stored(
+            "test",
+            StoredKind::Snapshot,
+            -(GIB as i64),
+            created + Duration::hours(2),
+        )
+        .await
+        .unwrap();
+
+    // Three more hours with nothing stored add nothing
+    let breakdown = tracker
+        .usage_breakdown("test", created + Duration::hours(5))
+        .await
+        .unwrap();
+    let byte_hours = breakdown.get("storage_byte_hours").unwrap();
+    assert_eq!(byte_hours.used, 2.0 * GIB as f64);
+    assert_eq!(byte_hours.limit, Some(7_200.0 * GIB as f64));
+    assert_eq!(breakdown.get("snapshot_bytes").unwrap().used, 0.0);
+}
+
+async fn test_artifact_quota_leaves_executions_alone(storage: Arc<dyn UsageStorage>) {
+    storage
+        .create_account("test".to_string(), Tier::Developer)
+        .await
+        .unwrap();
+
+    let tracker = UsageTracker::new(storage.clone());
+    let now = Utc::now();
+
+    // Developer tier allows 10 GB of artifacts
+    tracker
+        .record_stored("test", StoredKind::Artifact, 10 * GIB as i64 - 100, now)
+        .await
+        .unwrap();
+    assert!(tracker
+        .check_storage("test", StoredKind::Artifact, 100, now)
+        .await
+        .is_ok());
+
+    let result = tracker
+        .check_storage("test", StoredKind::Artifact, 101, now)
+        .await;
+    assert!(matches!(
+        result.unwrap_err(),
+        UsageError::LimitExceeded { .. }
+    ));
+
+    // Snapshots draw on the wider storage limit, and compute is metered separately
+    assert!(tracker
+        .check_storage("test", StoredKind::Snapshot, GIB, now)
+        .await
+        .is_ok());
+    assert!(tracker.check_limits("test", 2, 4).await.is_ok());
+    let execution = ExecutionRecord {
+        execution_id: "exec-789".to_string(),
+        account_id: "test".to_string(),
+        vcpu_seconds: 60.0,
+        ram_gb_seconds: 240.0,
+        mode: "ephemeral".to_string(),
+        timestamp: now,
+        duration_ms: 60000,
+    };
+    tracker.record_execution(execution).await.unwrap();
+
+    let breakdown = tracker.usage_breakdown("test", now).await.unwrap();
+    assert_eq!(
+        breakdown.get("artifact_bytes").unwrap().used,
+        (10 * GIB - 100) as f64
+    );
+    assert!(breakdown.get("mcus").unwrap().used > 0.0);
+}
+
+async fn test_measured_usage_is_kept_apart_from_billing(storage: Arc<dyn UsageStorage>) {
+    storage
+        .create_account("test".to_string(), Tier::Team)
+        .await
+        .unwrap();
+    let tracker = UsageTracker::new(storage.clone());
+
+    // Two CPU-hours outweigh 4 GiB held for an hour
+    let usage = ExecutionUsage {
+        wall_time_ms: 3_600_000,
+        cpu_time_ms: 7_200_000,
+        peak_memory_bytes: 4 * GIB,
+        stdout_bytes: 512,
+    };
+    let mcus = tracker.record("test", &usage).await.unwrap();
+    // Team takes 20% off
+    assert!((mcus - 1.6).abs() < 1e-9, "{mcus}");
+    assert_eq!(UsageTracker::execution_mcus(Tier::Developer, &usage), 0.0);
+
+    let breakdown = tracker.usage_breakdown("test", Utc::now()).await.unwrap();
+    assert_eq!(breakdown.get("cpu_seconds").unwrap().used, 7200.0);
+    assert_eq!(breakdown.get("stdout_bytes").unwrap().used, 512.0);
+    assert!((breakdown.get("measured_mcus").unwrap().used - 1.6).abs() < 1e-9);
+    assert_eq!(breakdown.get("mcus").unwrap().used, 0.0);
+}
+
+async fn test_concurrent_trackers_do_not_lose_updates(storage: Arc<dyn UsageStorage>) {
+    storage
+        .create_account("test".to_string(), Tier::Team)
+        .await
+        .unwrap();
+    // Two gateways sharing one store
+    let trackers = [
+        Arc::new(UsageTracker::new(storage.clone())),
+        Arc::new(UsageTracker::new(storage.clone())),
+    ];
+    let now = Utc::now();
+
+    let writers: Vec<_> = (0..20)
+        .map(|i| {
+            let tracker = trackers[i % 2].clone();
+            tokio::spawn(async move {
+                let execution = ExecutionRecord {
+                    execution_id: format!("exec-{i}"),
+                    account_id: "test".to_string(),
+                    vcpu_seconds: 1800.0,
+                    ram_gb_seconds: 0.0,
+                    mode: "ephemeral".to_string(),
+                    timestamp: now,
+                    duration_ms: 1_800_000,
+                };
+                tracker.record_execution(execution).await.unwrap();
+                tracker.record_egress("test", 1000).await.unwrap();
+                tracker
+                    .record_stored("test", StoredKind::Log, 10, now)
+                    .await
+                    .unwrap();
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap();
+    }
+
+    let usage = trackers[0].get_usage("test").await.unwrap();
+    assert_eq!(usage.usage.vcpu_hours, 10.0);
+    assert_eq!(usage.mcus_consumed, 10.0);
+    assert_eq!(usage.usage.egress_bytes, 20_000);
+    assert_eq!(usage.usage.log_bytes, 200);
+}
+
+async fn test_periods_roll_over_with_fresh_credits(storage: Arc<dyn UsageStorage>) {
+    storage
+        .create_account("test".to_string(), Tier::Developer)
+        .await
+        .unwrap();
+    let tracker = UsageTracker::new(storage.clone());
+    let opened = tracker.get_usage("test").await.unwrap();
+
+    // All 300 MCUs used, and a snapshot held from the start
+    let execution = ExecutionRecord {
+        execution_id: "exec-exhaust".to_string(),
+        account_id: "test".to_string(),
+        vcpu_seconds: 1080000.0,
+        ram_gb_seconds: 0.0,
+        mode: "persistent".to_string(),
+        timestamp: Utc::now(),
+        duration_ms: 1080000000,
+    };
+    tracker.record_execution(execution).await.unwrap();
+    tracker
+        .record_stored(
+            "test",
+            StoredKind::Snapshot,
+            GIB as i64,
+            opened.billing_period_start,
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        tracker.check_limits("test", 1, 1).await,
+        Err(UsageError::LimitExceeded {
+            limit: Limit::Credits,
+            ..
+        })
+    ));
+
+    let first_end = opened.billing_period_end;
+    assert_eq!(
+        tracker
+            .roll_over(first_end - Duration::seconds(1))
+            .await
+            .unwrap(),
+        0
+    );
+    // Two periods went by
+    let second_end = first_end + Duration::days(BILLING_PERIOD_DAYS);
+    assert_eq!(tracker.roll_over(second_end).await.unwrap(), 2);
+    assert_eq!(tracker.roll_over(second_end).await.unwrap(), 0);
+
+    let account = tracker.get_usage("test").await.unwrap();
+    assert_eq!(account.mcus_consumed, 0.0);
+    assert_eq!(account.mcus_allocated, 300.0);
+    assert!(account.billing_period_start > first_end);
+    assert_eq!(account.usage.snapshot_bytes, GIB);
+    assert!(tracker.check_limits("test", 1, 1).await.is_ok());
+
+    let history = storage
+        .get_usage_history(
+            "test",
+            opened.billing_period_start,
+            second_end + Duration::days(1),
+        )
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[0].mcus_consumed, 300.0);
+    assert_eq!(history[1].mcus_consumed, 0.0);
+    // The snapshot was held all through the second period
+    assert_eq!(
+        history[1].usage.storage_byte_hours,
+        (BILLING_PERIOD_DAYS * 24) as f64 * GIB as f64
+    );
+    assert_eq!(history[2].usage.storage_byte_hours, 0.0);
+}
+
+async fn test_accounts_are_created_once(storage: Arc<dyn UsageStorage>) {
+    storage
+        .create_account("test".to_string(), Tier::Team)
+        .await
+        .unwrap();
+    let tracker = UsageTracker::new(storage.clone());
+    tracker.record_egress("test", 512).await.unwrap();
+
+    // Another gateway opening the same account leaves it as it is
+    storage
+        .create_account("test".to_string(), Tier::Developer)
+        .await
+        .unwrap();
+    let account = tracker.get_usage("test").await.unwrap();
+    assert_eq!(account.tier, Tier::Team);
+    assert_eq!(account.usage.egress_bytes, 512);
+}
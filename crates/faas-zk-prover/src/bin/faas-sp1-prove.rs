@@ -0,0 +1,52 @@
+//! Entrypoint of the SP1 prover image
+//!
+//! Reads a `FaasProveRequest` on stdin and the program's ELF from the working directory,
+//! proves it with PLONK and prints the proof line `ZkProvingService::prove_sp1_faas`
+//! looks for. Failures go to stderr with a non-zero exit, which the caller reports.
+
+use faas_zkvm::faas::{FaasProveOutput, FaasProveRequest, ELF_FILE};
+use sp1_sdk::{ProverClient, SP1Stdin};
+use std::io::Read;
+use std::time::Instant;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("faas-sp1-prove: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = Vec::new();
+    std::io::stdin().read_to_end(&mut input)?;
+    let request: FaasProveRequest = serde_json::from_slice(&input)?;
+
+    let elf = std::fs::read(ELF_FILE).map_err(|e| format!("reading {ELF_FILE}: {e}"))?;
+    let hash = faas_common::hash::sha256_hex(&elf);
+    if hash != request.program_hash {
+        return Err(format!(
+            "{ELF_FILE} hashes to {hash}, not {} of {}",
+            request.program_hash, request.program
+        )
+        .into());
+    }
+    let stdin = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &request.stdin)?;
+    let stdin: SP1Stdin = bincode::deserialize(&stdin)?;
+
+    eprintln!("proving {} ({})", request.program, request.program_hash);
+    let start = Instant::now();
+    let client = ProverClient::from_env();
+    let (pk, vk) = client.setup(&elf);
+    let proof = client.prove(&pk, &stdin).plonk().run()?;
+    client.verify(&proof, &vk)?;
+
+    let output = FaasProveOutput {
+        proof_data: base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            bincode::serialize(&proof)?,
+        ),
+        proving_time_ms: start.elapsed().as_millis() as u64,
+    };
+    println!("{}", output.to_line());
+    Ok(())
+}
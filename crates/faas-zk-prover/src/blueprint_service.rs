@@ -36,7 +36,7 @@
 use faas_zkvm::{ZkBackend, ProgramRegistry};
 use crate::ZkProvingService;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 /// Job identifiers matching the Blueprint smart contract
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -85,21 +85,21 @@ pub struct JobResult {
 
 /// Blueprint service manager for ZK-FaaS
 pub struct BlueprintServiceManager {
-    /// Guest program registry (wrapped in Mutex for interior mutability)
-    registry: Arc<Mutex<ProgramRegistry>>,
+    /// Guest program registry, downloading ELFs by IPFS CID when a gateway is configured
+    registry: Arc<ProgramRegistry>,
     /// SP1 proving service
     sp1_service: Arc<ZkProvingService>,
 }
 
 impl BlueprintServiceManager {
     pub fn new(faas_url: String) -> Self {
-        let registry = Arc::new(Mutex::new(ProgramRegistry::new()));
+        let registry = Arc::new(crate::programs::with_fetcher_from_env(ProgramRegistry::new()));
 
         // Create SP1 local proving service
-        let sp1_service = Arc::new(ZkProvingService::new(
-            faas_url.clone(),
-            ZkBackend::Sp1Local,
-        ));
+        let sp1_service = Arc::new(
+            ZkProvingService::new(faas_url.clone(), ZkBackend::Sp1Local)
+                .with_program_registry(registry.clone()),
+        );
 
         Self {
             registry,
@@ -152,7 +152,7 @@ impl BlueprintServiceManager {
     /// - uint8: zkvmType (0=SP1, 1=RISCZero)
     ///
     /// Outputs (ABI-encoded):
-    /// - bool: verified (whether ELF was verified)
+    /// - bool: verified (whether the ELF was fetched and hashes to elfHash)
     async fn job_register_program(&self, inputs: &[u8]) -> Result<Vec<u8>, String> {
         println!("  → Job 0: Register ZK Program");
 
@@ -173,8 +173,6 @@ impl BlueprintServiceManager {
         println!("  🔗 IPFS CID: {}", ipfs_cid);
         println!("  🔑 ELF Hash: {}", &elf_hash[..16]);
 
-        // In production: Fetch ELF from IPFS and verify hash
-        // For now, just register metadata in local registry
         use faas_zkvm::ProgramMetadata;
         let metadata = ProgramMetadata {
             program_hash: elf_hash.to_string(),
@@ -186,15 +184,26 @@ impl BlueprintServiceManager {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            name: None,
+            size_bytes: None,
         };
 
         // Register in local registry (cache)
-        self.registry.lock().unwrap().register(metadata)?;
+        self.registry.register(metadata)?;
+
+        // Fetch the ELF from IPFS now so a CID that doesn't hold it is caught at registration
+        let verified = match self.registry.resolve(elf_hash).await {
+            Ok(_) => true,
+            Err(e) => {
+                println!("  ⚠️  ELF not verified: {}", e);
+                false
+            }
+        };
 
         println!("  ✅ Program registered: {}", &elf_hash[..16]);
 
         // Return verification result (ABI-encoded bool)
-        Ok(vec![1]) // true
+        Ok(vec![verified as u8])
     }
 
     /// Job 1: Generate Proof (SP1)
@@ -231,20 +240,18 @@ impl BlueprintServiceManager {
             .map(|s| s.to_string())
             .collect();
 
-        // Get program from registry
-        let program_name = {
-            let registry = self.registry.lock().unwrap();
-            registry
-                .get(program_hash)
-                .ok_or("Program not found")?
-                .description
-                .clone()
+        // Get program from registry: by hash when its ELF can be had, otherwise a built-in
+        // named by the description
+        let metadata = self.registry.get(program_hash).ok_or("Program not found")?;
+        let program = match self.registry.resolve(program_hash).await {
+            Ok(_) => metadata.program_hash,
+            Err(_) => metadata.description,
         };
 
         // Generate proof using SP1
         let proof = self
             .sp1_service
-            .prove(&program_name, public_inputs, vec![])
+            .prove(&program, public_inputs, vec![])
             .await
             .map_err(|e| format!("Proof generation failed: {}", e))?;
 
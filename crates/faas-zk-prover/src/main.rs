@@ -50,23 +50,78 @@
 //! ```
 
 mod blueprint_service;
+mod programs;
+mod sp1_verifier;
 
-use faas_sdk::FaasClient;
+use faas_sdk::{ExecuteRequest, FaasClient};
+use futures::TryStreamExt;
 // Import types from faas-zkvm library
-use faas_zkvm::{ZkBackend, ZkProof};
+use faas_zkvm::faas::{FaasProveOutput, FaasProveRequest, ELF_FILE};
+use faas_zkvm::{
+    verify_proof, CacheStats, ProgramMetadata, ProofCache, ProofJob, ProofJobStore,
+    ProofResponse, RegisterProgramRequest, RegistryError, Verification, Verifiers, VerifyError,
+    ZkBackend, ZkProof,
+};
 pub use blueprint_service::{BlueprintServiceManager, JobId, JobRequest, JobResult};
 use sha2::{Digest, Sha256};
-use std::time::Instant;
-use sp1_sdk::{include_elf, ProverClient, SP1Stdin, SP1ProofWithPublicValues};
+use programs::{Program, SharedRegistry};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sp1_sdk::{ProverClient, SP1Stdin, SP1ProofWithPublicValues};
+
+/// Prover image for `Sp1FaaS`, unless `FAAS_ZK_SP1_IMAGE` names another; built from
+/// `Dockerfile.sp1-prover`
+const SP1_FAAS_IMAGE: &str = "ghcr.io/tangle-network/faas-sp1-prover:latest";
+/// The image's entrypoint, `src/bin/faas-sp1-prove.rs`
+const SP1_FAAS_COMMAND: &str = "faas-sp1-prove";
+/// PLONK wrapping needs far more than the gateway's default
+const SP1_FAAS_MEMORY_MB: u32 = 32 * 1024;
+const SP1_FAAS_CPU_CORES: u8 = 8;
+const SP1_FAAS_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const SP1_FAAS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+fn sp1_faas_image() -> String {
+    std::env::var("FAAS_ZK_SP1_IMAGE").unwrap_or_else(|_| SP1_FAAS_IMAGE.to_string())
+}
+
+/// The guest program's stdin. Built-ins read typed inputs; uploaded programs read each
+/// public input and then each private input as a `String`.
+fn sp1_stdin(
+    program: &Program,
+    public_inputs: &[String],
+    private_inputs: &[String],
+) -> Result<SP1Stdin, Box<dyn std::error::Error>> {
+    let mut stdin = SP1Stdin::new();
+
+    if !program.builtin {
+        for input in public_inputs.iter().chain(private_inputs) {
+            stdin.write(input);
+        }
+    } else if program.id == "fibonacci" {
+        let n: u32 = public_inputs.first()
+            .ok_or("Missing input")?
+            .parse()?;
+        stdin.write(&n);
+    } else if program.id == "hash_preimage" {
+        let preimage = public_inputs.first()
+            .ok_or("Missing preimage")?;
+        stdin.write(&preimage.as_bytes().to_vec());
 
-// Include generated ELF binaries from guest programs
-const FIBONACCI_ELF: &[u8] = include_elf!("fibonacci-guest");
-const HASH_PREIMAGE_ELF: &[u8] = include_elf!("hash-preimage-guest");
+        let mut hasher = Sha256::new();
+        hasher.update(preimage.as_bytes());
+        let expected_hash: [u8; 32] = hasher.finalize().into();
+        stdin.write(&expected_hash);
+    }
+
+    Ok(stdin)
+}
 
 pub struct ZkProvingService {
     faas_client: FaasClient,
     backend: ZkBackend,
     use_cache: bool,
+    cache: Option<Arc<ProofCache>>,
+    programs: Option<SharedRegistry>,
 }
 
 impl ZkProvingService {
@@ -76,9 +131,32 @@ impl ZkProvingService {
             faas_client: FaasClient::new(faas_url),
             backend,
             use_cache: true,
+            cache: None,
+            programs: None,
         }
     }
 
+    /// Prove uploaded programs from `programs` as well as the built-ins
+    pub fn with_program_registry(mut self, programs: SharedRegistry) -> Self {
+        self.programs = Some(programs);
+        self
+    }
+
+    async fn program(&self, program: &str) -> Result<Program, RegistryError> {
+        programs::resolve(self.programs.as_deref(), program).await
+    }
+
+    /// Keep proofs in `cache`, which services proving on other requests can share
+    pub fn with_proof_cache(mut self, cache: Arc<ProofCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Hits, misses and evictions of the proof cache; `None` without one
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
     /// Disable proof caching (force fresh proof generation)
     pub fn with_caching(mut self, enabled: bool) -> Self {
         self.use_cache = enabled;
@@ -90,18 +168,46 @@ impl ZkProvingService {
         let mut hasher = Sha256::new();
         hasher.update(program.as_bytes());
         for input in public_inputs {
+            // Length first, so ["1", "0"] and ["10"] don't share a cached proof
+            hasher.update((input.len() as u64).to_le_bytes());
             hasher.update(input.as_bytes());
         }
         hasher.update(format!("{:?}", self.backend).as_bytes());
         format!("zkproof_{:x}", hasher.finalize())
     }
 
-    /// Generate proof using configured backend
+    /// Generate proof using configured backend, or answer from the proof cache when the
+    /// same program and public inputs were proven before
     pub async fn prove(
         &self,
         program: &str,
         public_inputs: Vec<String>,
         private_inputs: Vec<String>,
+    ) -> Result<ZkProof, Box<dyn std::error::Error>> {
+        match self.cache.as_ref().filter(|_| self.use_cache) {
+            Some(cache) => {
+                // An uploaded program is keyed by its hash, so uploading a new build under
+                // the same name doesn't answer with the old build's proof
+                let id = programs::program_id(self.programs.as_deref(), program);
+                let key = self.cache_key(&id, &public_inputs);
+                cache
+                    .get_or_prove(&key, || {
+                        self.prove_with_backend(program, public_inputs, private_inputs)
+                    })
+                    .await
+            }
+            None => {
+                self.prove_with_backend(program, public_inputs, private_inputs)
+                    .await
+            }
+        }
+    }
+
+    async fn prove_with_backend(
+        &self,
+        program: &str,
+        public_inputs: Vec<String>,
+        private_inputs: Vec<String>,
     ) -> Result<ZkProof, Box<dyn std::error::Error>> {
         match &self.backend {
             ZkBackend::Sp1Local => {
@@ -129,39 +235,18 @@ impl ZkProvingService {
         &self,
         program: &str,
         public_inputs: Vec<String>,
-        _private_inputs: Vec<String>,
+        private_inputs: Vec<String>,
     ) -> Result<ZkProof, Box<dyn std::error::Error>> {
         println!("  → Using SP1 Local Prover");
 
         let start = Instant::now();
-        let elf = match program {
-            "fibonacci" => FIBONACCI_ELF,
-            "hash_preimage" => HASH_PREIMAGE_ELF,
-            _ => return Err(format!("Unknown program: {}", program).into()),
-        };
+        let resolved = self.program(program).await?;
 
-        // Prepare inputs
-        let mut stdin = SP1Stdin::new();
-
-        if program == "fibonacci" {
-            let n: u32 = public_inputs.first()
-                .ok_or("Missing input")?
-                .parse()?;
-            stdin.write(&n);
-        } else if program == "hash_preimage" {
-            let preimage = public_inputs.first()
-                .ok_or("Missing preimage")?;
-            stdin.write(&preimage.as_bytes().to_vec());
-
-            let mut hasher = Sha256::new();
-            hasher.update(preimage.as_bytes());
-            let expected_hash: [u8; 32] = hasher.finalize().into();
-            stdin.write(&expected_hash);
-        }
+        let stdin = sp1_stdin(&resolved, &public_inputs, &private_inputs)?;
 
         // Generate proof
         let client = ProverClient::from_env();
-        let (pk, vk) = client.setup(elf);
+        let (pk, vk) = client.setup(&resolved.elf);
         let proof: SP1ProofWithPublicValues = client
             .prove(&pk, &stdin)
             .plonk()
@@ -173,24 +258,110 @@ impl ZkProvingService {
         let elapsed = start.elapsed().as_millis() as u64;
         println!("  ✅ Proof generated and verified in {}ms", elapsed);
 
+        // The whole proof with its public values, so /v1/verify can check it later
+        let proof_data = bincode::serialize(&proof)?;
         Ok(ZkProof {
-            proof_id: format!("{:x}", md5::compute(&proof.bytes())),
+            proof_id: faas_zkvm::proof_id(&proof_data),
             program: program.to_string(),
             public_inputs,
-            proof_data: proof.bytes().to_vec(),
+            proof_data,
             backend: "SP1 Local".to_string(),
             proving_time_ms: elapsed,
             execution_mode: "local".to_string(),
+            from_cache: false,
         })
     }
 
+    /// Prove in the SP1 prover image on the FaaS platform
+    ///
+    /// Runs as a background job rather than through `execute`, whose HTTP request would
+    /// time out long before a PLONK proof is done. A proof too big for the gateway's
+    /// output cap is read from the execution's full logs instead of its stdout.
     async fn prove_sp1_faas(
         &self,
-        _program: &str,
-        _public_inputs: Vec<String>,
-        _private_inputs: Vec<String>,
+        program: &str,
+        public_inputs: Vec<String>,
+        private_inputs: Vec<String>,
     ) -> Result<ZkProof, Box<dyn std::error::Error>> {
-        Err("SP1 FaaS proving not yet implemented - use Sp1Local or Sp1Network".into())
+        println!("  → Using SP1 prover on FaaS");
+
+        let start = Instant::now();
+        let resolved = self.program(program).await?;
+        let stdin = sp1_stdin(&resolved, &public_inputs, &private_inputs)?;
+        let elf = resolved.elf;
+        let request = FaasProveRequest {
+            program: program.to_string(),
+            program_hash: faas_common::hash::sha256_hex(&elf),
+            stdin: base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                bincode::serialize(&stdin)?,
+            ),
+        };
+
+        let job = self
+            .faas_client
+            .submit_job(ExecuteRequest {
+                command: SP1_FAAS_COMMAND.to_string(),
+                image: Some(sp1_faas_image()),
+                working_dir: Some("/work".to_string()),
+                input_files: Some(vec![(ELF_FILE.to_string(), elf.to_vec())]),
+                payload: Some(serde_json::to_vec(&request)?),
+                memory_mb: Some(SP1_FAAS_MEMORY_MB),
+                cpu_cores: Some(SP1_FAAS_CPU_CORES),
+                timeout_ms: Some(SP1_FAAS_TIMEOUT.as_millis() as u64),
+                ..Default::default()
+            })
+            .await?;
+        let job = self
+            .faas_client
+            .wait_for_job(&job.job_id, SP1_FAAS_POLL_INTERVAL)
+            .await?;
+
+        let Some(response) = job.response else {
+            let reason = job.error.map_or_else(|| format!("{:?}", job.status), |e| e.message);
+            return Err(format!("SP1 prover job {} failed: {}", job.job_id, reason).into());
+        };
+        if response.exit_code != 0 {
+            return Err(format!(
+                "SP1 prover exited with {}: {}",
+                response.exit_code,
+                response.stderr.trim()
+            )
+            .into());
+        }
+
+        let output = match FaasProveOutput::from_output(&response.stdout) {
+            Some(output) => output,
+            None if response.truncated => {
+                let logs = self.faas_client.stream_logs(&response.request_id).await?;
+                let logs: Vec<_> = logs.try_collect().await?;
+                FaasProveOutput::from_output(&String::from_utf8_lossy(&logs.concat()))
+                    .ok_or("SP1 prover logs carry no proof")?
+            }
+            None => return Err(format!(
+                "SP1 prover printed no proof: {}",
+                response.stderr.trim()
+            )
+            .into()),
+        };
+        let proof_data = output.proof_bytes().ok_or("SP1 prover sent invalid base64")?;
+
+        let elapsed = start.elapsed().as_millis() as u64;
+        println!(
+            "  ✅ Proof generated on FaaS in {}ms ({}ms proving)",
+            elapsed, output.proving_time_ms
+        );
+
+        Ok(ZkProof {
+            proof_id: faas_zkvm::proof_id(&proof_data),
+            program: program.to_string(),
+            public_inputs,
+            proof_data,
+            backend: "SP1 FaaS".to_string(),
+            proving_time_ms: elapsed,
+            execution_mode: "faas-docker".to_string(),
+            from_cache: false,
+        })
     }
 
     async fn prove_risczero_local(
@@ -253,9 +424,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_env_filter("info")
         .init();
 
+    // Fail at boot on a program directory that was tampered with
+    let programs: SharedRegistry = Arc::new(programs::registry_from_env()?);
+    let max_program_bytes = programs::max_program_bytes();
+    let state = AppState {
+        jobs: Arc::new(ProofJobStore::from_env()),
+        cache: Arc::new(ProofCache::from_env()),
+        verifiers: Verifiers::new().with(
+            "sp1",
+            Arc::new(sp1_verifier::Sp1Verifier::new(programs.clone())),
+        ),
+        programs,
+        max_program_bytes,
+    };
     let app = axum::Router::new()
         .route("/v1/prove", axum::routing::post(prove_handler))
-        .route("/health", axum::routing::get(health_handler));
+        .route("/v1/proofs/:id", axum::routing::get(proof_status_handler))
+        .route("/v1/verify", axum::routing::post(verify_handler))
+        .route(
+            "/v1/programs",
+            axum::routing::post(register_program_handler)
+                .get(list_programs_handler)
+                // base64 takes 4 bytes for every 3, plus room for the rest of the JSON
+                .layer(axum::extract::DefaultBodyLimit::max(
+                    max_program_bytes / 3 * 4 + 64 * 1024,
+                )),
+        )
+        .route("/v1/programs/:hash", axum::routing::get(program_handler))
+        .route("/v1/cache", axum::routing::get(cache_stats_handler))
+        .route("/health", axum::routing::get(health_handler))
+        .with_state(state);
 
     let addr = "0.0.0.0:8081";
     tracing::info!("🔐 faas-zk-prover starting on {}", addr);
@@ -266,52 +464,218 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[derive(Clone)]
+struct AppState {
+    jobs: Arc<ProofJobStore>,
+    cache: Arc<ProofCache>,
+    verifiers: Verifiers,
+    programs: SharedRegistry,
+    max_program_bytes: usize,
+}
+
 #[derive(serde::Deserialize)]
 struct ProveRequest {
     program: String,
     public_inputs: Vec<String>,
     #[serde(default)]
     private_inputs: Vec<String>,
-}
-
-#[derive(serde::Serialize)]
-struct ProveResponse {
-    proof_id: String,
-    program: String,
-    public_inputs: Vec<String>,
-    proof_data: String,  // base64 encoded
-    backend: String,
-    proving_time_ms: u64,
+    /// Answer with a proof job right away instead of holding the request open
+    #[serde(default, rename = "async")]
+    run_async: bool,
 }
 
 async fn prove_handler(
+    axum::extract::State(state): axum::extract::State<AppState>,
     axum::Json(req): axum::Json<ProveRequest>,
-) -> Result<axum::Json<ProveResponse>, (axum::http::StatusCode, String)> {
+) -> Result<axum::response::Response, (axum::http::StatusCode, String)> {
+    use axum::response::IntoResponse;
+
     tracing::info!("Proving request for program: {}", req.program);
 
-    let service = ZkProvingService::new("".to_string(), ZkBackend::Sp1Local);
+    // Downloads a program registered by CID now, so a bad download fails the request
+    if let Err(e) = programs::resolve(Some(state.programs.as_ref()), &req.program).await {
+        let status = match e {
+            RegistryError::NotFound(_) => axum::http::StatusCode::NOT_FOUND,
+            _ => axum::http::StatusCode::BAD_GATEWAY,
+        };
+        return Err((status, e.to_string()));
+    }
+
+    if req.run_async {
+        let job = state.jobs.submit(&req.program).map_err(|e| (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            e.to_string(),
+        ))?;
+        tokio::spawn(run_proof_job(state, job.proof_job_id.clone(), req));
+        return Ok((axum::http::StatusCode::ACCEPTED, axum::Json(job)).into_response());
+    }
+
+    let proof = prove(state, req).await.map_err(|e| (
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Proving failed: {}", e),
+    ))?;
+
+    Ok(axum::Json(ProofResponse::from(&proof)).into_response())
+}
+
+/// Proves with the SP1 image on the FaaS gateway at `FAAS_GATEWAY_URL` when it is set,
+/// and locally otherwise
+fn proving_service(state: AppState) -> ZkProvingService {
+    let service = match std::env::var("FAAS_GATEWAY_URL") {
+        Ok(url) if !url.is_empty() => ZkProvingService::new(url, ZkBackend::Sp1FaaS),
+        _ => ZkProvingService::new("".to_string(), ZkBackend::Sp1Local),
+    };
+    service
+        .with_proof_cache(state.cache)
+        .with_program_registry(state.programs)
+}
 
-    let proof = service
-        .prove(&req.program, req.public_inputs.clone(), req.private_inputs)
+/// Prove on a blocking thread; SP1 keeps a core busy for the whole proof
+async fn prove(state: AppState, req: ProveRequest) -> Result<ZkProof, String> {
+    let handle = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let service = proving_service(state);
+        handle
+            .block_on(service.prove(&req.program, req.public_inputs, req.private_inputs))
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("prover task ended: {}", e))?
+}
+
+async fn run_proof_job(state: AppState, id: String, req: ProveRequest) {
+    let jobs = state.jobs.clone();
+    jobs.start(&id);
+    let result = prove(state, req).await;
+    match &result {
+        Ok(proof) => tracing::info!("Proof job {} done in {}ms", id, proof.proving_time_ms),
+        Err(e) => tracing::warn!("Proof job {} failed: {}", id, e),
+    }
+    if let Err(e) = jobs.finish(&id, result) {
+        tracing::warn!("Could not write proof job {} to disk: {}", id, e);
+    }
+}
+
+async fn proof_status_handler(
+    axum::extract::State(AppState { jobs, .. }): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<axum::Json<ProofJob>, axum::http::StatusCode> {
+    jobs.get(&id)
+        .map(axum::Json)
+        .ok_or(axum::http::StatusCode::NOT_FOUND)
+}
+
+async fn verify_handler(
+    axum::extract::State(AppState { verifiers, programs, .. }): axum::extract::State<AppState>,
+    axum::Json(req): axum::Json<ProofResponse>,
+) -> Result<axum::Json<Verification>, (axum::http::StatusCode, String)> {
+    let proof = req.into_proof("remote").ok_or((
+        axum::http::StatusCode::BAD_REQUEST,
+        "Invalid base64 proof data".to_string(),
+    ))?;
+    tracing::info!("Verifying {} proof for program: {}", proof.backend, proof.program);
+
+    // The verifier only looks at ELFs already here; download one registered by CID first.
+    // A program that can't be had is the verifier's not found.
+    let _ = programs.resolve(&proof.program).await;
+
+    // Setting up a verifying key runs the program's ELF through SP1; keep it off the runtime
+    let verification = tokio::task::spawn_blocking(move || verify_proof(&proof, &verifiers))
         .await
         .map_err(|e| (
             axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Proving failed: {}", e),
+            format!("verifier task ended: {}", e),
         ))?;
 
-    Ok(axum::Json(ProveResponse {
-        proof_id: proof.proof_id,
-        program: proof.program,
-        public_inputs: proof.public_inputs,
-        proof_data: base64::Engine::encode(
-            &base64::engine::general_purpose::STANDARD,
-            &proof.proof_data,
-        ),
-        backend: proof.backend,
-        proving_time_ms: proof.proving_time_ms,
-    }))
+    verification.map(axum::Json).map_err(|e| {
+        let status = match e {
+            VerifyError::NotFound(_) => axum::http::StatusCode::NOT_FOUND,
+            VerifyError::UnsupportedBackend(_) => axum::http::StatusCode::BAD_REQUEST,
+            VerifyError::Verifier(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, e.to_string())
+    })
+}
+
+async fn register_program_handler(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::Json(req): axum::Json<RegisterProgramRequest>,
+) -> Result<(axum::http::StatusCode, axum::Json<ProgramMetadata>), (axum::http::StatusCode, String)> {
+    let elf = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &req.elf)
+        .map_err(|e| (axum::http::StatusCode::BAD_REQUEST, format!("Invalid base64 ELF: {}", e)))?;
+    if elf.len() > state.max_program_bytes {
+        return Err((
+            axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+            format!("ELF is {} bytes, over the {} byte limit", elf.len(), state.max_program_bytes),
+        ));
+    }
+
+    let registered = match &req.program_hash {
+        Some(hash) => state.programs.register_elf_verified(&req.name, elf, hash),
+        None => state.programs.register_elf(&req.name, elf),
+    };
+    let metadata = registered.map_err(|e| {
+        let status = match e {
+            RegistryError::Io(_) | RegistryError::Metadata(_) => {
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+            _ => axum::http::StatusCode::BAD_REQUEST,
+        };
+        (status, e.to_string())
+    })?;
+    tracing::info!("Registered program {} as {}", req.name, metadata.program_hash);
+    Ok((axum::http::StatusCode::CREATED, axum::Json(metadata)))
+}
+
+async fn list_programs_handler(
+    axum::extract::State(AppState { programs, .. }): axum::extract::State<AppState>,
+) -> axum::Json<Vec<ProgramMetadata>> {
+    axum::Json(programs.list())
+}
+
+async fn program_handler(
+    axum::extract::State(AppState { programs, .. }): axum::extract::State<AppState>,
+    axum::extract::Path(hash): axum::extract::Path<String>,
+) -> Result<axum::Json<ProgramMetadata>, axum::http::StatusCode> {
+    programs
+        .get(&hash)
+        .map(axum::Json)
+        .ok_or(axum::http::StatusCode::NOT_FOUND)
+}
+
+async fn cache_stats_handler(
+    axum::extract::State(AppState { cache, .. }): axum::extract::State<AppState>,
+) -> axum::Json<CacheStats> {
+    axum::Json(cache.stats())
 }
 
 async fn health_handler() -> &'static str {
     "ok"
 }
+
+/// Needs a gateway that can pull the SP1 prover image, at `FAAS_GATEWAY_URL` or
+/// `http://localhost:8080`: `cargo test --release --features faas-e2e`
+#[cfg(all(test, feature = "faas-e2e"))]
+mod faas_e2e {
+    use super::*;
+
+    #[tokio::test]
+    async fn fibonacci_is_proven_through_the_gateway() {
+        let gateway = std::env::var("FAAS_GATEWAY_URL")
+            .unwrap_or_else(|_| "http://localhost:8080".to_string());
+        let service = ZkProvingService::new(gateway, ZkBackend::Sp1FaaS);
+
+        let proof = service
+            .prove("fibonacci", vec!["10".to_string()], vec![])
+            .await
+            .expect("proving on FaaS failed");
+        assert_eq!(proof.execution_mode, "faas-docker");
+        assert_eq!(proof.backend, "SP1 FaaS");
+        assert_eq!(proof.proof_id, faas_zkvm::proof_id(&proof.proof_data));
+
+        let registry = Arc::new(faas_zkvm::ProgramRegistry::new());
+        let verifiers =
+            Verifiers::new().with("sp1", Arc::new(sp1_verifier::Sp1Verifier::new(registry)));
+        assert!(verify_proof(&proof, &verifiers).unwrap().valid);
+    }
+}
@@ -0,0 +1,134 @@
+//! Guest programs the service proves: registered ones from the registry, then the built-ins
+//!
+//! A name or hash the registry knows wins over a built-in of the same name, so a newer
+//! build of `fibonacci` can be uploaded without recompiling the service. Programs
+//! registered on chain by IPFS CID are downloaded the first time they're proven.
+
+use faas_zkvm::{IpfsGatewayFetcher, ProgramRegistry, RegistryError};
+use sp1_sdk::include_elf;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+// Include generated ELF binaries from guest programs
+const FIBONACCI_ELF: &[u8] = include_elf!("fibonacci-guest");
+const HASH_PREIMAGE_ELF: &[u8] = include_elf!("hash-preimage-guest");
+
+/// Uploaded programs, shared by the handlers and the proving services
+pub type SharedRegistry = Arc<ProgramRegistry>;
+
+/// Largest ELF accepted by `POST /v1/programs` unless `FAAS_ZK_MAX_PROGRAM_BYTES` says otherwise
+pub const DEFAULT_MAX_PROGRAM_BYTES: usize = 32 * 1024 * 1024;
+
+pub struct Program {
+    pub elf: Cow<'static, [u8]>,
+    /// The program hash of a registered program, the name of a built-in
+    pub id: String,
+    /// Built-ins take typed inputs and commit known public values
+    pub builtin: bool,
+}
+
+/// ELF of a guest program compiled into the service
+pub fn builtin_elf(program: &str) -> Option<&'static [u8]> {
+    match program {
+        "fibonacci" => Some(FIBONACCI_ELF),
+        "hash_preimage" => Some(HASH_PREIMAGE_ELF),
+        _ => None,
+    }
+}
+
+/// `program`, a name or hash, from `registry`, downloading it if need be, or else the
+/// built-ins
+pub async fn resolve(
+    registry: Option<&ProgramRegistry>,
+    program: &str,
+) -> Result<Program, RegistryError> {
+    if let Some((registry, hash)) = registered(registry, program) {
+        match registry.resolve(&hash).await {
+            Ok(elf) => return Ok(uploaded(elf, hash)),
+            Err(RegistryError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    builtin(program)
+}
+
+/// [`resolve`] without downloading: programs whose ELF `registry` holds right now
+pub fn resolve_local(
+    registry: Option<&ProgramRegistry>,
+    program: &str,
+) -> Result<Program, RegistryError> {
+    if let Some((registry, hash)) = registered(registry, program) {
+        if let Ok(elf) = registry.elf(&hash) {
+            return Ok(uploaded(elf, hash));
+        }
+    }
+    builtin(program)
+}
+
+/// What [`resolve`] names `program` by, without getting its ELF
+pub fn program_id(registry: Option<&ProgramRegistry>, program: &str) -> String {
+    registered(registry, program)
+        .map(|(_, hash)| hash)
+        .unwrap_or_else(|| program.to_string())
+}
+
+fn registered<'r>(
+    registry: Option<&'r ProgramRegistry>,
+    program: &str,
+) -> Option<(&'r ProgramRegistry, String)> {
+    let registry = registry?;
+    registry.program_hash(program).map(|hash| (registry, hash))
+}
+
+fn uploaded(elf: Vec<u8>, hash: String) -> Program {
+    Program {
+        elf: Cow::Owned(elf),
+        id: hash,
+        builtin: false,
+    }
+}
+
+fn builtin(program: &str) -> Result<Program, RegistryError> {
+    builtin_elf(program)
+        .map(|elf| Program {
+            elf: Cow::Borrowed(elf),
+            id: program.to_string(),
+            builtin: true,
+        })
+        .ok_or_else(|| RegistryError::NotFound(program.to_string()))
+}
+
+/// The registry under `FAAS_ZK_PROGRAM_DIR`, or one kept in memory when it is unset,
+/// fetching programs registered by CID through `FAAS_ZK_IPFS_GATEWAY`
+pub fn registry_from_env() -> Result<ProgramRegistry, RegistryError> {
+    let registry = match std::env::var("FAAS_ZK_PROGRAM_DIR") {
+        Ok(dir) if !dir.is_empty() => ProgramRegistry::open(dir)?,
+        _ => ProgramRegistry::new(),
+    };
+    Ok(with_fetcher_from_env(registry))
+}
+
+/// `registry` downloading through the IPFS gateway at `FAAS_ZK_IPFS_GATEWAY`, keeping
+/// `FAAS_ZK_PROGRAM_CACHE_BYTES` of downloads; unchanged without a gateway
+pub fn with_fetcher_from_env(registry: ProgramRegistry) -> ProgramRegistry {
+    let registry = match std::env::var("FAAS_ZK_IPFS_GATEWAY") {
+        Ok(url) if !url.is_empty() => registry.with_fetcher(Arc::new(
+            IpfsGatewayFetcher::new(url).with_max_bytes(max_program_bytes()),
+        )),
+        _ => registry,
+    };
+    match std::env::var("FAAS_ZK_PROGRAM_CACHE_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        Some(bytes) => registry.with_fetch_budget(bytes),
+        None => registry,
+    }
+}
+
+pub fn max_program_bytes() -> usize {
+    std::env::var("FAAS_ZK_MAX_PROGRAM_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PROGRAM_BYTES)
+}
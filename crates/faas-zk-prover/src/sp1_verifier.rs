@@ -0,0 +1,81 @@
+//! SP1 proof verification against the uploaded and built-in guest programs
+//!
+//! `proof_data` is a bincode-serialized `SP1ProofWithPublicValues`. The verifying key
+//! comes from setting up the program's ELF, which takes a while, so keys are kept once
+//! computed. Besides the SNARK itself, a built-in's committed public values must match
+//! the public inputs the proof claims, or a valid proof for `fibonacci(10)` would pass as
+//! one for `fibonacci(20)`. Uploaded programs commit whatever they like, so for them
+//! only the SNARK is checked.
+
+use crate::programs::{self, SharedRegistry};
+use faas_zkvm::{ProofVerifier, RegistryError, VerifyError, ZkProof};
+use sha2::{Digest, Sha256};
+use sp1_sdk::{ProverClient, SP1ProofWithPublicValues, SP1VerifyingKey};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub struct Sp1Verifier {
+    programs: SharedRegistry,
+    /// By program id: uploaded programs by hash, so a new upload under a name gets its own
+    keys: Mutex<HashMap<String, SP1VerifyingKey>>,
+}
+
+impl Sp1Verifier {
+    pub fn new(programs: SharedRegistry) -> Self {
+        Self {
+            programs,
+            keys: Mutex::default(),
+        }
+    }
+
+    /// The program's verifying key, and whether it is a built-in
+    fn verifying_key(&self, program: &str) -> Result<(SP1VerifyingKey, bool), VerifyError> {
+        let id = programs::program_id(Some(self.programs.as_ref()), program);
+        let builtin = programs::builtin_elf(&id).is_some();
+        if let Some(vk) = self.keys.lock().unwrap().get(&id) {
+            return Ok((vk.clone(), builtin));
+        }
+        let resolved = programs::resolve_local(Some(self.programs.as_ref()), program)
+            .map_err(|e| match e {
+                RegistryError::NotFound(program) => VerifyError::NotFound(program),
+                e => VerifyError::Verifier(e.to_string()),
+            })?;
+        let (_, vk) = ProverClient::from_env().setup(&resolved.elf);
+        self.keys.lock().unwrap().insert(resolved.id, vk.clone());
+        Ok((vk, resolved.builtin))
+    }
+}
+
+impl ProofVerifier for Sp1Verifier {
+    fn verify(&self, proof: &ZkProof) -> Result<bool, VerifyError> {
+        let (vk, builtin) = self.verifying_key(&proof.program)?;
+        let Ok(sp1_proof) = bincode::deserialize::<SP1ProofWithPublicValues>(&proof.proof_data)
+        else {
+            return Ok(false);
+        };
+        if builtin && !commits_to(&proof.program, &sp1_proof, &proof.public_inputs) {
+            return Ok(false);
+        }
+        Ok(ProverClient::from_env().verify(&sp1_proof, &vk).is_ok())
+    }
+}
+
+/// Whether the guest committed the claimed public inputs; see the guest programs for
+/// what each one commits first
+fn commits_to(program: &str, proof: &SP1ProofWithPublicValues, public_inputs: &[String]) -> bool {
+    let committed = proof.public_values.as_slice();
+    let Some(input) = public_inputs.first() else {
+        return false;
+    };
+    match program {
+        "fibonacci" => {
+            let n = bincode::deserialize::<u32>(committed).ok();
+            n.is_some() && input.parse::<u32>().ok() == n
+        }
+        "hash_preimage" => {
+            let expected: [u8; 32] = Sha256::digest(input.as_bytes()).into();
+            bincode::deserialize::<[u8; 32]>(committed).ok() == Some(expected)
+        }
+        _ => false,
+    }
+}
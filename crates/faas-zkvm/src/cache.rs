@@ -0,0 +1,314 @@
+//! Proofs kept for requests that ask for the same proof again
+//!
+//! Proving the same program on the same inputs takes minutes of CPU every time, so
+//! proofs are kept under the proving service's cache key: the most recently used ones in
+//! memory and, with a directory configured, every one on disk as well, where it survives
+//! eviction and restarts. Entries older than the TTL are treated as absent.
+
+use crate::ZkProof;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Proofs kept in memory unless `FAAS_ZK_CACHE_CAPACITY` says otherwise
+pub const DEFAULT_CACHE_CAPACITY: usize = 128;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Unix milliseconds
+    stored_at_ms: u64,
+    proof: ZkProof,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Proofs held in memory
+    pub entries: usize,
+    /// Proofs dropped from memory to make room
+    pub evictions: u64,
+    /// Proofs found but older than the TTL
+    pub expired: u64,
+}
+
+/// LRU of proofs in memory, optionally backed by a directory
+#[derive(Debug)]
+pub struct ProofCache {
+    ttl: Option<Duration>,
+    dir: Option<PathBuf>,
+    entries: Mutex<LruCache<String, Entry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expired: AtomicU64,
+}
+
+impl Default for ProofCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl ProofCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            ttl: None,
+            dir: None,
+            entries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+            )),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+        }
+    }
+
+    /// Treat proofs stored longer than `ttl` ago as absent
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Also write proofs to `dir`, one JSON file each
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// `FAAS_ZK_CACHE_CAPACITY` proofs in memory, kept for `FAAS_ZK_CACHE_TTL_SECS` when it
+    /// is set, and on disk under `FAAS_ZK_CACHE_DIR` when that is
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("FAAS_ZK_CACHE_CAPACITY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_CAPACITY);
+        let mut cache = Self::new(capacity);
+        if let Some(secs) = std::env::var("FAAS_ZK_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            cache = cache.with_ttl(Duration::from_secs(secs));
+        }
+        match std::env::var("FAAS_ZK_CACHE_DIR") {
+            Ok(dir) if !dir.is_empty() => cache.with_dir(dir),
+            _ => cache,
+        }
+    }
+
+    /// The proof stored under `key`, marked `from_cache` and with the lookup as its
+    /// proving time
+    pub fn get(&self, key: &str) -> Option<ZkProof> {
+        let start = Instant::now();
+        let found = self.lookup(key);
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found.map(|mut proof| {
+            proof.from_cache = true;
+            proof.proving_time_ms = start.elapsed().as_millis() as u64;
+            proof
+        })
+    }
+
+    /// Store `proof` under `key`. A failed disk write leaves it cached in memory only.
+    pub fn insert(&self, key: &str, proof: &ZkProof) -> std::io::Result<()> {
+        let entry = Entry {
+            stored_at_ms: now_ms(),
+            proof: ZkProof {
+                from_cache: false,
+                ..proof.clone()
+            },
+        };
+        self.remember(key, entry.clone());
+        let Some(path) = self.path(key) else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(path.parent().expect("a file in the cache directory"))?;
+        std::fs::write(path, serde_json::to_vec(&entry)?)
+    }
+
+    /// The cached proof for `key`, or the one `prove` makes, cached for next time
+    pub async fn get_or_prove<F, Fut, E>(&self, key: &str, prove: F) -> Result<ZkProof, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ZkProof, E>>,
+    {
+        if let Some(proof) = self.get(key) {
+            return Ok(proof);
+        }
+        let proof = prove().await?;
+        // The proof is good either way; a disk that can't take it only costs the next restart
+        let _ = self.insert(key, &proof);
+        Ok(proof)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().len(),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+        }
+    }
+
+    fn lookup(&self, key: &str) -> Option<ZkProof> {
+        let cached = self.entries.lock().unwrap().get(key).cloned();
+        let entry = match cached {
+            Some(entry) => entry,
+            None => {
+                let data = std::fs::read(self.path(key)?).ok()?;
+                let entry: Entry = serde_json::from_slice(&data).ok()?;
+                self.remember(key, entry.clone());
+                entry
+            }
+        };
+        if self.is_expired(&entry) {
+            self.expired.fetch_add(1, Ordering::Relaxed);
+            self.entries.lock().unwrap().pop(key);
+            if let Some(path) = self.path(key) {
+                let _ = std::fs::remove_file(path);
+            }
+            return None;
+        }
+        Some(entry.proof)
+    }
+
+    fn remember(&self, key: &str, entry: Entry) {
+        let evicted = self.entries.lock().unwrap().push(key.to_string(), entry);
+        if matches!(evicted, Some((evicted_key, _)) if evicted_key != key) {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn is_expired(&self, entry: &Entry) -> bool {
+        self.ttl.is_some_and(|ttl| {
+            now_ms().saturating_sub(entry.stored_at_ms) > ttl.as_millis() as u64
+        })
+    }
+
+    /// Where `key` lives on disk; keys that could leave the directory have no file
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let safe = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        safe.then(|| dir.join(format!("{key}.json")))
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Stands in for a backend, counting how often it is asked to prove
+    #[derive(Default)]
+    struct CountingProver {
+        calls: AtomicUsize,
+    }
+
+    impl CountingProver {
+        async fn prove(&self, program: &str) -> Result<ZkProof, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(ZkProof {
+                proof_id: crate::proof_id(program.as_bytes()),
+                program: program.to_string(),
+                public_inputs: vec!["10".to_string()],
+                proof_data: program.as_bytes().to_vec(),
+                backend: "SP1 Local".to_string(),
+                proving_time_ms: 90_000,
+                execution_mode: "local".to_string(),
+                from_cache: false,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn a_repeated_request_is_not_proven_again() {
+        let cache = ProofCache::new(8);
+        let prover = CountingProver::default();
+
+        let first = cache
+            .get_or_prove("zkproof_fib10", || prover.prove("fibonacci"))
+            .await
+            .unwrap();
+        assert!(!first.from_cache);
+        assert_eq!(first.proving_time_ms, 90_000);
+
+        let second = cache
+            .get_or_prove("zkproof_fib10", || prover.prove("fibonacci"))
+            .await
+            .unwrap();
+        assert_eq!(prover.calls.load(Ordering::SeqCst), 1);
+        assert!(second.from_cache);
+        assert!(second.proving_time_ms < 1000);
+        assert_eq!(second.proof_data, first.proof_data);
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1,
+                evictions: 0,
+                expired: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn evicted_proofs_are_found_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ProofCache::new(1).with_dir(dir.path());
+        let prover = CountingProver::default();
+
+        for key in ["zkproof_a", "zkproof_b"] {
+            cache.get_or_prove(key, || prover.prove(key)).await.unwrap();
+        }
+        assert_eq!(cache.stats().evictions, 1);
+
+        // A restarted service reads the same directory
+        let restarted = ProofCache::new(1).with_dir(dir.path());
+        let proof = restarted
+            .get_or_prove("zkproof_a", || prover.prove("zkproof_a"))
+            .await
+            .unwrap();
+        assert!(proof.from_cache);
+        assert_eq!(prover.calls.load(Ordering::SeqCst), 2);
+        assert!(restarted.get("../zkproof_a").is_none());
+    }
+
+    #[tokio::test]
+    async fn expired_proofs_are_proven_again() {
+        let cache = ProofCache::new(8).with_ttl(Duration::from_millis(50));
+        let prover = CountingProver::default();
+
+        cache
+            .get_or_prove("zkproof_fib10", || prover.prove("fibonacci"))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let proof = cache
+            .get_or_prove("zkproof_fib10", || prover.prove("fibonacci"))
+            .await
+            .unwrap();
+
+        assert!(!proof.from_cache);
+        assert_eq!(prover.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.stats().expired, 1);
+    }
+}
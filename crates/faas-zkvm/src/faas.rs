@@ -0,0 +1,84 @@
+//! Proving inside a FaaS execution
+//!
+//! The prover image reads a [`FaasProveRequest`] on stdin and the program's ELF from
+//! [`ELF_FILE`] in its working directory, and prints the proof as one line starting with
+//! [`PROOF_LINE_PREFIX`]. Everything else it writes is left alone, so the line can be
+//! picked out of stdout or, when the gateway cut stdout short, out of the full logs.
+
+use serde::{Deserialize, Serialize};
+
+/// Where the program's ELF is placed, relative to the execution's working directory
+pub const ELF_FILE: &str = "program.elf";
+
+/// Starts the line of prover output carrying the proof
+pub const PROOF_LINE_PREFIX: &str = "FAAS_ZK_PROOF ";
+
+/// What the prover image is asked to prove
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FaasProveRequest {
+    pub program: String,
+    /// SHA-256 of the ELF, checked by the image before proving
+    pub program_hash: String,
+    /// base64 of the zkVM's serialized stdin
+    pub stdin: String,
+}
+
+/// What the prover image answers with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FaasProveOutput {
+    /// base64 of the serialized proof
+    pub proof_data: String,
+    pub proving_time_ms: u64,
+}
+
+impl FaasProveOutput {
+    /// The line the prover image prints
+    pub fn to_line(&self) -> String {
+        format!(
+            "{PROOF_LINE_PREFIX}{}",
+            serde_json::to_string(self).expect("serializable")
+        )
+    }
+
+    /// The last proof line in `output`, if there is a complete one
+    pub fn from_output(output: &str) -> Option<Self> {
+        output
+            .lines()
+            .rev()
+            .find_map(|line| line.trim_end().strip_prefix(PROOF_LINE_PREFIX))
+            .and_then(|json| serde_json::from_str(json).ok())
+    }
+
+    pub fn proof_bytes(&self) -> Option<Vec<u8>> {
+        base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &self.proof_data).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_proof_line_is_found_among_prover_logs() {
+        let output = FaasProveOutput {
+            proof_data: base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                b"plonk",
+            ),
+            proving_time_ms: 61_000,
+        };
+        let stdout = format!(
+            "setting up keys\nproving shard 1/3\n{}\ndone\n",
+            output.to_line()
+        );
+
+        let parsed = FaasProveOutput::from_output(&stdout).unwrap();
+        assert_eq!(parsed, output);
+        assert_eq!(parsed.proof_bytes().unwrap(), b"plonk");
+
+        // A proof line cut short by the gateway's output cap is no proof at all
+        let line = output.to_line();
+        assert!(FaasProveOutput::from_output(&line[..line.len() - 4]).is_none());
+        assert!(FaasProveOutput::from_output("proving shard 1/3\n").is_none());
+    }
+}
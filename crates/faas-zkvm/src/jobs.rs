@@ -0,0 +1,317 @@
+//! Proof jobs, for proofs that take longer than a client will hold a request open
+//!
+//! `POST /v1/prove` with `"async": true` answers with a job that is `queued`, then
+//! `proving`, then `done` with its proof or `failed` with why. The store keeps a bounded
+//! number of jobs in memory, dropping the oldest finished one to make room; with a
+//! directory configured, finished jobs are also written there and still answer after
+//! they are dropped or the prover restarts.
+
+use crate::ZkProof;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Jobs kept in memory unless `FAAS_ZK_JOB_CAPACITY` says otherwise
+pub const DEFAULT_JOB_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProofJobStatus {
+    Queued,
+    Proving,
+    Done,
+    Failed,
+}
+
+impl ProofJobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, ProofJobStatus::Done | ProofJobStatus::Failed)
+    }
+}
+
+/// A proof as it travels over HTTP, with its bytes in base64
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofResponse {
+    pub proof_id: String,
+    pub program: String,
+    pub public_inputs: Vec<String>,
+    /// base64
+    pub proof_data: String,
+    pub backend: String,
+    pub proving_time_ms: u64,
+    #[serde(default)]
+    pub from_cache: bool,
+}
+
+impl From<&ZkProof> for ProofResponse {
+    fn from(proof: &ZkProof) -> Self {
+        Self {
+            proof_id: proof.proof_id.clone(),
+            program: proof.program.clone(),
+            public_inputs: proof.public_inputs.clone(),
+            proof_data: base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                &proof.proof_data,
+            ),
+            backend: proof.backend.clone(),
+            proving_time_ms: proof.proving_time_ms,
+            from_cache: proof.from_cache,
+        }
+    }
+}
+
+impl ProofResponse {
+    /// The proof a client received; `None` if its bytes aren't valid base64
+    pub fn into_proof(self, execution_mode: &str) -> Option<ZkProof> {
+        let proof_data =
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &self.proof_data)
+                .ok()?;
+        Some(ZkProof {
+            proof_id: self.proof_id,
+            program: self.program,
+            public_inputs: self.public_inputs,
+            proof_data,
+            backend: self.backend,
+            proving_time_ms: self.proving_time_ms,
+            execution_mode: execution_mode.to_string(),
+            from_cache: self.from_cache,
+        })
+    }
+}
+
+/// `GET /v1/proofs/:id`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofJob {
+    pub proof_job_id: String,
+    pub program: String,
+    pub status: ProofJobStatus,
+    /// Unix milliseconds
+    pub created_at_ms: u64,
+    pub updated_at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<ProofResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Every job the store holds is still queued or proving
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{capacity} proof jobs are already in flight")]
+pub struct JobStoreFull {
+    pub capacity: usize,
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    by_id: HashMap<String, ProofJob>,
+    /// Oldest first
+    order: VecDeque<String>,
+}
+
+/// Bounded in-memory proof jobs, optionally backed by a directory of finished ones
+#[derive(Debug)]
+pub struct ProofJobStore {
+    capacity: usize,
+    dir: Option<PathBuf>,
+    jobs: Mutex<Jobs>,
+}
+
+impl Default for ProofJobStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_JOB_CAPACITY)
+    }
+}
+
+impl ProofJobStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            dir: None,
+            jobs: Mutex::new(Jobs::default()),
+        }
+    }
+
+    /// Also write finished jobs to `dir`, one JSON file each
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// `FAAS_ZK_JOB_CAPACITY` jobs in memory, and finished ones under `FAAS_ZK_JOB_DIR`
+    /// when it is set
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("FAAS_ZK_JOB_CAPACITY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_JOB_CAPACITY);
+        let store = Self::new(capacity);
+        match std::env::var("FAAS_ZK_JOB_DIR") {
+            Ok(dir) if !dir.is_empty() => store.with_dir(dir),
+            _ => store,
+        }
+    }
+
+    /// Queue a job for `program`, dropping the oldest finished job if the store is full
+    pub fn submit(&self, program: &str) -> Result<ProofJob, JobStoreFull> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.by_id.len() >= self.capacity {
+            let finished = jobs
+                .order
+                .iter()
+                .position(|id| jobs.by_id[id].status.is_finished())
+                .ok_or(JobStoreFull {
+                    capacity: self.capacity,
+                })?;
+            let id = jobs.order.remove(finished).unwrap();
+            jobs.by_id.remove(&id);
+        }
+        let now = now_ms();
+        let job = ProofJob {
+            proof_job_id: uuid::Uuid::new_v4().to_string(),
+            program: program.to_string(),
+            status: ProofJobStatus::Queued,
+            created_at_ms: now,
+            updated_at_ms: now,
+            proof: None,
+            error: None,
+        };
+        jobs.order.push_back(job.proof_job_id.clone());
+        jobs.by_id.insert(job.proof_job_id.clone(), job.clone());
+        Ok(job)
+    }
+
+    /// The prover picked the job up
+    pub fn start(&self, id: &str) {
+        self.update(id, |job| job.status = ProofJobStatus::Proving);
+    }
+
+    /// Record how proving ended, writing the job to disk if a directory is configured. A
+    /// failed write leaves the job answering from memory until it is dropped.
+    pub fn finish(&self, id: &str, result: Result<ZkProof, String>) -> std::io::Result<()> {
+        let finished = self.update(id, |job| match &result {
+            Ok(proof) => {
+                job.status = ProofJobStatus::Done;
+                job.proof = Some(ProofResponse::from(proof));
+            }
+            Err(e) => {
+                job.status = ProofJobStatus::Failed;
+                job.error = Some(e.clone());
+            }
+        });
+        let (Some(job), Some(dir)) = (finished, &self.dir) else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(job_path(dir, id), serde_json::to_vec(&job)?)
+    }
+
+    /// The job from memory, or from disk once it was dropped
+    pub fn get(&self, id: &str) -> Option<ProofJob> {
+        if let Some(job) = self.jobs.lock().unwrap().by_id.get(id) {
+            return Some(job.clone());
+        }
+        // Ids are uuids; anything else could walk out of the directory
+        uuid::Uuid::parse_str(id).ok()?;
+        let data = std::fs::read(job_path(self.dir.as_ref()?, id)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// Jobs held in memory
+    pub fn len(&self) -> usize {
+        self.jobs.lock().unwrap().by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn update(&self, id: &str, change: impl FnOnce(&mut ProofJob)) -> Option<ProofJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.by_id.get_mut(id)?;
+        change(job);
+        job.updated_at_ms = now_ms();
+        Some(job.clone())
+    }
+}
+
+fn job_path(dir: &std::path::Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.json"))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proof() -> ZkProof {
+        ZkProof {
+            proof_id: crate::proof_id(b"proof"),
+            program: "fibonacci".to_string(),
+            public_inputs: vec!["10".to_string()],
+            proof_data: b"proof".to_vec(),
+            backend: "SP1 Local".to_string(),
+            proving_time_ms: 1200,
+            execution_mode: "local".to_string(),
+            from_cache: false,
+        }
+    }
+
+    #[test]
+    fn a_full_store_drops_its_oldest_finished_job() {
+        let store = ProofJobStore::new(2);
+        let first = store.submit("fibonacci").unwrap();
+        let second = store.submit("fibonacci").unwrap();
+        assert_eq!(
+            store.submit("fibonacci"),
+            Err(JobStoreFull { capacity: 2 }),
+            "nothing has finished yet"
+        );
+
+        store.start(&second.proof_job_id);
+        store
+            .finish(&second.proof_job_id, Err("out of memory".to_string()))
+            .unwrap();
+        let third = store.submit("fibonacci").unwrap();
+        assert_eq!(store.len(), 2);
+        assert!(store.get(&second.proof_job_id).is_none());
+        assert_eq!(
+            store.get(&first.proof_job_id).unwrap().status,
+            ProofJobStatus::Queued
+        );
+        assert_eq!(
+            store.get(&third.proof_job_id).unwrap().status,
+            ProofJobStatus::Queued
+        );
+    }
+
+    #[test]
+    fn finished_jobs_outlive_eviction_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProofJobStore::new(1).with_dir(dir.path());
+        let job = store.submit("fibonacci").unwrap();
+        store.start(&job.proof_job_id);
+        assert_eq!(
+            store.get(&job.proof_job_id).unwrap().status,
+            ProofJobStatus::Proving
+        );
+        store.finish(&job.proof_job_id, Ok(proof())).unwrap();
+        store.submit("fibonacci").unwrap();
+
+        // A restarted prover reads the same directory
+        let restarted = ProofJobStore::new(1).with_dir(dir.path());
+        let done = restarted.get(&job.proof_job_id).unwrap();
+        assert_eq!(done.status, ProofJobStatus::Done);
+        assert_eq!(
+            done.proof.unwrap().into_proof("remote").unwrap().proof_data,
+            b"proof"
+        );
+        assert!(restarted.get("../etc/passwd").is_none());
+    }
+}
@@ -7,7 +7,12 @@
 //!
 //! - **ZkBackend**: Enum for different proving backends (local, network, FaaS)
 //! - **ZkProof**: Standard proof format across all backends
-//! - **ProgramRegistry**: Program storage and caching (future: IPFS integration)
+//! - **ProgramRegistry**: Programs and their ELFs by hash, kept on disk or fetched from IPFS
+//! - **ProofStore**: Proofs keyed by the SHA-256 of their bytes
+//! - **ProofCache**: Finished proofs kept by cache key, so a repeated request isn't re-proven
+//! - **ProofJobStore**: Proofs being generated in the background, polled by id
+//! - **verify_proof**: Checks a proof with the verifier for the zkVM that made it
+//! - **faas**: What a prover image running as a FaaS execution reads and prints
 //!
 //! ## Usage
 //!
@@ -21,7 +26,24 @@
 //! // Implementation varies by backend
 //! ```
 
+pub mod cache;
+pub mod faas;
+pub mod jobs;
+pub mod registry;
+pub mod verify;
+
+pub use cache::{CacheStats, ProofCache};
+pub use jobs::{ProofJob, ProofJobStatus, ProofJobStore, ProofResponse};
+pub use registry::{
+    IpfsGatewayFetcher, ProgramFetcher, ProgramMetadata, ProgramRegistry, RegisterProgramRequest,
+    RegistryError,
+};
+pub use verify::{verify_proof, ProofVerifier, Verification, Verifiers, VerifyError};
+
+use faas_common::hash::{is_legacy_md5_hex, sha256_hex};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 /// Simple HTTP client for ZK Prover service
 pub struct ZkProverClient {
@@ -35,6 +57,18 @@ pub enum ZkProverError {
     Http(#[from] reqwest::Error),
     #[error("Prover service error: {0}")]
     Server(String),
+    #[error("Unknown program: {0}")]
+    UnknownProgram(String),
+    #[error("Unknown proof job: {0}")]
+    UnknownJob(String),
+    #[error("Proof job {proof_job_id} failed: {error}")]
+    ProofFailed { proof_job_id: String, error: String },
+    #[error("Proof job {proof_job_id} still {status:?} after {waited:?}")]
+    Timeout {
+        proof_job_id: String,
+        status: ProofJobStatus,
+        waited: Duration,
+    },
 }
 
 impl ZkProverClient {
@@ -47,42 +81,134 @@ impl ZkProverClient {
     }
 
     /// Request a ZK proof generation
+    ///
+    /// The request stays open until the proof is done, which for PLONK proofs can be
+    /// minutes; [`Self::prove_async`] doesn't have to.
     pub async fn prove(
         &self,
         program: &str,
         public_inputs: Vec<String>,
         private_inputs: Vec<String>,
     ) -> Result<ZkProof, ZkProverError> {
-        #[derive(Serialize)]
-        struct ProveRequest {
-            program: String,
-            public_inputs: Vec<String>,
-            private_inputs: Vec<String>,
+        let resp = self
+            .submit(ProveRequest {
+                program,
+                public_inputs,
+                private_inputs,
+                run_async: false,
+            })
+            .await?;
+        let prove_resp: ProofResponse = resp.json().await?;
+        remote_proof(prove_resp)
+    }
+
+    /// Queue a proof and return its job id at once; poll it with
+    /// [`Self::get_proof_status`] or [`Self::wait_for_proof`]
+    pub async fn prove_async(
+        &self,
+        program: &str,
+        public_inputs: Vec<String>,
+        private_inputs: Vec<String>,
+    ) -> Result<String, ZkProverError> {
+        let resp = self
+            .submit(ProveRequest {
+                program,
+                public_inputs,
+                private_inputs,
+                run_async: true,
+            })
+            .await?;
+        let job: ProofJob = resp.json().await?;
+        Ok(job.proof_job_id)
+    }
+
+    async fn submit(&self, req: ProveRequest<'_>) -> Result<reqwest::Response, ZkProverError> {
+        let resp = self
+            .http_client
+            .post(format!("{}/v1/prove", self.base_url))
+            .json(&req)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let error_msg = resp
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ZkProverError::Server(error_msg));
         }
+        Ok(resp)
+    }
 
-        #[derive(Deserialize)]
-        struct ProveResponse {
-            proof_id: String,
-            program: String,
-            public_inputs: Vec<String>,
-            proof_data: String, // base64
-            backend: String,
-            proving_time_ms: u64,
+    /// Where a proof job is; carries the proof once it is done
+    pub async fn get_proof_status(&self, proof_job_id: &str) -> Result<ProofJob, ZkProverError> {
+        let resp = self
+            .http_client
+            .get(format!("{}/v1/proofs/{}", self.base_url, proof_job_id))
+            .send()
+            .await?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(ZkProverError::UnknownJob(proof_job_id.to_string()));
+        }
+        if !resp.status().is_success() {
+            let error_msg = resp
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ZkProverError::Server(error_msg));
         }
+        Ok(resp.json().await?)
+    }
 
-        let req = ProveRequest {
-            program: program.to_string(),
-            public_inputs,
-            private_inputs,
-        };
+    /// Poll a proof job every `poll_interval` until it finishes, giving up after `timeout`
+    pub async fn wait_for_proof(
+        &self,
+        proof_job_id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<ZkProof, ZkProverError> {
+        let started = Instant::now();
+        loop {
+            let job = self.get_proof_status(proof_job_id).await?;
+            match job.status {
+                ProofJobStatus::Done => {
+                    let proof = job.proof.ok_or_else(|| {
+                        ZkProverError::Server(format!("job {proof_job_id} is done without a proof"))
+                    })?;
+                    return remote_proof(proof);
+                }
+                ProofJobStatus::Failed => {
+                    return Err(ZkProverError::ProofFailed {
+                        proof_job_id: proof_job_id.to_string(),
+                        error: job.error.unwrap_or_default(),
+                    })
+                }
+                status if started.elapsed() + poll_interval > timeout => {
+                    return Err(ZkProverError::Timeout {
+                        proof_job_id: proof_job_id.to_string(),
+                        status,
+                        waited: started.elapsed(),
+                    })
+                }
+                _ => tokio::time::sleep(poll_interval).await,
+            }
+        }
+    }
 
+    /// Ask the prover whether `proof` holds; an invalid proof is `valid: false`, a
+    /// program the prover has no verifying key for is [`ZkProverError::UnknownProgram`]
+    pub async fn verify(&self, proof: &ZkProof) -> Result<Verification, ZkProverError> {
         let resp = self
             .http_client
-            .post(format!("{}/v1/prove", self.base_url))
-            .json(&req)
+            .post(format!("{}/v1/verify", self.base_url))
+            .json(&ProofResponse::from(proof))
             .send()
             .await?;
 
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(ZkProverError::UnknownProgram(proof.program.clone()));
+        }
         if !resp.status().is_success() {
             let error_msg = resp
                 .text()
@@ -90,25 +216,51 @@ impl ZkProverClient {
                 .unwrap_or_else(|_| "Unknown error".to_string());
             return Err(ZkProverError::Server(error_msg));
         }
+        Ok(resp.json().await?)
+    }
 
-        let prove_resp: ProveResponse = resp.json().await?;
-
-        // Decode base64 proof data
-        let proof_data = base64::Engine::decode(
-            &base64::engine::general_purpose::STANDARD,
-            &prove_resp.proof_data,
-        )
-        .map_err(|e| ZkProverError::Server(format!("Invalid base64: {e}")))?;
-
-        Ok(ZkProof {
-            proof_id: prove_resp.proof_id,
-            program: prove_resp.program,
-            public_inputs: prove_resp.public_inputs,
-            proof_data,
-            backend: prove_resp.backend,
-            proving_time_ms: prove_resp.proving_time_ms,
-            execution_mode: "remote".to_string(),
-        })
+    /// Upload an SP1 ELF under `name`; it can be proven by name or by the hash in the
+    /// metadata that comes back
+    pub async fn register_program(
+        &self,
+        name: &str,
+        elf: &[u8],
+    ) -> Result<ProgramMetadata, ZkProverError> {
+        let req = RegisterProgramRequest {
+            name: name.to_string(),
+            elf: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, elf),
+            program_hash: Some(sha256_hex(elf)),
+        };
+        let resp = self
+            .http_client
+            .post(format!("{}/v1/programs", self.base_url))
+            .json(&req)
+            .send()
+            .await?;
+        json_or_server_error(resp).await
+    }
+
+    /// Programs uploaded to the prover
+    pub async fn list_programs(&self) -> Result<Vec<ProgramMetadata>, ZkProverError> {
+        let resp = self
+            .http_client
+            .get(format!("{}/v1/programs", self.base_url))
+            .send()
+            .await?;
+        json_or_server_error(resp).await
+    }
+
+    /// An uploaded program by hash; [`ZkProverError::UnknownProgram`] if there is none
+    pub async fn get_program(&self, program_hash: &str) -> Result<ProgramMetadata, ZkProverError> {
+        let resp = self
+            .http_client
+            .get(format!("{}/v1/programs/{}", self.base_url, program_hash))
+            .send()
+            .await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(ZkProverError::UnknownProgram(program_hash.to_string()));
+        }
+        json_or_server_error(resp).await
     }
 
     /// Health check
@@ -127,6 +279,35 @@ impl ZkProverClient {
     }
 }
 
+/// `POST /v1/prove`
+#[derive(Serialize)]
+struct ProveRequest<'a> {
+    program: &'a str,
+    public_inputs: Vec<String>,
+    private_inputs: Vec<String>,
+    #[serde(rename = "async")]
+    run_async: bool,
+}
+
+async fn json_or_server_error<T: serde::de::DeserializeOwned>(
+    resp: reqwest::Response,
+) -> Result<T, ZkProverError> {
+    if !resp.status().is_success() {
+        let error_msg = resp
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(ZkProverError::Server(error_msg));
+    }
+    Ok(resp.json().await?)
+}
+
+fn remote_proof(proof: ProofResponse) -> Result<ZkProof, ZkProverError> {
+    proof
+        .into_proof("remote")
+        .ok_or_else(|| ZkProverError::Server("Invalid base64 proof data".to_string()))
+}
+
 /// Zero-knowledge proving backend configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ZkBackend {
@@ -161,52 +342,60 @@ pub struct ZkProof {
     pub proving_time_ms: u64,
     /// Execution mode: "local", "faas-docker", "faas-firecracker"
     pub execution_mode: String,
+    /// Answered from a [`ProofCache`] instead of proven for this request
+    #[serde(default)]
+    pub from_cache: bool,
 }
 
-/// Program metadata for registry
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProgramMetadata {
-    /// Program hash (SHA256 of ELF binary)
-    pub program_hash: String,
-    /// IPFS CID (optional, for decentralized storage)
-    pub ipfs_cid: Option<String>,
-    /// Human-readable description
-    pub description: String,
-    /// zkVM type (sp1, risczero, etc.)
-    pub zkvm_type: String,
-    /// Author address
-    pub author: Option<String>,
-    /// Registration timestamp
-    pub timestamp: u64,
+/// Proof id for serialized proof bytes (hex SHA-256)
+pub fn proof_id(proof_data: &[u8]) -> String {
+    sha256_hex(proof_data)
 }
 
-/// Simple in-memory program registry (placeholder for future IPFS integration)
+/// Proofs keyed by [`proof_id`]
+///
+/// Proofs written before the SHA-256 migration carry md5 ids. They are re-keyed when
+/// inserted and the old id kept as an alias, so lookups by either id keep working.
+/// New proofs are only ever stored under their SHA-256 id.
 #[derive(Debug, Default)]
-pub struct ProgramRegistry {
-    programs: std::collections::HashMap<String, ProgramMetadata>,
+pub struct ProofStore {
+    proofs: HashMap<String, ZkProof>,
+    legacy_ids: HashMap<String, String>,
 }
 
-impl ProgramRegistry {
-    /// Create new empty registry
+impl ProofStore {
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Register a program with metadata
-    pub fn register(&mut self, metadata: ProgramMetadata) -> Result<(), String> {
-        self.programs
-            .insert(metadata.program_hash.clone(), metadata);
-        Ok(())
+    /// Store a proof, returning the id it is stored under
+    pub fn insert(&mut self, mut proof: ZkProof) -> String {
+        let id = proof_id(&proof.proof_data);
+        if proof.proof_id != id {
+            if is_legacy_md5_hex(&proof.proof_id) {
+                self.legacy_ids.insert(proof.proof_id.clone(), id.clone());
+            }
+            proof.proof_id = id.clone();
+        }
+        self.proofs.insert(id.clone(), proof);
+        id
+    }
+
+    /// Look a proof up by its current id or a legacy md5 id
+    pub fn get(&self, id: &str) -> Option<&ZkProof> {
+        self.proofs.get(id).or_else(|| {
+            self.legacy_ids
+                .get(id)
+                .and_then(|current| self.proofs.get(current))
+        })
     }
 
-    /// Get program metadata by hash
-    pub fn get(&self, program_hash: &str) -> Option<&ProgramMetadata> {
-        self.programs.get(program_hash)
+    pub fn len(&self) -> usize {
+        self.proofs.len()
     }
 
-    /// List all registered programs
-    pub fn list(&self) -> Vec<&ProgramMetadata> {
-        self.programs.values().collect()
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
     }
 }
 
@@ -224,7 +413,7 @@ mod tests {
 
     #[test]
     fn test_program_registry() {
-        let mut registry = ProgramRegistry::new();
+        let registry = ProgramRegistry::new();
 
         let metadata = ProgramMetadata {
             program_hash: "abc123".to_string(),
@@ -233,10 +422,41 @@ mod tests {
             zkvm_type: "sp1".to_string(),
             author: None,
             timestamp: 0,
+            name: None,
+            size_bytes: None,
         };
 
         registry.register(metadata.clone()).unwrap();
         let retrieved = registry.get("abc123").unwrap();
         assert_eq!(retrieved.description, "Test program");
     }
+
+    #[test]
+    fn legacy_md5_proof_ids_stay_retrievable() {
+        let proof = ZkProof {
+            // md5("sp1-plonk-proof"), as the prover wrote it before the migration
+            proof_id: "3655b7f2a07d39b7faa6d2170e68e861".to_string(),
+            program: "fibonacci".to_string(),
+            public_inputs: vec!["10".to_string()],
+            proof_data: b"sp1-plonk-proof".to_vec(),
+            backend: "SP1 Local".to_string(),
+            proving_time_ms: 0,
+            execution_mode: "local".to_string(),
+            from_cache: false,
+        };
+
+        let mut store = ProofStore::new();
+        let id = store.insert(proof);
+        assert_eq!(
+            id,
+            "86a1f4b978077a768d8ae42371fad87caff3a97d06acb2daff0045074e971244"
+        );
+        assert_eq!(id, proof_id(b"sp1-plonk-proof"));
+
+        let legacy = store.get("3655b7f2a07d39b7faa6d2170e68e861").unwrap();
+        assert_eq!(legacy.program, "fibonacci");
+        assert_eq!(legacy.proof_id, id);
+        assert!(store.get(&id).is_some());
+        assert_eq!(store.len(), 1);
+    }
 }
@@ -0,0 +1,625 @@
+//! Guest programs the prover can run, by the SHA-256 of their ELF
+//!
+//! Metadata can be registered on its own, as the blueprint's on-chain registry does, or
+//! together with the ELF through [`ProgramRegistry::register_elf`]. With a directory the
+//! ELFs are content-addressed files there, each next to its metadata, and are loaded
+//! again by [`ProgramRegistry::open`]; a file whose bytes no longer hash to its name is
+//! refused rather than proven with.
+//!
+//! A program registered by metadata alone is downloaded by [`ProgramRegistry::resolve`]
+//! from its `ipfs_cid` through the registry's [`ProgramFetcher`]. Downloads are checked
+//! like the files on disk and kept in memory up to a byte budget, least recently used
+//! dropped first. The registry locks internally, so one `Arc<ProgramRegistry>` serves
+//! every handler.
+
+use async_trait::async_trait;
+use faas_common::hash::sha256_hex;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Every ELF starts with these
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// Bytes of downloaded ELFs kept in memory unless [`ProgramRegistry::with_fetch_budget`]
+/// says otherwise
+pub const DEFAULT_FETCH_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+
+/// Program metadata for registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramMetadata {
+    /// Program hash (SHA256 of ELF binary)
+    pub program_hash: String,
+    /// IPFS CID (optional, for decentralized storage)
+    pub ipfs_cid: Option<String>,
+    /// Human-readable description
+    pub description: String,
+    /// zkVM type (sp1, risczero, etc.)
+    pub zkvm_type: String,
+    /// Author address
+    pub author: Option<String>,
+    /// Registration timestamp
+    pub timestamp: u64,
+    /// Name the program can be proven by instead of its hash
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Size of the ELF, when the registry holds it
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("Unknown program: {0}")]
+    NotFound(String),
+    #[error("ELF hashes to {actual}, not {expected}")]
+    HashMismatch { expected: String, actual: String },
+    #[error("Not an ELF binary")]
+    NotAnElf,
+    #[error("Invalid program name: {0:?}")]
+    InvalidName(String),
+    #[error("Program storage: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Program metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+    #[error("Fetching program: {0}")]
+    Fetch(String),
+}
+
+/// Downloads program ELFs the registry only has metadata for
+#[async_trait]
+pub trait ProgramFetcher: Send + Sync {
+    /// The bytes stored under `cid`. The registry checks them against the program hash,
+    /// so a fetcher needn't.
+    async fn fetch(&self, cid: &str) -> Result<Vec<u8>, RegistryError>;
+}
+
+/// Fetches from an IPFS HTTP gateway, `<gateway>/ipfs/<cid>`
+#[derive(Debug, Clone)]
+pub struct IpfsGatewayFetcher {
+    client: reqwest::Client,
+    gateway_url: String,
+    max_bytes: usize,
+}
+
+impl IpfsGatewayFetcher {
+    /// Gateway like `https://ipfs.io`; downloads are capped at 32 MiB
+    pub fn new(gateway_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            gateway_url: gateway_url.into().trim_end_matches('/').to_string(),
+            max_bytes: 32 * 1024 * 1024,
+        }
+    }
+
+    /// Refuse downloads over `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+#[async_trait]
+impl ProgramFetcher for IpfsGatewayFetcher {
+    async fn fetch(&self, cid: &str) -> Result<Vec<u8>, RegistryError> {
+        if cid.is_empty() || !cid.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(RegistryError::InvalidName(cid.to_string()));
+        }
+        let response = self
+            .client
+            .get(format!("{}/ipfs/{}", self.gateway_url, cid))
+            .send()
+            .await
+            .map_err(|e| RegistryError::Fetch(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(RegistryError::Fetch(format!(
+                "{} answered {} for {}",
+                self.gateway_url,
+                response.status(),
+                cid
+            )));
+        }
+        let too_large = || RegistryError::Fetch(format!("{cid} is over {} bytes", self.max_bytes));
+        if response.content_length().unwrap_or(0) > self.max_bytes as u64 {
+            return Err(too_large());
+        }
+        let elf = response
+            .bytes()
+            .await
+            .map_err(|e| RegistryError::Fetch(e.to_string()))?;
+        if elf.len() > self.max_bytes {
+            return Err(too_large());
+        }
+        Ok(elf.to_vec())
+    }
+}
+
+/// `POST /v1/programs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterProgramRequest {
+    pub name: String,
+    /// base64
+    pub elf: String,
+    /// Refuse the upload unless the ELF hashes to this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_hash: Option<String>,
+}
+
+/// Program metadata by hash, and the name each program was registered under
+#[derive(Debug, Default)]
+struct Index {
+    programs: HashMap<String, ProgramMetadata>,
+    /// Name to program hash
+    names: HashMap<String, String>,
+}
+
+/// Downloaded ELFs by program hash, within a byte budget
+struct Fetched {
+    elfs: LruCache<String, Vec<u8>>,
+    bytes: usize,
+    budget: usize,
+}
+
+impl Fetched {
+    fn insert(&mut self, program_hash: String, elf: Vec<u8>) {
+        // Not worth evicting everything else for
+        if elf.len() > self.budget {
+            return;
+        }
+        self.bytes += elf.len();
+        if let Some(old) = self.elfs.put(program_hash, elf) {
+            self.bytes -= old.len();
+        }
+        while self.bytes > self.budget {
+            match self.elfs.pop_lru() {
+                Some((_, evicted)) => self.bytes -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// In-memory program registry, optionally persisted to a directory
+pub struct ProgramRegistry {
+    index: RwLock<Index>,
+    /// ELFs registered here, kept for good
+    elfs: RwLock<HashMap<String, Vec<u8>>>,
+    fetched: Mutex<Fetched>,
+    fetcher: Option<Arc<dyn ProgramFetcher>>,
+    dir: Option<PathBuf>,
+}
+
+impl Default for ProgramRegistry {
+    fn default() -> Self {
+        Self {
+            index: RwLock::default(),
+            elfs: RwLock::default(),
+            fetched: Mutex::new(Fetched {
+                elfs: LruCache::unbounded(),
+                bytes: 0,
+                budget: DEFAULT_FETCH_BUDGET_BYTES,
+            }),
+            fetcher: None,
+            dir: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for ProgramRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgramRegistry")
+            .field("programs", &self.index.read().unwrap().programs.len())
+            .field("fetcher", &self.fetcher.is_some())
+            .field("dir", &self.dir)
+            .finish()
+    }
+}
+
+impl ProgramRegistry {
+    /// Create new empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry persisted under `dir`, with the programs registered there before
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, RegistryError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let registry = Self {
+            dir: Some(dir.clone()),
+            ..Self::default()
+        };
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let metadata: ProgramMetadata = serde_json::from_slice(&std::fs::read(&path)?)?;
+            let elf_path = elf_path(&dir, &metadata.program_hash);
+            if elf_path.exists() {
+                let elf = std::fs::read(&elf_path)?;
+                verify_hash(&metadata.program_hash, &elf)?;
+                registry
+                    .elfs
+                    .write()
+                    .unwrap()
+                    .insert(metadata.program_hash.clone(), elf);
+            }
+            registry.insert(metadata);
+        }
+        Ok(registry)
+    }
+
+    /// Download the ELFs of programs registered by metadata alone with `fetcher`
+    pub fn with_fetcher(mut self, fetcher: Arc<dyn ProgramFetcher>) -> Self {
+        self.fetcher = Some(fetcher);
+        self
+    }
+
+    /// Keep up to `bytes` of downloaded ELFs in memory
+    pub fn with_fetch_budget(mut self, bytes: usize) -> Self {
+        self.fetched.get_mut().unwrap().budget = bytes;
+        self
+    }
+
+    /// Register a program with metadata
+    pub fn register(&self, metadata: ProgramMetadata) -> Result<(), String> {
+        if let Some(dir) = &self.dir {
+            write_metadata(dir, &metadata).map_err(|e| e.to_string())?;
+        }
+        self.insert(metadata);
+        Ok(())
+    }
+
+    /// Register an SP1 ELF under `name`, returning its metadata. The program can then be
+    /// proven by name or by the hash in its metadata; registering another ELF under the
+    /// same name points the name at it.
+    pub fn register_elf(
+        &self,
+        name: &str,
+        elf: Vec<u8>,
+    ) -> Result<ProgramMetadata, RegistryError> {
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            return Err(RegistryError::InvalidName(name.to_string()));
+        }
+        if !elf.starts_with(ELF_MAGIC) {
+            return Err(RegistryError::NotAnElf);
+        }
+
+        let metadata = ProgramMetadata {
+            program_hash: sha256_hex(&elf),
+            ipfs_cid: None,
+            description: name.to_string(),
+            zkvm_type: "sp1".to_string(),
+            author: None,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            name: Some(name.to_string()),
+            size_bytes: Some(elf.len() as u64),
+        };
+        if let Some(dir) = &self.dir {
+            std::fs::create_dir_all(dir)?;
+            std::fs::write(elf_path(dir, &metadata.program_hash), &elf)?;
+            write_metadata(dir, &metadata)?;
+        }
+        self.elfs
+            .write()
+            .unwrap()
+            .insert(metadata.program_hash.clone(), elf);
+        self.insert(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// [`Self::register_elf`], refusing an ELF that doesn't hash to `expected_hash`
+    pub fn register_elf_verified(
+        &self,
+        name: &str,
+        elf: Vec<u8>,
+        expected_hash: &str,
+    ) -> Result<ProgramMetadata, RegistryError> {
+        verify_hash(expected_hash, &elf)?;
+        self.register_elf(name, elf)
+    }
+
+    /// Get program metadata by hash
+    pub fn get(&self, program_hash: &str) -> Option<ProgramMetadata> {
+        self.index.read().unwrap().programs.get(program_hash).cloned()
+    }
+
+    /// Hash of the program registered as `program`, a name or a hash
+    pub fn program_hash(&self, program: &str) -> Option<String> {
+        let index = self.index.read().unwrap();
+        if index.programs.contains_key(program) {
+            return Some(program.to_string());
+        }
+        index.names.get(program).cloned()
+    }
+
+    /// The ELF of `program`, a name or a hash, if it is registered here or was downloaded
+    /// and is still in memory
+    pub fn elf(&self, program: &str) -> Result<Vec<u8>, RegistryError> {
+        let not_found = || RegistryError::NotFound(program.to_string());
+        let hash = self.program_hash(program).ok_or_else(not_found)?;
+        if let Some(elf) = self.elfs.read().unwrap().get(&hash) {
+            return Ok(elf.clone());
+        }
+        self.fetched
+            .lock()
+            .unwrap()
+            .elfs
+            .get(&hash)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    /// The ELF of `program`, a name or a hash, downloading it from the program's
+    /// `ipfs_cid` when only its metadata is here. A download that isn't an ELF or doesn't
+    /// hash to the program hash is refused.
+    pub async fn resolve(&self, program: &str) -> Result<Vec<u8>, RegistryError> {
+        if let Ok(elf) = self.elf(program) {
+            return Ok(elf);
+        }
+        let metadata = self
+            .program_hash(program)
+            .and_then(|hash| self.get(&hash))
+            .ok_or_else(|| RegistryError::NotFound(program.to_string()))?;
+        let (Some(fetcher), Some(cid)) = (&self.fetcher, &metadata.ipfs_cid) else {
+            return Err(RegistryError::NotFound(program.to_string()));
+        };
+
+        let elf = fetcher.fetch(cid).await?;
+        if !elf.starts_with(ELF_MAGIC) {
+            return Err(RegistryError::NotAnElf);
+        }
+        verify_hash(&metadata.program_hash, &elf)?;
+        self.fetched
+            .lock()
+            .unwrap()
+            .insert(metadata.program_hash, elf.clone());
+        Ok(elf)
+    }
+
+    /// List all registered programs
+    pub fn list(&self) -> Vec<ProgramMetadata> {
+        self.index.read().unwrap().programs.values().cloned().collect()
+    }
+
+    fn insert(&self, metadata: ProgramMetadata) {
+        let mut index = self.index.write().unwrap();
+        if let Some(name) = &metadata.name {
+            index
+                .names
+                .insert(name.clone(), metadata.program_hash.clone());
+        }
+        index
+            .programs
+            .insert(metadata.program_hash.clone(), metadata);
+    }
+}
+
+/// Refuse `elf` unless it hashes to `expected`
+pub fn verify_hash(expected: &str, elf: &[u8]) -> Result<(), RegistryError> {
+    let actual = sha256_hex(elf);
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(RegistryError::HashMismatch {
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Metadata goes next to the ELF; hashes that could leave the directory are refused
+fn write_metadata(dir: &Path, metadata: &ProgramMetadata) -> Result<(), RegistryError> {
+    if !is_hex(&metadata.program_hash) {
+        return Err(RegistryError::InvalidName(metadata.program_hash.clone()));
+    }
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(
+        dir.join(format!("{}.json", metadata.program_hash)),
+        serde_json::to_vec_pretty(metadata)?,
+    )?;
+    Ok(())
+}
+
+fn elf_path(dir: &Path, program_hash: &str) -> PathBuf {
+    dir.join(format!("{program_hash}.elf"))
+}
+
+fn is_hex(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elf(body: &[u8]) -> Vec<u8> {
+        [ELF_MAGIC, body].concat()
+    }
+
+    #[test]
+    fn registered_elfs_resolve_by_name_and_hash_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ProgramRegistry::open(dir.path()).unwrap();
+        let sudoku = registry.register_elf("sudoku", elf(b"v1")).unwrap();
+        assert_eq!(sudoku.program_hash, sha256_hex(elf(b"v1")));
+        assert_eq!(sudoku.size_bytes, Some(6));
+
+        let restarted = ProgramRegistry::open(dir.path()).unwrap();
+        assert_eq!(restarted.list().len(), 1);
+        assert_eq!(restarted.elf("sudoku").unwrap(), elf(b"v1"));
+        assert_eq!(restarted.elf(&sudoku.program_hash).unwrap(), elf(b"v1"));
+        assert!(matches!(
+            restarted.elf(&sha256_hex(b"never registered")),
+            Err(RegistryError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn uploads_that_are_not_what_they_claim_are_refused() {
+        let registry = ProgramRegistry::new();
+        assert!(matches!(
+            registry.register_elf("script", b"#!/bin/sh".to_vec()),
+            Err(RegistryError::NotAnElf)
+        ));
+        assert!(matches!(
+            registry.register_elf("../escape", elf(b"v1")),
+            Err(RegistryError::InvalidName(_))
+        ));
+        assert!(matches!(
+            registry.register_elf_verified("sudoku", elf(b"v1"), &sha256_hex(elf(b"v2"))),
+            Err(RegistryError::HashMismatch { .. })
+        ));
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn a_tampered_elf_on_disk_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let hash = ProgramRegistry::open(dir.path())
+            .unwrap()
+            .register_elf("sudoku", elf(b"v1"))
+            .unwrap()
+            .program_hash;
+        std::fs::write(elf_path(dir.path(), &hash), elf(b"evil")).unwrap();
+
+        assert!(matches!(
+            ProgramRegistry::open(dir.path()),
+            Err(RegistryError::HashMismatch { .. })
+        ));
+    }
+
+    /// Serves fixed bytes per CID and counts downloads
+    #[derive(Default)]
+    struct MockFetcher {
+        files: HashMap<String, Vec<u8>>,
+        fetches: Mutex<usize>,
+    }
+
+    impl MockFetcher {
+        fn with(mut self, cid: &str, bytes: Vec<u8>) -> Self {
+            self.files.insert(cid.to_string(), bytes);
+            self
+        }
+
+        fn fetches(&self) -> usize {
+            *self.fetches.lock().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl ProgramFetcher for MockFetcher {
+        async fn fetch(&self, cid: &str) -> Result<Vec<u8>, RegistryError> {
+            *self.fetches.lock().unwrap() += 1;
+            self.files
+                .get(cid)
+                .cloned()
+                .ok_or_else(|| RegistryError::Fetch(format!("no such cid {cid}")))
+        }
+    }
+
+    /// Metadata for `elf` as the blueprint registers it: hash and CID, no bytes
+    fn on_chain(elf: &[u8], cid: &str) -> ProgramMetadata {
+        ProgramMetadata {
+            program_hash: sha256_hex(elf),
+            ipfs_cid: Some(cid.to_string()),
+            description: cid.to_string(),
+            zkvm_type: "sp1".to_string(),
+            author: None,
+            timestamp: 0,
+            name: None,
+            size_bytes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn programs_are_fetched_once_and_served_from_memory() {
+        let fetcher = Arc::new(MockFetcher::default().with("QmSudoku", elf(b"v1")));
+        let registry = ProgramRegistry::new().with_fetcher(fetcher.clone());
+        let metadata = on_chain(&elf(b"v1"), "QmSudoku");
+        registry.register(metadata.clone()).unwrap();
+
+        assert!(matches!(
+            registry.elf(&metadata.program_hash),
+            Err(RegistryError::NotFound(_))
+        ));
+        assert_eq!(registry.resolve(&metadata.program_hash).await.unwrap(), elf(b"v1"));
+        assert_eq!(registry.resolve(&metadata.program_hash).await.unwrap(), elf(b"v1"));
+        assert_eq!(registry.elf(&metadata.program_hash).unwrap(), elf(b"v1"));
+        assert_eq!(fetcher.fetches(), 1);
+
+        // Uploaded ELFs never go to the fetcher
+        let uploaded = registry.register_elf("fib", elf(b"fib")).unwrap();
+        assert_eq!(registry.resolve("fib").await.unwrap(), elf(b"fib"));
+        assert_eq!(registry.resolve(&uploaded.program_hash).await.unwrap(), elf(b"fib"));
+        assert_eq!(fetcher.fetches(), 1);
+    }
+
+    #[tokio::test]
+    async fn downloads_that_are_not_the_program_are_refused() {
+        let fetcher = Arc::new(
+            MockFetcher::default()
+                .with("QmSwapped", elf(b"evil"))
+                .with("QmGarbage", b"<html>gateway error</html>".to_vec()),
+        );
+        let registry = ProgramRegistry::new().with_fetcher(fetcher.clone());
+        let swapped = on_chain(&elf(b"v1"), "QmSwapped");
+        let garbage = on_chain(&elf(b"v2"), "QmGarbage");
+        registry.register(swapped.clone()).unwrap();
+        registry.register(garbage.clone()).unwrap();
+
+        assert!(matches!(
+            registry.resolve(&swapped.program_hash).await,
+            Err(RegistryError::HashMismatch { .. })
+        ));
+        assert!(matches!(
+            registry.resolve(&garbage.program_hash).await,
+            Err(RegistryError::NotAnElf)
+        ));
+        // Nothing refused is kept: asking again downloads again
+        assert!(registry.resolve(&swapped.program_hash).await.is_err());
+        assert_eq!(fetcher.fetches(), 3);
+        assert!(matches!(
+            registry.resolve(&sha256_hex(b"never registered")).await,
+            Err(RegistryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn downloads_past_the_byte_budget_drop_the_least_recently_used() {
+        let fetcher = Arc::new(
+            MockFetcher::default()
+                .with("QmA", elf(b"aaaa"))
+                .with("QmB", elf(b"bbbb"))
+                .with("QmC", elf(b"cccc")),
+        );
+        // Two 8-byte ELFs fit
+        let registry = ProgramRegistry::new()
+            .with_fetcher(fetcher.clone())
+            .with_fetch_budget(16);
+        let [a, b, c] = [("aaaa", "QmA"), ("bbbb", "QmB"), ("cccc", "QmC")].map(|(body, cid)| {
+            let metadata = on_chain(&elf(body.as_bytes()), cid);
+            registry.register(metadata.clone()).unwrap();
+            metadata.program_hash
+        });
+
+        registry.resolve(&a).await.unwrap();
+        registry.resolve(&b).await.unwrap();
+        registry.resolve(&a).await.unwrap();
+        registry.resolve(&c).await.unwrap();
+        assert_eq!(fetcher.fetches(), 3);
+
+        assert!(registry.elf(&a).is_ok());
+        assert!(registry.elf(&b).is_err());
+        assert!(registry.elf(&c).is_ok());
+    }
+}
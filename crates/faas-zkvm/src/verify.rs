@@ -0,0 +1,138 @@
+//! Checking a proof someone handed you
+//!
+//! Proving backends leave their name in [`ZkProof::backend`]; [`verify_proof`] maps it to
+//! the zkVM that produced the proof and asks the [`ProofVerifier`] registered for that
+//! zkVM. Verifiers look the verifying key up by [`ZkProof::program`], so a program they
+//! don't know is [`VerifyError::NotFound`] rather than a rejected proof.
+
+use crate::ZkProof;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Checks proofs from one zkVM
+pub trait ProofVerifier: Send + Sync {
+    /// Whether `proof` holds for its program and public inputs. Proof bytes that don't
+    /// even parse are `Ok(false)`; only a program without a verifying key is an error.
+    fn verify(&self, proof: &ZkProof) -> Result<bool, VerifyError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum VerifyError {
+    #[error("Unknown program: {0}")]
+    NotFound(String),
+    #[error("No verifier for backend: {0}")]
+    UnsupportedBackend(String),
+    #[error("Verification failed: {0}")]
+    Verifier(String),
+}
+
+/// `POST /v1/verify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verification {
+    pub valid: bool,
+    pub verified_in_ms: u64,
+}
+
+/// The zkVM a backend name belongs to, in [`crate::ProgramMetadata::zkvm_type`] terms
+pub fn zkvm_type(backend: &str) -> Option<&'static str> {
+    let backend = backend.to_ascii_lowercase();
+    if backend.starts_with("sp1") {
+        Some("sp1")
+    } else if ["risc zero", "risczero", "bonsai"]
+        .iter()
+        .any(|prefix| backend.starts_with(prefix))
+    {
+        Some("risczero")
+    } else {
+        None
+    }
+}
+
+/// Verifiers by zkVM type
+#[derive(Clone, Default)]
+pub struct Verifiers {
+    by_zkvm: HashMap<&'static str, Arc<dyn ProofVerifier>>,
+}
+
+impl Verifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verify proofs of `zkvm_type` ("sp1", "risczero") with `verifier`
+    pub fn with(mut self, zkvm_type: &'static str, verifier: Arc<dyn ProofVerifier>) -> Self {
+        self.by_zkvm.insert(zkvm_type, verifier);
+        self
+    }
+}
+
+impl std::fmt::Debug for Verifiers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.by_zkvm.keys()).finish()
+    }
+}
+
+/// Verify `proof` with the verifier for the zkVM its backend belongs to
+pub fn verify_proof(proof: &ZkProof, verifiers: &Verifiers) -> Result<Verification, VerifyError> {
+    let verifier = zkvm_type(&proof.backend)
+        .and_then(|zkvm| verifiers.by_zkvm.get(zkvm))
+        .ok_or_else(|| VerifyError::UnsupportedBackend(proof.backend.clone()))?;
+    let start = Instant::now();
+    let valid = verifier.verify(proof)?;
+    Ok(Verification {
+        valid,
+        verified_in_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Knows one program, whose only valid proof is `b"fib"`
+    struct Fake;
+
+    impl ProofVerifier for Fake {
+        fn verify(&self, proof: &ZkProof) -> Result<bool, VerifyError> {
+            match proof.program.as_str() {
+                "fibonacci" => Ok(proof.proof_data == b"fib"),
+                other => Err(VerifyError::NotFound(other.to_string())),
+            }
+        }
+    }
+
+    fn proof(program: &str, backend: &str, proof_data: &[u8]) -> ZkProof {
+        ZkProof {
+            proof_id: crate::proof_id(proof_data),
+            program: program.to_string(),
+            public_inputs: vec!["10".to_string()],
+            proof_data: proof_data.to_vec(),
+            backend: backend.to_string(),
+            proving_time_ms: 0,
+            execution_mode: "remote".to_string(),
+            from_cache: false,
+        }
+    }
+
+    #[test]
+    fn proofs_go_to_the_verifier_for_their_zkvm() {
+        let verifiers = Verifiers::new().with("sp1", Arc::new(Fake));
+
+        assert!(verify_proof(&proof("fibonacci", "SP1 Local", b"fib"), &verifiers)
+            .unwrap()
+            .valid);
+        assert!(!verify_proof(&proof("fibonacci", "SP1 Network", b"fob"), &verifiers)
+            .unwrap()
+            .valid);
+        assert_eq!(
+            verify_proof(&proof("sudoku", "SP1 Local", b"fib"), &verifiers),
+            Err(VerifyError::NotFound("sudoku".to_string()))
+        );
+        assert_eq!(
+            verify_proof(&proof("fibonacci", "RISC Zero Local", b"fib"), &verifiers),
+            Err(VerifyError::UnsupportedBackend("RISC Zero Local".to_string()))
+        );
+    }
+}
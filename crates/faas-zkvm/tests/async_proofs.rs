@@ -0,0 +1,138 @@
+//! Polling proof jobs against a stand-in prover that takes its time.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use faas_zkvm::{ProofJob, ProofJobStore, ZkProof, ZkProverClient, ZkProverError};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long the stand-in takes to prove anything
+const PROVING: Duration = Duration::from_millis(300);
+
+fn proof(program: &str) -> ZkProof {
+    ZkProof {
+        proof_id: faas_zkvm::proof_id(b"plonk"),
+        program: program.to_string(),
+        public_inputs: vec!["10".to_string()],
+        proof_data: b"plonk".to_vec(),
+        backend: "SP1 Local".to_string(),
+        proving_time_ms: PROVING.as_millis() as u64,
+        execution_mode: "local".to_string(),
+        from_cache: false,
+    }
+}
+
+async fn prove(
+    State(jobs): State<Arc<ProofJobStore>>,
+    Json(req): Json<Value>,
+) -> Result<(StatusCode, Json<ProofJob>), StatusCode> {
+    assert_eq!(req["async"], true, "only async proving is stood in for");
+    let program = req["program"].as_str().unwrap().to_string();
+    let job = jobs
+        .submit(&program)
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    let id = job.proof_job_id.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(PROVING / 3).await;
+        jobs.start(&id);
+        tokio::time::sleep(PROVING).await;
+        let result = match program.as_str() {
+            "broken" => Err("guest program panicked".to_string()),
+            _ => Ok(proof(&program)),
+        };
+        jobs.finish(&id, result).unwrap();
+    });
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+async fn status(
+    State(jobs): State<Arc<ProofJobStore>>,
+    Path(id): Path<String>,
+) -> Result<Json<ProofJob>, StatusCode> {
+    jobs.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn prover() -> ZkProverClient {
+    let app = Router::new()
+        .route("/v1/prove", post(prove))
+        .route("/v1/proofs/:id", get(status))
+        .with_state(Arc::new(ProofJobStore::new(8)));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    ZkProverClient::new(format!("http://{addr}"))
+}
+
+#[tokio::test]
+async fn a_queued_proof_is_polled_until_done() {
+    let client = prover().await;
+    let id = client
+        .prove_async("fibonacci", vec!["10".to_string()], vec![])
+        .await
+        .unwrap();
+
+    let queued = client.get_proof_status(&id).await.unwrap();
+    assert_eq!(
+        serde_json::to_value(queued.status).unwrap(),
+        "queued",
+        "answered before proving started"
+    );
+    assert!(queued.proof.is_none());
+
+    let proof = client
+        .wait_for_proof(&id, Duration::from_millis(25), Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(proof.program, "fibonacci");
+    assert_eq!(proof.proof_data, b"plonk");
+    assert_eq!(proof.execution_mode, "remote");
+}
+
+#[tokio::test]
+async fn waiting_gives_up_at_the_timeout() {
+    let client = prover().await;
+    let id = client
+        .prove_async("fibonacci", vec!["10".to_string()], vec![])
+        .await
+        .unwrap();
+    let started = std::time::Instant::now();
+    let waited = client
+        .wait_for_proof(&id, Duration::from_millis(25), Duration::from_millis(150))
+        .await;
+    assert!(started.elapsed() < PROVING);
+    match waited {
+        Err(ZkProverError::Timeout { proof_job_id, .. }) => assert_eq!(proof_job_id, id),
+        other => panic!("expected a timeout, got {other:?}"),
+    }
+
+    // The job kept going and can still be waited for
+    client
+        .wait_for_proof(&id, Duration::from_millis(25), Duration::from_secs(5))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn failed_and_unknown_jobs_are_errors() {
+    let client = prover().await;
+    let id = client.prove_async("broken", vec![], vec![]).await.unwrap();
+    match client
+        .wait_for_proof(&id, Duration::from_millis(25), Duration::from_secs(5))
+        .await
+    {
+        Err(ZkProverError::ProofFailed { error, .. }) => {
+            assert_eq!(error, "guest program panicked")
+        }
+        other => panic!("expected the job to fail, got {other:?}"),
+    }
+
+    assert!(matches!(
+        client.get_proof_status("no-such-job").await,
+        Err(ZkProverError::UnknownJob(_))
+    ));
+}
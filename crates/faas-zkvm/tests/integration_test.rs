@@ -8,7 +8,7 @@
 //! Run with: cargo test --package faas-zkvm --test integration_test -- --ignored
 //! (Requires faas-zk-prover server running: cargo run --release --package faas-zk-prover)
 
-use faas_zkvm::ZkProverClient;
+use faas_zkvm::{ZkProverClient, ZkProverError};
 
 #[tokio::test]
 #[ignore] // Requires server to be running
@@ -71,3 +71,30 @@ async fn test_hash_preimage_proof() {
     println!("✓ Hash preimage proof generated");
     println!("  Proof size: {} bytes", proof.proof_data.len());
 }
+
+#[tokio::test]
+#[ignore]
+async fn test_fibonacci_proof_verifies_until_corrupted() {
+    let client = ZkProverClient::new("http://localhost:8081");
+
+    let mut proof = client
+        .prove("fibonacci", vec!["10".to_string()], vec![])
+        .await
+        .expect("Proof generation failed");
+
+    let verification = client.verify(&proof).await.expect("Verification failed");
+    assert!(verification.valid, "a fresh proof should verify");
+    println!("✓ Proof verified in {}ms", verification.verified_in_ms);
+
+    let last = proof.proof_data.len() - 1;
+    proof.proof_data[last] ^= 0xff;
+    let verification = client.verify(&proof).await.expect("Verification failed");
+    assert!(!verification.valid, "a corrupted proof should be rejected");
+
+    proof.program = "no_such_program".to_string();
+    assert!(matches!(
+        client.verify(&proof).await,
+        Err(ZkProverError::UnknownProgram(_))
+    ));
+    println!("✓ Corrupted proof rejected");
+}
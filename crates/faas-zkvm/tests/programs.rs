@@ -0,0 +1,103 @@
+//! Uploading programs against a stand-in prover backed by a real registry.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use faas_zkvm::{
+    ProgramMetadata, ProgramRegistry, RegisterProgramRequest, RegistryError, ZkProverClient,
+    ZkProverError,
+};
+use std::sync::Arc;
+
+type Registry = Arc<ProgramRegistry>;
+
+async fn register(
+    State(registry): State<Registry>,
+    Json(req): Json<RegisterProgramRequest>,
+) -> Result<(StatusCode, Json<ProgramMetadata>), (StatusCode, String)> {
+    let elf = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &req.elf)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let registered = match &req.program_hash {
+        Some(hash) => registry.register_elf_verified(&req.name, elf, hash),
+        None => registry.register_elf(&req.name, elf),
+    };
+    registered
+        .map(|metadata| (StatusCode::CREATED, Json(metadata)))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn list(State(registry): State<Registry>) -> Json<Vec<ProgramMetadata>> {
+    Json(registry.list())
+}
+
+async fn program(
+    State(registry): State<Registry>,
+    Path(hash): Path<String>,
+) -> Result<Json<ProgramMetadata>, StatusCode> {
+    registry.get(&hash).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn prover(dir: &std::path::Path) -> ZkProverClient {
+    let registry = Arc::new(ProgramRegistry::open(dir).unwrap());
+    let app = Router::new()
+        .route("/v1/programs", post(register).get(list))
+        .route("/v1/programs/:hash", get(program))
+        .with_state(registry);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    ZkProverClient::new(format!("http://{addr}"))
+}
+
+#[tokio::test]
+async fn an_uploaded_program_is_listed_and_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = prover(dir.path()).await;
+    let elf = b"\x7fELF tiny guest".to_vec();
+
+    let registered = client.register_program("tiny", &elf).await.unwrap();
+    assert_eq!(registered.name.as_deref(), Some("tiny"));
+    assert_eq!(registered.size_bytes, Some(elf.len() as u64));
+
+    let listed = client.list_programs().await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].program_hash, registered.program_hash);
+    assert_eq!(
+        client
+            .get_program(&registered.program_hash)
+            .await
+            .unwrap()
+            .name
+            .as_deref(),
+        Some("tiny")
+    );
+
+    // The data directory is what survives a restart
+    let restarted = ProgramRegistry::open(dir.path()).unwrap();
+    assert_eq!(restarted.elf("tiny").unwrap(), elf);
+}
+
+#[tokio::test]
+async fn unregistered_and_invalid_programs_are_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = prover(dir.path()).await;
+
+    let unregistered = faas_common::hash::sha256_hex(b"never uploaded");
+    match client.get_program(&unregistered).await {
+        Err(ZkProverError::UnknownProgram(hash)) => assert_eq!(hash, unregistered),
+        other => panic!("expected an unknown program, got {other:?}"),
+    }
+    assert!(matches!(
+        ProgramRegistry::open(dir.path()).unwrap().elf(&unregistered),
+        Err(RegistryError::NotFound(_))
+    ));
+
+    assert!(matches!(
+        client.register_program("script", b"#!/bin/sh").await,
+        Err(ZkProverError::Server(_))
+    ));
+    assert!(client.list_programs().await.unwrap().is_empty());
+}
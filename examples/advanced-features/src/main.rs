@@ -49,10 +49,10 @@ console.log(JSON.stringify(compute(), null, 2));
     println!("\n4. Forked execution:");
     // First create a base execution
     let base = client
-        .execute(ExecuteRequest {
-            command: "echo 'Base execution established'".to_string(),
-            image: Some("alpine:latest".to_string()),
-            ..Default::default()
+        .execute_with(|b| {
+            b.command("echo 'Base execution established'")
+                .image("alpine:latest")
+                .mode("branched")
         })
         .await?;
 
@@ -64,26 +64,18 @@ console.log(JSON.stringify(compute(), null, 2));
 
     // 6. Use advanced features with explicit control
     println!("\n5. Advanced execution with full control:");
-    let advanced_result = client
-        .execute(ExecuteRequest {
-            command: "python -c 'import sys; print(f\"Python {sys.version}\")'".to_string(),
-            image: Some("python:3.11-slim".to_string()),
-            runtime: Some(Runtime::Docker), // Explicitly choose runtime
-            env_vars: Some(vec![
-                ("ENV".to_string(), "production".to_string()),
-                ("DEBUG".to_string(), "false".to_string()),
-            ]),
-            working_dir: None,
-            timeout_ms: Some(5000),
-            memory_mb: Some(512),
-            cpu_cores: Some(2),
-            cache_key: Some("python-version-check".to_string()),
-            snapshot_id: None,
-            branch_from: None,
-            mode: Some("cached".to_string()),
-            payload: None,
-        })
-        .await?;
+    let request =
+        ExecuteRequest::builder("python -c 'import sys; print(f\"Python {sys.version}\")'")
+            .image("python:3.11-slim")
+            .runtime(Runtime::Docker) // Explicitly choose runtime
+            .env("ENV", "production")
+            .env("DEBUG", "false")
+            .timeout_ms(5000)
+            .memory_mb(512)
+            .cpu_cores(2)
+            .cached("python-version-check")
+            .build()?;
+    let advanced_result = client.execute(request).await?;
 
     println!("   Duration: {}ms", advanced_result.duration_ms);
     println!("   Output: {}", advanced_result.stdout.trim());
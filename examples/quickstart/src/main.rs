@@ -1,22 +1,31 @@
 //! Quickstart example - minimal working demo
 
-use faas_sdk::{ExecuteRequest, FaasClient};
+use faas_sdk::{ExecuteRequest, FaasClient, Transport};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("FaaS Platform Quickstart\n");
 
+    // `--embedded` runs everything in-process instead of through a gateway
+    #[cfg(feature = "embedded")]
+    if std::env::args().any(|arg| arg == "--embedded") {
+        return run_examples(&faas_sdk::EmbeddedClient::new().await?).await;
+    }
+
     // Connect to FaaS platform
     let client = FaasClient::new("http://localhost:8080".to_string());
+    run_examples(&client).await
+}
 
+async fn run_examples(client: &impl Transport) -> Result<(), Box<dyn std::error::Error>> {
     // Example 1: Simple execution
     println!("1. Simple execution:");
     let result = client
-        .execute(ExecuteRequest {
-            command: "echo Hello from FaaS!".to_string(),
-            image: Some("alpine:latest".to_string()),
-            ..Default::default()
-        })
+        .execute(
+            ExecuteRequest::builder("echo Hello from FaaS!")
+                .image("alpine:latest")
+                .build()?,
+        )
         .await?;
 
     println!("   Output: {}", result.stdout);
@@ -24,11 +33,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Example 2: With input data via stdin
     println!("\n2. Processing data:");
     let result = client
-        .execute(ExecuteRequest {
-            command: "wc -l".to_string(),
-            image: Some("alpine:latest".to_string()),
-            ..Default::default()
-        })
+        .execute(
+            ExecuteRequest::builder("wc -l")
+                .image("alpine:latest")
+                .build()?,
+        )
         .await?;
 
     println!("   Line count: {}", result.stdout.trim());
@@ -36,15 +45,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Example 3: Environment variables
     println!("\n3. With environment:");
     let result = client
-        .execute(ExecuteRequest {
-            command: "sh -c 'echo $MESSAGE'".to_string(),
-            image: Some("alpine:latest".to_string()),
-            env_vars: Some(vec![(
-                "MESSAGE".to_string(),
-                "FaaS Platform Works!".to_string(),
-            )]),
-            ..Default::default()
-        })
+        .execute(
+            ExecuteRequest::builder("sh -c 'echo $MESSAGE'")
+                .image("alpine:latest")
+                .env("MESSAGE", "FaaS Platform Works!")
+                .build()?,
+        )
         .await?;
 
     println!("   Env output: {}", result.stdout);
@@ -1,11 +1,11 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use faas_executor::platform::{Executor, Mode, Request};
+use faas_executor::platform::{Executor, Mode, PlatformConfig, Request};
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
 fn benchmark_modes(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let executor = match rt.block_on(async { Executor::new().await }) {
+    let executor = match rt.block_on(async { Executor::new(PlatformConfig::default()).await }) {
         Ok(exec) => exec,
         Err(_) => {
             eprintln!(
@@ -44,7 +44,7 @@ fn benchmark_modes(c: &mut Criterion) {
 
 fn benchmark_fork_creation(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let executor = match rt.block_on(async { Executor::new().await }) {
+    let executor = match rt.block_on(async { Executor::new(PlatformConfig::default()).await }) {
         Ok(exec) => exec,
         Err(_) => {
             eprintln!("Skipping fork benchmark: Executor initialization failed");
@@ -89,7 +89,7 @@ fn benchmark_fork_creation(c: &mut Criterion) {
 
 fn benchmark_ai_exploration(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let executor = match rt.block_on(async { Executor::new().await }) {
+    let executor = match rt.block_on(async { Executor::new(PlatformConfig::default()).await }) {
         Ok(exec) => exec,
         Err(_) => {
             eprintln!("Skipping AI exploration benchmark: Executor initialization failed");
@@ -163,7 +163,7 @@ print(f'Exploring with strategy {{strategy}}')
 
 fn benchmark_memory_efficiency(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let executor = match rt.block_on(async { Executor::new().await }) {
+    let executor = match rt.block_on(async { Executor::new(PlatformConfig::default()).await }) {
         Ok(exec) => exec,
         Err(_) => {
             eprintln!("Skipping memory efficiency benchmark: Executor initialization failed");
@@ -234,12 +234,12 @@ criterion_main!(benches);
 #[cfg(test)]
 mod integration_tests {
     use super::*;
-    use faas_executor::platform::{Executor, Mode, Request};
+    use faas_executor::platform::{Executor, Mode, PlatformConfig, Request};
     use std::time::Duration;
 
     #[tokio::test]
     async fn test_performance_targets() {
-        let executor = Executor::new().await.unwrap();
+        let executor = Executor::new(PlatformConfig::default()).await.unwrap();
 
         // Test ephemeral execution <50ms
         let start = std::time::Instant::now();
@@ -266,7 +266,7 @@ mod integration_tests {
 
     #[tokio::test]
     async fn test_checkpoint_restore_cycle() {
-        let executor = Executor::new().await.unwrap();
+        let executor = Executor::new(PlatformConfig::default()).await.unwrap();
 
         // Create checkpoint
         let checkpoint_start = std::time::Instant::now();
@@ -315,7 +315,7 @@ mod integration_tests {
 
     #[tokio::test]
     async fn test_branch_performance() {
-        let executor = Executor::new().await.unwrap();
+        let executor = Executor::new(PlatformConfig::default()).await.unwrap();
 
         // Create parent
         let parent_req = Request {
@@ -356,7 +356,7 @@ mod integration_tests {
 
     #[tokio::test]
     async fn test_ai_agent_workflow() {
-        let executor = Executor::new().await.unwrap();
+        let executor = Executor::new(PlatformConfig::default()).await.unwrap();
 
         // Simulate AI agent setup
         let setup_req = Request {
@@ -19,7 +19,7 @@ use blueprint_sdk::{
     runner::config::BlueprintEnvironment,
     tangle_subxt::tangle_testnet_runtime::api,
 };
-use faas_executor::platform::Executor as PlatformExecutor;
+use faas_executor::platform::{Executor as PlatformExecutor, PlatformConfig};
 use k256::elliptic_curve::sec1::ToEncodedPoint;
 use k256::PublicKey;
 use reqwest::Url;
@@ -64,7 +64,9 @@ impl FaaSContext {
             config.keystore_uri
         );
 
-        let executor = PlatformExecutor::new()
+        let platform_config = PlatformConfig::from_env()
+            .map_err(|e| BlueprintLibError::PlatformExecutor(e.to_string()))?;
+        let executor = PlatformExecutor::new(platform_config)
             .await
             .map_err(|e| BlueprintLibError::PlatformExecutor(e.to_string()))?;
 
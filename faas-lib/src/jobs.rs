@@ -315,6 +315,7 @@ pub async fn execute_function_job(
         branch_from: None,
         runtime: None,
         env_vars: None,
+        ..Default::default()
     };
 
     let response = _ctx
@@ -433,6 +434,7 @@ pub async fn execute_advanced_job(
         branch_from: branch_from,
         runtime: None,
         env_vars: None,
+        ..Default::default()
     };
 
     let response = _ctx.executor.run(request).await.map_err(|e| {
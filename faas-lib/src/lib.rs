@@ -1,5 +1,6 @@
 use faas_common::InvocationResult;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 pub mod api_routes;
@@ -9,6 +10,9 @@ pub mod jobs;
 
 pub const EXECUTE_FUNCTION_JOB_ID: u64 = 0;
 
+/// Largest stdout, stderr or log field submitted on chain; longer ones are cut to this prefix.
+pub const MAX_ONCHAIN_OUTPUT_BYTES: usize = 16 * 1024;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[cfg_attr(
     feature = "scale",
@@ -16,30 +20,100 @@ pub const EXECUTE_FUNCTION_JOB_ID: u64 = 0;
 )]
 pub struct FaaSExecutionOutput {
     pub request_id: String,
-    pub stdout: Option<String>,
+    /// Response bytes exactly as the function produced them; not necessarily UTF-8
+    pub stdout: Option<Vec<u8>>,
     pub stderr: Option<String>,
+    /// Runtime logs, kept apart from the function's own stderr
+    pub logs: Option<String>,
     pub error: Option<String>,
+    /// Whether any field was cut to [`MAX_ONCHAIN_OUTPUT_BYTES`]
+    pub truncated: bool,
+    /// Hex SHA-256 of the full stdout, which is also its artifact store key, so a
+    /// truncated result can be checked against the off-chain copy
+    pub stdout_sha256: Option<String>,
 }
 
-impl From<InvocationResult> for FaaSExecutionOutput {
-    fn from(result: InvocationResult) -> Self {
-        // Attempt to decode stdout/stderr if they are UTF-8
-        let stdout = result
-            .response
-            .and_then(|bytes| String::from_utf8(bytes).ok());
-
-        // Note: The current InvocationResult bundles all logs (stdout+stderr) into `logs`.
-        // We'll put the combined logs into `stderr` field for now, and keep `stdout` based on `response`.
-        // Ideally, InvocationResult would separate stdout and stderr.
-        let stderr = result.logs;
-
+impl FaaSExecutionOutput {
+    pub fn new(
+        request_id: String,
+        stdout: Option<Vec<u8>>,
+        stderr: Option<String>,
+        logs: Option<String>,
+        error: Option<String>,
+    ) -> Self {
+        let stdout_sha256 = stdout
+            .as_ref()
+            .map(|bytes| format!("{:x}", Sha256::digest(bytes)));
+        let mut truncated = false;
+        let stdout = stdout.map(|mut bytes| {
+            if bytes.len() > MAX_ONCHAIN_OUTPUT_BYTES {
+                bytes.truncate(MAX_ONCHAIN_OUTPUT_BYTES);
+                truncated = true;
+            }
+            bytes
+        });
+        let mut cap = |text: Option<String>| {
+            text.map(|text| {
+                let (text, cut) = truncate_text(text);
+                truncated |= cut;
+                text
+            })
+        };
+        let stderr = cap(stderr);
+        let logs = cap(logs);
+        let error = cap(error);
         Self {
-            request_id: result.request_id,
+            request_id,
             stdout,
             stderr,
-            error: result.error,
+            logs,
+            error,
+            truncated,
+            stdout_sha256,
         }
     }
+
+    /// Output of a platform execution, which reports stdout and stderr separately
+    pub fn from_response(response: &faas_executor::platform::Response) -> Self {
+        let error =
+            (response.exit_code != 0).then(|| format!("exited with code {}", response.exit_code));
+        Self::new(
+            response.id.clone(),
+            Some(response.stdout.clone()),
+            Some(String::from_utf8_lossy(&response.stderr).into_owned()),
+            None,
+            error,
+        )
+    }
+}
+
+/// Cut `text` to the on-chain limit at a character boundary
+fn truncate_text(mut text: String) -> (String, bool) {
+    if text.len() <= MAX_ONCHAIN_OUTPUT_BYTES {
+        return (text, false);
+    }
+    let mut end = MAX_ONCHAIN_OUTPUT_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    (text, true)
+}
+
+impl From<InvocationResult> for FaaSExecutionOutput {
+    fn from(result: InvocationResult) -> Self {
+        // Runtimes that don't split the streams leave `stderr` unset; their combined output
+        // stays in `logs`
+        Self::new(
+            result.request_id,
+            result.stdout.or(result.response),
+            result
+                .stderr
+                .map(|stderr| String::from_utf8_lossy(&stderr).into_owned()),
+            result.logs,
+            result.error,
+        )
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -77,3 +151,80 @@ pub enum JobError {
     #[error("Operator not assigned to this job")]
     NotAssigned,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation(response: Vec<u8>) -> InvocationResult {
+        InvocationResult {
+            request_id: "job_7".to_string(),
+            response: Some(response),
+            logs: Some("pulled alpine:latest".to_string()),
+            error: None,
+            stdout: None,
+            stderr: None,
+            exit_code: None,
+        }
+    }
+
+    #[test]
+    fn binary_stdout_is_kept_as_bytes() {
+        let binary = vec![0xff, 0x00, 0xfe, 0x80];
+        let output = FaaSExecutionOutput::from(invocation(binary.clone()));
+        assert_eq!(output.stdout, Some(binary));
+        assert_eq!(output.stderr, None);
+        assert_eq!(output.logs.as_deref(), Some("pulled alpine:latest"));
+        assert!(!output.truncated);
+    }
+
+    #[test]
+    fn separate_streams_land_in_their_own_fields() {
+        let output = FaaSExecutionOutput::from(InvocationResult {
+            stdout: Some(b"result\n".to_vec()),
+            stderr: Some(b"warning\n".to_vec()),
+            ..invocation(b"result\n".to_vec())
+        });
+        assert_eq!(output.stdout.as_deref(), Some(&b"result\n"[..]));
+        assert_eq!(output.stderr.as_deref(), Some("warning\n"));
+    }
+
+    #[test]
+    fn long_output_truncates_deterministically() {
+        let full: Vec<u8> = (0..MAX_ONCHAIN_OUTPUT_BYTES * 2)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let first = FaaSExecutionOutput::from(invocation(full.clone()));
+        let second = FaaSExecutionOutput::from(invocation(full.clone()));
+        assert_eq!(first, second);
+        assert!(first.truncated);
+        assert_eq!(
+            first.stdout.as_deref(),
+            Some(&full[..MAX_ONCHAIN_OUTPUT_BYTES])
+        );
+        assert_eq!(
+            first.stdout_sha256,
+            Some(format!("{:x}", Sha256::digest(&full)))
+        );
+
+        let (text, cut) = truncate_text("é".repeat(MAX_ONCHAIN_OUTPUT_BYTES));
+        assert!(cut);
+        assert_eq!(text.len(), MAX_ONCHAIN_OUTPUT_BYTES);
+    }
+
+    #[cfg(feature = "scale")]
+    #[test]
+    fn scale_round_trip() {
+        use parity_scale_codec::{Decode, Encode};
+
+        let output = FaaSExecutionOutput::from(invocation(vec![0xde, 0xad, 0xbe, 0xef]));
+        for result in [
+            ExecuteFunctionResult::ok(output),
+            ExecuteFunctionResult::err("image not found".to_string()),
+        ] {
+            let encoded = result.encode();
+            let decoded = ExecuteFunctionResult::decode(&mut &encoded[..]).unwrap();
+            assert_eq!(decoded, result);
+        }
+    }
+}
@@ -7,6 +7,8 @@ use blueprint_sdk::tangle::extract::{CallId, TangleArgs4, TangleArgs8};
 use color_eyre::eyre::{eyre, Result};
 use faas_blueprint_lib::context::FaaSContext;
 use faas_blueprint_lib::jobs::{execute_advanced_job, execute_function_job};
+use faas_blueprint_lib::{FaaSExecutionOutput, MAX_ONCHAIN_OUTPUT_BYTES};
+use faas_common::InvocationResult;
 use faas_executor::platform::{Mode, Request};
 use std::fs;
 use tempfile::TempDir;
@@ -62,6 +64,7 @@ async fn verify_executor(ctx: &FaaSContext) -> Result<()> {
         branch_from: None,
         runtime: Some(faas_common::Runtime::Docker),
         env_vars: None,
+        ..Default::default()
     };
 
     let response = ctx
@@ -117,6 +120,65 @@ async fn execute_function_emits_stdout() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn binary_output_is_verifiable_off_chain() -> Result<()> {
+    let fixture = create_fixture().await?;
+    let ctx = fixture.ctx.clone();
+
+    // Random bytes are almost never valid UTF-8 and exceed the on-chain limit
+    let result = execute_function_job(
+        Context(ctx),
+        CallId(3),
+        TangleArgs4(
+            "alpine:latest".to_string(),
+            vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!("head -c {} /dev/urandom", MAX_ONCHAIN_OUTPUT_BYTES * 3),
+            ],
+            None,
+            vec![],
+        ),
+    )
+    .await?;
+    let full = result.0;
+    assert!(String::from_utf8(full.clone()).is_err());
+
+    let output = FaaSExecutionOutput::from(InvocationResult {
+        request_id: "job_3".to_string(),
+        response: Some(full.clone()),
+        logs: None,
+        error: None,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+    });
+    assert!(output.truncated);
+    let on_chain = output.stdout.clone().expect("binary stdout is kept");
+    assert_eq!(on_chain, full[..MAX_ONCHAIN_OUTPUT_BYTES]);
+
+    // The artifact store files content under its SHA-256, so the on-chain hash locates
+    // the full copy and proves it is the output that was truncated
+    let store = fixture._dir.path().join("artifacts");
+    fs::create_dir_all(&store)?;
+    let hash = output.stdout_sha256.clone().expect("hash of full stdout");
+    fs::write(store.join(&hash), &full)?;
+
+    let copy = fs::read(store.join(&hash))?;
+    let recomputed = FaaSExecutionOutput::from(InvocationResult {
+        request_id: "job_3".to_string(),
+        response: Some(copy.clone()),
+        logs: None,
+        error: None,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+    });
+    assert_eq!(recomputed.stdout_sha256, Some(hash));
+    assert!(copy.starts_with(&on_chain));
+    Ok(())
+}
+
 #[tokio::test]
 async fn execute_advanced_honors_mode() -> Result<()> {
     let fixture = create_fixture().await?;
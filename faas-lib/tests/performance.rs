@@ -1,11 +1,11 @@
 use color_eyre::Result;
-use faas_executor::platform::{Executor, Mode, Request};
+use faas_executor::platform::{Executor, Mode, PlatformConfig, Request};
 use std::collections::HashMap;
 use std::time::Duration;
 use tokio::time::Instant;
 
 async fn create_executor() -> Result<Executor> {
-    Executor::new()
+    Executor::new(PlatformConfig::default())
         .await
         .map_err(|e| color_eyre::eyre::eyre!("failed to initialize executor: {e}"))
 }
@@ -413,8 +413,7 @@ class FaaSClient:
 
                     data = await response.json()
 
-                    # Check for cache hit (very fast response)
-                    cache_hit = elapsed_ms < 10
+                    cache_hit = bool(data.get("cache_hit", False))
                     if cache_hit:
                         self.metrics.cache_hits += 1
 
@@ -540,8 +540,7 @@ export class FaaSClient extends EventEmitter {
     this.metrics.totalRequests++;
     this.metrics.totalLatencyMs += elapsedMs;
 
-    // Check for cache hit (very fast response)
-    const cacheHit = elapsedMs < 10;
+    const cacheHit = result.cache_hit === true;
     if (cacheHit) {
       this.metrics.cacheHits++;
     }
@@ -677,7 +676,7 @@ export class FaaSClient extends EventEmitter {
       error: response.data.error,
       exitCode: response.data.exit_code,
       durationMs: response.data.duration_ms || 0,
-      cacheHit: false
+      cacheHit: response.data.cache_hit === true
     };
   }
 